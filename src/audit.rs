@@ -0,0 +1,212 @@
+//! Audit log of write and admin operations
+//!
+//! With `audit.enabled`, every request that may change data or settings
+//! (index creation and deletion, document writes and deletes, mapping,
+//! settings, alias, template, pipeline, security and configuration changes)
+//! is recorded once answered: who made it, from which address, what it
+//! targeted, when, and with which status. Reads are not audited.
+//!
+//! Events are appended to an NDJSON file, one per line, or indexed into a
+//! system index (`.gbs-audit` by default), where they can be searched like
+//! any other documents. A failure to record an event is logged and does not
+//! fail the request.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+use crate::config::{AuditConfig, AuditOutput};
+use crate::error::Result;
+use crate::storage::Storage;
+
+/// A recorded operation
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub timestamp: DateTime<Utc>,
+    /// Kind of operation, see [`audit_action`]
+    pub action: String,
+    /// Authenticated user, or the caller named by the credentials when
+    /// security is disabled
+    pub user: String,
+    /// Address of the client connection
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    /// `X-Forwarded-For` header of requests that came through a proxy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_for: Option<String>,
+    pub method: String,
+    pub path: String,
+    /// Index expression of the path, for index APIs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<String>,
+    /// Response status
+    pub status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Where audit events go
+enum AuditSink {
+    Disabled,
+    File {
+        path: PathBuf,
+        // Serializes appends so that lines do not interleave
+        writer: Mutex<()>,
+    },
+    Index {
+        storage: Storage,
+        index: String,
+    },
+}
+
+/// Recorder of audit events
+pub struct AuditLog {
+    sink: AuditSink,
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::disabled()
+    }
+}
+
+impl std::fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let output = match &self.sink {
+            AuditSink::Disabled => "disabled".to_string(),
+            AuditSink::File { path, .. } => format!("file {}", path.display()),
+            AuditSink::Index { index, .. } => format!("index {}", index),
+        };
+        f.debug_struct("AuditLog").field("output", &output).finish()
+    }
+}
+
+impl AuditLog {
+    /// Audit log that records nothing
+    pub fn disabled() -> Self {
+        Self {
+            sink: AuditSink::Disabled,
+        }
+    }
+
+    /// Audit log appending events to an NDJSON file
+    pub fn to_file(path: impl Into<PathBuf>) -> Self {
+        Self {
+            sink: AuditSink::File {
+                path: path.into(),
+                writer: Mutex::new(()),
+            },
+        }
+    }
+
+    /// Audit log indexing events into an index, created on first use
+    pub fn to_index(storage: Storage, index: impl Into<String>) -> Self {
+        Self {
+            sink: AuditSink::Index {
+                storage,
+                index: index.into(),
+            },
+        }
+    }
+
+    /// Audit log of the configuration; `file` is the path of the `file`
+    /// output
+    pub fn from_config(config: &AuditConfig, file: PathBuf, storage: Storage) -> Self {
+        if !config.enabled {
+            return Self::disabled();
+        }
+        match config.output {
+            AuditOutput::File => Self::to_file(file),
+            AuditOutput::Index => Self::to_index(storage, config.index.clone()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !matches!(self.sink, AuditSink::Disabled)
+    }
+
+    /// Record an event
+    pub async fn record(&self, event: &AuditEvent) -> Result<()> {
+        match &self.sink {
+            AuditSink::Disabled => Ok(()),
+            AuditSink::File { path, writer } => {
+                let mut line = serde_json::to_string(event)?;
+                line.push('\n');
+                let _guard = writer.lock().await;
+                if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+                    tokio::fs::create_dir_all(parent).await?;
+                }
+                let mut file = tokio::fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?;
+                file.write_all(line.as_bytes()).await?;
+                file.flush().await?;
+                Ok(())
+            }
+            AuditSink::Index { storage, index } => {
+                if !storage.index_exists(index).await? {
+                    // Another request may have created it in the meantime
+                    if let Err(e) = storage.create_index(index, None, None).await {
+                        if !storage.index_exists(index).await? {
+                            return Err(e);
+                        }
+                    }
+                }
+                let id = uuid::Uuid::new_v4().to_string();
+                storage
+                    .index_document(index, &id, serde_json::to_value(event)?)
+                    .await?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Kind of operation of a write request, from its method and matched route
+///
+/// `create_index` and `delete_index` for `PUT` and `DELETE` on an index,
+/// `delete_document` and `write_document` for document, bulk and query
+/// write APIs, `security` for users and API keys, and `change_settings` for
+/// everything else (mappings, settings, aliases, templates, pipelines,
+/// scripts, index state and configuration).
+pub fn audit_action(method: &str, route: &str) -> &'static str {
+    const DOCUMENT_ENDPOINTS: &[&str] = &[
+        "/_doc",
+        "/_create",
+        "/_update",
+        "/_bulk",
+        "/_txn",
+        "/_reindex",
+        "/_delete_by_query",
+        "/_update_by_query",
+        // Typed document APIs of Elasticsearch 6.x and 7.x
+        "/:type",
+    ];
+
+    if route.contains("/_mapping") {
+        return "change_settings";
+    }
+    if route.ends_with("/:index") {
+        return match method {
+            "DELETE" => "delete_index",
+            _ => "create_index",
+        };
+    }
+    if DOCUMENT_ENDPOINTS
+        .iter()
+        .any(|endpoint| route.contains(endpoint))
+    {
+        return match method {
+            "DELETE" => "delete_document",
+            _ => "write_document",
+        };
+    }
+    if route.contains("/_security") {
+        return "security";
+    }
+    "change_settings"
+}
@@ -0,0 +1,820 @@
+//! Authentication store for Gummy Bear Search
+//!
+//! Holds native-realm users and API keys and verifies the credentials
+//! presented in `Authorization` headers (`Basic` for users, `ApiKey` for API
+//! keys). Users and created API keys are persisted in the Sled backend when
+//! the storage has one configured.
+
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use base64::Engine;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::config::{ApiKeyConfig, SecurityConfig};
+use crate::error::{GbsError, Result};
+use crate::storage::Storage;
+use crate::storage_backend::SledBackend;
+
+/// Role that grants access to every API, including user management
+pub const SUPERUSER_ROLE: &str = "superuser";
+
+/// Username reported when security is disabled
+const ANONYMOUS_USER: &str = "_anonymous";
+
+/// Realm of requests authenticated with an API key
+pub const API_KEY_REALM: &str = "_es_api_key";
+
+/// A native-realm user
+///
+/// Passwords are hashed with Argon2id.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct User {
+    pub username: String,
+    /// PHC string of the Argon2 hash
+    password_hash: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    #[serde(default)]
+    pub full_name: Option<String>,
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// ID and name of the API key the user authenticated with
+    #[serde(skip)]
+    pub api_key: Option<(String, String)>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl User {
+    /// Create a new enabled user with the given password and roles
+    ///
+    /// Hashing the password takes long enough to be kept off the async
+    /// workers.
+    pub fn new(username: &str, password: &str, roles: Vec<String>) -> Self {
+        let mut user = Self::without_password(username, roles);
+        user.set_password(password);
+        user
+    }
+
+    /// Create a user that cannot authenticate with a password
+    fn without_password(username: &str, roles: Vec<String>) -> Self {
+        Self {
+            username: username.to_string(),
+            password_hash: String::new(),
+            roles,
+            full_name: None,
+            email: None,
+            metadata: serde_json::Map::new(),
+            enabled: true,
+            api_key: None,
+        }
+    }
+
+    /// Replace the user's password (a fresh salt is generated)
+    pub fn set_password(&mut self, password: &str) {
+        self.password_hash = hash_password(password);
+    }
+
+    /// Check a plain-text password against the stored hash
+    pub fn verify_password(&self, password: &str) -> bool {
+        PasswordHash::new(&self.password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    }
+
+    /// Check if the user has the given role
+    pub fn has_role(&self, role: &str) -> bool {
+        self.roles.iter().any(|r| r == role)
+    }
+
+    /// Require the superuser role
+    pub fn authorize_admin(&self) -> Result<()> {
+        if !self.has_role(SUPERUSER_ROLE) {
+            return Err(GbsError::Forbidden(format!(
+                "action is unauthorized for user [{}]",
+                self.username
+            )));
+        }
+        Ok(())
+    }
+
+    /// User description as returned by `GET /_security/user/{name}`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "username": self.username,
+            "roles": self.roles,
+            "full_name": self.full_name,
+            "email": self.email,
+            "metadata": self.metadata,
+            "enabled": self.enabled
+        })
+    }
+
+    /// Response body for `GET /_security/_authenticate`
+    ///
+    /// Users authenticated with an API key are reported in the API key realm.
+    pub fn to_authenticate_json(&self, realm_name: &str, realm_type: &str) -> serde_json::Value {
+        let mut body = self.to_json();
+        if let Some(obj) = body.as_object_mut() {
+            let (realm, authentication_type) = match &self.api_key {
+                Some((id, name)) => {
+                    obj.insert(
+                        "api_key".to_string(),
+                        serde_json::json!({ "id": id, "name": name }),
+                    );
+                    let realm = serde_json::json!({ "name": API_KEY_REALM, "type": API_KEY_REALM });
+                    (realm, "api_key")
+                }
+                None => {
+                    let realm = serde_json::json!({ "name": realm_name, "type": realm_type });
+                    (realm, "realm")
+                }
+            };
+            obj.insert("authentication_realm".to_string(), realm.clone());
+            obj.insert("lookup_realm".to_string(), realm);
+            obj.insert(
+                "authentication_type".to_string(),
+                serde_json::json!(authentication_type),
+            );
+        }
+        body
+    }
+}
+
+/// An API key
+///
+/// Only a hash of the key is kept. The key is granted the roles of the user
+/// that created it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    key_hash: String,
+    salt: String,
+    /// Owner of the key
+    pub username: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Creation time in milliseconds since the epoch
+    pub creation: i64,
+    /// Expiration time in milliseconds since the epoch
+    #[serde(default)]
+    pub expiration: Option<i64>,
+    #[serde(default)]
+    pub invalidated: bool,
+    #[serde(default)]
+    pub metadata: serde_json::Map<String, serde_json::Value>,
+}
+
+impl ApiKey {
+    /// Create a new valid API key
+    pub fn new(id: &str, name: &str, key: &str, username: &str, roles: Vec<String>) -> Self {
+        let salt = Uuid::new_v4().simple().to_string();
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            key_hash: salted_sha256(&salt, key),
+            salt,
+            username: username.to_string(),
+            roles,
+            creation: Utc::now().timestamp_millis(),
+            expiration: None,
+            invalidated: false,
+            metadata: serde_json::Map::new(),
+        }
+    }
+
+    /// Check a plain-text key against the stored hash
+    pub fn verify_key(&self, key: &str) -> bool {
+        constant_time_eq(&salted_sha256(&self.salt, key), &self.key_hash)
+    }
+
+    /// Check if the key expired
+    pub fn is_expired(&self) -> bool {
+        self.expiration
+            .is_some_and(|expiration| expiration <= Utc::now().timestamp_millis())
+    }
+
+    /// Key description as returned by `GET /_security/api_key`
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut body = serde_json::json!({
+            "id": self.id,
+            "name": self.name,
+            "creation": self.creation,
+            "invalidated": self.invalidated,
+            "username": self.username,
+            "realm": "default_native",
+            "metadata": self.metadata
+        });
+        if let Some(expiration) = self.expiration {
+            body["expiration"] = serde_json::json!(expiration);
+        }
+        body
+    }
+}
+
+/// Parse an API key expiration like `30m`, `12h` or `7d` into milliseconds
+fn parse_expiration(expiration: &str) -> Result<i64> {
+    let invalid = || {
+        GbsError::InvalidRequest(format!(
+            "failed to parse setting [expiration] with value [{}] as a time value",
+            expiration
+        ))
+    };
+    let split = expiration
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: i64 = expiration[..split].parse().map_err(|_| invalid())?;
+    let unit = match &expiration[split..] {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => return Err(invalid()),
+    };
+    amount.checked_mul(unit).ok_or_else(invalid)
+}
+
+/// Hash a password with Argon2id and a random salt, as a PHC string
+fn hash_password(password: &str) -> String {
+    let salt =
+        SaltString::encode_b64(Uuid::new_v4().as_bytes()).expect("a UUID is a valid salt length");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default Argon2 parameters accept any password")
+        .to_string()
+}
+
+/// Hash a secret with the given salt (hex-encoded SHA-256)
+///
+/// Used for API keys, which are random and long enough not to need a slow
+/// hash, and for the passwords of the verified credentials cache.
+fn salted_sha256(salt: &str, secret: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(secret.as_bytes());
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Compare two hashes in a time independent of where they differ
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Extract username and password from an `Authorization: Basic ...` header value
+pub fn parse_basic_auth(authorization: &str) -> Option<(String, String)> {
+    let encoded = authorization
+        .strip_prefix("Basic ")
+        .or_else(|| authorization.strip_prefix("basic "))?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (username, password) = credentials.split_once(':')?;
+    Some((username.to_string(), password.to_string()))
+}
+
+/// Extract the key ID and key from an `Authorization: ApiKey ...` header value
+///
+/// The credential is the base64 encoding of `id:api_key`, as in Elasticsearch.
+pub fn parse_api_key(authorization: &str) -> Option<(String, String)> {
+    let encoded = authorization
+        .strip_prefix("ApiKey ")
+        .or_else(|| authorization.strip_prefix("apikey "))?;
+    let decoded = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()?;
+    let credentials = String::from_utf8(decoded).ok()?;
+    let (id, key) = credentials.split_once(':')?;
+    Some((id.to_string(), key.to_string()))
+}
+
+/// Extract the key ID from an `Authorization: ApiKey ...` header value
+pub fn parse_api_key_id(authorization: &str) -> Option<String> {
+    parse_api_key(authorization).map(|(id, _)| id)
+}
+
+/// Encode an API key credential for `Authorization: ApiKey` headers
+pub fn encode_api_key(id: &str, key: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", id, key))
+}
+
+/// Store of security users and API keys
+pub struct AuthStore {
+    enabled: bool,
+    users: RwLock<HashMap<String, User>>,
+    api_keys: RwLock<HashMap<String, ApiKey>>,
+    /// IDs of the API keys added from the configuration
+    configured_api_keys: RwLock<HashSet<String>>,
+    /// Passwords verified with Argon2, by username
+    verified: RwLock<HashMap<String, VerifiedPassword>>,
+    /// Salt of the hashes of the verified passwords, new on every start
+    verified_salt: String,
+    backend: Option<Arc<SledBackend>>,
+}
+
+/// A password that authenticated a user, kept so that the user's next
+/// requests are checked with a SHA-256 hash rather than Argon2
+struct VerifiedPassword {
+    /// Argon2 hash the password was verified against; a new password
+    /// replaces it
+    password_hash: String,
+    /// Salted SHA-256 hash of the password
+    digest: String,
+}
+
+impl AuthStore {
+    /// Create an in-memory auth store (no persistence)
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            users: RwLock::new(HashMap::new()),
+            api_keys: RwLock::new(HashMap::new()),
+            configured_api_keys: RwLock::new(HashSet::new()),
+            verified: RwLock::new(HashMap::new()),
+            verified_salt: Uuid::new_v4().simple().to_string(),
+            backend: None,
+        }
+    }
+
+    /// Create an auth store sharing the storage's backend, loading persisted
+    /// users and API keys and then applying the users and API keys declared
+    /// in the configuration and in the security file
+    pub async fn load(config: &SecurityConfig, storage: &Storage) -> Result<Self> {
+        let store = Self {
+            enabled: config.enabled,
+            users: RwLock::new(HashMap::new()),
+            api_keys: RwLock::new(HashMap::new()),
+            configured_api_keys: RwLock::new(HashSet::new()),
+            verified: RwLock::new(HashMap::new()),
+            verified_salt: Uuid::new_v4().simple().to_string(),
+            backend: storage.backend.clone(),
+        };
+
+        if let Some(backend) = &store.backend {
+            let users_backend = backend.clone();
+            let persisted = tokio::task::spawn_blocking(move || users_backend.load_users())
+                .await
+                .map_err(GbsError::TaskJoin)??;
+
+            let mut users = store.users.write().await;
+            for (username, value) in persisted {
+                match serde_json::from_value::<User>(value) {
+                    Ok(user) => {
+                        users.insert(username, user);
+                    }
+                    Err(e) => warn!("Skipping unreadable user record '{}': {}", username, e),
+                }
+            }
+            debug!("Loaded {} users from persistent storage", users.len());
+            drop(users);
+
+            let api_keys_backend = backend.clone();
+            let persisted = tokio::task::spawn_blocking(move || api_keys_backend.load_api_keys())
+                .await
+                .map_err(GbsError::TaskJoin)??;
+
+            let mut api_keys = store.api_keys.write().await;
+            for (id, value) in persisted {
+                match serde_json::from_value::<ApiKey>(value) {
+                    Ok(api_key) => {
+                        api_keys.insert(id, api_key);
+                    }
+                    Err(e) => warn!("Skipping unreadable API key record '{}': {}", id, e),
+                }
+            }
+            debug!("Loaded {} API keys from persistent storage", api_keys.len());
+        }
+
+        store.apply_configuration(config).await?;
+
+        info!(
+            "Security {} ({} users, {} API keys)",
+            if store.enabled { "enabled" } else { "disabled" },
+            store.users.read().await.len(),
+            store.api_keys.read().await.len()
+        );
+
+        Ok(store)
+    }
+
+    /// Apply the users and API keys of the configuration again, e.g. after
+    /// the config file changed
+    ///
+    /// API keys no longer configured stop being accepted. Users no longer
+    /// configured are kept, as they are persisted like users created through
+    /// the API. For configured users the configuration wins: their password
+    /// and roles are set again, also when they were changed through the API.
+    pub async fn reload_configuration(&self, config: &SecurityConfig) -> Result<()> {
+        self.apply_configuration(config).await?;
+        info!(
+            "Reloaded security configuration ({} users, {} API keys)",
+            self.users.read().await.len(),
+            self.api_keys.read().await.len()
+        );
+        Ok(())
+    }
+
+    /// Save the configured users and accept the configured API keys, from the
+    /// configuration and its security file, in place of those configured before
+    ///
+    /// The password and roles of configured users come from the
+    /// configuration; their other attributes, such as `enabled` or
+    /// `full_name`, keep the values set through the API.
+    async fn apply_configuration(&self, config: &SecurityConfig) -> Result<()> {
+        let file = match &config.file {
+            Some(path) => Some(load_security_file(path)?),
+            None => None,
+        };
+        let sections: Vec<&SecurityConfig> = std::iter::once(config).chain(file.as_ref()).collect();
+        for section in &sections {
+            for user_config in &section.users {
+                let user_config = user_config.clone();
+                let existing = self.get_user(&user_config.username).await;
+                let user = tokio::task::spawn_blocking(move || {
+                    let mut user = existing.unwrap_or_else(|| {
+                        User::without_password(&user_config.username, Vec::new())
+                    });
+                    user.set_password(&user_config.password);
+                    user.roles = user_config.roles;
+                    user
+                })
+                .await
+                .map_err(GbsError::TaskJoin)?;
+                self.save_user(user).await?;
+            }
+        }
+
+        let mut api_keys = self.api_keys.write().await;
+        let mut configured = self.configured_api_keys.write().await;
+        for id in configured.drain() {
+            api_keys.remove(&id);
+        }
+        for api_key_config in sections.iter().flat_map(|section| &section.api_keys) {
+            let api_key = configured_api_key(api_key_config);
+            configured.insert(api_key.id.clone());
+            api_keys.insert(api_key.id.clone(), api_key);
+        }
+        Ok(())
+    }
+
+    /// Check if authentication is enforced
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Authenticate the credentials from an `Authorization` header value
+    ///
+    /// When security is disabled every request is treated as an anonymous superuser.
+    pub async fn authenticate(&self, authorization: Option<&str>) -> Result<User> {
+        if !self.enabled {
+            return Ok(User::without_password(
+                ANONYMOUS_USER,
+                vec![SUPERUSER_ROLE.to_string()],
+            ));
+        }
+
+        let authorization = authorization.ok_or_else(|| {
+            GbsError::Unauthorized("missing authentication credentials".to_string())
+        })?;
+        if let Some((id, key)) = parse_api_key(authorization) {
+            return self.authenticate_api_key(&id, &key).await;
+        }
+        let (username, password) = parse_basic_auth(authorization).ok_or_else(|| {
+            GbsError::Unauthorized("unsupported authentication scheme".to_string())
+        })?;
+
+        let user = self.users.read().await.get(&username).cloned();
+        let verified = match user {
+            Some(user) if user.enabled => self.verify_password(user, password).await?,
+            _ => None,
+        };
+        match verified {
+            Some(user) => Ok(user),
+            None => {
+                warn!("Failed authentication attempt for user '{}'", username);
+                Err(GbsError::Unauthorized(format!(
+                    "unable to authenticate user [{}]",
+                    username
+                )))
+            }
+        }
+    }
+
+    /// Check the password of a user, returning the user if it matches
+    ///
+    /// A password that already authenticated the user is recognized by its
+    /// SHA-256 hash; others are checked with Argon2, which takes long enough
+    /// to be kept off the async workers.
+    async fn verify_password(&self, user: User, password: String) -> Result<Option<User>> {
+        let digest = salted_sha256(&self.verified_salt, &password);
+        let cached = self
+            .verified
+            .read()
+            .await
+            .get(&user.username)
+            .is_some_and(|verified| {
+                verified.password_hash == user.password_hash
+                    && constant_time_eq(&verified.digest, &digest)
+            });
+        if cached {
+            return Ok(Some(user));
+        }
+
+        let verified =
+            tokio::task::spawn_blocking(move || user.verify_password(&password).then_some(user))
+                .await
+                .map_err(GbsError::TaskJoin)?;
+        if let Some(user) = &verified {
+            self.verified.write().await.insert(
+                user.username.clone(),
+                VerifiedPassword {
+                    password_hash: user.password_hash.clone(),
+                    digest,
+                },
+            );
+        }
+        Ok(verified)
+    }
+
+    /// Authenticate an API key, as its owner with the key's roles
+    async fn authenticate_api_key(&self, id: &str, key: &str) -> Result<User> {
+        let api_keys = self.api_keys.read().await;
+        let api_key = match api_keys.get(id) {
+            Some(api_key) if api_key.verify_key(key) => api_key,
+            _ => {
+                warn!("Failed authentication attempt with API key '{}'", id);
+                return Err(GbsError::Unauthorized(format!(
+                    "unable to authenticate with API key [{}]",
+                    id
+                )));
+            }
+        };
+        if api_key.invalidated {
+            return Err(GbsError::Unauthorized(
+                "api key has been invalidated".to_string(),
+            ));
+        }
+        if api_key.is_expired() {
+            return Err(GbsError::Unauthorized("api key is expired".to_string()));
+        }
+
+        let mut user = User::without_password(&api_key.username, api_key.roles.clone());
+        user.api_key = Some((api_key.id.clone(), api_key.name.clone()));
+        Ok(user)
+    }
+
+    /// Get a user by name
+    pub async fn get_user(&self, username: &str) -> Option<User> {
+        self.users.read().await.get(username).cloned()
+    }
+
+    /// List all users sorted by name
+    pub async fn list_users(&self) -> Vec<User> {
+        let mut users: Vec<User> = self.users.read().await.values().cloned().collect();
+        users.sort_by(|a, b| a.username.cmp(&b.username));
+        users
+    }
+
+    /// Create or update a user from an ES-style user body
+    ///
+    /// Returns `true` if the user was created, `false` if it was updated.
+    pub async fn put_user(&self, username: &str, body: &serde_json::Value) -> Result<bool> {
+        let existing = self.get_user(username).await;
+        let password = body.get("password").and_then(|v| v.as_str());
+        if let Some(password) = password {
+            if password.len() < 6 {
+                return Err(GbsError::InvalidRequest(
+                    "passwords must be at least [6] characters long".to_string(),
+                ));
+            }
+        }
+
+        let mut user = match (existing.clone(), password) {
+            (Some(user), None) => user,
+            (existing, Some(password)) => {
+                let mut user =
+                    existing.unwrap_or_else(|| User::without_password(username, Vec::new()));
+                let password = password.to_string();
+                tokio::task::spawn_blocking(move || {
+                    user.set_password(&password);
+                    user
+                })
+                .await
+                .map_err(GbsError::TaskJoin)?
+            }
+            (None, None) => {
+                return Err(GbsError::InvalidRequest(format!(
+                    "password must be specified when creating user [{}]",
+                    username
+                )))
+            }
+        };
+
+        if let Some(roles) = body.get("roles").and_then(|v| v.as_array()) {
+            user.roles = roles
+                .iter()
+                .filter_map(|r| r.as_str().map(|s| s.to_string()))
+                .collect();
+        }
+        if let Some(full_name) = body.get("full_name") {
+            user.full_name = full_name.as_str().map(|s| s.to_string());
+        }
+        if let Some(email) = body.get("email") {
+            user.email = email.as_str().map(|s| s.to_string());
+        }
+        if let Some(metadata) = body.get("metadata").and_then(|v| v.as_object()) {
+            user.metadata = metadata.clone();
+        }
+        if let Some(enabled) = body.get("enabled").and_then(|v| v.as_bool()) {
+            user.enabled = enabled;
+        }
+
+        self.save_user(user).await?;
+        Ok(existing.is_none())
+    }
+
+    /// Delete a user, returning whether it existed
+    pub async fn delete_user(&self, username: &str) -> Result<bool> {
+        if let Some(backend) = &self.backend {
+            let backend = backend.clone();
+            let username_str = username.to_string();
+            tokio::task::spawn_blocking(move || backend.delete_user(&username_str))
+                .await
+                .map_err(GbsError::TaskJoin)??;
+        }
+
+        self.verified.write().await.remove(username);
+        let found = self.users.write().await.remove(username).is_some();
+        if found {
+            info!("User '{}' deleted", username);
+        }
+        Ok(found)
+    }
+
+    /// Create an API key owned by a user from an ES-style API key body
+    ///
+    /// Returns the key and its secret, which is not stored.
+    pub async fn create_api_key(
+        &self,
+        owner: &User,
+        body: &serde_json::Value,
+    ) -> Result<(ApiKey, String)> {
+        let name = body
+            .get("name")
+            .and_then(|v| v.as_str())
+            .filter(|name| !name.is_empty())
+            .ok_or_else(|| GbsError::InvalidRequest("api key name is required".to_string()))?;
+        let expiration = body
+            .get("expiration")
+            .and_then(|v| v.as_str())
+            .map(parse_expiration)
+            .transpose()?;
+
+        let id = Uuid::new_v4().simple().to_string();
+        let key = Uuid::new_v4().simple().to_string();
+        let mut api_key = ApiKey::new(&id, name, &key, &owner.username, owner.roles.clone());
+        api_key.expiration = expiration.map(|millis| api_key.creation + millis);
+        if let Some(metadata) = body.get("metadata").and_then(|v| v.as_object()) {
+            api_key.metadata = metadata.clone();
+        }
+
+        info!("Creating API key '{}' for user '{}'", name, owner.username);
+        self.save_api_key(api_key.clone()).await?;
+        Ok((api_key, key))
+    }
+
+    /// API keys visible to a user, filtered by ID, name and owner
+    ///
+    /// Superusers see every key, other users only their own.
+    pub async fn get_api_keys(
+        &self,
+        user: &User,
+        id: Option<&str>,
+        name: Option<&str>,
+        owned: bool,
+    ) -> Vec<ApiKey> {
+        let all = user.has_role(SUPERUSER_ROLE) && !owned;
+        let mut api_keys: Vec<ApiKey> = self
+            .api_keys
+            .read()
+            .await
+            .values()
+            .filter(|api_key| all || api_key.username == user.username)
+            .filter(|api_key| id.is_none_or(|id| api_key.id == id))
+            .filter(|api_key| name.is_none_or(|name| api_key.name == name))
+            .cloned()
+            .collect();
+        api_keys.sort_by_key(|api_key| api_key.creation);
+        api_keys
+    }
+
+    /// Invalidate the API keys visible to a user with the given IDs or name
+    ///
+    /// Returns the IDs of the invalidated keys and of the keys that were
+    /// already invalid.
+    pub async fn invalidate_api_keys(
+        &self,
+        user: &User,
+        ids: &[String],
+        name: Option<&str>,
+        owned: bool,
+    ) -> Result<(Vec<String>, Vec<String>)> {
+        let mut invalidated = Vec::new();
+        let mut previously_invalidated = Vec::new();
+        for mut api_key in self.get_api_keys(user, None, name, owned).await {
+            if !ids.is_empty() && !ids.contains(&api_key.id) {
+                continue;
+            }
+            if api_key.invalidated {
+                previously_invalidated.push(api_key.id);
+                continue;
+            }
+            info!("Invalidating API key '{}'", api_key.id);
+            api_key.invalidated = true;
+            invalidated.push(api_key.id.clone());
+            self.save_api_key(api_key).await?;
+        }
+        Ok((invalidated, previously_invalidated))
+    }
+
+    /// Persist an API key and add it to the in-memory map
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<()> {
+        if let Some(backend) = &self.backend {
+            let backend = backend.clone();
+            let id = api_key.id.clone();
+            let value = serde_json::to_value(&api_key)?;
+            tokio::task::spawn_blocking(move || backend.store_api_key(&id, &value))
+                .await
+                .map_err(GbsError::TaskJoin)??;
+        }
+
+        debug!("API key '{}' saved", api_key.id);
+        self.api_keys
+            .write()
+            .await
+            .insert(api_key.id.clone(), api_key);
+        Ok(())
+    }
+
+    /// Persist a user and add it to the in-memory map
+    async fn save_user(&self, user: User) -> Result<()> {
+        if let Some(backend) = &self.backend {
+            let backend = backend.clone();
+            let username = user.username.clone();
+            let value = serde_json::to_value(&user)?;
+            tokio::task::spawn_blocking(move || backend.store_user(&username, &value))
+                .await
+                .map_err(GbsError::TaskJoin)??;
+        }
+
+        debug!("User '{}' saved", user.username);
+        self.users.write().await.insert(user.username.clone(), user);
+        Ok(())
+    }
+}
+
+/// API key of the configuration
+///
+/// Configured keys are not persisted: they are added again on every start.
+fn configured_api_key(config: &ApiKeyConfig) -> ApiKey {
+    let name = config.name.as_deref().unwrap_or(&config.id);
+    ApiKey::new(&config.id, name, &config.key, name, config.roles.clone())
+}
+
+/// Read the `users` and `api_keys` of a YAML security file
+fn load_security_file(path: &str) -> Result<SecurityConfig> {
+    let content = std::fs::read_to_string(path)?;
+    serde_yaml::from_str(&content).map_err(|e| {
+        GbsError::InvalidRequest(format!("Failed to parse security file {}: {}", path, e))
+    })
+}
+
+impl std::fmt::Debug for AuthStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthStore")
+            .field("enabled", &self.enabled)
+            .finish()
+    }
+}
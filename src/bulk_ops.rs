@@ -1,4 +1,4 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
 use crate::error::{GbsError, Result};
@@ -26,7 +26,7 @@ pub enum BulkAction {
     },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum BulkItemResponse {
     Index { index: BulkOperationResult },
@@ -35,7 +35,7 @@ pub enum BulkItemResponse {
     Delete { delete: BulkOperationResult },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BulkOperationResult {
     #[serde(rename = "_index")]
     pub index: String,
@@ -44,31 +44,39 @@ pub struct BulkOperationResult {
     #[serde(rename = "_id")]
     pub id: String,
     #[serde(rename = "_version", skip_serializing_if = "Option::is_none")]
-    pub version: Option<u32>,
+    pub version: Option<u64>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub result: Option<String>,
     #[serde(rename = "_shards", skip_serializing_if = "Option::is_none")]
     pub shards: Option<ShardsInfo>,
+    /// Sequence number of the write in the index, counted from 0
+    #[serde(rename = "_seq_no", skip_serializing_if = "Option::is_none")]
+    pub seq_no: Option<u64>,
+    #[serde(rename = "_primary_term", skip_serializing_if = "Option::is_none")]
+    pub primary_term: Option<u64>,
+    /// Set when the request refreshed the index (`refresh=true`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub forced_refresh: Option<bool>,
     pub status: u16,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<BulkError>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ShardsInfo {
     pub total: u32,
     pub successful: u32,
     pub failed: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BulkError {
     #[serde(rename = "type")]
     pub r#type: String,
     pub reason: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct BulkResponse {
     pub took: u32,
     pub errors: bool,
@@ -227,3 +235,141 @@ pub fn parse_bulk_ndjson(body: &str, default_index: Option<&str>) -> Result<Vec<
 
     Ok(actions)
 }
+
+/// Serialize bulk actions to a bulk request body, the inverse of `parse_bulk_ndjson`
+pub fn to_bulk_ndjson(actions: &[BulkAction]) -> String {
+    let mut body = String::new();
+    for action in actions {
+        let (metadata, document) = match action {
+            BulkAction::Index {
+                index,
+                id,
+                document,
+            } => (
+                serde_json::json!({ "index": { "_index": index, "_id": id } }),
+                Some(document.clone()),
+            ),
+            BulkAction::Create {
+                index,
+                id,
+                document,
+            } => (
+                serde_json::json!({ "create": { "_index": index, "_id": id } }),
+                Some(document.clone()),
+            ),
+            BulkAction::Update {
+                index,
+                id,
+                document,
+            } => (
+                serde_json::json!({ "update": { "_index": index, "_id": id } }),
+                Some(serde_json::json!({ "doc": document })),
+            ),
+            BulkAction::Delete { index, id } => (
+                serde_json::json!({ "delete": { "_index": index, "_id": id } }),
+                None,
+            ),
+        };
+        body.push_str(&metadata.to_string());
+        body.push('\n');
+        if let Some(document) = document {
+            body.push_str(&document.to_string());
+            body.push('\n');
+        }
+    }
+    body
+}
+
+/// Response of a transaction (`POST /{index}/_txn`)
+#[derive(Debug, Serialize)]
+pub struct TransactionResponse {
+    pub took: u32,
+    /// Whether the operations were applied; if not, none of them was
+    pub committed: bool,
+    /// Results of the operations, when committed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<BulkItemResponse>,
+    /// Position of the operation that aborted the transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failed_operation: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<BulkError>,
+}
+
+/// Parse the operations of a transaction on `index`
+///
+/// The body lists operations in the form of bulk actions whose document is
+/// given inline under `doc`:
+///
+/// ```json
+/// {"operations": [
+///   {"index": {"_id": "1", "doc": {"sku": "A1", "stock": 4}}},
+///   {"update": {"_id": "2", "doc": {"stock": 0}}},
+///   {"delete": {"_id": "3"}}
+/// ]}
+/// ```
+pub fn parse_transaction(body: &Value, index: &str) -> Result<Vec<BulkAction>> {
+    let operations = body
+        .get("operations")
+        .and_then(|operations| operations.as_array())
+        .ok_or_else(|| {
+            GbsError::InvalidRequest("Transaction requires an [operations] array".to_string())
+        })?;
+    if operations.is_empty() {
+        return Err(GbsError::InvalidRequest(
+            "Transaction has no operations".to_string(),
+        ));
+    }
+
+    operations
+        .iter()
+        .enumerate()
+        .map(|(position, operation)| {
+            let invalid = |reason: &str| {
+                GbsError::InvalidRequest(format!(
+                    "Invalid transaction operation [{}]: {}",
+                    position, reason
+                ))
+            };
+            let (action_type, params) = operation
+                .as_object()
+                .filter(|operation| operation.len() == 1)
+                .and_then(|operation| operation.iter().next())
+                .ok_or_else(|| invalid("expected one of index, create, update or delete"))?;
+
+            let index = params
+                .get("_index")
+                .and_then(|v| v.as_str())
+                .unwrap_or(index)
+                .to_string();
+            let id = params
+                .get("_id")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string());
+            let document = params.get("doc").cloned();
+
+            Ok(match action_type.as_str() {
+                "index" => BulkAction::Index {
+                    index,
+                    id,
+                    document: document.ok_or_else(|| invalid("missing doc"))?,
+                },
+                "create" => BulkAction::Create {
+                    index,
+                    id,
+                    document: document.ok_or_else(|| invalid("missing doc"))?,
+                },
+                "update" => BulkAction::Update {
+                    index,
+                    id: id.ok_or_else(|| invalid("missing _id"))?,
+                    document: document.ok_or_else(|| invalid("missing doc"))?,
+                },
+                "delete" => BulkAction::Delete {
+                    index,
+                    id: id.ok_or_else(|| invalid("missing _id"))?,
+                },
+                other => return Err(invalid(&format!("unknown operation [{}]", other))),
+            })
+        })
+        .collect()
+}
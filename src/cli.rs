@@ -0,0 +1,174 @@
+//! Command line of the `gbs` binary
+//!
+//! `gbs` without a command serves, like `gbs serve`. `--config` and
+//! `--data-dir` are accepted by every command and apply before the command
+//! runs, so the maintenance commands and the pid file checks all see the same
+//! data directory.
+
+use clap::error::ErrorKind;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+
+use crate::soak::SoakOptions;
+
+#[derive(Debug, Parser)]
+#[command(
+    name = "gbs",
+    version,
+    about = "Elasticsearch-compatible search engine"
+)]
+pub struct Cli {
+    /// Config file to load instead of the first found of $GUMMY_CONFIG,
+    /// ./gbs.yaml, ./config/gbs.yaml and ~/.config/gbs/gbs.yaml
+    #[arg(long, global = true, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+    /// Data directory to use instead of the configured one
+    #[arg(long, global = true, value_name = "PATH")]
+    pub data_dir: Option<PathBuf>,
+    /// Options of `gbs serve`, which `gbs` alone runs
+    #[command(flatten)]
+    pub serve: ServeArgs,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The command to run, `gbs serve` if none was given
+    pub fn command_to_run(&self) -> Result<Command, clap::Error> {
+        match (&self.command, &self.serve.seed) {
+            (None, _) => Ok(Command::Serve(self.serve.clone())),
+            (Some(command), None) => Ok(command.clone()),
+            (Some(_), Some(_)) => Err(Cli::command().error(
+                ErrorKind::ArgumentConflict,
+                "--seed only applies to gbs serve, give it after the command",
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum Command {
+    /// Run the server in the foreground
+    #[command(alias = "run")]
+    Serve(ServeArgs),
+    /// Start the server in the background
+    Start,
+    /// Stop the background server
+    Stop,
+    /// Tell whether the background server is running (exit code 3 if not)
+    Status,
+    /// Manage the Windows service
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommand,
+    },
+    /// Index the documents of an NDJSON file, one per line
+    Import {
+        /// Index the documents are written to, created if needed
+        index: String,
+        /// NDJSON file to read
+        file: PathBuf,
+    },
+    /// Write every document of an index to an NDJSON file
+    Export {
+        /// Index whose documents are exported
+        index: String,
+        /// NDJSON file to write, replaced if it exists
+        file: PathBuf,
+    },
+    /// Rewrite the database to drop the space of overwritten entries
+    Compact,
+    /// Check that every entry of the database reads back
+    Validate,
+    /// Run randomized operations against a data directory, which `--data-dir`
+    /// must name
+    Soak(SoakArgs),
+    /// Build a Tantivy index from an index
+    ExportTantivy {
+        /// Index to export
+        #[arg(long)]
+        index: String,
+        /// Directory the Tantivy index is created in (must be empty or missing)
+        #[arg(long, value_name = "DIR")]
+        out: PathBuf,
+    },
+}
+
+#[derive(Debug, Clone, Default, Args)]
+pub struct ServeArgs {
+    /// Load the fixtures of a directory at startup
+    #[arg(long, value_name = "DIR")]
+    pub seed: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum ServiceCommand {
+    /// Register the service, started automatically with Windows
+    Install,
+    /// Remove the service
+    Uninstall,
+    /// Run as the service, as the service manager does
+    #[command(hide = true)]
+    Run {
+        /// Directory to change to before loading the configuration
+        #[arg(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Clone, Args)]
+pub struct SoakArgs {
+    /// Number of cycles to run, 0 runs until interrupted [default: 100]
+    #[arg(long)]
+    pub cycles: Option<u64>,
+    /// Random operations per cycle [default: 200]
+    #[arg(long = "ops")]
+    pub ops_per_cycle: Option<usize>,
+    /// Number of soak indices [default: 3]
+    #[arg(long, value_parser = at_least_one)]
+    pub indices: Option<usize>,
+    /// Number of distinct document IDs per index [default: 50]
+    #[arg(long = "docs", value_parser = at_least_one)]
+    pub docs_per_index: Option<usize>,
+    /// Seed of the operation sequence, taken from the clock if not given
+    #[arg(long)]
+    pub seed: Option<u64>,
+}
+
+impl SoakArgs {
+    /// Options of the soak run against `data_dir`, which is never defaulted
+    /// to the configured data directory
+    pub fn options(&self, data_dir: Option<PathBuf>) -> Result<SoakOptions, clap::Error> {
+        let data_dir = data_dir.ok_or_else(|| {
+            Cli::command().error(
+                ErrorKind::MissingRequiredArgument,
+                "gbs soak writes and deletes documents, name its data directory with --data-dir",
+            )
+        })?;
+        let mut options = SoakOptions::new(data_dir);
+        if let Some(cycles) = self.cycles {
+            options.cycles = cycles;
+        }
+        if let Some(ops_per_cycle) = self.ops_per_cycle {
+            options.ops_per_cycle = ops_per_cycle;
+        }
+        if let Some(indices) = self.indices {
+            options.indices = indices;
+        }
+        if let Some(docs_per_index) = self.docs_per_index {
+            options.docs_per_index = docs_per_index;
+        }
+        if let Some(seed) = self.seed {
+            options.seed = seed;
+        }
+        Ok(options)
+    }
+}
+
+fn at_least_one(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(n) => Ok(n),
+        Err(e) => Err(e.to_string()),
+    }
+}
@@ -0,0 +1,551 @@
+//! Rust client of the Gummy Bear Search API
+//!
+//! `GbsClient` talks to a gbs server over HTTP, or to a `GbsService` embedded
+//! in the same process without going through the network. Requests are built
+//! with typed builders (see `query` for the query DSL) and documents are
+//! (de)serialized from and into the caller's types.
+//!
+//! ```no_run
+//! # async fn example() -> gbs::Result<()> {
+//! use gbs::client::query::{match_query, SearchRequest};
+//! use gbs::client::{BulkRequest, CreateIndexRequest, FieldType, GbsClient};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Article {
+//!     title: String,
+//! }
+//!
+//! let client = GbsClient::http("http://localhost:9200")?;
+//! client
+//!     .create_index("articles", &CreateIndexRequest::new().field("title", FieldType::Text))
+//!     .await?;
+//! client
+//!     .bulk(&BulkRequest::new().index("articles", "1", &Article { title: "Rust".into() })?)
+//!     .await?;
+//! let response = client
+//!     .search::<Article>("articles", &SearchRequest::new().query(match_query("title", "rust")))
+//!     .await?;
+//! for article in response.documents() {
+//!     println!("{}", article.title);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+/// Query DSL and search request builders, from [`crate::models::query`]
+pub use crate::models::query;
+mod response;
+
+pub use response::{Hit, IndexInfo, SearchHits, SearchResponse, TotalHits};
+
+use async_trait::async_trait;
+use axum::body::{Body, Bytes};
+use base64::Engine;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE, HOST};
+use hyper::{Method, Request, StatusCode, Uri};
+use hyper_util::rt::TokioIo;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use tower::Service;
+use tracing::debug;
+
+use crate::bulk_ops::{to_bulk_ndjson, BulkAction, BulkResponse};
+use crate::document::{Document, DocumentOperations};
+use crate::error::{GbsError, Result};
+use crate::server::GbsService;
+use query::SearchRequest;
+use response::CreateResponse;
+
+/// Where requests go
+#[derive(Clone)]
+enum Transport {
+    /// A server reached over HTTP/1.1, one connection per request
+    Http(Uri),
+    /// A service in this process
+    Embedded(GbsService),
+}
+
+/// Client of the Gummy Bear Search API
+#[derive(Clone)]
+pub struct GbsClient {
+    transport: Transport,
+    authorization: Option<String>,
+}
+
+impl GbsClient {
+    /// Client of the server at a base URL like `http://localhost:9200`
+    pub fn http(base_url: &str) -> Result<Self> {
+        let uri: Uri = base_url
+            .parse()
+            .map_err(|e| GbsError::InvalidRequest(format!("Invalid URL [{}]: {}", base_url, e)))?;
+        if uri.scheme_str() != Some("http") || uri.host().is_none() {
+            return Err(GbsError::InvalidRequest(format!(
+                "Invalid URL [{}]: expected http://host[:port]",
+                base_url
+            )));
+        }
+        Ok(Self {
+            transport: Transport::Http(uri),
+            authorization: None,
+        })
+    }
+
+    /// Client of a service embedded in this process
+    pub fn embedded(service: GbsService) -> Self {
+        Self {
+            transport: Transport::Embedded(service),
+            authorization: None,
+        }
+    }
+
+    /// Authenticate as a user
+    pub fn with_basic_auth(mut self, username: &str, password: &str) -> Self {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password));
+        self.authorization = Some(format!("Basic {}", credentials));
+        self
+    }
+
+    /// Authenticate with an API key
+    pub fn with_api_key(mut self, id: &str, api_key: &str) -> Self {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", id, api_key));
+        self.authorization = Some(format!("ApiKey {}", credentials));
+        self
+    }
+
+    /// Create an index
+    pub async fn create_index(&self, index: &str, request: &CreateIndexRequest) -> Result<()> {
+        self.send_json(Method::PUT, &path(&[index]), Some(&request.to_json()))
+            .await?;
+        Ok(())
+    }
+
+    /// Check if an index (or alias) exists
+    pub async fn index_exists(&self, index: &str) -> Result<bool> {
+        let (status, _) = self.send(Method::HEAD, &path(&[index]), None).await?;
+        match status {
+            StatusCode::OK => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            status => Err(GbsError::Remote {
+                status,
+                reason: format!("Checking index [{}] failed", index),
+            }),
+        }
+    }
+
+    /// Get the settings, mappings and aliases of an index
+    pub async fn get_index(&self, index: &str) -> Result<IndexInfo> {
+        // Typeless mappings, whatever version the server emulates
+        let path = format!("{}?include_type_name=false", path(&[index]));
+        let mut response: std::collections::HashMap<String, IndexInfo> = self
+            .send_json(Method::GET, &path, None)
+            .await
+            .and_then(|body| Ok(serde_json::from_value(body)?))?;
+        response
+            .remove(index)
+            .ok_or_else(|| GbsError::IndexNotFound(index.to_string()))
+    }
+
+    /// Delete an index
+    pub async fn delete_index(&self, index: &str) -> Result<()> {
+        self.send_json(Method::DELETE, &path(&[index]), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Refresh an index
+    pub async fn refresh(&self, index: &str) -> Result<()> {
+        self.send_json(Method::POST, &path(&[index, "_refresh"]), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Index a document under an ID, replacing any document with that ID
+    pub async fn index_document<T: Serialize>(
+        &self,
+        index: &str,
+        id: &str,
+        document: &T,
+    ) -> Result<()> {
+        let document = serde_json::to_value(document)?;
+        self.send_json(Method::PUT, &path(&[index, "_doc", id]), Some(&document))
+            .await?;
+        Ok(())
+    }
+
+    /// Index a document under a generated ID, which is returned
+    pub async fn create_document<T: Serialize>(&self, index: &str, document: &T) -> Result<String> {
+        let document = serde_json::to_value(document)?;
+        let response = self
+            .send_json(Method::POST, &path(&[index, "_doc"]), Some(&document))
+            .await?;
+        let response: CreateResponse = serde_json::from_value(response)?;
+        Ok(response.id)
+    }
+
+    /// Get a document
+    ///
+    /// Returns `None` if the index or the document does not exist. See
+    /// [`DocumentOperations::get_typed`] for the metadata of the document.
+    pub async fn get_document<T: DeserializeOwned + Send>(
+        &self,
+        index: &str,
+        id: &str,
+    ) -> Result<Option<T>> {
+        Ok(self
+            .get_typed(index, id)
+            .await?
+            .map(|document| document.source))
+    }
+
+    /// Delete a document
+    pub async fn delete_document(&self, index: &str, id: &str) -> Result<()> {
+        self.send_json(Method::DELETE, &path(&[index, "_doc", id]), None)
+            .await?;
+        Ok(())
+    }
+
+    /// Execute bulk actions
+    ///
+    /// Actions fail one by one: check `errors` and the status of each item.
+    pub async fn bulk(&self, request: &BulkRequest) -> Result<BulkResponse> {
+        let body = Bytes::from(to_bulk_ndjson(&request.actions));
+        let response = self
+            .send(Method::POST, "/_bulk", Some((body, "application/x-ndjson")))
+            .await
+            .and_then(json_response)?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    /// Search an index expression (an index, alias, `a,b` list or `logs-*`
+    /// pattern)
+    pub async fn search<T: DeserializeOwned>(
+        &self,
+        index: &str,
+        request: &SearchRequest,
+    ) -> Result<SearchResponse<T>> {
+        // `hits.total` as an object, whatever version the server emulates
+        let path = format!("{}?rest_total_hits_as_int=false", path(&[index, "_search"]));
+        let response = self
+            .send_json(Method::POST, &path, Some(&request.to_json()))
+            .await?;
+        Ok(serde_json::from_value(response)?)
+    }
+
+    /// Send a request with an optional JSON body and return the JSON response
+    async fn send_json(&self, method: Method, path: &str, body: Option<&Value>) -> Result<Value> {
+        let body = body.map(|body| (Bytes::from(body.to_string()), "application/json"));
+        self.send(method, path, body).await.and_then(json_response)
+    }
+
+    /// Send a request and return the status and body of the response
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(Bytes, &str)>,
+    ) -> Result<(StatusCode, Bytes)> {
+        debug!("Client request {} {}", method, path);
+        let mut request = Request::builder().method(method).uri(path);
+        if let Some(authorization) = &self.authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        if let Some((_, content_type)) = &body {
+            request = request.header(CONTENT_TYPE, *content_type);
+        }
+        let body = body.map(|(body, _)| body).unwrap_or_default();
+
+        match &self.transport {
+            Transport::Http(uri) => {
+                let authority = uri.authority().map(|a| a.as_str()).unwrap_or_default();
+                let request = request
+                    .header(HOST, authority)
+                    .body(Full::new(body))
+                    .map_err(|e| GbsError::InvalidRequest(e.to_string()))?;
+                send_http(uri, request).await
+            }
+            Transport::Embedded(service) => {
+                let request = request
+                    .body(Body::from(body))
+                    .map_err(|e| GbsError::InvalidRequest(e.to_string()))?;
+                let mut service = service.clone();
+                let response = match std::future::poll_fn(|cx| service.poll_ready(cx)).await {
+                    Ok(()) => match service.call(request).await {
+                        Ok(response) => response,
+                        Err(infallible) => match infallible {},
+                    },
+                    Err(infallible) => match infallible {},
+                };
+                let status = response.status();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .map_err(|e| GbsError::Upstream(e.to_string()))?;
+                Ok((status, body))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl DocumentOperations for GbsClient {
+    async fn index_typed<T>(&self, index: &str, id: &str, document: &T) -> Result<()>
+    where
+        T: Serialize + Sync,
+    {
+        self.index_document(index, id, document).await
+    }
+
+    async fn get_typed<T>(&self, index: &str, id: &str) -> Result<Option<Document<T>>>
+    where
+        T: DeserializeOwned + Send,
+    {
+        match self
+            .send_json(Method::GET, &path(&[index, "_doc", id]), None)
+            .await
+        {
+            Ok(response) => Ok(Some(serde_json::from_value(response)?)),
+            Err(GbsError::Remote {
+                status: StatusCode::NOT_FOUND,
+                ..
+            }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn search_typed<T>(&self, index: &str, request: &SearchRequest) -> Result<Vec<Hit<T>>>
+    where
+        T: DeserializeOwned + Send,
+    {
+        Ok(self.search(index, request).await?.hits.hits)
+    }
+}
+
+async fn send_http(uri: &Uri, request: Request<Full<Bytes>>) -> Result<(StatusCode, Bytes)> {
+    let unreachable =
+        |reason: String| GbsError::Upstream(format!("Request to {} failed: {}", uri, reason));
+    let host = uri.host().unwrap_or_default();
+    let port = uri.port_u16().unwrap_or(80);
+    let stream = tokio::net::TcpStream::connect((host, port))
+        .await
+        .map_err(|e| unreachable(format!("connection failed: {}", e)))?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| unreachable(e.to_string()))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Client connection closed with error: {}", e);
+        }
+    });
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| unreachable(e.to_string()))?;
+    let status = response.status();
+    let body = response
+        .into_body()
+        .collect()
+        .await
+        .map_err(|e| unreachable(e.to_string()))?
+        .to_bytes();
+    Ok((status, body))
+}
+
+/// Body of a successful response as JSON, or the error it reports
+fn json_response((status, body): (StatusCode, Bytes)) -> Result<Value> {
+    let parsed: Option<Value> = serde_json::from_slice(&body).ok();
+    if status.is_success() {
+        return Ok(parsed.unwrap_or(Value::Null));
+    }
+    let reason = parsed
+        .as_ref()
+        .and_then(|body| body["error"]["reason"].as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned());
+    Err(GbsError::Remote { status, reason })
+}
+
+/// Path of the given segments, each percent-encoded
+fn path(segments: &[&str]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        path.push('/');
+        for byte in segment.bytes() {
+            if byte.is_ascii_alphanumeric() || b"-._~*,<>{}".contains(&byte) {
+                path.push(byte as char);
+            } else {
+                path.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    path
+}
+
+/// Type of a mapped field
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Text,
+    Keyword,
+    Long,
+    Integer,
+    Double,
+    Float,
+    Boolean,
+    Date,
+    Object,
+    Nested,
+}
+
+impl FieldType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Keyword => "keyword",
+            FieldType::Long => "long",
+            FieldType::Integer => "integer",
+            FieldType::Double => "double",
+            FieldType::Float => "float",
+            FieldType::Boolean => "boolean",
+            FieldType::Date => "date",
+            FieldType::Object => "object",
+            FieldType::Nested => "nested",
+        }
+    }
+}
+
+/// Body of a create index request
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CreateIndexRequest {
+    settings: serde_json::Map<String, Value>,
+    properties: serde_json::Map<String, Value>,
+    aliases: Vec<String>,
+}
+
+impl CreateIndexRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set an index setting, e.g. `index.retention.max_age`
+    pub fn setting(mut self, key: impl Into<String>, value: impl Into<Value>) -> Self {
+        self.settings.insert(key.into(), value.into());
+        self
+    }
+
+    pub fn shards(self, shards: u32) -> Self {
+        self.setting("number_of_shards", shards)
+    }
+
+    pub fn replicas(self, replicas: u32) -> Self {
+        self.setting("number_of_replicas", replicas)
+    }
+
+    /// Map a field to a type
+    pub fn field(self, name: impl Into<String>, field_type: FieldType) -> Self {
+        self.field_mapping(name, json!({ "type": field_type.as_str() }))
+    }
+
+    /// Map a field with a mapping given in full, e.g. with a date `format`
+    pub fn field_mapping(mut self, name: impl Into<String>, mapping: Value) -> Self {
+        self.properties.insert(name.into(), mapping);
+        self
+    }
+
+    /// Point an alias at the index
+    pub fn alias(mut self, alias: impl Into<String>) -> Self {
+        self.aliases.push(alias.into());
+        self
+    }
+
+    /// The request as a create index request body
+    pub fn to_json(&self) -> Value {
+        let mut body = serde_json::Map::new();
+        if !self.settings.is_empty() {
+            body.insert("settings".to_string(), json!(self.settings));
+        }
+        if !self.properties.is_empty() {
+            body.insert(
+                "mappings".to_string(),
+                json!({ "properties": self.properties }),
+            );
+        }
+        if !self.aliases.is_empty() {
+            let aliases: serde_json::Map<String, Value> = self
+                .aliases
+                .iter()
+                .map(|alias| (alias.clone(), json!({})))
+                .collect();
+            body.insert("aliases".to_string(), Value::Object(aliases));
+        }
+        Value::Object(body)
+    }
+}
+
+/// Actions of a bulk request, executed in order
+#[derive(Debug, Clone, Default)]
+pub struct BulkRequest {
+    actions: Vec<BulkAction>,
+}
+
+impl BulkRequest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Index a document under an ID, replacing any document with that ID
+    pub fn index<T: Serialize>(mut self, index: &str, id: &str, document: &T) -> Result<Self> {
+        self.actions.push(BulkAction::Index {
+            index: index.to_string(),
+            id: Some(id.to_string()),
+            document: serde_json::to_value(document)?,
+        });
+        Ok(self)
+    }
+
+    /// Index a document unless one with the ID exists; without an ID, one is
+    /// generated
+    pub fn create<T: Serialize>(
+        mut self,
+        index: &str,
+        id: Option<&str>,
+        document: &T,
+    ) -> Result<Self> {
+        self.actions.push(BulkAction::Create {
+            index: index.to_string(),
+            id: id.map(str::to_string),
+            document: serde_json::to_value(document)?,
+        });
+        Ok(self)
+    }
+
+    /// Merge fields into a document, creating it if it does not exist
+    pub fn update<T: Serialize>(mut self, index: &str, id: &str, fields: &T) -> Result<Self> {
+        self.actions.push(BulkAction::Update {
+            index: index.to_string(),
+            id: id.to_string(),
+            document: serde_json::to_value(fields)?,
+        });
+        Ok(self)
+    }
+
+    /// Delete a document
+    pub fn delete(mut self, index: &str, id: &str) -> Self {
+        self.actions.push(BulkAction::Delete {
+            index: index.to_string(),
+            id: id.to_string(),
+        });
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.actions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+}
@@ -0,0 +1,85 @@
+//! Typed responses of the client
+//!
+//! Documents are deserialized into the caller's type; everything the server
+//! reports besides them is kept as JSON only where it has no fixed shape
+//! (aggregations, index settings and mappings).
+
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// Response of a search
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchResponse<T> {
+    /// Time the search took in milliseconds
+    pub took: u64,
+    pub timed_out: bool,
+    pub hits: SearchHits<T>,
+    /// Results of the aggregations, by name
+    #[serde(default)]
+    pub aggregations: Option<serde_json::Value>,
+}
+
+impl<T> SearchResponse<T> {
+    /// Documents of the hits, in order
+    pub fn documents(&self) -> impl Iterator<Item = &T> {
+        self.hits.hits.iter().filter_map(|hit| hit.source.as_ref())
+    }
+}
+
+/// Hits of a search
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchHits<T> {
+    pub total: TotalHits,
+    pub max_score: Option<f64>,
+    pub hits: Vec<Hit<T>>,
+}
+
+/// Number of documents that matched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub struct TotalHits {
+    pub value: u64,
+}
+
+/// A document that matched a search
+#[derive(Debug, Clone, Deserialize)]
+pub struct Hit<T> {
+    #[serde(rename = "_index")]
+    pub index: String,
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "_score")]
+    pub score: Option<f64>,
+    /// The document, unless the search left out sources
+    #[serde(rename = "_source")]
+    pub source: Option<T>,
+    /// Highlighted fragments, by field
+    #[serde(default)]
+    pub highlight: HashMap<String, Vec<String>>,
+}
+
+/// Settings, mappings and aliases of an index
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexInfo {
+    pub settings: Option<serde_json::Value>,
+    pub mappings: Option<serde_json::Value>,
+    #[serde(default, deserialize_with = "alias_names")]
+    pub aliases: Vec<String>,
+}
+
+/// Response of creating a document with a generated ID
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct CreateResponse {
+    #[serde(rename = "_id")]
+    pub id: String,
+}
+
+/// Aliases are listed as an object keyed by alias name
+fn alias_names<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let aliases: HashMap<String, serde_json::Value> = HashMap::deserialize(deserializer)?;
+    let mut names: Vec<String> = aliases.into_keys().collect();
+    names.sort();
+    Ok(names)
+}
@@ -1,7 +1,10 @@
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
+use crate::document_cache::DEFAULT_DOCUMENT_CACHE_BYTES;
+
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
@@ -15,6 +18,38 @@ pub struct Config {
     /// Elasticsearch compatibility version (default: "6.8.23")
     #[serde(default = "default_es_version")]
     pub es_version: String,
+    /// Emulate the request and response shapes of the major version of
+    /// `es_version` (default: false, the 7.x shapes whatever the version)
+    #[serde(default)]
+    pub emulate_es_version: bool,
+    /// Security configuration
+    #[serde(default)]
+    pub security: SecurityConfig,
+    /// Background operation (pid and log file)
+    #[serde(default)]
+    pub daemon: DaemonConfig,
+    /// Content-based routing of writes to derived indices
+    #[serde(default)]
+    pub ingest: IngestConfig,
+    /// Usage accounting per index and API key
+    #[serde(default)]
+    pub usage: UsageConfig,
+    /// Indices served read-through from external sources
+    #[serde(default)]
+    pub federation: FederationConfig,
+    /// Requests gbs does not support, forwarded to or replayed for a real
+    /// Elasticsearch cluster
+    #[serde(default)]
+    pub proxy: ProxyConfig,
+    /// Execution of searches
+    #[serde(default)]
+    pub search: SearchConfig,
+    /// Audit log of write and admin operations
+    #[serde(default)]
+    pub audit: AuditConfig,
+    /// Request rate limits per client
+    #[serde(default)]
+    pub rate_limit: RateLimitConfig,
 }
 
 /// Server configuration
@@ -27,6 +62,55 @@ pub struct ServerConfig {
     /// Server port (default: 9200)
     #[serde(default = "default_port")]
     pub port: u16,
+    /// Run the storage self-test before accepting traffic (default: true)
+    #[serde(default = "default_self_test")]
+    pub self_test: bool,
+    /// Maximum request body size in bytes; larger requests are rejected with
+    /// 413 (default: 104857600, i.e. 100 MiB)
+    #[serde(default = "default_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Maximum number of actions in a bulk request (default: 100000)
+    #[serde(default = "default_max_bulk_actions")]
+    pub max_bulk_actions: usize,
+    /// Maximum size of a single document in bytes (default: 10485760, i.e. 10 MiB)
+    #[serde(default = "default_max_document_bytes")]
+    pub max_document_bytes: usize,
+    /// Maximum number of write requests served at once (default: the number
+    /// of CPUs)
+    #[serde(default = "default_max_concurrent_writes")]
+    pub max_concurrent_writes: usize,
+    /// Maximum number of write requests waiting for another one to finish;
+    /// more are rejected with 429 (default: 10000)
+    #[serde(default = "default_write_queue_size")]
+    pub write_queue_size: usize,
+    /// How long shutdown waits for in-flight requests before flushing the
+    /// storage and exiting (default: 30)
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+    /// TLS termination of the HTTP listener (default: plain HTTP)
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+}
+
+/// TLS configuration of the HTTP listener
+///
+/// Requires a build with the `tls` feature (the default); builds without it
+/// refuse to start with this section rather than serve plain HTTP.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TlsConfig {
+    /// PEM certificate chain
+    pub cert_path: String,
+    /// PEM private key
+    pub key_path: String,
+    /// Port of a plain HTTP listener redirecting every request to https
+    /// (default: none)
+    #[serde(default)]
+    pub redirect_port: Option<u16>,
+    /// How often the certificate and key are read again, so renewed
+    /// certificates are served without a restart (default: 3600)
+    #[serde(default = "default_tls_reload_interval_secs")]
+    pub reload_interval_secs: u64,
 }
 
 /// Storage configuration
@@ -36,6 +120,198 @@ pub struct StorageConfig {
     /// Data directory path (default: "./data")
     #[serde(default = "default_data_dir")]
     pub data_dir: String,
+    /// Maximum number of indices (dot-prefixed system indices excluded);
+    /// index creation beyond it is refused (default: unlimited)
+    #[serde(default)]
+    pub max_indices: Option<usize>,
+    /// Automatic rollover of indices written through an alias
+    #[serde(default)]
+    pub auto_rollover: AutoRolloverConfig,
+    /// Hot/warm index tiering
+    #[serde(default)]
+    pub tiering: TieringConfig,
+    /// Background enforcement of the `index.retention.*` settings
+    #[serde(default)]
+    pub retention: RetentionConfig,
+    /// When document writes are flushed to disk (default: none)
+    #[serde(default)]
+    pub durability: Durability,
+    /// How often the background flusher runs in `async` durability mode,
+    /// in milliseconds (default: 1000)
+    #[serde(default = "default_flush_interval_ms")]
+    pub flush_interval_ms: u64,
+    /// Which missing indices a document write creates: `true`, `false` or
+    /// patterns such as `"+logs-*,-tmp-*"` (default: only indices an index
+    /// template matches)
+    #[serde(default)]
+    pub auto_create_index: Option<AutoCreateIndex>,
+    /// Load only index metadata at startup and read documents from disk on
+    /// demand, like warm indices, until an index is moved to the hot tier
+    /// (default: false)
+    #[serde(default)]
+    pub lazy_loading: bool,
+    /// Memory budget of the cache of documents read from disk, in bytes;
+    /// 0 disables it (default: 67108864)
+    #[serde(default = "default_document_cache_bytes")]
+    pub document_cache_bytes: u64,
+}
+
+/// Automatic creation of missing indices on document writes, like
+/// Elasticsearch's `action.auto_create_index`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "AutoCreateIndexValue", into = "AutoCreateIndexValue")]
+pub enum AutoCreateIndex {
+    /// Every missing index is created
+    All,
+    /// No index is created, even if an index template matches it
+    Disabled,
+    /// Indices matching the patterns (`*` wildcards) are created. The first
+    /// matching pattern decides: `+pattern` or `pattern` allows, `-pattern`
+    /// refuses. Names no pattern matches are not created.
+    Patterns(Vec<(bool, String)>),
+}
+
+impl AutoCreateIndex {
+    /// Parse `true`, `false` or a comma-separated pattern list
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "true" => return Some(AutoCreateIndex::All),
+            "false" => return Some(AutoCreateIndex::Disabled),
+            _ => {}
+        }
+        let patterns = value
+            .split(',')
+            .map(str::trim)
+            .filter(|pattern| !pattern.is_empty())
+            .map(|pattern| match pattern.strip_prefix('-') {
+                Some(pattern) => (false, pattern.to_string()),
+                None => (true, pattern.trim_start_matches('+').to_string()),
+            })
+            .collect::<Vec<_>>();
+        if patterns.is_empty() || patterns.iter().any(|(_, pattern)| pattern.is_empty()) {
+            return None;
+        }
+        Some(AutoCreateIndex::Patterns(patterns))
+    }
+}
+
+/// `auto_create_index` as written in the configuration file
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum AutoCreateIndexValue {
+    Enabled(bool),
+    Patterns(String),
+}
+
+impl TryFrom<AutoCreateIndexValue> for AutoCreateIndex {
+    type Error = String;
+
+    fn try_from(value: AutoCreateIndexValue) -> std::result::Result<Self, Self::Error> {
+        match value {
+            AutoCreateIndexValue::Enabled(true) => Ok(AutoCreateIndex::All),
+            AutoCreateIndexValue::Enabled(false) => Ok(AutoCreateIndex::Disabled),
+            AutoCreateIndexValue::Patterns(patterns) => AutoCreateIndex::parse(&patterns)
+                .ok_or_else(|| format!("invalid auto_create_index patterns: {}", patterns)),
+        }
+    }
+}
+
+impl From<AutoCreateIndex> for AutoCreateIndexValue {
+    fn from(value: AutoCreateIndex) -> Self {
+        match value {
+            AutoCreateIndex::All => AutoCreateIndexValue::Enabled(true),
+            AutoCreateIndex::Disabled => AutoCreateIndexValue::Enabled(false),
+            AutoCreateIndex::Patterns(patterns) => AutoCreateIndexValue::Patterns(
+                patterns
+                    .iter()
+                    .map(|(allow, pattern)| {
+                        format!("{}{}", if *allow { '+' } else { '-' }, pattern)
+                    })
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+        }
+    }
+}
+
+/// Durability of acknowledged document writes
+///
+/// Document writes go to sled's in-memory page cache, which sled itself
+/// writes out about every 500ms. Index metadata and users are always flushed
+/// before they are acknowledged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Durability {
+    /// No flushing beyond sled's own; a crash can lose recently acknowledged writes
+    #[default]
+    None,
+    /// A background task flushes every `flush_interval_ms`
+    Async,
+    /// Each write request is flushed before it is acknowledged
+    Request,
+}
+
+impl Durability {
+    /// Parse a durability mode (`none`, `async` or `request`)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "none" => Some(Durability::None),
+            "async" => Some(Durability::Async),
+            "request" => Some(Durability::Request),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Durability::None => "none",
+            Durability::Async => "async",
+            Durability::Request => "request",
+        }
+    }
+}
+
+/// Hot/warm tiering configuration
+///
+/// Warm indices are served from disk instead of memory. Indices can be moved
+/// between tiers through the API; with `warm_after_secs` set, hot indices are
+/// also demoted in the background once they reach that age.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct TieringConfig {
+    /// Demote indices to the warm tier once they are this old (default: disabled)
+    #[serde(default)]
+    pub warm_after_secs: Option<u64>,
+    /// How often to check index ages (default: 300)
+    #[serde(default = "default_tiering_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+/// Retention configuration
+///
+/// Indices opt in to retention with the `index.retention.*` settings, which a
+/// background task applies at this interval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RetentionConfig {
+    /// How often to apply the retention settings (default: 60)
+    #[serde(default = "default_retention_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+/// Automatic rollover configuration
+///
+/// When a write through an alias leaves the target index above one of these
+/// limits, a new index is created and the alias is moved to it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AutoRolloverConfig {
+    /// Roll over once the index holds this many documents (default: disabled)
+    #[serde(default)]
+    pub max_docs: Option<usize>,
+    /// Roll over once the index documents exceed this many bytes (default: disabled)
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
 }
 
 /// Logging configuration
@@ -46,6 +322,395 @@ pub struct LoggingConfig {
     /// Valid values: trace, debug, info, warn, error
     #[serde(default = "default_log_level")]
     pub level: String,
+    /// Log line format: text or json (default: text)
+    #[serde(default)]
+    pub format: LogFormat,
+}
+
+/// Format of log lines
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines, colored on a terminal
+    #[default]
+    Text,
+    /// One JSON object per line, with the fields of the enclosing spans
+    Json,
+}
+
+impl LogFormat {
+    /// Parse a log format (`text` or `json`)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "text" => Some(LogFormat::Text),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogFormat::Text => "text",
+            LogFormat::Json => "json",
+        }
+    }
+}
+
+/// Security configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SecurityConfig {
+    /// Enable authentication (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Users created on startup (existing users with the same name are overwritten)
+    #[serde(default)]
+    pub users: Vec<UserConfig>,
+    /// API keys accepted in `Authorization: ApiKey` headers
+    #[serde(default)]
+    pub api_keys: Vec<ApiKeyConfig>,
+    /// YAML file with more `users` and `api_keys`, read on startup
+    #[serde(default)]
+    pub file: Option<String>,
+}
+
+/// Background operation configuration
+///
+/// Every running server writes its process ID to the pid file, which
+/// `gbs status` and `gbs stop` read. Servers started with `gbs start` append
+/// their output to the log file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct DaemonConfig {
+    /// Pid file path (default: "<data_dir>/gbs.pid")
+    #[serde(default)]
+    pub pid_file: Option<String>,
+    /// Log file of background servers (default: "<data_dir>/gbs.log")
+    #[serde(default)]
+    pub log_file: Option<String>,
+}
+
+/// Ingest configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct IngestConfig {
+    /// Logical write targets routed to indices derived from document fields
+    #[serde(default)]
+    pub routes: Vec<IngestRouteConfig>,
+}
+
+/// Ingest route: writes to `name` go to the index derived from `index_pattern`
+///
+/// The pattern holds `{field}` or `{field:format}` placeholders, e.g.
+/// `logs-{timestamp:yyyy.MM.dd}`. Missing target indices are created with the
+/// route's settings and mappings.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct IngestRouteConfig {
+    pub name: String,
+    pub index_pattern: String,
+    /// Settings of created target indices
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    /// Mappings of created target indices
+    #[serde(default)]
+    pub mappings: Option<serde_json::Value>,
+}
+
+/// Federation configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FederationConfig {
+    /// Indices whose documents are fetched from an external HTTP source
+    #[serde(default)]
+    pub indices: Vec<FederatedIndexConfig>,
+}
+
+/// Index served read-through from an external HTTP source
+///
+/// Searches and document lookups fetch the source's documents once the
+/// cached copy is older than `ttl_secs`, and serve them from the local index.
+/// SQL sources are reached through an HTTP SQL endpoint, with the statement
+/// as the request `body`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct FederatedIndexConfig {
+    /// Name of the local index
+    pub index: String,
+    /// URL the documents are fetched from (plain `http://`)
+    pub url: String,
+    /// HTTP method of the fetch request (default: "GET")
+    #[serde(default = "default_federation_method")]
+    pub method: String,
+    /// Body of the fetch request, e.g. a SQL statement (default: none)
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Headers of the fetch request
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+    /// JSON pointer to the array of documents in the response, e.g. "/data"
+    /// (default: the response itself)
+    #[serde(default)]
+    pub documents_path: String,
+    /// Document field holding the document ID (default: "id")
+    #[serde(default = "default_federation_id_field")]
+    pub id_field: String,
+    /// How long fetched documents are served before fetching again (default: 300)
+    #[serde(default = "default_federation_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Timeout of the fetch request (default: 30)
+    #[serde(default = "default_federation_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Settings of the local index
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    /// Mappings of the local index
+    #[serde(default)]
+    pub mappings: Option<serde_json::Value>,
+}
+
+/// Usage accounting configuration
+///
+/// Usage is kept in memory as totals since startup and a history of
+/// `history_buckets` buckets of `bucket_secs` each.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UsageConfig {
+    /// Length of a history bucket in seconds (default: 300)
+    #[serde(default = "default_usage_bucket_secs")]
+    pub bucket_secs: u64,
+    /// Number of history buckets kept (default: 288, i.e. one day of 5 minute buckets)
+    #[serde(default = "default_usage_history_buckets")]
+    pub history_buckets: usize,
+}
+
+/// Search configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct SearchConfig {
+    /// Maximum number of indices a multi-index search runs on at the same
+    /// time; the `max_concurrent_shard_requests` parameter overrides it per
+    /// request (default: 5)
+    #[serde(default = "default_max_concurrent_index_searches")]
+    pub max_concurrent_index_searches: usize,
+    /// Time budget of searches without a `timeout`, in milliseconds; a search
+    /// running longer returns the hits found so far with `timed_out: true`
+    /// (default: no limit)
+    #[serde(default)]
+    pub default_timeout_ms: Option<u64>,
+    /// Score the documents of large indices on several threads (default: true)
+    #[serde(default = "default_parallel_scoring")]
+    pub parallel_scoring: bool,
+    /// Number of documents from which an index is scored on several threads
+    /// (default: 50000)
+    #[serde(default = "default_parallel_scoring_min_docs")]
+    pub parallel_scoring_min_docs: usize,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_index_searches: default_max_concurrent_index_searches(),
+            default_timeout_ms: None,
+            parallel_scoring: default_parallel_scoring(),
+            parallel_scoring_min_docs: default_parallel_scoring_min_docs(),
+        }
+    }
+}
+
+/// Proxy configuration
+///
+/// Requests that match no gbs route, or a route without their method, are
+/// handled according to `mode`, so gbs can stand in for an Elasticsearch
+/// cluster before it supports every API a client uses.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ProxyConfig {
+    /// What happens to unsupported requests (default: off)
+    #[serde(default)]
+    pub mode: ProxyMode,
+    /// URL of the upstream cluster (plain `http://`), required to forward
+    /// and record
+    #[serde(default)]
+    pub upstream_url: Option<String>,
+    /// File the request/response pairs are recorded to and replayed from
+    /// (default: "<data_dir>/proxy-recordings.ndjson")
+    #[serde(default)]
+    pub recordings_file: Option<String>,
+    /// Timeout of an upstream request in seconds (default: 30)
+    #[serde(default = "default_proxy_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            mode: ProxyMode::default(),
+            upstream_url: None,
+            recordings_file: None,
+            timeout_secs: default_proxy_timeout_secs(),
+        }
+    }
+}
+
+/// What happens to requests gbs does not support
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyMode {
+    /// They are answered with 404 (or 405 for a known path)
+    #[default]
+    Off,
+    /// They are forwarded to the upstream cluster
+    Forward,
+    /// They are forwarded and the request/response pairs recorded
+    Record,
+    /// Recorded responses are served, without an upstream cluster
+    Replay,
+}
+
+impl ProxyMode {
+    /// Parse a proxy mode (`off`, `forward`, `record` or `replay`)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "off" => Some(ProxyMode::Off),
+            "forward" => Some(ProxyMode::Forward),
+            "record" => Some(ProxyMode::Record),
+            "replay" => Some(ProxyMode::Replay),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProxyMode::Off => "off",
+            ProxyMode::Forward => "forward",
+            ProxyMode::Record => "record",
+            ProxyMode::Replay => "replay",
+        }
+    }
+}
+
+/// Audit log configuration
+///
+/// When enabled, every request that changes data or settings is recorded
+/// with its caller, client address and outcome, either appended to an NDJSON
+/// file or indexed into a system index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct AuditConfig {
+    /// Record write and admin operations (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Where events are recorded (default: file)
+    #[serde(default)]
+    pub output: AuditOutput,
+    /// Audit file of the `file` output (default: "<data_dir>/audit.ndjson")
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Index of the `index` output (default: ".gbs-audit")
+    #[serde(default = "default_audit_index")]
+    pub index: String,
+}
+
+/// Rate limiting of requests per client
+///
+/// Each client has a bucket of `burst` requests, refilled at
+/// `requests_per_second`; requests finding the bucket empty are rejected with
+/// 429. Clients are told apart by their API key when security is enabled,
+/// and by their IP address otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct RateLimitConfig {
+    /// Limit the request rate of clients (default: false)
+    #[serde(default)]
+    pub enabled: bool,
+    /// Sustained requests per second of a client (default: 50)
+    #[serde(default = "default_rate_limit_requests_per_second")]
+    pub requests_per_second: f64,
+    /// Requests a client may send at once after being idle (default: 100)
+    #[serde(default = "default_rate_limit_burst")]
+    pub burst: u32,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            requests_per_second: default_rate_limit_requests_per_second(),
+            burst: default_rate_limit_burst(),
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            output: AuditOutput::default(),
+            file: None,
+            index: default_audit_index(),
+        }
+    }
+}
+
+/// Where audit events are recorded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutput {
+    /// Appended to an NDJSON file, one event per line
+    #[default]
+    File,
+    /// Indexed as documents of a system index
+    Index,
+}
+
+impl AuditOutput {
+    /// Parse an audit output (`file` or `index`)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_ascii_lowercase().as_str() {
+            "file" => Some(AuditOutput::File),
+            "index" => Some(AuditOutput::Index),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditOutput::File => "file",
+            AuditOutput::Index => "index",
+        }
+    }
+}
+
+/// Statically configured user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct UserConfig {
+    pub username: String,
+    pub password: String,
+    /// Roles granted to the user (default: ["superuser"])
+    #[serde(default = "default_user_roles")]
+    pub roles: Vec<String>,
+}
+
+/// Statically configured API key
+///
+/// Clients authenticate with the base64 encoding of `id:key`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub struct ApiKeyConfig {
+    pub id: String,
+    pub key: String,
+    /// Name of the key, also reported as its owner (default: the ID)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Roles granted to the key (default: ["superuser"])
+    #[serde(default = "default_user_roles")]
+    pub roles: Vec<String>,
+}
+
+fn default_user_roles() -> Vec<String> {
+    vec!["superuser".to_string()]
 }
 
 fn default_host() -> String {
@@ -56,32 +721,198 @@ fn default_port() -> u16 {
     9200
 }
 
+fn default_self_test() -> bool {
+    true
+}
+
+fn default_max_body_bytes() -> usize {
+    100 * 1024 * 1024
+}
+
+fn default_max_bulk_actions() -> usize {
+    100_000
+}
+
+fn default_max_document_bytes() -> usize {
+    10 * 1024 * 1024
+}
+
+fn default_max_concurrent_writes() -> usize {
+    std::thread::available_parallelism().map_or(1, |cpus| cpus.get())
+}
+
+fn default_write_queue_size() -> usize {
+    10_000
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    30
+}
+
+fn default_tls_reload_interval_secs() -> u64 {
+    3600
+}
+
 fn default_data_dir() -> String {
     "./data".to_string()
 }
 
+fn default_tiering_check_interval_secs() -> u64 {
+    300
+}
+
+fn default_retention_check_interval_secs() -> u64 {
+    60
+}
+
+fn default_flush_interval_ms() -> u64 {
+    1000
+}
+
+fn default_document_cache_bytes() -> u64 {
+    DEFAULT_DOCUMENT_CACHE_BYTES
+}
+
+fn default_federation_method() -> String {
+    "GET".to_string()
+}
+
+fn default_federation_id_field() -> String {
+    "id".to_string()
+}
+
+fn default_federation_ttl_secs() -> u64 {
+    300
+}
+
+fn default_federation_timeout_secs() -> u64 {
+    30
+}
+
+fn default_usage_bucket_secs() -> u64 {
+    300
+}
+
+fn default_usage_history_buckets() -> usize {
+    288
+}
+
 fn default_log_level() -> String {
     "info".to_string()
 }
 
-fn default_es_version() -> String {
+fn default_max_concurrent_index_searches() -> usize {
+    5
+}
+
+fn default_parallel_scoring() -> bool {
+    true
+}
+
+fn default_parallel_scoring_min_docs() -> usize {
+    50_000
+}
+
+fn default_proxy_timeout_secs() -> u64 {
+    30
+}
+
+fn default_audit_index() -> String {
+    ".gbs-audit".to_string()
+}
+
+fn default_rate_limit_requests_per_second() -> f64 {
+    50.0
+}
+
+fn default_rate_limit_burst() -> u32 {
+    100
+}
+
+pub(crate) fn default_es_version() -> String {
     "6.8.23".to_string()
 }
 
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            host: default_host(),
+            port: default_port(),
+            self_test: default_self_test(),
+            max_body_bytes: default_max_body_bytes(),
+            max_bulk_actions: default_max_bulk_actions(),
+            max_document_bytes: default_max_document_bytes(),
+            max_concurrent_writes: default_max_concurrent_writes(),
+            write_queue_size: default_write_queue_size(),
+            shutdown_timeout_secs: default_shutdown_timeout_secs(),
+            tls: None,
+        }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig {
+            check_interval_secs: default_retention_check_interval_secs(),
+        }
+    }
+}
+
+impl Default for TieringConfig {
+    fn default() -> Self {
+        TieringConfig {
+            warm_after_secs: None,
+            check_interval_secs: default_tiering_check_interval_secs(),
+        }
+    }
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            bucket_secs: default_usage_bucket_secs(),
+            history_buckets: default_usage_history_buckets(),
+        }
+    }
+}
+
+impl Default for StorageConfig {
+    fn default() -> Self {
+        StorageConfig {
+            data_dir: default_data_dir(),
+            max_indices: None,
+            auto_rollover: AutoRolloverConfig::default(),
+            tiering: TieringConfig::default(),
+            retention: RetentionConfig::default(),
+            durability: Durability::default(),
+            flush_interval_ms: default_flush_interval_ms(),
+            auto_create_index: None,
+            lazy_loading: false,
+            document_cache_bytes: default_document_cache_bytes(),
+        }
+    }
+}
+
 impl Default for Config {
     fn default() -> Self {
         Config {
-            server: ServerConfig {
-                host: default_host(),
-                port: default_port(),
-            },
-            storage: StorageConfig {
-                data_dir: default_data_dir(),
-            },
+            server: ServerConfig::default(),
+            storage: StorageConfig::default(),
             logging: LoggingConfig {
                 level: default_log_level(),
+                format: LogFormat::default(),
             },
             es_version: default_es_version(),
+            emulate_es_version: false,
+            security: SecurityConfig::default(),
+            daemon: DaemonConfig::default(),
+            ingest: IngestConfig::default(),
+            usage: UsageConfig::default(),
+            federation: FederationConfig::default(),
+            proxy: ProxyConfig::default(),
+            search: SearchConfig::default(),
+            audit: AuditConfig::default(),
+            rate_limit: RateLimitConfig::default(),
         }
     }
 }
@@ -115,14 +946,47 @@ impl Config {
         Ok(config)
     }
 
-    /// Load configuration from YAML file
+    /// Load the configuration again, for a reload of a running server
+    ///
+    /// Unlike `load`, a config file that cannot be read or parsed is an
+    /// error, so a typo does not reset the server to the defaults.
+    pub fn reload() -> anyhow::Result<Self> {
+        let config = match Self::config_file_path() {
+            Some(path) => Self::read_file(&path)?,
+            None => Config::default(),
+        };
+        Ok(config.with_env_overrides())
+    }
+
+    /// Load configuration from the given YAML file, as `gbs --config <path>`
+    /// does
     ///
-    /// Tries to load from:
+    /// The file was named explicitly, so unlike `load` a file that cannot be
+    /// read or parsed is an error.
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        Ok(Self::read_file(path)?.with_env_overrides())
+    }
+
+    /// Load configuration from YAML file
+    fn load_from_file() -> anyhow::Result<Self> {
+        match Self::config_file_path() {
+            Some(path) => Self::read_file(&path),
+            None => anyhow::bail!("No config file found"),
+        }
+    }
+
+    fn read_file(path: &Path) -> anyhow::Result<Self> {
+        info!("Loading config from: {}", path.display());
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    /// Path of the config file, the first that exists of:
     /// 1. GUMMY_CONFIG environment variable (if set)
     /// 2. ./gbs.yaml
     /// 3. ./config/gbs.yaml
     /// 4. ~/.config/gbs/gbs.yaml
-    fn load_from_file() -> anyhow::Result<Self> {
+    fn config_file_path() -> Option<PathBuf> {
         let config_paths = vec![
             std::env::var("GUMMY_CONFIG").ok().map(PathBuf::from),
             Some(PathBuf::from("./gbs.yaml")),
@@ -134,17 +998,10 @@ impl Config {
                 p
             }),
         ];
-
-        for path in config_paths.into_iter().flatten() {
-            if path.exists() {
-                info!("Loading config from: {}", path.display());
-                let content = std::fs::read_to_string(&path)?;
-                let config: Config = serde_yaml::from_str(&content)?;
-                return Ok(config);
-            }
-        }
-
-        anyhow::bail!("No config file found")
+        config_paths
+            .into_iter()
+            .flatten()
+            .find(|path| path.exists())
     }
 
     /// Apply environment variable overrides
@@ -163,11 +1020,125 @@ impl Config {
             }
         }
 
+        // Startup self-test
+        if let Ok(self_test) = std::env::var("GUMMY_SELF_TEST") {
+            match self_test.parse::<bool>() {
+                Ok(self_test) => self.server.self_test = self_test,
+                Err(_) => warn!("Invalid GUMMY_SELF_TEST value: {}. Ignoring.", self_test),
+            }
+        }
+
+        // Request size limits
+        if let Ok(max_body_bytes) = std::env::var("GUMMY_MAX_BODY_BYTES") {
+            match max_body_bytes.parse::<usize>() {
+                Ok(max_body_bytes) => self.server.max_body_bytes = max_body_bytes,
+                Err(_) => warn!(
+                    "Invalid GUMMY_MAX_BODY_BYTES value: {}. Ignoring.",
+                    max_body_bytes
+                ),
+            }
+        }
+        if let Ok(max_bulk_actions) = std::env::var("GUMMY_MAX_BULK_ACTIONS") {
+            match max_bulk_actions.parse::<usize>() {
+                Ok(max_bulk_actions) => self.server.max_bulk_actions = max_bulk_actions,
+                Err(_) => warn!(
+                    "Invalid GUMMY_MAX_BULK_ACTIONS value: {}. Ignoring.",
+                    max_bulk_actions
+                ),
+            }
+        }
+        if let Ok(max_document_bytes) = std::env::var("GUMMY_MAX_DOCUMENT_BYTES") {
+            match max_document_bytes.parse::<usize>() {
+                Ok(max_document_bytes) => self.server.max_document_bytes = max_document_bytes,
+                Err(_) => warn!(
+                    "Invalid GUMMY_MAX_DOCUMENT_BYTES value: {}. Ignoring.",
+                    max_document_bytes
+                ),
+            }
+        }
+        if let Ok(max_writes) = std::env::var("GUMMY_MAX_CONCURRENT_WRITES") {
+            match max_writes.parse::<usize>() {
+                Ok(max_writes) if max_writes > 0 => self.server.max_concurrent_writes = max_writes,
+                _ => warn!(
+                    "Invalid GUMMY_MAX_CONCURRENT_WRITES value: {}. Ignoring.",
+                    max_writes
+                ),
+            }
+        }
+        if let Ok(queue_size) = std::env::var("GUMMY_WRITE_QUEUE_SIZE") {
+            match queue_size.parse::<usize>() {
+                Ok(queue_size) => self.server.write_queue_size = queue_size,
+                Err(_) => warn!(
+                    "Invalid GUMMY_WRITE_QUEUE_SIZE value: {}. Ignoring.",
+                    queue_size
+                ),
+            }
+        }
+        if let Ok(timeout) = std::env::var("GUMMY_SHUTDOWN_TIMEOUT_SECS") {
+            match timeout.parse::<u64>() {
+                Ok(timeout) => self.server.shutdown_timeout_secs = timeout,
+                Err(_) => warn!(
+                    "Invalid GUMMY_SHUTDOWN_TIMEOUT_SECS value: {}. Ignoring.",
+                    timeout
+                ),
+            }
+        }
+
         // Data directory
         if let Ok(data_dir) = std::env::var("GUMMY_DATA_DIR") {
             self.storage.data_dir = data_dir;
         }
 
+        // Maximum index count
+        if let Ok(max_indices) = std::env::var("GUMMY_MAX_INDICES") {
+            match max_indices.parse::<usize>() {
+                Ok(max_indices) => self.storage.max_indices = Some(max_indices),
+                Err(_) => warn!(
+                    "Invalid GUMMY_MAX_INDICES value: {}. Ignoring.",
+                    max_indices
+                ),
+            }
+        }
+
+        // Durability mode
+        if let Ok(durability) = std::env::var("GUMMY_DURABILITY") {
+            match Durability::parse(&durability) {
+                Some(durability) => self.storage.durability = durability,
+                None => warn!("Invalid GUMMY_DURABILITY value: {}. Ignoring.", durability),
+            }
+        }
+
+        // Automatic index creation
+        if let Ok(auto_create_index) = std::env::var("GUMMY_AUTO_CREATE_INDEX") {
+            match AutoCreateIndex::parse(&auto_create_index) {
+                Some(auto_create_index) => self.storage.auto_create_index = Some(auto_create_index),
+                None => warn!(
+                    "Invalid GUMMY_AUTO_CREATE_INDEX value: {}. Ignoring.",
+                    auto_create_index
+                ),
+            }
+        }
+
+        // Lazy loading and the document cache
+        if let Ok(lazy_loading) = std::env::var("GUMMY_LAZY_LOADING") {
+            match lazy_loading.parse::<bool>() {
+                Ok(lazy_loading) => self.storage.lazy_loading = lazy_loading,
+                Err(_) => warn!(
+                    "Invalid GUMMY_LAZY_LOADING value: {}. Ignoring.",
+                    lazy_loading
+                ),
+            }
+        }
+        if let Ok(cache_bytes) = std::env::var("GUMMY_DOCUMENT_CACHE_BYTES") {
+            match cache_bytes.parse::<u64>() {
+                Ok(cache_bytes) => self.storage.document_cache_bytes = cache_bytes,
+                Err(_) => warn!(
+                    "Invalid GUMMY_DOCUMENT_CACHE_BYTES value: {}. Ignoring.",
+                    cache_bytes
+                ),
+            }
+        }
+
         // Log level (RUST_LOG takes precedence if set)
         if std::env::var("RUST_LOG").is_ok() {
             // RUST_LOG is handled by tracing_subscriber, so we don't override here
@@ -175,15 +1146,187 @@ impl Config {
         } else if let Ok(level) = std::env::var("GUMMY_LOG_LEVEL") {
             self.logging.level = level;
         }
+        if let Ok(format) = std::env::var("GUMMY_LOG_FORMAT") {
+            match LogFormat::parse(&format) {
+                Some(format) => self.logging.format = format,
+                None => warn!("Invalid GUMMY_LOG_FORMAT value: {}. Ignoring.", format),
+            }
+        }
 
         // Elasticsearch version
         if let Ok(es_version) = std::env::var("GUMMY_ES_VERSION") {
             self.es_version = es_version;
         }
+        if let Ok(emulate) = std::env::var("GUMMY_EMULATE_ES_VERSION") {
+            match emulate.parse::<bool>() {
+                Ok(emulate) => self.emulate_es_version = emulate,
+                Err(_) => warn!(
+                    "Invalid GUMMY_EMULATE_ES_VERSION value: {}. Ignoring.",
+                    emulate
+                ),
+            }
+        }
+
+        // Security
+        if let Ok(enabled) = std::env::var("GUMMY_SECURITY_ENABLED") {
+            match enabled.parse::<bool>() {
+                Ok(enabled) => self.security.enabled = enabled,
+                Err(_) => warn!(
+                    "Invalid GUMMY_SECURITY_ENABLED value: {}. Ignoring.",
+                    enabled
+                ),
+            }
+        }
+        if let Ok(file) = std::env::var("GUMMY_SECURITY_FILE") {
+            self.security.file = Some(file);
+        }
+
+        // Proxy of unsupported requests
+        if let Ok(mode) = std::env::var("GUMMY_PROXY_MODE") {
+            match ProxyMode::parse(&mode) {
+                Some(mode) => self.proxy.mode = mode,
+                None => warn!("Invalid GUMMY_PROXY_MODE value: {}. Ignoring.", mode),
+            }
+        }
+        if let Ok(upstream_url) = std::env::var("GUMMY_PROXY_URL") {
+            self.proxy.upstream_url = Some(upstream_url);
+        }
+
+        // Search execution
+        if let Ok(max_searches) = std::env::var("GUMMY_MAX_CONCURRENT_INDEX_SEARCHES") {
+            match max_searches.parse::<usize>() {
+                Ok(max_searches) if max_searches > 0 => {
+                    self.search.max_concurrent_index_searches = max_searches
+                }
+                _ => warn!(
+                    "Invalid GUMMY_MAX_CONCURRENT_INDEX_SEARCHES value: {}. Ignoring.",
+                    max_searches
+                ),
+            }
+        }
+
+        if let Ok(timeout_ms) = std::env::var("GUMMY_SEARCH_TIMEOUT_MS") {
+            match timeout_ms.parse::<u64>() {
+                Ok(timeout_ms) => self.search.default_timeout_ms = Some(timeout_ms),
+                Err(_) => warn!(
+                    "Invalid GUMMY_SEARCH_TIMEOUT_MS value: {}. Ignoring.",
+                    timeout_ms
+                ),
+            }
+        }
+
+        if let Ok(enabled) = std::env::var("GUMMY_PARALLEL_SCORING") {
+            match enabled.parse::<bool>() {
+                Ok(enabled) => self.search.parallel_scoring = enabled,
+                Err(_) => warn!(
+                    "Invalid GUMMY_PARALLEL_SCORING value: {}. Ignoring.",
+                    enabled
+                ),
+            }
+        }
+        if let Ok(min_docs) = std::env::var("GUMMY_PARALLEL_SCORING_MIN_DOCS") {
+            match min_docs.parse::<usize>() {
+                Ok(min_docs) => self.search.parallel_scoring_min_docs = min_docs,
+                Err(_) => warn!(
+                    "Invalid GUMMY_PARALLEL_SCORING_MIN_DOCS value: {}. Ignoring.",
+                    min_docs
+                ),
+            }
+        }
+
+        // Audit log
+        if let Ok(enabled) = std::env::var("GUMMY_AUDIT_ENABLED") {
+            match enabled.parse::<bool>() {
+                Ok(enabled) => self.audit.enabled = enabled,
+                Err(_) => warn!("Invalid GUMMY_AUDIT_ENABLED value: {}. Ignoring.", enabled),
+            }
+        }
+        if let Ok(output) = std::env::var("GUMMY_AUDIT_OUTPUT") {
+            match AuditOutput::parse(&output) {
+                Some(output) => self.audit.output = output,
+                None => warn!("Invalid GUMMY_AUDIT_OUTPUT value: {}. Ignoring.", output),
+            }
+        }
+        if let Ok(file) = std::env::var("GUMMY_AUDIT_FILE") {
+            self.audit.file = Some(file);
+        }
+
+        // Rate limiting
+        if let Ok(enabled) = std::env::var("GUMMY_RATE_LIMIT_ENABLED") {
+            match enabled.parse::<bool>() {
+                Ok(enabled) => self.rate_limit.enabled = enabled,
+                Err(_) => warn!(
+                    "Invalid GUMMY_RATE_LIMIT_ENABLED value: {}. Ignoring.",
+                    enabled
+                ),
+            }
+        }
+        if let Ok(rate) = std::env::var("GUMMY_RATE_LIMIT_REQUESTS_PER_SECOND") {
+            match rate.parse::<f64>() {
+                Ok(rate) if rate > 0.0 => self.rate_limit.requests_per_second = rate,
+                _ => warn!(
+                    "Invalid GUMMY_RATE_LIMIT_REQUESTS_PER_SECOND value: {}. Ignoring.",
+                    rate
+                ),
+            }
+        }
+        if let Ok(burst) = std::env::var("GUMMY_RATE_LIMIT_BURST") {
+            match burst.parse::<u32>() {
+                Ok(burst) if burst > 0 => self.rate_limit.burst = burst,
+                _ => warn!("Invalid GUMMY_RATE_LIMIT_BURST value: {}. Ignoring.", burst),
+            }
+        }
+
+        // Background operation
+        if let Ok(pid_file) = std::env::var("GUMMY_PID_FILE") {
+            self.daemon.pid_file = Some(pid_file);
+        }
+        if let Ok(log_file) = std::env::var("GUMMY_LOG_FILE") {
+            self.daemon.log_file = Some(log_file);
+        }
 
         self
     }
 
+    /// Path of the pid file (default: "<data_dir>/gbs.pid")
+    pub fn pid_file_path(&self) -> PathBuf {
+        self.daemon
+            .pid_file
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(&self.storage.data_dir).join("gbs.pid"))
+    }
+
+    /// Path of the background server log file (default: "<data_dir>/gbs.log")
+    pub fn log_file_path(&self) -> PathBuf {
+        self.daemon
+            .log_file
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(&self.storage.data_dir).join("gbs.log"))
+    }
+
+    /// Path of the proxy recordings file (default:
+    /// "<data_dir>/proxy-recordings.ndjson")
+    pub fn proxy_recordings_path(&self) -> PathBuf {
+        self.proxy
+            .recordings_file
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| {
+                PathBuf::from(&self.storage.data_dir).join("proxy-recordings.ndjson")
+            })
+    }
+
+    /// Path of the audit file (default: "<data_dir>/audit.ndjson")
+    pub fn audit_file_path(&self) -> PathBuf {
+        self.audit
+            .file
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from(&self.storage.data_dir).join("audit.ndjson"))
+    }
+
     /// Get server address as SocketAddr
     pub fn server_addr(&self) -> std::net::SocketAddr {
         std::net::SocketAddr::from((
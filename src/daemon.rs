@@ -0,0 +1,334 @@
+//! Background operation: pid file, `gbs start`, `gbs status` and `gbs stop`
+//!
+//! On Unix `gbs start` launches `gbs serve` as a detached background process
+//! with its output appended to the log file; on Windows it starts the
+//! installed service (see `crate::win_service`), which logs to the log file.
+//! Every running server (foreground, background or service) holds a pid file,
+//! which `gbs status` and `gbs stop` use to find it. `gbs stop` shuts the
+//! server down gracefully so that it flushes its storage: on Unix with
+//! SIGTERM, on Windows with the stop control of the service. Windows servers
+//! not running as the service are not stopped.
+
+use std::fs;
+#[cfg(unix)]
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+#[cfg(unix)]
+use std::process::Stdio;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+use tracing::warn;
+
+use crate::error::{GbsError, Result};
+
+/// How long `start` waits for the background server to write its pid file
+const START_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Pid file of a running server, removed again when dropped
+#[derive(Debug)]
+pub struct PidFile {
+    path: PathBuf,
+    pid: u32,
+}
+
+impl PidFile {
+    /// Write the current process ID to `path`
+    ///
+    /// Fails if the file names another process that is still running. A stale
+    /// file left behind by a crashed server is replaced.
+    pub fn create(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let pid = std::process::id();
+        if let Some(existing) = read_pid(&path)? {
+            if existing != pid && is_process_running(existing) {
+                return Err(GbsError::InvalidRequest(format!(
+                    "gbs is already running with pid {} (pid file {})",
+                    existing,
+                    path.display()
+                )));
+            }
+            warn!(
+                "Replacing stale pid file {} (pid {})",
+                path.display(),
+                existing
+            );
+        }
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, format!("{}\n", pid))?;
+        Ok(Self { path, pid })
+    }
+
+    /// Path of the pid file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Only remove the file if it still names this process
+        if read_pid(&self.path).ok().flatten() == Some(self.pid) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Read the process ID from a pid file, `None` if the file does not exist
+pub fn read_pid(path: &Path) -> Result<Option<u32>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => {
+            contents.trim().parse().map(Some).map_err(|_| {
+                GbsError::InvalidRequest(format!("Invalid pid file {}", path.display()))
+            })
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// State of the server named by a pid file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonStatus {
+    /// The server is running with this process ID
+    Running(u32),
+    /// The pid file names a process that no longer exists
+    Stale(u32),
+    /// There is no pid file
+    Stopped,
+}
+
+/// Check whether the server named by a pid file is running
+pub fn status(pid_file: &Path) -> Result<DaemonStatus> {
+    Ok(match read_pid(pid_file)? {
+        Some(pid) if is_process_running(pid) => DaemonStatus::Running(pid),
+        Some(pid) => DaemonStatus::Stale(pid),
+        None => DaemonStatus::Stopped,
+    })
+}
+
+/// Launch the server in the background and wait until it wrote its pid file
+///
+/// On Unix `gbs serve` is launched; the child inherits the environment and
+/// working directory, so it loads the same configuration, and its output is
+/// appended to `log_file`. On Windows the installed service is started.
+pub fn start(pid_file: &Path, log_file: &Path) -> Result<u32> {
+    if let DaemonStatus::Running(pid) = status(pid_file)? {
+        return Err(GbsError::InvalidRequest(format!(
+            "gbs is already running with pid {}",
+            pid
+        )));
+    }
+
+    let mut server = Background::launch(log_file)?;
+    let pid = server.pid();
+
+    let deadline = Instant::now() + START_TIMEOUT;
+    loop {
+        if let Some(exit) = server.exited()? {
+            return Err(GbsError::InvalidRequest(format!(
+                "gbs exited during startup ({}), see {}",
+                exit,
+                log_file.display()
+            )));
+        }
+        if read_pid(pid_file).ok().flatten() == Some(pid) {
+            return Ok(pid);
+        }
+        if Instant::now() >= deadline {
+            return Err(GbsError::InvalidRequest(format!(
+                "gbs (pid {}) did not write {} within {:?}, see {}",
+                pid,
+                pid_file.display(),
+                START_TIMEOUT,
+                log_file.display()
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Server launched by `start`
+#[cfg(unix)]
+struct Background(std::process::Child);
+
+#[cfg(unix)]
+impl Background {
+    fn launch(log_file: &Path) -> Result<Self> {
+        if let Some(parent) = log_file.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let log = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(log_file)?;
+
+        let mut command = Command::new(std::env::current_exe()?);
+        command
+            .arg("serve")
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log);
+        detach(&mut command);
+        Ok(Self(command.spawn()?))
+    }
+
+    fn pid(&self) -> u32 {
+        self.0.id()
+    }
+
+    /// How the server exited, `None` while it runs
+    fn exited(&mut self) -> Result<Option<String>> {
+        Ok(self.0.try_wait()?.map(|exit| exit.to_string()))
+    }
+}
+
+/// Server launched by `start`: the process of the service
+#[cfg(windows)]
+struct Background(u32);
+
+#[cfg(windows)]
+impl Background {
+    fn launch(_log_file: &Path) -> Result<Self> {
+        crate::win_service::start(START_TIMEOUT).map(Self)
+    }
+
+    fn pid(&self) -> u32 {
+        self.0
+    }
+
+    /// How the server exited, `None` while it runs
+    fn exited(&mut self) -> Result<Option<String>> {
+        Ok((!crate::win_service::is_running(self.0)).then(|| "service stopped".to_string()))
+    }
+}
+
+/// Stop the server named by a pid file and wait for it to exit
+///
+/// Returns the stopped process ID, or `None` if no server was running. A stale
+/// pid file is removed.
+pub fn stop(pid_file: &Path, timeout: Duration) -> Result<Option<u32>> {
+    let pid = match status(pid_file)? {
+        DaemonStatus::Running(pid) => pid,
+        DaemonStatus::Stale(_) => {
+            fs::remove_file(pid_file)?;
+            return Ok(None);
+        }
+        DaemonStatus::Stopped => return Ok(None),
+    };
+
+    terminate_process(pid)?;
+    let deadline = Instant::now() + timeout;
+    while is_process_running(pid) {
+        if Instant::now() >= deadline {
+            return Err(GbsError::InvalidRequest(format!(
+                "gbs (pid {}) did not stop within {:?}",
+                pid, timeout
+            )));
+        }
+        std::thread::sleep(POLL_INTERVAL);
+    }
+    // A terminated process cannot clean up after itself
+    match fs::remove_file(pid_file) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(Some(pid)),
+    }
+}
+
+/// Shutdown requested from within the process, by the Windows service manager
+static SHUTDOWN_REQUEST: OnceLock<Notify> = OnceLock::new();
+
+fn shutdown_request() -> &'static Notify {
+    SHUTDOWN_REQUEST.get_or_init(Notify::new)
+}
+
+/// Make `shutdown_signal` complete, as Ctrl-C does
+pub fn request_shutdown() {
+    shutdown_request().notify_one();
+}
+
+/// Wait for Ctrl-C, `request_shutdown` or, on Unix, SIGTERM
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                warn!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {}
+        _ = terminate => {}
+        _ = shutdown_request().notified() => {}
+    }
+}
+
+/// Check whether a process with this ID exists
+#[cfg(unix)]
+pub fn is_process_running(pid: u32) -> bool {
+    // Pid 0 would address our own process group
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    if pid == 0 {
+        return false;
+    }
+    // Signal 0 only checks for existence; EPERM means it exists under another user
+    let exists = unsafe { libc::kill(pid, 0) } == 0;
+    exists || std::io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Check whether a process with this ID exists
+#[cfg(windows)]
+pub fn is_process_running(pid: u32) -> bool {
+    Command::new("tasklist")
+        .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).contains(&format!("\"{}\"", pid)))
+        .unwrap_or(false)
+}
+
+#[cfg(unix)]
+fn terminate_process(pid: u32) -> Result<()> {
+    let pid = libc::pid_t::try_from(pid)
+        .map_err(|_| GbsError::InvalidRequest(format!("Invalid pid {}", pid)))?;
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(windows)]
+fn terminate_process(pid: u32) -> Result<()> {
+    crate::win_service::stop(pid)
+}
+
+/// Detach the child from the terminal session
+#[cfg(unix)]
+fn detach(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    unsafe {
+        command.pre_exec(|| {
+            if libc::setsid() == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            Ok(())
+        });
+    }
+}
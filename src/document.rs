@@ -1,3 +1,70 @@
+//! Typed document operations
+//!
+//! Documents are read and written as the caller's types rather than
+//! `serde_json::Value`: sources are serialized on the way in and
+//! deserialized on the way out, with the metadata of the document alongside.
+//!
+//! ```no_run
+//! # async fn example(client: gbs::client::GbsClient) -> gbs::Result<()> {
+//! use gbs::client::query::{match_query, SearchRequest};
+//! use gbs::document::DocumentOperations;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Article {
+//!     title: String,
+//! }
+//!
+//! client
+//!     .index_typed("articles", "1", &Article { title: "Rust".into() })
+//!     .await?;
+//! if let Some(article) = client.get_typed::<Article>("articles", "1").await? {
+//!     println!("{} (version {})", article.source.title, article.version);
+//! }
+//! let request = SearchRequest::new().query(match_query("title", "rust"));
+//! for hit in client.search_typed::<Article>("articles", &request).await? {
+//!     println!("{}: {:?}", hit.id, hit.score);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::client::query::SearchRequest;
+use crate::client::Hit;
+use crate::error::Result;
+
+/// A stored document, with its source deserialized
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Document<T> {
+    #[serde(rename = "_index")]
+    pub index: String,
+    #[serde(rename = "_id")]
+    pub id: String,
+    #[serde(rename = "_version", default)]
+    pub version: u64,
+    #[serde(rename = "_source")]
+    pub source: T,
+}
+
+/// Reading and writing documents as the caller's types
+#[async_trait]
 pub trait DocumentOperations {
-    // Будет реализовано в client.rs
+    /// Index a document under an ID, replacing any document with that ID
+    async fn index_typed<T>(&self, index: &str, id: &str, document: &T) -> Result<()>
+    where
+        T: Serialize + Sync;
+
+    /// Get a document, or `None` if the index or the document does not exist
+    async fn get_typed<T>(&self, index: &str, id: &str) -> Result<Option<Document<T>>>
+    where
+        T: DeserializeOwned + Send;
+
+    /// Hits of a search of an index expression, in order
+    async fn search_typed<T>(&self, index: &str, request: &SearchRequest) -> Result<Vec<Hit<T>>>
+    where
+        T: DeserializeOwned + Send;
 }
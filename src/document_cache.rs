@@ -0,0 +1,204 @@
+//! Cache of documents read from disk
+//!
+//! Documents of warm and lazily loaded indices are read from the Sled backend
+//! one at a time. The backend keeps the most recently read ones here, up to a
+//! memory budget, so repeated lookups do not read and decode them again.
+//! Writes and deletes of a document drop it from the cache.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// Default memory budget (64 MiB)
+pub const DEFAULT_DOCUMENT_CACHE_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Usage counters of the document cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocumentCacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups that read the document from disk
+    pub misses: u64,
+    /// Documents dropped to stay within the budget
+    pub evictions: u64,
+    /// Number of cached documents
+    pub entries: usize,
+    /// Estimated size of the cached documents
+    pub size_in_bytes: u64,
+}
+
+#[derive(Debug)]
+struct CachedDocument {
+    document: serde_json::Value,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    /// Documents by their backend key
+    documents: HashMap<String, CachedDocument>,
+    /// Keys by the tick they were last used at, least recent first
+    recency: BTreeMap<u64, String>,
+    size_in_bytes: u64,
+    tick: u64,
+    /// Incremented by every invalidation
+    generation: u64,
+}
+
+impl CacheState {
+    fn remove(&mut self, key: &str) {
+        if let Some(cached) = self.documents.remove(key) {
+            self.recency.remove(&cached.last_used);
+            self.size_in_bytes -= cached.size;
+        }
+    }
+}
+
+/// Least recently used documents within a memory budget
+#[derive(Debug)]
+pub struct DocumentCache {
+    budget: AtomicU64,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for DocumentCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_DOCUMENT_CACHE_BYTES)
+    }
+}
+
+impl DocumentCache {
+    pub fn new(budget: u64) -> Self {
+        Self {
+            budget: AtomicU64::new(budget),
+            state: Mutex::new(CacheState::default()),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// Change the memory budget, evicting documents beyond it; 0 disables
+    /// the cache
+    pub fn set_budget(&self, budget: u64) {
+        self.budget.store(budget, Ordering::Relaxed);
+        let mut state = self.lock();
+        self.evict_beyond(&mut state, budget);
+    }
+
+    /// A copy of a cached document, marking it as recently used
+    pub fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let mut guard = self.lock();
+        let state = &mut *guard;
+        state.tick += 1;
+        let tick = state.tick;
+        let Some(cached) = state.documents.get_mut(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        let previous = std::mem::replace(&mut cached.last_used, tick);
+        let document = cached.document.clone();
+        state.recency.remove(&previous);
+        state.recency.insert(tick, key.to_string());
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(document)
+    }
+
+    /// Generation to pass to `insert` for a document about to be read
+    pub fn generation(&self) -> u64 {
+        self.lock().generation
+    }
+
+    /// Cache a document read from disk
+    ///
+    /// The document is not cached if any document was invalidated since
+    /// `generation`, as it may have been read before a write, nor if it is
+    /// larger than the whole budget.
+    pub fn insert(&self, key: &str, document: &serde_json::Value, generation: u64) {
+        let budget = self.budget.load(Ordering::Relaxed);
+        let size = document_size(document);
+        if size > budget {
+            return;
+        }
+        let mut state = self.lock();
+        if state.generation != generation {
+            return;
+        }
+        state.remove(key);
+        self.evict_beyond(&mut state, budget - size);
+        state.tick += 1;
+        let tick = state.tick;
+        state.recency.insert(tick, key.to_string());
+        state.size_in_bytes += size;
+        state.documents.insert(
+            key.to_string(),
+            CachedDocument {
+                document: document.clone(),
+                size,
+                last_used: tick,
+            },
+        );
+    }
+
+    /// Drop a document, e.g. because it was written or deleted
+    pub fn invalidate(&self, key: &str) {
+        let mut state = self.lock();
+        state.generation += 1;
+        state.remove(key);
+    }
+
+    /// Drop all documents whose key starts with `prefix`
+    pub fn invalidate_prefix(&self, prefix: &str) {
+        let mut state = self.lock();
+        state.generation += 1;
+        let keys: Vec<String> = state
+            .documents
+            .keys()
+            .filter(|key| key.starts_with(prefix))
+            .cloned()
+            .collect();
+        for key in keys {
+            state.remove(&key);
+        }
+    }
+
+    pub fn stats(&self) -> DocumentCacheStats {
+        let state = self.lock();
+        DocumentCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: state.documents.len(),
+            size_in_bytes: state.size_in_bytes,
+        }
+    }
+
+    /// Evict the least recently used documents until at most `limit` bytes
+    /// are cached
+    fn evict_beyond(&self, state: &mut CacheState, limit: u64) {
+        while state.size_in_bytes > limit {
+            let Some((_, key)) = state.recency.pop_first() else {
+                break;
+            };
+            if let Some(cached) = state.documents.remove(&key) {
+                state.size_in_bytes -= cached.size;
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Estimated memory of a cached document (its serialized JSON size)
+fn document_size(document: &serde_json::Value) -> u64 {
+    serde_json::to_vec(document)
+        .map(|v| v.len() as u64)
+        .unwrap_or(0)
+}
@@ -0,0 +1,91 @@
+//! On-disk encoding of documents
+//!
+//! Documents are stored as MessagePack (encoded with `rmp-serde`) behind a
+//! three byte header: a marker byte, the format version and flags. Payloads of at least
+//! `COMPRESSION_THRESHOLD` bytes are compressed with LZ4 when that makes them
+//! smaller, which the `FLAG_LZ4` flag records.
+//!
+//! The marker (`0xc1`, which MessagePack never uses) cannot start a JSON text,
+//! so entries written before the binary format are told apart by their first
+//! byte and read as JSON. `SledBackend::load_all_documents` rewrites them in
+//! the binary format.
+
+use serde::Deserialize;
+
+use crate::error::{GbsError, Result};
+
+/// First byte of a binary document
+const MARKER: u8 = 0xc1;
+/// Version of the binary format written
+const VERSION: u8 = 1;
+/// The payload is compressed with LZ4 (size-prepended block format)
+const FLAG_LZ4: u8 = 0x01;
+const HEADER_LEN: usize = 3;
+/// Smaller payloads are stored uncompressed
+const COMPRESSION_THRESHOLD: usize = 256;
+/// Nesting depth beyond which a payload is considered corrupt
+const MAX_DEPTH: usize = 512;
+
+/// Encode a document for storage
+pub fn encode_document(document: &serde_json::Value) -> Vec<u8> {
+    let mut payload = rmp_serde::to_vec(document).expect("JSON values are valid MessagePack");
+
+    let mut flags = 0;
+    if payload.len() >= COMPRESSION_THRESHOLD {
+        let compressed = lz4_flex::compress_prepend_size(&payload);
+        if compressed.len() < payload.len() {
+            payload = compressed;
+            flags |= FLAG_LZ4;
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(HEADER_LEN + payload.len());
+    bytes.extend_from_slice(&[MARKER, VERSION, flags]);
+    bytes.extend_from_slice(&payload);
+    bytes
+}
+
+/// Decode a stored document, in the binary format or as legacy JSON
+pub fn decode_document(bytes: &[u8]) -> Result<serde_json::Value> {
+    if bytes.first() != Some(&MARKER) {
+        return Ok(serde_json::from_slice(bytes)?);
+    }
+    let [_, version, flags] = bytes
+        .get(..HEADER_LEN)
+        .and_then(|header| <[u8; HEADER_LEN]>::try_from(header).ok())
+        .ok_or_else(|| corrupt("truncated header"))?;
+    if version != VERSION {
+        return Err(GbsError::Storage(format!(
+            "Unsupported document format version {}",
+            version
+        )));
+    }
+
+    let payload = &bytes[HEADER_LEN..];
+    let decompressed;
+    let payload = if flags & FLAG_LZ4 != 0 {
+        decompressed = lz4_flex::decompress_size_prepended(payload)
+            .map_err(|e| corrupt(&format!("invalid LZ4 payload: {}", e)))?;
+        &decompressed[..]
+    } else {
+        payload
+    };
+
+    let mut deserializer = rmp_serde::Deserializer::new(payload);
+    deserializer.set_max_depth(MAX_DEPTH);
+    let document =
+        serde_json::Value::deserialize(&mut deserializer).map_err(|e| corrupt(&e.to_string()))?;
+    if !deserializer.get_ref().is_empty() {
+        return Err(corrupt("trailing bytes"));
+    }
+    Ok(document)
+}
+
+/// Check if a stored document is in the legacy JSON format
+pub fn is_legacy_document(bytes: &[u8]) -> bool {
+    bytes.first() != Some(&MARKER)
+}
+
+fn corrupt(reason: &str) -> GbsError {
+    GbsError::Storage(format!("Corrupt stored document: {}", reason))
+}
@@ -0,0 +1,153 @@
+//! In-process Gummy Bear Search, for tests and applications that embed it
+//! (feature `embedded`)
+//!
+//! `Gbs::start_in_memory()` creates an in-memory instance without binding a
+//! port. Requests go through the same router, middleware and handlers as the
+//! HTTP server, called as a `tower::Service` or through the typed
+//! `GbsClient`. Fixtures (see `fixtures`) seed indices and documents before a
+//! test runs.
+//!
+//! ```
+//! # async fn example() -> gbs::Result<()> {
+//! use gbs::embedded::{Fixture, Gbs};
+//! use serde_json::json;
+//!
+//! let gbs = Gbs::start_in_memory();
+//! gbs.seed(&Fixture::from_json(json!({
+//!     "books": {
+//!         "mappings": { "properties": { "title": { "type": "text" } } },
+//!         "documents": { "1": { "title": "Dune" } }
+//!     }
+//! }))?)
+//! .await?;
+//!
+//! let (status, body) = gbs.request("GET", "/books/_doc/1", None).await?;
+//! assert_eq!(status, 200);
+//! assert_eq!(body["_source"]["title"], "Dune");
+//! # Ok(())
+//! # }
+//! # tokio_test::block_on(example()).unwrap();
+//! ```
+
+use axum::body::{to_bytes, Body};
+use axum::http::{header::CONTENT_TYPE, Method, Request, StatusCode};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::Arc;
+use tower::Service;
+
+use crate::client::GbsClient;
+use crate::config::default_es_version;
+use crate::error::{GbsError, Result};
+use crate::fixtures::{self, SeedReport};
+use crate::server::{AppState, GbsService};
+use crate::storage::Storage;
+
+pub use crate::fixtures::{Fixture, IndexFixture};
+
+/// An in-process instance
+#[derive(Clone)]
+pub struct Gbs {
+    state: AppState,
+    service: GbsService,
+}
+
+impl Gbs {
+    /// Start an instance keeping everything in memory, with security disabled
+    pub fn start_in_memory() -> Self {
+        Self::with_state(AppState::new(
+            Arc::new(Storage::new()),
+            default_es_version(),
+        ))
+    }
+
+    /// Start an instance serving the given state, e.g. with security enabled
+    /// or request limits set
+    pub fn with_state(state: AppState) -> Self {
+        Self {
+            service: GbsService::new(state.clone()),
+            state,
+        }
+    }
+
+    /// Storage of the instance, to read or write it directly
+    pub fn storage(&self) -> &Arc<Storage> {
+        &self.state.storage
+    }
+
+    /// State of the instance
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// The API as a `tower::Service`
+    pub fn service(&self) -> GbsService {
+        self.service.clone()
+    }
+
+    /// Typed client of the instance
+    pub fn client(&self) -> GbsClient {
+        GbsClient::embedded(self.service())
+    }
+
+    /// Send a request with an optional JSON body, as over HTTP
+    ///
+    /// Returns the status and the JSON body of the response (`null` if the
+    /// body is empty or not JSON).
+    pub async fn request(
+        &self,
+        method: &str,
+        path: &str,
+        body: Option<serde_json::Value>,
+    ) -> Result<(StatusCode, serde_json::Value)> {
+        let method = Method::from_bytes(method.as_bytes())
+            .map_err(|_| GbsError::InvalidRequest(format!("Invalid method [{}]", method)))?;
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.map_or_else(Body::empty, |body| Body::from(body.to_string())))
+            .map_err(|e| GbsError::InvalidRequest(e.to_string()))?;
+
+        let mut service = self.service();
+        let response = match std::future::poll_fn(|cx| service.poll_ready(cx)).await {
+            Ok(()) => match service.call(request).await {
+                Ok(response) => response,
+                Err(infallible) => match infallible {},
+            },
+            Err(infallible) => match infallible {},
+        };
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX)
+            .await
+            .map_err(|e| GbsError::Upstream(e.to_string()))?;
+        Ok((
+            status,
+            serde_json::from_slice(&body).unwrap_or(serde_json::Value::Null),
+        ))
+    }
+
+    /// Create the indices of a fixture and index their documents
+    pub async fn seed(&self, fixture: &Fixture) -> Result<SeedReport> {
+        fixtures::seed(self.storage(), fixture).await
+    }
+
+    /// Index documents by ID, creating the index if needed
+    pub async fn seed_documents<I, T>(&self, index: &str, documents: I) -> Result<SeedReport>
+    where
+        I: IntoIterator<Item = (String, T)>,
+        T: Serialize,
+    {
+        fixtures::seed_documents(self.storage(), index, documents).await
+    }
+
+    /// Seed from a fixture file (see `fixtures::seed_file`)
+    pub async fn seed_file(&self, path: impl AsRef<Path>) -> Result<SeedReport> {
+        fixtures::seed_file(self.storage(), path).await
+    }
+
+    /// Seed from a fixture directory (see `fixtures`)
+    pub async fn seed_dir(&self, dir: impl AsRef<Path>) -> Result<SeedReport> {
+        fixtures::seed_dir(self.storage(), dir).await
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{
-    http::StatusCode,
+    http::{header, StatusCode},
     response::{IntoResponse, Response},
 };
 use thiserror::Error;
@@ -17,37 +17,291 @@ pub enum GbsError {
     #[error("Index not found: {0}")]
     IndexNotFound(String),
 
+    /// Creation of an index whose name is taken
+    #[error("Index {0} already exists")]
+    IndexAlreadyExists(String),
+
+    /// Search, read or write of a closed index
+    #[error("closed")]
+    IndexClosed(String),
+
+    /// Write to a frozen index
+    #[error("index [{0}] blocked by: [FORBIDDEN/8/index write (api)];")]
+    IndexWriteBlocked(String),
+
     #[error("Document not found: {0}")]
     DocumentNotFound(String),
 
+    /// Write that conflicts with the current version of a document, such as
+    /// creating a document that exists
+    #[error("[_doc][{id}]: version conflict, {reason}")]
+    VersionConflict { id: String, reason: String },
+
+    #[error("Alias not found: {0}")]
+    AliasNotFound(String),
+
+    #[error("Search profile not found: {0}")]
+    SearchProfileNotFound(String),
+
+    #[error("Index template not found: {0}")]
+    TemplateNotFound(String),
+
+    /// Stored script (search template) that does not exist
+    #[error("unable to find script [{0}] in cluster state")]
+    ScriptNotFound(String),
+
+    /// Ingest pipeline that does not exist
+    #[error("pipeline [{0}] is missing")]
+    PipelineNotFound(String),
+
+    #[error("Warmer not found: {0}")]
+    WarmerNotFound(String),
+
+    #[error("Task not found: {0}")]
+    TaskNotFound(String),
+
+    #[error("No recorded response: {0}")]
+    RecordingNotFound(String),
+
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
 
     #[error("Storage error: {0}")]
     Storage(String),
 
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
+    /// Rejected argument, reported with Elasticsearch's exception type
+    #[error("{0}")]
+    IllegalArgument(String),
+
+    /// Document with a field that a `strict` mapping does not define
+    #[error("mapping set to strict, dynamic introduction of [{0}] is not allowed")]
+    StrictDynamicMapping(String),
+
+    /// Document value that does not fit its mapped type, with the type and
+    /// reason of the underlying cause
+    #[error("{reason}")]
+    MapperParsing {
+        reason: String,
+        caused_by: Option<(&'static str, String)>,
+    },
+
+    /// Script that fails to compile or run
+    #[error("{0}")]
+    Script(String),
+
+    #[error("Upstream error: {0}")]
+    Upstream(String),
+
+    /// Write received while the server drains requests before shutting down
+    #[error("node is shutting down")]
+    ShuttingDown,
+
+    /// Request refused because the server is saturated or its client is
+    /// over its rate limit; clients retry it after `retry_after_secs`
+    #[error("{reason}")]
+    Rejected {
+        reason: String,
+        retry_after_secs: u64,
+    },
+
+    /// Error response of a gbs server, as reported to a client
+    #[error("{reason}")]
+    Remote { status: StatusCode, reason: String },
+
     #[error("Task join error: {0}")]
     TaskJoin(#[from] tokio::task::JoinError),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
-impl IntoResponse for GbsError {
-    fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            GbsError::IndexNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            GbsError::DocumentNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
-            GbsError::InvalidRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            GbsError::Elasticsearch(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            GbsError::Json(_) => (StatusCode::BAD_REQUEST, self.to_string()),
-            GbsError::Storage(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-            GbsError::TaskJoin(_) => (StatusCode::INTERNAL_SERVER_ERROR, self.to_string()),
-        };
-
-        let body = serde_json::json!({
-            "error": {
-                "type": "error",
-                "reason": error_message
+impl GbsError {
+    /// HTTP status of the error
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            GbsError::IndexNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::IndexAlreadyExists(_) => StatusCode::BAD_REQUEST,
+            GbsError::IndexClosed(_) => StatusCode::BAD_REQUEST,
+            GbsError::IndexWriteBlocked(_) => StatusCode::FORBIDDEN,
+            GbsError::DocumentNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::VersionConflict { .. } => StatusCode::CONFLICT,
+            GbsError::AliasNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::SearchProfileNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::TemplateNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::ScriptNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::PipelineNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::WarmerNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::TaskNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::RecordingNotFound(_) => StatusCode::NOT_FOUND,
+            GbsError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            GbsError::Elasticsearch(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            GbsError::Json(_) => StatusCode::BAD_REQUEST,
+            GbsError::Storage(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            GbsError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            GbsError::Forbidden(_) => StatusCode::FORBIDDEN,
+            GbsError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            GbsError::IllegalArgument(_) => StatusCode::BAD_REQUEST,
+            GbsError::StrictDynamicMapping(_) => StatusCode::BAD_REQUEST,
+            GbsError::MapperParsing { .. } => StatusCode::BAD_REQUEST,
+            GbsError::Script(_) => StatusCode::BAD_REQUEST,
+            GbsError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            GbsError::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            GbsError::Rejected { .. } => StatusCode::TOO_MANY_REQUESTS,
+            GbsError::Remote { status, .. } => *status,
+            GbsError::TaskJoin(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            GbsError::Io(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Type of the error in error response bodies, the Elasticsearch
+    /// exception type clients expect for the same failure
+    pub fn error_type(&self) -> &'static str {
+        match self {
+            GbsError::Json(_) => "x_content_parse_exception",
+            GbsError::IndexNotFound(_) => "index_not_found_exception",
+            GbsError::IndexAlreadyExists(_) => "resource_already_exists_exception",
+            GbsError::IndexClosed(_) => "index_closed_exception",
+            GbsError::IndexWriteBlocked(_) => "cluster_block_exception",
+            GbsError::DocumentNotFound(_) => "document_missing_exception",
+            GbsError::VersionConflict { .. } => "version_conflict_engine_exception",
+            GbsError::AliasNotFound(_) => "aliases_not_found_exception",
+            GbsError::TemplateNotFound(_) => "index_template_missing_exception",
+            GbsError::SearchProfileNotFound(_)
+            | GbsError::ScriptNotFound(_)
+            | GbsError::PipelineNotFound(_)
+            | GbsError::WarmerNotFound(_)
+            | GbsError::TaskNotFound(_)
+            | GbsError::RecordingNotFound(_) => "resource_not_found_exception",
+            GbsError::InvalidRequest(_) => "illegal_argument_exception",
+            GbsError::Unauthorized(_) | GbsError::Forbidden(_) => "security_exception",
+            GbsError::PayloadTooLarge(_) => "content_too_long_exception",
+            GbsError::IllegalArgument(_) => "illegal_argument_exception",
+            GbsError::StrictDynamicMapping(_) => "strict_dynamic_mapping_exception",
+            GbsError::MapperParsing { .. } => "mapper_parsing_exception",
+            GbsError::Script(_) => "script_exception",
+            GbsError::ShuttingDown => "node_closed_exception",
+            GbsError::Rejected { .. } => "es_rejected_execution_exception",
+            GbsError::Elasticsearch(_)
+            | GbsError::Storage(_)
+            | GbsError::Upstream(_)
+            | GbsError::Remote { .. }
+            | GbsError::TaskJoin(_)
+            | GbsError::Io(_) => "exception",
+        }
+    }
+
+    /// Reason of the error in error response bodies, worded as Elasticsearch
+    /// words it where clients or users match on it
+    pub fn reason(&self) -> String {
+        match self {
+            GbsError::IndexNotFound(index) => format!("no such index [{}]", index),
+            GbsError::IndexAlreadyExists(index) => {
+                format!("index [{}/_na_] already exists", index)
+            }
+            GbsError::DocumentNotFound(id) => format!("[_doc][{}]: document missing", id),
+            GbsError::AliasNotFound(alias) => format!("aliases [{}] missing", alias),
+            GbsError::TemplateNotFound(name) => format!("index_template [{}] missing", name),
+            GbsError::InvalidRequest(reason)
+            | GbsError::Unauthorized(reason)
+            | GbsError::Forbidden(reason) => reason.clone(),
+            _ => self.to_string(),
+        }
+    }
+
+    /// Metadata of the resource the error is about, as extra fields of the
+    /// error in response bodies
+    fn metadata(&self) -> Option<serde_json::Value> {
+        match self {
+            GbsError::IndexNotFound(index) => Some(serde_json::json!({
+                "resource.type": "index_or_alias",
+                "resource.id": index,
+                "index_uuid": "_na_",
+                "index": index
+            })),
+            GbsError::IndexAlreadyExists(index) | GbsError::IndexClosed(index) => {
+                Some(serde_json::json!({ "index_uuid": "_na_", "index": index }))
             }
+            GbsError::AliasNotFound(alias) => Some(serde_json::json!({
+                "resource.type": "aliases",
+                "resource.id": alias
+            })),
+            _ => None,
+        }
+    }
+
+    /// Cause of the error in error response bodies
+    pub fn caused_by(&self) -> Option<serde_json::Value> {
+        match self {
+            GbsError::MapperParsing {
+                caused_by: Some((cause_type, reason)),
+                ..
+            } => Some(serde_json::json!({ "type": cause_type, "reason": reason })),
+            _ => None,
+        }
+    }
+
+    /// Elasticsearch error envelope of the error:
+    /// `{"error": {"root_cause": [...], "type", "reason", ...}, "status"}`
+    ///
+    /// The root cause repeats the type, reason and metadata of the error, which
+    /// gbs reports without a chain of wrapped exceptions.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut cause = serde_json::json!({
+            "type": self.error_type(),
+            "reason": self.reason()
         });
+        if let Some(serde_json::Value::Object(metadata)) = self.metadata() {
+            cause.as_object_mut().expect("an object").extend(metadata);
+        }
+        let mut error = cause.clone();
+        error["root_cause"] = serde_json::json!([cause]);
+        if let Some(caused_by) = self.caused_by() {
+            error["caused_by"] = caused_by;
+        }
+        serde_json::json!({
+            "error": error,
+            "status": self.status_code().as_u16()
+        })
+    }
+}
+
+impl IntoResponse for GbsError {
+    fn into_response(self) -> Response {
+        let status = self.status_code();
+        let body = self.to_json();
+
+        if let GbsError::Rejected {
+            retry_after_secs, ..
+        } = &self
+        {
+            return (
+                status,
+                [(header::RETRY_AFTER, retry_after_secs.to_string())],
+                axum::Json(body),
+            )
+                .into_response();
+        }
+
+        if status == StatusCode::UNAUTHORIZED {
+            return (
+                status,
+                [(
+                    header::WWW_AUTHENTICATE,
+                    r#"Basic realm="security" charset="UTF-8""#,
+                )],
+                axum::Json(body),
+            )
+                .into_response();
+        }
 
         (status, axum::Json(body)).into_response()
     }
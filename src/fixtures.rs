@@ -0,0 +1,310 @@
+//! Seeding indices from fixtures (`gbs serve --seed <dir>`)
+//!
+//! A fixture directory holds one definition and/or one document file per
+//! index, named after the index:
+//!
+//! - `<index>.json`, `<index>.yaml` or `<index>.yml`: the index definition,
+//!   as the body of `PUT /<index>` (`settings`, `mappings`, `aliases`),
+//!   optionally with `documents` by ID
+//! - `<index>.ndjson`: bulk actions, as sent to `POST /<index>/_bulk`
+//!
+//! Indices are seeded in name order, each from its definition and then its
+//! bulk file, so a directory always produces the same data. Indices that
+//! already exist are skipped, so restarting with the same data directory
+//! neither fails nor duplicates documents.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+use crate::bulk_ops::{parse_bulk_ndjson, BulkAction};
+use crate::error::{GbsError, Result};
+use crate::storage::Storage;
+
+/// Indices and documents to seed storage with
+///
+/// In JSON, an object of indices by name:
+///
+/// ```json
+/// {
+///   "books": {
+///     "settings": { "number_of_shards": 1 },
+///     "mappings": { "properties": { "title": { "type": "text" } } },
+///     "aliases": ["library"],
+///     "documents": { "1": { "title": "Dune" } }
+///   }
+/// }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Fixture {
+    #[serde(flatten)]
+    pub indices: BTreeMap<String, IndexFixture>,
+}
+
+impl Fixture {
+    /// Fixture from its JSON form
+    pub fn from_json(value: serde_json::Value) -> Result<Self> {
+        Ok(serde_json::from_value(value)?)
+    }
+}
+
+/// An index of a fixture
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexFixture {
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub mappings: Option<serde_json::Value>,
+    /// Alias names, as a list or as an object keyed by alias name
+    #[serde(default, deserialize_with = "alias_names")]
+    pub aliases: Vec<String>,
+    /// Documents by ID, indexed in ID order
+    #[serde(default)]
+    pub documents: BTreeMap<String, serde_json::Value>,
+}
+
+/// What seeding did
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SeedReport {
+    /// Indices created
+    pub created: Vec<String>,
+    /// Indices left alone because they already existed
+    pub skipped: Vec<String>,
+    /// Documents indexed
+    pub documents: usize,
+}
+
+impl SeedReport {
+    fn merge(&mut self, other: SeedReport) {
+        self.created.extend(other.created);
+        self.skipped.extend(other.skipped);
+        self.documents += other.documents;
+    }
+}
+
+/// Create the indices of a fixture and index their documents
+///
+/// Fails if one of the indices already exists.
+pub async fn seed(storage: &Storage, fixture: &Fixture) -> Result<SeedReport> {
+    let mut report = SeedReport::default();
+    for (name, index) in &fixture.indices {
+        report.merge(create_index(storage, name, index).await?);
+    }
+    Ok(report)
+}
+
+/// Index documents by ID, creating the index if needed
+pub async fn seed_documents<I, T>(
+    storage: &Storage,
+    index: &str,
+    documents: I,
+) -> Result<SeedReport>
+where
+    I: IntoIterator<Item = (String, T)>,
+    T: Serialize,
+{
+    let mut report = SeedReport::default();
+    if !storage.index_exists(index).await? {
+        storage.create_index(index, None, None).await?;
+        report.created.push(index.to_string());
+    }
+    let actions = documents
+        .into_iter()
+        .map(|(id, document)| {
+            Ok(BulkAction::Index {
+                index: index.to_string(),
+                id: Some(id),
+                document: serde_json::to_value(document)?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    report.documents = execute(storage, actions).await?;
+    Ok(report)
+}
+
+/// Seed from a fixture file
+///
+/// `.ndjson` files hold bulk actions, as sent to `POST /_bulk`; `.yaml` and
+/// `.yml` files hold a `Fixture` in YAML, and any other file a `Fixture` in
+/// JSON.
+pub async fn seed_file(storage: &Storage, path: impl AsRef<Path>) -> Result<SeedReport> {
+    let path = path.as_ref();
+    match extension(path) {
+        Some("ndjson") => Ok(SeedReport {
+            documents: seed_bulk_file(storage, path, None).await?,
+            ..SeedReport::default()
+        }),
+        _ => seed(storage, &read_definition(path).await?).await,
+    }
+}
+
+/// Seed from a fixture directory (see the module documentation)
+pub async fn seed_dir(storage: &Storage, dir: impl AsRef<Path>) -> Result<SeedReport> {
+    let dir = dir.as_ref();
+    let mut files: BTreeMap<String, (Option<PathBuf>, Option<PathBuf>)> = BTreeMap::new();
+    let mut entries = tokio::fs::read_dir(dir).await.map_err(|e| {
+        GbsError::InvalidRequest(format!(
+            "Cannot read fixture directory [{}]: {}",
+            dir.display(),
+            e
+        ))
+    })?;
+    while let Some(entry) = entries.next_entry().await? {
+        let path = entry.path();
+        if !entry.file_type().await?.is_file() {
+            continue;
+        }
+        let Some(index) = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        let is_definition = match extension(&path) {
+            Some("json") | Some("yaml") | Some("yml") => true,
+            Some("ndjson") => false,
+            _ => {
+                warn!("Ignoring fixture file {} of unknown type", path.display());
+                continue;
+            }
+        };
+        let (definition, bulk) = files.entry(index).or_default();
+        let slot = if is_definition { definition } else { bulk };
+        if let Some(previous) = slot.replace(path.clone()) {
+            return Err(GbsError::InvalidRequest(format!(
+                "Fixture files [{}] and [{}] both define the same index",
+                previous.display(),
+                path.display()
+            )));
+        }
+    }
+
+    let mut report = SeedReport::default();
+    for (index, (definition, bulk)) in files {
+        if storage.index_exists(&index).await? {
+            info!("Index '{}' already exists, not seeding it", index);
+            report.skipped.push(index);
+            continue;
+        }
+        let definition = match definition {
+            Some(path) => {
+                serde_json::from_value(read_file(&path).await?).map_err(|e| invalid(&path, e))?
+            }
+            None => IndexFixture::default(),
+        };
+        report.merge(create_index(storage, &index, &definition).await?);
+        if let Some(path) = bulk {
+            report.documents += seed_bulk_file(storage, &path, Some(&index)).await?;
+        }
+    }
+    Ok(report)
+}
+
+/// Log what seeding did
+pub fn log_seed_report(report: &SeedReport) {
+    info!(
+        "Seeded {} documents into {} new indices{}",
+        report.documents,
+        report.created.len(),
+        if report.skipped.is_empty() {
+            String::new()
+        } else {
+            format!(", skipped existing indices: {}", report.skipped.join(", "))
+        }
+    );
+}
+
+async fn create_index(storage: &Storage, name: &str, index: &IndexFixture) -> Result<SeedReport> {
+    storage
+        .create_index(name, index.settings.clone(), index.mappings.clone())
+        .await?;
+    for alias in &index.aliases {
+        storage.put_alias(name, alias).await?;
+    }
+    let actions = index
+        .documents
+        .iter()
+        .map(|(id, document)| BulkAction::Index {
+            index: name.to_string(),
+            id: Some(id.clone()),
+            document: document.clone(),
+        })
+        .collect();
+    Ok(SeedReport {
+        created: vec![name.to_string()],
+        skipped: Vec::new(),
+        documents: execute(storage, actions).await?,
+    })
+}
+
+async fn seed_bulk_file(
+    storage: &Storage,
+    path: &Path,
+    default_index: Option<&str>,
+) -> Result<usize> {
+    let content = tokio::fs::read_to_string(path).await?;
+    let actions = parse_bulk_ndjson(&content, default_index).map_err(|e| invalid(path, e))?;
+    execute(storage, actions).await
+}
+
+/// Fixture of a JSON or YAML file
+async fn read_definition(path: &Path) -> Result<Fixture> {
+    serde_json::from_value(read_file(path).await?).map_err(|e| invalid(path, e))
+}
+
+/// Content of a JSON or YAML file, by extension
+async fn read_file(path: &Path) -> Result<serde_json::Value> {
+    let content = tokio::fs::read_to_string(path).await?;
+    match extension(path) {
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(|e| invalid(path, e)),
+        _ => serde_json::from_str(&content).map_err(|e| invalid(path, e)),
+    }
+}
+
+/// Execute seeding actions, failing on the first action that failed
+///
+/// Returns the number of documents written.
+async fn execute(storage: &Storage, actions: Vec<BulkAction>) -> Result<usize> {
+    let mut written = 0;
+    for result in storage.execute_bulk(actions).await {
+        let (index, id, status, error, ..) = result?;
+        if status >= 300 {
+            return Err(GbsError::InvalidRequest(format!(
+                "Seeding document [{}] of index [{}] failed: {}",
+                id,
+                index,
+                error.unwrap_or_else(|| status.to_string())
+            )));
+        }
+        written += 1;
+    }
+    Ok(written)
+}
+
+fn extension(path: &Path) -> Option<&str> {
+    path.extension().and_then(|extension| extension.to_str())
+}
+
+fn invalid(path: &Path, error: impl std::fmt::Display) -> GbsError {
+    GbsError::InvalidRequest(format!("Invalid fixture [{}]: {}", path.display(), error))
+}
+
+/// Aliases are listed by name, or as an object keyed by alias name as in a
+/// create index request
+fn alias_names<'de, D>(deserializer: D) -> std::result::Result<Vec<String>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Aliases {
+        Names(Vec<String>),
+        Object(BTreeMap<String, serde_json::Value>),
+    }
+    Ok(match Aliases::deserialize(deserializer)? {
+        Aliases::Names(names) => names,
+        Aliases::Object(aliases) => aliases.into_keys().collect(),
+    })
+}
@@ -1,11 +1,30 @@
+pub mod audit;
+pub mod auth;
 pub mod bulk;
 pub mod bulk_ops;
+pub mod cli;
 pub mod client;
+pub mod daemon;
 pub mod document;
+pub mod document_cache;
+pub mod document_codec;
+#[cfg(feature = "embedded")]
+pub mod embedded;
 pub mod error;
+pub mod fixtures;
 pub mod index;
+pub mod logging;
+pub mod maintenance;
+pub mod metrics;
 pub mod models;
+pub mod self_test;
 pub mod server;
+pub mod soak;
+pub mod tantivy_export;
+pub mod tasks;
+pub mod usage;
+#[cfg(windows)]
+pub mod win_service;
 pub use server::AppState;
 pub mod config;
 pub mod storage;
@@ -0,0 +1,214 @@
+//! Log output setup
+//!
+//! Logs are written to stdout (the log file for the Windows service) as text,
+//! or with `logging.format: json` as one JSON object per line, laid out as
+//! tracing-subscriber's JSON formatter does:
+//!
+//! ```json
+//! {"timestamp":"2024-06-01T12:00:00.000000Z","level":"INFO","fields":{"message":"..."},
+//!  "target":"gbs::server","span":{"name":"request","request_id":"..."},"spans":[...]}
+//! ```
+//!
+//! `span` is the innermost span of the event and `spans` all of them from the
+//! root, each with its fields, so every line logged while a request is served
+//! carries its `request_id`.
+
+use serde_json::{Map, Value};
+use std::fmt;
+use std::fs::OpenOptions;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{reload, EnvFilter};
+
+use crate::config::{LogFormat, LoggingConfig};
+use crate::error::GbsError;
+use crate::server::LogLevelSetter;
+
+/// Set up the global log output
+///
+/// `RUST_LOG` takes precedence over the configured level. The returned setter
+/// changes the level of the running process, unless `RUST_LOG` is set.
+pub fn init(config: &LoggingConfig) -> LogLevelSetter {
+    // Background servers write to a log file, which should not get color codes
+    init_with_writer(config, std::io::stdout, std::io::stdout().is_terminal())
+}
+
+/// Set up the global log output appending to a file, for processes without
+/// a console such as the Windows service
+pub fn init_to_file(config: &LoggingConfig, path: &Path) -> std::io::Result<LogLevelSetter> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(init_with_writer(config, Mutex::new(file), false))
+}
+
+fn init_with_writer<W>(config: &LoggingConfig, writer: W, ansi: bool) -> LogLevelSetter
+where
+    W: for<'writer> MakeWriter<'writer> + Send + Sync + 'static,
+{
+    let filter = if std::env::var("RUST_LOG").is_ok() {
+        EnvFilter::from_default_env()
+    } else {
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&config.level))
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer)
+        .with_ansi(ansi);
+
+    match config.format {
+        LogFormat::Text => {
+            let subscriber = builder.with_filter_reloading();
+            let handle = subscriber.reload_handle();
+            subscriber.init();
+            level_setter(move |filter| handle.reload(filter))
+        }
+        LogFormat::Json => {
+            let subscriber = builder
+                .with_ansi(false)
+                .event_format(JsonFormat)
+                .fmt_fields(JsonFields)
+                .with_filter_reloading();
+            let handle = subscriber.reload_handle();
+            subscriber.init();
+            level_setter(move |filter| handle.reload(filter))
+        }
+    }
+}
+
+fn level_setter(
+    reload: impl Fn(EnvFilter) -> Result<(), reload::Error> + Send + Sync + 'static,
+) -> LogLevelSetter {
+    Arc::new(move |level| {
+        // RUST_LOG takes precedence over the config, as at startup
+        if std::env::var("RUST_LOG").is_ok() {
+            tracing::warn!("RUST_LOG is set, not changing the log level to {}", level);
+            return Ok(());
+        }
+        let filter = EnvFilter::try_new(level)
+            .map_err(|e| GbsError::InvalidRequest(format!("Invalid log level {}: {}", level, e)))?;
+        reload(filter).map_err(|e| GbsError::InvalidRequest(e.to_string()))
+    })
+}
+
+/// Formats events as JSON objects, one per line
+///
+/// Span fields are read as recorded by [`JsonFields`]; with another field
+/// formatter, spans are reported by name only.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+
+        let spans: Vec<Value> = ctx
+            .event_scope()
+            .into_iter()
+            .flat_map(|scope| scope.from_root())
+            .map(|span| {
+                let mut object = span
+                    .extensions()
+                    .get::<FormattedFields<N>>()
+                    .and_then(|formatted| serde_json::from_str(&formatted.fields).ok())
+                    .unwrap_or_else(Map::new);
+                object.insert("name".to_string(), Value::from(span.name()));
+                Value::Object(object)
+            })
+            .collect();
+
+        let mut line = Map::new();
+        line.insert(
+            "timestamp".to_string(),
+            Value::from(chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Micros, true)),
+        );
+        line.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        line.insert("fields".to_string(), Value::Object(fields));
+        line.insert("target".to_string(), Value::from(metadata.target()));
+        if let Some(span) = spans.last() {
+            line.insert("span".to_string(), span.clone());
+            line.insert("spans".to_string(), Value::Array(spans));
+        }
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+/// Records span fields as a JSON object, for [`JsonFormat`]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: tracing_subscriber::field::RecordFields>(
+        &self,
+        mut writer: Writer<'writer>,
+        fields: R,
+    ) -> fmt::Result {
+        let mut object = Map::new();
+        fields.record(&mut JsonVisitor(&mut object));
+        write!(writer, "{}", Value::Object(object))
+    }
+
+    fn add_fields(
+        &self,
+        current: &'writer mut FormattedFields<Self>,
+        fields: &tracing::span::Record<'_>,
+    ) -> fmt::Result {
+        // Merge into the recorded object rather than appending a second one
+        let mut object: Map<String, Value> =
+            serde_json::from_str(&current.fields).unwrap_or_default();
+        fields.record(&mut JsonVisitor(&mut object));
+        current.fields = Value::Object(object).to_string();
+        Ok(())
+    }
+}
+
+/// Collects field values into a JSON object
+struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(
+            field.name().to_string(),
+            Value::from(format!("{:?}", value)),
+        );
+    }
+}
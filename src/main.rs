@@ -1,23 +1,271 @@
-use gbs::config::Config;
-use gbs::server::{create_router, AppState};
-use gbs::storage::Storage;
-use tracing_subscriber;
+use clap::Parser;
+use gbs::audit::AuditLog;
+use gbs::auth::AuthStore;
+#[cfg(windows)]
+use gbs::cli::ServiceCommand;
+use gbs::cli::{Cli, Command};
+use gbs::config::{Config, Durability};
+use gbs::daemon::{self, DaemonStatus, PidFile};
+use gbs::error::GbsError;
+use gbs::fixtures;
+use gbs::logging;
+use gbs::maintenance::{self, ExportOptions, ImportOptions};
+use gbs::self_test;
+use gbs::server::{
+    create_router, spawn_reload_on_sighup, AppState, LogLevelSetter, Proxy, RouteGroup,
+};
+#[cfg(feature = "tls")]
+use gbs::server::{
+    redirect_router, serve_tls, spawn_certificate_reload, tls_acceptor, CertificateResolver,
+};
+use gbs::soak;
+use gbs::storage::{
+    spawn_durability_flusher, spawn_retention, spawn_tier_demotion, Federation, IngestRoutes,
+    ParallelScoring, Storage, StorageLimits,
+};
+use gbs::tantivy_export::{self, TantivyExportOptions};
+use gbs::usage::UsageTracker;
+#[cfg(windows)]
+use gbs::win_service;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Exit code of `gbs status` when no server is running (as for LSB init scripts)
+const STATUS_NOT_RUNNING: i32 = 3;
+/// How long `gbs stop` waits for the server to exit
+const STOP_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    // The service manager starts services in the system directory
+    #[cfg(windows)]
+    if let Some(Command::Service {
+        command: ServiceCommand::Run { dir: Some(dir) },
+    }) = &cli.command
+    {
+        std::env::set_current_dir(dir)?;
+    }
+    let service = matches!(cli.command, Some(Command::Service { .. }));
+
     // Load configuration
-    let config = Config::load()?;
+    let mut config = load_config(cli.config.as_deref(), cli.data_dir.as_deref(), false)?;
 
-    // Initialize tracing
-    // RUST_LOG environment variable takes precedence over config
-    let log_filter = if std::env::var("RUST_LOG").is_ok() {
-        tracing_subscriber::EnvFilter::from_default_env()
+    // Initialize tracing; a service has no console to log to
+    let log_level_setter = if service {
+        logging::init_to_file(&config.logging, &config.log_file_path())?
     } else {
-        tracing_subscriber::EnvFilter::try_from_default_env()
-            .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new(&config.logging.level))
+        logging::init(&config.logging)
+    };
+
+    let command = cli.command_to_run().unwrap_or_else(|e| e.exit());
+    let seed_dir = match command {
+        Command::Serve(args) => args.seed,
+        Command::Start => {
+            let pid = daemon::start(&config.pid_file_path(), &config.log_file_path())?;
+            println!(
+                "gbs started (pid {}), logging to {}",
+                pid,
+                config.log_file_path().display()
+            );
+            return Ok(());
+        }
+        Command::Status => {
+            match daemon::status(&config.pid_file_path())? {
+                DaemonStatus::Running(pid) => println!("gbs is running (pid {})", pid),
+                DaemonStatus::Stale(pid) => {
+                    println!("gbs is not running (stale pid file for pid {})", pid);
+                    std::process::exit(STATUS_NOT_RUNNING);
+                }
+                DaemonStatus::Stopped => {
+                    println!("gbs is not running");
+                    std::process::exit(STATUS_NOT_RUNNING);
+                }
+            }
+            return Ok(());
+        }
+        Command::Stop => {
+            match daemon::stop(&config.pid_file_path(), STOP_TIMEOUT)? {
+                Some(pid) => println!("gbs stopped (pid {})", pid),
+                None => println!("gbs is not running"),
+            }
+            return Ok(());
+        }
+        Command::Service { command } => {
+            #[cfg(windows)]
+            match command {
+                ServiceCommand::Install => {
+                    win_service::install(&std::env::current_dir()?)?;
+                    println!("gbs service installed, start it with `gbs start`");
+                }
+                ServiceCommand::Uninstall => {
+                    win_service::uninstall()?;
+                    println!("gbs service uninstalled");
+                }
+                ServiceCommand::Run { .. } => {
+                    let runtime = tokio::runtime::Handle::current();
+                    let data_dir = cli.data_dir;
+                    tokio::task::block_in_place(|| {
+                        win_service::run(move || {
+                            runtime.block_on(serve(
+                                config,
+                                cli.config,
+                                data_dir,
+                                log_level_setter,
+                                None,
+                            ))
+                        })
+                    })?;
+                }
+            }
+            #[cfg(not(windows))]
+            {
+                let _ = command;
+                anyhow::bail!("gbs service is only available on Windows, use gbs start instead");
+            }
+            #[cfg(windows)]
+            return Ok(());
+        }
+        // `gbs soak --data-dir <dir> [options]` qualifies the storage layer
+        // instead of serving
+        Command::Soak(args) => {
+            let options = args
+                .options(cli.data_dir.clone())
+                .unwrap_or_else(|e| e.exit());
+            ensure_stopped(&config, "soak testing")?;
+            tracing::info!(
+                "Starting soak test against {} (seed {})",
+                options.data_dir.display(),
+                options.seed
+            );
+            let stop = async {
+                let _ = tokio::signal::ctrl_c().await;
+            };
+            let report = soak::run_soak(&options, stop).await?;
+            soak::log_soak_report(&report);
+            return Ok(());
+        }
+        // The commands below work on the data directory, which the server
+        // holds locked while running
+        Command::ExportTantivy { index, out } => {
+            ensure_stopped(&config, "exporting")?;
+            let options = TantivyExportOptions {
+                data_dir: std::mem::take(&mut config.storage.data_dir).into(),
+                index,
+                out,
+            };
+            let report =
+                tokio::task::spawn_blocking(move || tantivy_export::export_tantivy(&options))
+                    .await??;
+            tantivy_export::log_tantivy_export_report(&report);
+            return Ok(());
+        }
+        Command::Import { index, file } => {
+            ensure_stopped(&config, "importing")?;
+            let options = ImportOptions {
+                data_dir: std::mem::take(&mut config.storage.data_dir).into(),
+                index,
+                file,
+            };
+            let report = maintenance::import(&options).await?;
+            maintenance::log_import_report(&report);
+            return Ok(());
+        }
+        Command::Export { index, file } => {
+            ensure_stopped(&config, "exporting")?;
+            let options = ExportOptions {
+                data_dir: std::mem::take(&mut config.storage.data_dir).into(),
+                index,
+                file,
+            };
+            let documents = {
+                let options = options.clone();
+                tokio::task::spawn_blocking(move || maintenance::export(&options)).await??
+            };
+            tracing::info!(
+                "Exported {} documents of index '{}' to {}",
+                documents,
+                options.index,
+                options.file.display()
+            );
+            return Ok(());
+        }
+        Command::Compact => {
+            ensure_stopped(&config, "compacting")?;
+            let data_dir = PathBuf::from(&config.storage.data_dir);
+            let report = {
+                let data_dir = data_dir.clone();
+                tokio::task::spawn_blocking(move || maintenance::compact(&data_dir)).await??
+            };
+            maintenance::log_compact_report(&data_dir, &report);
+            return Ok(());
+        }
+        Command::Validate => {
+            ensure_stopped(&config, "validating")?;
+            let data_dir = PathBuf::from(&config.storage.data_dir);
+            let verification = {
+                let data_dir = data_dir.clone();
+                tokio::task::spawn_blocking(move || maintenance::validate(&data_dir)).await??
+            };
+            maintenance::log_validation(&data_dir, &verification);
+            if !verification.problems.is_empty() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+    };
+
+    serve(config, cli.config, cli.data_dir, log_level_setter, seed_dir).await
+}
+
+/// Load the configuration from `--config` or the default locations, with
+/// `--data-dir` replacing the configured data directory
+///
+/// A reload fails rather than falling back to the defaults.
+fn load_config(
+    config_file: Option<&Path>,
+    data_dir: Option<&Path>,
+    reload: bool,
+) -> anyhow::Result<Config> {
+    let mut config = match config_file {
+        Some(path) => Config::load_from(path)?,
+        None if reload => Config::reload()?,
+        None => Config::load()?,
+    };
+    if let Some(data_dir) = data_dir {
+        config.storage.data_dir = data_dir.display().to_string();
+    }
+    Ok(config)
+}
+
+/// Run the server until it is shut down, then flush the storage
+async fn serve(
+    config: Config,
+    config_file: Option<PathBuf>,
+    data_dir: Option<PathBuf>,
+    log_level_setter: LogLevelSetter,
+    seed_dir: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    #[cfg(not(feature = "tls"))]
+    if let Some(tls) = &config.server.tls {
+        anyhow::bail!(
+            "server.tls is configured (cert_path={}, key_path={}) but gbs was built without \
+             the tls feature; rebuild with it or terminate TLS in a reverse proxy",
+            tls.cert_path,
+            tls.key_path
+        );
+    }
+    // Certificates are checked before anything is started
+    #[cfg(feature = "tls")]
+    let certificates = match &config.server.tls {
+        Some(tls) => Some(Arc::new(CertificateResolver::load(tls)?)),
+        None => None,
     };
 
-    tracing_subscriber::fmt().with_env_filter(log_filter).init();
+    if let DaemonStatus::Running(pid) = daemon::status(&config.pid_file_path())? {
+        anyhow::bail!("gbs is already running with pid {}", pid);
+    }
 
     tracing::info!("Starting Gummy Bear Search server");
     tracing::info!(
@@ -29,15 +277,76 @@ async fn main() -> anyhow::Result<()> {
     );
 
     // Create storage with Sled persistence
-    let storage = Storage::with_sled(&config.storage.data_dir)?;
+    let storage = Storage::with_sled(&config.storage.data_dir)?
+        .with_limits(StorageLimits::from_config(&config.storage))
+        .with_ingest_routes(IngestRoutes::from_config(&config.ingest)?)
+        .with_durability(config.storage.durability)
+        .with_lazy_loading(config.storage.lazy_loading)
+        .with_document_cache_budget(config.storage.document_cache_bytes)
+        .with_federation(Federation::from_config(&config.federation)?)
+        .with_parallel_scoring(ParallelScoring::from_config(&config.search));
     storage.load_from_backend().await?;
+    storage.create_federated_indices().await?;
 
-    let state = AppState {
-        storage: std::sync::Arc::new(storage),
-        es_version: config.es_version.clone(),
-    };
+    // Verify the data directory works before accepting traffic
+    if config.server.self_test {
+        let report = self_test::run_self_test(&storage).await?;
+        self_test::log_self_test_report(&report);
+    }
+    // create_router serves every route group
+    self_test::log_feature_report(&config, &RouteGroup::ALL);
+
+    if let Some(dir) = &seed_dir {
+        tracing::info!("Seeding indices from {}", dir.display());
+        let report = fixtures::seed_dir(&storage, dir).await?;
+        fixtures::log_seed_report(&report);
+    }
+
+    if let Some(warm_after) = config.storage.tiering.warm_after_secs {
+        spawn_tier_demotion(
+            storage.clone(),
+            Duration::from_secs(warm_after),
+            Duration::from_secs(config.storage.tiering.check_interval_secs.max(1)),
+        );
+    }
+
+    spawn_retention(
+        storage.clone(),
+        Duration::from_secs(config.storage.retention.check_interval_secs.max(1)),
+    );
+
+    if config.storage.durability == Durability::Async {
+        spawn_durability_flusher(
+            storage.clone(),
+            Duration::from_millis(config.storage.flush_interval_ms.max(1)),
+        );
+    }
+
+    let auth = AuthStore::load(&config.security, &storage).await?;
+    let proxy = Proxy::from_config(&config.proxy, config.proxy_recordings_path()).await?;
+
+    let state = AppState::new(Arc::new(storage.clone()), config.es_version.clone())
+        .with_auth(auth)
+        .with_config(config.clone())
+        .with_config_loader(Arc::new(move || {
+            load_config(config_file.as_deref(), data_dir.as_deref(), true)
+                .map_err(|e| GbsError::InvalidRequest(e.to_string()))
+        }))
+        .with_log_level_setter(log_level_setter)
+        .with_usage_tracker(UsageTracker::from_config(&config.usage))
+        .with_proxy(proxy)
+        .with_audit_log(AuditLog::from_config(
+            &config.audit,
+            config.audit_file_path(),
+            storage.clone(),
+        ))
+        .with_http_address(config.server_addr());
+
+    spawn_reload_on_sighup(state.config.clone(), state.auth.clone());
 
     // Create app
+    let shutdown = state.shutdown.clone();
+    let writes = state.writes.clone();
     let app = create_router(state);
 
     // Start server
@@ -45,7 +354,82 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Gummy Bear Search server listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    #[cfg(feature = "tls")]
+    let acceptor = match (&config.server.tls, &certificates) {
+        (Some(tls), Some(certificates)) => {
+            tracing::info!(
+                "Terminating TLS with certificate {}, reloaded every {}s",
+                tls.cert_path,
+                tls.reload_interval_secs
+            );
+            spawn_certificate_reload(
+                certificates.clone(),
+                Duration::from_secs(tls.reload_interval_secs.max(1)),
+            );
+            if let Some(port) = tls.redirect_port {
+                let redirect_addr = std::net::SocketAddr::new(addr.ip(), port);
+                let redirect_listener = tokio::net::TcpListener::bind(redirect_addr).await?;
+                tracing::info!("Redirecting http://{} to https", redirect_addr);
+                let redirecting = shutdown.clone();
+                tokio::spawn(async move {
+                    let redirect = axum::serve(redirect_listener, redirect_router(addr.port()))
+                        .with_graceful_shutdown(async move { redirecting.started().await });
+                    if let Err(e) = redirect.await {
+                        tracing::warn!("HTTP to HTTPS redirect stopped: {}", e);
+                    }
+                });
+            }
+            Some(tls_acceptor(certificates.clone())?)
+        }
+        _ => None,
+    };
+
+    // Written once the server is about to accept connections, which is what
+    // `gbs start` waits for
+    let pid_file = PidFile::create(config.pid_file_path())?;
+    tracing::info!("Pid file written to {}", pid_file.path().display());
+    let drain_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+    let draining = shutdown.clone();
+    let signal = async move {
+        daemon::shutdown_signal().await;
+        tracing::info!("Shutting down, draining in-flight requests");
+        draining.begin();
+    };
+    // Client addresses are recorded in the audit log
+    let server = async move {
+        #[cfg(feature = "tls")]
+        if let Some(acceptor) = acceptor {
+            return serve_tls(listener, acceptor, app, signal).await;
+        }
+        let app = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+        axum::serve(listener, app)
+            .with_graceful_shutdown(signal)
+            .await
+    };
+    tokio::select! {
+        result = server => result?,
+        _ = async {
+            shutdown.started().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => tracing::warn!(
+            "Requests still in flight after {}s, shutting down anyway",
+            drain_timeout.as_secs()
+        ),
+    }
+
+    // Connections left open after the drain timeout may still be writing
+    writes.close().await;
+    tracing::info!("Flushing storage");
+    storage.flush().await?;
+    drop(pid_file);
+
+    Ok(())
+}
 
+/// Fail if a server is running on the configured data directory
+fn ensure_stopped(config: &Config, action: &str) -> anyhow::Result<()> {
+    if let DaemonStatus::Running(pid) = daemon::status(&config.pid_file_path())? {
+        anyhow::bail!("stop gbs (pid {}) before {}", pid, action);
+    }
     Ok(())
 }
@@ -0,0 +1,323 @@
+//! Offline maintenance of a data directory
+//!
+//! `gbs import`, `gbs export`, `gbs compact` and `gbs validate` work on the
+//! data directory directly, without starting the HTTP server, so the server
+//! must not be running:
+//!
+//! - `gbs import <index> <file.ndjson>` indexes one document per line, creating
+//!   the index if needed. A line is either the document itself, which gets a
+//!   generated ID, or an `{"_id": ..., "_source": ...}` record as written by
+//!   `gbs export`.
+//! - `gbs export <index> <file>` writes every document of an index as an
+//!   `{"_id": ..., "_source": ...}` line.
+//! - `gbs compact` rewrites the database into a new one, which drops the
+//!   space Sled keeps for overwritten and deleted entries.
+//! - `gbs validate` reads back every entry of the database and reports the
+//!   ones that are corrupt or belong to no index.
+//!
+//! All of them take `--data-dir <path>` to work on another directory than
+//! the configured one.
+
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncBufReadExt;
+use tracing::{info, warn};
+
+use crate::bulk_ops::BulkAction;
+use crate::error::{GbsError, Result};
+use crate::storage::Storage;
+use crate::storage_backend::{BackendVerification, SledBackend};
+
+/// Number of documents indexed per bulk request when importing
+const IMPORT_BATCH_SIZE: usize = 1000;
+
+/// Sled's own files in a data directory, which compaction replaces
+const SLED_FILES: &[&str] = &["conf", "db", "blobs"];
+
+/// Settings of an import
+#[derive(Debug, Clone)]
+pub struct ImportOptions {
+    pub data_dir: PathBuf,
+    /// Index the documents are written to
+    pub index: String,
+    /// NDJSON file to read
+    pub file: PathBuf,
+}
+
+/// Settings of an export
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub data_dir: PathBuf,
+    /// Index whose documents are exported
+    pub index: String,
+    /// NDJSON file to write, replaced if it exists
+    pub file: PathBuf,
+}
+
+/// Result of an import
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub index: String,
+    /// Whether the index was created by the import
+    pub created: bool,
+    /// Number of documents written
+    pub documents: u64,
+    /// Number of documents the index rejected
+    pub failed: u64,
+}
+
+/// Index the documents of an NDJSON file
+///
+/// A line that is not valid JSON stops the import; documents written before
+/// it are kept. Documents the index rejects, e.g. because they do not fit its
+/// mappings, are logged and counted.
+pub async fn import(options: &ImportOptions) -> Result<ImportReport> {
+    // Other indices are not needed in memory
+    let storage = Storage::with_sled(&options.data_dir)?.with_lazy_loading(true);
+    storage.load_from_backend().await?;
+
+    let mut report = ImportReport {
+        index: options.index.clone(),
+        ..ImportReport::default()
+    };
+    if !storage.index_exists(&options.index).await? {
+        storage.create_index(&options.index, None, None).await?;
+        report.created = true;
+    }
+
+    let file = tokio::fs::File::open(&options.file).await.map_err(|e| {
+        GbsError::InvalidRequest(format!("Cannot read {}: {}", options.file.display(), e))
+    })?;
+    let mut lines = tokio::io::BufReader::new(file).lines();
+    let mut batch = Vec::with_capacity(IMPORT_BATCH_SIZE);
+    let mut line_number = 0;
+    while let Some(line) = lines.next_line().await? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: serde_json::Value = serde_json::from_str(&line).map_err(|e| {
+            GbsError::InvalidRequest(format!(
+                "Invalid JSON on line {} of {}: {}",
+                line_number,
+                options.file.display(),
+                e
+            ))
+        })?;
+        batch.push(import_action(&options.index, value, line_number)?);
+        if batch.len() == IMPORT_BATCH_SIZE {
+            import_batch(&storage, std::mem::take(&mut batch), &mut report).await?;
+        }
+    }
+    import_batch(&storage, batch, &mut report).await?;
+    storage.flush().await?;
+    Ok(report)
+}
+
+/// Bulk action of an imported line
+fn import_action(index: &str, value: serde_json::Value, line_number: usize) -> Result<BulkAction> {
+    let (id, document) = match value {
+        serde_json::Value::Object(mut record) if record.contains_key("_source") => {
+            let id = match record.remove("_id") {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(id)) => Some(id),
+                Some(id) => Some(id.to_string()),
+            };
+            (id, record.remove("_source").unwrap_or_default())
+        }
+        document => (None, document),
+    };
+    if !document.is_object() {
+        return Err(GbsError::InvalidRequest(format!(
+            "Line {} is not a JSON object",
+            line_number
+        )));
+    }
+    Ok(BulkAction::Index {
+        index: index.to_string(),
+        id,
+        document,
+    })
+}
+
+async fn import_batch(
+    storage: &Storage,
+    actions: Vec<BulkAction>,
+    report: &mut ImportReport,
+) -> Result<()> {
+    for result in storage.execute_bulk(actions).await {
+        match result {
+            Ok((_, _, status, ..)) if status < 300 => report.documents += 1,
+            Ok((_, id, status, error, ..)) => {
+                warn!(
+                    "Document [{}] was not imported: {}",
+                    id,
+                    error.unwrap_or_else(|| status.to_string())
+                );
+                report.failed += 1;
+            }
+            Err(e) => {
+                warn!("Document was not imported: {}", e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Log what an import did
+pub fn log_import_report(report: &ImportReport) {
+    info!(
+        "Imported {} documents into {}index '{}'{}",
+        report.documents,
+        if report.created { "new " } else { "" },
+        report.index,
+        if report.failed > 0 {
+            format!(", {} documents failed", report.failed)
+        } else {
+            String::new()
+        }
+    );
+}
+
+/// Write the documents of an index to an NDJSON file
+///
+/// Returns the number of documents written.
+pub fn export(options: &ExportOptions) -> Result<u64> {
+    let backend = SledBackend::new(&options.data_dir)?;
+    if backend.load_index_metadata(&options.index)?.is_none() {
+        return Err(GbsError::IndexNotFound(options.index.clone()));
+    }
+
+    let file = std::fs::File::create(&options.file).map_err(|e| {
+        GbsError::InvalidRequest(format!("Cannot write {}: {}", options.file.display(), e))
+    })?;
+    let mut out = BufWriter::new(file);
+    let mut documents = 0;
+    backend.for_each_document(&options.index, |id, document| {
+        serde_json::to_writer(
+            &mut out,
+            &serde_json::json!({ "_id": id, "_source": document }),
+        )?;
+        out.write_all(b"\n")?;
+        documents += 1;
+        Ok(())
+    })?;
+    out.flush()?;
+    Ok(documents)
+}
+
+/// Result of a compaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompactReport {
+    /// Size of the database on disk before, in bytes
+    pub size_before: u64,
+    /// Size of the database on disk after, in bytes
+    pub size_after: u64,
+}
+
+/// Rewrite the database of a data directory into a compact one
+///
+/// The database is copied to `<data_dir>.compact` and its files then replace
+/// the original ones, which are moved to `<data_dir>.old` meanwhile and
+/// removed at the end. Other files in the data directory, such as the log
+/// file, are left alone. Should the replacement be interrupted, the original
+/// database is still complete in `<data_dir>.old`.
+pub fn compact(data_dir: &Path) -> Result<CompactReport> {
+    if !data_dir.join("conf").exists() {
+        return Err(not_a_data_dir(data_dir));
+    }
+    let compacted_dir = sibling(data_dir, "compact");
+    let old_dir = sibling(data_dir, "old");
+    for dir in [&compacted_dir, &old_dir] {
+        if dir.exists() {
+            return Err(GbsError::InvalidRequest(format!(
+                "{} exists, remove it after checking it holds nothing needed",
+                dir.display()
+            )));
+        }
+    }
+
+    let (size_before, size_after) = {
+        let source = SledBackend::new(data_dir)?;
+        let size_before = source.size_on_disk()?;
+        let target = SledBackend::new(&compacted_dir)?;
+        target.db().import(source.db().export());
+        target.flush()?;
+        (size_before, target.size_on_disk()?)
+    };
+
+    std::fs::create_dir(&old_dir)?;
+    for entry in std::fs::read_dir(data_dir)? {
+        let entry = entry?;
+        if is_sled_file(&entry.file_name()) {
+            std::fs::rename(entry.path(), old_dir.join(entry.file_name()))?;
+        }
+    }
+    for entry in std::fs::read_dir(&compacted_dir)? {
+        let entry = entry?;
+        std::fs::rename(entry.path(), data_dir.join(entry.file_name()))?;
+    }
+    std::fs::remove_dir_all(&compacted_dir)?;
+    std::fs::remove_dir_all(&old_dir)?;
+
+    Ok(CompactReport {
+        size_before,
+        size_after,
+    })
+}
+
+/// Log what a compaction did
+pub fn log_compact_report(data_dir: &Path, report: &CompactReport) {
+    info!(
+        "Compacted {} from {} to {} bytes",
+        data_dir.display(),
+        report.size_before,
+        report.size_after
+    );
+}
+
+/// Read back every entry of the database of a data directory
+pub fn validate(data_dir: &Path) -> Result<BackendVerification> {
+    if !data_dir.join("conf").exists() {
+        return Err(not_a_data_dir(data_dir));
+    }
+    SledBackend::new(data_dir)?.verify()
+}
+
+/// Log what a validation found
+pub fn log_validation(data_dir: &Path, verification: &BackendVerification) {
+    for problem in &verification.problems {
+        warn!("{}", problem);
+    }
+    info!(
+        "Validated {} indices and {} documents in {}: {} problems found",
+        verification.indices,
+        verification.documents,
+        data_dir.display(),
+        verification.problems.len()
+    );
+}
+
+fn not_a_data_dir(data_dir: &Path) -> GbsError {
+    GbsError::InvalidRequest(format!(
+        "{} is not a gbs data directory",
+        data_dir.display()
+    ))
+}
+
+fn is_sled_file(name: &std::ffi::OsStr) -> bool {
+    name.to_str()
+        .is_some_and(|name| SLED_FILES.contains(&name) || name.starts_with("snap."))
+}
+
+/// `<data_dir>.<suffix>`, next to the data directory
+fn sibling(data_dir: &Path, suffix: &str) -> PathBuf {
+    let mut name = data_dir
+        .file_name()
+        .map(|name| name.to_os_string())
+        .unwrap_or_else(|| "data".into());
+    name.push(".");
+    name.push(suffix);
+    data_dir.with_file_name(name)
+}
@@ -0,0 +1,243 @@
+//! Prometheus metrics (`GET /_metrics`)
+//!
+//! Requests are counted and timed per method, route and status. Routes are
+//! the matched route patterns (e.g. `/:index/_search`), so the number of
+//! series stays bounded however many indices there are. Documents indexed
+//! and searches are counted per index, or per index expression for searches.
+//! Document counts and sizes of indices are read from storage at scrape time.
+//! Counters start at zero when the server starts.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::storage::IndexStats;
+
+/// Upper bounds of the request latency histogram buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+/// Route label of requests that matched no route
+pub const UNMATCHED_ROUTE: &str = "unmatched";
+
+/// Latency histogram of one route
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    /// Observations per bucket (not cumulative)
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|&le| seconds <= le) {
+            self.buckets[bucket] += 1;
+        }
+        self.sum += seconds;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    /// Requests per (method, route, status)
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// Latency per (method, route)
+    latency: BTreeMap<(String, String), Histogram>,
+    docs_indexed: BTreeMap<String, u64>,
+    searches: BTreeMap<String, u64>,
+}
+
+/// Metrics registry of the server
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    /// Record an answered request
+    pub fn record_request(&self, method: &str, route: &str, status: u16, elapsed: Duration) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+        counters
+            .latency
+            .entry((method.to_string(), route.to_string()))
+            .or_default()
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Record a document written to an index
+    pub fn record_indexed(&self, index: &str) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .docs_indexed
+            .entry(index.to_string())
+            .or_default() += 1;
+    }
+
+    /// Record a search of an index expression
+    pub fn record_search(&self, index: &str) {
+        *self
+            .counters
+            .lock()
+            .unwrap()
+            .searches
+            .entry(index.to_string())
+            .or_default() += 1;
+    }
+
+    /// Render the metrics in the Prometheus text exposition format
+    pub fn render(&self, indices: &[IndexStats]) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        write_header(
+            &mut out,
+            "gbs_http_requests_total",
+            "counter",
+            "HTTP requests answered, by method, route and status",
+        );
+        for ((method, route, status), count) in &counters.requests {
+            let labels = labels(&[
+                ("method", method),
+                ("route", route),
+                ("status", &status.to_string()),
+            ]);
+            let _ = writeln!(out, "gbs_http_requests_total{{{}}} {}", labels, count);
+        }
+
+        write_header(
+            &mut out,
+            "gbs_http_request_duration_seconds",
+            "histogram",
+            "HTTP request latency, by method and route",
+        );
+        for ((method, route), histogram) in &counters.latency {
+            let route_labels = labels(&[("method", method), ("route", route)]);
+            let mut cumulative = 0;
+            for (le, observations) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                cumulative += observations;
+                let _ = writeln!(
+                    out,
+                    "gbs_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    route_labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "gbs_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                route_labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "gbs_http_request_duration_seconds_sum{{{}}} {}",
+                route_labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "gbs_http_request_duration_seconds_count{{{}}} {}",
+                route_labels, histogram.count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "gbs_documents_indexed_total",
+            "counter",
+            "Documents written, by index",
+        );
+        for (index, count) in &counters.docs_indexed {
+            let _ = writeln!(
+                out,
+                "gbs_documents_indexed_total{{{}}} {}",
+                labels(&[("index", index)]),
+                count
+            );
+        }
+
+        write_header(
+            &mut out,
+            "gbs_searches_total",
+            "counter",
+            "Search requests, by index expression",
+        );
+        for (index, count) in &counters.searches {
+            let _ = writeln!(
+                out,
+                "gbs_searches_total{{{}}} {}",
+                labels(&[("index", index)]),
+                count
+            );
+        }
+
+        write_gauge(
+            &mut out,
+            "gbs_indices",
+            "Number of indices",
+            indices.len() as f64,
+        );
+        write_header(
+            &mut out,
+            "gbs_index_documents",
+            "gauge",
+            "Documents in the index",
+        );
+        for index in indices {
+            let _ = writeln!(
+                out,
+                "gbs_index_documents{{{}}} {}",
+                labels(&[("index", &index.name)]),
+                index.docs_count
+            );
+        }
+        write_header(
+            &mut out,
+            "gbs_index_size_bytes",
+            "gauge",
+            "Estimated size of the documents in the index",
+        );
+        for index in indices {
+            let _ = writeln!(
+                out,
+                "gbs_index_size_bytes{{{}}} {}",
+                labels(&[("index", &index.name)]),
+                index.size_in_bytes
+            );
+        }
+        out
+    }
+}
+
+/// Append a gauge without labels
+pub fn write_gauge(out: &mut String, name: &str, help: &str, value: f64) {
+    write_header(out, name, "gauge", help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// Format labels, escaping their values
+fn labels(labels: &[(&str, &str)]) -> String {
+    labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n");
+            format!("{}=\"{}\"", name, value)
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
@@ -0,0 +1,713 @@
+//! Data models of the API: index settings and mappings, search and bulk
+//! bodies, and the query DSL, as a typed AST (`QueryAst`) and as builders
+//! (`query`)
+
+pub mod query;
+
+pub use query::{BoolQuery, MatchQuery, Query, RangeQuery, SearchRequest, SortOrder};
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexSettings {
+    pub number_of_shards: Option<u32>,
+    pub number_of_replicas: Option<u32>,
+    pub analysis: Option<AnalysisSettings>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisSettings {
+    pub analyzer: Option<std::collections::HashMap<String, Analyzer>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Analyzer {
+    #[serde(rename = "type")]
+    pub type_: String,
+    pub tokenizer: Option<String>,
+    pub filter: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mapping {
+    pub properties: std::collections::HashMap<String, FieldMapping>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FieldMapping {
+    #[serde(rename = "text")]
+    Text { analyzer: Option<String> },
+    #[serde(rename = "keyword")]
+    Keyword,
+    #[serde(rename = "integer")]
+    Integer,
+    #[serde(rename = "long")]
+    Long,
+    #[serde(rename = "date")]
+    Date,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CreateIndexRequest {
+    pub settings: Option<IndexSettings>,
+    pub mappings: Option<Mapping>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchResponse {
+    pub took: u32,
+    pub timed_out: bool,
+    pub _shards: ShardsInfo,
+    pub hits: Hits,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShardsInfo {
+    pub total: u32,
+    pub successful: u32,
+    pub skipped: u32,
+    pub failed: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hits {
+    pub total: TotalHits,
+    pub max_score: Option<f64>,
+    pub hits: Vec<Hit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TotalHits {
+    pub value: u32,
+    pub relation: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hit {
+    pub _index: String,
+    pub _type: String,
+    pub _id: String,
+    pub _score: Option<f64>,
+    pub _source: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkOperation {
+    pub action: BulkAction,
+    pub document: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "action")]
+pub enum BulkAction {
+    #[serde(rename = "index")]
+    Index { _index: String, _id: Option<String> },
+    #[serde(rename = "create")]
+    Create { _index: String, _id: Option<String> },
+    #[serde(rename = "update")]
+    Update { _index: String, _id: String },
+    #[serde(rename = "delete")]
+    Delete { _index: String, _id: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkResponse {
+    pub took: u32,
+    pub errors: bool,
+    pub items: Vec<BulkItemResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkItemResponse {
+    #[serde(flatten)]
+    pub result: std::collections::HashMap<String, BulkOperationResult>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkOperationResult {
+    pub _index: String,
+    pub _type: String,
+    pub _id: String,
+    pub _version: Option<u32>,
+    pub result: Option<String>,
+    #[serde(default)]
+    pub _seq_no: Option<u64>,
+    #[serde(default)]
+    pub _primary_term: Option<u64>,
+    #[serde(default)]
+    pub forced_refresh: Option<bool>,
+    pub status: u16,
+    pub error: Option<BulkError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BulkError {
+    pub r#type: String,
+    pub reason: String,
+}
+
+/// Structured form of a query DSL request body
+///
+/// Queries are executed from their JSON form; `QueryAst` gives Rust tooling a
+/// typed view of the same queries to analyze or rewrite them before sending
+/// them to gbs. It covers the query types gbs supports. The main value of each
+/// leaf query is a typed field; the other options (`boost`, `operator`,
+/// `fuzziness`, ...) are kept as-is in `params`, so
+/// `QueryAst::parse(&json)?.to_json()` is equivalent to `json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "serde_json::Value", into = "serde_json::Value")]
+pub enum QueryAst {
+    /// `match_all`, also used for an empty query object
+    MatchAll { params: QueryParams },
+    /// `match`: full-text match of the terms of `query`
+    Match {
+        field: String,
+        query: String,
+        params: QueryParams,
+    },
+    /// `match_phrase`: the terms of `query` in order
+    MatchPhrase {
+        field: String,
+        query: String,
+        params: QueryParams,
+    },
+    /// `multi_match`: full-text match over several fields
+    MultiMatch {
+        query: String,
+        fields: Vec<String>,
+        params: QueryParams,
+    },
+    /// `fuzzy`: terms within an edit distance of `value`
+    Fuzzy {
+        field: String,
+        value: String,
+        params: QueryParams,
+    },
+    /// `term`: exact value
+    Term {
+        field: String,
+        value: serde_json::Value,
+        params: QueryParams,
+    },
+    /// `terms`: any of the exact values
+    Terms {
+        field: String,
+        values: Vec<serde_json::Value>,
+        params: QueryParams,
+    },
+    /// `ids`: documents by ID
+    Ids {
+        values: Vec<serde_json::Value>,
+        params: QueryParams,
+    },
+    /// `prefix`
+    Prefix {
+        field: String,
+        value: String,
+        params: QueryParams,
+    },
+    /// `wildcard`: `*` and `?` patterns
+    Wildcard {
+        field: String,
+        value: String,
+        params: QueryParams,
+    },
+    /// `range`: bounds (`gt`, `gte`, `lt`, `lte`) and options are in `params`
+    Range { field: String, params: QueryParams },
+    /// `nested`: `query` must match a single object under `path`
+    Nested {
+        path: String,
+        query: Box<QueryAst>,
+        params: QueryParams,
+    },
+    /// `bool`: combination of clauses (`minimum_should_match` and `boost` are
+    /// in `params`)
+    Bool {
+        must: Vec<QueryAst>,
+        should: Vec<QueryAst>,
+        must_not: Vec<QueryAst>,
+        filter: Vec<QueryAst>,
+        params: QueryParams,
+    },
+    /// `function_score`: `query` (`match_all` if not given) with its scores
+    /// adjusted by functions (`functions`, `score_mode`, `boost_mode`, ... are
+    /// in `params`)
+    FunctionScore {
+        query: Box<QueryAst>,
+        params: QueryParams,
+    },
+    /// `query_string`: Lucene syntax, expanded by gbs when searching
+    QueryString { query: String, params: QueryParams },
+    /// `percolate`: stored queries in `field` matching the given documents
+    /// (`document`, `documents`, or `index` and `id`, are in `params`)
+    Percolate { field: String, params: QueryParams },
+}
+
+/// Options of a query besides its main value
+pub type QueryParams = serde_json::Map<String, serde_json::Value>;
+
+impl QueryAst {
+    /// Parse a query DSL object such as `{ "match": { "title": "rust" } }`
+    pub fn parse(query: &serde_json::Value) -> crate::Result<Self> {
+        let query_obj = query
+            .as_object()
+            .ok_or_else(|| malformed("query", "must be an object"))?;
+        let Some((query_type, body)) = query_obj.iter().next() else {
+            return Ok(QueryAst::MatchAll {
+                params: QueryParams::new(),
+            });
+        };
+        if query_obj.len() > 1 {
+            return Err(malformed(
+                query_type,
+                "a query object must hold a single query type",
+            ));
+        }
+        let body = body
+            .as_object()
+            .ok_or_else(|| malformed(query_type, "body must be an object"))?;
+
+        Ok(match query_type.as_str() {
+            "match_all" => QueryAst::MatchAll {
+                params: body.clone(),
+            },
+            "match" => {
+                let (field, query, params) = parse_field_query(query_type, body, "query")?;
+                QueryAst::Match {
+                    field,
+                    query: string_value(query_type, "query", query)?,
+                    params,
+                }
+            }
+            "match_phrase" => {
+                let (field, query, params) = parse_field_query(query_type, body, "query")?;
+                QueryAst::MatchPhrase {
+                    field,
+                    query: string_value(query_type, "query", query)?,
+                    params,
+                }
+            }
+            "multi_match" => {
+                let mut params = body.clone();
+                let query = params
+                    .remove("query")
+                    .ok_or_else(|| malformed(query_type, "requires [query]"))?;
+                let fields = match params.remove("fields") {
+                    None => Vec::new(),
+                    Some(serde_json::Value::String(field)) => vec![field],
+                    Some(serde_json::Value::Array(fields)) => fields
+                        .into_iter()
+                        .map(|field| string_value(query_type, "fields", field))
+                        .collect::<crate::Result<_>>()?,
+                    Some(_) => return Err(malformed(query_type, "[fields] must be an array")),
+                };
+                QueryAst::MultiMatch {
+                    query: string_value(query_type, "query", query)?,
+                    fields,
+                    params,
+                }
+            }
+            "fuzzy" | "prefix" | "wildcard" => {
+                let (field, value, params) = parse_field_query(query_type, body, "value")?;
+                let value = string_value(query_type, "value", value)?;
+                match query_type.as_str() {
+                    "fuzzy" => QueryAst::Fuzzy {
+                        field,
+                        value,
+                        params,
+                    },
+                    "prefix" => QueryAst::Prefix {
+                        field,
+                        value,
+                        params,
+                    },
+                    _ => QueryAst::Wildcard {
+                        field,
+                        value,
+                        params,
+                    },
+                }
+            }
+            "term" => {
+                let (field, value, params) = parse_field_query(query_type, body, "value")?;
+                QueryAst::Term {
+                    field,
+                    value,
+                    params,
+                }
+            }
+            "terms" => {
+                let mut params = body.clone();
+                let fields: Vec<String> = params
+                    .iter()
+                    .filter(|(_, values)| values.is_array())
+                    .map(|(field, _)| field.clone())
+                    .collect();
+                let (field, values) = match fields.as_slice() {
+                    [field] => match params.remove(field) {
+                        Some(serde_json::Value::Array(values)) => (field.clone(), values),
+                        _ => unreachable!("the field holds an array"),
+                    },
+                    _ => {
+                        return Err(malformed(
+                            query_type,
+                            "requires a single field with an array of values",
+                        ))
+                    }
+                };
+                QueryAst::Terms {
+                    field,
+                    values,
+                    params,
+                }
+            }
+            "ids" => {
+                let mut params = body.clone();
+                let values = match params.remove("values") {
+                    Some(serde_json::Value::Array(values)) => values,
+                    _ => return Err(malformed(query_type, "requires a [values] array")),
+                };
+                QueryAst::Ids { values, params }
+            }
+            "range" => {
+                let (field, params) = single_field(query_type, body)?;
+                let params = params
+                    .as_object()
+                    .cloned()
+                    .ok_or_else(|| malformed(query_type, "bounds must be an object"))?;
+                QueryAst::Range { field, params }
+            }
+            "nested" => {
+                let mut params = body.clone();
+                let path = params
+                    .remove("path")
+                    .ok_or_else(|| malformed(query_type, "requires [path]"))?;
+                let query = params
+                    .remove("query")
+                    .ok_or_else(|| malformed(query_type, "requires [query]"))?;
+                QueryAst::Nested {
+                    path: string_value(query_type, "path", path)?,
+                    query: Box::new(QueryAst::parse(&query)?),
+                    params,
+                }
+            }
+            "bool" => {
+                let mut params = body.clone();
+                let mut clauses = |occur: &str| -> crate::Result<Vec<QueryAst>> {
+                    match params.remove(occur) {
+                        None => Ok(Vec::new()),
+                        Some(serde_json::Value::Array(clauses)) => {
+                            clauses.iter().map(QueryAst::parse).collect()
+                        }
+                        Some(clause) => Ok(vec![QueryAst::parse(&clause)?]),
+                    }
+                };
+                QueryAst::Bool {
+                    must: clauses("must")?,
+                    should: clauses("should")?,
+                    must_not: clauses("must_not")?,
+                    filter: clauses("filter")?,
+                    params,
+                }
+            }
+            "function_score" => {
+                let mut params = body.clone();
+                let query = match params.remove("query") {
+                    Some(query) => QueryAst::parse(&query)?,
+                    None => QueryAst::MatchAll {
+                        params: QueryParams::new(),
+                    },
+                };
+                QueryAst::FunctionScore {
+                    query: Box::new(query),
+                    params,
+                }
+            }
+            "query_string" => {
+                let mut params = body.clone();
+                let query = params
+                    .remove("query")
+                    .ok_or_else(|| malformed(query_type, "requires [query]"))?;
+                QueryAst::QueryString {
+                    query: string_value(query_type, "query", query)?,
+                    params,
+                }
+            }
+            "percolate" => {
+                let mut params = body.clone();
+                let field = params
+                    .remove("field")
+                    .ok_or_else(|| malformed(query_type, "requires [field]"))?;
+                QueryAst::Percolate {
+                    field: string_value(query_type, "field", field)?,
+                    params,
+                }
+            }
+            other => {
+                return Err(crate::GbsError::InvalidRequest(format!(
+                    "Unknown query type [{}]",
+                    other
+                )))
+            }
+        })
+    }
+
+    /// Serialize the query back to the query DSL
+    ///
+    /// Leaf queries without options use the short form
+    /// (`{ "match": { "title": "rust" } }`), the others the long form
+    /// (`{ "match": { "title": { "query": "rust", "operator": "and" } } }`).
+    pub fn to_json(&self) -> serde_json::Value {
+        use serde_json::{json, Value};
+
+        fn with(params: &QueryParams, entries: Vec<(&str, Value)>) -> Value {
+            let mut body = params.clone();
+            for (key, value) in entries {
+                body.insert(key.to_string(), value);
+            }
+            Value::Object(body)
+        }
+        fn field_query(field: &str, key: &str, value: Value, params: &QueryParams) -> Value {
+            if params.is_empty() && !value.is_object() {
+                json!({ field: value })
+            } else {
+                json!({ field: with(params, vec![(key, value)]) })
+            }
+        }
+        fn clauses(clauses: &[QueryAst]) -> Value {
+            Value::Array(clauses.iter().map(QueryAst::to_json).collect())
+        }
+
+        match self {
+            QueryAst::MatchAll { params } => json!({ "match_all": params }),
+            QueryAst::Match {
+                field,
+                query,
+                params,
+            } => json!({ "match": field_query(field, "query", json!(query), params) }),
+            QueryAst::MatchPhrase {
+                field,
+                query,
+                params,
+            } => json!({ "match_phrase": field_query(field, "query", json!(query), params) }),
+            QueryAst::MultiMatch {
+                query,
+                fields,
+                params,
+            } => {
+                let mut entries = vec![("query", json!(query))];
+                if !fields.is_empty() {
+                    entries.push(("fields", json!(fields)));
+                }
+                json!({ "multi_match": with(params, entries) })
+            }
+            QueryAst::Fuzzy {
+                field,
+                value,
+                params,
+            } => json!({ "fuzzy": field_query(field, "value", json!(value), params) }),
+            QueryAst::Term {
+                field,
+                value,
+                params,
+            } => json!({ "term": field_query(field, "value", value.clone(), params) }),
+            QueryAst::Terms {
+                field,
+                values,
+                params,
+            } => json!({ "terms": with(params, vec![(field.as_str(), json!(values))]) }),
+            QueryAst::Ids { values, params } => {
+                json!({ "ids": with(params, vec![("values", json!(values))]) })
+            }
+            QueryAst::Prefix {
+                field,
+                value,
+                params,
+            } => json!({ "prefix": field_query(field, "value", json!(value), params) }),
+            QueryAst::Wildcard {
+                field,
+                value,
+                params,
+            } => json!({ "wildcard": field_query(field, "value", json!(value), params) }),
+            QueryAst::Range { field, params } => json!({ "range": { field: params } }),
+            QueryAst::Nested {
+                path,
+                query,
+                params,
+            } => json!({
+                "nested": with(params, vec![("path", json!(path)), ("query", query.to_json())])
+            }),
+            QueryAst::Bool {
+                must,
+                should,
+                must_not,
+                filter,
+                params,
+            } => {
+                let entries = [
+                    ("must", must),
+                    ("should", should),
+                    ("must_not", must_not),
+                    ("filter", filter),
+                ]
+                .into_iter()
+                .filter(|(_, occur)| !occur.is_empty())
+                .map(|(key, occur)| (key, clauses(occur)))
+                .collect();
+                json!({ "bool": with(params, entries) })
+            }
+            QueryAst::FunctionScore { query, params } => {
+                json!({ "function_score": with(params, vec![("query", query.to_json())]) })
+            }
+            QueryAst::QueryString { query, params } => {
+                json!({ "query_string": with(params, vec![("query", json!(query))]) })
+            }
+            QueryAst::Percolate { field, params } => {
+                json!({ "percolate": with(params, vec![("field", json!(field))]) })
+            }
+        }
+    }
+
+    /// Queries directly inside this one (bool clauses, nested and function
+    /// score queries)
+    pub fn children(&self) -> Vec<&QueryAst> {
+        match self {
+            QueryAst::Bool {
+                must,
+                should,
+                must_not,
+                filter,
+                ..
+            } => must
+                .iter()
+                .chain(should)
+                .chain(must_not)
+                .chain(filter)
+                .collect(),
+            QueryAst::Nested { query, .. } | QueryAst::FunctionScore { query, .. } => {
+                vec![query.as_ref()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Mutable queries directly inside this one, for rewriting
+    pub fn children_mut(&mut self) -> Vec<&mut QueryAst> {
+        match self {
+            QueryAst::Bool {
+                must,
+                should,
+                must_not,
+                filter,
+                ..
+            } => must
+                .iter_mut()
+                .chain(should.iter_mut())
+                .chain(must_not.iter_mut())
+                .chain(filter.iter_mut())
+                .collect(),
+            QueryAst::Nested { query, .. } | QueryAst::FunctionScore { query, .. } => {
+                vec![query.as_mut()]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Call `visit` on this query and every query inside it, depth first
+    pub fn walk<'a>(&'a self, visit: &mut impl FnMut(&'a QueryAst)) {
+        visit(self);
+        for child in self.children() {
+            child.walk(visit);
+        }
+    }
+
+    /// Fields the query searches, in order of appearance and without
+    /// duplicates (`multi_match` fields may carry a boost such as `title^2`;
+    /// nested queries use full field paths)
+    pub fn fields(&self) -> Vec<&str> {
+        let mut fields: Vec<&str> = Vec::new();
+        self.walk(&mut |query| {
+            let query_fields: Vec<&str> = match query {
+                QueryAst::Match { field, .. }
+                | QueryAst::MatchPhrase { field, .. }
+                | QueryAst::Fuzzy { field, .. }
+                | QueryAst::Term { field, .. }
+                | QueryAst::Terms { field, .. }
+                | QueryAst::Prefix { field, .. }
+                | QueryAst::Wildcard { field, .. }
+                | QueryAst::Range { field, .. }
+                | QueryAst::Percolate { field, .. } => vec![field.as_str()],
+                QueryAst::MultiMatch { fields, .. } => fields.iter().map(String::as_str).collect(),
+                _ => Vec::new(),
+            };
+            for field in query_fields {
+                if !fields.contains(&field) {
+                    fields.push(field);
+                }
+            }
+        });
+        fields
+    }
+}
+
+impl TryFrom<serde_json::Value> for QueryAst {
+    type Error = crate::GbsError;
+
+    fn try_from(query: serde_json::Value) -> crate::Result<Self> {
+        QueryAst::parse(&query)
+    }
+}
+
+impl From<QueryAst> for serde_json::Value {
+    fn from(query: QueryAst) -> Self {
+        query.to_json()
+    }
+}
+
+fn malformed(query_type: &str, reason: &str) -> crate::GbsError {
+    crate::GbsError::InvalidRequest(format!("[{}] query malformed: {}", query_type, reason))
+}
+
+/// The only field of a query body and its value
+fn single_field(
+    query_type: &str,
+    body: &QueryParams,
+) -> crate::Result<(String, serde_json::Value)> {
+    let mut fields = body.iter();
+    match (fields.next(), fields.next()) {
+        (Some((field, value)), None) => Ok((field.clone(), value.clone())),
+        _ => Err(malformed(query_type, "requires a single field")),
+    }
+}
+
+/// Field, main value and options of a `{ "field": value }` or
+/// `{ "field": { "<key>": value, ...options } }` query body
+fn parse_field_query(
+    query_type: &str,
+    body: &QueryParams,
+    key: &str,
+) -> crate::Result<(String, serde_json::Value, QueryParams)> {
+    let (field, value) = single_field(query_type, body)?;
+    match value {
+        serde_json::Value::Object(mut params) => {
+            let value = params
+                .remove(key)
+                .ok_or_else(|| malformed(query_type, &format!("requires [{}]", key)))?;
+            Ok((field, value, params))
+        }
+        value => Ok((field, value, QueryParams::new())),
+    }
+}
+
+/// A string query value (numbers and booleans are taken as their text)
+fn string_value(query_type: &str, key: &str, value: serde_json::Value) -> crate::Result<String> {
+    match value {
+        serde_json::Value::String(value) => Ok(value),
+        serde_json::Value::Number(_) | serde_json::Value::Bool(_) => Ok(value.to_string()),
+        _ => Err(malformed(
+            query_type,
+            &format!("[{}] must be a string", key),
+        )),
+    }
+}
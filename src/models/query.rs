@@ -0,0 +1,628 @@
+//! Query DSL and search request builders
+//!
+//! Builders produce a `QueryAst`, so they serialize to exactly the query DSL
+//! the server parses, and deserialize from it, so that queries read from JSON
+//! can be inspected or extended with the builders:
+//!
+//! ```
+//! use gbs::models::query::{bool_query, match_query, range, term, Operator};
+//!
+//! let query = bool_query()
+//!     .must(match_query("title", "rust search").operator(Operator::And))
+//!     .filter(term("status", "published"))
+//!     .filter(range("published_at").gte("now-7d/d"));
+//! assert_eq!(
+//!     serde_json::to_value(&query).unwrap()["bool"]["filter"][0],
+//!     serde_json::json!({ "term": { "status": "published" } })
+//! );
+//! let json = serde_json::to_value(&query).unwrap();
+//! assert_eq!(serde_json::from_value::<gbs::models::BoolQuery>(json).unwrap(), query);
+//! ```
+
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::{json, Value};
+
+use crate::models::{QueryAst, QueryParams};
+
+/// A query, built with the functions of this module
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query(QueryAst);
+
+impl Query {
+    /// Set an option of the query, e.g. `boost` or `analyzer`
+    pub fn param(mut self, key: &str, value: impl Into<Value>) -> Self {
+        params_mut(&mut self.0).insert(key.to_string(), value.into());
+        self
+    }
+
+    /// Weight of the query's score
+    pub fn boost(self, boost: f64) -> Self {
+        self.param("boost", boost)
+    }
+
+    /// The query as the query DSL
+    pub fn to_json(&self) -> Value {
+        self.0.to_json()
+    }
+}
+
+impl From<Query> for QueryAst {
+    fn from(query: Query) -> Self {
+        query.0
+    }
+}
+
+impl From<QueryAst> for Query {
+    fn from(query: QueryAst) -> Self {
+        Query(query)
+    }
+}
+
+impl Serialize for Query {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Query {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        QueryAst::deserialize(deserializer).map(Query)
+    }
+}
+
+/// Deserialize a query of the types a builder stands for
+fn deserialize_query<'de, D: Deserializer<'de>>(
+    deserializer: D,
+    expected: &str,
+    accepts: fn(&QueryAst) -> bool,
+) -> Result<Query, D::Error> {
+    let query = Query::deserialize(deserializer)?;
+    if !accepts(&query.0) {
+        let found = query
+            .to_json()
+            .as_object()
+            .and_then(|object| object.keys().next().cloned())
+            .unwrap_or_default();
+        return Err(D::Error::custom(format!(
+            "expected {} query, found [{}]",
+            expected, found
+        )));
+    }
+    Ok(query)
+}
+
+/// Options of a query, whichever its type
+fn params_mut(query: &mut QueryAst) -> &mut QueryParams {
+    match query {
+        QueryAst::MatchAll { params }
+        | QueryAst::Match { params, .. }
+        | QueryAst::MatchPhrase { params, .. }
+        | QueryAst::MultiMatch { params, .. }
+        | QueryAst::Fuzzy { params, .. }
+        | QueryAst::Term { params, .. }
+        | QueryAst::Terms { params, .. }
+        | QueryAst::Ids { params, .. }
+        | QueryAst::Prefix { params, .. }
+        | QueryAst::Wildcard { params, .. }
+        | QueryAst::Range { params, .. }
+        | QueryAst::Nested { params, .. }
+        | QueryAst::Bool { params, .. }
+        | QueryAst::FunctionScore { params, .. }
+        | QueryAst::QueryString { params, .. }
+        | QueryAst::Percolate { params, .. } => params,
+    }
+}
+
+/// How the terms of a full-text query combine
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    /// Any term matches (the default)
+    Or,
+    /// Every term must match
+    And,
+}
+
+impl Operator {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Operator::Or => "or",
+            Operator::And => "and",
+        }
+    }
+}
+
+/// A full-text query: `match` or `multi_match`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchQuery(Query);
+
+impl MatchQuery {
+    /// How the terms of the text combine
+    pub fn operator(self, operator: Operator) -> Self {
+        Self(self.0.param("operator", operator.as_str()))
+    }
+
+    /// Allowed edit distance of the terms, e.g. `AUTO` or `1`
+    pub fn fuzziness(self, fuzziness: impl Into<String>) -> Self {
+        Self(self.0.param("fuzziness", fuzziness.into()))
+    }
+
+    /// Number or percentage of the terms that must match
+    pub fn minimum_should_match(self, minimum: impl Into<Value>) -> Self {
+        Self(self.0.param("minimum_should_match", minimum))
+    }
+
+    pub fn boost(self, boost: f64) -> Self {
+        Self(self.0.boost(boost))
+    }
+}
+
+impl From<MatchQuery> for Query {
+    fn from(query: MatchQuery) -> Self {
+        query.0
+    }
+}
+
+impl Serialize for MatchQuery {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatchQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_query(deserializer, "a [match] or [multi_match]", |query| {
+            matches!(query, QueryAst::Match { .. } | QueryAst::MultiMatch { .. })
+        })
+        .map(MatchQuery)
+    }
+}
+
+/// A `range` query
+#[derive(Debug, Clone, PartialEq)]
+pub struct RangeQuery(Query);
+
+impl RangeQuery {
+    /// Greater than; dates may use date math like `now-1d/d`
+    pub fn gt(self, value: impl Into<Value>) -> Self {
+        Self(self.0.param("gt", value))
+    }
+
+    pub fn gte(self, value: impl Into<Value>) -> Self {
+        Self(self.0.param("gte", value))
+    }
+
+    pub fn lt(self, value: impl Into<Value>) -> Self {
+        Self(self.0.param("lt", value))
+    }
+
+    pub fn lte(self, value: impl Into<Value>) -> Self {
+        Self(self.0.param("lte", value))
+    }
+
+    /// Date format of the bounds, e.g. `yyyy-MM-dd`
+    pub fn format(self, format: impl Into<String>) -> Self {
+        Self(self.0.param("format", format.into()))
+    }
+
+    /// Time zone of the bounds, e.g. `+01:00`
+    pub fn time_zone(self, time_zone: impl Into<String>) -> Self {
+        Self(self.0.param("time_zone", time_zone.into()))
+    }
+
+    pub fn boost(self, boost: f64) -> Self {
+        Self(self.0.boost(boost))
+    }
+}
+
+impl From<RangeQuery> for Query {
+    fn from(query: RangeQuery) -> Self {
+        query.0
+    }
+}
+
+impl Serialize for RangeQuery {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RangeQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserialize_query(deserializer, "a [range]", |query| {
+            matches!(query, QueryAst::Range { .. })
+        })
+        .map(RangeQuery)
+    }
+}
+
+/// A `bool` query combining other queries
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BoolQuery {
+    must: Vec<QueryAst>,
+    should: Vec<QueryAst>,
+    must_not: Vec<QueryAst>,
+    filter: Vec<QueryAst>,
+    params: QueryParams,
+}
+
+impl BoolQuery {
+    /// A clause that must match and counts towards the score
+    pub fn must(mut self, query: impl Into<Query>) -> Self {
+        self.must.push(query.into().0);
+        self
+    }
+
+    /// A clause that should match
+    pub fn should(mut self, query: impl Into<Query>) -> Self {
+        self.should.push(query.into().0);
+        self
+    }
+
+    /// A clause that must not match
+    pub fn must_not(mut self, query: impl Into<Query>) -> Self {
+        self.must_not.push(query.into().0);
+        self
+    }
+
+    /// A clause that must match without counting towards the score
+    pub fn filter(mut self, query: impl Into<Query>) -> Self {
+        self.filter.push(query.into().0);
+        self
+    }
+
+    /// Number or percentage of the `should` clauses that must match
+    pub fn minimum_should_match(mut self, minimum: impl Into<Value>) -> Self {
+        self.params
+            .insert("minimum_should_match".to_string(), minimum.into());
+        self
+    }
+
+    pub fn boost(mut self, boost: f64) -> Self {
+        self.params.insert("boost".to_string(), json!(boost));
+        self
+    }
+}
+
+impl From<BoolQuery> for Query {
+    fn from(query: BoolQuery) -> Self {
+        Query(QueryAst::Bool {
+            must: query.must,
+            should: query.should,
+            must_not: query.must_not,
+            filter: query.filter,
+            params: query.params,
+        })
+    }
+}
+
+impl Serialize for BoolQuery {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Query::from(self.clone()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for BoolQuery {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let query = deserialize_query(deserializer, "a [bool]", |query| {
+            matches!(query, QueryAst::Bool { .. })
+        })?;
+        match query.0 {
+            QueryAst::Bool {
+                must,
+                should,
+                must_not,
+                filter,
+                params,
+            } => Ok(BoolQuery {
+                must,
+                should,
+                must_not,
+                filter,
+                params,
+            }),
+            _ => unreachable!("checked to be a bool query"),
+        }
+    }
+}
+
+/// Every document
+pub fn match_all() -> Query {
+    Query(QueryAst::MatchAll {
+        params: QueryParams::new(),
+    })
+}
+
+/// Full-text match of the terms of `text` in `field`
+pub fn match_query(field: impl Into<String>, text: impl Into<String>) -> MatchQuery {
+    MatchQuery(Query(QueryAst::Match {
+        field: field.into(),
+        query: text.into(),
+        params: QueryParams::new(),
+    }))
+}
+
+/// Full-text match of the terms of `text` over several fields
+pub fn multi_match<F: Into<String>>(
+    text: impl Into<String>,
+    fields: impl IntoIterator<Item = F>,
+) -> MatchQuery {
+    MatchQuery(Query(QueryAst::MultiMatch {
+        query: text.into(),
+        fields: fields.into_iter().map(Into::into).collect(),
+        params: QueryParams::new(),
+    }))
+}
+
+/// The terms of `text` in order in `field`
+pub fn match_phrase(field: impl Into<String>, text: impl Into<String>) -> Query {
+    Query(QueryAst::MatchPhrase {
+        field: field.into(),
+        query: text.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Exact value of a field
+pub fn term(field: impl Into<String>, value: impl Into<Value>) -> Query {
+    Query(QueryAst::Term {
+        field: field.into(),
+        value: value.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Any of the exact values of a field
+pub fn terms<V: Into<Value>>(
+    field: impl Into<String>,
+    values: impl IntoIterator<Item = V>,
+) -> Query {
+    Query(QueryAst::Terms {
+        field: field.into(),
+        values: values.into_iter().map(Into::into).collect(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Documents by ID
+pub fn ids<I: Into<String>>(ids: impl IntoIterator<Item = I>) -> Query {
+    Query(QueryAst::Ids {
+        values: ids.into_iter().map(|id| json!(id.into())).collect(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Values of a field starting with `prefix`
+pub fn prefix(field: impl Into<String>, prefix: impl Into<String>) -> Query {
+    Query(QueryAst::Prefix {
+        field: field.into(),
+        value: prefix.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Values of a field matching a `*` and `?` pattern
+pub fn wildcard(field: impl Into<String>, pattern: impl Into<String>) -> Query {
+    Query(QueryAst::Wildcard {
+        field: field.into(),
+        value: pattern.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Terms of a field within an edit distance of `value`
+pub fn fuzzy(field: impl Into<String>, value: impl Into<String>) -> Query {
+    Query(QueryAst::Fuzzy {
+        field: field.into(),
+        value: value.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// Values of a field within bounds, set on the returned query
+pub fn range(field: impl Into<String>) -> RangeQuery {
+    RangeQuery(Query(QueryAst::Range {
+        field: field.into(),
+        params: QueryParams::new(),
+    }))
+}
+
+/// `query` matching a single object under `path`
+pub fn nested(path: impl Into<String>, query: impl Into<Query>) -> Query {
+    Query(QueryAst::Nested {
+        path: path.into(),
+        query: Box::new(query.into().0),
+        params: QueryParams::new(),
+    })
+}
+
+/// A query in Lucene syntax, e.g. `title:rust AND year:[2020 TO *]`
+pub fn query_string(query: impl Into<String>) -> Query {
+    Query(QueryAst::QueryString {
+        query: query.into(),
+        params: QueryParams::new(),
+    })
+}
+
+/// A query combining clauses, added on the returned query
+pub fn bool_query() -> BoolQuery {
+    BoolQuery::default()
+}
+
+/// Sort direction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// Body of a search request
+///
+/// ```
+/// use gbs::models::query::{match_query, SearchRequest, SortOrder};
+///
+/// let request = SearchRequest::new()
+///     .query(match_query("title", "rust"))
+///     .sort("published_at", SortOrder::Desc)
+///     .size(20);
+/// assert_eq!(request.to_json()["size"], 20);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SearchRequest {
+    query: Option<Query>,
+    from: Option<u32>,
+    size: Option<u32>,
+    sort: Vec<Value>,
+    source: Option<Value>,
+    highlight: Vec<String>,
+    aggregations: serde_json::Map<String, Value>,
+}
+
+impl SearchRequest {
+    /// A request for the first hits of every document
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query(mut self, query: impl Into<Query>) -> Self {
+        self.query = Some(query.into());
+        self
+    }
+
+    /// Number of hits to skip
+    pub fn from(mut self, from: u32) -> Self {
+        self.from = Some(from);
+        self
+    }
+
+    /// Number of hits to return
+    pub fn size(mut self, size: u32) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    /// Sort by a field, after the sorts added before
+    pub fn sort(mut self, field: impl Into<String>, order: SortOrder) -> Self {
+        self.sort
+            .push(json!({ field.into(): { "order": order.as_str() } }));
+        self
+    }
+
+    /// Return only these fields of the documents (`*` wildcards allowed)
+    pub fn source_includes<F: Into<String>>(mut self, fields: impl IntoIterator<Item = F>) -> Self {
+        let fields: Vec<String> = fields.into_iter().map(Into::into).collect();
+        self.source = Some(json!({ "includes": fields }));
+        self
+    }
+
+    /// Return no documents, e.g. when only aggregations are of interest
+    pub fn without_source(mut self) -> Self {
+        self.source = Some(json!(false));
+        self
+    }
+
+    /// Highlight the matched terms in a field
+    pub fn highlight(mut self, field: impl Into<String>) -> Self {
+        self.highlight.push(field.into());
+        self
+    }
+
+    /// Add an aggregation, given in the aggregation DSL
+    pub fn aggregation(mut self, name: impl Into<String>, aggregation: Value) -> Self {
+        self.aggregations.insert(name.into(), aggregation);
+        self
+    }
+
+    /// The request as a search request body
+    pub fn to_json(&self) -> Value {
+        let mut body = serde_json::Map::new();
+        if let Some(query) = &self.query {
+            body.insert("query".to_string(), query.to_json());
+        }
+        if let Some(from) = self.from {
+            body.insert("from".to_string(), json!(from));
+        }
+        if let Some(size) = self.size {
+            body.insert("size".to_string(), json!(size));
+        }
+        if !self.sort.is_empty() {
+            body.insert("sort".to_string(), json!(self.sort));
+        }
+        if let Some(source) = &self.source {
+            body.insert("_source".to_string(), source.clone());
+        }
+        if !self.highlight.is_empty() {
+            let fields: serde_json::Map<String, Value> = self
+                .highlight
+                .iter()
+                .map(|field| (field.clone(), json!({})))
+                .collect();
+            body.insert("highlight".to_string(), json!({ "fields": fields }));
+        }
+        if !self.aggregations.is_empty() {
+            body.insert("aggs".to_string(), json!(self.aggregations));
+        }
+        Value::Object(body)
+    }
+}
+
+impl Serialize for SearchRequest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_json().serialize(serializer)
+    }
+}
+
+/// Reads the keys `to_json` writes (`aggregations` is taken for `aggs`).
+/// Highlighted fields keep their names only, and other keys are an error,
+/// so that a request is not silently narrowed.
+impl<'de> Deserialize<'de> for SearchRequest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let body = serde_json::Map::<String, Value>::deserialize(deserializer)?;
+        let mut request = SearchRequest::new();
+        for (key, value) in body {
+            match key.as_str() {
+                "query" => {
+                    request.query = Some(Query::deserialize(value).map_err(D::Error::custom)?)
+                }
+                "from" => request.from = Some(u32::deserialize(value).map_err(D::Error::custom)?),
+                "size" => request.size = Some(u32::deserialize(value).map_err(D::Error::custom)?),
+                "sort" => {
+                    request.sort = match value {
+                        Value::Array(sort) => sort,
+                        sort => vec![sort],
+                    }
+                }
+                "_source" => request.source = Some(value),
+                "highlight" => {
+                    let fields = value
+                        .get("fields")
+                        .and_then(Value::as_object)
+                        .ok_or_else(|| D::Error::custom("[highlight] requires [fields]"))?;
+                    request.highlight = fields.keys().cloned().collect();
+                }
+                "aggs" | "aggregations" => {
+                    request.aggregations = match value {
+                        Value::Object(aggregations) => aggregations,
+                        _ => return Err(D::Error::custom(format!("[{}] must be an object", key))),
+                    }
+                }
+                other => {
+                    return Err(D::Error::custom(format!(
+                        "unsupported search request key [{}]",
+                        other
+                    )))
+                }
+            }
+        }
+        Ok(request)
+    }
+}
@@ -0,0 +1,287 @@
+//! Startup self-test and feature report
+//!
+//! Before the server starts accepting traffic, a short round trip through the
+//! storage layer (create index, index, get, search, delete, flush) verifies that
+//! the data directory is usable. The feature report summarizes the
+//! configuration and lists the route groups being served.
+
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+
+use crate::config::{Config, ProxyMode};
+use crate::error::{GbsError, Result};
+use crate::server::RouteGroup;
+use crate::storage::Storage;
+
+/// Prefix of the temporary indices created by the self-test
+pub const SELF_TEST_INDEX_PREFIX: &str = ".gbs-self-test-";
+
+/// Result of a successful self-test
+#[derive(Debug, Clone)]
+pub struct SelfTestReport {
+    /// Completed steps with their duration
+    pub steps: Vec<(&'static str, Duration)>,
+    /// Total duration of the self-test
+    pub elapsed: Duration,
+}
+
+/// Run the self-test against the given storage
+///
+/// Leftover self-test indices (e.g. from a crash during a previous run) are
+/// removed first. Any failing step aborts the test with an error naming the step.
+pub async fn run_self_test(storage: &Storage) -> Result<SelfTestReport> {
+    let start = Instant::now();
+    let mut steps = Vec::new();
+
+    for leftover in storage
+        .match_indices(&format!("{}*", SELF_TEST_INDEX_PREFIX))
+        .await
+    {
+        warn!("Removing leftover self-test index '{}'", leftover);
+        storage.delete_index(&leftover).await?;
+    }
+
+    let index_name = format!(
+        "{}{}",
+        SELF_TEST_INDEX_PREFIX,
+        uuid::Uuid::new_v4().simple()
+    );
+    let doc = serde_json::json!({ "message": "gummy bear self-test" });
+
+    let result = run_steps(storage, &index_name, &doc, &mut steps).await;
+    if result.is_err() && storage.index_exists(&index_name).await.unwrap_or(false) {
+        // Best effort cleanup, the original error is what matters
+        let _ = storage.delete_index(&index_name).await;
+    }
+    result?;
+
+    Ok(SelfTestReport {
+        steps,
+        elapsed: start.elapsed(),
+    })
+}
+
+async fn run_steps(
+    storage: &Storage,
+    index_name: &str,
+    doc: &serde_json::Value,
+    steps: &mut Vec<(&'static str, Duration)>,
+) -> Result<()> {
+    let step = Instant::now();
+    storage
+        .create_index(index_name, None, None)
+        .await
+        .map_err(|e| step_error("create index", e))?;
+    steps.push(("create index", step.elapsed()));
+
+    let step = Instant::now();
+    storage
+        .index_document(index_name, "1", doc.clone())
+        .await
+        .map_err(|e| step_error("index document", e))?;
+    steps.push(("index document", step.elapsed()));
+
+    let step = Instant::now();
+    let fetched = storage
+        .get_document(index_name, "1")
+        .await
+        .map_err(|e| step_error("get document", e))?;
+    if fetched.get("_source") != Some(doc) {
+        return Err(step_error(
+            "get document",
+            GbsError::Storage("document read back differs from document written".to_string()),
+        ));
+    }
+    steps.push(("get document", step.elapsed()));
+
+    let step = Instant::now();
+    // An index template may give the index a refresh interval
+    storage
+        .refresh_index(index_name)
+        .await
+        .map_err(|e| step_error("search", e))?;
+    let query = serde_json::json!({ "match": { "message": "self-test" } });
+    let result = storage
+        .search(index_name, &query, None, None, None, None, None)
+        .await
+        .map_err(|e| step_error("search", e))?;
+    let total = result["hits"]["total"]["value"].as_u64().unwrap_or(0);
+    if total != 1 {
+        return Err(step_error(
+            "search",
+            GbsError::Storage(format!("expected 1 hit, got {}", total)),
+        ));
+    }
+    steps.push(("search", step.elapsed()));
+
+    let step = Instant::now();
+    storage
+        .delete_document(index_name, "1")
+        .await
+        .map_err(|e| step_error("delete document", e))?;
+    storage
+        .delete_index(index_name)
+        .await
+        .map_err(|e| step_error("delete index", e))?;
+    steps.push(("delete", step.elapsed()));
+
+    let step = Instant::now();
+    storage.flush().await.map_err(|e| step_error("flush", e))?;
+    steps.push(("flush", step.elapsed()));
+
+    Ok(())
+}
+
+fn step_error(step: &str, error: GbsError) -> GbsError {
+    GbsError::Storage(format!("Self-test failed at step '{}': {}", step, error))
+}
+
+/// Build the feature report of a configuration and the route groups served
+///
+/// Each entry is a feature family and a human-readable summary of its
+/// settings.
+pub fn feature_report(config: &Config, groups: &[RouteGroup]) -> Vec<(&'static str, String)> {
+    vec![
+        (
+            "apis",
+            groups
+                .iter()
+                .map(|group| group.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        ),
+        ("search", search_summary(config)),
+        (
+            "storage",
+            format!(
+                "sled (data_dir={}, durability={}), hot/warm tiering{}",
+                config.storage.data_dir,
+                config.storage.durability.as_str(),
+                match config.storage.tiering.warm_after_secs {
+                    Some(secs) => format!(" (warm after {}s)", secs),
+                    None => String::new(),
+                }
+            ),
+        ),
+        ("limits", limits_summary(config)),
+        (
+            "federation",
+            if config.federation.indices.is_empty() {
+                "none".to_string()
+            } else {
+                config
+                    .federation
+                    .indices
+                    .iter()
+                    .map(|index| index.index.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            },
+        ),
+        (
+            "security",
+            if config.security.enabled {
+                format!("enabled ({} configured users)", config.security.users.len())
+            } else {
+                "disabled".to_string()
+            },
+        ),
+        (
+            "rate limit",
+            if config.rate_limit.enabled {
+                format!(
+                    "{} requests/s, burst {}",
+                    config.rate_limit.requests_per_second, config.rate_limit.burst
+                )
+            } else {
+                "disabled".to_string()
+            },
+        ),
+        (
+            "tls",
+            match &config.server.tls {
+                Some(tls) => match tls.redirect_port {
+                    Some(port) => format!("enabled (http redirect on port {})", port),
+                    None => "enabled".to_string(),
+                },
+                None => "disabled".to_string(),
+            },
+        ),
+        (
+            "usage",
+            format!(
+                "{}s buckets, {} kept",
+                config.usage.bucket_secs, config.usage.history_buckets
+            ),
+        ),
+        (
+            "proxy",
+            match (&config.proxy.upstream_url, config.proxy.mode) {
+                (Some(url), ProxyMode::Forward | ProxyMode::Record) => {
+                    format!("{} to {}", config.proxy.mode.as_str(), url)
+                }
+                _ => config.proxy.mode.as_str().to_string(),
+            },
+        ),
+        ("es compatibility", config.es_version.clone()),
+    ]
+}
+
+fn search_summary(config: &Config) -> String {
+    let search = &config.search;
+    let mut summary = vec![format!(
+        "max_concurrent_index_searches={}",
+        search.max_concurrent_index_searches
+    )];
+    if let Some(timeout) = search.default_timeout_ms {
+        summary.push(format!("default_timeout_ms={}", timeout));
+    }
+    summary.push(if search.parallel_scoring {
+        format!(
+            "parallel scoring from {} documents",
+            search.parallel_scoring_min_docs
+        )
+    } else {
+        "parallel scoring disabled".to_string()
+    });
+    summary.join(", ")
+}
+
+fn limits_summary(config: &Config) -> String {
+    let storage = &config.storage;
+    let mut limits = vec![
+        format!("max_body_bytes={}", config.server.max_body_bytes),
+        format!("max_bulk_actions={}", config.server.max_bulk_actions),
+        format!("max_document_bytes={}", config.server.max_document_bytes),
+    ];
+    if let Some(max) = storage.max_indices {
+        limits.push(format!("max_indices={}", max));
+    }
+    if let Some(max) = storage.auto_rollover.max_docs {
+        limits.push(format!("rollover max_docs={}", max));
+    }
+    if let Some(max) = storage.auto_rollover.max_size_bytes {
+        limits.push(format!("rollover max_size_bytes={}", max));
+    }
+    limits.join(", ")
+}
+
+/// Log the feature report
+pub fn log_feature_report(config: &Config, groups: &[RouteGroup]) {
+    info!("Feature report:");
+    for (family, summary) in feature_report(config, groups) {
+        info!("  {:<18} {}", family, summary);
+    }
+}
+
+/// Log the result of a self-test
+pub fn log_self_test_report(report: &SelfTestReport) {
+    for (step, elapsed) in &report.steps {
+        debug!("Self-test step '{}' completed in {:?}", step, elapsed);
+    }
+    info!(
+        "Startup self-test passed ({} steps in {:?})",
+        report.steps.len(),
+        report.elapsed
+    );
+}
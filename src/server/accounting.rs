@@ -0,0 +1,84 @@
+//! Request usage accounting
+//!
+//! Records every request in the usage tracker (see `crate::usage`). Requests
+//! to index APIs are attributed to the index expression in their path.
+//! Indexed bytes are recorded by the write handlers, per concrete index and
+//! as the serialized size of each document written.
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::server::AppState;
+use crate::usage::{caller_key, Usage};
+
+/// Record the usage of a request
+pub async fn record_usage(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let caller = request_caller(request.headers());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let index = path_index(&route, request.uri().path());
+    let is_search = route.ends_with("/_search") || route.ends_with("/_msearch");
+
+    let start = std::time::Instant::now();
+    let response = next.run(request).await;
+    let elapsed = start.elapsed();
+
+    state.usage.record(Usage {
+        caller,
+        index,
+        requests: 1,
+        searches: u64::from(is_search),
+        search_time: if is_search {
+            elapsed
+        } else {
+            Default::default()
+        },
+        bytes_returned: response.body().size_hint().exact().unwrap_or(0),
+        ..Default::default()
+    });
+    response
+}
+
+/// Record the bytes of a document written to an index
+///
+/// Deletes are recorded with no bytes and are not counted as indexed documents.
+pub(crate) fn record_indexed(state: &AppState, headers: &HeaderMap, index: &str, bytes: usize) {
+    if bytes > 0 {
+        state.metrics.record_indexed(index);
+    }
+    state.usage.record(Usage {
+        caller: request_caller(headers),
+        index: Some(index.to_string()),
+        bytes_indexed: bytes as u64,
+        ..Default::default()
+    });
+}
+
+/// Index expression in the path of a request to an index API
+///
+/// When the router is nested, the matched route includes the nesting prefix
+/// while the URI does not, so segments are aligned from the end.
+pub(crate) fn path_index(route: &str, path: &str) -> Option<String> {
+    let route: Vec<&str> = route.split('/').collect();
+    let path: Vec<&str> = path.split('/').collect();
+    let position = route.iter().position(|&segment| segment == ":index")?;
+    let offset = route.len().checked_sub(path.len())?;
+    path.get(position.checked_sub(offset)?)
+        .map(|segment| segment.to_string())
+}
+
+fn request_caller(headers: &HeaderMap) -> String {
+    caller_key(
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok()),
+    )
+}
@@ -0,0 +1,94 @@
+//! Audit of write requests
+//!
+//! Records every request that may change data or settings in the audit log
+//! (see `crate::audit`) once it is answered. The layer runs inside
+//! authentication, so events name the authenticated user; requests rejected
+//! for their credentials are not audited.
+
+use axum::{
+    extract::{ConnectInfo, MatchedPath, Request, State},
+    http::header,
+    middleware::Next,
+    response::Response,
+};
+use std::net::SocketAddr;
+use tracing::warn;
+
+use crate::audit::{audit_action, AuditEvent};
+use crate::auth::User;
+use crate::server::accounting::path_index;
+use crate::server::drain::is_write;
+use crate::server::{AppState, RequestId};
+use crate::usage::caller_key;
+
+/// Record write requests in the audit log
+pub async fn record_audit_event(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if !state.audit.is_enabled() {
+        return next.run(request).await;
+    }
+    // Requests no route matched are proxied, not applied here
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    if !is_write(request.method(), Some(&route)) {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    let authenticated = request
+        .extensions()
+        .get::<User>()
+        .filter(|_| state.auth.is_enabled());
+    let user = match authenticated {
+        Some(user) => user.username.clone(),
+        None => caller_key(
+            headers
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok()),
+        ),
+    };
+    let forwarded_for = headers
+        .get("x-forwarded-for")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let client_ip = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip().to_string());
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(|id| id.as_str().to_string());
+    let method = request.method().to_string();
+    let path = request.uri().path().to_string();
+
+    let response = next.run(request).await;
+
+    let event = AuditEvent {
+        timestamp: chrono::Utc::now(),
+        action: audit_action(&method, &route).to_string(),
+        user,
+        client_ip,
+        forwarded_for,
+        index: path_index(&route, &path),
+        method,
+        path,
+        status: response.status().as_u16(),
+        request_id,
+    };
+    if let Err(e) = state.audit.record(&event).await {
+        warn!(
+            "Failed to record audit event for {} {}: {}",
+            event.method, event.path, e
+        );
+    }
+    response
+}
@@ -0,0 +1,58 @@
+//! Request authentication
+//!
+//! When security is enabled every request must carry valid credentials:
+//! `Authorization: Basic` for native users or `Authorization: ApiKey` for API
+//! keys (see `crate::auth`). Authenticated users are added to the request
+//! extensions, as is an anonymous superuser when security is disabled, so
+//! handlers take the user from there. Requests the rate limiter (see
+//! `crate::server::rate_limit`) already authenticated are not authenticated
+//! again, whether their credentials were accepted or not.
+
+use axum::{
+    extract::{Request, State},
+    http::{header, HeaderMap},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use std::sync::Arc;
+
+use crate::auth::User;
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+
+/// Credentials of a request that the rate limiter rejected
+#[derive(Debug, Clone)]
+pub(crate) struct FailedAuthentication(pub Arc<GbsError>);
+
+/// Reject requests without valid credentials when security is enabled
+pub async fn require_authentication(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if request.extensions().get::<User>().is_some() {
+        return next.run(request).await;
+    }
+    if let Some(FailedAuthentication(error)) = request.extensions_mut().remove() {
+        return Arc::try_unwrap(error)
+            .unwrap_or_else(|error| GbsError::Unauthorized(error.to_string()))
+            .into_response();
+    }
+
+    match authenticate(&state, request.headers()).await {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+            next.run(request).await
+        }
+        Err(error) => error.into_response(),
+    }
+}
+
+/// Authenticate the credentials of the `Authorization` header of a request
+pub(crate) async fn authenticate(state: &AppState, headers: &HeaderMap) -> Result<User> {
+    let authorization = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    state.auth.authenticate(authorization).await
+}
@@ -0,0 +1,338 @@
+//! Emulation of the request and response shapes of the configured
+//! Elasticsearch version
+//!
+//! Clients written for one major version of Elasticsearch expect its shapes.
+//! With `emulate_es_version`, the major version of `es_version` selects them;
+//! otherwise the 7.x shapes are used whatever the version:
+//!
+//! | | 6.x | 7.x | 8.x |
+//! |---|---|---|---|
+//! | `hits.total` | number | `{value, relation}` | `{value, relation}` |
+//! | mappings in responses | under the `_doc` type | typeless | typeless |
+//! | typed mappings in requests | accepted | accepted | no |
+//! | `_type` of documents | `_doc` | `_doc` | left out |
+//! | `/{index}/{type}/{id}` endpoints | yes | yes | no |
+//!
+//! The `_type` of documents is left out for an `es_version` of 8.x or later
+//! even without emulation, as no client of those versions knows it.
+//!
+//! As in Elasticsearch, `rest_total_hits_as_int=true` asks for a number in
+//! 7.x and 8.x, and `include_type_name` picks typed or typeless mappings in
+//! 6.x and 7.x. Handlers work with the 7.x shapes; the middleware below
+//! rewrites the bodies of the affected routes.
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequest, MatchedPath, Query, Request, State},
+    http::{header, Method},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::error::GbsError;
+use crate::server::handlers::cluster::es_major_minor;
+use crate::server::AppState;
+
+/// Name of the mapping type of typed responses
+const MAPPING_TYPE: &str = "_doc";
+
+/// Root-level parameters of a typeless mapping, told apart from type names
+const MAPPING_PARAMETERS: &[&str] = &[
+    "properties",
+    "dynamic",
+    "dynamic_templates",
+    "date_detection",
+    "numeric_detection",
+    "dynamic_date_formats",
+    "_source",
+    "_meta",
+    "_routing",
+    "_field_names",
+    "_all",
+    "enabled",
+    "runtime",
+];
+
+/// Shapes of the emulated Elasticsearch major version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compatibility {
+    major: u32,
+}
+
+impl Compatibility {
+    /// Shapes of an Elasticsearch version such as `6.8.23`
+    pub fn for_version(es_version: &str) -> Self {
+        Self {
+            major: es_major_minor(es_version).0,
+        }
+    }
+
+    /// Shapes served without version emulation, those of 7.x
+    pub fn native() -> Self {
+        Self { major: 7 }
+    }
+
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    /// Whether mapping types are known: typed endpoints and `_type` fields
+    pub fn has_types(&self) -> bool {
+        self.major < 8
+    }
+
+    /// Whether `hits.total` of a request with these parameters is a number
+    pub fn total_hits_as_int(&self, params: &HashMap<String, String>) -> bool {
+        flag(params, "rest_total_hits_as_int").unwrap_or(self.major < 7)
+    }
+
+    /// Whether mappings in responses to a request with these parameters are
+    /// under their type
+    pub fn include_type_name(&self, params: &HashMap<String, String>) -> bool {
+        self.has_types() && flag(params, "include_type_name").unwrap_or(self.major < 7)
+    }
+}
+
+/// Boolean query parameter; present without a value means true
+fn flag(params: &HashMap<String, String>, name: &str) -> Option<bool> {
+    params.get(name).map(|value| value != "false")
+}
+
+/// Adjust requests and responses to the emulated version
+///
+/// Runs inside the body limit, so rewritten request bodies are still capped.
+/// The version is read for every request, as other per-request settings.
+pub async fn emulate_es_version(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let compat = state.compat();
+    let method = request.method().clone();
+    let params = Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .map(|query| query.0)
+        .unwrap_or_default();
+
+    let request = match mapping_body(&method, &route) {
+        Some(body) if compat.has_types() => match rewrite_request(request, body).await {
+            Ok(request) => request,
+            Err(response) => return response,
+        },
+        _ => request,
+    };
+    let response = next.run(request).await;
+
+    let rewrite = ResponseRewrite {
+        total_hits_as_int: returns_hits(&route) && compat.total_hits_as_int(&params),
+        typed_mappings: method == Method::GET
+            && returns_mappings(&route)
+            && compat.include_type_name(&params),
+        strip_types: !state.has_document_types() && returns_documents(&route),
+    };
+    if !rewrite.is_needed() {
+        return response;
+    }
+    rewrite_response(response, rewrite).await
+}
+
+/// Where a request body carries mappings
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MappingBody {
+    /// Under `mappings` (create index, legacy templates)
+    Nested,
+    /// At the root or under `mappings` (put mapping)
+    Root,
+}
+
+fn mapping_body(method: &Method, route: &str) -> Option<MappingBody> {
+    if method != Method::PUT && method != Method::POST {
+        return None;
+    }
+    if route.ends_with("/_mapping") || route.ends_with("/_mapping/:type") {
+        Some(MappingBody::Root)
+    } else if (method == Method::PUT && route.ends_with("/:index"))
+        || route.ends_with("/_template/:name")
+    {
+        Some(MappingBody::Nested)
+    } else {
+        None
+    }
+}
+
+fn returns_hits(route: &str) -> bool {
+    route.ends_with("/_search")
+        || route.ends_with("/_search/template")
+        || route.ends_with("/_msearch")
+}
+
+fn returns_mappings(route: &str) -> bool {
+    route.ends_with("/:index")
+        || route.ends_with("/_mapping")
+        || route.ends_with("/_mapping/:type")
+        || route.ends_with("/_template")
+        || route.ends_with("/_template/:name")
+}
+
+fn returns_documents(route: &str) -> bool {
+    [
+        "/_doc",
+        "/_create",
+        "/_search",
+        "/_msearch",
+        "/_bulk",
+        "/_explain",
+    ]
+    .iter()
+    .any(|endpoint| route.contains(endpoint))
+}
+
+/// Replace typed mappings of a request body with their typeless form
+///
+/// Bodies that are not JSON are passed on as they are, for the handler to
+/// reject.
+async fn rewrite_request(request: Request, shape: MappingBody) -> Result<Request, Response> {
+    let (mut parts, body) = request.into_parts();
+    // Bodies over the limit are rejected with a 413 error
+    let bytes = Bytes::from_request(Request::new(body), &())
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Ok(Request::from_parts(parts, Body::from(bytes)));
+    };
+    match json.get_mut("mappings") {
+        Some(mappings) => untype_mappings(mappings),
+        None if shape == MappingBody::Root => untype_mappings(&mut json),
+        None => {}
+    }
+    let bytes = serde_json::to_vec(&json).map_err(|e| GbsError::from(e).into_response())?;
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Request::from_parts(parts, Body::from(bytes)))
+}
+
+/// Unwrap mappings given under a single type, such as `{"_doc": {...}}`
+fn untype_mappings(mappings: &mut Value) {
+    let Some(object) = mappings.as_object_mut() else {
+        return;
+    };
+    if object.len() != 1 {
+        return;
+    }
+    let typed = object.iter().next().is_some_and(|(name, value)| {
+        !MAPPING_PARAMETERS.contains(&name.as_str()) && value.is_object()
+    });
+    if typed {
+        let (_, value) = object.iter_mut().next().expect("one entry");
+        *mappings = value.take();
+    }
+}
+
+/// Changes made to a response body
+#[derive(Debug, Clone, Copy)]
+struct ResponseRewrite {
+    total_hits_as_int: bool,
+    typed_mappings: bool,
+    strip_types: bool,
+}
+
+impl ResponseRewrite {
+    fn is_needed(&self) -> bool {
+        self.total_hits_as_int || self.typed_mappings || self.strip_types
+    }
+
+    fn apply(&self, body: &mut Value) {
+        if self.total_hits_as_int {
+            total_hits_to_int(body);
+            if let Some(responses) = body.get_mut("responses").and_then(Value::as_array_mut) {
+                responses.iter_mut().for_each(total_hits_to_int);
+            }
+        }
+        if self.typed_mappings {
+            if let Some(entries) = body.as_object_mut() {
+                for entry in entries.values_mut() {
+                    if let Some(mappings) = entry.get_mut("mappings") {
+                        type_mappings(mappings);
+                    }
+                }
+            }
+        }
+        if self.strip_types {
+            strip_types(body);
+        }
+    }
+}
+
+/// Rewrite a JSON response body; other bodies are left alone
+async fn rewrite_response(response: Response, rewrite: ResponseRewrite) -> Response {
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => return GbsError::Io(std::io::Error::other(e)).into_response(),
+    };
+    let Ok(mut json) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    rewrite.apply(&mut json);
+    match serde_json::to_vec(&json) {
+        Ok(bytes) => {
+            parts.headers.remove(header::CONTENT_LENGTH);
+            Response::from_parts(parts, Body::from(bytes))
+        }
+        Err(e) => GbsError::from(e).into_response(),
+    }
+}
+
+/// Replace `hits.total` by its value, as in 6.x
+fn total_hits_to_int(body: &mut Value) {
+    if let Some(total) = body.get_mut("hits").and_then(|hits| hits.get_mut("total")) {
+        if let Some(value) = total.get("value").cloned() {
+            *total = value;
+        }
+    }
+}
+
+/// Put non-empty mappings under the mapping type
+fn type_mappings(mappings: &mut Value) {
+    if mappings
+        .as_object()
+        .is_some_and(|object| !object.is_empty())
+    {
+        *mappings = serde_json::json!({ MAPPING_TYPE: mappings.take() });
+    }
+}
+
+/// Leave out the `_type` of documents, hits and bulk items, as in 8.x
+fn strip_types(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if object.contains_key("_index") {
+                object.remove("_type");
+            }
+            for (key, child) in object.iter_mut() {
+                // Documents themselves are the user's
+                if key != "_source" && key != "fields" {
+                    strip_types(child);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_types),
+        _ => {}
+    }
+}
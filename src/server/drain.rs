@@ -0,0 +1,107 @@
+//! Request draining at shutdown
+//!
+//! On Ctrl-C or SIGTERM the server stops accepting connections and waits for
+//! the requests in flight to finish, for at most `server.shutdown_timeout_secs`,
+//! before the storage is flushed a last time. While it drains, writes that
+//! still arrive on open connections are refused with 503 so that clients
+//! retry them elsewhere instead of racing the final flush. Reads are served
+//! until the connection closes. Refreshes, flushes and cache clears count as
+//! writes.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::Method,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Notify;
+
+use crate::error::GbsError;
+use crate::server::AppState;
+
+/// Route templates that accept POST but do not change any data
+const READ_ROUTES: &[&str] = &[
+    "/_search",
+    "/:index/_search",
+    "/:index/:type/_search",
+    "/_msearch",
+    "/:index/_msearch",
+    "/_search/template",
+    "/:index/_search/template",
+    "/_render/template",
+    "/_render/template/:id",
+    "/_search_shards",
+    "/:index/_search_shards",
+    "/:index/_explain/:id",
+    "/_field_caps",
+    "/:index/_field_caps",
+    "/_validate/query",
+    "/:index/_validate/query",
+    "/_ingest/pipeline/_simulate",
+    "/_ingest/pipeline/:id/_simulate",
+    "/_tasks/_cancel",
+    "/_tasks/:task_id/_cancel",
+];
+
+/// Shutdown state shared by the server and its request handlers
+#[derive(Debug, Default)]
+pub struct Shutdown {
+    draining: AtomicBool,
+    started: Notify,
+}
+
+impl Shutdown {
+    /// Start draining: writes are refused from now on
+    pub fn begin(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        self.started.notify_waiters();
+    }
+
+    /// Check whether the server is draining requests
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Wait until draining starts
+    pub async fn started(&self) {
+        let notified = self.started.notified();
+        tokio::pin!(notified);
+        // Register before checking the flag so a concurrent `begin` is not missed
+        notified.as_mut().enable();
+        if self.is_draining() {
+            return;
+        }
+        notified.await;
+    }
+}
+
+/// Refuse writes while the server drains requests
+pub async fn refuse_writes_while_draining(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if state.shutdown.is_draining() {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str());
+        if is_write(request.method(), route) {
+            return GbsError::ShuttingDown.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Check whether a request may change data
+///
+/// Requests no route matched go to the proxy and count as writes unless
+/// their method is safe.
+pub(crate) fn is_write(method: &Method, route: Option<&str>) -> bool {
+    match *method {
+        Method::GET | Method::HEAD | Method::OPTIONS => false,
+        Method::POST => !route.is_some_and(|route| READ_ROUTES.contains(&route)),
+        _ => true,
+    }
+}
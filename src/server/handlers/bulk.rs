@@ -1,51 +1,90 @@
-//! Bulk operations handler
+//! Bulk operations, transaction and reindex handlers
 
 use axum::{
-    body::Body,
+    body::Bytes,
     extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
     response::Json,
 };
 use std::collections::{HashMap, HashSet};
 use tracing::{debug, info, warn};
 
 use crate::bulk_ops::{
-    parse_bulk_ndjson, BulkAction, BulkError, BulkItemResponse, BulkOperationResult, BulkResponse,
-    ShardsInfo,
+    parse_bulk_ndjson, parse_transaction, BulkAction, BulkError, BulkItemResponse,
+    BulkOperationResult, BulkResponse, ShardsInfo, TransactionResponse,
 };
-use crate::error::Result;
+use crate::error::{GbsError, Result};
+use crate::server::accounting::record_indexed;
+use crate::server::handlers::document::refresh_policy;
+use crate::server::handlers::tasks::run_as_task;
+use crate::server::limits::document_size;
 use crate::server::AppState;
+use crate::storage::{DeleteByQueryRequest, RefreshPolicy, ReindexRequest};
+use crate::tasks::{BULK_ACTION, DELETE_BY_QUERY_ACTION, REINDEX_ACTION};
 
+/// Execute a bulk request as a (not cancellable) task
+///
+/// With `wait_for_completion=false` the task ID is returned right away.
 pub async fn bulk_operations(
     State(state): State<AppState>,
-    Path(index): Path<Option<String>>,
+    index: Option<Path<String>>,
     Query(params): Query<HashMap<String, String>>,
-    body: Body,
-) -> Result<Json<BulkResponse>> {
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>> {
+    // `/_bulk` has no index in its path
+    let index = index.map(|Path(index)| index);
     info!("Bulk operations for index: {:?}", index);
 
-    // Convert body to String
-    let body_bytes = axum::body::to_bytes(body, usize::MAX).await.map_err(|e| {
-        crate::error::GbsError::InvalidRequest(format!("Failed to read body: {}", e))
-    })?;
-    let body_str = String::from_utf8(body_bytes.to_vec()).map_err(|e| {
+    // Convert body to String (its size is capped by the request body limit)
+    let body_str = String::from_utf8(body.to_vec()).map_err(|e| {
         crate::error::GbsError::InvalidRequest(format!("Invalid UTF-8 in body: {}", e))
     })?;
 
     debug!("Bulk request body length: {} bytes", body_str.len());
 
-    // Check refresh parameter
-    let refresh = params.get("refresh").map(|s| s.as_str()).unwrap_or("false");
+    let refresh = refresh_policy(&params)?;
 
     let start_time = std::time::Instant::now();
     let actions = parse_bulk_ndjson(&body_str, index.as_deref())?;
+    let limits = state.limits();
+    limits.check_bulk_actions(actions.len())?;
+
+    let description = format!(
+        "requests[{}], indices[{}]",
+        actions.len(),
+        index.unwrap_or_default()
+    );
+    let bulk_state = state.clone();
+    let pipeline = params.get("pipeline").cloned();
+    run_as_task(
+        &state,
+        &params,
+        BULK_ACTION,
+        description,
+        false,
+        move |_| execute_bulk_request(bulk_state, headers, actions, pipeline, refresh, start_time),
+    )
+    .await
+}
 
+/// Execute parsed bulk actions and build the bulk response
+async fn execute_bulk_request(
+    state: AppState,
+    headers: HeaderMap,
+    mut actions: Vec<BulkAction>,
+    pipeline: Option<String>,
+    refresh: RefreshPolicy,
+    start_time: std::time::Instant,
+) -> Result<BulkResponse> {
     let mut items = Vec::new();
     let mut has_errors = false;
     let mut affected_indices = HashSet::new();
 
-    for action in actions {
-        // Extract action type and identifiers before executing
-        let (action_type, index_name, id) = match &action {
+    // Extract action types and identifiers before executing
+    let descriptors: Vec<_> = actions
+        .iter()
+        .map(|action| match action {
             BulkAction::Index { index, id, .. } => {
                 affected_indices.insert(index.clone());
                 ("index", index.clone(), id.clone())
@@ -62,23 +101,76 @@ pub async fn bulk_operations(
                 affected_indices.insert(index.clone());
                 ("delete", index.clone(), Some(id.clone()))
             }
-        };
+        })
+        .collect();
 
-        let result = match state.storage.execute_bulk_action(action).await {
-            Ok((idx_name, doc_id, status, result)) => BulkOperationResult {
-                index: idx_name,
-                r#type: "_doc".to_string(),
-                id: doc_id,
-                version: Some(1),
-                result,
-                shards: Some(ShardsInfo {
-                    total: 1,
-                    successful: 1,
-                    failed: 0,
-                }),
-                status,
-                error: None,
-            },
+    // Documents that fail their ingest pipeline and oversized documents fail
+    // their own item; the other actions still run
+    let limits = state.limits();
+    let rejections: Vec<_> = actions
+        .iter_mut()
+        .map(|action| {
+            run_bulk_pipeline(&state, pipeline.as_deref(), action)
+                .and_then(|_| limits.check_bulk_action(action))
+                .err()
+        })
+        .collect();
+    let accepted: Vec<_> = actions
+        .into_iter()
+        .zip(&rejections)
+        .filter(|(_, rejection)| rejection.is_none())
+        .map(|(action, _)| action)
+        .collect();
+    // Document sizes for usage accounting, taken before the actions are consumed
+    let document_sizes: Vec<usize> = accepted
+        .iter()
+        .map(|action| match action {
+            BulkAction::Index { document, .. }
+            | BulkAction::Create { document, .. }
+            | BulkAction::Update { document, .. } => document_size(document),
+            BulkAction::Delete { .. } => 0,
+        })
+        .collect();
+    let mut document_sizes = document_sizes.into_iter();
+    let mut results = state.storage.execute_bulk(accepted).await.into_iter();
+
+    for ((action_type, index_name, id), rejection) in descriptors.into_iter().zip(rejections) {
+        let outcome = match rejection {
+            Some(e) => Err(e),
+            None => {
+                let outcome = results
+                    .next()
+                    .expect("execute_bulk returns one result per action");
+                let bytes = document_sizes.next().unwrap_or(0);
+                // Writes are accounted to the concrete index they landed in
+                if let Ok((idx_name, ..)) = &outcome {
+                    record_indexed(&state, &headers, idx_name, bytes);
+                }
+                outcome
+            }
+        };
+        let result = match outcome {
+            Ok((idx_name, doc_id, status, result, seq_no, version)) => {
+                // Routed writes refresh the concrete index
+                affected_indices.insert(idx_name.clone());
+                BulkOperationResult {
+                    index: idx_name,
+                    r#type: "_doc".to_string(),
+                    id: doc_id,
+                    version,
+                    result,
+                    shards: Some(ShardsInfo {
+                        total: 1,
+                        successful: 1,
+                        failed: 0,
+                    }),
+                    seq_no,
+                    primary_term: seq_no.map(|_| 1),
+                    forced_refresh: (refresh == RefreshPolicy::Immediate).then_some(true),
+                    status,
+                    error: None,
+                }
+            }
             Err(e) => {
                 has_errors = true;
                 let doc_id = id.unwrap_or_else(|| "unknown".to_string());
@@ -94,10 +186,13 @@ pub async fn bulk_operations(
                         successful: 0,
                         failed: 1,
                     }),
-                    status: 400,
+                    seq_no: None,
+                    primary_term: None,
+                    forced_refresh: None,
+                    status: e.status_code().as_u16(),
                     error: Some(BulkError {
-                        r#type: "invalid_request_exception".to_string(),
-                        reason: e.to_string(),
+                        r#type: e.error_type().to_string(),
+                        reason: e.reason(),
                     }),
                 }
             }
@@ -117,7 +212,16 @@ pub async fn bulk_operations(
     let took = start_time.elapsed().as_millis() as u32;
 
     // Handle refresh parameter
-    if refresh == "true" || refresh == "wait_for" {
+    if refresh == RefreshPolicy::WaitFor {
+        for index_name in &affected_indices {
+            if let Err(e) = state.storage.wait_for_refresh(index_name).await {
+                warn!(
+                    "Failed to wait for index '{}' to refresh after bulk operations: {}",
+                    index_name, e
+                );
+            }
+        }
+    } else if refresh == RefreshPolicy::Immediate {
         debug!(
             "Refreshing {} indices after bulk operations",
             affected_indices.len()
@@ -138,9 +242,170 @@ pub async fn bulk_operations(
         debug!("Refresh completed for bulk operations");
     }
 
-    Ok(Json(BulkResponse {
+    Ok(BulkResponse {
         took,
         errors: has_errors,
         items,
-    }))
+    })
+}
+
+/// Run the document of an index or create action through an ingest pipeline
+fn run_bulk_pipeline(
+    state: &AppState,
+    pipeline: Option<&str>,
+    action: &mut BulkAction,
+) -> Result<()> {
+    if let (
+        Some(pipeline),
+        BulkAction::Index { document, .. } | BulkAction::Create { document, .. },
+    ) = (pipeline, action)
+    {
+        *document = state.storage.run_pipeline(pipeline, document.take())?;
+    }
+    Ok(())
+}
+
+/// Apply a transaction: index, create, update and delete operations on one
+/// index, all of them or none
+pub async fn execute_transaction(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+    headers: HeaderMap,
+    Json(body): Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<TransactionResponse>)> {
+    info!("Transaction on index: {}", index);
+    let start_time = std::time::Instant::now();
+    let actions = parse_transaction(&body, &index)?;
+    let limits = state.limits();
+    limits.check_bulk_actions(actions.len())?;
+
+    let aborted = |operation: Option<usize>, error: GbsError| {
+        warn!("Transaction on index '{}' aborted: {}", index, error);
+        (
+            error.status_code(),
+            Json(TransactionResponse {
+                took: start_time.elapsed().as_millis() as u32,
+                committed: false,
+                items: Vec::new(),
+                failed_operation: operation,
+                error: Some(BulkError {
+                    r#type: error.error_type().to_string(),
+                    reason: error.reason(),
+                }),
+            }),
+        )
+    };
+
+    // An oversized document aborts the whole transaction
+    let rejection = actions.iter().enumerate().find_map(|(position, action)| {
+        let error = limits.check_bulk_action(action).err()?;
+        Some((position, error))
+    });
+    if let Some((position, error)) = rejection {
+        return Ok(aborted(Some(position), error));
+    }
+
+    let action_types: Vec<_> = actions
+        .iter()
+        .map(|action| match action {
+            BulkAction::Index { document, .. } => ("index", document_size(document)),
+            BulkAction::Create { document, .. } => ("create", document_size(document)),
+            BulkAction::Update { document, .. } => ("update", document_size(document)),
+            BulkAction::Delete { .. } => ("delete", 0),
+        })
+        .collect();
+
+    let results = match state.storage.execute_transaction(&index, actions).await {
+        Ok(results) => results,
+        Err(abort) => return Ok(aborted(abort.operation, abort.error)),
+    };
+
+    let items = results
+        .into_iter()
+        .zip(action_types)
+        .map(
+            |((idx_name, doc_id, status, result, seq_no, version), (action_type, bytes))| {
+                record_indexed(&state, &headers, &idx_name, bytes);
+                let result = BulkOperationResult {
+                    index: idx_name,
+                    r#type: "_doc".to_string(),
+                    id: doc_id,
+                    version,
+                    result,
+                    shards: Some(ShardsInfo {
+                        total: 1,
+                        successful: 1,
+                        failed: 0,
+                    }),
+                    seq_no,
+                    primary_term: seq_no.map(|_| 1),
+                    forced_refresh: None,
+                    status,
+                    error: None,
+                };
+                match action_type {
+                    "index" => BulkItemResponse::Index { index: result },
+                    "create" => BulkItemResponse::Create { create: result },
+                    "update" => BulkItemResponse::Update { update: result },
+                    "delete" => BulkItemResponse::Delete { delete: result },
+                    _ => unreachable!(),
+                }
+            },
+        )
+        .collect();
+
+    Ok((
+        StatusCode::OK,
+        Json(TransactionResponse {
+            took: start_time.elapsed().as_millis() as u32,
+            committed: true,
+            items,
+            failed_operation: None,
+            error: None,
+        }),
+    ))
+}
+
+/// Copy documents from source indices into a destination index, as a
+/// cancellable task
+pub async fn reindex(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let request = ReindexRequest::parse(&body)?;
+    info!("Reindexing '{}' into '{}'", request.source, request.dest);
+    let description = format!("reindex from [{}] to [{}]", request.source, request.dest);
+    let storage = state.storage.clone();
+    run_as_task(
+        &state,
+        &params,
+        REINDEX_ACTION,
+        description,
+        true,
+        move |task| async move { storage.reindex_with_task(&request, &task).await },
+    )
+    .await
+}
+
+/// Delete the documents matching a query, as a cancellable task
+pub async fn delete_by_query(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let request = DeleteByQueryRequest::parse(&index, &body, &params)?;
+    info!("Deleting documents matching a query from '{}'", index);
+    let description = format!("delete-by-query [{}]", index);
+    let storage = state.storage.clone();
+    run_as_task(
+        &state,
+        &params,
+        DELETE_BY_QUERY_ACTION,
+        description,
+        true,
+        move |task| async move { storage.delete_by_query(&request, &task).await },
+    )
+    .await
 }
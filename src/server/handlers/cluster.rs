@@ -1,68 +1,646 @@
 //! Cluster management handlers
 
 use axum::{
-    extract::{State, Query},
-    response::Json,
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Json, Response},
 };
+use chrono::Utc;
+use regex::Regex;
 use std::collections::HashMap;
 use tracing::info;
 
-use crate::error::Result;
-use crate::server::AppState;
+use crate::error::{GbsError, Result};
+use crate::server::{AppState, ProcessMetrics};
+use crate::storage::{
+    wildcard_regex, ClusterHealth, FilterCacheStats, IndexState, IndexStats, OperationStats,
+    NUMBER_OF_NODES,
+};
 
 #[axum::debug_handler]
-pub async fn cluster_health(State(_state): State<AppState>) -> Json<serde_json::Value> {
+pub async fn cluster_health(State(state): State<AppState>) -> Json<serde_json::Value> {
+    let health = cluster_health_of(&state).await;
     Json(serde_json::json!({
-        "status": "green",
-        "number_of_nodes": 1,
-        "number_of_data_nodes": 1,
-        "active_primary_shards": 0,
-        "active_shards": 0,
+        "status": health.status.as_str(),
+        "number_of_nodes": NUMBER_OF_NODES,
+        "number_of_data_nodes": NUMBER_OF_NODES,
+        "active_primary_shards": health.active_primary_shards,
+        "active_shards": health.active_shards,
         "relocating_shards": 0,
         "initializing_shards": 0,
-        "unassigned_shards": 0,
+        "unassigned_shards": health.unassigned_shards,
         "delayed_unassigned_shards": 0,
         "number_of_pending_tasks": 0,
         "number_of_in_flight_fetch": 0,
         "task_max_waiting_in_queue_millis": 0,
-        "active_shards_percent_as_number": 100.0
+        "active_shards_percent_as_number": health.active_shards_percent()
     }))
 }
 
+/// Health of the cluster: yellow while indices have replicas, which the
+/// single node cannot allocate
+pub(crate) async fn cluster_health_of(state: &AppState) -> ClusterHealth {
+    ClusterHealth::of(&state.storage.get_index_stats().await)
+}
+
 pub async fn cluster_stats(State(state): State<AppState>) -> Result<Json<serde_json::Value>> {
     info!("Getting cluster statistics");
     let stats = state.storage.get_cluster_stats(&state.es_version).await;
     Ok(Json(stats))
 }
 
+/// Get the cluster settings (`GET /_cluster/settings`)
+///
+/// With `flat_settings=true` the keys are dotted instead of nested.
+pub async fn get_cluster_settings(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Json<serde_json::Value> {
+    let settings = state.storage.cluster_settings();
+    Json(settings.to_json(flat_settings(&params)))
+}
+
+/// Update the persistent and transient cluster settings (`PUT /_cluster/settings`)
+pub async fn put_cluster_settings(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    Json(body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Updating cluster settings");
+    let response = state
+        .storage
+        .update_cluster_settings(&body, flat_settings(&params))
+        .await?;
+    Ok(Json(response))
+}
+
+fn flat_settings(params: &HashMap<String, String>) -> bool {
+    params
+        .get("flat_settings")
+        .is_some_and(|value| value == "true")
+}
+
+/// A column of a cat API table
+struct CatColumn {
+    name: &'static str,
+    /// Short names accepted by `h=`
+    aliases: &'static [&'static str],
+    /// Shown when `h=` is not given
+    default: bool,
+}
+
+const fn column(name: &'static str, aliases: &'static [&'static str], default: bool) -> CatColumn {
+    CatColumn {
+        name,
+        aliases,
+        default,
+    }
+}
+
+/// A cell of a cat API table
+enum CatValue {
+    Text(String),
+    /// A size, shown according to the `bytes=` parameter
+    Bytes(u64),
+}
+
+impl From<&str> for CatValue {
+    fn from(value: &str) -> Self {
+        CatValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for CatValue {
+    fn from(value: String) -> Self {
+        CatValue::Text(value)
+    }
+}
+
+impl From<u64> for CatValue {
+    fn from(value: u64) -> Self {
+        CatValue::Text(value.to_string())
+    }
+}
+
+impl From<usize> for CatValue {
+    fn from(value: usize) -> Self {
+        CatValue::Text(value.to_string())
+    }
+}
+
+/// Byte units accepted by `bytes=`, with their size
+const BYTE_UNITS: [(&str, u64); 6] = [
+    ("b", 1),
+    ("kb", 1 << 10),
+    ("mb", 1 << 20),
+    ("gb", 1 << 30),
+    ("tb", 1 << 40),
+    ("pb", 1 << 50),
+];
+
+/// Format a size in the given unit (whole units), or human-readable
+fn format_bytes(bytes: u64, unit: Option<u64>) -> String {
+    if let Some(unit) = unit {
+        return (bytes / unit).to_string();
+    }
+    let (name, size) = BYTE_UNITS
+        .iter()
+        .rev()
+        .find(|(_, size)| bytes >= *size)
+        .copied()
+        .unwrap_or(BYTE_UNITS[0]);
+    if size == 1 {
+        format!("{}b", bytes)
+    } else {
+        let value = bytes as f64 / size as f64;
+        let value = format!("{:.1}", value);
+        format!("{}{}", value.trim_end_matches(".0"), name)
+    }
+}
+
+/// Render a cat API table
+///
+/// Supports the common cat parameters: `v` (header row), `h` (columns by name
+/// or alias, comma-separated), `format` (`text` or `json`) and `bytes` (unit of
+/// size columns: `b`, `kb`, `mb`, `gb`, `tb` or `pb`; human-readable by
+/// default). JSON output is an array of objects keyed by column name, with the
+/// values as strings.
+fn render_cat(
+    params: &HashMap<String, String>,
+    columns: &[CatColumn],
+    rows: Vec<Vec<CatValue>>,
+) -> Result<Response> {
+    let selected: Vec<usize> = match params.get("h") {
+        Some(names) => names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .map(|name| {
+                columns
+                    .iter()
+                    .position(|column| column.name == name || column.aliases.contains(&name))
+                    .ok_or_else(|| {
+                        GbsError::InvalidRequest(format!("Unknown cat column [{}] in [h]", name))
+                    })
+            })
+            .collect::<Result<_>>()?,
+        None => (0..columns.len()).filter(|&i| columns[i].default).collect(),
+    };
+    let unit = match params.get("bytes").map(String::as_str) {
+        None => None,
+        Some(unit) => Some(
+            BYTE_UNITS
+                .iter()
+                .find(|(name, _)| *name == unit)
+                .map(|(_, size)| *size)
+                .ok_or_else(|| {
+                    GbsError::InvalidRequest(format!(
+                        "Invalid [bytes] unit [{}], expected one of b, kb, mb, gb, tb, pb",
+                        unit
+                    ))
+                })?,
+        ),
+    };
+
+    let cells: Vec<Vec<String>> = rows
+        .into_iter()
+        .map(|row| {
+            let mut row: Vec<Option<CatValue>> = row.into_iter().map(Some).collect();
+            selected
+                .iter()
+                .map(|&i| match row[i].take() {
+                    Some(CatValue::Text(text)) => text,
+                    Some(CatValue::Bytes(bytes)) => format_bytes(bytes, unit),
+                    // A column selected twice
+                    None => String::new(),
+                })
+                .collect()
+        })
+        .collect();
+
+    match params.get("format").map(String::as_str) {
+        Some("json") => {
+            let objects: Vec<serde_json::Value> = cells
+                .into_iter()
+                .map(|row| {
+                    let object: serde_json::Map<String, serde_json::Value> = selected
+                        .iter()
+                        .zip(row)
+                        .map(|(&i, cell)| (columns[i].name.to_string(), serde_json::json!(cell)))
+                        .collect();
+                    serde_json::Value::Object(object)
+                })
+                .collect();
+            Ok(Json(objects).into_response())
+        }
+        None | Some("text") | Some("txt") => {
+            let header: Option<Vec<String>> = params.contains_key("v").then(|| {
+                selected
+                    .iter()
+                    .map(|&i| columns[i].name.to_string())
+                    .collect()
+            });
+            let lines: Vec<&Vec<String>> = header.iter().chain(cells.iter()).collect();
+            let widths: Vec<usize> = (0..selected.len())
+                .map(|i| {
+                    lines
+                        .iter()
+                        .map(|line| line[i].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            let mut output = String::new();
+            for line in lines {
+                let padded: Vec<String> = line
+                    .iter()
+                    .zip(&widths)
+                    .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+                    .collect();
+                output.push_str(padded.join(" ").trim_end());
+                output.push('\n');
+            }
+            Ok((
+                [(header::CONTENT_TYPE, "text/plain; charset=UTF-8")],
+                output,
+            )
+                .into_response())
+        }
+        Some(format) => Err(GbsError::InvalidRequest(format!(
+            "Unsupported cat format [{}], expected text or json",
+            format
+        ))),
+    }
+}
+
+/// Statistics of the indices named by the optional index expression
+async fn cat_index_stats(state: &AppState, expression: Option<&str>) -> Result<Vec<IndexStats>> {
+    let stats = state.storage.get_index_stats().await;
+    match expression {
+        None => Ok(stats),
+        Some(expression) => {
+            let targets = state
+                .storage
+                .resolve_index_expression_with_closed(expression)
+                .await?;
+            Ok(stats
+                .into_iter()
+                .filter(|index| targets.contains(&index.name))
+                .collect())
+        }
+    }
+}
+
+/// Current time as the `epoch` and `timestamp` cells of a cat row
+fn cat_time() -> [CatValue; 2] {
+    let now = Utc::now();
+    [
+        now.timestamp().to_string().into(),
+        now.format("%H:%M:%S").to_string().into(),
+    ]
+}
+
+const CAT_INDICES_COLUMNS: [CatColumn; 12] = [
+    column("health", &["h"], true),
+    column("status", &["s"], true),
+    column("index", &["i", "idx"], true),
+    column("uuid", &["id"], true),
+    column("pri", &["p", "shards.primary"], true),
+    column("rep", &["r", "shards.replica"], true),
+    column("docs.count", &["dc", "docsCount"], true),
+    column("docs.deleted", &["dd", "docsDeleted"], true),
+    column("store.size", &["ss", "storeSize"], true),
+    column("pri.store.size", &[], true),
+    column("creation.date", &["cd"], false),
+    column("tier", &[], false),
+];
+
 pub async fn cat_indices(
     State(state): State<AppState>,
+    index: Option<Path<String>>,
     Query(params): Query<HashMap<String, String>>,
-) -> Result<String> {
+) -> Result<Response> {
     info!("Getting indices list (cat format)");
-    let stats = state.storage.get_indices_stats().await;
+    let index = index.map(|Path(index)| index);
+    let rows = cat_index_stats(&state, index.as_deref())
+        .await?
+        .into_iter()
+        .map(|stats| {
+            vec![
+                stats.health().as_str().into(),
+                // Frozen indices are open as far as cat is concerned
+                match stats.state {
+                    IndexState::Close => "close",
+                    IndexState::Open | IndexState::Frozen => "open",
+                }
+                .into(),
+                stats.name.into(),
+                "-".into(),
+                stats.primaries.into(),
+                stats.replicas.into(),
+                stats.docs_count.into(),
+                "0".into(),
+                CatValue::Bytes(stats.size_in_bytes),
+                CatValue::Bytes(stats.size_in_bytes),
+                stats
+                    .creation_date
+                    .map_or_else(|| "-".to_string(), |date| date.to_string())
+                    .into(),
+                stats.tier.as_str().into(),
+            ]
+        })
+        .collect();
+    render_cat(&params, &CAT_INDICES_COLUMNS, rows)
+}
 
-    // Check if verbose mode (v parameter)
-    let verbose = params.contains_key("v");
+const CAT_HEALTH_COLUMNS: [CatColumn; 14] = [
+    column("epoch", &["t", "time"], true),
+    column("timestamp", &["ts", "hms", "hhmmss"], true),
+    column("cluster", &["cl"], true),
+    column("status", &["st"], true),
+    column("node.total", &["nt", "nodeTotal"], true),
+    column("node.data", &["nd", "nodeData"], true),
+    column("shards", &["sh", "shards.total"], true),
+    column("pri", &["p", "shards.primary"], true),
+    column("relo", &["r", "shards.relocating"], true),
+    column("init", &["i", "shards.initializing"], true),
+    column("unassign", &["u", "shards.unassigned"], true),
+    column("pending_tasks", &["pt", "pendingTasks"], true),
+    column("max_task_wait_time", &["mtwt", "maxTaskWaitTime"], true),
+    column(
+        "active_shards_percent",
+        &["asp", "activeShardsPercent"],
+        true,
+    ),
+];
 
-    if verbose {
-        // Header row
-        let mut output = String::from("health status index uuid pri rep docs.count store.size\n");
+pub async fn cat_health(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response> {
+    info!("Getting cluster health (cat format)");
+    let health = cluster_health_of(&state).await;
+    let [epoch, timestamp] = cat_time();
+    let row = vec![
+        epoch,
+        timestamp,
+        "gbs".into(),
+        health.status.as_str().into(),
+        NUMBER_OF_NODES.into(),
+        NUMBER_OF_NODES.into(),
+        health.active_shards.into(),
+        health.active_primary_shards.into(),
+        "0".into(),
+        "0".into(),
+        health.unassigned_shards.into(),
+        "0".into(),
+        "-".into(),
+        format!("{:.1}%", health.active_shards_percent()).into(),
+    ];
+    render_cat(&params, &CAT_HEALTH_COLUMNS, vec![row])
+}
 
-        // Data rows
-        for (name, doc_count) in stats {
-            output.push_str(&format!(
-                "green   open   {}   -   1   0   {}b\n",
-                name, doc_count
-            ));
-        }
+const CAT_COUNT_COLUMNS: [CatColumn; 3] = [
+    column("epoch", &["t", "time"], true),
+    column("timestamp", &["ts", "hms", "hhmmss"], true),
+    column("count", &["dc", "docs.count", "docsCount"], true),
+];
 
-        Ok(output)
-    } else {
-        // Simple format: just index names
-        let output: Vec<String> = stats.iter().map(|(name, _)| name.clone()).collect();
-        Ok(output.join("\n") + "\n")
+pub async fn cat_count(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response> {
+    let index = index.map(|Path(index)| index);
+    info!("Getting document count (cat format) for {:?}", index);
+    let count: usize = cat_index_stats(&state, index.as_deref())
+        .await?
+        .iter()
+        .map(|stats| stats.docs_count)
+        .sum();
+    let [epoch, timestamp] = cat_time();
+    render_cat(
+        &params,
+        &CAT_COUNT_COLUMNS,
+        vec![vec![epoch, timestamp, count.into()]],
+    )
+}
+
+const CAT_ALIASES_COLUMNS: [CatColumn; 6] = [
+    column("alias", &["a"], true),
+    column("index", &["i", "idx"], true),
+    column("filter", &["f", "fi"], true),
+    column("routing.index", &["ri", "routingIndex"], true),
+    column("routing.search", &["rs", "routingSearch"], true),
+    column("is_write_index", &["w", "isWriteIndex"], true),
+];
+
+pub async fn cat_aliases(
+    State(state): State<AppState>,
+    name: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response> {
+    info!("Getting aliases (cat format)");
+    // Alias names or wildcard patterns, comma-separated
+    let patterns: Option<Vec<Regex>> = name.map(|Path(name)| {
+        name.split(',')
+            .filter_map(|pattern| wildcard_regex(pattern.trim()))
+            .collect()
+    });
+    let mut aliases: Vec<(String, String)> = state
+        .storage
+        .get_index_stats()
+        .await
+        .into_iter()
+        .flat_map(|stats| {
+            let index = stats.name;
+            stats
+                .aliases
+                .into_iter()
+                .map(move |alias| (alias, index.clone()))
+        })
+        .filter(|(alias, _)| {
+            patterns
+                .as_ref()
+                .is_none_or(|patterns| patterns.iter().any(|pattern| pattern.is_match(alias)))
+        })
+        .collect();
+    aliases.sort();
+
+    let rows = aliases
+        .into_iter()
+        .map(|(alias, index)| {
+            vec![
+                alias.into(),
+                index.into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+                "-".into(),
+            ]
+        })
+        .collect();
+    render_cat(&params, &CAT_ALIASES_COLUMNS, rows)
+}
+
+const CAT_SHARDS_COLUMNS: [CatColumn; 8] = [
+    column("index", &["i", "idx"], true),
+    column("shard", &["s", "sh"], true),
+    column("prirep", &["p", "pr", "primaryOrReplica"], true),
+    column("state", &["st"], true),
+    column("docs", &["d", "dc"], true),
+    column("store", &["sto"], true),
+    column("ip", &[], true),
+    column("node", &["n"], true),
+];
+
+/// Shards of the indices
+///
+/// Every index lists its `number_of_shards` primaries as started on the local
+/// node, with its documents and size spread evenly over them; replicas are
+/// never allocated.
+pub async fn cat_shards(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Response> {
+    let index = index.map(|Path(index)| index);
+    info!("Getting shards (cat format) for {:?}", index);
+    let rows = cat_index_stats(&state, index.as_deref())
+        .await?
+        .into_iter()
+        // Closed indices have no started shards
+        .filter(|stats| stats.state != IndexState::Close)
+        .flat_map(|stats| {
+            let shards = stats.primaries;
+            (0..shards).map(move |shard| {
+                // The first shards take the remainder
+                let share = |total: u64| total / shards + u64::from(shard < total % shards);
+                vec![
+                    stats.name.clone().into(),
+                    shard.to_string().into(),
+                    "p".into(),
+                    "STARTED".into(),
+                    share(stats.docs_count as u64).into(),
+                    CatValue::Bytes(share(stats.size_in_bytes)),
+                    "127.0.0.1".into(),
+                    "gbs".into(),
+                ]
+            })
+        })
+        .collect();
+    render_cat(&params, &CAT_SHARDS_COLUMNS, rows)
+}
+
+/// Metrics of the index stats API
+const INDICES_STATS_METRICS: [&str; 5] = ["docs", "store", "indexing", "search", "query_cache"];
+
+/// Index statistics (`GET /_stats`, `GET /{index}/_stats`, optionally
+/// followed by `/{metric}`)
+///
+/// Every index is a single primary shard, so `primaries` and `total` are the
+/// same. Documents are removed right away, so none are ever counted as deleted.
+pub async fn indices_stats(
+    State(state): State<AppState>,
+    path: Option<Path<HashMap<String, String>>>,
+) -> Result<Json<serde_json::Value>> {
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    let expression = path.get("index").map_or("_all", String::as_str);
+    info!("Getting index stats for {}", expression);
+    let metrics = requested_metrics(
+        path.get("metric").map(String::as_str),
+        &INDICES_STATS_METRICS,
+    )?;
+
+    let targets = state.storage.resolve_index_expression(expression).await?;
+    let stats: Vec<IndexStats> = state
+        .storage
+        .get_index_stats()
+        .await
+        .into_iter()
+        .filter(|index| targets.contains(&index.name))
+        .collect();
+    if let Some(closed) = stats.iter().find(|index| index.state == IndexState::Close) {
+        return Err(GbsError::IndexClosed(closed.name.clone()));
+    }
+
+    let (mut docs, mut size, mut operations) = (0, 0, OperationStats::default());
+    let mut filter_cache = FilterCacheStats::default();
+    let mut indices = serde_json::Map::new();
+    for index in &stats {
+        docs += index.docs_count;
+        size += index.size_in_bytes;
+        operations += index.operations;
+        filter_cache += index.filter_cache;
+        let sections = index_stats_sections(
+            index.docs_count,
+            index.size_in_bytes,
+            &index.operations,
+            &index.filter_cache,
+            metrics.as_deref(),
+        );
+        indices.insert(
+            index.name.clone(),
+            serde_json::json!({ "primaries": sections, "total": sections }),
+        );
     }
+    let all = index_stats_sections(docs, size, &operations, &filter_cache, metrics.as_deref());
+    let shards: u64 = stats.iter().map(|index| index.primaries).sum();
+
+    Ok(Json(serde_json::json!({
+        "_shards": { "total": shards, "successful": shards, "failed": 0 },
+        "_all": { "primaries": all, "total": all },
+        "indices": indices
+    })))
+}
+
+/// Stats sections of an index, limited to the requested metrics
+fn index_stats_sections(
+    docs: usize,
+    size_in_bytes: u64,
+    operations: &OperationStats,
+    filter_cache: &FilterCacheStats,
+    metrics: Option<&[String]>,
+) -> serde_json::Value {
+    let sections = [
+        ("docs", serde_json::json!({ "count": docs, "deleted": 0 })),
+        (
+            "store",
+            serde_json::json!({ "size_in_bytes": size_in_bytes }),
+        ),
+        (
+            "indexing",
+            serde_json::json!({
+                "index_total": operations.index_total,
+                "index_time_in_millis": operations.index_time_in_millis,
+                "delete_total": operations.delete_total,
+                "delete_time_in_millis": operations.delete_time_in_millis
+            }),
+        ),
+        (
+            "search",
+            serde_json::json!({
+                "query_total": operations.query_total,
+                "query_time_in_millis": operations.query_time_in_millis
+            }),
+        ),
+        (
+            "query_cache",
+            serde_json::json!({
+                "total_count": filter_cache.hits + filter_cache.misses,
+                "hit_count": filter_cache.hits,
+                "miss_count": filter_cache.misses,
+                "cache_size": filter_cache.entries,
+                "evictions": filter_cache.evictions
+            }),
+        ),
+    ];
+    sections
+        .into_iter()
+        .filter(|(name, _)| metrics.is_none_or(|metrics| metrics.iter().any(|m| m == name)))
+        .map(|(name, section)| (name.to_string(), section))
+        .collect::<serde_json::Map<_, _>>()
+        .into()
 }
 
 pub async fn get_aliases(State(state): State<AppState>) -> Result<Json<serde_json::Value>> {
@@ -70,3 +648,351 @@ pub async fn get_aliases(State(state): State<AppState>) -> Result<Json<serde_jso
     let aliases = state.storage.get_aliases().await;
     Ok(Json(aliases))
 }
+
+/// Metrics of the nodes info API
+const NODES_INFO_METRICS: [&str; 12] = [
+    "settings",
+    "os",
+    "process",
+    "jvm",
+    "thread_pool",
+    "transport",
+    "http",
+    "plugins",
+    "ingest",
+    "modules",
+    "indices",
+    "aggregations",
+];
+
+/// Metrics of the nodes stats API
+const NODES_STATS_METRICS: [&str; 15] = [
+    "indices",
+    "os",
+    "process",
+    "jvm",
+    "thread_pool",
+    "fs",
+    "transport",
+    "http",
+    "breaker",
+    "script",
+    "discovery",
+    "ingest",
+    "adaptive_selection",
+    "script_cache",
+    "indexing_pressure",
+];
+
+/// Major and minor version of the emulated Elasticsearch version
+pub(crate) fn es_major_minor(es_version: &str) -> (u32, u32) {
+    let mut parts = es_version.split('.').map(|part| part.parse().unwrap_or(0));
+    (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+}
+
+/// Lucene version bundled with the emulated Elasticsearch version
+pub(crate) fn lucene_version(es_version: &str) -> &'static str {
+    match es_major_minor(es_version).0 {
+        0..=6 => "7.7.3",
+        7 => "8.11.1",
+        _ => "9.7.0",
+    }
+}
+
+/// Roles of the node, as named by the emulated Elasticsearch version
+pub(crate) fn node_roles(es_version: &str) -> Vec<&'static str> {
+    match es_major_minor(es_version) {
+        (major, _) if major < 7 => vec!["master", "data", "ingest"],
+        (7, minor) if minor < 10 => vec!["data", "ingest", "master", "remote_cluster_client"],
+        _ => vec![
+            "data",
+            "data_content",
+            "data_hot",
+            "data_warm",
+            "ingest",
+            "master",
+            "remote_cluster_client",
+        ],
+    }
+}
+
+/// Whether a node selector (`_all`, `_local`, IDs, names or wildcards,
+/// comma-separated) selects the local node
+fn selects_local_node(state: &AppState, selector: &str) -> bool {
+    selector.split(',').map(str::trim).any(|part| match part {
+        "_all" | "_local" | "_master" | "*" => true,
+        pattern => [&state.node.id, &state.node.name]
+            .iter()
+            .any(|name| wildcard_regex(pattern).is_some_and(|regex| regex.is_match(name))),
+    })
+}
+
+/// Requested metrics, checked against the metrics an API knows
+fn requested_metrics(metrics: Option<&str>, known: &[&str]) -> Result<Option<Vec<String>>> {
+    let Some(metrics) = metrics else {
+        return Ok(None);
+    };
+    if metrics == "_all" {
+        return Ok(None);
+    }
+    metrics
+        .split(',')
+        .map(|metric| {
+            let metric = metric.trim();
+            if known.contains(&metric) {
+                Ok(metric.to_string())
+            } else {
+                Err(GbsError::InvalidRequest(format!(
+                    "Unknown metric [{}], expected one of {:?}",
+                    metric, known
+                )))
+            }
+        })
+        .collect::<Result<Vec<_>>>()
+        .map(Some)
+}
+
+/// A single segment after `/_nodes/` is a list of metrics if every entry is a
+/// known metric, and a node selector otherwise
+fn split_nodes_path<'a>(segment: &'a str, known: &[&str]) -> (Option<&'a str>, Option<&'a str>) {
+    let all_metrics = segment
+        .split(',')
+        .all(|part| known.contains(&part.trim()) || part.trim() == "_all");
+    if all_metrics && segment != "_all" {
+        (None, Some(segment))
+    } else {
+        (Some(segment), None)
+    }
+}
+
+/// Response of a nodes API, holding the local node if it is selected
+fn nodes_response(
+    state: &AppState,
+    selector: Option<&str>,
+    metrics: &Option<Vec<String>>,
+    sections: Vec<(&'static str, serde_json::Value)>,
+) -> serde_json::Value {
+    let node = &state.node;
+    let publish_address = node.publish_address();
+    let mut nodes = serde_json::Map::new();
+    if selector.is_none_or(|selector| selects_local_node(state, selector)) {
+        let mut body = serde_json::json!({
+            "name": node.name,
+            "transport_address": publish_address.to_string(),
+            "host": publish_address.ip().to_string(),
+            "ip": publish_address.ip().to_string(),
+            "roles": node_roles(&state.es_version),
+            "attributes": {},
+        });
+        for (metric, section) in sections {
+            if metrics
+                .as_ref()
+                .is_none_or(|metrics| metrics.iter().any(|m| m == metric))
+            {
+                body[metric] = section;
+            }
+        }
+        nodes.insert(node.id.clone(), body);
+    }
+    serde_json::json!({
+        "_nodes": { "total": nodes.len(), "successful": nodes.len(), "failed": 0 },
+        "cluster_name": "gbs",
+        "nodes": nodes,
+    })
+}
+
+/// Nodes info (`GET /_nodes`, `/_nodes/{nodes}`, `/_nodes/{metrics}`,
+/// `/_nodes/{nodes}/{metrics}`)
+///
+/// Reports the version, roles and HTTP address of the local node, with OS,
+/// process and runtime details. The runtime is reported in the `jvm` section,
+/// as clients expect one.
+pub async fn nodes_info(
+    State(state): State<AppState>,
+    path: Option<Path<Vec<String>>>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Getting nodes info");
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    let (selector, metrics) = match path.as_slice() {
+        [] => (None, None),
+        [segment] => split_nodes_path(segment, &NODES_INFO_METRICS),
+        [selector, metrics, ..] => (Some(selector.as_str()), Some(metrics.as_str())),
+    };
+    let metrics = requested_metrics(metrics, &NODES_INFO_METRICS)?;
+
+    let node = &state.node;
+    let process = ProcessMetrics::collect();
+    let publish_address = node.publish_address().to_string();
+    let (major, minor) = es_major_minor(&state.es_version);
+
+    let mut sections = vec![
+        ("version", serde_json::json!(state.es_version)),
+        ("build_hash", serde_json::json!(env!("CARGO_PKG_VERSION"))),
+        (
+            "settings",
+            serde_json::json!({
+                "cluster": { "name": "gbs" },
+                "node": { "name": node.name },
+                "http": { "port": node.http_address.port().to_string() }
+            }),
+        ),
+        (
+            "os",
+            serde_json::json!({
+                "refresh_interval_in_millis": 1000,
+                "name": std::env::consts::OS,
+                "arch": std::env::consts::ARCH,
+                "available_processors": num_cpus::get(),
+                "allocated_processors": num_cpus::get()
+            }),
+        ),
+        (
+            "process",
+            serde_json::json!({
+                "refresh_interval_in_millis": 1000,
+                "id": process.pid,
+                "mlockall": false
+            }),
+        ),
+        (
+            "jvm",
+            serde_json::json!({
+                "pid": process.pid,
+                "version": env!("CARGO_PKG_VERSION"),
+                "vm_name": "gbs",
+                "vm_version": env!("CARGO_PKG_VERSION"),
+                "vm_vendor": "gbs",
+                "start_time_in_millis": node.start_time_millis,
+                "mem": {
+                    "heap_init_in_bytes": 0,
+                    "heap_max_in_bytes": process.total_memory_bytes,
+                    "non_heap_init_in_bytes": 0,
+                    "non_heap_max_in_bytes": 0,
+                    "direct_max_in_bytes": 0
+                },
+                "gc_collectors": [],
+                "memory_pools": []
+            }),
+        ),
+        (
+            "http",
+            serde_json::json!({
+                "bound_address": [node.http_address.to_string()],
+                "publish_address": publish_address,
+                "max_content_length_in_bytes": state.limits().max_body_bytes
+            }),
+        ),
+        ("plugins", serde_json::json!([])),
+        ("modules", serde_json::json!([])),
+    ];
+    // Build flavor and type were added in Elasticsearch 6.3
+    if (major, minor) >= (6, 3) {
+        sections.push(("build_flavor", serde_json::json!("default")));
+        sections.push(("build_type", serde_json::json!("tar")));
+    }
+    // Always present, whatever the requested metrics
+    let metrics = metrics.map(|mut metrics| {
+        metrics.extend(["version", "build_hash", "build_flavor", "build_type"].map(str::to_string));
+        metrics
+    });
+
+    Ok(Json(nodes_response(&state, selector, &metrics, sections)))
+}
+
+/// Nodes stats (`GET /_nodes/stats`, `/_nodes/stats/{metrics}`,
+/// `/_nodes/{nodes}/stats`, `/_nodes/{nodes}/stats/{metrics}`)
+///
+/// Process metrics are reported in the `process` section and, for clients
+/// that monitor a JVM, as heap usage and threads in the `jvm` section.
+pub async fn nodes_stats(
+    State(state): State<AppState>,
+    path: Option<Path<HashMap<String, String>>>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Getting nodes stats");
+    let path = path.map(|Path(path)| path).unwrap_or_default();
+    let metrics = requested_metrics(
+        path.get("metrics").map(String::as_str),
+        &NODES_STATS_METRICS,
+    )?;
+
+    let node = &state.node;
+    let process = ProcessMetrics::collect();
+    let timestamp = Utc::now().timestamp_millis();
+    let stats = state.storage.get_index_stats().await;
+    let docs: usize = stats.iter().map(|index| index.docs_count).sum();
+    let size: u64 = stats.iter().map(|index| index.size_in_bytes).sum();
+    let heap_used_percent = (process.resident_bytes * 100)
+        .checked_div(process.total_memory_bytes)
+        .unwrap_or(0);
+
+    let sections = vec![
+        ("timestamp", serde_json::json!(timestamp)),
+        (
+            "indices",
+            serde_json::json!({
+                "docs": { "count": docs, "deleted": 0 },
+                "store": { "size_in_bytes": size }
+            }),
+        ),
+        (
+            "os",
+            serde_json::json!({
+                "timestamp": timestamp,
+                "cpu": {
+                    "load_average": {
+                        "1m": process.load_average[0],
+                        "5m": process.load_average[1],
+                        "15m": process.load_average[2]
+                    }
+                },
+                "mem": { "total_in_bytes": process.total_memory_bytes }
+            }),
+        ),
+        (
+            "process",
+            serde_json::json!({
+                "timestamp": timestamp,
+                "open_file_descriptors": process.open_file_descriptors,
+                "max_file_descriptors": process.max_file_descriptors,
+                "cpu": { "total_in_millis": process.cpu_millis },
+                "mem": {
+                    "resident_in_bytes": process.resident_bytes,
+                    "total_virtual_in_bytes": process.virtual_bytes
+                }
+            }),
+        ),
+        (
+            "jvm",
+            serde_json::json!({
+                "timestamp": timestamp,
+                "uptime_in_millis": node.uptime_millis(),
+                "mem": {
+                    "heap_used_in_bytes": process.resident_bytes,
+                    "heap_used_percent": heap_used_percent,
+                    "heap_committed_in_bytes": process.resident_bytes,
+                    "heap_max_in_bytes": process.total_memory_bytes,
+                    "non_heap_used_in_bytes": 0,
+                    "non_heap_committed_in_bytes": 0,
+                    "peak_used_in_bytes": process.peak_resident_bytes
+                },
+                "threads": { "count": process.threads, "peak_count": process.threads },
+                "gc": { "collectors": {} }
+            }),
+        ),
+        (
+            "thread_pool",
+            serde_json::json!({ "write": state.writes.stats() }),
+        ),
+    ];
+    let metrics = metrics.map(|mut metrics| {
+        metrics.push("timestamp".to_string());
+        metrics
+    });
+
+    Ok(Json(nodes_response(
+        &state,
+        path.get("nodes").map(String::as_str),
+        &metrics,
+        sections,
+    )))
+}
@@ -0,0 +1,31 @@
+//! Configuration handlers (`/_config`)
+
+use axum::{
+    extract::{Extension, State},
+    response::Json,
+};
+use tracing::info;
+
+use crate::auth::User;
+use crate::error::Result;
+use crate::server::{AppState, ReloadReport};
+
+/// Effective configuration, with secrets redacted
+pub async fn get_config(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<serde_json::Value>> {
+    user.authorize_admin()?;
+    Ok(Json(state.config.redacted()))
+}
+
+/// Reload the configuration and apply the settings that can change without
+/// a restart
+pub async fn reload_config(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<ReloadReport>> {
+    user.authorize_admin()?;
+    info!("Reloading configuration");
+    Ok(Json(state.config.reload(&state.auth).await?))
+}
@@ -1,55 +1,400 @@
 //! Document management handlers
 
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    response::Json,
+    extract::{FromRequest, Path, Query, Request, State},
+    http::{HeaderMap, Method, StatusCode},
+    response::{IntoResponse, Json, Response},
 };
-use tracing::{info, debug};
+use std::collections::HashMap;
+use tracing::{debug, error, info};
 
-use crate::error::Result;
+use crate::error::{GbsError, Result};
+use crate::server::accounting::record_indexed;
+use crate::server::limits::document_size;
+use crate::server::proxy::proxy_unmatched;
 use crate::server::AppState;
+use crate::storage::{filter_source, ChangeOp, ChangesRequest, ChangesResponse, RefreshPolicy};
 
 pub async fn index_document(
     State(state): State<AppState>,
     Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     body: Json<serde_json::Value>,
-) -> Result<StatusCode> {
+) -> Result<(StatusCode, Json<serde_json::Value>)> {
     info!("Indexing document {} in index {}", id, index);
-    state.storage.index_document(&index, &id, body.0).await?;
-    Ok(StatusCode::CREATED)
+    match params.get("op_type").map(String::as_str) {
+        None | Some("index") => {}
+        Some("create") => {
+            let path = Path((index, id));
+            return create_document_with_id(State(state), path, Query(params), headers, body).await;
+        }
+        Some(other) => {
+            return Err(GbsError::IllegalArgument(format!(
+                "opType must be 'create' or 'index', found: [{}]",
+                other
+            )))
+        }
+    }
+    let refresh = refresh_policy(&params)?;
+    let document = run_pipeline(&state, &params, body.0)?;
+    state.limits().check_document(&document)?;
+    let bytes = document_size(&document);
+    let outcome = state.storage.index_document(&index, &id, document).await?;
+    record_indexed(&state, &headers, &outcome.index, bytes);
+    apply_refresh(&state, &outcome.index, refresh).await?;
+    let status = match outcome.change.op {
+        ChangeOp::Create => StatusCode::CREATED,
+        _ => StatusCode::OK,
+    };
+    let result = outcome.change.op.result();
+    Ok((
+        status,
+        Json(write_response(
+            &outcome.index,
+            &id,
+            result,
+            outcome.version,
+            Some(outcome.change.seq_no()),
+        )),
+    ))
 }
 
 pub async fn create_document(
     State(state): State<AppState>,
     Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
     body: Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>> {
     info!("Creating document in index {}", index);
-    let id = state.storage.create_document(&index, body.0).await?;
-    Ok(Json(serde_json::json!({
+    let refresh = refresh_policy(&params)?;
+    let document = run_pipeline(&state, &params, body.0)?;
+    state.limits().check_document(&document)?;
+    let bytes = document_size(&document);
+    let (id, outcome) = state.storage.create_document(&index, document).await?;
+    // Report the concrete index of documents written through an ingest route
+    record_indexed(&state, &headers, &outcome.index, bytes);
+    apply_refresh(&state, &outcome.index, refresh).await?;
+    Ok(Json(write_response(
+        &outcome.index,
+        &id,
+        "created",
+        outcome.version,
+        Some(outcome.change.seq_no()),
+    )))
+}
+
+/// Create a document under an ID, failing with `409 Conflict` if the ID
+/// exists (`PUT /{index}/_create/{id}`, or `op_type=create` on
+/// `PUT /{index}/_doc/{id}`)
+pub async fn create_document_with_id(
+    State(state): State<AppState>,
+    Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<serde_json::Value>)> {
+    info!("Creating document {} in index {}", id, index);
+    let refresh = refresh_policy(&params)?;
+    let document = run_pipeline(&state, &params, body.0)?;
+    state.limits().check_document(&document)?;
+    let bytes = document_size(&document);
+    // Report the concrete index of documents written through an ingest route
+    let (index, _, _, _, seq_no, version) = state
+        .storage
+        .create_document_with_id(&index, &id, document)
+        .await?;
+    record_indexed(&state, &headers, &index, bytes);
+    apply_refresh(&state, &index, refresh).await?;
+    Ok((
+        StatusCode::CREATED,
+        Json(write_response(
+            &index,
+            &id,
+            "created",
+            version.unwrap_or(1),
+            seq_no,
+        )),
+    ))
+}
+
+/// Body of the response to a single-document write, as Elasticsearch gives
+/// it
+///
+/// `_version` is the version of the document after the write, and
+/// `_seq_no` the position of the write among the writes to the index, counted
+/// from 0, for the writes that know it.
+pub(crate) fn write_response(
+    index: &str,
+    id: &str,
+    result: &str,
+    version: u64,
+    seq_no: Option<u64>,
+) -> serde_json::Value {
+    let mut response = serde_json::json!({
         "_index": index,
         "_type": "_doc",
         "_id": id,
-        "_version": 1,
-        "result": "created"
-    })))
+        "_version": version,
+        "result": result,
+        "_shards": { "total": 1, "successful": 1, "failed": 0 }
+    });
+    if let Some(seq_no) = seq_no {
+        response["_seq_no"] = seq_no.into();
+        response["_primary_term"] = 1.into();
+    }
+    response
+}
+
+/// Run a document through the ingest pipeline of the `pipeline` parameter,
+/// if there is one
+pub(crate) fn run_pipeline(
+    state: &AppState,
+    params: &HashMap<String, String>,
+    document: serde_json::Value,
+) -> Result<serde_json::Value> {
+    match params.get("pipeline") {
+        Some(pipeline) => state.storage.run_pipeline(pipeline, document),
+        None => Ok(document),
+    }
+}
+
+/// Parse the `refresh` parameter of a write
+pub(crate) fn refresh_policy(params: &HashMap<String, String>) -> Result<RefreshPolicy> {
+    RefreshPolicy::parse(params.get("refresh").map(String::as_str))
+}
+
+/// Make a write to an index searchable as its `refresh` parameter asks:
+/// refresh the index now, or wait for its next refresh (`wait_for`)
+pub(crate) async fn apply_refresh(
+    state: &AppState,
+    index: &str,
+    refresh: RefreshPolicy,
+) -> Result<()> {
+    match refresh {
+        RefreshPolicy::None => Ok(()),
+        RefreshPolicy::Immediate => state.storage.refresh_index(index).await,
+        RefreshPolicy::WaitFor => state.storage.wait_for_refresh(index).await,
+    }
 }
 
 pub async fn get_document(
     State(state): State<AppState>,
     Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Json<serde_json::Value>> {
     debug!("Getting document '{}' from index '{}'", id, index);
-    let doc = state.storage.get_document(&index, &id).await?;
+    let mut doc = state.storage.get_document(&index, &id).await?;
     debug!("Document '{}' retrieved successfully", id);
+    match source_filter(&params) {
+        Some(serde_json::Value::Bool(false)) => {
+            if let Some(doc) = doc.as_object_mut() {
+                doc.remove("_source");
+            }
+        }
+        filter => doc["_source"] = filter_source(&doc["_source"], filter.as_ref()),
+    }
     Ok(Json(doc))
 }
 
+/// Source filter of a get or URI search, in the shape `_source` takes in a
+/// search body, from the `_source`, `_source_includes` and `_source_excludes`
+/// parameters
+///
+/// `_source` is `true`, `false` or a comma-separated list of fields to
+/// include; `_source_includes` takes precedence over such a list.
+pub(crate) fn source_filter(params: &HashMap<String, String>) -> Option<serde_json::Value> {
+    let fields = |list: &str| -> Vec<serde_json::Value> {
+        list.split(',')
+            .map(str::trim)
+            .filter(|field| !field.is_empty())
+            .map(|field| field.into())
+            .collect()
+    };
+    let mut filter = serde_json::Map::new();
+    match params.get("_source").map(String::as_str) {
+        None | Some("") | Some("true") => {}
+        Some("false") => return Some(false.into()),
+        Some(list) => {
+            filter.insert("includes".to_string(), fields(list).into());
+        }
+    }
+    if let Some(list) = params.get("_source_includes") {
+        filter.insert("includes".to_string(), fields(list).into());
+    }
+    if let Some(list) = params.get("_source_excludes") {
+        filter.insert("excludes".to_string(), fields(list).into());
+    }
+    (!filter.is_empty()).then_some(serde_json::Value::Object(filter))
+}
+
+pub async fn check_document(
+    State(state): State<AppState>,
+    Path((index, id)): Path<(String, String)>,
+) -> StatusCode {
+    debug!(
+        "Checking existence of document '{}' in index '{}'",
+        id, index
+    );
+    match state.storage.document_exists(&index, &id).await {
+        Ok(true) => StatusCode::OK,
+        Ok(false) => StatusCode::NOT_FOUND,
+        Err(e) => {
+            error!(
+                "Error checking document '{}' in index '{}': {}",
+                id, index, e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Get only the source of a document (`GET /{index}/_source/{id}`)
+pub async fn get_source(
+    State(state): State<AppState>,
+    Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    debug!("Getting source of document '{}' from index '{}'", id, index);
+    let doc = state.storage.get_document(&index, &id).await?;
+    Ok(Json(filter_source(
+        &doc["_source"],
+        source_filter(&params).as_ref(),
+    )))
+}
+
 pub async fn delete_document(
     State(state): State<AppState>,
     Path((index, id)): Path<(String, String)>,
-) -> Result<StatusCode> {
-    state.storage.delete_document(&index, &id).await?;
-    Ok(StatusCode::OK)
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<(StatusCode, Json<serde_json::Value>)> {
+    let refresh = refresh_policy(&params)?;
+    // A missing document is reported in the body of a write response, as
+    // Elasticsearch does; a missing index is an error
+    let outcome = match state.storage.delete_document(&index, &id).await {
+        Ok(outcome) => outcome,
+        Err(GbsError::DocumentNotFound(_)) => {
+            return Ok((
+                StatusCode::NOT_FOUND,
+                Json(write_response(&index, &id, "not_found", 1, None)),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    apply_refresh(&state, &index, refresh).await?;
+    let result = outcome.change.op.result();
+    Ok((
+        StatusCode::OK,
+        Json(write_response(
+            &index,
+            &id,
+            result,
+            outcome.version,
+            Some(outcome.change.seq_no()),
+        )),
+    ))
+}
+
+/// Document APIs with a mapping type in the path (`/{index}/{type}/{id}`),
+/// as in Elasticsearch 6.x and 7.x
+///
+/// The type of the path is reported as the `_type` of the document. When
+/// emulating 8.x, and for paths of other APIs (`/{index}/_name/{id}`), the
+/// request goes to the proxy as other unsupported requests.
+pub async fn typed_document(
+    State(state): State<AppState>,
+    Path((index, doc_type, id)): Path<(String, String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    request: Request,
+) -> Response {
+    if !is_typed_path(&state, &index, &doc_type) {
+        return proxy_unmatched(State(state), request).await;
+    }
+    let path = Path((index, id));
+    match request.method().clone() {
+        Method::GET => match get_document(State(state), path, Query(params)).await {
+            Ok(Json(mut doc)) => {
+                doc["_type"] = doc_type.into();
+                Json(doc).into_response()
+            }
+            Err(e) => e.into_response(),
+        },
+        Method::HEAD => check_document(State(state), path).await.into_response(),
+        Method::DELETE => typed_write(
+            delete_document(State(state), path, Query(params)).await,
+            doc_type,
+        ),
+        Method::PUT | Method::POST => {
+            let headers = request.headers().clone();
+            match Json::from_request(request, &state).await {
+                Ok(body) => typed_write(
+                    index_document(State(state), path, Query(params), headers, body).await,
+                    doc_type,
+                ),
+                Err(rejection) => rejection.into_response(),
+            }
+        }
+        _ => proxy_unmatched(State(state), request).await,
+    }
+}
+
+/// Response to a write under a mapping type, reporting the type of the path
+fn typed_write(
+    response: Result<(StatusCode, Json<serde_json::Value>)>,
+    doc_type: String,
+) -> Response {
+    match response {
+        Ok((status, Json(mut body))) => {
+            body["_type"] = doc_type.into();
+            (status, Json(body)).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Create a document with a generated ID under a mapping type
+/// (`POST /{index}/{type}`), as in Elasticsearch 6.x and 7.x
+pub async fn typed_create_document(
+    State(state): State<AppState>,
+    Path((index, doc_type)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    request: Request,
+) -> Response {
+    if !is_typed_path(&state, &index, &doc_type) || request.method() != Method::POST {
+        return proxy_unmatched(State(state), request).await;
+    }
+    let headers = request.headers().clone();
+    let body = match Json::from_request(request, &state).await {
+        Ok(body) => body,
+        Err(rejection) => return rejection.into_response(),
+    };
+    match create_document(State(state), Path(index), Query(params), headers, body).await {
+        Ok(Json(mut response)) => {
+            response["_type"] = doc_type.into();
+            Json(response).into_response()
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Whether a path names an index and a mapping type rather than another API
+pub(crate) fn is_typed_path(state: &AppState, index: &str, doc_type: &str) -> bool {
+    state.compat().has_types() && !index.starts_with('_') && !doc_type.starts_with('_')
+}
+
+/// Changes of an index after a sequence number (`GET /{index}/_changes`)
+///
+/// With `timeout`, a request with no changes yet waits for the next one.
+pub async fn get_changes(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<ChangesResponse>> {
+    let request = ChangesRequest::parse(&params)?;
+    debug!(
+        "Getting changes of index {} after seq {}",
+        index, request.since
+    );
+    Ok(Json(state.storage.get_changes(&index, &request).await?))
 }
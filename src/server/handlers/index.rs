@@ -1,33 +1,70 @@
 //! Index management handlers
 
 use axum::{
-    extract::{Path, State},
+    extract::{FromRequest, Path, Query, Request, State},
     http::StatusCode,
-    response::Json,
+    response::{IntoResponse, Json, Response},
 };
+use std::collections::HashMap;
 use tracing::{debug, error, info};
 
 use crate::error::{GbsError, Result};
+use crate::server::handlers::cluster::lucene_version;
+use crate::server::proxy::proxy_unmatched;
 use crate::server::AppState;
+use crate::storage::{
+    resolve_date_math_index_name, DynamicMode, IndexState, IndexTier, RolloverRequest,
+    RolloverResponse,
+};
 
 pub async fn create_index(
     State(state): State<AppState>,
     Path(index): Path<String>,
     body: Option<Json<serde_json::Value>>,
 ) -> Result<StatusCode> {
+    let index = resolve_date_math_index_name(&index, chrono::Utc::now())?;
     info!("Creating index: {}", index);
 
     let settings = body.as_ref().and_then(|b| b.get("settings").cloned());
     let mappings = body.as_ref().and_then(|b| b.get("mappings").cloned());
 
+    let aliases: Vec<String> = body
+        .as_ref()
+        .and_then(|b| b.get("aliases"))
+        .and_then(|a| a.as_object())
+        .map(|a| a.keys().cloned().collect())
+        .unwrap_or_default();
+
     state
         .storage
         .create_index(&index, settings, mappings)
         .await?;
 
+    for alias in aliases {
+        state.storage.put_alias(&index, &alias).await?;
+    }
+
     Ok(StatusCode::OK)
 }
 
+pub async fn put_alias(
+    State(state): State<AppState>,
+    Path((index, alias)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Adding alias '{}' to index '{}'", alias, index);
+    state.storage.put_alias(&index, &alias).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+pub async fn delete_alias(
+    State(state): State<AppState>,
+    Path((index, alias)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Removing alias '{}' from index '{}'", alias, index);
+    state.storage.delete_alias(&index, &alias).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
 pub async fn check_index(State(state): State<AppState>, Path(index): Path<String>) -> StatusCode {
     debug!("Checking existence of index: {}", index);
     match state.storage.index_exists(&index).await {
@@ -76,19 +113,112 @@ pub async fn update_mapping(
     info!("Updating mapping for index: {}", index);
 
     // Extract mappings from body
-    let new_mappings = body
-        .get("properties")
-        .or_else(|| body.get("mappings").and_then(|m| m.get("properties")))
-        .cloned();
+    let root = body.get("mappings").unwrap_or(&body);
+    let new_mappings = root.get("properties").cloned();
+    let dynamic = root.get("dynamic").map(DynamicMode::parse).transpose()?;
 
+    if new_mappings.is_none() && dynamic.is_none() {
+        return Err(GbsError::InvalidRequest(
+            "Missing 'properties' or 'mappings.properties' in request body".to_string(),
+        ));
+    }
     if let Some(mappings) = new_mappings {
         state.storage.update_mapping(&index, mappings).await?;
-        Ok(StatusCode::OK)
-    } else {
-        Err(GbsError::InvalidRequest(
-            "Missing 'properties' or 'mappings.properties' in request body".to_string(),
-        ))
     }
+    if let Some(mode) = dynamic {
+        state.storage.set_dynamic_mapping(&index, mode).await?;
+    }
+    Ok(StatusCode::OK)
+}
+
+pub async fn get_mapping(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Getting mapping for: {}", index);
+
+    let mut response = serde_json::Map::new();
+    for name in state.storage.resolve_index_expression(&index).await? {
+        let mappings = state.storage.get_mapping(&name).await?;
+        response.insert(name, serde_json::json!({ "mappings": mappings }));
+    }
+    Ok(Json(serde_json::Value::Object(response)))
+}
+
+/// Mapping of a type (`GET /{index}/_mapping/{type}`), as in Elasticsearch
+/// 6.x and 7.x
+///
+/// Indices have a single type, so this is the mapping of the index.
+pub async fn get_typed_mapping(
+    State(state): State<AppState>,
+    Path((index, _doc_type)): Path<(String, String)>,
+    request: Request,
+) -> Response {
+    if !state.compat().has_types() {
+        return proxy_unmatched(State(state), request).await;
+    }
+    get_mapping(State(state), Path(index)).await.into_response()
+}
+
+/// Update the mapping of a type (`PUT/POST /{index}/_mapping/{type}`), as in
+/// Elasticsearch 6.x and 7.x
+pub async fn update_typed_mapping(
+    State(state): State<AppState>,
+    Path((index, _doc_type)): Path<(String, String)>,
+    request: Request,
+) -> Response {
+    if !state.compat().has_types() {
+        return proxy_unmatched(State(state), request).await;
+    }
+    match Json::from_request(request, &state).await {
+        Ok(body) => update_mapping(State(state), Path(index), body)
+            .await
+            .into_response(),
+        Err(rejection) => rejection.into_response(),
+    }
+}
+
+/// Field capabilities (`GET|POST /_field_caps`, `GET|POST /{index}/_field_caps`)
+///
+/// Fields are selected with the `fields` parameter or body key (names with
+/// wildcards, comma-separated; default `*`). `include_unmapped=true` also
+/// lists the indices that lack a field.
+pub async fn field_caps(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Option<Json<serde_json::Value>>,
+) -> Result<Json<serde_json::Value>> {
+    // `/_field_caps` has no index in its path
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Field capabilities for: {}", expression);
+
+    let patterns: Vec<String> = match (
+        params.get("fields"),
+        body.as_ref().and_then(|body| body.get("fields")),
+    ) {
+        (Some(fields), _) => fields.split(',').map(str::to_string).collect(),
+        (None, Some(serde_json::Value::Array(fields))) => fields
+            .iter()
+            .filter_map(|field| field.as_str().map(str::to_string))
+            .collect(),
+        (None, Some(serde_json::Value::String(fields))) => {
+            fields.split(',').map(str::to_string).collect()
+        }
+        _ => vec!["*".to_string()],
+    };
+    let include_unmapped = params.get("include_unmapped").is_some_and(|v| v == "true");
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    debug!(
+        "Index expression '{}' resolved to {:?}",
+        expression, targets
+    );
+    let response = state
+        .storage
+        .field_caps(&targets, &patterns, include_unmapped)
+        .await?;
+    Ok(Json(response))
 }
 
 pub async fn update_settings(
@@ -98,24 +228,336 @@ pub async fn update_settings(
 ) -> Result<StatusCode> {
     info!("Updating settings for index: {}", index);
 
-    state.storage.update_settings(&index, body.0).await?;
+    // Settings may be wrapped in a "settings" object, as in create index
+    let mut body = body.0;
+    let wrapped = body.as_object().is_some_and(|b| b.len() == 1);
+    let settings = match body.get_mut("settings") {
+        Some(settings) if wrapped => settings.take(),
+        _ => body,
+    };
+    state.storage.update_settings(&index, settings).await?;
     Ok(StatusCode::OK)
 }
 
 pub async fn refresh_index(
-    State(_state): State<AppState>,
+    State(state): State<AppState>,
     Path(index): Path<String>,
 ) -> Result<StatusCode> {
     info!("Refreshing index: {}", index);
-    // Refresh is a no-op for in-memory storage
-    // In a persistent storage backend, this would flush changes to disk
-    // For now, we just return OK as changes are immediately available
+    // Refreshing makes the writes held back by a refresh interval searchable,
+    // flushes them to disk and runs the index's warmers (a missing index has
+    // none)
+    state.storage.refresh_index(&index).await?;
     Ok(StatusCode::OK)
 }
 
-pub async fn refresh_all(State(_state): State<AppState>) -> Result<StatusCode> {
+pub async fn refresh_all(State(state): State<AppState>) -> Result<StatusCode> {
     info!("Refreshing all indices");
-    // Refresh all is a no-op for in-memory storage
-    // In a persistent storage backend, this would flush all changes to disk
+    state.storage.refresh_all().await?;
     Ok(StatusCode::OK)
 }
+
+/// Force-merge indices (`POST /_forcemerge`, `POST /{index}/_forcemerge`)
+///
+/// Documents are removed right away, so there are never deletes to expunge
+/// and every shard is already a single segment. Merging releases spare
+/// memory and, unless `flush=false`, flushes the backend so it can reclaim
+/// space on disk.
+pub async fn force_merge(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Force merging: {}", expression);
+
+    let max_num_segments = params
+        .get("max_num_segments")
+        .map(|value| {
+            value.parse::<u64>().ok().filter(|&n| n > 0).ok_or_else(|| {
+                GbsError::IllegalArgument(format!(
+                    "[max_num_segments] must be a positive integer, got [{}]",
+                    value
+                ))
+            })
+        })
+        .transpose()?;
+    let only_expunge_deletes = params
+        .get("only_expunge_deletes")
+        .is_some_and(|v| v == "true");
+    if only_expunge_deletes && max_num_segments.is_some() {
+        return Err(GbsError::IllegalArgument(
+            "cannot set only_expunge_deletes and max_num_segments at the same time, those two \
+             parameters are mutually exclusive"
+                .to_string(),
+        ));
+    }
+    let flush = params.get("flush").is_none_or(|v| v != "false");
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    state.storage.force_merge(&targets, flush).await?;
+    shards_response(&state, &targets).await
+}
+
+/// Flush indices to disk (`POST /_flush`, `POST /{index}/_flush`)
+///
+/// Every write is in the backend's write buffer once acknowledged; flushing
+/// syncs the buffer to disk. Without persistent storage there is nothing to
+/// flush.
+pub async fn flush(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Flushing: {}", expression);
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    state.storage.flush().await?;
+    shards_response(&state, &targets).await
+}
+
+/// Clear the caches of indices (`POST /_cache/clear`,
+/// `POST /{index}/_cache/clear`)
+///
+/// `query=true` clears the filter caches and `request=true` the cached
+/// aggregation results; with neither (nor `fielddata`, which is never
+/// cached), both are cleared.
+pub async fn clear_cache(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Clearing caches: {}", expression);
+    let flag = |name: &str| {
+        params
+            .get(name)
+            .is_some_and(|v| v.is_empty() || v == "true")
+    };
+    let (query, request) = match (flag("query"), flag("request"), flag("fielddata")) {
+        (false, false, false) => (true, true),
+        (query, request, _) => (query, request),
+    };
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    state.storage.clear_caches(&targets, query, request).await;
+    shards_response(&state, &targets).await
+}
+
+/// `_shards` response of an operation on every shard of the given indices
+async fn shards_response(state: &AppState, targets: &[String]) -> Result<Json<serde_json::Value>> {
+    let mut shards = 0;
+    for name in targets {
+        shards += state.storage.number_of_shards(name).await?;
+    }
+    Ok(Json(serde_json::json!({
+        "_shards": { "total": shards, "successful": shards, "failed": 0 }
+    })))
+}
+
+/// Segments of indices (`GET /_segments`, `GET /{index}/_segments`)
+///
+/// Each shard holding documents reports one committed segment with its share
+/// of the index's documents and size, split as in `_cat/shards`.
+pub async fn segments(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Getting segments for: {}", expression);
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    let version = lucene_version(&state.es_version);
+    let mut total_shards = 0;
+    let mut indices = serde_json::Map::new();
+    for stats in state.storage.get_index_stats().await {
+        if !targets.contains(&stats.name) {
+            continue;
+        }
+        if stats.state == IndexState::Close {
+            return Err(GbsError::IndexClosed(stats.name));
+        }
+
+        let shards = stats.primaries;
+        total_shards += shards;
+        let mut shard_entries = serde_json::Map::new();
+        for shard in 0..shards {
+            // The first shards take the remainder
+            let share = |total: u64| total / shards + u64::from(shard < total % shards);
+            let num_docs = share(stats.docs_count as u64);
+            let mut segments = serde_json::Map::new();
+            if num_docs > 0 {
+                segments.insert(
+                    "_0".to_string(),
+                    serde_json::json!({
+                        "generation": 0,
+                        "num_docs": num_docs,
+                        "deleted_docs": 0,
+                        "size_in_bytes": share(stats.size_in_bytes),
+                        "memory_in_bytes": 0,
+                        "committed": true,
+                        "search": true,
+                        "version": version,
+                        "compound": true,
+                        "attributes": {}
+                    }),
+                );
+            }
+            shard_entries.insert(
+                shard.to_string(),
+                serde_json::json!([{
+                    "routing": { "state": "STARTED", "primary": true, "node": state.node.id },
+                    "num_committed_segments": segments.len(),
+                    "num_search_segments": segments.len(),
+                    "segments": segments
+                }]),
+            );
+        }
+        indices.insert(stats.name, serde_json::json!({ "shards": shard_entries }));
+    }
+
+    Ok(Json(serde_json::json!({
+        "_shards": { "total": total_shards, "successful": total_shards, "failed": 0 },
+        "indices": indices
+    })))
+}
+
+pub async fn put_warmer(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Storing warmer '{}' on index '{}'", name, index);
+    state.storage.put_warmer(&index, &name, body.0).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+pub async fn get_warmers(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    let warmers = state.storage.get_warmers(&index).await?;
+    Ok(Json(serde_json::json!({ index: { "warmers": warmers } })))
+}
+
+pub async fn get_warmer(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    let warmer = state
+        .storage
+        .get_warmers(&index)
+        .await?
+        .remove(&name)
+        .ok_or_else(|| GbsError::WarmerNotFound(format!("[{}] on index [{}]", name, index)))?;
+    Ok(Json(
+        serde_json::json!({ index: { "warmers": { name: warmer } } }),
+    ))
+}
+
+pub async fn delete_warmer(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Deleting warmer '{}' from index '{}'", name, index);
+    state.storage.delete_warmer(&index, &name).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+/// Open closed or frozen indices (`POST /{index}/_open`)
+pub async fn open_index(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    set_indices_state(&state, &index, IndexState::Open).await
+}
+
+/// Close indices, keeping their documents (`POST /{index}/_close`)
+pub async fn close_index(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    set_indices_state(&state, &index, IndexState::Close).await
+}
+
+/// Make indices read-only (`POST /{index}/_freeze`)
+pub async fn freeze_index(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    set_indices_state(&state, &index, IndexState::Frozen).await
+}
+
+/// Make frozen indices writable again (`POST /{index}/_unfreeze`)
+pub async fn unfreeze_index(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    set_indices_state(&state, &index, IndexState::Open).await
+}
+
+/// Move every index of an expression to a state; wildcards match closed
+/// indices too
+async fn set_indices_state(
+    state: &AppState,
+    expression: &str,
+    index_state: IndexState,
+) -> Result<Json<serde_json::Value>> {
+    let targets = state
+        .storage
+        .resolve_index_expression_with_closed(expression)
+        .await?;
+    info!("Setting indices {:?} to {}", targets, index_state.as_str());
+    for name in &targets {
+        state.storage.set_index_state(name, index_state).await?;
+    }
+    Ok(Json(serde_json::json!({
+        "acknowledged": true,
+        "shards_acknowledged": true
+    })))
+}
+
+/// Roll an alias over to a new index (`POST /{alias}/_rollover`,
+/// `POST /{alias}/_rollover/{new_index}`)
+pub async fn rollover(
+    State(state): State<AppState>,
+    Path(path): Path<HashMap<String, String>>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Option<Json<serde_json::Value>>,
+) -> Result<Json<RolloverResponse>> {
+    let alias = path.get("index").cloned().unwrap_or_default();
+    let body = body.map(|Json(body)| body).unwrap_or_default();
+    let request = RolloverRequest::parse(
+        &alias,
+        path.get("new_index").map(String::as_str),
+        &body,
+        &params,
+    )?;
+    info!("Rolling over alias: {}", alias);
+    let response = state.storage.rollover(&request).await?;
+    Ok(Json(response))
+}
+
+pub async fn get_index_tier(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    let tier = state.storage.get_index_tier(&index).await?;
+    Ok(Json(tier))
+}
+
+pub async fn set_index_tier(
+    State(state): State<AppState>,
+    Path((index, tier)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    let tier = IndexTier::parse(&tier).ok_or_else(|| {
+        GbsError::InvalidRequest(format!("Unknown tier [{}], expected [hot] or [warm]", tier))
+    })?;
+    info!("Moving index '{}' to {} tier", index, tier.as_str());
+
+    let changed = state.storage.set_index_tier(&index, tier).await?;
+    Ok(Json(serde_json::json!({
+        "acknowledged": true,
+        "index": index,
+        "tier": tier.as_str(),
+        "changed": changed
+    })))
+}
@@ -0,0 +1,39 @@
+//! Prometheus metrics handler
+
+use axum::{extract::State, http::header, response::IntoResponse};
+use tracing::debug;
+
+use crate::metrics::write_gauge;
+use crate::server::{AppState, ProcessMetrics};
+
+/// Content type of the Prometheus text exposition format
+const EXPOSITION_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Metrics in the Prometheus text exposition format (`GET /_metrics`)
+pub async fn get_metrics(State(state): State<AppState>) -> impl IntoResponse {
+    debug!("Rendering metrics");
+    let indices = state.storage.get_index_stats().await;
+    let mut body = state.metrics.render(&indices);
+
+    let process = ProcessMetrics::collect();
+    write_gauge(
+        &mut body,
+        "gbs_uptime_seconds",
+        "Time since the server started",
+        state.node.uptime_millis() as f64 / 1000.0,
+    );
+    write_gauge(
+        &mut body,
+        "process_resident_memory_bytes",
+        "Resident memory size",
+        process.resident_bytes as f64,
+    );
+    write_gauge(
+        &mut body,
+        "process_open_fds",
+        "Open file descriptors",
+        process.open_file_descriptors as f64,
+    );
+
+    ([(header::CONTENT_TYPE, EXPOSITION_CONTENT_TYPE)], body)
+}
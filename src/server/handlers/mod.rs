@@ -2,16 +2,34 @@
 
 pub mod bulk;
 pub mod cluster;
+pub mod config;
 pub mod document;
 pub mod index;
+pub mod metrics;
+pub mod pipeline;
+pub mod script;
 pub mod search;
+pub mod search_profile;
+pub mod security;
+pub mod tasks;
+pub mod template;
+pub mod usage;
 pub mod web;
 pub mod websocket;
 
 pub use bulk::*;
 pub use cluster::*;
+pub use config::*;
 pub use document::*;
 pub use index::*;
+pub use metrics::*;
+pub use pipeline::*;
+pub use script::*;
 pub use search::*;
+pub use search_profile::*;
+pub use security::*;
+pub use tasks::*;
+pub use template::*;
+pub use usage::*;
 pub use web::*;
 pub use websocket::*;
@@ -0,0 +1,163 @@
+//! Ingest pipeline handlers (`/_ingest/pipeline`)
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use chrono::{SecondsFormat, Utc};
+use std::collections::HashMap;
+use tracing::info;
+
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+use crate::storage::{IngestPipeline, ProcessorOutcome};
+
+pub async fn put_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Storing ingest pipeline '{}'", id);
+    let pipeline = IngestPipeline::parse(&body)?;
+    state.storage.put_pipeline(&id, pipeline).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+pub async fn get_pipelines(State(state): State<AppState>) -> Result<Json<serde_json::Value>> {
+    find_pipelines(&state, "*")
+}
+
+/// Pipelines by id or wildcard pattern; an explicit id that matches nothing
+/// is an error
+pub async fn get_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    find_pipelines(&state, &id)
+}
+
+pub async fn delete_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Deleting ingest pipeline '{}'", id);
+    state.storage.delete_pipeline(&id).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+/// Run documents through a stored pipeline without indexing them
+/// (`GET|POST /_ingest/pipeline/{id}/_simulate`)
+pub async fn simulate_stored_pipeline(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let (_, pipeline) = state
+        .storage
+        .get_pipelines(&id)
+        .into_iter()
+        .find(|(pipeline_id, _)| *pipeline_id == id)
+        .ok_or_else(|| GbsError::PipelineNotFound(id.clone()))?;
+    simulate(&pipeline, &params, &body)
+}
+
+/// Run documents through the pipeline of the request body
+/// (`GET|POST /_ingest/pipeline/_simulate`)
+pub async fn simulate_pipeline(
+    Query(params): Query<HashMap<String, String>>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let definition = body.get("pipeline").ok_or_else(|| {
+        GbsError::IllegalArgument("[pipeline] required property is missing".to_string())
+    })?;
+    simulate(&IngestPipeline::parse(definition)?, &params, &body)
+}
+
+/// The result of each of the `docs` of a simulate request: the processed
+/// document or the error it failed with, or with `verbose=true` the result
+/// of each processor
+fn simulate(
+    pipeline: &IngestPipeline,
+    params: &HashMap<String, String>,
+    body: &serde_json::Value,
+) -> Result<Json<serde_json::Value>> {
+    let verbose = params.get("verbose").is_some_and(|v| v != "false");
+    let docs = body
+        .get("docs")
+        .and_then(|docs| docs.as_array())
+        .filter(|docs| !docs.is_empty())
+        .ok_or_else(|| {
+            GbsError::IllegalArgument("must specify at least one document in [docs]".to_string())
+        })?;
+    let timestamp = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let results = docs
+        .iter()
+        .map(|doc| {
+            let source = doc
+                .get("_source")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({}));
+            // Simulated documents are reported with their metadata
+            let simulated = |source: serde_json::Value| {
+                serde_json::json!({
+                    "_index": doc.get("_index").cloned().unwrap_or_else(|| serde_json::json!("_index")),
+                    "_id": doc.get("_id").cloned().unwrap_or_else(|| serde_json::json!("_id")),
+                    "_source": source,
+                    "_ingest": { "timestamp": timestamp },
+                })
+            };
+            if !verbose {
+                return Ok(match pipeline.run(source) {
+                    Ok(source) => serde_json::json!({ "doc": simulated(source) }),
+                    Err(e) => serde_json::json!({ "error": error_json(&e) }),
+                });
+            }
+            let results: Vec<_> = pipeline
+                .run_verbose(source)?
+                .into_iter()
+                .map(|result| {
+                    let mut json = serde_json::json!({ "processor_type": result.processor_type });
+                    if let Some(tag) = result.tag {
+                        json["tag"] = serde_json::json!(tag);
+                    }
+                    match result.outcome {
+                        ProcessorOutcome::Success(source) => {
+                            json["status"] = serde_json::json!("success");
+                            json["doc"] = simulated(source);
+                        }
+                        ProcessorOutcome::ErrorIgnored(source, e) => {
+                            json["status"] = serde_json::json!("error_ignored");
+                            json["ignored_error"] = serde_json::json!({ "error": error_json(&e) });
+                            json["doc"] = simulated(source);
+                        }
+                        ProcessorOutcome::Error(e) => {
+                            json["status"] = serde_json::json!("error");
+                            json["error"] = error_json(&e);
+                        }
+                    }
+                    json
+                })
+                .collect();
+            Ok(serde_json::json!({ "processor_results": results }))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Json(serde_json::json!({ "docs": results })))
+}
+
+fn error_json(error: &GbsError) -> serde_json::Value {
+    serde_json::json!({ "type": error.error_type(), "reason": error.reason() })
+}
+
+fn find_pipelines(state: &AppState, pattern: &str) -> Result<Json<serde_json::Value>> {
+    let pipelines = state.storage.get_pipelines(pattern);
+    if pipelines.is_empty() && !pattern.contains('*') {
+        return Err(GbsError::PipelineNotFound(pattern.to_string()));
+    }
+    Ok(Json(serde_json::Value::Object(
+        pipelines
+            .into_iter()
+            .map(|(id, pipeline)| (id, pipeline.to_json().clone()))
+            .collect(),
+    )))
+}
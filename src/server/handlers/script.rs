@@ -0,0 +1,50 @@
+//! Stored script handlers (`/_scripts`)
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use tracing::info;
+
+use crate::error::Result;
+use crate::server::AppState;
+use crate::storage::StoredScript;
+
+/// Store a search template (`PUT/POST /_scripts/{id}`)
+pub async fn put_script(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Storing script '{}'", id);
+    let script = StoredScript::parse(&body)?;
+    state.storage.put_script(&id, script).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+/// Get a stored script; a missing one is reported with `found: false`
+pub async fn get_script(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    match state.storage.get_script(&id) {
+        Some(script) => (
+            StatusCode::OK,
+            Json(serde_json::json!({ "_id": id, "found": true, "script": script.to_json() })),
+        ),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({ "_id": id, "found": false })),
+        ),
+    }
+}
+
+pub async fn delete_script(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Deleting script '{}'", id);
+    state.storage.delete_script(&id).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
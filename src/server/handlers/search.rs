@@ -1,14 +1,29 @@
 //! Search handlers
 
 use axum::{
-    extract::{Path, State, Query},
-    response::Json,
+    body::Bytes,
+    extract::{FromRequest, Path, Query, Request, State},
+    http::Method,
+    response::{IntoResponse, Json, Response},
 };
 use std::collections::HashMap;
-use tracing::{info, debug};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinSet;
+use tracing::instrument::WithSubscriber;
+use tracing::{debug, info, Instrument};
 
-use crate::error::Result;
+use crate::error::{GbsError, Result};
+use crate::models::QueryAst;
+use crate::server::handlers::document::{is_typed_path, source_filter};
+use crate::server::proxy::proxy_unmatched;
 use crate::server::AppState;
+use crate::storage::{
+    compare_sort_values, expand_query_strings, merge_aggregations, parse_docvalue_fields,
+    parse_script_fields, parse_sort, parse_stored_fields, time_value_millis, Collapse, IndexState,
+    RuntimeFields, SearchRequest,
+};
 
 pub async fn search_get(
     State(state): State<AppState>,
@@ -19,139 +34,1114 @@ pub async fn search_get(
     debug!("Search query parameters: {:?}", params);
 
     // Parse query from query parameters or use match_all
-    let query = if let Some(q) = params.get("q") {
-        debug!("Using query string: {}", q);
-        // Simple query string: search in all fields
-        serde_json::json!({
-            "match": {
-                "_all": q
-            }
-        })
-    } else {
+    let query = query_string_param(&params).unwrap_or_else(|| {
         debug!("No query string, using match_all");
         // Default to match_all
         serde_json::json!({
             "match_all": {}
         })
-    };
+    });
 
-    let from = params.get("from").and_then(|s| s.parse::<u32>().ok());
-    let size = params.get("size").and_then(|s| s.parse::<u32>().ok());
-    let sort = None; // TODO: Parse sort from query params if needed
-    let source_filter = None; // TODO: Parse _source from query params if needed
-    let highlight = None; // TODO: Parse highlight from query params if needed
+    let body = uri_search_body(&params)?;
+    let options = SearchOptions::from_body(&body)?;
 
-    let result = state.storage.search(&index, &query, from, size, sort, source_filter, highlight).await?;
+    let result = search_index_expression(&state, &index, &params, query, &options).await?;
     Ok(Json(result))
 }
 
+/// Query of the `q` parameter (Lucene syntax), if given
+///
+/// `df` and `default_operator` are used as in the query_string query.
+fn query_string_param(params: &HashMap<String, String>) -> Option<serde_json::Value> {
+    let q = params.get("q")?;
+    debug!("Using query string: {}", q);
+    let mut query_string = serde_json::json!({ "query": q });
+    if let Some(df) = params.get("df") {
+        query_string["default_field"] = serde_json::json!(df);
+    }
+    if let Some(operator) = params.get("default_operator") {
+        query_string["default_operator"] = serde_json::json!(operator);
+    }
+    Some(serde_json::json!({ "query_string": query_string }))
+}
+
+/// Search body equivalent to the options of a URI search: `from`, `size`,
+/// `sort` (`field:asc,other:desc`), `_source` with `_source_includes` and
+/// `_source_excludes`, `stored_fields`, `docvalue_fields` and `explain`
+///
+/// URI searches then go through the same options as search bodies.
+fn uri_search_body(params: &HashMap<String, String>) -> Result<serde_json::Value> {
+    let mut body = serde_json::Map::new();
+    for name in ["from", "size"] {
+        if let Some(value) = params.get(name) {
+            let value: u32 = value.parse().map_err(|_| {
+                GbsError::IllegalArgument(format!(
+                    "Failed to parse int parameter [{}] with value [{}]",
+                    name, value
+                ))
+            })?;
+            body.insert(name.to_string(), value.into());
+        }
+    }
+    if let Some(sort) = params.get("sort") {
+        let clauses: Vec<serde_json::Value> = sort
+            .split(',')
+            .filter(|clause| !clause.is_empty())
+            .map(|clause| match clause.rsplit_once(':') {
+                Some((field, order)) => serde_json::json!({ field: order }),
+                None => serde_json::json!(clause),
+            })
+            .collect();
+        body.insert("sort".to_string(), clauses.into());
+    }
+    if let Some(filter) = source_filter(params) {
+        body.insert("_source".to_string(), filter);
+    }
+    // Comma-separated field lists
+    for name in ["stored_fields", "docvalue_fields"] {
+        if let Some(fields) = params.get(name) {
+            body.insert(name.to_string(), serde_json::json!(fields));
+        }
+    }
+    if params.get("explain").is_some_and(|v| v == "true") {
+        body.insert("explain".to_string(), true.into());
+    }
+    Ok(serde_json::Value::Object(body))
+}
+
+/// Search all indices with query parameters (`GET /_search`)
+pub async fn search_all_get(
+    state: State<AppState>,
+    params: Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    search_get(state, Path("_all".to_string()), params).await
+}
+
 pub async fn search_post(
     State(state): State<AppState>,
     Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
     body: Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>> {
     info!("Search POST for index: {}", index);
-    debug!("Search query: {}", serde_json::to_string(&body.0).unwrap_or_default());
+    debug!(
+        "Search query: {}",
+        serde_json::to_string(&body.0).unwrap_or_default()
+    );
 
-    let query = body.get("query").cloned().unwrap_or_else(|| {
-        serde_json::json!({ "match_all": {} })
-    });
-    let from = body.get("from").and_then(|v| v.as_u64()).map(|v| v as u32);
-    let size = body.get("size").and_then(|v| v.as_u64()).map(|v| v as u32);
-    let sort = body.get("sort");
-    let source_filter = body.get("_source");
-    let highlight = body.get("highlight");
+    let query = body
+        .get("query")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+    let options = SearchOptions::from_body(&body)?;
+
+    let result = search_index_expression(&state, &index, &params, query, &options).await?;
+    Ok(Json(result))
+}
 
-    let result = state.storage.search(&index, &query, from, size, sort, source_filter, highlight).await?;
+/// Search with a mapping type in the path (`GET/POST /{index}/{type}/_search`),
+/// as in Elasticsearch 6.x and 7.x
+///
+/// The type does not narrow the search, as indices have a single type.
+pub async fn typed_search(
+    State(state): State<AppState>,
+    Path((index, doc_type)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    request: Request,
+) -> Response {
+    if !is_typed_path(&state, &index, &doc_type) {
+        return proxy_unmatched(State(state), request).await;
+    }
+    match request.method().clone() {
+        Method::GET => search_get(State(state), Path(index), Query(params))
+            .await
+            .into_response(),
+        Method::POST => match Json::from_request(request, &state).await {
+            Ok(body) => search_post(State(state), Path(index), Query(params), body)
+                .await
+                .into_response(),
+            Err(rejection) => rejection.into_response(),
+        },
+        _ => proxy_unmatched(State(state), request).await,
+    }
+}
+
+/// Search with a search template (`GET/POST /{index}/_search/template`)
+///
+/// The body names a stored template by `id` or gives it as `source`; the
+/// template is rendered with `params` and run as a regular search body.
+pub async fn search_template(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Search template for index: {}", index);
+    let mut rendered = state.storage.render_search_template(&body)?;
+    debug!("Rendered search template: {}", rendered);
+    if body.get("explain").and_then(|v| v.as_bool()) == Some(true) {
+        rendered["explain"] = serde_json::json!(true);
+    }
+
+    let query = rendered
+        .get("query")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+    let options = SearchOptions::from_body(&rendered)?;
+
+    let result = search_index_expression(&state, &index, &params, query, &options).await?;
     Ok(Json(result))
 }
 
+/// Search all indices with a search template (`GET/POST /_search/template`)
+pub async fn search_template_all(
+    state: State<AppState>,
+    params: Query<HashMap<String, String>>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    search_template(state, Path("_all".to_string()), params, body).await
+}
+
+/// Render a search template without searching (`GET/POST /_render/template`)
+pub async fn render_template(
+    State(state): State<AppState>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let rendered = state.storage.render_search_template(&body)?;
+    Ok(Json(serde_json::json!({ "template_output": rendered })))
+}
+
+/// Render a stored search template (`GET/POST /_render/template/{id}`)
+pub async fn render_stored_template(
+    state: State<AppState>,
+    Path(id): Path<String>,
+    Json(mut body): Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    if !body.is_object() {
+        return Err(GbsError::InvalidRequest(
+            "Request body must be an object".to_string(),
+        ));
+    }
+    body["id"] = serde_json::json!(id);
+    render_template(state, Json(body)).await
+}
+
+/// Pagination, sorting and response shaping shared by the search endpoints
+struct SearchOptions<'a> {
+    from: Option<u32>,
+    size: Option<u32>,
+    sort: Option<&'a serde_json::Value>,
+    source_filter: Option<&'a serde_json::Value>,
+    highlight: Option<&'a serde_json::Value>,
+    aggs: Option<&'a serde_json::Value>,
+    /// Values computed by scripts, added to each hit's `fields`
+    script_fields: Option<&'a serde_json::Value>,
+    /// Fields whose values are added to each hit's `fields` in place of the
+    /// `_source`
+    stored_fields: Option<&'a serde_json::Value>,
+    /// Fields whose doc values are added to each hit's `fields`
+    docvalue_fields: Option<&'a serde_json::Value>,
+    /// Add an `_explanation` of its score to each hit
+    explain: bool,
+    /// Time budget of the search, a time value like `500ms`
+    timeout: Option<&'a serde_json::Value>,
+    /// Field to collapse hits on, with optional inner hits
+    collapse: Option<&'a serde_json::Value>,
+    /// Fields computed at search time (`runtime_mappings`)
+    runtime_mappings: Option<&'a serde_json::Value>,
+}
+
+impl<'a> SearchOptions<'a> {
+    fn from_body(body: &'a serde_json::Value) -> Result<Self> {
+        Ok(Self {
+            from: paging_value(body, "from")?,
+            size: paging_value(body, "size")?,
+            sort: body.get("sort"),
+            source_filter: body.get("_source"),
+            highlight: body.get("highlight"),
+            aggs: body.get("aggs").or_else(|| body.get("aggregations")),
+            script_fields: body.get("script_fields"),
+            stored_fields: body.get("stored_fields"),
+            docvalue_fields: body.get("docvalue_fields"),
+            explain: body
+                .get("explain")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            timeout: body.get("timeout"),
+            collapse: body.get("collapse"),
+            runtime_mappings: body.get("runtime_mappings"),
+        })
+    }
+
+    /// Number of hits up to the end of the requested page (`from + size`)
+    fn window(&self) -> Result<u32> {
+        let window = u64::from(self.from.unwrap_or(0)) + u64::from(self.size.unwrap_or(10));
+        u32::try_from(window).map_err(|_| {
+            GbsError::IllegalArgument(format!(
+                "Result window is too large, from + size must be less than or equal to: [{}] but was [{}]",
+                u32::MAX,
+                window
+            ))
+        })
+    }
+
+    /// The search of one index with these options, its time budget and its
+    /// parsed runtime fields
+    fn search_request(
+        &self,
+        query: serde_json::Value,
+        timeout: Option<Duration>,
+        runtime_fields: Option<RuntimeFields>,
+    ) -> SearchRequest {
+        SearchRequest {
+            query,
+            from: self.from,
+            size: self.size,
+            sort: self.sort.cloned(),
+            source_filter: self.source_filter.cloned(),
+            highlight: self.highlight.cloned(),
+            aggs: self.aggs.cloned(),
+            timeout,
+            runtime_fields,
+        }
+    }
+}
+
+/// A `from` or `size` of a search body
+fn paging_value(body: &serde_json::Value, name: &str) -> Result<Option<u32>> {
+    let Some(value) = body.get(name).and_then(|v| v.as_u64()) else {
+        return Ok(None);
+    };
+    u32::try_from(value).map(Some).map_err(|_| {
+        GbsError::IllegalArgument(format!(
+            "[{}] must be less than or equal to: [{}] but was [{}]",
+            name,
+            u32::MAX,
+            value
+        ))
+    })
+}
+
+/// Hits one concrete index contributed to a search
+struct IndexHits {
+    index: String,
+    total_hits: u64,
+    returned_hits: usize,
+    error: Option<String>,
+}
+
+/// Search an index expression (index, alias, wildcard or comma-separated list)
+///
+/// An expression naming a single concrete index is searched directly; anything
+/// else is searched index by index and merged. With `?resolved_indices=true`
+/// the response lists the concrete indices that were searched.
+async fn search_index_expression(
+    state: &AppState,
+    expression: &str,
+    params: &HashMap<String, String>,
+    query: serde_json::Value,
+    options: &SearchOptions<'_>,
+) -> Result<serde_json::Value> {
+    check_result_window(state, options)?;
+    let targets = state.storage.resolve_index_expression(expression).await?;
+    debug!(
+        "Index expression '{}' resolved to {:?}",
+        expression, targets
+    );
+
+    // Collapsing groups every matching document, so the indices return all
+    // of them with their whole source and the groups are paged afterwards
+    let collapse = options.collapse.map(Collapse::parse).transpose()?;
+    let collapsed;
+    let searched = match &collapse {
+        Some(_) => {
+            collapsed = SearchOptions {
+                from: Some(0),
+                size: Some(u32::MAX),
+                source_filter: None,
+                ..*options
+            };
+            &collapsed
+        }
+        None => options,
+    };
+
+    let (mut result, mut contributions) = if let [index] = targets.as_slice() {
+        let query = apply_search_profile(state, index, params, query.clone()).await?;
+        let timeout = search_timeout(state, params, searched)?;
+        let runtime_fields = searched
+            .runtime_mappings
+            .map(RuntimeFields::parse)
+            .transpose()?;
+        let request = searched.search_request(query, timeout, runtime_fields);
+        let result = state.storage.search_with_request(index, &request).await?;
+        let hits = IndexHits {
+            index: index.clone(),
+            total_hits: result["hits"]["total"]["value"].as_u64().unwrap_or(0),
+            returned_hits: result["hits"]["hits"]
+                .as_array()
+                .map_or(0, |hits| hits.len()),
+            error: None,
+        };
+        (result, vec![hits])
+    } else {
+        search_indices(state, &targets, params, &query, searched).await?
+    };
+    if let Some(collapse) = &collapse {
+        let hits = match result["hits"]["hits"].take() {
+            serde_json::Value::Array(hits) => hits,
+            _ => Vec::new(),
+        };
+        let hits = collapse.apply(
+            hits,
+            options.from.unwrap_or(0) as usize,
+            options.size.unwrap_or(10) as usize,
+            options.source_filter,
+        )?;
+        for contribution in &mut contributions {
+            contribution.returned_hits = hits
+                .iter()
+                .filter(|hit| hit["_index"].as_str() == Some(contribution.index.as_str()))
+                .count();
+        }
+        result["hits"]["hits"] = serde_json::Value::Array(hits);
+    }
+    if options.explain {
+        add_explanations(state, &mut result, params, &query).await?;
+    }
+    if let Some(script_fields) = options.script_fields {
+        add_script_fields(state, &mut result, script_fields).await?;
+    }
+    add_document_fields(state, &mut result, options).await?;
+
+    if params.get("resolved_indices").is_some_and(|v| v == "true") {
+        result["resolved_indices"] = resolved_indices_section(expression, &contributions);
+    }
+    Ok(result)
+}
+
+/// The gbs-specific `resolved_indices` response section
+fn resolved_indices_section(expression: &str, contributions: &[IndexHits]) -> serde_json::Value {
+    let indices: Vec<serde_json::Value> = contributions
+        .iter()
+        .map(|hits| {
+            let mut entry = serde_json::json!({
+                "index": hits.index,
+                "total_hits": hits.total_hits,
+                "returned_hits": hits.returned_hits
+            });
+            if let Some(error) = &hits.error {
+                entry["error"] = serde_json::json!(error);
+            }
+            entry
+        })
+        .collect();
+    serde_json::json!({
+        "expression": expression,
+        "indices": indices
+    })
+}
+
+/// Add an `_explanation` of its score to each hit of a search result
+async fn add_explanations(
+    state: &AppState,
+    result: &mut serde_json::Value,
+    params: &HashMap<String, String>,
+    query: &serde_json::Value,
+) -> Result<()> {
+    let Some(hits) = result["hits"]["hits"].as_array_mut() else {
+        return Ok(());
+    };
+    for hit in hits {
+        let (Some(index), Some(id)) = (hit["_index"].as_str(), hit["_id"].as_str()) else {
+            continue;
+        };
+        let query = apply_search_profile(state, index, params, query.clone()).await?;
+        let explanation = state.storage.explain(index, id, &query).await?;
+        hit["_explanation"] = serde_json::to_value(explanation)?;
+    }
+    Ok(())
+}
+
+/// Add the values of the `script_fields` of a search to the `fields` of each hit
+async fn add_script_fields(
+    state: &AppState,
+    result: &mut serde_json::Value,
+    script_fields: &serde_json::Value,
+) -> Result<()> {
+    // Scripts are compiled before looking at the hits, so errors are reported
+    // by searches without hits too
+    let scripts = parse_script_fields(script_fields)?;
+    let Some(hits) = result["hits"]["hits"].as_array_mut() else {
+        return Ok(());
+    };
+    for hit in hits {
+        let (Some(index), Some(id)) = (hit["_index"].as_str(), hit["_id"].as_str()) else {
+            continue;
+        };
+        let score = hit["_score"].as_f64().unwrap_or(0.0);
+        let values = state
+            .storage
+            .script_fields(index, id, &scripts, score)
+            .await?;
+        if !hit["fields"].is_object() {
+            hit["fields"] = serde_json::json!({});
+        }
+        if let Some(fields) = hit["fields"].as_object_mut() {
+            fields.extend(values);
+        }
+    }
+    Ok(())
+}
+
+/// Add the `stored_fields` and `docvalue_fields` of a search to the `fields`
+/// of each hit
+///
+/// With `stored_fields`, hits have no `_source` unless the search asks for it.
+async fn add_document_fields(
+    state: &AppState,
+    result: &mut serde_json::Value,
+    options: &SearchOptions<'_>,
+) -> Result<()> {
+    let stored_fields = options.stored_fields.map(parse_stored_fields).transpose()?;
+    let docvalue_fields = options
+        .docvalue_fields
+        .map(parse_docvalue_fields)
+        .transpose()?
+        .unwrap_or_default();
+    if stored_fields.is_none() && docvalue_fields.is_empty() {
+        return Ok(());
+    }
+    let Some(hits) = result["hits"]["hits"].as_array_mut() else {
+        return Ok(());
+    };
+    for hit in hits {
+        let (Some(index), Some(id)) = (hit["_index"].as_str(), hit["_id"].as_str()) else {
+            continue;
+        };
+        let values = state
+            .storage
+            .document_fields(index, id, stored_fields.as_ref(), &docvalue_fields)
+            .await?;
+        if stored_fields.is_some() && options.source_filter.is_none() {
+            if let Some(hit) = hit.as_object_mut() {
+                hit.remove("_source");
+            }
+        }
+        if values.is_empty() {
+            continue;
+        }
+        if !hit["fields"].is_object() {
+            hit["fields"] = serde_json::json!({});
+        }
+        if let Some(fields) = hit["fields"].as_object_mut() {
+            fields.extend(values);
+        }
+    }
+    Ok(())
+}
+
+/// Explain how a document scores against a query (`GET/POST /{index}/_explain/{id}`)
+///
+/// The query is taken from the body or the `q` parameter; an alias must point
+/// to a single index.
+pub async fn explain(
+    State(state): State<AppState>,
+    Path((index, id)): Path<(String, String)>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Option<Json<serde_json::Value>>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Explain document {} in index {}", id, index);
+    let query = match body.as_ref().and_then(|body| body.get("query")) {
+        Some(query) => query.clone(),
+        None => query_string_param(&params).ok_or_else(|| {
+            GbsError::InvalidRequest(
+                "Explain requires a [query] in the body or a [q] parameter".to_string(),
+            )
+        })?,
+    };
+
+    let targets = state.storage.resolve_index_expression(&index).await?;
+    let [target] = targets.as_slice() else {
+        return Err(GbsError::InvalidRequest(format!(
+            "Explain needs a single index, but [{}] resolves to {} indices",
+            index,
+            targets.len()
+        )));
+    };
+    let query = apply_search_profile(&state, target, &params, query).await?;
+    let explanation = state.storage.explain(target, &id, &query).await?;
+
+    Ok(Json(serde_json::json!({
+        "_index": target,
+        "_type": "_doc",
+        "_id": id,
+        "matched": explanation.is_match(),
+        "explanation": explanation
+    })))
+}
+
+/// Rewrite the query with the profile selected by the `search_profile` parameter
+async fn apply_search_profile(
+    state: &AppState,
+    index: &str,
+    params: &HashMap<String, String>,
+    query: serde_json::Value,
+) -> Result<serde_json::Value> {
+    match params.get("search_profile") {
+        Some(name) => {
+            let profile = state.storage.get_search_profile(index, name).await?;
+            debug!("Applying search profile '{}' to query", name);
+            Ok(profile.apply(&query))
+        }
+        None => Ok(query),
+    }
+}
+
 pub async fn search_multi_index(
     State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
     body: Json<serde_json::Value>,
 ) -> Result<Json<serde_json::Value>> {
     info!("Multi-index search");
-    debug!("Search query: {}", serde_json::to_string(&body.0).unwrap_or_default());
+    debug!(
+        "Search query: {}",
+        serde_json::to_string(&body.0).unwrap_or_default()
+    );
 
     // Extract indices from body or use _all
-    let indices = body.get("indices")
+    let expression = body
+        .get("indices")
         .and_then(|v| v.as_array())
-        .map(|arr| arr.iter().filter_map(|v| v.as_str().map(|s| s.to_string())).collect::<Vec<_>>())
-        .unwrap_or_else(|| vec!["*".to_string()]);
+        .map(|arr| {
+            arr.iter()
+                .filter_map(|v| v.as_str())
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .unwrap_or_else(|| "_all".to_string());
 
-    let query = body.get("query").cloned().unwrap_or_else(|| {
-        serde_json::json!({ "match_all": {} })
-    });
-    let from = body.get("from").and_then(|v| v.as_u64()).map(|v| v as u32);
-    let size = body.get("size").and_then(|v| v.as_u64()).map(|v| v as u32);
-    let sort = body.get("sort");
-    let source_filter = body.get("_source");
-    let highlight = body.get("highlight");
+    let query = body
+        .get("query")
+        .cloned()
+        .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+    let options = SearchOptions::from_body(&body)?;
+    check_result_window(&state, &options)?;
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    debug!(
+        "Index expression '{}' resolved to {:?}",
+        expression, targets
+    );
+    let (mut result, contributions) =
+        search_indices(&state, &targets, &params, &query, &options).await?;
+    if options.explain {
+        add_explanations(&state, &mut result, &params, &query).await?;
+    }
+    if let Some(script_fields) = options.script_fields {
+        add_script_fields(&state, &mut result, script_fields).await?;
+    }
+    add_document_fields(&state, &mut result, &options).await?;
+
+    if params.get("resolved_indices").is_some_and(|v| v == "true") {
+        result["resolved_indices"] = resolved_indices_section(&expression, &contributions);
+    }
+    Ok(Json(result))
+}
+
+/// Multi-search (`_msearch`)
+///
+/// The body is NDJSON with a header line and a search body line per search.
+/// The header selects the indices (`{"index": "logs-*"}`, default: the index
+/// in the path, or all indices); the body is a regular search body. Each
+/// search gets its own entry in `responses`, with an `error` instead of hits
+/// when it fails.
+pub async fn msearch(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Bytes,
+) -> Result<Json<serde_json::Value>> {
+    // `/_msearch` has no index in its path
+    let index = index.map(|Path(index)| index);
+    info!("Multi-search for index: {:?}", index);
+
+    let body_str = String::from_utf8(body.to_vec())
+        .map_err(|e| GbsError::InvalidRequest(format!("Invalid UTF-8 in body: {}", e)))?;
+    let searches = parse_msearch_ndjson(&body_str, index.as_deref())?;
+    debug!("Multi-search with {} searches", searches.len());
+
+    let start_time = std::time::Instant::now();
+    let mut responses = Vec::with_capacity(searches.len());
+    for (expression, search_body) in &searches {
+        let query = search_body
+            .get("query")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+        let outcome = match SearchOptions::from_body(search_body) {
+            Ok(options) => {
+                search_index_expression(&state, expression, &params, query, &options).await
+            }
+            Err(e) => Err(e),
+        };
+        let response = match outcome {
+            Ok(mut result) => {
+                result["status"] = serde_json::json!(200);
+                result
+            }
+            Err(e) => {
+                debug!("Search {} of multi-search failed: {}", responses.len(), e);
+                e.to_json()
+            }
+        };
+        responses.push(response);
+    }
+
+    Ok(Json(serde_json::json!({
+        "took": start_time.elapsed().as_millis() as u64,
+        "responses": responses
+    })))
+}
+
+/// Parse a multi-search body into the index expression and body of each search
+fn parse_msearch_ndjson(
+    body: &str,
+    default_index: Option<&str>,
+) -> Result<Vec<(String, serde_json::Value)>> {
+    let lines: Vec<&str> = body.lines().filter(|l| !l.trim().is_empty()).collect();
+    if !lines.len().is_multiple_of(2) {
+        return Err(GbsError::InvalidRequest(
+            "Multi-search body must alternate header and search body lines".to_string(),
+        ));
+    }
+
+    let mut searches = Vec::with_capacity(lines.len() / 2);
+    for pair in lines.chunks(2) {
+        let header: serde_json::Value = serde_json::from_str(pair[0]).map_err(|e| {
+            GbsError::InvalidRequest(format!("Invalid JSON in multi-search header: {}", e))
+        })?;
+        let search_body: serde_json::Value = serde_json::from_str(pair[1]).map_err(|e| {
+            GbsError::InvalidRequest(format!("Invalid JSON in multi-search body: {}", e))
+        })?;
+        if !header.is_object() || !search_body.is_object() {
+            return Err(GbsError::InvalidRequest(
+                "Multi-search header and body lines must be JSON objects".to_string(),
+            ));
+        }
+
+        let expression = match header.get("index") {
+            Some(serde_json::Value::String(index)) => index.clone(),
+            Some(serde_json::Value::Array(indices)) => indices
+                .iter()
+                .map(|index| {
+                    index.as_str().ok_or_else(|| {
+                        GbsError::InvalidRequest(
+                            "Multi-search [index] must be a string or an array of strings"
+                                .to_string(),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join(","),
+            Some(_) => {
+                return Err(GbsError::InvalidRequest(
+                    "Multi-search [index] must be a string or an array of strings".to_string(),
+                ))
+            }
+            None => default_index.unwrap_or("_all").to_string(),
+        };
+        searches.push((expression, search_body));
+    }
+    Ok(searches)
+}
+
+/// Search several indices and merge their hits by score or the requested sort
+///
+/// Each index returns its top `from + size` hits, with the values they were
+/// sorted by, so that the merged page is the same one a single index holding
+/// all documents would return. The total counts every matching document. Up to
+/// `max_concurrent_index_searches` indices (or the request's
+/// `max_concurrent_shard_requests`) are searched at the same time, each in a
+/// task of its own so that they run on different workers. The tasks are
+/// aborted when the request is dropped, e.g. when its client goes away. An
+/// index whose search fails is listed in `_shards.failures` and the hits of
+/// the others are still returned.
+async fn search_indices(
+    state: &AppState,
+    targets: &[String],
+    params: &HashMap<String, String>,
+    query: &serde_json::Value,
+    options: &SearchOptions<'_>,
+) -> Result<(serde_json::Value, Vec<IndexHits>)> {
+    let from_val = options.from.unwrap_or(0) as usize;
+    let size_val = options.size.unwrap_or(10) as usize;
+    let window = options.window()?;
+    // Checked before searching, as an invalid sort would fail every index
+    let sort_clauses = options
+        .sort
+        .map(parse_sort)
+        .transpose()?
+        .unwrap_or_default();
+    let timeout = search_timeout(state, params, options)?;
+    let runtime_fields = options
+        .runtime_mappings
+        .map(RuntimeFields::parse)
+        .transpose()?;
+    let start_time = std::time::Instant::now();
+
+    let concurrency = params
+        .get("max_concurrent_shard_requests")
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(state.search().max_concurrent_index_searches)
+        .max(1);
+    let permits = Arc::new(Semaphore::new(concurrency));
+    let mut searches = JoinSet::new();
+    let mut positions = HashMap::new();
+    for (position, index_name) in targets.iter().enumerate() {
+        let permits = permits.clone();
+        let state = state.clone();
+        let params = params.clone();
+        let index_name = index_name.clone();
+        let mut request = SearchRequest {
+            from: Some(0),
+            size: Some(window),
+            ..options.search_request(query.clone(), None, runtime_fields.clone())
+        };
+        let search = async move {
+            // The semaphore is never closed
+            let _permit = permits.acquire_owned().await;
+            request.query =
+                apply_search_profile(&state, &index_name, &params, request.query).await?;
+            // Indices waiting for their turn use up the same budget
+            request.timeout = timeout.map(|timeout| timeout.saturating_sub(start_time.elapsed()));
+            state
+                .storage
+                .search_with_request(&index_name, &request)
+                .await
+        };
+        // The task keeps the span and the subscriber of the request
+        let task = searches.spawn(search.in_current_span().with_current_subscriber());
+        positions.insert(task.id(), position);
+    }
+    let mut outcomes: Vec<_> = (0..targets.len()).map(|_| None).collect();
+    while let Some(joined) = searches.join_next_with_id().await {
+        // A task that panicked fails its index only
+        let (id, outcome) = match joined {
+            Ok((id, outcome)) => (id, outcome),
+            Err(e) => (e.id(), Err(GbsError::TaskJoin(e))),
+        };
+        outcomes[positions[&id]] = Some(outcome);
+    }
+    let outcomes = outcomes.into_iter().flatten();
 
-    // Search across all matching indices
     let mut all_hits: Vec<serde_json::Value> = Vec::new();
+    let mut contributions: Vec<IndexHits> = Vec::new();
+    let mut aggregations: Vec<serde_json::Value> = Vec::new();
     let mut total = 0;
+    // Highest score of any hit, not only of those on the page
+    let mut max_score: Option<f64> = None;
+    let mut timed_out = false;
+    // Whether an index counted only part of its matches
+    let mut total_is_lower_bound = false;
+    // Shards searched and shards of the indices that failed
+    let (mut total_shards, mut failed_shards) = (0, 0);
+    let mut failures = Vec::new();
 
-    for index_pattern in &indices {
-        let matched_indices = state.storage.match_indices(index_pattern).await;
-        debug!("Pattern '{}' matched {} indices", index_pattern, matched_indices.len());
-
-        for index_name in matched_indices {
-            match state.storage.search(&index_name, &query, from, size, sort, source_filter, highlight).await {
-                Ok(result) => {
-                    if let Some(hits_obj) = result.get("hits") {
-                        if let Some(hits_array) = hits_obj.get("hits").and_then(|h| h.as_array()) {
-                            all_hits.extend(hits_array.iter().cloned());
-                        }
-                        if let Some(total_obj) = hits_obj.get("total") {
-                            if let Some(total_val) = total_obj.get("value").and_then(|v| v.as_u64()) {
-                                total += total_val as usize;
-                            }
+    for (index_name, outcome) in targets.iter().zip(outcomes) {
+        let mut hits = IndexHits {
+            index: index_name.clone(),
+            total_hits: 0,
+            returned_hits: 0,
+            error: None,
+        };
+        match outcome {
+            Ok(mut result) => {
+                total_shards += result["_shards"]["total"].as_u64().unwrap_or(1);
+                timed_out |= result["timed_out"].as_bool().unwrap_or(false);
+                if let Some(index_aggregations) = result.get_mut("aggregations") {
+                    aggregations.push(index_aggregations.take());
+                }
+                if let Some(hits_obj) = result.get("hits") {
+                    if let Some(hits_array) = hits_obj.get("hits").and_then(|h| h.as_array()) {
+                        all_hits.extend(hits_array.iter().cloned());
+                    }
+                    if let Some(index_max) = hits_obj.get("max_score").and_then(|s| s.as_f64()) {
+                        max_score =
+                            Some(max_score.map_or(index_max, |max: f64| max.max(index_max)));
+                    }
+                    if let Some(total_obj) = hits_obj.get("total") {
+                        if let Some(total_val) = total_obj.get("value").and_then(|v| v.as_u64()) {
+                            total += total_val as usize;
+                            hits.total_hits = total_val;
                         }
+                        total_is_lower_bound |= total_obj["relation"] == "gte";
                     }
                 }
-                Err(e) => {
-                    debug!("Error searching index '{}': {}", index_name, e);
-                    // Continue with other indices
-                }
+            }
+            Err(e) => {
+                debug!("Error searching index '{}': {}", index_name, e);
+                // Continue with other indices
+                let shards = state
+                    .storage
+                    .number_of_shards(index_name)
+                    .await
+                    .unwrap_or(1);
+                total_shards += shards;
+                failed_shards += shards;
+                failures.push(serde_json::json!({
+                    "shard": 0,
+                    "index": index_name,
+                    "node": state.node.id,
+                    "reason": { "type": e.error_type(), "reason": e.reason() }
+                }));
+                hits.error = Some(e.to_string());
             }
         }
+        contributions.push(hits);
     }
 
-    // Sort all hits by score (descending)
+    // Merge by score (descending), then by the requested sort; ties keep the
+    // order of the indices
     all_hits.sort_by(|a, b| {
         let score_a = a.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0);
         let score_b = b.get("_score").and_then(|s| s.as_f64()).unwrap_or(0.0);
-        score_b.partial_cmp(&score_a).unwrap_or(std::cmp::Ordering::Equal)
+        score_b
+            .partial_cmp(&score_a)
+            .unwrap_or(std::cmp::Ordering::Equal)
     });
+    if !sort_clauses.is_empty() {
+        all_hits.sort_by(|a, b| {
+            compare_sort_values(hit_sort_values(a), hit_sort_values(b), &sort_clauses)
+        });
+    }
 
     // Apply pagination to combined results
-    let from_val = from.unwrap_or(0) as usize;
-    let size_val = size.unwrap_or(10) as usize;
-    let paginated_hits: Vec<_> = all_hits.into_iter()
-        .skip(from_val)
-        .take(size_val)
-        .collect();
+    let paginated_hits: Vec<_> = all_hits.into_iter().skip(from_val).take(size_val).collect();
 
-    let max_score = paginated_hits.first()
-        .and_then(|h| h.get("_score").and_then(|s| s.as_f64()));
+    for hits in &mut contributions {
+        hits.returned_hits = paginated_hits
+            .iter()
+            .filter(|hit| hit.get("_index").and_then(|i| i.as_str()) == Some(hits.index.as_str()))
+            .count();
+    }
 
-    Ok(Json(serde_json::json!({
-        "took": 0,
-        "timed_out": false,
+    let mut result = serde_json::json!({
+        "took": start_time.elapsed().as_millis() as u64,
+        "timed_out": timed_out,
         "_shards": {
-            "total": 1,
-            "successful": 1,
+            "total": total_shards,
+            "successful": total_shards - failed_shards,
             "skipped": 0,
-            "failed": 0
+            "failed": failed_shards
         },
         "hits": {
             "total": {
                 "value": total,
-                "relation": "eq"
+                "relation": if timed_out || total_is_lower_bound { "gte" } else { "eq" }
             },
             "max_score": max_score,
             "hits": paginated_hits
         }
+    });
+    if !failures.is_empty() {
+        result["_shards"]["failures"] = serde_json::Value::Array(failures);
+    }
+    if let Some(aggs) = options.aggs {
+        let sections: Vec<&serde_json::Value> = aggregations.iter().collect();
+        result["aggregations"] = merge_aggregations(aggs, &sections)?;
+    }
+    Ok((result, contributions))
+}
+
+/// Time budget of a search: its `timeout`, given in the body or as a
+/// parameter, or the server's default
+///
+/// A `timeout` of `-1` disables the default.
+fn search_timeout(
+    state: &AppState,
+    params: &HashMap<String, String>,
+    options: &SearchOptions<'_>,
+) -> Result<Option<Duration>> {
+    let value = match options.timeout {
+        Some(value) => value.clone(),
+        None => match params.get("timeout") {
+            Some(value) => serde_json::json!(value),
+            // The `search.default_search_timeout` cluster setting overrides
+            // the configured default
+            None => {
+                return Ok(
+                    match state.storage.cluster_settings().default_search_timeout() {
+                        Some(timeout) => timeout,
+                        None => state.search().default_timeout_ms.map(Duration::from_millis),
+                    },
+                )
+            }
+        },
+    };
+    if value == "-1" || value == -1 {
+        return Ok(None);
+    }
+    time_value_millis(&value)
+        .map(|millis| Some(Duration::from_millis(millis)))
+        .ok_or_else(|| {
+            GbsError::InvalidRequest(format!(
+                "Invalid search [timeout] {}, expected a time value like [500ms]",
+                value
+            ))
+        })
+}
+
+/// Reject searches paging past the `search.max_result_window` cluster setting
+/// or past the hits a search can return at all
+fn check_result_window(state: &AppState, options: &SearchOptions<'_>) -> Result<()> {
+    let window = u64::from(options.window()?);
+    let Some(max_window) = state.storage.cluster_settings().max_result_window() else {
+        return Ok(());
+    };
+    if window > max_window {
+        return Err(GbsError::IllegalArgument(format!(
+            "Result window is too large, from + size must be less than or equal to: [{}] but was [{}]",
+            max_window, window
+        )));
+    }
+    Ok(())
+}
+
+/// The `sort` values a hit was returned with
+fn hit_sort_values(hit: &serde_json::Value) -> &[serde_json::Value] {
+    hit.get("sort")
+        .and_then(|v| v.as_array())
+        .map_or(&[], Vec::as_slice)
+}
+
+/// Shards a search on an index expression runs on (`GET|POST /_search_shards`,
+/// `GET|POST /{index}/_search_shards`)
+///
+/// Every index lists its `number_of_shards` primaries, all started on the
+/// local node.
+pub async fn search_shards(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Search shards for: {}", expression);
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    let node = &state.node;
+    let mut indices = serde_json::Map::new();
+    let mut shards = Vec::new();
+    for stats in state.storage.get_index_stats().await {
+        if !targets.contains(&stats.name) {
+            continue;
+        }
+        if stats.state == IndexState::Close {
+            return Err(GbsError::IndexClosed(stats.name));
+        }
+        for shard in 0..stats.primaries {
+            // Each shard is a group of copies; there are no replicas
+            shards.push(serde_json::json!([{
+                "state": "STARTED",
+                "primary": true,
+                "node": node.id,
+                "relocating_node": null,
+                "shard": shard,
+                "index": stats.name,
+                "allocation_id": { "id": format!("{}-{}-{}", node.id, stats.name, shard) }
+            }]));
+        }
+        indices.insert(stats.name, serde_json::json!({}));
+    }
+
+    Ok(Json(serde_json::json!({
+        "nodes": {
+            node.id.clone(): {
+                "name": node.name,
+                "ephemeral_id": node.id,
+                "transport_address": node.publish_address().to_string(),
+                "attributes": {}
+            }
+        },
+        "indices": indices,
+        "shards": shards
     })))
 }
+
+/// Check a query without running it (`GET|POST /_validate/query`,
+/// `GET|POST /{index}/_validate/query`)
+///
+/// The query is taken from the body or the `q` parameter (default
+/// `match_all`) and parsed as a search would parse it. An invalid query is not
+/// an error: the response has `"valid": false` and the reason in `error`.
+/// With `explain=true`, each index explains a valid query by the query it runs
+/// as, with query strings translated to the query DSL.
+pub async fn validate_query(
+    State(state): State<AppState>,
+    index: Option<Path<String>>,
+    Query(params): Query<HashMap<String, String>>,
+    body: Option<Json<serde_json::Value>>,
+) -> Result<Json<serde_json::Value>> {
+    let expression = index.map_or_else(|| "_all".to_string(), |Path(index)| index);
+    info!("Validate query for: {}", expression);
+
+    let targets = state.storage.resolve_index_expression(&expression).await?;
+    let mut shards = 0;
+    for name in &targets {
+        shards += state.storage.number_of_shards(name).await?;
+    }
+
+    let query = match (query_string_param(&params), body) {
+        (Some(query), _) => Ok(query),
+        (None, Some(Json(body))) => query_to_validate(body),
+        (None, None) => Ok(serde_json::json!({ "match_all": {} })),
+    };
+    let executed = query.and_then(|query| {
+        QueryAst::parse(&query)?;
+        expand_query_strings(&query)
+    });
+
+    let mut response = serde_json::json!({
+        "_shards": { "total": shards, "successful": shards, "failed": 0 },
+        "valid": executed.is_ok()
+    });
+    let explain = params.get("explain").is_some_and(|v| v == "true");
+    match executed {
+        Ok(executed) if explain => {
+            let explanation = executed.to_string();
+            response["explanations"] = targets
+                .iter()
+                .map(|index| {
+                    serde_json::json!({
+                        "index": index,
+                        "valid": true,
+                        "explanation": explanation
+                    })
+                })
+                .collect();
+        }
+        Ok(_) => {}
+        Err(e) => {
+            debug!("Invalid query: {}", e);
+            response["error"] = serde_json::json!(e.to_string());
+        }
+    }
+    Ok(Json(response))
+}
+
+/// Query of a validate request body, which has no other keys
+fn query_to_validate(body: serde_json::Value) -> Result<serde_json::Value> {
+    let serde_json::Value::Object(mut body) = body else {
+        return Err(GbsError::InvalidRequest(
+            "request body must be an object".to_string(),
+        ));
+    };
+    let query = body
+        .remove("query")
+        .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+    match body.keys().next() {
+        Some(key) => Err(GbsError::InvalidRequest(format!(
+            "request does not support [{}]",
+            key
+        ))),
+        None => Ok(query),
+    }
+}
@@ -0,0 +1,60 @@
+//! Search profile handlers
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use tracing::info;
+
+use crate::error::Result;
+use crate::server::AppState;
+use crate::storage::SearchProfile;
+
+pub async fn put_search_profile(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+    body: Json<serde_json::Value>,
+) -> Result<(StatusCode, Json<serde_json::Value>)> {
+    info!("Storing search profile '{}' on index '{}'", name, index);
+    let profile = SearchProfile::from_json(&body)?;
+    let created = state
+        .storage
+        .put_search_profile(&index, &name, profile)
+        .await?;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((
+        status,
+        Json(serde_json::json!({ "acknowledged": true, "created": created })),
+    ))
+}
+
+pub async fn get_search_profiles(
+    State(state): State<AppState>,
+    Path(index): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    let profiles = state.storage.get_search_profiles(&index).await?;
+    Ok(Json(serde_json::to_value(profiles)?))
+}
+
+pub async fn get_search_profile(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    let profile = state.storage.get_search_profile(&index, &name).await?;
+    Ok(Json(serde_json::json!({ name: profile })))
+}
+
+pub async fn delete_search_profile(
+    State(state): State<AppState>,
+    Path((index, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Deleting search profile '{}' from index '{}'", name, index);
+    state.storage.delete_search_profile(&index, &name).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
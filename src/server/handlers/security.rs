@@ -0,0 +1,167 @@
+//! Security handlers (authenticate, native user and API key management)
+
+use axum::{
+    extract::{Extension, Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json, Response},
+};
+use std::collections::HashMap;
+use tracing::{debug, info};
+
+use crate::auth::{encode_api_key, User};
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+
+pub async fn authenticate(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<serde_json::Value>> {
+    debug!("Authenticating request credentials");
+    let (realm_name, realm_type) = if state.auth.is_enabled() {
+        ("default_native", "native")
+    } else {
+        ("__anonymous", "anonymous")
+    };
+    Ok(Json(user.to_authenticate_json(realm_name, realm_type)))
+}
+
+pub async fn get_users(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<serde_json::Value>> {
+    user.authorize_admin()?;
+
+    let mut result = serde_json::Map::new();
+    for user in state.auth.list_users().await {
+        result.insert(user.username.clone(), user.to_json());
+    }
+    Ok(Json(serde_json::Value::Object(result)))
+}
+
+pub async fn get_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Extension(user): Extension<User>,
+) -> Result<Response> {
+    user.authorize_admin()?;
+
+    // Comma-separated list of usernames is allowed, like in Elasticsearch
+    let mut result = serde_json::Map::new();
+    for name in username.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+        if let Some(user) = state.auth.get_user(name).await {
+            result.insert(user.username.clone(), user.to_json());
+        }
+    }
+
+    if result.is_empty() {
+        return Ok((StatusCode::NOT_FOUND, Json(serde_json::json!({}))).into_response());
+    }
+    Ok(Json(serde_json::Value::Object(result)).into_response())
+}
+
+pub async fn put_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Extension(user): Extension<User>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    user.authorize_admin()?;
+
+    info!("Creating or updating user: {}", username);
+    let created = state.auth.put_user(&username, &body.0).await?;
+    Ok(Json(serde_json::json!({ "created": created })))
+}
+
+pub async fn delete_user(
+    State(state): State<AppState>,
+    Path(username): Path<String>,
+    Extension(user): Extension<User>,
+) -> Result<Response> {
+    user.authorize_admin()?;
+
+    info!("Deleting user: {}", username);
+    let found = state.auth.delete_user(&username).await?;
+    let status = if found {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    };
+    Ok((status, Json(serde_json::json!({ "found": found }))).into_response())
+}
+
+/// Create an API key owned by the authenticated user
+pub async fn create_api_key(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let (api_key, key) = state.auth.create_api_key(&user, &body.0).await?;
+    let mut response = serde_json::json!({
+        "id": api_key.id,
+        "name": api_key.name,
+        "api_key": key,
+        "encoded": encode_api_key(&api_key.id, &key)
+    });
+    if let Some(expiration) = api_key.expiration {
+        response["expiration"] = serde_json::json!(expiration);
+    }
+    Ok(Json(response))
+}
+
+/// Get API keys by `id`, `name` or `owner`
+pub async fn get_api_keys(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+    Extension(user): Extension<User>,
+) -> Result<Json<serde_json::Value>> {
+    let owned = params.get("owner").is_some_and(|owner| owner == "true");
+    let api_keys = state
+        .auth
+        .get_api_keys(
+            &user,
+            params.get("id").map(String::as_str),
+            params.get("name").map(String::as_str),
+            owned,
+        )
+        .await;
+    let api_keys: Vec<serde_json::Value> =
+        api_keys.iter().map(|api_key| api_key.to_json()).collect();
+    Ok(Json(serde_json::json!({ "api_keys": api_keys })))
+}
+
+/// Invalidate API keys by `ids` (or `id`), `name` or `owner`
+pub async fn invalidate_api_keys(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    let mut ids: Vec<String> = body
+        .get("ids")
+        .and_then(|v| v.as_array())
+        .map(|ids| {
+            ids.iter()
+                .filter_map(|id| id.as_str().map(|s| s.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(id) = body.get("id").and_then(|v| v.as_str()) {
+        ids.push(id.to_string());
+    }
+    let name = body.get("name").and_then(|v| v.as_str());
+    let owned = body.get("owner").and_then(|v| v.as_bool()).unwrap_or(false);
+    if ids.is_empty() && name.is_none() && !owned {
+        return Err(GbsError::InvalidRequest(
+            "One of [ids, name, owner] must be specified".to_string(),
+        ));
+    }
+
+    let (invalidated, previously_invalidated) = state
+        .auth
+        .invalidate_api_keys(&user, &ids, name, owned)
+        .await?;
+    Ok(Json(serde_json::json!({
+        "invalidated_api_keys": invalidated,
+        "previously_invalidated_api_keys": previously_invalidated,
+        "error_count": 0
+    })))
+}
@@ -0,0 +1,192 @@
+//! Task management handlers (`/_tasks`)
+
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::future::Future;
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::server::handlers::cluster::node_roles;
+use crate::server::AppState;
+use crate::tasks::{Task, TaskHandle};
+
+/// Run an operation as a task
+///
+/// The operation runs in the background either way, so it completes even if
+/// the client goes away. Its response is returned once it completes, or, with
+/// `wait_for_completion=false`, the task ID is returned right away and the
+/// outcome is kept for `GET /_tasks/{id}`.
+pub(crate) async fn run_as_task<T, F>(
+    state: &AppState,
+    params: &HashMap<String, String>,
+    action: &str,
+    description: String,
+    cancellable: bool,
+    operation: impl FnOnce(TaskHandle) -> F,
+) -> Result<Json<serde_json::Value>>
+where
+    T: Serialize,
+    F: Future<Output = Result<T>> + Send + 'static,
+{
+    let wait_for_completion = params
+        .get("wait_for_completion")
+        .is_none_or(|wait| wait != "false");
+    let (id, handle) = state.tasks.register(action, description, cancellable);
+    debug!("Started task {} ({})", id, action);
+
+    let future = operation(handle);
+    let tasks = state.tasks.clone();
+    let running = tokio::spawn(async move {
+        let result = future
+            .await
+            .and_then(|response| Ok(serde_json::to_value(response)?));
+        let outcome = (!wait_for_completion).then(|| match &result {
+            Ok(response) => Ok(response.clone()),
+            Err(e) => Err(serde_json::json!({ "type": e.error_type(), "reason": e.reason() })),
+        });
+        tasks.complete(id, outcome);
+        result
+    });
+
+    if wait_for_completion {
+        Ok(Json(running.await??))
+    } else {
+        Ok(Json(serde_json::json!({ "task": task_id(state, id) })))
+    }
+}
+
+/// Task ID as reported by the API: `{node_id}:{number}`
+fn task_id(state: &AppState, id: u64) -> String {
+    format!("{}:{}", state.node.id, id)
+}
+
+/// Parse a task ID, which must name a task of the local node
+fn parse_task_id(state: &AppState, task_id: &str) -> Result<u64> {
+    let (node, id) = task_id
+        .split_once(':')
+        .ok_or_else(|| GbsError::IllegalArgument(format!("malformed task id {}", task_id)))?;
+    let id = id
+        .parse()
+        .map_err(|_| GbsError::IllegalArgument(format!("malformed task id {}", task_id)))?;
+    if node != state.node.id {
+        return Err(GbsError::TaskNotFound(task_id.to_string()));
+    }
+    Ok(id)
+}
+
+/// A task in the format of the Elasticsearch tasks API
+fn task_json(state: &AppState, task: &Task, detailed: bool) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "node": state.node.id,
+        "id": task.id,
+        "type": "transport",
+        "action": task.action,
+        "start_time_in_millis": task.start_time_millis,
+        "running_time_in_nanos": task.running_time_nanos,
+        "cancellable": task.cancellable,
+        "headers": {}
+    });
+    if task.cancellable {
+        body["cancelled"] = serde_json::json!(task.cancelled);
+    }
+    if detailed {
+        body["description"] = serde_json::json!(task.description);
+        if let Some(status) = &task.status {
+            body["status"] = status.clone();
+        }
+    }
+    body
+}
+
+/// Tasks grouped under the local node, as returned by listing and cancelling
+fn tasks_response(state: &AppState, tasks: &[Task], detailed: bool) -> serde_json::Value {
+    if tasks.is_empty() {
+        return serde_json::json!({ "nodes": {} });
+    }
+    let publish_address = state.node.publish_address();
+    let tasks: serde_json::Map<String, serde_json::Value> = tasks
+        .iter()
+        .map(|task| (task_id(state, task.id), task_json(state, task, detailed)))
+        .collect();
+    serde_json::json!({
+        "nodes": {
+            state.node.id.clone(): {
+                "name": state.node.name,
+                "transport_address": publish_address.to_string(),
+                "host": publish_address.ip().to_string(),
+                "ip": publish_address.ip().to_string(),
+                "roles": node_roles(&state.es_version),
+                "tasks": tasks
+            }
+        }
+    })
+}
+
+/// List running tasks (`GET /_tasks`)
+///
+/// `actions` filters by action (comma-separated, `*` wildcards), and
+/// `detailed=true` adds each task's description and progress.
+pub async fn list_tasks(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Listing tasks");
+    let detailed = params.get("detailed").is_some_and(|v| v == "true");
+    let tasks = state.tasks.list(params.get("actions").map(String::as_str));
+    Ok(Json(tasks_response(&state, &tasks, detailed)))
+}
+
+/// Get a running or completed task (`GET /_tasks/{task_id}`)
+///
+/// A completed background task includes its `response`, or its `error`.
+pub async fn get_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Getting task {}", task_id);
+    let id = parse_task_id(&state, &task_id)?;
+    let result = state
+        .tasks
+        .get(id)
+        .ok_or_else(|| GbsError::TaskNotFound(task_id.clone()))?;
+
+    let mut body = serde_json::json!({
+        "completed": result.outcome.is_some(),
+        "task": task_json(&state, &result.task, true)
+    });
+    match result.outcome {
+        Some(Ok(response)) => body["response"] = response,
+        Some(Err(error)) => body["error"] = error,
+        None => {}
+    }
+    Ok(Json(body))
+}
+
+/// Cancel a task (`POST /_tasks/{task_id}/_cancel`)
+///
+/// The task stops at its next batch boundary.
+pub async fn cancel_task(
+    State(state): State<AppState>,
+    Path(task_id): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Cancelling task {}", task_id);
+    let id = parse_task_id(&state, &task_id)?;
+    let task = state.tasks.cancel(id)?;
+    Ok(Json(tasks_response(&state, &[task], true)))
+}
+
+/// Cancel every cancellable task matching `actions` (`POST /_tasks/_cancel`)
+pub async fn cancel_tasks(
+    State(state): State<AppState>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Result<Json<serde_json::Value>> {
+    info!("Cancelling tasks matching {:?}", params.get("actions"));
+    let tasks = state
+        .tasks
+        .cancel_matching(params.get("actions").map(String::as_str));
+    Ok(Json(tasks_response(&state, &tasks, true)))
+}
@@ -0,0 +1,119 @@
+//! Index template handlers (`/_template` and `/_index_template`)
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use tracing::info;
+
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+use crate::storage::{IndexTemplate, TemplateKind};
+
+pub async fn put_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    store_template(&state, TemplateKind::Legacy, &name, &body).await
+}
+
+pub async fn get_templates(State(state): State<AppState>) -> Result<Json<serde_json::Value>> {
+    find_templates(&state, TemplateKind::Legacy, "*", false)
+}
+
+pub async fn get_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    find_templates(&state, TemplateKind::Legacy, &name, true)
+}
+
+pub async fn delete_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    remove_template(&state, TemplateKind::Legacy, &name).await
+}
+
+pub async fn put_index_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+    body: Json<serde_json::Value>,
+) -> Result<Json<serde_json::Value>> {
+    store_template(&state, TemplateKind::Composable, &name, &body).await
+}
+
+pub async fn get_index_templates(State(state): State<AppState>) -> Result<Json<serde_json::Value>> {
+    find_templates(&state, TemplateKind::Composable, "*", false)
+}
+
+pub async fn get_index_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    find_templates(&state, TemplateKind::Composable, &name, true)
+}
+
+pub async fn delete_index_template(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<serde_json::Value>> {
+    remove_template(&state, TemplateKind::Composable, &name).await
+}
+
+async fn store_template(
+    state: &AppState,
+    kind: TemplateKind,
+    name: &str,
+    body: &serde_json::Value,
+) -> Result<Json<serde_json::Value>> {
+    info!("Storing {} index template '{}'", kind.as_str(), name);
+    let template = IndexTemplate::parse(kind, body)?;
+    state.storage.put_template(kind, name, template).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
+
+/// Templates whose name matches `pattern`, in the response format of the kind
+///
+/// An explicit name that matches nothing is an error.
+fn find_templates(
+    state: &AppState,
+    kind: TemplateKind,
+    pattern: &str,
+    explicit: bool,
+) -> Result<Json<serde_json::Value>> {
+    let templates = state.storage.get_templates(kind, pattern);
+    if explicit && templates.is_empty() && !pattern.contains('*') {
+        return Err(GbsError::TemplateNotFound(pattern.to_string()));
+    }
+
+    let body = match kind {
+        TemplateKind::Legacy => serde_json::Value::Object(
+            templates
+                .into_iter()
+                .map(|(name, template)| (name, template.to_json(kind)))
+                .collect(),
+        ),
+        TemplateKind::Composable => serde_json::json!({
+            "index_templates": templates
+                .into_iter()
+                .map(|(name, template)| serde_json::json!({
+                    "name": name,
+                    "index_template": template.to_json(kind),
+                }))
+                .collect::<Vec<_>>(),
+        }),
+    };
+    Ok(Json(body))
+}
+
+async fn remove_template(
+    state: &AppState,
+    kind: TemplateKind,
+    name: &str,
+) -> Result<Json<serde_json::Value>> {
+    info!("Deleting {} index template '{}'", kind.as_str(), name);
+    state.storage.delete_template(kind, name).await?;
+    Ok(Json(serde_json::json!({ "acknowledged": true })))
+}
@@ -0,0 +1,23 @@
+//! Usage accounting handlers
+
+use axum::{
+    extract::{Extension, State},
+    response::Json,
+};
+use tracing::debug;
+
+use crate::auth::User;
+use crate::error::Result;
+use crate::server::AppState;
+use crate::usage::UsageReport;
+
+/// Usage totals and history per index and API key
+pub async fn get_usage(
+    State(state): State<AppState>,
+    Extension(user): Extension<User>,
+) -> Result<Json<UsageReport>> {
+    user.authorize_admin()?;
+
+    debug!("Getting usage report");
+    Ok(Json(state.usage.report()))
+}
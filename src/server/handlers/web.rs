@@ -1,12 +1,51 @@
 //! Web interface handlers
 
 use crate::error::{GbsError, Result};
-use axum::response::Html;
+use crate::server::handlers::cluster::{es_major_minor, lucene_version};
+use crate::server::AppState;
+use axum::{
+    extract::State,
+    response::{Html, IntoResponse},
+    Json,
+};
 use std::fs;
 
 /// Handler for the root endpoint
-pub async fn root() -> &'static str {
-    "Gummy Bear Search - Elasticsearch-compatible search engine"
+///
+/// Returns the Elasticsearch product banner for the emulated version. Official
+/// clients check it before sending any other request: `version.number`, the
+/// `tagline` and `build_flavor` for 7.x, and the `X-Elastic-Product` header
+/// from 7.14.
+pub async fn root(State(state): State<AppState>) -> impl IntoResponse {
+    let (major, minor) = es_major_minor(&state.es_version);
+    let (wire_version, index_version) = match major {
+        0..=6 => ("5.6.0", "5.0.0"),
+        7 => ("6.8.0", "6.0.0-beta1"),
+        _ => ("7.17.0", "7.0.0"),
+    };
+
+    let mut version = serde_json::json!({
+        "number": state.es_version,
+        "build_hash": env!("CARGO_PKG_VERSION"),
+        "build_date": "2024-01-01T00:00:00.000Z",
+        "build_snapshot": false,
+        "lucene_version": lucene_version(&state.es_version),
+        "minimum_wire_compatibility_version": wire_version,
+        "minimum_index_compatibility_version": index_version
+    });
+    if (major, minor) >= (6, 3) {
+        version["build_flavor"] = serde_json::json!("default");
+        version["build_type"] = serde_json::json!("tar");
+    }
+
+    let body = serde_json::json!({
+        "name": state.node.name,
+        "cluster_name": "gbs",
+        "cluster_uuid": "gbs-cluster",
+        "version": version,
+        "tagline": "You Know, for Search"
+    });
+    ([("X-Elastic-Product", "Elasticsearch")], Json(body))
 }
 
 /// Handler for the web dashboard index page
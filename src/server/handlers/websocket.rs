@@ -1,18 +1,20 @@
 //! WebSocket handler for real-time updates
 
 use axum::{
-    extract::{State, ws::{WebSocketUpgrade, Message}},
+    extract::{
+        ws::{Message, WebSocketUpgrade},
+        State,
+    },
     response::Response,
 };
 use futures_util::{SinkExt, StreamExt};
-use tracing::{info, debug, error};
+use tracing::{debug, error, info};
 
+use crate::server::handlers::cluster::cluster_health_of;
 use crate::server::AppState;
+use crate::storage::NUMBER_OF_NODES;
 
-pub async fn websocket_handler(
-    ws: WebSocketUpgrade,
-    State(state): State<AppState>,
-) -> Response {
+pub async fn websocket_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> Response {
     info!("WebSocket connection requested");
     ws.on_upgrade(|socket| handle_socket(socket, state))
 }
@@ -24,14 +26,16 @@ async fn handle_socket(socket: axum::extract::ws::WebSocket, state: AppState) {
     info!("WebSocket connection established");
 
     // Send initial cluster health
+    let health = cluster_health_of(&state).await;
     let health = serde_json::json!({
         "type": "cluster_health",
         "data": {
-            "status": "green",
-            "number_of_nodes": 1,
-            "number_of_data_nodes": 1,
-            "active_primary_shards": 0,
-            "active_shards": 0,
+            "status": health.status.as_str(),
+            "number_of_nodes": NUMBER_OF_NODES,
+            "number_of_data_nodes": NUMBER_OF_NODES,
+            "active_primary_shards": health.active_primary_shards,
+            "active_shards": health.active_shards,
+            "unassigned_shards": health.unassigned_shards,
         }
     });
 
@@ -95,14 +99,16 @@ async fn handle_socket(socket: axum::extract::ws::WebSocket, state: AppState) {
             // Periodic updates
             _ = interval.tick() => {
                 // Send periodic updates
+                let health = cluster_health_of(&state).await;
                 let health = serde_json::json!({
                     "type": "cluster_health",
                     "data": {
-                        "status": "green",
-                        "number_of_nodes": 1,
-                        "number_of_data_nodes": 1,
-                        "active_primary_shards": 0,
-                        "active_shards": 0,
+                        "status": health.status.as_str(),
+                        "number_of_nodes": NUMBER_OF_NODES,
+                        "number_of_data_nodes": NUMBER_OF_NODES,
+                        "active_primary_shards": health.active_primary_shards,
+                        "active_shards": health.active_shards,
+                        "unassigned_shards": health.unassigned_shards,
                     }
                 });
 
@@ -0,0 +1,50 @@
+//! Request metrics
+//!
+//! Records every request in the metrics registry (see `crate::metrics`),
+//! labelled with its matched route rather than its path. The layer wraps
+//! authentication, so rejected requests are counted too.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::metrics::UNMATCHED_ROUTE;
+use crate::server::accounting::path_index;
+use crate::server::AppState;
+
+/// Record the route, status and latency of a request
+pub async fn record_metrics(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let index = route
+        .as_deref()
+        .and_then(|route| path_index(route, request.uri().path()));
+    let is_search = route
+        .as_deref()
+        .is_some_and(|route| route.ends_with("/_search") || route.ends_with("/_msearch"));
+
+    let start = std::time::Instant::now();
+    let response = next.run(request).await;
+
+    state.metrics.record_request(
+        &method,
+        route.as_deref().unwrap_or(UNMATCHED_ROUTE),
+        response.status().as_u16(),
+        start.elapsed(),
+    );
+    if is_search && response.status().is_success() {
+        state
+            .metrics
+            .record_search(index.as_deref().unwrap_or("_all"));
+    }
+    response
+}
@@ -0,0 +1,148 @@
+//! Request size limits
+//!
+//! Request bodies are capped by the middleware below, which checks the
+//! `Content-Length` of requests that announce it and cuts off other bodies
+//! once they exceed the limit. Both answer with a 413 error. Bulk requests
+//! are further limited in their number of actions, and single documents in
+//! their serialized size, both with 400.
+
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use http_body_util::Limited;
+
+use crate::bulk_ops::BulkAction;
+use crate::config::ServerConfig;
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+
+/// Size limits applied to incoming requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RequestLimits {
+    /// Maximum request body size in bytes
+    pub max_body_bytes: usize,
+    /// Maximum number of actions in a bulk request
+    pub max_bulk_actions: usize,
+    /// Maximum serialized size of a document in bytes
+    pub max_document_bytes: usize,
+}
+
+impl Default for RequestLimits {
+    fn default() -> Self {
+        Self::from_config(&ServerConfig::default())
+    }
+}
+
+impl RequestLimits {
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self {
+            max_body_bytes: config.max_body_bytes,
+            max_bulk_actions: config.max_bulk_actions,
+            max_document_bytes: config.max_document_bytes,
+        }
+    }
+
+    /// Check the number of actions of a bulk request
+    pub fn check_bulk_actions(&self, count: usize) -> Result<()> {
+        if count > self.max_bulk_actions {
+            return Err(GbsError::InvalidRequest(format!(
+                "Bulk request contains {} actions, more than the limit of {}",
+                count, self.max_bulk_actions
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the serialized size of a document
+    pub fn check_document(&self, document: &serde_json::Value) -> Result<()> {
+        let size = document_size(document);
+        if size > self.max_document_bytes {
+            return Err(GbsError::InvalidRequest(format!(
+                "Document of {} bytes exceeds the limit of {} bytes",
+                size, self.max_document_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check the document of a bulk action, if it has one
+    pub fn check_bulk_action(&self, action: &BulkAction) -> Result<()> {
+        match action {
+            BulkAction::Index { document, .. }
+            | BulkAction::Create { document, .. }
+            | BulkAction::Update { document, .. } => self.check_document(document),
+            BulkAction::Delete { .. } => Ok(()),
+        }
+    }
+}
+
+/// Serialized size of a document in bytes
+pub(crate) fn document_size(document: &serde_json::Value) -> usize {
+    let mut size = ByteCount(0);
+    // Writing to ByteCount cannot fail
+    let _ = serde_json::to_writer(&mut size, document);
+    size.0
+}
+
+/// Writer that only counts the bytes written to it
+struct ByteCount(usize);
+
+impl std::io::Write for ByteCount {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Reject requests whose body exceeds the limit
+///
+/// Requests announcing a larger `Content-Length` are refused before their
+/// body is read. Streamed bodies are cut off where the extractors read them,
+/// and their plain-text 413 rejection is replaced by a JSON error. The limit
+/// is read for every request, so a configuration reload changes it.
+pub async fn enforce_body_limit(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let max_body_bytes = state.limits().max_body_bytes;
+    let content_length = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    if let Some(length) = content_length.filter(|&length| length > max_body_bytes as u64) {
+        return body_too_large(Some(length), max_body_bytes);
+    }
+
+    let request = request.map(|body| Body::new(Limited::new(body, max_body_bytes)));
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return body_too_large(None, max_body_bytes);
+    }
+    response
+}
+
+fn body_too_large(length: Option<u64>, max_body_bytes: usize) -> Response {
+    let reason = match length {
+        Some(length) => format!(
+            "Request body of {} bytes exceeds the limit of {} bytes",
+            length, max_body_bytes
+        ),
+        None => format!("Request body exceeds the limit of {} bytes", max_body_bytes),
+    };
+    GbsError::PayloadTooLarge(reason).into_response()
+}
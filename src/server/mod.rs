@@ -1,19 +1,207 @@
 //! HTTP server module for Gummy Bear Search
 
+mod accounting;
+mod auditing;
+mod authentication;
+mod compat;
+mod drain;
 mod handlers;
+mod instrumentation;
+mod limits;
+mod live_config;
+mod node;
+mod proxy;
+mod rate_limit;
+mod rejections;
+mod request_id;
 mod routes;
+mod service;
+mod throttling;
+#[cfg(feature = "tls")]
+mod tls;
 
+pub use compat::{emulate_es_version, Compatibility};
+pub use drain::Shutdown;
 pub use handlers::*;
+pub use limits::RequestLimits;
+pub use live_config::{
+    spawn_reload_on_sighup, ConfigLoader, LiveConfig, LogLevelSetter, ReloadReport,
+};
+pub use node::{LocalNode, ProcessMetrics};
+pub use proxy::{Proxy, Recording};
+pub use rate_limit::{
+    RateLimit, RateLimiter, RATE_LIMIT_LIMIT_HEADER, RATE_LIMIT_REMAINING_HEADER,
+    RATE_LIMIT_RESET_HEADER,
+};
+pub use request_id::{assign_request_id, request_span, RequestId, REQUEST_ID_HEADER};
 pub use routes::create_router;
+pub use service::{GbsService, GbsServiceBuilder, RouteGroup};
+pub use throttling::{WritePermit, WriteThrottle, WriteThrottleStats};
+#[cfg(feature = "tls")]
+pub use tls::{
+    redirect_router, serve_tls, spawn_certificate_reload, tls_acceptor, CertificateResolver,
+};
 
 // Re-export create_router as create_app for backward compatibility
 pub use routes::create_router as create_app;
 
+use crate::audit::AuditLog;
+use crate::auth::AuthStore;
+use crate::config::{Config, SearchConfig};
+use crate::metrics::Metrics;
 use crate::storage::Storage;
+use crate::tasks::TaskRegistry;
+use crate::usage::UsageTracker;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub storage: Arc<Storage>,
     pub es_version: String,
+    /// Whether the shapes of the major version of `es_version` are emulated
+    pub emulate_es_version: bool,
+    pub auth: Arc<AuthStore>,
+    /// Effective configuration, including the request limits and search
+    /// settings
+    pub config: LiveConfig,
+    pub usage: Arc<UsageTracker>,
+    pub node: Arc<LocalNode>,
+    pub tasks: Arc<TaskRegistry>,
+    pub metrics: Arc<Metrics>,
+    pub proxy: Arc<Proxy>,
+    pub shutdown: Arc<Shutdown>,
+    pub audit: Arc<AuditLog>,
+    /// Write requests served and queued
+    pub writes: Arc<WriteThrottle>,
+    /// Request rates of clients, if they are limited
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+}
+
+impl AppState {
+    /// Create application state with security disabled
+    pub fn new(storage: Arc<Storage>, es_version: impl Into<String>) -> Self {
+        Self {
+            storage,
+            es_version: es_version.into(),
+            emulate_es_version: false,
+            auth: Arc::new(AuthStore::new(false)),
+            config: LiveConfig::default(),
+            usage: Arc::new(UsageTracker::default()),
+            node: Arc::new(LocalNode::default()),
+            tasks: Arc::new(TaskRegistry::default()),
+            metrics: Arc::new(Metrics::default()),
+            proxy: Arc::new(Proxy::disabled()),
+            shutdown: Arc::new(Shutdown::default()),
+            audit: Arc::new(AuditLog::disabled()),
+            writes: Arc::new(WriteThrottle::default()),
+            rate_limiter: None,
+        }
+    }
+
+    /// Replace the auth store
+    pub fn with_auth(mut self, auth: AuthStore) -> Self {
+        self.auth = Arc::new(auth);
+        self
+    }
+
+    /// Replace the audit log
+    pub fn with_audit_log(mut self, audit: AuditLog) -> Self {
+        self.audit = Arc::new(audit);
+        self
+    }
+
+    /// Replace the effective configuration
+    ///
+    /// Only the request limits, write throttling, rate limits, version
+    /// emulation, search settings and the configuration reported by
+    /// `GET /_config` are taken from it.
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.emulate_es_version = config.emulate_es_version;
+        self.writes = Arc::new(WriteThrottle::from_config(&config.server));
+        self.rate_limiter = RateLimiter::from_config(&config.rate_limit).map(Arc::new);
+        self.config = self.config.updated(|current| *current = config);
+        self
+    }
+
+    /// Set how the configuration is loaded again by a reload
+    pub fn with_config_loader(mut self, loader: ConfigLoader) -> Self {
+        self.config = self.config.clone().with_loader(loader);
+        self
+    }
+
+    /// Set how a reload changes the log level
+    pub fn with_log_level_setter(mut self, setter: LogLevelSetter) -> Self {
+        self.config = self.config.clone().with_log_level_setter(setter);
+        self
+    }
+
+    /// Replace the request size limits
+    pub fn with_request_limits(mut self, limits: RequestLimits) -> Self {
+        self.config = self.config.updated(|config| {
+            config.server.max_body_bytes = limits.max_body_bytes;
+            config.server.max_bulk_actions = limits.max_bulk_actions;
+            config.server.max_document_bytes = limits.max_document_bytes;
+        });
+        self
+    }
+
+    /// Replace the search execution settings
+    pub fn with_search_config(mut self, search: SearchConfig) -> Self {
+        self.config = self.config.updated(|config| config.search = search);
+        self
+    }
+
+    /// Request size limits in effect
+    pub fn limits(&self) -> RequestLimits {
+        self.config.limits()
+    }
+
+    /// Search execution settings in effect
+    pub fn search(&self) -> SearchConfig {
+        self.config.search()
+    }
+
+    /// Emulate the request and response shapes of the major version of
+    /// `es_version`
+    pub fn with_version_emulation(mut self) -> Self {
+        self.emulate_es_version = true;
+        self
+    }
+
+    /// Request and response shapes of the emulated Elasticsearch version,
+    /// or the native 7.x shapes without emulation
+    pub fn compat(&self) -> Compatibility {
+        if self.emulate_es_version {
+            Compatibility::for_version(&self.es_version)
+        } else {
+            Compatibility::native()
+        }
+    }
+
+    /// Whether documents, hits and bulk items carry a `_type`, which 8.x
+    /// left out whether or not its other shapes are emulated
+    pub fn has_document_types(&self) -> bool {
+        Compatibility::for_version(&self.es_version).has_types()
+    }
+
+    /// Set the address the HTTP server is bound to, reported by the nodes APIs
+    pub fn with_http_address(mut self, address: SocketAddr) -> Self {
+        let mut node = (*self.node).clone();
+        node.http_address = address;
+        self.node = Arc::new(node);
+        self
+    }
+
+    /// Replace the usage tracker
+    pub fn with_usage_tracker(mut self, usage: UsageTracker) -> Self {
+        self.usage = Arc::new(usage);
+        self
+    }
+
+    /// Replace the handling of unsupported requests
+    pub fn with_proxy(mut self, proxy: Proxy) -> Self {
+        self.proxy = Arc::new(proxy);
+        self
+    }
 }
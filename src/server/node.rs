@@ -0,0 +1,173 @@
+//! Identity and runtime metrics of the local node
+//!
+//! gbs always runs as a single node. Its identity is reported by the nodes
+//! APIs, which clients probe at startup to discover the HTTP address and
+//! version of each node. Process metrics are read from the operating system
+//! (`/proc` on Linux, `getrusage`/`getrlimit` on Unix); metrics that are not
+//! available are reported as 0.
+
+use chrono::Utc;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Instant;
+
+/// Default HTTP address reported when the server address is unknown
+const DEFAULT_HTTP_ADDRESS: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 9200);
+
+/// The local node
+#[derive(Debug, Clone)]
+pub struct LocalNode {
+    /// Random ID, new for each server start
+    pub id: String,
+    pub name: String,
+    /// Address the HTTP server is bound to
+    pub http_address: SocketAddr,
+    started_at: Instant,
+    /// Start time in milliseconds since the epoch
+    pub start_time_millis: u64,
+}
+
+impl Default for LocalNode {
+    fn default() -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().simple().to_string(),
+            name: "gbs".to_string(),
+            http_address: DEFAULT_HTTP_ADDRESS,
+            started_at: Instant::now(),
+            start_time_millis: Utc::now().timestamp_millis() as u64,
+        }
+    }
+}
+
+impl LocalNode {
+    /// Address clients should use to reach the node over HTTP
+    ///
+    /// A server bound to all interfaces publishes the loopback address.
+    pub fn publish_address(&self) -> SocketAddr {
+        let mut address = self.http_address;
+        if address.ip().is_unspecified() {
+            address.set_ip(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        }
+        address
+    }
+
+    pub fn uptime_millis(&self) -> u64 {
+        self.started_at.elapsed().as_millis() as u64
+    }
+}
+
+/// Runtime metrics of the gbs process
+#[derive(Debug, Clone, Default)]
+pub struct ProcessMetrics {
+    pub pid: u32,
+    /// Resident memory
+    pub resident_bytes: u64,
+    /// Virtual memory
+    pub virtual_bytes: u64,
+    /// Peak resident memory
+    pub peak_resident_bytes: u64,
+    /// User and system CPU time
+    pub cpu_millis: u64,
+    pub open_file_descriptors: u64,
+    pub max_file_descriptors: u64,
+    pub threads: u64,
+    /// System load averages over 1, 5 and 15 minutes
+    pub load_average: [f64; 3],
+    /// Total physical memory of the machine
+    pub total_memory_bytes: u64,
+}
+
+impl ProcessMetrics {
+    /// Read the current metrics of this process
+    pub fn collect() -> Self {
+        let mut metrics = ProcessMetrics {
+            pid: std::process::id(),
+            ..Default::default()
+        };
+        read_proc(&mut metrics);
+        read_unix(&mut metrics);
+        metrics
+    }
+}
+
+/// Fill the metrics available from `/proc` (Linux)
+fn read_proc(metrics: &mut ProcessMetrics) {
+    let page_size = page_size();
+    if let Ok(statm) = std::fs::read_to_string("/proc/self/statm") {
+        let mut fields = statm
+            .split_whitespace()
+            .map(|f| f.parse::<u64>().unwrap_or(0));
+        metrics.virtual_bytes = fields.next().unwrap_or(0) * page_size;
+        metrics.resident_bytes = fields.next().unwrap_or(0) * page_size;
+    }
+    if let Ok(status) = std::fs::read_to_string("/proc/self/status") {
+        for line in status.lines() {
+            if let Some(threads) = line.strip_prefix("Threads:") {
+                metrics.threads = threads.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    if let Ok(entries) = std::fs::read_dir("/proc/self/fd") {
+        metrics.open_file_descriptors = entries.count() as u64;
+    }
+    if let Ok(meminfo) = std::fs::read_to_string("/proc/meminfo") {
+        metrics.total_memory_bytes = meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix("MemTotal:"))
+            .and_then(|total| {
+                total
+                    .trim()
+                    .trim_end_matches("kB")
+                    .trim()
+                    .parse::<u64>()
+                    .ok()
+            })
+            .map_or(0, |kb| kb * 1024);
+    }
+}
+
+#[cfg(unix)]
+fn page_size() -> u64 {
+    let size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    if size > 0 {
+        size as u64
+    } else {
+        4096
+    }
+}
+
+#[cfg(not(unix))]
+fn page_size() -> u64 {
+    4096
+}
+
+/// Fill the metrics available from the Unix process APIs
+#[cfg(unix)]
+fn read_unix(metrics: &mut ProcessMetrics) {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    if unsafe { libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) } == 0 {
+        let usage = unsafe { usage.assume_init() };
+        let millis = |time: libc::timeval| time.tv_sec as u64 * 1000 + time.tv_usec as u64 / 1000;
+        metrics.cpu_millis = millis(usage.ru_utime) + millis(usage.ru_stime);
+        // Kilobytes on Linux, bytes on macOS
+        let peak = usage.ru_maxrss as u64;
+        metrics.peak_resident_bytes = if cfg!(target_os = "macos") {
+            peak
+        } else {
+            peak * 1024
+        };
+    }
+
+    let mut limit = std::mem::MaybeUninit::<libc::rlimit>::uninit();
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr()) } == 0 {
+        let limit = unsafe { limit.assume_init() };
+        metrics.max_file_descriptors = limit.rlim_cur;
+    }
+
+    let mut load = [0.0f64; 3];
+    if unsafe { libc::getloadavg(load.as_mut_ptr(), 3) } == 3 {
+        metrics.load_average = load;
+    }
+}
+
+#[cfg(not(unix))]
+fn read_unix(_metrics: &mut ProcessMetrics) {}
@@ -0,0 +1,386 @@
+//! Proxy of unsupported requests to a real Elasticsearch cluster
+//!
+//! Requests that match no route, or a route without their method, end up in
+//! the router's fallback. Depending on the proxy mode they are answered with
+//! 404/405 as usual, forwarded to the upstream cluster (optionally recording
+//! the request/response pairs), or answered with recorded responses:
+//!
+//! ```yaml
+//! proxy:
+//!   mode: record
+//!   upstream_url: "http://localhost:9201"
+//! ```
+//!
+//! Recordings are kept as NDJSON, one request/response pair per line, and
+//! matched on method, path with query string and request body. When a
+//! request was recorded more than once, the latest response is replayed.
+
+use axum::body::{to_bytes, Body, Bytes};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use axum::response::{IntoResponse, Response};
+use http_body_util::{BodyExt, Full};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::config::{ProxyConfig, ProxyMode};
+use crate::error::{GbsError, Result};
+use crate::server::AppState;
+
+/// Headers of one connection, which are not forwarded
+const HOP_BY_HOP_HEADERS: [HeaderName; 5] = [
+    header::CONNECTION,
+    header::TRANSFER_ENCODING,
+    header::CONTENT_LENGTH,
+    header::HOST,
+    header::UPGRADE,
+];
+
+/// A recorded request/response pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Recording {
+    pub method: String,
+    /// Path with query string
+    pub path: String,
+    #[serde(default)]
+    pub request_body: String,
+    pub status: u16,
+    #[serde(default)]
+    pub content_type: Option<String>,
+    #[serde(default)]
+    pub response_body: String,
+}
+
+impl Recording {
+    fn key(&self) -> RecordingKey {
+        recording_key(&self.method, &self.path, self.request_body.as_bytes())
+    }
+}
+
+/// Method, path and normalized request body of a recording
+type RecordingKey = (String, String, String);
+
+/// Handling of unsupported requests
+#[derive(Debug, Default)]
+pub struct Proxy {
+    mode: ProxyMode,
+    upstream: Option<Uri>,
+    timeout: Duration,
+    recordings_file: Option<PathBuf>,
+    /// Recorded responses by request, loaded in replay mode
+    recordings: RwLock<HashMap<RecordingKey, Recording>>,
+    /// Serializes appends to the recordings file
+    writer: Mutex<()>,
+}
+
+impl Proxy {
+    /// Proxy in the configured mode
+    ///
+    /// Forwarding and recording need an upstream URL; replaying loads the
+    /// recordings file, which must exist.
+    pub async fn from_config(config: &ProxyConfig, recordings_file: PathBuf) -> Result<Self> {
+        let upstream = match (&config.upstream_url, config.mode) {
+            (_, ProxyMode::Off) | (_, ProxyMode::Replay) => None,
+            (Some(url), _) => Some(parse_upstream(url)?),
+            (None, mode) => {
+                return Err(GbsError::InvalidRequest(format!(
+                    "proxy.upstream_url is required in proxy mode [{}]",
+                    mode.as_str()
+                )))
+            }
+        };
+        let proxy = Self {
+            mode: config.mode,
+            upstream,
+            timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            recordings_file: Some(recordings_file),
+            recordings: RwLock::new(HashMap::new()),
+            writer: Mutex::new(()),
+        };
+        if proxy.mode == ProxyMode::Replay {
+            proxy.load_recordings().await?;
+        }
+        Ok(proxy)
+    }
+
+    /// Proxy answering unsupported requests with 404/405 (the default)
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    pub fn mode(&self) -> ProxyMode {
+        self.mode
+    }
+
+    /// Number of recordings available for replay
+    pub async fn recording_count(&self) -> usize {
+        self.recordings.read().await.len()
+    }
+
+    async fn load_recordings(&self) -> Result<()> {
+        let Some(path) = &self.recordings_file else {
+            return Ok(());
+        };
+        let content = tokio::fs::read_to_string(path).await.map_err(|e| {
+            GbsError::InvalidRequest(format!(
+                "Cannot read proxy recordings [{}]: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        let mut recordings = self.recordings.write().await;
+        for (number, line) in content.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let recording: Recording = serde_json::from_str(line).map_err(|e| {
+                GbsError::InvalidRequest(format!(
+                    "Invalid proxy recording at line {} of [{}]: {}",
+                    number + 1,
+                    path.display(),
+                    e
+                ))
+            })?;
+            recordings.insert(recording.key(), recording);
+        }
+        info!(
+            "Loaded {} proxy recordings from {}",
+            recordings.len(),
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Answer an unsupported request
+    async fn handle(
+        &self,
+        request: Request,
+        unsupported: StatusCode,
+        max_body_bytes: usize,
+    ) -> Response {
+        if self.mode == ProxyMode::Off {
+            return unsupported.into_response();
+        }
+        let (parts, body) = request.into_parts();
+        let body = match to_bytes(body, max_body_bytes).await {
+            Ok(body) => body,
+            Err(e) => return GbsError::InvalidRequest(e.to_string()).into_response(),
+        };
+        let path = parts
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str().to_string())
+            .unwrap_or_else(|| "/".to_string());
+
+        let result = match self.mode {
+            ProxyMode::Replay => self.replay(&parts.method, &path, &body).await,
+            _ => {
+                self.forward(&parts.method, &path, &parts.headers, body)
+                    .await
+            }
+        };
+        match result {
+            Ok(response) => response,
+            Err(e) => e.into_response(),
+        }
+    }
+
+    async fn replay(&self, method: &Method, path: &str, body: &[u8]) -> Result<Response> {
+        let recordings = self.recordings.read().await;
+        let recording = recordings
+            .get(&recording_key(method.as_str(), path, body))
+            .ok_or_else(|| GbsError::RecordingNotFound(format!("{} {}", method, path)))?;
+        debug!("Replaying recorded response to {} {}", method, path);
+        let mut response = Response::builder().status(recording.status);
+        if let Some(content_type) = &recording.content_type {
+            response = response.header(header::CONTENT_TYPE, content_type);
+        }
+        response
+            .body(Body::from(recording.response_body.clone()))
+            .map_err(|e| GbsError::Storage(e.to_string()))
+    }
+
+    async fn forward(
+        &self,
+        method: &Method,
+        path: &str,
+        headers: &HeaderMap,
+        body: Bytes,
+    ) -> Result<Response> {
+        let Some(upstream) = &self.upstream else {
+            return Err(GbsError::Upstream(
+                "no proxy upstream configured".to_string(),
+            ));
+        };
+        debug!("Forwarding {} {} to {}", method, path, upstream);
+        let (status, response_headers, response_body) = tokio::time::timeout(
+            self.timeout,
+            send(upstream, method, path, headers, body.clone()),
+        )
+        .await
+        .map_err(|_| {
+            GbsError::Upstream(format!(
+                "request to {} timed out after {:?}",
+                upstream, self.timeout
+            ))
+        })??;
+
+        if self.mode == ProxyMode::Record {
+            let recording = Recording {
+                method: method.to_string(),
+                path: path.to_string(),
+                request_body: String::from_utf8_lossy(&body).into_owned(),
+                status: status.as_u16(),
+                content_type: response_headers
+                    .get(header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                response_body: String::from_utf8_lossy(&response_body).into_owned(),
+            };
+            if let Err(e) = self.record(&recording).await {
+                warn!("Failed to record response to {} {}: {}", method, path, e);
+            }
+        }
+
+        let mut response = Response::builder().status(status);
+        for (name, value) in &response_headers {
+            if !HOP_BY_HOP_HEADERS.contains(name) {
+                response = response.header(name, value);
+            }
+        }
+        response
+            .body(Body::from(response_body))
+            .map_err(|e| GbsError::Upstream(e.to_string()))
+    }
+
+    async fn record(&self, recording: &Recording) -> Result<()> {
+        let Some(path) = &self.recordings_file else {
+            return Ok(());
+        };
+        let mut line = serde_json::to_string(recording)?;
+        line.push('\n');
+        let _guard = self.writer.lock().await;
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        file.flush().await?;
+        self.recordings
+            .write()
+            .await
+            .insert(recording.key(), recording.clone());
+        Ok(())
+    }
+}
+
+/// Fallback of requests that match no route
+pub async fn proxy_unmatched(State(state): State<AppState>, request: Request) -> Response {
+    state
+        .proxy
+        .handle(
+            request,
+            StatusCode::NOT_FOUND,
+            state.limits().max_body_bytes,
+        )
+        .await
+}
+
+/// Fallback of requests to a route without their method
+pub async fn proxy_method_not_allowed(State(state): State<AppState>, request: Request) -> Response {
+    state
+        .proxy
+        .handle(
+            request,
+            StatusCode::METHOD_NOT_ALLOWED,
+            state.limits().max_body_bytes,
+        )
+        .await
+}
+
+fn parse_upstream(url: &str) -> Result<Uri> {
+    let uri: Uri = url.parse().map_err(|e| {
+        GbsError::InvalidRequest(format!("Invalid proxy.upstream_url [{}]: {}", url, e))
+    })?;
+    if uri.scheme_str() != Some("http") || uri.host().is_none() {
+        return Err(GbsError::InvalidRequest(format!(
+            "Invalid proxy.upstream_url [{}]: must be an absolute http:// URL",
+            url
+        )));
+    }
+    Ok(uri)
+}
+
+/// Key of a request: JSON bodies are compared by value, not formatting
+fn recording_key(method: &str, path: &str, body: &[u8]) -> RecordingKey {
+    let body = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(json) => json.to_string(),
+        Err(_) => String::from_utf8_lossy(body).into_owned(),
+    };
+    (method.to_uppercase(), path.to_string(), body)
+}
+
+async fn send(
+    upstream: &Uri,
+    method: &Method,
+    path: &str,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, HeaderMap, Bytes)> {
+    let failed =
+        |reason: String| GbsError::Upstream(format!("request to {} failed: {}", upstream, reason));
+    let host = upstream.host().unwrap_or_default();
+    let port = upstream.port_u16().unwrap_or(80);
+    let stream = tokio::net::TcpStream::connect((host, port))
+        .await
+        .map_err(|e| failed(format!("connection failed: {}", e)))?;
+    let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Proxy connection closed with error: {}", e);
+        }
+    });
+
+    // The upstream URL may carry a path prefix
+    let prefix = upstream.path().trim_end_matches('/');
+    let mut request = hyper::Request::builder()
+        .method(method.clone())
+        .uri(format!("{}{}", prefix, path));
+    for (name, value) in headers {
+        if !HOP_BY_HOP_HEADERS.contains(name) {
+            request = request.header(name, value);
+        }
+    }
+    let authority = upstream.authority().map(|a| a.as_str()).unwrap_or(host);
+    let request = request
+        .header(
+            header::HOST,
+            HeaderValue::from_str(authority).map_err(|e| failed(e.to_string()))?,
+        )
+        .body(Full::new(body))
+        .map_err(|e| failed(e.to_string()))?;
+
+    let response = sender
+        .send_request(request)
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+    let (parts, body) = response.into_parts();
+    let body = body
+        .collect()
+        .await
+        .map_err(|e| failed(e.to_string()))?
+        .to_bytes();
+    Ok((parts.status, parts.headers, body))
+}
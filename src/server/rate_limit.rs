@@ -0,0 +1,235 @@
+//! Request rate limiting per client
+//!
+//! With `rate_limit.enabled`, every client has a token bucket of
+//! `rate_limit.burst` requests refilled at `rate_limit.requests_per_second`
+//! (see `crate::config::RateLimitConfig`). Each request takes a token; a
+//! request finding the bucket empty is rejected with 429 and a `Retry-After`
+//! header. Clients are told apart by the ID of their API key once it is
+//! authenticated, and by their IP address otherwise, so made-up credentials
+//! are counted against the address sending them. API keys are checked before
+//! the bucket is chosen, which takes a single SHA-256 hash; user passwords
+//! are only checked, with Argon2, once the address has a request left.
+//! Responses carry the state of the bucket:
+//!
+//! - `X-RateLimit-Limit`: requests of a full bucket
+//! - `X-RateLimit-Remaining`: requests left in the bucket
+//! - `X-RateLimit-Reset`: seconds until the bucket is full again
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::auth::{parse_api_key, User};
+use crate::config::RateLimitConfig;
+use crate::error::GbsError;
+use crate::server::authentication::{self, FailedAuthentication};
+use crate::server::AppState;
+
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "x-ratelimit-limit";
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+pub const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+
+/// Clients tracked at most; the buckets of idle clients are dropped first,
+/// then those of the clients whose last request is the oldest
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Token buckets of the clients of the server
+#[derive(Debug)]
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Outcome of a request against the bucket of its client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    pub allowed: bool,
+    /// Requests of a full bucket
+    pub limit: u32,
+    /// Requests left in the bucket
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset_secs: u64,
+    /// Seconds until the next request is allowed, for rejected requests
+    pub retry_after_secs: u64,
+}
+
+impl RateLimiter {
+    /// Buckets of `burst` requests (at least one) refilled at
+    /// `requests_per_second`
+    pub fn new(requests_per_second: f64, burst: u32) -> Self {
+        Self {
+            requests_per_second: requests_per_second.max(f64::MIN_POSITIVE),
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The rate limiter of a configuration, `None` unless it is enabled
+    pub fn from_config(config: &RateLimitConfig) -> Option<Self> {
+        config
+            .enabled
+            .then(|| Self::new(config.requests_per_second, config.burst))
+    }
+
+    /// Take a request from the bucket of a client
+    pub fn check(&self, client: &str) -> RateLimit {
+        self.check_at(client, Instant::now())
+    }
+
+    fn check_at(&self, client: &str, now: Instant) -> RateLimit {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= MAX_TRACKED_CLIENTS && !buckets.contains_key(client) {
+            buckets.retain(|_, bucket| self.refilled(bucket, now) < self.burst);
+            while buckets.len() >= MAX_TRACKED_CLIENTS {
+                let Some(oldest) = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(client, _)| client.clone())
+                else {
+                    break;
+                };
+                buckets.remove(&oldest);
+            }
+        }
+        let bucket = buckets.entry(client.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        let allowed = bucket.tokens >= 1.0;
+        if allowed {
+            bucket.tokens -= 1.0;
+        }
+        let seconds = |tokens: f64| (tokens / self.requests_per_second).ceil() as u64;
+        RateLimit {
+            allowed,
+            limit: self.burst as u32,
+            remaining: bucket.tokens.floor() as u32,
+            reset_secs: seconds(self.burst - bucket.tokens),
+            retry_after_secs: if allowed {
+                0
+            } else {
+                seconds(1.0 - bucket.tokens).max(1)
+            },
+        }
+    }
+
+    /// Number of clients with a bucket
+    pub fn tracked_clients(&self) -> usize {
+        self.buckets.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Tokens of a bucket once refilled for the time since its last request
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.requests_per_second).min(self.burst)
+    }
+}
+
+impl RateLimit {
+    fn add_headers(&self, headers: &mut HeaderMap) {
+        for (name, value) in [
+            (RATE_LIMIT_LIMIT_HEADER, u64::from(self.limit)),
+            (RATE_LIMIT_REMAINING_HEADER, u64::from(self.remaining)),
+            (RATE_LIMIT_RESET_HEADER, self.reset_secs),
+        ] {
+            headers.insert(name, HeaderValue::from(value));
+        }
+    }
+}
+
+/// Reject requests of clients that exceed their rate limit
+///
+/// When security is enabled the request is authenticated for the
+/// authentication layer, which is passed the user or the failure: API keys
+/// before the bucket is chosen, so that only verified keys get a bucket of
+/// their own, and other credentials once the request is allowed.
+pub async fn limit_rate(
+    State(state): State<AppState>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state.rate_limiter.clone() else {
+        return next.run(request).await;
+    };
+    let mut authenticated = false;
+    if state.auth.is_enabled() && has_api_key(request.headers()) {
+        authenticate(&state, &mut request).await;
+        authenticated = true;
+    }
+    let limit = limiter.check(&client_key(&request));
+    let mut response = if limit.allowed {
+        if state.auth.is_enabled() && !authenticated {
+            authenticate(&state, &mut request).await;
+        }
+        next.run(request).await
+    } else {
+        GbsError::Rejected {
+            reason: format!(
+                "rate limit of {} requests per second exceeded",
+                limiter.requests_per_second
+            ),
+            retry_after_secs: limit.retry_after_secs,
+        }
+        .into_response()
+    };
+    limit.add_headers(response.headers_mut());
+    response
+}
+
+/// Authenticate a request, adding the user or the failure to its extensions
+async fn authenticate(state: &AppState, request: &mut Request) {
+    match authentication::authenticate(state, request.headers()).await {
+        Ok(user) => {
+            request.extensions_mut().insert(user);
+        }
+        Err(error) => {
+            request
+                .extensions_mut()
+                .insert(FailedAuthentication(Arc::new(error)));
+        }
+    }
+}
+
+/// Whether a request presents an API key
+fn has_api_key(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_api_key)
+        .is_some()
+}
+
+/// The client a request is counted against: its authenticated API key, or
+/// its IP address
+fn client_key(request: &Request) -> String {
+    let api_key = request
+        .extensions()
+        .get::<User>()
+        .and_then(|user| user.api_key.as_ref());
+    if let Some((id, _)) = api_key {
+        return format!("api_key:{}", id);
+    }
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(address)| address.ip().to_string())
+        .unwrap_or_else(|| "unknown".to_string())
+}
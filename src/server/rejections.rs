@@ -0,0 +1,70 @@
+//! Error bodies of rejected requests
+//!
+//! Requests that extractors reject (a body that is not JSON, a missing
+//! `Content-Type`, query parameters of the wrong type) are answered by axum
+//! with a plain-text message. Clients of Elasticsearch parse every error body
+//! as an error envelope, so these messages are wrapped in one, as handler
+//! errors are.
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+/// Longest rejection message that is wrapped; rejections are one line
+const MAX_REJECTION_LENGTH: usize = 64 * 1024;
+
+/// Wrap plain-text error responses in an Elasticsearch error envelope
+///
+/// Applies to matched routes only, so responses relayed by the proxy keep
+/// their bodies. Bodies over the size limit are reported by the limit
+/// middleware, which knows the limit.
+pub async fn wrap_rejections(request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain"));
+    let is_error = status.is_client_error() || status.is_server_error();
+    if !is_error || status == StatusCode::PAYLOAD_TOO_LARGE || !is_text {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_REJECTION_LENGTH).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let reason = String::from_utf8_lossy(&bytes);
+    let cause = serde_json::json!({
+        "type": rejection_type(status),
+        "reason": reason.trim()
+    });
+    let body = serde_json::json!({
+        "error": {
+            "root_cause": [cause.clone()],
+            "type": cause["type"],
+            "reason": cause["reason"]
+        },
+        "status": status.as_u16()
+    });
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// Elasticsearch exception type of a rejection with this status
+fn rejection_type(status: StatusCode) -> &'static str {
+    match status {
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => "parse_exception",
+        StatusCode::UNSUPPORTED_MEDIA_TYPE => "media_type_header_exception",
+        _ => "exception",
+    }
+}
@@ -0,0 +1,77 @@
+//! Request correlation IDs
+//!
+//! Every request gets an ID, taken from its `X-Request-Id` header when the
+//! client sent a usable one and generated otherwise. The ID is stored in the
+//! request extensions and headers (so the proxy forwards it), recorded on the
+//! request span, so every line logged while the request is served carries it,
+//! and echoed in the `X-Request-Id` response header.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Span;
+
+/// Header carrying the request ID
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest incoming request ID that is kept
+const MAX_REQUEST_ID_LENGTH: usize = 200;
+
+/// ID of the request being served
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Assign the request its ID and echo it in the response
+pub async fn assign_request_id(mut request: Request, next: Next) -> Response {
+    let incoming = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid(id))
+        .map(str::to_string);
+    let id = incoming.unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    // Valid IDs are visible ASCII, so they always make a header value
+    let header = HeaderValue::from_str(&id).expect("request ID is a valid header value");
+
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, header.clone());
+    request.extensions_mut().insert(RequestId(id));
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    response
+}
+
+/// Span of a request, with its method, URI and ID
+///
+/// Used by the request tracing layer, which runs inside
+/// [`assign_request_id`]. The span is at INFO level so that the ID is on the
+/// lines logged at the default level.
+pub fn request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(RequestId::as_str)
+        .unwrap_or_default();
+    tracing::info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        request_id = %id,
+    )
+}
+
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.bytes().all(|byte| byte.is_ascii_graphic())
+}
@@ -1,9 +1,6 @@
-//! Bulk operation routes
+//! Bulk operation, transaction, reindex and delete-by-query routes
 
-use axum::{
-    routing::post,
-    Router,
-};
+use axum::{routing::post, Router};
 
 use crate::server::{handlers, AppState};
 
@@ -11,4 +8,7 @@ pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/:index/_bulk", post(handlers::bulk_operations))
         .route("/_bulk", post(handlers::bulk_operations))
+        .route("/:index/_txn", post(handlers::execute_transaction))
+        .route("/_reindex", post(handlers::reindex))
+        .route("/:index/_delete_by_query", post(handlers::delete_by_query))
 }
@@ -1,7 +1,7 @@
 //! Cluster management routes
 
 use axum::{
-    routing::get,
+    routing::{get, post},
     Router,
 };
 
@@ -11,6 +11,33 @@ pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/_cluster/health", get(handlers::cluster_health))
         .route("/_cluster/stats", get(handlers::cluster_stats))
+        .route(
+            "/_cluster/settings",
+            get(handlers::get_cluster_settings).put(handlers::put_cluster_settings),
+        )
         .route("/_cat/indices", get(handlers::cat_indices))
+        .route("/_cat/indices/:index", get(handlers::cat_indices))
+        .route("/_cat/health", get(handlers::cat_health))
+        .route("/_cat/count", get(handlers::cat_count))
+        .route("/_cat/count/:index", get(handlers::cat_count))
+        .route("/_cat/aliases", get(handlers::cat_aliases))
+        .route("/_cat/aliases/:name", get(handlers::cat_aliases))
+        .route("/_cat/shards", get(handlers::cat_shards))
+        .route("/_cat/shards/:index", get(handlers::cat_shards))
+        .route("/_stats", get(handlers::indices_stats))
+        .route("/_stats/:metric", get(handlers::indices_stats))
+        .route("/:index/_stats", get(handlers::indices_stats))
+        .route("/:index/_stats/:metric", get(handlers::indices_stats))
         .route("/_aliases", get(handlers::get_aliases))
+        .route("/_nodes", get(handlers::nodes_info))
+        .route("/_nodes/stats", get(handlers::nodes_stats))
+        .route("/_nodes/stats/:metrics", get(handlers::nodes_stats))
+        .route("/_nodes/:nodes", get(handlers::nodes_info))
+        .route("/_nodes/:nodes/stats", get(handlers::nodes_stats))
+        .route("/_nodes/:nodes/stats/:metrics", get(handlers::nodes_stats))
+        .route("/_nodes/:nodes/:metrics", get(handlers::nodes_info))
+        .route("/_tasks", get(handlers::list_tasks))
+        .route("/_tasks/_cancel", post(handlers::cancel_tasks))
+        .route("/_tasks/:task_id", get(handlers::get_task))
+        .route("/_tasks/:task_id/_cancel", post(handlers::cancel_task))
 }
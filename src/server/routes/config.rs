@@ -0,0 +1,14 @@
+//! Configuration routes
+
+use axum::{
+    routing::{get, post},
+    Router,
+};
+
+use crate::server::{handlers, AppState};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/_config", get(handlers::get_config))
+        .route("/_config/reload", post(handlers::reload_config))
+}
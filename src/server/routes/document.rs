@@ -1,7 +1,7 @@
 //! Document operation routes
 
 use axum::{
-    routing::{get, post, put, delete},
+    routing::{any, delete, get, head, post, put},
     Router,
 };
 
@@ -12,5 +12,17 @@ pub fn routes() -> Router<AppState> {
         .route("/:index/_doc/:id", put(handlers::index_document))
         .route("/:index/_doc/:id", get(handlers::get_document))
         .route("/:index/_doc/:id", delete(handlers::delete_document))
+        .route("/:index/_doc/:id", head(handlers::check_document))
+        .route("/:index/_source/:id", get(handlers::get_source))
+        .route("/:index/_source/:id", head(handlers::check_document))
         .route("/:index/_doc", post(handlers::create_document))
+        .route(
+            "/:index/_create/:id",
+            put(handlers::create_document_with_id).post(handlers::create_document_with_id),
+        )
+        .route("/:index/_changes", get(handlers::get_changes))
+        // Typed endpoints of Elasticsearch 6.x and 7.x; other methods and
+        // paths of unknown APIs go to the proxy
+        .route("/:index/:type/:id", any(handlers::typed_document))
+        .route("/:index/:type", any(handlers::typed_create_document))
 }
@@ -1,7 +1,7 @@
 //! Index management routes
 
 use axum::{
-    routing::{get, put, delete, head},
+    routing::{delete, get, head, post, put},
     Router,
 };
 
@@ -13,6 +13,60 @@ pub fn routes() -> Router<AppState> {
         .route("/:index", head(handlers::check_index))
         .route("/:index", get(handlers::get_index))
         .route("/:index", delete(handlers::delete_index))
-        .route("/:index/_mapping", put(handlers::update_mapping))
+        .route(
+            "/:index/_mapping",
+            get(handlers::get_mapping).put(handlers::update_mapping),
+        )
+        .route(
+            "/:index/_mapping/:type",
+            get(handlers::get_typed_mapping)
+                .put(handlers::update_typed_mapping)
+                .post(handlers::update_typed_mapping),
+        )
+        .route(
+            "/:index/_field_caps",
+            get(handlers::field_caps).post(handlers::field_caps),
+        )
+        .route(
+            "/_field_caps",
+            get(handlers::field_caps).post(handlers::field_caps),
+        )
         .route("/:index/_settings", put(handlers::update_settings))
+        .route("/:index/_alias/:name", put(handlers::put_alias))
+        .route("/:index/_alias/:name", delete(handlers::delete_alias))
+        .route("/:index/_open", post(handlers::open_index))
+        .route("/:index/_close", post(handlers::close_index))
+        .route("/:index/_freeze", post(handlers::freeze_index))
+        .route("/:index/_unfreeze", post(handlers::unfreeze_index))
+        .route("/:index/_rollover", post(handlers::rollover))
+        .route("/:index/_rollover/:new_index", post(handlers::rollover))
+        .route("/:index/_tier", get(handlers::get_index_tier))
+        .route("/:index/_tier/:tier", post(handlers::set_index_tier))
+        .route("/:index/_warmer", get(handlers::get_warmers))
+        .route("/:index/_warmer/:name", put(handlers::put_warmer))
+        .route("/:index/_warmer/:name", get(handlers::get_warmer))
+        .route("/:index/_warmer/:name", delete(handlers::delete_warmer))
+        .route("/_template", get(handlers::get_templates))
+        .route("/_template/:name", put(handlers::put_template))
+        .route("/_template/:name", get(handlers::get_template))
+        .route("/_template/:name", delete(handlers::delete_template))
+        .route("/_index_template", get(handlers::get_index_templates))
+        .route("/_index_template/:name", put(handlers::put_index_template))
+        .route("/_index_template/:name", get(handlers::get_index_template))
+        .route(
+            "/_index_template/:name",
+            delete(handlers::delete_index_template),
+        )
+        .route("/_ingest/pipeline", get(handlers::get_pipelines))
+        .route("/_ingest/pipeline/:id", put(handlers::put_pipeline))
+        .route("/_ingest/pipeline/:id", get(handlers::get_pipeline))
+        .route("/_ingest/pipeline/:id", delete(handlers::delete_pipeline))
+        .route(
+            "/_ingest/pipeline/_simulate",
+            get(handlers::simulate_pipeline).post(handlers::simulate_pipeline),
+        )
+        .route(
+            "/_ingest/pipeline/:id/_simulate",
+            get(handlers::simulate_stored_pipeline).post(handlers::simulate_stored_pipeline),
+        )
 }
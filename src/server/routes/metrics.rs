@@ -0,0 +1,9 @@
+//! Prometheus metrics routes
+
+use axum::{routing::get, Router};
+
+use crate::server::{handlers, AppState};
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/_metrics", get(handlers::get_metrics))
+}
@@ -4,31 +4,115 @@
 
 mod bulk;
 mod cluster;
+mod config;
 mod document;
 mod index;
+mod metrics;
 mod refresh;
 mod search;
+mod security;
+mod usage;
 mod web;
 mod websocket;
 
-use axum::Router;
+use axum::{extract::DefaultBodyLimit, middleware, Router};
 use tower_http::{cors::CorsLayer, services::ServeDir, trace::TraceLayer};
 
-use crate::server::AppState;
+use crate::server::{
+    accounting, auditing, authentication, compat, drain, instrumentation, limits, proxy,
+    rate_limit, rejections, request_id, throttling, AppState, GbsService, RouteGroup,
+};
 
 /// Create the main router with all routes
+///
+/// Requests are assigned their ID before the tracing layer opens their span.
+/// With `rate_limit.enabled`, requests of clients over their rate are
+/// rejected before they reach the API.
 pub fn create_router(state: AppState) -> Router {
-    Router::new()
-        .merge(web::routes())
-        .merge(cluster::routes())
-        .merge(index::routes())
-        .merge(document::routes())
-        .merge(search::routes())
-        .merge(bulk::routes())
-        .merge(refresh::routes())
-        .merge(websocket::routes())
-        .nest_service("/static", ServeDir::new("static"))
+    let rate_limited = state.rate_limiter.is_some();
+    let router = GbsService::new(state.clone()).into_router();
+    let router = if rate_limited {
+        router.layer(middleware::from_fn_with_state(
+            state,
+            rate_limit::limit_rate,
+        ))
+    } else {
+        router
+    };
+    router
         .layer(CorsLayer::permissive())
-        .layer(TraceLayer::new_for_http())
+        .layer(TraceLayer::new_for_http().make_span_with(request_id::request_span))
+        .layer(middleware::from_fn(request_id::assign_request_id))
+}
+
+/// Create a router with the routes of the given groups
+///
+/// Version emulation, request limits, usage accounting, auditing,
+/// authentication, draining and metrics apply to every group. Requests are
+/// authenticated before usage is accounted and writes are audited, so
+/// rejected requests are not counted as usage nor audited; they are counted
+/// in the metrics.
+/// Requests no route supports go to the proxy; the requests of routes that
+/// are rejected get an error envelope.
+pub(crate) fn group_router(state: AppState, groups: &[RouteGroup]) -> Router {
+    groups
+        .iter()
+        .fold(Router::new(), |router, &group| {
+            router.merge(group_routes(group))
+        })
+        .route_layer(middleware::from_fn(rejections::wrap_rejections))
+        .fallback(proxy::proxy_unmatched)
+        .method_not_allowed_fallback(proxy::proxy_method_not_allowed)
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            compat::emulate_es_version,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            limits::enforce_body_limit,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            accounting::record_usage,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            throttling::throttle_writes,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auditing::record_audit_event,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            authentication::require_authentication,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            drain::refuse_writes_while_draining,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            instrumentation::record_metrics,
+        ))
         .with_state(state)
 }
+
+fn group_routes(group: RouteGroup) -> Router<AppState> {
+    match group {
+        RouteGroup::Web => web::routes().nest_service("/static", ServeDir::new("static")),
+        RouteGroup::Cluster => cluster::routes(),
+        RouteGroup::Index => index::routes(),
+        RouteGroup::Document => document::routes(),
+        RouteGroup::Search => search::routes(),
+        RouteGroup::SearchProfile => search::profile_routes(),
+        RouteGroup::Bulk => bulk::routes(),
+        RouteGroup::Refresh => refresh::routes(),
+        RouteGroup::Security => security::routes(),
+        RouteGroup::Usage => usage::routes(),
+        RouteGroup::Config => config::routes(),
+        RouteGroup::Metrics => metrics::routes(),
+        RouteGroup::WebSocket => websocket::routes(),
+    }
+}
@@ -1,7 +1,7 @@
-//! Index refresh routes
+//! Index refresh, flush, cache, force merge and segments routes
 
 use axum::{
-    routing::post,
+    routing::{get, post},
     Router,
 };
 
@@ -11,4 +11,12 @@ pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/:index/_refresh", post(handlers::refresh_index))
         .route("/_refresh", post(handlers::refresh_all))
+        .route("/:index/_flush", post(handlers::flush))
+        .route("/_flush", post(handlers::flush))
+        .route("/:index/_cache/clear", post(handlers::clear_cache))
+        .route("/_cache/clear", post(handlers::clear_cache))
+        .route("/:index/_forcemerge", post(handlers::force_merge))
+        .route("/_forcemerge", post(handlers::force_merge))
+        .route("/:index/_segments", get(handlers::segments))
+        .route("/_segments", get(handlers::segments))
 }
@@ -1,7 +1,7 @@
 //! Search operation routes
 
 use axum::{
-    routing::{get, post},
+    routing::{any, get, post},
     Router,
 };
 
@@ -11,5 +11,73 @@ pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/:index/_search", get(handlers::search_get))
         .route("/:index/_search", post(handlers::search_post))
-        .route("/_search", post(handlers::search_multi_index))
+        .route(
+            "/_search",
+            get(handlers::search_all_get).post(handlers::search_multi_index),
+        )
+        .route("/_msearch", get(handlers::msearch).post(handlers::msearch))
+        .route(
+            "/:index/_msearch",
+            get(handlers::msearch).post(handlers::msearch),
+        )
+        .route(
+            "/:index/_search/template",
+            get(handlers::search_template).post(handlers::search_template),
+        )
+        .route(
+            "/_search/template",
+            get(handlers::search_template_all).post(handlers::search_template_all),
+        )
+        .route(
+            "/_render/template",
+            get(handlers::render_template).post(handlers::render_template),
+        )
+        .route(
+            "/_render/template/:id",
+            get(handlers::render_stored_template).post(handlers::render_stored_template),
+        )
+        .route(
+            "/_search_shards",
+            get(handlers::search_shards).post(handlers::search_shards),
+        )
+        .route(
+            "/:index/_search_shards",
+            get(handlers::search_shards).post(handlers::search_shards),
+        )
+        .route(
+            "/:index/_explain/:id",
+            get(handlers::explain).post(handlers::explain),
+        )
+        .route(
+            "/_validate/query",
+            get(handlers::validate_query).post(handlers::validate_query),
+        )
+        .route(
+            "/:index/_validate/query",
+            get(handlers::validate_query).post(handlers::validate_query),
+        )
+        // Typed search of Elasticsearch 6.x and 7.x
+        .route("/:index/:type/_search", any(handlers::typed_search))
+}
+
+/// Search profile and stored script management routes
+pub fn profile_routes() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/_scripts/:id",
+            get(handlers::get_script)
+                .put(handlers::put_script)
+                .post(handlers::put_script)
+                .delete(handlers::delete_script),
+        )
+        .route(
+            "/:index/_search_profile",
+            get(handlers::get_search_profiles),
+        )
+        .route(
+            "/:index/_search_profile/:name",
+            get(handlers::get_search_profile)
+                .put(handlers::put_search_profile)
+                .delete(handlers::delete_search_profile),
+        )
 }
@@ -0,0 +1,25 @@
+//! Security routes
+
+use axum::{routing::get, Router};
+
+use crate::server::{handlers, AppState};
+
+pub fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/_security/_authenticate", get(handlers::authenticate))
+        .route("/_security/user", get(handlers::get_users))
+        .route(
+            "/_security/api_key",
+            get(handlers::get_api_keys)
+                .post(handlers::create_api_key)
+                .put(handlers::create_api_key)
+                .delete(handlers::invalidate_api_keys),
+        )
+        .route(
+            "/_security/user/:username",
+            get(handlers::get_user)
+                .put(handlers::put_user)
+                .post(handlers::put_user)
+                .delete(handlers::delete_user),
+        )
+}
@@ -0,0 +1,9 @@
+//! Usage accounting routes
+
+use axum::{routing::get, Router};
+
+use crate::server::{handlers, AppState};
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/_gbs/usage", get(handlers::get_usage))
+}
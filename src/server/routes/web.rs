@@ -1,11 +1,11 @@
 //! Web interface routes
 
-use axum::{
-    routing::get,
-    Router,
-};
+use axum::{routing::get, Router};
 
-use crate::server::{handlers::web::{root, web_index}, AppState};
+use crate::server::{
+    handlers::web::{root, web_index},
+    AppState,
+};
 
 pub fn routes() -> Router<AppState> {
     Router::new()
@@ -1,13 +1,9 @@
 //! WebSocket routes
 
-use axum::{
-    routing::get,
-    Router,
-};
+use axum::{routing::get, Router};
 
 use crate::server::{handlers, AppState};
 
 pub fn routes() -> Router<AppState> {
-    Router::new()
-        .route("/_ws", get(handlers::websocket_handler))
+    Router::new().route("/_ws", get(handlers::websocket_handler))
 }
@@ -0,0 +1,200 @@
+//! Embedding Gummy Bear Search in an axum application
+//!
+//! `GbsService` is the API as a router or tower service without a server of
+//! its own: it runs on the host application's runtime and serves the storage
+//! of the `AppState` it is built from, which the host can keep using
+//! directly. The builder selects the route groups that get mounted, e.g.
+//! search only, or everything but the administrative APIs.
+//!
+//! ```no_run
+//! # use std::sync::Arc;
+//! # use gbs::server::{AppState, GbsService};
+//! # use gbs::storage::Storage;
+//! let storage = Arc::new(Storage::new());
+//! let search = GbsService::builder(AppState::new(storage.clone(), "6.8.23"))
+//!     .search_only()
+//!     .build();
+//! let app: axum::Router = axum::Router::new().nest("/search", search.into_router());
+//! ```
+
+use axum::{extract::Request, response::Response, routing::future::RouteFuture, Router};
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+use crate::server::routes::group_router;
+use crate::server::AppState;
+
+/// A group of related routes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RouteGroup {
+    /// Web interface and static files
+    Web,
+    /// Cluster health, stats, cat indices, aliases, nodes and tasks
+    Cluster,
+    /// Index management: create, delete, mappings, settings, aliases, tiers
+    Index,
+    /// Document CRUD
+    Document,
+    /// Search, single and multi-index
+    Search,
+    /// Search profile and stored script management
+    SearchProfile,
+    /// Bulk operations, transactions, reindex and delete by query
+    Bulk,
+    /// Index refresh
+    Refresh,
+    /// Authentication and user management
+    Security,
+    /// Usage accounting
+    Usage,
+    /// Effective configuration and its reload
+    Config,
+    /// Prometheus metrics
+    Metrics,
+    /// WebSocket connection
+    WebSocket,
+}
+
+impl RouteGroup {
+    /// All route groups
+    pub const ALL: [RouteGroup; 13] = [
+        RouteGroup::Web,
+        RouteGroup::Cluster,
+        RouteGroup::Index,
+        RouteGroup::Document,
+        RouteGroup::Search,
+        RouteGroup::SearchProfile,
+        RouteGroup::Bulk,
+        RouteGroup::Refresh,
+        RouteGroup::Security,
+        RouteGroup::Usage,
+        RouteGroup::Config,
+        RouteGroup::Metrics,
+        RouteGroup::WebSocket,
+    ];
+
+    /// Name of the group, as listed in the feature report
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RouteGroup::Web => "web",
+            RouteGroup::Cluster => "cluster",
+            RouteGroup::Index => "index",
+            RouteGroup::Document => "document",
+            RouteGroup::Search => "search",
+            RouteGroup::SearchProfile => "search_profile",
+            RouteGroup::Bulk => "bulk",
+            RouteGroup::Refresh => "refresh",
+            RouteGroup::Security => "security",
+            RouteGroup::Usage => "usage",
+            RouteGroup::Config => "config",
+            RouteGroup::Metrics => "metrics",
+            RouteGroup::WebSocket => "websocket",
+        }
+    }
+
+    /// Whether the group administers the instance rather than serving data
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            RouteGroup::Web
+                | RouteGroup::Index
+                | RouteGroup::SearchProfile
+                | RouteGroup::Security
+                | RouteGroup::Usage
+                | RouteGroup::Config
+                | RouteGroup::Metrics
+        )
+    }
+}
+
+/// The Gummy Bear Search API as a router or tower service
+#[derive(Clone)]
+pub struct GbsService {
+    router: Router,
+}
+
+impl GbsService {
+    /// Service with all route groups
+    pub fn new(state: AppState) -> Self {
+        Self::builder(state).build()
+    }
+
+    /// Builder selecting the route groups to mount
+    pub fn builder(state: AppState) -> GbsServiceBuilder {
+        GbsServiceBuilder {
+            state,
+            groups: RouteGroup::ALL.to_vec(),
+        }
+    }
+
+    /// Router to nest or merge into an application router of any state
+    pub fn into_router<S>(self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.router.with_state(())
+    }
+}
+
+impl tower::Service<Request> for GbsService {
+    type Response = Response;
+    type Error = Infallible;
+    type Future = RouteFuture<Infallible>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        tower::Service::<Request>::poll_ready(&mut self.router, cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.router.call(request)
+    }
+}
+
+/// Builder of a `GbsService`
+#[derive(Clone)]
+pub struct GbsServiceBuilder {
+    state: AppState,
+    groups: Vec<RouteGroup>,
+}
+
+impl GbsServiceBuilder {
+    /// Mount exactly the given route groups
+    pub fn route_groups(mut self, groups: impl IntoIterator<Item = RouteGroup>) -> Self {
+        self.groups = Vec::new();
+        for group in groups {
+            self = self.include(group);
+        }
+        self
+    }
+
+    /// Mount a route group
+    pub fn include(mut self, group: RouteGroup) -> Self {
+        if !self.groups.contains(&group) {
+            self.groups.push(group);
+        }
+        self
+    }
+
+    /// Leave a route group unmounted
+    pub fn exclude(mut self, group: RouteGroup) -> Self {
+        self.groups.retain(|&g| g != group);
+        self
+    }
+
+    /// Mount only the search routes
+    pub fn search_only(self) -> Self {
+        self.route_groups([RouteGroup::Search])
+    }
+
+    /// Leave the administrative route groups unmounted
+    pub fn without_admin(mut self) -> Self {
+        self.groups.retain(|group| !group.is_admin());
+        self
+    }
+
+    pub fn build(self) -> GbsService {
+        GbsService {
+            router: group_router(self.state, &self.groups),
+        }
+    }
+}
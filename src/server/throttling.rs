@@ -0,0 +1,183 @@
+//! Write throttling
+//!
+//! Like the write thread pool of Elasticsearch, at most
+//! `server.max_concurrent_writes` write requests are served at once. Further
+//! writes wait in a queue of `server.write_queue_size` requests; once the
+//! queue is full, writes are rejected with 429 and a `Retry-After` header
+//! instead of piling up in memory. Reads are not throttled. The pool is
+//! reported as the `write` thread pool of `GET /_nodes/stats`.
+
+use axum::{
+    extract::{MatchedPath, Request, State},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use tokio::sync::{Semaphore, SemaphorePermit, TryAcquireError};
+
+use crate::config::ServerConfig;
+use crate::error::{GbsError, Result};
+use crate::server::drain::is_write;
+use crate::server::AppState;
+
+/// Seconds a client is asked to wait before retrying a rejected write
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Limits the write requests served at once and queued
+#[derive(Debug)]
+pub struct WriteThrottle {
+    permits: Semaphore,
+    max_concurrent: usize,
+    queue_size: usize,
+    queued: AtomicUsize,
+    largest: AtomicUsize,
+    rejected: AtomicU64,
+    completed: AtomicU64,
+}
+
+/// Statistics of the write pool, in the shape of an Elasticsearch thread pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct WriteThrottleStats {
+    /// Writes served at once at most
+    pub threads: usize,
+    /// Writes waiting to be served
+    pub queue: usize,
+    /// Writes being served
+    pub active: usize,
+    /// Writes rejected because the queue was full
+    pub rejected: u64,
+    /// Most writes served at once so far
+    pub largest: usize,
+    /// Writes served
+    pub completed: u64,
+}
+
+impl Default for WriteThrottle {
+    fn default() -> Self {
+        Self::from_config(&ServerConfig::default())
+    }
+}
+
+impl WriteThrottle {
+    /// Serve `max_concurrent` writes at once (at least one) and queue up to
+    /// `queue_size` more
+    pub fn new(max_concurrent: usize, queue_size: usize) -> Self {
+        let max_concurrent = max_concurrent.max(1);
+        Self {
+            permits: Semaphore::new(max_concurrent),
+            max_concurrent,
+            queue_size,
+            queued: AtomicUsize::new(0),
+            largest: AtomicUsize::new(0),
+            rejected: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+        }
+    }
+
+    pub fn from_config(config: &ServerConfig) -> Self {
+        Self::new(config.max_concurrent_writes, config.write_queue_size)
+    }
+
+    /// Wait for a write to be served, or reject it if the queue is full
+    pub async fn acquire(&self) -> Result<WritePermit<'_>> {
+        let permit = match self.permits.try_acquire() {
+            Ok(permit) => permit,
+            Err(TryAcquireError::Closed) => return Err(GbsError::ShuttingDown),
+            Err(TryAcquireError::NoPermits) => {
+                let admitted = self
+                    .queued
+                    .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |queued| {
+                        (queued < self.queue_size).then_some(queued + 1)
+                    })
+                    .is_ok();
+                if !admitted {
+                    self.rejected.fetch_add(1, Ordering::Relaxed);
+                    return Err(GbsError::Rejected {
+                        reason: format!(
+                            "rejected execution of write request: {} writes in progress and {} queued",
+                            self.max_concurrent, self.queue_size
+                        ),
+                        retry_after_secs: RETRY_AFTER_SECS,
+                    });
+                }
+                // Leaves the queue even if the request is dropped while waiting
+                let _queued = Queued(&self.queued);
+                self.permits
+                    .acquire()
+                    .await
+                    .map_err(|_| GbsError::ShuttingDown)?
+            }
+        };
+        self.largest.fetch_max(self.active(), Ordering::Relaxed);
+        Ok(WritePermit {
+            _permit: permit,
+            completed: &self.completed,
+        })
+    }
+
+    /// Wait for the writes being served and queued to finish, and refuse
+    /// any further ones
+    pub async fn close(&self) {
+        if let Ok(permits) = self.permits.acquire_many(self.max_concurrent as u32).await {
+            permits.forget();
+        }
+        self.permits.close();
+    }
+
+    fn active(&self) -> usize {
+        self.max_concurrent - self.permits.available_permits()
+    }
+
+    pub fn stats(&self) -> WriteThrottleStats {
+        WriteThrottleStats {
+            threads: self.max_concurrent,
+            queue: self.queued.load(Ordering::SeqCst),
+            active: self.active(),
+            rejected: self.rejected.load(Ordering::Relaxed),
+            largest: self.largest.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A write being served; the next one is let in when it is dropped
+#[derive(Debug)]
+pub struct WritePermit<'a> {
+    _permit: SemaphorePermit<'a>,
+    completed: &'a AtomicU64,
+}
+
+impl Drop for WritePermit<'_> {
+    fn drop(&mut self) {
+        self.completed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+/// A place in the write queue
+struct Queued<'a>(&'a AtomicUsize);
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Serve writes through the write throttle
+pub async fn throttle_writes(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str());
+    if !is_write(request.method(), route) {
+        return next.run(request).await;
+    }
+    match state.writes.acquire().await {
+        Ok(_permit) => next.run(request).await,
+        Err(e) => e.into_response(),
+    }
+}
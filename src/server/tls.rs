@@ -0,0 +1,263 @@
+//! TLS termination of the HTTP listener
+//!
+//! With `server.tls`, connections are accepted over TLS with the PEM
+//! certificate chain and private key of `cert_path` and `key_path`. Both are
+//! read again every `reload_interval_secs`, so renewed certificates are
+//! served to new connections without a restart; a certificate that fails to
+//! load is logged and the previous one kept. With `redirect_port`, a plain
+//! HTTP listener answers every request with a permanent redirect to the same
+//! path over https.
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use hyper::body::Incoming;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::server::graceful::GracefulShutdown;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use std::future::Future;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+use tracing::{debug, warn};
+
+use crate::config::TlsConfig;
+use crate::error::{GbsError, Result};
+
+/// Time a client has to complete the TLS handshake once connected
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Certificate served to new TLS connections, read again on reload
+pub struct CertificateResolver {
+    cert_path: String,
+    key_path: String,
+    current: RwLock<Arc<CertifiedKey>>,
+}
+
+impl CertificateResolver {
+    /// Read the certificate chain and private key of a TLS configuration
+    pub fn load(config: &TlsConfig) -> Result<Self> {
+        let key = load_certified_key(&config.cert_path, &config.key_path)?;
+        Ok(Self {
+            cert_path: config.cert_path.clone(),
+            key_path: config.key_path.clone(),
+            current: RwLock::new(Arc::new(key)),
+        })
+    }
+
+    /// Read the certificate chain and private key again
+    ///
+    /// On failure the previous certificate keeps being served.
+    pub fn reload(&self) -> Result<()> {
+        let key = load_certified_key(&self.cert_path, &self.key_path)?;
+        *self.current.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(key);
+        Ok(())
+    }
+
+    fn current(&self) -> Arc<CertifiedKey> {
+        self.current
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current())
+    }
+}
+
+impl std::fmt::Debug for CertificateResolver {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CertificateResolver")
+            .field("cert_path", &self.cert_path)
+            .field("key_path", &self.key_path)
+            .finish()
+    }
+}
+
+/// Read a PEM certificate chain and private key
+fn load_certified_key(cert_path: &str, key_path: &str) -> Result<CertifiedKey> {
+    let invalid = |what: &str, path: &str, reason: String| {
+        GbsError::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("Failed to load TLS {} {}: {}", what, path, reason),
+        ))
+    };
+    let read = |what: &str, path: &str| {
+        std::fs::read(path).map_err(|e| {
+            GbsError::Io(std::io::Error::new(
+                e.kind(),
+                format!("Failed to read TLS {} {}: {}", what, path, e),
+            ))
+        })
+    };
+    let certs = rustls_pemfile::certs(&mut read("certificate", cert_path)?.as_slice())
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| invalid("certificate", cert_path, e.to_string()))?;
+    if certs.is_empty() {
+        return Err(invalid(
+            "certificate",
+            cert_path,
+            "no certificate found".to_string(),
+        ));
+    }
+    let key = rustls_pemfile::private_key(&mut read("key", key_path)?.as_slice())
+        .map_err(|e| invalid("key", key_path, e.to_string()))?
+        .ok_or_else(|| invalid("key", key_path, "no private key found".to_string()))?;
+    let signing_key = rustls::crypto::ring::sign::any_supported_type(&key)
+        .map_err(|e| invalid("key", key_path, e.to_string()))?;
+    Ok(CertifiedKey::new(certs, signing_key))
+}
+
+/// Acceptor of TLS connections serving the resolver's current certificate
+pub fn tls_acceptor(resolver: Arc<CertificateResolver>) -> Result<TlsAcceptor> {
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|e| {
+        GbsError::Io(std::io::Error::other(format!(
+            "Invalid TLS configuration: {}",
+            e
+        )))
+    })?
+    .with_no_client_auth()
+    .with_cert_resolver(resolver);
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Read the certificate again every `interval`
+pub fn spawn_certificate_reload(
+    resolver: Arc<CertificateResolver>,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match resolver.reload() {
+                Ok(()) => debug!("Reloaded TLS certificate {}", resolver.cert_path),
+                Err(e) => warn!("Keeping the current TLS certificate: {}", e),
+            }
+        }
+    })
+}
+
+/// Serve an app over TLS until `signal` completes, then wait for the open
+/// connections to finish
+///
+/// Client addresses are added to the request extensions as
+/// `ConnectInfo<SocketAddr>`, as by `into_make_service_with_connect_info`.
+/// Connections that do not complete the TLS handshake within
+/// `HANDSHAKE_TIMEOUT` are closed.
+pub async fn serve_tls(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    app: Router,
+    signal: impl Future<Output = ()>,
+) -> std::io::Result<()> {
+    let graceful = GracefulShutdown::new();
+    // Pending handshakes are abandoned on shutdown rather than served
+    let (shutdown, _) = tokio::sync::watch::channel(false);
+    tokio::pin!(signal);
+    loop {
+        let (stream, address) = tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok(accepted) => accepted,
+                Err(e) => {
+                    warn!("Failed to accept connection: {}", e);
+                    continue;
+                }
+            },
+            _ = &mut signal => break,
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        // Taken before the handshake so shutdown waits for it too
+        let watcher = graceful.watcher();
+        let mut shutting_down = shutdown.subscribe();
+        tokio::spawn(async move {
+            let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+            let stream = tokio::select! {
+                handshake = handshake => match handshake {
+                    Ok(Ok(stream)) => stream,
+                    Ok(Err(e)) => {
+                        debug!("TLS handshake with {} failed: {}", address, e);
+                        return;
+                    }
+                    Err(_) => {
+                        debug!("TLS handshake with {} timed out", address);
+                        return;
+                    }
+                },
+                _ = shutting_down.wait_for(|shutting_down| *shutting_down) => return,
+            };
+            let service = hyper::service::service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(address));
+                // Routers are always ready
+                app.clone().call(request)
+            });
+            let builder = auto::Builder::new(TokioExecutor::new());
+            let connection = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            if let Err(e) = watcher.watch(connection.into_owned()).await {
+                debug!("Connection with {} closed: {}", address, e);
+            }
+        });
+    }
+    drop(listener);
+    shutdown.send_replace(true);
+    graceful.shutdown().await;
+    Ok(())
+}
+
+/// App of the plain HTTP listener, redirecting every request to https on
+/// `https_port`
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new()
+        .fallback(move |request: Request| async move { redirect_to_https(&request, https_port) })
+}
+
+fn redirect_to_https(request: &Request, https_port: u16) -> Response {
+    let Some(host) = request
+        .headers()
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|host| host.parse::<axum::http::uri::Authority>().ok())
+    else {
+        return (StatusCode::BAD_REQUEST, "missing Host header").into_response();
+    };
+    let authority = if https_port == 443 {
+        host.host().to_string()
+    } else {
+        format!("{}:{}", host.host(), https_port)
+    };
+    let path = request
+        .uri()
+        .path_and_query()
+        .map(|path| path.as_str())
+        .unwrap_or("/");
+    match Uri::builder()
+        .scheme("https")
+        .authority(authority)
+        .path_and_query(path)
+        .build()
+    {
+        Ok(location) => (
+            StatusCode::PERMANENT_REDIRECT,
+            [(header::LOCATION, location.to_string())],
+        )
+            .into_response(),
+        Err(_) => (StatusCode::BAD_REQUEST, "invalid Host header").into_response(),
+    }
+}
@@ -0,0 +1,380 @@
+//! Soak test mode
+//!
+//! `gbs soak` runs randomized cycles of index, delete, search, get and tier
+//! changes against a data directory, restarting the storage between cycles.
+//! After every cycle (and every restart) it checks that the documents seen
+//! through the storage layer, the per-index document counts and the documents
+//! on disk all agree with a model of what was written. New storage features
+//! are qualified by letting this run for a long time.
+//!
+//! The data directory must be given with `--data-dir`: the soak test writes
+//! and deletes documents, so it never runs against the configured directory
+//! by default.
+
+use futures_util::FutureExt;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+use crate::error::{GbsError, Result};
+use crate::storage::{IndexTier, Storage};
+
+/// Prefix of the indices created by the soak test
+pub const SOAK_INDEX_PREFIX: &str = ".gbs-soak-";
+
+/// Words the `word` field of soak documents is drawn from
+const WORDS: [&str; 6] = ["alpha", "bravo", "charlie", "delta", "echo", "foxtrot"];
+
+/// Settings of a soak run
+#[derive(Debug, Clone)]
+pub struct SoakOptions {
+    /// Data directory to run against
+    pub data_dir: PathBuf,
+    /// Number of cycles to run, 0 runs until interrupted
+    pub cycles: u64,
+    /// Random operations per cycle
+    pub ops_per_cycle: usize,
+    /// Number of soak indices
+    pub indices: usize,
+    /// Number of distinct document IDs per index
+    pub docs_per_index: usize,
+    /// Seed of the operation sequence; the same seed replays the same run
+    pub seed: u64,
+}
+
+impl SoakOptions {
+    /// Default options for a data directory, seeded from the clock
+    pub fn new(data_dir: impl Into<PathBuf>) -> Self {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self {
+            data_dir: data_dir.into(),
+            cycles: 100,
+            ops_per_cycle: 200,
+            indices: 3,
+            docs_per_index: 50,
+            seed,
+        }
+    }
+}
+
+/// Result of a soak run that held all invariants
+#[derive(Debug, Clone)]
+pub struct SoakReport {
+    /// Seed of the run
+    pub seed: u64,
+    /// Completed cycles
+    pub cycles: u64,
+    /// Executed operations
+    pub operations: u64,
+    /// Storage restarts
+    pub restarts: u64,
+    /// Total duration of the run
+    pub elapsed: Duration,
+}
+
+/// Small deterministic random number generator (SplitMix64)
+struct SoakRng(u64);
+
+impl SoakRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform value in `0..bound`
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+}
+
+/// Documents the soak test expects in each index
+type Model = BTreeMap<String, BTreeMap<String, serde_json::Value>>;
+
+/// Run the soak test
+///
+/// Leftover soak indices from a previous run are removed first, other indices
+/// in the data directory are left alone. The run stops at the first invariant
+/// violation with an error naming the cycle; the soak indices are kept in that
+/// case so they can be inspected. `stop` ends the run after the current cycle.
+pub async fn run_soak(
+    options: &SoakOptions,
+    stop: impl std::future::Future<Output = ()>,
+) -> Result<SoakReport> {
+    let start = Instant::now();
+    let mut rng = SoakRng(options.seed);
+    let mut model = Model::new();
+    let mut report = SoakReport {
+        seed: options.seed,
+        cycles: 0,
+        operations: 0,
+        restarts: 0,
+        elapsed: Duration::ZERO,
+    };
+    tokio::pin!(stop);
+
+    let mut storage = open_storage(options).await?;
+    for leftover in storage
+        .match_indices(&format!("{}*", SOAK_INDEX_PREFIX))
+        .await
+    {
+        warn!("Removing leftover soak index '{}'", leftover);
+        storage.delete_index(&leftover).await?;
+    }
+
+    while options.cycles == 0 || report.cycles < options.cycles {
+        if stop.as_mut().now_or_never().is_some() {
+            info!("Soak test interrupted after cycle {}", report.cycles);
+            break;
+        }
+
+        let cycle = report.cycles + 1;
+        for _ in 0..options.ops_per_cycle {
+            run_operation(&storage, options, &mut rng, &mut model)
+                .await
+                .map_err(|e| soak_error(cycle, "operation", e))?;
+            report.operations += 1;
+        }
+        storage.flush().await?;
+        check_invariants(&storage, &model)
+            .await
+            .map_err(|e| soak_error(cycle, "invariant check", e))?;
+
+        if rng.below(2) == 0 {
+            drop(storage);
+            storage = open_storage(options).await?;
+            report.restarts += 1;
+            check_invariants(&storage, &model)
+                .await
+                .map_err(|e| soak_error(cycle, "invariant check after restart", e))?;
+        }
+
+        report.cycles = cycle;
+        info!(
+            "Soak cycle {} passed ({} operations, {} restarts)",
+            cycle, report.operations, report.restarts
+        );
+    }
+
+    for index_name in model.keys() {
+        storage.delete_index(index_name).await?;
+    }
+    storage.flush().await?;
+
+    report.elapsed = start.elapsed();
+    Ok(report)
+}
+
+async fn open_storage(options: &SoakOptions) -> Result<Storage> {
+    let storage = Storage::with_sled(&options.data_dir)?;
+    storage.load_from_backend().await?;
+    Ok(storage)
+}
+
+fn soak_error(cycle: u64, stage: &str, error: GbsError) -> GbsError {
+    GbsError::Storage(format!(
+        "Soak test failed in cycle {} ({}): {}",
+        cycle, stage, error
+    ))
+}
+
+fn violation(message: String) -> GbsError {
+    GbsError::Storage(message)
+}
+
+/// Run one random operation and check its immediate result against the model
+async fn run_operation(
+    storage: &Storage,
+    options: &SoakOptions,
+    rng: &mut SoakRng,
+    model: &mut Model,
+) -> Result<()> {
+    let index_name = format!("{}{}", SOAK_INDEX_PREFIX, rng.below(options.indices));
+    let id = format!("doc-{}", rng.below(options.docs_per_index));
+    let roll = rng.below(100);
+
+    if !model.contains_key(&index_name) {
+        storage.create_index(&index_name, None, None).await?;
+        model.insert(index_name.clone(), BTreeMap::new());
+    }
+    let docs = model
+        .get_mut(&index_name)
+        .expect("index was just added to the model");
+
+    match roll {
+        // Index (create or overwrite)
+        0..=44 => {
+            let doc = serde_json::json!({
+                "word": WORDS[rng.below(WORDS.len())],
+                "n": rng.next_u64() % 1000,
+            });
+            storage
+                .index_document(&index_name, &id, doc.clone())
+                .await?;
+            docs.insert(id, doc);
+        }
+        // Delete document
+        45..=64 => {
+            let result = storage.delete_document(&index_name, &id).await;
+            match (result, docs.remove(&id)) {
+                (Ok(_), Some(_)) | (Err(GbsError::DocumentNotFound(_)), None) => {}
+                (Ok(_), None) => {
+                    return Err(violation(format!(
+                        "deleted '{}/{}' which was never written",
+                        index_name, id
+                    )))
+                }
+                (Err(e), _) => return Err(e),
+            }
+        }
+        // Search
+        65..=79 => {
+            let word = WORDS[rng.below(WORDS.len())];
+            let query = serde_json::json!({ "term": { "word": word } });
+            let size = options.docs_per_index as u32;
+            let result = storage
+                .search(&index_name, &query, None, Some(size), None, None, None)
+                .await?;
+            let total = result["hits"]["total"]["value"].as_u64().unwrap_or(0) as usize;
+            let expected = docs.values().filter(|doc| doc["word"] == word).count();
+            if total != expected {
+                return Err(violation(format!(
+                    "search for '{}' in '{}' found {} documents, expected {}",
+                    word, index_name, total, expected
+                )));
+            }
+        }
+        // Get document
+        80..=91 => match (storage.get_document(&index_name, &id).await, docs.get(&id)) {
+            (Ok(found), Some(doc)) if found["_source"] == *doc => {}
+            (Err(GbsError::DocumentNotFound(_)), None) => {}
+            (Err(e), Some(_)) => return Err(e),
+            (found, _) => {
+                return Err(violation(format!(
+                    "get '{}/{}' returned {:?}, expected {:?}",
+                    index_name,
+                    id,
+                    found.ok(),
+                    docs.get(&id)
+                )))
+            }
+        },
+        // Move between the hot and warm tiers
+        92..=96 => {
+            let tier = if rng.below(2) == 0 {
+                IndexTier::Hot
+            } else {
+                IndexTier::Warm
+            };
+            storage.set_index_tier(&index_name, tier).await?;
+        }
+        // Delete the index; it is recreated by a later operation
+        _ => {
+            storage.delete_index(&index_name).await?;
+            model.remove(&index_name);
+        }
+    }
+    Ok(())
+}
+
+/// Check that storage, per-index counts and the backend agree with the model
+async fn check_invariants(storage: &Storage, model: &Model) -> Result<()> {
+    let counts: BTreeMap<String, usize> = storage
+        .get_indices_stats()
+        .await
+        .into_iter()
+        .filter(|(name, _)| name.starts_with(SOAK_INDEX_PREFIX))
+        .collect();
+    let expected_counts: BTreeMap<String, usize> = model
+        .iter()
+        .map(|(name, docs)| (name.clone(), docs.len()))
+        .collect();
+    if counts != expected_counts {
+        return Err(violation(format!(
+            "document counts {:?}, expected {:?}",
+            counts, expected_counts
+        )));
+    }
+
+    let backend = storage
+        .backend
+        .clone()
+        .ok_or_else(|| violation("soak test requires a persistent backend".to_string()))?;
+    let backend_indices = {
+        let backend = backend.clone();
+        tokio::task::spawn_blocking(move || backend.list_indices())
+            .await
+            .map_err(GbsError::TaskJoin)??
+    };
+    let mut backend_indices: Vec<String> = backend_indices
+        .into_iter()
+        .filter(|name| name.starts_with(SOAK_INDEX_PREFIX))
+        .collect();
+    backend_indices.sort();
+    if backend_indices.iter().ne(model.keys()) {
+        return Err(violation(format!(
+            "backend holds indices {:?}, expected {:?}",
+            backend_indices,
+            model.keys().collect::<Vec<_>>()
+        )));
+    }
+
+    for (index_name, docs) in model {
+        let query = serde_json::json!({ "match_all": {} });
+        let size = docs.len().max(1) as u32;
+        let result = storage
+            .search(index_name, &query, None, Some(size), None, None, None)
+            .await?;
+        let searched: BTreeMap<String, serde_json::Value> = result["hits"]["hits"]
+            .as_array()
+            .map(|hits| {
+                hits.iter()
+                    .map(|hit| {
+                        let id = hit["_id"].as_str().unwrap_or_default().to_string();
+                        (id, hit["_source"].clone())
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        if searched != *docs {
+            return Err(violation(format!(
+                "index '{}' serves {} documents that differ from the {} written",
+                index_name,
+                searched.len(),
+                docs.len()
+            )));
+        }
+
+        let stored = {
+            let backend = backend.clone();
+            let index_name = index_name.clone();
+            tokio::task::spawn_blocking(move || backend.load_all_documents(&index_name))
+                .await
+                .map_err(GbsError::TaskJoin)??
+        };
+        let stored: BTreeMap<String, serde_json::Value> = stored.into_iter().collect();
+        if stored != *docs {
+            return Err(violation(format!(
+                "backend holds {} documents for '{}' that differ from the {} written",
+                stored.len(),
+                index_name,
+                docs.len()
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Log a completed soak run
+pub fn log_soak_report(report: &SoakReport) {
+    info!(
+        "Soak test passed: {} cycles, {} operations, {} restarts in {:?} (seed {})",
+        report.cycles, report.operations, report.restarts, report.elapsed, report.seed
+    );
+}
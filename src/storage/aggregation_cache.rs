@@ -0,0 +1,153 @@
+//! Cache of aggregation results
+//!
+//! Entries are keyed by index, aggregation tree and query, and hold the
+//! bucket counts and hit total along with the index's document epoch (see
+//! `Index::epoch`). While an index only receives new documents its epoch stays
+//! the same, so a cached entry is brought up to date by counting just the
+//! documents appended since it was computed. For date histograms over
+//! time-series indices that means the buckets of closed time ranges are
+//! reused and only the current bucket changes. Replacing or deleting a
+//! document starts a new epoch, after which entries are recomputed.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use crate::storage::search::AggregationCounts;
+
+/// Number of entries kept before the least recently used one is evicted
+const DEFAULT_CAPACITY: usize = 256;
+
+/// Identifies a cached aggregation: index, aggregation tree and query
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AggregationCacheKey {
+    index: String,
+    aggs: String,
+    query: String,
+}
+
+impl AggregationCacheKey {
+    pub fn new(index: &str, aggs: &serde_json::Value, query: &serde_json::Value) -> Self {
+        Self {
+            index: index.to_string(),
+            aggs: aggs.to_string(),
+            query: query.to_string(),
+        }
+    }
+}
+
+/// Aggregation result computed at a point of an index's epoch
+#[derive(Debug, Clone)]
+pub struct AggregationCacheEntry {
+    /// Document epoch of the index the counts belong to
+    pub epoch: u64,
+    /// Number of documents appended in the epoch that are already counted
+    pub appended: usize,
+    /// Number of documents matching the query
+    pub total_hits: usize,
+    pub counts: AggregationCounts,
+}
+
+/// Usage counters of the aggregation cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AggregationCacheStats {
+    /// Results served unchanged from the cache
+    pub hits: u64,
+    /// Results served from the cache after counting appended documents
+    pub partial_hits: u64,
+    /// Results computed from all documents
+    pub misses: u64,
+    /// Number of cached entries
+    pub entries: usize,
+}
+
+#[derive(Debug)]
+pub struct AggregationCache {
+    capacity: usize,
+    /// Entries with the tick of their last use
+    entries: Mutex<HashMap<AggregationCacheKey, (AggregationCacheEntry, u64)>>,
+    tick: AtomicU64,
+    hits: AtomicU64,
+    partial_hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl Default for AggregationCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl AggregationCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            tick: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            partial_hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Look up the entry of a key if it belongs to the given epoch
+    pub fn get(&self, key: &AggregationCacheKey, epoch: u64) -> Option<AggregationCacheEntry> {
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        match entries.get_mut(key) {
+            Some((entry, last_used)) if entry.epoch == epoch => {
+                *last_used = tick;
+                Some(entry.clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Store an entry, evicting the least recently used one when full
+    pub fn insert(&self, key: AggregationCacheKey, entry: AggregationCacheEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, (entry, tick));
+    }
+
+    /// Drop the entries of an index; the usage counters are kept
+    pub fn clear_index(&self, index_name: &str) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .retain(|key, _| key.index != index_name);
+    }
+
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_partial_hit(&self) {
+        self.partial_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self) -> AggregationCacheStats {
+        AggregationCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            partial_hits: self.partial_hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.lock().unwrap_or_else(|e| e.into_inner()).len(),
+        }
+    }
+}
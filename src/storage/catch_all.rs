@@ -0,0 +1,226 @@
+//! Catch-all (`_all`) field
+//!
+//! An index whose mappings enable the `_all` field collects the string values
+//! of each document into it as the document is written:
+//!
+//! ```json
+//! { "_all": { "enabled": true },
+//!   "properties": { "password_hint": { "type": "text", "include_in_all": false } } }
+//! ```
+//!
+//! Fields mapped with `"include_in_all": false`, and the fields of objects
+//! mapped so, are left out. Query strings (`q=` and `query_string` queries)
+//! search `_all` unless they name other fields or the index names them in its
+//! `index.query.default_field` setting.
+//!
+//! Searches of the `_all` field alone score the collected values instead of
+//! the documents; other searches referring to it compute it like a runtime
+//! field. Without the field enabled, `_all` searches every value of the
+//! documents.
+
+use std::collections::BTreeSet;
+
+use crate::storage::dynamic_mapping::mapping_root;
+use crate::storage::field_caps::mapping_properties;
+use crate::storage::index::Documents;
+use crate::storage::settings::setting_value;
+
+/// Name of the catch-all field
+pub const CATCH_ALL_FIELD: &str = "_all";
+
+/// Setting naming the fields query strings search by default
+const DEFAULT_FIELD_SETTING: &str = "index.query.default_field";
+
+/// Fields query strings search when they name none: those of the
+/// `index.query.default_field` setting, or the catch-all field
+pub fn default_query_fields(settings: Option<&serde_json::Value>) -> Vec<String> {
+    let fields = match settings.and_then(|settings| setting_value(settings, DEFAULT_FIELD_SETTING))
+    {
+        Some(serde_json::Value::String(field)) => vec![field.clone()],
+        Some(serde_json::Value::Array(fields)) => fields
+            .iter()
+            .filter_map(|field| field.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    };
+    if fields.is_empty() {
+        vec![CATCH_ALL_FIELD.to_string()]
+    } else {
+        fields
+    }
+}
+
+/// The catch-all field of an index, as its mappings define it
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatchAllField {
+    /// Dotted paths of the fields left out, with their sub-fields
+    excluded: BTreeSet<String>,
+}
+
+impl CatchAllField {
+    /// The catch-all field of mappings, `None` unless they enable it
+    pub fn of_mapping(mappings: Option<&serde_json::Value>) -> Option<Self> {
+        let mappings = mappings?;
+        let enabled = mapping_root(mappings)
+            .get(CATCH_ALL_FIELD)
+            .and_then(|field| field.get("enabled"))
+            .and_then(|enabled| enabled.as_bool());
+        if enabled != Some(true) {
+            return None;
+        }
+        let mut excluded = BTreeSet::new();
+        if let Some(properties) = mapping_properties(mappings) {
+            collect_excluded(properties, "", &mut excluded);
+        }
+        Some(Self { excluded })
+    }
+
+    /// The values of the catch-all field of a document: its string values,
+    /// in document order
+    pub fn values(&self, doc: &serde_json::Value) -> serde_json::Value {
+        let mut values = Vec::new();
+        self.collect_values(doc, "", &mut values);
+        serde_json::Value::Array(values)
+    }
+
+    fn collect_values(
+        &self,
+        value: &serde_json::Value,
+        path: &str,
+        values: &mut Vec<serde_json::Value>,
+    ) {
+        match value {
+            serde_json::Value::String(_) => values.push(value.clone()),
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.collect_values(item, path, values);
+                }
+            }
+            serde_json::Value::Object(map) => {
+                for (name, value) in map {
+                    let path = match path {
+                        "" => name.clone(),
+                        _ => format!("{}.{}", path, name),
+                    };
+                    if !self.excluded.contains(&path) {
+                        self.collect_values(value, &path, values);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// A document holding only the catch-all field of `doc`
+    pub fn document(&self, doc: &serde_json::Value) -> serde_json::Value {
+        serde_json::json!({ CATCH_ALL_FIELD: self.values(doc) })
+    }
+}
+
+fn collect_excluded(properties: &serde_json::Value, prefix: &str, excluded: &mut BTreeSet<String>) {
+    let Some(properties) = properties.as_object() else {
+        return;
+    };
+    for (name, definition) in properties {
+        let path = format!("{}{}", prefix, name);
+        if definition
+            .get("include_in_all")
+            .and_then(|include| include.as_bool())
+            == Some(false)
+        {
+            excluded.insert(path);
+        } else if let Some(nested) = definition.get("properties") {
+            collect_excluded(nested, &format!("{}.", path), excluded);
+        }
+    }
+}
+
+/// The catch-all field of the documents of an index, kept up to date as
+/// documents are written
+#[derive(Debug, Clone)]
+pub struct CatchAllDocuments {
+    field: CatchAllField,
+    /// Documents holding only the catch-all field, by ID
+    documents: Documents,
+}
+
+impl CatchAllDocuments {
+    pub fn new(field: CatchAllField) -> Self {
+        Self {
+            field,
+            documents: Documents::new(),
+        }
+    }
+
+    /// Collect the catch-all field of every document
+    pub fn of_documents(field: CatchAllField, documents: &Documents) -> Self {
+        let documents = documents
+            .iter()
+            .map(|(id, doc)| (id.clone(), field.document(doc)))
+            .collect();
+        Self { field, documents }
+    }
+
+    pub fn field(&self) -> &CatchAllField {
+        &self.field
+    }
+
+    /// Add or replace a document
+    pub fn insert(&mut self, id: &str, doc: &serde_json::Value) {
+        self.documents
+            .insert(id.to_string(), self.field.document(doc));
+    }
+
+    /// Remove a document
+    pub fn remove(&mut self, id: &str) {
+        self.documents.remove(id);
+    }
+
+    pub fn clear(&mut self) {
+        self.documents.clear();
+    }
+
+    /// Documents holding only the catch-all field, by ID
+    pub fn documents(&self) -> &Documents {
+        &self.documents
+    }
+}
+
+/// Check if a query refers to the catch-all field
+pub fn refers_to_catch_all(query: &serde_json::Value) -> bool {
+    match query {
+        serde_json::Value::String(name) => name.split('^').next() == Some(CATCH_ALL_FIELD),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .any(|(key, value)| key == CATCH_ALL_FIELD || refers_to_catch_all(value)),
+        serde_json::Value::Array(items) => items.iter().any(refers_to_catch_all),
+        _ => false,
+    }
+}
+
+/// Check if a query reads no field but the catch-all field: `bool`
+/// combinations of `match_all` and text and term-level queries of `_all`
+pub fn refers_only_to_catch_all(query: &serde_json::Value) -> bool {
+    let Some((kind, body)) = query
+        .as_object()
+        .and_then(|query| (query.len() == 1).then(|| query.iter().next()).flatten())
+    else {
+        return false;
+    };
+    match kind.as_str() {
+        "match_all" => true,
+        "bool" => ["must", "should", "filter", "must_not"]
+            .iter()
+            .all(|occur| match body.get(occur) {
+                None => true,
+                Some(serde_json::Value::Array(clauses)) => {
+                    clauses.iter().all(refers_only_to_catch_all)
+                }
+                Some(clause) => refers_only_to_catch_all(clause),
+            }),
+        "match" | "match_phrase" | "match_phrase_prefix" | "prefix" | "wildcard" | "fuzzy" => body
+            .as_object()
+            .is_some_and(|fields| fields.keys().all(|field| field == CATCH_ALL_FIELD)),
+        _ => false,
+    }
+}
@@ -0,0 +1,265 @@
+//! Per-index change feed (`GET /{index}/_changes`)
+//!
+//! Every document created, updated or deleted in an index appends a change
+//! with the next sequence number of the index. Clients sync incrementally by
+//! asking for the changes after the last sequence number they have seen,
+//! optionally waiting for the next change (long polling). The most recent
+//! `MAX_RETAINED_CHANGES` changes of each index are kept in memory and in the
+//! backend, so sequence numbers carry on after a restart. Changes only name
+//! the document; its current version is fetched with a get.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+use tracing::{debug, error};
+
+use crate::error::{GbsError, Result};
+use crate::storage::settings::time_value_millis;
+use crate::storage::Index;
+use crate::storage_backend::SledBackend;
+
+/// Number of changes retained per index
+pub const MAX_RETAINED_CHANGES: usize = 10_000;
+
+/// Default maximum number of changes returned per request
+const DEFAULT_CHANGES_LIMIT: usize = 1000;
+
+/// What happened to a document
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeOp {
+    Create,
+    Update,
+    Delete,
+}
+
+impl ChangeOp {
+    /// `result` of a write making this change, as Elasticsearch reports it
+    pub fn result(&self) -> &'static str {
+        match self {
+            ChangeOp::Create => "created",
+            ChangeOp::Update => "updated",
+            ChangeOp::Delete => "deleted",
+        }
+    }
+}
+
+/// A change of one document
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Change {
+    pub seq: u64,
+    pub op: ChangeOp,
+    #[serde(rename = "_id")]
+    pub id: String,
+    /// Time of the change in milliseconds since the epoch
+    pub timestamp: u64,
+}
+
+impl Change {
+    /// `_seq_no` of the write making this change, as Elasticsearch reports
+    /// it: counted from 0, where the `seq` of changes counts from 1
+    pub fn seq_no(&self) -> u64 {
+        self.seq.saturating_sub(1)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ChangeLogState {
+    last_seq: u64,
+    changes: VecDeque<Change>,
+}
+
+/// Change log of an index, shared by all copies of the index
+#[derive(Debug, Default)]
+pub struct ChangeLog {
+    state: Mutex<ChangeLogState>,
+    appended: Notify,
+}
+
+impl ChangeLog {
+    /// Change log continuing from changes loaded from the backend
+    pub fn restore(changes: Vec<Change>) -> Self {
+        let mut changes: VecDeque<Change> = changes.into();
+        changes.make_contiguous().sort_by_key(|change| change.seq);
+        while changes.len() > MAX_RETAINED_CHANGES {
+            changes.pop_front();
+        }
+        Self {
+            state: Mutex::new(ChangeLogState {
+                last_seq: changes.back().map_or(0, |change| change.seq),
+                changes,
+            }),
+            appended: Notify::new(),
+        }
+    }
+
+    /// Append a change with the next sequence number and wake up waiters
+    pub fn append(&self, op: ChangeOp, id: &str) -> Change {
+        let change = {
+            let mut state = self.state.lock().unwrap();
+            state.last_seq += 1;
+            let change = Change {
+                seq: state.last_seq,
+                op,
+                id: id.to_string(),
+                timestamp: chrono::Utc::now().timestamp_millis() as u64,
+            };
+            state.changes.push_back(change.clone());
+            if state.changes.len() > MAX_RETAINED_CHANGES {
+                state.changes.pop_front();
+            }
+            change
+        };
+        self.appended.notify_waiters();
+        change
+    }
+
+    /// Sequence number of the latest change (0 before the first one)
+    pub fn last_seq(&self) -> u64 {
+        self.state.lock().unwrap().last_seq
+    }
+
+    /// Up to `limit` changes after `since`
+    ///
+    /// Fails if changes after `since` are no longer retained, in which case
+    /// the client has to sync from scratch.
+    pub fn since(&self, since: u64, limit: usize) -> Result<Vec<Change>> {
+        let state = self.state.lock().unwrap();
+        if let Some(oldest) = state.changes.front() {
+            if since + 1 < oldest.seq {
+                return Err(GbsError::IllegalArgument(format!(
+                    "changes after seq [{}] are no longer retained, the oldest retained change is [{}]",
+                    since, oldest.seq
+                )));
+            }
+        }
+        let start = state.changes.partition_point(|change| change.seq <= since);
+        Ok(state.changes.range(start..).take(limit).cloned().collect())
+    }
+
+    /// Changes after `since`, waiting up to `wait` for one if there are none
+    pub async fn wait_since(
+        &self,
+        since: u64,
+        limit: usize,
+        wait: Duration,
+    ) -> Result<Vec<Change>> {
+        let deadline = tokio::time::Instant::now() + wait;
+        loop {
+            // Registered before checking, so a change appended in between is not missed
+            let appended = self.appended.notified();
+            let changes = self.since(since, limit)?;
+            if !changes.is_empty() || tokio::time::timeout_at(deadline, appended).await.is_err() {
+                return Ok(changes);
+            }
+        }
+    }
+}
+
+/// A changes request
+#[derive(Debug, Clone)]
+pub struct ChangesRequest {
+    /// Sequence number after which changes are returned
+    pub since: u64,
+    /// Maximum number of changes returned
+    pub limit: usize,
+    /// How long to wait for a change if there are none yet
+    pub wait: Duration,
+}
+
+impl ChangesRequest {
+    /// Parse a request from its query parameters: `since` (default 0),
+    /// `limit` and `timeout`, a time value like `30s` enabling long polling
+    pub fn parse(params: &HashMap<String, String>) -> Result<Self> {
+        let since = match params.get("since") {
+            Some(since) => since
+                .parse()
+                .map_err(|_| invalid("[since] must be a non-negative integer"))?,
+            None => 0,
+        };
+        let limit = match params.get("limit") {
+            Some(limit) => limit
+                .parse()
+                .ok()
+                .filter(|&limit| limit > 0)
+                .ok_or_else(|| invalid("[limit] must be a positive integer"))?,
+            None => DEFAULT_CHANGES_LIMIT,
+        };
+        let wait = match params.get("timeout") {
+            Some(timeout) => Duration::from_millis(
+                time_value_millis(&serde_json::json!(timeout))
+                    .ok_or_else(|| invalid("[timeout] must be a time value like [30s]"))?,
+            ),
+            None => Duration::ZERO,
+        };
+        Ok(Self { since, limit, wait })
+    }
+}
+
+/// Changes of an index after a sequence number, in the format of the response
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangesResponse {
+    #[serde(rename = "_index")]
+    pub index: String,
+    /// Sequence number to ask for changes after next time
+    pub last_seq: u64,
+    pub changes: Vec<Change>,
+}
+
+/// Get the changes of an index
+///
+/// The index lock is not held while waiting for changes.
+pub async fn get_changes(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+    request: &ChangesRequest,
+) -> Result<ChangesResponse> {
+    let log = indices
+        .read()
+        .await
+        .get(index_name)
+        .map(|index| index.changes.clone())
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    let changes = log
+        .wait_since(request.since, request.limit, request.wait)
+        .await?;
+    debug!(
+        "Returning {} changes of index '{}' after seq {}",
+        changes.len(),
+        index_name,
+        request.since
+    );
+    Ok(ChangesResponse {
+        index: index_name.to_string(),
+        last_seq: changes.last().map_or(request.since, |change| change.seq),
+        changes,
+    })
+}
+
+/// Persist changes of an index
+///
+/// The documents are already written by then, so a failure only loses the
+/// changes from the persisted log and is logged rather than returned.
+pub(crate) async fn persist_changes(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    changes: Vec<Change>,
+) {
+    let Some(backend) = backend.clone() else {
+        return;
+    };
+    let name = index_name.to_string();
+    let persisted = tokio::task::spawn_blocking(move || backend.store_changes(&name, &changes))
+        .await
+        .map_err(GbsError::TaskJoin)
+        .and_then(|persisted| persisted);
+    if let Err(e) = persisted {
+        error!("Failed to persist changes of index '{}': {}", index_name, e);
+    }
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(format!("Invalid changes request: {}", reason))
+}
@@ -0,0 +1,178 @@
+//! Consistent point-in-time views of the indices
+//!
+//! A checkpoint captures every index as of one instant without copying its
+//! documents: document maps are shared copy-on-write, so taking a checkpoint
+//! only holds the index read lock for as long as it takes to clone the map
+//! pointers. Backups and exports then serialize from the checkpoint while
+//! writers carry on; the first write to an index after a checkpoint copies
+//! that index's map.
+//!
+//! Warm indices keep their documents on disk only, so a checkpoint reads them
+//! from disk when it is consumed; writes made to a warm index in between are
+//! included.
+
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::index::Documents;
+use crate::storage::persistence::index_metadata;
+use crate::storage::Index;
+use crate::storage_backend::{DocumentWrite, IndexMetadata, SledBackend};
+
+/// Number of documents written per batch when writing a backup
+const BACKUP_BATCH_SIZE: usize = 1000;
+
+struct IndexCheckpoint {
+    metadata: IndexMetadata,
+    /// Documents of a hot index; `None` for warm indices
+    documents: Option<Documents>,
+    doc_count: usize,
+}
+
+/// A point-in-time view of all indices
+pub struct Checkpoint {
+    taken_at: DateTime<Utc>,
+    indices: BTreeMap<String, IndexCheckpoint>,
+    backend: Option<Arc<SledBackend>>,
+}
+
+/// Outcome of writing a backup
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupReport {
+    pub path: PathBuf,
+    /// Time the backed up checkpoint was taken
+    pub taken_at: DateTime<Utc>,
+    pub indices: usize,
+    pub documents: u64,
+}
+
+/// Take a checkpoint of the indices
+pub async fn checkpoint(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+) -> Checkpoint {
+    let indices_guard = indices.read().await;
+    let taken_at = Utc::now();
+    let indices = indices_guard
+        .iter()
+        .map(|(name, index)| {
+            let checkpoint = IndexCheckpoint {
+                metadata: index_metadata(index),
+                documents: (!index.is_warm()).then(|| index.documents.clone()),
+                doc_count: index.doc_count(),
+            };
+            (name.clone(), checkpoint)
+        })
+        .collect();
+    drop(indices_guard);
+    debug!("Checkpoint taken at {}", taken_at);
+
+    Checkpoint {
+        taken_at,
+        indices,
+        backend: backend.clone(),
+    }
+}
+
+impl Checkpoint {
+    /// Time the checkpoint was taken
+    pub fn taken_at(&self) -> DateTime<Utc> {
+        self.taken_at
+    }
+
+    /// Names of the indices, sorted
+    pub fn index_names(&self) -> Vec<String> {
+        self.indices.keys().cloned().collect()
+    }
+
+    /// Settings, mappings, aliases, search profiles and tier of an index
+    pub fn metadata(&self, index_name: &str) -> Option<&IndexMetadata> {
+        self.indices.get(index_name).map(|index| &index.metadata)
+    }
+
+    /// Number of documents of an index when the checkpoint was taken
+    pub fn doc_count(&self, index_name: &str) -> Option<usize> {
+        self.indices.get(index_name).map(|index| index.doc_count)
+    }
+
+    /// Visit every document of an index
+    ///
+    /// Documents of warm indices are read from disk, so this may block.
+    pub fn for_each_document<F>(&self, index_name: &str, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&str, serde_json::Value) -> Result<()>,
+    {
+        let index = self
+            .indices
+            .get(index_name)
+            .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+        match (&index.documents, &self.backend) {
+            (Some(documents), _) => {
+                for (id, document) in documents.iter() {
+                    visit(id, document.clone())?;
+                }
+                Ok(())
+            }
+            (None, Some(backend)) => backend.for_each_document(index_name, visit),
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Write the checkpoint to a new data directory
+    ///
+    /// The backup is a complete gbs data directory: a server started with
+    /// `storage.data_dir` pointing at it serves the indices as they were at
+    /// the checkpoint. The directory must not exist or be empty. This blocks
+    /// while writing.
+    pub fn write_backup(&self, path: &Path) -> Result<BackupReport> {
+        if path.exists() && std::fs::read_dir(path)?.next().is_some() {
+            return Err(GbsError::InvalidRequest(format!(
+                "Backup directory {} is not empty",
+                path.display()
+            )));
+        }
+        let backup = SledBackend::new(path)?;
+
+        let mut documents = 0;
+        for (name, index) in &self.indices {
+            backup.store_index_metadata(name, &index.metadata)?;
+            let mut batch = Vec::with_capacity(BACKUP_BATCH_SIZE);
+            self.for_each_document(name, |id, document| {
+                batch.push(DocumentWrite::Store {
+                    index: name.clone(),
+                    id: id.to_string(),
+                    document,
+                });
+                if batch.len() == BACKUP_BATCH_SIZE {
+                    backup.apply_document_writes(&batch)?;
+                    documents += batch.len() as u64;
+                    batch.clear();
+                }
+                Ok(())
+            })?;
+            backup.apply_document_writes(&batch)?;
+            documents += batch.len() as u64;
+        }
+        backup.flush()?;
+
+        let report = BackupReport {
+            path: path.to_path_buf(),
+            taken_at: self.taken_at,
+            indices: self.indices.len(),
+            documents,
+        };
+        info!(
+            "Backed up {} indices with {} documents as of {} to {}",
+            report.indices,
+            report.documents,
+            report.taken_at,
+            report.path.display()
+        );
+        Ok(report)
+    }
+}
@@ -0,0 +1,291 @@
+//! Cluster settings (`GET/PUT /_cluster/settings`)
+//!
+//! Settings are `persistent`, kept in the backend across restarts, or
+//! `transient`, kept in memory only; a transient setting overrides the
+//! persistent one. Keys may be nested or dotted and are stored flat, with
+//! values as strings like Elasticsearch does; `null` resets a setting, or
+//! every setting of a group. Any setting is accepted, but only these change
+//! what gbs does:
+//!
+//! - `action.auto_create_index`: which missing indices a document write
+//!   creates (`true`, `false` or patterns like `+logs-*,-tmp-*`), in place of
+//!   the configured `auto_create_index`
+//! - `search.default_search_timeout`: time budget of searches without a
+//!   `timeout` (`-1` for none), in place of the configured default
+//! - `search.max_result_window`: maximum `from + size` of a search
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde_json::{Map, Value};
+use tokio::sync::Mutex;
+use tracing::{debug, info};
+
+use crate::config::AutoCreateIndex;
+use crate::error::{GbsError, Result};
+use crate::storage::settings::time_value_millis;
+use crate::storage_backend::SledBackend;
+
+const AUTO_CREATE_INDEX: &str = "action.auto_create_index";
+const DEFAULT_SEARCH_TIMEOUT: &str = "search.default_search_timeout";
+const MAX_RESULT_WINDOW: &str = "search.max_result_window";
+
+/// Flat settings by key
+type FlatSettings = BTreeMap<String, Value>;
+
+/// The cluster settings of a storage
+#[derive(Debug, Default)]
+pub struct ClusterSettings {
+    persistent: RwLock<FlatSettings>,
+    transient: RwLock<FlatSettings>,
+    /// Serializes updates, from reading the settings to applying them
+    updates: Mutex<()>,
+}
+
+impl ClusterSettings {
+    /// The effective value of a setting, transient before persistent
+    pub fn get(&self, key: &str) -> Option<Value> {
+        read(&self.transient)
+            .get(key)
+            .cloned()
+            .or_else(|| read(&self.persistent).get(key).cloned())
+    }
+
+    /// `action.auto_create_index`, if set
+    pub fn auto_create_index(&self) -> Option<AutoCreateIndex> {
+        self.get(AUTO_CREATE_INDEX)
+            .and_then(|value| AutoCreateIndex::parse(value.as_str()?))
+    }
+
+    /// `search.default_search_timeout`, if set; `Some(None)` for no time budget
+    pub fn default_search_timeout(&self) -> Option<Option<Duration>> {
+        let value = self.get(DEFAULT_SEARCH_TIMEOUT)?;
+        if value == "-1" {
+            return Some(None);
+        }
+        time_value_millis(&value).map(|millis| Some(Duration::from_millis(millis)))
+    }
+
+    /// `search.max_result_window`, if set
+    pub fn max_result_window(&self) -> Option<u64> {
+        self.get(MAX_RESULT_WINDOW)?.as_str()?.parse().ok()
+    }
+
+    /// The settings as returned by `GET /_cluster/settings`, nested unless
+    /// `flat`
+    pub fn to_json(&self, flat: bool) -> Value {
+        serde_json::json!({
+            "persistent": render(&read(&self.persistent), flat),
+            "transient": render(&read(&self.transient), flat)
+        })
+    }
+
+    fn load(&self, persistent: FlatSettings) {
+        *write(&self.persistent) = persistent;
+    }
+}
+
+/// Update the cluster settings with the body of `PUT /_cluster/settings`,
+/// persisting the persistent ones to the backend
+///
+/// Returns the acknowledgement, listing the settings that were set.
+pub async fn update_cluster_settings(
+    settings: &ClusterSettings,
+    backend: &Option<Arc<SledBackend>>,
+    body: &Value,
+    flat: bool,
+) -> Result<Value> {
+    let body = body
+        .as_object()
+        .ok_or_else(|| GbsError::IllegalArgument("request body must be an object".to_string()))?;
+    if let Some(key) = body
+        .keys()
+        .find(|key| *key != "persistent" && *key != "transient")
+    {
+        return Err(GbsError::IllegalArgument(format!(
+            "request body contains unknown field [{}]",
+            key
+        )));
+    }
+    let persistent = body.get("persistent").map(flatten).transpose()?;
+    let transient = body.get("transient").map(flatten).transpose()?;
+    if persistent.iter().chain(&transient).all(Vec::is_empty) {
+        return Err(GbsError::IllegalArgument(
+            "no settings to update".to_string(),
+        ));
+    }
+
+    let persistent = persistent.unwrap_or_default();
+    let transient = transient.unwrap_or_default();
+    let _guard = settings.updates.lock().await;
+    let mut updated_persistent = read(&settings.persistent).clone();
+    apply(&mut updated_persistent, &persistent);
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let value = serde_json::to_value(&updated_persistent)?;
+        tokio::task::spawn_blocking(move || backend.store_cluster_settings(&value))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    *write(&settings.persistent) = updated_persistent;
+    apply(&mut write(&settings.transient), &transient);
+    info!(
+        "Updated cluster settings ({} persistent, {} transient)",
+        persistent.len(),
+        transient.len()
+    );
+
+    let set = |updates: &[(String, Value)]| {
+        let updates: FlatSettings = updates
+            .iter()
+            .filter(|(_, value)| !value.is_null())
+            .cloned()
+            .collect();
+        render(&updates, flat)
+    };
+    Ok(serde_json::json!({
+        "acknowledged": true,
+        "persistent": set(&persistent),
+        "transient": set(&transient)
+    }))
+}
+
+/// Load the persisted cluster settings
+pub async fn load_cluster_settings(
+    settings: &ClusterSettings,
+    backend: &Option<Arc<SledBackend>>,
+) -> Result<()> {
+    let Some(backend) = backend else {
+        return Ok(());
+    };
+    let backend = backend.clone();
+    let stored = tokio::task::spawn_blocking(move || backend.load_cluster_settings())
+        .await
+        .map_err(GbsError::TaskJoin)??;
+    if let Some(stored) = stored {
+        let stored: FlatSettings = serde_json::from_value(stored)?;
+        debug!("Loaded {} persistent cluster settings", stored.len());
+        settings.load(stored);
+    }
+    Ok(())
+}
+
+/// Flatten settings into dotted keys with string values, checking the
+/// settings gbs acts on
+fn flatten(settings: &Value) -> Result<Vec<(String, Value)>> {
+    fn collect(value: &Value, prefix: &str, flat: &mut Vec<(String, Value)>) -> Result<()> {
+        match value {
+            Value::Object(map) => {
+                for (key, child) in map {
+                    let key = match prefix {
+                        "" => key.clone(),
+                        prefix => format!("{}.{}", prefix, key),
+                    };
+                    collect(child, &key, flat)?;
+                }
+                Ok(())
+            }
+            Value::Null => {
+                flat.push((prefix.to_string(), Value::Null));
+                Ok(())
+            }
+            Value::Array(items) => {
+                let items = items.iter().map(setting_string).collect::<Result<_>>()?;
+                flat.push((prefix.to_string(), Value::Array(items)));
+                Ok(())
+            }
+            value => {
+                let value = setting_string(value)?;
+                check(prefix, &value)?;
+                flat.push((prefix.to_string(), value));
+                Ok(())
+            }
+        }
+    }
+
+    if !settings.is_object() {
+        return Err(GbsError::IllegalArgument(
+            "cluster settings must be an object".to_string(),
+        ));
+    }
+    let mut flat = Vec::new();
+    collect(settings, "", &mut flat)?;
+    Ok(flat)
+}
+
+/// A scalar setting value as a string
+fn setting_string(value: &Value) -> Result<Value> {
+    match value {
+        Value::String(_) => Ok(value.clone()),
+        Value::Bool(_) | Value::Number(_) => Ok(Value::String(value.to_string())),
+        _ => Err(GbsError::IllegalArgument(format!(
+            "invalid setting value [{}]",
+            value
+        ))),
+    }
+}
+
+/// Check the value of a setting gbs acts on
+fn check(key: &str, value: &Value) -> Result<()> {
+    let text = value.as_str().unwrap_or_default();
+    let valid = match key {
+        AUTO_CREATE_INDEX => AutoCreateIndex::parse(text).is_some(),
+        DEFAULT_SEARCH_TIMEOUT => text == "-1" || time_value_millis(value).is_some(),
+        MAX_RESULT_WINDOW => text.parse::<u64>().is_ok_and(|window| window > 0),
+        _ => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(GbsError::IllegalArgument(format!(
+            "illegal value [{}] for setting [{}]",
+            text, key
+        )))
+    }
+}
+
+/// Apply flat updates; `null` removes a setting and the settings below it
+fn apply(settings: &mut FlatSettings, updates: &[(String, Value)]) {
+    for (key, value) in updates {
+        if value.is_null() {
+            let group = format!("{}.", key);
+            settings.retain(|existing, _| existing != key && !existing.starts_with(&group));
+        } else {
+            settings.insert(key.clone(), value.clone());
+        }
+    }
+}
+
+/// Flat settings as an object, nested unless `flat`
+fn render(settings: &FlatSettings, flat: bool) -> Value {
+    let mut rendered = Map::new();
+    for (key, value) in settings {
+        if flat {
+            rendered.insert(key.clone(), value.clone());
+            continue;
+        }
+        let mut parts: Vec<&str> = key.split('.').collect();
+        let leaf = parts.pop().expect("split gives at least one part");
+        let mut current = &mut rendered;
+        for part in parts {
+            let child = current
+                .entry(part.to_string())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if !child.is_object() {
+                *child = Value::Object(Map::new());
+            }
+            current = child.as_object_mut().expect("made an object above");
+        }
+        current.insert(leaf.to_string(), value.clone());
+    }
+    Value::Object(rendered)
+}
+
+fn read(settings: &RwLock<FlatSettings>) -> std::sync::RwLockReadGuard<'_, FlatSettings> {
+    settings.read().unwrap_or_else(|e| e.into_inner())
+}
+
+fn write(settings: &RwLock<FlatSettings>) -> std::sync::RwLockWriteGuard<'_, FlatSettings> {
+    settings.write().unwrap_or_else(|e| e.into_inner())
+}
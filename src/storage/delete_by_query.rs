@@ -0,0 +1,238 @@
+//! Deleting the documents that match a query (`POST /{index}/_delete_by_query`)
+//!
+//! Matching documents are collected first and then deleted in batches, so
+//! documents indexed while the request runs are not deleted. Documents
+//! deleted by someone else in the meantime are version conflicts.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::error::{GbsError, Result};
+use crate::storage::aggregation_cache::AggregationCache;
+use crate::storage::document_ops::delete_document;
+use crate::storage::reindex::failure;
+use crate::storage::search_impl::{search, ParallelScoring, SearchRequest};
+use crate::storage::{Index, ReindexFailure};
+use crate::storage_backend::SledBackend;
+use crate::tasks::{TaskHandle, CANCELED_BY_USER};
+
+/// Default number of documents deleted per batch
+const DEFAULT_SCROLL_SIZE: usize = 1000;
+
+/// A delete-by-query request
+#[derive(Debug, Clone)]
+pub struct DeleteByQueryRequest {
+    /// Index expression of the indices to delete from
+    pub index: String,
+    /// Query selecting the documents to delete
+    pub query: serde_json::Value,
+    /// Maximum number of documents to delete
+    pub max_docs: Option<usize>,
+    /// Number of documents deleted per batch
+    pub batch_size: usize,
+    /// Whether version conflicts are counted instead of aborting
+    pub proceed_on_conflicts: bool,
+}
+
+impl DeleteByQueryRequest {
+    /// Parse a request from its body and query parameters
+    ///
+    /// The body holds the `query` and optionally `max_docs` and `conflicts`;
+    /// `max_docs`, `conflicts` and `scroll_size` (the batch size) may also be
+    /// given as parameters.
+    pub fn parse(
+        index: &str,
+        body: &serde_json::Value,
+        params: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let query = body
+            .get("query")
+            .cloned()
+            .ok_or_else(|| invalid("[query] is required"))?;
+
+        let max_docs = match (body.get("max_docs"), params.get("max_docs")) {
+            (Some(max_docs), _) => Some(
+                max_docs
+                    .as_u64()
+                    .ok_or_else(|| invalid("[max_docs] must be a non-negative integer"))?
+                    as usize,
+            ),
+            (None, Some(max_docs)) => Some(
+                max_docs
+                    .parse()
+                    .map_err(|_| invalid("[max_docs] must be a non-negative integer"))?,
+            ),
+            (None, None) => None,
+        };
+        let conflicts = body
+            .get("conflicts")
+            .and_then(|c| c.as_str())
+            .or(params.get("conflicts").map(String::as_str));
+        let proceed_on_conflicts = match conflicts {
+            None | Some("abort") => false,
+            Some("proceed") => true,
+            Some(other) => {
+                return Err(invalid(&format!(
+                    "[conflicts] must be [abort] or [proceed], got [{}]",
+                    other
+                )))
+            }
+        };
+        let batch_size = match params.get("scroll_size") {
+            Some(size) => size
+                .parse()
+                .ok()
+                .filter(|&size| size > 0)
+                .ok_or_else(|| invalid("[scroll_size] must be a positive integer"))?,
+            None => DEFAULT_SCROLL_SIZE,
+        };
+
+        Ok(Self {
+            index: index.to_string(),
+            query,
+            max_docs,
+            batch_size,
+            proceed_on_conflicts,
+        })
+    }
+}
+
+/// Outcome of a delete-by-query, in the format of the Elasticsearch response
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DeleteByQueryResponse {
+    pub took: u64,
+    pub timed_out: bool,
+    /// Number of documents that matched the query
+    pub total: usize,
+    pub deleted: usize,
+    pub batches: usize,
+    pub version_conflicts: usize,
+    pub noops: usize,
+    pub failures: Vec<ReindexFailure>,
+    /// Why the request stopped early, if it was cancelled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canceled: Option<String>,
+}
+
+impl DeleteByQueryResponse {
+    /// Progress counters, reported as the status of the task
+    fn progress(&self) -> serde_json::Value {
+        serde_json::json!({
+            "total": self.total,
+            "deleted": self.deleted,
+            "batches": self.batches,
+            "version_conflicts": self.version_conflicts,
+            "noops": self.noops
+        })
+    }
+}
+
+/// Delete the documents of the given indices that match the query
+///
+/// The first batch with failures ends the request; documents of earlier
+/// batches stay deleted. Progress is reported to `task`, and cancelling it
+/// stops the request before the next batch.
+pub async fn delete_by_query(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    cache: &AggregationCache,
+    scoring: ParallelScoring,
+    target_indices: &[String],
+    request: &DeleteByQueryRequest,
+    task: &TaskHandle,
+) -> Result<DeleteByQueryResponse> {
+    let start_time = std::time::Instant::now();
+
+    let mut documents: Vec<(String, String)> = Vec::new();
+    for index_name in target_indices {
+        let remaining = request
+            .max_docs
+            .map(|max_docs| max_docs.saturating_sub(documents.len()));
+        if remaining == Some(0) {
+            break;
+        }
+        let search_request = SearchRequest {
+            from: Some(0),
+            size: Some(remaining.map_or(u32::MAX, |remaining| {
+                remaining.min(u32::MAX as usize) as u32
+            })),
+            source_filter: Some(serde_json::json!(false)),
+            ..SearchRequest::new(request.query.clone())
+        };
+        let response = search(
+            indices,
+            backend,
+            index_name,
+            &search_request,
+            cache,
+            scoring,
+        )
+        .await?;
+        for hit in response["hits"]["hits"].as_array().into_iter().flatten() {
+            if let Some(id) = hit["_id"].as_str() {
+                documents.push((index_name.clone(), id.to_string()));
+            }
+        }
+    }
+
+    let mut response = DeleteByQueryResponse {
+        total: documents.len(),
+        ..Default::default()
+    };
+    task.set_status(response.progress());
+    for batch in documents.chunks(request.batch_size) {
+        if task.is_cancelled() {
+            info!("Delete by query on {:?} cancelled", target_indices);
+            response.canceled = Some(CANCELED_BY_USER.to_string());
+            break;
+        }
+        response.batches += 1;
+        for (index_name, id) in batch {
+            match delete_document(indices, backend, index_name, id).await {
+                Ok(_) => response.deleted += 1,
+                Err(GbsError::DocumentNotFound(_)) => {
+                    response.version_conflicts += 1;
+                    if !request.proceed_on_conflicts {
+                        response.failures.push(failure(
+                            index_name,
+                            id,
+                            409,
+                            "version_conflict_engine_exception",
+                            format!("[_doc][{}]: version conflict, document already deleted", id),
+                        ));
+                    }
+                }
+                Err(e) => response.failures.push(failure(
+                    index_name,
+                    id,
+                    e.status_code().as_u16(),
+                    e.error_type(),
+                    e.reason(),
+                )),
+            }
+        }
+        task.set_status(response.progress());
+        if !response.failures.is_empty() {
+            warn!(
+                "Delete by query on {:?} aborted after {} failures",
+                target_indices,
+                response.failures.len()
+            );
+            break;
+        }
+    }
+
+    response.took = start_time.elapsed().as_millis() as u64;
+    info!(
+        "Deleted {} of {} documents matching the query from {:?}",
+        response.deleted, response.total, target_indices
+    );
+    Ok(response)
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(format!("Invalid delete by query request: {}", reason))
+}
@@ -8,19 +8,78 @@ use uuid::Uuid;
 
 use crate::bulk_ops::BulkAction;
 use crate::error::{GbsError, Result};
-use crate::storage::Index;
-use crate::storage_backend::SledBackend;
+use crate::storage::changes::persist_changes;
+use crate::storage::dynamic_mapping::{add_field_mappings, new_field_mappings};
+use crate::storage::index_ops::{create_index, resolve_write_index, rollover_index};
+use crate::storage::index_state::{ensure_readable, ensure_writable};
+use crate::storage::limits::StorageLimits;
+use crate::storage::mapping_validation::{validate_document, validate_percolator_queries};
+use crate::storage::persistence::persist_index_metadata;
+use crate::storage::routing::IngestRoutes;
+use crate::storage::search::resolve_date_math_index_name;
+use crate::storage::templates::IndexTemplates;
+use crate::storage::tiering::warm_index_backend;
+use crate::storage::{Change, ChangeOp, Index};
+use crate::storage_backend::{DocumentWrite, SledBackend};
+
+/// Outcome of a single document write
+#[derive(Debug, Clone)]
+pub struct WriteOutcome {
+    /// Index the document was written to: the write index of an alias target
+    pub index: String,
+    /// Change log entry of the write, with its sequence number and whether
+    /// the document was created, updated or deleted
+    pub change: Change,
+    /// Version of the document after the write (for a delete, the version
+    /// of the deletion)
+    pub version: u64,
+}
 
 /// Index a document (create or update)
+///
+/// `target` may be an index or an alias pointing at a single index. Writes
+/// through an alias roll the index over once it exceeds the automatic
+/// rollover limits.
 pub async fn index_document(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
-    index_name: &str,
+    limits: &StorageLimits,
+    target: &str,
     id: &str,
     document: serde_json::Value,
-) -> Result<()> {
+) -> Result<WriteOutcome> {
+    let start_time = std::time::Instant::now();
+    let index_name = resolve_write_index(&*indices.read().await, target).ok_or_else(|| {
+        error!(
+            "Index '{}' not found when indexing document '{}'",
+            target, id
+        );
+        GbsError::IndexNotFound(target.to_string())
+    })?;
+    let index_name = index_name.as_str();
     debug!("Indexing document '{}' in index '{}'", id, index_name);
 
+    // Fields new to the mappings; strict mappings and values that do not fit
+    // their mapped types reject the document here, before anything is written
+    let new_fields = match indices.read().await.get(index_name) {
+        Some(index) => {
+            ensure_writable(index)?;
+            validate_document(index, id, &document)?;
+            validate_percolator_queries(index, id, &document)?;
+            new_field_mappings(index.mappings.as_ref(), &document)?
+        }
+        None => None,
+    };
+
+    // Warm indices track their stats without the documents in memory, so the
+    // replaced document (if any) has to come from disk
+    let warm = is_warm_index(indices, index_name).await;
+    let previous = if warm {
+        load_backend_document(backend, index_name, id).await?
+    } else {
+        None
+    };
+
     // Persist to backend if available
     if let Some(backend) = backend {
         let backend_clone = backend.clone();
@@ -45,47 +104,230 @@ pub async fn index_document(
         GbsError::IndexNotFound(index_name.to_string())
     })?;
 
-    index.documents.insert(id.to_string(), document);
+    let change = if index.is_warm() {
+        index.record_evicted_write(previous.as_ref(), &document);
+        index.changes.append(write_op(previous.is_some()), id)
+    } else {
+        let op = write_op(index.documents.contains_key(id));
+        index.insert_document(id.to_string(), document);
+        index.changes.append(op, id)
+    };
+    let version = index.bump_version(id, change.op == ChangeOp::Update);
+    index.operations.record_index(1, start_time.elapsed());
+    persist_changes(backend, index_name, vec![change.clone()]).await;
+    persist_versions(backend, index_name, vec![(id.to_string(), Some(version))]).await;
+    if let Some(new_fields) = new_fields {
+        debug!(
+            "Adding new fields to the mappings of index '{}'",
+            index_name
+        );
+        add_field_mappings(index, new_fields);
+        persist_index_metadata(backend, index).await?;
+    }
     debug!(
         "Document '{}' indexed successfully in index '{}'",
         id, index_name
     );
-    Ok(())
+
+    if target != index_name && limits.needs_rollover(index) {
+        // The write itself succeeded; a failed rollover only means the index
+        // keeps growing until the limits allow a new one
+        if let Err(e) =
+            rollover_index(&mut indices_guard, backend, limits, target, index_name).await
+        {
+            warn!(
+                "Automatic rollover of alias '{}' from index '{}' failed: {}",
+                target, index_name, e
+            );
+        }
+    }
+    Ok(WriteOutcome {
+        index: index_name.to_string(),
+        change,
+        version,
+    })
+}
+
+/// Resolve the index a document written to `target` belongs in
+///
+/// A target naming an ingest route (and no index or alias) is routed to the
+/// index derived from the document, which is created from the route's settings
+/// and mappings if it does not exist yet. A missing target is created, with
+/// the matching index templates, when automatic index creation allows it.
+/// Date math targets like `<logs-{now/d}>` are resolved first. Any other
+/// target is returned as is.
+pub async fn route_document(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
+    target: &str,
+    document: &serde_json::Value,
+) -> Result<String> {
+    let target = &resolve_date_math_index_name(target, chrono::Utc::now())?;
+    if resolve_write_index(&*indices.read().await, target).is_some() {
+        return Ok(target.to_string());
+    }
+
+    let (index_name, settings, mappings) = match routes.get(target) {
+        Some(route) => {
+            let index_name = route.target_index(document)?;
+            if indices.read().await.contains_key(&index_name) {
+                return Ok(index_name);
+            }
+            info!(
+                "Creating index '{}' for ingest route '{}'",
+                index_name, route.name
+            );
+            (index_name, route.settings.clone(), route.mappings.clone())
+        }
+        None if limits.allows_auto_create(target, templates.matches(target)) => {
+            info!("Automatically creating index '{}'", target);
+            (target.to_string(), None, None)
+        }
+        None => return Ok(target.to_string()),
+    };
+
+    let created = create_index(
+        indices,
+        backend,
+        limits,
+        templates,
+        &index_name,
+        settings,
+        mappings,
+    )
+    .await;
+    // A concurrent write may have created the index in the meantime
+    if let Err(e) = created {
+        if !indices.read().await.contains_key(&index_name) {
+            return Err(e);
+        }
+    }
+    Ok(index_name)
 }
 
 /// Create a document with auto-generated ID
 pub async fn create_document(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
     index_name: &str,
     document: serde_json::Value,
-) -> Result<String> {
+) -> Result<(String, WriteOutcome)> {
     let id = Uuid::new_v4().to_string();
-    index_document(indices, backend, index_name, &id, document).await?;
-    Ok(id)
+    let outcome = index_document(indices, backend, limits, index_name, &id, document).await?;
+    Ok((id, outcome))
+}
+
+/// Persist the versions of written documents, `None` for deleted ones
+///
+/// The documents are already written by then, so a failure only loses the
+/// versions, which start over at 1, and is logged rather than returned.
+async fn persist_versions(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    versions: Vec<(String, Option<u64>)>,
+) {
+    let Some(backend) = backend.clone() else {
+        return;
+    };
+    let name = index_name.to_string();
+    let persisted = tokio::task::spawn_blocking(move || backend.store_versions(&name, &versions))
+        .await
+        .map_err(GbsError::TaskJoin)
+        .and_then(|persisted| persisted);
+    if let Err(e) = persisted {
+        error!(
+            "Failed to persist document versions of index '{}': {}",
+            index_name, e
+        );
+    }
+}
+
+/// Change operation of a document write
+fn write_op(replaces: bool) -> ChangeOp {
+    if replaces {
+        ChangeOp::Update
+    } else {
+        ChangeOp::Create
+    }
+}
+
+/// Check if an index exists and is in the warm tier
+async fn is_warm_index(indices: &Arc<RwLock<HashMap<String, Index>>>, index_name: &str) -> bool {
+    indices
+        .read()
+        .await
+        .get(index_name)
+        .is_some_and(|index| index.is_warm())
+}
+
+/// Load a document straight from the backend (documents of warm indices)
+async fn load_backend_document(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    id: &str,
+) -> Result<Option<serde_json::Value>> {
+    let backend = warm_index_backend(backend, index_name)?;
+    let index_name = index_name.to_string();
+    let id = id.to_string();
+
+    tokio::task::spawn_blocking(move || backend.load_document(&index_name, &id))
+        .await
+        .map_err(GbsError::TaskJoin)?
+}
+
+/// Look up a document in memory or, for warm indices, on disk
+///
+/// Returns `None` if the index or the document does not exist.
+pub async fn fetch_document(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    id: &str,
+) -> Result<Option<serde_json::Value>> {
+    {
+        let indices_guard = indices.read().await;
+        match indices_guard.get(index_name) {
+            Some(index) => {
+                ensure_readable(index)?;
+                if !index.is_warm() {
+                    return Ok(index.documents.get(id).cloned());
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+    load_backend_document(backend, index_name, id).await
 }
 
 /// Get a document by ID
 pub async fn get_document(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
     index_name: &str,
     id: &str,
 ) -> Result<serde_json::Value> {
-    let indices_guard = indices.read().await;
-    let index = indices_guard
-        .get(index_name)
-        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    if !indices.read().await.contains_key(index_name) {
+        return Err(GbsError::IndexNotFound(index_name.to_string()));
+    }
 
-    let doc = index
-        .documents
-        .get(id)
+    let doc = fetch_document(indices, backend, index_name, id)
+        .await?
         .ok_or_else(|| GbsError::DocumentNotFound(id.to_string()))?;
 
+    let version = indices
+        .read()
+        .await
+        .get(index_name)
+        .map_or(1, |index| index.version(id));
     Ok(serde_json::json!({
         "_index": index_name,
         "_type": "_doc",
         "_id": id,
-        "_version": 1,
+        "_version": version,
         "_source": doc
     }))
 }
@@ -96,8 +338,24 @@ pub async fn delete_document(
     backend: &Option<Arc<SledBackend>>,
     index_name: &str,
     id: &str,
-) -> Result<()> {
+) -> Result<WriteOutcome> {
     debug!("Deleting document '{}' from index '{}'", id, index_name);
+    let start_time = std::time::Instant::now();
+    if let Some(index) = indices.read().await.get(index_name) {
+        ensure_writable(index)?;
+    }
+
+    let warm = is_warm_index(indices, index_name).await;
+    let removed = if warm {
+        let removed = load_backend_document(backend, index_name, id).await?;
+        if removed.is_none() {
+            warn!("Document '{}' not found in index '{}'", id, index_name);
+            return Err(GbsError::DocumentNotFound(id.to_string()));
+        }
+        removed
+    } else {
+        None
+    };
 
     // Delete from backend if available
     if let Some(backend) = backend {
@@ -122,51 +380,475 @@ pub async fn delete_document(
         GbsError::IndexNotFound(index_name.to_string())
     })?;
 
-    index.documents.remove(id).ok_or_else(|| {
-        warn!("Document '{}' not found in index '{}'", id, index_name);
-        GbsError::DocumentNotFound(id.to_string())
-    })?;
+    match (index.is_warm(), removed) {
+        (true, Some(removed)) => index.record_evicted_delete(&removed),
+        _ => {
+            index.remove_document(id).ok_or_else(|| {
+                warn!("Document '{}' not found in index '{}'", id, index_name);
+                GbsError::DocumentNotFound(id.to_string())
+            })?;
+        }
+    }
+    let change = index.changes.append(ChangeOp::Delete, id);
+    let version = index.remove_version(id);
+    index.operations.record_delete(1, start_time.elapsed());
+    persist_changes(backend, index_name, vec![change.clone()]).await;
+    persist_versions(backend, index_name, vec![(id.to_string(), None)]).await;
 
     info!("Document '{}' deleted from index '{}'", id, index_name);
-    Ok(())
+    Ok(WriteOutcome {
+        index: index_name.to_string(),
+        change,
+        version,
+    })
 }
 
+/// Outcome of a bulk action: index, document ID, status, result, the
+/// `_seq_no` of the write and the version of the document after it
+pub type BulkItemOutcome = (
+    String,
+    String,
+    u16,
+    Option<String>,
+    Option<u64>,
+    Option<u64>,
+);
+
+/// Outcome of a bulk action, or why it failed
+pub type BulkItemResult = Result<BulkItemOutcome>;
+
+/// Number of bulk actions written under one lock and in one backend batch
+const BULK_BATCH_SIZE: usize = 1000;
+
 /// Execute a bulk action
 pub async fn execute_bulk_action(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
     action: BulkAction,
-) -> Result<(String, String, u16, Option<String>)> {
-    match action {
+) -> BulkItemResult {
+    execute_bulk(indices, backend, limits, routes, templates, vec![action])
+        .await
+        .pop()
+        .expect("one result per bulk action")
+}
+
+/// Execute bulk actions in order, returning one result per action
+///
+/// Actions are applied in batches: each batch reads the documents it replaces
+/// in warm indices, then takes the index write lock once and persists its
+/// documents in a single atomic backend write. A failed action
+/// does not affect the others; a failed backend write fails every action of
+/// its batch.
+pub async fn execute_bulk(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
+    actions: Vec<BulkAction>,
+) -> Vec<BulkItemResult> {
+    let mut results = Vec::with_capacity(actions.len());
+    let mut actions = actions.into_iter().peekable();
+    while actions.peek().is_some() {
+        let batch: Vec<BulkAction> = actions.by_ref().take(BULK_BATCH_SIZE).collect();
+        results
+            .extend(execute_bulk_batch(indices, backend, limits, routes, templates, batch).await);
+    }
+    results
+}
+
+/// Why a transaction was not applied
+#[derive(Debug)]
+pub struct TransactionAbort {
+    /// Position of the operation that failed; `None` if the transaction as a
+    /// whole failed (unknown index, failed backend write)
+    pub operation: Option<usize>,
+    pub error: GbsError,
+}
+
+impl TransactionAbort {
+    fn whole(error: GbsError) -> Self {
+        Self {
+            operation: None,
+            error,
+        }
+    }
+}
+
+/// Apply actions on one index atomically: all of them or none
+///
+/// Unlike bulk, the first failed action aborts the transaction before anything
+/// is written. The writes are applied under one index write lock and persisted
+/// in a single backend batch. Ingest routes and automatic rollover do not
+/// apply; every action must target the index `index_name` writes to, by its
+/// name or an alias.
+pub async fn execute_transaction(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    actions: Vec<BulkAction>,
+) -> std::result::Result<Vec<BulkItemOutcome>, TransactionAbort> {
+    let prefetched = PrefetchedDocuments::read(indices, backend, actions.iter()).await;
+    let mut indices_guard = indices.write().await;
+    let Some(write_index) = resolve_write_index(&indices_guard, index_name) else {
+        return Err(TransactionAbort::whole(GbsError::IndexNotFound(
+            index_name.to_string(),
+        )));
+    };
+    if let Some(index) = indices_guard.get(&write_index) {
+        ensure_writable(index).map_err(TransactionAbort::whole)?;
+    }
+
+    let mut staged = StagedWrites::default();
+    let mut results = Vec::with_capacity(actions.len());
+    for (item, mut action) in actions.into_iter().enumerate() {
+        let action_index = match &mut action {
+            BulkAction::Index { index, .. }
+            | BulkAction::Create { index, .. }
+            | BulkAction::Update { index, .. }
+            | BulkAction::Delete { index, .. } => index,
+        };
+        // Actions may name the index or an alias of it, and write to the index
+        if resolve_write_index(&indices_guard, action_index).as_ref() != Some(&write_index) {
+            return Err(TransactionAbort {
+                operation: Some(item),
+                error: GbsError::InvalidRequest(format!(
+                    "Transaction on index [{}] cannot write to index [{}]",
+                    index_name, action_index
+                )),
+            });
+        }
+        action_index.clone_from(&write_index);
+        let (target, id, status, result, _) = stage_bulk_action(
+            &indices_guard,
+            backend,
+            &prefetched,
+            &mut staged,
+            item,
+            action,
+        )
+        .await
+        .map_err(|error| TransactionAbort {
+            operation: Some(item),
+            error,
+        })?;
+        results.push(Some(Ok((target, id, status, result, None, None))));
+    }
+
+    debug!(
+        "Committing transaction of {} writes on index '{}'",
+        results.len(),
+        index_name
+    );
+    commit_staged(&mut indices_guard, backend, staged, &mut results).await;
+    results
+        .into_iter()
+        .map(|result| {
+            result
+                .ok_or_else(|| {
+                    GbsError::Storage("transaction action left without a result".to_string())
+                })
+                .and_then(|result| result)
+                .map_err(TransactionAbort::whole)
+        })
+        .collect()
+}
+
+/// Document writes staged under the write lock, not yet persisted or applied
+#[derive(Default)]
+struct StagedWrites {
+    writes: Vec<DocumentWrite>,
+    /// Result slot, for warm indices the replaced document, and the mappings
+    /// of the new fields of each write
+    items: Vec<StagedItem>,
+    /// Latest staged write of each (index, document ID)
+    latest: HashMap<(String, String), usize>,
+}
+
+type StagedItem = (usize, Option<serde_json::Value>, Option<serde_json::Value>);
+
+impl StagedWrites {
+    fn stage(
+        &mut self,
+        item: usize,
+        previous: Option<serde_json::Value>,
+        new_fields: Option<serde_json::Value>,
+        write: DocumentWrite,
+    ) {
+        let key = match &write {
+            DocumentWrite::Store { index, id, .. } | DocumentWrite::Delete { index, id } => {
+                (index.clone(), id.clone())
+            }
+        };
+        self.latest.insert(key, self.writes.len());
+        self.writes.push(write);
+        self.items.push((item, previous, new_fields));
+    }
+
+    /// The staged state of a document: `None` if untouched, `Some(None)` if deleted
+    fn document(&self, index: &str, id: &str) -> Option<Option<&serde_json::Value>> {
+        let position = self.latest.get(&(index.to_string(), id.to_string()))?;
+        Some(match &self.writes[*position] {
+            DocumentWrite::Store { document, .. } => Some(document),
+            DocumentWrite::Delete { .. } => None,
+        })
+    }
+}
+
+/// Documents of warm indices read from the backend before a batch takes the
+/// write lock, so that the lock is not held while the disk is read
+#[derive(Default)]
+struct PrefetchedDocuments {
+    /// Epoch of each index when its documents were read; the writes made
+    /// since start new epochs, which leave the documents stale
+    epochs: HashMap<String, u64>,
+    documents: HashMap<(String, String), Option<serde_json::Value>>,
+}
+
+impl PrefetchedDocuments {
+    /// Read the documents that actions on warm indices replace or delete
+    ///
+    /// Documents that fail to read are left to the batch, which reads them
+    /// again under the lock.
+    async fn read<'a>(
+        indices: &Arc<RwLock<HashMap<String, Index>>>,
+        backend: &Option<Arc<SledBackend>>,
+        actions: impl Iterator<Item = &'a BulkAction>,
+    ) -> Self {
+        let mut prefetched = Self::default();
+        let mut wanted = Vec::new();
+        {
+            let indices_guard = indices.read().await;
+            for action in actions {
+                let (target, id) = match action {
+                    BulkAction::Index {
+                        index,
+                        id: Some(id),
+                        ..
+                    }
+                    | BulkAction::Create {
+                        index,
+                        id: Some(id),
+                        ..
+                    }
+                    | BulkAction::Update { index, id, .. }
+                    | BulkAction::Delete { index, id } => (index, id),
+                    _ => continue,
+                };
+                let Some(index_name) = resolve_write_index(&indices_guard, target) else {
+                    continue;
+                };
+                let Some(index) = indices_guard.get(&index_name).filter(|i| i.is_warm()) else {
+                    continue;
+                };
+                prefetched.epochs.insert(index_name.clone(), index.epoch());
+                wanted.push((index_name, id.clone()));
+            }
+        }
+        for (index_name, id) in wanted {
+            if prefetched
+                .documents
+                .contains_key(&(index_name.clone(), id.clone()))
+            {
+                continue;
+            }
+            if let Ok(document) = load_backend_document(backend, &index_name, &id).await {
+                prefetched.documents.insert((index_name, id), document);
+            }
+        }
+        prefetched
+    }
+
+    /// A document read before the lock was taken: `None` if it was not read
+    /// or the index was written since, `Some(None)` if it does not exist
+    fn document(
+        &self,
+        index: &Index,
+        index_name: &str,
+        id: &str,
+    ) -> Option<Option<&serde_json::Value>> {
+        if self.epochs.get(index_name) != Some(&index.epoch()) {
+            return None;
+        }
+        self.documents
+            .get(&(index_name.to_string(), id.to_string()))
+            .map(Option::as_ref)
+    }
+}
+
+async fn execute_bulk_batch(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
+    actions: Vec<BulkAction>,
+) -> Vec<BulkItemResult> {
+    // Routing may create indices, so it happens before the write lock is taken
+    let mut routed = Vec::with_capacity(actions.len());
+    for action in actions {
+        routed.push(route_bulk_action(indices, backend, limits, routes, templates, action).await);
+    }
+
+    let prefetched = PrefetchedDocuments::read(indices, backend, routed.iter().flatten()).await;
+    let mut results: Vec<Option<BulkItemResult>> = Vec::with_capacity(routed.len());
+    let mut staged = StagedWrites::default();
+    let mut indices_guard = indices.write().await;
+
+    for (item, action) in routed.into_iter().enumerate() {
+        let outcome = match action {
+            Ok(action) => {
+                stage_bulk_action(
+                    &indices_guard,
+                    backend,
+                    &prefetched,
+                    &mut staged,
+                    item,
+                    action,
+                )
+                .await
+            }
+            Err(e) => Err(e),
+        };
+        let rollover = match &outcome {
+            // Writes through an alias may have to roll the index over, which
+            // needs the write applied first
+            Ok((target, _, _, _, Some(index_name))) if limits.has_rollover() => {
+                Some((target.clone(), index_name.clone()))
+            }
+            _ => None,
+        };
+        results.push(Some(outcome.map(|(target, id, status, result, _)| {
+            (target, id, status, result, None, None)
+        })));
+
+        if let Some((alias, index_name)) = rollover {
+            commit_staged(
+                &mut indices_guard,
+                backend,
+                std::mem::take(&mut staged),
+                &mut results,
+            )
+            .await;
+            if indices_guard
+                .get(&index_name)
+                .is_some_and(|index| limits.needs_rollover(index))
+            {
+                if let Err(e) =
+                    rollover_index(&mut indices_guard, backend, limits, &alias, &index_name).await
+                {
+                    warn!(
+                        "Automatic rollover of alias '{}' from index '{}' failed: {}",
+                        alias, index_name, e
+                    );
+                }
+            }
+        }
+    }
+    commit_staged(&mut indices_guard, backend, staged, &mut results).await;
+
+    results
+        .into_iter()
+        .map(|result| result.expect("every bulk action has a result"))
+        .collect()
+}
+
+/// Resolve the ingest route of a bulk action that writes a document
+async fn route_bulk_action(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
+    action: BulkAction,
+) -> Result<BulkAction> {
+    Ok(match action {
+        BulkAction::Index {
+            index,
+            id,
+            document,
+        } => BulkAction::Index {
+            index: route_document(
+                indices, backend, limits, routes, templates, &index, &document,
+            )
+            .await?,
+            id,
+            document,
+        },
+        BulkAction::Create {
+            index,
+            id,
+            document,
+        } => BulkAction::Create {
+            index: route_document(
+                indices, backend, limits, routes, templates, &index, &document,
+            )
+            .await?,
+            id,
+            document,
+        },
+        BulkAction::Update {
+            index,
+            id,
+            document,
+        } => BulkAction::Update {
+            index: route_document(
+                indices, backend, limits, routes, templates, &index, &document,
+            )
+            .await?,
+            id,
+            document,
+        },
+        delete @ BulkAction::Delete { .. } => delete,
+    })
+}
+
+/// Check a bulk action against the current and staged documents and stage its write
+///
+/// Returns the item result and, for writes through an alias, the concrete index.
+async fn stage_bulk_action(
+    indices_guard: &HashMap<String, Index>,
+    backend: &Option<Arc<SledBackend>>,
+    prefetched: &PrefetchedDocuments,
+    staged: &mut StagedWrites,
+    item: usize,
+    action: BulkAction,
+) -> Result<(String, String, u16, Option<String>, Option<String>)> {
+    // Generated IDs are new, so there is no document to look up for them
+    let generated = matches!(
+        action,
+        BulkAction::Index { id: None, .. } | BulkAction::Create { id: None, .. }
+    );
+    let (target, id, document, status, result) = match action {
         BulkAction::Index {
             index,
             id,
             document,
         } => {
-            let doc_id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
-            index_document(indices, backend, &index, &doc_id, document).await?;
-            Ok((index, doc_id, 201, Some("created".to_string())))
+            let id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
+            (index, id, Some(document), 201, "created")
         }
         BulkAction::Create {
             index,
             id,
             document,
         } => {
-            let doc_id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
-            // Check if document exists
-            if index_exists(indices, &index).await? {
-                let indices_guard = indices.read().await;
-                if let Some(idx) = indices_guard.get(&index) {
-                    if idx.documents.contains_key(&doc_id) {
-                        return Err(GbsError::InvalidRequest(format!(
-                            "Document {} already exists",
-                            doc_id
-                        )));
-                    }
-                }
+            let id = id.unwrap_or_else(|| Uuid::new_v4().to_string());
+            let index_name = write_index(indices_guard, &index, &id)?;
+            if !generated
+                && staged_document(indices_guard, backend, prefetched, staged, &index_name, &id)
+                    .await?
+                    .is_some()
+            {
+                return Err(GbsError::VersionConflict {
+                    id,
+                    reason: "document already exists".to_string(),
+                });
             }
-            index_document(indices, backend, &index, &doc_id, document).await?;
-            Ok((index, doc_id, 201, Some("created".to_string())))
+            (index, id, Some(document), 201, "created")
         }
         BulkAction::Update {
             index,
@@ -174,40 +856,252 @@ pub async fn execute_bulk_action(
             document,
         } => {
             // For update, we merge with existing document or create new
-            let indices_guard = indices.read().await;
-            let existing = indices_guard
-                .get(&index)
-                .and_then(|idx| idx.documents.get(&id).cloned());
-            drop(indices_guard);
-
-            let updated_doc = if let Some(mut existing_doc) = existing {
-                // Merge: if document is an object, merge fields
-                if let (Some(existing_obj), Some(new_obj)) =
-                    (existing_doc.as_object_mut(), document.as_object())
-                {
-                    for (k, v) in new_obj {
-                        existing_obj.insert(k.clone(), v.clone());
-                    }
-                    serde_json::Value::Object(existing_obj.clone())
-                } else {
-                    document
+            let index_name = write_index(indices_guard, &index, &id)?;
+            let existing =
+                staged_document(indices_guard, backend, prefetched, staged, &index_name, &id)
+                    .await?;
+            let updated_doc = match (existing, document) {
+                // Merge: if both are objects, merge fields
+                (
+                    Some(serde_json::Value::Object(mut existing_obj)),
+                    serde_json::Value::Object(new_obj),
+                ) => {
+                    existing_obj.extend(new_obj);
+                    serde_json::Value::Object(existing_obj)
                 }
-            } else {
-                document
+                (_, document) => document,
             };
+            (index, id, Some(updated_doc), 200, "updated")
+        }
+        BulkAction::Delete { index, id } => (index, id, None, 200, "deleted"),
+    };
 
-            index_document(indices, backend, &index, &id, updated_doc).await?;
-            Ok((index, id, 200, Some("updated".to_string())))
+    let index_name = write_index(indices_guard, &target, &id)?;
+    let warm = indices_guard
+        .get(&index_name)
+        .is_some_and(|index| index.is_warm());
+    let new_fields = match (&document, indices_guard.get(&index_name)) {
+        (Some(document), Some(index)) => {
+            validate_document(index, &id, document)?;
+            validate_percolator_queries(index, &id, document)?;
+            new_field_mappings(index.mappings.as_ref(), document)?
         }
-        BulkAction::Delete { index, id } => {
-            delete_document(indices, backend, &index, &id).await?;
-            Ok((index, id, 200, Some("deleted".to_string())))
+        _ => None,
+    };
+    // Warm indices account for replaced documents, which only the disk has
+    let previous = if !generated && (warm || document.is_none()) {
+        staged_document(indices_guard, backend, prefetched, staged, &index_name, &id).await?
+    } else {
+        None
+    };
+
+    let write = match document {
+        Some(document) => DocumentWrite::Store {
+            index: index_name.clone(),
+            id: id.clone(),
+            document,
+        },
+        None => {
+            if previous.is_none() {
+                warn!("Document '{}' not found in index '{}'", id, index_name);
+                return Err(GbsError::DocumentNotFound(id));
+            }
+            DocumentWrite::Delete {
+                index: index_name.clone(),
+                id: id.clone(),
+            }
         }
+    };
+    staged.stage(item, previous.filter(|_| warm), new_fields, write);
+
+    let through_alias = (target != index_name).then_some(index_name);
+    Ok((target, id, status, Some(result.to_string()), through_alias))
+}
+
+/// Resolve the index a bulk write goes to
+fn write_index(indices_guard: &HashMap<String, Index>, target: &str, id: &str) -> Result<String> {
+    let index_name = resolve_write_index(indices_guard, target).ok_or_else(|| {
+        error!(
+            "Index '{}' not found when indexing document '{}'",
+            target, id
+        );
+        GbsError::IndexNotFound(target.to_string())
+    })?;
+    if let Some(index) = indices_guard.get(&index_name) {
+        ensure_writable(index)?;
+    }
+    Ok(index_name)
+}
+
+/// Look up a document, taking writes staged earlier in the batch into account
+async fn staged_document(
+    indices_guard: &HashMap<String, Index>,
+    backend: &Option<Arc<SledBackend>>,
+    prefetched: &PrefetchedDocuments,
+    staged: &StagedWrites,
+    index_name: &str,
+    id: &str,
+) -> Result<Option<serde_json::Value>> {
+    if let Some(document) = staged.document(index_name, id) {
+        return Ok(document.cloned());
+    }
+    match indices_guard.get(index_name) {
+        Some(index) if index.is_warm() => match prefetched.document(index, index_name, id) {
+            Some(document) => Ok(document.cloned()),
+            None => load_backend_document(backend, index_name, id).await,
+        },
+        Some(index) => Ok(index.documents.get(id).cloned()),
+        None => Ok(None),
     }
 }
 
-// Helper function for index_exists (needed by execute_bulk_action)
-async fn index_exists(indices: &Arc<RwLock<HashMap<String, Index>>>, name: &str) -> Result<bool> {
-    let indices_guard = indices.read().await;
-    Ok(indices_guard.contains_key(name))
+/// Persist staged writes in one backend batch, then apply them in memory
+///
+/// If the backend write fails, every staged action fails and memory is left
+/// unchanged.
+async fn commit_staged(
+    indices_guard: &mut HashMap<String, Index>,
+    backend: &Option<Arc<SledBackend>>,
+    staged: StagedWrites,
+    results: &mut [Option<BulkItemResult>],
+) {
+    let StagedWrites { writes, items, .. } = staged;
+    if writes.is_empty() {
+        return;
+    }
+    let start_time = std::time::Instant::now();
+    let write_count = writes.len();
+
+    let writes = match backend {
+        Some(backend) => {
+            let backend = backend.clone();
+            let persisted = tokio::task::spawn_blocking(move || {
+                backend.apply_document_writes(&writes).map(|_| writes)
+            })
+            .await
+            .map_err(GbsError::TaskJoin)
+            .and_then(|persisted| persisted);
+            match persisted {
+                Ok(writes) => writes,
+                Err(e) => {
+                    error!(
+                        "Failed to persist batch of {} bulk writes: {}",
+                        items.len(),
+                        e
+                    );
+                    for (item, _, _) in &items {
+                        results[*item] = Some(Err(GbsError::Storage(e.to_string())));
+                    }
+                    return;
+                }
+            }
+        }
+        None => writes,
+    };
+    debug!("Applying {} bulk writes", writes.len());
+
+    let mut changes: HashMap<String, Vec<Change>> = HashMap::new();
+    let mut versions: HashMap<String, Vec<(String, Option<u64>)>> = HashMap::new();
+    let mut remapped: Vec<String> = Vec::new();
+    // Documents stored and deleted per index
+    let mut counts: HashMap<String, (u64, u64)> = HashMap::new();
+    for (write, (item, previous, new_fields)) in writes.into_iter().zip(items) {
+        let (index_name, id) = match &write {
+            DocumentWrite::Store { index, id, .. } | DocumentWrite::Delete { index, id } => {
+                (index.clone(), id.clone())
+            }
+        };
+        let Some(index) = indices_guard.get_mut(&index_name) else {
+            results[item] = Some(Err(GbsError::IndexNotFound(index_name)));
+            continue;
+        };
+        let count = counts.entry(index_name.clone()).or_default();
+        match write {
+            DocumentWrite::Store { .. } => count.0 += 1,
+            DocumentWrite::Delete { .. } => count.1 += 1,
+        }
+        let op = match (write, index.is_warm()) {
+            (DocumentWrite::Store { document, .. }, true) => {
+                index.record_evicted_write(previous.as_ref(), &document);
+                write_op(previous.is_some())
+            }
+            (DocumentWrite::Store { document, .. }, false) => {
+                let op = write_op(index.documents.contains_key(&id));
+                index.insert_document(id.clone(), document);
+                op
+            }
+            (DocumentWrite::Delete { .. }, true) => {
+                if let Some(previous) = &previous {
+                    index.record_evicted_delete(previous);
+                }
+                ChangeOp::Delete
+            }
+            (DocumentWrite::Delete { .. }, false) => {
+                index.remove_document(&id);
+                ChangeOp::Delete
+            }
+        };
+        if let Some(new_fields) = new_fields {
+            add_field_mappings(index, new_fields);
+            if !remapped.contains(&index_name) {
+                remapped.push(index_name.clone());
+            }
+        }
+        let change = index.changes.append(op, &id);
+        let (version, stored_version) = match op {
+            ChangeOp::Delete => (index.remove_version(&id), None),
+            _ => {
+                let version = index.bump_version(&id, op == ChangeOp::Update);
+                (version, Some(version))
+            }
+        };
+        // Whether a write created or replaced a document is only known once
+        // it is applied
+        if let Some(Ok((_, _, status, result, seq_no, item_version))) = &mut results[item] {
+            *status = match change.op {
+                ChangeOp::Create => 201,
+                _ => 200,
+            };
+            *result = Some(change.op.result().to_string());
+            *seq_no = Some(change.seq_no());
+            *item_version = Some(version);
+        }
+        versions
+            .entry(index_name.clone())
+            .or_default()
+            .push((id, stored_version));
+        changes.entry(index_name).or_default().push(change);
+    }
+
+    // The batch is persisted at once; its time is shared out per document
+    let elapsed = start_time.elapsed();
+    let share = |count: u64| elapsed.mul_f64(count as f64 / write_count as f64);
+    for (index_name, (stored, deleted)) in counts {
+        if let Some(index) = indices_guard.get(&index_name) {
+            index.operations.record_index(stored, share(stored));
+            index.operations.record_delete(deleted, share(deleted));
+        }
+    }
+    for (index_name, changes) in changes {
+        persist_changes(backend, &index_name, changes).await;
+    }
+    for (index_name, versions) in versions {
+        persist_versions(backend, &index_name, versions).await;
+    }
+    for index_name in remapped {
+        debug!(
+            "Adding new fields to the mappings of index '{}'",
+            index_name
+        );
+        if let Some(index) = indices_guard.get(&index_name) {
+            // The documents are written; mappings are inferred again from
+            // later documents if persisting them fails
+            if let Err(e) = persist_index_metadata(backend, index).await {
+                warn!(
+                    "Failed to persist mappings of index '{}': {}",
+                    index_name, e
+                );
+            }
+        }
+    }
 }
@@ -0,0 +1,48 @@
+//! Durability of document writes
+//!
+//! In `request` mode every write request is flushed to disk before it is
+//! acknowledged, so a crash cannot lose acknowledged writes. In `async` mode
+//! a background task flushes at a fixed interval, bounding the writes a
+//! crash can lose to those of the last interval.
+
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+use crate::config::Durability;
+use crate::error::Result;
+use crate::storage::persistence::flush;
+use crate::storage::Storage;
+use crate::storage_backend::SledBackend;
+
+/// Flush the writes of a request before acknowledging it, in `request` mode
+pub async fn sync_request(
+    backend: &Option<Arc<SledBackend>>,
+    durability: Durability,
+) -> Result<()> {
+    if durability == Durability::Request {
+        flush(backend).await?;
+    }
+    Ok(())
+}
+
+/// Periodically flush pending writes to disk
+pub fn spawn_durability_flusher(
+    storage: Storage,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    info!(
+        "Background flushing enabled: pending writes are flushed every {:?}",
+        interval
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match storage.flush().await {
+                Ok(()) => debug!("Flushed pending writes"),
+                Err(e) => warn!("Background flush failed: {}", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,298 @@
+//! Dynamic mapping of the fields documents introduce
+//!
+//! When a document is written, the fields its index mappings do not define
+//! are added to them, typed the way Elasticsearch's dynamic mapping types
+//! them: strings as `text` with a `keyword` sub-field (or `date` when they
+//! parse as a date), integers as `long`, other numbers as `double`, booleans
+//! as `boolean` and objects as `object` with their own properties.
+//!
+//! The `dynamic` mapping parameter, at the top of the mappings or on an
+//! object field (and inherited by its sub-fields), controls this:
+//!
+//! - `true` (default): new fields are added to the mappings
+//! - `false`: new fields are kept in `_source` but not mapped
+//! - `strict`: documents with new fields are rejected
+//!
+//! `"date_detection": false` at the top of the mappings maps date strings as
+//! text.
+
+use crate::error::{GbsError, Result};
+use crate::storage::search::parse_date;
+use crate::storage::Index;
+
+/// Handling of fields the mappings do not define
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DynamicMode {
+    /// Add new fields to the mappings
+    #[default]
+    True,
+    /// Keep new fields in `_source` only
+    False,
+    /// Reject documents with new fields
+    Strict,
+}
+
+impl DynamicMode {
+    /// Mode of a `dynamic` mapping parameter (`true`, `false`, `"strict"`,
+    /// or `"runtime"`, which is treated like `false`)
+    pub fn parse(value: &serde_json::Value) -> Result<Self> {
+        match value {
+            serde_json::Value::Bool(true) => Ok(Self::True),
+            serde_json::Value::Bool(false) => Ok(Self::False),
+            serde_json::Value::String(mode) => match mode.as_str() {
+                "true" => Ok(Self::True),
+                "false" | "runtime" => Ok(Self::False),
+                "strict" => Ok(Self::Strict),
+                _ => Err(invalid_mode(value)),
+            },
+            _ => Err(invalid_mode(value)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::True => "true",
+            Self::False => "false",
+            Self::Strict => "strict",
+        }
+    }
+}
+
+fn invalid_mode(value: &serde_json::Value) -> GbsError {
+    GbsError::InvalidRequest(format!(
+        "Invalid [dynamic] mapping value [{}]: expected true, false or strict",
+        value
+    ))
+}
+
+/// Type dynamic mapping gives a value, or `None` for nulls and arrays
+pub fn dynamic_field_type(value: &serde_json::Value, date_detection: bool) -> Option<&'static str> {
+    Some(match value {
+        serde_json::Value::Null | serde_json::Value::Array(_) => return None,
+        serde_json::Value::Object(_) => "object",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(n) if n.is_f64() => "double",
+        serde_json::Value::Number(_) => "long",
+        serde_json::Value::String(s)
+            if date_detection && s.parse::<f64>().is_err() && parse_date(value).is_some() =>
+        {
+            "date"
+        }
+        serde_json::Value::String(_) => "text",
+    })
+}
+
+/// Mappings of the fields of a document that `mappings` do not define
+///
+/// Returns them as `properties` to merge into the mappings' own, or `None`
+/// if the document brings no new mapped fields. Fails if the document has a
+/// field where the mappings are `strict`.
+pub fn new_field_mappings(
+    mappings: Option<&serde_json::Value>,
+    document: &serde_json::Value,
+) -> Result<Option<serde_json::Value>> {
+    let Some(fields) = document.as_object() else {
+        return Ok(None);
+    };
+    let root = mappings.map(mapping_root);
+    let mode = match root.and_then(|root| root.get("dynamic")) {
+        Some(dynamic) => DynamicMode::parse(dynamic)?,
+        None => DynamicMode::default(),
+    };
+    let date_detection = root
+        .and_then(|root| root.get("date_detection"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(true);
+    let properties = root
+        .and_then(|root| root.get("properties"))
+        .and_then(|p| p.as_object());
+
+    let mut added = serde_json::Map::new();
+    collect_new_fields(properties, mode, date_detection, "", fields, &mut added)?;
+    Ok((!added.is_empty()).then_some(serde_json::Value::Object(added)))
+}
+
+/// Merge the mappings of new fields (from `new_field_mappings`) into the
+/// mappings of an index
+///
+/// Fields mapped in the meantime keep their mapping.
+pub fn add_field_mappings(index: &mut Index, new_fields: serde_json::Value) {
+    let mappings = index
+        .mappings
+        .get_or_insert_with(|| serde_json::json!({ "properties": {} }));
+    let root = mapping_root_mut(mappings);
+    let Some(root) = root.as_object_mut() else {
+        return;
+    };
+    let properties = root
+        .entry("properties")
+        .or_insert_with(|| serde_json::json!({}));
+    merge_missing(properties, new_fields);
+}
+
+/// Set the `dynamic` parameter at the top of the mappings of an index
+pub fn set_dynamic_mode(index: &mut Index, mode: DynamicMode) {
+    let mappings = index
+        .mappings
+        .get_or_insert_with(|| serde_json::json!({ "properties": {} }));
+    if let Some(root) = mapping_root_mut(mappings).as_object_mut() {
+        root.insert("dynamic".to_string(), serde_json::json!(mode.as_str()));
+    }
+}
+
+/// Whether a mapping root is an ES 6 typed mapping (`{"_doc": {"properties": ...}}`)
+fn is_typed(mappings: &serde_json::Value) -> bool {
+    mappings.as_object().is_some_and(|obj| {
+        obj.len() == 1
+            && obj.iter().all(|(name, mapping)| {
+                !matches!(name.as_str(), "properties" | "dynamic" | "date_detection")
+                    && mapping.get("properties").is_some()
+            })
+    })
+}
+
+/// The mapping holding `properties`, also of ES 6 typed mappings
+pub fn mapping_root(mappings: &serde_json::Value) -> &serde_json::Value {
+    match mappings.as_object() {
+        Some(obj) if is_typed(mappings) => obj.values().next().unwrap_or(mappings),
+        _ => mappings,
+    }
+}
+
+fn mapping_root_mut(mappings: &mut serde_json::Value) -> &mut serde_json::Value {
+    if !is_typed(mappings) {
+        return mappings;
+    }
+    match mappings {
+        serde_json::Value::Object(obj) => obj
+            .values_mut()
+            .next()
+            .expect("typed mappings have a single type"),
+        mappings => mappings,
+    }
+}
+
+fn collect_new_fields(
+    properties: Option<&serde_json::Map<String, serde_json::Value>>,
+    mode: DynamicMode,
+    date_detection: bool,
+    prefix: &str,
+    fields: &serde_json::Map<String, serde_json::Value>,
+    added: &mut serde_json::Map<String, serde_json::Value>,
+) -> Result<()> {
+    for (name, value) in fields {
+        let path = format!("{}{}", prefix, name);
+        match properties.and_then(|properties| properties.get(name)) {
+            // Mapped objects may have new sub-fields
+            Some(mapping) => {
+                let sub_properties = mapping.get("properties").and_then(|p| p.as_object());
+                let is_object = sub_properties.is_some()
+                    || matches!(
+                        mapping.get("type").and_then(|t| t.as_str()),
+                        Some("object") | Some("nested")
+                    );
+                if !is_object || mapping.get("enabled").and_then(|v| v.as_bool()) == Some(false) {
+                    continue;
+                }
+                let mode = match mapping.get("dynamic") {
+                    Some(dynamic) => DynamicMode::parse(dynamic)?,
+                    None => mode,
+                };
+                let mut sub_added = serde_json::Map::new();
+                for object in objects(value) {
+                    collect_new_fields(
+                        sub_properties,
+                        mode,
+                        date_detection,
+                        &format!("{}.", path),
+                        object,
+                        &mut sub_added,
+                    )?;
+                }
+                if !sub_added.is_empty() {
+                    insert_missing(added, name, serde_json::json!({ "properties": sub_added }));
+                }
+            }
+            None => match mode {
+                DynamicMode::False => {}
+                DynamicMode::Strict => return Err(GbsError::StrictDynamicMapping(path)),
+                DynamicMode::True => {
+                    if let Some(mapping) = infer_mapping(value, date_detection) {
+                        insert_missing(added, name, mapping);
+                    }
+                }
+            },
+        }
+    }
+    Ok(())
+}
+
+/// Mapping of a new field from its value
+fn infer_mapping(value: &serde_json::Value, date_detection: bool) -> Option<serde_json::Value> {
+    if let serde_json::Value::Array(items) = value {
+        // Arrays are mapped by their elements; objects merge their fields
+        let mut mapping: Option<serde_json::Value> = None;
+        for item in items {
+            if let Some(item_mapping) = infer_mapping(item, date_detection) {
+                match &mut mapping {
+                    Some(mapping) => merge_missing(mapping, item_mapping),
+                    None => mapping = Some(item_mapping),
+                }
+            }
+        }
+        return mapping;
+    }
+    let field_type = dynamic_field_type(value, date_detection)?;
+    Some(match (field_type, value) {
+        ("object", serde_json::Value::Object(fields)) if fields.is_empty() => {
+            serde_json::json!({ "type": "object" })
+        }
+        ("object", serde_json::Value::Object(fields)) => {
+            let properties: serde_json::Map<String, serde_json::Value> = fields
+                .iter()
+                .filter_map(|(name, value)| {
+                    infer_mapping(value, date_detection).map(|mapping| (name.clone(), mapping))
+                })
+                .collect();
+            serde_json::json!({ "properties": properties })
+        }
+        ("text", _) => serde_json::json!({
+            "type": "text",
+            "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+        }),
+        (field_type, _) => serde_json::json!({ "type": field_type }),
+    })
+}
+
+/// The objects of a value that is an object or an array of objects
+fn objects(value: &serde_json::Value) -> Vec<&serde_json::Map<String, serde_json::Value>> {
+    match value {
+        serde_json::Value::Object(object) => vec![object],
+        serde_json::Value::Array(items) => {
+            items.iter().filter_map(|item| item.as_object()).collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn insert_missing(
+    map: &mut serde_json::Map<String, serde_json::Value>,
+    name: &str,
+    mapping: serde_json::Value,
+) {
+    match map.get_mut(name) {
+        Some(existing) => merge_missing(existing, mapping),
+        None => {
+            map.insert(name.to_string(), mapping);
+        }
+    }
+}
+
+/// Merge mappings recursively; fields of `base` keep their mapping
+fn merge_missing(base: &mut serde_json::Value, extra: serde_json::Value) {
+    if let (Some(base), serde_json::Value::Object(extra)) = (base.as_object_mut(), extra) {
+        for (key, value) in extra {
+            insert_missing(base, &key, value);
+        }
+    }
+}
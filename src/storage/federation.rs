@@ -0,0 +1,393 @@
+//! Read-through federation of indices to external sources
+//!
+//! A federated index is a local cache of the documents of an external HTTP
+//! source, for searching data that should not be duplicated by hand:
+//!
+//! ```yaml
+//! federation:
+//!   indices:
+//!     - index: products
+//!       url: "http://catalog.internal:8080/api/products"
+//!       documents_path: "/items"
+//!       ttl_secs: 300
+//! ```
+//!
+//! Searches and document lookups on the index fetch the source once the
+//! cached copy is older than the TTL and replace the index's documents with
+//! the fetched ones. When a fetch fails, the cached documents keep being
+//! served until the next TTL expires.
+
+use axum::body::Bytes;
+use http_body_util::{BodyExt, Full};
+use hyper::header::{HeaderName, HeaderValue, HOST};
+use hyper::{Method, Request, Uri};
+use hyper_util::rt::TokioIo;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::bulk_ops::BulkAction;
+use crate::config::{FederatedIndexConfig, FederationConfig};
+use crate::error::{GbsError, Result};
+use crate::storage::document_ops::execute_bulk;
+use crate::storage::index_ops::create_index;
+use crate::storage::search::get_field_value;
+use crate::storage::templates::IndexTemplates;
+use crate::storage::{Index, IngestRoutes, StorageLimits};
+use crate::storage_backend::SledBackend;
+
+/// An index whose documents are fetched from an external HTTP source
+#[derive(Debug)]
+pub struct FederatedIndex {
+    /// Name of the local index
+    pub index: String,
+    /// Settings of the local index
+    pub settings: Option<serde_json::Value>,
+    /// Mappings of the local index
+    pub mappings: Option<serde_json::Value>,
+    uri: Uri,
+    method: Method,
+    body: Option<String>,
+    headers: Vec<(HeaderName, HeaderValue)>,
+    documents_path: String,
+    id_field: String,
+    ttl: Duration,
+    timeout: Duration,
+    /// When the documents were last fetched; held while fetching
+    fetched_at: Mutex<Option<Instant>>,
+}
+
+impl FederatedIndex {
+    /// Create a federated index, validating its fetch request
+    pub fn from_config(config: &FederatedIndexConfig) -> Result<Self> {
+        let invalid = |reason: String| {
+            GbsError::InvalidRequest(format!(
+                "Invalid federated index [{}]: {}",
+                config.index, reason
+            ))
+        };
+
+        let uri: Uri = config
+            .url
+            .parse()
+            .map_err(|e| invalid(format!("invalid url [{}]: {}", config.url, e)))?;
+        if uri.scheme_str() != Some("http") || uri.host().is_none() {
+            return Err(invalid(format!(
+                "url [{}] must be an absolute http:// URL",
+                config.url
+            )));
+        }
+        let method = Method::from_bytes(config.method.to_ascii_uppercase().as_bytes())
+            .map_err(|_| invalid(format!("invalid method [{}]", config.method)))?;
+        let headers = config
+            .headers
+            .iter()
+            .map(|(name, value)| {
+                Ok((
+                    HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|_| invalid(format!("invalid header name [{}]", name)))?,
+                    HeaderValue::from_str(value)
+                        .map_err(|_| invalid(format!("invalid value of header [{}]", name)))?,
+                ))
+            })
+            .collect::<Result<_>>()?;
+        if !config.documents_path.is_empty() && !config.documents_path.starts_with('/') {
+            return Err(invalid(format!(
+                "documents_path [{}] must be a JSON pointer starting with '/'",
+                config.documents_path
+            )));
+        }
+
+        Ok(Self {
+            index: config.index.clone(),
+            settings: config.settings.clone(),
+            mappings: config.mappings.clone(),
+            uri,
+            method,
+            body: config.body.clone(),
+            headers,
+            documents_path: config.documents_path.clone(),
+            id_field: config.id_field.clone(),
+            ttl: Duration::from_secs(config.ttl_secs),
+            timeout: Duration::from_secs(config.timeout_secs.max(1)),
+            fetched_at: Mutex::new(None),
+        })
+    }
+
+    /// Fetch the documents of the source, keyed by ID
+    pub async fn fetch(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let body = tokio::time::timeout(self.timeout, self.send())
+            .await
+            .map_err(|_| self.upstream(format!("timed out after {:?}", self.timeout)))??;
+        let response: serde_json::Value = serde_json::from_slice(&body)
+            .map_err(|e| self.upstream(format!("response is not JSON: {}", e)))?;
+        let documents = if self.documents_path.is_empty() {
+            response
+        } else {
+            response
+                .pointer(&self.documents_path)
+                .cloned()
+                .ok_or_else(|| {
+                    self.upstream(format!("response has no [{}]", self.documents_path))
+                })?
+        };
+        let serde_json::Value::Array(documents) = documents else {
+            return Err(self.upstream("documents are not an array".to_string()));
+        };
+
+        let mut fetched = Vec::with_capacity(documents.len());
+        let mut skipped = 0;
+        for document in documents {
+            let id = match get_field_value(&document, &self.id_field) {
+                Some(serde_json::Value::String(id)) => id.clone(),
+                Some(serde_json::Value::Number(id)) => id.to_string(),
+                _ => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            fetched.push((id, document));
+        }
+        if skipped > 0 {
+            warn!(
+                "Skipped {} documents without a [{}] ID fetched for federated index '{}'",
+                skipped, self.id_field, self.index
+            );
+        }
+        Ok(fetched)
+    }
+
+    async fn send(&self) -> Result<Bytes> {
+        let host = self.uri.host().unwrap_or_default();
+        let port = self.uri.port_u16().unwrap_or(80);
+        let stream = tokio::net::TcpStream::connect((host, port))
+            .await
+            .map_err(|e| self.upstream(format!("connection failed: {}", e)))?;
+        let (mut sender, connection) = hyper::client::conn::http1::handshake(TokioIo::new(stream))
+            .await
+            .map_err(|e| self.upstream(e.to_string()))?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                debug!("Federation connection closed with error: {}", e);
+            }
+        });
+
+        let path = self
+            .uri
+            .path_and_query()
+            .map(|path| path.as_str())
+            .unwrap_or("/");
+        let mut request = Request::builder()
+            .method(self.method.clone())
+            .uri(path)
+            .header(
+                HOST,
+                self.uri.authority().map(|a| a.as_str()).unwrap_or(host),
+            );
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+        let request = request
+            .body(Full::new(Bytes::from(
+                self.body.clone().unwrap_or_default(),
+            )))
+            .map_err(|e| self.upstream(e.to_string()))?;
+
+        let response = sender
+            .send_request(request)
+            .await
+            .map_err(|e| self.upstream(e.to_string()))?;
+        let status = response.status();
+        let body = response
+            .into_body()
+            .collect()
+            .await
+            .map_err(|e| self.upstream(e.to_string()))?
+            .to_bytes();
+        if !status.is_success() {
+            return Err(self.upstream(format!("source answered {}", status)));
+        }
+        Ok(body)
+    }
+
+    fn upstream(&self, reason: String) -> GbsError {
+        GbsError::Upstream(format!(
+            "Fetching federated index [{}] from {} failed: {}",
+            self.index, self.uri, reason
+        ))
+    }
+}
+
+/// The configured federated indices, by index name
+#[derive(Debug, Default)]
+pub struct Federation {
+    indices: HashMap<String, FederatedIndex>,
+}
+
+impl Federation {
+    /// Build the federated indices from the federation configuration
+    pub fn from_config(config: &FederationConfig) -> Result<Self> {
+        let mut federation = Self::default();
+        for index in &config.indices {
+            federation.add(FederatedIndex::from_config(index)?);
+        }
+        Ok(federation)
+    }
+
+    /// Add a federated index, replacing any with the same name
+    pub fn add(&mut self, index: FederatedIndex) {
+        self.indices.insert(index.index.clone(), index);
+    }
+
+    /// Get the federated index of an index name
+    pub fn get(&self, index_name: &str) -> Option<&FederatedIndex> {
+        self.indices.get(index_name)
+    }
+
+    /// Names of the federated indices
+    pub fn index_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.indices.keys().cloned().collect();
+        names.sort();
+        names
+    }
+}
+
+/// Create the local indices of federated indices that do not exist yet
+pub async fn create_federated_indices(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    federation: &Federation,
+) -> Result<()> {
+    for source in federation.indices.values() {
+        ensure_index(indices, backend, limits, source).await?;
+    }
+    Ok(())
+}
+
+/// Refresh the cached documents of a federated index if they are stale
+///
+/// Does nothing for other indices. A failed fetch is an error only when
+/// there are no cached documents to serve instead.
+pub async fn read_through(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    federation: &Federation,
+    index_name: &str,
+) -> Result<()> {
+    let Some(source) = federation.get(index_name) else {
+        return Ok(());
+    };
+    // Concurrent requests wait for one fetch instead of each fetching
+    let mut fetched_at = source.fetched_at.lock().await;
+    if fetched_at.is_some_and(|fetched_at| fetched_at.elapsed() < source.ttl) {
+        return Ok(());
+    }
+
+    ensure_index(indices, backend, limits, source).await?;
+    match source.fetch().await {
+        Ok(documents) => {
+            replace_documents(indices, backend, limits, source, documents).await;
+        }
+        Err(e) => {
+            let cached = indices
+                .read()
+                .await
+                .get(index_name)
+                .is_some_and(|index| index.doc_count() > 0);
+            if fetched_at.is_none() && !cached {
+                return Err(e);
+            }
+            warn!("{}; serving cached documents", e);
+        }
+    }
+    *fetched_at = Some(Instant::now());
+    Ok(())
+}
+
+async fn ensure_index(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    source: &FederatedIndex,
+) -> Result<()> {
+    if indices.read().await.contains_key(&source.index) {
+        return Ok(());
+    }
+    // The configuration defines federated indices; templates do not apply
+    create_index(
+        indices,
+        backend,
+        limits,
+        &IndexTemplates::default(),
+        &source.index,
+        source.settings.clone(),
+        source.mappings.clone(),
+    )
+    .await
+}
+
+/// Replace the documents of the local index with the fetched ones
+async fn replace_documents(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    source: &FederatedIndex,
+    documents: Vec<(String, serde_json::Value)>,
+) {
+    let fetched_ids: HashSet<&str> = documents.iter().map(|(id, _)| id.as_str()).collect();
+    let removed: Vec<String> = indices
+        .read()
+        .await
+        .get(&source.index)
+        .map(|index| {
+            index
+                .documents
+                .keys()
+                .filter(|id| !fetched_ids.contains(id.as_str()))
+                .cloned()
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let fetched = documents.len();
+    let actions = documents
+        .into_iter()
+        .map(|(id, document)| BulkAction::Index {
+            index: source.index.clone(),
+            id: Some(id),
+            document,
+        })
+        .chain(removed.iter().map(|id| BulkAction::Delete {
+            index: source.index.clone(),
+            id: id.clone(),
+        }))
+        .collect();
+    let failed = execute_bulk(
+        indices,
+        backend,
+        limits,
+        &IngestRoutes::default(),
+        &IndexTemplates::default(),
+        actions,
+    )
+    .await
+    .into_iter()
+    .filter(|result| result.is_err())
+    .count();
+    if failed > 0 {
+        warn!(
+            "Failed to cache {} documents of federated index '{}'",
+            failed, source.index
+        );
+    }
+    info!(
+        "Fetched {} documents for federated index '{}' ({} removed)",
+        fetched,
+        source.index,
+        removed.len()
+    );
+}
@@ -0,0 +1,292 @@
+//! Field capabilities (`_field_caps`)
+//!
+//! The fields of an index are the fields of its mappings plus the fields
+//! seen in its documents that the mappings leave out. Those are typed the way
+//! Elasticsearch's dynamic mapping would type them: strings as `text` with a
+//! `keyword` sub-field (or `date` when they parse as one), integers as
+//! `long`, other numbers as `double` and objects as `object`.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::error::{GbsError, Result};
+use crate::storage::dynamic_mapping::dynamic_field_type;
+use crate::storage::tiering::warm_index_backend;
+use crate::storage::{wildcard_regex, Index};
+use crate::storage_backend::SledBackend;
+
+/// Type and capabilities of a field in one index
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldCapability {
+    pub field_type: String,
+    /// Whether the field can be queried
+    pub searchable: bool,
+    /// Whether the field can be sorted on and aggregated
+    pub aggregatable: bool,
+}
+
+impl FieldCapability {
+    /// Capabilities a field of a type has by default
+    fn of_type(field_type: &str) -> Self {
+        let (searchable, aggregatable) = match field_type {
+            "text" | "match_only_text" => (true, false),
+            "object" | "nested" | "binary" | "_source" => (false, false),
+            _ => (true, true),
+        };
+        Self {
+            field_type: field_type.to_string(),
+            searchable,
+            aggregatable,
+        }
+    }
+}
+
+/// Metadata fields every index has
+const METADATA_FIELDS: [&str; 3] = ["_id", "_index", "_source"];
+
+/// Fields of an index by dotted path, with their type and capabilities
+pub async fn index_fields(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+) -> Result<BTreeMap<String, FieldCapability>> {
+    let mut fields = BTreeMap::new();
+    let mut dynamic = BTreeMap::new();
+    let is_warm = {
+        let indices_guard = indices.read().await;
+        let index = indices_guard
+            .get(index_name)
+            .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+        if let Some(properties) = index.mappings.as_ref().and_then(mapping_properties) {
+            collect_mapped_fields(properties, "", &mut fields);
+        }
+        if !index.is_warm() {
+            // In ID order, so the first document decides conflicting types
+            let mut ids: Vec<&String> = index.documents.keys().collect();
+            ids.sort();
+            for id in ids {
+                observe_fields(&index.documents[id], "", &mut dynamic);
+            }
+        }
+        index.is_warm()
+    };
+
+    if is_warm {
+        // Documents of warm indices are streamed from the backend, in ID order
+        let backend = warm_index_backend(backend, index_name)?;
+        let index_name = index_name.to_string();
+        dynamic = tokio::task::spawn_blocking(move || {
+            let mut dynamic = BTreeMap::new();
+            backend.for_each_document(&index_name, |_, doc| {
+                observe_fields(&doc, "", &mut dynamic);
+                Ok(())
+            })?;
+            Ok::<_, GbsError>(dynamic)
+        })
+        .await
+        .map_err(GbsError::TaskJoin)??;
+    }
+
+    for (path, field_type) in dynamic {
+        if fields.contains_key(&path) {
+            continue;
+        }
+        if field_type == "text" {
+            fields
+                .entry(format!("{}.keyword", path))
+                .or_insert_with(|| FieldCapability::of_type("keyword"));
+        }
+        fields.insert(path, FieldCapability::of_type(field_type));
+    }
+    for field in METADATA_FIELDS {
+        fields.insert(field.to_string(), FieldCapability::of_type(field));
+    }
+    Ok(fields)
+}
+
+/// Field capabilities response for the fields of several indices
+///
+/// `patterns` select fields by name, with `*` and `?` wildcards. A field with
+/// different types across the indices gets an entry per type listing its
+/// indices; with `include_unmapped`, indices without the field are listed
+/// under the `unmapped` type.
+pub fn field_caps_response(
+    index_fields: &[(String, BTreeMap<String, FieldCapability>)],
+    patterns: &[String],
+    include_unmapped: bool,
+) -> serde_json::Value {
+    let patterns: Vec<regex::Regex> = patterns
+        .iter()
+        .filter_map(|pattern| wildcard_regex(pattern.trim()))
+        .collect();
+    let names: BTreeSet<&String> = index_fields
+        .iter()
+        .flat_map(|(_, fields)| fields.keys())
+        .filter(|name| patterns.iter().any(|pattern| pattern.is_match(name)))
+        .collect();
+
+    let mut response_fields = serde_json::Map::new();
+    for name in names {
+        // Indices having the field, by type
+        let mut by_type: BTreeMap<&str, Vec<(&String, &FieldCapability)>> = BTreeMap::new();
+        let mut unmapped = Vec::new();
+        for (index, fields) in index_fields {
+            match fields.get(name) {
+                Some(capability) => by_type
+                    .entry(capability.field_type.as_str())
+                    .or_default()
+                    .push((index, capability)),
+                None => unmapped.push(index),
+            }
+        }
+        let list_indices = by_type.len() > 1 || (include_unmapped && !unmapped.is_empty());
+
+        let mut types = serde_json::Map::new();
+        for (field_type, indices) in &by_type {
+            let mut entry = serde_json::json!({
+                "type": field_type,
+                "searchable": indices.iter().all(|(_, c)| c.searchable),
+                "aggregatable": indices.iter().all(|(_, c)| c.aggregatable),
+            });
+            if list_indices {
+                entry["indices"] =
+                    serde_json::json!(indices.iter().map(|(index, _)| index).collect::<Vec<_>>());
+            }
+            let non_searchable: Vec<&&String> = indices
+                .iter()
+                .filter(|(_, c)| !c.searchable)
+                .map(|(index, _)| index)
+                .collect();
+            if !non_searchable.is_empty() && non_searchable.len() < indices.len() {
+                entry["non_searchable_indices"] = serde_json::json!(non_searchable);
+            }
+            let non_aggregatable: Vec<&&String> = indices
+                .iter()
+                .filter(|(_, c)| !c.aggregatable)
+                .map(|(index, _)| index)
+                .collect();
+            if !non_aggregatable.is_empty() && non_aggregatable.len() < indices.len() {
+                entry["non_aggregatable_indices"] = serde_json::json!(non_aggregatable);
+            }
+            types.insert(field_type.to_string(), entry);
+        }
+        if include_unmapped && !unmapped.is_empty() {
+            types.insert(
+                "unmapped".to_string(),
+                serde_json::json!({
+                    "type": "unmapped",
+                    "searchable": false,
+                    "aggregatable": false,
+                    "indices": unmapped
+                }),
+            );
+        }
+        response_fields.insert(name.clone(), serde_json::Value::Object(types));
+    }
+
+    serde_json::json!({
+        "indices": index_fields.iter().map(|(index, _)| index).collect::<Vec<_>>(),
+        "fields": response_fields
+    })
+}
+
+/// Properties of a mapping, also of ES 6 typed mappings
+/// (`{"_doc": {"properties": ...}}`)
+pub(crate) fn mapping_properties(mappings: &serde_json::Value) -> Option<&serde_json::Value> {
+    match mappings.get("properties") {
+        Some(properties) => Some(properties),
+        None => mappings
+            .as_object()
+            .filter(|obj| obj.len() == 1)
+            .and_then(|obj| obj.values().next())
+            .and_then(|mapping| mapping.get("properties")),
+    }
+}
+
+/// Fields of mapping properties, with object properties and multi-fields
+/// flattened to dotted paths
+fn collect_mapped_fields(
+    properties: &serde_json::Value,
+    prefix: &str,
+    fields: &mut BTreeMap<String, FieldCapability>,
+) {
+    let Some(properties) = properties.as_object() else {
+        return;
+    };
+    for (name, definition) in properties {
+        let path = format!("{}{}", prefix, name);
+        let field_type = match definition.get("type").and_then(|t| t.as_str()) {
+            Some(field_type) => field_type,
+            None if definition.get("properties").is_some() => "object",
+            None => continue,
+        };
+        let mut capability = FieldCapability::of_type(field_type);
+        if definition.get("index").and_then(|v| v.as_bool()) == Some(false) {
+            capability.searchable = false;
+        }
+        match field_type {
+            "text" => {
+                capability.aggregatable =
+                    definition.get("fielddata").and_then(|v| v.as_bool()) == Some(true);
+            }
+            _ if definition.get("doc_values").and_then(|v| v.as_bool()) == Some(false) => {
+                capability.aggregatable = false;
+            }
+            _ => {}
+        }
+        fields.insert(path.clone(), capability);
+
+        if let Some(nested) = definition.get("properties") {
+            collect_mapped_fields(nested, &format!("{}.", path), fields);
+        }
+        // Multi-fields: { "type": "text", "fields": { "keyword": { "type": "keyword" } } }
+        if let Some(multi_fields) = definition.get("fields") {
+            collect_mapped_fields(multi_fields, &format!("{}.", path), fields);
+        }
+    }
+}
+
+/// Record the dynamic types of the fields of a document
+///
+/// The first type seen for a field wins, except that `long` fields become
+/// `double` once they hold a non-integer.
+fn observe_fields(
+    value: &serde_json::Value,
+    prefix: &str,
+    fields: &mut BTreeMap<String, &'static str>,
+) {
+    let Some(obj) = value.as_object() else {
+        return;
+    };
+    for (name, value) in obj {
+        observe_field(&format!("{}{}", prefix, name), value, fields);
+    }
+}
+
+fn observe_field(
+    path: &str,
+    value: &serde_json::Value,
+    fields: &mut BTreeMap<String, &'static str>,
+) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                observe_field(path, item, fields);
+            }
+            return;
+        }
+        serde_json::Value::Object(_) => observe_fields(value, &format!("{}.", path), fields),
+        _ => {}
+    }
+    let Some(field_type) = dynamic_field_type(value, true) else {
+        return;
+    };
+    match fields.get_mut(path) {
+        Some(existing) if *existing == "long" && field_type == "double" => *existing = "double",
+        Some(_) => {}
+        None => {
+            fields.insert(path.to_string(), field_type);
+        }
+    }
+}
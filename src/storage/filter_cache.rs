@@ -0,0 +1,269 @@
+//! Cache of the documents matching filter clauses
+//!
+//! Searches with `term`, `terms`, `range` or `exists` clauses in the `filter`
+//! of a top-level `bool` query look up the IDs of the matching documents here
+//! instead of evaluating the clauses on every document. Each index has its own
+//! cache, keyed by the clause's JSON.
+//!
+//! Entries hold the index's document epoch (see `Index::epoch`). Documents
+//! appended within the epoch are added to an entry the next time it is used.
+//! Replacing or deleting a document starts a new epoch; entries that are up
+//! to date with the old one are updated for just that document and carried
+//! over, and all others are recomputed when next used.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::RwLock;
+
+use crate::error::Result;
+use crate::storage::index::Documents;
+use crate::storage::search::score_document;
+use crate::storage::Index;
+
+/// Number of clauses cached per index before the least recently used one is
+/// evicted
+const DEFAULT_CAPACITY: usize = 64;
+
+/// IDs of the documents matching a clause at a point of an index's epoch
+#[derive(Debug, Clone)]
+struct FilterCacheEntry {
+    clause: serde_json::Value,
+    /// Document epoch of the index the IDs belong to
+    epoch: u64,
+    /// Number of documents appended in the epoch that are already included
+    appended: usize,
+    ids: Arc<HashSet<String>>,
+    last_used: u64,
+}
+
+/// Usage counters of a filter cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FilterCacheStats {
+    /// Clauses answered from the cache, possibly after adding appended documents
+    pub hits: u64,
+    /// Clauses evaluated on every document
+    pub misses: u64,
+    /// Entries dropped to make room for others
+    pub evictions: u64,
+    /// Number of cached clauses
+    pub entries: usize,
+}
+
+impl std::ops::AddAssign for FilterCacheStats {
+    fn add_assign(&mut self, other: Self) {
+        self.hits += other.hits;
+        self.misses += other.misses;
+        self.evictions += other.evictions;
+        self.entries += other.entries;
+    }
+}
+
+#[derive(Debug)]
+pub struct FilterCache {
+    capacity: usize,
+    entries: Mutex<HashMap<String, FilterCacheEntry>>,
+    tick: AtomicU64,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Default for FilterCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl FilterCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(HashMap::new()),
+            tick: AtomicU64::new(0),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    /// IDs of the documents of a hot index matching a filter clause
+    pub fn matching_ids(
+        &self,
+        index: &Index,
+        clause: &serde_json::Value,
+    ) -> Result<Arc<HashSet<String>>> {
+        let key = clause.to_string();
+        let tick = self.tick.fetch_add(1, Ordering::Relaxed);
+        // The entry is taken out while it is brought up to date, so that its
+        // IDs are not copied
+        let cached = self.lock().remove(&key).filter(|entry| {
+            entry.epoch == index.epoch() && entry.appended <= index.appended_count()
+        });
+
+        let entry = match cached {
+            Some(mut entry) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                let ids = Arc::make_mut(&mut entry.ids);
+                for id in index.appended_since(entry.appended) {
+                    if let Some(doc) = index.documents.get(id) {
+                        if score_document(id, doc, clause)?.is_some() {
+                            ids.insert(id.clone());
+                        }
+                    }
+                }
+                entry.appended = index.appended_count();
+                entry.last_used = tick;
+                entry
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                let mut ids = HashSet::new();
+                for (id, doc) in index.documents.iter() {
+                    if score_document(id, doc, clause)?.is_some() {
+                        ids.insert(id.clone());
+                    }
+                }
+                FilterCacheEntry {
+                    clause: clause.clone(),
+                    epoch: index.epoch(),
+                    appended: index.appended_count(),
+                    ids: Arc::new(ids),
+                    last_used: tick,
+                }
+            }
+        };
+        let ids = entry.ids.clone();
+        self.insert(key, entry);
+        Ok(ids)
+    }
+
+    fn insert(&self, key: String, entry: FilterCacheEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+                self.evictions.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        entries.insert(key, entry);
+    }
+
+    /// Carry the entries of an epoch over to the next one, which starts with
+    /// a change of the document `id` (`None` if it was removed)
+    ///
+    /// `appended` and `documents` are the IDs appended in the old epoch and
+    /// the documents after the change.
+    pub fn advance_epoch(
+        &self,
+        from: u64,
+        appended: &[String],
+        documents: &Documents,
+        to: u64,
+        id: &str,
+        document: Option<&serde_json::Value>,
+    ) {
+        let matches = |clause: &serde_json::Value, id: &str, doc: &serde_json::Value| {
+            score_document(id, doc, clause).map(|score| score.is_some())
+        };
+        self.lock().retain(|_, entry| {
+            if entry.epoch != from || entry.appended > appended.len() {
+                return false;
+            }
+            let clause = &entry.clause;
+            let ids = Arc::make_mut(&mut entry.ids);
+            for appended_id in &appended[entry.appended..] {
+                let Some(doc) = documents.get(appended_id) else {
+                    continue;
+                };
+                match matches(clause, appended_id, doc) {
+                    Ok(true) => {
+                        ids.insert(appended_id.clone());
+                    }
+                    Ok(false) => {}
+                    Err(_) => return false,
+                }
+            }
+            ids.remove(id);
+            if let Some(doc) = document {
+                match matches(clause, id, doc) {
+                    Ok(true) => {
+                        ids.insert(id.to_string());
+                    }
+                    Ok(false) => {}
+                    Err(_) => return false,
+                }
+            }
+            entry.epoch = to;
+            entry.appended = 0;
+            true
+        });
+    }
+
+    /// Drop every entry; the usage counters are kept
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub fn stats(&self) -> FilterCacheStats {
+        FilterCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+            entries: self.lock().len(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, FilterCacheEntry>> {
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Clear the filter caches of indices (missing ones are skipped)
+pub async fn clear_filter_caches(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_names: &[String],
+) {
+    let indices = indices.read().await;
+    for index in index_names.iter().filter_map(|name| indices.get(name)) {
+        index.filter_cache.clear();
+    }
+}
+
+/// Check if a filter clause can be answered from the filter cache
+///
+/// Ranges relative to `now` match different documents over time and are
+/// evaluated every time.
+pub fn is_cacheable_filter(clause: &serde_json::Value) -> bool {
+    let Some((kind, body)) = clause
+        .as_object()
+        .filter(|c| c.len() == 1)
+        .and_then(|c| c.iter().next())
+    else {
+        return false;
+    };
+    match kind.as_str() {
+        "term" | "terms" => body
+            .as_object()
+            .is_some_and(|fields| !fields.contains_key("_id")),
+        "exists" => true,
+        "range" => !mentions_now(body),
+        _ => false,
+    }
+}
+
+fn mentions_now(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(text) => text.trim_start().starts_with("now"),
+        serde_json::Value::Object(fields) => fields.values().any(mentions_now),
+        serde_json::Value::Array(items) => items.iter().any(mentions_now),
+        _ => false,
+    }
+}
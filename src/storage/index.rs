@@ -1,22 +1,464 @@
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::storage::catch_all::{CatchAllDocuments, CatchAllField};
+use crate::storage::filter_cache::FilterCache;
+use crate::storage::index_sort::{index_sort, SortedDocuments};
+use crate::storage::refresh::{RefreshInterval, RefreshListeners};
+use crate::storage::settings::merge_settings;
+use crate::storage::stats::OperationCounters;
+use crate::storage::{ChangeLog, SearchProfile};
+
+/// Maximum number of appended document IDs remembered per epoch
+const APPEND_LOG_LIMIT: usize = 10_000;
+
+/// Source of document epochs, unique across all indices
+static NEXT_EPOCH: AtomicU64 = AtomicU64::new(1);
+
+/// Documents of an index by ID
+///
+/// A persistent map: copies share their entries, and a write to one copy
+/// only copies the part of the map it changes.
+pub type Documents = imbl::HashMap<String, serde_json::Value>;
+
+/// Storage tier of an index
+///
+/// Hot indices keep their documents in memory. Warm indices keep only their
+/// metadata in memory and serve documents straight from the disk backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexTier {
+    #[default]
+    Hot,
+    Warm,
+}
+
+impl IndexTier {
+    /// Parse a tier name (`hot` or `warm`)
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "hot" => Some(IndexTier::Hot),
+            "warm" => Some(IndexTier::Warm),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexTier::Hot => "hot",
+            IndexTier::Warm => "warm",
+        }
+    }
+}
+
+/// State of an index
+///
+/// Closed indices keep their documents but reject searches, reads and writes.
+/// Frozen indices are searchable but reject writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexState {
+    #[default]
+    Open,
+    Frozen,
+    Close,
+}
+
+impl IndexState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IndexState::Open => "open",
+            IndexState::Frozen => "frozen",
+            IndexState::Close => "close",
+        }
+    }
+}
 
 #[derive(Clone, Debug)]
 pub struct Index {
     pub name: String,
     pub settings: Option<serde_json::Value>,
     pub mappings: Option<serde_json::Value>,
-    pub documents: HashMap<String, serde_json::Value>,
+    /// Documents by ID, shared with checkpoints and the searchable copy of
+    /// the index
+    pub documents: Documents,
+    /// Versions of the documents by ID, shared like the documents and kept
+    /// while the index is warm
+    versions: imbl::HashMap<String, u64>,
+    /// IDs of the documents in index sort order, for indices created with
+    /// `index.sort.*` settings, shared like the documents
+    pub sorted: Option<SortedDocuments>,
+    /// Catch-all field of the documents, if the mappings enable it (see
+    /// `catch_all`), shared like the documents
+    pub catch_all: Option<CatchAllDocuments>,
     pub aliases: Vec<String>, // List of alias names for this index
+    /// Estimated size of all documents (serialized JSON bytes)
+    pub size_in_bytes: u64,
+    /// Named search profiles (field boosts and default operator)
+    pub search_profiles: HashMap<String, SearchProfile>,
+    /// Storage tier (hot: in memory, warm: on disk only)
+    pub tier: IndexTier,
+    /// Open, frozen or closed
+    pub state: IndexState,
+    /// Creation time in milliseconds since the epoch (unknown for indices
+    /// created before it was recorded)
+    pub creation_date: Option<u64>,
+    /// Change log of the documents, shared with copies of the index
+    pub changes: Arc<ChangeLog>,
+    /// Indexing and search counters, shared with copies of the index
+    pub operations: Arc<OperationCounters>,
+    /// Documents matching filter clauses, shared with copies of the index
+    pub filter_cache: Arc<FilterCache>,
+    /// Number of documents held on disk only while the index is warm
+    evicted_doc_count: usize,
+    /// Hot index whose documents were left on disk by lazy loading
+    lazy: bool,
+    /// Document epoch: unchanged while documents are only added
+    epoch: u64,
+    /// IDs of the documents added in the current epoch, in insertion order
+    appended: Vec<String>,
+    /// Copy of the index as of its last refresh, which searches read while
+    /// writes made since are held back (see `refresh`)
+    searchable: Option<Arc<Index>>,
+    /// When writes become searchable, from the settings
+    refresh_interval: RefreshInterval,
+    /// Time of the last refresh
+    last_refresh: Instant,
+    /// Writes waiting for the next refresh, shared with copies of the index
+    refresh_listeners: Arc<RefreshListeners>,
 }
 
 impl Index {
-    pub fn new(name: String, settings: Option<serde_json::Value>, mappings: Option<serde_json::Value>) -> Self {
+    pub fn new(
+        name: String,
+        settings: Option<serde_json::Value>,
+        mappings: Option<serde_json::Value>,
+    ) -> Self {
+        // Settings were checked when the index was created
+        let sorted = settings
+            .as_ref()
+            .and_then(|settings| index_sort(settings).ok().flatten())
+            .map(SortedDocuments::new);
+        let catch_all = CatchAllField::of_mapping(mappings.as_ref()).map(CatchAllDocuments::new);
+        let refresh_interval = RefreshInterval::of_settings(settings.as_ref());
         Self {
             name,
             settings,
             mappings,
-            documents: HashMap::new(),
+            documents: Documents::new(),
+            versions: imbl::HashMap::new(),
+            sorted,
+            catch_all,
             aliases: Vec::new(),
+            size_in_bytes: 0,
+            search_profiles: HashMap::new(),
+            tier: IndexTier::Hot,
+            state: IndexState::Open,
+            creation_date: Some(chrono::Utc::now().timestamp_millis() as u64),
+            changes: Arc::new(ChangeLog::default()),
+            operations: Arc::new(OperationCounters::default()),
+            filter_cache: Arc::new(FilterCache::default()),
+            evicted_doc_count: 0,
+            lazy: false,
+            epoch: NEXT_EPOCH.fetch_add(1, Ordering::Relaxed),
+            appended: Vec::new(),
+            searchable: None,
+            refresh_interval,
+            last_refresh: Instant::now(),
+            refresh_listeners: Arc::new(RefreshListeners::default()),
+        }
+    }
+
+    /// Insert or replace a document, keeping the size estimate up to date
+    pub fn insert_document(&mut self, id: String, document: serde_json::Value) {
+        self.hold_back_writes();
+        let added = document_size(&document);
+        if let Some(sorted) = &mut self.sorted {
+            sorted.insert(&id, self.documents.get(&id), &document);
+        }
+        if let Some(catch_all) = &mut self.catch_all {
+            catch_all.insert(&id, &document);
+        }
+        if let Some(previous) = self.documents.insert(id.clone(), document) {
+            self.size_in_bytes = self.size_in_bytes.saturating_sub(document_size(&previous));
+            self.start_epoch_for(&id);
+        } else if self.appended.len() < APPEND_LOG_LIMIT {
+            self.appended.push(id);
+        } else {
+            self.start_epoch_for(&id);
+        }
+        self.size_in_bytes += added;
+    }
+
+    /// Remove a document, keeping the size estimate up to date
+    pub fn remove_document(&mut self, id: &str) -> Option<serde_json::Value> {
+        // Unknown IDs must not hold back writes
+        if !self.documents.contains_key(id) {
+            return None;
+        }
+        self.hold_back_writes();
+        let removed = self.documents.remove(id)?;
+        if let Some(sorted) = &mut self.sorted {
+            sorted.remove(id, &removed);
+        }
+        if let Some(catch_all) = &mut self.catch_all {
+            catch_all.remove(id);
+        }
+        self.size_in_bytes = self.size_in_bytes.saturating_sub(document_size(&removed));
+        self.start_epoch_for(id);
+        Some(removed)
+    }
+
+    /// Version of a document: 1 when it was created, one more for every
+    /// write since
+    ///
+    /// Documents written before versions were recorded are at version 1.
+    pub fn version(&self, id: &str) -> u64 {
+        self.versions.get(id).copied().unwrap_or(1)
+    }
+
+    /// Record a write of a document, returning its new version
+    ///
+    /// `replaces` tells whether the write replaced an existing document;
+    /// otherwise the document starts over at version 1.
+    pub fn bump_version(&mut self, id: &str, replaces: bool) -> u64 {
+        let version = if replaces { self.version(id) + 1 } else { 1 };
+        self.versions.insert(id.to_string(), version);
+        version
+    }
+
+    /// Forget the version of a deleted document, returning the version of
+    /// the deletion
+    pub fn remove_version(&mut self, id: &str) -> u64 {
+        let version = self.version(id) + 1;
+        self.versions.remove(id);
+        version
+    }
+
+    /// Set the versions of the documents loaded from the backend
+    pub fn set_versions(&mut self, versions: HashMap<String, u64>) {
+        self.versions = versions.into_iter().collect();
+    }
+
+    /// Collect the catch-all field of the documents anew after a change of
+    /// the mappings
+    pub fn rebuild_catch_all(&mut self) {
+        let field = CatchAllField::of_mapping(self.mappings.as_ref());
+        if self.catch_all.as_ref().map(|catch_all| catch_all.field()) == field.as_ref() {
+            return;
+        }
+        self.catch_all = field.map(|field| CatchAllDocuments::of_documents(field, &self.documents));
+    }
+
+    /// Document epoch of the index
+    ///
+    /// The epoch changes whenever a document is replaced or removed, or the
+    /// index changes tier. Within an epoch, documents are only added, so results
+    /// computed earlier in the epoch can be updated from the appended documents.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Number of documents added in the current epoch
+    pub fn appended_count(&self) -> usize {
+        self.appended.len()
+    }
+
+    /// IDs of the documents added in the current epoch after the first `position`
+    pub fn appended_since(&self, position: usize) -> &[String] {
+        self.appended.get(position..).unwrap_or_default()
+    }
+
+    fn start_epoch(&mut self) {
+        self.epoch = NEXT_EPOCH.fetch_add(1, Ordering::Relaxed);
+        self.appended.clear();
+    }
+
+    /// Start a new epoch for a change of one document, carrying the filter
+    /// cache over to it
+    fn start_epoch_for(&mut self, id: &str) {
+        let epoch = NEXT_EPOCH.fetch_add(1, Ordering::Relaxed);
+        self.filter_cache.advance_epoch(
+            self.epoch,
+            &self.appended,
+            &self.documents,
+            epoch,
+            id,
+            self.documents.get(id),
+        );
+        self.epoch = epoch;
+        self.appended.clear();
+    }
+
+    /// The index as searches see it: as of its last refresh when writes are
+    /// held back, the index itself otherwise
+    pub fn searchable(&self) -> &Index {
+        self.searchable.as_deref().unwrap_or(self)
+    }
+
+    /// Check if writes made since the last refresh are hidden from searches
+    pub fn has_held_back_writes(&self) -> bool {
+        self.searchable.is_some()
+    }
+
+    /// Time of the last refresh
+    pub fn last_refresh(&self) -> Instant {
+        self.last_refresh
+    }
+
+    /// Writes waiting for the next refresh
+    pub fn refresh_listeners(&self) -> &Arc<RefreshListeners> {
+        &self.refresh_listeners
+    }
+
+    /// Make the writes held back since the last refresh searchable
+    pub fn refresh(&mut self) {
+        self.searchable = None;
+        self.last_refresh = Instant::now();
+        self.refresh_listeners.notify();
+    }
+
+    /// When writes to the index become searchable
+    pub fn refresh_interval(&self) -> RefreshInterval {
+        self.refresh_interval
+    }
+
+    /// Merge a settings update into the settings of the index
+    ///
+    /// Writes held back under the old refresh interval are not left waiting
+    /// for the new one.
+    pub fn update_settings(&mut self, update: &serde_json::Value) {
+        merge_settings(
+            self.settings.get_or_insert_with(|| serde_json::json!({})),
+            update,
+        );
+        let refresh_interval = RefreshInterval::of_settings(self.settings.as_ref());
+        if refresh_interval != self.refresh_interval {
+            self.refresh_interval = refresh_interval;
+            self.refresh();
+        }
+    }
+
+    /// Keep a copy of the index for searches before the first write after a
+    /// refresh, unless writes are searchable at once
+    ///
+    /// The copy shares the documents of the index.
+    fn hold_back_writes(&mut self) {
+        if self.searchable.is_some()
+            || self.is_warm()
+            || self.refresh_interval == RefreshInterval::Immediate
+        {
+            return;
+        }
+        self.searchable = Some(Arc::new(self.clone()));
+    }
+
+    /// Check if the documents of the index are served from disk: it is in the
+    /// warm tier or was loaded lazily
+    pub fn is_warm(&self) -> bool {
+        self.tier == IndexTier::Warm || self.lazy
+    }
+
+    /// Check if the index is hot but its documents were left on disk by lazy
+    /// loading
+    pub fn is_lazy(&self) -> bool {
+        self.lazy
+    }
+
+    /// Check if the index is closed
+    pub fn is_closed(&self) -> bool {
+        self.state == IndexState::Close
+    }
+
+    /// Number of documents in the index, whichever tier it is in
+    pub fn doc_count(&self) -> usize {
+        if self.is_warm() {
+            self.evicted_doc_count
+        } else {
+            self.documents.len()
+        }
+    }
+
+    /// Move the index to the warm tier, dropping its documents from memory
+    ///
+    /// The documents must already be persisted in the backend.
+    pub fn evict_documents(&mut self) {
+        if !self.is_warm() {
+            self.evicted_doc_count = self.documents.len();
+        }
+        self.documents = Documents::new();
+        if let Some(sorted) = &mut self.sorted {
+            sorted.clear();
+        }
+        if let Some(catch_all) = &mut self.catch_all {
+            catch_all.clear();
+        }
+        self.tier = IndexTier::Warm;
+        self.lazy = false;
+        self.start_epoch();
+        // Warm indices are searched on disk, where every write is
+        self.refresh();
+    }
+
+    /// Leave the documents of a hot index on disk, serving it like a warm
+    /// index until it is moved to the hot tier (lazy loading)
+    pub fn leave_documents_on_disk(&mut self, doc_count: usize, size_in_bytes: u64) {
+        self.evict_documents();
+        self.tier = IndexTier::Hot;
+        self.lazy = true;
+        self.set_evicted_stats(doc_count, size_in_bytes);
+    }
+
+    /// Move the index to the hot tier with documents loaded from the backend
+    pub fn restore_documents(&mut self, documents: Vec<(String, serde_json::Value)>) {
+        self.tier = IndexTier::Hot;
+        self.lazy = false;
+        self.evicted_doc_count = 0;
+        self.size_in_bytes = 0;
+        for (id, document) in documents {
+            self.insert_document(id, document);
+        }
+        self.start_epoch();
+        self.refresh();
+    }
+
+    /// Set the document count and size of a warm index loaded from disk
+    pub fn set_evicted_stats(&mut self, doc_count: usize, size_in_bytes: u64) {
+        self.evicted_doc_count = doc_count;
+        self.size_in_bytes = size_in_bytes;
+    }
+
+    /// Account for a document written to a warm index
+    pub fn record_evicted_write(
+        &mut self,
+        previous: Option<&serde_json::Value>,
+        document: &serde_json::Value,
+    ) {
+        match previous {
+            Some(previous) => {
+                self.size_in_bytes = self.size_in_bytes.saturating_sub(document_size(previous));
+            }
+            None => self.evicted_doc_count += 1,
         }
+        self.size_in_bytes += document_size(document);
+        // Warm documents are not at hand to update cached results with
+        self.start_epoch();
+    }
+
+    /// Account for a document deleted from a warm index
+    pub fn record_evicted_delete(&mut self, removed: &serde_json::Value) {
+        self.evicted_doc_count = self.evicted_doc_count.saturating_sub(1);
+        self.size_in_bytes = self.size_in_bytes.saturating_sub(document_size(removed));
+        self.start_epoch();
     }
 }
+
+/// Estimated stored size of a document
+fn document_size(document: &serde_json::Value) -> u64 {
+    serde_json::to_vec(document)
+        .map(|v| v.len() as u64)
+        .unwrap_or(0)
+}
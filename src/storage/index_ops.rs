@@ -7,55 +7,337 @@ use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 use crate::error::{GbsError, Result};
-use crate::storage::Index;
+use crate::storage::dynamic_mapping::{set_dynamic_mode, DynamicMode};
+use crate::storage::field_caps::mapping_properties;
+use crate::storage::index_sort::validate_index_sort;
+use crate::storage::limits::{next_rollover_name, StorageLimits};
+use crate::storage::persistence::persist_index_metadata;
+use crate::storage::search::{resolve_date_math_index_name, validate_normalizers};
+use crate::storage::settings::{validate_new_settings, validate_settings_update};
+use crate::storage::templates::IndexTemplates;
+use crate::storage::warmers::validate_warmers;
+use crate::storage::{Index, SearchProfile};
 use crate::storage_backend::SledBackend;
 
 /// Create a new index
+///
+/// Index templates matching the name contribute settings, mappings and
+/// aliases; the given settings and mappings override the templates'. A date
+/// math name like `<logs-{now/d}>` creates the index it resolves to.
 pub async fn create_index(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    templates: &IndexTemplates,
     name: &str,
     settings: Option<serde_json::Value>,
     mappings: Option<serde_json::Value>,
 ) -> Result<()> {
+    let name = &resolve_date_math_index_name(name, chrono::Utc::now())?;
     info!("Creating index: {}", name);
+    if let Some(settings) = &settings {
+        validate_new_settings(settings)?;
+    }
+    let template = templates.resolve(name);
+    if !template.templates.is_empty() {
+        debug!(
+            "Applying index templates {:?} to index '{}'",
+            template.templates, name
+        );
+    }
+    let settings = template.index_settings(settings);
+    let mappings = template.index_mappings(mappings);
+    if let Some(settings) = &settings {
+        validate_warmers(settings)?;
+        validate_index_sort(settings)?;
+    }
+    validate_normalizers(
+        settings.as_ref(),
+        mappings.as_ref().and_then(mapping_properties),
+    )?;
     let mut indices_guard = indices.write().await;
 
     if indices_guard.contains_key(name) {
         warn!("Attempted to create index '{}' that already exists", name);
+        return Err(GbsError::IndexAlreadyExists(name.to_string()));
+    }
+    check_new_index_name(&indices_guard, limits, name)?;
+    if let Some(alias) = template
+        .aliases
+        .iter()
+        .find(|alias| indices_guard.contains_key(alias.as_str()))
+    {
         return Err(GbsError::InvalidRequest(format!(
-            "Index {} already exists",
-            name
+            "Invalid alias name [{}], an index with the same name already exists",
+            alias
         )));
     }
 
+    let mut index = Index::new(name.to_string(), settings, mappings);
+    index.aliases = template.aliases;
+
     // Persist to backend if available
-    if let Some(backend) = backend {
+    if backend.is_some() {
         debug!("Persisting index '{}' to storage backend", name);
-        let backend_clone = backend.clone();
-        let name_str = name.to_string();
-        let settings_clone = settings.clone();
-        let mappings_clone = mappings.clone();
-
-        tokio::task::spawn_blocking(move || {
-            backend_clone.store_index_metadata(
-                &name_str,
-                settings_clone.as_ref(),
-                mappings_clone.as_ref(),
-            )
-        })
-        .await
-        .map_err(GbsError::TaskJoin)??;
+        persist_index_metadata(backend, &index).await?;
         debug!("Index '{}' persisted successfully", name);
     }
 
-    let index = Index::new(name.to_string(), settings, mappings);
     indices_guard.insert(name.to_string(), index);
     info!("Index '{}' created successfully", name);
 
     Ok(())
 }
 
+/// Validate that an index named `name` may be added next to the existing indices
+fn check_new_index_name(
+    indices_guard: &HashMap<String, Index>,
+    limits: &StorageLimits,
+    name: &str,
+) -> Result<()> {
+    if indices_guard
+        .values()
+        .any(|index| index.aliases.iter().any(|alias| alias == name))
+    {
+        return Err(GbsError::InvalidRequest(format!(
+            "Invalid index name [{}], an alias with the same name already exists",
+            name
+        )));
+    }
+
+    if !limits.allows_new_index(name, indices_guard.keys()) {
+        warn!(
+            "Refusing to create index '{}': maximum of {} indices reached",
+            name,
+            limits.max_indices.unwrap_or_default()
+        );
+        return Err(GbsError::InvalidRequest(format!(
+            "Cannot create index [{}]: maximum number of indices [{}] reached",
+            name,
+            limits.max_indices.unwrap_or_default()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Resolve the concrete index a write to `name` goes to
+///
+/// `name` is either an index or an alias pointing at exactly one index.
+pub fn resolve_write_index(indices_guard: &HashMap<String, Index>, name: &str) -> Option<String> {
+    if indices_guard.contains_key(name) {
+        return Some(name.to_string());
+    }
+
+    let mut targets = indices_guard
+        .values()
+        .filter(|index| index.aliases.iter().any(|alias| alias == name));
+    match (targets.next(), targets.next()) {
+        (Some(index), None) => Some(index.name.clone()),
+        _ => None,
+    }
+}
+
+/// Add an alias to an index
+pub async fn put_alias(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    alias: &str,
+) -> Result<()> {
+    let mut indices_guard = indices.write().await;
+    if indices_guard.contains_key(alias) {
+        return Err(GbsError::InvalidRequest(format!(
+            "Invalid alias name [{}], an index with the same name already exists",
+            alias
+        )));
+    }
+
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    if !index.aliases.iter().any(|a| a == alias) {
+        index.aliases.push(alias.to_string());
+        persist_index_metadata(backend, index).await?;
+    }
+
+    info!("Alias '{}' added to index '{}'", alias, index_name);
+    Ok(())
+}
+
+/// Remove an alias from an index
+pub async fn delete_alias(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    alias: &str,
+) -> Result<()> {
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    let before = index.aliases.len();
+    index.aliases.retain(|a| a != alias);
+    if index.aliases.len() == before {
+        return Err(GbsError::AliasNotFound(format!(
+            "[{}] missing on index [{}]",
+            alias, index_name
+        )));
+    }
+    persist_index_metadata(backend, index).await?;
+
+    info!("Alias '{}' removed from index '{}'", alias, index_name);
+    Ok(())
+}
+
+/// Create or replace a search profile, returning whether it was created
+pub async fn put_search_profile(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    profile_name: &str,
+    profile: SearchProfile,
+) -> Result<bool> {
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    let created = index
+        .search_profiles
+        .insert(profile_name.to_string(), profile)
+        .is_none();
+    persist_index_metadata(backend, index).await?;
+
+    info!(
+        "Search profile '{}' {} on index '{}'",
+        profile_name,
+        if created { "created" } else { "updated" },
+        index_name
+    );
+    Ok(created)
+}
+
+/// Get the search profiles of an index
+pub async fn get_search_profiles(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+) -> Result<HashMap<String, SearchProfile>> {
+    let indices_guard = indices.read().await;
+    let index = indices_guard
+        .get(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    Ok(index.search_profiles.clone())
+}
+
+/// Get a single search profile of an index
+pub async fn get_search_profile(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+    profile_name: &str,
+) -> Result<SearchProfile> {
+    get_search_profiles(indices, index_name)
+        .await?
+        .remove(profile_name)
+        .ok_or_else(|| {
+            GbsError::SearchProfileNotFound(format!("[{}] on index [{}]", profile_name, index_name))
+        })
+}
+
+/// Delete a search profile
+pub async fn delete_search_profile(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    profile_name: &str,
+) -> Result<()> {
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    if index.search_profiles.remove(profile_name).is_none() {
+        return Err(GbsError::SearchProfileNotFound(format!(
+            "[{}] on index [{}]",
+            profile_name, index_name
+        )));
+    }
+    persist_index_metadata(backend, index).await?;
+
+    info!(
+        "Search profile '{}' deleted from index '{}'",
+        profile_name, index_name
+    );
+    Ok(())
+}
+
+/// Roll `alias` over from `source` to a newly created index
+///
+/// The new index copies the settings and mappings of `source` and takes over
+/// the alias. Returns the name of the new index.
+pub async fn rollover_index(
+    indices_guard: &mut HashMap<String, Index>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    alias: &str,
+    source: &str,
+) -> Result<String> {
+    let (settings, mappings) = {
+        let index = indices_guard
+            .get(source)
+            .ok_or_else(|| GbsError::IndexNotFound(source.to_string()))?;
+        (index.settings.clone(), index.mappings.clone())
+    };
+
+    let new_name = next_rollover_name(source);
+    let new_index = Index::new(new_name.clone(), settings, mappings);
+    roll_alias_over(indices_guard, backend, limits, alias, source, new_index).await?;
+    Ok(new_name)
+}
+
+/// Validate that an alias may be rolled over to a new index named `name`
+pub fn check_rollover_target(
+    indices_guard: &HashMap<String, Index>,
+    limits: &StorageLimits,
+    name: &str,
+) -> Result<()> {
+    if indices_guard.contains_key(name) {
+        return Err(GbsError::IndexAlreadyExists(name.to_string()));
+    }
+    check_new_index_name(indices_guard, limits, name)
+}
+
+/// Move `alias` from `source` to `new_index`, adding the new index
+pub async fn roll_alias_over(
+    indices_guard: &mut HashMap<String, Index>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    alias: &str,
+    source: &str,
+    mut new_index: Index,
+) -> Result<()> {
+    let new_name = new_index.name.clone();
+    check_rollover_target(indices_guard, limits, &new_name)?;
+
+    if !new_index.aliases.iter().any(|a| a == alias) {
+        new_index.aliases.push(alias.to_string());
+    }
+    persist_index_metadata(backend, &new_index).await?;
+
+    if let Some(old_index) = indices_guard.get_mut(source) {
+        old_index.aliases.retain(|a| a != alias);
+        persist_index_metadata(backend, old_index).await?;
+    }
+    indices_guard.insert(new_name.clone(), new_index);
+
+    info!(
+        "Rolled over alias '{}' from index '{}' to '{}'",
+        alias, source, new_name
+    );
+    Ok(())
+}
+
 /// Check if an index exists
 pub async fn index_exists(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
@@ -77,25 +359,23 @@ pub async fn match_indices(
     pattern: &str,
 ) -> Vec<String> {
     let all_indices = list_indices(indices).await;
+    match wildcard_regex(pattern) {
+        Some(re) => all_indices
+            .into_iter()
+            .filter(|name| re.is_match(name))
+            .collect(),
+        // Invalid pattern, return empty
+        None => Vec::new(),
+    }
+}
 
-    // Convert pattern to regex
+/// Regex matching a whole name against a pattern with * and ? wildcards
+pub fn wildcard_regex(pattern: &str) -> Option<Regex> {
     let mut regex_pattern = String::new();
     for c in pattern.chars() {
         match c {
             '*' => regex_pattern.push_str(".*"),
             '?' => regex_pattern.push('.'),
-            '.' => regex_pattern.push_str(r"\."),
-            '+' => regex_pattern.push_str(r"\+"),
-            '(' => regex_pattern.push_str(r"\("),
-            ')' => regex_pattern.push_str(r"\)"),
-            '[' => regex_pattern.push_str(r"\["),
-            ']' => regex_pattern.push_str(r"\]"),
-            '{' => regex_pattern.push_str(r"\{"),
-            '}' => regex_pattern.push_str(r"\}"),
-            '^' => regex_pattern.push_str(r"\^"),
-            '$' => regex_pattern.push_str(r"\$"),
-            '|' => regex_pattern.push_str(r"\|"),
-            '\\' => regex_pattern.push_str(r"\\"),
             _ => {
                 let mut buf = [0; 4];
                 let s = c.encode_utf8(&mut buf);
@@ -103,19 +383,89 @@ pub async fn match_indices(
             }
         }
     }
+    Regex::new(&format!("^{}$", regex_pattern)).ok()
+}
 
-    let full_pattern = format!("^{}$", regex_pattern);
+/// Resolve an index expression to the concrete indices it names
+///
+/// The expression is a comma-separated list of index names, aliases and
+/// wildcard patterns; `_all` matches every index. Aliases expand to every
+/// index carrying them. A `-` prefix removes the indices matching a name or
+/// pattern from those resolved so far (`logs-*,-logs-old*`). Date math names
+/// like `<logs-{now/d}>` are resolved first. A name that is neither an index
+/// nor an alias is an error, while a pattern matching nothing simply
+/// contributes no indices. Wildcards and `_all` skip closed indices, which
+/// are only resolved when named.
+pub async fn resolve_index_expression(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    expression: &str,
+) -> Result<Vec<String>> {
+    resolve_expression(indices, expression, false).await
+}
 
-    match Regex::new(&full_pattern) {
-        Ok(re) => all_indices
-            .into_iter()
-            .filter(|name| re.is_match(name))
-            .collect(),
-        Err(_) => {
-            // Invalid pattern, return empty
-            Vec::new()
+/// Resolve an index expression whose wildcards also match closed indices
+pub async fn resolve_index_expression_with_closed(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    expression: &str,
+) -> Result<Vec<String>> {
+    resolve_expression(indices, expression, true).await
+}
+
+async fn resolve_expression(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    expression: &str,
+    include_closed: bool,
+) -> Result<Vec<String>> {
+    let now = chrono::Utc::now();
+    let mut resolved: Vec<String> = Vec::new();
+    for part in expression
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+    {
+        let part = resolve_date_math_index_name(part, now)?;
+        let part = part.as_str();
+        if let Some(excluded) = part.strip_prefix('-').filter(|_| !resolved.is_empty()) {
+            if let Some(re) = wildcard_regex(excluded) {
+                resolved.retain(|name| !re.is_match(name));
+            }
+            continue;
+        }
+        let mut names = if part == "_all" || part.contains('*') || part.contains('?') {
+            let mut names = match_indices(indices, if part == "_all" { "*" } else { part }).await;
+            if !include_closed {
+                let indices_guard = indices.read().await;
+                names.retain(|name| {
+                    indices_guard
+                        .get(name)
+                        .is_some_and(|index| !index.is_closed())
+                });
+            }
+            names
+        } else {
+            let indices_guard = indices.read().await;
+            if indices_guard.contains_key(part) {
+                vec![part.to_string()]
+            } else {
+                let aliased: Vec<String> = indices_guard
+                    .values()
+                    .filter(|index| index.aliases.iter().any(|alias| alias == part))
+                    .map(|index| index.name.clone())
+                    .collect();
+                if aliased.is_empty() {
+                    return Err(GbsError::IndexNotFound(part.to_string()));
+                }
+                aliased
+            }
+        };
+        names.sort();
+        for name in names {
+            if !resolved.contains(&name) {
+                resolved.push(name);
+            }
         }
     }
+    Ok(resolved)
 }
 
 /// Get statistics for all indices
@@ -125,7 +475,7 @@ pub async fn get_indices_stats(
     let indices_guard = indices.read().await;
     indices_guard
         .iter()
-        .map(|(name, index)| (name.clone(), index.documents.len()))
+        .map(|(name, index)| (name.clone(), index.doc_count()))
         .collect()
 }
 
@@ -165,11 +515,30 @@ pub async fn get_index(
         name: {
             "settings": index.settings,
             "mappings": index.mappings,
-            "aliases": {}
+            "aliases": index
+                .aliases
+                .iter()
+                .map(|alias| (alias.clone(), serde_json::json!({})))
+                .collect::<serde_json::Map<_, _>>()
         }
     }))
 }
 
+/// Get the mappings of an index
+pub async fn get_mapping(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    name: &str,
+) -> Result<serde_json::Value> {
+    let indices_guard = indices.read().await;
+    let index = indices_guard
+        .get(name)
+        .ok_or_else(|| GbsError::IndexNotFound(name.to_string()))?;
+    Ok(index
+        .mappings
+        .clone()
+        .unwrap_or_else(|| serde_json::json!({})))
+}
+
 /// Delete an index
 pub async fn delete_index(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
@@ -193,7 +562,7 @@ pub async fn delete_index(
     let mut indices_guard = indices.write().await;
     let doc_count = indices_guard
         .get(name)
-        .map(|idx| idx.documents.len())
+        .map(|idx| idx.doc_count())
         .unwrap_or(0);
     indices_guard.remove(name).ok_or_else(|| {
         warn!("Attempted to delete non-existent index: {}", name);
@@ -263,6 +632,8 @@ pub async fn update_mapping(
         error!("Index '{}' not found when updating mapping", index_name);
         GbsError::IndexNotFound(index_name.to_string())
     })?;
+    // The new properties may only use normalizers the index defines
+    validate_normalizers(index.settings.as_ref(), Some(&new_mappings))?;
 
     // Update mappings - merge with existing if present
     if let Some(existing_mappings) = &mut index.mappings {
@@ -294,26 +665,16 @@ pub async fn update_mapping(
         }));
     }
 
+    // New properties may leave fields out of the catch-all field
+    index.rebuild_catch_all();
+
     // Persist updated mappings to backend
-    if let Some(backend) = backend {
+    if backend.is_some() {
         debug!(
             "Persisting updated mapping for index '{}' to storage backend",
             index_name
         );
-        let backend_clone = backend.clone();
-        let index_name_str = index_name.to_string();
-        let final_mappings = index.mappings.clone();
-        let settings = index.settings.clone();
-
-        tokio::task::spawn_blocking(move || {
-            backend_clone.store_index_metadata(
-                &index_name_str,
-                settings.as_ref(),
-                final_mappings.as_ref(),
-            )
-        })
-        .await
-        .map_err(GbsError::TaskJoin)??;
+        persist_index_metadata(backend, index).await?;
         debug!("Mapping for index '{}' persisted successfully", index_name);
     }
 
@@ -321,6 +682,28 @@ pub async fn update_mapping(
     Ok(())
 }
 
+/// Set how an index maps fields its mappings do not define
+pub async fn set_dynamic_mapping(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    mode: DynamicMode,
+) -> Result<()> {
+    info!(
+        "Setting dynamic mapping of index '{}' to {}",
+        index_name,
+        mode.as_str()
+    );
+
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    set_dynamic_mode(index, mode);
+    persist_index_metadata(backend, index).await?;
+    Ok(())
+}
+
 /// Update index settings
 pub async fn update_settings(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
@@ -340,44 +723,17 @@ pub async fn update_settings(
         GbsError::IndexNotFound(index_name.to_string())
     })?;
 
-    // Update settings - merge with existing if present
-    if let Some(existing_settings) = &mut index.settings {
-        if let (Some(existing_obj), Some(new_obj)) =
-            (existing_settings.as_object_mut(), new_settings.as_object())
-        {
-            // Merge settings
-            for (key, value) in new_obj {
-                existing_obj.insert(key.clone(), value.clone());
-            }
-        } else {
-            // Replace entire settings
-            index.settings = Some(new_settings.clone());
-        }
-    } else {
-        // No existing settings, set new ones
-        index.settings = Some(new_settings.clone());
-    }
+    validate_settings_update(index_name, &new_settings)?;
+    validate_warmers(&new_settings)?;
+    index.update_settings(&new_settings);
 
     // Persist updated settings to backend
-    if let Some(backend) = backend {
+    if backend.is_some() {
         debug!(
             "Persisting updated settings for index '{}' to storage backend",
             index_name
         );
-        let backend_clone = backend.clone();
-        let index_name_str = index_name.to_string();
-        let final_settings = index.settings.clone();
-        let mappings = index.mappings.clone();
-
-        tokio::task::spawn_blocking(move || {
-            backend_clone.store_index_metadata(
-                &index_name_str,
-                final_settings.as_ref(),
-                mappings.as_ref(),
-            )
-        })
-        .await
-        .map_err(GbsError::TaskJoin)??;
+        persist_index_metadata(backend, index).await?;
         debug!("Settings for index '{}' persisted successfully", index_name);
     }
 
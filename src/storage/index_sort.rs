@@ -0,0 +1,175 @@
+//! Index sorting (`index.sort.*` settings)
+//!
+//! An index created with a sort keeps the IDs of its documents in that order
+//! as they are written:
+//!
+//! ```json
+//! { "settings": { "index.sort.field": "timestamp", "index.sort.order": "desc" } }
+//! ```
+//!
+//! `index.sort.order` (`asc` or `desc`, default `asc`), `index.sort.missing`
+//! (`_first` or `_last`, default `_last`) and `index.sort.mode` (`min` or
+//! `max`) mean what they mean in the `sort` of a search. One field can be
+//! given, as a string or an array of one. Searches for all documents, in
+//! index order or sorted like the index, read the first hits off the sorted
+//! IDs instead of scanning every document.
+
+use imbl::{OrdMap, OrdSet};
+
+use crate::error::{GbsError, Result};
+use crate::storage::search::{parse_sort, SortClause, SortValue};
+use crate::storage::settings::setting_group;
+
+/// Settings group holding the index sort
+const SORT_SETTING: &str = "index.sort";
+
+/// Keys of the index sort settings
+const SORT_KEYS: [&str; 4] = ["field", "order", "missing", "mode"];
+
+/// The sort of an index given in its settings, `None` if it has none
+pub fn index_sort(settings: &serde_json::Value) -> Result<Option<SortClause>> {
+    let group = setting_group(settings, SORT_SETTING);
+    if group.is_empty() {
+        return Ok(None);
+    }
+    let invalid =
+        |reason: String| GbsError::InvalidRequest(format!("Invalid index sort: {}", reason));
+    if let Some(key) = group.keys().find(|key| !SORT_KEYS.contains(&key.as_str())) {
+        return Err(invalid(format!(
+            "unsupported setting [index.sort.{}], expected one of {:?}",
+            key, SORT_KEYS
+        )));
+    }
+    // Each setting is a string or an array of one string
+    let single = |key: &str| -> Result<Option<String>> {
+        let value = match group.get(key) {
+            None => return Ok(None),
+            Some(serde_json::Value::Array(values)) if values.len() == 1 => &values[0],
+            Some(serde_json::Value::Array(_)) => {
+                return Err(invalid(format!(
+                "[index.sort.{}] must have one value, sorting by several fields is not supported",
+                key
+            )))
+            }
+            Some(value) => value,
+        };
+        value
+            .as_str()
+            .map(|value| Some(value.to_string()))
+            .ok_or_else(|| invalid(format!("[index.sort.{}] must be a string", key)))
+    };
+    let field =
+        single("field")?.ok_or_else(|| invalid("[index.sort.field] is required".to_string()))?;
+    if field.is_empty() || field.starts_with('_') {
+        return Err(invalid(format!("cannot sort an index by [{}]", field)));
+    }
+    let mut options = serde_json::Map::new();
+    for key in ["order", "missing", "mode"] {
+        if let Some(value) = single(key)? {
+            options.insert(key.to_string(), serde_json::Value::String(value));
+        }
+    }
+    if options
+        .get("mode")
+        .is_some_and(|mode| mode != "min" && mode != "max")
+    {
+        return Err(invalid(
+            "[index.sort.mode] must be [min] or [max]".to_string(),
+        ));
+    }
+    let mut clauses = parse_sort(&serde_json::json!({ field: options }))?;
+    Ok(clauses.pop())
+}
+
+/// Check the index sort given in index settings
+pub fn validate_index_sort(settings: &serde_json::Value) -> Result<()> {
+    index_sort(settings).map(|_| ())
+}
+
+/// The IDs of the documents of an index in index sort order
+///
+/// Documents with the same value are ordered by ID, as hits with the same
+/// sort values and score are.
+#[derive(Debug, Clone)]
+pub struct SortedDocuments {
+    sort: SortClause,
+    by_value: OrdMap<SortValue, OrdSet<String>>,
+    /// Documents without a value
+    missing: OrdSet<String>,
+}
+
+impl SortedDocuments {
+    pub fn new(sort: SortClause) -> Self {
+        Self {
+            sort,
+            by_value: OrdMap::new(),
+            missing: OrdSet::new(),
+        }
+    }
+
+    /// Check if hits sorted by `clauses` come in index order: no clauses, or
+    /// the index sort alone
+    pub fn sorts_like(&self, clauses: &[SortClause]) -> bool {
+        clauses.is_empty() || clauses == std::slice::from_ref(&self.sort)
+    }
+
+    /// Add a document, replacing its `previous` version
+    pub fn insert(
+        &mut self,
+        id: &str,
+        previous: Option<&serde_json::Value>,
+        doc: &serde_json::Value,
+    ) {
+        if let Some(previous) = previous {
+            self.remove(id, previous);
+        }
+        match self.sort.field_value(doc) {
+            Some(value) => {
+                self.by_value
+                    .entry(value)
+                    .or_default()
+                    .insert(id.to_string());
+            }
+            None => {
+                self.missing.insert(id.to_string());
+            }
+        }
+    }
+
+    /// Remove a document
+    pub fn remove(&mut self, id: &str, doc: &serde_json::Value) {
+        match self.sort.field_value(doc) {
+            Some(value) => {
+                if let Some(ids) = self.by_value.get_mut(&value) {
+                    ids.remove(id);
+                    if ids.is_empty() {
+                        self.by_value.remove(&value);
+                    }
+                }
+            }
+            None => {
+                self.missing.remove(id);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.by_value.clear();
+        self.missing.clear();
+    }
+
+    /// The document IDs in index order
+    pub fn ids(&self) -> Box<dyn Iterator<Item = &String> + '_> {
+        let valued: Box<dyn Iterator<Item = &OrdSet<String>>> = if self.sort.is_descending() {
+            Box::new(self.by_value.values().rev())
+        } else {
+            Box::new(self.by_value.values())
+        };
+        let valued = valued.flatten();
+        if self.sort.is_missing_first() {
+            Box::new(self.missing.iter().chain(valued))
+        } else {
+            Box::new(valued.chain(self.missing.iter()))
+        }
+    }
+}
@@ -0,0 +1,54 @@
+//! Opening, closing and freezing indices
+//!
+//! Closed indices keep their documents, in memory and on disk, but reject
+//! searches, reads and writes until they are opened again. Frozen indices stay
+//! searchable and reject writes.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::persistence::persist_index_metadata;
+use crate::storage::{Index, IndexState};
+use crate::storage_backend::SledBackend;
+
+/// Check that an index can be searched and read
+pub fn ensure_readable(index: &Index) -> Result<()> {
+    if index.is_closed() {
+        return Err(GbsError::IndexClosed(index.name.clone()));
+    }
+    Ok(())
+}
+
+/// Check that documents can be written to an index
+pub fn ensure_writable(index: &Index) -> Result<()> {
+    match index.state {
+        IndexState::Open => Ok(()),
+        IndexState::Frozen => Err(GbsError::IndexWriteBlocked(index.name.clone())),
+        IndexState::Close => Err(GbsError::IndexClosed(index.name.clone())),
+    }
+}
+
+/// Move an index to a state, returning whether its state changed
+pub async fn set_index_state(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    state: IndexState,
+) -> Result<bool> {
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    if index.state == state {
+        debug!("Index '{}' is already {}", index_name, state.as_str());
+        return Ok(false);
+    }
+    index.state = state;
+    persist_index_metadata(backend, index).await?;
+    info!("Index '{}' is now {}", index_name, state.as_str());
+    Ok(true)
+}
@@ -0,0 +1,97 @@
+//! Growth limits for a single node
+//!
+//! Caps the number of indices and rolls over indices written through an alias
+//! once they grow past a configured document count or size. Also decides which
+//! missing indices a document write creates.
+
+use crate::config::{AutoCreateIndex, StorageConfig};
+use crate::storage::index_ops::wildcard_regex;
+use crate::storage::Index;
+
+/// Limits enforced by the storage layer (all disabled by default)
+#[derive(Debug, Clone, Default)]
+pub struct StorageLimits {
+    /// Maximum number of indices, dot-prefixed system indices excluded
+    pub max_indices: Option<usize>,
+    /// Roll over an aliased index once it holds this many documents
+    pub rollover_max_docs: Option<usize>,
+    /// Roll over an aliased index once its documents exceed this many bytes
+    pub rollover_max_size_bytes: Option<u64>,
+    /// Missing indices a document write creates; `None` creates only the
+    /// indices an index template matches
+    pub auto_create_index: Option<AutoCreateIndex>,
+}
+
+impl StorageLimits {
+    /// Build the limits from the storage configuration
+    pub fn from_config(config: &StorageConfig) -> Self {
+        Self {
+            max_indices: config.max_indices,
+            rollover_max_docs: config.auto_rollover.max_docs,
+            rollover_max_size_bytes: config.auto_rollover.max_size_bytes,
+            auto_create_index: config.auto_create_index.clone(),
+        }
+    }
+
+    /// Check if a document write to the missing index `name` creates it
+    pub fn allows_auto_create(&self, name: &str, template_matches: bool) -> bool {
+        match &self.auto_create_index {
+            None => template_matches,
+            Some(AutoCreateIndex::All) => true,
+            Some(AutoCreateIndex::Disabled) => false,
+            Some(AutoCreateIndex::Patterns(patterns)) => patterns
+                .iter()
+                .find(|(_, pattern)| {
+                    wildcard_regex(pattern).is_some_and(|regex| regex.is_match(name))
+                })
+                .is_some_and(|(allow, _)| *allow),
+        }
+    }
+
+    /// Check if an index named `name` can be created next to the existing ones
+    pub fn allows_new_index<'a>(
+        &self,
+        name: &str,
+        existing: impl Iterator<Item = &'a String>,
+    ) -> bool {
+        match self.max_indices {
+            Some(_) if is_system_index(name) => true,
+            Some(max) => existing.filter(|name| !is_system_index(name)).count() < max,
+            None => true,
+        }
+    }
+
+    /// Check if automatic rollover is configured
+    pub fn has_rollover(&self) -> bool {
+        self.rollover_max_docs.is_some() || self.rollover_max_size_bytes.is_some()
+    }
+
+    /// Check if an index has grown past the automatic rollover limits
+    pub fn needs_rollover(&self, index: &Index) -> bool {
+        self.rollover_max_docs
+            .is_some_and(|max| index.doc_count() >= max)
+            || self
+                .rollover_max_size_bytes
+                .is_some_and(|max| index.size_in_bytes >= max)
+    }
+}
+
+/// System indices (dot-prefixed) do not count towards `max_indices`
+pub fn is_system_index(name: &str) -> bool {
+    name.starts_with('.')
+}
+
+/// Name of the index that replaces `name` on rollover
+///
+/// A trailing `-NNNNNN` counter is incremented (keeping its zero padding),
+/// otherwise `-000001` is appended.
+pub fn next_rollover_name(name: &str) -> String {
+    if let Some((base, counter)) = name.rsplit_once('-') {
+        if !counter.is_empty() && counter.chars().all(|c| c.is_ascii_digit()) {
+            if let Ok(n) = counter.parse::<u64>() {
+                return format!("{}-{:0width$}", base, n + 1, width = counter.len());
+            }
+        }
+    }
+    format!("{}-000001", name)
+}
@@ -0,0 +1,292 @@
+//! Validation of document values against their mapped types
+//!
+//! With the `index.mapping.validate` setting, writes are rejected when a value
+//! does not fit the type its field is mapped to (a word in an `integer` field,
+//! a concrete value where an object is mapped, ...), with the
+//! `mapper_parsing_exception` Elasticsearch reports.
+//!
+//! Numeric fields accept numeric strings and, in integer fields, decimals
+//! (truncated) unless coercion is disabled, by the `index.mapping.coerce`
+//! setting or the `coerce` parameter of the field. Documents are stored as
+//! sent. Unmapped fields and types other than numbers, `boolean`, `date`,
+//! `text`, `keyword` and objects are not checked.
+//!
+//! Queries stored in `percolator` fields are always checked, whatever the
+//! setting, so a broken query fails when it is registered rather than when
+//! documents are percolated.
+
+use serde_json::Value;
+
+use crate::error::{GbsError, Result};
+use crate::storage::dynamic_mapping::mapping_root;
+use crate::storage::search::{expand_query_strings, score_document};
+use crate::storage::settings::setting_value;
+use crate::storage::{get_field_value, parse_date, Index};
+
+/// Type and reason of why a value does not fit a field type
+type Cause = (&'static str, String);
+
+/// Check the values of a document against the mappings of an index, if the
+/// index validates them
+pub fn validate_document(index: &Index, id: &str, document: &Value) -> Result<()> {
+    let setting = |key| {
+        index
+            .settings
+            .as_ref()
+            .and_then(|settings| setting_value(settings, key))
+    };
+    if !setting("index.mapping.validate").is_some_and(is_true) {
+        return Ok(());
+    }
+    let coerce = setting("index.mapping.coerce").is_none_or(is_true);
+
+    let properties = index
+        .mappings
+        .as_ref()
+        .and_then(|mappings| mapping_root(mappings).get("properties"))
+        .and_then(Value::as_object);
+    match (properties, document.as_object()) {
+        (Some(properties), Some(fields)) => validate_fields(properties, coerce, id, "", fields),
+        _ => Ok(()),
+    }
+}
+
+/// Check the queries a document stores in the `percolator` fields of an index
+pub fn validate_percolator_queries(index: &Index, id: &str, document: &Value) -> Result<()> {
+    let Some(properties) = index
+        .mappings
+        .as_ref()
+        .and_then(|mappings| mapping_root(mappings).get("properties"))
+        .and_then(Value::as_object)
+    else {
+        return Ok(());
+    };
+    let mut fields = Vec::new();
+    collect_percolator_fields(properties, "", &mut fields);
+
+    for field in fields {
+        let Some(query) = get_field_value(document, &field).filter(|query| !query.is_null()) else {
+            continue;
+        };
+        let failure = |reason: String| GbsError::MapperParsing {
+            reason: format!(
+                "failed to parse field [{}] of type [percolator] in document with id '{}'",
+                field, id
+            ),
+            caused_by: Some(("parsing_exception", reason)),
+        };
+        if !query.is_object() {
+            return Err(failure(
+                "query malformed, must start with start_object".to_string(),
+            ));
+        }
+        // Scoring an empty document surfaces malformed queries
+        expand_query_strings(query)
+            .and_then(|query| score_document("", &Value::Object(Default::default()), &query))
+            .map_err(|e| failure(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Dotted paths of the `percolator` fields of mapping properties
+fn collect_percolator_fields(
+    properties: &serde_json::Map<String, Value>,
+    prefix: &str,
+    fields: &mut Vec<String>,
+) {
+    for (name, mapping) in properties {
+        let path = format!("{}{}", prefix, name);
+        if mapping.get("type").and_then(Value::as_str) == Some("percolator") {
+            fields.push(path);
+        } else if let Some(properties) = mapping.get("properties").and_then(Value::as_object) {
+            collect_percolator_fields(properties, &format!("{}.", path), fields);
+        }
+    }
+}
+
+fn is_true(value: &Value) -> bool {
+    value.as_bool() == Some(true) || value.as_str() == Some("true")
+}
+
+fn validate_fields(
+    properties: &serde_json::Map<String, Value>,
+    coerce: bool,
+    id: &str,
+    prefix: &str,
+    fields: &serde_json::Map<String, Value>,
+) -> Result<()> {
+    for (name, value) in fields {
+        if let Some(mapping) = properties.get(name) {
+            validate_value(mapping, coerce, id, &format!("{}{}", prefix, name), value)?;
+        }
+    }
+    Ok(())
+}
+
+fn validate_value(
+    mapping: &Value,
+    coerce: bool,
+    id: &str,
+    path: &str,
+    value: &Value,
+) -> Result<()> {
+    match value {
+        Value::Null => return Ok(()),
+        Value::Array(items) => {
+            for item in items {
+                validate_value(mapping, coerce, id, path, item)?;
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+    let field_type = match mapping.get("type").and_then(Value::as_str) {
+        Some(field_type) => field_type,
+        None if mapping.get("properties").is_some() => "object",
+        None => return Ok(()),
+    };
+
+    if matches!(field_type, "object" | "nested") {
+        if mapping.get("enabled").and_then(Value::as_bool) == Some(false) {
+            return Ok(());
+        }
+        let Some(fields) = value.as_object() else {
+            return Err(GbsError::MapperParsing {
+                reason: format!(
+                    "object mapping for [{0}] tried to parse field [{0}] as object, but found a concrete value",
+                    path
+                ),
+                caused_by: None,
+            });
+        };
+        return match mapping.get("properties").and_then(Value::as_object) {
+            Some(properties) => {
+                validate_fields(properties, coerce, id, &format!("{}.", path), fields)
+            }
+            None => Ok(()),
+        };
+    }
+
+    let coerce = mapping
+        .get("coerce")
+        .and_then(Value::as_bool)
+        .unwrap_or(coerce);
+    check_value(field_type, mapping, coerce, value).map_err(|cause| GbsError::MapperParsing {
+        reason: format!(
+            "failed to parse field [{}] of type [{}] in document with id '{}'",
+            path, field_type, id
+        ),
+        caused_by: Some(cause),
+    })
+}
+
+/// Check a non-null, non-array value against a field type
+fn check_value(
+    field_type: &str,
+    mapping: &Value,
+    coerce: bool,
+    value: &Value,
+) -> std::result::Result<(), Cause> {
+    match field_type {
+        "long" | "integer" | "short" | "byte" => check_integer(field_type, coerce, value),
+        "double" | "float" | "half_float" | "scaled_float" => {
+            parse_number(coerce, value).map(|_| ())
+        }
+        "boolean" => match value {
+            Value::Bool(_) => Ok(()),
+            Value::String(s) if matches!(s.as_str(), "true" | "false" | "") => Ok(()),
+            _ if value.is_object() => Err(not_a_value()),
+            _ => Err((
+                "illegal_argument_exception",
+                format!(
+                    "Failed to parse value [{}] as only [true] or [false] are allowed.",
+                    display(value)
+                ),
+            )),
+        },
+        // Dates with a custom format are not checked
+        "date" if mapping.get("format").is_none() => match value {
+            Value::Number(_) => Ok(()),
+            Value::String(_) if parse_date(value).is_some() => Ok(()),
+            _ if value.is_object() => Err(not_a_value()),
+            _ => Err((
+                "illegal_argument_exception",
+                format!(
+                    "failed to parse date field [{}] with format [strict_date_optional_time||epoch_millis]",
+                    display(value)
+                ),
+            )),
+        },
+        "text" | "keyword" if value.is_object() => Err(not_a_value()),
+        _ => Ok(()),
+    }
+}
+
+fn check_integer(field_type: &str, coerce: bool, value: &Value) -> std::result::Result<(), Cause> {
+    let number = parse_number(coerce, value)?;
+    if number.fract() != 0.0 && !coerce {
+        return Err((
+            "illegal_argument_exception",
+            format!("Value [{}] has a decimal part", display(value)),
+        ));
+    }
+    let (min, max, name) = match field_type {
+        "byte" => (i8::MIN as f64, i8::MAX as f64, "a byte"),
+        "short" => (i16::MIN as f64, i16::MAX as f64, "a short"),
+        "integer" => (i32::MIN as f64, i32::MAX as f64, "an integer"),
+        _ => (i64::MIN as f64, i64::MAX as f64, "a long"),
+    };
+    if number.trunc() < min || number.trunc() > max {
+        return Err((
+            "illegal_argument_exception",
+            format!("Value [{}] is out of range for {}", display(value), name),
+        ));
+    }
+    Ok(())
+}
+
+/// The number a value of a numeric field holds
+fn parse_number(coerce: bool, value: &Value) -> std::result::Result<f64, Cause> {
+    match value {
+        Value::Number(n) => Ok(n.as_f64().unwrap_or_default()),
+        Value::String(s) if !coerce => Err((
+            "illegal_argument_exception",
+            format!("Value [{}] is a string and coercion is disabled", s),
+        )),
+        Value::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|number| number.is_finite())
+            .ok_or_else(|| {
+                (
+                    "number_format_exception",
+                    format!("For input string: \"{}\"", s),
+                )
+            }),
+        Value::Bool(b) => Err((
+            "illegal_argument_exception",
+            format!(
+                "Current token ({}) not numeric, can not use numeric value accessors",
+                if *b { "VALUE_TRUE" } else { "VALUE_FALSE" }
+            ),
+        )),
+        _ => Err(not_a_value()),
+    }
+}
+
+/// Cause of an object where a concrete value is mapped
+fn not_a_value() -> Cause {
+    (
+        "illegal_state_exception",
+        "Can't get text on a START_OBJECT".to_string(),
+    )
+}
+
+/// A value as it appears in error messages: strings without quotes
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
@@ -4,17 +4,157 @@
 //! indices, documents, and search operations.
 
 // Declare submodules
+mod aggregation_cache;
+mod catch_all;
+mod changes;
+mod checkpoint;
+mod cluster_settings;
+mod delete_by_query;
 mod document_ops;
+mod durability;
+mod dynamic_mapping;
+mod federation;
+mod field_caps;
+mod filter_cache;
 mod index;
 mod index_ops;
+mod index_sort;
+mod index_state;
+mod limits;
+mod mapping_validation;
 mod persistence;
+mod pipelines;
+mod refresh;
+mod reindex;
+mod retention;
+mod rollover;
+mod routing;
+mod scripts;
 mod search;
 mod search_impl;
+mod search_profile;
+mod settings;
 mod stats;
 mod storage;
+mod templates;
+mod tiering;
+mod warmers;
+
+// Re-export aggregation cache statistics
+pub use aggregation_cache::AggregationCacheStats;
+
+// Re-export filter cache statistics
+pub use filter_cache::FilterCacheStats;
+
+// Re-export aggregation merging across indices
+pub use search::merge_aggregations;
+
+// Re-export date math index name resolution
+pub use search::resolve_date_math_index_name;
+
+// Re-export query string expansion
+pub use search::expand_query_strings;
+
+// Re-export scoring explanations
+pub use search::Explanation;
+
+// Re-export scripts
+pub use search::{parse_script_fields, Script};
+
+// Re-export time value parsing
+pub use settings::time_value_millis;
+
+// Re-export sorting of search hits
+pub use search::{compare_sort_values, parse_sort, SortClause};
+
+// Re-export field collapsing
+pub use search::Collapse;
+
+// Re-export runtime fields
+pub use search::RuntimeFields;
+
+// Re-export stored and doc value fields
+pub use search::{parse_docvalue_fields, parse_stored_fields, DocvalueField, StoredFields};
+
+// Re-export field capabilities
+pub use field_caps::FieldCapability;
+
+// Re-export dynamic mapping
+pub use dynamic_mapping::DynamicMode;
+
+// Re-export write and transaction outcomes
+pub use document_ops::{BulkItemOutcome, TransactionAbort, WriteOutcome};
+
+// Re-export the change feed
+pub use changes::{
+    Change, ChangeLog, ChangeOp, ChangesRequest, ChangesResponse, MAX_RETAINED_CHANGES,
+};
+
+// Re-export delete by query
+pub use delete_by_query::{DeleteByQueryRequest, DeleteByQueryResponse};
+
+// Re-export reindexing
+pub use reindex::{
+    ReindexFailure, ReindexFailureCause, ReindexOpType, ReindexRequest, ReindexResponse,
+};
+
+// Re-export refresh policies
+pub use refresh::{RefreshInterval, RefreshPolicy};
+
+// Re-export rollover
+pub use rollover::{RolloverCondition, RolloverRequest, RolloverResponse};
+
+// Re-export index templates
+pub use templates::{IndexTemplate, ResolvedTemplate, TemplateKind};
+
+// Re-export stored scripts
+pub use scripts::StoredScript;
+
+// Re-export cluster settings
+pub use cluster_settings::ClusterSettings;
+
+// Re-export ingest pipelines
+pub use pipelines::{IngestPipeline, ProcessorOutcome, ProcessorResult};
+
+// Re-export warm-up outcomes
+pub use warmers::WarmupReport;
+
+// Re-export checkpoints
+pub use checkpoint::{BackupReport, Checkpoint};
 
 // Re-export Index
-pub use index::Index;
+pub use index::{Index, IndexState, IndexTier};
+
+// Re-export index statistics and wildcard matching for the cat APIs
+pub use index_ops::wildcard_regex;
+pub use stats::{ClusterHealth, HealthStatus, IndexStats, OperationStats, NUMBER_OF_NODES};
+
+// Re-export limits
+pub use limits::{next_rollover_name, StorageLimits};
+
+// Re-export federation
+pub use federation::{FederatedIndex, Federation};
+
+// Re-export ingest routing
+pub use routing::{IngestRoute, IngestRoutes};
+
+// Field access and date parsing for exporters, source filtering for gets
+pub(crate) use search::{filter_source, get_field_value, parse_date};
+
+// Re-export parallel scoring settings
+pub use search_impl::{ParallelScoring, SearchRequest};
+
+// Re-export search profiles
+pub use search_profile::SearchProfile;
 
 // Re-export Storage
 pub use storage::Storage;
+
+// Re-export background tiering
+pub use tiering::spawn_tier_demotion;
+
+// Re-export retention
+pub use retention::{spawn_retention, RetentionReport, DEFAULT_RETENTION_FIELD};
+
+// Re-export background flushing
+pub use durability::spawn_durability_flusher;
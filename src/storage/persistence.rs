@@ -6,8 +6,10 @@ use tokio::sync::RwLock;
 use tracing::{debug, info};
 
 use crate::error::{GbsError, Result};
-use crate::storage::Index;
-use crate::storage_backend::SledBackend;
+use crate::storage::index_state::ensure_readable;
+use crate::storage::refresh::refresh_documents;
+use crate::storage::{ChangeLog, Index, IndexTier};
+use crate::storage_backend::{IndexMetadata, SledBackend};
 
 /// Flush pending writes to disk (for persistent storage)
 pub async fn flush(backend: &Option<Arc<SledBackend>>) -> Result<()> {
@@ -20,23 +22,90 @@ pub async fn flush(backend: &Option<Arc<SledBackend>>) -> Result<()> {
     Ok(())
 }
 
+/// Persist an index's metadata (settings, mappings, aliases) to the backend
+pub async fn persist_index_metadata(
+    backend: &Option<Arc<SledBackend>>,
+    index: &Index,
+) -> Result<()> {
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let name = index.name.clone();
+        let metadata = index_metadata(index);
+
+        tokio::task::spawn_blocking(move || backend.store_index_metadata(&name, &metadata))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    Ok(())
+}
+
+/// Metadata of an index as it is persisted
+pub fn index_metadata(index: &Index) -> IndexMetadata {
+    IndexMetadata {
+        settings: index.settings.clone(),
+        mappings: index.mappings.clone(),
+        aliases: index.aliases.clone(),
+        search_profiles: index.search_profiles.clone(),
+        tier: index.tier,
+        state: index.state,
+        creation_date: index.creation_date,
+    }
+}
+
 /// Refresh an index (flush changes to persistent storage)
 pub async fn refresh_index(
-    _indices: &Arc<RwLock<HashMap<String, Index>>>,
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
     index_name: &str,
 ) -> Result<()> {
     debug!("Refreshing index: {}", index_name);
+    refresh_documents(indices, index_name).await;
     // For persistent storage, flush to disk
     flush(backend).await?;
     info!("Index '{}' refreshed successfully", index_name);
     Ok(())
 }
 
+/// Force-merge indices: check they can be read and, when `flush` is set,
+/// flush the backend so Sled can reclaim space on disk
+pub async fn force_merge(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_names: &[String],
+    flush_backend: bool,
+) -> Result<()> {
+    {
+        let indices = indices.read().await;
+        for name in index_names {
+            let index = indices
+                .get(name)
+                .ok_or_else(|| GbsError::IndexNotFound(name.clone()))?;
+            ensure_readable(index)?;
+        }
+    }
+
+    if flush_backend {
+        flush(backend).await?;
+        if let Some(backend) = backend {
+            let backend = backend.clone();
+            let size = tokio::task::spawn_blocking(move || backend.size_on_disk())
+                .await
+                .map_err(GbsError::TaskJoin)??;
+            debug!("Backend size on disk after force merge: {} bytes", size);
+        }
+    }
+    info!("Force-merged indices {:?}", index_names);
+    Ok(())
+}
+
 /// Load indices from backend (call this after creating with sled)
+///
+/// With `lazy`, the documents of hot indices are left on disk as well, and
+/// only their metadata and stats are loaded.
 pub async fn load_from_backend(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
     backend: &Option<Arc<SledBackend>>,
+    lazy: bool,
 ) -> Result<()> {
     if let Some(backend) = backend {
         info!("Loading indices from persistent storage");
@@ -50,15 +119,51 @@ pub async fn load_from_backend(
 
                 for index_name in indices_list {
                     debug!("Loading index: {}", index_name);
-                    if let Some((settings, mappings)) = backend.load_index_metadata(&index_name)? {
-                        let mut index = Index::new(index_name.clone(), settings, mappings);
+                    if let Some(metadata) = backend.load_index_metadata(&index_name)? {
+                        let mut index =
+                            Index::new(index_name.clone(), metadata.settings, metadata.mappings);
+                        index.aliases = metadata.aliases;
+                        index.search_profiles = metadata.search_profiles;
+                        index.creation_date = metadata.creation_date;
+                        index.state = metadata.state;
+                        index.changes =
+                            Arc::new(ChangeLog::restore(backend.load_changes(&index_name)?));
+                        index.set_versions(backend.load_versions(&index_name)?);
+
+                        if metadata.tier == IndexTier::Warm {
+                            // Warm indices stay on disk, only their stats are loaded
+                            let (doc_count, size) = backend.document_stats(&index_name)?;
+                            index.evict_documents();
+                            index.set_evicted_stats(doc_count, size);
+                            loaded.insert(index_name.clone(), index);
+                            info!(
+                                "Loaded warm index '{}' with {} documents on disk",
+                                index_name, doc_count
+                            );
+                            continue;
+                        }
+
+                        if lazy {
+                            // Hot indices are read from disk like warm ones
+                            // until they are moved to the hot tier
+                            let (doc_count, size) = backend.document_stats(&index_name)?;
+                            index.leave_documents_on_disk(doc_count, size);
+                            loaded.insert(index_name.clone(), index);
+                            info!(
+                                "Loaded index '{}' lazily with {} documents on disk",
+                                index_name, doc_count
+                            );
+                            continue;
+                        }
 
                         let documents = backend.load_all_documents(&index_name)?;
                         let doc_count = documents.len();
                         debug!("Loading {} documents for index: {}", doc_count, index_name);
                         for (doc_id, doc) in documents {
-                            index.documents.insert(doc_id, doc);
+                            index.insert_document(doc_id, doc);
                         }
+                        // Loaded documents are searchable at once
+                        index.refresh();
 
                         loaded.insert(index_name.clone(), index);
                         info!("Loaded index '{}' with {} documents", index_name, doc_count);
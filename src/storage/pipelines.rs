@@ -0,0 +1,955 @@
+//! Ingest pipelines
+//!
+//! A pipeline is a chain of processors that transform documents before they
+//! are stored. Pipelines are stored with `PUT /_ingest/pipeline/{id}` and run
+//! for index and bulk requests with `?pipeline={id}`:
+//!
+//! ```json
+//! {
+//!   "description": "normalize log lines",
+//!   "processors": [
+//!     { "grok": { "field": "message", "patterns": ["%{IP:client} %{WORD:method} %{NUMBER:bytes:int}"] } },
+//!     { "lowercase": { "field": "method" } },
+//!     { "date": { "field": "time", "formats": ["dd/MM/yyyy HH:mm:ss"] } },
+//!     { "remove": { "field": "time" } }
+//!   ]
+//! }
+//! ```
+//!
+//! Processors:
+//!
+//! - `set`: sets `field` to `value`, or to the value of `copy_from`; with
+//!   `override: false` an existing value is kept
+//! - `remove`: removes a field or a list of fields
+//! - `rename`: moves `field` to `target_field`, which must not exist
+//! - `lowercase` and `uppercase`: change the case of a string field, or of
+//!   each string of an array, in place or into `target_field`
+//! - `convert`: converts a field to `integer`, `long`, `float`, `double`,
+//!   `boolean`, `string` or `auto`
+//! - `date`: parses a field with the first of `formats` that fits (`ISO8601`,
+//!   `UNIX`, `UNIX_MS` or a pattern like `dd/MM/yyyy`), in `timezone`, into
+//!   `target_field` (default `@timestamp`)
+//! - `grok`: matches a field with the first of `patterns` that fits and sets
+//!   the named captures, `%{SYNTAX:field}` or `%{SYNTAX:field:int|float}`;
+//!   `pattern_definitions` adds or overrides patterns
+//!
+//! Fields are dotted paths into nested objects. Processors that take a field
+//! accept `ignore_missing` to skip documents without it, and every processor
+//! accepts `ignore_failure` to skip documents it fails for, and a `tag` that
+//! names it in simulation results. Any other failure rejects the document.
+
+use chrono::{FixedOffset, SecondsFormat};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::index_ops::wildcard_regex;
+use crate::storage::search::{get_field_value, parse_formatted_date, parse_time_zone};
+use crate::storage_backend::SledBackend;
+
+/// How deeply grok patterns may reference other patterns
+const MAX_GROK_DEPTH: usize = 16;
+
+/// Patterns grok expressions can reference by name
+const GROK_PATTERNS: [(&str, &str); 22] = [
+    ("WORD", r"\b\w+\b"),
+    ("NOTSPACE", r"\S+"),
+    ("SPACE", r"\s*"),
+    ("DATA", r".*?"),
+    ("GREEDYDATA", r".*"),
+    ("INT", r"[+-]?\d+"),
+    ("POSINT", r"\b[1-9]\d*\b"),
+    ("NONNEGINT", r"\b\d+\b"),
+    ("NUMBER", r"[+-]?(?:\d+(?:\.\d+)?|\.\d+)"),
+    ("BASE10NUM", r"[+-]?(?:\d+(?:\.\d+)?|\.\d+)"),
+    ("IPV4", r"(?:\d{1,3}\.){3}\d{1,3}"),
+    ("IP", r"%{IPV4}"),
+    (
+        "HOSTNAME",
+        r"\b[0-9A-Za-z][0-9A-Za-z-]{0,62}(?:\.[0-9A-Za-z][0-9A-Za-z-]{0,62})*\.?\b",
+    ),
+    ("USERNAME", r"[a-zA-Z0-9._-]+"),
+    ("USER", r"%{USERNAME}"),
+    (
+        "EMAILADDRESS",
+        r"[a-zA-Z0-9_.+-]+@[a-zA-Z0-9-]+(?:\.[a-zA-Z0-9-]+)*",
+    ),
+    (
+        "UUID",
+        r"[A-Fa-f0-9]{8}-(?:[A-Fa-f0-9]{4}-){3}[A-Fa-f0-9]{12}",
+    ),
+    ("URIPATH", r"(?:/[A-Za-z0-9$.+!*'(){},~:;=@#%&_\-]*)+"),
+    ("QUOTEDSTRING", r#""(?:[^"\\]|\\.)*""#),
+    (
+        "LOGLEVEL",
+        r"(?:TRACE|DEBUG|INFO|NOTICE|WARN(?:ING)?|ERROR|CRIT(?:ICAL)?|FATAL|SEVERE|EMERG(?:ENCY)?|[Tt]race|[Dd]ebug|[Ii]nfo|[Ww]arn(?:ing)?|[Ee]rr(?:or)?)",
+    ),
+    (
+        "TIMESTAMP_ISO8601",
+        r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(?::\d{2}(?:\.\d+)?)?(?:Z|[+-]\d{2}:?\d{2})?",
+    ),
+    ("HTTPDATE", r"\d{2}/\w{3}/\d{4}:\d{2}:\d{2}:\d{2} [+-]\d{4}"),
+];
+
+/// A stored ingest pipeline
+#[derive(Debug, Clone)]
+pub struct IngestPipeline {
+    /// The pipeline as it was stored, returned by `GET /_ingest/pipeline`
+    definition: serde_json::Value,
+    processors: Vec<Processor>,
+}
+
+/// One processor of a pipeline
+#[derive(Debug, Clone)]
+struct Processor {
+    /// The processor type, e.g. `set`
+    name: String,
+    tag: Option<String>,
+    kind: ProcessorKind,
+    ignore_failure: bool,
+}
+
+/// What one processor did to a document, as reported by verbose simulation
+#[derive(Debug)]
+pub struct ProcessorResult {
+    pub processor_type: String,
+    pub tag: Option<String>,
+    pub outcome: ProcessorOutcome,
+}
+
+#[derive(Debug)]
+pub enum ProcessorOutcome {
+    /// The document after the processor ran
+    Success(serde_json::Value),
+    /// The processor failed with `ignore_failure`; the document is unchanged
+    ErrorIgnored(serde_json::Value, GbsError),
+    /// The processor failed and the document is rejected
+    Error(GbsError),
+}
+
+#[derive(Debug, Clone)]
+enum ProcessorKind {
+    Set {
+        field: String,
+        value: SetValue,
+        override_existing: bool,
+    },
+    Remove {
+        fields: Vec<String>,
+        ignore_missing: bool,
+    },
+    Rename {
+        field: String,
+        target_field: String,
+        ignore_missing: bool,
+    },
+    ChangeCase {
+        field: String,
+        target_field: String,
+        upper: bool,
+        ignore_missing: bool,
+    },
+    Convert {
+        field: String,
+        target_field: String,
+        target_type: ConvertType,
+        ignore_missing: bool,
+    },
+    Date {
+        field: String,
+        target_field: String,
+        formats: Vec<String>,
+        time_zone: FixedOffset,
+    },
+    Grok {
+        field: String,
+        patterns: Vec<GrokPattern>,
+        ignore_missing: bool,
+    },
+}
+
+/// What a `set` processor sets a field to
+#[derive(Debug, Clone)]
+enum SetValue {
+    Value(serde_json::Value),
+    CopyFrom(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ConvertType {
+    Integer,
+    Float,
+    Boolean,
+    String,
+    Auto,
+}
+
+/// A compiled grok expression with the field and type of each capture group
+#[derive(Debug, Clone)]
+struct GrokPattern {
+    regex: Regex,
+    captures: Vec<(String, Option<ConvertType>)>,
+}
+
+impl IngestPipeline {
+    /// Parse the body of `PUT /_ingest/pipeline/{id}`
+    pub fn parse(body: &serde_json::Value) -> Result<Self> {
+        let processors = body
+            .get("processors")
+            .and_then(|p| p.as_array())
+            .ok_or_else(|| {
+                GbsError::IllegalArgument("[processors] required property is missing".to_string())
+            })?
+            .iter()
+            .map(Processor::parse)
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            definition: body.clone(),
+            processors,
+        })
+    }
+
+    /// The pipeline as returned by `GET /_ingest/pipeline/{id}`
+    pub fn to_json(&self) -> &serde_json::Value {
+        &self.definition
+    }
+
+    /// Run a document through the processors
+    pub fn run(&self, mut doc: serde_json::Value) -> Result<serde_json::Value> {
+        check_document(&doc)?;
+        for processor in &self.processors {
+            if let Err(e) = processor.apply(&mut doc) {
+                if !processor.ignore_failure {
+                    return Err(e);
+                }
+            }
+        }
+        Ok(doc)
+    }
+
+    /// Run a document through the processors, reporting the document after
+    /// each of them; processing stops at the first failure that is not ignored
+    pub fn run_verbose(&self, mut doc: serde_json::Value) -> Result<Vec<ProcessorResult>> {
+        check_document(&doc)?;
+        let mut results = Vec::with_capacity(self.processors.len());
+        for processor in &self.processors {
+            let (outcome, failed) = match processor.apply(&mut doc) {
+                Ok(()) => (ProcessorOutcome::Success(doc.clone()), false),
+                Err(e) if processor.ignore_failure => {
+                    (ProcessorOutcome::ErrorIgnored(doc.clone(), e), false)
+                }
+                Err(e) => (ProcessorOutcome::Error(e), true),
+            };
+            results.push(ProcessorResult {
+                processor_type: processor.name.clone(),
+                tag: processor.tag.clone(),
+                outcome,
+            });
+            if failed {
+                break;
+            }
+        }
+        Ok(results)
+    }
+}
+
+fn check_document(doc: &serde_json::Value) -> Result<()> {
+    if doc.is_object() {
+        Ok(())
+    } else {
+        Err(GbsError::IllegalArgument(
+            "ingest pipelines only process object documents".to_string(),
+        ))
+    }
+}
+
+impl Processor {
+    fn parse(spec: &serde_json::Value) -> Result<Self> {
+        let (name, options) = spec
+            .as_object()
+            .filter(|obj| obj.len() == 1)
+            .and_then(|obj| obj.iter().next())
+            .ok_or_else(|| {
+                GbsError::IllegalArgument(format!(
+                    "a processor must be an object with one processor type, got {}",
+                    spec
+                ))
+            })?;
+        let options = Options { name, options };
+        let ignore_missing = options.bool("ignore_missing")?;
+        let kind = match name.as_str() {
+            "set" => {
+                let value = match (
+                    options.options.get("value"),
+                    options.options.get("copy_from"),
+                ) {
+                    (Some(value), None) => SetValue::Value(value.clone()),
+                    (None, Some(_)) => SetValue::CopyFrom(options.string("copy_from")?),
+                    (Some(_), Some(_)) => {
+                        return Err(
+                            options.invalid("value", "cannot set both [value] and [copy_from]")
+                        )
+                    }
+                    (None, None) => {
+                        return Err(options.invalid("value", "required property is missing"))
+                    }
+                };
+                ProcessorKind::Set {
+                    field: options.string("field")?,
+                    value,
+                    override_existing: options.optional_bool("override")?.unwrap_or(true),
+                }
+            }
+            "remove" => {
+                let fields = match options.options.get("field") {
+                    Some(serde_json::Value::String(field)) => vec![field.clone()],
+                    Some(serde_json::Value::Array(fields)) => fields
+                        .iter()
+                        .map(|f| f.as_str().map(str::to_string))
+                        .collect::<Option<Vec<_>>>()
+                        .ok_or_else(|| {
+                            options.invalid("field", "must be a string or a list of strings")
+                        })?,
+                    _ => return Err(options.invalid("field", "required property is missing")),
+                };
+                ProcessorKind::Remove {
+                    fields,
+                    ignore_missing,
+                }
+            }
+            "rename" => ProcessorKind::Rename {
+                field: options.string("field")?,
+                target_field: options.string("target_field")?,
+                ignore_missing,
+            },
+            "lowercase" | "uppercase" => {
+                let field = options.string("field")?;
+                ProcessorKind::ChangeCase {
+                    target_field: options
+                        .optional_string("target_field")?
+                        .unwrap_or_else(|| field.clone()),
+                    field,
+                    upper: name == "uppercase",
+                    ignore_missing,
+                }
+            }
+            "convert" => {
+                let field = options.string("field")?;
+                let target_type = match options.string("type")?.as_str() {
+                    "integer" | "long" => ConvertType::Integer,
+                    "float" | "double" => ConvertType::Float,
+                    "boolean" => ConvertType::Boolean,
+                    "string" => ConvertType::String,
+                    "auto" => ConvertType::Auto,
+                    other => {
+                        return Err(
+                            options.invalid("type", &format!("type [{}] not supported", other))
+                        )
+                    }
+                };
+                ProcessorKind::Convert {
+                    target_field: options
+                        .optional_string("target_field")?
+                        .unwrap_or_else(|| field.clone()),
+                    field,
+                    target_type,
+                    ignore_missing,
+                }
+            }
+            "date" => {
+                let formats = options
+                    .options
+                    .get("formats")
+                    .and_then(|f| f.as_array())
+                    .and_then(|f| {
+                        f.iter()
+                            .map(|f| f.as_str().map(str::to_string))
+                            .collect::<Option<Vec<_>>>()
+                    })
+                    .filter(|f| !f.is_empty())
+                    .ok_or_else(|| {
+                        options.invalid("formats", "must be a non-empty list of strings")
+                    })?;
+                let time_zone = match options.optional_string("timezone")? {
+                    Some(zone) => parse_time_zone(&zone).ok_or_else(|| {
+                        options.invalid("timezone", &format!("unknown time zone [{}]", zone))
+                    })?,
+                    None => FixedOffset::east_opt(0).expect("UTC is a valid offset"),
+                };
+                ProcessorKind::Date {
+                    field: options.string("field")?,
+                    target_field: options
+                        .optional_string("target_field")?
+                        .unwrap_or_else(|| "@timestamp".to_string()),
+                    formats,
+                    time_zone,
+                }
+            }
+            "grok" => {
+                let mut definitions: HashMap<String, String> = GROK_PATTERNS
+                    .iter()
+                    .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+                    .collect();
+                if let Some(custom) = options.options.get("pattern_definitions") {
+                    let custom = custom.as_object().ok_or_else(|| {
+                        options.invalid("pattern_definitions", "must be an object")
+                    })?;
+                    for (name, pattern) in custom {
+                        let pattern = pattern.as_str().ok_or_else(|| {
+                            options.invalid("pattern_definitions", "patterns must be strings")
+                        })?;
+                        definitions.insert(name.clone(), pattern.to_string());
+                    }
+                }
+                let patterns = options
+                    .options
+                    .get("patterns")
+                    .and_then(|p| p.as_array())
+                    .filter(|p| !p.is_empty())
+                    .ok_or_else(|| {
+                        options.invalid("patterns", "must be a non-empty list of strings")
+                    })?
+                    .iter()
+                    .map(|pattern| {
+                        let pattern = pattern.as_str().ok_or_else(|| {
+                            options.invalid("patterns", "patterns must be strings")
+                        })?;
+                        GrokPattern::compile(pattern, &definitions)
+                            .map_err(|reason| options.invalid("patterns", &reason))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                ProcessorKind::Grok {
+                    field: options.string("field")?,
+                    patterns,
+                    ignore_missing,
+                }
+            }
+            other => {
+                return Err(GbsError::IllegalArgument(format!(
+                    "No processor type exists with name [{}]",
+                    other
+                )))
+            }
+        };
+        Ok(Self {
+            name: name.clone(),
+            tag: options.optional_string("tag")?,
+            kind,
+            ignore_failure: options.bool("ignore_failure")?,
+        })
+    }
+
+    /// Apply the processor; a failing processor leaves the document unchanged
+    fn apply(&self, doc: &mut serde_json::Value) -> Result<()> {
+        let before = self.ignore_failure.then(|| doc.clone());
+        let result = self.kind.apply(doc);
+        if let (Err(_), Some(before)) = (&result, before) {
+            *doc = before;
+        }
+        result
+    }
+}
+
+/// The options of a processor, with errors naming the processor
+struct Options<'a> {
+    name: &'a str,
+    options: &'a serde_json::Value,
+}
+
+impl Options<'_> {
+    fn invalid(&self, property: &str, reason: &str) -> GbsError {
+        GbsError::IllegalArgument(format!(
+            "[{}] processor [{}] {}",
+            self.name, property, reason
+        ))
+    }
+
+    fn optional_string(&self, property: &str) -> Result<Option<String>> {
+        match self.options.get(property) {
+            None => Ok(None),
+            Some(serde_json::Value::String(value)) => Ok(Some(value.clone())),
+            Some(_) => Err(self.invalid(property, "must be a string")),
+        }
+    }
+
+    fn string(&self, property: &str) -> Result<String> {
+        self.optional_string(property)?
+            .ok_or_else(|| self.invalid(property, "required property is missing"))
+    }
+
+    fn optional_bool(&self, property: &str) -> Result<Option<bool>> {
+        match self.options.get(property) {
+            None => Ok(None),
+            Some(serde_json::Value::Bool(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.invalid(property, "must be a boolean")),
+        }
+    }
+
+    fn bool(&self, property: &str) -> Result<bool> {
+        Ok(self.optional_bool(property)?.unwrap_or(false))
+    }
+}
+
+impl ProcessorKind {
+    fn apply(&self, doc: &mut serde_json::Value) -> Result<()> {
+        match self {
+            ProcessorKind::Set {
+                field,
+                value,
+                override_existing,
+            } => {
+                if !override_existing && get_field_value(doc, field).is_some_and(|v| !v.is_null()) {
+                    return Ok(());
+                }
+                let value = match value {
+                    SetValue::Value(value) => value.clone(),
+                    SetValue::CopyFrom(source) => get_field_value(doc, source)
+                        .cloned()
+                        .ok_or_else(|| missing_field(source))?,
+                };
+                set_field(doc, field, value)
+            }
+            ProcessorKind::Remove {
+                fields,
+                ignore_missing,
+            } => {
+                for field in fields {
+                    if remove_field(doc, field).is_none() && !ignore_missing {
+                        return Err(missing_field(field));
+                    }
+                }
+                Ok(())
+            }
+            ProcessorKind::Rename {
+                field,
+                target_field,
+                ignore_missing,
+            } => {
+                if get_field_value(doc, field).is_none() {
+                    return skip_missing(field, *ignore_missing);
+                }
+                if get_field_value(doc, target_field).is_some() {
+                    return Err(GbsError::IllegalArgument(format!(
+                        "field [{}] already exists",
+                        target_field
+                    )));
+                }
+                let value = remove_field(doc, field).expect("the field exists");
+                set_field(doc, target_field, value)
+            }
+            ProcessorKind::ChangeCase {
+                field,
+                target_field,
+                upper,
+                ignore_missing,
+            } => {
+                let Some(value) = get_field_value(doc, field).filter(|v| !v.is_null()) else {
+                    return skip_missing(field, *ignore_missing);
+                };
+                let change = |value: &serde_json::Value| match value {
+                    serde_json::Value::String(s) if *upper => {
+                        Ok(serde_json::json!(s.to_uppercase()))
+                    }
+                    serde_json::Value::String(s) => Ok(serde_json::json!(s.to_lowercase())),
+                    other => Err(GbsError::IllegalArgument(format!(
+                        "field [{}] of type [{}] cannot be cast to [string]",
+                        field,
+                        json_type(other)
+                    ))),
+                };
+                let changed = match value {
+                    serde_json::Value::Array(items) => {
+                        serde_json::Value::Array(items.iter().map(change).collect::<Result<_>>()?)
+                    }
+                    value => change(value)?,
+                };
+                set_field(doc, target_field, changed)
+            }
+            ProcessorKind::Convert {
+                field,
+                target_field,
+                target_type,
+                ignore_missing,
+            } => {
+                let Some(value) = get_field_value(doc, field).filter(|v| !v.is_null()) else {
+                    return skip_missing(field, *ignore_missing);
+                };
+                let converted = match value {
+                    serde_json::Value::Array(items) => serde_json::Value::Array(
+                        items
+                            .iter()
+                            .map(|item| convert(item, *target_type))
+                            .collect::<Result<_>>()?,
+                    ),
+                    value => convert(value, *target_type)?,
+                };
+                set_field(doc, target_field, converted)
+            }
+            ProcessorKind::Date {
+                field,
+                target_field,
+                formats,
+                time_zone,
+            } => {
+                let value = get_field_value(doc, field).ok_or_else(|| missing_field(field))?;
+                let text = match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Number(n) => n.to_string(),
+                    other => {
+                        return Err(GbsError::IllegalArgument(format!(
+                            "unable to parse date [{}]",
+                            other
+                        )))
+                    }
+                };
+                let date = formats
+                    .iter()
+                    .find_map(|format| {
+                        let format = match format.as_str() {
+                            "ISO8601" => "strict_date_optional_time",
+                            "UNIX" => "epoch_second",
+                            "UNIX_MS" => "epoch_millis",
+                            pattern => pattern,
+                        };
+                        parse_formatted_date(&text, Some(format), *time_zone)
+                    })
+                    .ok_or_else(|| {
+                        GbsError::IllegalArgument(format!("unable to parse date [{}]", text))
+                    })?;
+                let date = date.with_timezone(time_zone);
+                set_field(
+                    doc,
+                    target_field,
+                    serde_json::json!(date.to_rfc3339_opts(SecondsFormat::Millis, true)),
+                )
+            }
+            ProcessorKind::Grok {
+                field,
+                patterns,
+                ignore_missing,
+            } => {
+                let Some(value) = get_field_value(doc, field).filter(|v| !v.is_null()) else {
+                    return skip_missing(field, *ignore_missing);
+                };
+                let text = value.as_str().ok_or_else(|| {
+                    GbsError::IllegalArgument(format!(
+                        "field [{}] of type [{}] cannot be cast to [string]",
+                        field,
+                        json_type(value)
+                    ))
+                })?;
+                let captures = patterns
+                    .iter()
+                    .find_map(|pattern| pattern.captures(text))
+                    .ok_or_else(|| {
+                        GbsError::IllegalArgument(format!(
+                            "Provided Grok expressions do not match field value: [{}]",
+                            text
+                        ))
+                    })?;
+                for (target, value) in captures {
+                    set_field(doc, &target, value)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl GrokPattern {
+    /// Compile a grok expression, expanding `%{SYNTAX}` and `%{SYNTAX:field}`
+    fn compile(
+        pattern: &str,
+        definitions: &HashMap<String, String>,
+    ) -> std::result::Result<Self, String> {
+        let mut captures = Vec::new();
+        let expanded = expand_grok(pattern, definitions, &mut captures, 0)?;
+        let regex = Regex::new(&expanded)
+            .map_err(|e| format!("invalid grok pattern [{}]: {}", pattern, e))?;
+        Ok(Self { regex, captures })
+    }
+
+    /// The captured fields and values, if the expression matches the text
+    fn captures(&self, text: &str) -> Option<Vec<(String, serde_json::Value)>> {
+        let matched = self.regex.captures(text)?;
+        let values = self
+            .captures
+            .iter()
+            .enumerate()
+            .filter_map(|(i, (field, convert_type))| {
+                let text = matched.name(&format!("g{}", i))?.as_str();
+                let value = serde_json::json!(text);
+                let value = match convert_type {
+                    Some(convert_type) => convert(&value, *convert_type).unwrap_or(value),
+                    None => value,
+                };
+                Some((field.clone(), value))
+            })
+            .collect();
+        Some(values)
+    }
+}
+
+/// Replace the `%{...}` references of a grok expression with their patterns,
+/// turning named references into numbered capture groups
+fn expand_grok(
+    pattern: &str,
+    definitions: &HashMap<String, String>,
+    captures: &mut Vec<(String, Option<ConvertType>)>,
+    depth: usize,
+) -> std::result::Result<String, String> {
+    if depth > MAX_GROK_DEPTH {
+        return Err(format!(
+            "grok patterns nest deeper than {} levels",
+            MAX_GROK_DEPTH
+        ));
+    }
+    let mut expanded = String::with_capacity(pattern.len());
+    let mut rest = pattern;
+    while let Some(start) = rest.find("%{") {
+        expanded.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("unclosed reference in grok pattern [{}]", pattern))?;
+        let reference = &rest[start + 2..start + end];
+        let mut parts = reference.splitn(3, ':');
+        let syntax = parts.next().unwrap_or_default();
+        let definition = definitions.get(syntax).ok_or_else(|| {
+            format!(
+                "Unable to find pattern [{}] in Grok's pattern dictionary",
+                syntax
+            )
+        })?;
+        let inner = expand_grok(definition, definitions, captures, depth + 1)?;
+        match parts.next() {
+            Some(field) => {
+                let convert_type = match parts.next() {
+                    None => None,
+                    Some("int" | "long") => Some(ConvertType::Integer),
+                    Some("float" | "double") => Some(ConvertType::Float),
+                    Some(other) => {
+                        return Err(format!("unsupported grok capture type [{}]", other))
+                    }
+                };
+                expanded.push_str(&format!("(?P<g{}>{})", captures.len(), inner));
+                captures.push((field.to_string(), convert_type));
+            }
+            None => expanded.push_str(&format!("(?:{})", inner)),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Convert a scalar value to a type
+fn convert(value: &serde_json::Value, target_type: ConvertType) -> Result<serde_json::Value> {
+    let text = match value {
+        serde_json::Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
+    };
+    let failed = |type_name: &str| {
+        GbsError::IllegalArgument(format!("unable to convert [{}] to {}", text, type_name))
+    };
+    match target_type {
+        ConvertType::Integer => match value {
+            serde_json::Value::Number(n) if n.is_i64() => Ok(value.clone()),
+            _ => text
+                .parse::<i64>()
+                .map(|n| serde_json::json!(n))
+                .map_err(|_| failed("integer")),
+        },
+        ConvertType::Float => text
+            .parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map(|n| serde_json::json!(n))
+            .ok_or_else(|| failed("float")),
+        ConvertType::Boolean => match text.to_ascii_lowercase().as_str() {
+            "true" => Ok(serde_json::json!(true)),
+            "false" => Ok(serde_json::json!(false)),
+            _ => Err(failed("boolean")),
+        },
+        ConvertType::String => Ok(serde_json::json!(text)),
+        ConvertType::Auto => Ok(convert(value, ConvertType::Integer)
+            .or_else(|_| convert(value, ConvertType::Float))
+            .or_else(|_| convert(value, ConvertType::Boolean))
+            .unwrap_or_else(|_| value.clone())),
+    }
+}
+
+fn missing_field(field: &str) -> GbsError {
+    GbsError::IllegalArgument(format!(
+        "field [{}] not present as part of path [{}]",
+        field, field
+    ))
+}
+
+fn skip_missing(field: &str, ignore_missing: bool) -> Result<()> {
+    if ignore_missing {
+        Ok(())
+    } else {
+        Err(missing_field(field))
+    }
+}
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Set a dotted field, creating the objects on its path
+fn set_field(doc: &mut serde_json::Value, field: &str, value: serde_json::Value) -> Result<()> {
+    let mut parts = field.split('.').peekable();
+    let mut current = doc;
+    while let Some(part) = parts.next() {
+        let serde_json::Value::Object(obj) = current else {
+            return Err(GbsError::IllegalArgument(format!(
+                "cannot set [{}] with a parent of type [{}]",
+                field,
+                json_type(current)
+            )));
+        };
+        if parts.peek().is_none() {
+            obj.insert(part.to_string(), value);
+            return Ok(());
+        }
+        current = obj
+            .entry(part.to_string())
+            .or_insert_with(|| serde_json::json!({}));
+    }
+    Ok(())
+}
+
+/// Remove a dotted field, returning its value
+fn remove_field(doc: &mut serde_json::Value, field: &str) -> Option<serde_json::Value> {
+    match field.rsplit_once('.') {
+        Some((parent, name)) => {
+            let mut current = doc;
+            for part in parent.split('.') {
+                current = current.as_object_mut()?.get_mut(part)?;
+            }
+            current.as_object_mut()?.remove(name)
+        }
+        None => doc.as_object_mut()?.remove(field),
+    }
+}
+
+/// The ingest pipelines of a storage
+#[derive(Debug, Default)]
+pub struct IngestPipelines {
+    pipelines: RwLock<BTreeMap<String, IngestPipeline>>,
+}
+
+impl IngestPipelines {
+    /// Add or replace a pipeline in memory
+    pub fn insert(&self, id: &str, pipeline: IngestPipeline) {
+        self.write().insert(id.to_string(), pipeline);
+    }
+
+    /// Remove a pipeline from memory, returning whether it existed
+    pub fn remove(&self, id: &str) -> bool {
+        self.write().remove(id).is_some()
+    }
+
+    /// Whether a pipeline exists
+    pub fn contains(&self, id: &str) -> bool {
+        self.read().contains_key(id)
+    }
+
+    /// The pipelines whose id matches a pattern (`*` wildcards allowed)
+    pub fn get(&self, pattern: &str) -> Vec<(String, IngestPipeline)> {
+        let Some(regex) = wildcard_regex(pattern) else {
+            return Vec::new();
+        };
+        self.read()
+            .iter()
+            .filter(|(id, _)| regex.is_match(id))
+            .map(|(id, pipeline)| (id.clone(), pipeline.clone()))
+            .collect()
+    }
+
+    /// Run a document through a pipeline
+    pub fn run(&self, id: &str, doc: serde_json::Value) -> Result<serde_json::Value> {
+        let pipeline = self.read().get(id).cloned().ok_or_else(|| {
+            GbsError::IllegalArgument(format!("pipeline with id [{}] does not exist", id))
+        })?;
+        pipeline.run(doc)
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, IngestPipeline>> {
+        self.pipelines.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, IngestPipeline>> {
+        self.pipelines.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Store a pipeline, persisting it to the backend
+pub async fn put_pipeline(
+    pipelines: &IngestPipelines,
+    backend: &Option<Arc<SledBackend>>,
+    id: &str,
+    pipeline: IngestPipeline,
+) -> Result<()> {
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let value = pipeline.definition.clone();
+        let key = id.to_string();
+        tokio::task::spawn_blocking(move || backend.store_pipeline(&key, &value))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    pipelines.insert(id, pipeline);
+    info!("Stored ingest pipeline '{}'", id);
+    Ok(())
+}
+
+/// Delete a pipeline, removing it from the backend
+pub async fn delete_pipeline(
+    pipelines: &IngestPipelines,
+    backend: &Option<Arc<SledBackend>>,
+    id: &str,
+) -> Result<()> {
+    if !pipelines.contains(id) {
+        return Err(GbsError::PipelineNotFound(id.to_string()));
+    }
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let key = id.to_string();
+        tokio::task::spawn_blocking(move || backend.delete_pipeline(&key))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    pipelines.remove(id);
+    info!("Deleted ingest pipeline '{}'", id);
+    Ok(())
+}
+
+/// Load the persisted pipelines
+pub async fn load_pipelines(
+    pipelines: &IngestPipelines,
+    backend: &Option<Arc<SledBackend>>,
+) -> Result<()> {
+    let Some(backend) = backend else {
+        return Ok(());
+    };
+    let backend = backend.clone();
+    let stored = tokio::task::spawn_blocking(move || backend.load_pipelines())
+        .await
+        .map_err(GbsError::TaskJoin)??;
+    for (id, definition) in stored {
+        pipelines.insert(&id, IngestPipeline::parse(&definition)?);
+    }
+    debug!("Loaded {} ingest pipelines", pipelines.read().len());
+    Ok(())
+}
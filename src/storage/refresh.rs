@@ -0,0 +1,218 @@
+//! Visibility of writes to searches (refresh)
+//!
+//! Unless an index has an `index.refresh_interval`, its writes are
+//! searchable as soon as they are acknowledged. An index with a refresh
+//! interval behaves as in Elasticsearch: searches see its documents as of the
+//! last refresh, and the writes made since wait in the index until the next
+//! one. Reads by ID (`GET /{index}/_doc/{id}`) are real-time and see them.
+//!
+//! - `index.refresh_interval: 1s` refreshes the index when a search finds the
+//!   last refresh over an interval old, as Elasticsearch does for search-idle
+//!   shards, so no timer runs for indices nobody searches
+//! - `index.refresh_interval: -1` only refreshes on request
+//! - `POST /{index}/_refresh` and `?refresh=true` on a write refresh at once
+//! - `?refresh=wait_for` on a write answers once the write is searchable: at
+//!   the next interval, or at the next requested refresh when automatic
+//!   refreshes are off. Past `index.max_refresh_listeners` waiting writes, a
+//!   write refreshes the index instead of waiting.
+//!
+//! Held-back writes are the difference between the documents of the index
+//! and the copy of the index taken at the first write after a refresh, which
+//! searches read (see `Index::searchable`). The documents are kept in
+//! persistent maps, so the copy shares them with the index and writes made
+//! after it only copy the entries they change. Warm and lazily loaded indices
+//! are searched on disk, so their writes are always visible.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{Notify, RwLock};
+use tracing::debug;
+
+use crate::error::{GbsError, Result};
+use crate::storage::settings::{setting_value, time_value_millis};
+use crate::storage::Index;
+
+/// Default of `index.max_refresh_listeners`
+const DEFAULT_MAX_REFRESH_LISTENERS: usize = 1000;
+
+/// When the writes to an index become searchable
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshInterval {
+    /// As soon as they are made (no `index.refresh_interval`)
+    Immediate,
+    /// At the first search an interval after the last refresh
+    Every(Duration),
+    /// Only on request (`-1`)
+    Disabled,
+}
+
+impl RefreshInterval {
+    /// Refresh interval given by the `index.refresh_interval` of index
+    /// settings (see `Index::refresh_interval`)
+    pub fn of_settings(settings: Option<&serde_json::Value>) -> Self {
+        let value = settings.and_then(|settings| setting_value(settings, "index.refresh_interval"));
+        match value {
+            None | Some(serde_json::Value::Null) => RefreshInterval::Immediate,
+            Some(value) => match time_value_millis(value) {
+                Some(millis) => RefreshInterval::Every(Duration::from_millis(millis)),
+                None => RefreshInterval::Disabled,
+            },
+        }
+    }
+}
+
+/// What a write does about the visibility of its changes (`refresh`
+/// parameter)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RefreshPolicy {
+    /// Nothing (`false`, the default)
+    #[default]
+    None,
+    /// Refresh the written indices before answering (`true` or no value)
+    Immediate,
+    /// Answer once the changes are searchable (`wait_for`)
+    WaitFor,
+}
+
+impl RefreshPolicy {
+    /// Parse the value of a `refresh` parameter
+    pub fn parse(value: Option<&str>) -> Result<Self> {
+        match value {
+            None | Some("false") => Ok(RefreshPolicy::None),
+            Some("") | Some("true") => Ok(RefreshPolicy::Immediate),
+            Some("wait_for") => Ok(RefreshPolicy::WaitFor),
+            Some(other) => Err(GbsError::IllegalArgument(format!(
+                "Unknown value for refresh: [{}].",
+                other
+            ))),
+        }
+    }
+}
+
+/// Writes waiting for an index to be refreshed (`refresh=wait_for`), shared
+/// by the copies of the index
+#[derive(Debug, Default)]
+pub struct RefreshListeners {
+    refreshed: Notify,
+    waiting: AtomicUsize,
+}
+
+impl RefreshListeners {
+    /// Wake up the writes waiting for a refresh
+    pub fn notify(&self) {
+        self.refreshed.notify_waiters();
+    }
+}
+
+/// A write waiting for a refresh, counted until dropped, also when the
+/// request is abandoned
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn new(waiting: &'a AtomicUsize) -> Self {
+        waiting.fetch_add(1, Ordering::SeqCst);
+        Self(waiting)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Make the held-back writes of an index searchable
+///
+/// A missing index has nothing to refresh.
+pub async fn refresh_documents(indices: &Arc<RwLock<HashMap<String, Index>>>, index_name: &str) {
+    if let Some(index) = indices.write().await.get_mut(index_name) {
+        index.refresh();
+    }
+}
+
+/// Make the held-back writes of every index searchable
+pub async fn refresh_all_documents(indices: &Arc<RwLock<HashMap<String, Index>>>) {
+    for index in indices.write().await.values_mut() {
+        index.refresh();
+    }
+}
+
+/// Refresh an index whose refresh interval has passed since its last
+/// refresh, before it is searched
+pub async fn refresh_if_due(indices: &Arc<RwLock<HashMap<String, Index>>>, index_name: &str) {
+    let is_due = |index: &Index| match index.refresh_interval() {
+        RefreshInterval::Every(interval) => {
+            index.has_held_back_writes() && index.last_refresh().elapsed() >= interval
+        }
+        RefreshInterval::Immediate | RefreshInterval::Disabled => false,
+    };
+    // Most searches find nothing due and only need the read lock
+    if !indices.read().await.get(index_name).is_some_and(is_due) {
+        return;
+    }
+    let mut indices_guard = indices.write().await;
+    if let Some(index) = indices_guard
+        .get_mut(index_name)
+        .filter(|index| is_due(index))
+    {
+        debug!("Refreshing index '{}' at its refresh interval", index_name);
+        index.refresh();
+    }
+}
+
+/// Wait until the writes made so far to an index are searchable
+/// (`refresh=wait_for`)
+pub async fn wait_for_refresh(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+) -> Result<()> {
+    let indices_guard = indices.read().await;
+    let index = indices_guard
+        .get(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    if !index.has_held_back_writes() {
+        return Ok(());
+    }
+    match index.refresh_interval() {
+        RefreshInterval::Immediate => Ok(()),
+        RefreshInterval::Every(interval) => {
+            let remaining = interval.saturating_sub(index.last_refresh().elapsed());
+            drop(indices_guard);
+            tokio::time::sleep(remaining).await;
+            refresh_if_due(indices, index_name).await;
+            Ok(())
+        }
+        RefreshInterval::Disabled => {
+            let listeners = index.refresh_listeners().clone();
+            let max_listeners = index
+                .settings
+                .as_ref()
+                .and_then(|settings| setting_value(settings, "index.max_refresh_listeners"))
+                .and_then(|value| match value {
+                    serde_json::Value::String(s) => s.parse().ok(),
+                    value => value.as_u64().map(|max| max as usize),
+                })
+                .unwrap_or(DEFAULT_MAX_REFRESH_LISTENERS);
+            if listeners.waiting.load(Ordering::SeqCst) >= max_listeners {
+                drop(indices_guard);
+                debug!(
+                    "Too many writes wait for index '{}' to refresh, refreshing it",
+                    index_name
+                );
+                refresh_documents(indices, index_name).await;
+                return Ok(());
+            }
+            // Refreshes take the write lock, so none is missed between the
+            // check above and registering here
+            let refreshed = listeners.refreshed.notified();
+            tokio::pin!(refreshed);
+            refreshed.as_mut().enable();
+            let _waiting = Waiting::new(&listeners.waiting);
+            drop(indices_guard);
+            refreshed.await;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,436 @@
+//! Copying documents between indices (`POST /_reindex`)
+//!
+//! Mappings cannot be changed once a field is mapped, so migrations create a
+//! new index and reindex into it. Documents can be selected with a query and
+//! reshaped without scripts: `_source` includes/excludes drop fields and
+//! `rename` moves them.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::bulk_ops::BulkAction;
+use crate::error::{GbsError, Result};
+use crate::storage::aggregation_cache::AggregationCache;
+use crate::storage::document_ops::{execute_bulk, fetch_document};
+use crate::storage::index_ops::{create_index, resolve_write_index};
+use crate::storage::search_impl::{search, ParallelScoring, SearchRequest};
+use crate::storage::templates::IndexTemplates;
+use crate::storage::{Index, IngestRoutes, StorageLimits};
+use crate::storage_backend::SledBackend;
+use crate::tasks::{TaskHandle, CANCELED_BY_USER};
+
+/// Default number of documents written per batch
+const DEFAULT_BATCH_SIZE: usize = 1000;
+
+/// How documents are written to the destination
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReindexOpType {
+    /// Overwrite documents with the same ID
+    Index,
+    /// Only create missing documents; existing ones are version conflicts
+    Create,
+}
+
+/// A reindex request, as given in the body of `POST /_reindex`
+#[derive(Debug, Clone)]
+pub struct ReindexRequest {
+    /// Index expression of the source indices
+    pub source: String,
+    /// Query selecting the documents to copy
+    pub query: serde_json::Value,
+    /// `_source` filter applied to the copied documents
+    pub source_filter: Option<serde_json::Value>,
+    /// Fields to rename, from old to new (dotted paths)
+    pub rename: BTreeMap<String, String>,
+    /// Number of documents written per batch
+    pub batch_size: usize,
+    /// Destination index, created if it does not exist
+    pub dest: String,
+    pub op_type: ReindexOpType,
+    /// Maximum number of documents to copy
+    pub max_docs: Option<usize>,
+    /// Whether version conflicts are counted instead of aborting
+    pub proceed_on_conflicts: bool,
+}
+
+impl ReindexRequest {
+    /// Parse a reindex request body
+    ///
+    /// ```json
+    /// {
+    ///   "source": {"index": "products-v1", "query": {...}, "_source": {"excludes": ["tmp"]}},
+    ///   "dest": {"index": "products-v2", "op_type": "create"},
+    ///   "rename": {"title": "name"},
+    ///   "conflicts": "proceed",
+    ///   "max_docs": 1000
+    /// }
+    /// ```
+    pub fn parse(body: &serde_json::Value) -> Result<Self> {
+        let source = body
+            .get("source")
+            .ok_or_else(|| invalid("[source] is required"))?;
+        let dest = body
+            .get("dest")
+            .ok_or_else(|| invalid("[dest] is required"))?;
+
+        let source_index = match source.get("index") {
+            Some(serde_json::Value::String(index)) => index.clone(),
+            Some(serde_json::Value::Array(indices)) => indices
+                .iter()
+                .map(|index| {
+                    index
+                        .as_str()
+                        .ok_or_else(|| invalid("[source.index] must be strings"))
+                })
+                .collect::<Result<Vec<_>>>()?
+                .join(","),
+            _ => return Err(invalid("[source.index] is required")),
+        };
+        let dest_index = dest
+            .get("index")
+            .and_then(|index| index.as_str())
+            .ok_or_else(|| invalid("[dest.index] is required"))?
+            .to_string();
+
+        let op_type = match dest.get("op_type").and_then(|op| op.as_str()) {
+            None | Some("index") => ReindexOpType::Index,
+            Some("create") => ReindexOpType::Create,
+            Some(other) => {
+                return Err(invalid(&format!(
+                    "[dest.op_type] must be [index] or [create], got [{}]",
+                    other
+                )))
+            }
+        };
+        let proceed_on_conflicts = match body.get("conflicts").and_then(|c| c.as_str()) {
+            None | Some("abort") => false,
+            Some("proceed") => true,
+            Some(other) => {
+                return Err(invalid(&format!(
+                    "[conflicts] must be [abort] or [proceed], got [{}]",
+                    other
+                )))
+            }
+        };
+
+        let rename = match body.get("rename") {
+            None => BTreeMap::new(),
+            Some(serde_json::Value::Object(fields)) => fields
+                .iter()
+                .map(|(from, to)| {
+                    let to = to.as_str().ok_or_else(|| {
+                        invalid(&format!("[rename.{}] must be a field name", from))
+                    })?;
+                    Ok((from.clone(), to.to_string()))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(invalid("[rename] must be an object of field names")),
+        };
+
+        // `size` is the pre-7.x name of `max_docs`
+        let max_docs = body
+            .get("max_docs")
+            .or_else(|| body.get("size"))
+            .map(|max_docs| {
+                max_docs
+                    .as_u64()
+                    .map(|max_docs| max_docs as usize)
+                    .ok_or_else(|| invalid("[max_docs] must be a non-negative integer"))
+            })
+            .transpose()?;
+        let batch_size = match source.get("size") {
+            Some(size) => size
+                .as_u64()
+                .filter(|&size| size > 0)
+                .ok_or_else(|| invalid("[source.size] must be a positive integer"))?
+                as usize,
+            None => DEFAULT_BATCH_SIZE,
+        };
+
+        Ok(Self {
+            source: source_index,
+            query: source
+                .get("query")
+                .cloned()
+                .unwrap_or_else(|| serde_json::json!({ "match_all": {} })),
+            source_filter: source.get("_source").cloned(),
+            rename,
+            batch_size,
+            dest: dest_index,
+            op_type,
+            max_docs,
+            proceed_on_conflicts,
+        })
+    }
+}
+
+/// A document that could not be copied
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexFailure {
+    pub index: String,
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub id: String,
+    pub cause: ReindexFailureCause,
+    pub status: u16,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReindexFailureCause {
+    #[serde(rename = "type")]
+    pub r#type: String,
+    pub reason: String,
+}
+
+/// Outcome of a reindex, in the format of the Elasticsearch response
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ReindexResponse {
+    pub took: u64,
+    pub timed_out: bool,
+    /// Number of documents selected for copying
+    pub total: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+    pub batches: usize,
+    pub version_conflicts: usize,
+    pub noops: usize,
+    pub failures: Vec<ReindexFailure>,
+    /// Why the reindex stopped early, if it was cancelled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub canceled: Option<String>,
+}
+
+impl ReindexResponse {
+    /// Progress counters, reported as the status of the reindex task
+    fn progress(&self) -> serde_json::Value {
+        serde_json::json!({
+            "total": self.total,
+            "created": self.created,
+            "updated": self.updated,
+            "deleted": self.deleted,
+            "batches": self.batches,
+            "version_conflicts": self.version_conflicts,
+            "noops": self.noops
+        })
+    }
+}
+
+/// Copy the documents of the source indices into the destination index
+///
+/// Batches are written like bulk requests. The first batch with failures
+/// ends the reindex; documents of earlier batches stay copied. Progress is
+/// reported to `task`, and cancelling it stops the reindex before the next
+/// batch.
+#[allow(clippy::too_many_arguments)]
+pub async fn reindex(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    routes: &IngestRoutes,
+    templates: &IndexTemplates,
+    cache: &AggregationCache,
+    scoring: ParallelScoring,
+    source_indices: &[String],
+    request: &ReindexRequest,
+    task: &TaskHandle,
+) -> Result<ReindexResponse> {
+    let start_time = std::time::Instant::now();
+    if source_indices.contains(&request.dest) {
+        return Err(invalid(&format!(
+            "reindex cannot write into an index its reading from [{}]",
+            request.dest
+        )));
+    }
+
+    let mut documents = Vec::new();
+    for source_index in source_indices {
+        let remaining = request
+            .max_docs
+            .map(|max_docs| max_docs.saturating_sub(documents.len()));
+        if remaining == Some(0) {
+            break;
+        }
+        let search_request = SearchRequest {
+            from: Some(0),
+            size: Some(remaining.map_or(u32::MAX, |remaining| {
+                remaining.min(u32::MAX as usize) as u32
+            })),
+            source_filter: request.source_filter.clone(),
+            ..SearchRequest::new(request.query.clone())
+        };
+        let response = search(
+            indices,
+            backend,
+            source_index,
+            &search_request,
+            cache,
+            scoring,
+        )
+        .await?;
+        let hits = response["hits"]["hits"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        for mut hit in hits {
+            let id = hit["_id"].as_str().unwrap_or_default().to_string();
+            let mut document = hit["_source"].take();
+            for (from, to) in &request.rename {
+                if let Some(value) = take_field(&mut document, from) {
+                    put_field(&mut document, to, value);
+                }
+            }
+            documents.push((id, document));
+        }
+    }
+
+    let dest_exists = resolve_write_index(&*indices.read().await, &request.dest).is_some();
+    if !dest_exists && routes.get(&request.dest).is_none() {
+        info!("Creating destination index '{}' for reindex", request.dest);
+        create_index(
+            indices,
+            backend,
+            limits,
+            templates,
+            &request.dest,
+            None,
+            None,
+        )
+        .await?;
+    }
+
+    let mut response = ReindexResponse {
+        total: documents.len(),
+        ..Default::default()
+    };
+    task.set_status(response.progress());
+    for batch in documents.chunks(request.batch_size) {
+        if task.is_cancelled() {
+            info!("Reindex into '{}' cancelled", request.dest);
+            response.canceled = Some(CANCELED_BY_USER.to_string());
+            break;
+        }
+        response.batches += 1;
+
+        let mut actions = Vec::with_capacity(batch.len());
+        let mut existing = HashSet::new();
+        for (id, document) in batch {
+            if fetch_document(indices, backend, &request.dest, id)
+                .await?
+                .is_some()
+            {
+                if request.op_type == ReindexOpType::Create {
+                    response.version_conflicts += 1;
+                    if !request.proceed_on_conflicts {
+                        response.failures.push(failure(
+                            &request.dest,
+                            id,
+                            409,
+                            "version_conflict_engine_exception",
+                            format!("[_doc][{}]: version conflict, document already exists", id),
+                        ));
+                    }
+                    continue;
+                }
+                existing.insert(id.as_str());
+            }
+            actions.push((
+                id,
+                BulkAction::Index {
+                    index: request.dest.clone(),
+                    id: Some(id.clone()),
+                    document: document.clone(),
+                },
+            ));
+        }
+
+        let (ids, actions): (Vec<_>, Vec<_>) = actions.into_iter().unzip();
+        let results = execute_bulk(indices, backend, limits, routes, templates, actions).await;
+        for (id, result) in ids.into_iter().zip(results) {
+            match result {
+                Ok(_) if existing.contains(id.as_str()) => response.updated += 1,
+                Ok(_) => response.created += 1,
+                Err(e) => response.failures.push(failure(
+                    &request.dest,
+                    id,
+                    e.status_code().as_u16(),
+                    e.error_type(),
+                    e.reason(),
+                )),
+            }
+        }
+        task.set_status(response.progress());
+        if !response.failures.is_empty() {
+            warn!(
+                "Reindex into '{}' aborted after {} failures",
+                request.dest,
+                response.failures.len()
+            );
+            break;
+        }
+    }
+
+    response.took = start_time.elapsed().as_millis() as u64;
+    info!(
+        "Reindexed {} documents from {:?} into '{}' ({} created, {} updated)",
+        response.total, source_indices, request.dest, response.created, response.updated
+    );
+    Ok(response)
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(format!("Invalid reindex request: {}", reason))
+}
+
+pub(crate) fn failure(
+    index: &str,
+    id: &str,
+    status: u16,
+    error_type: &str,
+    reason: String,
+) -> ReindexFailure {
+    ReindexFailure {
+        index: index.to_string(),
+        r#type: "_doc".to_string(),
+        id: id.to_string(),
+        cause: ReindexFailureCause {
+            r#type: error_type.to_string(),
+            reason,
+        },
+        status,
+    }
+}
+
+/// Remove the field at a dotted path from a document
+fn take_field(document: &mut serde_json::Value, path: &str) -> Option<serde_json::Value> {
+    let object = document.as_object_mut()?;
+    if let Some(value) = object.remove(path) {
+        return Some(value);
+    }
+    let (first, rest) = path.split_once('.')?;
+    take_field(object.get_mut(first)?, rest)
+}
+
+/// Set the field at a dotted path of a document, creating parent objects
+fn put_field(document: &mut serde_json::Value, path: &str, value: serde_json::Value) {
+    let Some(object) = document.as_object_mut() else {
+        return;
+    };
+    match path.split_once('.') {
+        Some((first, rest)) => {
+            let parent = object
+                .entry(first.to_string())
+                .or_insert_with(|| serde_json::json!({}));
+            if !parent.is_object() {
+                *parent = serde_json::json!({});
+            }
+            put_field(parent, rest, value);
+        }
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+//! Document and index retention
+//!
+//! Indices opt in with dynamic settings, usually given by an index template so
+//! that every index matching its patterns gets them:
+//!
+//! - `index.retention.max_document_age`: documents whose timestamp (the
+//!   `index.retention.field`, `@timestamp` by default) is older are deleted;
+//!   documents without a timestamp are kept
+//! - `index.retention.rollover_after`: an index written through an alias rolls
+//!   the alias over to a new index once it is this old (empty indices are not
+//!   rolled over)
+//! - `index.retention.delete_after`: the index is deleted once it is this old,
+//!   unless it still holds the alias it rolls over
+//!
+//! Ages of indices are counted from their creation date. A background task
+//! applies the settings periodically (see `spawn_retention`).
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::error::{GbsError, Result};
+use crate::storage::document_ops::delete_document;
+use crate::storage::index_ops::{delete_index, rollover_index};
+use crate::storage::settings::{setting_value, time_value_millis};
+use crate::storage::tiering::warm_index_backend;
+use crate::storage::{get_field_value, parse_date, Index, IndexState, Storage, StorageLimits};
+use crate::storage_backend::SledBackend;
+
+/// Default timestamp field of `index.retention.max_document_age`
+pub const DEFAULT_RETENTION_FIELD: &str = "@timestamp";
+
+/// Outcome of a retention run
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RetentionReport {
+    /// Number of expired documents deleted, per index
+    pub deleted_documents: BTreeMap<String, usize>,
+    /// Aliases rolled over, with the index each moved to
+    pub rolled_over: Vec<(String, String)>,
+    pub deleted_indices: Vec<String>,
+}
+
+impl RetentionReport {
+    /// Check if the run changed nothing
+    pub fn is_empty(&self) -> bool {
+        self.deleted_documents.is_empty()
+            && self.rolled_over.is_empty()
+            && self.deleted_indices.is_empty()
+    }
+}
+
+/// Retention settings of an index, in milliseconds
+#[derive(Debug, Clone)]
+struct RetentionPolicy {
+    field: String,
+    max_document_age: Option<u64>,
+    rollover_after: Option<u64>,
+    delete_after: Option<u64>,
+}
+
+impl RetentionPolicy {
+    /// The policy of an index, `None` if it sets no retention
+    fn from_index(index: &Index) -> Option<Self> {
+        let settings = index.settings.as_ref()?;
+        let millis = |key| setting_value(settings, key).and_then(time_value_millis);
+        let policy = Self {
+            field: setting_value(settings, "index.retention.field")
+                .and_then(|v| v.as_str())
+                .unwrap_or(DEFAULT_RETENTION_FIELD)
+                .to_string(),
+            max_document_age: millis("index.retention.max_document_age"),
+            rollover_after: millis("index.retention.rollover_after"),
+            delete_after: millis("index.retention.delete_after"),
+        };
+        let has_retention = policy.max_document_age.is_some()
+            || policy.rollover_after.is_some()
+            || policy.delete_after.is_some();
+        has_retention.then_some(policy)
+    }
+}
+
+/// Index due for retention, as seen at the start of a run
+struct Candidate {
+    name: String,
+    policy: RetentionPolicy,
+    /// Age in milliseconds, if the creation date is known
+    age: Option<u64>,
+    /// Expired documents of a hot index (warm indices are read from disk)
+    expired: Option<Vec<String>>,
+}
+
+/// Check if a document's timestamp is before `cutoff` (milliseconds)
+fn is_expired(document: &serde_json::Value, field: &str, cutoff: i64) -> bool {
+    get_field_value(document, field)
+        .and_then(parse_date)
+        .is_some_and(|timestamp| timestamp.timestamp_millis() < cutoff)
+}
+
+/// Apply the retention settings of every index
+///
+/// Expired documents are deleted first, then aliases are rolled over and
+/// finally old indices are deleted, so an index rolled over in a run can be
+/// deleted in the same run.
+pub async fn apply_retention(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+) -> Result<RetentionReport> {
+    let now = chrono::Utc::now().timestamp_millis();
+    let mut candidates: Vec<Candidate> = indices
+        .read()
+        .await
+        .values()
+        .filter_map(|index| {
+            let policy = RetentionPolicy::from_index(index)?;
+            let expired = match policy.max_document_age {
+                // Closed and frozen indices keep their documents
+                Some(max_age) if !index.is_warm() && index.state == IndexState::Open => {
+                    let cutoff = now - max_age as i64;
+                    Some(
+                        index
+                            .documents
+                            .iter()
+                            .filter(|(_, doc)| is_expired(doc, &policy.field, cutoff))
+                            .map(|(id, _)| id.clone())
+                            .collect(),
+                    )
+                }
+                _ => None,
+            };
+            Some(Candidate {
+                name: index.name.clone(),
+                age: index
+                    .creation_date
+                    .map(|created| (now as u64).saturating_sub(created)),
+                policy,
+                expired,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let mut report = RetentionReport::default();
+    for candidate in &mut candidates {
+        let Some(max_age) = candidate.policy.max_document_age else {
+            continue;
+        };
+        let expired = match candidate.expired.take() {
+            Some(expired) => expired,
+            None => {
+                let sled = warm_index_backend(backend, &candidate.name)?;
+                let name = candidate.name.clone();
+                let documents = tokio::task::spawn_blocking(move || sled.load_all_documents(&name))
+                    .await
+                    .map_err(GbsError::TaskJoin)??;
+                let cutoff = now - max_age as i64;
+                let field = &candidate.policy.field;
+                documents
+                    .into_iter()
+                    .filter(|(_, document)| is_expired(document, field, cutoff))
+                    .map(|(id, _)| id)
+                    .collect()
+            }
+        };
+
+        let mut deleted = 0;
+        for id in expired {
+            match delete_document(indices, backend, &candidate.name, &id).await {
+                Ok(_) => deleted += 1,
+                // Deleted in the meantime
+                Err(GbsError::DocumentNotFound(_)) | Err(GbsError::IndexNotFound(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        if deleted > 0 {
+            info!(
+                "Deleted {} expired documents from index '{}'",
+                deleted, candidate.name
+            );
+            report
+                .deleted_documents
+                .insert(candidate.name.clone(), deleted);
+        }
+    }
+
+    for candidate in &candidates {
+        let (Some(rollover_after), Some(age)) = (candidate.policy.rollover_after, candidate.age)
+        else {
+            continue;
+        };
+        if age < rollover_after {
+            continue;
+        }
+        let mut indices_guard = indices.write().await;
+        let aliases = match indices_guard.get(&candidate.name) {
+            Some(index) if index.doc_count() > 0 => index.aliases.clone(),
+            _ => continue,
+        };
+        for alias in aliases {
+            match rollover_index(&mut indices_guard, backend, limits, &alias, &candidate.name).await
+            {
+                Ok(new_name) => report.rolled_over.push((alias, new_name)),
+                // The next run tries again, e.g. once the index limit allows it
+                Err(e) => warn!(
+                    "Retention rollover of alias '{}' from index '{}' failed: {}",
+                    alias, candidate.name, e
+                ),
+            }
+        }
+    }
+
+    for candidate in &candidates {
+        let (Some(delete_after), Some(age)) = (candidate.policy.delete_after, candidate.age) else {
+            continue;
+        };
+        if age < delete_after {
+            continue;
+        }
+        let holds_alias = indices
+            .read()
+            .await
+            .get(&candidate.name)
+            .is_some_and(|index| !index.aliases.is_empty());
+        if holds_alias && candidate.policy.rollover_after.is_some() {
+            debug!(
+                "Index '{}' is past its retention but still holds its rollover alias",
+                candidate.name
+            );
+            continue;
+        }
+        match delete_index(indices, backend, &candidate.name).await {
+            Ok(()) => report.deleted_indices.push(candidate.name.clone()),
+            Err(GbsError::IndexNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Periodically apply the retention settings of every index
+pub fn spawn_retention(storage: Storage, interval: Duration) -> tokio::task::JoinHandle<()> {
+    info!(
+        "Background retention enabled (checked every {:?})",
+        interval
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match storage.apply_retention().await {
+                Ok(report) if !report.is_empty() => info!(
+                    "Retention deleted documents {:?}, rolled over {:?}, deleted indices {:?}",
+                    report.deleted_documents, report.rolled_over, report.deleted_indices
+                ),
+                Ok(_) => debug!("No documents or indices due for retention"),
+                Err(e) => warn!("Background retention failed: {}", e),
+            }
+        }
+    })
+}
@@ -0,0 +1,266 @@
+//! Rolling an alias over to a new index (`POST /{alias}/_rollover`)
+//!
+//! The alias must point at exactly one index. When any of the request's
+//! conditions is met (or it gives none), a new index is created from the
+//! index templates matching its name and the request's settings, mappings and
+//! aliases, and the alias moves to it in the same step.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::field_caps::mapping_properties;
+use crate::storage::index_ops::{check_rollover_target, roll_alias_over};
+use crate::storage::index_sort::validate_index_sort;
+use crate::storage::search::validate_normalizers;
+use crate::storage::settings::{byte_size_bytes, time_value_millis, validate_new_settings};
+use crate::storage::templates::IndexTemplates;
+use crate::storage::warmers::validate_warmers;
+use crate::storage::{next_rollover_name, resolve_date_math_index_name, Index, StorageLimits};
+use crate::storage_backend::SledBackend;
+
+/// A condition for rolling an alias over
+#[derive(Debug, Clone, PartialEq)]
+pub enum RolloverCondition {
+    /// The index holds at least this many documents
+    Docs(u64),
+    /// The index was created at least this long ago, in milliseconds
+    Age { text: String, millis: u64 },
+    /// The index's documents take at least this many bytes
+    Size { text: String, bytes: u64 },
+}
+
+impl RolloverCondition {
+    /// Parse a condition from its name and value in the request
+    fn parse(name: &str, value: &serde_json::Value) -> Result<Self> {
+        let text = || {
+            value
+                .as_str()
+                .map(str::to_string)
+                .unwrap_or_else(|| value.to_string())
+        };
+        match name {
+            "max_docs" => value.as_u64().map(Self::Docs).ok_or_else(|| {
+                invalid(&format!(
+                    "[max_docs] must be a non-negative integer, got [{}]",
+                    value
+                ))
+            }),
+            "max_age" => time_value_millis(value)
+                .map(|millis| Self::Age {
+                    text: text(),
+                    millis,
+                })
+                .ok_or_else(|| {
+                    invalid(&format!("[max_age] must be a time value, got [{}]", text()))
+                }),
+            "max_size" => byte_size_bytes(value)
+                .map(|bytes| Self::Size {
+                    text: text(),
+                    bytes,
+                })
+                .ok_or_else(|| {
+                    invalid(&format!("[max_size] must be a byte size, got [{}]", text()))
+                }),
+            other => Err(invalid(&format!("unknown condition [{}]", other))),
+        }
+    }
+
+    /// Name of the condition in the response, like `[max_docs: 1000]`
+    pub fn description(&self) -> String {
+        match self {
+            Self::Docs(docs) => format!("[max_docs: {}]", docs),
+            Self::Age { text, .. } => format!("[max_age: {}]", text),
+            Self::Size { text, .. } => format!("[max_size: {}]", text),
+        }
+    }
+
+    /// Check if an index meets the condition at `now` (milliseconds)
+    fn is_met(&self, index: &Index, now: u64) -> bool {
+        match self {
+            Self::Docs(docs) => index.doc_count() as u64 >= *docs,
+            Self::Age { millis, .. } => index
+                .creation_date
+                .is_some_and(|created| now.saturating_sub(created) >= *millis),
+            Self::Size { bytes, .. } => index.size_in_bytes >= *bytes,
+        }
+    }
+}
+
+/// A rollover request
+#[derive(Debug, Clone)]
+pub struct RolloverRequest {
+    /// Alias to roll over
+    pub alias: String,
+    /// Name of the new index, by default the next in the `name-000001` sequence
+    pub new_index: Option<String>,
+    pub conditions: Vec<RolloverCondition>,
+    /// Settings and mappings of the new index, over its templates'
+    pub settings: Option<serde_json::Value>,
+    pub mappings: Option<serde_json::Value>,
+    /// Aliases added to the new index besides the rolled over one
+    pub aliases: Vec<String>,
+    /// Whether to only evaluate the conditions
+    pub dry_run: bool,
+}
+
+impl RolloverRequest {
+    /// Parse a request from its path, body and query parameters
+    ///
+    /// The body optionally holds `conditions`, `settings`, `mappings` and
+    /// `aliases`; `dry_run` is a parameter.
+    pub fn parse(
+        alias: &str,
+        new_index: Option<&str>,
+        body: &serde_json::Value,
+        params: &HashMap<String, String>,
+    ) -> Result<Self> {
+        let conditions = match body.get("conditions") {
+            Some(serde_json::Value::Object(conditions)) => conditions
+                .iter()
+                .map(|(name, value)| RolloverCondition::parse(name, value))
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(invalid("[conditions] must be an object")),
+            None => Vec::new(),
+        };
+        let settings = body.get("settings").cloned();
+        if let Some(settings) = &settings {
+            validate_new_settings(settings)?;
+        }
+        let aliases = match body.get("aliases") {
+            Some(serde_json::Value::Object(aliases)) => aliases.keys().cloned().collect(),
+            Some(_) => return Err(invalid("[aliases] must be an object")),
+            None => Vec::new(),
+        };
+
+        Ok(Self {
+            alias: alias.to_string(),
+            new_index: new_index.map(str::to_string),
+            conditions,
+            settings,
+            mappings: body.get("mappings").cloned(),
+            aliases,
+            dry_run: params.get("dry_run").is_some_and(|v| v == "true"),
+        })
+    }
+}
+
+/// Outcome of a rollover, in the format of the Elasticsearch response
+#[derive(Debug, Clone, Serialize)]
+pub struct RolloverResponse {
+    pub acknowledged: bool,
+    pub shards_acknowledged: bool,
+    pub old_index: String,
+    pub new_index: String,
+    pub rolled_over: bool,
+    pub dry_run: bool,
+    /// Whether each condition was met, by description
+    pub conditions: BTreeMap<String, bool>,
+}
+
+/// Roll an alias over to a new index if the request's conditions allow it
+pub async fn rollover(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    limits: &StorageLimits,
+    templates: &IndexTemplates,
+    request: &RolloverRequest,
+) -> Result<RolloverResponse> {
+    let alias = &request.alias;
+    // The write lock is held throughout, so the conditions still hold when
+    // the alias moves
+    let mut indices_guard = indices.write().await;
+    if indices_guard.contains_key(alias) {
+        return Err(GbsError::IllegalArgument(format!(
+            "rollover target [{}] is a [concrete index] but one of [alias] was expected",
+            alias
+        )));
+    }
+    let mut sources = indices_guard
+        .values()
+        .filter(|index| index.aliases.iter().any(|a| a == alias));
+    let source = match (sources.next(), sources.next()) {
+        (Some(index), None) => index,
+        (None, _) => return Err(GbsError::AliasNotFound(alias.clone())),
+        (Some(_), Some(_)) => {
+            return Err(GbsError::IllegalArgument(format!(
+                "rollover target [{}] does not point to a write index",
+                alias
+            )))
+        }
+    };
+
+    let new_name = match &request.new_index {
+        Some(name) => resolve_date_math_index_name(name, chrono::Utc::now())?,
+        None => next_rollover_name(&source.name),
+    };
+    check_rollover_target(&indices_guard, limits, &new_name)?;
+
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let conditions: BTreeMap<String, bool> = request
+        .conditions
+        .iter()
+        .map(|condition| (condition.description(), condition.is_met(source, now)))
+        .collect();
+    let met = conditions.is_empty() || conditions.values().any(|&met| met);
+    let mut response = RolloverResponse {
+        acknowledged: false,
+        shards_acknowledged: false,
+        old_index: source.name.clone(),
+        new_index: new_name.clone(),
+        rolled_over: false,
+        dry_run: request.dry_run,
+        conditions,
+    };
+    if !met || request.dry_run {
+        debug!(
+            "Not rolling over alias '{}' (conditions met: {}, dry run: {})",
+            alias, met, request.dry_run
+        );
+        return Ok(response);
+    }
+
+    let template = templates.resolve(&new_name);
+    let settings = template.index_settings(request.settings.clone());
+    let mappings = template.index_mappings(request.mappings.clone());
+    if let Some(settings) = &settings {
+        validate_warmers(settings)?;
+        validate_index_sort(settings)?;
+    }
+    validate_normalizers(
+        settings.as_ref(),
+        mappings.as_ref().and_then(mapping_properties),
+    )?;
+    let mut new_index = Index::new(new_name, settings, mappings);
+    for extra in template.aliases.iter().chain(&request.aliases) {
+        if !new_index.aliases.contains(extra) {
+            new_index.aliases.push(extra.clone());
+        }
+    }
+    let old_index = response.old_index.clone();
+    roll_alias_over(
+        &mut indices_guard,
+        backend,
+        limits,
+        alias,
+        &old_index,
+        new_index,
+    )
+    .await?;
+
+    info!(
+        "Rollover of alias '{}' to index '{}' done",
+        alias, response.new_index
+    );
+    response.acknowledged = true;
+    response.shards_acknowledged = true;
+    response.rolled_over = true;
+    Ok(response)
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::IllegalArgument(format!("Invalid rollover request: {}", reason))
+}
@@ -0,0 +1,208 @@
+//! Content-based routing of documents to derived indices
+//!
+//! An ingest route gives agents one logical write target (e.g. `logs`) while
+//! documents land in indices derived from their content, such as daily
+//! indices from a timestamp field:
+//!
+//! ```yaml
+//! ingest:
+//!   routes:
+//!     - name: logs
+//!       index_pattern: "logs-{timestamp:yyyy.MM.dd}"
+//! ```
+//!
+//! Placeholders are `{field}` (the field value) or `{field:format}` (a date
+//! field formatted with `yyyy`, `yy`, `MM`, `dd`, `HH`, `mm` and `ss`). Target
+//! indices are created on first write with the route's settings and mappings.
+
+use std::collections::HashMap;
+
+use crate::config::IngestConfig;
+use crate::error::{GbsError, Result};
+use crate::storage::search::{date_format, get_field_value, parse_date};
+
+/// A part of an index name pattern
+#[derive(Debug, Clone, PartialEq)]
+enum PatternPart {
+    Literal(String),
+    /// A document field, formatted as a date when `format` (strftime) is set
+    Field {
+        field: String,
+        format: Option<String>,
+    },
+}
+
+/// A logical write target whose documents are routed to derived indices
+#[derive(Debug, Clone)]
+pub struct IngestRoute {
+    /// Name writes are addressed to
+    pub name: String,
+    /// Pattern the target index name is derived from
+    pub index_pattern: String,
+    /// Settings of automatically created target indices
+    pub settings: Option<serde_json::Value>,
+    /// Mappings of automatically created target indices
+    pub mappings: Option<serde_json::Value>,
+    parts: Vec<PatternPart>,
+}
+
+impl IngestRoute {
+    /// Create a route, validating its index pattern
+    pub fn new(
+        name: &str,
+        index_pattern: &str,
+        settings: Option<serde_json::Value>,
+        mappings: Option<serde_json::Value>,
+    ) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            index_pattern: index_pattern.to_string(),
+            settings,
+            mappings,
+            parts: parse_pattern(index_pattern)?,
+        })
+    }
+
+    /// Derive the name of the index a document is routed to
+    pub fn target_index(&self, document: &serde_json::Value) -> Result<String> {
+        let mut index_name = String::new();
+        for part in &self.parts {
+            match part {
+                PatternPart::Literal(text) => index_name.push_str(text),
+                PatternPart::Field { field, format } => {
+                    let value = get_field_value(document, field)
+                        .filter(|value| !value.is_null())
+                        .ok_or_else(|| {
+                            GbsError::InvalidRequest(format!(
+                                "Document has no [{}] field required by ingest route [{}]",
+                                field, self.name
+                            ))
+                        })?;
+                    let text = match format {
+                        Some(format) => parse_date(value)
+                            .ok_or_else(|| {
+                                GbsError::InvalidRequest(format!(
+                                    "Field [{}] is not a date, required by ingest route [{}]",
+                                    field, self.name
+                                ))
+                            })?
+                            .format(format)
+                            .to_string(),
+                        None => match value {
+                            serde_json::Value::String(s) => s.to_lowercase(),
+                            serde_json::Value::Number(n) => n.to_string(),
+                            serde_json::Value::Bool(b) => b.to_string(),
+                            _ => {
+                                return Err(GbsError::InvalidRequest(format!(
+                                    "Field [{}] must be a string, number or boolean for ingest route [{}]",
+                                    field, self.name
+                                )))
+                            }
+                        },
+                    };
+                    index_name.push_str(&text);
+                }
+            }
+        }
+
+        if !is_valid_index_name(&index_name) {
+            return Err(GbsError::InvalidRequest(format!(
+                "Ingest route [{}] derived invalid index name [{}]",
+                self.name, index_name
+            )));
+        }
+        Ok(index_name)
+    }
+}
+
+/// The configured ingest routes, by name
+#[derive(Debug, Clone, Default)]
+pub struct IngestRoutes {
+    routes: HashMap<String, IngestRoute>,
+}
+
+impl IngestRoutes {
+    /// Build the routes from the ingest configuration
+    pub fn from_config(config: &IngestConfig) -> Result<Self> {
+        let mut routes = Self::default();
+        for route in &config.routes {
+            routes.add(IngestRoute::new(
+                &route.name,
+                &route.index_pattern,
+                route.settings.clone(),
+                route.mappings.clone(),
+            )?);
+        }
+        Ok(routes)
+    }
+
+    /// Add a route, replacing any route with the same name
+    pub fn add(&mut self, route: IngestRoute) {
+        self.routes.insert(route.name.clone(), route);
+    }
+
+    /// Get the route for a write target
+    pub fn get(&self, name: &str) -> Option<&IngestRoute> {
+        self.routes.get(name)
+    }
+}
+
+fn parse_pattern(pattern: &str) -> Result<Vec<PatternPart>> {
+    let invalid = |reason: &str| {
+        GbsError::InvalidRequest(format!("Invalid index pattern [{}]: {}", pattern, reason))
+    };
+
+    let mut parts = Vec::new();
+    let mut rest = pattern;
+    while let Some(start) = rest.find('{') {
+        if start > 0 {
+            parts.push(PatternPart::Literal(rest[..start].to_string()));
+        }
+        let end = rest[start..]
+            .find('}')
+            .map(|end| start + end)
+            .ok_or_else(|| invalid("unclosed '{'"))?;
+        let placeholder = &rest[start + 1..end];
+        let (field, format) = match placeholder.split_once(':') {
+            Some((field, format)) => (field, Some(date_format(format).map_err(|e| invalid(&e))?)),
+            None => (placeholder, None),
+        };
+        if field.is_empty() {
+            return Err(invalid("empty field name"));
+        }
+        parts.push(PatternPart::Field {
+            field: field.to_string(),
+            format,
+        });
+        rest = &rest[end + 1..];
+    }
+    if rest.contains('}') {
+        return Err(invalid("unmatched '}'"));
+    }
+    if !rest.is_empty() {
+        parts.push(PatternPart::Literal(rest.to_string()));
+    }
+    if !parts
+        .iter()
+        .any(|part| matches!(part, PatternPart::Field { .. }))
+    {
+        return Err(invalid("no {field} placeholder"));
+    }
+    Ok(parts)
+}
+
+/// Index names are lowercase and free of path and pattern characters
+fn is_valid_index_name(name: &str) -> bool {
+    !name.is_empty()
+        && !name.starts_with(['-', '_', '+'])
+        && name != "."
+        && name != ".."
+        && !name.chars().any(|c| {
+            c.is_uppercase()
+                || c.is_whitespace()
+                || matches!(
+                    c,
+                    '\\' | '/' | '*' | '?' | '"' | '<' | '>' | '|' | ',' | '#' | ':'
+                )
+        })
+}
@@ -0,0 +1,203 @@
+//! Stored scripts and search templates
+//!
+//! Search templates are mustache templates of search bodies. They are stored
+//! with `PUT /_scripts/{id}` (`{"script": {"lang": "mustache", "source": ...}}`)
+//! or given inline, and rendered with the `params` of a template request:
+//!
+//! ```json
+//! { "id": "by_title", "params": { "title": "rust", "size": 5 } }
+//! { "source": "{\"query\": {\"match\": {\"title\": \"{{title}}\"}}}", "params": { "title": "rust" } }
+//! ```
+//!
+//! A `source` given as an object is serialized before rendering, so it can
+//! only hold placeholders inside strings.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::search::render_mustache;
+use crate::storage_backend::SledBackend;
+
+/// The only script language gbs runs
+pub const MUSTACHE_LANG: &str = "mustache";
+
+/// A stored script
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StoredScript {
+    pub lang: String,
+    pub source: String,
+}
+
+impl StoredScript {
+    /// Parse the body of `PUT /_scripts/{id}`
+    pub fn parse(body: &serde_json::Value) -> Result<Self> {
+        let script = body
+            .get("script")
+            .filter(|s| s.is_object())
+            .ok_or_else(|| GbsError::IllegalArgument("must specify [script]".to_string()))?;
+        let lang = script.get("lang").and_then(|l| l.as_str()).ok_or_else(|| {
+            GbsError::IllegalArgument("must specify lang for stored script".to_string())
+        })?;
+        if lang != MUSTACHE_LANG {
+            return Err(GbsError::IllegalArgument(format!(
+                "unsupported script lang [{}], only [{}] search templates can be stored",
+                lang, MUSTACHE_LANG
+            )));
+        }
+        let source = script
+            .get("source")
+            .and_then(template_source)
+            .ok_or_else(|| {
+                GbsError::IllegalArgument("must specify source for stored script".to_string())
+            })?;
+        // Reject templates that cannot be rendered
+        render_mustache(&source, &serde_json::json!({}))?;
+        Ok(Self {
+            lang: lang.to_string(),
+            source,
+        })
+    }
+
+    /// The script as returned by `GET /_scripts/{id}`
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({ "lang": self.lang, "source": self.source })
+    }
+}
+
+/// Template text of a `source`: strings as is, objects serialized
+fn template_source(source: &serde_json::Value) -> Option<String> {
+    match source {
+        serde_json::Value::String(source) => Some(source.clone()),
+        serde_json::Value::Object(_) => Some(source.to_string()),
+        _ => None,
+    }
+}
+
+/// The stored scripts of a storage
+#[derive(Debug, Default)]
+pub struct StoredScripts {
+    scripts: RwLock<BTreeMap<String, StoredScript>>,
+}
+
+impl StoredScripts {
+    /// Add or replace a script in memory
+    pub fn insert(&self, id: &str, script: StoredScript) {
+        self.write().insert(id.to_string(), script);
+    }
+
+    /// Remove a script from memory, returning whether it existed
+    pub fn remove(&self, id: &str) -> bool {
+        self.write().remove(id).is_some()
+    }
+
+    /// A script by id
+    pub fn get(&self, id: &str) -> Option<StoredScript> {
+        self.read().get(id).cloned()
+    }
+
+    /// Render the search body of a search template request
+    ///
+    /// The request names a stored template by `id` or gives it as `source`.
+    pub fn render_search_template(&self, request: &serde_json::Value) -> Result<serde_json::Value> {
+        let source = match (request.get("id"), request.get("source")) {
+            (Some(id), None) => {
+                let id = id.as_str().ok_or_else(|| {
+                    GbsError::IllegalArgument("[id] must be a string".to_string())
+                })?;
+                self.get(id)
+                    .ok_or_else(|| GbsError::ScriptNotFound(id.to_string()))?
+                    .source
+            }
+            (None, Some(source)) => template_source(source).ok_or_else(|| {
+                GbsError::IllegalArgument("[source] must be a string or an object".to_string())
+            })?,
+            _ => {
+                return Err(GbsError::IllegalArgument(
+                    "search template requires either [id] or [source]".to_string(),
+                ))
+            }
+        };
+        let params = request
+            .get("params")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({}));
+        let rendered = render_mustache(&source, &params)?;
+        serde_json::from_str(&rendered).map_err(|e| {
+            GbsError::InvalidRequest(format!(
+                "Rendered search template is not valid JSON ({}): {}",
+                e, rendered
+            ))
+        })
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, BTreeMap<String, StoredScript>> {
+        self.scripts.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, BTreeMap<String, StoredScript>> {
+        self.scripts.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Store a script, persisting it to the backend
+pub async fn put_script(
+    scripts: &StoredScripts,
+    backend: &Option<Arc<SledBackend>>,
+    id: &str,
+    script: StoredScript,
+) -> Result<()> {
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let value = serde_json::to_value(&script)?;
+        let key = id.to_string();
+        tokio::task::spawn_blocking(move || backend.store_script(&key, &value))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    scripts.insert(id, script);
+    info!("Stored script '{}'", id);
+    Ok(())
+}
+
+/// Delete a script, removing it from the backend
+pub async fn delete_script(
+    scripts: &StoredScripts,
+    backend: &Option<Arc<SledBackend>>,
+    id: &str,
+) -> Result<()> {
+    if scripts.get(id).is_none() {
+        return Err(GbsError::ScriptNotFound(id.to_string()));
+    }
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let key = id.to_string();
+        tokio::task::spawn_blocking(move || backend.delete_script(&key))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    scripts.remove(id);
+    info!("Deleted script '{}'", id);
+    Ok(())
+}
+
+/// Load the persisted scripts
+pub async fn load_scripts(
+    scripts: &StoredScripts,
+    backend: &Option<Arc<SledBackend>>,
+) -> Result<()> {
+    let Some(backend) = backend else {
+        return Ok(());
+    };
+    let backend = backend.clone();
+    let stored = tokio::task::spawn_blocking(move || backend.load_scripts())
+        .await
+        .map_err(GbsError::TaskJoin)??;
+    for (id, value) in stored {
+        scripts.insert(&id, serde_json::from_value(value)?);
+    }
+    debug!("Loaded {} stored scripts", scripts.read().len());
+    Ok(())
+}
@@ -0,0 +1,352 @@
+//! Aggregations over search results
+//!
+//! The `aggs` (or `aggregations`) section of a search request names one or more
+//! aggregations. Only `date_histogram` is supported:
+//!
+//! ```json
+//! { "aggs": { "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } } } }
+//! ```
+//!
+//! - `calendar_interval`: `minute`, `hour`, `day`, `week` (starting Monday),
+//!   `month`, `quarter` or `year` (or `1m`, `1h`, `1d`, `1w`, `1M`, `1q`, `1y`)
+//! - `fixed_interval`: a multiple of `ms`, `s`, `m`, `h` or `d`, e.g. `30m`
+//! - `interval`: either of the above
+//! - `min_doc_count`: buckets with fewer documents are left out (default 0,
+//!   which fills empty buckets between the first and the last one)
+//!
+//! Buckets are computed in UTC. Counting is split from rendering so partial
+//! results can be kept, extended with new documents and merged across indices.
+
+use chrono::{DateTime, Datelike, NaiveDate};
+use std::collections::{BTreeMap, BTreeSet};
+
+use super::utils::{get_field_value, parse_date};
+use crate::error::{GbsError, Result};
+
+/// Upper bound on the number of buckets in a response
+const MAX_BUCKETS: usize = 65_536;
+
+const MINUTE_MS: i64 = 60 * 1000;
+const HOUR_MS: i64 = 60 * MINUTE_MS;
+const DAY_MS: i64 = 24 * HOUR_MS;
+/// 1970-01-01 was a Thursday; weeks start on the Monday before it
+const WEEK_OFFSET_MS: i64 = -3 * DAY_MS;
+
+/// Parsed aggregations of a search request
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aggregations {
+    histograms: Vec<(String, DateHistogram)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct DateHistogram {
+    field: String,
+    interval: Interval,
+    min_doc_count: u64,
+}
+
+/// Bucket width of a date histogram
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Interval {
+    /// Fixed-length buckets aligned to the epoch plus `offset`
+    Fixed { millis: i64, offset: i64 },
+    /// Calendar months (1 for months, 3 for quarters, 12 for years)
+    Months(i64),
+}
+
+/// Document counts per bucket key of each aggregation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AggregationCounts {
+    buckets: Vec<BTreeMap<i64, u64>>,
+}
+
+impl Aggregations {
+    /// Parse the `aggs` section of a search request
+    pub fn parse(spec: &serde_json::Value) -> Result<Self> {
+        let obj = spec.as_object().ok_or_else(|| {
+            GbsError::InvalidRequest("Aggregations must be an object".to_string())
+        })?;
+
+        let mut histograms = Vec::new();
+        for (name, aggregation) in obj {
+            let definition = aggregation.as_object().ok_or_else(|| {
+                GbsError::InvalidRequest(format!("Aggregation [{}] must be an object", name))
+            })?;
+            if definition.contains_key("aggs") || definition.contains_key("aggregations") {
+                return Err(GbsError::InvalidRequest(format!(
+                    "Sub-aggregations of [{}] are not supported",
+                    name
+                )));
+            }
+            let histogram = match (definition.len(), definition.get("date_histogram")) {
+                (1, Some(histogram)) => DateHistogram::parse(name, histogram)?,
+                _ => {
+                    let kind = definition.keys().next().map_or("", |k| k.as_str());
+                    return Err(GbsError::InvalidRequest(format!(
+                        "Unsupported aggregation type [{}] in [{}], only [date_histogram] is supported",
+                        kind, name
+                    )));
+                }
+            };
+            histograms.push((name.clone(), histogram));
+        }
+        Ok(Self { histograms })
+    }
+
+    /// Empty counts for these aggregations
+    pub fn new_counts(&self) -> AggregationCounts {
+        AggregationCounts {
+            buckets: vec![BTreeMap::new(); self.histograms.len()],
+        }
+    }
+
+    /// Count a matching document
+    pub fn collect(&self, counts: &mut AggregationCounts, doc: &serde_json::Value) {
+        for ((_, histogram), buckets) in self.histograms.iter().zip(&mut counts.buckets) {
+            // A document with several dates counts once per bucket
+            let keys: BTreeSet<i64> = match get_field_value(doc, &histogram.field) {
+                Some(serde_json::Value::Array(values)) => values
+                    .iter()
+                    .filter_map(|value| histogram.bucket_key(value))
+                    .collect(),
+                Some(value) => histogram.bucket_key(value).into_iter().collect(),
+                None => BTreeSet::new(),
+            };
+            for key in keys {
+                *buckets.entry(key).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Build the `aggregations` response section
+    pub fn render(&self, counts: &AggregationCounts) -> Result<serde_json::Value> {
+        let mut result = serde_json::Map::new();
+        for ((name, histogram), buckets) in self.histograms.iter().zip(&counts.buckets) {
+            result.insert(
+                name.clone(),
+                serde_json::json!({ "buckets": histogram.render(buckets)? }),
+            );
+        }
+        Ok(serde_json::Value::Object(result))
+    }
+
+    /// Merge the `aggregations` sections of several searches (one per index)
+    pub fn merge(&self, sections: &[&serde_json::Value]) -> Result<serde_json::Value> {
+        let mut counts = self.new_counts();
+        for section in sections {
+            for ((name, _), buckets) in self.histograms.iter().zip(&mut counts.buckets) {
+                let rendered = section[name]["buckets"].as_array().into_iter().flatten();
+                for bucket in rendered {
+                    if let (Some(key), Some(count)) =
+                        (bucket["key"].as_i64(), bucket["doc_count"].as_u64())
+                    {
+                        *buckets.entry(key).or_insert(0) += count;
+                    }
+                }
+            }
+        }
+        self.render(&counts)
+    }
+}
+
+/// Merge the `aggregations` sections of per-index searches for the `aggs` spec
+pub fn merge_aggregations(
+    aggs: &serde_json::Value,
+    sections: &[&serde_json::Value],
+) -> Result<serde_json::Value> {
+    Aggregations::parse(aggs)?.merge(sections)
+}
+
+impl DateHistogram {
+    fn parse(name: &str, spec: &serde_json::Value) -> Result<Self> {
+        let obj = spec.as_object().ok_or_else(|| {
+            GbsError::InvalidRequest(format!("date_histogram [{}] must be an object", name))
+        })?;
+
+        let mut field = None;
+        let mut interval = None;
+        let mut min_doc_count = 0;
+        for (key, value) in obj {
+            match key.as_str() {
+                "field" => field = value.as_str(),
+                "calendar_interval" => interval = Some(parse_interval(value, true, false)?),
+                "fixed_interval" => interval = Some(parse_interval(value, false, true)?),
+                "interval" => interval = Some(parse_interval(value, true, true)?),
+                "min_doc_count" => {
+                    min_doc_count = value.as_u64().ok_or_else(|| {
+                        GbsError::InvalidRequest(format!(
+                            "[min_doc_count] of [{}] must be a non-negative integer",
+                            name
+                        ))
+                    })?
+                }
+                other => {
+                    return Err(GbsError::InvalidRequest(format!(
+                        "Unsupported date_histogram parameter [{}] in [{}]",
+                        other, name
+                    )))
+                }
+            }
+        }
+
+        Ok(Self {
+            field: field
+                .ok_or_else(|| {
+                    GbsError::InvalidRequest(format!("date_histogram [{}] requires [field]", name))
+                })?
+                .to_string(),
+            interval: interval.ok_or_else(|| {
+                GbsError::InvalidRequest(format!(
+                    "date_histogram [{}] requires [calendar_interval] or [fixed_interval]",
+                    name
+                ))
+            })?,
+            min_doc_count,
+        })
+    }
+
+    fn bucket_key(&self, value: &serde_json::Value) -> Option<i64> {
+        let millis = parse_date(value)?.timestamp_millis();
+        Some(self.interval.bucket_key(millis))
+    }
+
+    fn render(&self, buckets: &BTreeMap<i64, u64>) -> Result<Vec<serde_json::Value>> {
+        let too_many = || {
+            GbsError::InvalidRequest(format!(
+                "date_histogram on [{}] would create more than {} buckets",
+                self.field, MAX_BUCKETS
+            ))
+        };
+
+        let mut rendered = Vec::new();
+        if self.min_doc_count == 0 {
+            // Fill the empty buckets between the first and the last one
+            let (Some(&first), Some(&last)) = (buckets.keys().next(), buckets.keys().last()) else {
+                return Ok(rendered);
+            };
+            let mut key = first;
+            while key <= last {
+                if rendered.len() == MAX_BUCKETS {
+                    return Err(too_many());
+                }
+                rendered.push(bucket(key, buckets.get(&key).copied().unwrap_or(0)));
+                key = self.interval.next_key(key);
+            }
+        } else {
+            for (&key, &count) in buckets {
+                if count >= self.min_doc_count {
+                    if rendered.len() == MAX_BUCKETS {
+                        return Err(too_many());
+                    }
+                    rendered.push(bucket(key, count));
+                }
+            }
+        }
+        Ok(rendered)
+    }
+}
+
+impl Interval {
+    fn bucket_key(&self, millis: i64) -> i64 {
+        match *self {
+            Interval::Fixed {
+                millis: width,
+                offset,
+            } => (millis - offset).div_euclid(width) * width + offset,
+            Interval::Months(months) => {
+                let Some(date) = DateTime::from_timestamp_millis(millis) else {
+                    return millis;
+                };
+                let index = date.year() as i64 * 12 + date.month0() as i64;
+                month_start(index.div_euclid(months) * months)
+            }
+        }
+    }
+
+    fn next_key(&self, key: i64) -> i64 {
+        match *self {
+            Interval::Fixed { millis, .. } => key + millis,
+            Interval::Months(months) => {
+                let Some(date) = DateTime::from_timestamp_millis(key) else {
+                    return i64::MAX;
+                };
+                month_start(date.year() as i64 * 12 + date.month0() as i64 + months)
+            }
+        }
+    }
+}
+
+/// Start of a month counted from year 0, in milliseconds since the epoch
+fn month_start(month_index: i64) -> i64 {
+    NaiveDate::from_ymd_opt(
+        month_index.div_euclid(12) as i32,
+        month_index.rem_euclid(12) as u32 + 1,
+        1,
+    )
+    .and_then(|date| date.and_hms_opt(0, 0, 0))
+    .map_or(i64::MAX, |date| date.and_utc().timestamp_millis())
+}
+
+fn parse_interval(value: &serde_json::Value, calendar: bool, fixed: bool) -> Result<Interval> {
+    let invalid = || GbsError::InvalidRequest(format!("Invalid date_histogram interval {}", value));
+    let text = value.as_str().ok_or_else(invalid)?;
+
+    if calendar {
+        let interval = match text {
+            "minute" | "1m" => Some(Interval::Fixed {
+                millis: MINUTE_MS,
+                offset: 0,
+            }),
+            "hour" | "1h" => Some(Interval::Fixed {
+                millis: HOUR_MS,
+                offset: 0,
+            }),
+            "day" | "1d" => Some(Interval::Fixed {
+                millis: DAY_MS,
+                offset: 0,
+            }),
+            "week" | "1w" => Some(Interval::Fixed {
+                millis: 7 * DAY_MS,
+                offset: WEEK_OFFSET_MS,
+            }),
+            "month" | "1M" => Some(Interval::Months(1)),
+            "quarter" | "1q" => Some(Interval::Months(3)),
+            "year" | "1y" => Some(Interval::Months(12)),
+            _ => None,
+        };
+        if let Some(interval) = interval {
+            return Ok(interval);
+        }
+    }
+    if !fixed {
+        return Err(invalid());
+    }
+
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .ok_or_else(invalid)?;
+    let amount: i64 = text[..split].parse().map_err(|_| invalid())?;
+    let unit = match &text[split..] {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => MINUTE_MS,
+        "h" => HOUR_MS,
+        "d" => DAY_MS,
+        _ => return Err(invalid()),
+    };
+    match amount.checked_mul(unit) {
+        Some(millis) if millis > 0 => Ok(Interval::Fixed { millis, offset: 0 }),
+        _ => Err(invalid()),
+    }
+}
+
+fn bucket(key: i64, doc_count: u64) -> serde_json::Value {
+    let key_as_string = DateTime::from_timestamp_millis(key)
+        .map(|date| date.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+        .unwrap_or_default();
+    serde_json::json!({
+        "key_as_string": key_as_string,
+        "key": key,
+        "doc_count": doc_count
+    })
+}
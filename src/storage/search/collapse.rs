@@ -0,0 +1,147 @@
+//! Field collapsing (`collapse` in search bodies)
+//!
+//! Hits are grouped by the value of a field and only the best hit of each
+//! group is returned, e.g. one product per family or one log line per source:
+//!
+//! ```json
+//! { "collapse": {
+//!     "field": "family",
+//!     "inner_hits": { "name": "variants", "size": 3, "sort": [{ "price": "asc" }] }
+//! } }
+//! ```
+//!
+//! `from` and `size` page through the groups, and `hits.total` still counts
+//! the matching documents. The top hit of a group lists the group's value in
+//! `fields`; documents without the field form a group of their own. The field
+//! must hold a single value. `inner_hits` (one object or an array) adds the
+//! members of each group (see `search::inner_hits`).
+
+use std::collections::HashMap;
+
+use super::inner_hits::{filter_hit_source, InnerHits};
+use super::utils::get_field_value;
+use crate::error::{GbsError, Result};
+
+/// A parsed `collapse` specification
+#[derive(Debug, Clone, PartialEq)]
+pub struct Collapse {
+    field: String,
+    inner_hits: Vec<InnerHits>,
+}
+
+impl Collapse {
+    /// Parse `{ "field": "...", "inner_hits": ... }`
+    pub fn parse(spec: &serde_json::Value) -> Result<Self> {
+        let field = spec
+            .get("field")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| invalid("[collapse] requires [field]"))?
+            .to_string();
+        let inner_hits = match spec.get("inner_hits") {
+            None => Vec::new(),
+            Some(serde_json::Value::Array(specs)) => specs
+                .iter()
+                .map(|spec| InnerHits::parse(spec, &field))
+                .collect::<Result<_>>()?,
+            Some(spec) => vec![InnerHits::parse(spec, &field)?],
+        };
+        Ok(Self { field, inner_hits })
+    }
+
+    /// Field the hits are grouped by
+    pub fn field(&self) -> &str {
+        &self.field
+    }
+
+    /// Collapse ranked hits, best first and with their whole `_source`, into
+    /// the top hits of the groups on the page of `from` and `size`
+    ///
+    /// The sources of the returned hits are filtered with `source_filter`.
+    pub fn apply(
+        &self,
+        hits: Vec<serde_json::Value>,
+        from: usize,
+        size: usize,
+        source_filter: Option<&serde_json::Value>,
+    ) -> Result<Vec<serde_json::Value>> {
+        // Groups in the order of their top hits
+        let mut groups: Vec<(serde_json::Value, Vec<serde_json::Value>)> = Vec::new();
+        let mut positions: HashMap<String, usize> = HashMap::new();
+        for hit in hits {
+            let value = self.group_value(&hit)?;
+            let position = *positions.entry(value.to_string()).or_insert_with(|| {
+                groups.push((value, Vec::new()));
+                groups.len() - 1
+            });
+            groups[position].1.push(hit);
+        }
+
+        groups
+            .into_iter()
+            .skip(from)
+            .take(size)
+            .map(|(value, members)| {
+                let mut top = members[0].clone();
+                filter_hit_source(&mut top, source_filter);
+                if !top["fields"].is_object() {
+                    top["fields"] = serde_json::json!({});
+                }
+                top["fields"][self.field.as_str()] = serde_json::json!([value]);
+                if self.inner_hits.is_empty() {
+                    return Ok(top);
+                }
+                // Next to the inner hits of nested queries, if any
+                if !top["inner_hits"].is_object() {
+                    top["inner_hits"] = serde_json::json!({});
+                }
+                for spec in &self.inner_hits {
+                    let hits = members
+                        .iter()
+                        .map(|member| {
+                            let hit = serde_json::json!({
+                                "_index": member["_index"],
+                                "_type": member["_type"],
+                                "_id": member["_id"],
+                                "_score": member["_score"],
+                                "_source": member["_source"]
+                            });
+                            (hit, member["_source"].clone())
+                        })
+                        .collect();
+                    top["inner_hits"][spec.name.as_str()] = spec.render(hits, source_filter);
+                }
+                Ok(top)
+            })
+            .collect()
+    }
+
+    /// Value of the collapse field of a hit, `null` when it has none
+    ///
+    /// Runtime fields are read from the `fields` of the hit.
+    fn group_value(&self, hit: &serde_json::Value) -> Result<serde_json::Value> {
+        let value = hit["fields"]
+            .get(&self.field)
+            .or_else(|| get_field_value(&hit["_source"], &self.field))
+            .cloned();
+        match value {
+            None => Ok(serde_json::Value::Null),
+            Some(serde_json::Value::Array(mut values)) => match values.len() {
+                0 => Ok(serde_json::Value::Null),
+                1 => Ok(values.remove(0)),
+                _ => Err(invalid(&format!(
+                    "failed to collapse on [{}], the field holds several values",
+                    self.field
+                ))),
+            },
+            Some(serde_json::Value::Object(_)) => Err(invalid(&format!(
+                "failed to collapse on [{}], the field is an object",
+                self.field
+            ))),
+            Some(value) => Ok(value),
+        }
+    }
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(reason.to_string())
+}
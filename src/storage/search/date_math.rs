@@ -0,0 +1,339 @@
+//! Date math, date formats and time zones
+//!
+//! Date math expressions start with an anchor, `now` or a date followed by
+//! `||`, and apply operations in order: `+1d` and `-7d` add or subtract, `/d`
+//! rounds down to the start of the unit, or up to its last millisecond when
+//! rounding up (as for `gt` and `lte` range bounds). Units are `y`, `M`, `w`,
+//! `d`, `h` (or `H`), `m` and `s`, e.g. `now-7d/d` or `2024-01-01||+1M/M`.
+//! Arithmetic and rounding happen in the given time zone.
+//!
+//! Date formats are `||`-separated alternatives, each a built-in format
+//! (`epoch_millis`, `epoch_second`, `strict_date_optional_time`, `date`, ...)
+//! or a pattern like `dd/MM/yyyy HH:mm`.
+
+use chrono::{
+    DateTime, Datelike, Duration, FixedOffset, Months, NaiveDate, NaiveDateTime, NaiveTime, Offset,
+    TimeZone, Timelike, Utc,
+};
+
+use crate::error::{GbsError, Result};
+
+/// Format of the dates in date math index names when none is given
+const DEFAULT_INDEX_NAME_FORMAT: &str = "yyyy.MM.dd";
+
+/// Check if a range bound is a date math expression
+pub fn is_date_math(text: &str) -> bool {
+    text.starts_with("now") || text.contains("||")
+}
+
+/// Parse a time zone: `Z`, `UTC`, or an offset like `+01:00`, `-0500` or `+01`
+///
+/// Named time zones (`Europe/Paris`) are not supported.
+pub fn parse_time_zone(text: &str) -> Option<FixedOffset> {
+    if matches!(text, "Z" | "UTC" | "GMT" | "utc") {
+        return FixedOffset::east_opt(0);
+    }
+    let (sign, offset) = match text.as_bytes().first()? {
+        b'+' => (1, &text[1..]),
+        b'-' => (-1, &text[1..]),
+        _ => return None,
+    };
+    let digits: String = offset.chars().filter(|c| *c != ':').collect();
+    if !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let (hours, minutes) = match digits.len() {
+        1 | 2 => (digits.parse::<i32>().ok()?, 0),
+        4 => (
+            digits[..2].parse::<i32>().ok()?,
+            digits[2..].parse::<i32>().ok()?,
+        ),
+        _ => return None,
+    };
+    if hours > 18 || minutes > 59 {
+        return None;
+    }
+    FixedOffset::east_opt(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Translate a date pattern like `yyyy.MM.dd` to a strftime format
+///
+/// Text in single quotes is literal, e.g. `yyyy-MM-dd'T'HH:mm`.
+pub fn date_format(format: &str) -> std::result::Result<String, String> {
+    const TOKENS: [(&str, &str); 8] = [
+        ("yyyy", "%Y"),
+        ("yy", "%y"),
+        ("MM", "%m"),
+        ("dd", "%d"),
+        ("HH", "%H"),
+        ("mm", "%M"),
+        ("ss", "%S"),
+        ("SSS", "%3f"),
+    ];
+
+    let mut strftime = String::new();
+    let mut rest = format;
+    'outer: while let Some(c) = rest.chars().next() {
+        if c == '\'' {
+            let (literal, after) = rest[1..]
+                .split_once('\'')
+                .ok_or_else(|| format!("unterminated quote in date format [{}]", format))?;
+            strftime.push_str(&literal.replace('%', "%%"));
+            rest = after;
+            continue;
+        }
+        for (token, replacement) in TOKENS {
+            if let Some(after) = rest.strip_prefix(token) {
+                strftime.push_str(replacement);
+                rest = after;
+                continue 'outer;
+            }
+        }
+        if c.is_ascii_alphabetic() {
+            return Err(format!("unsupported date format [{}]", format));
+        }
+        if c == '%' {
+            strftime.push_str("%%");
+        } else {
+            strftime.push(c);
+        }
+        rest = &rest[c.len_utf8()..];
+    }
+    if strftime.is_empty() {
+        return Err("empty date format".to_string());
+    }
+    Ok(strftime)
+}
+
+/// Parse a date with `||`-separated formats (default
+/// `strict_date_optional_time||epoch_millis`)
+///
+/// Dates without an offset are read in `time_zone`.
+pub fn parse_formatted_date(
+    text: &str,
+    format: Option<&str>,
+    time_zone: FixedOffset,
+) -> Option<DateTime<Utc>> {
+    let Some(format) = format else {
+        return parse_epoch_millis(text).or_else(|| parse_optional_time(text, time_zone));
+    };
+    format.split("||").find_map(|format| match format.trim() {
+        "epoch_millis" => parse_epoch_millis(text),
+        "epoch_second" => {
+            let seconds = text.parse::<f64>().ok()?;
+            DateTime::from_timestamp_millis((seconds * 1000.0) as i64)
+        }
+        "strict_date_optional_time"
+        | "date_optional_time"
+        | "strict_date_optional_time_nanos"
+        | "date_time"
+        | "strict_date_time"
+        | "date_time_no_millis"
+        | "strict_date_time_no_millis" => parse_optional_time(text, time_zone),
+        "date" | "strict_date" | "year_month_day" | "strict_year_month_day" => {
+            parse_with_pattern(text, "%Y-%m-%d", time_zone)
+        }
+        "basic_date" => parse_with_pattern(text, "%Y%m%d", time_zone),
+        pattern => parse_with_pattern(text, &date_format(pattern).ok()?, time_zone),
+    })
+}
+
+fn parse_epoch_millis(text: &str) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp_millis(text.parse::<i64>().ok()?)
+}
+
+/// ISO 8601 dates with optional time and offset: `2024`, `2024-05`,
+/// `2024-05-01`, `2024-05-01T10:00:00`, `2024-05-01T10:00:00.123+02:00`
+fn parse_optional_time(text: &str, time_zone: FixedOffset) -> Option<DateTime<Utc>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(text) {
+        return Some(date.with_timezone(&Utc));
+    }
+    for format in [
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+    ] {
+        if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+            return local_to_utc(date, time_zone);
+        }
+    }
+    let date = match text.len() {
+        4 => NaiveDate::from_ymd_opt(text.parse().ok()?, 1, 1)?,
+        7 => NaiveDate::parse_from_str(&format!("{}-01", text), "%Y-%m-%d").ok()?,
+        _ => NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?,
+    };
+    local_to_utc(date.and_time(NaiveTime::MIN), time_zone)
+}
+
+/// Parse with a strftime format, as a date and time or as a date alone
+fn parse_with_pattern(text: &str, format: &str, time_zone: FixedOffset) -> Option<DateTime<Utc>> {
+    if let Ok(date) = NaiveDateTime::parse_from_str(text, format) {
+        return local_to_utc(date, time_zone);
+    }
+    let date = NaiveDate::parse_from_str(text, format).ok()?;
+    local_to_utc(date.and_time(NaiveTime::MIN), time_zone)
+}
+
+fn local_to_utc(date: NaiveDateTime, time_zone: FixedOffset) -> Option<DateTime<Utc>> {
+    time_zone
+        .from_local_datetime(&date)
+        .single()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+/// Evaluate a date math expression, or parse a plain date
+///
+/// `round_up` makes rounding go to the last millisecond of the unit.
+pub fn parse_date_math(
+    expression: &str,
+    now: DateTime<Utc>,
+    round_up: bool,
+    format: Option<&str>,
+    time_zone: FixedOffset,
+) -> Option<DateTime<Utc>> {
+    let (anchor, operations) = if let Some(operations) = expression.strip_prefix("now") {
+        (now, operations)
+    } else if let Some((date, operations)) = expression.split_once("||") {
+        (parse_formatted_date(date, format, time_zone)?, operations)
+    } else {
+        return parse_formatted_date(expression, format, time_zone);
+    };
+
+    let mut date = anchor.with_timezone(&time_zone).naive_local();
+    let mut rest = operations;
+    while let Some(operation) = rest.chars().next() {
+        rest = &rest[1..];
+        let digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let amount: u32 = match digits {
+            0 => 1,
+            _ => rest[..digits].parse().ok()?,
+        };
+        rest = &rest[digits..];
+        let unit = rest.chars().next()?;
+        rest = &rest[unit.len_utf8()..];
+        date = match operation {
+            '+' => add(date, amount, unit)?,
+            '-' => subtract(date, amount, unit)?,
+            '/' if digits == 0 => {
+                let start = round_down(date, unit)?;
+                if round_up {
+                    add(start, 1, unit)? - Duration::milliseconds(1)
+                } else {
+                    start
+                }
+            }
+            _ => return None,
+        };
+    }
+    local_to_utc(date, time_zone)
+}
+
+fn add(date: NaiveDateTime, amount: u32, unit: char) -> Option<NaiveDateTime> {
+    match unit {
+        'y' => date.checked_add_months(Months::new(amount.checked_mul(12)?)),
+        'M' => date.checked_add_months(Months::new(amount)),
+        _ => date.checked_add_signed(unit_duration(amount, unit)?),
+    }
+}
+
+fn subtract(date: NaiveDateTime, amount: u32, unit: char) -> Option<NaiveDateTime> {
+    match unit {
+        'y' => date.checked_sub_months(Months::new(amount.checked_mul(12)?)),
+        'M' => date.checked_sub_months(Months::new(amount)),
+        _ => date.checked_sub_signed(unit_duration(amount, unit)?),
+    }
+}
+
+fn unit_duration(amount: u32, unit: char) -> Option<Duration> {
+    let amount = i64::from(amount);
+    match unit {
+        'w' => Duration::try_weeks(amount),
+        'd' => Duration::try_days(amount),
+        'h' | 'H' => Duration::try_hours(amount),
+        'm' => Duration::try_minutes(amount),
+        's' => Duration::try_seconds(amount),
+        _ => None,
+    }
+}
+
+/// Start of the unit containing `date` (weeks start on Monday)
+fn round_down(date: NaiveDateTime, unit: char) -> Option<NaiveDateTime> {
+    let day = date.date();
+    let start = match unit {
+        'y' => NaiveDate::from_ymd_opt(day.year(), 1, 1)?.and_time(NaiveTime::MIN),
+        'M' => NaiveDate::from_ymd_opt(day.year(), day.month(), 1)?.and_time(NaiveTime::MIN),
+        'w' => (day - Duration::days(i64::from(day.weekday().num_days_from_monday())))
+            .and_time(NaiveTime::MIN),
+        'd' => day.and_time(NaiveTime::MIN),
+        'h' | 'H' => day.and_hms_opt(date.hour(), 0, 0)?,
+        'm' => day.and_hms_opt(date.hour(), date.minute(), 0)?,
+        's' => day.and_hms_opt(date.hour(), date.minute(), date.second())?,
+        _ => return None,
+    };
+    Some(start)
+}
+
+/// Resolve a date math index name like `<logs-{now/d}>` to `logs-2024.05.01`
+///
+/// Expressions are `{date_math}`, `{date_math{format}}` or
+/// `{date_math{format|time_zone}}`; the default format is `yyyy.MM.dd`.
+/// Literal braces are escaped with a backslash. Names not enclosed in `<>`
+/// are returned as they are.
+pub fn resolve_date_math_index_name(name: &str, now: DateTime<Utc>) -> Result<String> {
+    let Some(inner) = name.strip_prefix('<').and_then(|n| n.strip_suffix('>')) else {
+        return Ok(name.to_string());
+    };
+    let invalid = |reason: &str| {
+        GbsError::InvalidRequest(format!(
+            "invalid date math index name [{}]: {}",
+            name, reason
+        ))
+    };
+
+    let mut resolved = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => resolved.push(chars.next().ok_or_else(|| invalid("dangling escape"))?),
+            '{' => {
+                let mut expression = String::new();
+                let mut spec = None;
+                loop {
+                    match chars.next().ok_or_else(|| invalid("unclosed expression"))? {
+                        '{' => {
+                            let mut inner_spec = String::new();
+                            loop {
+                                match chars.next().ok_or_else(|| invalid("unclosed format"))? {
+                                    '}' => break,
+                                    c => inner_spec.push(c),
+                                }
+                            }
+                            spec = Some(inner_spec);
+                        }
+                        '}' => break,
+                        c if spec.is_none() => expression.push(c),
+                        _ => return Err(invalid("unexpected text after format")),
+                    }
+                }
+
+                let (format, time_zone) = match spec.as_deref().map(|s| s.split_once('|')) {
+                    Some(Some((format, time_zone))) => (format.to_string(), Some(time_zone)),
+                    Some(None) => (spec.clone().unwrap_or_default(), None),
+                    None => (DEFAULT_INDEX_NAME_FORMAT.to_string(), None),
+                };
+                let time_zone = match time_zone {
+                    Some(time_zone) => parse_time_zone(time_zone)
+                        .ok_or_else(|| invalid(&format!("unknown time zone [{}]", time_zone)))?,
+                    None => Utc.fix(),
+                };
+                let strftime = date_format(&format).map_err(|e| invalid(&e))?;
+                let date = parse_date_math(&expression, now, false, None, time_zone)
+                    .ok_or_else(|| invalid(&format!("invalid expression [{}]", expression)))?;
+                resolved.push_str(&date.with_timezone(&time_zone).format(&strftime).to_string());
+            }
+            '}' => return Err(invalid("unexpected '}'")),
+            c => resolved.push(c),
+        }
+    }
+    Ok(resolved)
+}
@@ -0,0 +1,271 @@
+//! Scoring explanations (`_explain` and `"explain": true` in searches)
+//!
+//! `explain_document` scores a document the way `score_document` does and
+//! records how the score came about: which clauses of `bool` and `nested`
+//! queries matched, and what each contributed. Other queries are explained
+//! by their own score.
+
+use serde::{Deserialize, Serialize};
+
+use super::query::{
+    bool_clauses, combine_nested_scores, minimum_should_match, nested_element_docs,
+    nested_query_parts, query_boost, score_query,
+};
+use crate::error::Result;
+
+/// How the score of a document came about
+///
+/// Serializes like an Elasticsearch explanation; the `value` of a match is
+/// the score `score_document` gives the document, and 0 otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "ExplanationFields")]
+pub struct Explanation {
+    pub value: f64,
+    pub description: String,
+    pub details: Vec<Explanation>,
+    /// Whether the document matched, which a value of 0 does not tell
+    #[serde(skip)]
+    matched: bool,
+}
+
+/// Fields of a serialized explanation
+#[derive(Deserialize)]
+struct ExplanationFields {
+    value: f64,
+    description: String,
+    #[serde(default)]
+    details: Vec<Explanation>,
+}
+
+impl From<ExplanationFields> for Explanation {
+    /// Serialized explanations do not say whether the document matched; a
+    /// positive value does
+    fn from(fields: ExplanationFields) -> Self {
+        Self {
+            matched: fields.value > 0.0,
+            value: fields.value,
+            description: fields.description,
+            details: fields.details,
+        }
+    }
+}
+
+impl Explanation {
+    fn new(value: f64, description: impl Into<String>, details: Vec<Explanation>) -> Self {
+        Self {
+            value,
+            description: description.into(),
+            details,
+            matched: true,
+        }
+    }
+
+    fn no_match(description: impl Into<String>, details: Vec<Explanation>) -> Self {
+        Self {
+            matched: false,
+            ..Self::new(0.0, description, details)
+        }
+    }
+
+    /// Whether the document matched the query
+    pub fn is_match(&self) -> bool {
+        self.matched
+    }
+}
+
+/// Score a document against a query and explain the score
+pub fn explain_document(
+    id: &str,
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Explanation> {
+    let explanation = explain_query(id, doc, query)?;
+    Ok(match query_boost(query) {
+        Some(boost) if explanation.is_match() => Explanation::new(
+            explanation.value * boost,
+            "product of:",
+            vec![explanation, Explanation::new(boost, "boost", Vec::new())],
+        ),
+        _ => explanation,
+    })
+}
+
+/// Explain the score of a query, ignoring its boost
+fn explain_query(
+    id: &str,
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Explanation> {
+    if let Some(query_obj) = query.as_object() {
+        if let Some(bool_query) = query_obj.get("bool") {
+            return explain_bool_query(id, doc, bool_query);
+        }
+        if let Some(nested_query) = query_obj.get("nested") {
+            return explain_nested_query(id, doc, nested_query);
+        }
+    }
+
+    let description = describe_query(query);
+    Ok(match score_query(id, doc, query)? {
+        Some(score) => Explanation::new(score, description, Vec::new()),
+        None => Explanation::no_match(format!("no match on {}", description), Vec::new()),
+    })
+}
+
+/// Explain a bool query, following `score_bool_query`
+///
+/// Matching `must` and `should` clauses are summed; matching `filter`
+/// clauses are listed with a value of 0. A document that is rejected is
+/// explained by the clause that rejected it.
+fn explain_bool_query(
+    id: &str,
+    doc: &serde_json::Value,
+    bool_query: &serde_json::Value,
+) -> Result<Explanation> {
+    let Some(bool_obj) = bool_query.as_object() else {
+        return Ok(Explanation::no_match(
+            "no match on a bool query that is not an object",
+            Vec::new(),
+        ));
+    };
+    let mut details = Vec::new();
+
+    let must = bool_clauses(bool_obj, "must");
+    for clause in &must {
+        let explanation = explain_document(id, doc, clause)?;
+        if !explanation.is_match() {
+            return Ok(Explanation::no_match(
+                "no match on required clause",
+                vec![explanation],
+            ));
+        }
+        details.push(explanation);
+    }
+
+    for clause in bool_clauses(bool_obj, "must_not") {
+        let explanation = explain_document(id, doc, clause)?;
+        if explanation.is_match() {
+            return Ok(Explanation::no_match(
+                "match on prohibited clause",
+                vec![explanation],
+            ));
+        }
+    }
+
+    let filter = bool_clauses(bool_obj, "filter");
+    for clause in &filter {
+        let explanation = explain_document(id, doc, clause)?;
+        if !explanation.is_match() {
+            return Ok(Explanation::no_match(
+                "no match on filter clause",
+                vec![explanation],
+            ));
+        }
+        details.push(Explanation::new(
+            0.0,
+            "match on filter clause, not scored",
+            vec![explanation],
+        ));
+    }
+
+    let should = bool_clauses(bool_obj, "should");
+    let required = match bool_obj.get("minimum_should_match") {
+        Some(spec) => minimum_should_match(spec, should.len())?,
+        None if !must.is_empty() || !filter.is_empty() => 0,
+        None => should.len().min(1),
+    };
+    let mut matched = 0;
+    for clause in &should {
+        let explanation = explain_document(id, doc, clause)?;
+        if explanation.is_match() {
+            matched += 1;
+            details.push(explanation);
+        }
+    }
+    if matched < required {
+        return Ok(Explanation::no_match(
+            format!(
+                "{} of {} should clauses matched, {} required",
+                matched,
+                should.len(),
+                required
+            ),
+            details,
+        ));
+    }
+
+    let score: f64 = details.iter().map(|detail| detail.value).sum();
+    Ok(if score > 0.0 || !should.is_empty() {
+        Explanation::new(score, "sum of:", details)
+    } else {
+        Explanation::new(
+            1.0,
+            "constant score of a bool query without scoring clauses",
+            details,
+        )
+    })
+}
+
+/// Explain a nested query, following `score_nested_query`
+fn explain_nested_query(
+    id: &str,
+    doc: &serde_json::Value,
+    nested_query: &serde_json::Value,
+) -> Result<Explanation> {
+    let (path, inner_query, score_mode) = nested_query_parts(nested_query)?;
+
+    let mut details = Vec::new();
+    for (position, element_doc) in nested_element_docs(doc, path).iter().enumerate() {
+        let explanation = explain_document(id, element_doc, inner_query)?;
+        if explanation.is_match() {
+            details.push(Explanation::new(
+                explanation.value,
+                format!("object {} of [{}]", position, path),
+                vec![explanation],
+            ));
+        }
+    }
+    if details.is_empty() {
+        return Ok(Explanation::no_match(
+            format!("no object of [{}] matched", path),
+            Vec::new(),
+        ));
+    }
+
+    let scores: Vec<f64> = details.iter().map(|detail| detail.value).collect();
+    let mode = match score_mode {
+        "max" | "min" | "sum" | "none" => score_mode,
+        _ => "avg",
+    };
+    Ok(Explanation::new(
+        combine_nested_scores(score_mode, &scores),
+        format!("score mode [{}] of:", mode),
+        details,
+    ))
+}
+
+/// Short description of a query, e.g. `match(title:rust)`
+fn describe_query(query: &serde_json::Value) -> String {
+    let Some((kind, body)) = query.as_object().and_then(|obj| obj.iter().next()) else {
+        return "match_all".to_string();
+    };
+    let fields: Vec<(&String, &serde_json::Value)> = body
+        .as_object()
+        .map(|obj| obj.iter().filter(|(name, _)| *name != "boost").collect())
+        .unwrap_or_default();
+    match fields.as_slice() {
+        [] => kind.clone(),
+        [(field, value)] => {
+            // `{ "field": "text" }` or `{ "field": { "query": "text", ... } }`
+            let value = value
+                .get("query")
+                .or_else(|| value.get("value"))
+                .unwrap_or(value);
+            match value.as_str() {
+                Some(text) => format!("{}({}:{})", kind, field, text),
+                None => format!("{}({}:{})", kind, field, value),
+            }
+        }
+        _ => format!("{}({})", kind, body),
+    }
+}
@@ -0,0 +1,422 @@
+//! Stored fields and doc value fields of search hits
+//!
+//! `stored_fields` lists fields, with `*` and `?` wildcards, whose values are
+//! returned in the `fields` of each hit instead of the `_source`:
+//!
+//! ```json
+//! { "stored_fields": ["title", "user.*"] }
+//! ```
+//!
+//! Every leaf field of a document counts as stored. `_none_` returns neither
+//! stored fields nor the source.
+//!
+//! `docvalue_fields` returns the doc values of fields: their values sorted,
+//! keyword sub-fields (`title.keyword`) read from their parent field, dates
+//! formatted with the `format` of the request (or ISO 8601):
+//!
+//! ```json
+//! { "docvalue_fields": ["status", { "field": "created", "format": "yyyy-MM-dd" }] }
+//! ```
+//!
+//! Text fields have no doc values, unless they are mapped with `fielddata`.
+
+use chrono::{DateTime, FixedOffset, Utc};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+
+use super::date_math::{date_format, parse_formatted_date};
+use super::utils::{get_field_value, parse_date};
+use crate::error::{GbsError, Result};
+use crate::storage::dynamic_mapping::dynamic_field_type;
+use crate::storage::wildcard_regex;
+
+/// `stored_fields` value that disables stored fields and the source
+const NONE_FIELDS: &str = "_none_";
+
+/// The stored fields of a search request
+#[derive(Debug, Clone, PartialEq)]
+pub enum StoredFields {
+    /// Fields by name or wildcard pattern
+    Fields(Vec<String>),
+    /// `_none_`
+    None,
+}
+
+/// A requested doc value field
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocvalueField {
+    /// Field name or wildcard pattern
+    pub field: String,
+    /// Date format of the values (`epoch_millis`, `epoch_second` or a pattern)
+    pub format: Option<String>,
+}
+
+/// Type, date format, fielddata and normalizer of a mapped field
+#[derive(Debug, Clone, PartialEq)]
+pub struct MappedField {
+    pub field_type: String,
+    pub format: Option<String>,
+    pub fielddata: bool,
+    pub normalizer: Option<String>,
+}
+
+/// Parse `stored_fields`: a field, a comma-separated list or an array
+pub fn parse_stored_fields(spec: &serde_json::Value) -> Result<StoredFields> {
+    let fields = field_list(spec)
+        .ok_or_else(|| GbsError::InvalidRequest(format!("Invalid stored_fields {}", spec)))?;
+    if fields.iter().any(|field| field == NONE_FIELDS) {
+        if fields.len() > 1 {
+            return Err(GbsError::IllegalArgument(format!(
+                "cannot combine [{}] with other fields",
+                NONE_FIELDS
+            )));
+        }
+        return Ok(StoredFields::None);
+    }
+    Ok(StoredFields::Fields(fields))
+}
+
+/// Parse `docvalue_fields`: fields as strings or `{"field", "format"}` objects
+pub fn parse_docvalue_fields(spec: &serde_json::Value) -> Result<Vec<DocvalueField>> {
+    let invalid = || GbsError::InvalidRequest(format!("Invalid docvalue_fields {}", spec));
+    if let Some(fields) = field_list(spec) {
+        return Ok(fields
+            .into_iter()
+            .map(|field| DocvalueField {
+                field,
+                format: None,
+            })
+            .collect());
+    }
+    spec.as_array()
+        .ok_or_else(invalid)?
+        .iter()
+        .map(|field| match field {
+            serde_json::Value::String(field) => Ok(DocvalueField {
+                field: field.clone(),
+                format: None,
+            }),
+            serde_json::Value::Object(obj) => Ok(DocvalueField {
+                field: obj
+                    .get("field")
+                    .and_then(|f| f.as_str())
+                    .ok_or_else(invalid)?
+                    .to_string(),
+                format: match obj.get("format") {
+                    None => None,
+                    Some(format) => Some(format.as_str().ok_or_else(invalid)?.to_string()),
+                },
+            }),
+            _ => Err(invalid()),
+        })
+        .collect()
+}
+
+/// Field names of a string (comma-separated) or an array of strings
+fn field_list(spec: &serde_json::Value) -> Option<Vec<String>> {
+    match spec {
+        serde_json::Value::String(fields) => Some(
+            fields
+                .split(',')
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(str::to_string)
+                .collect(),
+        ),
+        serde_json::Value::Array(fields) => fields
+            .iter()
+            .map(|f| f.as_str().map(str::to_string))
+            .collect(),
+        _ => None,
+    }
+}
+
+/// Mapped fields by dotted path, including object properties and multi-fields
+pub fn mapped_fields(properties: Option<&serde_json::Value>) -> BTreeMap<String, MappedField> {
+    let mut fields = BTreeMap::new();
+    if let Some(properties) = properties {
+        collect_mapped_fields(properties, "", &mut fields);
+    }
+    fields
+}
+
+fn collect_mapped_fields(
+    properties: &serde_json::Value,
+    prefix: &str,
+    fields: &mut BTreeMap<String, MappedField>,
+) {
+    let Some(properties) = properties.as_object() else {
+        return;
+    };
+    for (name, definition) in properties {
+        let path = format!("{}{}", prefix, name);
+        let field_type = match definition.get("type").and_then(|t| t.as_str()) {
+            Some(field_type) => field_type,
+            None if definition.get("properties").is_some() => "object",
+            None => continue,
+        };
+        fields.insert(
+            path.clone(),
+            MappedField {
+                field_type: field_type.to_string(),
+                format: definition
+                    .get("format")
+                    .and_then(|f| f.as_str())
+                    .map(str::to_string),
+                fielddata: definition.get("fielddata").and_then(|f| f.as_bool()) == Some(true),
+                normalizer: definition
+                    .get("normalizer")
+                    .and_then(|n| n.as_str())
+                    .map(str::to_string),
+            },
+        );
+        for nested in ["properties", "fields"] {
+            if let Some(nested) = definition.get(nested) {
+                collect_mapped_fields(nested, &format!("{}.", path), fields);
+            }
+        }
+    }
+}
+
+/// Values of the stored fields of a document, as arrays by field name
+pub fn stored_field_values(
+    fields: &StoredFields,
+    doc: &serde_json::Value,
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut values = serde_json::Map::new();
+    let StoredFields::Fields(patterns) = fields else {
+        return values;
+    };
+    let patterns: Vec<regex::Regex> = patterns
+        .iter()
+        .filter(|pattern| !pattern.starts_with('_'))
+        .filter_map(|pattern| wildcard_regex(pattern))
+        .collect();
+    let mut leaves = BTreeMap::new();
+    collect_leaves(doc, "", &mut leaves);
+    for (path, leaf_values) in leaves {
+        if patterns.iter().any(|pattern| pattern.is_match(&path)) {
+            values.insert(path, serde_json::Value::Array(leaf_values));
+        }
+    }
+    values
+}
+
+/// Leaf values of a document by dotted path; arrays are flattened
+fn collect_leaves(
+    value: &serde_json::Value,
+    path: &str,
+    leaves: &mut BTreeMap<String, Vec<serde_json::Value>>,
+) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (name, value) in obj {
+                let path = if path.is_empty() {
+                    name.clone()
+                } else {
+                    format!("{}.{}", path, name)
+                };
+                collect_leaves(value, &path, leaves);
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_leaves(item, path, leaves);
+            }
+        }
+        serde_json::Value::Null => {}
+        leaf => leaves
+            .entry(path.to_string())
+            .or_default()
+            .push(leaf.clone()),
+    }
+}
+
+/// Doc values of the requested fields of a document, as arrays by field name
+///
+/// Fields the document has no value for are left out. Wildcard patterns
+/// select mapped fields.
+pub fn docvalue_field_values(
+    fields: &[DocvalueField],
+    doc: &serde_json::Value,
+    mapped: &BTreeMap<String, MappedField>,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mut values = serde_json::Map::new();
+    for requested in fields {
+        let names: Vec<&str> = if requested.field.contains(['*', '?']) {
+            let Some(pattern) = wildcard_regex(&requested.field) else {
+                continue;
+            };
+            mapped
+                .iter()
+                .filter(|(name, field)| pattern.is_match(name) && has_doc_values(field))
+                .map(|(name, _)| name.as_str())
+                .collect()
+        } else {
+            vec![requested.field.as_str()]
+        };
+        for name in names {
+            if let Some(field_values) =
+                field_doc_values(name, requested.format.as_deref(), doc, mapped)?
+            {
+                values.insert(name.to_string(), serde_json::Value::Array(field_values));
+            }
+        }
+    }
+    Ok(values)
+}
+
+fn has_doc_values(field: &MappedField) -> bool {
+    match field.field_type.as_str() {
+        "text" => field.fielddata,
+        "object" | "nested" => false,
+        _ => true,
+    }
+}
+
+/// The sorted doc values of one field, or `None` if the document has none
+fn field_doc_values(
+    name: &str,
+    format: Option<&str>,
+    doc: &serde_json::Value,
+    mapped: &BTreeMap<String, MappedField>,
+) -> Result<Option<Vec<serde_json::Value>>> {
+    // Multi-fields such as `title.keyword` hold the values of their parent
+    let value = get_field_value(doc, name).or_else(|| {
+        let (parent, _) = name.rsplit_once('.')?;
+        let is_multi_field = match mapped.get(parent) {
+            Some(parent) => {
+                !matches!(parent.field_type.as_str(), "object" | "nested")
+                    && mapped.contains_key(name)
+            }
+            None => name.ends_with(".keyword"),
+        };
+        is_multi_field
+            .then(|| get_field_value(doc, parent))
+            .flatten()
+    });
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let mut leaves = BTreeMap::new();
+    collect_leaves(value, "", &mut leaves);
+    let leaves: Vec<serde_json::Value> = leaves.into_values().flatten().collect();
+    if leaves.is_empty() {
+        return Ok(None);
+    }
+
+    let mapping = mapped.get(name);
+    let keyword_suffix = mapping.is_none() && name.ends_with(".keyword");
+    let field_type = match mapping {
+        Some(field) => field.field_type.as_str(),
+        None if keyword_suffix => "keyword",
+        None => dynamic_field_type(value, true)
+            .or_else(|| dynamic_field_type(&leaves[0], true))
+            .unwrap_or("keyword"),
+    };
+    match field_type {
+        "text" if !mapping.is_some_and(|field| field.fielddata) => {
+            return Err(GbsError::IllegalArgument(format!(
+                "Text fields are not optimised for operations that require per-document field data like aggregations and sorting, so these operations are disabled by default. Please use a keyword field instead. Alternatively, set fielddata=true on [{}] in order to load field data by uninverting the inverted index. Note that this can use significant memory.",
+                name
+            )))
+        }
+        "object" | "nested" => {
+            return Err(GbsError::IllegalArgument(format!(
+                "field [{}] of type [{}] has no doc values",
+                name, field_type
+            )))
+        }
+        _ => {}
+    }
+
+    let mut values: Vec<serde_json::Value> = match field_type {
+        "date" | "date_nanos" => {
+            let mapping_format = mapping.and_then(|field| field.format.as_deref());
+            let mut dates: Vec<DateTime<Utc>> = leaves
+                .iter()
+                .filter_map(|value| parse_doc_date(value, mapping_format))
+                .collect();
+            dates.sort();
+            return dates
+                .into_iter()
+                .map(|date| format_date(date, format))
+                .collect::<Result<Vec<_>>>()
+                .map(Some);
+        }
+        "long" | "integer" | "short" | "byte" => leaves
+            .iter()
+            .filter_map(|value| number(value).map(|n| serde_json::json!(n as i64)))
+            .collect(),
+        "double" | "float" | "half_float" | "scaled_float" => leaves
+            .iter()
+            .filter_map(|value| number(value).map(|n| serde_json::json!(n)))
+            .collect(),
+        "boolean" => leaves
+            .iter()
+            .filter_map(|value| match value {
+                serde_json::Value::Bool(b) => Some(serde_json::json!(b)),
+                serde_json::Value::String(s) => {
+                    s.parse::<bool>().ok().map(|b| serde_json::json!(b))
+                }
+                _ => None,
+            })
+            .collect(),
+        _ => leaves
+            .into_iter()
+            .map(|value| match value {
+                serde_json::Value::String(_) => value,
+                other => serde_json::json!(other.to_string()),
+            })
+            .collect(),
+    };
+    values.sort_by(compare_values);
+    Ok(Some(values))
+}
+
+fn number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn parse_doc_date(value: &serde_json::Value, format: Option<&str>) -> Option<DateTime<Utc>> {
+    match (value, format) {
+        (serde_json::Value::String(text), Some(format)) => {
+            parse_formatted_date(text, Some(format), utc())
+        }
+        _ => parse_date(value),
+    }
+}
+
+/// Format a date doc value: ISO 8601 with milliseconds by default
+fn format_date(date: DateTime<Utc>, format: Option<&str>) -> Result<serde_json::Value> {
+    let text = match format {
+        None | Some("strict_date_optional_time" | "date_optional_time") => {
+            date.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+        }
+        Some("epoch_millis") => date.timestamp_millis().to_string(),
+        Some("epoch_second") => date.timestamp().to_string(),
+        Some(pattern) => {
+            let strftime = date_format(pattern).map_err(GbsError::IllegalArgument)?;
+            date.format(&strftime).to_string()
+        }
+    };
+    Ok(serde_json::json!(text))
+}
+
+fn utc() -> FixedOffset {
+    FixedOffset::east_opt(0).expect("UTC is a valid offset")
+}
+
+fn compare_values(a: &serde_json::Value, b: &serde_json::Value) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => match (a, b) {
+            (serde_json::Value::String(a), serde_json::Value::String(b)) => a.cmp(b),
+            _ => a.to_string().cmp(&b.to_string()),
+        },
+    }
+}
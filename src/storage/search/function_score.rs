@@ -0,0 +1,243 @@
+//! Function score queries: adjust the scores of a query with functions of
+//! the matching documents
+//!
+//! ```json
+//! { "function_score": {
+//!     "query": { "match": { "title": "rust" } },
+//!     "functions": [
+//!       { "field_value_factor": { "field": "likes", "modifier": "log1p" } },
+//!       { "filter": { "term": { "featured": true } }, "weight": 2 }
+//!     ],
+//!     "score_mode": "multiply",
+//!     "boost_mode": "multiply"
+//! } }
+//! ```
+//!
+//! The functions whose `filter` matches a document (all, without a filter)
+//! give values that `score_mode` combines into a factor: `multiply`
+//! (default), `sum`, `avg`, `first`, `max` or `min`. `boost_mode` combines the
+//! factor with the score of `query` (default `match_all`): `multiply`
+//! (default), `replace`, `sum`, `avg`, `max` or `min`. A document no function
+//! applies to gets a factor of 1. `max_boost` caps the factor and documents
+//! scoring below `min_score` do not match. A single function may be given at
+//! the top level instead of under `functions`.
+//!
+//! Functions are `weight` on its own, `field_value_factor`
+//! (`modifier(factor * field)`, with `missing` for documents without the
+//! field) and `random_score` (a value in [0, 1) from the `seed` and the
+//! document ID, or the `field` of the document; reproducible, seed 0 by
+//! default). A `weight` next to another function multiplies its value.
+
+use sha2::{Digest, Sha256};
+
+use crate::error::{GbsError, Result};
+
+use super::query::score_document;
+use super::utils::get_field_value;
+
+/// Score a document against a function score query
+pub(super) fn score_function_score_query(
+    id: &str,
+    doc: &serde_json::Value,
+    function_score: &serde_json::Value,
+) -> Result<Option<f64>> {
+    let body = function_score
+        .as_object()
+        .ok_or_else(|| malformed("body must be an object"))?;
+    let query_score = match body.get("query") {
+        Some(query) => score_document(id, doc, query)?,
+        None => Some(1.0),
+    };
+    let Some(query_score) = query_score else {
+        return Ok(None);
+    };
+
+    let functions: Vec<&serde_json::Value> = match body.get("functions") {
+        Some(serde_json::Value::Array(functions)) => functions.iter().collect(),
+        Some(_) => return Err(malformed("[functions] must be an array")),
+        None if is_function(function_score) => vec![function_score],
+        None => Vec::new(),
+    };
+    let mut values = Vec::new();
+    for function in functions {
+        if let Some(filter) = function.get("filter") {
+            if score_document(id, doc, filter)?.is_none() {
+                continue;
+            }
+        }
+        values.push(function_value(id, doc, function)?);
+    }
+
+    let score_mode = mode(body, "score_mode", "multiply")?;
+    let factor = match values.as_slice() {
+        [] => 1.0,
+        [first, ..] => match score_mode {
+            "multiply" => values.iter().product(),
+            "sum" => values.iter().sum(),
+            "avg" => values.iter().sum::<f64>() / values.len() as f64,
+            "first" => *first,
+            "max" => values.iter().cloned().fold(f64::MIN, f64::max),
+            "min" => values.iter().cloned().fold(f64::MAX, f64::min),
+            other => return Err(malformed(&format!("unknown [score_mode] [{}]", other))),
+        },
+    };
+    let factor = match body.get("max_boost").and_then(|v| v.as_f64()) {
+        Some(max_boost) => factor.min(max_boost),
+        None => factor,
+    };
+
+    let score = match mode(body, "boost_mode", "multiply")? {
+        "multiply" => query_score * factor,
+        "replace" => factor,
+        "sum" => query_score + factor,
+        "avg" => (query_score + factor) / 2.0,
+        "max" => query_score.max(factor),
+        "min" => query_score.min(factor),
+        other => return Err(malformed(&format!("unknown [boost_mode] [{}]", other))),
+    };
+    if body
+        .get("min_score")
+        .and_then(|v| v.as_f64())
+        .is_some_and(|min_score| score < min_score)
+    {
+        return Ok(None);
+    }
+    Ok(Some(score))
+}
+
+/// Whether an object holds a function (at the top level of the query or as
+/// an entry of `functions`)
+fn is_function(value: &serde_json::Value) -> bool {
+    ["weight", "field_value_factor", "random_score"]
+        .iter()
+        .any(|key| value.get(key).is_some())
+}
+
+/// Value of a function for a document
+fn function_value(id: &str, doc: &serde_json::Value, function: &serde_json::Value) -> Result<f64> {
+    let weight = match function.get("weight") {
+        Some(weight) => weight
+            .as_f64()
+            .ok_or_else(|| malformed("[weight] must be a number"))?,
+        None => 1.0,
+    };
+    let value = if let Some(factor) = function.get("field_value_factor") {
+        field_value_factor(doc, factor)?
+    } else if let Some(random) = function.get("random_score") {
+        random_score(id, doc, random)?
+    } else if function.get("weight").is_some() {
+        1.0
+    } else {
+        return Err(malformed(
+            "a function requires [weight], [field_value_factor] or [random_score]",
+        ));
+    };
+    Ok(value * weight)
+}
+
+/// `modifier(factor * field)` of a `field_value_factor` function
+fn field_value_factor(doc: &serde_json::Value, spec: &serde_json::Value) -> Result<f64> {
+    let field = spec
+        .get("field")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| malformed("[field_value_factor] requires [field]"))?;
+    let factor = spec.get("factor").and_then(|v| v.as_f64()).unwrap_or(1.0);
+    let value = match get_field_value(doc, field).and_then(numeric_value) {
+        Some(value) => value,
+        None => spec
+            .get("missing")
+            .and_then(|v| v.as_f64())
+            .ok_or_else(|| {
+                GbsError::InvalidRequest(format!(
+                    "[field_value_factor] missing value for field [{}]",
+                    field
+                ))
+            })?,
+    };
+    let value = factor * value;
+    let modified = match spec
+        .get("modifier")
+        .and_then(|v| v.as_str())
+        .unwrap_or("none")
+    {
+        "none" => value,
+        "log" => value.log10(),
+        "log1p" => (value + 1.0).log10(),
+        "log2p" => (value + 2.0).log10(),
+        "ln" => value.ln(),
+        "ln1p" => value.ln_1p(),
+        "ln2p" => (value + 2.0).ln(),
+        "square" => value * value,
+        "sqrt" => value.sqrt(),
+        "reciprocal" => 1.0 / value,
+        other => {
+            return Err(malformed(&format!(
+                "unknown [field_value_factor] modifier [{}]",
+                other
+            )))
+        }
+    };
+    if !modified.is_finite() || modified < 0.0 {
+        return Err(GbsError::InvalidRequest(format!(
+            "[field_value_factor] gives the invalid score [{}] for field [{}]",
+            modified, field
+        )));
+    }
+    Ok(modified)
+}
+
+/// A number, a numeric string, or the first number of an array
+fn numeric_value(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.parse().ok(),
+        serde_json::Value::Array(values) => values.iter().find_map(numeric_value),
+        _ => None,
+    }
+}
+
+/// Value in [0, 1) of a `random_score` function, the same for the same seed
+/// and document
+fn random_score(id: &str, doc: &serde_json::Value, spec: &serde_json::Value) -> Result<f64> {
+    let seed = match spec.get("seed") {
+        None => String::from("0"),
+        Some(serde_json::Value::String(seed)) => seed.clone(),
+        Some(seed @ serde_json::Value::Number(_)) => seed.to_string(),
+        Some(_) => {
+            return Err(malformed(
+                "[random_score] [seed] must be a number or a string",
+            ))
+        }
+    };
+    let source = match spec.get("field").and_then(|v| v.as_str()) {
+        Some(field) => get_field_value(doc, field)
+            .map(|value| value.to_string())
+            .unwrap_or_default(),
+        None => id.to_string(),
+    };
+    let hash = Sha256::new()
+        .chain_update(seed.as_bytes())
+        .chain_update([0])
+        .chain_update(source.as_bytes())
+        .finalize();
+    let bits = u64::from_be_bytes(hash[..8].try_into().expect("8 bytes"));
+    // The top 53 bits, as a fraction of 2^53
+    Ok((bits >> 11) as f64 / (1u64 << 53) as f64)
+}
+
+fn mode<'a>(
+    body: &'a serde_json::Map<String, serde_json::Value>,
+    name: &str,
+    default: &'a str,
+) -> Result<&'a str> {
+    match body.get(name) {
+        None => Ok(default),
+        Some(value) => value
+            .as_str()
+            .ok_or_else(|| malformed(&format!("[{}] must be a string", name))),
+    }
+}
+
+fn malformed(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(format!("[function_score] query malformed: {}", reason))
+}
@@ -2,49 +2,90 @@
 
 use super::utils::get_field_value;
 
+/// Default maximum length of a fragment, in characters
+const DEFAULT_FRAGMENT_SIZE: usize = 100;
+
+/// Default maximum number of fragments per field
+const DEFAULT_NUMBER_OF_FRAGMENTS: usize = 5;
+
+/// Highlighting options of a field
+///
+/// Options are given at the top level of the highlight request and can be
+/// overridden per field.
+struct HighlightOptions<'a> {
+    pre_tags: Vec<&'a str>,
+    post_tags: Vec<&'a str>,
+    /// Maximum fragment length in characters
+    fragment_size: usize,
+    /// Maximum number of fragments; 0 highlights the whole field as one fragment
+    number_of_fragments: usize,
+    /// Characters returned from the start of a field without matches
+    no_match_size: usize,
+    /// Whether fragments are ordered by their number of matches instead of
+    /// their position in the field
+    order_by_score: bool,
+}
+
+impl<'a> HighlightOptions<'a> {
+    fn parse(config: &'a serde_json::Value, field_config: &'a serde_json::Value) -> Self {
+        let option = |key: &str| field_config.get(key).or_else(|| config.get(key));
+        let tags = |key: &str, default: &'a str| -> Vec<&'a str> {
+            let tags: Vec<&str> = match option(key) {
+                Some(serde_json::Value::String(tag)) => vec![tag.as_str()],
+                Some(serde_json::Value::Array(tags)) => {
+                    tags.iter().filter_map(|tag| tag.as_str()).collect()
+                }
+                _ => Vec::new(),
+            };
+            if tags.is_empty() {
+                vec![default]
+            } else {
+                tags
+            }
+        };
+        let count = |key: &str, default: usize| {
+            option(key)
+                .and_then(|value| value.as_u64())
+                .map_or(default, |value| value as usize)
+        };
+        Self {
+            pre_tags: tags("pre_tags", "<em>"),
+            post_tags: tags("post_tags", "</em>"),
+            fragment_size: count("fragment_size", DEFAULT_FRAGMENT_SIZE).max(1),
+            number_of_fragments: count("number_of_fragments", DEFAULT_NUMBER_OF_FRAGMENTS),
+            no_match_size: count("no_match_size", 0),
+            order_by_score: option("order").and_then(|order| order.as_str()) == Some("score"),
+        }
+    }
+
+    /// Tags wrapping matches of the term at `term` in the query terms
+    ///
+    /// With several tags, different terms get different tags.
+    fn tags(&self, term: usize) -> (&str, &str) {
+        (
+            self.pre_tags[term % self.pre_tags.len()],
+            self.post_tags[term % self.post_tags.len()],
+        )
+    }
+}
+
 /// Highlight matched terms in a document based on query and highlight configuration
 ///
-/// Returns a JSON object with highlighted fields, e.g.:
+/// Returns a JSON object with the fragments of each field that has matches,
+/// e.g.:
 /// {
 ///   "title": ["This is a <em>search</em> result"],
-///   "body": ["Some <em>search</em> content here"]
+///   "body": ["...text around the first <em>search</em> match", "..."]
 /// }
+///
+/// Supports `pre_tags`/`post_tags`, `fragment_size`, `number_of_fragments`,
+/// `no_match_size` and `order: "score"`, globally or per field.
 pub fn highlight_document(
     doc: &serde_json::Value,
     query: &serde_json::Value,
     highlight_config: &serde_json::Value,
 ) -> Option<serde_json::Value> {
-    // Extract fields to highlight
-    let fields_to_highlight = if let Some(config_obj) = highlight_config.as_object() {
-        if let Some(fields) = config_obj.get("fields") {
-            if let Some(fields_obj) = fields.as_object() {
-                fields_obj.keys().collect::<Vec<_>>()
-            } else {
-                return None;
-            }
-        } else {
-            return None;
-        }
-    } else {
-        return None;
-    };
-
-    // Extract highlight tags (default: <em></em>)
-    let pre_tag = highlight_config
-        .as_object()
-        .and_then(|c| c.get("pre_tags"))
-        .and_then(|t| t.as_array())
-        .and_then(|a| a.get(0))
-        .and_then(|v| v.as_str())
-        .unwrap_or("<em>");
-
-    let post_tag = highlight_config
-        .as_object()
-        .and_then(|c| c.get("post_tags"))
-        .and_then(|t| t.as_array())
-        .and_then(|a| a.get(0))
-        .and_then(|v| v.as_str())
-        .unwrap_or("</em>");
+    let fields = highlight_config.get("fields")?.as_object()?;
 
     // Extract query terms from the query
     let query_terms = extract_query_terms(query);
@@ -55,14 +96,21 @@ pub fn highlight_document(
     // Build highlight result
     let mut highlight_result = serde_json::Map::new();
 
-    for field in fields_to_highlight {
-        if let Some(field_value) = get_field_value(doc, field) {
-            if let Some(field_str) = field_value.as_str() {
-                let highlighted = highlight_text(field_str, &query_terms, pre_tag, post_tag);
-                if !highlighted.is_empty() {
-                    highlight_result.insert(field.to_string(), serde_json::json!([highlighted]));
-                }
+    for (field, field_config) in fields {
+        let Some(field_value) = get_field_value(doc, field) else {
+            continue;
+        };
+        let values: Vec<&str> = match &field_value {
+            serde_json::Value::String(text) => vec![text.as_str()],
+            serde_json::Value::Array(values) => {
+                values.iter().filter_map(|value| value.as_str()).collect()
             }
+            _ => continue,
+        };
+        let options = HighlightOptions::parse(highlight_config, field_config);
+        let fragments = highlight_values(&values, &query_terms, &options);
+        if !fragments.is_empty() {
+            highlight_result.insert(field.to_string(), serde_json::json!(fragments));
         }
     }
 
@@ -73,6 +121,200 @@ pub fn highlight_document(
     }
 }
 
+/// Highlighted fragments of the values of a field
+fn highlight_values(values: &[&str], terms: &[String], options: &HighlightOptions) -> Vec<String> {
+    let mut fragments: Vec<(usize, String)> = Vec::new();
+    for text in values {
+        let matches = find_matches(text, terms);
+        if matches.is_empty() {
+            continue;
+        }
+        if options.number_of_fragments == 0 {
+            fragments.push((
+                matches.len(),
+                render(text, 0, text.len(), &matches, options),
+            ));
+            continue;
+        }
+        for (start, end, count) in fragment_ranges(text, &matches, options.fragment_size) {
+            fragments.push((count, render(text, start, end, &matches, options)));
+        }
+    }
+
+    if fragments.is_empty() {
+        // Fields without matches may still return their beginning
+        return match values.first() {
+            Some(text) if options.no_match_size > 0 => {
+                let end = snap_end(text, 0, char_offset(text, 0, options.no_match_size), 0);
+                vec![text[..end].trim().to_string()]
+            }
+            _ => Vec::new(),
+        };
+    }
+    if options.order_by_score {
+        // Stable sort: fragments with as many matches keep their position order
+        fragments.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+    }
+    let limit = options.number_of_fragments.max(1);
+    fragments
+        .into_iter()
+        .take(limit)
+        .map(|(_, fragment)| fragment)
+        .collect()
+}
+
+/// Find the matches of the query terms in a text, case-insensitively and on
+/// word boundaries
+///
+/// Returns the byte range of each match in the text and the index of the
+/// matched term, sorted by position and without overlaps.
+fn find_matches(text: &str, terms: &[String]) -> Vec<(usize, usize, usize)> {
+    // Lowercasing may change byte lengths, so offsets are mapped back
+    let mut lowered = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len() + 1);
+    for (offset, c) in text.char_indices() {
+        for lower in c.to_lowercase() {
+            for _ in 0..lower.len_utf8() {
+                offsets.push(offset);
+            }
+            lowered.push(lower);
+        }
+    }
+    offsets.push(text.len());
+
+    let mut matches = Vec::new();
+    for (term_index, term) in terms.iter().enumerate() {
+        let term = term.to_lowercase();
+        if term.is_empty() {
+            continue;
+        }
+        let mut from = 0;
+        while let Some(position) = lowered[from..].find(&term) {
+            let start = from + position;
+            let end = start + term.len();
+            let bounded_before = lowered[..start]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !c.is_alphanumeric());
+            let bounded_after = lowered[end..]
+                .chars()
+                .next()
+                .is_none_or(|c| !c.is_alphanumeric());
+            if bounded_before && bounded_after {
+                matches.push((offsets[start], offsets[end], term_index));
+            }
+            from = start + lowered[start..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    matches.sort_by_key(|(start, end, _)| (*start, std::cmp::Reverse(*end)));
+    let mut non_overlapping: Vec<(usize, usize, usize)> = Vec::new();
+    for m in matches {
+        if non_overlapping.last().is_none_or(|last| m.0 >= last.1) {
+            non_overlapping.push(m);
+        }
+    }
+    non_overlapping
+}
+
+/// Byte ranges of the fragments around the matches, with their match counts
+///
+/// Each fragment holds up to `fragment_size` characters around its first
+/// match, cut at whitespace where possible; following matches within it are
+/// part of the same fragment.
+fn fragment_ranges(
+    text: &str,
+    matches: &[(usize, usize, usize)],
+    fragment_size: usize,
+) -> Vec<(usize, usize, usize)> {
+    let mut fragments: Vec<(usize, usize, usize)> = Vec::new();
+    for &(match_start, match_end, _) in matches {
+        if let Some(last) = fragments.last_mut() {
+            if match_end <= last.1 {
+                last.2 += 1;
+                continue;
+            }
+        }
+        let previous_end = fragments.last().map_or(0, |last| last.1);
+        let match_chars = text[match_start..match_end].chars().count();
+        let padding = fragment_size.saturating_sub(match_chars) / 2;
+
+        let start = text[previous_end..match_start]
+            .char_indices()
+            .rev()
+            .nth(padding.saturating_sub(1))
+            .map_or(previous_end, |(offset, _)| previous_end + offset);
+        let start = if padding == 0 { match_start } else { start };
+        let start = snap_start(text, start, match_start);
+        let remaining = fragment_size.saturating_sub(text[start..match_end].chars().count());
+        let end = char_offset(text, match_end, remaining);
+        let end = snap_end(text, start, end, match_end);
+        fragments.push((start, end, 1));
+    }
+    fragments
+}
+
+/// Byte offset `chars` characters after `from`, at most the end of the text
+fn char_offset(text: &str, from: usize, chars: usize) -> usize {
+    text[from..]
+        .char_indices()
+        .nth(chars)
+        .map_or(text.len(), |(offset, _)| from + offset)
+}
+
+/// Move a fragment start forward to the start of a word, not past `limit`
+fn snap_start(text: &str, start: usize, limit: usize) -> usize {
+    let at_word_start = text[..start]
+        .chars()
+        .next_back()
+        .is_none_or(char::is_whitespace);
+    if at_word_start {
+        return start;
+    }
+    text[start..limit]
+        .char_indices()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(start, |(offset, c)| start + offset + c.len_utf8())
+}
+
+/// Move a fragment end back to the end of a word, not before `limit`
+fn snap_end(text: &str, start: usize, end: usize, limit: usize) -> usize {
+    let at_word_end = end == text.len() || text[end..].starts_with(char::is_whitespace);
+    if at_word_end {
+        return end;
+    }
+    text[limit.max(start)..end]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| c.is_whitespace())
+        .map_or(end, |(offset, _)| limit.max(start) + offset)
+}
+
+/// The text between `start` and `end` with its matches wrapped in tags
+fn render(
+    text: &str,
+    start: usize,
+    end: usize,
+    matches: &[(usize, usize, usize)],
+    options: &HighlightOptions,
+) -> String {
+    let mut result = String::new();
+    let mut last_end = start;
+    for &(match_start, match_end, term) in matches {
+        if match_start < start || match_end > end {
+            continue;
+        }
+        let (pre_tag, post_tag) = options.tags(term);
+        result.push_str(&text[last_end..match_start]);
+        result.push_str(pre_tag);
+        result.push_str(&text[match_start..match_end]);
+        result.push_str(post_tag);
+        last_end = match_end;
+    }
+    result.push_str(&text[last_end..end]);
+    result.trim().to_string()
+}
+
 /// Extract search terms from a query
 pub fn extract_query_terms(query: &serde_json::Value) -> Vec<String> {
     let mut terms = Vec::new();
@@ -149,63 +391,5 @@ pub fn extract_query_terms(query: &serde_json::Value) -> Vec<String> {
 
 /// Tokenize a query string into terms (simple whitespace splitting)
 pub fn tokenize_query(query: &str) -> Vec<String> {
-    query
-        .split_whitespace()
-        .map(|s| s.to_lowercase())
-        .collect()
-}
-
-/// Highlight text by wrapping matched terms with tags
-pub fn highlight_text(text: &str, terms: &[String], pre_tag: &str, post_tag: &str) -> String {
-    if terms.is_empty() {
-        return text.to_string();
-    }
-
-    let text_lower = text.to_lowercase();
-    let mut result = String::new();
-    let mut last_end = 0;
-
-    // Find all matches and their positions
-    let mut matches: Vec<(usize, usize, &str)> = Vec::new();
-    for term in terms {
-        let term_lower = term.to_lowercase();
-        let mut start = 0;
-        while let Some(pos) = text_lower[start..].find(&term_lower) {
-            let actual_pos = start + pos;
-            let end = actual_pos + term.len();
-            matches.push((actual_pos, end, &text[actual_pos..end]));
-            start = end;
-        }
-    }
-
-    // Sort matches by position
-    matches.sort_by_key(|(start, _, _)| *start);
-
-    // Remove overlapping matches (keep the first one)
-    let mut non_overlapping: Vec<(usize, usize, &str)> = Vec::new();
-    for (start, end, matched_text) in matches {
-        if non_overlapping.is_empty() || start >= non_overlapping.last().unwrap().1 {
-            non_overlapping.push((start, end, matched_text));
-        }
-    }
-
-    // Build highlighted string
-    for (start, end, matched_text) in non_overlapping {
-        // Add text before match
-        if start > last_end {
-            result.push_str(&text[last_end..start]);
-        }
-        // Add highlighted match
-        result.push_str(pre_tag);
-        result.push_str(matched_text);
-        result.push_str(post_tag);
-        last_end = end;
-    }
-
-    // Add remaining text
-    if last_end < text.len() {
-        result.push_str(&text[last_end..]);
-    }
-
-    result
+    query.split_whitespace().map(|s| s.to_lowercase()).collect()
 }
@@ -0,0 +1,210 @@
+//! Inner hits: the parts of a hit that matched, returned inside the hit
+//!
+//! A `nested` query with `inner_hits` lists the objects under its `path` that
+//! match its inner query, and `collapse` with `inner_hits` lists the members
+//! of each group (see `search::collapse`):
+//!
+//! ```json
+//! { "nested": {
+//!     "path": "comments",
+//!     "query": { "match": { "comments.text": "great" } },
+//!     "inner_hits": { "name": "great_comments", "size": 2, "sort": [{ "comments.stars": "desc" }] }
+//! } }
+//! ```
+//!
+//! Each hit gets `inner_hits.<name>` (the name defaults to the nested path or
+//! the collapse field) in the shape of the `hits` of a search, paged with
+//! `from` and `size` (default 3) and ordered by `sort`, or else best first.
+//! Nested inner hits carry the `_id` of their document, the position of the
+//! object in `_nested` and the object itself as `_source`. Nested queries are
+//! found in `bool` clauses (except `must_not`) and `function_score` queries.
+
+use super::query::{nested_elements, nested_query_parts, score_document};
+use super::sort::{compare_sort_values, parse_sort, sort_values, SortClause};
+use super::utils::filter_source;
+use crate::error::{GbsError, Result};
+
+/// Default number of inner hits returned per hit
+const DEFAULT_INNER_HITS_SIZE: usize = 3;
+
+/// A parsed `inner_hits` specification
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct InnerHits {
+    pub(super) name: String,
+    from: usize,
+    size: usize,
+    sort: Vec<SortClause>,
+}
+
+impl InnerHits {
+    /// Parse `{ "name": ..., "from": ..., "size": ..., "sort": ... }`
+    pub(super) fn parse(spec: &serde_json::Value, default_name: &str) -> Result<Self> {
+        if !spec.is_object() {
+            return Err(invalid("[inner_hits] must be an object"));
+        }
+        let count = |name: &str, default: usize| -> Result<usize> {
+            match spec.get(name) {
+                None => Ok(default),
+                Some(value) => value.as_u64().map(|value| value as usize).ok_or_else(|| {
+                    invalid(&format!(
+                        "[inner_hits] [{}] must be a non-negative integer",
+                        name
+                    ))
+                }),
+            }
+        };
+        Ok(Self {
+            name: spec
+                .get("name")
+                .and_then(|v| v.as_str())
+                .unwrap_or(default_name)
+                .to_string(),
+            from: count("from", 0)?,
+            size: count("size", DEFAULT_INNER_HITS_SIZE)?,
+            sort: spec
+                .get("sort")
+                .map(parse_sort)
+                .transpose()?
+                .unwrap_or_default(),
+        })
+    }
+
+    /// Page of inner hits, in the shape of the `hits` of a search
+    ///
+    /// `hits` are best first, each with the document its `sort` is computed
+    /// on. Their sources are filtered with `source_filter`.
+    pub(super) fn render(
+        &self,
+        hits: Vec<(serde_json::Value, serde_json::Value)>,
+        source_filter: Option<&serde_json::Value>,
+    ) -> serde_json::Value {
+        let total = hits.len();
+        let max_score = hits
+            .iter()
+            .filter_map(|(hit, _)| hit["_score"].as_f64())
+            .reduce(f64::max);
+        let mut hits: Vec<serde_json::Value> = hits
+            .into_iter()
+            .map(|(mut hit, doc)| {
+                if !self.sort.is_empty() {
+                    let ranked = (
+                        hit["_id"].as_str().unwrap_or_default().to_string(),
+                        doc,
+                        hit["_score"].as_f64().unwrap_or(0.0),
+                    );
+                    hit["sort"] = serde_json::json!(sort_values(&ranked, &self.sort));
+                }
+                hit
+            })
+            .collect();
+        if !self.sort.is_empty() {
+            hits.sort_by(|a, b| compare_sort_values(hit_sort(a), hit_sort(b), &self.sort));
+        }
+        let hits: Vec<serde_json::Value> = hits
+            .into_iter()
+            .skip(self.from)
+            .take(self.size)
+            .map(|mut hit| {
+                filter_hit_source(&mut hit, source_filter);
+                hit
+            })
+            .collect();
+        serde_json::json!({
+            "hits": {
+                "total": { "value": total, "relation": "eq" },
+                "max_score": max_score,
+                "hits": hits
+            }
+        })
+    }
+}
+
+/// Inner hits of the nested queries with `inner_hits` in `query` for a hit
+///
+/// `None` if the query has no such nested query.
+pub fn nested_inner_hits(
+    index_name: &str,
+    id: &str,
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Option<serde_json::Value>> {
+    let mut nested_queries = Vec::new();
+    find_nested_inner_hits(query, &mut nested_queries);
+    if nested_queries.is_empty() {
+        return Ok(None);
+    }
+
+    let mut inner_hits = serde_json::Map::new();
+    for nested in nested_queries {
+        let (path, inner_query, _) = nested_query_parts(nested)?;
+        let spec = InnerHits::parse(&nested["inner_hits"], path)?;
+        let mut hits = Vec::new();
+        for (offset, element, element_doc) in nested_elements(doc, path) {
+            if let Some(score) = score_document(id, &element_doc, inner_query)? {
+                let hit = serde_json::json!({
+                    "_index": index_name,
+                    "_type": "_doc",
+                    "_id": id,
+                    "_nested": { "field": path, "offset": offset },
+                    "_score": score,
+                    "_source": element
+                });
+                hits.push((hit, element_doc));
+            }
+        }
+        // Best first, as the hits of a search
+        hits.sort_by(|(a, _), (b, _)| {
+            let score = |hit: &serde_json::Value| hit["_score"].as_f64().unwrap_or(0.0);
+            score(b).total_cmp(&score(a))
+        });
+        inner_hits.insert(spec.name.clone(), spec.render(hits, None));
+    }
+    Ok(Some(serde_json::Value::Object(inner_hits)))
+}
+
+/// Bodies of the nested queries with `inner_hits` in a query
+fn find_nested_inner_hits<'a>(
+    query: &'a serde_json::Value,
+    found: &mut Vec<&'a serde_json::Value>,
+) {
+    let Some(query_obj) = query.as_object() else {
+        return;
+    };
+    if let Some(nested) = query_obj.get("nested") {
+        if nested.get("inner_hits").is_some() {
+            found.push(nested);
+        }
+    }
+    if let Some(bool_obj) = query_obj.get("bool").and_then(|b| b.as_object()) {
+        for occur in ["must", "should", "filter"] {
+            match bool_obj.get(occur) {
+                Some(serde_json::Value::Array(clauses)) => clauses
+                    .iter()
+                    .for_each(|clause| find_nested_inner_hits(clause, found)),
+                Some(clause) => find_nested_inner_hits(clause, found),
+                None => {}
+            }
+        }
+    }
+    if let Some(inner) = query_obj.get("function_score").and_then(|f| f.get("query")) {
+        find_nested_inner_hits(inner, found);
+    }
+}
+
+fn hit_sort(hit: &serde_json::Value) -> &[serde_json::Value] {
+    hit["sort"].as_array().map_or(&[], Vec::as_slice)
+}
+
+/// Filter the `_source` of a hit, if there is a filter
+pub(super) fn filter_hit_source(
+    hit: &mut serde_json::Value,
+    source_filter: Option<&serde_json::Value>,
+) {
+    if source_filter.is_some() {
+        hit["_source"] = filter_source(&hit["_source"], source_filter);
+    }
+}
+
+fn invalid(reason: &str) -> GbsError {
+    GbsError::InvalidRequest(reason.to_string())
+}
@@ -1,7 +1,12 @@
 //! Query matchers for different query types
 
+use super::date_math::{is_date_math, parse_date_math, parse_formatted_date, parse_time_zone};
+use super::normalizer::TermNormalization;
+use super::utils::{get_field_value, parse_date};
+use crate::error::{GbsError, Result};
+use chrono::{DateTime, FixedOffset, Offset, Utc};
 use regex::Regex;
-use super::utils::get_field_value;
+use std::cmp::Ordering;
 
 /// Match a field against query text (case-insensitive substring match)
 pub fn match_field(doc: &serde_json::Value, field: &str, query_text: &str) -> Option<f64> {
@@ -11,11 +16,22 @@ pub fn match_field(doc: &serde_json::Value, field: &str, query_text: &str) -> Op
 
     // Handle _all field - search in all fields
     if field == "_all" || field == "*" {
-        return match_all_fields(doc, query_text);
+        return match_all_fields(get_field_value(doc, field)?, query_text);
     }
 
-    let field_value = get_field_value(doc, field)?;
-    let field_str = match field_value {
+    match_value(get_field_value(doc, field)?, query_text)
+}
+
+/// Match one field value against query text; an array matches like its best
+/// matching value
+fn match_value(value: &serde_json::Value, query_text: &str) -> Option<f64> {
+    let field_str = match value {
+        serde_json::Value::Array(values) => {
+            return values
+                .iter()
+                .filter_map(|value| match_value(value, query_text))
+                .max_by(f64::total_cmp)
+        }
         serde_json::Value::String(s) => s.to_lowercase(),
         serde_json::Value::Number(n) => n.to_string(),
         serde_json::Value::Bool(b) => b.to_string(),
@@ -33,7 +49,8 @@ pub fn match_field(doc: &serde_json::Value, field: &str, query_text: &str) -> Op
         // Check for word matches
         let words: Vec<&str> = query_lower.split_whitespace().collect();
         let field_words: Vec<&str> = field_str.split_whitespace().collect();
-        let matches = words.iter()
+        let matches = words
+            .iter()
             .filter(|w| field_words.iter().any(|fw| fw.contains(*w)))
             .count();
         if matches > 0 {
@@ -76,7 +93,8 @@ fn search_value(value: &serde_json::Value, query: &str, max_score: &mut f64) {
                 // Check for word matches
                 let words: Vec<&str> = query.split_whitespace().collect();
                 let field_words: Vec<&str> = s_lower.split_whitespace().collect();
-                let matches = words.iter()
+                let matches = words
+                    .iter()
                     .filter(|w| field_words.iter().any(|fw| fw.contains(*w)))
                     .count();
                 if matches > 0 {
@@ -107,12 +125,41 @@ fn search_value(value: &serde_json::Value, query: &str, max_score: &mut f64) {
     }
 }
 
-/// Check if a field matches a term exactly
-pub fn term_match(doc: &serde_json::Value, field: &str, value: &serde_json::Value) -> bool {
-    if let Some(field_value) = get_field_value(doc, field) {
-        *field_value == *value
-    } else {
-        false
+/// Check if a document ID equals a query value (numbers match their string form)
+pub fn id_matches(id: &str, value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(s) => s == id,
+        serde_json::Value::Number(n) => n.to_string() == id,
+        _ => false,
+    }
+}
+
+/// Check if a field matches a term exactly, or once normalized
+pub fn term_match(
+    doc: &serde_json::Value,
+    field: &str,
+    value: &serde_json::Value,
+    normalization: &TermNormalization,
+) -> bool {
+    match get_field_value(doc, field) {
+        Some(field_value) => normalized_equal(field_value, value, normalization),
+        None => false,
+    }
+}
+
+/// Compare a field value to a query value, strings as normalized
+fn normalized_equal(
+    field_value: &serde_json::Value,
+    value: &serde_json::Value,
+    normalization: &TermNormalization,
+) -> bool {
+    match (field_value, value) {
+        (serde_json::Value::String(field_value), serde_json::Value::String(value))
+            if !normalization.is_exact() =>
+        {
+            normalization.apply(field_value) == normalization.apply(value)
+        }
+        _ => field_value == value,
     }
 }
 
@@ -124,7 +171,7 @@ pub fn match_phrase_field(doc: &serde_json::Value, field: &str, phrase: &str) ->
 
     // Handle _all field - search in all fields
     if field == "_all" || field == "*" {
-        return match_phrase_all_fields(doc, phrase);
+        return match_phrase_all_fields(get_field_value(doc, field)?, phrase);
     }
 
     let field_value = get_field_value(doc, field)?;
@@ -225,16 +272,51 @@ fn search_phrase_value(value: &serde_json::Value, phrase: &str, max_score: &mut
     }
 }
 
-/// Match query text against multiple fields (returns highest score)
-pub fn multi_match_fields(doc: &serde_json::Value, fields: &[&str], query_text: &str) -> Option<f64> {
+/// Check that every word of the query text occurs in a field (`"operator": "and"`)
+pub fn all_terms_match(doc: &serde_json::Value, field: &str, query_text: &str) -> bool {
+    let value = match get_field_value(doc, field) {
+        Some(value) => value,
+        None => return false,
+    };
+    let mut tokens = Vec::new();
+    field_tokens(value, &mut tokens);
+
+    query_text
+        .to_lowercase()
+        .split_whitespace()
+        .all(|word| tokens.iter().any(|token| token.contains(word)))
+}
+
+/// Split a `field^boost` specification into the field name and its boost
+pub fn parse_field_boost(field: &str) -> (&str, f64) {
+    match field.split_once('^') {
+        Some((name, boost)) => (name, boost.parse::<f64>().unwrap_or(1.0)),
+        None => (field, 1.0),
+    }
+}
+
+/// Match query text against multiple fields (returns highest boosted score)
+///
+/// Fields may carry a boost (`"title^3"`). With `require_all_terms` a field only
+/// matches if it contains every word of the query text.
+pub fn multi_match_fields(
+    doc: &serde_json::Value,
+    fields: &[&str],
+    query_text: &str,
+    require_all_terms: bool,
+) -> Option<f64> {
     if query_text.is_empty() {
         return Some(1.0);
     }
 
     let mut max_score: f64 = 0.0;
     for field in fields {
+        let (field, boost) = parse_field_boost(field);
+        if require_all_terms && !all_terms_match(doc, field, query_text) {
+            continue;
+        }
         if let Some(score) = match_field(doc, field, query_text) {
-            max_score = max_score.max(score);
+            max_score = max_score.max(score * boost);
         }
     }
 
@@ -246,90 +328,154 @@ pub fn multi_match_fields(doc: &serde_json::Value, fields: &[&str], query_text:
 }
 
 /// Check if a field value matches a range query
+///
+/// Bounds that are date math (`now-7d/d`, `2024-01-01||+1M`), or any bound
+/// when `format` or `time_zone` is given, compare the field as a date.
 pub fn range_match(
     doc: &serde_json::Value,
     field: &str,
     range_params: &serde_json::Map<String, serde_json::Value>,
-) -> bool {
-    let field_value = match get_field_value(doc, field) {
-        Some(v) => v,
-        None => return false,
-    };
-
-    // Extract numeric value
-    let num_value = match field_value {
-        serde_json::Value::Number(n) => n.as_f64(),
-        serde_json::Value::String(s) => s.parse::<f64>().ok(),
-        _ => return false,
+) -> Result<bool> {
+    let format = match range_params.get("format") {
+        Some(serde_json::Value::String(format)) => Some(format.as_str()),
+        Some(other) => {
+            return Err(GbsError::InvalidRequest(format!(
+                "[range] format must be a string, got [{}]",
+                other
+            )))
+        }
+        None => None,
     };
+    let time_zone =
+        match range_params.get("time_zone") {
+            Some(time_zone) => Some(time_zone.as_str().and_then(parse_time_zone).ok_or_else(
+                || GbsError::InvalidRequest(format!("[range] unknown time zone [{}]", time_zone)),
+            )?),
+            None => None,
+        };
+    let as_dates = format.is_some() || time_zone.is_some();
+    let time_zone = time_zone.unwrap_or(Utc.fix());
 
-    let num_value = match num_value {
+    let field_value = match get_field_value(doc, field) {
         Some(v) => v,
-        None => return false,
+        None => return Ok(false),
     };
+    if !matches!(
+        field_value,
+        serde_json::Value::Number(_) | serde_json::Value::String(_)
+    ) {
+        return Ok(false);
+    }
 
     // Check range conditions
-    if let Some(gte) = range_params.get("gte") {
-        if let Some(gte_val) = gte.as_f64() {
-            if num_value < gte_val {
-                return false;
+    for (key, bound) in range_params {
+        // Rounding goes up for bounds that include the rest of the period
+        let (holds, round_up): (fn(Ordering) -> bool, bool) = match key.as_str() {
+            "gte" => (Ordering::is_ge, false),
+            "gt" => (Ordering::is_gt, true),
+            "lte" => (Ordering::is_le, true),
+            "lt" => (Ordering::is_lt, false),
+            _ => continue,
+        };
+        let ordering = match bound {
+            serde_json::Value::String(text) if as_dates || is_date_math(text) => {
+                let bound = date_bound(text, round_up, format, time_zone)?;
+                document_date(field_value, format).map(|value| value.cmp(&bound))
             }
-        }
-    }
-
-    if let Some(gt) = range_params.get("gt") {
-        if let Some(gt_val) = gt.as_f64() {
-            if num_value <= gt_val {
-                return false;
+            serde_json::Value::Number(n) if format.is_some() => {
+                let bound = date_bound(&n.to_string(), round_up, format, time_zone)?;
+                document_date(field_value, format).map(|value| value.cmp(&bound))
             }
+            _ => compare_to_bound(field_value, bound),
+        };
+        match ordering {
+            Some(ordering) if holds(ordering) => {}
+            _ => return Ok(false),
         }
     }
 
-    if let Some(lte) = range_params.get("lte") {
-        if let Some(lte_val) = lte.as_f64() {
-            if num_value > lte_val {
-                return false;
-            }
-        }
-    }
+    Ok(true)
+}
 
-    if let Some(lt) = range_params.get("lt") {
-        if let Some(lt_val) = lt.as_f64() {
-            if num_value >= lt_val {
-                return false;
-            }
+/// Evaluate a date range bound
+fn date_bound(
+    text: &str,
+    round_up: bool,
+    format: Option<&str>,
+    time_zone: FixedOffset,
+) -> Result<DateTime<Utc>> {
+    parse_date_math(text, Utc::now(), round_up, format, time_zone).ok_or_else(|| {
+        GbsError::InvalidRequest(format!(
+            "[range] failed to parse date field [{}] with format [{}]",
+            text,
+            format.unwrap_or("strict_date_optional_time||epoch_millis")
+        ))
+    })
+}
+
+/// A date field value: epoch millis, ISO 8601, or a date in the query's format
+///
+/// Dates without an offset in the document are UTC.
+fn document_date(value: &serde_json::Value, format: Option<&str>) -> Option<DateTime<Utc>> {
+    parse_date(value).or_else(|| match value {
+        serde_json::Value::String(text) => parse_formatted_date(text, format, Utc.fix()),
+        _ => None,
+    })
+}
+
+/// Compare a field value to a range bound
+///
+/// Numbers (and numeric strings) compare numerically, dates by time, and
+/// other strings lexicographically.
+fn compare_to_bound(value: &serde_json::Value, bound: &serde_json::Value) -> Option<Ordering> {
+    let as_number = |v: &serde_json::Value| match v {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    };
+    if let (Some(value), Some(bound)) = (as_number(value), as_number(bound)) {
+        return value.partial_cmp(&bound);
+    }
+    if let (Some(value), Some(bound)) = (parse_date(value), parse_date(bound)) {
+        return Some(value.cmp(&bound));
+    }
+    match (value, bound) {
+        (serde_json::Value::String(value), serde_json::Value::String(bound)) => {
+            Some(value.as_str().cmp(bound.as_str()))
         }
+        _ => None,
     }
+}
 
-    true
+/// Check whether any string in a value (recursing into objects and arrays) satisfies `matches`
+fn any_string(value: &serde_json::Value, matches: &dyn Fn(&str) -> bool) -> bool {
+    match value {
+        serde_json::Value::String(s) => matches(s),
+        serde_json::Value::Object(map) => map.values().any(|v| any_string(v, matches)),
+        serde_json::Value::Array(arr) => arr.iter().any(|v| any_string(v, matches)),
+        _ => false,
+    }
 }
 
 /// Match a field against a wildcard pattern (* matches any sequence, ? matches any single character)
-pub fn wildcard_match(doc: &serde_json::Value, field: &str, pattern: &str) -> bool {
+pub fn wildcard_match(
+    doc: &serde_json::Value,
+    field: &str,
+    pattern: &str,
+    normalization: &TermNormalization,
+) -> bool {
     if pattern.is_empty() {
         return true;
     }
 
-    let field_value = match get_field_value(doc, field) {
-        Some(v) => v,
-        None => return false,
-    };
-
-    let field_str = match field_value {
-        serde_json::Value::String(s) => s.to_lowercase(), // Case-insensitive matching
-        serde_json::Value::Number(_n) => return false, // Wildcard only works on strings
-        serde_json::Value::Bool(_b) => return false,
-        _ => return false,
-    };
-
-    let pattern_lower = pattern.to_lowercase();
+    let pattern = normalization.apply(pattern);
 
     // Convert wildcard pattern to regex
     // * -> .* (matches any sequence)
     // ? -> . (matches any single character)
     // Escape other regex special characters
     let mut regex_pattern = String::new();
-    for c in pattern_lower.chars() {
+    for c in pattern.chars() {
         match c {
             '*' => regex_pattern.push_str(".*"),
             '?' => regex_pattern.push('.'),
@@ -356,36 +502,68 @@ pub fn wildcard_match(doc: &serde_json::Value, field: &str, pattern: &str) -> bo
     // Anchor the pattern to match the entire string
     let full_pattern = format!("^{}$", regex_pattern);
 
-    match Regex::new(&full_pattern) {
-        Ok(re) => re.is_match(&field_str),
-        Err(_) => false, // Invalid regex pattern
+    let re = match Regex::new(&full_pattern) {
+        Ok(re) => re,
+        Err(_) => return false, // Invalid regex pattern
+    };
+
+    // Handle _all field - match any string in the document
+    if field == "_all" || field == "*" {
+        return get_field_value(doc, field)
+            .is_some_and(|values| any_string(values, &|s| re.is_match(&normalization.apply(s))));
+    }
+
+    match get_field_value(doc, field) {
+        // Wildcards only work on strings
+        Some(serde_json::Value::String(s)) => re.is_match(&normalization.apply(s)),
+        _ => false,
     }
 }
 
-/// Match a field against a prefix (case-insensitive)
-pub fn prefix_match(doc: &serde_json::Value, field: &str, prefix: &str) -> bool {
+/// Match a field against a prefix
+pub fn prefix_match(
+    doc: &serde_json::Value,
+    field: &str,
+    prefix: &str,
+    normalization: &TermNormalization,
+) -> bool {
     if prefix.is_empty() {
         return true;
     }
 
+    let prefix = normalization.apply(prefix);
+
+    // Handle _all field - match any string in the document
+    if field == "_all" || field == "*" {
+        return get_field_value(doc, field).is_some_and(|values| {
+            any_string(values, &|s| {
+                normalization.apply(s).starts_with(prefix.as_ref())
+            })
+        });
+    }
+
     let field_value = match get_field_value(doc, field) {
         Some(v) => v,
         None => return false,
     };
 
     let field_str = match field_value {
-        serde_json::Value::String(s) => s.to_lowercase(),
+        serde_json::Value::String(s) => normalization.apply(s).into_owned(),
         serde_json::Value::Number(n) => n.to_string(),
         serde_json::Value::Bool(b) => b.to_string(),
         _ => return false,
     };
 
-    let prefix_lower = prefix.to_lowercase();
-    field_str.starts_with(&prefix_lower)
+    field_str.starts_with(prefix.as_ref())
 }
 
 /// Match a field against any of multiple values
-pub fn terms_match(doc: &serde_json::Value, field: &str, values: &[serde_json::Value]) -> bool {
+pub fn terms_match(
+    doc: &serde_json::Value,
+    field: &str,
+    values: &[serde_json::Value],
+    normalization: &TermNormalization,
+) -> bool {
     if values.is_empty() {
         return true;
     }
@@ -396,11 +574,229 @@ pub fn terms_match(doc: &serde_json::Value, field: &str, values: &[serde_json::V
     };
 
     // Check if field value matches any of the provided values
-    for value in values {
-        if *field_value == *value {
-            return true;
+    values
+        .iter()
+        .any(|value| normalized_equal(field_value, value, normalization))
+}
+
+/// Maximum number of edits allowed by a fuzzy match
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fuzziness {
+    /// Fixed edit distance (0, 1 or 2)
+    Fixed(usize),
+    /// Edit distance derived from term length: 0 below `low`, 1 below `high`, 2 otherwise
+    Auto { low: usize, high: usize },
+}
+
+impl Fuzziness {
+    /// Parse an ES fuzziness value: `"AUTO"`, `"AUTO:3,6"`, `0`, `1`, `2` or a numeric string
+    pub fn parse(value: &serde_json::Value) -> Option<Self> {
+        if let Some(n) = value.as_u64() {
+            return Some(Fuzziness::Fixed(n.min(2) as usize));
         }
+
+        let s = value.as_str()?.trim();
+        if s.eq_ignore_ascii_case("auto") {
+            return Some(Fuzziness::Auto { low: 3, high: 6 });
+        }
+        if let Some(bounds) = s.strip_prefix("AUTO:").or_else(|| s.strip_prefix("auto:")) {
+            let (low, high) = bounds.split_once(',')?;
+            return Some(Fuzziness::Auto {
+                low: low.trim().parse().ok()?,
+                high: high.trim().parse().ok()?,
+            });
+        }
+        s.parse::<f64>()
+            .ok()
+            .map(|n| Fuzziness::Fixed((n.max(0.0) as usize).min(2)))
     }
 
-    false
+    /// Maximum number of edits allowed for a term
+    pub fn max_edits(&self, term: &str) -> usize {
+        match *self {
+            Fuzziness::Fixed(n) => n,
+            Fuzziness::Auto { low, high } => {
+                let len = term.chars().count();
+                if len < low {
+                    0
+                } else if len < high {
+                    1
+                } else {
+                    2
+                }
+            }
+        }
+    }
+}
+
+/// Compute the edit distance between two strings
+///
+/// With `transpositions` enabled, swapping two adjacent characters counts as a single
+/// edit (optimal string alignment / restricted Damerau-Levenshtein distance);
+/// otherwise this is the plain Levenshtein distance.
+pub fn edit_distance(a: &str, b: &str, transpositions: bool) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() {
+        return b.len();
+    }
+    if b.is_empty() {
+        return a.len();
+    }
+
+    // Three rows are enough: the transposition case looks two rows back
+    let mut prev_prev: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut curr: Vec<usize> = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        curr[0] = i;
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            curr[j] = (prev[j] + 1).min(curr[j - 1] + 1).min(prev[j - 1] + cost);
+            if transpositions && i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                curr[j] = curr[j].min(prev_prev[j - 2] + 1);
+            }
+        }
+        std::mem::swap(&mut prev_prev, &mut prev);
+        std::mem::swap(&mut prev, &mut curr);
+    }
+
+    prev[b.len()]
+}
+
+/// Options for fuzzy term matching
+#[derive(Debug, Clone, Copy)]
+pub struct FuzzyOptions {
+    pub fuzziness: Fuzziness,
+    /// Number of leading characters that must match exactly
+    pub prefix_length: usize,
+    /// Count adjacent transpositions as a single edit
+    pub transpositions: bool,
+}
+
+impl Default for FuzzyOptions {
+    fn default() -> Self {
+        Self {
+            fuzziness: Fuzziness::Auto { low: 3, high: 6 },
+            prefix_length: 0,
+            transpositions: true,
+        }
+    }
+}
+
+/// Score how closely a token matches a term (1.0 for exact, lower for more edits)
+fn fuzzy_term_score(token: &str, term: &str, options: &FuzzyOptions) -> Option<f64> {
+    if token == term {
+        return Some(1.0);
+    }
+
+    let prefix: String = term.chars().take(options.prefix_length).collect();
+    if !token.starts_with(&prefix) {
+        return None;
+    }
+
+    let max_edits = options.fuzziness.max_edits(term);
+    if max_edits == 0 {
+        return None;
+    }
+    // Cheap length check before computing the full distance
+    let (token_len, term_len) = (token.chars().count(), term.chars().count());
+    if token_len.abs_diff(term_len) > max_edits {
+        return None;
+    }
+
+    let distance = edit_distance(token, term, options.transpositions);
+    if distance <= max_edits {
+        Some(1.0 - distance as f64 / (term_len.max(token_len) as f64 + 1.0))
+    } else {
+        None
+    }
+}
+
+/// Collect the lowercase tokens of a field value (strings, numbers, and arrays of them)
+fn field_tokens(value: &serde_json::Value, tokens: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(s) => {
+            tokens.extend(s.to_lowercase().split_whitespace().map(|t| t.to_string()))
+        }
+        serde_json::Value::Number(n) => tokens.push(n.to_string()),
+        serde_json::Value::Bool(b) => tokens.push(b.to_string()),
+        serde_json::Value::Array(arr) => {
+            for v in arr {
+                field_tokens(v, tokens);
+            }
+        }
+        serde_json::Value::Object(map) => {
+            for v in map.values() {
+                field_tokens(v, tokens);
+            }
+        }
+        serde_json::Value::Null => {}
+    }
+}
+
+/// Match a field against a single term allowing edits (fuzzy query)
+pub fn fuzzy_match(
+    doc: &serde_json::Value,
+    field: &str,
+    term: &str,
+    options: &FuzzyOptions,
+) -> Option<f64> {
+    // Handle _all field - match the tokens of all fields
+    let field_value = get_field_value(doc, field)?;
+    let mut tokens = Vec::new();
+    field_tokens(field_value, &mut tokens);
+
+    let term_lower = term.to_lowercase();
+    tokens
+        .iter()
+        .filter_map(|token| fuzzy_term_score(token, &term_lower, options))
+        .fold(None, |best: Option<f64>, score| {
+            Some(best.map_or(score, |b| b.max(score)))
+        })
+}
+
+/// Match a field against query text allowing edits per query word (match query with fuzziness)
+///
+/// Each query word is matched against the best field token; the score is the
+/// average word similarity, scaled like a partial word match.
+pub fn match_field_fuzzy(
+    doc: &serde_json::Value,
+    field: &str,
+    query_text: &str,
+    options: &FuzzyOptions,
+) -> Option<f64> {
+    // Exact and substring matches keep their regular (higher) scores
+    if let Some(score) = match_field(doc, field, query_text) {
+        return Some(score);
+    }
+
+    let field_value = get_field_value(doc, field)?;
+    let mut tokens = Vec::new();
+    field_tokens(field_value, &mut tokens);
+
+    let query_lower = query_text.to_lowercase();
+    let words: Vec<&str> = query_lower.split_whitespace().collect();
+    if words.is_empty() {
+        return None;
+    }
+
+    let total: f64 = words
+        .iter()
+        .filter_map(|word| {
+            tokens
+                .iter()
+                .filter_map(|token| fuzzy_term_score(token, word, options))
+                .fold(None, |best: Option<f64>, score| {
+                    Some(best.map_or(score, |b| b.max(score)))
+                })
+        })
+        .sum();
+
+    if total > 0.0 {
+        Some(0.5 * total / words.len() as f64)
+    } else {
+        None
+    }
 }
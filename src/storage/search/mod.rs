@@ -3,12 +3,48 @@
 //! This module contains all search-related logic including query parsing,
 //! document scoring, highlighting, and source filtering.
 
+mod aggregations;
+mod collapse;
+mod date_math;
+mod explain;
+mod fields;
+mod function_score;
 mod highlighting;
+mod inner_hits;
 mod matchers;
+mod multi_fields;
+mod mustache;
+mod normalizer;
+mod percolate;
 mod query;
+mod query_string;
+mod runtime_fields;
+mod script;
+mod sort;
 mod utils;
 
 // Only export functions that are used outside this module
+pub use aggregations::{merge_aggregations, AggregationCounts, Aggregations};
+pub use collapse::Collapse;
+pub use date_math::{
+    date_format, parse_formatted_date, parse_time_zone, resolve_date_math_index_name,
+};
+pub use explain::{explain_document, Explanation};
+pub use fields::{
+    docvalue_field_values, mapped_fields, parse_docvalue_fields, parse_stored_fields,
+    stored_field_values, DocvalueField, StoredFields,
+};
 pub use highlighting::highlight_document;
-pub use query::score_document;
-pub use utils::{compare_documents, filter_source};
+pub use inner_hits::nested_inner_hits;
+pub use multi_fields::MappedFields;
+pub use mustache::render_mustache;
+pub use normalizer::{normalize_query_terms, validate_normalizers};
+pub use percolate::{percolate_document_ref, percolate_queries_mut, percolator_slots};
+pub use query::{query_ids, score_document};
+pub use query_string::{expand_query_strings, expand_query_strings_with};
+pub use runtime_fields::RuntimeFields;
+pub use script::{parse_script_fields, script_field_values, Script};
+pub use sort::{
+    compare_sort_values, parse_sort, sort_values, OwnedTopHits, SortClause, SortValue, TopHits,
+};
+pub use utils::{filter_source, get_field_value, parse_date};
@@ -0,0 +1,176 @@
+//! Multi-fields and `copy_to` in mappings
+//!
+//! A multi-field indexes the values of its parent field once more, usually as
+//! a `keyword` next to a `text` field, for exact matches, sorting and
+//! aggregations:
+//!
+//! ```json
+//! { "title": { "type": "text", "fields": { "keyword": { "type": "keyword" } } } }
+//! ```
+//!
+//! Queries, sorts and aggregations of a search read a multi-field such as
+//! `title.keyword` from its parent field: the field names they refer to are
+//! rewritten once per search (see `MappedFields::resolve`).
+//!
+//! `copy_to` feeds the values of a field into other fields, such as a
+//! catch-all field searched in place of several others:
+//!
+//! ```json
+//! { "first_name": { "type": "text", "copy_to": "full_name" },
+//!   "last_name": { "type": "text", "copy_to": "full_name" } }
+//! ```
+//!
+//! The target holds the values of its sources after its own ones. Like in
+//! Elasticsearch, the `_source` of documents is left as it is: targets are
+//! computed when a search refers to them, like runtime fields are (see
+//! `search::runtime_fields`).
+
+use std::collections::BTreeMap;
+
+/// The multi-fields and `copy_to` targets of a mapping
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MappedFields {
+    /// Parent field of each multi-field, by dotted path
+    parents: BTreeMap<String, String>,
+    /// Fields copied to each `copy_to` target, by dotted path
+    copies: BTreeMap<String, Vec<String>>,
+}
+
+impl MappedFields {
+    /// Collect the multi-fields and `copy_to` targets of mapping properties
+    pub fn of_mapping(properties: Option<&serde_json::Value>) -> Self {
+        let mut fields = Self::default();
+        if let Some(properties) = properties {
+            fields.collect(properties, "");
+        }
+        fields
+    }
+
+    fn collect(&mut self, properties: &serde_json::Value, prefix: &str) {
+        let Some(properties) = properties.as_object() else {
+            return;
+        };
+        for (name, definition) in properties {
+            let path = format!("{}{}", prefix, name);
+            if let Some(multi_fields) = definition.get("fields").and_then(|f| f.as_object()) {
+                for sub_field in multi_fields.keys() {
+                    self.parents
+                        .insert(format!("{}.{}", path, sub_field), path.clone());
+                }
+            }
+            let targets = match definition.get("copy_to") {
+                Some(serde_json::Value::String(target)) => vec![target.as_str()],
+                Some(serde_json::Value::Array(targets)) => {
+                    targets.iter().filter_map(|t| t.as_str()).collect()
+                }
+                _ => Vec::new(),
+            };
+            for target in targets {
+                self.copies
+                    .entry(target.to_string())
+                    .or_default()
+                    .push(path.clone());
+            }
+            if let Some(nested) = definition.get("properties") {
+                self.collect(nested, &format!("{}.", path));
+            }
+        }
+    }
+
+    /// Refer to the parent field wherever a query, sort or aggregation refers
+    /// to a multi-field
+    ///
+    /// Field names are the keys of objects and the values of `field`,
+    /// `fields` and `default_field`, and may carry a `^boost`.
+    pub fn resolve(&self, request: &mut serde_json::Value) {
+        if self.parents.is_empty() {
+            return;
+        }
+        match request {
+            serde_json::Value::Object(map) => {
+                let renamed: Vec<(String, String)> = map
+                    .keys()
+                    .filter_map(|key| Some((key.clone(), self.parent_of(key)?)))
+                    .collect();
+                for (key, parent) in renamed {
+                    if let Some(value) = map.remove(&key) {
+                        map.insert(parent, value);
+                    }
+                }
+                for (key, value) in map.iter_mut() {
+                    match (key.as_str(), value) {
+                        // Documents of percolate queries are not queries
+                        ("document" | "documents", _) => {}
+                        ("field" | "fields" | "default_field", value) => self.resolve_names(value),
+                        (_, value) => self.resolve(value),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.resolve(item);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Refer to the parent fields in a sort specification
+    pub fn resolve_sort(&self, sort: &mut serde_json::Value) {
+        self.resolve_names(sort);
+    }
+
+    /// Field names given as a string or an array of strings
+    fn resolve_names(&self, names: &mut serde_json::Value) {
+        match names {
+            serde_json::Value::String(name) => {
+                if let Some(parent) = self.parent_of(name) {
+                    *name = parent;
+                }
+            }
+            serde_json::Value::Array(items) => {
+                for item in items {
+                    self.resolve_names(item);
+                }
+            }
+            other => self.resolve(other),
+        }
+    }
+
+    /// The field a multi-field name, possibly with a `^boost`, refers to
+    fn parent_of(&self, name: &str) -> Option<String> {
+        let (field, boost) = match name.split_once('^') {
+            Some((field, boost)) => (field, Some(boost)),
+            None => (name, None),
+        };
+        let parent = self.parents.get(field)?;
+        Some(match boost {
+            Some(boost) => format!("{}^{}", parent, boost),
+            None => parent.clone(),
+        })
+    }
+
+    /// The `copy_to` targets a search request refers to, with the fields
+    /// copied to them
+    pub fn copies_in(&self, request: &[Option<&serde_json::Value>]) -> Vec<(String, Vec<String>)> {
+        self.copies
+            .iter()
+            .filter(|(target, _)| request.iter().flatten().any(|part| mentions(part, target)))
+            .map(|(target, sources)| (target.clone(), sources.clone()))
+            .collect()
+    }
+}
+
+/// Check if a key or string of a request names a field, possibly with a
+/// `^boost`
+fn mentions(request: &serde_json::Value, field: &str) -> bool {
+    let names = |name: &str| name.split('^').next() == Some(field);
+    match request {
+        serde_json::Value::String(name) => names(name),
+        serde_json::Value::Object(map) => map
+            .iter()
+            .any(|(key, value)| names(key) || mentions(value, field)),
+        serde_json::Value::Array(items) => items.iter().any(|item| mentions(item, field)),
+        _ => false,
+    }
+}
@@ -0,0 +1,235 @@
+//! Mustache rendering of search templates
+//!
+//! Supports the subset of mustache Elasticsearch search templates use:
+//!
+//! - `{{name}}`: a parameter, with dotted names for nested values and `.` for
+//!   the current section value; strings are JSON-escaped
+//! - `{{{name}}}` and `{{& name}}`: a parameter without escaping
+//! - `{{#name}}...{{/name}}`: rendered once per element of an array, once
+//!   for any other present value that is not `false`, `null` or empty
+//! - `{{^name}}...{{/name}}`: rendered when the value is missing or empty,
+//!   e.g. for defaults: `{{size}}{{^size}}10{{/size}}`
+//! - `{{#toJson}}name{{/toJson}}`: a parameter as JSON
+//! - `{{#join}}name{{/join}}`: the elements of an array joined by `,` (or by
+//!   the `delimiter='...'` of the tag)
+//! - `{{! comment }}`
+
+use crate::error::{GbsError, Result};
+
+/// A parsed template node
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Variable {
+        name: String,
+        escape: bool,
+    },
+    Section {
+        name: String,
+        /// Arguments after the name, e.g. `delimiter='||'`
+        args: String,
+        inverted: bool,
+        /// The unparsed text between the tags
+        raw: String,
+        children: Vec<Node>,
+    },
+}
+
+/// Render a mustache template with parameters
+pub fn render_mustache(template: &str, params: &serde_json::Value) -> Result<String> {
+    let (nodes, rest) = parse(template, None)?;
+    debug_assert!(rest.is_empty());
+    let mut output = String::with_capacity(template.len());
+    render(&nodes, &mut vec![params], &mut output);
+    Ok(output)
+}
+
+fn invalid(reason: String) -> GbsError {
+    GbsError::IllegalArgument(format!("Invalid search template: {}", reason))
+}
+
+/// Parse nodes until the closing tag of `section` (or the end of the input),
+/// returning them with the input after the closing tag
+fn parse<'a>(mut input: &'a str, section: Option<&str>) -> Result<(Vec<Node>, &'a str)> {
+    let mut nodes = Vec::new();
+    loop {
+        let Some(start) = input.find("{{") else {
+            if let Some(name) = section {
+                return Err(invalid(format!("unclosed section [{}]", name)));
+            }
+            if !input.is_empty() {
+                nodes.push(Node::Text(input.to_string()));
+            }
+            return Ok((nodes, ""));
+        };
+        if start > 0 {
+            nodes.push(Node::Text(input[..start].to_string()));
+        }
+        let after_open = &input[start + 2..];
+
+        // Triple mustaches are unescaped variables
+        if let Some(tag) = after_open.strip_prefix('{') {
+            let end = tag
+                .find("}}}")
+                .ok_or_else(|| invalid("unclosed tag [{{{]".to_string()))?;
+            nodes.push(Node::Variable {
+                name: tag[..end].trim().to_string(),
+                escape: false,
+            });
+            input = &tag[end + 3..];
+            continue;
+        }
+
+        let end = after_open
+            .find("}}")
+            .ok_or_else(|| invalid("unclosed tag [{{]".to_string()))?;
+        let tag = after_open[..end].trim();
+        input = &after_open[end + 2..];
+
+        match tag.chars().next() {
+            Some('!') => {}
+            Some('&') => nodes.push(Node::Variable {
+                name: tag[1..].trim().to_string(),
+                escape: false,
+            }),
+            Some(sigil @ ('#' | '^')) => {
+                let tag = tag[1..].trim();
+                let (name, args) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                let body = input;
+                let (children, rest) = parse(input, Some(name))?;
+                // The raw text runs up to the closing tag
+                let consumed = body.len() - rest.len();
+                let close = body[..consumed]
+                    .rfind("{{")
+                    .ok_or_else(|| invalid(format!("unclosed section [{}]", name)))?;
+                nodes.push(Node::Section {
+                    name: name.to_string(),
+                    args: args.trim().to_string(),
+                    inverted: sigil == '^',
+                    raw: body[..close].to_string(),
+                    children,
+                });
+                input = rest;
+            }
+            Some('/') => {
+                let tag = tag[1..].trim();
+                let name = tag.split_whitespace().next().unwrap_or("");
+                return match section {
+                    Some(open) if open == name => Ok((nodes, input)),
+                    Some(open) => Err(invalid(format!("section [{}] closed by [{}]", open, name))),
+                    None => Err(invalid(format!("unopened section [{}] closed", name))),
+                };
+            }
+            _ => nodes.push(Node::Variable {
+                name: tag.to_string(),
+                escape: true,
+            }),
+        }
+    }
+}
+
+fn render<'a>(nodes: &'a [Node], context: &mut Vec<&'a serde_json::Value>, output: &mut String) {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Variable { name, escape } => {
+                if let Some(value) = lookup(context, name) {
+                    push_value(value, *escape, output);
+                }
+            }
+            Node::Section {
+                name,
+                args,
+                inverted,
+                raw,
+                children,
+            } => match (name.as_str(), inverted) {
+                ("toJson", false) => {
+                    let value = lookup(context, raw.trim()).unwrap_or(&serde_json::Value::Null);
+                    output.push_str(&value.to_string());
+                }
+                ("join", false) => {
+                    let delimiter = join_delimiter(args);
+                    match lookup(context, raw.trim()) {
+                        Some(serde_json::Value::Array(values)) => {
+                            for (i, value) in values.iter().enumerate() {
+                                if i > 0 {
+                                    output.push_str(delimiter);
+                                }
+                                push_value(value, true, output);
+                            }
+                        }
+                        Some(value) => push_value(value, true, output),
+                        None => {}
+                    }
+                }
+                (_, true) => {
+                    if !lookup(context, name).is_some_and(is_truthy) {
+                        render(children, context, output);
+                    }
+                }
+                (_, false) => match lookup(context, name) {
+                    Some(serde_json::Value::Array(values)) => {
+                        for value in values {
+                            context.push(value);
+                            render(children, context, output);
+                            context.pop();
+                        }
+                    }
+                    Some(value) if is_truthy(value) => {
+                        context.push(value);
+                        render(children, context, output);
+                        context.pop();
+                    }
+                    _ => {}
+                },
+            },
+        }
+    }
+}
+
+/// Resolve a (dotted) name against the section values, innermost first
+fn lookup<'a>(context: &[&'a serde_json::Value], name: &str) -> Option<&'a serde_json::Value> {
+    if name == "." {
+        return context.last().copied();
+    }
+    let mut segments = name.split('.');
+    let first = segments.next()?;
+    let mut value = context.iter().rev().find_map(|scope| scope.get(first))?;
+    for segment in segments {
+        value = match value {
+            serde_json::Value::Array(values) => values.get(segment.parse::<usize>().ok()?)?,
+            value => value.get(segment)?,
+        };
+    }
+    Some(value)
+}
+
+fn is_truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null | serde_json::Value::Bool(false) => false,
+        serde_json::Value::String(s) => !s.is_empty(),
+        serde_json::Value::Array(values) => !values.is_empty(),
+        _ => true,
+    }
+}
+
+fn push_value(value: &serde_json::Value, escape: bool, output: &mut String) {
+    match value {
+        serde_json::Value::Null => {}
+        serde_json::Value::String(s) if escape => {
+            // The JSON string literal without its quotes
+            let quoted = serde_json::Value::String(s.clone()).to_string();
+            output.push_str(&quoted[1..quoted.len() - 1]);
+        }
+        serde_json::Value::String(s) => output.push_str(s),
+        value => output.push_str(&value.to_string()),
+    }
+}
+
+/// Delimiter of a `join` section: `delimiter='...'`, `,` by default
+fn join_delimiter(args: &str) -> &str {
+    args.strip_prefix("delimiter=")
+        .map(|d| d.trim_matches(|c| c == '\'' || c == '"'))
+        .unwrap_or(",")
+}
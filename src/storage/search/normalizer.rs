@@ -0,0 +1,369 @@
+//! Keyword normalizers and case-insensitive term-level queries
+//!
+//! A `keyword` field mapped with a `normalizer` is compared by normalized
+//! value in `term`, `terms`, `prefix` and `wildcard` queries: the query value
+//! and the values of documents are normalized the same way. The built-in
+//! `lowercase` normalizer is always available; others are defined in the
+//! index settings as a list of filters:
+//!
+//! ```json
+//! { "settings": { "analysis": { "normalizer": {
+//!       "folded": { "type": "custom", "filter": ["lowercase", "asciifolding"] }
+//!   } } },
+//!   "mappings": { "properties": {
+//!       "city": { "type": "keyword", "normalizer": "folded" }
+//!   } } }
+//! ```
+//!
+//! Filters are `lowercase`, `uppercase`, `asciifolding` (accented Latin
+//! letters to their ASCII base letters) and `trim`.
+//!
+//! Whatever the mapping, `term`, `prefix` and `wildcard` queries take
+//! `case_insensitive: true` to ignore case. `prefix` and `wildcard` queries
+//! ignore case by default; `case_insensitive: false` makes them exact.
+//!
+//! Searches rewrite the term-level queries of normalized fields once (see
+//! `normalize_query_terms`): the value is normalized and the filters are
+//! passed to the matchers under `normalizer`, so `terms` queries of such
+//! fields take the form `{"field": {"values": [...], "normalizer": [...]}}`.
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+
+use super::fields::mapped_fields;
+use crate::error::{GbsError, Result};
+use crate::storage::settings::setting_group;
+
+/// Settings group holding the normalizers of an index
+const NORMALIZER_SETTING: &str = "index.analysis.normalizer";
+
+/// One step of a normalizer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Filter {
+    Lowercase,
+    Uppercase,
+    Asciifolding,
+    Trim,
+}
+
+impl Filter {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "lowercase" => Some(Self::Lowercase),
+            "uppercase" => Some(Self::Uppercase),
+            "asciifolding" => Some(Self::Asciifolding),
+            "trim" => Some(Self::Trim),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Lowercase => "lowercase",
+            Self::Uppercase => "uppercase",
+            Self::Asciifolding => "asciifolding",
+            Self::Trim => "trim",
+        }
+    }
+
+    fn apply(self, text: &str) -> String {
+        match self {
+            Self::Lowercase => text.to_lowercase(),
+            Self::Uppercase => text.to_uppercase(),
+            Self::Asciifolding => {
+                let mut folded = String::with_capacity(text.len());
+                for c in text.chars() {
+                    match fold_ligature(c) {
+                        Some(letters) => folded.push_str(letters),
+                        None => folded.push(fold_to_ascii(c)),
+                    }
+                }
+                folded
+            }
+            Self::Trim => text.trim().to_string(),
+        }
+    }
+}
+
+/// How a term-level query compares strings: through a normalizer and,
+/// with `case_insensitive`, ignoring case
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TermNormalization {
+    filters: Vec<Filter>,
+    case_insensitive: bool,
+}
+
+impl TermNormalization {
+    /// Read the normalization of a field in a term-level query from its
+    /// options (`{"value": ..., "case_insensitive": true}`), ignoring case by
+    /// default if `case_insensitive` is set
+    pub fn of(options: &serde_json::Value, case_insensitive: bool) -> Self {
+        let filters = options
+            .get("normalizer")
+            .and_then(|filters| filters.as_array())
+            .map(|filters| {
+                filters
+                    .iter()
+                    .filter_map(|name| Filter::parse(name.as_str()?))
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            filters,
+            case_insensitive: options
+                .get("case_insensitive")
+                .and_then(|flag| flag.as_bool())
+                .unwrap_or(case_insensitive),
+        }
+    }
+
+    /// Check if strings are compared as they are
+    pub fn is_exact(&self) -> bool {
+        self.filters.is_empty() && !self.case_insensitive
+    }
+
+    /// A string as the query compares it
+    pub fn apply<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        let mut text = Cow::Borrowed(text);
+        for filter in &self.filters {
+            text = Cow::Owned(filter.apply(&text));
+        }
+        if self.case_insensitive {
+            text = Cow::Owned(text.to_lowercase());
+        }
+        text
+    }
+}
+
+/// The normalizers of an index by name: `lowercase` and those defined in
+/// its settings
+fn index_normalizers(settings: Option<&serde_json::Value>) -> Result<HashMap<String, Vec<Filter>>> {
+    let mut normalizers = HashMap::from([("lowercase".to_string(), vec![Filter::Lowercase])]);
+    let Some(settings) = settings else {
+        return Ok(normalizers);
+    };
+    for (name, definition) in setting_group(settings, NORMALIZER_SETTING) {
+        let invalid = |reason: String| {
+            GbsError::InvalidRequest(format!("Invalid normalizer [{}]: {}", name, reason))
+        };
+        if let Some(kind) = definition.get("type") {
+            if kind != "custom" {
+                return Err(invalid(format!(
+                    "unsupported type {}, expected [custom]",
+                    kind
+                )));
+            }
+        }
+        if definition.get("char_filter").is_some() {
+            return Err(invalid("[char_filter] is not supported".to_string()));
+        }
+        let filters = match definition.get("filter") {
+            None => Vec::new(),
+            Some(serde_json::Value::String(filter)) => vec![filter.as_str()],
+            Some(serde_json::Value::Array(filters)) => filters
+                .iter()
+                .map(|filter| {
+                    filter
+                        .as_str()
+                        .ok_or_else(|| invalid("[filter] must list filter names".to_string()))
+                })
+                .collect::<Result<_>>()?,
+            Some(_) => return Err(invalid("[filter] must list filter names".to_string())),
+        };
+        let filters = filters
+            .into_iter()
+            .map(|filter| {
+                Filter::parse(filter).ok_or_else(|| {
+                    invalid(format!(
+                        "unsupported filter [{}], expected [lowercase], [uppercase], [asciifolding] or [trim]",
+                        filter
+                    ))
+                })
+            })
+            .collect::<Result<_>>()?;
+        normalizers.insert(name, filters);
+    }
+    Ok(normalizers)
+}
+
+/// The filters of the normalized fields of an index, by dotted path
+fn normalized_fields(
+    settings: Option<&serde_json::Value>,
+    properties: Option<&serde_json::Value>,
+) -> Result<BTreeMap<String, Vec<Filter>>> {
+    let normalizers = index_normalizers(settings)?;
+    let mut fields = BTreeMap::new();
+    for (path, field) in mapped_fields(properties) {
+        let Some(name) = field.normalizer else {
+            continue;
+        };
+        if field.field_type != "keyword" {
+            return Err(GbsError::InvalidRequest(format!(
+                "Field [{}] of type [{}] cannot have a normalizer, only [keyword] fields can",
+                path, field.field_type
+            )));
+        }
+        let filters = normalizers.get(&name).ok_or_else(|| {
+            GbsError::InvalidRequest(format!(
+                "normalizer [{}] not found for field [{}]",
+                name, path
+            ))
+        })?;
+        fields.insert(path, filters.clone());
+    }
+    Ok(fields)
+}
+
+/// Check the normalizers of index settings and the fields of mapping
+/// properties using them
+pub fn validate_normalizers(
+    settings: Option<&serde_json::Value>,
+    properties: Option<&serde_json::Value>,
+) -> Result<()> {
+    normalized_fields(settings, properties).map(|_| ())
+}
+
+/// Rewrite the `term`, `terms`, `prefix` and `wildcard` queries of
+/// normalized fields for the matchers, `None` if the index has no such field
+pub fn normalize_query_terms(
+    query: &serde_json::Value,
+    settings: Option<&serde_json::Value>,
+    properties: Option<&serde_json::Value>,
+) -> Result<Option<serde_json::Value>> {
+    let fields = normalized_fields(settings, properties)?;
+    if fields.is_empty() {
+        return Ok(None);
+    }
+    let mut query = query.clone();
+    rewrite(&mut query, &fields);
+    Ok(Some(query))
+}
+
+fn rewrite(query: &mut serde_json::Value, fields: &BTreeMap<String, Vec<Filter>>) {
+    match query {
+        serde_json::Value::Object(map) => {
+            for (key, child) in map.iter_mut() {
+                match key.as_str() {
+                    "term" | "prefix" | "wildcard" => rewrite_fields(child, fields, false),
+                    "terms" => rewrite_fields(child, fields, true),
+                    // Documents of percolate queries are not queries
+                    "document" | "documents" => {}
+                    _ => rewrite(child, fields),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => {
+            for item in items {
+                rewrite(item, fields);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Normalize the values of the normalized fields of a term-level query
+fn rewrite_fields(
+    body: &mut serde_json::Value,
+    fields: &BTreeMap<String, Vec<Filter>>,
+    several: bool,
+) {
+    let Some(body) = body.as_object_mut() else {
+        return;
+    };
+    for (field, options) in body.iter_mut() {
+        let Some(filters) = fields.get(field) else {
+            continue;
+        };
+        let normalization = TermNormalization {
+            filters: filters.clone(),
+            case_insensitive: false,
+        };
+        let normalize = |value: &serde_json::Value| match value {
+            serde_json::Value::String(text) => {
+                serde_json::Value::String(normalization.apply(text).into_owned())
+            }
+            value => value.clone(),
+        };
+        let filter_names: Vec<&str> = filters.iter().map(|filter| filter.as_str()).collect();
+        let rewritten = if several {
+            let Some(values) = options.as_array() else {
+                continue;
+            };
+            serde_json::json!({
+                "values": values.iter().map(normalize).collect::<Vec<_>>(),
+                "normalizer": filter_names
+            })
+        } else {
+            let mut rewritten = match &*options {
+                serde_json::Value::Object(options) => options.clone(),
+                value => serde_json::Map::from_iter([("value".to_string(), value.clone())]),
+            };
+            if let Some(value) = rewritten.get("value") {
+                let value = normalize(value);
+                rewritten.insert("value".to_string(), value);
+            }
+            rewritten.insert("normalizer".to_string(), serde_json::json!(filter_names));
+            serde_json::Value::Object(rewritten)
+        };
+        *options = rewritten;
+    }
+}
+
+/// The ASCII letters a Latin ligature folds to
+fn fold_ligature(c: char) -> Option<&'static str> {
+    match c {
+        'Æ' => Some("AE"),
+        'æ' => Some("ae"),
+        'Œ' => Some("OE"),
+        'œ' => Some("oe"),
+        'Þ' => Some("TH"),
+        'þ' => Some("th"),
+        'ß' => Some("ss"),
+        _ => None,
+    }
+}
+
+/// The ASCII letter an accented Latin letter folds to
+fn fold_to_ascii(c: char) -> char {
+    match c {
+        'À'..='Å' | 'Ā' | 'Ă' | 'Ą' => 'A',
+        'à'..='å' | 'ā' | 'ă' | 'ą' => 'a',
+        'Ç' | 'Ć' | 'Ĉ' | 'Ċ' | 'Č' => 'C',
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => 'c',
+        'Ð' | 'Ď' | 'Đ' => 'D',
+        'ð' | 'ď' | 'đ' => 'd',
+        'È'..='Ë' | 'Ē' | 'Ĕ' | 'Ė' | 'Ę' | 'Ě' => 'E',
+        'è'..='ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => 'e',
+        'Ĝ' | 'Ğ' | 'Ġ' | 'Ģ' => 'G',
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => 'g',
+        'Ĥ' | 'Ħ' => 'H',
+        'ĥ' | 'ħ' => 'h',
+        'Ì'..='Ï' | 'Ĩ' | 'Ī' | 'Ĭ' | 'Į' | 'İ' => 'I',
+        'ì'..='ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => 'i',
+        'Ĵ' => 'J',
+        'ĵ' => 'j',
+        'Ķ' => 'K',
+        'ķ' => 'k',
+        'Ĺ' | 'Ļ' | 'Ľ' | 'Ŀ' | 'Ł' => 'L',
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => 'l',
+        'Ñ' | 'Ń' | 'Ņ' | 'Ň' => 'N',
+        'ñ' | 'ń' | 'ņ' | 'ň' => 'n',
+        'Ò'..='Ö' | 'Ø' | 'Ō' | 'Ŏ' | 'Ő' => 'O',
+        'ò'..='ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => 'o',
+        'Ŕ' | 'Ŗ' | 'Ř' => 'R',
+        'ŕ' | 'ŗ' | 'ř' => 'r',
+        'Ś' | 'Ŝ' | 'Ş' | 'Š' => 'S',
+        'ś' | 'ŝ' | 'ş' | 'š' => 's',
+        'Ţ' | 'Ť' | 'Ŧ' => 'T',
+        'ţ' | 'ť' | 'ŧ' => 't',
+        'Ù'..='Ü' | 'Ũ' | 'Ū' | 'Ŭ' | 'Ů' | 'Ű' | 'Ų' => 'U',
+        'ù'..='ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => 'u',
+        'Ŵ' => 'W',
+        'ŵ' => 'w',
+        'Ý' | 'Ŷ' | 'Ÿ' => 'Y',
+        'ý' | 'ÿ' | 'ŷ' => 'y',
+        'Ź' | 'Ż' | 'Ž' => 'Z',
+        'ź' | 'ż' | 'ž' => 'z',
+        c => c,
+    }
+}
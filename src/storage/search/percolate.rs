@@ -0,0 +1,162 @@
+//! Percolate queries: match documents against stored queries
+//!
+//! Queries are registered as documents of an index whose mappings have a
+//! `percolator` field, e.g. `{"query": {"match": {"message": "error"}}}` with
+//! `"query": {"type": "percolator"}`. A `percolate` query matches the stored
+//! queries that match candidate documents:
+//!
+//! ```json
+//! { "percolate": { "field": "query", "document": { "message": "disk error" } } }
+//! ```
+//!
+//! Candidates are given as `document`, as `documents` (matched hits list the
+//! positions of the documents they match in `_percolator_document_slot`), or
+//! by `index` and `id` of a stored document, which the search resolves to
+//! `document` before scoring.
+
+use crate::error::{GbsError, Result};
+
+use super::query::score_document;
+use super::query_string::expand_query_strings;
+use super::utils::get_field_value;
+
+/// Score a stored query document against the candidates of a percolate query
+///
+/// The score is the best score the stored query gives a candidate, `None`
+/// if it matches none.
+pub(super) fn score_percolate_query(
+    doc: &serde_json::Value,
+    percolate: &serde_json::Value,
+) -> Result<Option<f64>> {
+    let scores = candidate_scores(doc, percolate)?;
+    Ok(scores.into_iter().flatten().reduce(f64::max))
+}
+
+/// Positions of the candidates of the first percolate query in `query` that
+/// the stored query document `doc` matches
+///
+/// `None` if the query has no percolate query.
+pub fn percolator_slots(
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Option<Vec<usize>>> {
+    let Some(percolate) = find_percolate_query(query) else {
+        return Ok(None);
+    };
+    let slots = candidate_scores(doc, percolate)?
+        .into_iter()
+        .enumerate()
+        .filter(|(_, score)| score.is_some())
+        .map(|(slot, _)| slot)
+        .collect();
+    Ok(Some(slots))
+}
+
+/// Bodies of the percolate queries in a query, for the search to fill in
+/// candidates given by `index` and `id`
+///
+/// Looks through `bool` clauses.
+pub fn percolate_queries_mut(
+    query: &mut serde_json::Value,
+) -> Vec<&mut serde_json::Map<String, serde_json::Value>> {
+    let Some(query_obj) = query.as_object_mut() else {
+        return Vec::new();
+    };
+    let mut found = Vec::new();
+    for (kind, body) in query_obj.iter_mut() {
+        match kind.as_str() {
+            "percolate" => found.extend(body.as_object_mut()),
+            "bool" => {
+                let Some(bool_obj) = body.as_object_mut() else {
+                    continue;
+                };
+                for (occur, clauses) in bool_obj.iter_mut() {
+                    if !matches!(occur.as_str(), "must" | "should" | "must_not" | "filter") {
+                        continue;
+                    }
+                    match clauses {
+                        serde_json::Value::Array(clauses) => {
+                            for clause in clauses {
+                                found.extend(percolate_queries_mut(clause));
+                            }
+                        }
+                        clause => found.extend(percolate_queries_mut(clause)),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+/// The stored document a percolate query takes its candidate from, if it
+/// names one by `index` and `id` instead of giving it inline
+pub fn percolate_document_ref(
+    percolate: &serde_json::Map<String, serde_json::Value>,
+) -> Option<(String, String)> {
+    if percolate.contains_key("document") || percolate.contains_key("documents") {
+        return None;
+    }
+    let index = percolate.get("index")?.as_str()?;
+    let id = percolate.get("id")?.as_str()?;
+    Some((index.to_string(), id.to_string()))
+}
+
+/// Scores the stored query of `doc` gives each candidate of a percolate
+/// query, `None` for candidates it does not match
+fn candidate_scores(
+    doc: &serde_json::Value,
+    percolate: &serde_json::Value,
+) -> Result<Vec<Option<f64>>> {
+    let field = percolate
+        .get("field")
+        .and_then(|f| f.as_str())
+        .ok_or_else(|| {
+            GbsError::InvalidRequest("[percolate] query requires a [field]".to_string())
+        })?;
+    let candidates: Vec<&serde_json::Value> =
+        match (percolate.get("document"), percolate.get("documents")) {
+            (Some(document), _) => vec![document],
+            (None, Some(serde_json::Value::Array(documents))) => documents.iter().collect(),
+            _ => {
+                return Err(GbsError::InvalidRequest(
+                    "[percolate] query requires a [document], [documents] or an [index] and [id]"
+                        .to_string(),
+                ))
+            }
+        };
+
+    // Documents without a stored query match nothing
+    let Some(stored_query) = get_field_value(doc, field).filter(|q| q.is_object()) else {
+        return Ok(vec![None; candidates.len()]);
+    };
+    let stored_query = expand_query_strings(stored_query)?;
+    candidates
+        .into_iter()
+        .map(|candidate| score_document("", candidate, &stored_query))
+        .collect()
+}
+
+/// The first percolate query of a query, looking through `bool` clauses
+fn find_percolate_query(query: &serde_json::Value) -> Option<&serde_json::Value> {
+    let query_obj = query.as_object()?;
+    if let Some(percolate) = query_obj.get("percolate") {
+        return Some(percolate);
+    }
+    if let Some(bool_obj) = query_obj.get("bool").and_then(|b| b.as_object()) {
+        for occur in ["must", "filter", "should"] {
+            let found = match bool_obj.get(occur) {
+                Some(serde_json::Value::Array(clauses)) => {
+                    clauses.iter().find_map(find_percolate_query)
+                }
+                Some(clause) => find_percolate_query(clause),
+                None => None,
+            };
+            if found.is_some() {
+                return found;
+            }
+        }
+    }
+    None
+}
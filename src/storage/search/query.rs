@@ -1,10 +1,76 @@
 //! Query parsing and scoring
 
-use crate::error::Result;
+use super::function_score::score_function_score_query;
 use super::matchers::*;
+use super::normalizer::TermNormalization;
+use super::percolate::score_percolate_query;
+use super::utils::get_field_value;
+use crate::error::{GbsError, Result};
 
-/// Score a document against a query
-pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Result<f64> {
+/// Score a document against a query, `None` if it does not match
+///
+/// A matching document may score 0, as one matching only the filters of a
+/// bool query with should clauses does. A `boost` on the query (e.g.
+/// `{ "term": { "tag": { "value": "x", "boost": 2 } } }` or
+/// `{ "bool": { ..., "boost": 2 } }`) multiplies the score of a match.
+pub fn score_document(
+    id: &str,
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Option<f64>> {
+    let score = score_query(id, doc, query)?;
+    Ok(match query_boost(query) {
+        Some(boost) => score.map(|score| score * boost),
+        None => score,
+    })
+}
+
+/// Read the `boost` of a query, either on the query body or on its field options
+pub(super) fn query_boost(query: &serde_json::Value) -> Option<f64> {
+    let (_, body) = query.as_object()?.iter().next()?;
+    if let Some(boost) = body.get("boost") {
+        return boost.as_f64();
+    }
+    body.as_object()?
+        .values()
+        .find_map(|options| options.get("boost").and_then(|b| b.as_f64()))
+}
+
+/// Score a document against a query, ignoring its boost
+pub(super) fn score_query(
+    id: &str,
+    doc: &serde_json::Value,
+    query: &serde_json::Value,
+) -> Result<Option<f64>> {
+    if let Some(query_obj) = query.as_object() {
+        // Handle percolate query: { "percolate": { "field": "query", "document": { ... } } }
+        if let Some(percolate_query) = query_obj.get("percolate") {
+            return score_percolate_query(doc, percolate_query);
+        }
+
+        // Handle nested query: { "nested": { "path": "comments", "query": { ... } } }
+        if let Some(nested_query) = query_obj.get("nested") {
+            return score_nested_query(id, doc, nested_query);
+        }
+
+        // Handle function_score query: { "function_score": { "query": { ... }, "functions": [ ... ] } }
+        if let Some(function_score) = query_obj.get("function_score") {
+            return score_function_score_query(id, doc, function_score);
+        }
+
+        // Handle bool query
+        if let Some(bool_query) = query_obj.get("bool") {
+            return score_bool_query(id, doc, bool_query);
+        }
+    }
+
+    let score = score_leaf_query(id, doc, query)?;
+    Ok((score > 0.0).then_some(score))
+}
+
+/// Score a document against a query that is not made of other queries,
+/// 0 if it does not match
+fn score_leaf_query(id: &str, doc: &serde_json::Value, query: &serde_json::Value) -> Result<f64> {
     if let Some(query_obj) = query.as_object() {
         // Handle match_all query (no query or empty query)
         if query_obj.is_empty() {
@@ -21,7 +87,43 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
                         query_value.as_str().unwrap_or("")
                     };
 
-                    if let Some(score) = match_field(doc, field, query_text) {
+                    // Typo-tolerant matching: { "match": { "field": { "query": "...", "fuzziness": "AUTO" } } }
+                    let fuzziness = query_value.get("fuzziness").and_then(Fuzziness::parse);
+                    let score = match fuzziness {
+                        Some(fuzziness) => {
+                            let options = fuzzy_options(query_value, fuzziness);
+                            match_field_fuzzy(doc, field, query_text, &options)
+                        }
+                        None if is_and_operator(query_value)
+                            && !all_terms_match(doc, field, query_text) =>
+                        {
+                            None
+                        }
+                        None => match_field(doc, field, query_text),
+                    };
+
+                    if let Some(score) = score {
+                        return Ok(score);
+                    }
+                }
+            }
+        }
+
+        // Handle fuzzy query: { "fuzzy": { "field": { "value": "ki", "fuzziness": 2 } } }
+        if let Some(fuzzy_query) = query_obj.get("fuzzy") {
+            if let Some(fuzzy_obj) = fuzzy_query.as_object() {
+                for (field, fuzzy_value) in fuzzy_obj {
+                    let (term, fuzziness) = if let Some(f) = fuzzy_value.as_object() {
+                        let term = f.get("value").and_then(|v| v.as_str()).unwrap_or("");
+                        let fuzziness = f.get("fuzziness").and_then(Fuzziness::parse);
+                        (term, fuzziness)
+                    } else {
+                        (fuzzy_value.as_str().unwrap_or(""), None)
+                    };
+                    let fuzziness = fuzziness.unwrap_or(FuzzyOptions::default().fuzziness);
+
+                    let options = fuzzy_options(fuzzy_value, fuzziness);
+                    if let Some(score) = fuzzy_match(doc, field, term, &options) {
                         return Ok(score);
                     }
                 }
@@ -48,13 +150,15 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
         // Handle multi_match query: { "multi_match": { "query": "text", "fields": ["field1", "field2"] } }
         if let Some(multi_match_query) = query_obj.get("multi_match") {
             if let Some(multi_match_obj) = multi_match_query.as_object() {
-                let query_text = multi_match_obj.get("query")
+                let query_text = multi_match_obj
+                    .get("query")
                     .and_then(|v| v.as_str())
                     .unwrap_or("");
 
                 let fields = if let Some(fields_val) = multi_match_obj.get("fields") {
                     if let Some(fields_array) = fields_val.as_array() {
-                        fields_array.iter()
+                        fields_array
+                            .iter()
                             .filter_map(|f| f.as_str())
                             .collect::<Vec<_>>()
                     } else if let Some(field_str) = fields_val.as_str() {
@@ -66,7 +170,9 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
                     vec!["_all"]
                 };
 
-                if let Some(score) = multi_match_fields(doc, &fields, query_text) {
+                let require_all_terms = is_and_operator(multi_match_query);
+                if let Some(score) = multi_match_fields(doc, &fields, query_text, require_all_terms)
+                {
                     return Ok(score);
                 }
             }
@@ -77,7 +183,7 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
             if let Some(range_obj) = range_query.as_object() {
                 for (field, range_spec) in range_obj {
                     if let Some(range_params) = range_spec.as_object() {
-                        if range_match(doc, field, range_params) {
+                        if range_match(doc, field, range_params)? {
                             return Ok(1.0);
                         }
                     }
@@ -85,11 +191,29 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
             }
         }
 
+        // Handle ids query: { "ids": { "values": ["1", "2"] } }
+        if let Some(ids_query) = query_obj.get("ids") {
+            let values = ids_query.get("values").and_then(|v| v.as_array());
+            if values.is_some_and(|values| values.iter().any(|v| id_matches(id, v))) {
+                return Ok(1.0);
+            }
+        }
+
         // Handle term query: { "term": { "field": "value" } }
         if let Some(term_query) = query_obj.get("term") {
             if let Some(term_obj) = term_query.as_object() {
-                for (field, value) in term_obj {
-                    if term_match(doc, field, value) {
+                for (field, options) in term_obj {
+                    // { "term": { "field": "x" } } or { "term": { "field": { "value": "x" } } }
+                    let value = options
+                        .as_object()
+                        .and_then(|v| v.get("value"))
+                        .unwrap_or(options);
+                    let matched = if field == "_id" {
+                        id_matches(id, value)
+                    } else {
+                        term_match(doc, field, value, &TermNormalization::of(options, false))
+                    };
+                    if matched {
                         return Ok(1.0);
                     }
                 }
@@ -100,8 +224,17 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
         if let Some(terms_query) = query_obj.get("terms") {
             if let Some(terms_obj) = terms_query.as_object() {
                 for (field, values) in terms_obj {
+                    // Values of normalized fields come with their normalizer
+                    // (see `search::normalizer`)
+                    let normalization = TermNormalization::of(values, false);
+                    let values = values.get("values").unwrap_or(values);
                     if let Some(values_array) = values.as_array() {
-                        if terms_match(doc, field, values_array) {
+                        let matched = if field == "_id" {
+                            values_array.iter().any(|v| id_matches(id, v))
+                        } else {
+                            terms_match(doc, field, values_array, &normalization)
+                        };
+                        if matched {
                             return Ok(1.0);
                         }
                     }
@@ -118,7 +251,12 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
                     } else {
                         prefix_value.as_str().unwrap_or("")
                     };
-                    if prefix_match(doc, field, prefix_str) {
+                    if prefix_match(
+                        doc,
+                        field,
+                        prefix_str,
+                        &TermNormalization::of(prefix_value, true),
+                    ) {
                         return Ok(1.0);
                     }
                 }
@@ -134,18 +272,18 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
                     } else {
                         pattern_value.as_str().unwrap_or("")
                     };
-                    if wildcard_match(doc, field, pattern_str) {
+                    if wildcard_match(
+                        doc,
+                        field,
+                        pattern_str,
+                        &TermNormalization::of(pattern_value, true),
+                    ) {
                         return Ok(1.0);
                     }
                 }
             }
         }
 
-        // Handle bool query
-        if let Some(bool_query) = query_obj.get("bool") {
-            return score_bool_query(doc, bool_query);
-        }
-
         // Handle match_all query: { "match_all": {} }
         if query_obj.contains_key("match_all") {
             return Ok(1.0);
@@ -156,69 +294,273 @@ pub fn score_document(doc: &serde_json::Value, query: &serde_json::Value) -> Res
     Ok(0.0)
 }
 
-/// Score a bool query
-pub fn score_bool_query(doc: &serde_json::Value, bool_query: &serde_json::Value) -> Result<f64> {
-    if let Some(bool_obj) = bool_query.as_object() {
-        let mut score = 0.0;
-        let mut must_match = true;
-
-        // Handle must clauses (all must match)
-        if let Some(must) = bool_obj.get("must") {
-            if let Some(must_array) = must.as_array() {
-                for clause in must_array {
-                    let clause_score = score_document(doc, clause)?;
-                    if clause_score == 0.0 {
-                        must_match = false;
-                        break;
-                    }
-                    score += clause_score;
-                }
-            }
+/// Document IDs a query is restricted to, if it only selects by `_id`
+///
+/// Recognizes top-level `ids` queries and `term`/`terms` queries on `_id`, which
+/// can be answered by looking the IDs up instead of scanning every document.
+pub fn query_ids(query: &serde_json::Value) -> Option<Vec<String>> {
+    let query_obj = query.as_object()?;
+    if query_obj.len() != 1 {
+        return None;
+    }
+
+    let values: Vec<serde_json::Value> = if let Some(ids_query) = query_obj.get("ids") {
+        ids_query.get("values")?.as_array()?.clone()
+    } else if let Some(term_query) = query_obj.get("term") {
+        let value = term_query
+            .as_object()
+            .filter(|t| t.len() == 1)?
+            .get("_id")?;
+        vec![value.get("value").unwrap_or(value).clone()]
+    } else if let Some(terms_query) = query_obj.get("terms") {
+        terms_query
+            .as_object()
+            .filter(|t| t.len() == 1)?
+            .get("_id")?
+            .as_array()?
+            .clone()
+    } else {
+        return None;
+    };
+
+    let mut ids: Vec<String> = Vec::new();
+    for value in values {
+        let id = match value {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Number(n) => n.to_string(),
+            _ => continue,
+        };
+        if !ids.contains(&id) {
+            ids.push(id);
         }
+    }
+    Some(ids)
+}
+
+/// Check if a full-text query requires all terms (`"operator": "and"`)
+fn is_and_operator(query_value: &serde_json::Value) -> bool {
+    query_value
+        .get("operator")
+        .and_then(|v| v.as_str())
+        .is_some_and(|op| op.eq_ignore_ascii_case("and"))
+}
+
+/// Read fuzzy matching options (`prefix_length`, `transpositions`) from a query object
+fn fuzzy_options(query_value: &serde_json::Value, fuzziness: Fuzziness) -> FuzzyOptions {
+    let defaults = FuzzyOptions::default();
+    FuzzyOptions {
+        fuzziness,
+        prefix_length: query_value
+            .get("prefix_length")
+            .and_then(|v| v.as_u64())
+            .map(|v| v as usize)
+            .unwrap_or(defaults.prefix_length),
+        transpositions: query_value
+            .get("transpositions")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(defaults.transpositions),
+    }
+}
+
+/// Score a nested query
+///
+/// Each object under `path` is scored on its own, so all conditions of the inner
+/// query must hold for the same array element. Element scores are combined
+/// according to `score_mode` (avg, max, min, sum or none; default avg).
+pub fn score_nested_query(
+    id: &str,
+    doc: &serde_json::Value,
+    nested_query: &serde_json::Value,
+) -> Result<Option<f64>> {
+    let (path, inner_query, score_mode) = nested_query_parts(nested_query)?;
 
-        if !must_match {
-            return Ok(0.0);
+    let mut scores = Vec::new();
+    for element_doc in nested_element_docs(doc, path) {
+        if let Some(score) = score_document(id, &element_doc, inner_query)? {
+            scores.push(score);
         }
+    }
 
-        // Handle should clauses (at least one should match, or boost score)
-        if let Some(should) = bool_obj.get("should") {
-            if let Some(should_array) = should.as_array() {
-                let mut should_score = 0.0;
-                for clause in should_array {
-                    should_score += score_document(doc, clause)?;
-                }
-                if should_score > 0.0 {
-                    score += should_score * 0.5; // Boost for should matches
-                }
-            }
+    Ok((!scores.is_empty()).then(|| combine_nested_scores(score_mode, &scores)))
+}
+
+/// Path, inner query and score mode of a nested query
+pub(super) fn nested_query_parts(
+    nested_query: &serde_json::Value,
+) -> Result<(&str, &serde_json::Value, &str)> {
+    let path = nested_query
+        .get("path")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| GbsError::InvalidRequest("[nested] requires 'path' field".to_string()))?;
+    let inner_query = nested_query
+        .get("query")
+        .ok_or_else(|| GbsError::InvalidRequest("[nested] requires 'query' field".to_string()))?;
+    let score_mode = nested_query
+        .get("score_mode")
+        .and_then(|v| v.as_str())
+        .unwrap_or("avg");
+    Ok((path, inner_query, score_mode))
+}
+
+/// Combine the scores of the matching elements of a nested query
+pub(super) fn combine_nested_scores(score_mode: &str, scores: &[f64]) -> f64 {
+    if scores.is_empty() {
+        return 0.0;
+    }
+    match score_mode {
+        "max" => scores.iter().cloned().fold(f64::MIN, f64::max),
+        "min" => scores.iter().cloned().fold(f64::MAX, f64::min),
+        "sum" => scores.iter().sum(),
+        "none" => 1.0,
+        _ => scores.iter().sum::<f64>() / scores.len() as f64,
+    }
+}
+
+/// The objects under a nested `path`, each as a document of its own
+///
+/// Inner queries use full field paths (e.g. "comments.author"), so each
+/// element becomes a document holding only that element under `path`.
+pub(super) fn nested_element_docs(doc: &serde_json::Value, path: &str) -> Vec<serde_json::Value> {
+    nested_elements(doc, path)
+        .into_iter()
+        .map(|(_, _, element_doc)| element_doc)
+        .collect()
+}
+
+/// The objects under a nested `path` with their position in the array, as
+/// they are and as documents of their own (see `nested_element_docs`)
+pub(super) fn nested_elements(
+    doc: &serde_json::Value,
+    path: &str,
+) -> Vec<(usize, serde_json::Value, serde_json::Value)> {
+    let elements: Vec<(usize, &serde_json::Value)> = match get_field_value(doc, path) {
+        Some(serde_json::Value::Array(items)) => items
+            .iter()
+            .enumerate()
+            .filter(|(_, v)| v.is_object())
+            .collect(),
+        Some(value) if value.is_object() => vec![(0, value)],
+        _ => return Vec::new(),
+    };
+    elements
+        .into_iter()
+        .map(|(offset, element)| {
+            let element_doc = path.rsplit('.').fold(
+                element.clone(),
+                |inner, part| serde_json::json!({ part: inner }),
+            );
+            (offset, element.clone(), element_doc)
+        })
+        .collect()
+}
+
+/// Score a bool query
+///
+/// `must` and `filter` clauses must all match and `must_not` clauses must not.
+/// At least `minimum_should_match` of the `should` clauses must match; it
+/// defaults to 1 when there are no `must` or `filter` clauses and to 0
+/// otherwise. The score is the sum of the matching `must` and `should` scores.
+pub fn score_bool_query(
+    id: &str,
+    doc: &serde_json::Value,
+    bool_query: &serde_json::Value,
+) -> Result<Option<f64>> {
+    let Some(bool_obj) = bool_query.as_object() else {
+        return Ok(None);
+    };
+    let mut score = 0.0;
+
+    // Handle must clauses (all must match)
+    let must = bool_clauses(bool_obj, "must");
+    for clause in &must {
+        match score_document(id, doc, clause)? {
+            Some(clause_score) => score += clause_score,
+            None => return Ok(None),
         }
+    }
 
-        // Handle must_not clauses (none should match)
-        if let Some(must_not) = bool_obj.get("must_not") {
-            if let Some(must_not_array) = must_not.as_array() {
-                for clause in must_not_array {
-                    let clause_score = score_document(doc, clause)?;
-                    if clause_score > 0.0 {
-                        return Ok(0.0); // Document matches must_not, exclude it
-                    }
-                }
-            }
+    // Handle must_not clauses (none should match)
+    for clause in bool_clauses(bool_obj, "must_not") {
+        if score_document(id, doc, clause)?.is_some() {
+            return Ok(None); // Document matches must_not, exclude it
         }
+    }
 
-        // Handle filter clauses (must match, but don't affect score)
-        if let Some(filter) = bool_obj.get("filter") {
-            if let Some(filter_array) = filter.as_array() {
-                for clause in filter_array {
-                    let clause_score = score_document(doc, clause)?;
-                    if clause_score == 0.0 {
-                        return Ok(0.0); // Filter doesn't match, exclude
-                    }
-                }
-            }
+    // Handle filter clauses (must match, but don't affect score)
+    let filter = bool_clauses(bool_obj, "filter");
+    for clause in &filter {
+        if score_document(id, doc, clause)?.is_none() {
+            return Ok(None); // Filter doesn't match, exclude
+        }
+    }
+
+    // Handle should clauses (at least minimum_should_match must match)
+    let should = bool_clauses(bool_obj, "should");
+    let required = match bool_obj.get("minimum_should_match") {
+        Some(spec) => minimum_should_match(spec, should.len())?,
+        None if !must.is_empty() || !filter.is_empty() => 0,
+        None => should.len().min(1),
+    };
+    let mut matched = 0;
+    for clause in &should {
+        if let Some(clause_score) = score_document(id, doc, clause)? {
+            matched += 1;
+            score += clause_score;
         }
+    }
+    if matched < required {
+        return Ok(None);
+    }
 
-        Ok(if score > 0.0 { score } else { 1.0 }) // At least 1.0 if all filters pass
+    Ok(Some(if score > 0.0 || !should.is_empty() {
+        // Matching none of the optional should clauses scores 0
+        score
     } else {
-        Ok(0.0)
+        1.0 // Constant score if all filters pass
+    }))
+}
+
+/// Clauses of one occurrence type of a bool query (a single clause or an array)
+pub(super) fn bool_clauses<'a>(
+    bool_obj: &'a serde_json::Map<String, serde_json::Value>,
+    occur: &str,
+) -> Vec<&'a serde_json::Value> {
+    match bool_obj.get(occur) {
+        Some(serde_json::Value::Array(clauses)) => clauses.iter().collect(),
+        Some(clause) if clause.is_object() => vec![clause],
+        _ => Vec::new(),
     }
 }
+
+/// Number of should clauses required by a `minimum_should_match` value
+///
+/// Accepts an integer (`2`), a negative integer counting the clauses that may
+/// be missing (`-1`), or a percentage of the clauses rounded down (`"75%"`,
+/// `"-25%"`). The result is clamped to the number of clauses.
+pub(super) fn minimum_should_match(spec: &serde_json::Value, clauses: usize) -> Result<usize> {
+    let invalid =
+        || GbsError::InvalidRequest(format!("Invalid [minimum_should_match] value [{}]", spec));
+    let spec = match spec {
+        serde_json::Value::Number(n) => n.to_string(),
+        serde_json::Value::String(s) => s.trim().to_string(),
+        _ => return Err(invalid()),
+    };
+
+    let required = if let Some(percent) = spec.strip_suffix('%') {
+        let percent: i64 = percent.trim().parse().map_err(|_| invalid())?;
+        let count = (clauses as i64 * percent.abs()) / 100;
+        if percent < 0 {
+            clauses as i64 - count
+        } else {
+            count
+        }
+    } else {
+        let count: i64 = spec.parse().map_err(|_| invalid())?;
+        if count < 0 {
+            clauses as i64 + count
+        } else {
+            count
+        }
+    };
+    Ok(required.clamp(0, clauses as i64) as usize)
+}
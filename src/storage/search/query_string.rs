@@ -0,0 +1,662 @@
+//! Lucene query string syntax
+//!
+//! The `query_string` query and URI search (`?q=`) accept Lucene syntax, which
+//! is translated to the query DSL before searching:
+//!
+//! - terms and phrases: `quick`, `"quick fox"` (in the default field unless
+//!   prefixed with a field: `title:quick`, `title:(quick OR fox)`)
+//! - boolean operators: `AND`/`&&`, `OR`/`||`, `NOT`/`!`, and the `+`
+//!   (required) and `-` (prohibited) prefixes
+//! - ranges: `age:[20 TO 30]`, `age:{20 TO *}`, `age:>=20`, `date:<2024-01-01`
+//! - wildcards and fuzziness: `qu?ck`, `qui*`, `quikc~`, `quikc~1`
+//! - boosts: `title:quick^2`, `(quick fox)^2`
+//!
+//! Characters with a special meaning are escaped with a backslash.
+
+use super::matchers::parse_field_boost;
+use crate::error::{GbsError, Result};
+
+/// How unprefixed clauses are combined
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DefaultOperator {
+    /// Any clause may match (default)
+    #[default]
+    Or,
+    /// Every clause must match
+    And,
+}
+
+impl DefaultOperator {
+    /// Parse `AND` or `OR` (case-insensitive)
+    pub fn parse(value: &str) -> Result<Self> {
+        if value.eq_ignore_ascii_case("or") {
+            Ok(DefaultOperator::Or)
+        } else if value.eq_ignore_ascii_case("and") {
+            Ok(DefaultOperator::And)
+        } else {
+            Err(GbsError::InvalidRequest(format!(
+                "Invalid default_operator [{}], expected [AND] or [OR]",
+                value
+            )))
+        }
+    }
+}
+
+/// Options of a query string
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueryStringOptions {
+    /// Fields searched by terms without a field prefix, with optional boosts
+    /// such as `title^3` (default: `_all`)
+    pub default_fields: Vec<String>,
+    pub default_operator: DefaultOperator,
+}
+
+impl Default for QueryStringOptions {
+    fn default() -> Self {
+        Self {
+            default_fields: vec!["_all".to_string()],
+            default_operator: DefaultOperator::Or,
+        }
+    }
+}
+
+/// Translate a query string to the query DSL
+pub fn parse_query_string(query: &str, options: &QueryStringOptions) -> Result<serde_json::Value> {
+    let mut parser = Parser {
+        query,
+        chars: query.chars().collect(),
+        pos: 0,
+        options,
+    };
+    parser.parse_query(None, false)
+}
+
+/// Translate the `query_string` queries in a query to the query DSL
+///
+/// Looks through `bool` clauses, `nested` queries and the query and filters
+/// of `function_score` queries, so a query string can be combined with other
+/// queries.
+pub fn expand_query_strings(query: &serde_json::Value) -> Result<serde_json::Value> {
+    expand_query_strings_with(query, &QueryStringOptions::default().default_fields)
+}
+
+/// Translate the `query_string` queries in a query to the query DSL, with
+/// terms of query strings that name no fields searching `default_fields`
+/// (the `index.query.default_field` setting of the searched index)
+pub fn expand_query_strings_with(
+    query: &serde_json::Value,
+    default_fields: &[String],
+) -> Result<serde_json::Value> {
+    let expand = |query: &serde_json::Value| expand_query_strings_with(query, default_fields);
+    let Some(query_obj) = query.as_object() else {
+        return Ok(query.clone());
+    };
+
+    if let Some(spec) = query_obj.get("query_string") {
+        return translate_query_string(spec, default_fields);
+    }
+
+    if let Some(bool_query) = query_obj.get("bool").and_then(|b| b.as_object()) {
+        let mut expanded = bool_query.clone();
+        for occur in ["must", "should", "must_not", "filter"] {
+            match bool_query.get(occur) {
+                Some(serde_json::Value::Array(clauses)) => {
+                    let clauses = clauses.iter().map(expand).collect::<Result<Vec<_>>>()?;
+                    expanded.insert(occur.to_string(), serde_json::Value::Array(clauses));
+                }
+                Some(clause) if clause.is_object() => {
+                    expanded.insert(occur.to_string(), expand(clause)?);
+                }
+                _ => {}
+            }
+        }
+        return Ok(serde_json::json!({ "bool": expanded }));
+    }
+
+    if let Some(nested) = query_obj.get("nested").and_then(|n| n.as_object()) {
+        if let Some(inner) = nested.get("query") {
+            let mut expanded = nested.clone();
+            expanded.insert("query".to_string(), expand(inner)?);
+            return Ok(serde_json::json!({ "nested": expanded }));
+        }
+    }
+
+    if let Some(function_score) = query_obj.get("function_score").and_then(|f| f.as_object()) {
+        let mut expanded = function_score.clone();
+        if let Some(inner) = function_score.get("query") {
+            expanded.insert("query".to_string(), expand(inner)?);
+        }
+        if let Some(serde_json::Value::Array(functions)) = expanded.get_mut("functions") {
+            for function in functions.iter_mut() {
+                if let Some(filter) = function.get("filter") {
+                    function["filter"] = expand(filter)?;
+                }
+            }
+        }
+        return Ok(serde_json::json!({ "function_score": expanded }));
+    }
+
+    Ok(query.clone())
+}
+
+/// Translate the body of a `query_string` query
+///
+/// `{ "query": "...", "default_field": "title", "default_operator": "AND" }`,
+/// where `fields` (a list) may replace `default_field`.
+fn translate_query_string(
+    spec: &serde_json::Value,
+    default_fields: &[String],
+) -> Result<serde_json::Value> {
+    let query = spec.get("query").and_then(|q| q.as_str()).ok_or_else(|| {
+        GbsError::InvalidRequest("[query_string] requires a 'query' string".to_string())
+    })?;
+
+    let mut options = QueryStringOptions {
+        default_fields: default_fields.to_vec(),
+        ..QueryStringOptions::default()
+    };
+    if let Some(fields) = spec.get("fields").and_then(|f| f.as_array()) {
+        options.default_fields = fields
+            .iter()
+            .filter_map(|f| f.as_str().map(|f| f.to_string()))
+            .collect();
+    } else if let Some(field) = spec.get("default_field").and_then(|f| f.as_str()) {
+        options.default_fields = vec![field.to_string()];
+    }
+    if options.default_fields.is_empty() {
+        options.default_fields = default_fields.to_vec();
+    }
+    if let Some(operator) = spec.get("default_operator").and_then(|o| o.as_str()) {
+        options.default_operator = DefaultOperator::parse(operator)?;
+    }
+
+    let mut translated = parse_query_string(query, &options)?;
+    if let Some(boost) = spec.get("boost").and_then(|b| b.as_f64()) {
+        apply_boost(&mut translated, boost);
+    }
+    Ok(translated)
+}
+
+/// How a clause takes part in a boolean query
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Occur {
+    Must,
+    Should,
+    MustNot,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Conjunction {
+    None,
+    And,
+    Or,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Modifier {
+    None,
+    Required,
+    Prohibited,
+}
+
+/// Characters ending a bare term
+fn is_term_end(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '[' | ']' | '{' | '}' | '"' | '^' | '~' | ':')
+}
+
+struct Parser<'a> {
+    query: &'a str,
+    chars: Vec<char>,
+    pos: usize,
+    options: &'a QueryStringOptions,
+}
+
+impl Parser<'_> {
+    fn error(&self, reason: &str) -> GbsError {
+        GbsError::InvalidRequest(format!(
+            "Failed to parse query [{}]: {} at position {}",
+            self.query, reason, self.pos
+        ))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(|c| c.is_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    /// Consume a keyword (`AND`, `OR`, `NOT`) if it comes next as a whole word
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let len = keyword.chars().count();
+        let matches = self.chars[self.pos..]
+            .iter()
+            .take(len)
+            .copied()
+            .eq(keyword.chars());
+        // "ANDROID" is a term, not an operator
+        let word_ends = self
+            .chars
+            .get(self.pos + len)
+            .is_none_or(|c| c.is_whitespace() || *c == '(');
+        if matches && word_ends {
+            self.pos += len;
+        }
+        matches && word_ends
+    }
+
+    /// Consume an operator symbol (`&&`, `||`) if it comes next
+    fn eat_symbol(&mut self, symbol: &str) -> bool {
+        let len = symbol.chars().count();
+        let matches = self.chars[self.pos..]
+            .iter()
+            .take(len)
+            .copied()
+            .eq(symbol.chars());
+        if matches {
+            self.pos += len;
+        }
+        matches
+    }
+
+    /// Parse clauses up to the end of the input or, in a group, the closing `)`
+    fn parse_query(&mut self, field: Option<&str>, in_group: bool) -> Result<serde_json::Value> {
+        let mut clauses: Vec<(Occur, serde_json::Value)> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None if in_group => return Err(self.error("missing ')'")),
+                None => break,
+                Some(')') if in_group => {
+                    self.pos += 1;
+                    break;
+                }
+                Some(')') => return Err(self.error("unexpected ')'")),
+                _ => {}
+            }
+
+            let conjunction = if self.eat_keyword("AND") || self.eat_symbol("&&") {
+                Conjunction::And
+            } else if self.eat_keyword("OR") || self.eat_symbol("||") {
+                Conjunction::Or
+            } else {
+                Conjunction::None
+            };
+            if conjunction != Conjunction::None && clauses.is_empty() {
+                return Err(self.error("operator without a preceding clause"));
+            }
+            self.skip_whitespace();
+
+            let modifier = match self.peek() {
+                Some('+') => {
+                    self.pos += 1;
+                    Modifier::Required
+                }
+                Some('-') | Some('!') => {
+                    self.pos += 1;
+                    Modifier::Prohibited
+                }
+                _ if self.eat_keyword("NOT") => Modifier::Prohibited,
+                _ => Modifier::None,
+            };
+            self.skip_whitespace();
+            if matches!(self.peek(), None | Some(')')) {
+                return Err(self.error("missing clause after operator"));
+            }
+
+            let clause = self.parse_clause(field)?;
+            self.add_clause(&mut clauses, conjunction, modifier, clause);
+        }
+
+        if clauses.len() == 1 && clauses[0].0 != Occur::MustNot {
+            return Ok(clauses.remove(0).1);
+        }
+        if clauses.is_empty() {
+            return Ok(serde_json::json!({ "match_all": {} }));
+        }
+        let mut bool_query = serde_json::Map::new();
+        for (occur, name) in [
+            (Occur::Must, "must"),
+            (Occur::Should, "should"),
+            (Occur::MustNot, "must_not"),
+        ] {
+            let matching: Vec<serde_json::Value> = clauses
+                .iter()
+                .filter(|(o, _)| *o == occur)
+                .map(|(_, q)| q.clone())
+                .collect();
+            if !matching.is_empty() {
+                bool_query.insert(name.to_string(), serde_json::Value::Array(matching));
+            }
+        }
+        Ok(serde_json::json!({ "bool": bool_query }))
+    }
+
+    /// Add a clause the way Lucene's classic query parser does
+    ///
+    /// `AND` makes the preceding clause required and, with the `AND` default
+    /// operator, `OR` makes it optional. The clause itself is prohibited by
+    /// `-`/`!`/`NOT`, and required by `+`, by `AND`, or by the `AND` default
+    /// operator unless introduced by `OR`.
+    fn add_clause(
+        &self,
+        clauses: &mut Vec<(Occur, serde_json::Value)>,
+        conjunction: Conjunction,
+        modifier: Modifier,
+        query: serde_json::Value,
+    ) {
+        let default_and = self.options.default_operator == DefaultOperator::And;
+        if let Some((occur, _)) = clauses.last_mut() {
+            if *occur != Occur::MustNot {
+                if conjunction == Conjunction::And {
+                    *occur = Occur::Must;
+                } else if conjunction == Conjunction::Or && default_and {
+                    *occur = Occur::Should;
+                }
+            }
+        }
+
+        let occur = match modifier {
+            Modifier::Prohibited => Occur::MustNot,
+            Modifier::Required => Occur::Must,
+            Modifier::None if conjunction == Conjunction::And => Occur::Must,
+            Modifier::None if default_and && conjunction != Conjunction::Or => Occur::Must,
+            Modifier::None => Occur::Should,
+        };
+        clauses.push((occur, query));
+    }
+
+    /// Parse a term, phrase, range or group, with its field prefix and boost
+    fn parse_clause(&mut self, field: Option<&str>) -> Result<serde_json::Value> {
+        let mut query = match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                self.parse_query(field, true)?
+            }
+            Some('"') => {
+                let phrase = self.read_phrase()?;
+                // Proximity (`"quick fox"~2`) is accepted but not applied
+                if self.peek() == Some('~') {
+                    self.pos += 1;
+                    self.read_number();
+                }
+                self.leaf(
+                    field,
+                    |field| serde_json::json!({ "match_phrase": { field: phrase } }),
+                )
+            }
+            Some('[') | Some('{') => self.parse_range(field)?,
+            Some('>') | Some('<') => self.parse_comparison(field)?,
+            _ => {
+                let (term, wildcard) = self.read_term()?;
+                if self.peek() == Some(':') {
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    if matches!(self.peek(), None | Some(')')) {
+                        return Err(self.error("missing value after field"));
+                    }
+                    return self.parse_clause(Some(&term));
+                }
+                if self.peek() == Some('~') {
+                    self.pos += 1;
+                    let fuzziness = match self.read_number() {
+                        Some(n) => serde_json::json!(n.max(0.0).round() as u64),
+                        None => serde_json::json!("AUTO"),
+                    };
+                    self.leaf(field, |field| {
+                        serde_json::json!({ "fuzzy": { field: { "value": term, "fuzziness": fuzziness } } })
+                    })
+                } else {
+                    self.term_query(field, &term, wildcard)
+                }
+            }
+        };
+
+        if self.peek() == Some('^') {
+            self.pos += 1;
+            let boost = self
+                .read_number()
+                .ok_or_else(|| self.error("missing boost value after '^'"))?;
+            apply_boost(&mut query, boost);
+        }
+        Ok(query)
+    }
+
+    /// Query for a bare term, a wildcard pattern or a prefix
+    fn term_query(&self, field: Option<&str>, term: &str, wildcard: bool) -> serde_json::Value {
+        if !wildcard {
+            return self.leaf(
+                field,
+                |field| serde_json::json!({ "match": { field: term } }),
+            );
+        }
+        if term == "*" && field.is_none_or(|f| f == "*" || f == "_all") {
+            return serde_json::json!({ "match_all": {} });
+        }
+        let prefix = term.strip_suffix('*').filter(|p| !p.contains(['*', '?']));
+        self.leaf(field, |field| match prefix {
+            Some(prefix) if !prefix.is_empty() => {
+                serde_json::json!({ "prefix": { field: prefix } })
+            }
+            _ => serde_json::json!({ "wildcard": { field: term } }),
+        })
+    }
+
+    /// Build a query on a field, or on each default field if there is none
+    fn leaf(
+        &self,
+        field: Option<&str>,
+        build: impl Fn(&str) -> serde_json::Value,
+    ) -> serde_json::Value {
+        let build_boosted = |field: &str| {
+            let (name, boost) = parse_field_boost(field);
+            let mut query = build(name);
+            if boost != 1.0 {
+                apply_boost(&mut query, boost);
+            }
+            query
+        };
+        match field {
+            Some(field) => build(field),
+            None => match self.options.default_fields.as_slice() {
+                [field] => build_boosted(field),
+                fields => {
+                    let should: Vec<serde_json::Value> =
+                        fields.iter().map(|field| build_boosted(field)).collect();
+                    serde_json::json!({ "bool": { "should": should } })
+                }
+            },
+        }
+    }
+
+    /// Parse `[from TO to]`, with `{`/`}` for exclusive bounds and `*` for none
+    fn parse_range(&mut self, field: Option<&str>) -> Result<serde_json::Value> {
+        let inclusive_lower = self.peek() == Some('[');
+        self.pos += 1;
+        self.skip_whitespace();
+        let lower = self.read_range_bound()?;
+        self.skip_whitespace();
+        if !self.eat_keyword("TO") {
+            return Err(self.error("expected 'TO' in range"));
+        }
+        self.skip_whitespace();
+        let upper = self.read_range_bound()?;
+        self.skip_whitespace();
+        let inclusive_upper = match self.peek() {
+            Some(']') => true,
+            Some('}') => false,
+            _ => return Err(self.error("missing ']' or '}' closing the range")),
+        };
+        self.pos += 1;
+
+        let mut params = serde_json::Map::new();
+        if let Some(lower) = lower {
+            let key = if inclusive_lower { "gte" } else { "gt" };
+            params.insert(key.to_string(), lower);
+        }
+        if let Some(upper) = upper {
+            let key = if inclusive_upper { "lte" } else { "lt" };
+            params.insert(key.to_string(), upper);
+        }
+        Ok(self.leaf(
+            field,
+            |field| serde_json::json!({ "range": { field: params } }),
+        ))
+    }
+
+    /// Parse `>value`, `>=value`, `<value` or `<=value`
+    fn parse_comparison(&mut self, field: Option<&str>) -> Result<serde_json::Value> {
+        let greater = self.peek() == Some('>');
+        self.pos += 1;
+        let inclusive = self.eat_symbol("=");
+        let key = match (greater, inclusive) {
+            (true, true) => "gte",
+            (true, false) => "gt",
+            (false, true) => "lte",
+            (false, false) => "lt",
+        };
+        let value = self
+            .read_range_bound()?
+            .ok_or_else(|| self.error("missing value after comparison"))?;
+        Ok(self.leaf(
+            field,
+            |field| serde_json::json!({ "range": { field: { key: value } } }),
+        ))
+    }
+
+    /// Read a range bound: a number, a string, or `*` for an open bound
+    fn read_range_bound(&mut self) -> Result<Option<serde_json::Value>> {
+        let text = if self.peek() == Some('"') {
+            self.read_phrase()?
+        } else {
+            let start = self.pos;
+            let mut text = String::new();
+            while let Some(c) = self.peek() {
+                if c.is_whitespace() || matches!(c, ']' | '}' | ')') {
+                    break;
+                }
+                if c == '\\' {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(escaped) => text.push(escaped),
+                        None => return Err(self.error("dangling escape character")),
+                    }
+                } else {
+                    text.push(c);
+                }
+                self.pos += 1;
+            }
+            if self.pos == start {
+                return Err(self.error("missing range bound"));
+            }
+            if text == "*" {
+                return Ok(None);
+            }
+            text
+        };
+        Ok(Some(bound_value(&text)))
+    }
+
+    /// Read a quoted phrase, without the quotes
+    fn read_phrase(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut phrase = String::new();
+        loop {
+            match self.peek() {
+                None => return Err(self.error("unclosed '\"'")),
+                Some('"') => {
+                    self.pos += 1;
+                    return Ok(phrase);
+                }
+                Some('\\') => {
+                    self.pos += 1;
+                    match self.peek() {
+                        Some(escaped) => phrase.push(escaped),
+                        None => return Err(self.error("dangling escape character")),
+                    }
+                }
+                Some(c) => phrase.push(c),
+            }
+            self.pos += 1;
+        }
+    }
+
+    /// Read a bare term, returning whether it has unescaped wildcards
+    fn read_term(&mut self) -> Result<(String, bool)> {
+        let mut term = String::new();
+        let mut wildcard = false;
+        while let Some(c) = self.peek() {
+            if is_term_end(c) {
+                break;
+            }
+            if c == '\\' {
+                self.pos += 1;
+                match self.peek() {
+                    Some(escaped) => term.push(escaped),
+                    None => return Err(self.error("dangling escape character")),
+                }
+            } else {
+                wildcard |= matches!(c, '*' | '?');
+                term.push(c);
+            }
+            self.pos += 1;
+        }
+        if term.is_empty() {
+            let unexpected = self.peek().map_or(String::new(), |c| c.to_string());
+            return Err(self.error(&format!("unexpected '{}'", unexpected)));
+        }
+        Ok((term, wildcard))
+    }
+
+    /// Read a number following `^` or `~`, if there is one
+    fn read_number(&mut self) -> Option<f64> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse().ok()
+    }
+}
+
+/// Numbers become JSON numbers so they compare numerically in ranges
+fn bound_value(text: &str) -> serde_json::Value {
+    if let Ok(n) = text.parse::<i64>() {
+        return serde_json::json!(n);
+    }
+    match text.parse::<f64>() {
+        Ok(n) if n.is_finite() => serde_json::json!(n),
+        _ => serde_json::json!(text),
+    }
+}
+
+/// Put a boost on a query, moving a bare field value into its options object
+fn apply_boost(query: &mut serde_json::Value, boost: f64) {
+    let Some((kind, body)) = query.as_object_mut().and_then(|q| q.iter_mut().next()) else {
+        return;
+    };
+    let Some(body) = body.as_object_mut() else {
+        return;
+    };
+    if matches!(
+        kind.as_str(),
+        "bool" | "match_all" | "nested" | "function_score"
+    ) {
+        body.insert("boost".to_string(), serde_json::json!(boost));
+        return;
+    }
+    let value_key = if matches!(kind.as_str(), "match" | "match_phrase") {
+        "query"
+    } else {
+        "value"
+    };
+    for options in body.values_mut() {
+        if !options.is_object() {
+            *options = serde_json::json!({ value_key: options.take() });
+        }
+        options["boost"] = serde_json::json!(boost);
+    }
+}
@@ -0,0 +1,297 @@
+//! Runtime fields (`runtime_mappings` in search bodies)
+//!
+//! Runtime fields are computed from the other fields of a document when a
+//! search runs, without reindexing. Queries, sorts and aggregations of the
+//! search see them like fields of the source:
+//!
+//! ```json
+//! { "runtime_mappings": {
+//!     "total": {
+//!         "type": "double",
+//!         "script": "emit(doc['price'].value * doc['qty'].value)"
+//!     },
+//!     "status": { "type": "keyword" }
+//! } }
+//! ```
+//!
+//! A script passes the values of the field to `emit` (see `search::script`);
+//! a field without a script takes the values of the source field of the same
+//! name, shadowing it. Values are converted to the field's `type`: `keyword`,
+//! `long`, `double`, `boolean`, `date` (formatted like
+//! `2024-01-01T00:00:00.000Z`) or `ip`. Scripts read the fields of the
+//! source, not other runtime fields.
+//!
+//! The `copy_to` targets of mappings (see `search::multi_fields`) and the
+//! catch-all field (see `catch_all`) are computed along with runtime fields,
+//! without being listed in hits.
+
+use chrono::SecondsFormat;
+
+use super::script::Script;
+use super::utils::{get_field_value, parse_date};
+use crate::error::{GbsError, Result};
+use crate::storage::catch_all::{CatchAllField, CATCH_ALL_FIELD};
+
+/// Types a runtime field can have
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RuntimeType {
+    Keyword,
+    Long,
+    Double,
+    Boolean,
+    Date,
+    Ip,
+}
+
+impl RuntimeType {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "keyword" => Some(Self::Keyword),
+            "long" => Some(Self::Long),
+            "double" => Some(Self::Double),
+            "boolean" => Some(Self::Boolean),
+            "date" => Some(Self::Date),
+            "ip" => Some(Self::Ip),
+            _ => None,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Keyword => "keyword",
+            Self::Long => "long",
+            Self::Double => "double",
+            Self::Boolean => "boolean",
+            Self::Date => "date",
+            Self::Ip => "ip",
+        }
+    }
+
+    /// A value converted to this type, `None` if it cannot be
+    fn convert(self, value: &serde_json::Value) -> Option<serde_json::Value> {
+        use serde_json::Value;
+        match (self, value) {
+            (Self::Keyword, Value::String(_)) => Some(value.clone()),
+            (Self::Keyword, Value::Number(_) | Value::Bool(_)) => {
+                Some(Value::String(value.to_string()))
+            }
+            (Self::Long, Value::Number(n)) => n
+                .as_i64()
+                .or_else(|| n.as_f64().filter(|n| n.is_finite()).map(|n| n as i64))
+                .map(Value::from),
+            (Self::Long, Value::String(s)) => s.trim().parse::<i64>().ok().map(Value::from),
+            (Self::Double, Value::Number(n)) => n.as_f64().map(Value::from),
+            (Self::Double, Value::String(s)) => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Value::from),
+            (Self::Boolean, Value::Bool(_)) => Some(value.clone()),
+            (Self::Boolean, Value::String(s)) => match s.as_str() {
+                "true" => Some(Value::Bool(true)),
+                "false" => Some(Value::Bool(false)),
+                _ => None,
+            },
+            (Self::Date, Value::Number(_) | Value::String(_)) => parse_date(value)
+                .map(|date| Value::String(date.to_rfc3339_opts(SecondsFormat::Millis, true))),
+            (Self::Ip, Value::String(s)) => s
+                .parse::<std::net::IpAddr>()
+                .ok()
+                .map(|ip| Value::String(ip.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// A runtime field
+#[derive(Debug, Clone, PartialEq)]
+struct RuntimeField {
+    name: String,
+    kind: RuntimeType,
+    /// Without a script, the values of the source field of the same name
+    script: Option<Script>,
+}
+
+impl RuntimeField {
+    fn values(&self, doc: &serde_json::Value) -> Result<Vec<serde_json::Value>> {
+        let raw = match &self.script {
+            Some(script) => script.emit(doc)?,
+            None => get_field_value(doc, &self.name)
+                .cloned()
+                .into_iter()
+                .collect(),
+        };
+        let mut values = Vec::new();
+        for value in raw {
+            match value {
+                serde_json::Value::Array(items) => values.extend(items),
+                serde_json::Value::Null => {}
+                value => values.push(value),
+            }
+        }
+        values
+            .iter()
+            .map(|value| {
+                self.kind.convert(value).ok_or_else(|| {
+                    GbsError::InvalidRequest(format!(
+                        "runtime field [{}] of type [{}] cannot hold the value [{}]",
+                        self.name,
+                        self.kind.as_str(),
+                        value
+                    ))
+                })
+            })
+            .collect()
+    }
+}
+
+/// The runtime fields of a search
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RuntimeFields {
+    fields: Vec<RuntimeField>,
+    /// `copy_to` targets with the fields copied to them
+    copies: Vec<(String, Vec<String>)>,
+    catch_all: Option<CatchAllField>,
+}
+
+impl RuntimeFields {
+    /// Parse `runtime_mappings`: `{"name": {"type": ..., "script": ...}}`
+    pub fn parse(spec: &serde_json::Value) -> Result<Self> {
+        let mappings = spec.as_object().ok_or_else(|| {
+            GbsError::InvalidRequest("[runtime_mappings] must be an object".to_string())
+        })?;
+        let fields = mappings
+            .iter()
+            .map(|(name, mapping)| {
+                let kind = mapping
+                    .get("type")
+                    .and_then(|t| t.as_str())
+                    .ok_or_else(|| {
+                        GbsError::InvalidRequest(format!(
+                            "runtime field [{}] requires a [type]",
+                            name
+                        ))
+                    })?;
+                let kind = RuntimeType::parse(kind).ok_or_else(|| {
+                    GbsError::InvalidRequest(format!(
+                        "runtime field [{}] has unsupported type [{}]",
+                        name, kind
+                    ))
+                })?;
+                Ok(RuntimeField {
+                    name: name.clone(),
+                    kind,
+                    script: mapping.get("script").map(Script::parse).transpose()?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            fields,
+            ..Self::default()
+        })
+    }
+
+    /// Also compute `copy_to` targets from the fields copied to them
+    pub fn with_copies(mut self, copies: Vec<(String, Vec<String>)>) -> Self {
+        self.copies.extend(copies);
+        self
+    }
+
+    /// Also compute the catch-all field
+    pub fn with_catch_all(mut self, catch_all: CatchAllField) -> Self {
+        self.catch_all = Some(catch_all);
+        self
+    }
+
+    /// A copy of a document source with the values of the runtime fields
+    ///
+    /// A field with one value holds it directly, one with several values an
+    /// array; a field without values is left out.
+    pub fn apply(&self, doc: &serde_json::Value) -> Result<serde_json::Value> {
+        let mut applied = doc.clone();
+        for field in &self.fields {
+            let mut values = field.values(doc)?;
+            let value = match values.len() {
+                0 => None,
+                1 => values.pop(),
+                _ => Some(serde_json::Value::Array(values)),
+            };
+            set_field_value(&mut applied, &field.name, value);
+        }
+        for (target, sources) in &self.copies {
+            let mut values = Vec::new();
+            for field in std::iter::once(target).chain(sources) {
+                match get_field_value(doc, field) {
+                    Some(serde_json::Value::Array(items)) => values.extend(items.iter().cloned()),
+                    Some(serde_json::Value::Null) | None => {}
+                    Some(value) => values.push(value.clone()),
+                }
+            }
+            let value = match values.len() {
+                0 => None,
+                1 => values.pop(),
+                _ => Some(serde_json::Value::Array(values)),
+            };
+            set_field_value(&mut applied, target, value);
+        }
+        if let (Some(catch_all), Some(fields)) = (&self.catch_all, applied.as_object_mut()) {
+            fields.insert(CATCH_ALL_FIELD.to_string(), catch_all.values(doc));
+        }
+        Ok(applied)
+    }
+
+    /// Values of the runtime fields of a document built by `apply`, as listed
+    /// in the `fields` of a hit
+    pub fn values(&self, doc: &serde_json::Value) -> serde_json::Map<String, serde_json::Value> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let values = match get_field_value(doc, &field.name)? {
+                    values @ serde_json::Value::Array(_) => values.clone(),
+                    value => serde_json::json!([value]),
+                };
+                Some((field.name.clone(), values))
+            })
+            .collect()
+    }
+}
+
+/// Set or, with `None`, remove the value at a dotted path, creating the
+/// objects on the way
+fn set_field_value(doc: &mut serde_json::Value, path: &str, value: Option<serde_json::Value>) {
+    let mut parts: Vec<&str> = path.split('.').collect();
+    let name = parts.pop().expect("split gives at least one part");
+    let mut current = doc;
+    for part in parts {
+        current = match value {
+            Some(_) => {
+                if !current.is_object() {
+                    *current = serde_json::json!({});
+                }
+                current
+                    .as_object_mut()
+                    .expect("checked above")
+                    .entry(part)
+                    .or_insert(serde_json::Value::Null)
+            }
+            None => match current.get_mut(part) {
+                Some(next) => next,
+                None => return,
+            },
+        };
+    }
+    match value {
+        Some(value) => {
+            if !current.is_object() {
+                *current = serde_json::json!({});
+            }
+            current[name] = value;
+        }
+        None => {
+            if let Some(object) = current.as_object_mut() {
+                object.remove(name);
+            }
+        }
+    }
+}
@@ -0,0 +1,1460 @@
+//! Scripts: a small painless-like expression language
+//!
+//! Scripts compute values from a document at query time, for `script_fields`
+//! and `_script` sorting. A script is a string, or an object with `source`,
+//! `params` and `lang` (`painless`, the default, or `expression`):
+//!
+//! ```json
+//! { "source": "doc['price'].value * params.rate", "params": { "rate": 1.2 } }
+//! ```
+//!
+//! Supported:
+//! - literals: numbers, `'strings'` and `"strings"`, `true`, `false`, `null`
+//!   and lists (`[1, 2]`)
+//! - document values: `doc['field'].value`, `doc['field'].size()`,
+//!   `doc['field'].empty`, `doc['field'][i]` and `doc.containsKey('field')`;
+//!   values are read from the source, sorted as doc values are, and
+//!   `field.keyword` falls back to `field`
+//! - parameters: `params.name`, `params['name']`, and `params._source` (the
+//!   document source)
+//! - `_score`
+//! - arithmetic (`+ - * / %`, integer arithmetic on integers), string
+//!   concatenation with `+`, comparisons, `&&`, `||`, `!`, `cond ? a : b`
+//!   and casts such as `(int) x`
+//! - statements: `def x = ...;`, `x = ...;`, `x += ...;`,
+//!   `if (...) {...} else {...}` and `return ...;`; a script without
+//!   `return` returns the value of its last expression
+//! - `Math` functions, `String.valueOf`, `Integer.parseInt`,
+//!   `Double.parseDouble`, and common string, list and map methods
+//! - `emit(value)` in the scripts of runtime fields (see
+//!   `search::runtime_fields`)
+//!
+//! There are no loops, so every script terminates.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+
+use super::utils::get_field_value;
+use crate::error::{GbsError, Result};
+
+/// Nesting depth beyond which a script is rejected
+const MAX_DEPTH: usize = 64;
+
+/// Type names accepted in declarations and casts
+const TYPES: &[&str] = &[
+    "def", "var", "int", "long", "short", "byte", "double", "float", "String", "boolean", "Object",
+];
+
+/// A compiled script with its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Script {
+    program: Vec<Stmt>,
+    params: Value,
+    /// Whether the script reads `params._source`
+    uses_source: bool,
+}
+
+impl Script {
+    /// Parse and compile a script given as a string or as an object with
+    /// `source` (or `inline`), `params` and `lang`
+    pub fn parse(spec: &serde_json::Value) -> Result<Self> {
+        let (source, params) = match spec {
+            serde_json::Value::String(source) => (source.as_str(), None),
+            serde_json::Value::Object(obj) => {
+                if obj.contains_key("id") {
+                    return Err(GbsError::Script(
+                        "stored scripts are only supported as search templates".to_string(),
+                    ));
+                }
+                match obj.get("lang").and_then(|l| l.as_str()) {
+                    None | Some("painless") | Some("expression") => {}
+                    Some(lang) => {
+                        return Err(GbsError::Script(format!(
+                            "script_lang not supported [{}]",
+                            lang
+                        )))
+                    }
+                }
+                let source = obj
+                    .get("source")
+                    .or_else(|| obj.get("inline"))
+                    .and_then(|s| s.as_str())
+                    .ok_or_else(|| {
+                        GbsError::Script("must specify [source] for an inline script".to_string())
+                    })?;
+                (source, obj.get("params"))
+            }
+            _ => {
+                return Err(GbsError::Script(format!(
+                    "script must be a string or an object, got {}",
+                    spec
+                )))
+            }
+        };
+        let params = match params {
+            None | Some(serde_json::Value::Null) => Value::Map(BTreeMap::new()),
+            Some(params @ serde_json::Value::Object(_)) => Value::from_json(params),
+            Some(_) => return Err(GbsError::Script("[params] must be an object".to_string())),
+        };
+        Self::compile(source, params)
+    }
+
+    fn compile(source: &str, params: Value) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let uses_source = tokens.iter().any(|(token, _)| {
+            matches!(token, Token::Ident(name) | Token::Str(name) if name == "_source")
+        });
+        let mut parser = Parser {
+            tokens,
+            pos: 0,
+            depth: 0,
+            end: source.len(),
+        };
+        let mut program = Vec::new();
+        while parser.peek().is_some() {
+            program.push(parser.statement()?);
+        }
+        if program.is_empty() {
+            return Err(GbsError::Script("compile error: empty script".to_string()));
+        }
+        check_symbols(&program)?;
+        Ok(Self {
+            program,
+            params,
+            uses_source,
+        })
+    }
+
+    /// Run the script against a document source and its score
+    pub fn execute(&self, doc: &serde_json::Value, score: f64) -> Result<serde_json::Value> {
+        self.run(doc, score, None)
+    }
+
+    /// Run the script of a runtime field against a document source, giving
+    /// the values it passes to `emit`
+    pub fn emit(&self, doc: &serde_json::Value) -> Result<Vec<serde_json::Value>> {
+        let mut emitted = Vec::new();
+        self.run(doc, 0.0, Some(&mut emitted))?;
+        Ok(emitted)
+    }
+
+    fn run(
+        &self,
+        doc: &serde_json::Value,
+        score: f64,
+        emitted: Option<&mut Vec<serde_json::Value>>,
+    ) -> Result<serde_json::Value> {
+        let params = match (&self.params, self.uses_source) {
+            (Value::Map(params), true) => {
+                let mut params = params.clone();
+                params
+                    .entry("_source".to_string())
+                    .or_insert_with(|| Value::from_json(doc));
+                Value::Map(params)
+            }
+            (params, _) => params.clone(),
+        };
+        let mut env = Env {
+            doc,
+            params: &params,
+            score,
+            locals: HashMap::new(),
+            last: Value::Null,
+            emitted,
+        };
+        let value = match env.run(&self.program)? {
+            Some(returned) => returned,
+            None => std::mem::replace(&mut env.last, Value::Null),
+        };
+        Ok(value.to_json())
+    }
+}
+
+/// Names a script can refer to without declaring them
+const BUILTINS: &[&str] = &[
+    "doc", "params", "_score", "Math", "String", "Integer", "Long", "Double", "Float",
+];
+
+/// Reject references to variables the script never declares
+fn check_symbols(program: &[Stmt]) -> Result<()> {
+    fn declared<'a>(statements: &'a [Stmt], names: &mut Vec<&'a str>) {
+        for statement in statements {
+            match statement {
+                Stmt::Assign(name, _) => names.push(name),
+                Stmt::If(_, then, otherwise) => {
+                    declared(then, names);
+                    declared(otherwise, names);
+                }
+                Stmt::Expr(_) | Stmt::Return(_) => {}
+            }
+        }
+    }
+    fn check_statements(statements: &[Stmt], names: &[&str]) -> Result<()> {
+        for statement in statements {
+            match statement {
+                Stmt::Expr(expr) | Stmt::Assign(_, expr) | Stmt::Return(Some(expr)) => {
+                    check_expr(expr, names)?
+                }
+                Stmt::If(condition, then, otherwise) => {
+                    check_expr(condition, names)?;
+                    check_statements(then, names)?;
+                    check_statements(otherwise, names)?;
+                }
+                Stmt::Return(None) => {}
+            }
+        }
+        Ok(())
+    }
+    fn check_expr(expr: &Expr, names: &[&str]) -> Result<()> {
+        match expr {
+            Expr::Var(name)
+                if !names.contains(&name.as_str()) && !BUILTINS.contains(&name.as_str()) =>
+            {
+                Err(compile_error(format!("cannot resolve symbol [{}]", name)))
+            }
+            Expr::Literal(_) | Expr::Var(_) => Ok(()),
+            Expr::List(items) => items.iter().try_for_each(|item| check_expr(item, names)),
+            Expr::Not(expr)
+            | Expr::Neg(expr)
+            | Expr::Cast(_, expr)
+            | Expr::Member(expr, _)
+            | Expr::Emit(expr) => check_expr(expr, names),
+            Expr::Binary(_, left, right)
+            | Expr::And(left, right)
+            | Expr::Or(left, right)
+            | Expr::Index(left, right) => {
+                check_expr(left, names)?;
+                check_expr(right, names)
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                check_expr(condition, names)?;
+                check_expr(then, names)?;
+                check_expr(otherwise, names)
+            }
+            Expr::Call(base, _, args) => {
+                check_expr(base, names)?;
+                args.iter().try_for_each(|arg| check_expr(arg, names))
+            }
+        }
+    }
+
+    let mut names = Vec::new();
+    declared(program, &mut names);
+    check_statements(program, &names)
+}
+
+/// Parse the `script_fields` of a search body: `{"name": {"script": ...}}`
+pub fn parse_script_fields(spec: &serde_json::Value) -> Result<Vec<(String, Script)>> {
+    let fields = spec
+        .as_object()
+        .ok_or_else(|| GbsError::InvalidRequest("[script_fields] must be an object".to_string()))?;
+    fields
+        .iter()
+        .map(|(name, field)| {
+            let script = field.get("script").ok_or_else(|| {
+                GbsError::InvalidRequest(format!("script field [{}] requires a [script]", name))
+            })?;
+            Ok((name.clone(), Script::parse(script)?))
+        })
+        .collect()
+}
+
+/// Values of the script fields of a hit, as returned in its `fields`
+pub fn script_field_values(
+    scripts: &[(String, Script)],
+    doc: &serde_json::Value,
+    score: f64,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    scripts
+        .iter()
+        .map(|(name, script)| {
+            let values = match script.execute(doc, score)? {
+                values @ serde_json::Value::Array(_) => values,
+                value => serde_json::Value::Array(vec![value]),
+            };
+            Ok((name.clone(), values))
+        })
+        .collect()
+}
+
+fn compile_error(message: String) -> GbsError {
+    GbsError::Script(format!("compile error: {}", message))
+}
+
+fn runtime_error(message: String) -> GbsError {
+    GbsError::Script(format!("runtime error: {}", message))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Long(i64),
+    Double(f64),
+    Str(String),
+    Ident(String),
+    Sym(&'static str),
+}
+
+/// Symbols, longest first
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "+", "-", "*", "/", "%", "<", ">",
+    "!", "?", ":", "(", ")", "[", "]", "{", "}", ".", ",", ";", "=",
+];
+
+/// Split a script into tokens with their byte offsets
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if source[i..].starts_with("//") {
+            i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+        } else if source[i..].starts_with("/*") {
+            i = source[i + 2..]
+                .find("*/")
+                .map(|end| i + 2 + end + 2)
+                .ok_or_else(|| compile_error(format!("unclosed comment at offset {}", i)))?;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_digit() {
+                i += 1;
+            }
+            let mut is_double = false;
+            if i + 1 < bytes.len() && bytes[i] == b'.' && bytes[i + 1].is_ascii_digit() {
+                is_double = true;
+                i += 1;
+                while i < bytes.len() && bytes[i].is_ascii_digit() {
+                    i += 1;
+                }
+            }
+            if i < bytes.len() && matches!(bytes[i], b'e' | b'E') {
+                let mut j = i + 1;
+                if j < bytes.len() && matches!(bytes[j], b'+' | b'-') {
+                    j += 1;
+                }
+                if j < bytes.len() && bytes[j].is_ascii_digit() {
+                    is_double = true;
+                    i = j;
+                    while i < bytes.len() && bytes[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text = &source[start..i];
+            // Type suffixes
+            let token = match bytes.get(i) {
+                Some(b'd' | b'D' | b'f' | b'F') => {
+                    i += 1;
+                    Token::Double(text.parse().unwrap_or(f64::NAN))
+                }
+                Some(b'l' | b'L') if !is_double => {
+                    i += 1;
+                    Token::Long(parse_long(text, start)?)
+                }
+                _ if is_double => Token::Double(text.parse().unwrap_or(f64::NAN)),
+                _ => Token::Long(parse_long(text, start)?),
+            };
+            tokens.push((token, start));
+        } else if c == b'\'' || c == b'"' {
+            let start = i;
+            let mut value = String::new();
+            let mut chars = source[i + 1..].char_indices();
+            loop {
+                let Some((offset, ch)) = chars.next() else {
+                    return Err(compile_error(format!(
+                        "unclosed string at offset {}",
+                        start
+                    )));
+                };
+                match ch {
+                    '\\' => match chars.next() {
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        Some((_, 'r')) => value.push('\r'),
+                        Some((_, escaped)) => value.push(escaped),
+                        None => {
+                            return Err(compile_error(format!(
+                                "unclosed string at offset {}",
+                                start
+                            )))
+                        }
+                    },
+                    ch if ch as u32 == c as u32 => {
+                        i += 1 + offset + 1;
+                        break;
+                    }
+                    ch => value.push(ch),
+                }
+            }
+            tokens.push((Token::Str(value), start));
+        } else if c.is_ascii_alphabetic() || c == b'_' || c == b'$' {
+            let start = i;
+            while i < bytes.len()
+                && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_' || bytes[i] == b'$')
+            {
+                i += 1;
+            }
+            tokens.push((Token::Ident(source[start..i].to_string()), start));
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| source[i..].starts_with(**s)) {
+            tokens.push((Token::Sym(symbol), i));
+            i += symbol.len();
+        } else {
+            let ch = source[i..].chars().next().unwrap_or('?');
+            return Err(compile_error(format!(
+                "unexpected character [{}] at offset {}",
+                ch, i
+            )));
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_long(text: &str, offset: usize) -> Result<i64> {
+    text.parse().map_err(|_| {
+        compile_error(format!(
+            "number [{}] at offset {} is out of range",
+            text, offset
+        ))
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stmt {
+    Expr(Expr),
+    /// Declaration or assignment of a local variable
+    Assign(String, Expr),
+    If(Expr, Vec<Stmt>, Vec<Stmt>),
+    Return(Option<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    List(Vec<Expr>),
+    Var(String),
+    Not(Box<Expr>),
+    Neg(Box<Expr>),
+    Cast(String, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Member(Box<Expr>, String),
+    Index(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, String, Vec<Expr>),
+    /// `emit(value)`
+    Emit(Box<Expr>),
+}
+
+/// Binary operators by increasing precedence
+const BINARY_LEVELS: &[&[&str]] = &[
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<", "<=", ">", ">="],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    depth: usize,
+    /// Offset reported for errors at the end of the script
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn peek_at(&self, ahead: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + ahead).map(|(token, _)| token)
+    }
+
+    fn offset(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .map_or(self.end, |(_, offset)| *offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(token, _)| token.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn is_sym(&self, symbol: &str) -> bool {
+        matches!(self.peek(), Some(Token::Sym(s)) if *s == symbol)
+    }
+
+    fn is_ident(&self, name: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(ident)) if ident == name)
+    }
+
+    fn eat_sym(&mut self, symbol: &str) -> bool {
+        let found = self.is_sym(symbol);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn eat_ident(&mut self, name: &str) -> bool {
+        let found = self.is_ident(name);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_sym(&mut self, symbol: &str) -> Result<()> {
+        if self.eat_sym(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("[{}]", symbol)))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.unexpected("a name")),
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> GbsError {
+        let found = match self.peek() {
+            None => "the end of the script".to_string(),
+            Some(Token::Long(n)) => format!("[{}]", n),
+            Some(Token::Double(n)) => format!("[{}]", n),
+            Some(Token::Str(s)) => format!("['{}']", s),
+            Some(Token::Ident(name)) => format!("[{}]", name),
+            Some(Token::Sym(symbol)) => format!("[{}]", symbol),
+        };
+        compile_error(format!(
+            "expected {} but found {} at offset {}",
+            expected,
+            found,
+            self.offset()
+        ))
+    }
+
+    fn enter(&mut self) -> Result<()> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(compile_error(format!(
+                "script is nested too deeply at offset {}",
+                self.offset()
+            )));
+        }
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<Stmt> {
+        self.enter()?;
+        let stmt = self.statement_inner();
+        self.depth -= 1;
+        stmt
+    }
+
+    fn statement_inner(&mut self) -> Result<Stmt> {
+        if self.eat_ident("if") {
+            self.expect_sym("(")?;
+            let condition = self.expr()?;
+            self.expect_sym(")")?;
+            let then = self.block()?;
+            let otherwise = if self.eat_ident("else") {
+                self.block()?
+            } else {
+                Vec::new()
+            };
+            return Ok(Stmt::If(condition, then, otherwise));
+        }
+        if self.eat_ident("return") {
+            let value = if self.is_sym(";") || self.is_sym("}") || self.peek().is_none() {
+                None
+            } else {
+                Some(self.expr()?)
+            };
+            self.end_statement()?;
+            return Ok(Stmt::Return(value));
+        }
+        // Declaration: `def x = ...;`
+        if let (Some(Token::Ident(kind)), Some(Token::Ident(_))) = (self.peek(), self.peek_at(1)) {
+            if TYPES.contains(&kind.as_str()) {
+                self.pos += 1;
+                let name = self.ident()?;
+                let value = if self.eat_sym("=") {
+                    self.expr()?
+                } else {
+                    Expr::Literal(Value::Null)
+                };
+                self.end_statement()?;
+                return Ok(Stmt::Assign(name, value));
+            }
+        }
+        // Assignment: `x = ...;`, `x += ...;`
+        if let (Some(Token::Ident(name)), Some(Token::Sym(symbol))) = (self.peek(), self.peek_at(1))
+        {
+            let operator = match *symbol {
+                "=" => Some(None),
+                "+=" => Some(Some("+")),
+                "-=" => Some(Some("-")),
+                "*=" => Some(Some("*")),
+                "/=" => Some(Some("/")),
+                _ => None,
+            };
+            if let Some(operator) = operator {
+                let name = name.clone();
+                self.pos += 2;
+                let value = self.expr()?;
+                let value = match operator {
+                    Some(op) => {
+                        Expr::Binary(op, Box::new(Expr::Var(name.clone())), Box::new(value))
+                    }
+                    None => value,
+                };
+                self.end_statement()?;
+                return Ok(Stmt::Assign(name, value));
+            }
+        }
+        let expr = self.expr()?;
+        self.end_statement()?;
+        Ok(Stmt::Expr(expr))
+    }
+
+    /// Statements end with `;`, which is optional before `}` and at the end
+    fn end_statement(&mut self) -> Result<()> {
+        if self.eat_sym(";") || self.is_sym("}") || self.peek().is_none() {
+            Ok(())
+        } else {
+            Err(self.unexpected("[;]"))
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>> {
+        if !self.eat_sym("{") {
+            return Ok(vec![self.statement()?]);
+        }
+        let mut statements = Vec::new();
+        while !self.eat_sym("}") {
+            if self.peek().is_none() {
+                return Err(self.unexpected("[}]"));
+            }
+            statements.push(self.statement()?);
+        }
+        Ok(statements)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.enter()?;
+        let condition = self.binary(0);
+        let expr = match condition {
+            Ok(condition) if self.eat_sym("?") => (|| {
+                let then = self.expr()?;
+                self.expect_sym(":")?;
+                let otherwise = self.expr()?;
+                Ok(Expr::Conditional(
+                    Box::new(condition),
+                    Box::new(then),
+                    Box::new(otherwise),
+                ))
+            })(),
+            result => result,
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr> {
+        let Some(operators) = BINARY_LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut left = self.binary(level + 1)?;
+        loop {
+            let Some(Token::Sym(symbol)) = self.peek() else {
+                return Ok(left);
+            };
+            let Some(operator) = operators.iter().find(|op| **op == *symbol).copied() else {
+                return Ok(left);
+            };
+            self.pos += 1;
+            let right = Box::new(self.binary(level + 1)?);
+            left = match operator {
+                "&&" => Expr::And(Box::new(left), right),
+                "||" => Expr::Or(Box::new(left), right),
+                operator => Expr::Binary(operator, Box::new(left), right),
+            };
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        self.enter()?;
+        let expr = if self.eat_sym("!") {
+            self.unary().map(|e| Expr::Not(Box::new(e)))
+        } else if self.eat_sym("-") {
+            self.unary().map(|e| Expr::Neg(Box::new(e)))
+        } else if self.eat_sym("+") {
+            self.unary()
+        } else if let (Some(Token::Sym("(")), Some(Token::Ident(kind)), Some(Token::Sym(")"))) =
+            (self.peek(), self.peek_at(1), self.peek_at(2))
+        {
+            if TYPES.contains(&kind.as_str()) {
+                let kind = kind.clone();
+                self.pos += 3;
+                self.unary().map(|e| Expr::Cast(kind, Box::new(e)))
+            } else {
+                self.postfix()
+            }
+        } else {
+            self.postfix()
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    fn postfix(&mut self) -> Result<Expr> {
+        let mut expr = self.primary()?;
+        loop {
+            if self.eat_sym(".") {
+                let name = self.ident()?;
+                if self.eat_sym("(") {
+                    let args = self.arguments(")")?;
+                    expr = Expr::Call(Box::new(expr), name, args);
+                } else {
+                    expr = Expr::Member(Box::new(expr), name);
+                }
+            } else if self.eat_sym("[") {
+                let index = self.expr()?;
+                self.expect_sym("]")?;
+                expr = Expr::Index(Box::new(expr), Box::new(index));
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    /// Comma-separated expressions up to `close`
+    fn arguments(&mut self, close: &str) -> Result<Vec<Expr>> {
+        let mut args = Vec::new();
+        if self.eat_sym(close) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.expr()?);
+            if self.eat_sym(close) {
+                return Ok(args);
+            }
+            self.expect_sym(",")?;
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Long(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Long(n)))
+            }
+            Some(Token::Double(n)) => {
+                let n = *n;
+                self.pos += 1;
+                Ok(Expr::Literal(Value::Double(n)))
+            }
+            Some(Token::Str(_)) => match self.next() {
+                Some(Token::Str(s)) => Ok(Expr::Literal(Value::Str(s))),
+                _ => unreachable!("peeked a string"),
+            },
+            Some(Token::Ident(name))
+                if name == "emit" && self.peek_at(1) == Some(&Token::Sym("(")) =>
+            {
+                self.pos += 2;
+                let mut args = self.arguments(")")?;
+                if args.len() != 1 {
+                    return Err(compile_error(format!(
+                        "[emit] takes 1 argument, got {}",
+                        args.len()
+                    )));
+                }
+                Ok(Expr::Emit(Box::new(args.remove(0))))
+            }
+            Some(Token::Ident(name)) => {
+                let expr = match name.as_str() {
+                    "true" => Expr::Literal(Value::Bool(true)),
+                    "false" => Expr::Literal(Value::Bool(false)),
+                    "null" => Expr::Literal(Value::Null),
+                    name => Expr::Var(name.to_string()),
+                };
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Sym("(")) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect_sym(")")?;
+                Ok(expr)
+            }
+            Some(Token::Sym("[")) => {
+                self.pos += 1;
+                Ok(Expr::List(self.arguments("]")?))
+            }
+            _ => Err(self.unexpected("an expression")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Long(i64),
+    Double(f64),
+    Str(String),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+    /// `doc`
+    Doc,
+    /// `doc['field']`
+    DocValues(Vec<Value>),
+}
+
+impl Value {
+    fn from_json(value: &serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => Value::Null,
+            serde_json::Value::Bool(b) => Value::Bool(*b),
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(n) => Value::Long(n),
+                None => Value::Double(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::String(s) => Value::Str(s.clone()),
+            serde_json::Value::Array(items) => {
+                Value::List(items.iter().map(Value::from_json).collect())
+            }
+            serde_json::Value::Object(obj) => Value::Map(
+                obj.iter()
+                    .map(|(key, value)| (key.clone(), Value::from_json(value)))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Value::Null | Value::Doc => serde_json::Value::Null,
+            Value::Bool(b) => serde_json::json!(b),
+            Value::Long(n) => serde_json::json!(n),
+            Value::Double(n) => serde_json::Number::from_f64(*n)
+                .map_or(serde_json::Value::Null, serde_json::Value::Number),
+            Value::Str(s) => serde_json::json!(s),
+            Value::List(items) | Value::DocValues(items) => {
+                serde_json::Value::Array(items.iter().map(Value::to_json).collect())
+            }
+            Value::Map(map) => serde_json::Value::Object(
+                map.iter()
+                    .map(|(key, value)| (key.clone(), value.to_json()))
+                    .collect(),
+            ),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Long(_) => "long",
+            Value::Double(_) => "double",
+            Value::Str(_) => "String",
+            Value::List(_) => "List",
+            Value::Map(_) => "Map",
+            Value::Doc => "doc",
+            Value::DocValues(_) => "ScriptDocValues",
+        }
+    }
+
+    fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Long(n) => Some(*n as f64),
+            Value::Double(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    /// The value as Java's `toString` renders it
+    fn display(&self) -> String {
+        match self {
+            Value::Null => "null".to_string(),
+            Value::Bool(b) => b.to_string(),
+            Value::Long(n) => n.to_string(),
+            Value::Double(n) if n.is_finite() && n.fract() == 0.0 && n.abs() < 1e7 => {
+                format!("{:.1}", n)
+            }
+            Value::Double(n) => n.to_string(),
+            Value::Str(s) => s.clone(),
+            Value::List(items) | Value::DocValues(items) => format!(
+                "[{}]",
+                items
+                    .iter()
+                    .map(Value::display)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Map(map) => format!(
+                "{{{}}}",
+                map.iter()
+                    .map(|(key, value)| format!("{}={}", key, value.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Value::Doc => "doc".to_string(),
+        }
+    }
+}
+
+/// Order of doc values: numbers, then strings, then booleans
+fn compare_doc_values(a: &Value, b: &Value) -> Ordering {
+    match (a.as_f64(), b.as_f64()) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => match (a, b) {
+            (Value::Str(a), Value::Str(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (Value::Str(_), _) => Ordering::Less,
+            (_, Value::Str(_)) => Ordering::Greater,
+            _ => Ordering::Equal,
+        },
+    }
+}
+
+struct Env<'a> {
+    doc: &'a serde_json::Value,
+    params: &'a Value,
+    score: f64,
+    locals: HashMap<String, Value>,
+    /// Value of the last expression statement, returned without `return`
+    last: Value,
+    /// Values passed to `emit`, in the scripts of runtime fields
+    emitted: Option<&'a mut Vec<serde_json::Value>>,
+}
+
+impl Env<'_> {
+    /// Run statements, returning the value of a `return`
+    fn run(&mut self, statements: &[Stmt]) -> Result<Option<Value>> {
+        for statement in statements {
+            match statement {
+                Stmt::Expr(expr) => self.last = self.eval(expr)?,
+                Stmt::Assign(name, expr) => {
+                    let value = self.eval(expr)?;
+                    self.locals.insert(name.clone(), value);
+                }
+                Stmt::If(condition, then, otherwise) => {
+                    let branch = if self.condition(condition)? {
+                        then
+                    } else {
+                        otherwise
+                    };
+                    if let Some(returned) = self.run(branch)? {
+                        return Ok(Some(returned));
+                    }
+                }
+                Stmt::Return(value) => {
+                    let value = match value {
+                        Some(expr) => self.eval(expr)?,
+                        None => Value::Null,
+                    };
+                    return Ok(Some(value));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn condition(&mut self, expr: &Expr) -> Result<bool> {
+        match self.eval(expr)? {
+            Value::Bool(b) => Ok(b),
+            value => Err(runtime_error(format!(
+                "cannot cast [{}] to [boolean]",
+                value.type_name()
+            ))),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::List(items) => Ok(Value::List(
+                items
+                    .iter()
+                    .map(|item| self.eval(item))
+                    .collect::<Result<_>>()?,
+            )),
+            Expr::Var(name) => self.variable(name),
+            Expr::Not(expr) => Ok(Value::Bool(!self.condition(expr)?)),
+            Expr::Neg(expr) => match self.eval(expr)? {
+                Value::Long(n) => Ok(Value::Long(n.wrapping_neg())),
+                Value::Double(n) => Ok(Value::Double(-n)),
+                value => Err(runtime_error(format!(
+                    "cannot apply [-] to [{}]",
+                    value.type_name()
+                ))),
+            },
+            Expr::Cast(kind, expr) => cast(kind, self.eval(expr)?),
+            Expr::Binary(operator, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(operator, left, right)
+            }
+            Expr::And(left, right) => {
+                Ok(Value::Bool(self.condition(left)? && self.condition(right)?))
+            }
+            Expr::Or(left, right) => {
+                Ok(Value::Bool(self.condition(left)? || self.condition(right)?))
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if self.condition(condition)? {
+                    self.eval(then)
+                } else {
+                    self.eval(otherwise)
+                }
+            }
+            Expr::Member(base, name) => {
+                if let Expr::Var(class) = base.as_ref() {
+                    if class == "Math" && !self.locals.contains_key(class) {
+                        return match name.as_str() {
+                            "PI" => Ok(Value::Double(std::f64::consts::PI)),
+                            "E" => Ok(Value::Double(std::f64::consts::E)),
+                            _ => Err(runtime_error(format!("unknown field [Math.{}]", name))),
+                        };
+                    }
+                }
+                let base = self.eval(base)?;
+                self.member(base, name)
+            }
+            Expr::Index(base, index) => {
+                let base = self.eval(base)?;
+                let index = self.eval(index)?;
+                self.index(base, index)
+            }
+            Expr::Call(base, name, args) => {
+                let args: Vec<Value> = args
+                    .iter()
+                    .map(|arg| self.eval(arg))
+                    .collect::<Result<_>>()?;
+                if let Expr::Var(class) = base.as_ref() {
+                    if !self.locals.contains_key(class) {
+                        if let Some(result) = static_call(class, name, &args) {
+                            return result;
+                        }
+                    }
+                }
+                let base = self.eval(base)?;
+                self.method(base, name, &args)
+            }
+            Expr::Emit(expr) => {
+                let value = self.eval(expr)?.to_json();
+                match &mut self.emitted {
+                    Some(emitted) => {
+                        emitted.push(value);
+                        Ok(Value::Null)
+                    }
+                    None => Err(runtime_error(
+                        "[emit] is only available in runtime fields".to_string(),
+                    )),
+                }
+            }
+        }
+    }
+
+    fn variable(&self, name: &str) -> Result<Value> {
+        if let Some(value) = self.locals.get(name) {
+            return Ok(value.clone());
+        }
+        match name {
+            "doc" => Ok(Value::Doc),
+            "params" => Ok(self.params.clone()),
+            "_score" => Ok(Value::Double(self.score)),
+            _ => Err(runtime_error(format!("cannot resolve symbol [{}]", name))),
+        }
+    }
+
+    /// `doc['field']`: the values of a field, sorted
+    fn doc_values(&self, field: &str) -> Value {
+        let value = get_field_value(self.doc, field).or_else(|| {
+            field
+                .strip_suffix(".keyword")
+                .and_then(|base| get_field_value(self.doc, base))
+        });
+        let mut values = Vec::new();
+        if let Some(value) = value {
+            collect_doc_values(value, &mut values);
+        }
+        values.sort_by(compare_doc_values);
+        Value::DocValues(values)
+    }
+
+    fn member(&self, base: Value, name: &str) -> Result<Value> {
+        match (base, name) {
+            (Value::Doc, field) => Ok(self.doc_values(field)),
+            (Value::DocValues(values), "value") => first_doc_value(values),
+            (Value::DocValues(values), "values") => Ok(Value::List(values)),
+            (Value::DocValues(values), "empty") => Ok(Value::Bool(values.is_empty())),
+            (Value::DocValues(values) | Value::List(values), "length") => {
+                Ok(Value::Long(values.len() as i64))
+            }
+            (Value::Map(mut map), key) => Ok(map.remove(key).unwrap_or(Value::Null)),
+            (Value::Null, name) => Err(runtime_error(format!(
+                "cannot access [{}] of a null value",
+                name
+            ))),
+            (base, name) => Err(runtime_error(format!(
+                "unknown field [{}] of [{}]",
+                name,
+                base.type_name()
+            ))),
+        }
+    }
+
+    fn index(&self, base: Value, index: Value) -> Result<Value> {
+        match (base, index) {
+            (Value::Doc, Value::Str(field)) => Ok(self.doc_values(&field)),
+            (Value::Map(mut map), Value::Str(key)) => Ok(map.remove(&key).unwrap_or(Value::Null)),
+            (Value::List(items) | Value::DocValues(items), Value::Long(i)) => element(items, i),
+            (base, index) => Err(runtime_error(format!(
+                "cannot index [{}] with [{}]",
+                base.type_name(),
+                index.type_name()
+            ))),
+        }
+    }
+
+    fn method(&self, base: Value, name: &str, args: &[Value]) -> Result<Value> {
+        let result = match (&base, name, args) {
+            (Value::Doc, "containsKey", [Value::Str(field)]) => {
+                matches!(self.doc_values(field), Value::DocValues(values) if !values.is_empty())
+                    .into()
+            }
+            (Value::Doc, "get", [Value::Str(field)]) => self.doc_values(field),
+            (Value::DocValues(values), "getValue", []) => first_doc_value(values.clone())?,
+            (Value::DocValues(values), "getValues", []) => Value::List(values.clone()),
+            (Value::DocValues(values) | Value::List(values), "size", []) => {
+                Value::Long(values.len() as i64)
+            }
+            (Value::DocValues(values) | Value::List(values), "isEmpty", []) => {
+                values.is_empty().into()
+            }
+            (Value::DocValues(values) | Value::List(values), "get", [Value::Long(i)]) => {
+                element(values.clone(), *i)?
+            }
+            (Value::DocValues(values) | Value::List(values), "contains", [value]) => {
+                values.iter().any(|v| equals(v, value)).into()
+            }
+            (Value::Map(map), "get", [Value::Str(key)]) => {
+                map.get(key).cloned().unwrap_or(Value::Null)
+            }
+            (Value::Map(map), "containsKey", [Value::Str(key)]) => map.contains_key(key).into(),
+            (Value::Map(map), "size", []) => Value::Long(map.len() as i64),
+            (Value::Map(map), "isEmpty", []) => map.is_empty().into(),
+            (Value::Str(s), _, _) => return string_method(s, name, args),
+            (Value::Long(_) | Value::Double(_), "intValue" | "longValue", []) => {
+                cast("long", base.clone())?
+            }
+            (Value::Long(_) | Value::Double(_), "doubleValue" | "floatValue", []) => {
+                cast("double", base.clone())?
+            }
+            (_, "toString", []) => Value::Str(base.display()),
+            (_, "equals", [other]) => equals(&base, other).into(),
+            _ => {
+                return Err(runtime_error(format!(
+                    "unknown method [{}] with [{}] arguments for [{}]",
+                    name,
+                    args.len(),
+                    base.type_name()
+                )))
+            }
+        };
+        Ok(result)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+fn collect_doc_values(value: &serde_json::Value, values: &mut Vec<Value>) {
+    match value {
+        serde_json::Value::Array(items) => {
+            for item in items {
+                collect_doc_values(item, values);
+            }
+        }
+        serde_json::Value::Null | serde_json::Value::Object(_) => {}
+        value => values.push(Value::from_json(value)),
+    }
+}
+
+fn first_doc_value(values: Vec<Value>) -> Result<Value> {
+    values.into_iter().next().ok_or_else(|| {
+        runtime_error(
+            "A document doesn't have a value for a field! Use doc[<field>].size()==0 to check if a document is missing a field!"
+                .to_string(),
+        )
+    })
+}
+
+fn element(items: Vec<Value>, index: i64) -> Result<Value> {
+    let len = items.len();
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| items.into_iter().nth(i))
+        .ok_or_else(|| runtime_error(format!("index {} out of bounds for length {}", index, len)))
+}
+
+fn cast(kind: &str, value: Value) -> Result<Value> {
+    let invalid =
+        |value: &Value| runtime_error(format!("cannot cast [{}] to [{}]", value.type_name(), kind));
+    match kind {
+        "int" | "long" | "short" | "byte" => match value {
+            Value::Long(n) => Ok(Value::Long(n)),
+            Value::Double(n) => Ok(Value::Long(n as i64)),
+            value => Err(invalid(&value)),
+        },
+        "double" | "float" => value
+            .as_f64()
+            .map(Value::Double)
+            .ok_or_else(|| invalid(&value)),
+        "String" => match value {
+            Value::Str(_) | Value::Null => Ok(value),
+            value => Err(invalid(&value)),
+        },
+        "boolean" => match value {
+            Value::Bool(_) => Ok(value),
+            value => Err(invalid(&value)),
+        },
+        _ => Ok(value),
+    }
+}
+
+/// `==`: numbers by value, other values structurally
+fn equals(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Long(a), Value::Long(b)) => a == b,
+        (a, b) => match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => a == b,
+            _ => a == b,
+        },
+    }
+}
+
+fn binary(operator: &str, left: Value, right: Value) -> Result<Value> {
+    let invalid = |left: &Value, right: &Value| {
+        runtime_error(format!(
+            "cannot apply [{}] to [{}] and [{}]",
+            operator,
+            left.type_name(),
+            right.type_name()
+        ))
+    };
+    match operator {
+        "==" => return Ok(equals(&left, &right).into()),
+        "!=" => return Ok((!equals(&left, &right)).into()),
+        "<" | "<=" | ">" | ">=" => {
+            let ordering = match (&left, &right) {
+                (Value::Long(a), Value::Long(b)) => a.cmp(b),
+                _ => match (left.as_f64(), right.as_f64()) {
+                    (Some(a), Some(b)) => match a.partial_cmp(&b) {
+                        Some(ordering) => ordering,
+                        // NaN compares false
+                        None => return Ok(false.into()),
+                    },
+                    _ => return Err(invalid(&left, &right)),
+                },
+            };
+            let result = match operator {
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                ">" => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            };
+            return Ok(result.into());
+        }
+        "+" if matches!(left, Value::Str(_)) || matches!(right, Value::Str(_)) => {
+            return Ok(Value::Str(left.display() + &right.display()));
+        }
+        _ => {}
+    }
+
+    match (&left, &right) {
+        (Value::Long(a), Value::Long(b)) => {
+            let (a, b) = (*a, *b);
+            if matches!(operator, "/" | "%") && b == 0 {
+                return Err(runtime_error("/ by zero".to_string()));
+            }
+            Ok(Value::Long(match operator {
+                "+" => a.wrapping_add(b),
+                "-" => a.wrapping_sub(b),
+                "*" => a.wrapping_mul(b),
+                "/" => a.wrapping_div(b),
+                _ => a.wrapping_rem(b),
+            }))
+        }
+        _ => match (left.as_f64(), right.as_f64()) {
+            (Some(a), Some(b)) => Ok(Value::Double(match operator {
+                "+" => a + b,
+                "-" => a - b,
+                "*" => a * b,
+                "/" => a / b,
+                _ => a % b,
+            })),
+            _ => Err(invalid(&left, &right)),
+        },
+    }
+}
+
+/// Static methods of `Math`, `String`, `Integer`, `Long` and `Double`, if
+/// `class` is one of them
+fn static_call(class: &str, name: &str, args: &[Value]) -> Option<Result<Value>> {
+    let number = |i: usize| -> Result<f64> {
+        args.get(i).and_then(Value::as_f64).ok_or_else(|| {
+            runtime_error(format!("[{}.{}] requires numeric arguments", class, name))
+        })
+    };
+    let result = match class {
+        "Math" => (|| {
+            Ok(match (name, args) {
+                ("max" | "min", [Value::Long(a), Value::Long(b)]) => {
+                    Value::Long(if name == "max" { *a.max(b) } else { *a.min(b) })
+                }
+                ("max", [_, _]) => Value::Double(number(0)?.max(number(1)?)),
+                ("min", [_, _]) => Value::Double(number(0)?.min(number(1)?)),
+                ("abs", [Value::Long(n)]) => Value::Long(n.wrapping_abs()),
+                ("abs", [_]) => Value::Double(number(0)?.abs()),
+                ("round", [_]) => Value::Long((number(0)? + 0.5).floor() as i64),
+                ("floor", [_]) => Value::Double(number(0)?.floor()),
+                ("ceil", [_]) => Value::Double(number(0)?.ceil()),
+                ("sqrt", [_]) => Value::Double(number(0)?.sqrt()),
+                ("pow", [_, _]) => Value::Double(number(0)?.powf(number(1)?)),
+                ("log", [_]) => Value::Double(number(0)?.ln()),
+                ("log10", [_]) => Value::Double(number(0)?.log10()),
+                ("exp", [_]) => Value::Double(number(0)?.exp()),
+                ("signum", [_]) => {
+                    let n = number(0)?;
+                    Value::Double(if n == 0.0 || n.is_nan() {
+                        n
+                    } else {
+                        n.signum()
+                    })
+                }
+                _ => {
+                    return Err(runtime_error(format!(
+                        "unknown method [Math.{}] with [{}] arguments",
+                        name,
+                        args.len()
+                    )))
+                }
+            })
+        })(),
+        "String" => match (name, args) {
+            ("valueOf", [value]) => Ok(Value::Str(value.display())),
+            _ => Err(runtime_error(format!("unknown method [String.{}]", name))),
+        },
+        "Integer" | "Long" => match (name, args) {
+            ("parseInt" | "parseLong", [Value::Str(s)]) => s
+                .trim()
+                .parse()
+                .map(Value::Long)
+                .map_err(|_| runtime_error(format!("For input string: \"{}\"", s))),
+            _ => Err(runtime_error(format!(
+                "unknown method [{}.{}]",
+                class, name
+            ))),
+        },
+        "Double" | "Float" => match (name, args) {
+            ("parseDouble" | "parseFloat", [Value::Str(s)]) => s
+                .trim()
+                .parse()
+                .map(Value::Double)
+                .map_err(|_| runtime_error(format!("For input string: \"{}\"", s))),
+            _ => Err(runtime_error(format!(
+                "unknown method [{}.{}]",
+                class, name
+            ))),
+        },
+        _ => return None,
+    };
+    Some(result)
+}
+
+fn string_method(s: &str, name: &str, args: &[Value]) -> Result<Value> {
+    let chars: Vec<char> = s.chars().collect();
+    let position = |value: &Value| -> Result<usize> {
+        match value {
+            Value::Long(i) if *i >= 0 && (*i as usize) <= chars.len() => Ok(*i as usize),
+            value => Err(runtime_error(format!(
+                "string index {} out of range for length {}",
+                value.display(),
+                chars.len()
+            ))),
+        }
+    };
+    let result = match (name, args) {
+        ("length", []) => Value::Long(chars.len() as i64),
+        ("isEmpty", []) => s.is_empty().into(),
+        ("toUpperCase", []) => Value::Str(s.to_uppercase()),
+        ("toLowerCase", []) => Value::Str(s.to_lowercase()),
+        ("trim", []) => Value::Str(s.trim().to_string()),
+        ("substring", [start]) => Value::Str(chars[position(start)?..].iter().collect()),
+        ("substring", [start, end]) => {
+            let (start, end) = (position(start)?, position(end)?);
+            if start > end {
+                return Err(runtime_error(format!(
+                    "begin {}, end {}, length {}",
+                    start,
+                    end,
+                    chars.len()
+                )));
+            }
+            Value::Str(chars[start..end].iter().collect())
+        }
+        ("charAt", [index]) => {
+            let index = position(index)?;
+            let ch = chars.get(index).ok_or_else(|| {
+                runtime_error(format!(
+                    "string index {} out of range for length {}",
+                    index,
+                    chars.len()
+                ))
+            })?;
+            Value::Str(ch.to_string())
+        }
+        ("contains", [Value::Str(other)]) => s.contains(other.as_str()).into(),
+        ("startsWith", [Value::Str(other)]) => s.starts_with(other.as_str()).into(),
+        ("endsWith", [Value::Str(other)]) => s.ends_with(other.as_str()).into(),
+        ("indexOf", [Value::Str(other)]) => Value::Long(
+            s.find(other.as_str())
+                .map_or(-1, |byte| s[..byte].chars().count() as i64),
+        ),
+        ("replace", [Value::Str(from), Value::Str(to)]) => Value::Str(s.replace(from.as_str(), to)),
+        ("equals", [other]) => matches!(other, Value::Str(other) if other == s).into(),
+        ("equalsIgnoreCase", [Value::Str(other)]) => s.eq_ignore_ascii_case(other).into(),
+        ("toString", []) => Value::Str(s.to_string()),
+        _ => {
+            return Err(runtime_error(format!(
+                "unknown method [{}] with [{}] arguments for [String]",
+                name,
+                args.len()
+            )))
+        }
+    };
+    Ok(result)
+}
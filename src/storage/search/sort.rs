@@ -0,0 +1,557 @@
+//! Sorting of search hits
+//!
+//! A sort specification is a single clause or an array of clauses evaluated in
+//! priority order. Each clause is a field name, `_score` or `_doc`, given as a
+//! string (`"price"`), with an order (`{"price": "desc"}`) or with options:
+//!
+//! ```json
+//! { "price": { "order": "asc", "missing": "_first", "mode": "avg" } }
+//! ```
+//!
+//! - `order`: `asc` or `desc` (default `desc` for `_score`, `asc` otherwise)
+//! - `missing`: `_first` or `_last` (default `_last`), for documents without the field
+//! - `mode`: `min`, `max`, `avg` or `sum` picks the value of array fields
+//!   (default `min` for ascending and `max` for descending order)
+//!
+//! `_script` sorts by the value a script computes (see `search::script`):
+//!
+//! ```json
+//! { "_script": { "type": "number", "script": "doc['price'].value * doc['qty'].value", "order": "desc" } }
+//! ```
+//!
+//! `type` is `number` or `string`. Documents the script fails for, e.g.
+//! because a field has no value, sort as missing.
+
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::script::Script;
+use super::utils::get_field_value;
+use crate::error::{GbsError, Result};
+
+/// What a sort clause sorts by
+#[derive(Debug, Clone, PartialEq)]
+enum SortKey {
+    Field(String),
+    Score,
+    /// Index order, which is document ID order here
+    Doc,
+    /// Value computed by a script, compared as a number or as a string
+    Script {
+        script: Box<Script>,
+        numeric: bool,
+    },
+}
+
+/// How the values of an array field are reduced to one sort value
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortMode {
+    Min,
+    Max,
+    Avg,
+    Sum,
+}
+
+/// One parsed sort clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct SortClause {
+    key: SortKey,
+    descending: bool,
+    missing_first: bool,
+    mode: SortMode,
+}
+
+/// A single sort value of a document
+///
+/// Values are totally ordered, numbers before text, as `compare_values`
+/// compares them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortValue {
+    Number(f64),
+    Text(String),
+}
+
+// Sort values come from JSON, which has no NaN
+impl Eq for SortValue {}
+
+impl Ord for SortValue {
+    fn cmp(&self, other: &Self) -> Ordering {
+        compare_values(self, other)
+    }
+}
+
+impl PartialOrd for SortValue {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl SortClause {
+    /// The field a field clause sorts by, `None` for `_score`, `_doc` and
+    /// `_script` clauses
+    pub fn field(&self) -> Option<&str> {
+        match &self.key {
+            SortKey::Field(field) => Some(field),
+            _ => None,
+        }
+    }
+
+    pub fn is_descending(&self) -> bool {
+        self.descending
+    }
+
+    /// Whether documents without a value sort before the others
+    pub fn is_missing_first(&self) -> bool {
+        self.missing_first
+    }
+
+    /// The value a field clause sorts a document by, `None` if the document
+    /// has no value or the clause is not a field clause
+    pub fn field_value(&self, doc: &serde_json::Value) -> Option<SortValue> {
+        match &self.key {
+            SortKey::Field(field) => sort_value(doc, field, self.mode),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a sort specification into its clauses
+pub fn parse_sort(spec: &serde_json::Value) -> Result<Vec<SortClause>> {
+    match spec {
+        serde_json::Value::Array(clauses) => clauses.iter().map(parse_clause).collect(),
+        clause => Ok(vec![parse_clause(clause)?]),
+    }
+}
+
+fn parse_clause(clause: &serde_json::Value) -> Result<SortClause> {
+    let (field, options) = match clause {
+        serde_json::Value::String(field) => (field.as_str(), None),
+        serde_json::Value::Object(obj) if obj.len() == 1 => {
+            let (field, options) = obj.iter().next().expect("object has one entry");
+            (field.as_str(), Some(options))
+        }
+        _ => return Err(invalid_sort(clause)),
+    };
+
+    let key = match field {
+        "_score" => SortKey::Score,
+        "_doc" => SortKey::Doc,
+        "_script" => parse_script_key(options)?,
+        field => SortKey::Field(field.to_string()),
+    };
+
+    let (order, missing, mode) = match options {
+        None => (None, None, None),
+        Some(serde_json::Value::String(order)) => (Some(order.as_str()), None, None),
+        Some(serde_json::Value::Object(options)) => {
+            let option = |name: &str| -> Result<Option<&str>> {
+                match options.get(name) {
+                    None => Ok(None),
+                    Some(value) => value.as_str().map(Some).ok_or_else(|| invalid_sort(clause)),
+                }
+            };
+            (option("order")?, option("missing")?, option("mode")?)
+        }
+        Some(_) => return Err(invalid_sort(clause)),
+    };
+
+    let descending = match order {
+        None => key == SortKey::Score,
+        Some(order) if order.eq_ignore_ascii_case("asc") => false,
+        Some(order) if order.eq_ignore_ascii_case("desc") => true,
+        Some(order) => {
+            return Err(GbsError::InvalidRequest(format!(
+                "Invalid sort order [{}], expected [asc] or [desc]",
+                order
+            )))
+        }
+    };
+    let missing_first = match missing {
+        None | Some("_last") => false,
+        Some("_first") => true,
+        Some(missing) => {
+            return Err(GbsError::InvalidRequest(format!(
+                "Invalid sort missing value [{}], expected [_first] or [_last]",
+                missing
+            )))
+        }
+    };
+    let mode = match mode {
+        None if descending => SortMode::Max,
+        None => SortMode::Min,
+        Some("min") => SortMode::Min,
+        Some("max") => SortMode::Max,
+        Some("avg") => SortMode::Avg,
+        Some("sum") => SortMode::Sum,
+        Some(mode) => {
+            return Err(GbsError::InvalidRequest(format!(
+                "Invalid sort mode [{}], expected [min], [max], [avg] or [sum]",
+                mode
+            )))
+        }
+    };
+
+    Ok(SortClause {
+        key,
+        descending,
+        missing_first,
+        mode,
+    })
+}
+
+/// The script and type of a `_script` sort clause
+fn parse_script_key(options: Option<&serde_json::Value>) -> Result<SortKey> {
+    let options = options.filter(|o| o.is_object()).ok_or_else(|| {
+        GbsError::InvalidRequest("[_script] sort requires a [script] and a [type]".to_string())
+    })?;
+    let script = options.get("script").ok_or_else(|| {
+        GbsError::InvalidRequest("[_script] sort requires a [script]".to_string())
+    })?;
+    let numeric = match options.get("type").and_then(|t| t.as_str()) {
+        Some("number") => true,
+        Some("string") => false,
+        _ => {
+            return Err(GbsError::InvalidRequest(
+                "[_script] sort requires a [type] of [number] or [string]".to_string(),
+            ))
+        }
+    };
+    Ok(SortKey::Script {
+        script: Box::new(Script::parse(script)?),
+        numeric,
+    })
+}
+
+fn invalid_sort(clause: &serde_json::Value) -> GbsError {
+    GbsError::InvalidRequest(format!("Invalid sort clause {}", clause))
+}
+
+/// Compare two scored documents `(id, document, score)` by the sort clauses
+///
+/// Clauses are applied in order; later clauses only break ties of earlier ones.
+fn compare_documents(
+    a: (&str, &serde_json::Value, f64),
+    b: (&str, &serde_json::Value, f64),
+    clauses: &[SortClause],
+) -> Ordering {
+    compare_by_clauses(clauses, |_, clause| {
+        (clause_value(clause, a), clause_value(clause, b))
+    })
+}
+
+/// The values a scored document is sorted by, one per clause, as reported in
+/// the `sort` of its hit
+///
+/// Documents without a value have `null`; `_doc` is the document ID.
+pub fn sort_values(
+    hit: &(String, serde_json::Value, f64),
+    clauses: &[SortClause],
+) -> Vec<serde_json::Value> {
+    let (id, doc, score) = hit;
+    clauses
+        .iter()
+        .map(|clause| match clause_value(clause, (id, doc, *score)) {
+            Some(SortValue::Number(n)) => serde_json::json!(n),
+            Some(SortValue::Text(text)) => serde_json::json!(text),
+            None => serde_json::Value::Null,
+        })
+        .collect()
+}
+
+/// Compare two hits by the `sort` values they were returned with
+///
+/// Merges hits sorted by the same clauses in different indices, whose
+/// `_source` may not hold the values anymore.
+pub fn compare_sort_values(
+    a: &[serde_json::Value],
+    b: &[serde_json::Value],
+    clauses: &[SortClause],
+) -> Ordering {
+    compare_by_clauses(clauses, |position, _| {
+        (
+            a.get(position).and_then(scalar_value),
+            b.get(position).and_then(scalar_value),
+        )
+    })
+}
+
+/// Compare by the values `values` gives for each clause, by its position
+fn compare_by_clauses(
+    clauses: &[SortClause],
+    values: impl Fn(usize, &SortClause) -> (Option<SortValue>, Option<SortValue>),
+) -> Ordering {
+    for (position, clause) in clauses.iter().enumerate() {
+        let ordering = match values(position, clause) {
+            (Some(a_value), Some(b_value)) => compare_values(&a_value, &b_value),
+            // Missing values are placed regardless of the order
+            (Some(_), None) if clause.missing_first => return Ordering::Greater,
+            (Some(_), None) => return Ordering::Less,
+            (None, Some(_)) if clause.missing_first => return Ordering::Less,
+            (None, Some(_)) => return Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        let ordering = if clause.descending {
+            ordering.reverse()
+        } else {
+            ordering
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    Ordering::Equal
+}
+
+/// The `k` best of the scored documents pushed into it, without sorting the
+/// others
+///
+/// Documents rank in hit order: by the sort clauses, then by descending score
+/// and, to be independent of the order they were pushed in, by ID.
+#[derive(Debug)]
+pub struct TopHits<'a> {
+    k: usize,
+    clauses: &'a [SortClause],
+    /// The worst ranked of the kept documents is on top
+    heap: BinaryHeap<RankedHit<'a>>,
+}
+
+impl<'a> TopHits<'a> {
+    pub fn new(k: usize, clauses: &'a [SortClause]) -> Self {
+        Self {
+            k,
+            clauses,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub fn push(&mut self, id: &'a str, doc: &'a serde_json::Value, score: f64) {
+        let hit = RankedHit {
+            id,
+            doc,
+            score,
+            clauses: self.clauses,
+        };
+        if self.heap.len() < self.k {
+            self.heap.push(hit);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if hit < *worst {
+                *worst = hit;
+            }
+        }
+    }
+
+    /// Keep the best documents of both
+    pub fn merge(mut self, other: Self) -> Self {
+        for hit in other.heap {
+            self.push(hit.id, hit.doc, hit.score);
+        }
+        self
+    }
+
+    /// The kept documents, best first
+    pub fn into_sorted_vec(self) -> Vec<(&'a str, &'a serde_json::Value, f64)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|hit| (hit.id, hit.doc, hit.score))
+            .collect()
+    }
+}
+
+/// `TopHits` of documents read one at a time, which it keeps along with a
+/// value of their own (e.g. the stored source of documents carrying runtime
+/// fields)
+#[derive(Debug)]
+pub struct OwnedTopHits<'a, T> {
+    k: usize,
+    clauses: &'a [SortClause],
+    /// The worst ranked of the kept documents is on top
+    heap: BinaryHeap<OwnedHit<'a, T>>,
+}
+
+impl<'a, T> OwnedTopHits<'a, T> {
+    pub fn new(k: usize, clauses: &'a [SortClause]) -> Self {
+        Self {
+            k,
+            clauses,
+            heap: BinaryHeap::new(),
+        }
+    }
+
+    pub fn push(&mut self, id: String, doc: serde_json::Value, score: f64, extra: T) {
+        let hit = OwnedHit {
+            id,
+            doc,
+            score,
+            extra,
+            clauses: self.clauses,
+        };
+        if self.heap.len() < self.k {
+            self.heap.push(hit);
+        } else if let Some(mut worst) = self.heap.peek_mut() {
+            if hit < *worst {
+                *worst = hit;
+            }
+        }
+    }
+
+    /// The kept documents, best first
+    pub fn into_sorted_vec(self) -> Vec<(String, serde_json::Value, f64, T)> {
+        self.heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|hit| (hit.id, hit.doc, hit.score, hit.extra))
+            .collect()
+    }
+}
+
+/// Rank two scored documents in hit order
+fn rank(
+    a: (&str, &serde_json::Value, f64),
+    b: (&str, &serde_json::Value, f64),
+    clauses: &[SortClause],
+) -> Ordering {
+    compare_documents(a, b, clauses)
+        .then_with(|| b.2.partial_cmp(&a.2).unwrap_or(Ordering::Equal))
+        .then_with(|| a.0.cmp(b.0))
+}
+
+#[derive(Debug)]
+struct RankedHit<'a> {
+    id: &'a str,
+    doc: &'a serde_json::Value,
+    score: f64,
+    clauses: &'a [SortClause],
+}
+
+impl Ord for RankedHit<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        rank(
+            (self.id, self.doc, self.score),
+            (other.id, other.doc, other.score),
+            self.clauses,
+        )
+    }
+}
+
+impl PartialOrd for RankedHit<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for RankedHit<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for RankedHit<'_> {}
+
+#[derive(Debug)]
+struct OwnedHit<'a, T> {
+    id: String,
+    doc: serde_json::Value,
+    score: f64,
+    extra: T,
+    clauses: &'a [SortClause],
+}
+
+impl<T> Ord for OwnedHit<'_, T> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        rank(
+            (&self.id, &self.doc, self.score),
+            (&other.id, &other.doc, other.score),
+            self.clauses,
+        )
+    }
+}
+
+impl<T> PartialOrd for OwnedHit<'_, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<T> PartialEq for OwnedHit<'_, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<T> Eq for OwnedHit<'_, T> {}
+
+/// The value of a scored document for one sort clause
+fn clause_value(
+    clause: &SortClause,
+    (id, doc, score): (&str, &serde_json::Value, f64),
+) -> Option<SortValue> {
+    match &clause.key {
+        SortKey::Score => Some(SortValue::Number(score)),
+        SortKey::Doc => Some(SortValue::Text(id.to_string())),
+        SortKey::Field(field) => sort_value(doc, field, clause.mode),
+        SortKey::Script { script, numeric } => script_sort_value(script, *numeric, doc, score),
+    }
+}
+
+/// The value of `field` a document is sorted by, reducing arrays by `mode`
+fn sort_value(doc: &serde_json::Value, field: &str, mode: SortMode) -> Option<SortValue> {
+    let values: Vec<SortValue> = match get_field_value(doc, field)? {
+        serde_json::Value::Array(items) => items.iter().filter_map(scalar_value).collect(),
+        value => vec![scalar_value(value)?],
+    };
+
+    let numbers: Option<Vec<f64>> = values
+        .iter()
+        .map(|value| match value {
+            SortValue::Number(n) => Some(*n),
+            SortValue::Text(_) => None,
+        })
+        .collect();
+    match (mode, numbers) {
+        (SortMode::Avg, Some(numbers)) if !numbers.is_empty() => Some(SortValue::Number(
+            numbers.iter().sum::<f64>() / numbers.len() as f64,
+        )),
+        (SortMode::Sum, Some(numbers)) if !numbers.is_empty() => {
+            Some(SortValue::Number(numbers.iter().sum()))
+        }
+        (SortMode::Max, _) => values.into_iter().max_by(compare_values),
+        // Text values have no average or sum; they sort by their minimum
+        _ => values.into_iter().min_by(compare_values),
+    }
+}
+
+/// The value a script computes for a document, if it computes one of the type
+fn script_sort_value(
+    script: &Script,
+    numeric: bool,
+    doc: &serde_json::Value,
+    score: f64,
+) -> Option<SortValue> {
+    match script.execute(doc, score).ok()? {
+        serde_json::Value::Number(n) if numeric => n.as_f64().map(SortValue::Number),
+        _ if numeric => None,
+        serde_json::Value::Null => None,
+        serde_json::Value::String(s) => Some(SortValue::Text(s)),
+        value => Some(SortValue::Text(value.to_string())),
+    }
+}
+
+fn scalar_value(value: &serde_json::Value) -> Option<SortValue> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64().map(SortValue::Number),
+        serde_json::Value::String(s) => Some(SortValue::Text(s.clone())),
+        serde_json::Value::Bool(b) => Some(SortValue::Number(if *b { 1.0 } else { 0.0 })),
+        _ => None,
+    }
+}
+
+/// Numbers sort before text
+fn compare_values(a: &SortValue, b: &SortValue) -> Ordering {
+    match (a, b) {
+        (SortValue::Number(a), SortValue::Number(b)) => a.partial_cmp(b).unwrap_or(Ordering::Equal),
+        (SortValue::Text(a), SortValue::Text(b)) => a.cmp(b),
+        (SortValue::Number(_), SortValue::Text(_)) => Ordering::Less,
+        (SortValue::Text(_), SortValue::Number(_)) => Ordering::Greater,
+    }
+}
@@ -1,8 +1,18 @@
 //! Utility functions for search operations
 
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
 /// Get a field value from a document (supports nested fields with dot notation)
-pub fn get_field_value<'a>(doc: &'a serde_json::Value, field: &str) -> Option<&'a serde_json::Value> {
-    if field == "_all" || field == "*" {
+pub fn get_field_value<'a>(
+    doc: &'a serde_json::Value,
+    field: &str,
+) -> Option<&'a serde_json::Value> {
+    // The catch-all field when it was computed (see `catch_all`), every value
+    // of the document otherwise
+    if field == "_all" {
+        return Some(doc.get("_all").unwrap_or(doc));
+    }
+    if field == "*" {
         return Some(doc);
     }
 
@@ -27,7 +37,10 @@ pub fn get_field_value<'a>(doc: &'a serde_json::Value, field: &str) -> Option<&'
 /// - false: exclude all fields (return empty object)
 /// - ["field1", "field2"]: include only specified fields
 /// - {"includes": ["field1"], "excludes": ["field2"]}: include/exclude pattern
-pub fn filter_source(doc: &serde_json::Value, source_filter: Option<&serde_json::Value>) -> serde_json::Value {
+pub fn filter_source(
+    doc: &serde_json::Value,
+    source_filter: Option<&serde_json::Value>,
+) -> serde_json::Value {
     let Some(filter) = source_filter else {
         return doc.clone();
     };
@@ -97,47 +110,27 @@ pub fn filter_source(doc: &serde_json::Value, source_filter: Option<&serde_json:
     doc.clone()
 }
 
-/// Compare two documents for sorting
-pub fn compare_documents(
-    a: &serde_json::Value,
-    b: &serde_json::Value,
-    sort_spec: &serde_json::Value,
-) -> std::cmp::Ordering {
-    if let Some(sort_obj) = sort_spec.as_object() {
-        for (field, order_spec) in sort_obj {
-            let order = if let Some(order_obj) = order_spec.as_object() {
-                order_obj.get("order")
-                    .and_then(|o| o.as_str())
-                    .unwrap_or("asc")
-            } else {
-                order_spec.as_str().unwrap_or("asc")
-            };
-
-            let a_val = get_field_value(a, field);
-            let b_val = get_field_value(b, field);
-
-            let cmp = match (a_val, b_val) {
-                (Some(serde_json::Value::String(a_str)), Some(serde_json::Value::String(b_str))) => {
-                    a_str.cmp(b_str)
-                }
-                (Some(serde_json::Value::Number(a_num)), Some(serde_json::Value::Number(b_num))) => {
-                    if let (Some(a_f64), Some(b_f64)) = (a_num.as_f64(), b_num.as_f64()) {
-                        a_f64.partial_cmp(&b_f64).unwrap_or(std::cmp::Ordering::Equal)
-                    } else {
-                        std::cmp::Ordering::Equal
-                    }
+/// Read a date from epoch milliseconds, RFC 3339 or `yyyy-MM-dd[THH:mm:ss]` (UTC)
+pub fn parse_date(value: &serde_json::Value) -> Option<DateTime<Utc>> {
+    match value {
+        serde_json::Value::Number(n) => DateTime::from_timestamp_millis(n.as_i64()?),
+        serde_json::Value::String(s) => {
+            if let Ok(millis) = s.parse::<i64>() {
+                return DateTime::from_timestamp_millis(millis);
+            }
+            if let Ok(date) = DateTime::parse_from_rfc3339(s) {
+                return Some(date.with_timezone(&Utc));
+            }
+            for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+                if let Ok(date) = NaiveDateTime::parse_from_str(s, format) {
+                    return Some(date.and_utc());
                 }
-                (Some(_), None) => std::cmp::Ordering::Greater,
-                (None, Some(_)) => std::cmp::Ordering::Less,
-                _ => std::cmp::Ordering::Equal,
-            };
-
-            match order {
-                "desc" => return cmp.reverse(),
-                _ => return cmp,
             }
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .map(|date| date.and_utc())
         }
+        _ => None,
     }
-
-    std::cmp::Ordering::Equal
 }
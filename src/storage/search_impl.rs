@@ -1,15 +1,100 @@
 //! Search implementation for Storage
 
 use std::collections::HashMap;
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
 use tokio::sync::RwLock;
 use tracing::{debug, error, info};
 
+use crate::config::SearchConfig;
 use crate::error::{GbsError, Result};
+use crate::storage::aggregation_cache::{
+    AggregationCache, AggregationCacheEntry, AggregationCacheKey,
+};
+use crate::storage::catch_all::{
+    default_query_fields, refers_only_to_catch_all, refers_to_catch_all, CatchAllField,
+};
+use crate::storage::document_ops::fetch_document;
+use crate::storage::field_caps::mapping_properties;
+use crate::storage::filter_cache::is_cacheable_filter;
+use crate::storage::index::Documents;
+use crate::storage::index_state::ensure_readable;
+use crate::storage::refresh::refresh_if_due;
 use crate::storage::search::{
-    compare_documents, filter_source, highlight_document, score_document,
+    docvalue_field_values, expand_query_strings_with, explain_document, filter_source,
+    highlight_document, mapped_fields, nested_inner_hits, normalize_query_terms, parse_sort,
+    percolate_document_ref, percolate_queries_mut, percolator_slots, query_ids, score_document,
+    script_field_values, sort_values, stored_field_values, Aggregations, DocvalueField,
+    Explanation, MappedFields, OwnedTopHits, RuntimeFields, Script, SortClause, StoredFields,
+    TopHits,
 };
+use crate::storage::stats::number_of_shards;
+use crate::storage::tiering::warm_index_backend;
 use crate::storage::Index;
+use crate::storage_backend::SledBackend;
+
+/// Documents scored between two checks of the search timeout
+const TIMEOUT_CHECK_INTERVAL: usize = 256;
+
+/// When the documents of an index are scored on several threads
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelScoring {
+    pub enabled: bool,
+    /// Indices with fewer documents are scored on the searching thread
+    pub min_docs: usize,
+}
+
+impl Default for ParallelScoring {
+    fn default() -> Self {
+        Self::from_config(&SearchConfig::default())
+    }
+}
+
+impl ParallelScoring {
+    /// Build the settings from the search configuration
+    pub fn from_config(config: &SearchConfig) -> Self {
+        Self {
+            enabled: config.parallel_scoring,
+            min_docs: config.parallel_scoring_min_docs,
+        }
+    }
+
+    fn applies_to(&self, doc_count: usize) -> bool {
+        self.enabled && doc_count >= self.min_docs
+    }
+}
+
+/// A search of one index: its query and the options of the request
+#[derive(Debug, Clone, Default)]
+pub struct SearchRequest {
+    pub query: serde_json::Value,
+    pub from: Option<u32>,
+    pub size: Option<u32>,
+    pub sort: Option<serde_json::Value>,
+    /// `_source` filter of the hits
+    pub source_filter: Option<serde_json::Value>,
+    pub highlight: Option<serde_json::Value>,
+    /// `date_histogram` aggregations
+    pub aggs: Option<serde_json::Value>,
+    /// Time budget of the search
+    pub timeout: Option<Duration>,
+    /// Fields computed at search time (`runtime_mappings`)
+    pub runtime_fields: Option<RuntimeFields>,
+}
+
+impl SearchRequest {
+    /// A search for the first page of the documents matching `query`
+    pub fn new(query: serde_json::Value) -> Self {
+        Self {
+            query,
+            ..Default::default()
+        }
+    }
+}
 
 /// Search documents in an index
 ///
@@ -18,91 +103,404 @@ use crate::storage::Index;
 /// - match_all query (return all documents)
 /// - term query (exact match)
 /// - bool query (must, should, must_not, filter)
+/// - query_string query (Lucene syntax, see `search::query_string`)
+/// - percolate query (stored queries matching documents, see `search::percolate`)
 /// - Pagination (from, size)
-/// - Sorting
+/// - Sorting on fields, `_score` and `_doc` (see `search::sort`)
 /// - _source filtering
 /// - Highlighting
+/// - `date_histogram` aggregations, cached in `cache` (see `aggregation_cache`)
+/// - runtime fields (see `search::runtime_fields`)
+/// - keyword normalizers and case-insensitive term-level queries (see
+///   `search::normalizer`)
+/// - early termination of `match_all` searches of sorted indices (see
+///   `index_sort`)
+/// - the catch-all `_all` field and `index.query.default_field` (see
+///   `catch_all`)
+///
+/// A search that runs longer than `timeout` stops scoring documents and
+/// returns the hits found so far with `timed_out: true`.
+///
+/// Hot indices that `scoring` applies to are scored on the rayon thread pool
+/// (see `score_in_parallel`).
+///
+/// Successful searches are counted in the index's operation counters.
 pub async fn search(
     indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
     index_name: &str,
-    query: &serde_json::Value,
-    from: Option<u32>,
-    size: Option<u32>,
-    sort: Option<&serde_json::Value>,
-    source_filter: Option<&serde_json::Value>,
-    highlight: Option<&serde_json::Value>,
+    request: &SearchRequest,
+    cache: &AggregationCache,
+    scoring: ParallelScoring,
+) -> Result<serde_json::Value> {
+    let start_time = std::time::Instant::now();
+    let result = search_index(indices, backend, index_name, request, cache, scoring).await?;
+    if let Some(index) = indices.read().await.get(index_name) {
+        index.operations.record_query(start_time.elapsed());
+    }
+    Ok(result)
+}
+
+async fn search_index(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    request: &SearchRequest,
+    cache: &AggregationCache,
+    scoring: ParallelScoring,
 ) -> Result<serde_json::Value> {
+    let query = &request.query;
+    let (from, size, timeout) = (request.from, request.size, request.timeout);
+    let sort = request.sort.as_ref();
+    let source_filter = request.source_filter.as_ref();
+    let highlight = request.highlight.as_ref();
+    let aggs = request.aggs.as_ref();
+    let runtime_fields = request.runtime_fields.as_ref();
     debug!(
         "Searching index '{}' with query: {}",
         index_name,
         serde_json::to_string(query).unwrap_or_default()
     );
+    let start_time = std::time::Instant::now();
+    let (mut query, mapped) = fit_to_mapping(indices, index_name, query, sort, aggs).await?;
+    resolve_percolate_documents(indices, backend, &mut query).await?;
+    let query = &query;
+    let sort_clauses = mapped
+        .sort
+        .as_ref()
+        .map(parse_sort)
+        .transpose()?
+        .unwrap_or_default();
+    // Searches of the catch-all field alone score the values collected as
+    // documents were written
+    let scans_catch_all = mapped.catch_all.is_some()
+        && runtime_fields.is_none()
+        && mapped.copies.is_empty()
+        && sort_clauses.is_empty()
+        && refers_only_to_catch_all(query);
+    let mapped_fields = mapped.runtime_fields(runtime_fields);
+    let runtime_fields = mapped_fields.as_ref().or(runtime_fields);
+    let aggregations = match &mapped.aggs {
+        Some(aggs) => Some((
+            Aggregations::parse(aggs)?,
+            AggregationCacheKey::new(index_name, aggs, query),
+        )),
+        None => None,
+    };
+    refresh_if_due(indices, index_name).await;
     let indices_guard = indices.read().await;
     let index = indices_guard.get(index_name).ok_or_else(|| {
         error!("Index '{}' not found for search", index_name);
         GbsError::IndexNotFound(index_name.to_string())
     })?;
+    ensure_readable(index)?;
+    let shards = number_of_shards(index);
+    // Writes made since the last refresh are not searchable yet
+    let index = index.searchable();
 
-    let start_time = std::time::Instant::now();
-    let total_docs = index.documents.len();
+    let total_docs = index.doc_count();
     debug!(
-        "Searching {} documents in index '{}'",
-        total_docs, index_name
+        "Searching {} documents in index '{}' ({} tier)",
+        total_docs,
+        index_name,
+        index.tier.as_str()
     );
 
-    // Collect all documents with their IDs
-    let mut scored_docs: Vec<(String, serde_json::Value, f64)> = Vec::new();
-
-    for (id, doc) in &index.documents {
-        let score = score_document(doc, query)?;
-        if score > 0.0 {
-            scored_docs.push((id.clone(), doc.clone(), score));
+    // Requests for aggregations only are answered from the cache when
+    // possible; aggregations over runtime fields are not cached
+    let epoch = index.epoch();
+    let appended = index.appended_count();
+    let cached = runtime_fields.is_none();
+    if let (Some((aggregations, key)), Some(0), true) = (&aggregations, size, cached) {
+        if let Some(entry) = cache.get(key, epoch) {
+            let entry = count_appended(index, entry, query, aggregations, cache)?;
+            let total = entry.total_hits;
+            let rendered = aggregations.render(&entry.counts)?;
+            cache.insert(key.clone(), entry);
+            debug!(
+                "Aggregations for index '{}' served from cache ({} hits)",
+                index_name, total
+            );
+            let took = start_time.elapsed().as_millis() as u32;
+            return Ok(search_response(
+                took,
+                shards,
+                total,
+                Vec::new(),
+                Some(rendered),
+            ));
         }
     }
 
-    // Sort by score (descending) first, then apply custom sorting if specified
-    scored_docs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+    // Queries that only select by _id look the documents up directly
+    let ids = query_ids(query);
+    let mut timed_out = false;
+    // Only the documents of the requested pages are ranked, unless
+    // aggregations need every matching one
+    let from_val = from.unwrap_or(0) as usize;
+    let size_val = size.unwrap_or(10) as usize;
+    let keep = match aggregations {
+        Some(_) => usize::MAX,
+        None => from_val.saturating_add(size_val),
+    };
+    // Set when the documents are already ranked, and possibly fewer than all
+    // matching ones
+    let mut ranked_total = None;
+    // Stored sources of the documents carrying runtime fields, for the
+    // `_source` of their hits
+    let mut stored_sources: HashMap<String, serde_json::Value> = HashMap::new();
 
-    // Apply custom sorting if specified
-    if let Some(sort_spec) = sort {
-        if let Some(sort_array) = sort_spec.as_array() {
-            for sort_item in sort_array.iter().rev() {
-                scored_docs.sort_by(|a, b| compare_documents(&a.1, &b.1, sort_item));
+    // Collect all matching documents with their IDs
+    let scored_docs: Vec<(String, serde_json::Value, f64)> = if let Some(ids) = ids {
+        debug!("Looking up {} document IDs directly", ids.len());
+        let found = if index.is_warm() {
+            drop(indices_guard);
+            lookup_on_disk(backend, index_name, ids).await?
+        } else {
+            ids.into_iter()
+                .filter_map(|id| {
+                    let doc = index.documents.get(&id)?.clone();
+                    Some((id, doc, 1.0))
+                })
+                .collect()
+        };
+        match runtime_fields {
+            Some(runtime_fields) => found
+                .into_iter()
+                .map(|(id, doc, score)| {
+                    let applied = runtime_fields.apply(&doc)?;
+                    stored_sources.insert(id.clone(), doc);
+                    Ok((id, applied, score))
+                })
+                .collect::<Result<_>>()?,
+            None => found,
+        }
+    } else if index.is_warm() {
+        drop(indices_guard);
+        let scan = search_on_disk(
+            backend,
+            index_name,
+            query,
+            runtime_fields,
+            keep,
+            &sort_clauses,
+            timeout.map(|timeout| start_time + timeout),
+        )
+        .await?;
+        if scan.timed_out {
+            debug!(
+                "Search of warm index '{}' timed out after matching {} documents",
+                index_name, scan.matched
+            );
+        }
+        timed_out = scan.timed_out;
+        ranked_total = Some(scan.matched);
+        stored_sources = scan.stored_sources;
+        scan.hits
+    } else if let Some(catch_all) = index.catch_all.as_ref().filter(|_| scans_catch_all) {
+        // Documents holding only the catch-all field are scored in place of
+        // the documents, which the hits then take
+        let documents = catch_all.documents();
+        let scan = if scoring.applies_to(total_docs) {
+            score_in_parallel(
+                par_documents(documents),
+                query,
+                keep,
+                &sort_clauses,
+                timeout,
+                start_time,
+            )?
+        } else {
+            score_sequentially(
+                documents.iter(),
+                query,
+                keep,
+                &sort_clauses,
+                timeout,
+                start_time,
+            )?
+        };
+        timed_out = scan.timed_out;
+        ranked_total = Some(scan.matched);
+        scan.hits
+            .into_sorted_vec()
+            .into_iter()
+            .filter_map(|(id, _, score)| {
+                let doc = index.documents.get(id)?;
+                Some((id.to_string(), doc.clone(), score))
+            })
+            .collect()
+    } else if let Some(runtime_fields) = runtime_fields {
+        // Runtime fields are computed for every document before scoring,
+        // and filters on them cannot be cached
+        let documents = index
+            .documents
+            .iter()
+            .map(|(id, doc)| Ok((id.clone(), runtime_fields.apply(doc)?)))
+            .collect::<Result<Vec<_>>>()?;
+        let scan = if scoring.applies_to(total_docs) {
+            score_in_parallel(
+                documents.par_iter().map(|(id, doc)| (id, doc)),
+                query,
+                keep,
+                &sort_clauses,
+                timeout,
+                start_time,
+            )?
+        } else {
+            score_sequentially(
+                documents.iter().map(|(id, doc)| (id, doc)),
+                query,
+                keep,
+                &sort_clauses,
+                timeout,
+                start_time,
+            )?
+        };
+        timed_out = scan.timed_out;
+        ranked_total = Some(scan.matched);
+        let hits = owned_hits(scan.hits);
+        for (id, _, _) in &hits {
+            if let Some(doc) = index.documents.get(id) {
+                stored_sources.insert(id.clone(), doc.clone());
             }
-        } else if sort_spec.is_object() {
-            // Single sort field
-            scored_docs.sort_by(|a, b| compare_documents(&a.1, &b.1, sort_spec));
         }
-    }
+        hits
+    } else if let Some(sorted) = index.sorted.as_ref().filter(|sorted| {
+        aggregations.is_none() && is_match_all(query) && sorted.sorts_like(&sort_clauses)
+    }) {
+        // Every document matches and hits come in index order, so the first
+        // documents of a sorted index are the hits
+        ranked_total = Some(index.documents.len());
+        sorted
+            .ids()
+            .take(keep)
+            .filter_map(|id| index.documents.get_key_value(id))
+            .map(|(id, doc)| {
+                let score = score_document(id, doc, query)?.unwrap_or_default();
+                Ok((id.clone(), doc.clone(), score))
+            })
+            .collect::<Result<_>>()?
+    } else {
+        // Cached filter clauses narrow down the documents to score
+        let filtered = cached_filters(index, query)?;
+        let scan_query = match &filtered {
+            Some((rest, _)) => rest,
+            None => query,
+        };
+        let scan = if scoring.applies_to(total_docs) {
+            match &filtered {
+                Some((_, ids)) => score_in_parallel(
+                    ids.par_iter()
+                        .filter_map(|id| index.documents.get_key_value(id)),
+                    scan_query,
+                    keep,
+                    &sort_clauses,
+                    timeout,
+                    start_time,
+                )?,
+                None => score_in_parallel(
+                    par_documents(&index.documents),
+                    scan_query,
+                    keep,
+                    &sort_clauses,
+                    timeout,
+                    start_time,
+                )?,
+            }
+        } else {
+            let documents: Box<dyn Iterator<Item = _>> = match &filtered {
+                Some((_, ids)) => Box::new(
+                    ids.iter()
+                        .filter_map(|id| index.documents.get_key_value(id)),
+                ),
+                None => Box::new(index.documents.iter()),
+            };
+            score_sequentially(
+                documents,
+                scan_query,
+                keep,
+                &sort_clauses,
+                timeout,
+                start_time,
+            )?
+        };
+        if scan.timed_out {
+            debug!(
+                "Search of index '{}' timed out after matching {} of {} documents",
+                index_name, scan.matched, total_docs
+            );
+        }
+        timed_out = scan.timed_out;
+        ranked_total = Some(scan.matched);
+        owned_hits(scan.hits)
+    };
+
+    let total = ranked_total.unwrap_or(scored_docs.len());
+
+    let rendered_aggregations = match &aggregations {
+        Some((aggregations, key)) => {
+            let mut counts = aggregations.new_counts();
+            for (_, doc, _) in &scored_docs {
+                aggregations.collect(&mut counts, doc);
+            }
+            let rendered = aggregations.render(&counts)?;
+            cache.record_miss();
+            // Counts of a search that timed out are incomplete
+            if !timed_out && cached {
+                cache.insert(
+                    key.clone(),
+                    AggregationCacheEntry {
+                        epoch,
+                        appended,
+                        total_hits: total,
+                        counts,
+                    },
+                );
+            }
+            Some(rendered)
+        }
+        None => None,
+    };
+
+    // Rank documents looked up by ID, keeping only the requested pages
+    let scored_docs = match ranked_total {
+        Some(_) => scored_docs,
+        None => {
+            let mut hits = TopHits::new(from_val.saturating_add(size_val), &sort_clauses);
+            for (id, doc, score) in &scored_docs {
+                hits.push(id, doc, *score);
+            }
+            owned_hits(hits)
+        }
+    };
 
     // Apply pagination
-    let from_val = from.unwrap_or(0) as usize;
-    let size_val = size.unwrap_or(10) as usize;
-    let total = scored_docs.len();
     let paginated_docs: Vec<_> = scored_docs
         .into_iter()
         .skip(from_val)
         .take(size_val)
         .collect();
 
-    let max_score = if paginated_docs.is_empty() {
-        None
-    } else {
-        Some(paginated_docs[0].2)
-    };
-
     // Build hits with _source filtering and highlighting
     let hits: Vec<serde_json::Value> = paginated_docs
         .into_iter()
-        .map(|(id, doc, score)| {
-            let filtered_source = filter_source(&doc, source_filter);
+        .map(|scored| -> Result<serde_json::Value> {
+            let source = stored_sources.get(&scored.0).unwrap_or(&scored.1);
+            let filtered_source = filter_source(source, source_filter);
             let mut hit = serde_json::json!({
                 "_index": index_name,
                 "_type": "_doc",
-                "_id": id,
-                "_score": score,
+                "_id": scored.0,
+                "_score": scored.2,
                 "_source": filtered_source
             });
+            // Sorted hits carry the values they were sorted by, so hits of
+            // several indices can be merged
+            if !sort_clauses.is_empty() {
+                hit["sort"] = serde_json::json!(sort_values(&scored, &sort_clauses));
+            }
+            let (id, doc, _) = scored;
 
             // Add highlighting if configured
             if let Some(highlight_config) = highlight {
@@ -113,9 +511,32 @@ pub async fn search(
                 }
             }
 
-            hit
+            // Percolated hits list the candidates they match
+            if let Some(slots) = percolator_slots(&doc, query)? {
+                hit["fields"] = serde_json::json!({ "_percolator_document_slot": slots });
+            }
+
+            // Runtime fields are listed with their values
+            if let Some(runtime_fields) = runtime_fields {
+                let values = runtime_fields.values(&doc);
+                if !values.is_empty() {
+                    if !hit["fields"].is_object() {
+                        hit["fields"] = serde_json::json!({});
+                    }
+                    if let Some(fields) = hit["fields"].as_object_mut() {
+                        fields.extend(values);
+                    }
+                }
+            }
+
+            // Nested queries with inner hits list the matching objects
+            if let Some(inner_hits) = nested_inner_hits(index_name, &id, &doc, query)? {
+                hit["inner_hits"] = inner_hits;
+            }
+
+            Ok(hit)
         })
-        .collect();
+        .collect::<Result<_>>()?;
 
     let took = start_time.elapsed().as_millis() as u32;
     let elapsed = start_time.elapsed();
@@ -134,12 +555,357 @@ pub async fn search(
         total_docs
     );
 
-    Ok(serde_json::json!({
+    let mut response = search_response(took, shards, total, hits, rendered_aggregations);
+    response["timed_out"] = serde_json::json!(timed_out);
+    if timed_out {
+        // Documents left unscored when time ran out are not counted
+        response["hits"]["total"]["relation"] = serde_json::json!("gte");
+    }
+    Ok(response)
+}
+
+/// A search fitted to the mapping of an index (see `fit_to_mapping`)
+struct MappedSearch {
+    sort: Option<serde_json::Value>,
+    aggs: Option<serde_json::Value>,
+    /// `copy_to` targets the search refers to, with the fields copied to them
+    copies: Vec<(String, Vec<String>)>,
+    /// Catch-all field of the index, if the search refers to it
+    catch_all: Option<CatchAllField>,
+}
+
+impl MappedSearch {
+    /// Runtime fields of the search along with the `copy_to` targets and the
+    /// catch-all field it refers to, `None` if it refers to neither
+    fn runtime_fields(&self, runtime_fields: Option<&RuntimeFields>) -> Option<RuntimeFields> {
+        if self.copies.is_empty() && self.catch_all.is_none() {
+            return None;
+        }
+        let fields = runtime_fields
+            .cloned()
+            .unwrap_or_default()
+            .with_copies(self.copies.clone());
+        Some(match &self.catch_all {
+            Some(catch_all) => fields.with_catch_all(catch_all.clone()),
+            None => fields,
+        })
+    }
+}
+
+/// Fit a search to the mapping and settings of an index: query strings
+/// search the index's default fields (see `catch_all`), term-level queries of
+/// normalized keyword fields compare normalized values (see
+/// `search::normalizer`) and multi-fields are read from their parent field
+/// (see `search::multi_fields`)
+///
+/// Gives the query to run along with the fitted search.
+async fn fit_to_mapping(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+    query: &serde_json::Value,
+    sort: Option<&serde_json::Value>,
+    aggs: Option<&serde_json::Value>,
+) -> Result<(serde_json::Value, MappedSearch)> {
+    let indices_guard = indices.read().await;
+    let index = indices_guard
+        .get(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    // Query strings are translated once instead of for every document
+    let default_fields = default_query_fields(index.settings.as_ref());
+    let mut query = expand_query_strings_with(query, &default_fields)?;
+    let properties = index.mappings.as_ref().and_then(mapping_properties);
+    // Normalizers of multi-fields apply before they are read from their parent
+    if let Some(normalized) = normalize_query_terms(&query, index.settings.as_ref(), properties)? {
+        query = normalized;
+    }
+    let fields = MappedFields::of_mapping(properties);
+    let mut sort = sort.cloned();
+    let mut aggs = aggs.cloned();
+    fields.resolve(&mut query);
+    if let Some(sort) = &mut sort {
+        fields.resolve_sort(sort);
+    }
+    if let Some(aggs) = &mut aggs {
+        fields.resolve(aggs);
+    }
+    let copies = fields.copies_in(&[Some(&query), sort.as_ref(), aggs.as_ref()]);
+    let catch_all = index
+        .catch_all
+        .as_ref()
+        .filter(|_| refers_to_catch_all(&query))
+        .map(|catch_all| catch_all.field().clone());
+    let mapped = MappedSearch {
+        sort,
+        aggs,
+        copies,
+        catch_all,
+    };
+    Ok((query, mapped))
+}
+
+/// Documents matched by a scan of a hot index
+struct Scan<'a> {
+    matched: usize,
+    hits: TopHits<'a>,
+    timed_out: bool,
+}
+
+/// Score documents one after the other, keeping the best `keep` of the
+/// matching ones
+///
+/// The timeout is checked every `TIMEOUT_CHECK_INTERVAL` documents.
+fn score_sequentially<'a>(
+    documents: impl Iterator<Item = (&'a String, &'a serde_json::Value)>,
+    query: &serde_json::Value,
+    keep: usize,
+    sort_clauses: &'a [SortClause],
+    timeout: Option<Duration>,
+    start_time: Instant,
+) -> Result<Scan<'a>> {
+    let mut scan = Scan {
+        matched: 0,
+        hits: TopHits::new(keep, sort_clauses),
+        timed_out: false,
+    };
+    for (scanned, (id, doc)) in documents.enumerate() {
+        if scanned > 0
+            && scanned % TIMEOUT_CHECK_INTERVAL == 0
+            && timeout.is_some_and(|timeout| start_time.elapsed() >= timeout)
+        {
+            scan.timed_out = true;
+            break;
+        }
+        if let Some(score) = score_document(id, doc, query)? {
+            scan.matched += 1;
+            scan.hits.push(id, doc, score);
+        }
+    }
+    Ok(scan)
+}
+
+/// Copies of the documents kept by a top-k selection, best first
+fn owned_hits(hits: TopHits<'_>) -> Vec<(String, serde_json::Value, f64)> {
+    hits.into_sorted_vec()
+        .into_iter()
+        .map(|(id, doc, score)| (id.to_string(), doc.clone(), score))
+        .collect()
+}
+
+/// The documents of a map, split between threads
+fn par_documents(
+    documents: &Documents,
+) -> impl ParallelIterator<Item = (&String, &serde_json::Value)> {
+    documents.iter().collect::<Vec<_>>().into_par_iter()
+}
+
+/// Score documents on the rayon thread pool, keeping the best `keep` of the
+/// matching ones
+///
+/// Every worker collects its share of the documents in a bounded heap; the
+/// heaps are merged at the end instead of sorting all matches. Workers stop
+/// scoring once the search runs longer than `timeout`.
+fn score_in_parallel<'a>(
+    documents: impl ParallelIterator<Item = (&'a String, &'a serde_json::Value)>,
+    query: &serde_json::Value,
+    keep: usize,
+    sort_clauses: &'a [SortClause],
+    timeout: Option<Duration>,
+    start_time: Instant,
+) -> Result<Scan<'a>> {
+    let timed_out = AtomicBool::new(false);
+    let empty = || Scan {
+        matched: 0,
+        hits: TopHits::new(keep, sort_clauses),
+        timed_out: false,
+    };
+    let scan = documents
+        .try_fold(empty, |mut scan, (id, doc)| -> Result<Scan<'a>> {
+            if timed_out.load(Ordering::Relaxed) {
+                return Ok(scan);
+            }
+            if timeout.is_some_and(|timeout| start_time.elapsed() >= timeout) {
+                timed_out.store(true, Ordering::Relaxed);
+                return Ok(scan);
+            }
+            if let Some(score) = score_document(id, doc, query)? {
+                scan.matched += 1;
+                scan.hits.push(id, doc, score);
+            }
+            Ok(scan)
+        })
+        .try_reduce(empty, |a, b| {
+            Ok(Scan {
+                matched: a.matched + b.matched,
+                hits: a.hits.merge(b.hits),
+                timed_out: false,
+            })
+        })?;
+    Ok(Scan {
+        timed_out: timed_out.into_inner(),
+        ..scan
+    })
+}
+
+/// Check if a query matches every document: `match_all` or an empty query
+fn is_match_all(query: &serde_json::Value) -> bool {
+    query.as_object().is_some_and(|query| {
+        query.is_empty() || (query.len() == 1 && query.contains_key("match_all"))
+    })
+}
+
+/// Look up the cacheable filter clauses of a top-level `bool` query in the
+/// index's filter cache (see `filter_cache`)
+///
+/// Gives the query without those clauses and the IDs of the documents that
+/// match all of them, or `None` if the query has no such clause.
+fn cached_filters(
+    index: &Index,
+    query: &serde_json::Value,
+) -> Result<Option<(serde_json::Value, Vec<String>)>> {
+    let Some(filter) = query
+        .get("bool")
+        .and_then(|bool_query| bool_query.get("filter"))
+    else {
+        return Ok(None);
+    };
+    let clauses: Vec<&serde_json::Value> = match filter {
+        serde_json::Value::Array(clauses) => clauses.iter().collect(),
+        clause => vec![clause],
+    };
+    let (cacheable, rest): (Vec<_>, Vec<_>) = clauses
+        .into_iter()
+        .partition(|clause| is_cacheable_filter(clause));
+    if cacheable.is_empty() {
+        return Ok(None);
+    }
+
+    let mut sets = cacheable
+        .into_iter()
+        .map(|clause| index.filter_cache.matching_ids(index, clause))
+        .collect::<Result<Vec<_>>>()?;
+    sets.sort_by_key(|ids| ids.len());
+    let (smallest, others) = sets.split_first().expect("at least one cacheable clause");
+    let ids = smallest
+        .iter()
+        .filter(|id| others.iter().all(|ids| ids.contains(*id)))
+        .cloned()
+        .collect();
+
+    // A filter left empty would change the default of `minimum_should_match`
+    let rest = if rest.is_empty() {
+        serde_json::json!([{ "match_all": {} }])
+    } else {
+        serde_json::json!(rest)
+    };
+    let mut query = query.clone();
+    query["bool"]["filter"] = rest;
+    Ok(Some((query, ids)))
+}
+
+/// Explain how a document scores against a query
+///
+/// The explanation's value is the document's score in a search with the same
+/// query, or 0 if the document does not match.
+pub async fn explain(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    id: &str,
+    query: &serde_json::Value,
+) -> Result<Explanation> {
+    let (mut query, mapped) = fit_to_mapping(indices, index_name, query, None, None).await?;
+    resolve_percolate_documents(indices, backend, &mut query).await?;
+    let mut doc = fetch_document(indices, backend, index_name, id)
+        .await?
+        .ok_or_else(|| GbsError::DocumentNotFound(id.to_string()))?;
+    if let Some(fields) = mapped.runtime_fields(None) {
+        doc = fields.apply(&doc)?;
+    }
+    explain_document(id, &doc, &query)
+}
+
+/// Compute script fields for a document with the score it got in a search
+pub async fn script_fields(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    id: &str,
+    scripts: &[(String, Script)],
+    score: f64,
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    if !indices.read().await.contains_key(index_name) {
+        return Err(GbsError::IndexNotFound(index_name.to_string()));
+    }
+    let doc = fetch_document(indices, backend, index_name, id)
+        .await?
+        .ok_or_else(|| GbsError::DocumentNotFound(id.to_string()))?;
+    script_field_values(scripts, &doc, score)
+}
+
+/// Values of the `stored_fields` and `docvalue_fields` of a search hit
+pub async fn document_fields(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    id: &str,
+    stored_fields: Option<&StoredFields>,
+    docvalue_fields: &[DocvalueField],
+) -> Result<serde_json::Map<String, serde_json::Value>> {
+    let mapped = {
+        let indices_guard = indices.read().await;
+        let index = indices_guard
+            .get(index_name)
+            .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+        mapped_fields(index.mappings.as_ref().and_then(mapping_properties))
+    };
+    let doc = fetch_document(indices, backend, index_name, id)
+        .await?
+        .ok_or_else(|| GbsError::DocumentNotFound(id.to_string()))?;
+    let mut fields = match stored_fields {
+        Some(stored_fields) => stored_field_values(stored_fields, &doc),
+        None => serde_json::Map::new(),
+    };
+    fields.extend(docvalue_field_values(docvalue_fields, &doc, &mapped)?);
+    Ok(fields)
+}
+
+/// Replace the candidates percolate queries name by `index` and `id` with the
+/// stored documents
+async fn resolve_percolate_documents(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    query: &mut serde_json::Value,
+) -> Result<()> {
+    for percolate in percolate_queries_mut(query) {
+        let Some((index_name, id)) = percolate_document_ref(percolate) else {
+            continue;
+        };
+        if !indices.read().await.contains_key(&index_name) {
+            return Err(GbsError::IndexNotFound(index_name));
+        }
+        let document = fetch_document(indices, backend, &index_name, &id)
+            .await?
+            .ok_or(GbsError::DocumentNotFound(id))?;
+        percolate.insert("document".to_string(), document);
+    }
+    Ok(())
+}
+
+/// Build a search response from its hits and aggregations
+fn search_response(
+    took: u32,
+    shards: u64,
+    total: usize,
+    hits: Vec<serde_json::Value>,
+    aggregations: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let max_score = hits.first().and_then(|hit| hit["_score"].as_f64());
+    let mut response = serde_json::json!({
         "took": took,
         "timed_out": false,
         "_shards": {
-            "total": 1,
-            "successful": 1,
+            "total": shards,
+            "successful": shards,
             "skipped": 0,
             "failed": 0
         },
@@ -151,5 +917,138 @@ pub async fn search(
             "max_score": max_score,
             "hits": hits
         }
-    }))
+    });
+    if let Some(aggregations) = aggregations {
+        response["aggregations"] = aggregations;
+    }
+    response
+}
+
+/// Bring a cached aggregation result up to date with the documents appended
+/// to the index since it was computed
+///
+/// The entry must belong to the index's current epoch, in which documents
+/// were only added, so the counted documents are unchanged.
+fn count_appended(
+    index: &Index,
+    mut entry: AggregationCacheEntry,
+    query: &serde_json::Value,
+    aggregations: &Aggregations,
+    cache: &AggregationCache,
+) -> Result<AggregationCacheEntry> {
+    let new_ids = index.appended_since(entry.appended);
+    if new_ids.is_empty() {
+        cache.record_hit();
+        return Ok(entry);
+    }
+
+    for id in new_ids {
+        if let Some(doc) = index.documents.get(id) {
+            if score_document(id, doc, query)?.is_some() {
+                entry.total_hits += 1;
+                aggregations.collect(&mut entry.counts, doc);
+            }
+        }
+    }
+    entry.appended += new_ids.len();
+    cache.record_partial_hit();
+    Ok(entry)
+}
+
+/// Documents matched by a scan of a warm index
+struct DiskScan {
+    matched: usize,
+    /// The best `keep` of the matching documents, best first
+    hits: Vec<(String, serde_json::Value, f64)>,
+    /// Stored sources of the hits that carry runtime fields
+    stored_sources: HashMap<String, serde_json::Value>,
+    timed_out: bool,
+}
+
+/// Score the documents of a warm index, streaming them from the backend,
+/// keeping the best `keep` of the matching ones
+///
+/// Like the scans of hot indices, the deadline of the search timeout is
+/// checked every `TIMEOUT_CHECK_INTERVAL` documents. With runtime fields, documents are
+/// scored with their values.
+async fn search_on_disk(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    query: &serde_json::Value,
+    runtime_fields: Option<&RuntimeFields>,
+    keep: usize,
+    sort_clauses: &[SortClause],
+    deadline: Option<Instant>,
+) -> Result<DiskScan> {
+    let backend = warm_index_backend(backend, index_name)?;
+    let index_name = index_name.to_string();
+    let query = query.clone();
+    let runtime_fields = runtime_fields.cloned();
+    let sort_clauses = sort_clauses.to_vec();
+
+    tokio::task::spawn_blocking(move || {
+        let mut matched = 0;
+        let mut hits = OwnedTopHits::new(keep, &sort_clauses);
+        let mut scanned = 0;
+        let complete = backend.for_each_document_until(&index_name, |id, doc| {
+            if scanned > 0
+                && scanned % TIMEOUT_CHECK_INTERVAL == 0
+                && deadline.is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Ok(ControlFlow::Break(()));
+            }
+            scanned += 1;
+            let (scored, stored) = match &runtime_fields {
+                Some(runtime_fields) => (runtime_fields.apply(&doc)?, Some(doc)),
+                None => (doc, None),
+            };
+            if let Some(score) = score_document(id, &scored, &query)? {
+                matched += 1;
+                hits.push(id.to_string(), scored, score, stored);
+            }
+            Ok(ControlFlow::Continue(()))
+        })?;
+
+        let mut stored_sources = HashMap::new();
+        let hits = hits
+            .into_sorted_vec()
+            .into_iter()
+            .map(|(id, doc, score, stored)| {
+                if let Some(stored) = stored {
+                    stored_sources.insert(id.clone(), stored);
+                }
+                (id, doc, score)
+            })
+            .collect();
+        Ok(DiskScan {
+            matched,
+            hits,
+            stored_sources,
+            timed_out: !complete,
+        })
+    })
+    .await
+    .map_err(GbsError::TaskJoin)?
+}
+
+/// Fetch documents of a warm index by ID from the backend
+async fn lookup_on_disk(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    ids: Vec<String>,
+) -> Result<Vec<(String, serde_json::Value, f64)>> {
+    let backend = warm_index_backend(backend, index_name)?;
+    let index_name = index_name.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let mut found = Vec::new();
+        for id in ids {
+            if let Some(doc) = backend.load_document(&index_name, &id)? {
+                found.push((id, doc, 1.0));
+            }
+        }
+        Ok(found)
+    })
+    .await
+    .map_err(GbsError::TaskJoin)?
 }
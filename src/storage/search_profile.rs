@@ -0,0 +1,194 @@
+//! Search profiles
+//!
+//! A search profile is a named, per-index set of field boosts and a default
+//! operator. Selecting a profile at query time (`?search_profile=name`) fills
+//! in the fields and operator of full-text queries that do not set them, so
+//! relevance tuning lives on the server instead of in every client.
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{GbsError, Result};
+
+/// A named set of field boosts and a default operator
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SearchProfile {
+    /// Fields to search, with optional boosts (e.g. `"title^3"`)
+    pub fields: Vec<String>,
+    /// Operator for full-text queries without one (`"or"` or `"and"`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_operator: Option<String>,
+}
+
+impl SearchProfile {
+    /// Parse and validate a profile from a request body
+    pub fn from_json(body: &serde_json::Value) -> Result<Self> {
+        let profile: SearchProfile = serde_json::from_value(body.clone())
+            .map_err(|e| GbsError::InvalidRequest(format!("Invalid search profile: {}", e)))?;
+
+        if profile.fields.is_empty() {
+            return Err(GbsError::InvalidRequest(
+                "Search profile requires at least one field".to_string(),
+            ));
+        }
+        for field in &profile.fields {
+            if let Some((name, boost)) = field.split_once('^') {
+                if name.is_empty() || boost.parse::<f64>().map_or(true, |b| b < 0.0) {
+                    return Err(GbsError::InvalidRequest(format!(
+                        "Invalid field boost [{}] in search profile",
+                        field
+                    )));
+                }
+            }
+        }
+        if let Some(operator) = &profile.default_operator {
+            let operator = operator.to_lowercase();
+            if operator != "or" && operator != "and" {
+                return Err(GbsError::InvalidRequest(format!(
+                    "Invalid default_operator [{}], expected [or] or [and]",
+                    operator
+                )));
+            }
+        }
+
+        Ok(profile)
+    }
+
+    /// Rewrite a query so that full-text clauses use this profile
+    ///
+    /// - `multi_match` without `fields` searches the profile fields
+    /// - `match` on `_all` becomes a `multi_match` over the profile fields
+    /// - `match` and `multi_match` without `operator` use the default operator
+    /// - `query_string` (and so `?q=`) without `fields` or `default_field`
+    ///   searches the profile fields, and without `default_operator` uses the
+    ///   default operator
+    ///
+    /// Compound queries (`bool`, `nested`, `function_score`) are rewritten
+    /// recursively.
+    pub fn apply(&self, query: &serde_json::Value) -> serde_json::Value {
+        let Some(query_obj) = query.as_object() else {
+            return query.clone();
+        };
+
+        let mut rewritten = serde_json::Map::new();
+        for (query_type, body) in query_obj {
+            let body = match query_type.as_str() {
+                "match" => {
+                    if let Some(multi_match) = self.match_all_fields_to_multi_match(body) {
+                        rewritten.insert("multi_match".to_string(), multi_match);
+                        continue;
+                    }
+                    self.apply_match(body)
+                }
+                "multi_match" => self.apply_multi_match(body),
+                "query_string" => self.apply_query_string(body),
+                "bool" => self.apply_bool(body),
+                "nested" | "function_score" => {
+                    let mut compound = body.clone();
+                    if let Some(inner) = body.get("query") {
+                        compound["query"] = self.apply(inner);
+                    }
+                    compound
+                }
+                _ => body.clone(),
+            };
+            rewritten.insert(query_type.clone(), body);
+        }
+        serde_json::Value::Object(rewritten)
+    }
+
+    /// `{ "match": { "_all": ... } }` as a multi_match over the profile fields
+    fn match_all_fields_to_multi_match(
+        &self,
+        body: &serde_json::Value,
+    ) -> Option<serde_json::Value> {
+        let match_obj = body.as_object()?;
+        if match_obj.len() != 1 {
+            return None;
+        }
+        let (field, value) = match_obj.iter().next()?;
+        if field != "_all" && field != "*" {
+            return None;
+        }
+
+        let mut multi_match = match value {
+            serde_json::Value::Object(options) => options.clone(),
+            text => {
+                let mut options = serde_json::Map::new();
+                options.insert("query".to_string(), text.clone());
+                options
+            }
+        };
+        multi_match.insert("fields".to_string(), serde_json::json!(self.fields));
+        Some(self.apply_multi_match(&serde_json::Value::Object(multi_match)))
+    }
+
+    fn apply_match(&self, body: &serde_json::Value) -> serde_json::Value {
+        let (Some(operator), Some(match_obj)) = (&self.default_operator, body.as_object()) else {
+            return body.clone();
+        };
+
+        let mut rewritten = serde_json::Map::new();
+        for (field, value) in match_obj {
+            let value = match value {
+                serde_json::Value::Object(options) => {
+                    let mut options = options.clone();
+                    options
+                        .entry("operator")
+                        .or_insert_with(|| serde_json::json!(operator));
+                    serde_json::Value::Object(options)
+                }
+                text => serde_json::json!({ "query": text, "operator": operator }),
+            };
+            rewritten.insert(field.clone(), value);
+        }
+        serde_json::Value::Object(rewritten)
+    }
+
+    fn apply_multi_match(&self, body: &serde_json::Value) -> serde_json::Value {
+        let mut multi_match = body.clone();
+        if let Some(options) = multi_match.as_object_mut() {
+            options
+                .entry("fields")
+                .or_insert_with(|| serde_json::json!(self.fields));
+            if let Some(operator) = &self.default_operator {
+                options
+                    .entry("operator")
+                    .or_insert_with(|| serde_json::json!(operator));
+            }
+        }
+        multi_match
+    }
+
+    fn apply_query_string(&self, body: &serde_json::Value) -> serde_json::Value {
+        let mut query_string = body.clone();
+        if let Some(options) = query_string.as_object_mut() {
+            if !options.contains_key("fields") && !options.contains_key("default_field") {
+                options.insert("fields".to_string(), serde_json::json!(self.fields));
+            }
+            if let Some(operator) = &self.default_operator {
+                options
+                    .entry("default_operator")
+                    .or_insert_with(|| serde_json::json!(operator));
+            }
+        }
+        query_string
+    }
+
+    fn apply_bool(&self, body: &serde_json::Value) -> serde_json::Value {
+        let mut bool_query = body.clone();
+        if let Some(bool_obj) = bool_query.as_object_mut() {
+            for occur in ["must", "should", "must_not", "filter"] {
+                match bool_obj.get_mut(occur) {
+                    Some(serde_json::Value::Array(clauses)) => {
+                        for clause in clauses.iter_mut() {
+                            *clause = self.apply(clause);
+                        }
+                    }
+                    Some(clause) => *clause = self.apply(clause),
+                    None => {}
+                }
+            }
+        }
+        bool_query
+    }
+}
@@ -0,0 +1,540 @@
+//! Index settings validation
+//!
+//! Settings are checked against the index settings known from Elasticsearch
+//! 6.8. Keys may be nested (`{"index": {"number_of_replicas": 1}}`), dotted
+//! (`index.number_of_replicas`) or given without the `index.` prefix; they are
+//! compared as flat `index.*` keys. Static settings can only be set when an
+//! index is created, since indices are always open. Keys under `archived.`
+//! (settings Elasticsearch archived on upgrade) are kept without validation.
+
+use serde_json::{Map, Value};
+
+use crate::error::{GbsError, Result};
+
+/// Prefix of archived settings, accepted without validation
+const ARCHIVED_PREFIX: &str = "archived.";
+
+/// Settings managed by the index itself
+const PRIVATE_SETTINGS: [&str; 6] = [
+    "index.creation_date",
+    "index.uuid",
+    "index.version.created",
+    "index.version.upgraded",
+    "index.provided_name",
+    "index.resize.source",
+];
+
+/// Value type of a setting
+#[derive(Debug, Clone, Copy)]
+enum SettingType {
+    Integer {
+        min: i64,
+        max: i64,
+    },
+    Boolean,
+    /// A duration like `30s`, or `-1` to disable
+    Time,
+    /// A size like `512mb`
+    ByteSize,
+    OneOf(&'static [&'static str]),
+    String,
+    /// A string or a list of strings
+    Strings,
+    /// `false` or a range like `0-1` / `0-all`
+    AutoExpandReplicas,
+    /// Any value; the setting is a group of settings under its key
+    Group,
+}
+
+struct Setting {
+    key: &'static str,
+    kind: SettingType,
+    dynamic: bool,
+}
+
+const fn setting(key: &'static str, kind: SettingType, dynamic: bool) -> Setting {
+    Setting { key, kind, dynamic }
+}
+
+const fn count(min: i64) -> SettingType {
+    SettingType::Integer {
+        min,
+        max: i32::MAX as i64,
+    }
+}
+
+const KNOWN_SETTINGS: &[Setting] = &[
+    // Static settings
+    setting(
+        "index.number_of_shards",
+        SettingType::Integer { min: 1, max: 1024 },
+        false,
+    ),
+    setting(
+        "index.codec",
+        SettingType::OneOf(&["default", "best_compression"]),
+        false,
+    ),
+    setting("index.routing_partition_size", count(1), false),
+    setting(
+        "index.shard.check_on_startup",
+        SettingType::OneOf(&["false", "true", "checksum", "fix"]),
+        false,
+    ),
+    setting(
+        "index.load_fixed_bitset_filters_eagerly",
+        SettingType::Boolean,
+        false,
+    ),
+    setting("index.analysis", SettingType::Group, false),
+    setting("index.similarity", SettingType::Group, false),
+    setting("index.sort", SettingType::Group, false),
+    setting("index.store.type", SettingType::String, false),
+    // Dynamic settings
+    setting("index.number_of_replicas", count(0), true),
+    setting(
+        "index.auto_expand_replicas",
+        SettingType::AutoExpandReplicas,
+        true,
+    ),
+    setting("index.refresh_interval", SettingType::Time, true),
+    setting("index.max_result_window", count(1), true),
+    setting("index.max_inner_result_window", count(1), true),
+    setting("index.max_rescore_window", count(1), true),
+    setting("index.max_docvalue_fields_search", count(0), true),
+    setting("index.max_script_fields", count(0), true),
+    setting("index.max_ngram_diff", count(0), true),
+    setting("index.max_shingle_diff", count(0), true),
+    setting("index.max_refresh_listeners", count(0), true),
+    setting("index.max_terms_count", count(1), true),
+    setting("index.max_regex_length", count(1), true),
+    setting("index.highlight.max_analyzed_offset", count(1), true),
+    setting("index.blocks.read_only", SettingType::Boolean, true),
+    setting(
+        "index.blocks.read_only_allow_delete",
+        SettingType::Boolean,
+        true,
+    ),
+    setting("index.blocks.read", SettingType::Boolean, true),
+    setting("index.blocks.write", SettingType::Boolean, true),
+    setting("index.blocks.metadata", SettingType::Boolean, true),
+    setting("index.routing.allocation", SettingType::Group, true),
+    setting(
+        "index.routing.rebalance.enable",
+        SettingType::OneOf(&["all", "primaries", "replicas", "none"]),
+        true,
+    ),
+    setting("index.gc_deletes", SettingType::Time, true),
+    setting("index.default_pipeline", SettingType::String, true),
+    setting("index.mapping.total_fields.limit", count(0), true),
+    setting("index.mapping.depth.limit", count(1), true),
+    setting("index.mapping.nested_fields.limit", count(0), true),
+    setting("index.mapping.nested_objects.limit", count(0), true),
+    setting("index.mapping.ignore_malformed", SettingType::Boolean, true),
+    setting("index.mapping.coerce", SettingType::Boolean, true),
+    // Reject values that do not fit their mapped type (see `mapping_validation`)
+    setting("index.mapping.validate", SettingType::Boolean, true),
+    setting(
+        "index.translog.durability",
+        SettingType::OneOf(&["request", "async"]),
+        true,
+    ),
+    setting("index.translog.sync_interval", SettingType::Time, true),
+    setting(
+        "index.translog.flush_threshold_size",
+        SettingType::ByteSize,
+        true,
+    ),
+    setting("index.search.slowlog", SettingType::Group, true),
+    setting("index.indexing.slowlog", SettingType::Group, true),
+    setting(
+        "index.unassigned.node_left.delayed_timeout",
+        SettingType::Time,
+        true,
+    ),
+    setting("index.priority", count(0), true),
+    setting("index.query.default_field", SettingType::Strings, true),
+    // Search requests run after load and refresh (see `warmers`)
+    setting("index.warmers", SettingType::Group, true),
+    // Document and index retention (see `retention`)
+    setting("index.retention.field", SettingType::String, true),
+    setting("index.retention.max_document_age", SettingType::Time, true),
+    setting("index.retention.rollover_after", SettingType::Time, true),
+    setting("index.retention.delete_after", SettingType::Time, true),
+];
+
+/// Validate the settings of a new index
+pub fn validate_new_settings(settings: &Value) -> Result<()> {
+    for (key, value) in flatten_settings(settings)? {
+        if !value.is_null() {
+            check_setting(&key, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Validate a settings update of an existing (open) index
+pub fn validate_settings_update(index_name: &str, settings: &Value) -> Result<()> {
+    let mut static_keys = Vec::new();
+    for (key, value) in flatten_settings(settings)? {
+        let Some(setting) = check_key(&key)? else {
+            continue;
+        };
+        if !setting.dynamic {
+            static_keys.push(key);
+            continue;
+        }
+        if !value.is_null() {
+            check_value(&key, setting.kind, &value)?;
+        }
+    }
+    if !static_keys.is_empty() {
+        return Err(GbsError::IllegalArgument(format!(
+            "Can't update non dynamic settings [[{}]] for open indices [[{}]]",
+            static_keys.join(", "),
+            index_name
+        )));
+    }
+    Ok(())
+}
+
+/// Merge a settings update into the existing settings
+///
+/// Each updated setting replaces the existing one, whatever form the key was
+/// written in; `null` removes it (resetting it to its default), or every
+/// setting of a group.
+pub fn merge_settings(existing: &mut Value, update: &Value) {
+    if !existing.is_object() {
+        *existing = Value::Object(Map::new());
+    }
+    let mut updates = Vec::new();
+    collect_leaves(update, &mut Vec::new(), &mut updates);
+    let mut leaves = Vec::new();
+    collect_leaves(existing, &mut Vec::new(), &mut leaves);
+    let current: Vec<Vec<String>> = leaves.into_iter().map(|(path, _)| path).collect();
+
+    for (path, value) in updates {
+        let key = canonical_key(&path);
+        let removed = |p: &&Vec<String>| {
+            let existing_key = canonical_key(p);
+            existing_key == key || (value.is_null() && covers(&key, &existing_key))
+        };
+        for existing_path in current.iter().filter(removed) {
+            remove_path(existing, existing_path);
+        }
+        if !value.is_null() {
+            insert_path(existing, &path, value.clone());
+        }
+    }
+}
+
+/// The value of a setting, whatever form its key was written in
+///
+/// `key` is the flat key, e.g. `index.number_of_replicas`.
+pub fn setting_value<'a>(settings: &'a Value, key: &str) -> Option<&'a Value> {
+    let mut leaves = Vec::new();
+    collect_leaves(settings, &mut Vec::new(), &mut leaves);
+    leaves
+        .into_iter()
+        .find(|(path, _)| canonical_key(path) == key)
+        .map(|(_, value)| value)
+}
+
+/// The settings of a group as nested objects, keyed below the group
+///
+/// The group's settings may be written in any key form, e.g.
+/// `{"index": {"warmers": {"errors": {...}}}}` or `{"warmers.errors": {...}}`.
+pub fn setting_group(settings: &Value, group: &str) -> Map<String, Value> {
+    let mut grouped = Value::Object(Map::new());
+    let mut leaves = Vec::new();
+    collect_leaves(settings, &mut Vec::new(), &mut leaves);
+    let prefix = format!("{}.", group);
+    for (path, value) in leaves {
+        // Find the key segment in which the group key ends
+        for end in 1..=path.len() {
+            let key = canonical_key(&path[..end]);
+            let mut sub_path: Vec<String> = match key.strip_prefix(&prefix) {
+                Some(rest) => rest.split('.').map(str::to_string).collect(),
+                None if key == group => Vec::new(),
+                None => continue,
+            };
+            sub_path.extend(path[end..].iter().cloned());
+            insert_path(&mut grouped, &sub_path, value.clone());
+            break;
+        }
+    }
+    match grouped {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Check a setting key and value
+fn check_setting(key: &str, value: &Value) -> Result<()> {
+    match check_key(key)? {
+        Some(setting) => check_value(key, setting.kind, value),
+        None => Ok(()),
+    }
+}
+
+/// Find the definition of a setting key; `None` for archived settings
+fn check_key(key: &str) -> Result<Option<&'static Setting>> {
+    if key.starts_with(ARCHIVED_PREFIX) {
+        return Ok(None);
+    }
+    if PRIVATE_SETTINGS.iter().any(|private| covers(private, key)) {
+        return Err(GbsError::IllegalArgument(format!(
+            "private index setting [{}] can not be set explicitly",
+            key
+        )));
+    }
+    KNOWN_SETTINGS
+        .iter()
+        .find(|setting| covers(setting.key, key))
+        .map(Some)
+        .ok_or_else(|| {
+            GbsError::IllegalArgument(format!(
+                "unknown setting [{}] please check that any required plugins are installed, \
+                 or check the breaking changes documentation for removed settings",
+                key
+            ))
+        })
+}
+
+/// Whether `key` is the setting `name` or a setting of the group `name`
+fn covers(name: &str, key: &str) -> bool {
+    key == name
+        || key
+            .strip_prefix(name)
+            .is_some_and(|rest| rest.starts_with('.'))
+}
+
+fn check_value(key: &str, kind: SettingType, value: &Value) -> Result<()> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(_) | Value::Bool(_) => value.to_string(),
+        _ if matches!(kind, SettingType::Group | SettingType::Strings) => String::new(),
+        _ => return Err(parse_error(key, &value.to_string(), None)),
+    };
+
+    let valid = match kind {
+        SettingType::Integer { min, max } => {
+            let number = text
+                .parse::<i64>()
+                .map_err(|_| parse_error(key, &text, None))?;
+            if number < min {
+                return Err(parse_error(key, &text, Some(format!("must be >= {}", min))));
+            }
+            if number > max {
+                return Err(parse_error(key, &text, Some(format!("must be <= {}", max))));
+            }
+            true
+        }
+        SettingType::Boolean => {
+            if text != "true" && text != "false" {
+                return Err(GbsError::IllegalArgument(format!(
+                    "Failed to parse value [{}] as only [true] or [false] are allowed.",
+                    text
+                )));
+            }
+            true
+        }
+        SettingType::Time => {
+            if !is_time_value(&text) {
+                return Err(GbsError::IllegalArgument(format!(
+                    "failed to parse setting [{}] with value [{}] as a time value: \
+                     unit is missing or unrecognized",
+                    key, text
+                )));
+            }
+            true
+        }
+        SettingType::ByteSize => {
+            if !is_byte_size(&text) {
+                return Err(GbsError::IllegalArgument(format!(
+                    "failed to parse setting [{}] with value [{}] as a size in bytes: \
+                     unit is missing or unrecognized",
+                    key, text
+                )));
+            }
+            true
+        }
+        SettingType::OneOf(allowed) => allowed.contains(&text.to_lowercase().as_str()),
+        SettingType::String => value.is_string(),
+        SettingType::Strings => match value {
+            Value::String(_) => true,
+            Value::Array(items) => items.iter().all(Value::is_string),
+            _ => false,
+        },
+        SettingType::AutoExpandReplicas => text == "false" || is_replica_range(&text),
+        SettingType::Group => true,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(parse_error(key, &text, None))
+    }
+}
+
+fn parse_error(key: &str, value: &str, reason: Option<String>) -> GbsError {
+    let mut message = format!("Failed to parse value [{}] for setting [{}]", value, key);
+    if let Some(reason) = reason {
+        message.push(' ');
+        message.push_str(&reason);
+    }
+    GbsError::IllegalArgument(message)
+}
+
+/// Time values: `-1`, `0`, or a number with a unit like `30s`
+fn is_time_value(text: &str) -> bool {
+    if text == "-1" || text == "0" {
+        return true;
+    }
+    ["nanos", "micros", "ms", "s", "m", "h", "d"]
+        .iter()
+        .any(|unit| is_number_with_unit(text, unit))
+}
+
+/// Milliseconds of a time value like `30s` or `7d`
+///
+/// `None` for `-1` (disabled) and values that are not time values.
+pub fn time_value_millis(value: &Value) -> Option<u64> {
+    let text = value.as_str()?;
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let amount: f64 = text[..split].parse().ok()?;
+    let unit_millis = match &text[split..] {
+        "nanos" => 0.000_001,
+        "micros" => 0.001,
+        "ms" => 1.0,
+        "s" => 1000.0,
+        "m" => 60.0 * 1000.0,
+        "h" => 60.0 * 60.0 * 1000.0,
+        "d" => 24.0 * 60.0 * 60.0 * 1000.0,
+        _ => return None,
+    };
+    Some((amount * unit_millis) as u64)
+}
+
+/// Byte sizes: a number with a unit like `512mb`
+fn is_byte_size(text: &str) -> bool {
+    let text = text.to_lowercase();
+    text == "-1"
+        || text == "0"
+        || ["b", "kb", "mb", "gb", "tb", "pb"]
+            .iter()
+            .any(|unit| is_number_with_unit(&text, unit))
+}
+
+/// Bytes of a byte size like `512mb`
+///
+/// `None` for `-1` (disabled) and values that are not byte sizes.
+pub fn byte_size_bytes(value: &Value) -> Option<u64> {
+    let text = value.as_str()?.to_lowercase();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.')?;
+    let amount: f64 = text[..split].parse().ok()?;
+    let unit_bytes = match &text[split..] {
+        "b" => 1u64,
+        "kb" => 1 << 10,
+        "mb" => 1 << 20,
+        "gb" => 1 << 30,
+        "tb" => 1 << 40,
+        "pb" => 1 << 50,
+        _ => return None,
+    };
+    Some((amount * unit_bytes as f64) as u64)
+}
+
+fn is_number_with_unit(text: &str, unit: &str) -> bool {
+    text.strip_suffix(unit)
+        .is_some_and(|number| !number.is_empty() && number.parse::<f64>().is_ok_and(|n| n >= 0.0))
+}
+
+/// Replica ranges like `0-5` or `0-all`
+fn is_replica_range(text: &str) -> bool {
+    text.split_once('-').is_some_and(|(min, max)| {
+        min.parse::<u32>().is_ok() && (max == "all" || max.parse::<u32>().is_ok())
+    })
+}
+
+/// Flatten settings into canonical `index.*` keys and their values
+fn flatten_settings(settings: &Value) -> Result<Vec<(String, Value)>> {
+    if !settings.is_object() {
+        return Err(GbsError::IllegalArgument(
+            "index settings must be an object".to_string(),
+        ));
+    }
+    let mut leaves = Vec::new();
+    collect_leaves(settings, &mut Vec::new(), &mut leaves);
+    Ok(leaves
+        .into_iter()
+        .map(|(path, value)| (canonical_key(&path), value.clone()))
+        .collect())
+}
+
+/// Collect the non-object values of nested settings with their paths
+fn collect_leaves<'a>(
+    value: &'a Value,
+    path: &mut Vec<String>,
+    leaves: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    match value {
+        Value::Object(map) if !map.is_empty() || path.is_empty() => {
+            for (key, child) in map {
+                path.push(key.clone());
+                collect_leaves(child, path, leaves);
+                path.pop();
+            }
+        }
+        _ => leaves.push((path.clone(), value)),
+    }
+}
+
+/// Flat key of a settings path, with the implied `index.` prefix
+fn canonical_key(path: &[String]) -> String {
+    let key = path.join(".");
+    if key.starts_with("index.") || key.starts_with(ARCHIVED_PREFIX) {
+        key
+    } else {
+        format!("index.{}", key)
+    }
+}
+
+fn remove_path(value: &mut Value, path: &[String]) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    let Some(map) = value.as_object_mut() else {
+        return;
+    };
+    if rest.is_empty() {
+        map.remove(first);
+        return;
+    }
+    if let Some(child) = map.get_mut(first) {
+        remove_path(child, rest);
+        // Drop objects emptied by the removal
+        if child.as_object().is_some_and(Map::is_empty) {
+            map.remove(first);
+        }
+    }
+}
+
+fn insert_path(value: &mut Value, path: &[String], leaf: Value) {
+    let Some((first, rest)) = path.split_first() else {
+        return;
+    };
+    if !value.is_object() {
+        *value = Value::Object(Map::new());
+    }
+    let map = value.as_object_mut().expect("value was made an object");
+    if rest.is_empty() {
+        map.insert(first.clone(), leaf);
+    } else {
+        insert_path(
+            map.entry(first.clone())
+                .or_insert_with(|| Value::Object(Map::new())),
+            rest,
+            leaf,
+        );
+    }
+}
@@ -2,10 +2,252 @@
 
 use chrono::Utc;
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::RwLock;
 
-use crate::storage::Index;
+use crate::error::{GbsError, Result};
+use crate::storage::settings::setting_value;
+use crate::storage::{FilterCacheStats, Index, IndexState, IndexTier};
+
+/// Indexing and search counters of an index since it was created or loaded
+///
+/// Shared with copies of the index and updated under its read lock. Times are
+/// kept in microseconds so that fast operations still add up.
+#[derive(Debug, Default)]
+pub struct OperationCounters {
+    index_total: AtomicU64,
+    index_time_micros: AtomicU64,
+    delete_total: AtomicU64,
+    delete_time_micros: AtomicU64,
+    query_total: AtomicU64,
+    query_time_micros: AtomicU64,
+}
+
+impl OperationCounters {
+    /// Count documents written in `elapsed`
+    pub fn record_index(&self, count: u64, elapsed: Duration) {
+        self.index_total.fetch_add(count, Ordering::Relaxed);
+        self.index_time_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Count documents deleted in `elapsed`
+    pub fn record_delete(&self, count: u64, elapsed: Duration) {
+        self.delete_total.fetch_add(count, Ordering::Relaxed);
+        self.delete_time_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    /// Count a search that took `elapsed`
+    pub fn record_query(&self, elapsed: Duration) {
+        self.query_total.fetch_add(1, Ordering::Relaxed);
+        self.query_time_micros
+            .fetch_add(elapsed.as_micros() as u64, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> OperationStats {
+        let millis = |micros: &AtomicU64| micros.load(Ordering::Relaxed) / 1000;
+        OperationStats {
+            index_total: self.index_total.load(Ordering::Relaxed),
+            index_time_in_millis: millis(&self.index_time_micros),
+            delete_total: self.delete_total.load(Ordering::Relaxed),
+            delete_time_in_millis: millis(&self.delete_time_micros),
+            query_total: self.query_total.load(Ordering::Relaxed),
+            query_time_in_millis: millis(&self.query_time_micros),
+        }
+    }
+}
+
+/// Indexing and search counts of an index, as reported by `_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OperationStats {
+    pub index_total: u64,
+    pub index_time_in_millis: u64,
+    pub delete_total: u64,
+    pub delete_time_in_millis: u64,
+    pub query_total: u64,
+    pub query_time_in_millis: u64,
+}
+
+impl std::ops::AddAssign for OperationStats {
+    fn add_assign(&mut self, other: Self) {
+        self.index_total += other.index_total;
+        self.index_time_in_millis += other.index_time_in_millis;
+        self.delete_total += other.delete_total;
+        self.delete_time_in_millis += other.delete_time_in_millis;
+        self.query_total += other.query_total;
+        self.query_time_in_millis += other.query_time_in_millis;
+    }
+}
+
+/// Number of nodes of the cluster, which is the local node alone
+pub const NUMBER_OF_NODES: u64 = 1;
+
+/// Health of an index or of the cluster
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthStatus {
+    Green,
+    Yellow,
+}
+
+impl HealthStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            HealthStatus::Green => "green",
+            HealthStatus::Yellow => "yellow",
+        }
+    }
+}
+
+/// Replicas of a shard left unassigned, as no node holds two copies of it
+fn unassigned_replicas(replicas: u64) -> u64 {
+    replicas.saturating_sub(NUMBER_OF_NODES - 1)
+}
+
+/// Statistics of one index, as listed by the cat and stats APIs
+#[derive(Debug, Clone)]
+pub struct IndexStats {
+    pub name: String,
+    pub docs_count: usize,
+    /// Estimated size of the documents (serialized JSON bytes)
+    pub size_in_bytes: u64,
+    /// `index.number_of_shards` (default 1)
+    pub primaries: u64,
+    /// `index.number_of_replicas` (default 1)
+    pub replicas: u64,
+    pub aliases: Vec<String>,
+    pub tier: IndexTier,
+    pub state: IndexState,
+    pub creation_date: Option<u64>,
+    pub operations: OperationStats,
+    pub filter_cache: FilterCacheStats,
+}
+
+impl IndexStats {
+    /// Shards that cannot be allocated: replicas need a node of their own
+    pub fn unassigned_shards(&self) -> u64 {
+        self.primaries * unassigned_replicas(self.replicas)
+    }
+
+    /// Yellow while replicas are unassigned, as primaries are always started
+    pub fn health(&self) -> HealthStatus {
+        if self.unassigned_shards() > 0 {
+            HealthStatus::Yellow
+        } else {
+            HealthStatus::Green
+        }
+    }
+}
+
+/// Status and shard counts reported by the cluster health APIs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClusterHealth {
+    pub status: HealthStatus,
+    pub active_primary_shards: u64,
+    pub active_shards: u64,
+    pub unassigned_shards: u64,
+}
+
+impl ClusterHealth {
+    /// Health of the open indices; closed indices have no shards
+    pub fn of(stats: &[IndexStats]) -> Self {
+        let open = stats
+            .iter()
+            .filter(|index| index.state != IndexState::Close);
+        let (primaries, unassigned) = open.fold((0, 0), |(primaries, unassigned), index| {
+            (
+                primaries + index.primaries,
+                unassigned + index.unassigned_shards(),
+            )
+        });
+        ClusterHealth {
+            status: if unassigned > 0 {
+                HealthStatus::Yellow
+            } else {
+                HealthStatus::Green
+            },
+            active_primary_shards: primaries,
+            // Only the primaries are allocated on the single node
+            active_shards: primaries,
+            unassigned_shards: unassigned,
+        }
+    }
+
+    /// Share of the shards that are started, 100 without any shard
+    pub fn active_shards_percent(&self) -> f64 {
+        let total = self.active_shards + self.unassigned_shards;
+        if total == 0 {
+            100.0
+        } else {
+            self.active_shards as f64 * 100.0 / total as f64
+        }
+    }
+}
+
+/// A count setting of an index, or `default` if it is not set
+fn count_setting(index: &Index, key: &str, default: u64) -> u64 {
+    index
+        .settings
+        .as_ref()
+        .and_then(|settings| setting_value(settings, key))
+        .and_then(|value| match value {
+            serde_json::Value::String(s) => s.parse().ok(),
+            value => value.as_u64(),
+        })
+        .unwrap_or(default)
+}
+
+/// Number of primary shards of an index (`index.number_of_shards`, default 1)
+///
+/// Shards only shape responses: every index is held as a whole on the local
+/// node.
+pub fn number_of_shards(index: &Index) -> u64 {
+    count_setting(index, "index.number_of_shards", 1).max(1)
+}
+
+/// Number of replicas of each shard of an index (`index.number_of_replicas`,
+/// default 1)
+fn number_of_replicas(index: &Index) -> u64 {
+    count_setting(index, "index.number_of_replicas", 1)
+}
+
+/// Get the number of primary shards of an index
+pub async fn get_number_of_shards(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+) -> Result<u64> {
+    indices
+        .read()
+        .await
+        .get(index_name)
+        .map(number_of_shards)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))
+}
+
+/// Get the statistics of every index, sorted by name
+pub async fn get_index_stats(indices: &Arc<RwLock<HashMap<String, Index>>>) -> Vec<IndexStats> {
+    let indices_guard = indices.read().await;
+    let mut stats: Vec<IndexStats> = indices_guard
+        .values()
+        .map(|index| IndexStats {
+            name: index.name.clone(),
+            docs_count: index.doc_count(),
+            size_in_bytes: index.size_in_bytes,
+            primaries: number_of_shards(index),
+            replicas: number_of_replicas(index),
+            aliases: index.aliases.clone(),
+            tier: index.tier,
+            state: index.state,
+            creation_date: index.creation_date,
+            operations: index.operations.snapshot(),
+            filter_cache: index.filter_cache.stats(),
+        })
+        .collect();
+    stats.sort_by(|a, b| a.name.cmp(&b.name));
+    stats
+}
 
 /// Get cluster statistics
 pub async fn get_cluster_stats(
@@ -14,29 +256,45 @@ pub async fn get_cluster_stats(
 ) -> serde_json::Value {
     let indices_guard = indices.read().await;
     let total_indices = indices_guard.len();
-    let total_docs: usize = indices_guard.values().map(|idx| idx.documents.len()).sum();
+    let status = if indices_guard.values().any(|index| {
+        index.state != IndexState::Close && unassigned_replicas(number_of_replicas(index)) > 0
+    }) {
+        HealthStatus::Yellow
+    } else {
+        HealthStatus::Green
+    };
+    let total_docs: usize = indices_guard.values().map(|idx| idx.doc_count()).sum();
+    let shards: Vec<u64> = indices_guard.values().map(number_of_shards).collect();
+    let total_shards: u64 = shards.iter().sum();
+    let min_shards = shards.iter().min().copied().unwrap_or(0);
+    let max_shards = shards.iter().max().copied().unwrap_or(0);
+    let avg_shards = if shards.is_empty() {
+        0.0
+    } else {
+        total_shards as f64 / shards.len() as f64
+    };
 
     serde_json::json!({
         "cluster_name": "gbs",
         "cluster_uuid": "gbs-cluster",
         "timestamp": Utc::now().timestamp_millis(),
-        "status": "green",
+        "status": status.as_str(),
         "indices": {
             "count": total_indices,
             "shards": {
-                "total": total_indices,
-                "primaries": total_indices,
+                "total": total_shards,
+                "primaries": total_shards,
                 "replication": 0,
                 "index": {
                     "shards": {
-                        "min": 1,
-                        "max": 1,
-                        "avg": 1.0
+                        "min": min_shards,
+                        "max": max_shards,
+                        "avg": avg_shards
                     },
                     "primaries": {
-                        "min": 1,
-                        "max": 1,
-                        "avg": 1.0
+                        "min": min_shards,
+                        "max": max_shards,
+                        "avg": avg_shards
                     },
                     "replication": {
                         "min": 0,
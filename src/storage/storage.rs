@@ -3,6 +3,7 @@
 //! Manages indices, documents, and provides search functionality.
 //! Supports both in-memory and persistent (Sled) storage backends.
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::Arc;
@@ -10,16 +11,42 @@ use tokio::sync::RwLock;
 use tracing::info;
 
 use crate::bulk_ops::BulkAction;
-use crate::error::Result;
-use crate::storage::Index;
+use crate::config::Durability;
+use crate::document_cache::DocumentCacheStats;
+use crate::error::{GbsError, Result};
+use crate::storage::aggregation_cache::AggregationCache;
+use crate::storage::{
+    AggregationCacheStats, ClusterSettings, DocvalueField, DynamicMode, Explanation, Federation,
+    FieldCapability, Index, IndexState, IndexTier, IngestRoutes, Script, SearchProfile,
+    StorageLimits, StoredFields, WriteOutcome,
+};
 use crate::storage_backend::SledBackend;
+use crate::tasks::TaskHandle;
 
 // Import operations from submodules
+use crate::storage::changes::*;
+use crate::storage::checkpoint::*;
+use crate::storage::cluster_settings::*;
+use crate::storage::delete_by_query::*;
 use crate::storage::document_ops::*;
+use crate::storage::durability::*;
+use crate::storage::federation::*;
+use crate::storage::field_caps::*;
+use crate::storage::filter_cache::clear_filter_caches;
 use crate::storage::index_ops::*;
+use crate::storage::index_state::*;
 use crate::storage::persistence::*;
+use crate::storage::pipelines::*;
+use crate::storage::refresh::*;
+use crate::storage::reindex::*;
+use crate::storage::retention::*;
+use crate::storage::rollover::*;
+use crate::storage::scripts::*;
 use crate::storage::search_impl::*;
 use crate::storage::stats::*;
+use crate::storage::templates::*;
+use crate::storage::tiering::*;
+use crate::storage::warmers::*;
 
 /// Main Storage struct for Gummy Bear Search
 ///
@@ -29,6 +56,17 @@ use crate::storage::stats::*;
 pub struct Storage {
     indices: Arc<RwLock<HashMap<String, Index>>>,
     pub(crate) backend: Option<Arc<SledBackend>>,
+    limits: StorageLimits,
+    routes: Arc<IngestRoutes>,
+    aggregation_cache: Arc<AggregationCache>,
+    durability: Durability,
+    federation: Arc<Federation>,
+    templates: Arc<IndexTemplates>,
+    scripts: Arc<StoredScripts>,
+    pipelines: Arc<IngestPipelines>,
+    cluster_settings: Arc<ClusterSettings>,
+    scoring: ParallelScoring,
+    lazy_loading: bool,
 }
 
 impl Storage {
@@ -37,6 +75,17 @@ impl Storage {
         Self {
             indices: Arc::new(RwLock::new(HashMap::new())),
             backend: None,
+            limits: StorageLimits::default(),
+            routes: Arc::new(IngestRoutes::default()),
+            aggregation_cache: Arc::new(AggregationCache::default()),
+            durability: Durability::default(),
+            federation: Arc::new(Federation::default()),
+            templates: Arc::new(IndexTemplates::default()),
+            scripts: Arc::new(StoredScripts::default()),
+            pipelines: Arc::new(IngestPipelines::default()),
+            cluster_settings: Arc::new(ClusterSettings::default()),
+            scoring: ParallelScoring::default(),
+            lazy_loading: false,
         }
     }
 
@@ -49,22 +98,190 @@ impl Storage {
         Ok(Self {
             indices: Arc::new(RwLock::new(HashMap::new())),
             backend: Some(backend),
+            limits: StorageLimits::default(),
+            routes: Arc::new(IngestRoutes::default()),
+            aggregation_cache: Arc::new(AggregationCache::default()),
+            durability: Durability::default(),
+            federation: Arc::new(Federation::default()),
+            templates: Arc::new(IndexTemplates::default()),
+            scripts: Arc::new(StoredScripts::default()),
+            pipelines: Arc::new(IngestPipelines::default()),
+            cluster_settings: Arc::new(ClusterSettings::default()),
+            scoring: ParallelScoring::default(),
+            lazy_loading: false,
         })
     }
 
+    /// Set the index count and automatic rollover limits
+    pub fn with_limits(mut self, limits: StorageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Set the ingest routes that map logical write targets to derived indices
+    pub fn with_ingest_routes(mut self, routes: IngestRoutes) -> Self {
+        self.routes = Arc::new(routes);
+        self
+    }
+
+    /// Set when document writes are flushed to disk
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// Set when the documents of large indices are scored on several threads
+    pub fn with_parallel_scoring(mut self, scoring: ParallelScoring) -> Self {
+        self.scoring = scoring;
+        self
+    }
+
+    /// Leave the documents of hot indices on disk when loading from the
+    /// backend, reading them on demand until an index is moved to the hot tier
+    pub fn with_lazy_loading(mut self, lazy_loading: bool) -> Self {
+        self.lazy_loading = lazy_loading;
+        self
+    }
+
+    /// Set the memory budget of the cache of documents read from disk
+    pub fn with_document_cache_budget(self, bytes: u64) -> Self {
+        if let Some(backend) = &self.backend {
+            backend.set_document_cache_budget(bytes);
+        }
+        self
+    }
+
+    /// Set the indices served read-through from external sources
+    pub fn with_federation(mut self, federation: Federation) -> Self {
+        self.federation = Arc::new(federation);
+        self
+    }
+
+    /// Create the local indices of federated indices that do not exist yet
+    pub async fn create_federated_indices(&self) -> Result<()> {
+        create_federated_indices(&self.indices, &self.backend, &self.limits, &self.federation).await
+    }
+
+    /// Refresh the cached documents of a federated index if they are stale
+    async fn read_through(&self, index_name: &str) -> Result<()> {
+        read_through(
+            &self.indices,
+            &self.backend,
+            &self.limits,
+            &self.federation,
+            index_name,
+        )
+        .await
+    }
+
+    /// Take a consistent point-in-time view of all indices
+    ///
+    /// Documents are not copied, so writers are only held up for as long as
+    /// it takes to capture the index map.
+    pub async fn checkpoint(&self) -> Checkpoint {
+        checkpoint(&self.indices, &self.backend).await
+    }
+
+    /// Write a consistent backup of all indices to a new data directory
+    pub async fn backup(&self, path: impl AsRef<Path>) -> Result<BackupReport> {
+        let checkpoint = self.checkpoint().await;
+        let path = path.as_ref().to_path_buf();
+        tokio::task::spawn_blocking(move || checkpoint.write_backup(&path))
+            .await
+            .map_err(GbsError::TaskJoin)?
+    }
+
     /// Flush pending writes to disk (for persistent storage)
     pub async fn flush(&self) -> Result<()> {
         flush(&self.backend).await
     }
 
-    /// Refresh an index (flush changes to persistent storage)
+    /// Refresh an index (make its held-back writes searchable and flush
+    /// changes to persistent storage), then run its warmers
     pub async fn refresh_index(&self, index_name: &str) -> Result<()> {
-        refresh_index(&self.indices, &self.backend, index_name).await
+        refresh_index(&self.indices, &self.backend, index_name).await?;
+        self.warm_up(index_name).await;
+        Ok(())
     }
 
-    /// Load indices from backend (call this after creating with sled)
+    /// Wait until the writes made so far to an index are searchable
+    /// (`refresh=wait_for`; see `refresh`)
+    pub async fn wait_for_refresh(&self, index_name: &str) -> Result<()> {
+        wait_for_refresh(&self.indices, index_name).await
+    }
+
+    /// Force-merge indices, optionally flushing the backend
+    pub async fn force_merge(&self, index_names: &[String], flush_backend: bool) -> Result<()> {
+        force_merge(&self.indices, &self.backend, index_names, flush_backend).await
+    }
+
+    /// Refresh all indices, then run their warmers
+    pub async fn refresh_all(&self) -> Result<()> {
+        refresh_all_documents(&self.indices).await;
+        flush(&self.backend).await?;
+        self.warm_up_all().await;
+        Ok(())
+    }
+
+    /// Load indices from backend (call this after creating with sled), then
+    /// run their warmers
     pub async fn load_from_backend(&self) -> Result<()> {
-        load_from_backend(&self.indices, &self.backend).await
+        load_from_backend(&self.indices, &self.backend, self.lazy_loading).await?;
+        load_templates(&self.templates, &self.backend).await?;
+        load_scripts(&self.scripts, &self.backend).await?;
+        load_pipelines(&self.pipelines, &self.backend).await?;
+        load_cluster_settings(&self.cluster_settings, &self.backend).await?;
+        self.warm_up_all().await;
+        Ok(())
+    }
+
+    /// Run the warmers of an index
+    pub async fn warm_up(&self, index_name: &str) -> WarmupReport {
+        run_warmers(
+            &self.indices,
+            &self.backend,
+            &self.aggregation_cache,
+            self.scoring,
+            index_name,
+        )
+        .await
+    }
+
+    /// Run the warmers of every index
+    pub async fn warm_up_all(&self) -> Vec<WarmupReport> {
+        let mut reports = Vec::new();
+        for index_name in self.list_indices().await {
+            reports.push(self.warm_up(&index_name).await);
+        }
+        reports
+    }
+
+    /// Add or replace a warmer of an index
+    pub async fn put_warmer(
+        &self,
+        index_name: &str,
+        name: &str,
+        body: serde_json::Value,
+    ) -> Result<()> {
+        put_warmer(&self.indices, &self.backend, index_name, name, body).await
+    }
+
+    /// Get the warmers of an index, by name
+    pub async fn get_warmers(
+        &self,
+        index_name: &str,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        self.indices
+            .read()
+            .await
+            .get(index_name)
+            .map(index_warmers)
+            .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))
+    }
+
+    /// Remove a warmer from an index
+    pub async fn delete_warmer(&self, index_name: &str, name: &str) -> Result<()> {
+        delete_warmer(&self.indices, &self.backend, index_name, name).await
     }
 
     // Index operations
@@ -74,7 +291,177 @@ impl Storage {
         settings: Option<serde_json::Value>,
         mappings: Option<serde_json::Value>,
     ) -> Result<()> {
-        create_index(&self.indices, &self.backend, name, settings, mappings).await
+        create_index(
+            &self.indices,
+            &self.backend,
+            &self.limits,
+            &self.templates,
+            name,
+            settings,
+            mappings,
+        )
+        .await
+    }
+
+    /// Add an alias to an index
+    pub async fn put_alias(&self, index_name: &str, alias: &str) -> Result<()> {
+        put_alias(&self.indices, &self.backend, index_name, alias).await
+    }
+
+    /// Remove an alias from an index
+    pub async fn delete_alias(&self, index_name: &str, alias: &str) -> Result<()> {
+        delete_alias(&self.indices, &self.backend, index_name, alias).await
+    }
+
+    /// Create or replace a search profile, returning whether it was created
+    pub async fn put_search_profile(
+        &self,
+        index_name: &str,
+        profile_name: &str,
+        profile: SearchProfile,
+    ) -> Result<bool> {
+        put_search_profile(
+            &self.indices,
+            &self.backend,
+            index_name,
+            profile_name,
+            profile,
+        )
+        .await
+    }
+
+    /// Get all search profiles of an index
+    pub async fn get_search_profiles(
+        &self,
+        index_name: &str,
+    ) -> Result<HashMap<String, SearchProfile>> {
+        get_search_profiles(&self.indices, index_name).await
+    }
+
+    /// Get a search profile by name
+    pub async fn get_search_profile(
+        &self,
+        index_name: &str,
+        profile_name: &str,
+    ) -> Result<SearchProfile> {
+        get_search_profile(&self.indices, index_name, profile_name).await
+    }
+
+    /// Delete a search profile
+    pub async fn delete_search_profile(&self, index_name: &str, profile_name: &str) -> Result<()> {
+        delete_search_profile(&self.indices, &self.backend, index_name, profile_name).await
+    }
+
+    /// Create or replace an index template
+    pub async fn put_template(
+        &self,
+        kind: TemplateKind,
+        name: &str,
+        template: IndexTemplate,
+    ) -> Result<()> {
+        put_template(&self.templates, &self.backend, kind, name, template).await
+    }
+
+    /// Get the index templates of a kind whose name matches a pattern
+    pub fn get_templates(&self, kind: TemplateKind, pattern: &str) -> Vec<(String, IndexTemplate)> {
+        self.templates.get(kind, pattern)
+    }
+
+    /// Delete an index template
+    pub async fn delete_template(&self, kind: TemplateKind, name: &str) -> Result<()> {
+        delete_template(&self.templates, &self.backend, kind, name).await
+    }
+
+    /// Settings, mappings and aliases the index templates give a new index
+    pub fn resolve_templates(&self, index_name: &str) -> ResolvedTemplate {
+        self.templates.resolve(index_name)
+    }
+
+    /// Create or replace a stored script
+    pub async fn put_script(&self, id: &str, script: StoredScript) -> Result<()> {
+        put_script(&self.scripts, &self.backend, id, script).await
+    }
+
+    /// Get a stored script
+    pub fn get_script(&self, id: &str) -> Option<StoredScript> {
+        self.scripts.get(id)
+    }
+
+    /// Delete a stored script
+    pub async fn delete_script(&self, id: &str) -> Result<()> {
+        delete_script(&self.scripts, &self.backend, id).await
+    }
+
+    /// Cluster settings (`GET /_cluster/settings`)
+    pub fn cluster_settings(&self) -> &ClusterSettings {
+        &self.cluster_settings
+    }
+
+    /// Update the cluster settings (`PUT /_cluster/settings`), returning the
+    /// acknowledgement
+    pub async fn update_cluster_settings(
+        &self,
+        body: &serde_json::Value,
+        flat: bool,
+    ) -> Result<serde_json::Value> {
+        update_cluster_settings(&self.cluster_settings, &self.backend, body, flat).await
+    }
+
+    /// Render the search body of a search template request (`id` or
+    /// `source`, with `params`)
+    pub fn render_search_template(&self, request: &serde_json::Value) -> Result<serde_json::Value> {
+        self.scripts.render_search_template(request)
+    }
+
+    /// Create or replace an ingest pipeline
+    pub async fn put_pipeline(&self, id: &str, pipeline: IngestPipeline) -> Result<()> {
+        put_pipeline(&self.pipelines, &self.backend, id, pipeline).await
+    }
+
+    /// Get the ingest pipelines whose id matches a pattern
+    pub fn get_pipelines(&self, pattern: &str) -> Vec<(String, IngestPipeline)> {
+        self.pipelines.get(pattern)
+    }
+
+    /// Delete an ingest pipeline
+    pub async fn delete_pipeline(&self, id: &str) -> Result<()> {
+        delete_pipeline(&self.pipelines, &self.backend, id).await
+    }
+
+    /// Run a document through an ingest pipeline
+    pub fn run_pipeline(&self, id: &str, doc: serde_json::Value) -> Result<serde_json::Value> {
+        self.pipelines.run(id, doc)
+    }
+
+    /// Move an index to the hot or warm tier, returning whether its tier changed
+    pub async fn set_index_tier(&self, index_name: &str, tier: IndexTier) -> Result<bool> {
+        set_index_tier(&self.indices, &self.backend, index_name, tier).await
+    }
+
+    /// Open, close or freeze an index, returning whether its state changed
+    pub async fn set_index_state(&self, index_name: &str, state: IndexState) -> Result<bool> {
+        set_index_state(&self.indices, &self.backend, index_name, state).await
+    }
+
+    /// Get the tier, creation date and document count of an index
+    pub async fn get_index_tier(&self, index_name: &str) -> Result<serde_json::Value> {
+        get_index_tier(&self.indices, index_name).await
+    }
+
+    /// Demote hot indices older than `max_age` to the warm tier
+    pub async fn demote_indices_older_than(
+        &self,
+        max_age: std::time::Duration,
+    ) -> Result<Vec<String>> {
+        demote_indices_older_than(&self.indices, &self.backend, max_age).await
+    }
+
+    /// Delete expired documents and roll over or delete old indices, as
+    /// their `index.retention.*` settings ask
+    pub async fn apply_retention(&self) -> Result<RetentionReport> {
+        let report = apply_retention(&self.indices, &self.backend, &self.limits).await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(report)
     }
 
     pub async fn index_exists(&self, name: &str) -> Result<bool> {
@@ -90,11 +477,34 @@ impl Storage {
         match_indices(&self.indices, pattern).await
     }
 
+    /// Resolve a comma-separated list of indices, aliases and patterns
+    pub async fn resolve_index_expression(&self, expression: &str) -> Result<Vec<String>> {
+        resolve_index_expression(&self.indices, expression).await
+    }
+
+    /// Resolve an index expression, with wildcards matching closed indices too
+    pub async fn resolve_index_expression_with_closed(
+        &self,
+        expression: &str,
+    ) -> Result<Vec<String>> {
+        resolve_index_expression_with_closed(&self.indices, expression).await
+    }
+
     /// Get statistics for all indices
     pub async fn get_indices_stats(&self) -> Vec<(String, usize)> {
         get_indices_stats(&self.indices).await
     }
 
+    /// Get the statistics of every index, sorted by name
+    pub async fn get_index_stats(&self) -> Vec<IndexStats> {
+        get_index_stats(&self.indices).await
+    }
+
+    /// Get the number of primary shards of an index
+    pub async fn number_of_shards(&self, index_name: &str) -> Result<u64> {
+        get_number_of_shards(&self.indices, index_name).await
+    }
+
     /// Get aliases for all indices
     pub async fn get_aliases(&self) -> serde_json::Value {
         get_aliases(&self.indices).await
@@ -113,6 +523,15 @@ impl Storage {
         update_mapping(&self.indices, &self.backend, index_name, new_mappings).await
     }
 
+    /// Set how an index maps fields its mappings do not define
+    pub async fn set_dynamic_mapping(&self, index_name: &str, mode: DynamicMode) -> Result<()> {
+        set_dynamic_mapping(&self.indices, &self.backend, index_name, mode).await
+    }
+
+    pub async fn get_mapping(&self, name: &str) -> Result<serde_json::Value> {
+        get_mapping(&self.indices, name).await
+    }
+
     pub async fn update_settings(
         &self,
         index_name: &str,
@@ -139,31 +558,247 @@ impl Storage {
         index_name: &str,
         id: &str,
         document: serde_json::Value,
-    ) -> Result<()> {
-        index_document(&self.indices, &self.backend, index_name, id, document).await
+    ) -> Result<WriteOutcome> {
+        let index_name = self.route_document(index_name, &document).await?;
+        let outcome = index_document(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &index_name,
+            id,
+            document,
+        )
+        .await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(outcome)
     }
 
+    /// Create a document under a generated ID, returned with the outcome of
+    /// the write
     pub async fn create_document(
         &self,
         index_name: &str,
         document: serde_json::Value,
+    ) -> Result<(String, WriteOutcome)> {
+        let index_name = self.route_document(index_name, &document).await?;
+        let created = create_document(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &index_name,
+            document,
+        )
+        .await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(created)
+    }
+
+    /// Create a document under an ID, failing with a version conflict if the
+    /// ID exists; the outcome names the index the document was written to
+    ///
+    /// The check and the write happen under one lock, as for `create` bulk
+    /// actions, so of concurrent creates of an ID exactly one succeeds.
+    pub async fn create_document_with_id(
+        &self,
+        index_name: &str,
+        id: &str,
+        document: serde_json::Value,
+    ) -> Result<BulkItemOutcome> {
+        let action = BulkAction::Create {
+            index: index_name.to_string(),
+            id: Some(id.to_string()),
+            document,
+        };
+        self.execute_bulk_action(action).await
+    }
+
+    /// Resolve the index a document written to `target` goes to, creating
+    /// the target index of an ingest route or template if needed
+    pub async fn route_document(
+        &self,
+        target: &str,
+        document: &serde_json::Value,
     ) -> Result<String> {
-        create_document(&self.indices, &self.backend, index_name, document).await
+        route_document(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &self.routes,
+            &self.templates,
+            target,
+            document,
+        )
+        .await
+    }
+
+    /// Limits of document writes: the configured ones, with the
+    /// `action.auto_create_index` cluster setting overriding the configured
+    /// `auto_create_index`
+    fn write_limits(&self) -> Cow<'_, StorageLimits> {
+        match self.cluster_settings.auto_create_index() {
+            Some(auto_create_index) => Cow::Owned(StorageLimits {
+                auto_create_index: Some(auto_create_index),
+                ..self.limits.clone()
+            }),
+            None => Cow::Borrowed(&self.limits),
+        }
     }
 
     pub async fn get_document(&self, index_name: &str, id: &str) -> Result<serde_json::Value> {
-        get_document(&self.indices, index_name, id).await
+        self.read_through(index_name).await?;
+        get_document(&self.indices, &self.backend, index_name, id).await
+    }
+
+    /// Whether a document exists (`false` also when the index does not exist)
+    pub async fn document_exists(&self, index_name: &str, id: &str) -> Result<bool> {
+        self.read_through(index_name).await?;
+        Ok(fetch_document(&self.indices, &self.backend, index_name, id)
+            .await?
+            .is_some())
+    }
+
+    pub async fn delete_document(&self, index_name: &str, id: &str) -> Result<WriteOutcome> {
+        let outcome = delete_document(&self.indices, &self.backend, index_name, id).await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(outcome)
+    }
+
+    /// Execute bulk actions in order, writing them in batches
+    pub async fn execute_bulk(&self, actions: Vec<BulkAction>) -> Vec<Result<BulkItemOutcome>> {
+        let results = execute_bulk(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &self.routes,
+            &self.templates,
+            actions,
+        )
+        .await;
+        // Writes that could not be made durable are not acknowledged
+        match sync_request(&self.backend, self.durability).await {
+            Ok(()) => results,
+            Err(e) => results
+                .into_iter()
+                .map(|result| {
+                    result.and_then(|_| {
+                        Err(GbsError::Storage(format!("Failed to flush write: {}", e)))
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// Execute actions on one index atomically, all of them or none
+    pub async fn execute_transaction(
+        &self,
+        index_name: &str,
+        actions: Vec<BulkAction>,
+    ) -> std::result::Result<Vec<BulkItemOutcome>, TransactionAbort> {
+        let results =
+            execute_transaction(&self.indices, &self.backend, index_name, actions).await?;
+        // A transaction that could not be made durable is not acknowledged
+        sync_request(&self.backend, self.durability)
+            .await
+            .map_err(|e| TransactionAbort {
+                operation: None,
+                error: GbsError::Storage(format!("Failed to flush write: {}", e)),
+            })?;
+        Ok(results)
+    }
+
+    /// Roll an alias over to a new index if the request's conditions allow it
+    pub async fn rollover(&self, request: &RolloverRequest) -> Result<RolloverResponse> {
+        rollover(
+            &self.indices,
+            &self.backend,
+            &self.limits,
+            &self.templates,
+            request,
+        )
+        .await
+    }
+
+    /// Copy documents from the source indices into the destination index
+    pub async fn reindex(&self, request: &ReindexRequest) -> Result<ReindexResponse> {
+        self.reindex_with_task(request, &TaskHandle::default())
+            .await
+    }
+
+    /// Reindex as a task, reporting progress to it and stopping once it is
+    /// cancelled
+    pub async fn reindex_with_task(
+        &self,
+        request: &ReindexRequest,
+        task: &TaskHandle,
+    ) -> Result<ReindexResponse> {
+        let source_indices = self.resolve_index_expression(&request.source).await?;
+        for source_index in &source_indices {
+            self.read_through(source_index).await?;
+        }
+        let response = reindex(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &self.routes,
+            &self.templates,
+            &self.aggregation_cache,
+            self.scoring,
+            &source_indices,
+            request,
+            task,
+        )
+        .await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(response)
     }
 
-    pub async fn delete_document(&self, index_name: &str, id: &str) -> Result<()> {
-        delete_document(&self.indices, &self.backend, index_name, id).await
+    /// Delete the documents matching a query, reporting progress to the task
+    /// and stopping once it is cancelled
+    pub async fn delete_by_query(
+        &self,
+        request: &DeleteByQueryRequest,
+        task: &TaskHandle,
+    ) -> Result<DeleteByQueryResponse> {
+        let target_indices = self.resolve_index_expression(&request.index).await?;
+        for index_name in &target_indices {
+            self.read_through(index_name).await?;
+        }
+        let response = delete_by_query(
+            &self.indices,
+            &self.backend,
+            &self.aggregation_cache,
+            self.scoring,
+            &target_indices,
+            request,
+            task,
+        )
+        .await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(response)
     }
 
-    pub async fn execute_bulk_action(
+    /// Get the changes of an index after a sequence number, waiting for the
+    /// next change as long as the request allows if there are none
+    pub async fn get_changes(
         &self,
-        action: BulkAction,
-    ) -> Result<(String, String, u16, Option<String>)> {
-        execute_bulk_action(&self.indices, &self.backend, action).await
+        index_name: &str,
+        request: &ChangesRequest,
+    ) -> Result<ChangesResponse> {
+        get_changes(&self.indices, index_name, request).await
+    }
+
+    pub async fn execute_bulk_action(&self, action: BulkAction) -> Result<BulkItemOutcome> {
+        let result = execute_bulk_action(
+            &self.indices,
+            &self.backend,
+            &self.write_limits(),
+            &self.routes,
+            &self.templates,
+            action,
+        )
+        .await?;
+        sync_request(&self.backend, self.durability).await?;
+        Ok(result)
     }
 
     /// Search documents in an index
@@ -187,16 +822,132 @@ impl Storage {
         source_filter: Option<&serde_json::Value>,
         highlight: Option<&serde_json::Value>,
     ) -> Result<serde_json::Value> {
+        let request = SearchRequest {
+            from,
+            size,
+            sort: sort.cloned(),
+            source_filter: source_filter.cloned(),
+            highlight: highlight.cloned(),
+            ..SearchRequest::new(query.clone())
+        };
+        self.search_with_request(index_name, &request).await
+    }
+
+    /// Search documents in an index with all the options of a request
+    ///
+    /// Besides those of `search`: aggregations, whose results are cached so
+    /// that requests with `size` 0 are answered from the cache while the
+    /// index only received new documents; a time budget, past which the hits
+    /// found so far are returned with `timed_out: true`; and runtime fields
+    /// (`runtime_mappings`), whose values hits list in their `fields`.
+    pub async fn search_with_request(
+        &self,
+        index_name: &str,
+        request: &SearchRequest,
+    ) -> Result<serde_json::Value> {
+        self.read_through(index_name).await?;
         search(
             &self.indices,
+            &self.backend,
             index_name,
-            query,
-            from,
-            size,
-            sort,
-            source_filter,
-            highlight,
+            request,
+            &self.aggregation_cache,
+            self.scoring,
+        )
+        .await
+    }
+
+    /// Explain how a document scores against a query (`_explain`)
+    pub async fn explain(
+        &self,
+        index_name: &str,
+        id: &str,
+        query: &serde_json::Value,
+    ) -> Result<Explanation> {
+        self.read_through(index_name).await?;
+        explain(&self.indices, &self.backend, index_name, id, query).await
+    }
+
+    /// Values of the `stored_fields` and `docvalue_fields` of a search hit
+    pub async fn document_fields(
+        &self,
+        index_name: &str,
+        id: &str,
+        stored_fields: Option<&StoredFields>,
+        docvalue_fields: &[DocvalueField],
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        document_fields(
+            &self.indices,
+            &self.backend,
+            index_name,
+            id,
+            stored_fields,
+            docvalue_fields,
         )
         .await
     }
+
+    /// Compute script fields (`script_fields`) for a search hit
+    pub async fn script_fields(
+        &self,
+        index_name: &str,
+        id: &str,
+        scripts: &[(String, Script)],
+        score: f64,
+    ) -> Result<serde_json::Map<String, serde_json::Value>> {
+        self.read_through(index_name).await?;
+        script_fields(&self.indices, &self.backend, index_name, id, scripts, score).await
+    }
+
+    /// Fields of an index by dotted path, from its mappings and documents
+    pub async fn index_fields(
+        &self,
+        index_name: &str,
+    ) -> Result<std::collections::BTreeMap<String, FieldCapability>> {
+        self.read_through(index_name).await?;
+        index_fields(&self.indices, &self.backend, index_name).await
+    }
+
+    /// Field capabilities of several indices (`_field_caps`)
+    ///
+    /// See `field_caps_response` for the meaning of `patterns` and
+    /// `include_unmapped`.
+    pub async fn field_caps(
+        &self,
+        index_names: &[String],
+        patterns: &[String],
+        include_unmapped: bool,
+    ) -> Result<serde_json::Value> {
+        let mut fields = Vec::with_capacity(index_names.len());
+        for index_name in index_names {
+            fields.push((index_name.clone(), self.index_fields(index_name).await?));
+        }
+        Ok(field_caps_response(&fields, patterns, include_unmapped))
+    }
+
+    /// Clear the caches of indices: their filter caches (`query`) and their
+    /// entries in the aggregation cache (`request`)
+    pub async fn clear_caches(&self, index_names: &[String], query: bool, request: bool) {
+        if query {
+            clear_filter_caches(&self.indices, index_names).await;
+        }
+        if request {
+            for index_name in index_names {
+                self.aggregation_cache.clear_index(index_name);
+            }
+        }
+    }
+
+    /// Usage counters of the aggregation cache
+    pub fn aggregation_cache_stats(&self) -> AggregationCacheStats {
+        self.aggregation_cache.stats()
+    }
+
+    /// Usage of the cache of documents read from disk (`None` without a
+    /// storage backend)
+    pub fn document_cache_stats(&self) -> Option<DocumentCacheStats> {
+        self.backend
+            .as_ref()
+            .map(|backend| backend.document_cache_stats())
+    }
 }
@@ -0,0 +1,418 @@
+//! Index templates
+//!
+//! Templates hold settings, mappings and aliases applied to new indices whose
+//! name matches one of their patterns, so tools writing to dated indices such
+//! as `logs-2024.05.01` get the same configuration for every index. Both kinds
+//! of Elasticsearch templates are supported:
+//!
+//! - legacy templates (`/_template`): every matching template applies, in
+//!   ascending `order`, later ones overriding earlier ones
+//! - composable templates (`/_index_template`): only the matching template
+//!   with the highest `priority` applies, and it takes precedence over legacy
+//!   templates
+//!
+//! Settings and mappings given when creating an index override the templates.
+//! Writes to a missing index that a template matches create the index.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::index_ops::wildcard_regex;
+use crate::storage::settings::{merge_settings, validate_new_settings};
+use crate::storage_backend::SledBackend;
+
+/// Kind of an index template
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TemplateKind {
+    /// `/_template`
+    Legacy,
+    /// `/_index_template`
+    Composable,
+}
+
+impl TemplateKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TemplateKind::Legacy => "legacy",
+            TemplateKind::Composable => "composable",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "legacy" => Some(TemplateKind::Legacy),
+            "composable" => Some(TemplateKind::Composable),
+            _ => None,
+        }
+    }
+}
+
+/// An index template
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexTemplate {
+    pub index_patterns: Vec<String>,
+    /// `order` of legacy templates, `priority` of composable ones
+    #[serde(default)]
+    pub priority: i64,
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub mappings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub aliases: Option<serde_json::Value>,
+    #[serde(default)]
+    pub version: Option<i64>,
+    /// `_meta` of composable templates
+    #[serde(default)]
+    pub meta: Option<serde_json::Value>,
+}
+
+impl IndexTemplate {
+    /// Parse the body of `PUT /_template/{name}` or `PUT /_index_template/{name}`
+    pub fn parse(kind: TemplateKind, body: &serde_json::Value) -> Result<Self> {
+        let invalid =
+            |reason: &str| GbsError::InvalidRequest(format!("Invalid index template: {}", reason));
+        if !body.is_object() {
+            return Err(invalid("body must be an object"));
+        }
+
+        // 6.x legacy templates may give a single pattern as `template`
+        let patterns = match kind {
+            TemplateKind::Legacy => body.get("index_patterns").or_else(|| body.get("template")),
+            TemplateKind::Composable => body.get("index_patterns"),
+        };
+        let index_patterns: Vec<String> = match patterns {
+            Some(serde_json::Value::String(pattern)) => vec![pattern.clone()],
+            Some(serde_json::Value::Array(patterns)) => patterns
+                .iter()
+                .map(|pattern| {
+                    pattern
+                        .as_str()
+                        .map(str::to_string)
+                        .ok_or_else(|| invalid("[index_patterns] must be strings"))
+                })
+                .collect::<Result<_>>()?,
+            _ => return Err(invalid("[index_patterns] is required")),
+        };
+        if index_patterns.is_empty() {
+            return Err(invalid("[index_patterns] must not be empty"));
+        }
+
+        let integer = |key: &str| -> Result<Option<i64>> {
+            body.get(key)
+                .map(|value| {
+                    value
+                        .as_i64()
+                        .ok_or_else(|| invalid(&format!("[{}] must be an integer", key)))
+                })
+                .transpose()
+        };
+        let (priority, template) = match kind {
+            TemplateKind::Legacy => (integer("order")?, body),
+            TemplateKind::Composable => {
+                let composed_of = body.get("composed_of").and_then(|c| c.as_array());
+                if composed_of.is_some_and(|composed_of| !composed_of.is_empty()) {
+                    return Err(invalid(
+                        "component templates ([composed_of]) are not supported",
+                    ));
+                }
+                static EMPTY: serde_json::Value = serde_json::Value::Null;
+                (integer("priority")?, body.get("template").unwrap_or(&EMPTY))
+            }
+        };
+
+        let settings = template.get("settings").cloned();
+        if let Some(settings) = &settings {
+            validate_new_settings(settings)?;
+        }
+        let aliases = template.get("aliases").cloned();
+        if aliases.as_ref().is_some_and(|aliases| !aliases.is_object()) {
+            return Err(invalid("[aliases] must be an object"));
+        }
+
+        Ok(Self {
+            index_patterns,
+            priority: priority.unwrap_or(0),
+            settings,
+            mappings: template.get("mappings").cloned(),
+            aliases,
+            version: integer("version")?,
+            meta: body.get("_meta").cloned(),
+        })
+    }
+
+    /// Whether the template applies to an index name
+    pub fn matches(&self, index_name: &str) -> bool {
+        self.index_patterns
+            .iter()
+            .any(|pattern| wildcard_regex(pattern).is_some_and(|regex| regex.is_match(index_name)))
+    }
+
+    /// The template as returned by the GET APIs
+    pub fn to_json(&self, kind: TemplateKind) -> serde_json::Value {
+        let empty = || serde_json::json!({});
+        let mut template = match kind {
+            TemplateKind::Legacy => serde_json::json!({
+                "order": self.priority,
+                "index_patterns": self.index_patterns,
+                "settings": self.settings.clone().unwrap_or_else(empty),
+                "mappings": self.mappings.clone().unwrap_or_else(empty),
+                "aliases": self.aliases.clone().unwrap_or_else(empty),
+            }),
+            TemplateKind::Composable => {
+                let mut inner = serde_json::Map::new();
+                for (key, value) in [
+                    ("settings", &self.settings),
+                    ("mappings", &self.mappings),
+                    ("aliases", &self.aliases),
+                ] {
+                    if let Some(value) = value {
+                        inner.insert(key.to_string(), value.clone());
+                    }
+                }
+                serde_json::json!({
+                    "index_patterns": self.index_patterns,
+                    "template": inner,
+                    "composed_of": [],
+                    "priority": self.priority,
+                })
+            }
+        };
+        let object = template.as_object_mut().expect("template is an object");
+        if let Some(version) = self.version {
+            object.insert("version".to_string(), version.into());
+        }
+        if let Some(meta) = &self.meta {
+            object.insert("_meta".to_string(), meta.clone());
+        }
+        template
+    }
+}
+
+/// Settings, mappings and aliases that templates contribute to a new index
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResolvedTemplate {
+    /// Names of the applied templates, in the order they were applied
+    pub templates: Vec<String>,
+    pub settings: Option<serde_json::Value>,
+    pub mappings: Option<serde_json::Value>,
+    pub aliases: Vec<String>,
+}
+
+impl ResolvedTemplate {
+    fn apply(&mut self, name: &str, template: &IndexTemplate) {
+        self.templates.push(name.to_string());
+        if let Some(settings) = &template.settings {
+            merge_settings(
+                self.settings.get_or_insert_with(|| serde_json::json!({})),
+                settings,
+            );
+        }
+        if let Some(mappings) = &template.mappings {
+            merge_mappings(
+                self.mappings.get_or_insert_with(|| serde_json::json!({})),
+                mappings,
+            );
+        }
+        if let Some(serde_json::Value::Object(aliases)) = &template.aliases {
+            for alias in aliases.keys() {
+                if !self.aliases.contains(alias) {
+                    self.aliases.push(alias.clone());
+                }
+            }
+        }
+    }
+
+    /// Settings of a new index: the templates' overridden by the request's
+    pub fn index_settings(&self, settings: Option<serde_json::Value>) -> Option<serde_json::Value> {
+        match (self.settings.clone(), settings) {
+            (Some(mut merged), Some(settings)) => {
+                merge_settings(&mut merged, &settings);
+                Some(merged)
+            }
+            (merged, settings) => settings.or(merged),
+        }
+    }
+
+    /// Mappings of a new index: the templates' overridden by the request's
+    pub fn index_mappings(&self, mappings: Option<serde_json::Value>) -> Option<serde_json::Value> {
+        match (self.mappings.clone(), mappings) {
+            (Some(mut merged), Some(mappings)) => {
+                merge_mappings(&mut merged, &mappings);
+                Some(merged)
+            }
+            (merged, mappings) => mappings.or(merged),
+        }
+    }
+}
+
+/// Merge mappings recursively; fields of `overlay` replace those of `base`
+fn merge_mappings(base: &mut serde_json::Value, overlay: &serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(key) {
+                    Some(existing) if existing.is_object() && value.is_object() => {
+                        merge_mappings(existing, value)
+                    }
+                    _ => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay.clone(),
+    }
+}
+
+/// The index templates of a storage
+#[derive(Debug, Default)]
+pub struct IndexTemplates {
+    templates: RwLock<BTreeMap<(TemplateKind, String), IndexTemplate>>,
+}
+
+impl IndexTemplates {
+    /// Add or replace a template in memory
+    pub fn insert(&self, kind: TemplateKind, name: &str, template: IndexTemplate) {
+        self.write().insert((kind, name.to_string()), template);
+    }
+
+    /// Remove a template from memory, returning whether it existed
+    pub fn remove(&self, kind: TemplateKind, name: &str) -> bool {
+        self.write().remove(&(kind, name.to_string())).is_some()
+    }
+
+    /// Whether a template exists
+    pub fn contains(&self, kind: TemplateKind, name: &str) -> bool {
+        self.read().contains_key(&(kind, name.to_string()))
+    }
+
+    /// Templates of a kind whose name matches a pattern (`*` for all)
+    pub fn get(&self, kind: TemplateKind, pattern: &str) -> Vec<(String, IndexTemplate)> {
+        let Some(regex) = wildcard_regex(pattern) else {
+            return Vec::new();
+        };
+        self.read()
+            .iter()
+            .filter(|((template_kind, name), _)| *template_kind == kind && regex.is_match(name))
+            .map(|((_, name), template)| (name.clone(), template.clone()))
+            .collect()
+    }
+
+    /// Whether any template applies to an index name
+    pub fn matches(&self, index_name: &str) -> bool {
+        self.read()
+            .values()
+            .any(|template| template.matches(index_name))
+    }
+
+    /// Combine the templates that apply to a new index
+    pub fn resolve(&self, index_name: &str) -> ResolvedTemplate {
+        let templates = self.read();
+        let mut resolved = ResolvedTemplate::default();
+
+        // The highest priority composable template wins alone
+        let composable = templates
+            .iter()
+            .filter(|((kind, _), template)| {
+                *kind == TemplateKind::Composable && template.matches(index_name)
+            })
+            .max_by_key(|(_, template)| template.priority);
+        if let Some(((_, name), template)) = composable {
+            resolved.apply(name, template);
+            return resolved;
+        }
+
+        let mut legacy: Vec<_> = templates
+            .iter()
+            .filter(|((kind, _), template)| {
+                *kind == TemplateKind::Legacy && template.matches(index_name)
+            })
+            .collect();
+        legacy.sort_by_key(|(_, template)| template.priority);
+        for ((_, name), template) in legacy {
+            resolved.apply(name, template);
+        }
+        resolved
+    }
+
+    fn read(
+        &self,
+    ) -> std::sync::RwLockReadGuard<'_, BTreeMap<(TemplateKind, String), IndexTemplate>> {
+        self.templates.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(
+        &self,
+    ) -> std::sync::RwLockWriteGuard<'_, BTreeMap<(TemplateKind, String), IndexTemplate>> {
+        self.templates.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Store a template, persisting it to the backend
+pub async fn put_template(
+    templates: &IndexTemplates,
+    backend: &Option<Arc<SledBackend>>,
+    kind: TemplateKind,
+    name: &str,
+    template: IndexTemplate,
+) -> Result<()> {
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let value = serde_json::to_value(&template)?;
+        let key = name.to_string();
+        tokio::task::spawn_blocking(move || backend.store_template(kind.as_str(), &key, &value))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    templates.insert(kind, name, template);
+    info!("Stored {} index template '{}'", kind.as_str(), name);
+    Ok(())
+}
+
+/// Delete a template, removing it from the backend
+pub async fn delete_template(
+    templates: &IndexTemplates,
+    backend: &Option<Arc<SledBackend>>,
+    kind: TemplateKind,
+    name: &str,
+) -> Result<()> {
+    if !templates.contains(kind, name) {
+        return Err(GbsError::TemplateNotFound(name.to_string()));
+    }
+    if let Some(backend) = backend {
+        let backend = backend.clone();
+        let key = name.to_string();
+        tokio::task::spawn_blocking(move || backend.delete_template(kind.as_str(), &key))
+            .await
+            .map_err(GbsError::TaskJoin)??;
+    }
+    templates.remove(kind, name);
+    info!("Deleted {} index template '{}'", kind.as_str(), name);
+    Ok(())
+}
+
+/// Load the persisted templates
+pub async fn load_templates(
+    templates: &IndexTemplates,
+    backend: &Option<Arc<SledBackend>>,
+) -> Result<()> {
+    let Some(backend) = backend else {
+        return Ok(());
+    };
+    let backend = backend.clone();
+    let stored = tokio::task::spawn_blocking(move || backend.load_templates())
+        .await
+        .map_err(GbsError::TaskJoin)??;
+    for (kind, name, value) in stored {
+        let Some(kind) = TemplateKind::parse(&kind) else {
+            continue;
+        };
+        templates.insert(kind, &name, serde_json::from_value(value)?);
+    }
+    debug!("Loaded {} index templates", templates.read().len());
+    Ok(())
+}
@@ -0,0 +1,174 @@
+//! Hot/warm index tiering
+//!
+//! Hot indices keep their documents in memory. Warm indices drop them from
+//! memory and are served from the disk backend: lookups read single documents
+//! and searches stream over the stored documents, trading speed for memory.
+//! Indices can be moved between tiers explicitly or demoted by age.
+//!
+//! With lazy loading, hot indices loaded at startup are served from disk like
+//! warm ones until they are moved to the hot tier, which loads them.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::error::{GbsError, Result};
+use crate::storage::limits::is_system_index;
+use crate::storage::persistence::persist_index_metadata;
+use crate::storage::{Index, IndexTier, Storage};
+use crate::storage_backend::SledBackend;
+
+/// Backend serving the documents of a warm index
+pub fn warm_index_backend(
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+) -> Result<Arc<SledBackend>> {
+    backend.clone().ok_or_else(|| {
+        GbsError::Storage(format!(
+            "Index '{}' is warm but no storage backend is configured",
+            index_name
+        ))
+    })
+}
+
+/// Move an index to a tier, returning whether its tier changed
+pub async fn set_index_tier(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    tier: IndexTier,
+) -> Result<bool> {
+    let sled = backend.clone().ok_or_else(|| {
+        GbsError::InvalidRequest(
+            "Index tiering requires persistent storage (no storage backend configured)".to_string(),
+        )
+    })?;
+
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    // Lazily loaded indices are hot but load their documents when moved to
+    // the hot tier
+    if index.tier == tier && !index.is_lazy() {
+        debug!("Index '{}' is already {}", index_name, tier.as_str());
+        return Ok(false);
+    }
+
+    match tier {
+        IndexTier::Warm => {
+            // Documents are already persisted on every write; make sure they
+            // are on disk before dropping the in-memory copy
+            tokio::task::spawn_blocking(move || sled.flush())
+                .await
+                .map_err(GbsError::TaskJoin)??;
+            index.evict_documents();
+        }
+        IndexTier::Hot => {
+            let name = index_name.to_string();
+            let documents = tokio::task::spawn_blocking(move || sled.load_all_documents(&name))
+                .await
+                .map_err(GbsError::TaskJoin)??;
+            index.restore_documents(documents);
+        }
+    }
+    persist_index_metadata(backend, index).await?;
+
+    info!(
+        "Index '{}' moved to {} tier ({} documents)",
+        index_name,
+        tier.as_str(),
+        index.doc_count()
+    );
+    Ok(true)
+}
+
+/// Describe the tier of an index
+pub async fn get_index_tier(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    index_name: &str,
+) -> Result<serde_json::Value> {
+    let indices_guard = indices.read().await;
+    let index = indices_guard
+        .get(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    Ok(serde_json::json!({
+        "index": index_name,
+        "tier": index.tier.as_str(),
+        "lazy": index.is_lazy(),
+        "creation_date": index.creation_date,
+        "docs_count": index.doc_count(),
+        "size_in_bytes": index.size_in_bytes
+    }))
+}
+
+/// Demote hot indices created more than `max_age` ago to the warm tier
+///
+/// System (dot-prefixed) indices and indices without a recorded creation date
+/// are left alone. Returns the names of the demoted indices.
+pub async fn demote_indices_older_than(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    max_age: Duration,
+) -> Result<Vec<String>> {
+    let now = chrono::Utc::now().timestamp_millis() as u64;
+    let max_age_ms = max_age.as_millis() as u64;
+
+    let candidates: Vec<String> = indices
+        .read()
+        .await
+        .values()
+        .filter(|index| index.tier == IndexTier::Hot && !is_system_index(&index.name))
+        .filter(|index| {
+            index
+                .creation_date
+                .is_some_and(|created| now.saturating_sub(created) >= max_age_ms)
+        })
+        .map(|index| index.name.clone())
+        .collect();
+
+    let mut demoted = Vec::new();
+    for name in candidates {
+        match set_index_tier(indices, backend, &name, IndexTier::Warm).await {
+            Ok(true) => demoted.push(name),
+            Ok(false) => {}
+            // The index may have been deleted in the meantime
+            Err(GbsError::IndexNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(demoted)
+}
+
+/// Periodically demote indices older than `warm_after` to the warm tier
+pub fn spawn_tier_demotion(
+    storage: Storage,
+    warm_after: Duration,
+    interval: Duration,
+) -> tokio::task::JoinHandle<()> {
+    info!(
+        "Background tier demotion enabled: indices older than {:?} become warm (checked every {:?})",
+        warm_after, interval
+    );
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match storage.demote_indices_older_than(warm_after).await {
+                Ok(demoted) if !demoted.is_empty() => {
+                    info!(
+                        "Demoted {} indices to warm tier: {:?}",
+                        demoted.len(),
+                        demoted
+                    )
+                }
+                Ok(_) => debug!("No indices due for demotion"),
+                Err(e) => warn!("Background tier demotion failed: {}", e),
+            }
+        }
+    })
+}
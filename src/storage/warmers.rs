@@ -0,0 +1,213 @@
+//! Index warmers
+//!
+//! Warmers are search requests stored in the index settings under
+//! `index.warmers.<name>`. They run after the indices are loaded on startup and
+//! after each refresh, so the first real searches after a restart find the
+//! aggregation cache filled and, for warm indices, the documents in the disk
+//! page cache.
+
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::error::{GbsError, Result};
+use crate::storage::aggregation_cache::AggregationCache;
+use crate::storage::persistence::persist_index_metadata;
+use crate::storage::search_impl::{search, ParallelScoring, SearchRequest};
+use crate::storage::settings::{merge_settings, setting_group};
+use crate::storage::Index;
+use crate::storage_backend::SledBackend;
+
+/// Settings group holding the warmers
+const WARMERS_SETTING: &str = "index.warmers";
+
+/// Keys of a search request a warmer may use
+const WARMER_KEYS: [&str; 5] = ["query", "aggs", "aggregations", "sort", "size"];
+
+/// Check the body of a warmer: a search request with a query, aggregations,
+/// sort and size (default 0)
+pub fn validate_warmer(name: &str, body: &serde_json::Value) -> Result<()> {
+    let invalid =
+        |reason: String| GbsError::InvalidRequest(format!("Invalid warmer [{}]: {}", name, reason));
+    let object = body
+        .as_object()
+        .ok_or_else(|| invalid("body must be an object".to_string()))?;
+    if let Some(key) = object
+        .keys()
+        .find(|key| !WARMER_KEYS.contains(&key.as_str()))
+    {
+        return Err(invalid(format!(
+            "unsupported key [{}], expected one of {:?}",
+            key, WARMER_KEYS
+        )));
+    }
+    if object
+        .get("size")
+        .is_some_and(|size| size.as_u64().is_none())
+    {
+        return Err(invalid("[size] must be a non-negative integer".to_string()));
+    }
+    Ok(())
+}
+
+/// Check the warmers given in index settings
+pub fn validate_warmers(settings: &serde_json::Value) -> Result<()> {
+    for (name, body) in setting_group(settings, WARMERS_SETTING) {
+        validate_warmer(&name, &body)?;
+    }
+    Ok(())
+}
+
+/// The warmers of an index, by name
+pub fn index_warmers(index: &Index) -> serde_json::Map<String, serde_json::Value> {
+    index
+        .settings
+        .as_ref()
+        .map(|settings| setting_group(settings, WARMERS_SETTING))
+        .unwrap_or_default()
+}
+
+/// Add or replace a warmer of an index
+pub async fn put_warmer(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    name: &str,
+    body: serde_json::Value,
+) -> Result<()> {
+    if name.is_empty() || name.contains('.') {
+        return Err(GbsError::InvalidRequest(format!(
+            "Invalid warmer name [{}]: must be non-empty and must not contain '.'",
+            name
+        )));
+    }
+    validate_warmer(name, &body)?;
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+
+    // The new body replaces the old one instead of being merged into it
+    let key = format!("{}.{}", WARMERS_SETTING, name);
+    let settings = index.settings.get_or_insert_with(|| serde_json::json!({}));
+    merge_settings(settings, &serde_json::json!({ key.clone(): null }));
+    merge_settings(
+        settings,
+        &serde_json::json!({ "index": { "warmers": { name: body } } }),
+    );
+    persist_index_metadata(backend, index).await?;
+
+    info!("Warmer '{}' stored on index '{}'", name, index_name);
+    Ok(())
+}
+
+/// Remove a warmer from an index
+pub async fn delete_warmer(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    index_name: &str,
+    name: &str,
+) -> Result<()> {
+    let mut indices_guard = indices.write().await;
+    let index = indices_guard
+        .get_mut(index_name)
+        .ok_or_else(|| GbsError::IndexNotFound(index_name.to_string()))?;
+    if !index_warmers(index).contains_key(name) {
+        return Err(GbsError::WarmerNotFound(format!(
+            "[{}] on index [{}]",
+            name, index_name
+        )));
+    }
+
+    let key = format!("{}.{}", WARMERS_SETTING, name);
+    if let Some(settings) = index.settings.as_mut() {
+        merge_settings(settings, &serde_json::json!({ key: null }));
+    }
+    persist_index_metadata(backend, index).await?;
+
+    info!("Warmer '{}' deleted from index '{}'", name, index_name);
+    Ok(())
+}
+
+/// Outcome of running the warmers of an index
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WarmupReport {
+    pub index: String,
+    /// Names of the warmers that ran successfully
+    pub warmers: Vec<String>,
+    /// Warmers that failed, with the error
+    pub failures: Vec<(String, String)>,
+    pub took_ms: u64,
+}
+
+/// Run the warmers of an index
+///
+/// Failed warmers are logged and reported, never returned as errors: warming
+/// up must not fail a refresh or a restart. A missing index has no warmers.
+pub async fn run_warmers(
+    indices: &Arc<RwLock<HashMap<String, Index>>>,
+    backend: &Option<Arc<SledBackend>>,
+    cache: &AggregationCache,
+    scoring: ParallelScoring,
+    index_name: &str,
+) -> WarmupReport {
+    let start_time = std::time::Instant::now();
+    // Closed indices cannot be searched, so they have nothing to warm up
+    let warmers = indices
+        .read()
+        .await
+        .get(index_name)
+        .filter(|index| !index.is_closed())
+        .map(index_warmers)
+        .unwrap_or_default();
+    let mut report = WarmupReport {
+        index: index_name.to_string(),
+        ..Default::default()
+    };
+
+    for (name, body) in warmers {
+        let query = body
+            .get("query")
+            .cloned()
+            .unwrap_or_else(|| serde_json::json!({ "match_all": {} }));
+        let size = body
+            .get("size")
+            .and_then(|size| size.as_u64())
+            .unwrap_or(0)
+            .min(u32::MAX as u64) as u32;
+        let request = SearchRequest {
+            size: Some(size),
+            sort: body.get("sort").cloned(),
+            aggs: body
+                .get("aggs")
+                .or_else(|| body.get("aggregations"))
+                .cloned(),
+            ..SearchRequest::new(query)
+        };
+        let result = search(indices, backend, index_name, &request, cache, scoring).await;
+        match result {
+            Ok(_) => {
+                debug!("Warmer '{}' ran on index '{}'", name, index_name);
+                report.warmers.push(name);
+            }
+            Err(e) => {
+                warn!("Warmer '{}' failed on index '{}': {}", name, index_name, e);
+                report.failures.push((name, e.to_string()));
+            }
+        }
+    }
+
+    report.took_ms = start_time.elapsed().as_millis() as u64;
+    if !report.warmers.is_empty() || !report.failures.is_empty() {
+        info!(
+            "Ran {} warmers on index '{}' in {}ms ({} failed)",
+            report.warmers.len() + report.failures.len(),
+            index_name,
+            report.took_ms,
+            report.failures.len()
+        );
+    }
+    report
+}
@@ -1,30 +1,136 @@
+use crate::document_cache::{DocumentCache, DocumentCacheStats};
+use crate::document_codec::{decode_document, encode_document, is_legacy_document};
 use crate::error::{GbsError, Result};
+use crate::storage::{Change, IndexState, IndexTier, SearchProfile, MAX_RETAINED_CHANGES};
+use serde::{Deserialize, Serialize};
 use serde_json;
 use sled::Db;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
 use std::path::Path;
 use std::sync::Arc;
-use tracing::{debug, warn};
+use tracing::{debug, info, warn};
 
 /// Key prefixes for different data types
 const INDEX_PREFIX: &str = "index:";
 const DOC_PREFIX: &str = "doc:";
+const USER_PREFIX: &str = "user:";
+const API_KEY_PREFIX: &str = "api_key:";
+const TEMPLATE_PREFIX: &str = "template:";
+const SCRIPT_PREFIX: &str = "script:";
+const PIPELINE_PREFIX: &str = "pipeline:";
+const CHANGE_PREFIX: &str = "change:";
+const VERSION_PREFIX: &str = "version:";
+/// Key of the persistent cluster settings
+const CLUSTER_SETTINGS_KEY: &str = "cluster_settings";
+
+/// Retries while another handle still holds the database lock (~2s in total)
+const OPEN_LOCK_RETRIES: u32 = 40;
+const OPEN_LOCK_RETRY_DELAY: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// Persisted index metadata
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IndexMetadata {
+    #[serde(default)]
+    pub settings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub mappings: Option<serde_json::Value>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub search_profiles: HashMap<String, SearchProfile>,
+    #[serde(default)]
+    pub tier: IndexTier,
+    #[serde(default)]
+    pub state: IndexState,
+    #[serde(default)]
+    pub creation_date: Option<u64>,
+}
+
+/// A document change applied as part of a batch
+#[derive(Debug, Clone)]
+pub enum DocumentWrite {
+    Store {
+        index: String,
+        id: String,
+        document: serde_json::Value,
+    },
+    Delete {
+        index: String,
+        id: String,
+    },
+}
+
+/// Key of a change, zero-padded so changes sort by sequence number
+fn change_key(index_name: &str, seq: u64) -> String {
+    format!("{}:{}:{:020}", CHANGE_PREFIX, index_name, seq)
+}
+
+/// Decode a stored document version
+fn decode_version(value: &[u8]) -> Result<u64> {
+    <[u8; 8]>::try_from(value)
+        .map(u64::from_be_bytes)
+        .map_err(|_| {
+            GbsError::Storage(format!("Invalid document version of {} bytes", value.len()))
+        })
+}
 
 /// Convert sled error to GbsError
 fn sled_error(e: sled::Error) -> GbsError {
     GbsError::Storage(format!("Sled error: {}", e))
 }
 
+/// Check if opening failed because the database lock is held
+///
+/// sled reports a held lock as an `Other` error wrapping the `WouldBlock`.
+fn is_lock_error(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::WouldBlock
+        || e.to_string().starts_with("could not acquire lock")
+}
+
 /// Sled-based persistent storage backend
 pub struct SledBackend {
     db: Arc<Db>,
+    /// Documents recently read one at a time, shared with clones
+    document_cache: Arc<DocumentCache>,
 }
 
 impl SledBackend {
     /// Create a new Sled backend with the given data directory
+    ///
+    /// A database that was just closed in this process can keep its file lock
+    /// until sled's background flusher exits, so opening is retried briefly
+    /// while the lock is held.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let db = sled::open(path)
-            .map_err(|e| GbsError::Storage(format!("Failed to open sled database: {}", e)))?;
-        Ok(Self { db: Arc::new(db) })
+        let mut attempts = 0;
+        let db = loop {
+            match sled::open(path.as_ref()) {
+                Ok(db) => break db,
+                Err(sled::Error::Io(e)) if is_lock_error(&e) && attempts < OPEN_LOCK_RETRIES => {
+                    attempts += 1;
+                    std::thread::sleep(OPEN_LOCK_RETRY_DELAY);
+                }
+                Err(e) => {
+                    return Err(GbsError::Storage(format!(
+                        "Failed to open sled database: {}",
+                        e
+                    )))
+                }
+            }
+        };
+        Ok(Self {
+            db: Arc::new(db),
+            document_cache: Arc::new(DocumentCache::default()),
+        })
+    }
+
+    /// Set the memory budget of the cache of documents read one at a time
+    pub fn set_document_cache_budget(&self, bytes: u64) {
+        self.document_cache.set_budget(bytes);
+    }
+
+    pub fn document_cache_stats(&self) -> DocumentCacheStats {
+        self.document_cache.stats()
     }
 
     /// Get the sled database instance
@@ -33,19 +139,11 @@ impl SledBackend {
     }
 
     /// Store index metadata
-    pub fn store_index_metadata(
-        &self,
-        index_name: &str,
-        settings: Option<&serde_json::Value>,
-        mappings: Option<&serde_json::Value>,
-    ) -> Result<()> {
+    pub fn store_index_metadata(&self, index_name: &str, metadata: &IndexMetadata) -> Result<()> {
         debug!("Storing index metadata for '{}'", index_name);
         let key = format!("{}:{}", INDEX_PREFIX, index_name);
-        let metadata = serde_json::json!({
-            "name": index_name,
-            "settings": settings,
-            "mappings": mappings
-        });
+        let mut metadata = serde_json::to_value(metadata)?;
+        metadata["name"] = serde_json::json!(index_name);
         let value = serde_json::to_vec(&metadata)?;
         self.db.insert(key.as_bytes(), value).map_err(|e| {
             warn!("Failed to store index metadata for '{}': {}", index_name, e);
@@ -63,16 +161,10 @@ impl SledBackend {
     }
 
     /// Load index metadata
-    pub fn load_index_metadata(
-        &self,
-        index_name: &str,
-    ) -> Result<Option<(Option<serde_json::Value>, Option<serde_json::Value>)>> {
+    pub fn load_index_metadata(&self, index_name: &str) -> Result<Option<IndexMetadata>> {
         let key = format!("{}:{}", INDEX_PREFIX, index_name);
         if let Some(value) = self.db.get(key.as_bytes()).map_err(sled_error)? {
-            let metadata: serde_json::Value = serde_json::from_slice(&value)?;
-            let settings = metadata.get("settings").cloned();
-            let mappings = metadata.get("mappings").cloned();
-            Ok(Some((settings, mappings)))
+            Ok(Some(serde_json::from_slice(&value)?))
         } else {
             Ok(None)
         }
@@ -113,9 +205,18 @@ impl SledBackend {
             to_remove.len(),
             index_name
         );
+        // And its change log and document versions
+        for prefix in [CHANGE_PREFIX, VERSION_PREFIX] {
+            let prefix = format!("{}:{}:", prefix, index_name);
+            for result in self.db.scan_prefix(prefix.as_bytes()) {
+                let (key, _) = result.map_err(sled_error)?;
+                to_remove.push(key);
+            }
+        }
         for key in to_remove {
             self.db.remove(key).map_err(sled_error)?;
         }
+        self.document_cache.invalidate_prefix(&doc_prefix);
 
         self.db.flush().map_err(sled_error)?;
         debug!("Index '{}' deleted successfully from storage", index_name);
@@ -131,7 +232,7 @@ impl SledBackend {
     ) -> Result<()> {
         debug!("Storing document '{}' in index '{}'", doc_id, index_name);
         let key = format!("{}:{}:{}", DOC_PREFIX, index_name, doc_id);
-        let value = serde_json::to_vec(document)?;
+        let value = encode_document(document);
         self.db.insert(key.as_bytes(), value).map_err(|e| {
             warn!(
                 "Failed to store document '{}' in index '{}': {}",
@@ -139,20 +240,65 @@ impl SledBackend {
             );
             sled_error(e)
         })?;
+        // Dropped after the write, so a concurrent read of the previous
+        // version is not cached
+        self.document_cache.invalidate(&key);
         // Don't flush on every document write for performance
         debug!("Document '{}' stored successfully", doc_id);
         Ok(())
     }
 
-    /// Load a document
+    /// Apply document changes atomically in a single batch
+    pub fn apply_document_writes(&self, writes: &[DocumentWrite]) -> Result<()> {
+        debug!("Applying batch of {} document writes", writes.len());
+        let mut batch = sled::Batch::default();
+        let mut keys = Vec::with_capacity(writes.len());
+        for write in writes {
+            match write {
+                DocumentWrite::Store {
+                    index,
+                    id,
+                    document,
+                } => {
+                    let key = format!("{}:{}:{}", DOC_PREFIX, index, id);
+                    batch.insert(key.as_bytes(), encode_document(document));
+                    keys.push(key);
+                }
+                DocumentWrite::Delete { index, id } => {
+                    let key = format!("{}:{}:{}", DOC_PREFIX, index, id);
+                    batch.remove(key.as_bytes());
+                    keys.push(key);
+                }
+            }
+        }
+        self.db.apply_batch(batch).map_err(|e| {
+            warn!(
+                "Failed to apply batch of {} document writes: {}",
+                writes.len(),
+                e
+            );
+            sled_error(e)
+        })?;
+        for key in keys {
+            self.document_cache.invalidate(&key);
+        }
+        Ok(())
+    }
+
+    /// Load a document, through the document cache
     pub fn load_document(
         &self,
         index_name: &str,
         doc_id: &str,
     ) -> Result<Option<serde_json::Value>> {
         let key = format!("{}:{}:{}", DOC_PREFIX, index_name, doc_id);
+        if let Some(doc) = self.document_cache.get(&key) {
+            return Ok(Some(doc));
+        }
+        let generation = self.document_cache.generation();
         if let Some(value) = self.db.get(key.as_bytes()).map_err(sled_error)? {
-            let doc: serde_json::Value = serde_json::from_slice(&value)?;
+            let doc = decode_document(&value)?;
+            self.document_cache.insert(&key, &doc, generation);
             Ok(Some(doc))
         } else {
             Ok(None)
@@ -163,32 +309,447 @@ impl SledBackend {
     pub fn delete_document(&self, index_name: &str, doc_id: &str) -> Result<()> {
         let key = format!("{}:{}:{}", DOC_PREFIX, index_name, doc_id);
         self.db.remove(key.as_bytes()).map_err(sled_error)?;
+        self.document_cache.invalidate(&key);
         Ok(())
     }
 
     /// Load all documents for an index
+    ///
+    /// Documents still stored as JSON are rewritten in the binary format (see
+    /// `document_codec`), so they are not parsed as JSON again.
     pub fn load_all_documents(&self, index_name: &str) -> Result<Vec<(String, serde_json::Value)>> {
         let prefix = format!("{}:{}:", DOC_PREFIX, index_name);
         let mut documents = Vec::new();
+        let mut migration = sled::Batch::default();
+        let mut migrated = 0;
 
         for result in self.db.scan_prefix(prefix.as_bytes()) {
             let (key, value) = result.map_err(sled_error)?;
             if let Ok(key_str) = std::str::from_utf8(&key) {
                 if let Some(suffix) = key_str.strip_prefix(&prefix) {
-                    let doc: serde_json::Value = serde_json::from_slice(&value)?;
+                    let doc = decode_document(&value)?;
+                    if is_legacy_document(&value) {
+                        migration.insert(key.clone(), encode_document(&doc));
+                        migrated += 1;
+                    }
                     documents.push((suffix.to_string(), doc));
                 }
             }
         }
 
+        if migrated > 0 {
+            self.db.apply_batch(migration).map_err(sled_error)?;
+            info!(
+                "Migrated {} documents of index '{}' to the binary format",
+                migrated, index_name
+            );
+        }
         Ok(documents)
     }
 
+    /// Visit every document of an index, one at a time
+    ///
+    /// Unlike `load_all_documents` this never holds more than one document in
+    /// memory, which is how warm indices are searched.
+    pub fn for_each_document<F>(&self, index_name: &str, mut visit: F) -> Result<()>
+    where
+        F: FnMut(&str, serde_json::Value) -> Result<()>,
+    {
+        self.for_each_document_until(index_name, |id, doc| {
+            visit(id, doc).map(ControlFlow::Continue)
+        })?;
+        Ok(())
+    }
+
+    /// Visit the documents of an index, one at a time, until `visit` breaks
+    ///
+    /// Returns whether every document was visited.
+    pub fn for_each_document_until<F>(&self, index_name: &str, mut visit: F) -> Result<bool>
+    where
+        F: FnMut(&str, serde_json::Value) -> Result<ControlFlow<()>>,
+    {
+        let prefix = format!("{}:{}:", DOC_PREFIX, index_name);
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some(doc_id) = key_str.strip_prefix(&prefix) {
+                    if visit(doc_id, decode_document(&value)?)?.is_break() {
+                        return Ok(false);
+                    }
+                }
+            }
+        }
+        Ok(true)
+    }
+
+    /// Count the documents of an index and their stored size in bytes
+    pub fn document_stats(&self, index_name: &str) -> Result<(usize, u64)> {
+        let prefix = format!("{}:{}:", DOC_PREFIX, index_name);
+        let mut count = 0;
+        let mut size = 0;
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = result.map_err(sled_error)?;
+            count += 1;
+            size += value.len() as u64;
+        }
+        Ok((count, size))
+    }
+
+    /// Append changes to the change log of an index, dropping the changes
+    /// that are no longer retained
+    pub fn store_changes(&self, index_name: &str, changes: &[Change]) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for change in changes {
+            batch.insert(
+                change_key(index_name, change.seq).as_bytes(),
+                serde_json::to_vec(change)?,
+            );
+            if let Some(expired) = change.seq.checked_sub(MAX_RETAINED_CHANGES as u64) {
+                batch.remove(change_key(index_name, expired).as_bytes());
+            }
+        }
+        self.db.apply_batch(batch).map_err(sled_error)
+    }
+
+    /// Load the change log of an index, oldest change first
+    pub fn load_changes(&self, index_name: &str) -> Result<Vec<Change>> {
+        let prefix = format!("{}:{}:", CHANGE_PREFIX, index_name);
+        let mut changes = Vec::new();
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (_, value) = result.map_err(sled_error)?;
+            changes.push(serde_json::from_slice(&value)?);
+        }
+        Ok(changes)
+    }
+
+    /// Store the versions of written documents, removing those of deleted
+    /// ones (`None`)
+    pub fn store_versions(
+        &self,
+        index_name: &str,
+        versions: &[(String, Option<u64>)],
+    ) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (id, version) in versions {
+            let key = format!("{}:{}:{}", VERSION_PREFIX, index_name, id);
+            match version {
+                Some(version) => batch.insert(key.as_bytes(), &version.to_be_bytes()),
+                None => batch.remove(key.as_bytes()),
+            }
+        }
+        self.db.apply_batch(batch).map_err(sled_error)
+    }
+
+    /// Load the versions of the documents of an index
+    pub fn load_versions(&self, index_name: &str) -> Result<HashMap<String, u64>> {
+        let prefix = format!("{}:{}:", VERSION_PREFIX, index_name);
+        let mut versions = HashMap::new();
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            let id = std::str::from_utf8(&key[prefix.len()..])
+                .map_err(|e| GbsError::Storage(format!("Invalid version key: {}", e)))?;
+            versions.insert(id.to_string(), decode_version(&value)?);
+        }
+        Ok(versions)
+    }
+
+    /// Store a security user record
+    pub fn store_user(&self, username: &str, user: &serde_json::Value) -> Result<()> {
+        debug!("Storing user '{}'", username);
+        let key = format!("{}:{}", USER_PREFIX, username);
+        let value = serde_json::to_vec(user)?;
+        self.db.insert(key.as_bytes(), value).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load all security user records
+    pub fn load_users(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let prefix = format!("{}:", USER_PREFIX);
+        let mut users = Vec::new();
+
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some(username) = key_str.strip_prefix(&prefix) {
+                    let user: serde_json::Value = serde_json::from_slice(&value)?;
+                    users.push((username.to_string(), user));
+                }
+            }
+        }
+
+        Ok(users)
+    }
+
+    /// Delete a security user record
+    pub fn delete_user(&self, username: &str) -> Result<()> {
+        let key = format!("{}:{}", USER_PREFIX, username);
+        self.db.remove(key.as_bytes()).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Store a security API key record
+    pub fn store_api_key(&self, id: &str, api_key: &serde_json::Value) -> Result<()> {
+        debug!("Storing API key '{}'", id);
+        let key = format!("{}:{}", API_KEY_PREFIX, id);
+        let value = serde_json::to_vec(api_key)?;
+        self.db.insert(key.as_bytes(), value).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load all security API key records
+    pub fn load_api_keys(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let prefix = format!("{}:", API_KEY_PREFIX);
+        let mut api_keys = Vec::new();
+
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some(id) = key_str.strip_prefix(&prefix) {
+                    let api_key: serde_json::Value = serde_json::from_slice(&value)?;
+                    api_keys.push((id.to_string(), api_key));
+                }
+            }
+        }
+
+        Ok(api_keys)
+    }
+
+    /// Store an index template of a kind (`legacy` or `composable`)
+    pub fn store_template(
+        &self,
+        kind: &str,
+        name: &str,
+        template: &serde_json::Value,
+    ) -> Result<()> {
+        debug!("Storing {} index template '{}'", kind, name);
+        let key = format!("{}:{}:{}", TEMPLATE_PREFIX, kind, name);
+        let value = serde_json::to_vec(template)?;
+        self.db.insert(key.as_bytes(), value).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load all index templates as (kind, name, template)
+    pub fn load_templates(&self) -> Result<Vec<(String, String, serde_json::Value)>> {
+        let prefix = format!("{}:", TEMPLATE_PREFIX);
+        let mut templates = Vec::new();
+
+        for result in self.db.scan_prefix(prefix.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some((kind, name)) = key_str
+                    .strip_prefix(&prefix)
+                    .and_then(|rest| rest.split_once(':'))
+                {
+                    let template: serde_json::Value = serde_json::from_slice(&value)?;
+                    templates.push((kind.to_string(), name.to_string(), template));
+                }
+            }
+        }
+
+        Ok(templates)
+    }
+
+    /// Delete an index template
+    pub fn delete_template(&self, kind: &str, name: &str) -> Result<()> {
+        let key = format!("{}:{}:{}", TEMPLATE_PREFIX, kind, name);
+        self.db.remove(key.as_bytes()).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Store a script
+    pub fn store_script(&self, id: &str, script: &serde_json::Value) -> Result<()> {
+        debug!("Storing script '{}'", id);
+        let key = format!("{}{}", SCRIPT_PREFIX, id);
+        let value = serde_json::to_vec(script)?;
+        self.db.insert(key.as_bytes(), value).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load all scripts as (id, script)
+    pub fn load_scripts(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let mut scripts = Vec::new();
+
+        for result in self.db.scan_prefix(SCRIPT_PREFIX.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some(id) = key_str.strip_prefix(SCRIPT_PREFIX) {
+                    let script: serde_json::Value = serde_json::from_slice(&value)?;
+                    scripts.push((id.to_string(), script));
+                }
+            }
+        }
+
+        Ok(scripts)
+    }
+
+    /// Delete a script
+    pub fn delete_script(&self, id: &str) -> Result<()> {
+        let key = format!("{}{}", SCRIPT_PREFIX, id);
+        self.db.remove(key.as_bytes()).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Store the persistent cluster settings
+    pub fn store_cluster_settings(&self, settings: &serde_json::Value) -> Result<()> {
+        debug!("Storing cluster settings");
+        let value = serde_json::to_vec(settings)?;
+        self.db
+            .insert(CLUSTER_SETTINGS_KEY.as_bytes(), value)
+            .map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load the persistent cluster settings, if any were stored
+    pub fn load_cluster_settings(&self) -> Result<Option<serde_json::Value>> {
+        let stored = self
+            .db
+            .get(CLUSTER_SETTINGS_KEY.as_bytes())
+            .map_err(sled_error)?;
+        match stored {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Store an ingest pipeline
+    pub fn store_pipeline(&self, id: &str, pipeline: &serde_json::Value) -> Result<()> {
+        debug!("Storing ingest pipeline '{}'", id);
+        let key = format!("{}{}", PIPELINE_PREFIX, id);
+        let value = serde_json::to_vec(pipeline)?;
+        self.db.insert(key.as_bytes(), value).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
+    /// Load all ingest pipelines as (id, pipeline)
+    pub fn load_pipelines(&self) -> Result<Vec<(String, serde_json::Value)>> {
+        let mut pipelines = Vec::new();
+
+        for result in self.db.scan_prefix(PIPELINE_PREFIX.as_bytes()) {
+            let (key, value) = result.map_err(sled_error)?;
+            if let Ok(key_str) = std::str::from_utf8(&key) {
+                if let Some(id) = key_str.strip_prefix(PIPELINE_PREFIX) {
+                    let pipeline: serde_json::Value = serde_json::from_slice(&value)?;
+                    pipelines.push((id.to_string(), pipeline));
+                }
+            }
+        }
+
+        Ok(pipelines)
+    }
+
+    /// Delete an ingest pipeline
+    pub fn delete_pipeline(&self, id: &str) -> Result<()> {
+        let key = format!("{}{}", PIPELINE_PREFIX, id);
+        self.db.remove(key.as_bytes()).map_err(sled_error)?;
+        self.db.flush().map_err(sled_error)?;
+        Ok(())
+    }
+
     /// Flush pending writes to disk
     pub fn flush(&self) -> Result<()> {
         self.db.flush().map_err(sled_error)?;
         Ok(())
     }
+
+    /// Size of the database on disk, in bytes
+    pub fn size_on_disk(&self) -> Result<u64> {
+        self.db.size_on_disk().map_err(sled_error)
+    }
+
+    /// Check that every entry can be read back
+    ///
+    /// Returns the problems found: entries that do not decode, documents and
+    /// changes of indices without metadata, and keys of no known kind. Fails
+    /// only if the database itself cannot be read.
+    pub fn verify(&self) -> Result<BackendVerification> {
+        let mut verification = BackendVerification::default();
+        let indices: std::collections::HashSet<String> = self.list_indices()?.into_iter().collect();
+        verification.indices = indices.len();
+
+        for result in self.db.iter() {
+            let (key, value) = result.map_err(sled_error)?;
+            let Ok(key) = std::str::from_utf8(&key) else {
+                verification
+                    .problems
+                    .push(format!("Key {:?} is not valid UTF-8", key));
+                continue;
+            };
+            let problem = if let Some(name) = key.strip_prefix(&format!("{}:", INDEX_PREFIX)) {
+                serde_json::from_slice::<IndexMetadata>(&value)
+                    .err()
+                    .map(|e| format!("Metadata of index '{}' is invalid: {}", name, e))
+            } else if let Some(rest) = key.strip_prefix(&format!("{}:", DOC_PREFIX)) {
+                verification.documents += 1;
+                let (index, id) = rest.split_once(':').unwrap_or((rest, ""));
+                if !indices.contains(index) {
+                    Some(format!("Document '{}' of missing index '{}'", id, index))
+                } else {
+                    decode_document(&value)
+                        .err()
+                        .map(|e| format!("Document '{}' of index '{}': {}", id, index, e))
+                }
+            } else if let Some(rest) = key.strip_prefix(&format!("{}:", CHANGE_PREFIX)) {
+                let (index, seq) = rest.split_once(':').unwrap_or((rest, ""));
+                if !indices.contains(index) {
+                    Some(format!("Change {} of missing index '{}'", seq, index))
+                } else {
+                    serde_json::from_slice::<Change>(&value)
+                        .err()
+                        .map(|e| format!("Change {} of index '{}' is invalid: {}", seq, index, e))
+                }
+            } else if let Some(rest) = key.strip_prefix(&format!("{}:", VERSION_PREFIX)) {
+                let (index, id) = rest.split_once(':').unwrap_or((rest, ""));
+                if !indices.contains(index) {
+                    Some(format!(
+                        "Version of document '{}' of missing index '{}'",
+                        id, index
+                    ))
+                } else {
+                    decode_version(&value).err().map(|e| {
+                        format!("Version of document '{}' of index '{}': {}", id, index, e)
+                    })
+                }
+            } else if key == CLUSTER_SETTINGS_KEY
+                || [
+                    USER_PREFIX,
+                    API_KEY_PREFIX,
+                    TEMPLATE_PREFIX,
+                    SCRIPT_PREFIX,
+                    PIPELINE_PREFIX,
+                ]
+                .iter()
+                .any(|prefix| key.starts_with(prefix))
+            {
+                serde_json::from_slice::<serde_json::Value>(&value)
+                    .err()
+                    .map(|e| format!("Entry '{}' is invalid: {}", key, e))
+            } else {
+                Some(format!("Unknown entry '{}'", key))
+            };
+            verification.problems.extend(problem);
+        }
+        Ok(verification)
+    }
+}
+
+/// Outcome of `SledBackend::verify`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BackendVerification {
+    /// Number of indices with metadata
+    pub indices: usize,
+    /// Number of stored documents
+    pub documents: usize,
+    /// Problems found, one per entry
+    pub problems: Vec<String>,
 }
 
 impl std::fmt::Debug for SledBackend {
@@ -201,6 +762,7 @@ impl Clone for SledBackend {
     fn clone(&self) -> Self {
         Self {
             db: Arc::clone(&self.db),
+            document_cache: Arc::clone(&self.document_cache),
         }
     }
 }
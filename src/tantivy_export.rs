@@ -0,0 +1,311 @@
+//! Export of an index to Tantivy
+//!
+//! `gbs export-tantivy --index <name> --out <dir>` builds a Tantivy index from
+//! the documents and mappings of a gbs index, giving users a way to move data
+//! to a full-featured embedded search engine. The index is read straight from
+//! the data directory, so the server must not be running; within a running
+//! server, `export_checkpoint_tantivy` exports from a storage checkpoint.
+//!
+//! Every document becomes a Tantivy document with:
+//! - `_id`: the document ID (raw string, stored)
+//! - `_source`: the whole document as a stored, indexed JSON field
+//! - one typed field per mapped field (`text`, `keyword`, integer and floating
+//!   point types, `boolean` and `date`), with object properties flattened to
+//!   dotted names such as `user.name`
+//!
+//! Values that do not fit the mapped type are left out of the typed field and
+//! counted in the report; they stay available in `_source`.
+
+use std::path::{Path, PathBuf};
+use tantivy::schema::{
+    DateOptions, DateTimePrecision, Field, OwnedValue, Schema, FAST, INDEXED, STORED, STRING, TEXT,
+};
+use tantivy::{TantivyDocument, TantivyError};
+use tracing::{debug, info};
+
+use crate::error::{GbsError, Result};
+use crate::storage::{get_field_value, parse_date, Checkpoint};
+use crate::storage_backend::SledBackend;
+
+/// Memory budget of the Tantivy index writer
+const WRITER_MEMORY_BYTES: usize = 50_000_000;
+
+/// Settings of an export
+#[derive(Debug, Clone)]
+pub struct TantivyExportOptions {
+    /// Data directory holding the index
+    pub data_dir: PathBuf,
+    /// Name of the index to export
+    pub index: String,
+    /// Directory the Tantivy index is created in (must be empty or missing)
+    pub out: PathBuf,
+}
+
+/// Result of an export
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TantivyExportReport {
+    pub index: String,
+    pub out: PathBuf,
+    /// Number of exported documents
+    pub documents: u64,
+    /// Number of typed fields created from the mappings
+    pub mapped_fields: usize,
+    /// Values left out of typed fields because they did not fit the mapped type
+    pub skipped_values: u64,
+}
+
+/// Type of a field in the Tantivy schema
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FieldKind {
+    Text,
+    Keyword,
+    I64,
+    F64,
+    Bool,
+    Date,
+}
+
+/// Build a Tantivy index from a gbs index
+pub fn export_tantivy(options: &TantivyExportOptions) -> Result<TantivyExportReport> {
+    let backend = SledBackend::new(&options.data_dir)?;
+    let metadata = backend
+        .load_index_metadata(&options.index)?
+        .ok_or_else(|| GbsError::IndexNotFound(options.index.clone()))?;
+    build_tantivy_index(
+        &options.index,
+        metadata.mappings.as_ref(),
+        &options.out,
+        |visit| backend.for_each_document(&options.index, visit),
+    )
+}
+
+/// Build a Tantivy index from an index of a checkpoint
+///
+/// Unlike `export_tantivy` this works on a running server, which keeps
+/// serving writes while the checkpoint is exported. Blocks while exporting.
+pub fn export_checkpoint_tantivy(
+    checkpoint: &Checkpoint,
+    index: &str,
+    out: &Path,
+) -> Result<TantivyExportReport> {
+    let metadata = checkpoint
+        .metadata(index)
+        .ok_or_else(|| GbsError::IndexNotFound(index.to_string()))?;
+    build_tantivy_index(index, metadata.mappings.as_ref(), out, |visit| {
+        checkpoint.for_each_document(index, visit)
+    })
+}
+
+/// Visitor of the documents of an index
+type DocumentVisitor<'a> = &'a mut dyn FnMut(&str, serde_json::Value) -> Result<()>;
+
+fn build_tantivy_index<F>(
+    index_name: &str,
+    mappings: Option<&serde_json::Value>,
+    out: &Path,
+    for_each_document: F,
+) -> Result<TantivyExportReport>
+where
+    F: FnOnce(DocumentVisitor) -> Result<()>,
+{
+    prepare_out_dir(out)?;
+
+    let mapped = mapped_fields(mappings);
+    let mut builder = Schema::builder();
+    let id_field = builder.add_text_field("_id", STRING | STORED);
+    let source_field = builder.add_json_field("_source", TEXT | STORED);
+    let fields: Vec<(String, FieldKind, Field)> = mapped
+        .into_iter()
+        .map(|(path, kind)| {
+            let field = match kind {
+                FieldKind::Text => builder.add_text_field(&path, TEXT | STORED),
+                FieldKind::Keyword => builder.add_text_field(&path, STRING | STORED | FAST),
+                FieldKind::I64 => builder.add_i64_field(&path, INDEXED | STORED | FAST),
+                FieldKind::F64 => builder.add_f64_field(&path, INDEXED | STORED | FAST),
+                FieldKind::Bool => builder.add_bool_field(&path, INDEXED | STORED | FAST),
+                FieldKind::Date => builder.add_date_field(
+                    &path,
+                    DateOptions::from(INDEXED)
+                        .set_stored()
+                        .set_fast()
+                        .set_precision(DateTimePrecision::Milliseconds),
+                ),
+            };
+            debug!("Exporting field '{}' as {:?}", path, kind);
+            (path, kind, field)
+        })
+        .collect();
+    let schema = builder.build();
+
+    let index = tantivy::Index::create_in_dir(out, schema).map_err(tantivy_error)?;
+    let mut writer: tantivy::IndexWriter =
+        index.writer(WRITER_MEMORY_BYTES).map_err(tantivy_error)?;
+
+    let mut documents = 0;
+    let mut skipped_values = 0;
+    for_each_document(&mut |id, source| {
+        let mut doc = TantivyDocument::default();
+        doc.add_text(id_field, id);
+        for (path, kind, field) in &fields {
+            let values = match get_field_value(&source, path) {
+                Some(serde_json::Value::Array(values)) => values.iter().collect(),
+                Some(serde_json::Value::Null) | None => Vec::new(),
+                Some(value) => vec![value],
+            };
+            for value in values {
+                if !add_value(&mut doc, *field, *kind, value) {
+                    skipped_values += 1;
+                }
+            }
+        }
+        if let serde_json::Value::Object(object) = source {
+            doc.add_field_value(source_field, OwnedValue::from(object));
+        }
+        writer.add_document(doc).map_err(tantivy_error)?;
+        documents += 1;
+        Ok(())
+    })?;
+
+    writer.commit().map_err(tantivy_error)?;
+    writer.wait_merging_threads().map_err(tantivy_error)?;
+
+    Ok(TantivyExportReport {
+        index: index_name.to_string(),
+        out: out.to_path_buf(),
+        documents,
+        mapped_fields: fields.len(),
+        skipped_values,
+    })
+}
+
+/// Log the outcome of an export
+pub fn log_tantivy_export_report(report: &TantivyExportReport) {
+    info!(
+        "Exported {} documents of index '{}' to Tantivy index {} ({} mapped fields, {} values skipped)",
+        report.documents,
+        report.index,
+        report.out.display(),
+        report.mapped_fields,
+        report.skipped_values
+    );
+}
+
+/// Create the output directory, refusing to write into a non-empty one
+fn prepare_out_dir(out: &Path) -> Result<()> {
+    std::fs::create_dir_all(out)?;
+    if std::fs::read_dir(out)?.next().is_some() {
+        return Err(GbsError::InvalidRequest(format!(
+            "Output directory {} is not empty",
+            out.display()
+        )));
+    }
+    Ok(())
+}
+
+/// Typed fields of a mapping, object properties flattened to dotted paths
+///
+/// Accepts both `{"properties": ...}` and ES 6 typed mappings
+/// (`{"_doc": {"properties": ...}}`).
+fn mapped_fields(mappings: Option<&serde_json::Value>) -> Vec<(String, FieldKind)> {
+    let Some(mappings) = mappings else {
+        return Vec::new();
+    };
+    let properties = match mappings.get("properties") {
+        Some(properties) => Some(properties),
+        None => mappings
+            .as_object()
+            .filter(|obj| obj.len() == 1)
+            .and_then(|obj| obj.values().next())
+            .and_then(|mapping| mapping.get("properties")),
+    };
+
+    let mut fields = Vec::new();
+    if let Some(properties) = properties {
+        collect_fields(properties, "", &mut fields);
+    }
+    fields
+}
+
+fn collect_fields(
+    properties: &serde_json::Value,
+    prefix: &str,
+    fields: &mut Vec<(String, FieldKind)>,
+) {
+    let Some(properties) = properties.as_object() else {
+        return;
+    };
+    for (name, definition) in properties {
+        let path = format!("{}{}", prefix, name);
+        let field_type = definition.get("type").and_then(|t| t.as_str());
+        if let (Some(nested), None | Some("object")) = (definition.get("properties"), field_type) {
+            collect_fields(nested, &format!("{}.", path), fields);
+            continue;
+        }
+        let kind = match field_type {
+            Some("text") | Some("string") => FieldKind::Text,
+            Some("keyword") => FieldKind::Keyword,
+            Some("long") | Some("integer") | Some("short") | Some("byte") => FieldKind::I64,
+            Some("double") | Some("float") | Some("half_float") | Some("scaled_float") => {
+                FieldKind::F64
+            }
+            Some("boolean") => FieldKind::Bool,
+            Some("date") => FieldKind::Date,
+            // Other types (nested, ip, geo_point, ...) are only kept in _source
+            _ => continue,
+        };
+        if path != "_id" && path != "_source" {
+            fields.push((path, kind));
+        }
+    }
+}
+
+/// Add a value to a typed field, returning false if it does not fit the type
+fn add_value(
+    doc: &mut TantivyDocument,
+    field: Field,
+    kind: FieldKind,
+    value: &serde_json::Value,
+) -> bool {
+    match kind {
+        FieldKind::Text | FieldKind::Keyword => match value {
+            serde_json::Value::String(s) => doc.add_text(field, s),
+            serde_json::Value::Number(_) | serde_json::Value::Bool(_) => {
+                doc.add_text(field, value.to_string())
+            }
+            _ => return false,
+        },
+        FieldKind::I64 => match value
+            .as_i64()
+            .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        {
+            Some(n) => doc.add_i64(field, n),
+            None => return false,
+        },
+        FieldKind::F64 => match value
+            .as_f64()
+            .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        {
+            Some(n) => doc.add_f64(field, n),
+            None => return false,
+        },
+        FieldKind::Bool => match value
+            .as_bool()
+            .or_else(|| value.as_str().and_then(|s| s.parse().ok()))
+        {
+            Some(b) => doc.add_bool(field, b),
+            None => return false,
+        },
+        FieldKind::Date => match parse_date(value) {
+            Some(date) => doc.add_date(
+                field,
+                tantivy::DateTime::from_timestamp_millis(date.timestamp_millis()),
+            ),
+            None => return false,
+        },
+    }
+    true
+}
+
+fn tantivy_error(e: TantivyError) -> GbsError {
+    GbsError::Storage(format!("Tantivy error: {}", e))
+}
@@ -0,0 +1,231 @@
+//! Registry of long-running operations (`/_tasks`)
+//!
+//! Reindex, delete-by-query and bulk requests run as tasks registered here
+//! for as long as they execute, so they can be listed and, if cancellable,
+//! cancelled. With `wait_for_completion=false` a request returns its task ID
+//! right away and the operation continues in the background; its outcome is
+//! kept for `GET /_tasks/{id}` (up to `MAX_COMPLETED_TASKS`, oldest first out).
+//! Cancelled operations stop at their next batch boundary.
+
+use chrono::Utc;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::error::{GbsError, Result};
+use crate::storage::wildcard_regex;
+
+/// Number of completed background tasks whose outcome is kept
+pub const MAX_COMPLETED_TASKS: usize = 1000;
+
+/// Reason reported by operations stopped by cancellation
+pub const CANCELED_BY_USER: &str = "by user request";
+
+/// Action of reindex tasks
+pub const REINDEX_ACTION: &str = "indices:data/write/reindex";
+/// Action of delete-by-query tasks
+pub const DELETE_BY_QUERY_ACTION: &str = "indices:data/write/delete/byquery";
+/// Action of bulk tasks
+pub const BULK_ACTION: &str = "indices:data/write/bulk";
+
+/// Shared view of a running task, given to the operation it tracks
+#[derive(Debug, Clone, Default)]
+pub struct TaskHandle {
+    cancelled: Arc<AtomicBool>,
+    status: Arc<Mutex<Option<serde_json::Value>>>,
+}
+
+impl TaskHandle {
+    /// Check if the task was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Report the progress of the operation
+    pub fn set_status(&self, status: serde_json::Value) {
+        *self.status.lock().unwrap() = Some(status);
+    }
+
+    fn status(&self) -> Option<serde_json::Value> {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// A registered task, as listed by the tasks API
+#[derive(Debug, Clone)]
+pub struct Task {
+    pub id: u64,
+    pub action: String,
+    pub description: String,
+    /// Start time in milliseconds since the epoch
+    pub start_time_millis: i64,
+    pub running_time_nanos: u64,
+    pub cancellable: bool,
+    pub cancelled: bool,
+    /// Progress last reported by the operation
+    pub status: Option<serde_json::Value>,
+}
+
+/// Outcome of a completed task: its response, or an error body
+pub type TaskOutcome = std::result::Result<serde_json::Value, serde_json::Value>;
+
+#[derive(Debug)]
+struct RunningTask {
+    action: String,
+    description: String,
+    start_time_millis: i64,
+    started: Instant,
+    cancellable: bool,
+    handle: TaskHandle,
+}
+
+impl RunningTask {
+    fn snapshot(&self, id: u64) -> Task {
+        Task {
+            id,
+            action: self.action.clone(),
+            description: self.description.clone(),
+            start_time_millis: self.start_time_millis,
+            running_time_nanos: self.started.elapsed().as_nanos() as u64,
+            cancellable: self.cancellable,
+            cancelled: self.handle.is_cancelled(),
+            status: self.handle.status(),
+        }
+    }
+}
+
+/// A task and, once it completed, its outcome
+#[derive(Debug, Clone)]
+pub struct TaskResult {
+    pub task: Task,
+    pub outcome: Option<TaskOutcome>,
+}
+
+#[derive(Debug, Default)]
+struct Tasks {
+    running: BTreeMap<u64, RunningTask>,
+    completed: VecDeque<TaskResult>,
+}
+
+/// Tasks of the node
+#[derive(Debug, Default)]
+pub struct TaskRegistry {
+    next_id: AtomicU64,
+    tasks: Mutex<Tasks>,
+}
+
+impl TaskRegistry {
+    /// Register a running task
+    pub fn register(
+        &self,
+        action: &str,
+        description: impl Into<String>,
+        cancellable: bool,
+    ) -> (u64, TaskHandle) {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let handle = TaskHandle::default();
+        self.tasks.lock().unwrap().running.insert(
+            id,
+            RunningTask {
+                action: action.to_string(),
+                description: description.into(),
+                start_time_millis: Utc::now().timestamp_millis(),
+                started: Instant::now(),
+                cancellable,
+                handle: handle.clone(),
+            },
+        );
+        (id, handle)
+    }
+
+    /// Unregister a task, keeping its outcome if given
+    pub fn complete(&self, id: u64, outcome: Option<TaskOutcome>) {
+        let mut tasks = self.tasks.lock().unwrap();
+        let Some(running) = tasks.running.remove(&id) else {
+            return;
+        };
+        if let Some(outcome) = outcome {
+            if tasks.completed.len() >= MAX_COMPLETED_TASKS {
+                tasks.completed.pop_front();
+            }
+            let task = running.snapshot(id);
+            tasks.completed.push_back(TaskResult {
+                task,
+                outcome: Some(outcome),
+            });
+        }
+    }
+
+    /// Running tasks, optionally only those whose action matches one of the
+    /// comma-separated `actions` patterns (`*` wildcards)
+    pub fn list(&self, actions: Option<&str>) -> Vec<Task> {
+        self.tasks
+            .lock()
+            .unwrap()
+            .running
+            .iter()
+            .map(|(id, running)| running.snapshot(*id))
+            .filter(|task| actions.is_none_or(|actions| matches_actions(&task.action, actions)))
+            .collect()
+    }
+
+    /// A running or completed task
+    pub fn get(&self, id: u64) -> Option<TaskResult> {
+        let tasks = self.tasks.lock().unwrap();
+        if let Some(running) = tasks.running.get(&id) {
+            return Some(TaskResult {
+                task: running.snapshot(id),
+                outcome: None,
+            });
+        }
+        tasks
+            .completed
+            .iter()
+            .find(|result| result.task.id == id)
+            .cloned()
+    }
+
+    /// Cancel a running task
+    pub fn cancel(&self, id: u64) -> Result<Task> {
+        let tasks = self.tasks.lock().unwrap();
+        let running = tasks
+            .running
+            .get(&id)
+            .ok_or_else(|| GbsError::TaskNotFound(id.to_string()))?;
+        if !running.cancellable {
+            return Err(GbsError::IllegalArgument(format!(
+                "task [{}] doesn't support cancellation",
+                id
+            )));
+        }
+        running.handle.cancelled.store(true, Ordering::Relaxed);
+        Ok(running.snapshot(id))
+    }
+
+    /// Cancel every cancellable running task matching the `actions` patterns
+    pub fn cancel_matching(&self, actions: Option<&str>) -> Vec<Task> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks
+            .running
+            .iter()
+            .filter(|(_, running)| running.cancellable)
+            .filter(|(_, running)| {
+                actions.is_none_or(|actions| matches_actions(&running.action, actions))
+            })
+            .map(|(id, running)| {
+                running.handle.cancelled.store(true, Ordering::Relaxed);
+                running.snapshot(*id)
+            })
+            .collect()
+    }
+}
+
+/// Check if an action matches one of comma-separated patterns
+fn matches_actions(action: &str, patterns: &str) -> bool {
+    patterns
+        .split(',')
+        .map(str::trim)
+        .filter(|pattern| !pattern.is_empty())
+        .any(|pattern| wildcard_regex(pattern).is_some_and(|re| re.is_match(action)))
+}
@@ -0,0 +1,199 @@
+//! Usage accounting per index and per API key
+//!
+//! Every request is attributed to the caller named by its `Authorization`
+//! header (the ID of an `ApiKey` credential or a Basic username, otherwise
+//! `anonymous`) and, for index APIs, to the index expression in its path. The
+//! tracker keeps running totals since startup plus a history of fixed-length
+//! time buckets, so load on a shared instance can be attributed to teams.
+//! Attribution does not verify credentials; it is accounting, not security.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::auth::{parse_api_key_id, parse_basic_auth};
+use crate::config::UsageConfig;
+
+/// Caller of requests without credentials
+pub const ANONYMOUS_CALLER: &str = "anonymous";
+
+/// Name of the caller a request is attributed to
+pub fn caller_key(authorization: Option<&str>) -> String {
+    authorization
+        .and_then(|authorization| {
+            parse_api_key_id(authorization)
+                .or_else(|| parse_basic_auth(authorization).map(|(username, _)| username))
+        })
+        .unwrap_or_else(|| ANONYMOUS_CALLER.to_string())
+}
+
+/// Usage counters of one index or caller
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct UsageCounters {
+    /// Number of requests
+    pub requests: u64,
+    /// Number of search requests
+    pub searches: u64,
+    /// Time spent answering search requests, in milliseconds
+    pub search_time_ms: f64,
+    /// Bytes of documents written
+    pub bytes_indexed: u64,
+    /// Bytes of response bodies
+    pub bytes_returned: u64,
+}
+
+impl UsageCounters {
+    fn add(&mut self, usage: &Usage) {
+        self.requests += usage.requests;
+        self.searches += usage.searches;
+        self.search_time_ms += usage.search_time.as_secs_f64() * 1000.0;
+        self.bytes_indexed += usage.bytes_indexed;
+        self.bytes_returned += usage.bytes_returned;
+    }
+}
+
+/// Usage to record for a caller and, optionally, an index
+#[derive(Debug, Clone, Default)]
+pub struct Usage {
+    pub caller: String,
+    pub index: Option<String>,
+    pub requests: u64,
+    pub searches: u64,
+    pub search_time: Duration,
+    pub bytes_indexed: u64,
+    pub bytes_returned: u64,
+}
+
+/// Counters broken down per index and per API key
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct UsageBreakdown {
+    pub indices: BTreeMap<String, UsageCounters>,
+    pub api_keys: BTreeMap<String, UsageCounters>,
+}
+
+impl UsageBreakdown {
+    fn add(&mut self, usage: &Usage) {
+        self.api_keys
+            .entry(usage.caller.clone())
+            .or_default()
+            .add(usage);
+        if let Some(index) = &usage.index {
+            self.indices.entry(index.clone()).or_default().add(usage);
+        }
+    }
+}
+
+/// Usage within one time bucket
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageBucket {
+    /// Start of the bucket
+    pub start: DateTime<Utc>,
+    #[serde(flatten)]
+    pub usage: UsageBreakdown,
+}
+
+/// Usage totals and history, as returned by `GET /_gbs/usage`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageReport {
+    /// Start of accounting (server startup)
+    pub since: DateTime<Utc>,
+    pub bucket_secs: u64,
+    pub totals: UsageBreakdown,
+    /// Buckets with activity, oldest first
+    pub history: Vec<UsageBucket>,
+}
+
+#[derive(Debug)]
+struct UsageState {
+    totals: UsageBreakdown,
+    buckets: VecDeque<UsageBucket>,
+}
+
+/// Accumulates usage in totals and time buckets
+#[derive(Debug)]
+pub struct UsageTracker {
+    since: DateTime<Utc>,
+    bucket_secs: u64,
+    history_buckets: usize,
+    state: Mutex<UsageState>,
+}
+
+impl Default for UsageTracker {
+    fn default() -> Self {
+        Self::from_config(&UsageConfig::default())
+    }
+}
+
+impl UsageTracker {
+    pub fn from_config(config: &UsageConfig) -> Self {
+        Self {
+            since: Utc::now(),
+            bucket_secs: config.bucket_secs.max(1),
+            history_buckets: config.history_buckets,
+            state: Mutex::new(UsageState {
+                totals: UsageBreakdown::default(),
+                buckets: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Record usage now
+    pub fn record(&self, usage: Usage) {
+        self.record_at(Utc::now(), usage);
+    }
+
+    /// Record usage at the given time
+    pub fn record_at(&self, time: DateTime<Utc>, usage: Usage) {
+        let bucket_secs = self.bucket_secs as i64;
+        let start = time.timestamp().div_euclid(bucket_secs) * bucket_secs;
+        let start = DateTime::from_timestamp(start, 0).unwrap_or(time);
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.totals.add(&usage);
+        if self.history_buckets == 0 {
+            return;
+        }
+
+        // Usage arriving out of order joins its bucket if that is still kept
+        let position = state.buckets.iter().rposition(|b| b.start <= start);
+        match position {
+            Some(i) if state.buckets[i].start == start => state.buckets[i].usage.add(&usage),
+            _ => {
+                let insert_at = position.map_or(0, |i| i + 1);
+                let mut bucket = UsageBucket {
+                    start,
+                    usage: UsageBreakdown::default(),
+                };
+                bucket.usage.add(&usage);
+                state.buckets.insert(insert_at, bucket);
+            }
+        }
+
+        // Drop buckets older than the history window
+        let oldest_kept = state
+            .buckets
+            .back()
+            .map(|b| b.start.timestamp() - bucket_secs * (self.history_buckets as i64 - 1));
+        while state
+            .buckets
+            .front()
+            .zip(oldest_kept)
+            .is_some_and(|(b, oldest)| b.start.timestamp() < oldest)
+        {
+            state.buckets.pop_front();
+        }
+    }
+
+    /// Totals and history
+    pub fn report(&self) -> UsageReport {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        UsageReport {
+            since: self.since,
+            bucket_secs: self.bucket_secs,
+            totals: state.totals.clone(),
+            history: state.buckets.iter().cloned().collect(),
+        }
+    }
+}
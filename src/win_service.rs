@@ -0,0 +1,218 @@
+//! Windows service
+//!
+//! `gbs service install` registers a `gbs` service running
+//! `gbs service run --dir <dir>` from the directory it was installed from, so
+//! the service loads the same configuration; `gbs service uninstall` removes
+//! it. The service logs to the log file. Its stop and shutdown controls
+//! request the graceful shutdown of `crate::daemon::shutdown_signal`: requests
+//! in flight are drained and the storage flushed before the service reports
+//! it stopped. `gbs start` and `gbs stop` start and stop the installed
+//! service.
+
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::error;
+use windows_service::service::{
+    ServiceAccess, ServiceControl, ServiceControlAccept, ServiceErrorControl, ServiceExitCode,
+    ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+use windows_service::service_manager::{ServiceManager, ServiceManagerAccess};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::daemon;
+use crate::error::{GbsError, Result};
+
+/// Name of the service
+pub const SERVICE_NAME: &str = "gbs";
+const DISPLAY_NAME: &str = "Gummy Bear Search";
+const SERVICE_TYPE: ServiceType = ServiceType::OWN_PROCESS;
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Server run by the service, set by `run` before the dispatcher starts it
+type Serve = Box<dyn FnOnce() -> anyhow::Result<()> + Send>;
+static SERVE: Mutex<Option<Serve>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// Hand the current process to the service manager, which runs `serve` as
+/// the service; returns once the service stopped
+pub fn run(serve: impl FnOnce() -> anyhow::Result<()> + Send + 'static) -> Result<()> {
+    *SERVE.lock().unwrap_or_else(|e| e.into_inner()) = Some(Box::new(serve));
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(service_error)
+}
+
+fn service_main(_arguments: Vec<OsString>) {
+    if let Err(e) = run_service() {
+        error!("gbs service failed: {}", e);
+    }
+}
+
+fn run_service() -> Result<()> {
+    let status_handle = service_control_handler::register(SERVICE_NAME, |control| match control {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            daemon::request_shutdown();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    })
+    .map_err(service_error)?;
+    let set_state = |state: ServiceState, exit_code: u32| {
+        status_handle.set_service_status(ServiceStatus {
+            service_type: SERVICE_TYPE,
+            current_state: state,
+            controls_accepted: if state == ServiceState::Running {
+                ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN
+            } else {
+                ServiceControlAccept::empty()
+            },
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        })
+    };
+    set_state(ServiceState::Running, 0).map_err(service_error)?;
+
+    let serve = SERVE.lock().unwrap_or_else(|e| e.into_inner()).take();
+    let result = match serve {
+        Some(serve) => serve(),
+        None => Err(anyhow::anyhow!("gbs service started without a server")),
+    };
+    if let Err(e) = &result {
+        error!("gbs service stopped with an error: {}", e);
+    }
+    set_state(ServiceState::Stopped, u32::from(result.is_err())).map_err(service_error)?;
+    Ok(())
+}
+
+/// Register the service, started automatically with Windows
+pub fn install(working_dir: &Path) -> Result<()> {
+    let manager = ServiceManager::local_computer(
+        None::<&str>,
+        ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+    )
+    .map_err(service_error)?;
+    let info = ServiceInfo {
+        name: OsString::from(SERVICE_NAME),
+        display_name: OsString::from(DISPLAY_NAME),
+        service_type: SERVICE_TYPE,
+        start_type: ServiceStartType::AutoStart,
+        error_control: ServiceErrorControl::Normal,
+        executable_path: std::env::current_exe()?,
+        launch_arguments: vec![
+            OsString::from("service"),
+            OsString::from("run"),
+            OsString::from("--dir"),
+            working_dir.as_os_str().to_owned(),
+        ],
+        dependencies: vec![],
+        account_name: None,
+        account_password: None,
+    };
+    let service = manager
+        .create_service(&info, ServiceAccess::CHANGE_CONFIG)
+        .map_err(service_error)?;
+    service
+        .set_description("Elasticsearch-compatible search engine")
+        .map_err(service_error)
+}
+
+/// Remove the service, which must be stopped
+pub fn uninstall() -> Result<()> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(service_error)?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::DELETE,
+        )
+        .map_err(service_error)?;
+    if service.query_status().map_err(service_error)?.current_state != ServiceState::Stopped {
+        return Err(GbsError::InvalidRequest(
+            "stop the gbs service before uninstalling it".to_string(),
+        ));
+    }
+    service.delete().map_err(service_error)
+}
+
+/// Start the installed service, returning the process ID it runs in
+pub fn start(timeout: Duration) -> Result<u32> {
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(service_error)?;
+    let service = manager
+        .open_service(
+            SERVICE_NAME,
+            ServiceAccess::QUERY_STATUS | ServiceAccess::START,
+        )
+        .map_err(|e| {
+            GbsError::InvalidRequest(format!(
+                "the gbs service is not installed, run `gbs service install` first ({})",
+                e
+            ))
+        })?;
+    service.start::<&OsStr>(&[]).map_err(service_error)?;
+    let deadline = Instant::now() + timeout;
+    loop {
+        let status = service.query_status().map_err(service_error)?;
+        match (status.current_state, status.process_id) {
+            (ServiceState::Running, Some(pid)) => return Ok(pid),
+            (ServiceState::Stopped, _) => {
+                return Err(GbsError::InvalidRequest(
+                    "the gbs service stopped during startup".to_string(),
+                ))
+            }
+            _ if Instant::now() >= deadline => {
+                return Err(GbsError::InvalidRequest(format!(
+                    "the gbs service did not start within {:?}",
+                    timeout
+                )))
+            }
+            _ => std::thread::sleep(POLL_INTERVAL),
+        }
+    }
+}
+
+/// Whether the service runs in the process `pid`
+pub fn is_running(pid: u32) -> bool {
+    status().is_some_and(|status| {
+        status.current_state == ServiceState::Running && status.process_id == Some(pid)
+    })
+}
+
+/// Ask the service running in the process `pid` to stop
+///
+/// Servers not running as the service have no console to receive Ctrl-C and
+/// are not stopped, as terminating them would skip the final flush.
+pub fn stop(pid: u32) -> Result<()> {
+    if !is_running(pid) {
+        return Err(GbsError::InvalidRequest(format!(
+            "gbs (pid {}) does not run as the gbs service; stop it with Ctrl-C in its console",
+            pid
+        )));
+    }
+    let manager = ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT)
+        .map_err(service_error)?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::STOP)
+        .map_err(service_error)?;
+    service.stop().map_err(service_error)?;
+    Ok(())
+}
+
+/// Status of the installed service, `None` if it is not installed
+fn status() -> Option<ServiceStatus> {
+    let manager =
+        ServiceManager::local_computer(None::<&str>, ServiceManagerAccess::CONNECT).ok()?;
+    let service = manager
+        .open_service(SERVICE_NAME, ServiceAccess::QUERY_STATUS)
+        .ok()?;
+    service.query_status().ok()
+}
+
+fn service_error(error: windows_service::Error) -> GbsError {
+    GbsError::InvalidRequest(format!("gbs service: {}", error))
+}
@@ -0,0 +1,270 @@
+//! Tests for date histogram aggregations and the aggregation cache
+
+use gbs::storage::{AggregationCacheStats, SearchRequest, Storage};
+use serde_json::{json, Value};
+
+async fn aggregate(storage: &Storage, query: Value, aggs: Value) -> Value {
+    storage
+        .search_with_request(
+            "logs",
+            &SearchRequest {
+                size: Some(0),
+                aggs: Some(aggs),
+                ..SearchRequest::new(query)
+            },
+        )
+        .await
+        .unwrap()
+}
+
+fn doc_counts(result: &Value, name: &str) -> Vec<(String, u64)> {
+    result["aggregations"][name]["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| {
+            (
+                bucket["key_as_string"].as_str().unwrap().to_string(),
+                bucket["doc_count"].as_u64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+async fn storage_with_logs(timestamps: &[&str]) -> Storage {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    for (id, timestamp) in timestamps.iter().enumerate() {
+        storage
+            .index_document(
+                "logs",
+                &id.to_string(),
+                json!({ "timestamp": timestamp, "level": "info" }),
+            )
+            .await
+            .unwrap();
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_date_histogram_intervals() {
+    let storage = storage_with_logs(&[
+        "2024-03-15T10:00:00Z",
+        "2024-03-15T23:00:00Z",
+        "2024-03-17T01:00:00Z",
+        "2024-05-02",
+    ])
+    .await;
+    let match_all = json!({ "match_all": {} });
+
+    // Empty buckets between the first and the last one are filled
+    let result = aggregate(
+        &storage,
+        match_all.clone(),
+        json!({ "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } } }),
+    )
+    .await;
+    assert_eq!(result["hits"]["total"]["value"], 4);
+    assert_eq!(result["hits"]["hits"], json!([]));
+    let buckets = doc_counts(&result, "per_day");
+    assert_eq!(buckets.len(), 49);
+    assert_eq!(buckets[0], ("2024-03-15T00:00:00.000Z".to_string(), 2));
+    assert_eq!(buckets[1], ("2024-03-16T00:00:00.000Z".to_string(), 0));
+    assert_eq!(buckets[2], ("2024-03-17T00:00:00.000Z".to_string(), 1));
+    assert_eq!(buckets[48], ("2024-05-02T00:00:00.000Z".to_string(), 1));
+    assert_eq!(
+        result["aggregations"]["per_day"]["buckets"][0]["key"],
+        1_710_460_800_000_i64
+    );
+
+    let result = aggregate(
+        &storage,
+        match_all.clone(),
+        json!({
+            "per_month": { "date_histogram": { "field": "timestamp", "calendar_interval": "1M" } },
+            "per_week": { "date_histogram": { "field": "timestamp", "calendar_interval": "week", "min_doc_count": 1 } },
+            "per_12h": { "date_histogram": { "field": "timestamp", "fixed_interval": "12h", "min_doc_count": 1 } }
+        }),
+    )
+    .await;
+    assert_eq!(
+        doc_counts(&result, "per_month"),
+        vec![
+            ("2024-03-01T00:00:00.000Z".to_string(), 3),
+            ("2024-04-01T00:00:00.000Z".to_string(), 0),
+            ("2024-05-01T00:00:00.000Z".to_string(), 1),
+        ]
+    );
+    // Weeks start on Monday
+    assert_eq!(
+        doc_counts(&result, "per_week"),
+        vec![
+            ("2024-03-11T00:00:00.000Z".to_string(), 3),
+            ("2024-04-29T00:00:00.000Z".to_string(), 1),
+        ]
+    );
+    assert_eq!(
+        doc_counts(&result, "per_12h"),
+        vec![
+            ("2024-03-15T00:00:00.000Z".to_string(), 1),
+            ("2024-03-15T12:00:00.000Z".to_string(), 1),
+            ("2024-03-17T00:00:00.000Z".to_string(), 1),
+            ("2024-05-02T00:00:00.000Z".to_string(), 1),
+        ]
+    );
+
+    for aggs in [
+        json!({ "levels": { "terms": { "field": "level" } } }),
+        json!({ "h": { "date_histogram": { "field": "timestamp" } } }),
+        json!({ "h": { "date_histogram": { "field": "timestamp", "calendar_interval": "2d" } } }),
+        json!({ "h": { "date_histogram": { "field": "timestamp", "fixed_interval": "month" } } }),
+        json!({ "h": { "date_histogram": { "field": "timestamp", "interval": "day", "time_zone": "+01:00" } } }),
+    ] {
+        assert!(storage
+            .search_with_request(
+                "logs",
+                &SearchRequest {
+                    size: Some(0),
+                    aggs: Some(aggs),
+                    ..SearchRequest::new(match_all.clone())
+                },
+            )
+            .await
+            .is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_aggregation_cache_reuses_buckets_for_appends() {
+    let storage = storage_with_logs(&["2024-03-14T08:00:00Z", "2024-03-15T09:00:00Z"]).await;
+    let query = json!({ "match_all": {} });
+    let aggs = json!({ "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } } });
+    let stats = |hits, partial_hits, misses| AggregationCacheStats {
+        hits,
+        partial_hits,
+        misses,
+        entries: 1,
+    };
+
+    let result = aggregate(&storage, query.clone(), aggs.clone()).await;
+    assert_eq!(doc_counts(&result, "per_day").len(), 2);
+    assert_eq!(storage.aggregation_cache_stats(), stats(0, 0, 1));
+
+    let cached = aggregate(&storage, query.clone(), aggs.clone()).await;
+    assert_eq!(cached["aggregations"], result["aggregations"]);
+    assert_eq!(storage.aggregation_cache_stats(), stats(1, 0, 1));
+
+    // Appended documents are counted into the cached buckets
+    for (id, timestamp) in [("2", "2024-03-15T10:00:00Z"), ("3", "2024-03-16T00:30:00Z")] {
+        storage
+            .index_document("logs", id, json!({ "timestamp": timestamp }))
+            .await
+            .unwrap();
+    }
+    let result = aggregate(&storage, query.clone(), aggs.clone()).await;
+    assert_eq!(result["hits"]["total"]["value"], 4);
+    assert_eq!(
+        doc_counts(&result, "per_day"),
+        vec![
+            ("2024-03-14T00:00:00.000Z".to_string(), 1),
+            ("2024-03-15T00:00:00.000Z".to_string(), 2),
+            ("2024-03-16T00:00:00.000Z".to_string(), 1),
+        ]
+    );
+    assert_eq!(storage.aggregation_cache_stats(), stats(1, 1, 1));
+
+    // Replacing or deleting a document invalidates the cached counts
+    storage
+        .index_document("logs", "0", json!({ "timestamp": "2024-03-16T08:00:00Z" }))
+        .await
+        .unwrap();
+    let result = aggregate(&storage, query.clone(), aggs.clone()).await;
+    assert_eq!(
+        doc_counts(&result, "per_day"),
+        vec![
+            ("2024-03-15T00:00:00.000Z".to_string(), 2),
+            ("2024-03-16T00:00:00.000Z".to_string(), 2),
+        ]
+    );
+    assert_eq!(storage.aggregation_cache_stats(), stats(1, 1, 2));
+
+    storage.delete_document("logs", "3").await.unwrap();
+    let result = aggregate(&storage, query.clone(), aggs.clone()).await;
+    assert_eq!(result["hits"]["total"]["value"], 3);
+    assert_eq!(storage.aggregation_cache_stats(), stats(1, 1, 3));
+
+    // A recreated index does not see the entries of its predecessor
+    storage.delete_index("logs").await.unwrap();
+    storage.create_index("logs", None, None).await.unwrap();
+    let result = aggregate(&storage, query, aggs).await;
+    assert_eq!(result["hits"]["total"]["value"], 0);
+    assert_eq!(doc_counts(&result, "per_day"), vec![]);
+    assert_eq!(storage.aggregation_cache_stats(), stats(1, 1, 4));
+}
+
+#[tokio::test]
+async fn test_aggregation_cache_is_keyed_by_query() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    for (id, level) in ["info", "error", "error"].iter().enumerate() {
+        storage
+            .index_document(
+                "logs",
+                &id.to_string(),
+                json!({ "timestamp": "2024-03-15T10:00:00Z", "level": level }),
+            )
+            .await
+            .unwrap();
+    }
+    let aggs = json!({ "per_hour": { "date_histogram": { "field": "timestamp", "fixed_interval": "1h" } } });
+
+    let errors = aggregate(
+        &storage,
+        json!({ "term": { "level": "error" } }),
+        aggs.clone(),
+    )
+    .await;
+    let infos = aggregate(
+        &storage,
+        json!({ "term": { "level": "info" } }),
+        aggs.clone(),
+    )
+    .await;
+    assert_eq!(doc_counts(&errors, "per_hour")[0].1, 2);
+    assert_eq!(doc_counts(&infos, "per_hour")[0].1, 1);
+
+    // Appended documents that do not match the query are not counted
+    storage
+        .index_document(
+            "logs",
+            "3",
+            json!({ "timestamp": "2024-03-15T10:30:00Z", "level": "info" }),
+        )
+        .await
+        .unwrap();
+    let errors = aggregate(
+        &storage,
+        json!({ "term": { "level": "error" } }),
+        aggs.clone(),
+    )
+    .await;
+    assert_eq!(errors["hits"]["total"]["value"], 2);
+    assert_eq!(doc_counts(&errors, "per_hour")[0].1, 2);
+
+    // Searches returning hits compute the aggregations along with them
+    let result = storage
+        .search_with_request(
+            "logs",
+            &SearchRequest {
+                size: Some(10),
+                aggs: Some(aggs),
+                ..SearchRequest::new(json!({ "match_all": {} }))
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 4);
+    assert_eq!(doc_counts(&result, "per_hour")[0].1, 4);
+    assert_eq!(storage.aggregation_cache_stats().entries, 3);
+}
@@ -0,0 +1,201 @@
+//! Tests for the audit log of write and admin operations
+
+mod common;
+
+use axum_test::TestServer;
+use common::{basic_auth, server_with};
+use gbs::audit::{audit_action, AuditEvent, AuditLog};
+use gbs::auth::AuthStore;
+use gbs::config::{AuditConfig, SecurityConfig, UserConfig};
+use gbs::server::{create_router, AppState};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn read_events(path: &Path) -> Vec<AuditEvent> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn test_audit_action() {
+    assert_eq!(audit_action("PUT", "/:index"), "create_index");
+    assert_eq!(audit_action("DELETE", "/es/:index"), "delete_index");
+    assert_eq!(audit_action("PUT", "/:index/_doc/:id"), "write_document");
+    assert_eq!(
+        audit_action("DELETE", "/:index/_doc/:id"),
+        "delete_document"
+    );
+    assert_eq!(audit_action("POST", "/_bulk"), "write_document");
+    assert_eq!(
+        audit_action("POST", "/:index/_delete_by_query"),
+        "write_document"
+    );
+    assert_eq!(audit_action("PUT", "/:index/_settings"), "change_settings");
+    assert_eq!(audit_action("PUT", "/:index/_mapping"), "change_settings");
+    assert_eq!(
+        audit_action("PUT", "/:index/_mapping/:type"),
+        "change_settings"
+    );
+    assert_eq!(audit_action("POST", "/:index/:type"), "write_document");
+    assert_eq!(
+        audit_action("DELETE", "/:index/:type/:id"),
+        "delete_document"
+    );
+    assert_eq!(audit_action("POST", "/_config/reload"), "change_settings");
+    assert_eq!(
+        audit_action("DELETE", "/_security/user/:username"),
+        "security"
+    );
+}
+
+#[tokio::test]
+async fn test_writes_are_appended_to_audit_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("logs").join("audit.ndjson");
+    let state =
+        AppState::new(Arc::new(Storage::new()), "6.8.23").with_audit_log(AuditLog::to_file(&path));
+    let app = create_router(state).into_make_service_with_connect_info::<SocketAddr>();
+    let server = TestServer::builder().http_transport().build(app).unwrap();
+
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .add_header("X-Request-Id", "req-1")
+        .add_header("X-Forwarded-For", "203.0.113.7")
+        .json(&json!({ "title": "dune" }))
+        .await;
+    server
+        .put("/books/_mapping")
+        .json(&json!({ "properties": { "year": { "type": "integer" } } }))
+        .await
+        .assert_status_ok();
+    // Reads are not audited
+    server.get("/books/_doc/1").await.assert_status_ok();
+    server
+        .post("/books/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+    server.delete("/books/_doc/1").await.assert_status_ok();
+    server.delete("/missing").await.assert_status_not_found();
+    server.delete("/books").await.assert_status_ok();
+
+    let events = read_events(&path);
+    let actions: Vec<(&str, &str, u16)> = events
+        .iter()
+        .map(|event| {
+            (
+                event.action.as_str(),
+                event.index.as_deref().unwrap_or_default(),
+                event.status,
+            )
+        })
+        .collect();
+    assert_eq!(
+        actions,
+        vec![
+            ("create_index", "books", 200),
+            ("write_document", "books", 201),
+            ("change_settings", "books", 200),
+            ("delete_document", "books", 200),
+            ("delete_index", "missing", 404),
+            ("delete_index", "books", 200),
+        ]
+    );
+
+    let write = &events[1];
+    assert_eq!(write.user, "anonymous");
+    assert_eq!(write.method, "PUT");
+    assert_eq!(write.path, "/books/_doc/1");
+    assert_eq!(write.client_ip.as_deref(), Some("127.0.0.1"));
+    assert_eq!(write.forwarded_for.as_deref(), Some("203.0.113.7"));
+    assert_eq!(write.request_id.as_deref(), Some("req-1"));
+    assert!(events[0].forwarded_for.is_none());
+}
+
+#[tokio::test]
+async fn test_writes_are_indexed_into_audit_index() {
+    let storage = Storage::new();
+    let config = SecurityConfig {
+        enabled: true,
+        users: vec![UserConfig {
+            username: "elastic".to_string(),
+            password: "changeme".to_string(),
+            roles: vec!["superuser".to_string()],
+        }],
+        ..Default::default()
+    };
+    let auth = AuthStore::load(&config, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage.clone()), "6.8.23")
+        .with_auth(auth)
+        .with_audit_log(AuditLog::to_index(storage.clone(), ".gbs-audit"));
+    let server = server_with(state);
+    let credentials = basic_auth("elastic", "changeme");
+
+    // Rejected credentials are not audited
+    server.put("/books").await.assert_status_unauthorized();
+    assert!(!storage.index_exists(".gbs-audit").await.unwrap());
+
+    server
+        .put("/books")
+        .add_header("Authorization", credentials.clone())
+        .await
+        .assert_status_ok();
+    server
+        .put("/_security/user/reader")
+        .add_header("Authorization", credentials.clone())
+        .json(&json!({ "password": "secret123", "roles": ["viewer"] }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .post("/.gbs-audit/_search")
+        .add_header("Authorization", credentials)
+        .json(&json!({ "query": { "match": { "action": "security" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    let event = &body["hits"]["hits"][0]["_source"];
+    assert_eq!(event["user"], "elastic");
+    assert_eq!(event["path"], "/_security/user/reader");
+    assert_eq!(event["status"], 200);
+    assert!(event["request_id"].is_string());
+    assert!(event.get("client_ip").is_none());
+}
+
+#[tokio::test]
+async fn test_disabled_audit_log_records_nothing() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("audit.ndjson");
+    let mut config = AuditConfig::default();
+    let log = AuditLog::from_config(&config, path.clone(), Storage::new());
+    assert!(!log.is_enabled());
+
+    let event = AuditEvent {
+        timestamp: chrono::Utc::now(),
+        action: "create_index".to_string(),
+        user: "anonymous".to_string(),
+        client_ip: None,
+        forwarded_for: None,
+        method: "PUT".to_string(),
+        path: "/books".to_string(),
+        index: Some("books".to_string()),
+        status: 200,
+        request_id: None,
+    };
+    log.record(&event).await.unwrap();
+    assert!(!path.exists());
+
+    config.enabled = true;
+    let log = AuditLog::from_config(&config, path.clone(), Storage::new());
+    log.record(&event).await.unwrap();
+    assert_eq!(read_events(&path), vec![event]);
+}
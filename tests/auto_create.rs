@@ -0,0 +1,156 @@
+//! Tests for automatic index creation on document writes
+
+mod common;
+
+use common::index_action;
+use gbs::bulk_ops::BulkAction;
+use gbs::config::AutoCreateIndex;
+use gbs::error::GbsError;
+use gbs::storage::{IndexTemplate, Storage, StorageLimits, TemplateKind};
+use serde_json::json;
+
+fn auto_create(policy: &str) -> Storage {
+    Storage::new().with_limits(StorageLimits {
+        auto_create_index: Some(AutoCreateIndex::parse(policy).unwrap()),
+        ..Default::default()
+    })
+}
+
+fn action(index: &str, id: &str) -> BulkAction {
+    index_action(index, id, json!({ "title": "doc" }))
+}
+
+async fn put_logs_template(storage: &Storage) {
+    let template = IndexTemplate::parse(
+        TemplateKind::Legacy,
+        &json!({
+            "index_patterns": ["logs-*"],
+            "mappings": { "properties": { "level": { "type": "keyword" } } }
+        }),
+    )
+    .unwrap();
+    storage
+        .put_template(TemplateKind::Legacy, "logs", template)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_index_document_creates_missing_index() {
+    let storage = auto_create("true");
+    storage
+        .index_document("products", "1", json!({ "title": "doc" }))
+        .await
+        .unwrap();
+    assert!(storage.index_exists("products").await.unwrap());
+    assert!(storage.get_document("products", "1").await.is_ok());
+
+    let (id, _) = storage
+        .create_document("orders", json!({ "total": 3 }))
+        .await
+        .unwrap();
+    assert!(storage.get_document("orders", &id).await.is_ok());
+}
+
+#[tokio::test]
+async fn test_bulk_creates_missing_indices_with_templates() {
+    let storage = auto_create("true");
+    put_logs_template(&storage).await;
+
+    let results = storage
+        .execute_bulk(vec![
+            action("logs-1", "1"),
+            action("logs-1", "2"),
+            action("other", "1"),
+        ])
+        .await;
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    let index = storage.get_index("logs-1").await.unwrap();
+    assert_eq!(
+        index["logs-1"]["mappings"]["properties"]["level"]["type"],
+        json!("keyword")
+    );
+    assert!(storage.index_exists("other").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_disabled_auto_create_ignores_templates() {
+    let storage = auto_create("false");
+    put_logs_template(&storage).await;
+
+    let result = storage
+        .index_document("logs-1", "1", json!({ "title": "doc" }))
+        .await;
+    assert!(matches!(result, Err(GbsError::IndexNotFound(_))));
+    assert!(!storage.index_exists("logs-1").await.unwrap());
+
+    // Without a configured policy only template matches are created
+    let storage = Storage::new();
+    put_logs_template(&storage).await;
+    storage
+        .index_document("logs-1", "1", json!({ "title": "doc" }))
+        .await
+        .unwrap();
+    assert!(storage
+        .index_document("other", "1", json!({ "title": "doc" }))
+        .await
+        .is_err());
+    assert!(!storage.index_exists("other").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_auto_create_patterns_first_match_decides() {
+    let storage = auto_create("-logs-tmp*,+logs-*,metrics");
+
+    let results = storage
+        .execute_bulk(vec![
+            action("logs-1", "1"),
+            action("logs-tmp-1", "1"),
+            action("metrics", "1"),
+            action("other", "1"),
+        ])
+        .await;
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(GbsError::IndexNotFound(_))));
+    assert!(results[2].is_ok());
+    assert!(matches!(results[3], Err(GbsError::IndexNotFound(_))));
+    assert!(!storage.index_exists("logs-tmp-1").await.unwrap());
+    assert!(!storage.index_exists("other").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_auto_create_respects_max_indices() {
+    let storage = Storage::new().with_limits(StorageLimits {
+        max_indices: Some(1),
+        auto_create_index: Some(AutoCreateIndex::All),
+        ..Default::default()
+    });
+    storage
+        .index_document("first", "1", json!({ "title": "doc" }))
+        .await
+        .unwrap();
+    let result = storage
+        .index_document("second", "1", json!({ "title": "doc" }))
+        .await;
+    assert!(result.is_err());
+    assert!(!storage.index_exists("second").await.unwrap());
+}
+
+#[test]
+fn test_parse_auto_create_index() {
+    assert_eq!(AutoCreateIndex::parse("true"), Some(AutoCreateIndex::All));
+    assert_eq!(
+        AutoCreateIndex::parse("false"),
+        Some(AutoCreateIndex::Disabled)
+    );
+    assert_eq!(
+        AutoCreateIndex::parse("+logs-*, -tmp*"),
+        Some(AutoCreateIndex::Patterns(vec![
+            (true, "logs-*".to_string()),
+            (false, "tmp*".to_string()),
+        ]))
+    );
+    assert_eq!(AutoCreateIndex::parse(""), None);
+    assert_eq!(AutoCreateIndex::parse("logs-*,-"), None);
+}
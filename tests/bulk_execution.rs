@@ -0,0 +1,160 @@
+//! Tests for batched execution of bulk actions
+
+mod common;
+
+use common::{index_action, open_sled};
+use gbs::bulk_ops::{parse_bulk_ndjson, BulkAction};
+use gbs::error::GbsError;
+use gbs::storage::{IndexTier, Storage, StorageLimits};
+use serde_json::json;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_bulk_actions_see_earlier_actions_of_the_batch() {
+    let storage = Storage::new();
+    storage.create_index("test", None, None).await.unwrap();
+
+    let body = r#"{"index":{"_index":"test","_id":"1"}}
+{"title":"first","views":1}
+{"create":{"_index":"test","_id":"1"}}
+{"title":"duplicate"}
+{"update":{"_index":"test","_id":"1"}}
+{"doc":{"views":2}}
+{"create":{"_index":"test","_id":"2"}}
+{"title":"second"}
+{"delete":{"_index":"test","_id":"2"}}
+{"delete":{"_index":"test","_id":"2"}}
+{"index":{"_index":"missing","_id":"1"}}
+{"title":"nowhere"}
+"#;
+    let actions = parse_bulk_ndjson(body, None).unwrap();
+    let results = storage.execute_bulk(actions).await;
+
+    let statuses: Vec<Option<u16>> = results
+        .iter()
+        .map(|result| result.as_ref().ok().map(|(_, _, status, ..)| *status))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![Some(201), None, Some(200), Some(201), Some(200), None, None]
+    );
+
+    let doc = storage.get_document("test", "1").await.unwrap();
+    assert_eq!(doc["_source"], json!({ "title": "first", "views": 2 }));
+    assert!(storage.get_document("test", "2").await.is_err());
+    assert!(!storage.index_exists("missing").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_bulk_load_persists_all_batches() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("bulk_db");
+
+    {
+        let storage = open_sled(&data_path).await;
+        storage.create_index("bulk", None, None).await.unwrap();
+
+        // More actions than fit in one batch
+        let mut actions: Vec<BulkAction> = (0..2500)
+            .map(|i| index_action("bulk", &i.to_string(), json!({ "n": i })))
+            .collect();
+        actions.push(BulkAction::Delete {
+            index: "bulk".to_string(),
+            id: "7".to_string(),
+        });
+        let results = storage.execute_bulk(actions).await;
+        assert_eq!(results.len(), 2501);
+        assert!(results.iter().all(|result| result.is_ok()));
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&data_path).await;
+    let stats = storage.get_indices_stats().await;
+    assert_eq!(stats, vec![("bulk".to_string(), 2499)]);
+    assert!(storage.get_document("bulk", "2499").await.is_ok());
+    assert!(storage.get_document("bulk", "7").await.is_err());
+}
+
+#[tokio::test]
+async fn test_bulk_writes_through_alias_roll_over() {
+    let storage = Storage::new().with_limits(StorageLimits {
+        rollover_max_docs: Some(2),
+        ..Default::default()
+    });
+    storage
+        .create_index("logs-000001", None, None)
+        .await
+        .unwrap();
+    storage.put_alias("logs-000001", "logs").await.unwrap();
+
+    let actions = (0..5)
+        .map(|i| index_action("logs", &i.to_string(), json!({ "n": i })))
+        .collect();
+    let results = storage.execute_bulk(actions).await;
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    let mut indices = storage.list_indices().await;
+    indices.sort();
+    assert_eq!(indices, vec!["logs-000001", "logs-000002", "logs-000003"]);
+    assert!(storage.get_document("logs-000001", "1").await.is_ok());
+    assert!(storage.get_document("logs-000002", "3").await.is_ok());
+    assert!(storage.get_document("logs-000003", "4").await.is_ok());
+}
+
+#[tokio::test]
+async fn test_bulk_deletes_through_alias() {
+    let storage = Storage::new();
+    storage.create_index("books-v1", None, None).await.unwrap();
+    storage.put_alias("books-v1", "books").await.unwrap();
+
+    let body = r#"{"index":{"_index":"books","_id":"1"}}
+{"title":"Dune"}
+{"delete":{"_index":"books","_id":"1"}}
+{"delete":{"_index":"books","_id":"1"}}
+"#;
+    let actions = parse_bulk_ndjson(body, None).unwrap();
+    let results = storage.execute_bulk(actions).await;
+    assert!(results[0].is_ok());
+    assert!(results[1].is_ok());
+    assert!(matches!(results[2], Err(GbsError::DocumentNotFound(_))));
+    assert!(storage.get_document("books-v1", "1").await.is_err());
+}
+
+#[tokio::test]
+async fn test_bulk_writes_to_warm_index() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = open_sled(&temp_dir.path().join("warm_db")).await;
+    storage.create_index("archive", None, None).await.unwrap();
+    let actions = (0..3)
+        .map(|i| index_action("archive", &i.to_string(), json!({ "n": i })))
+        .collect();
+    storage.execute_bulk(actions).await;
+    storage
+        .set_index_tier("archive", IndexTier::Warm)
+        .await
+        .unwrap();
+
+    let body = r#"{"create":{"_index":"archive","_id":"0"}}
+{"n":10}
+{"update":{"_index":"archive","_id":"1"}}
+{"doc":{"tag":"updated"}}
+{"delete":{"_index":"archive","_id":"2"}}
+{"delete":{"_index":"archive","_id":"3"}}
+"#;
+    let actions = parse_bulk_ndjson(body, None).unwrap();
+    let statuses: Vec<Option<u16>> = storage
+        .execute_bulk(actions)
+        .await
+        .iter()
+        .map(|result| result.as_ref().ok().map(|(_, _, status, ..)| *status))
+        .collect();
+    assert_eq!(statuses, vec![None, Some(200), Some(200), None]);
+
+    let doc = storage.get_document("archive", "1").await.unwrap();
+    assert_eq!(doc["_source"], json!({ "n": 1, "tag": "updated" }));
+    assert!(storage.get_document("archive", "2").await.is_err());
+    assert_eq!(
+        storage.get_indices_stats().await,
+        vec![("archive".to_string(), 2)]
+    );
+}
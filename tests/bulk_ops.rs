@@ -1,6 +1,6 @@
 //! Unit tests for bulk operations parsing
 
-use gbs::bulk_ops::{parse_bulk_ndjson, BulkAction};
+use gbs::bulk_ops::{parse_bulk_ndjson, to_bulk_ndjson, BulkAction};
 
 #[test]
 fn test_parse_bulk_index_operations() {
@@ -573,3 +573,21 @@ fn test_parse_bulk_mixed_with_default_index() {
         }
     }
 }
+
+#[test]
+fn test_to_bulk_ndjson_round_trip() {
+    let bulk_body = r#"{"index":{"_index":"products","_id":"1"}}
+{"name":"Product 1"}
+{"create":{"_index":"products"}}
+{"name":"Product 2"}
+{"update":{"_index":"products","_id":"1"}}
+{"doc":{"price":10}}
+{"delete":{"_index":"products","_id":"3"}}
+"#;
+
+    let actions = parse_bulk_ndjson(bulk_body, None).unwrap();
+    let serialized = to_bulk_ndjson(&actions);
+    assert!(serialized.ends_with('\n'));
+    let reparsed = parse_bulk_ndjson(&serialized, None).unwrap();
+    assert_eq!(format!("{:?}", reparsed), format!("{:?}", actions));
+}
@@ -0,0 +1,106 @@
+//! Tests for the details of bulk item responses
+
+mod common;
+
+use axum_test::TestServer;
+use common::test_server;
+use gbs::storage::Storage;
+use serde_json::Value;
+
+async fn server() -> TestServer {
+    let server = test_server(Storage::new(), "8.11.0");
+    server.put("/books").await.assert_status_ok();
+    server
+}
+
+async fn bulk(server: &TestServer, body: &str, refresh: Option<&str>) -> Value {
+    let mut request = server
+        .post("/books/_bulk")
+        .text(body.to_string())
+        .content_type("application/x-ndjson");
+    if let Some(refresh) = refresh {
+        request = request.add_query_param("refresh", refresh);
+    }
+    let response = request.await;
+    response.assert_status_ok();
+    response.json()
+}
+
+#[tokio::test]
+async fn test_bulk_items_carry_sequence_numbers_and_results() {
+    let server = server().await;
+    let body = bulk(
+        &server,
+        "{\"index\":{\"_id\":\"1\"}}\n{\"title\":\"Dune\"}\n\
+         {\"index\":{\"_id\":\"1\"}}\n{\"title\":\"Dune Messiah\"}\n\
+         {\"create\":{\"_id\":\"1\"}}\n{\"title\":\"Emma\"}\n\
+         {\"delete\":{\"_id\":\"1\"}}\n",
+        None,
+    )
+    .await;
+    assert_eq!(body["errors"], true);
+    let items = body["items"].as_array().unwrap();
+
+    assert_eq!(items[0]["index"]["status"], 201);
+    assert_eq!(items[0]["index"]["result"], "created");
+    // Replacing a document updates it, like a single-document write does
+    assert_eq!(items[1]["index"]["status"], 200);
+    assert_eq!(items[1]["index"]["result"], "updated");
+    assert_eq!(items[3]["delete"]["result"], "deleted");
+    let seq_nos: Vec<u64> = [&items[0]["index"], &items[1]["index"], &items[3]["delete"]]
+        .iter()
+        .map(|item| item["_seq_no"].as_u64().unwrap())
+        .collect();
+    assert!(seq_nos.windows(2).all(|pair| pair[0] < pair[1]));
+    assert_eq!(seq_nos[0], 0);
+    assert_eq!(items[0]["index"]["_version"], 1);
+    assert_eq!(items[1]["index"]["_version"], 2);
+    assert_eq!(items[3]["delete"]["_version"], 3);
+    assert_eq!(items[0]["index"]["_primary_term"], 1);
+    assert!(items[0]["index"].get("forced_refresh").is_none());
+
+    // Creating a document that exists conflicts
+    let conflict = &items[2]["create"];
+    assert_eq!(conflict["status"], 409);
+    assert_eq!(
+        conflict["error"]["type"],
+        "version_conflict_engine_exception"
+    );
+    assert!(conflict.get("result").is_none());
+    assert!(conflict.get("_seq_no").is_none());
+
+    // Sequence numbers follow the change feed, whose `seq` counts from 1
+    let response = server.get("/books/_changes").await;
+    let changes: Value = response.json();
+    let feed: Vec<u64> = changes["changes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|change| change["seq"].as_u64().unwrap() - 1)
+        .collect();
+    assert_eq!(feed, seq_nos);
+}
+
+#[tokio::test]
+async fn test_bulk_items_report_forced_refresh() {
+    let server = server().await;
+    let body = bulk(
+        &server,
+        "{\"index\":{\"_id\":\"1\"}}\n{\"title\":\"Dune\"}\n\
+         {\"create\":{\"_id\":\"1\"}}\n{\"title\":\"Emma\"}\n",
+        Some("true"),
+    )
+    .await;
+    let items = body["items"].as_array().unwrap();
+    assert_eq!(items[0]["index"]["forced_refresh"], true);
+    assert!(items[1]["create"].get("forced_refresh").is_none());
+
+    let body = bulk(
+        &server,
+        "{\"index\":{\"_id\":\"2\"}}\n{\"title\":\"Emma\"}\n",
+        Some("wait_for"),
+    )
+    .await;
+    assert!(body["items"][0]["index"].get("forced_refresh").is_none());
+    assert!(body["items"][0]["index"]["_seq_no"].is_u64());
+}
@@ -0,0 +1,161 @@
+//! Tests for the catch-all (`_all`) field and `index.query.default_field`
+
+mod common;
+
+use axum_test::TestServer;
+use common::{sorted_ids, test_server};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// Accounts collecting their string values into `_all`, except for secrets
+async fn accounts() -> TestServer {
+    let server = test_server(Storage::new(), "8.11.0");
+    server
+        .put("/accounts")
+        .json(&json!({
+            "mappings": {
+                "_all": { "enabled": true },
+                "properties": {
+                    "name": { "type": "text" },
+                    "status": { "type": "keyword" },
+                    "hint": { "type": "text", "include_in_all": false },
+                    "internal": {
+                        "include_in_all": false,
+                        "properties": { "note": { "type": "text" } }
+                    }
+                }
+            }
+        }))
+        .await
+        .assert_status_ok();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "name": "Ann Lee", "status": "active", "hint": "blue", "internal": { "note": "vip" } }),
+        ),
+        (
+            "2",
+            json!({ "name": "Bob Blue", "status": "closed", "internal": { "note": "ann" } }),
+        ),
+        (
+            "3",
+            json!({ "name": "Cy", "status": "active", "tags": ["vip", "blue"], "age": 41 }),
+        ),
+    ] {
+        server
+            .put(&format!("/accounts/_doc/{}?refresh=true", id))
+            .json(&doc)
+            .await;
+    }
+    server
+}
+
+async fn ids(server: &TestServer, path: &str) -> Vec<String> {
+    let response = server.get(path).await;
+    response.assert_status_ok();
+    let body: Value = response.json();
+    sorted_ids(&body)
+}
+
+#[tokio::test]
+async fn test_query_strings_search_the_catch_all_field() {
+    let server = accounts().await;
+    // Excluded fields are left out of the catch-all field
+    assert_eq!(ids(&server, "/accounts/_search?q=blue").await, ["2", "3"]);
+    assert_eq!(ids(&server, "/accounts/_search?q=vip").await, ["3"]);
+    assert_eq!(ids(&server, "/accounts/_search?q=ann").await, ["1"]);
+    assert_eq!(ids(&server, "/accounts/_search?q=ac*").await, ["1", "3"]);
+    // Only string values are collected
+    assert!(ids(&server, "/accounts/_search?q=41").await.is_empty());
+    // Along with other fields
+    assert_eq!(
+        ids(&server, "/accounts/_search?q=blue%20AND%20status:active").await,
+        ["3"]
+    );
+    assert!(ids(&server, "/accounts/_search?q=vip%20AND%20name:ann")
+        .await
+        .is_empty());
+    // Fields are still searched by name
+    assert_eq!(ids(&server, "/accounts/_search?q=hint:blue").await, ["1"]);
+
+    let response = server
+        .post("/accounts/_search")
+        .json(&json!({ "query": { "match": { "_all": "lee" } } }))
+        .await;
+    let body: Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    // The catch-all field is not part of the source
+    assert!(body["hits"]["hits"][0]["_source"].get("_all").is_none());
+    assert_eq!(body["hits"]["hits"][0]["_source"]["hint"], "blue");
+}
+
+#[tokio::test]
+async fn test_catch_all_field_follows_writes_and_mappings() {
+    let server = accounts().await;
+    server
+        .put("/accounts/_doc/3?refresh=true")
+        .json(&json!({ "name": "Cy", "status": "active" }))
+        .await
+        .assert_status_ok();
+    server
+        .delete("/accounts/_doc/2?refresh=true")
+        .await
+        .assert_status_ok();
+    assert!(ids(&server, "/accounts/_search?q=blue").await.is_empty());
+
+    // New fields can be left out too
+    server
+        .put("/accounts/_mapping")
+        .json(
+            &json!({ "properties": { "status": { "type": "keyword", "include_in_all": false } } }),
+        )
+        .await
+        .assert_status_ok();
+    assert!(ids(&server, "/accounts/_search?q=active").await.is_empty());
+    assert_eq!(
+        ids(&server, "/accounts/_search?q=status:active").await,
+        ["1", "3"]
+    );
+}
+
+#[tokio::test]
+async fn test_without_catch_all_field_every_value_is_searched() {
+    let server = accounts().await;
+    server.put("/plain").await.assert_status_ok();
+    server
+        .put("/plain/_doc/1?refresh=true")
+        .json(&json!({ "name": "Ann", "hint": "blue", "age": 41 }))
+        .await;
+    assert_eq!(ids(&server, "/plain/_search?q=blue").await, ["1"]);
+    assert_eq!(ids(&server, "/plain/_search?q=41").await, ["1"]);
+}
+
+#[tokio::test]
+async fn test_default_query_fields_setting() {
+    let server = accounts().await;
+    server
+        .put("/accounts/_settings")
+        .json(&json!({ "index.query.default_field": ["name", "hint"] }))
+        .await
+        .assert_status_ok();
+    assert_eq!(ids(&server, "/accounts/_search?q=blue").await, ["1", "2"]);
+    assert!(ids(&server, "/accounts/_search?q=active").await.is_empty());
+    // Fields given with the query win
+    assert_eq!(
+        ids(&server, "/accounts/_search?q=active&df=status").await,
+        ["1", "3"]
+    );
+    let response = server
+        .post("/accounts/_search")
+        .json(&json!({ "query": { "query_string": { "query": "vip" } } }))
+        .await;
+    let body: Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 0);
+
+    server
+        .put("/accounts/_settings")
+        .json(&json!({ "index.query.default_field": "*" }))
+        .await
+        .assert_status_ok();
+    assert_eq!(ids(&server, "/accounts/_search?q=vip").await, ["1", "3"]);
+}
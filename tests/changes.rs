@@ -0,0 +1,179 @@
+//! Tests for the per-index change feed
+
+mod common;
+
+use common::open_sled;
+use gbs::bulk_ops::BulkAction;
+use gbs::storage::{Change, ChangeLog, ChangeOp, ChangesRequest, Storage, MAX_RETAINED_CHANGES};
+use gbs::GbsError;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn request(since: u64) -> ChangesRequest {
+    ChangesRequest::parse(&HashMap::from([("since".to_string(), since.to_string())])).unwrap()
+}
+
+fn ops(changes: &[Change]) -> Vec<(u64, ChangeOp, &str)> {
+    changes
+        .iter()
+        .map(|change| (change.seq, change.op, change.id.as_str()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_writes_append_changes_in_order() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document("logs", "1", json!({ "level": "info" }))
+        .await
+        .unwrap();
+    storage
+        .index_document("logs", "1", json!({ "level": "warn" }))
+        .await
+        .unwrap();
+    storage.delete_document("logs", "1").await.unwrap();
+    let results = storage
+        .execute_bulk(vec![
+            BulkAction::Index {
+                index: "logs".to_string(),
+                id: Some("2".to_string()),
+                document: json!({ "level": "error" }),
+            },
+            BulkAction::Delete {
+                index: "logs".to_string(),
+                id: "2".to_string(),
+            },
+        ])
+        .await;
+    assert!(results.iter().all(|result| result.is_ok()));
+
+    let response = storage.get_changes("logs", &request(0)).await.unwrap();
+    assert_eq!(
+        ops(&response.changes),
+        vec![
+            (1, ChangeOp::Create, "1"),
+            (2, ChangeOp::Update, "1"),
+            (3, ChangeOp::Delete, "1"),
+            (4, ChangeOp::Create, "2"),
+            (5, ChangeOp::Delete, "2"),
+        ]
+    );
+    assert_eq!(response.last_seq, 5);
+
+    // Nothing new after the last change: the next request starts from there
+    let response = storage.get_changes("logs", &request(5)).await.unwrap();
+    assert!(response.changes.is_empty());
+    assert_eq!(response.last_seq, 5);
+
+    let limited = ChangesRequest::parse(&HashMap::from([
+        ("since".to_string(), "1".to_string()),
+        ("limit".to_string(), "2".to_string()),
+    ]))
+    .unwrap();
+    let response = storage.get_changes("logs", &limited).await.unwrap();
+    assert_eq!(response.changes.len(), 2);
+    assert_eq!(response.last_seq, 3);
+
+    assert!(matches!(
+        storage.get_changes("missing", &request(0)).await,
+        Err(GbsError::IndexNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_long_poll_returns_the_next_change() {
+    let storage = Arc::new(Storage::new());
+    storage.create_index("logs", None, None).await.unwrap();
+
+    let waiting = tokio::spawn({
+        let storage = storage.clone();
+        async move {
+            let request = ChangesRequest::parse(&HashMap::from([
+                ("since".to_string(), "0".to_string()),
+                ("timeout".to_string(), "10s".to_string()),
+            ]))
+            .unwrap();
+            storage.get_changes("logs", &request).await.unwrap()
+        }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    storage
+        .index_document("logs", "1", json!({ "level": "info" }))
+        .await
+        .unwrap();
+
+    let response = tokio::time::timeout(Duration::from_secs(5), waiting)
+        .await
+        .expect("long poll answered by the write")
+        .unwrap();
+    assert_eq!(ops(&response.changes), vec![(1, ChangeOp::Create, "1")]);
+
+    // Without changes, the request returns once the timeout is up
+    let request = ChangesRequest::parse(&HashMap::from([
+        ("since".to_string(), "1".to_string()),
+        ("timeout".to_string(), "20ms".to_string()),
+    ]))
+    .unwrap();
+    let response = storage.get_changes("logs", &request).await.unwrap();
+    assert!(response.changes.is_empty());
+    assert_eq!(response.last_seq, 1);
+}
+
+#[test]
+fn test_old_changes_are_dropped() {
+    let log = ChangeLog::default();
+    for id in 0..MAX_RETAINED_CHANGES + 2 {
+        log.append(ChangeOp::Create, &id.to_string());
+    }
+    assert_eq!(log.last_seq(), MAX_RETAINED_CHANGES as u64 + 2);
+    assert!(matches!(
+        log.since(0, 10),
+        Err(GbsError::IllegalArgument(_))
+    ));
+    assert_eq!(log.since(2, 10).unwrap()[0].seq, 3);
+
+    assert!(ChangesRequest::parse(&HashMap::from([(
+        "timeout".to_string(),
+        "soon".to_string()
+    )]))
+    .is_err());
+}
+
+#[tokio::test]
+async fn test_sequence_numbers_survive_restarts() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+    {
+        let storage = open_sled(&data_path).await;
+        storage.create_index("logs", None, None).await.unwrap();
+        for id in ["1", "2"] {
+            storage
+                .index_document("logs", id, json!({ "level": "info" }))
+                .await
+                .unwrap();
+        }
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&data_path).await;
+    storage.delete_document("logs", "1").await.unwrap();
+    let response = storage.get_changes("logs", &request(0)).await.unwrap();
+    assert_eq!(
+        ops(&response.changes),
+        vec![
+            (1, ChangeOp::Create, "1"),
+            (2, ChangeOp::Create, "2"),
+            (3, ChangeOp::Delete, "1"),
+        ]
+    );
+
+    // A deleted index takes its change log with it
+    storage.delete_index("logs").await.unwrap();
+    storage.create_index("logs", None, None).await.unwrap();
+    let response = storage.get_changes("logs", &request(0)).await.unwrap();
+    assert!(response.changes.is_empty());
+}
@@ -0,0 +1,156 @@
+//! Tests for checkpoints and consistent backups
+
+mod common;
+
+use common::open_sled;
+use gbs::storage::{IndexTier, Storage};
+use gbs::tantivy_export::export_checkpoint_tantivy;
+use serde_json::json;
+use tempfile::TempDir;
+
+fn checkpoint_documents(
+    checkpoint: &gbs::storage::Checkpoint,
+    index: &str,
+) -> Vec<(String, serde_json::Value)> {
+    let mut documents = Vec::new();
+    checkpoint
+        .for_each_document(index, |id, document| {
+            documents.push((id.to_string(), document));
+            Ok(())
+        })
+        .unwrap();
+    documents.sort_by(|a, b| a.0.cmp(&b.0));
+    documents
+}
+
+#[tokio::test]
+async fn test_checkpoint_is_isolated_from_later_writes() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "stock",
+            None,
+            Some(json!({ "properties": { "qty": { "type": "integer" } } })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document("stock", "a", json!({ "qty": 1 }))
+        .await
+        .unwrap();
+    storage
+        .index_document("stock", "b", json!({ "qty": 2 }))
+        .await
+        .unwrap();
+
+    let checkpoint = storage.checkpoint().await;
+
+    storage
+        .index_document("stock", "a", json!({ "qty": 10 }))
+        .await
+        .unwrap();
+    storage.delete_document("stock", "b").await.unwrap();
+    storage
+        .index_document("stock", "c", json!({ "qty": 3 }))
+        .await
+        .unwrap();
+    storage.create_index("later", None, None).await.unwrap();
+
+    assert_eq!(checkpoint.index_names(), vec!["stock".to_string()]);
+    assert_eq!(checkpoint.doc_count("stock"), Some(2));
+    assert_eq!(
+        checkpoint_documents(&checkpoint, "stock"),
+        vec![
+            ("a".to_string(), json!({ "qty": 1 })),
+            ("b".to_string(), json!({ "qty": 2 })),
+        ]
+    );
+    assert_eq!(
+        checkpoint.metadata("stock").unwrap().mappings,
+        Some(json!({ "properties": { "qty": { "type": "integer" } } }))
+    );
+
+    // The live index has the later writes
+    let live = storage.get_document("stock", "a").await.unwrap();
+    assert_eq!(live["_source"]["qty"], 10);
+    assert!(storage.get_document("stock", "b").await.is_err());
+}
+
+#[tokio::test]
+async fn test_backup_restores_indices_as_of_checkpoint() {
+    let temp_dir = TempDir::new().unwrap();
+    let backup_dir = temp_dir.path().join("backup");
+    let storage = Storage::with_sled(temp_dir.path().join("data")).unwrap();
+    storage
+        .create_index("orders", Some(json!({ "number_of_shards": 1 })), None)
+        .await
+        .unwrap();
+    storage.put_alias("orders", "current-orders").await.unwrap();
+    for id in 0..2500 {
+        storage
+            .index_document("orders", &id.to_string(), json!({ "n": id }))
+            .await
+            .unwrap();
+    }
+    storage.create_index("archive", None, None).await.unwrap();
+    storage
+        .index_document("archive", "old", json!({ "n": -1 }))
+        .await
+        .unwrap();
+    storage
+        .set_index_tier("archive", IndexTier::Warm)
+        .await
+        .unwrap();
+
+    let report = storage.backup(&backup_dir).await.unwrap();
+    assert_eq!(report.indices, 2);
+    assert_eq!(report.documents, 2501);
+
+    // A backup refuses to overwrite another
+    assert!(storage.backup(&backup_dir).await.is_err());
+    drop(storage);
+
+    let restored = open_sled(&backup_dir).await;
+    let stats = restored.get_indices_stats().await;
+    assert!(stats.contains(&("orders".to_string(), 2500)));
+    assert!(stats.contains(&("archive".to_string(), 1)));
+    let index = restored.get_index("orders").await.unwrap();
+    assert_eq!(index["orders"]["settings"]["number_of_shards"], 1);
+    assert!(index["orders"]["aliases"].get("current-orders").is_some());
+    assert_eq!(
+        restored.get_index_tier("archive").await.unwrap()["tier"],
+        "warm"
+    );
+    let old = restored.get_document("archive", "old").await.unwrap();
+    assert_eq!(old["_source"]["n"], -1);
+}
+
+#[tokio::test]
+async fn test_export_checkpoint_to_tantivy() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "products",
+            None,
+            Some(json!({ "properties": { "name": { "type": "text" } } })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document("products", "1", json!({ "name": "red shoes" }))
+        .await
+        .unwrap();
+
+    let checkpoint = storage.checkpoint().await;
+    storage
+        .index_document("products", "2", json!({ "name": "blue shirt" }))
+        .await
+        .unwrap();
+
+    let report =
+        export_checkpoint_tantivy(&checkpoint, "products", &temp_dir.path().join("out")).unwrap();
+    assert_eq!(report.documents, 1);
+    assert_eq!(report.mapped_fields, 1);
+    assert!(export_checkpoint_tantivy(&checkpoint, "missing", &temp_dir.path().join("x")).is_err());
+}
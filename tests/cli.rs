@@ -0,0 +1,105 @@
+//! Tests for the command line of the gbs binary
+
+use clap::{CommandFactory, Parser};
+use gbs::cli::{Cli, Command};
+use std::path::{Path, PathBuf};
+
+fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+    Cli::try_parse_from(std::iter::once("gbs").chain(args.iter().copied()))
+}
+
+#[test]
+fn test_cli_definition() {
+    Cli::command().debug_assert();
+}
+
+#[test]
+fn test_serve_is_the_default_command() {
+    let cli = parse(&["--seed", "fixtures"]).unwrap();
+    match cli.command_to_run().unwrap() {
+        Command::Serve(args) => assert_eq!(args.seed, Some(PathBuf::from("fixtures"))),
+        command => panic!("unexpected command {:?}", command),
+    }
+    let cli = parse(&["run"]).unwrap();
+    assert!(matches!(cli.command_to_run().unwrap(), Command::Serve(_)));
+
+    // --seed only belongs to serve
+    let cli = parse(&["--seed", "fixtures", "compact"]).unwrap();
+    assert!(cli.command_to_run().is_err());
+}
+
+#[test]
+fn test_global_args_before_and_after_the_command() {
+    for args in [
+        &["--data-dir", "/tmp/gbs", "--config", "gbs.yaml", "validate"][..],
+        &["validate", "--data-dir", "/tmp/gbs", "--config", "gbs.yaml"],
+    ] {
+        let cli = parse(args).unwrap();
+        assert_eq!(cli.data_dir.as_deref(), Some(Path::new("/tmp/gbs")));
+        assert_eq!(cli.config.as_deref(), Some(Path::new("gbs.yaml")));
+        assert!(matches!(cli.command_to_run().unwrap(), Command::Validate));
+    }
+}
+
+#[test]
+fn test_maintenance_commands() {
+    match parse(&["import", "books", "books.ndjson"]).unwrap().command {
+        Some(Command::Import { index, file }) => {
+            assert_eq!(index, "books");
+            assert_eq!(file, Path::new("books.ndjson"));
+        }
+        command => panic!("unexpected command {:?}", command),
+    }
+    match parse(&["export-tantivy", "--index", "logs", "--out", "/tmp/logs"])
+        .unwrap()
+        .command
+    {
+        Some(Command::ExportTantivy { index, out }) => {
+            assert_eq!(index, "logs");
+            assert_eq!(out, Path::new("/tmp/logs"));
+        }
+        command => panic!("unexpected command {:?}", command),
+    }
+
+    assert!(parse(&["import", "books"]).is_err());
+    assert!(parse(&["export", "books", "a", "b"]).is_err());
+    assert!(parse(&["compact", "--force"]).is_err());
+    assert!(parse(&["export-tantivy", "--index", "logs"]).is_err());
+    assert!(parse(&["bogus"]).is_err());
+}
+
+#[test]
+fn test_soak_options() {
+    let cli = parse(&[
+        "soak",
+        "--cycles",
+        "0",
+        "--ops",
+        "10",
+        "--seed",
+        "7",
+        "--data-dir",
+        "/tmp/soak",
+    ])
+    .unwrap();
+    let Some(Command::Soak(args)) = &cli.command else {
+        panic!("unexpected command {:?}", cli.command);
+    };
+    let options = args.options(cli.data_dir.clone()).unwrap();
+    assert_eq!(options.cycles, 0);
+    assert_eq!(options.ops_per_cycle, 10);
+    assert_eq!(options.seed, 7);
+    assert_eq!(options.data_dir, Path::new("/tmp/soak"));
+    assert_eq!(options.indices, 3);
+
+    // The data directory is never defaulted
+    assert!(args.options(None).is_err());
+    for args in [
+        &["soak", "--cycles"][..],
+        &["soak", "--cycles", "many"],
+        &["soak", "--verbose", "1"],
+        &["soak", "--indices", "0"],
+    ] {
+        assert!(parse(args).is_err());
+    }
+}
@@ -0,0 +1,225 @@
+//! Tests for the Rust client
+
+use gbs::client::query::{bool_query, match_query, range, term, SearchRequest, SortOrder};
+use gbs::client::{BulkRequest, CreateIndexRequest, FieldType, GbsClient};
+use gbs::document::DocumentOperations;
+use gbs::error::GbsError;
+use gbs::server::{AppState, GbsService};
+use gbs::storage::Storage;
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Book {
+    title: String,
+    year: u32,
+    status: String,
+}
+
+fn book(title: &str, year: u32, status: &str) -> Book {
+    Book {
+        title: title.to_string(),
+        year,
+        status: status.to_string(),
+    }
+}
+
+fn embedded_client() -> GbsClient {
+    let storage = Arc::new(Storage::new());
+    GbsClient::embedded(GbsService::new(AppState::new(storage, "6.8.23")))
+}
+
+fn books_index() -> CreateIndexRequest {
+    CreateIndexRequest::new()
+        .shards(1)
+        .field("title", FieldType::Text)
+        .field("year", FieldType::Integer)
+        .field("status", FieldType::Keyword)
+        .alias("library")
+}
+
+#[tokio::test]
+async fn test_index_lifecycle() {
+    let client = embedded_client();
+    assert!(!client.index_exists("books").await.unwrap());
+
+    client.create_index("books", &books_index()).await.unwrap();
+    assert!(client.index_exists("books").await.unwrap());
+    let info = client.get_index("books").await.unwrap();
+    assert_eq!(info.aliases, vec!["library".to_string()]);
+    assert_eq!(
+        info.mappings.unwrap()["properties"]["status"],
+        json!({ "type": "keyword" })
+    );
+
+    let error = client
+        .create_index("books", &books_index())
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        error,
+        GbsError::Remote {
+            status: StatusCode::BAD_REQUEST,
+            ..
+        }
+    ));
+
+    client.delete_index("books").await.unwrap();
+    assert!(!client.index_exists("books").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_document_crud() {
+    let client = embedded_client();
+    client.create_index("books", &books_index()).await.unwrap();
+
+    let dune = book("Dune", 1965, "published");
+    client.index_document("books", "dune", &dune).await.unwrap();
+    assert_eq!(
+        client.get_document::<Book>("books", "dune").await.unwrap(),
+        Some(dune)
+    );
+
+    let id = client
+        .create_document("books", &book("Hyperion", 1989, "draft"))
+        .await
+        .unwrap();
+    let hyperion: Option<Book> = client.get_document("books", &id).await.unwrap();
+    assert_eq!(hyperion.unwrap().title, "Hyperion");
+
+    client.delete_document("books", "dune").await.unwrap();
+    assert_eq!(
+        client.get_document::<Book>("books", "dune").await.unwrap(),
+        None
+    );
+    assert_eq!(
+        client
+            .get_document::<Book>("missing", "dune")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_typed_document_operations() {
+    let client = embedded_client();
+    client.create_index("books", &books_index()).await.unwrap();
+
+    let dune = book("Dune", 1965, "published");
+    client.index_typed("books", "dune", &dune).await.unwrap();
+    client
+        .index_typed("books", "emma", &book("Emma", 1815, "published"))
+        .await
+        .unwrap();
+    let document = client
+        .get_typed::<Book>("books", "dune")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(document.index, "books");
+    assert_eq!(document.id, "dune");
+    assert_eq!(document.version, 1);
+    assert_eq!(document.source, dune);
+    assert!(client
+        .get_typed::<Book>("books", "missing")
+        .await
+        .unwrap()
+        .is_none());
+
+    let request = SearchRequest::new().query(match_query("title", "dune"));
+    let hits = client
+        .search_typed::<Book>("books", &request)
+        .await
+        .unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].id, "dune");
+    assert!(hits[0].score.unwrap() > 0.0);
+    assert_eq!(hits[0].source.as_ref(), Some(&dune));
+
+    // Sources that do not fit the type are errors, not missing documents
+    let error = client.get_typed::<u32>("books", "dune").await.unwrap_err();
+    assert!(matches!(error, GbsError::Json(_)));
+}
+
+#[tokio::test]
+async fn test_bulk_and_search() {
+    let client = embedded_client();
+    client.create_index("books", &books_index()).await.unwrap();
+
+    let bulk = BulkRequest::new()
+        .index("books", "1", &book("Rust in Action", 2021, "published"))
+        .unwrap()
+        .index("books", "2", &book("Programming Rust", 2017, "published"))
+        .unwrap()
+        .create("books", Some("3"), &book("Rust Atomics", 2023, "draft"))
+        .unwrap()
+        .update("books", "2", &json!({ "year": 2022 }))
+        .unwrap()
+        .delete("books", "missing");
+    assert_eq!(bulk.len(), 5);
+    let response = client.bulk(&bulk).await.unwrap();
+    assert_eq!(response.items.len(), 5);
+    assert!(response.errors);
+    client.refresh("books").await.unwrap();
+
+    let request = SearchRequest::new()
+        .query(
+            bool_query()
+                .must(match_query("title", "rust"))
+                .filter(term("status", "published"))
+                .filter(range("year").gte(2020)),
+        )
+        .sort("year", SortOrder::Desc)
+        .size(10);
+    let response = client.search::<Book>("library", &request).await.unwrap();
+    assert_eq!(response.hits.total.value, 2);
+    let titles: Vec<&str> = response.documents().map(|b| b.title.as_str()).collect();
+    assert_eq!(titles, vec!["Programming Rust", "Rust in Action"]);
+    assert_eq!(response.hits.hits[0].index, "books");
+    assert_eq!(response.hits.hits[0].id, "2");
+}
+
+#[tokio::test]
+async fn test_errors_carry_status_and_reason() {
+    let client = embedded_client();
+    let request = SearchRequest::new().query(match_query("title", "rust"));
+    match client.search::<Book>("missing", &request).await {
+        Err(GbsError::Remote { status, reason }) => {
+            assert_eq!(status, StatusCode::NOT_FOUND);
+            assert!(reason.contains("missing"), "{}", reason);
+        }
+        other => panic!(
+            "expected a remote error, got {:?}",
+            other.map(|r| r.hits.total)
+        ),
+    }
+    assert!(GbsClient::http("ftp://localhost").is_err());
+}
+
+#[tokio::test]
+async fn test_http_transport() {
+    let storage = Arc::new(Storage::new());
+    let app = GbsService::new(AppState::new(storage, "6.8.23")).into_router::<()>();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let client = GbsClient::http(&format!("http://{}", address)).unwrap();
+    client.create_index("books", &books_index()).await.unwrap();
+    client
+        .index_document("books", "a b", &book("Dune", 1965, "published"))
+        .await
+        .unwrap();
+    client.refresh("books").await.unwrap();
+    let response = client
+        .search::<Book>(
+            "books",
+            &SearchRequest::new().query(match_query("title", "dune")),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.hits.hits[0].id, "a b");
+}
@@ -0,0 +1,232 @@
+//! Tests for the cluster settings API (`GET/PUT /_cluster/settings`)
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::{open_sled, test_server};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn server() -> TestServer {
+    test_server(Storage::new(), "8.11.0")
+}
+
+#[tokio::test]
+async fn test_get_and_put_cluster_settings() {
+    let server = server();
+    let response = server.get("/_cluster/settings").await;
+    response.assert_status_ok();
+    response.assert_json(&json!({ "persistent": {}, "transient": {} }));
+
+    let response = server
+        .put("/_cluster/settings")
+        .json(&json!({
+            "persistent": {
+                "cluster": { "routing.allocation.enable": "all" },
+                "search.max_result_window": 50
+            },
+            "transient": { "indices.recovery.max_bytes_per_sec": "50mb" }
+        }))
+        .await;
+    response.assert_status_ok();
+    response.assert_json(&json!({
+        "acknowledged": true,
+        "persistent": {
+            "cluster": { "routing": { "allocation": { "enable": "all" } } },
+            "search": { "max_result_window": "50" }
+        },
+        "transient": { "indices": { "recovery": { "max_bytes_per_sec": "50mb" } } }
+    }));
+
+    server
+        .get("/_cluster/settings")
+        .add_query_param("flat_settings", "true")
+        .await
+        .assert_json(&json!({
+            "persistent": {
+                "cluster.routing.allocation.enable": "all",
+                "search.max_result_window": "50"
+            },
+            "transient": { "indices.recovery.max_bytes_per_sec": "50mb" }
+        }));
+
+    // null resets a setting, or a whole group
+    server
+        .put("/_cluster/settings")
+        .json(&json!({ "persistent": { "cluster.routing": null } }))
+        .await
+        .assert_status_ok();
+    server
+        .get("/_cluster/settings")
+        .add_query_param("flat_settings", "true")
+        .await
+        .assert_json(&json!({
+            "persistent": { "search.max_result_window": "50" },
+            "transient": { "indices.recovery.max_bytes_per_sec": "50mb" }
+        }));
+}
+
+#[tokio::test]
+async fn test_invalid_cluster_settings() {
+    let server = server();
+    for body in [
+        json!({}),
+        json!({ "persistent": {} }),
+        json!({ "defaults": { "search.max_result_window": 10 } }),
+        json!({ "persistent": { "search.max_result_window": 0 } }),
+        json!({ "persistent": { "search.default_search_timeout": "soon" } }),
+        json!({ "transient": { "action.auto_create_index": "-" } }),
+    ] {
+        server
+            .put("/_cluster/settings")
+            .json(&body)
+            .await
+            .assert_status_bad_request();
+    }
+}
+
+#[tokio::test]
+async fn test_auto_create_index_setting() {
+    let server = server();
+    server
+        .put("/_cluster/settings")
+        .json(&json!({ "persistent": { "action.auto_create_index": "+logs-*,-*" } }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/logs-app/_doc/1")
+        .json(&json!({ "message": "started" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    // No template matches these, the setting alone lets every write create them
+    server
+        .put("/logs-web/_create/1")
+        .json(&json!({ "message": "started" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    let body: serde_json::Value = server
+        .post("/_bulk")
+        .bytes("{\"index\":{\"_index\":\"logs-db\",\"_id\":\"1\"}}\n{}\n".into())
+        .content_type("application/x-ndjson")
+        .await
+        .json();
+    assert_eq!(body["errors"], false);
+    server
+        .put("/metrics/_doc/1")
+        .json(&json!({ "cpu": 1 }))
+        .await
+        .assert_status_not_found();
+    server
+        .put("/metrics/_create/1")
+        .json(&json!({ "cpu": 1 }))
+        .await
+        .assert_status_not_found();
+    let body: serde_json::Value = server
+        .post("/_bulk")
+        .bytes("{\"index\":{\"_index\":\"metrics\",\"_id\":\"1\"}}\n{\"cpu\":1}\n".into())
+        .content_type("application/x-ndjson")
+        .await
+        .json();
+    assert_eq!(body["errors"], true);
+    assert_eq!(body["items"][0]["index"]["status"], 404);
+    server.get("/metrics").await.assert_status_not_found();
+
+    // Transient settings override persistent ones
+    server
+        .put("/_cluster/settings")
+        .json(&json!({ "transient": { "action.auto_create_index": true } }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/metrics/_doc/1")
+        .json(&json!({ "cpu": 1 }))
+        .await
+        .assert_status(StatusCode::CREATED);
+}
+
+#[tokio::test]
+async fn test_max_result_window_setting() {
+    let server = server();
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/_cluster/settings")
+        .json(&json!({ "transient": { "search.max_result_window": 20 } }))
+        .await
+        .assert_status_ok();
+    server
+        .post("/books/_search")
+        .json(&json!({ "from": 10, "size": 10 }))
+        .await
+        .assert_status_ok();
+    let response = server
+        .post("/books/_search")
+        .json(&json!({ "from": 15, "size": 10 }))
+        .await;
+    response.assert_status_bad_request();
+    assert!(response.text().contains("[20] but was [25]"));
+    server
+        .get("/books/_search")
+        .add_query_param("size", "21")
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_persistent_settings_survive_restarts() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+    {
+        let storage = open_sled(&data_path).await;
+        storage
+            .update_cluster_settings(
+                &json!({
+                    "persistent": { "search.default_search_timeout": "5s" },
+                    "transient": { "search.max_result_window": 100 }
+                }),
+                false,
+            )
+            .await
+            .unwrap();
+    }
+
+    let storage = open_sled(&data_path).await;
+    let settings = storage.cluster_settings();
+    assert_eq!(
+        settings.default_search_timeout(),
+        Some(Some(std::time::Duration::from_secs(5)))
+    );
+    assert_eq!(settings.max_result_window(), None);
+    assert_eq!(
+        settings.to_json(true),
+        json!({ "persistent": { "search.default_search_timeout": "5s" }, "transient": {} })
+    );
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_persistent_updates_are_all_kept() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = Arc::new(Storage::with_sled(temp_dir.path().join("db")).unwrap());
+    storage.load_from_backend().await.unwrap();
+
+    let updates = (0..16).map(|i| {
+        let storage = storage.clone();
+        tokio::spawn(async move {
+            storage
+                .update_cluster_settings(
+                    &json!({ "persistent": { format!("custom.key_{}", i): i } }),
+                    true,
+                )
+                .await
+                .unwrap();
+        })
+    });
+    for update in updates.collect::<Vec<_>>() {
+        update.await.unwrap();
+    }
+
+    let settings = storage.cluster_settings().to_json(true);
+    assert_eq!(settings["persistent"].as_object().unwrap().len(), 16);
+}
@@ -0,0 +1,152 @@
+//! Tests for field collapsing (`collapse` in search bodies)
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::ids;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// Products in families of variants, in two indices
+async fn products() -> TestServer {
+    let server = common::test_server(Storage::new(), "8.11.0");
+    for index in ["shop", "outlet"] {
+        server.put(&format!("/{}", index)).await.assert_status_ok();
+    }
+    for (index, id, doc) in [
+        (
+            "shop",
+            "1",
+            json!({ "name": "red shirt", "family": "shirt", "price": 20 }),
+        ),
+        (
+            "shop",
+            "2",
+            json!({ "name": "blue shirt", "family": "shirt", "price": 15 }),
+        ),
+        (
+            "shop",
+            "3",
+            json!({ "name": "shirt dress", "family": "dress", "price": 40 }),
+        ),
+        ("shop", "4", json!({ "name": "shirt patch", "price": 2 })),
+        (
+            "outlet",
+            "5",
+            json!({ "name": "green shirt", "family": "shirt", "price": 10 }),
+        ),
+        (
+            "outlet",
+            "6",
+            json!({ "name": "shirt socks", "family": "socks", "price": 5 }),
+        ),
+    ] {
+        server
+            .put(&format!("/{}/_doc/{}", index, id))
+            .json(&doc)
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+    server
+}
+
+#[tokio::test]
+async fn test_collapse_returns_the_top_hit_of_each_group() {
+    let server = products().await;
+    let body: Value = server
+        .post("/shop/_search")
+        .json(&json!({
+            "query": { "match_all": {} },
+            "sort": [{ "price": "asc" }],
+            "collapse": { "field": "family" }
+        }))
+        .await
+        .json();
+    // Documents without the field form a group of their own
+    assert_eq!(ids(&body), vec!["4", "2", "3"]);
+    assert_eq!(body["hits"]["total"]["value"], 4);
+    assert_eq!(
+        body["hits"]["hits"][1]["fields"]["family"],
+        json!(["shirt"])
+    );
+    assert_eq!(body["hits"]["hits"][0]["fields"]["family"], json!([null]));
+
+    // Pages are pages of groups
+    let body: Value = server
+        .post("/shop/_search")
+        .json(&json!({
+            "sort": [{ "price": "asc" }],
+            "collapse": { "field": "family" },
+            "from": 1,
+            "size": 1
+        }))
+        .await
+        .json();
+    assert_eq!(ids(&body), vec!["2"]);
+}
+
+#[tokio::test]
+async fn test_collapse_inner_hits() {
+    let server = products().await;
+    let body: Value = server
+        .post("/shop,outlet/_search")
+        .json(&json!({
+            "query": { "match": { "name": "shirt" } },
+            "sort": [{ "price": "desc" }],
+            "_source": ["name"],
+            "collapse": {
+                "field": "family",
+                "inner_hits": [
+                    { "name": "cheapest", "size": 2, "sort": [{ "price": "asc" }] },
+                    { "name": "best", "size": 1 }
+                ]
+            }
+        }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 6);
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    let top: Vec<&str> = hits
+        .iter()
+        .map(|hit| hit["_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(top, vec!["3", "1", "6", "4"]);
+    assert_eq!(hits[1]["_source"], json!({ "name": "red shirt" }));
+
+    let cheapest = &hits[1]["inner_hits"]["cheapest"]["hits"];
+    assert_eq!(cheapest["total"]["value"], 3);
+    let cheapest_ids: Vec<&str> = cheapest["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| hit["_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(cheapest_ids, vec!["5", "2"]);
+    assert_eq!(cheapest["hits"][0]["_index"], "outlet");
+    assert_eq!(
+        cheapest["hits"][0]["_source"],
+        json!({ "name": "green shirt" })
+    );
+    // Without a sort of their own, inner hits follow the order of the search
+    let best = &hits[1]["inner_hits"]["best"]["hits"]["hits"];
+    assert_eq!(best.as_array().unwrap().len(), 1);
+    assert_eq!(best[0]["_id"], "1");
+}
+
+#[tokio::test]
+async fn test_collapse_requires_a_single_valued_field() {
+    let server = products().await;
+    server
+        .put("/shop/_doc/7")
+        .json(&json!({ "name": "shirt set", "family": ["shirt", "dress"] }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    for collapse in [json!({ "field": "family" }), json!({ "inner_hits": {} })] {
+        server
+            .post("/shop/_search")
+            .json(&json!({ "collapse": collapse }))
+            .await
+            .assert_status_bad_request();
+    }
+}
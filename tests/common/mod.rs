@@ -0,0 +1,107 @@
+//! Helpers shared by the integration tests
+//!
+//! Each test crate declares `mod common;` and uses part of these instead of
+//! building its own servers, storages and request helpers.
+#![allow(dead_code)]
+
+use axum_test::TestServer;
+use base64::Engine;
+use gbs::bulk_ops::BulkAction;
+use gbs::server::{create_router, AppState};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Server with every API over a storage, emulating an Elasticsearch version
+pub fn test_server(storage: Storage, es_version: &str) -> TestServer {
+    server_with(AppState::new(Arc::new(storage), es_version))
+}
+
+/// Server with every API over a state set up by the test
+pub fn server_with(state: AppState) -> TestServer {
+    TestServer::new(create_router(state)).unwrap()
+}
+
+/// Index `{ "message": "disk full" }` under each of the IDs
+pub async fn index_disk_full(storage: &Storage, index: &str, ids: &[&str]) {
+    for id in ids {
+        storage
+            .index_document(index, id, json!({ "message": "disk full" }))
+            .await
+            .unwrap();
+    }
+}
+
+/// A `logs` index of three shards holding four documents, and an empty
+/// `users` index of one shard
+pub async fn sharded_logs() -> Storage {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            Some(json!({ "number_of_shards": 3, "number_of_replicas": 0 })),
+            None,
+        )
+        .await
+        .unwrap();
+    storage.create_index("users", None, None).await.unwrap();
+    index_disk_full(&storage, "logs", &["1", "2", "3", "4"]).await;
+    storage
+}
+
+/// IDs of the hits of a search response, in order
+pub fn ids(body: &Value) -> Vec<&str> {
+    body["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| hit["_id"].as_str().unwrap())
+        .collect()
+}
+
+/// Storage persisted under `path`, with the indices stored there loaded
+pub async fn open_sled(path: impl AsRef<Path>) -> Storage {
+    let storage = Storage::with_sled(path.as_ref()).unwrap();
+    storage.load_from_backend().await.unwrap();
+    storage
+}
+
+/// Sorted IDs of the documents of `index` matching a query
+pub async fn search_ids(storage: &Storage, index: &str, query: Value) -> Vec<String> {
+    let result = storage
+        .search(index, &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    sorted_ids(&result)
+}
+
+/// IDs of the hits of a search response, sorted
+pub fn sorted_ids(body: &Value) -> Vec<String> {
+    let mut ids: Vec<String> = ids(body).into_iter().map(String::from).collect();
+    ids.sort();
+    ids
+}
+
+/// Bulk action indexing a document under an ID
+pub fn index_action(index: &str, id: &str, document: Value) -> BulkAction {
+    BulkAction::Index {
+        index: index.to_string(),
+        id: Some(id.to_string()),
+        document,
+    }
+}
+
+/// `Authorization` header value of an API key
+pub fn api_key(id: &str, key: &str) -> String {
+    format!("ApiKey {}", encode(id, key))
+}
+
+/// `Authorization` header value of a user's credentials
+pub fn basic_auth(username: &str, password: &str) -> String {
+    format!("Basic {}", encode(username, password))
+}
+
+fn encode(name: &str, secret: &str) -> String {
+    base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", name, secret))
+}
@@ -0,0 +1,319 @@
+//! Tests for the emulation of Elasticsearch 6.x, 7.x and 8.x shapes
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::server_with;
+use gbs::config::Config;
+use gbs::server::{AppState, Compatibility};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+fn create_test_server(es_version: &str) -> TestServer {
+    let state = AppState::new(Arc::new(Storage::new()), es_version).with_version_emulation();
+    server_with(state)
+}
+
+async fn create_books(server: &TestServer) {
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "dune" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+}
+
+#[test]
+fn test_compatibility_defaults() {
+    let none = HashMap::new();
+    let as_int = HashMap::from([("rest_total_hits_as_int".to_string(), "true".to_string())]);
+    let typeless = HashMap::from([("include_type_name".to_string(), "false".to_string())]);
+    let typed = HashMap::from([("include_type_name".to_string(), String::new())]);
+
+    let v6 = Compatibility::for_version("6.8.23");
+    assert_eq!(v6.major(), 6);
+    assert!(v6.has_types());
+    assert!(v6.total_hits_as_int(&none));
+    assert!(v6.include_type_name(&none));
+    assert!(!v6.include_type_name(&typeless));
+
+    let v7 = Compatibility::for_version("7.17.0");
+    assert!(v7.has_types());
+    assert!(!v7.total_hits_as_int(&none));
+    assert!(v7.total_hits_as_int(&as_int));
+    assert!(!v7.include_type_name(&none));
+    assert!(v7.include_type_name(&typed));
+
+    let v8 = Compatibility::for_version("8.11.0");
+    assert!(!v8.has_types());
+    assert!(v8.total_hits_as_int(&as_int));
+    assert!(!v8.include_type_name(&typed));
+}
+
+#[tokio::test]
+async fn test_total_hits_shape_follows_version() {
+    for (version, total) in [
+        ("6.8.23", json!(1)),
+        ("7.17.0", json!({ "value": 1, "relation": "eq" })),
+        ("8.11.0", json!({ "value": 1, "relation": "eq" })),
+    ] {
+        let server = create_test_server(version);
+        create_books(&server).await;
+
+        let body: Value = server
+            .post("/books/_search")
+            .json(&json!({ "query": { "match": { "title": "dune" } } }))
+            .await
+            .json();
+        assert_eq!(body["hits"]["total"], total, "{}", version);
+
+        let body: Value = server
+            .post("/_msearch")
+            .bytes("{\"index\":\"books\"}\n{}\n".into())
+            .content_type("application/x-ndjson")
+            .await
+            .json();
+        assert_eq!(body["responses"][0]["hits"]["total"], total, "{}", version);
+
+        let body: Value = server
+            .get("/books/_search")
+            .add_query_param("rest_total_hits_as_int", "true")
+            .await
+            .json();
+        assert_eq!(body["hits"]["total"], 1, "{}", version);
+    }
+
+    // 7.x clients asking for the object get it from a 6.x server too
+    let server = create_test_server("6.8.23");
+    create_books(&server).await;
+    let body: Value = server
+        .get("/books/_search")
+        .add_query_param("rest_total_hits_as_int", "false")
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+}
+
+#[tokio::test]
+async fn test_native_shapes_without_emulation() {
+    let config = Config::default();
+    let state =
+        AppState::new(Arc::new(Storage::new()), config.es_version.clone()).with_config(config);
+    assert_eq!(state.compat(), Compatibility::native());
+    let server = server_with(state);
+    create_books(&server).await;
+
+    let body: Value = server.get("/books/_search").await.json();
+    assert_eq!(
+        body["hits"]["total"],
+        json!({ "value": 1, "relation": "eq" })
+    );
+    let body: Value = server.get("/books/_mapping").await.json();
+    assert!(body["books"]["mappings"]["properties"].is_object());
+    // The configured version is still reported
+    let body: Value = server.get("/").await.json();
+    assert_eq!(body["version"]["number"], "6.8.23");
+}
+
+#[tokio::test]
+async fn test_no_types_for_8x_without_emulation() {
+    let state = AppState::new(Arc::new(Storage::new()), "8.11.0");
+    assert_eq!(state.compat(), Compatibility::native());
+    assert!(!state.has_document_types());
+    let server = server_with(state);
+    create_books(&server).await;
+
+    let body: Value = server.get("/books/_doc/1").await.json();
+    assert!(body.get("_type").is_none());
+    let body: Value = server
+        .put("/books/_doc/2")
+        .json(&json!({ "title": "emma" }))
+        .await
+        .json();
+    assert!(body.get("_type").is_none());
+    let body: Value = server.get("/books/_search").await.json();
+    assert!(body["hits"]["hits"][0].get("_type").is_none());
+    // Other shapes stay those of 7.x
+    assert_eq!(body["hits"]["total"]["relation"], "eq");
+    let body: Value = server.get("/books/_explain/1").await.json();
+    assert!(body.get("_type").is_none());
+    let body: Value = server
+        .post("/_bulk")
+        .bytes("{\"index\":{\"_index\":\"books\",\"_id\":\"3\"}}\n{}\n".into())
+        .content_type("application/x-ndjson")
+        .await
+        .json();
+    assert!(body["items"][0]["index"].get("_type").is_none());
+}
+
+#[tokio::test]
+async fn test_typed_mappings_in_6x() {
+    let server = create_test_server("6.8.23");
+    server
+        .put("/books")
+        .json(&json!({
+            "mappings": { "book": { "properties": { "title": { "type": "text" } } } }
+        }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/books/_mapping/book")
+        .json(&json!({ "book": { "properties": { "year": { "type": "integer" } } } }))
+        .await
+        .assert_status_ok();
+
+    let body: Value = server.get("/books/_mapping").await.json();
+    let properties = &body["books"]["mappings"]["_doc"]["properties"];
+    assert_eq!(properties["title"]["type"], "text");
+    assert_eq!(properties["year"]["type"], "integer");
+
+    let body: Value = server.get("/books").await.json();
+    assert!(body["books"]["mappings"]["_doc"]["properties"].is_object());
+
+    let body: Value = server
+        .get("/books/_mapping")
+        .add_query_param("include_type_name", "false")
+        .await
+        .json();
+    assert_eq!(
+        body["books"]["mappings"]["properties"]["title"]["type"],
+        "text"
+    );
+
+    let body: Value = server.get("/books/_mapping/book").await.json();
+    assert!(body["books"]["mappings"]["_doc"]["properties"]["year"].is_object());
+}
+
+#[tokio::test]
+async fn test_typeless_mappings_in_7x() {
+    let server = create_test_server("7.17.0");
+    // Typed mappings are still accepted
+    server
+        .put("/books")
+        .json(&json!({
+            "mappings": { "_doc": { "properties": { "title": { "type": "text" } } } }
+        }))
+        .await
+        .assert_status_ok();
+
+    let body: Value = server.get("/books/_mapping").await.json();
+    assert_eq!(
+        body["books"]["mappings"]["properties"]["title"]["type"],
+        "text"
+    );
+
+    let body: Value = server
+        .get("/books/_mapping")
+        .add_query_param("include_type_name", "true")
+        .await
+        .json();
+    assert_eq!(
+        body["books"]["mappings"]["_doc"]["properties"]["title"]["type"],
+        "text"
+    );
+}
+
+#[tokio::test]
+async fn test_typed_document_endpoints() {
+    for version in ["6.8.23", "7.17.0"] {
+        let server = create_test_server(version);
+        server.put("/books").await.assert_status_ok();
+
+        let response = server
+            .put("/books/book/1")
+            .json(&json!({ "title": "dune" }))
+            .await;
+        response.assert_status(StatusCode::CREATED);
+        assert_eq!(response.json::<Value>()["_type"], "book", "{}", version);
+        let body: Value = server.get("/books/book/1").await.json();
+        assert_eq!(body["_type"], "book", "{}", version);
+        assert_eq!(body["_source"]["title"], "dune");
+        server.get("/books/_doc/1").await.assert_status_ok();
+        server
+            .method(axum::http::Method::HEAD, "/books/book/1")
+            .await
+            .assert_status_ok();
+
+        let body: Value = server
+            .post("/books/book")
+            .json(&json!({ "title": "emma" }))
+            .await
+            .json();
+        assert_eq!(body["_type"], "book");
+        assert_eq!(body["result"], "created");
+
+        let body: Value = server
+            .post("/books/book/_search")
+            .json(&json!({ "query": { "match_all": {} } }))
+            .await
+            .json();
+        assert_eq!(body["hits"]["hits"].as_array().unwrap().len(), 2);
+
+        server.delete("/books/book/1").await.assert_status_ok();
+        server.get("/books/book/1").await.assert_status_not_found();
+    }
+
+    // Paths of unknown APIs are not typed paths
+    let server = create_test_server("6.8.23");
+    server.put("/books").await.assert_status_ok();
+    server
+        .get("/books/_unknown/1")
+        .await
+        .assert_status_not_found();
+    server.get("/_unknown/book").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_types_are_gone_in_8x() {
+    let server = create_test_server("8.11.0");
+    create_books(&server).await;
+
+    let body: Value = server.get("/books/_doc/1").await.json();
+    assert!(body.get("_type").is_none());
+    assert_eq!(body["_id"], "1");
+
+    let body: Value = server
+        .post("/books/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    let hit = &body["hits"]["hits"][0];
+    assert!(hit.get("_type").is_none());
+    assert_eq!(hit["_index"], "books");
+
+    let body: Value = server
+        .post("/books/_doc")
+        .json(&json!({ "title": "emma", "_type": "kept in the source" }))
+        .await
+        .json();
+    assert!(body.get("_type").is_none());
+    let id = body["_id"].as_str().unwrap();
+    let body: Value = server.get(&format!("/books/_doc/{}", id)).await.json();
+    assert_eq!(body["_source"]["_type"], "kept in the source");
+
+    // Write responses have no `_type` either
+    for path in ["/books/_doc/2", "/books/_create/3"] {
+        let body: Value = server
+            .put(path)
+            .json(&json!({ "title": "emma" }))
+            .await
+            .json();
+        assert!(body.get("_type").is_none(), "{}", path);
+        assert_eq!(body["result"], "created");
+    }
+    let body: Value = server.delete("/books/_doc/2").await.json();
+    assert!(body.get("_type").is_none());
+
+    server
+        .put("/books/book/2")
+        .json(&json!({ "title": "dune" }))
+        .await
+        .assert_status_not_found();
+    server
+        .get("/books/_mapping/_doc")
+        .await
+        .assert_status_not_found();
+}
@@ -1,8 +1,7 @@
 //! Unit tests for Config module
 
-use gbs::config::Config;
+use gbs::config::{AuditOutput, AutoCreateIndex, Config, Durability, LogFormat, ProxyMode};
 use std::fs;
-use std::path::Path;
 use tempfile::TempDir;
 
 #[test]
@@ -12,6 +11,7 @@ fn test_default_config() {
     assert_eq!(config.server.port, 9200);
     assert_eq!(config.storage.data_dir, "./data");
     assert_eq!(config.logging.level, "info");
+    assert_eq!(config.logging.format, LogFormat::Text);
     assert_eq!(config.es_version, "6.8.23");
 }
 
@@ -115,6 +115,17 @@ fn test_env_override_log_level() {
     std::env::remove_var("GUMMY_LOG_LEVEL");
 }
 
+#[test]
+fn test_env_override_log_format() {
+    std::env::set_var("GUMMY_LOG_FORMAT", "JSON");
+    let config = Config::default().with_env_overrides();
+    assert_eq!(config.logging.format, LogFormat::Json);
+    std::env::set_var("GUMMY_LOG_FORMAT", "xml");
+    let config = Config::default().with_env_overrides();
+    assert_eq!(config.logging.format, LogFormat::Text);
+    std::env::remove_var("GUMMY_LOG_FORMAT");
+}
+
 #[test]
 fn test_env_override_es_version() {
     std::env::set_var("GUMMY_ES_VERSION", "7.17.0");
@@ -197,12 +208,37 @@ fn test_load_from_file_invalid_yaml() {
     std::env::remove_var("GUMMY_CONFIG");
 }
 
+#[test]
+fn test_tls_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+  tls:
+    cert_path: "/etc/gbs/cert.pem"
+    key_path: "/etc/gbs/key.pem"
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    let tls = config.server.tls.unwrap();
+    assert_eq!(tls.cert_path, "/etc/gbs/cert.pem");
+    assert_eq!(tls.key_path, "/etc/gbs/key.pem");
+    assert_eq!(tls.redirect_port, None);
+    assert_eq!(tls.reload_interval_secs, 3600);
+
+    // Plain HTTP by default
+    assert!(Config::default().server.tls.is_none());
+}
+
 #[test]
 fn test_server_addr() {
     let config = Config {
         server: gbs::config::ServerConfig {
             host: "127.0.0.1".to_string(),
             port: 8080,
+            ..Default::default()
         },
         ..Config::default()
     };
@@ -217,6 +253,7 @@ fn test_server_addr_invalid_host() {
         server: gbs::config::ServerConfig {
             host: "invalid-host".to_string(),
             port: 8080,
+            ..Default::default()
         },
         ..Config::default()
     };
@@ -262,3 +299,334 @@ es_version: "7.0.0"
     std::env::remove_var("GUMMY_PORT");
     std::env::remove_var("GUMMY_LOG_LEVEL");
 }
+
+#[test]
+fn test_security_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+security:
+  enabled: true
+  users:
+    - username: "elastic"
+      password: "changeme"
+  api_keys:
+    - id: "ingest"
+      key: "ingest-secret"
+  file: "/etc/gbs/security.yaml"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert!(config.security.enabled);
+    assert_eq!(config.security.users.len(), 1);
+    assert_eq!(config.security.users[0].username, "elastic");
+    assert_eq!(
+        config.security.users[0].roles,
+        vec!["superuser".to_string()]
+    );
+    assert_eq!(config.security.api_keys[0].id, "ingest");
+    assert_eq!(
+        config.security.api_keys[0].roles,
+        vec!["superuser".to_string()]
+    );
+    assert_eq!(
+        config.security.file.as_deref(),
+        Some("/etc/gbs/security.yaml")
+    );
+
+    // Security section is optional and disabled by default
+    assert!(!Config::default().security.enabled);
+}
+
+#[test]
+fn test_storage_limits_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  max_indices: 50
+  auto_rollover:
+    max_docs: 1000
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.storage.max_indices, Some(50));
+    assert_eq!(config.storage.auto_rollover.max_docs, Some(1000));
+    assert_eq!(config.storage.auto_rollover.max_size_bytes, None);
+
+    // Limits are disabled by default
+    let default = Config::default();
+    assert_eq!(default.storage.max_indices, None);
+    assert_eq!(default.storage.auto_rollover.max_docs, None);
+}
+
+#[test]
+fn test_request_limits_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+  max_body_bytes: 1048576
+  max_bulk_actions: 500
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.server.max_body_bytes, 1048576);
+    assert_eq!(config.server.max_bulk_actions, 500);
+    assert_eq!(config.server.max_document_bytes, 10 * 1024 * 1024);
+    assert_eq!(config.server.shutdown_timeout_secs, 30);
+}
+
+#[test]
+fn test_search_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+search:
+  max_concurrent_index_searches: 16
+  default_timeout_ms: 2000
+  parallel_scoring: false
+  parallel_scoring_min_docs: 1000
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.search.max_concurrent_index_searches, 16);
+    assert_eq!(config.search.default_timeout_ms, Some(2000));
+    assert!(!config.search.parallel_scoring);
+    assert_eq!(config.search.parallel_scoring_min_docs, 1000);
+    assert_eq!(Config::default().search.max_concurrent_index_searches, 5);
+    assert_eq!(Config::default().search.default_timeout_ms, None);
+    assert!(Config::default().search.parallel_scoring);
+    assert_eq!(Config::default().search.parallel_scoring_min_docs, 50_000);
+}
+
+#[test]
+fn test_tiering_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  tiering:
+    warm_after_secs: 604800
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.storage.tiering.warm_after_secs, Some(604800));
+    assert_eq!(config.storage.tiering.check_interval_secs, 300);
+
+    assert_eq!(Config::default().storage.tiering.warm_after_secs, None);
+}
+
+#[test]
+fn test_lazy_loading_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  lazy_loading: true
+  document_cache_bytes: 1048576
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert!(config.storage.lazy_loading);
+    assert_eq!(config.storage.document_cache_bytes, 1048576);
+
+    assert!(!Config::default().storage.lazy_loading);
+    assert_eq!(
+        Config::default().storage.document_cache_bytes,
+        64 * 1024 * 1024
+    );
+}
+
+#[test]
+fn test_retention_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  retention:
+    check_interval_secs: 10
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.storage.retention.check_interval_secs, 10);
+
+    assert_eq!(Config::default().storage.retention.check_interval_secs, 60);
+}
+
+#[test]
+fn test_durability_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  durability: async
+  flush_interval_ms: 250
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.storage.durability, Durability::Async);
+    assert_eq!(config.storage.flush_interval_ms, 250);
+
+    let config = Config::default();
+    assert_eq!(config.storage.durability, Durability::None);
+    assert_eq!(config.storage.flush_interval_ms, 1000);
+
+    let invalid = yaml.replace("durability: async", "durability: sometimes");
+    assert!(serde_yaml::from_str::<Config>(&invalid).is_err());
+    assert_eq!(Durability::parse("REQUEST"), Some(Durability::Request));
+    assert_eq!(Durability::parse("sometimes"), None);
+}
+
+#[test]
+fn test_auto_create_index_config_deserialization() {
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+  auto_create_index: true
+logging:
+  level: "info"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.storage.auto_create_index, Some(AutoCreateIndex::All));
+    assert_eq!(Config::default().storage.auto_create_index, None);
+
+    let patterns = yaml.replace(
+        "auto_create_index: true",
+        "auto_create_index: \"+logs-*,-tmp*\"",
+    );
+    let config: Config = serde_yaml::from_str(&patterns).unwrap();
+    assert_eq!(
+        config.storage.auto_create_index,
+        Some(AutoCreateIndex::Patterns(vec![
+            (true, "logs-*".to_string()),
+            (false, "tmp*".to_string()),
+        ]))
+    );
+    let round_trip: Config =
+        serde_yaml::from_str(&serde_yaml::to_string(&config).unwrap()).unwrap();
+    assert_eq!(
+        round_trip.storage.auto_create_index,
+        config.storage.auto_create_index
+    );
+
+    let invalid = yaml.replace("auto_create_index: true", "auto_create_index: \",\"");
+    assert!(serde_yaml::from_str::<Config>(&invalid).is_err());
+}
+
+#[test]
+fn test_daemon_config_paths() {
+    let config = Config::default();
+    assert_eq!(
+        config.pid_file_path(),
+        std::path::PathBuf::from("./data/gbs.pid")
+    );
+    assert_eq!(
+        config.log_file_path(),
+        std::path::PathBuf::from("./data/gbs.log")
+    );
+
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "/var/lib/gbs"
+logging:
+  level: "info"
+daemon:
+  pid_file: "/run/gbs.pid"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(
+        config.pid_file_path(),
+        std::path::PathBuf::from("/run/gbs.pid")
+    );
+    assert_eq!(
+        config.log_file_path(),
+        std::path::PathBuf::from("/var/lib/gbs/gbs.log")
+    );
+}
+
+#[test]
+fn test_proxy_config_deserialization() {
+    let config = Config::default();
+    assert_eq!(config.proxy.mode, ProxyMode::Off);
+    assert_eq!(
+        config.proxy_recordings_path(),
+        std::path::PathBuf::from("./data/proxy-recordings.ndjson")
+    );
+
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+proxy:
+  mode: replay
+  recordings_file: "/tmp/recordings.ndjson"
+  timeout_secs: 5
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert_eq!(config.proxy.mode, ProxyMode::Replay);
+    assert_eq!(config.proxy.upstream_url, None);
+    assert_eq!(config.proxy.timeout_secs, 5);
+    assert_eq!(
+        config.proxy_recordings_path(),
+        std::path::PathBuf::from("/tmp/recordings.ndjson")
+    );
+    assert_eq!(ProxyMode::parse("RECORD"), Some(ProxyMode::Record));
+    assert_eq!(ProxyMode::parse("mirror"), None);
+}
+
+#[test]
+fn test_audit_config_deserialization() {
+    let config = Config::default();
+    assert!(!config.audit.enabled);
+    assert_eq!(config.audit.output, AuditOutput::File);
+    assert_eq!(config.audit.index, ".gbs-audit");
+    assert_eq!(
+        config.audit_file_path(),
+        std::path::PathBuf::from("./data/audit.ndjson")
+    );
+
+    let yaml = r#"
+server:
+  port: 9200
+storage:
+  data_dir: "./data"
+logging:
+  level: "info"
+audit:
+  enabled: true
+  output: index
+  index: "compliance-audit"
+"#;
+    let config: Config = serde_yaml::from_str(yaml).unwrap();
+    assert!(config.audit.enabled);
+    assert_eq!(config.audit.output, AuditOutput::Index);
+    assert_eq!(config.audit.index, "compliance-audit");
+    assert_eq!(AuditOutput::parse("FILE"), Some(AuditOutput::File));
+    assert_eq!(AuditOutput::parse("syslog"), None);
+}
@@ -0,0 +1,111 @@
+//! Tests for the pid file and background server commands
+
+use gbs::daemon::{read_pid, status, stop, DaemonStatus, PidFile};
+#[cfg(unix)]
+use std::process::Command;
+use std::time::Duration;
+use tempfile::TempDir;
+
+#[test]
+fn test_pid_file_lifecycle() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("run").join("gbs.pid");
+
+    let pid_file = PidFile::create(&path).unwrap();
+    assert_eq!(read_pid(&path).unwrap(), Some(std::process::id()));
+    assert_eq!(
+        status(&path).unwrap(),
+        DaemonStatus::Running(std::process::id())
+    );
+
+    drop(pid_file);
+    assert!(!path.exists());
+    assert_eq!(status(&path).unwrap(), DaemonStatus::Stopped);
+    assert_eq!(stop(&path, Duration::from_secs(1)).unwrap(), None);
+
+    std::fs::write(&path, "not a pid").unwrap();
+    assert!(read_pid(&path).is_err());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_pid_file_of_live_and_exited_process() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("gbs.pid");
+
+    let mut child = Command::new("sleep").arg("30").spawn().unwrap();
+    let pid = child.id();
+    std::fs::write(&path, format!("{}\n", pid)).unwrap();
+
+    // Another live process holds the pid file
+    assert_eq!(status(&path).unwrap(), DaemonStatus::Running(pid));
+    assert!(PidFile::create(&path).is_err());
+
+    // Reap the child in the background so it disappears once stopped
+    let waiter = std::thread::spawn(move || child.wait().unwrap());
+    assert_eq!(stop(&path, Duration::from_secs(10)).unwrap(), Some(pid));
+    assert!(!waiter.join().unwrap().success());
+    assert!(!path.exists());
+
+    // A pid file naming an exited process is stale and gets replaced
+    std::fs::write(&path, format!("{}\n", pid)).unwrap();
+    assert_eq!(status(&path).unwrap(), DaemonStatus::Stale(pid));
+    let pid_file = PidFile::create(&path).unwrap();
+    assert_eq!(read_pid(&path).unwrap(), Some(std::process::id()));
+    drop(pid_file);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_start_status_stop_commands() {
+    let temp_dir = TempDir::new().unwrap();
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let gbs = |command: &str| {
+        Command::new(env!("CARGO_BIN_EXE_gbs"))
+            .arg(command)
+            .current_dir(temp_dir.path())
+            .env("GUMMY_CONFIG", temp_dir.path().join("missing.yaml"))
+            .env("GUMMY_DATA_DIR", temp_dir.path().join("data"))
+            .env("GUMMY_HOST", "127.0.0.1")
+            .env("GUMMY_PORT", port.to_string())
+            .env("GUMMY_SELF_TEST", "false")
+            .output()
+            .unwrap()
+    };
+
+    let output = gbs("status");
+    assert_eq!(output.status.code(), Some(3));
+
+    let output = gbs("start");
+    assert!(output.status.success(), "{:?}", output);
+    let pid_path = temp_dir.path().join("data").join("gbs.pid");
+    let pid = read_pid(&pid_path).unwrap().unwrap();
+    assert!(String::from_utf8_lossy(&output.stdout).contains(&pid.to_string()));
+
+    let output = gbs("status");
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("is running"));
+
+    // A second server refuses to start while the first is running
+    assert!(!gbs("start").status.success());
+
+    let output = gbs("stop");
+    assert!(output.status.success(), "{:?}", output);
+    assert!(!pid_path.exists());
+    assert!(temp_dir.path().join("data").join("gbs.log").exists());
+    assert_eq!(gbs("status").status.code(), Some(3));
+}
+
+#[tokio::test]
+async fn test_requested_shutdown_completes_the_shutdown_signal() {
+    // Requested before the server waits for it, as a service stop can be
+    gbs::daemon::request_shutdown();
+    tokio::time::timeout(Duration::from_secs(5), gbs::daemon::shutdown_signal())
+        .await
+        .unwrap();
+}
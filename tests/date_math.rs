@@ -0,0 +1,151 @@
+//! Tests for date range queries and date math index names
+
+mod common;
+
+use chrono::{Duration, TimeZone, Utc};
+use common::search_ids;
+use gbs::storage::{resolve_date_math_index_name, Storage};
+use serde_json::json;
+
+#[tokio::test]
+async fn test_range_date_math_relative_to_now() {
+    let storage = Storage::new();
+    storage.create_index("events", None, None).await.unwrap();
+    let now = Utc::now();
+    for (id, document) in [
+        ("today", json!({ "@timestamp": now.to_rfc3339() })),
+        (
+            "recent",
+            json!({ "@timestamp": (now - Duration::days(3)).timestamp_millis() }),
+        ),
+        (
+            "old",
+            json!({ "@timestamp": (now - Duration::days(30)).to_rfc3339() }),
+        ),
+    ] {
+        storage
+            .index_document("events", id, document)
+            .await
+            .unwrap();
+    }
+
+    let query = json!({ "range": { "@timestamp": { "gte": "now-7d/d", "lte": "now" } } });
+    assert_eq!(
+        search_ids(&storage, "events", query).await,
+        ["recent", "today"]
+    );
+
+    let query = json!({ "range": { "@timestamp": { "lt": "now-1d/d" } } });
+    assert_eq!(
+        search_ids(&storage, "events", query).await,
+        ["old", "recent"]
+    );
+}
+
+#[tokio::test]
+async fn test_range_with_format_and_rounding() {
+    let storage = Storage::new();
+    storage.create_index("orders", None, None).await.unwrap();
+    for (id, date) in [
+        ("1", json!("2024-04-30T23:59:59Z")),
+        ("2", json!("2024-05-15")),
+        ("3", json!("2024-05-31T12:00:00Z")),
+        ("4", json!(1717286400000i64)), // 2024-06-02
+    ] {
+        storage
+            .index_document("orders", id, json!({ "date": date }))
+            .await
+            .unwrap();
+    }
+
+    let query = json!({
+        "range": { "date": { "gte": "01/05/2024", "lte": "31/05/2024", "format": "dd/MM/yyyy" } }
+    });
+    assert_eq!(search_ids(&storage, "orders", query).await, ["2"]);
+
+    // Rounding up an inclusive upper bound covers the whole day
+    let query = json!({
+        "range": { "date": { "gte": "01/05/2024", "lte": "31/05/2024||/d", "format": "dd/MM/yyyy" } }
+    });
+    assert_eq!(search_ids(&storage, "orders", query).await, ["2", "3"]);
+
+    let query = json!({ "range": { "date": { "gte": "2024-05-01||+1M/M" } } });
+    assert_eq!(search_ids(&storage, "orders", query).await, ["4"]);
+
+    let query = json!({ "range": { "date": { "gte": 1717200000, "format": "epoch_second" } } });
+    assert_eq!(search_ids(&storage, "orders", query).await, ["4"]);
+}
+
+#[tokio::test]
+async fn test_range_with_time_zone() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document("logs", "1", json!({ "ts": "2024-05-01T23:30:00Z" }))
+        .await
+        .unwrap();
+
+    let query = json!({ "range": { "ts": { "gte": "2024-05-02" } } });
+    assert!(search_ids(&storage, "logs", query).await.is_empty());
+
+    // Midnight in +01:00 is 23:00 UTC the day before
+    let query = json!({ "range": { "ts": { "gte": "2024-05-02", "time_zone": "+01:00" } } });
+    assert_eq!(search_ids(&storage, "logs", query).await, ["1"]);
+
+    let query = json!({ "range": { "ts": { "gte": "2024-05-02", "time_zone": "Mars/Olympus" } } });
+    assert!(storage
+        .search("logs", &query, None, None, None, None, None)
+        .await
+        .is_err());
+
+    let query = json!({ "range": { "ts": { "gte": "yesterday", "format": "yyyy-MM-dd" } } });
+    assert!(storage
+        .search("logs", &query, None, None, None, None, None)
+        .await
+        .is_err());
+}
+
+#[test]
+fn test_resolve_date_math_index_name() {
+    let now = Utc.with_ymd_and_hms(2024, 3, 1, 0, 30, 0).unwrap();
+    let resolve = |name| resolve_date_math_index_name(name, now).unwrap();
+
+    assert_eq!(resolve("logs"), "logs");
+    assert_eq!(resolve("<logs-{now/d}>"), "logs-2024.03.01");
+    assert_eq!(resolve("<logs-{now/d-1d}>"), "logs-2024.02.29");
+    assert_eq!(resolve("<logs-{now/M{yyyy.MM}}>"), "logs-2024.03");
+    assert_eq!(
+        resolve("<logs-{now/d{yyyy.MM.dd|-01:00}}>"),
+        "logs-2024.02.29"
+    );
+    assert_eq!(resolve("<logs-{now/w}>"), "logs-2024.02.26");
+    assert_eq!(resolve("<\\{literal\\}-{now/y{yyyy}}>"), "{literal}-2024");
+
+    assert!(resolve_date_math_index_name("<logs-{now/d>", now).is_err());
+    assert!(resolve_date_math_index_name("<logs-{now/q}>", now).is_err());
+    assert!(resolve_date_math_index_name("<logs-{now{yyyy|Mars}}>", now).is_err());
+}
+
+#[tokio::test]
+async fn test_date_math_index_names() {
+    let storage = Storage::new();
+    let today = format!("logs-{}", Utc::now().format("%Y.%m.%d"));
+
+    storage
+        .create_index("<logs-{now/d}>", None, None)
+        .await
+        .unwrap();
+    assert!(storage.index_exists(&today).await.unwrap());
+
+    storage
+        .index_document("<logs-{now/d}>", "1", json!({ "message": "hello" }))
+        .await
+        .unwrap();
+    assert!(storage.document_exists(&today, "1").await.unwrap());
+
+    let resolved = storage
+        .resolve_index_expression("<logs-{now/d}>,<logs-{now/d-1d}*>")
+        .await
+        .unwrap();
+    assert_eq!(resolved, [today]);
+}
@@ -0,0 +1,151 @@
+//! Tests for the on-disk encoding of documents
+
+mod common;
+
+use common::open_sled;
+use gbs::document_codec::{decode_document, encode_document, is_legacy_document};
+use gbs::storage::Storage;
+use gbs::storage_backend::SledBackend;
+use serde_json::json;
+use tempfile::TempDir;
+
+#[test]
+fn test_documents_round_trip() {
+    let long_text = "x".repeat(300);
+    let many_fields: serde_json::Map<String, serde_json::Value> =
+        (0..20).map(|i| (format!("f{}", i), json!(i))).collect();
+    let documents = [
+        json!({}),
+        json!({ "title": "Grüße 🐻", "tags": ["a", "b"], "nested": { "empty": [], "null": null } }),
+        json!({ "small": 7, "byte": 200, "short": 60000, "int": 4_000_000_000u64, "max": u64::MAX }),
+        json!({ "neg": -5, "neg8": -100, "neg16": -30000, "neg32": -2_000_000_000, "min": i64::MIN }),
+        json!({ "float": 1.5, "whole_float": 2.0, "tiny": 1e-300, "flags": [true, false] }),
+        json!({ "long": long_text, "items": (0..100).collect::<Vec<_>>() }),
+        serde_json::Value::Object(many_fields),
+    ];
+    for document in documents {
+        let bytes = encode_document(&document);
+        assert!(!is_legacy_document(&bytes));
+        assert_eq!(decode_document(&bytes).unwrap(), document);
+    }
+}
+
+#[test]
+fn test_long_strings_and_collections_round_trip() {
+    // Lengths past the 8 and 16 bit forms of each marker
+    let wide_map: serde_json::Map<String, serde_json::Value> =
+        (0..70_000).map(|i| (format!("k{}", i), json!(i))).collect();
+    let documents = [
+        json!({ "str8": "s".repeat(200), "str16": "m".repeat(60_000), "str32": "l".repeat(70_000) }),
+        json!({ "array16": vec![1; 60_000], "array32": vec![-1; 70_000] }),
+        json!({ "map32": wide_map }),
+        json!({ "above_i64": i64::MAX as u64 + 1, "f64": std::f64::consts::PI, "neg_f64": -1.25e300 }),
+    ];
+    for document in documents {
+        assert_eq!(
+            decode_document(&encode_document(&document)).unwrap(),
+            document
+        );
+    }
+}
+
+#[test]
+fn test_messagepack_markers_are_read() {
+    // Integer, float and 16 bit length markers, as written by the first version
+    let payloads: [(&[u8], serde_json::Value); 10] = [
+        (&[0xcc, 0xff], json!(255)),
+        (&[0xcd, 0xff, 0xff], json!(65535)),
+        (&[0xce, 0xff, 0xff, 0xff, 0xff], json!(4_294_967_295u64)),
+        (
+            &[0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            json!(u64::MAX),
+        ),
+        (&[0xd0, 0x80], json!(-128)),
+        (&[0xd1, 0x80, 0x00], json!(-32768)),
+        (&[0xd2, 0x80, 0x00, 0x00, 0x00], json!(i32::MIN)),
+        (&[0xd3, 0x80, 0, 0, 0, 0, 0, 0, 0], json!(i64::MIN)),
+        (&[0xca, 0x3f, 0xc0, 0x00, 0x00], json!(1.5)),
+        (
+            &[
+                0xde, 0x00, 0x01, 0xda, 0x00, 0x01, b'k', 0xdc, 0x00, 0x01, 0xe0,
+            ],
+            json!({ "k": [-32] }),
+        ),
+    ];
+    for (payload, expected) in payloads {
+        let bytes = [&[0xc1, 1, 0][..], payload].concat();
+        assert_eq!(decode_document(&bytes).unwrap(), expected);
+    }
+}
+
+#[test]
+fn test_large_documents_are_compressed() {
+    let document = json!({ "message": "the same words again and again ".repeat(50) });
+    let json_len = serde_json::to_vec(&document).unwrap().len();
+    let bytes = encode_document(&document);
+    assert!(bytes.len() < json_len / 4);
+    assert_eq!(decode_document(&bytes).unwrap(), document);
+}
+
+#[test]
+fn test_legacy_json_documents_are_read() {
+    let bytes = br#"{"title":"old","count":3}"#;
+    assert!(is_legacy_document(bytes));
+    assert_eq!(
+        decode_document(bytes).unwrap(),
+        json!({ "title": "old", "count": 3 })
+    );
+}
+
+#[test]
+fn test_corrupt_documents_are_rejected() {
+    let bytes = encode_document(&json!({ "title": "cut short" }));
+    assert!(decode_document(&bytes[..bytes.len() - 3]).is_err());
+    assert!(decode_document(&[0xc1, 99, 0]).is_err());
+    assert!(decode_document(&[0xc1]).is_err());
+    // Trailing bytes, a key that is not a string, nesting past the limit
+    assert!(decode_document(&[0xc1, 1, 0, 0xc0, 0xc0]).is_err());
+    assert!(decode_document(&[0xc1, 1, 0, 0x81, 0x01, 0x02]).is_err());
+    let nested = [&[0xc1, 1, 0][..], &[0x91; 600], &[0xc0]].concat();
+    assert!(decode_document(&nested).is_err());
+}
+
+#[tokio::test]
+async fn test_legacy_documents_are_migrated_on_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+    {
+        let storage = Storage::with_sled(&data_path).unwrap();
+        storage.create_index("books", None, None).await.unwrap();
+        storage
+            .index_document("books", "1", json!({ "title": "new" }))
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+    {
+        // A document written before the binary format
+        let backend = SledBackend::new(&data_path).unwrap();
+        backend
+            .db()
+            .insert("doc::books:2", &br#"{"title":"old"}"#[..])
+            .unwrap();
+        backend.flush().unwrap();
+    }
+
+    {
+        let storage = open_sled(&data_path).await;
+        let old = storage.get_document("books", "2").await.unwrap();
+        assert_eq!(old["_source"]["title"], "old");
+        let new = storage.get_document("books", "1").await.unwrap();
+        assert_eq!(new["_source"]["title"], "new");
+    }
+
+    let backend = SledBackend::new(&data_path).unwrap();
+    let stored = backend.db().get("doc::books:2").unwrap().unwrap();
+    assert!(!is_legacy_document(&stored));
+    assert_eq!(
+        backend.load_document("books", "2").unwrap(),
+        Some(json!({ "title": "old" }))
+    );
+}
@@ -0,0 +1,84 @@
+//! Tests for write durability modes
+
+use gbs::bulk_ops::BulkAction;
+use gbs::config::Durability;
+use gbs::storage::{spawn_durability_flusher, Storage};
+use serde_json::json;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+
+async fn open(path: &Path, durability: Durability) -> Storage {
+    let storage = Storage::with_sled(path)
+        .unwrap()
+        .with_durability(durability);
+    storage.load_from_backend().await.unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn test_request_durability_persists_acknowledged_writes() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+
+    {
+        let storage = open(&data_path, Durability::Request).await;
+        storage.create_index("logs", None, None).await.unwrap();
+        storage
+            .index_document("logs", "1", json!({ "message": "one" }))
+            .await
+            .unwrap();
+        let (id, _) = storage
+            .create_document("logs", json!({ "message": "two" }))
+            .await
+            .unwrap();
+        storage.delete_document("logs", &id).await.unwrap();
+
+        let results = storage
+            .execute_bulk(vec![
+                BulkAction::Index {
+                    index: "logs".to_string(),
+                    id: Some("3".to_string()),
+                    document: json!({ "message": "three" }),
+                },
+                BulkAction::Delete {
+                    index: "logs".to_string(),
+                    id: "missing".to_string(),
+                },
+            ])
+            .await;
+        assert!(results[0].is_ok());
+    }
+
+    let storage = open(&data_path, Durability::Request).await;
+    assert_eq!(
+        storage.get_indices_stats().await,
+        vec![("logs".to_string(), 2)]
+    );
+    let doc = storage.get_document("logs", "3").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "three");
+}
+
+#[tokio::test]
+async fn test_async_durability_flusher() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+
+    {
+        let storage = open(&data_path, Durability::Async).await;
+        let flusher = spawn_durability_flusher(storage.clone(), Duration::from_millis(10));
+        storage.create_index("logs", None, None).await.unwrap();
+        storage
+            .index_document("logs", "1", json!({ "message": "one" }))
+            .await
+            .unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!flusher.is_finished());
+        flusher.abort();
+        let _ = flusher.await;
+    }
+
+    let storage = open(&data_path, Durability::Async).await;
+    let doc = storage.get_document("logs", "1").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "one");
+}
@@ -0,0 +1,295 @@
+//! Tests for dynamic mapping of new document fields
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{index_action, open_sled, test_server};
+use gbs::error::GbsError;
+use gbs::storage::{DynamicMode, Storage};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_new_fields_are_mapped_by_type() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({
+                "message": "started",
+                "count": 3,
+                "ratio": 0.5,
+                "ok": true,
+                "@timestamp": "2024-05-01T10:00:00Z",
+                "user": { "name": "ann", "tags": ["a", "b"] },
+                "extra": {},
+                "missing": null
+            }),
+        )
+        .await
+        .unwrap();
+
+    let mappings = storage.get_mapping("logs").await.unwrap();
+    assert_eq!(
+        mappings,
+        json!({
+            "properties": {
+                "message": {
+                    "type": "text",
+                    "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+                },
+                "count": { "type": "long" },
+                "ratio": { "type": "double" },
+                "ok": { "type": "boolean" },
+                "@timestamp": { "type": "date" },
+                "user": {
+                    "properties": {
+                        "name": {
+                            "type": "text",
+                            "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+                        },
+                        "tags": {
+                            "type": "text",
+                            "fields": { "keyword": { "type": "keyword", "ignore_above": 256 } }
+                        }
+                    }
+                },
+                "extra": { "type": "object" }
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_new_fields_merge_into_existing_mappings() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            None,
+            Some(json!({
+                "date_detection": false,
+                "properties": {
+                    "status": { "type": "keyword" },
+                    "user": { "properties": { "name": { "type": "keyword" } } }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({
+                "status": 200,
+                "day": "2024-05-01",
+                "user": { "name": "ann", "age": 31 }
+            }),
+        )
+        .await
+        .unwrap();
+    // A later document with another type does not change the mapping
+    storage
+        .index_document("logs", "2", json!({ "user": { "age": 31.5 } }))
+        .await
+        .unwrap();
+
+    let mappings = storage.get_mapping("logs").await.unwrap();
+    assert_eq!(
+        mappings["properties"]["status"],
+        json!({ "type": "keyword" })
+    );
+    assert_eq!(mappings["properties"]["day"]["type"], "text");
+    assert_eq!(
+        mappings["properties"]["user"]["properties"],
+        json!({
+            "name": { "type": "keyword" },
+            "age": { "type": "long" }
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_dynamic_false_keeps_new_fields_unmapped() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            None,
+            Some(json!({
+                "dynamic": false,
+                "properties": {
+                    "message": { "type": "text" },
+                    // Objects can opt back in
+                    "labels": { "type": "object", "dynamic": true }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({ "message": "hi", "other": 1, "labels": { "env": "prod" } }),
+        )
+        .await
+        .unwrap();
+
+    let mappings = storage.get_mapping("logs").await.unwrap();
+    assert!(mappings["properties"].get("other").is_none());
+    assert_eq!(
+        mappings["properties"]["labels"]["properties"]["env"]["type"],
+        "text"
+    );
+    // The document keeps the unmapped field
+    let doc = storage.get_document("logs", "1").await.unwrap();
+    assert_eq!(doc["_source"]["other"], 1);
+}
+
+#[tokio::test]
+async fn test_dynamic_strict_rejects_new_fields() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            None,
+            Some(json!({
+                "dynamic": "strict",
+                "properties": {
+                    "message": { "type": "text" },
+                    "user": { "properties": { "name": { "type": "keyword" } } }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({ "message": "hi", "user": { "name": "ann" } }),
+        )
+        .await
+        .unwrap();
+    // Sub-fields inherit the mode of their parent
+    let err = storage
+        .index_document("logs", "2", json!({ "user": { "age": 31 } }))
+        .await
+        .unwrap_err();
+    assert!(matches!(err, GbsError::StrictDynamicMapping(ref field) if field == "user.age"));
+    assert!(storage.get_document("logs", "2").await.is_err());
+
+    let results = storage
+        .execute_bulk(vec![
+            index_action("logs", "3", json!({ "message": "ok" })),
+            index_action("logs", "4", json!({ "level": "warn" })),
+        ])
+        .await;
+    assert!(results[0].is_ok());
+    assert!(matches!(
+        results[1],
+        Err(GbsError::StrictDynamicMapping(ref field)) if field == "level"
+    ));
+    assert!(storage.get_document("logs", "4").await.is_err());
+
+    // Relaxed later, the field is mapped
+    storage
+        .set_dynamic_mapping("logs", DynamicMode::True)
+        .await
+        .unwrap();
+    storage
+        .index_document("logs", "4", json!({ "level": "warn" }))
+        .await
+        .unwrap();
+    let mappings = storage.get_mapping("logs").await.unwrap();
+    assert_eq!(mappings["dynamic"], "true");
+    assert_eq!(mappings["properties"]["level"]["type"], "text");
+}
+
+#[tokio::test]
+async fn test_bulk_mappings_persist() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("dynamic_db");
+
+    {
+        let storage = open_sled(&data_path).await;
+        storage.create_index("logs", None, None).await.unwrap();
+        let results = storage
+            .execute_bulk(vec![
+                index_action("logs", "1", json!({ "count": 1 })),
+                index_action("logs", "2", json!({ "count": 2, "ok": true })),
+            ])
+            .await;
+        assert!(results.iter().all(|result| result.is_ok()));
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&data_path).await;
+    let mappings = storage.get_mapping("logs").await.unwrap();
+    assert_eq!(
+        mappings,
+        json!({
+            "properties": {
+                "count": { "type": "long" },
+                "ok": { "type": "boolean" }
+            }
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_mapping_api() {
+    let storage = Storage::new();
+    let server = test_server(storage, "6.8.23");
+
+    server.put("/logs-1").await.assert_status_ok();
+    server.put("/logs-2").await.assert_status_ok();
+    server
+        .put("/logs-1/_doc/1")
+        .json(&json!({ "count": 1 }))
+        .await;
+
+    let body: Value = server.get("/logs-*/_mapping").await.json();
+    assert_eq!(
+        body,
+        json!({
+            "logs-1": { "mappings": { "properties": { "count": { "type": "long" } } } },
+            "logs-2": { "mappings": {} }
+        })
+    );
+
+    server
+        .put("/logs-1/_mapping")
+        .json(&json!({ "dynamic": "strict" }))
+        .await
+        .assert_status_ok();
+    let response = server
+        .put("/logs-1/_doc/2")
+        .json(&json!({ "count": 2, "level": "warn" }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "strict_dynamic_mapping_exception");
+    assert_eq!(
+        body["error"]["reason"],
+        "mapping set to strict, dynamic introduction of [level] is not allowed"
+    );
+
+    server
+        .put("/logs-1/_mapping")
+        .json(&json!({ "dynamic": "sometimes" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/missing/_mapping")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
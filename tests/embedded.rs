@@ -0,0 +1,116 @@
+//! Tests for the in-process instance (feature `embedded`)
+#![cfg(feature = "embedded")]
+
+use gbs::client::query::{match_query, SearchRequest};
+use gbs::embedded::{Fixture, Gbs};
+use serde_json::{json, Value};
+
+fn books_fixture() -> Fixture {
+    Fixture::from_json(json!({
+        "books": {
+            "settings": { "number_of_shards": 1 },
+            "mappings": { "properties": { "title": { "type": "text" } } },
+            "aliases": ["library"],
+            "documents": {
+                "1": { "title": "Dune" },
+                "2": { "title": "Children of Dune" }
+            }
+        }
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn test_requests_without_a_port() {
+    let gbs = Gbs::start_in_memory();
+    gbs.seed(&books_fixture()).await.unwrap();
+
+    let (status, body) = gbs
+        .request(
+            "POST",
+            "/library/_search",
+            Some(json!({ "query": { "match": { "title": "dune" } } })),
+        )
+        .await
+        .unwrap();
+    assert_eq!(status, 200);
+    assert_eq!(body["hits"]["total"]["value"], 2);
+
+    let (status, body) = gbs.request("GET", "/books/_doc/3", None).await.unwrap();
+    assert_eq!(status, 404);
+    assert!(body["error"]["reason"].is_string());
+
+    let (status, _) = gbs.request("HEAD", "/missing", None).await.unwrap();
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_client_and_storage_share_the_instance() {
+    let gbs = Gbs::start_in_memory();
+    gbs.seed_documents(
+        "notes",
+        vec![
+            ("a".to_string(), json!({ "text": "buy milk" })),
+            ("b".to_string(), json!({ "text": "call home" })),
+        ],
+    )
+    .await
+    .unwrap();
+    let note = gbs.storage().get_document("notes", "b").await.unwrap();
+    assert_eq!(note["_source"]["text"], "call home");
+
+    let response = gbs
+        .client()
+        .search::<Value>(
+            "notes",
+            &SearchRequest::new().query(match_query("text", "milk")),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.hits.hits.len(), 1);
+    assert_eq!(response.hits.hits[0].id, "a");
+}
+
+#[tokio::test]
+async fn test_seed_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let yaml = dir.path().join("books.yaml");
+    std::fs::write(
+        &yaml,
+        "books:\n  aliases: [library]\n  documents:\n    '1': { title: Dune }\n",
+    )
+    .unwrap();
+    let ndjson = dir.path().join("more.ndjson");
+    std::fs::write(
+        &ndjson,
+        "{\"index\":{\"_index\":\"books\",\"_id\":\"2\"}}\n{\"title\":\"Hyperion\"}\n",
+    )
+    .unwrap();
+    let json_file = dir.path().join("authors.json");
+    std::fs::write(
+        &json_file,
+        json!({ "authors": { "documents": { "herbert": { "name": "Frank Herbert" } } } })
+            .to_string(),
+    )
+    .unwrap();
+
+    let gbs = Gbs::start_in_memory();
+    gbs.seed_file(&yaml).await.unwrap();
+    gbs.seed_file(&ndjson).await.unwrap();
+    gbs.seed_file(&json_file).await.unwrap();
+
+    let (_, body) = gbs.request("GET", "/library/_search", None).await.unwrap();
+    assert_eq!(body["hits"]["total"]["value"], 2);
+    let (_, body) = gbs
+        .request("GET", "/authors/_doc/herbert", None)
+        .await
+        .unwrap();
+    assert_eq!(body["_source"]["name"], "Frank Herbert");
+}
+
+#[tokio::test]
+async fn test_seed_fails_on_existing_index() {
+    let gbs = Gbs::start_in_memory();
+    gbs.seed(&books_fixture()).await.unwrap();
+    assert!(gbs.seed(&books_fixture()).await.is_err());
+}
@@ -0,0 +1,141 @@
+//! Tests for embedding the API in an axum application
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use axum::routing::get;
+use axum::Router;
+use axum_test::TestServer;
+use gbs::server::{AppState, GbsService, RouteGroup};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::sync::Arc;
+use tower::Service;
+
+async fn storage_with_docs() -> Arc<Storage> {
+    let storage = Arc::new(Storage::new());
+    storage.create_index("products", None, None).await.unwrap();
+    storage
+        .index_document("products", "1", json!({ "name": "Rust Book" }))
+        .await
+        .unwrap();
+    storage
+}
+
+async fn call(service: &mut GbsService, path: &str) -> StatusCode {
+    std::future::poll_fn(|cx| service.poll_ready(cx))
+        .await
+        .unwrap();
+    let request = Request::get(path).body(Body::empty()).unwrap();
+    service.call(request).await.unwrap().status()
+}
+
+#[derive(Clone)]
+struct HostState {
+    name: &'static str,
+}
+
+async fn host_name(State(state): State<HostState>) -> &'static str {
+    state.name
+}
+
+#[tokio::test]
+async fn test_nested_search_only_router() {
+    let storage = storage_with_docs().await;
+    let search = GbsService::builder(AppState::new(storage.clone(), "6.8.23"))
+        .search_only()
+        .build();
+    let app = Router::new()
+        .route("/name", get(host_name))
+        .nest("/search", search.into_router())
+        .with_state(HostState { name: "host" });
+    let server = TestServer::new(app).unwrap();
+
+    server.get("/name").await.assert_text("host");
+
+    let response = server
+        .post("/search/products/_search")
+        .json(&json!({ "query": { "match": { "name": "rust" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+
+    // Only the search routes are mounted
+    server
+        .get("/search/products/_doc/1")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .delete("/search/products")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    assert!(storage.index_exists("products").await.unwrap());
+
+    // The host shares the storage
+    storage
+        .index_document("products", "2", json!({ "name": "Rust Mug" }))
+        .await
+        .unwrap();
+    let response = server.get("/search/products/_search?q=rust").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 2);
+}
+
+#[tokio::test]
+async fn test_without_admin_route_groups() {
+    let storage = storage_with_docs().await;
+    let service = GbsService::builder(AppState::new(storage, "6.8.23"))
+        .without_admin()
+        .build();
+    let server = TestServer::new(service.into_router::<()>()).unwrap();
+
+    server.get("/products/_doc/1").await.assert_status_ok();
+    server.get("/_cluster/health").await.assert_status_ok();
+    for path in [
+        "/products",
+        "/_security/user",
+        "/_gbs/usage",
+        "/products/_search_profile",
+    ] {
+        server.get(path).await.assert_status(StatusCode::NOT_FOUND);
+    }
+
+    assert!(RouteGroup::Index.is_admin());
+    assert!(!RouteGroup::Bulk.is_admin());
+}
+
+#[tokio::test]
+async fn test_service_without_http() {
+    let storage = storage_with_docs().await;
+    let mut service = GbsService::builder(AppState::new(storage, "6.8.23"))
+        .route_groups([RouteGroup::Document, RouteGroup::Usage])
+        .exclude(RouteGroup::Usage)
+        .include(RouteGroup::Cluster)
+        .build();
+
+    assert_eq!(call(&mut service, "/products/_doc/1").await, StatusCode::OK);
+    assert_eq!(call(&mut service, "/_cluster/health").await, StatusCode::OK);
+    assert_eq!(
+        call(&mut service, "/_gbs/usage").await,
+        StatusCode::NOT_FOUND
+    );
+}
+
+#[tokio::test]
+async fn test_nested_usage_accounting() {
+    let storage = storage_with_docs().await;
+    let app: Router = Router::new().nest(
+        "/gbs",
+        GbsService::new(AppState::new(storage, "6.8.23")).into_router(),
+    );
+    let server = TestServer::new(app).unwrap();
+
+    server
+        .post("/gbs/products/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+    let usage: serde_json::Value = server.get("/gbs/_gbs/usage").await.json();
+    assert_eq!(usage["totals"]["indices"]["products"]["searches"], 1);
+}
@@ -251,3 +251,110 @@ fn test_task_join_error_status_code() {
 
     // TaskJoin would also map to INTERNAL_SERVER_ERROR (verified in code review)
 }
+
+#[test]
+fn test_error_envelope() {
+    let body = GbsError::IndexNotFound("books".to_string()).to_json();
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["error"]["type"], "index_not_found_exception");
+    assert_eq!(body["error"]["reason"], "no such index [books]");
+    assert_eq!(body["error"]["index"], "books");
+    assert_eq!(body["error"]["resource.type"], "index_or_alias");
+    assert_eq!(
+        body["error"]["root_cause"],
+        serde_json::json!([{
+            "type": "index_not_found_exception",
+            "reason": "no such index [books]",
+            "resource.type": "index_or_alias",
+            "resource.id": "books",
+            "index_uuid": "_na_",
+            "index": "books"
+        }])
+    );
+
+    let error = GbsError::VersionConflict {
+        id: "1".to_string(),
+        reason: "document already exists".to_string(),
+    };
+    assert_eq!(error.status_code(), StatusCode::CONFLICT);
+    let body = error.to_json();
+    assert_eq!(body["error"]["type"], "version_conflict_engine_exception");
+    assert_eq!(
+        body["error"]["reason"],
+        "[_doc][1]: version conflict, document already exists"
+    );
+
+    // Reasons are the messages themselves, without the prefix of the variant
+    let body = GbsError::InvalidRequest("[size] must be positive".to_string()).to_json();
+    assert_eq!(body["error"]["type"], "illegal_argument_exception");
+    assert_eq!(body["error"]["reason"], "[size] must be positive");
+    assert_eq!(body["status"], 400);
+}
+
+#[tokio::test]
+async fn test_error_responses_over_http() {
+    use axum_test::TestServer;
+    use gbs::server::{create_router, AppState};
+    use gbs::storage::Storage;
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    let state = AppState::new(Arc::new(Storage::new()), "7.17.0");
+    let server = TestServer::new(create_router(state)).unwrap();
+
+    let response = server.get("/missing/_search").await;
+    response.assert_status_not_found();
+    let body: Value = response.json();
+    assert_eq!(body["status"], 404);
+    assert_eq!(body["error"]["type"], "index_not_found_exception");
+    assert_eq!(
+        body["error"]["root_cause"][0]["type"],
+        "index_not_found_exception"
+    );
+
+    server.put("/books").await.assert_status_ok();
+    let response = server.put("/books").await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "resource_already_exists_exception");
+    assert_eq!(body["error"]["index"], "books");
+
+    // Bulk items carry the status of their error
+    let body: Value = server
+        .post("/_bulk")
+        .bytes(
+            concat!(
+                "{\"create\":{\"_index\":\"books\",\"_id\":\"1\"}}\n{\"title\":\"dune\"}\n",
+                "{\"create\":{\"_index\":\"books\",\"_id\":\"1\"}}\n{\"title\":\"dune\"}\n",
+            )
+            .into(),
+        )
+        .content_type("application/x-ndjson")
+        .await
+        .json();
+    let item = &body["items"][1]["create"];
+    assert_eq!(item["status"], 409);
+    assert_eq!(item["error"]["type"], "version_conflict_engine_exception");
+
+    // Rejected request bodies get the same envelope
+    let response = server
+        .post("/books/_search")
+        .bytes("{\"query\":".into())
+        .content_type("application/json")
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: Value = response.json();
+    assert_eq!(body["status"], 400);
+    assert_eq!(body["error"]["type"], "parse_exception");
+    assert!(body["error"]["reason"].as_str().unwrap().contains("JSON"));
+    assert_eq!(body["error"]["root_cause"][0]["type"], "parse_exception");
+
+    let response = server
+        .post("/books/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .content_type("text/plain")
+        .await;
+    response.assert_status(StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "media_type_header_exception");
+}
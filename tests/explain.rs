@@ -0,0 +1,243 @@
+//! Tests for scoring explanations (`_explain` and `"explain": true`)
+
+mod common;
+
+use common::test_server;
+use gbs::storage::{Explanation, Storage};
+use serde_json::{json, Value};
+
+async fn books() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "title": "Rust in Action", "genre": "systems", "year": 2021 }),
+        ),
+        (
+            "2",
+            json!({ "title": "Programming Rust", "genre": "programming", "year": 2017 }),
+        ),
+        (
+            "3",
+            json!({
+                "title": "Dune",
+                "genre": "novel",
+                "year": 1965,
+                "reviews": [
+                    { "author": "ann", "stars": 5 },
+                    { "author": "bob", "stars": 3 }
+                ]
+            }),
+        ),
+    ] {
+        storage.index_document("books", id, doc).await.unwrap();
+    }
+    storage
+}
+
+/// Scores of the hits of a search, by ID
+async fn search_scores(storage: &Storage, query: &Value) -> Vec<(String, f64)> {
+    let result = storage
+        .search("books", query, None, None, None, None, None)
+        .await
+        .unwrap();
+    result["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| {
+            (
+                hit["_id"].as_str().unwrap().to_string(),
+                hit["_score"].as_f64().unwrap(),
+            )
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn test_explanation_values_equal_search_scores() {
+    let storage = books().await;
+    let queries = [
+        json!({ "match": { "title": "rust" } }),
+        json!({ "term": { "genre": { "value": "programming", "boost": 3 } } }),
+        json!({
+            "bool": {
+                "must": { "match": { "title": "rust" } },
+                "should": [
+                    { "term": { "genre": "systems" } },
+                    { "range": { "year": { "gte": 2020 } } }
+                ],
+                "filter": { "range": { "year": { "gte": 2000 } } },
+                "boost": 2
+            }
+        }),
+        json!({
+            "nested": {
+                "path": "reviews",
+                "score_mode": "sum",
+                "query": { "range": { "reviews.stars": { "gte": 3 } } }
+            }
+        }),
+        json!({ "query_string": { "query": "title:rust OR genre:novel" } }),
+        json!({ "match_all": {} }),
+    ];
+    for query in &queries {
+        let scores = search_scores(&storage, query).await;
+        assert!(!scores.is_empty(), "no hits for {}", query);
+        for (id, score) in scores {
+            let explanation = storage.explain("books", &id, query).await.unwrap();
+            assert!(explanation.is_match());
+            assert_eq!(explanation.value, score, "{} on document {}", query, id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_bool_explanation_lists_clauses() {
+    let storage = books().await;
+    let query = json!({
+        "bool": {
+            "must": { "match": { "title": "rust" } },
+            "should": [
+                { "term": { "genre": "systems" } },
+                { "term": { "genre": "novel" } }
+            ],
+            "filter": { "range": { "year": { "gte": 2000 } } }
+        }
+    });
+
+    let explanation = storage.explain("books", "1", &query).await.unwrap();
+    assert_eq!(explanation.description, "sum of:");
+    let descriptions: Vec<&str> = explanation
+        .details
+        .iter()
+        .map(|detail| detail.description.as_str())
+        .collect();
+    assert_eq!(
+        descriptions,
+        [
+            "match(title:rust)",
+            "match on filter clause, not scored",
+            "term(genre:systems)"
+        ]
+    );
+    let sum: f64 = explanation.details.iter().map(|d| d.value).sum();
+    assert_eq!(explanation.value, sum);
+    assert_eq!(explanation.details[1].value, 0.0);
+
+    // A rejected document is explained by the clause that rejected it
+    let explanation = storage.explain("books", "3", &query).await.unwrap();
+    assert!(!explanation.is_match());
+    assert_eq!(explanation.description, "no match on required clause");
+    assert_eq!(
+        explanation.details[0].description,
+        "no match on match(title:rust)"
+    );
+
+    let excluded = json!({ "bool": { "must_not": { "term": { "genre": "programming" } } } });
+    let explanation = storage.explain("books", "2", &excluded).await.unwrap();
+    assert_eq!(explanation.description, "match on prohibited clause");
+}
+
+#[tokio::test]
+async fn test_nested_and_boost_explanations() {
+    let storage = books().await;
+    let query = json!({
+        "nested": {
+            "path": "reviews",
+            "score_mode": "max",
+            "query": { "term": { "reviews.author": { "value": "bob", "boost": 2 } } }
+        }
+    });
+    let explanation = storage.explain("books", "3", &query).await.unwrap();
+    assert_eq!(explanation.description, "score mode [max] of:");
+    assert_eq!(explanation.details.len(), 1);
+    assert_eq!(explanation.details[0].description, "object 1 of [reviews]");
+    let boosted = &explanation.details[0].details[0];
+    assert_eq!(boosted.description, "product of:");
+    assert_eq!(boosted.details[1].description, "boost");
+    assert_eq!(boosted.details[1].value, 2.0);
+
+    // Explanations round-trip through JSON
+    let json = serde_json::to_value(&explanation).unwrap();
+    let parsed: Explanation = serde_json::from_value(json).unwrap();
+    assert_eq!(parsed, explanation);
+}
+
+#[tokio::test]
+async fn test_explain_errors() {
+    let storage = books().await;
+    let query = json!({ "match_all": {} });
+    assert!(storage.explain("missing", "1", &query).await.is_err());
+    assert!(storage.explain("books", "9", &query).await.is_err());
+}
+
+#[tokio::test]
+async fn test_explain_api() {
+    let storage = books().await;
+    storage.put_alias("books", "library").await.unwrap();
+    let server = test_server(storage, "6.8.23");
+
+    let response = server
+        .post("/library/_explain/2")
+        .json(&json!({ "query": { "match": { "title": "rust" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: Value = response.json();
+    assert_eq!(body["_index"], "books");
+    assert_eq!(body["_id"], "2");
+    assert_eq!(body["matched"], true);
+    assert_eq!(body["explanation"]["description"], "match(title:rust)");
+    assert!(body["explanation"]["value"].as_f64().unwrap() > 0.0);
+
+    let body: Value = server.get("/books/_explain/3?q=title:rust").await.json();
+    assert_eq!(body["matched"], false);
+    assert_eq!(body["explanation"]["value"], 0.0);
+
+    server
+        .get("/books/_explain/3")
+        .expect_failure()
+        .await
+        .assert_status_bad_request();
+    server
+        .get("/books/_explain/9?q=dune")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_search_with_explain() {
+    let storage = books().await;
+    let server = test_server(storage, "6.8.23");
+
+    let body: Value = server
+        .post("/books/_search")
+        .json(&json!({
+            "explain": true,
+            "query": {
+                "bool": {
+                    "should": [
+                        { "match": { "title": "rust" } },
+                        { "term": { "genre": "systems" } }
+                    ]
+                }
+            }
+        }))
+        .await
+        .json();
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    for hit in hits {
+        assert_eq!(hit["_explanation"]["value"], hit["_score"]);
+        assert_eq!(hit["_explanation"]["description"], "sum of:");
+    }
+
+    let body: Value = server.get("/_search?q=dune&explain=true").await.json();
+    assert_eq!(body["hits"]["hits"][0]["_id"], "3");
+    assert!(body["hits"]["hits"][0]["_explanation"].is_object());
+
+    let body: Value = server.get("/books/_search?q=dune").await.json();
+    assert!(body["hits"]["hits"][0].get("_explanation").is_none());
+}
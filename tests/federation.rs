@@ -0,0 +1,232 @@
+//! Tests for read-through federation of indices to external sources
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use gbs::config::{FederatedIndexConfig, FederationConfig};
+use gbs::storage::{Federation, Storage};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// External source serving a changeable product list
+#[derive(Clone, Default)]
+struct Upstream {
+    products: Arc<Mutex<Value>>,
+    fetches: Arc<AtomicUsize>,
+    last_body: Arc<Mutex<String>>,
+}
+
+async fn products(State(upstream): State<Upstream>) -> (StatusCode, Json<Value>) {
+    upstream.fetches.fetch_add(1, Ordering::SeqCst);
+    let products = upstream.products.lock().unwrap().clone();
+    if products.is_null() {
+        return (StatusCode::SERVICE_UNAVAILABLE, Json(json!({})));
+    }
+    (StatusCode::OK, Json(json!({ "items": products })))
+}
+
+async fn sql(State(upstream): State<Upstream>, body: String) -> Json<Value> {
+    *upstream.last_body.lock().unwrap() = body;
+    Json(json!({ "data": [{ "sku": 7, "name": "SQL Row" }] }))
+}
+
+async fn spawn_upstream(upstream: Upstream) -> String {
+    let app = Router::new()
+        .route("/products", get(products))
+        .route("/sql", post(sql))
+        .with_state(upstream);
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", address)
+}
+
+fn federated_index(index: &str, url: String, ttl_secs: u64) -> FederatedIndexConfig {
+    serde_yaml::from_value(
+        serde_yaml::to_value(json!({
+            "index": index,
+            "url": url,
+            "documents_path": "/items",
+            "ttl_secs": ttl_secs
+        }))
+        .unwrap(),
+    )
+    .unwrap()
+}
+
+async fn storage_with(indices: Vec<FederatedIndexConfig>) -> Storage {
+    let federation = Federation::from_config(&FederationConfig { indices }).unwrap();
+    let storage = Storage::new().with_federation(federation);
+    storage.create_federated_indices().await.unwrap();
+    storage
+}
+
+async fn search_names(storage: &Storage, index: &str) -> Vec<String> {
+    let result = storage
+        .search(
+            index,
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    let mut names: Vec<String> = result["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| hit["_source"]["name"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[tokio::test]
+async fn test_federated_index_read_through() {
+    let upstream = Upstream::default();
+    *upstream.products.lock().unwrap() = json!([
+        { "id": "a", "name": "Rust Book" },
+        { "id": "b", "name": "Rust Mug" },
+        { "name": "No ID" }
+    ]);
+    let url = spawn_upstream(upstream.clone()).await;
+    let storage = storage_with(vec![federated_index(
+        "products",
+        format!("{}/products", url),
+        3600,
+    )])
+    .await;
+
+    // The local index exists before the first fetch
+    assert!(storage.index_exists("products").await.unwrap());
+    assert_eq!(upstream.fetches.load(Ordering::SeqCst), 0);
+
+    assert_eq!(
+        search_names(&storage, "products").await,
+        vec!["Rust Book", "Rust Mug"]
+    );
+    let result = storage
+        .search(
+            "products",
+            &json!({ "match": { "name": "mug" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["total"]["value"], 1);
+    let doc = storage.get_document("products", "a").await.unwrap();
+    assert_eq!(doc["_source"]["name"], "Rust Book");
+
+    // Cached documents are served until the TTL expires
+    assert_eq!(upstream.fetches.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_federated_index_refetch_and_stale_cache() {
+    let upstream = Upstream::default();
+    *upstream.products.lock().unwrap() = json!([
+        { "id": 1, "name": "Rust Book" },
+        { "id": 2, "name": "Rust Mug" }
+    ]);
+    let url = spawn_upstream(upstream.clone()).await;
+    let storage = storage_with(vec![federated_index(
+        "products",
+        format!("{}/products", url),
+        0,
+    )])
+    .await;
+
+    assert_eq!(
+        search_names(&storage, "products").await,
+        vec!["Rust Book", "Rust Mug"]
+    );
+
+    // Expired caches are replaced, dropping removed documents
+    *upstream.products.lock().unwrap() = json!([
+        { "id": 2, "name": "Rust Mug" },
+        { "id": 3, "name": "Go Book" }
+    ]);
+    assert_eq!(
+        search_names(&storage, "products").await,
+        vec!["Go Book", "Rust Mug"]
+    );
+    assert!(storage.get_document("products", "1").await.is_err());
+
+    // A failing source leaves the cached documents in place
+    *upstream.products.lock().unwrap() = Value::Null;
+    assert_eq!(
+        search_names(&storage, "products").await,
+        vec!["Go Book", "Rust Mug"]
+    );
+    assert_eq!(upstream.fetches.load(Ordering::SeqCst), 4);
+}
+
+#[tokio::test]
+async fn test_federated_index_source_errors() {
+    let upstream = Upstream::default();
+    let url = spawn_upstream(upstream.clone()).await;
+    let storage = storage_with(vec![federated_index(
+        "products",
+        format!("{}/products", url),
+        0,
+    )])
+    .await;
+
+    // Without cached documents a failing source is an error
+    let result = storage
+        .search(
+            "products",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(gbs::GbsError::Upstream(_))));
+
+    for url in ["https://example.com/items", "/relative", "not a url"] {
+        let config = FederationConfig {
+            indices: vec![federated_index("products", url.to_string(), 60)],
+        };
+        assert!(Federation::from_config(&config).is_err(), "{}", url);
+    }
+}
+
+#[tokio::test]
+async fn test_federated_index_sql_over_http() {
+    let upstream = Upstream::default();
+    let url = spawn_upstream(upstream.clone()).await;
+    let config: FederatedIndexConfig = serde_yaml::from_str(&format!(
+        r#"
+index: rows
+url: "{}/sql"
+method: post
+body: "SELECT sku, name FROM products"
+headers:
+  content-type: text/plain
+documents_path: /data
+id_field: sku
+"#,
+        url
+    ))
+    .unwrap();
+    let storage = storage_with(vec![config]).await;
+
+    let doc = storage.get_document("rows", "7").await.unwrap();
+    assert_eq!(doc["_source"]["name"], "SQL Row");
+    assert_eq!(
+        *upstream.last_body.lock().unwrap(),
+        "SELECT sku, name FROM products"
+    );
+}
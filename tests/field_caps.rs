@@ -0,0 +1,198 @@
+//! Tests for the field capabilities API (`_field_caps`)
+
+mod common;
+
+use common::test_server;
+use gbs::storage::{FieldCapability, Storage};
+use serde_json::{json, Value};
+
+fn capability(field_type: &str, searchable: bool, aggregatable: bool) -> FieldCapability {
+    FieldCapability {
+        field_type: field_type.to_string(),
+        searchable,
+        aggregatable,
+    }
+}
+
+async fn storage() -> Storage {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs-1",
+            None,
+            Some(json!({
+                "properties": {
+                    "message": {
+                        "type": "text",
+                        "fields": { "raw": { "type": "keyword" } }
+                    },
+                    "status": { "type": "keyword", "index": false },
+                    "user": { "properties": { "name": { "type": "keyword" } } }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "logs-1",
+            "1",
+            json!({
+                "message": "started",
+                "status": "ok",
+                "user": { "name": "ann", "age": 31 },
+                "@timestamp": "2024-05-01T10:00:00Z"
+            }),
+        )
+        .await
+        .unwrap();
+
+    // No mappings: every field is dynamic
+    storage.create_index("logs-2", None, None).await.unwrap();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "message": "stopped", "status": 500, "duration": 3 }),
+        ),
+        (
+            "2",
+            json!({ "message": "stopped", "status": 200, "duration": 2.5, "tags": ["a", "b"] }),
+        ),
+    ] {
+        storage.index_document("logs-2", id, doc).await.unwrap();
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_index_fields_combine_mappings_and_documents() {
+    let storage = storage().await;
+
+    let fields = storage.index_fields("logs-1").await.unwrap();
+    assert_eq!(fields["message"], capability("text", true, false));
+    assert_eq!(fields["message.raw"], capability("keyword", true, true));
+    assert_eq!(fields["status"], capability("keyword", false, true));
+    assert_eq!(fields["user"], capability("object", false, false));
+    assert_eq!(fields["user.name"], capability("keyword", true, true));
+    // Dynamic fields next to mapped ones
+    assert_eq!(fields["user.age"], capability("long", true, true));
+    assert_eq!(fields["@timestamp"], capability("date", true, true));
+    // Mapped strings get no dynamic keyword sub-field
+    assert!(!fields.contains_key("message.keyword"));
+    assert_eq!(fields["_id"].field_type, "_id");
+    assert_eq!(fields["_source"], capability("_source", false, false));
+
+    let fields = storage.index_fields("logs-2").await.unwrap();
+    assert_eq!(fields["message"], capability("text", true, false));
+    assert_eq!(fields["message.keyword"], capability("keyword", true, true));
+    assert_eq!(fields["status"], capability("long", true, true));
+    // The first document maps the field; later decimals keep it a long
+    assert_eq!(fields["duration"], capability("long", true, true));
+    assert_eq!(fields["tags"], capability("text", true, false));
+
+    assert!(storage.index_fields("missing").await.is_err());
+}
+
+#[tokio::test]
+async fn test_field_caps_across_indices() {
+    let storage = storage().await;
+    let response = storage
+        .field_caps(
+            &["logs-1".to_string(), "logs-2".to_string()],
+            &["message*".to_string(), "status".to_string()],
+            false,
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response["indices"], json!(["logs-1", "logs-2"]));
+    let fields = response["fields"].as_object().unwrap();
+    let names: Vec<&String> = fields.keys().collect();
+    assert_eq!(
+        names,
+        ["message", "message.keyword", "message.raw", "status"]
+    );
+
+    // Same type everywhere: one entry without an index list
+    assert_eq!(
+        response["fields"]["message"],
+        json!({ "text": { "type": "text", "searchable": true, "aggregatable": false } })
+    );
+    // Conflicting types: an entry per type with its indices
+    assert_eq!(
+        response["fields"]["status"],
+        json!({
+            "keyword": {
+                "type": "keyword",
+                "searchable": false,
+                "aggregatable": true,
+                "indices": ["logs-1"]
+            },
+            "long": {
+                "type": "long",
+                "searchable": true,
+                "aggregatable": true,
+                "indices": ["logs-2"]
+            }
+        })
+    );
+
+    let response = storage
+        .field_caps(
+            &["logs-1".to_string(), "logs-2".to_string()],
+            &["message.raw".to_string()],
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        response["fields"]["message.raw"]["unmapped"],
+        json!({
+            "type": "unmapped",
+            "searchable": false,
+            "aggregatable": false,
+            "indices": ["logs-2"]
+        })
+    );
+    assert_eq!(
+        response["fields"]["message.raw"]["keyword"]["indices"],
+        json!(["logs-1"])
+    );
+}
+
+#[tokio::test]
+async fn test_field_caps_api() {
+    let storage = storage().await;
+    storage.put_alias("logs-2", "current").await.unwrap();
+    let server = test_server(storage, "6.8.23");
+
+    let body: Value = server
+        .get("/logs-*/_field_caps?fields=user.*,duration")
+        .await
+        .json();
+    assert_eq!(body["indices"], json!(["logs-1", "logs-2"]));
+    let names: Vec<&String> = body["fields"].as_object().unwrap().keys().collect();
+    assert_eq!(names, ["duration", "user.age", "user.name"]);
+
+    let body: Value = server
+        .post("/current/_field_caps")
+        .json(&json!({ "fields": ["status"] }))
+        .await
+        .json();
+    assert_eq!(body["indices"], json!(["logs-2"]));
+    assert_eq!(body["fields"]["status"]["long"]["aggregatable"], true);
+
+    // Without `fields`, all fields of all indices
+    let body: Value = server.get("/_field_caps").await.json();
+    assert_eq!(body["indices"], json!(["logs-1", "logs-2"]));
+    assert!(body["fields"]["_index"]["_index"]["searchable"]
+        .as_bool()
+        .unwrap());
+    assert!(body["fields"].get("@timestamp").is_some());
+
+    server
+        .get("/missing/_field_caps?fields=*")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+}
@@ -0,0 +1,123 @@
+//! Tests for the filter cache
+
+mod common;
+
+use axum_test::TestServer;
+use common::{sorted_ids, test_server};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn server() -> TestServer {
+    let storage = Storage::new();
+    storage.create_index("orders", None, None).await.unwrap();
+    for (id, status, amount) in [
+        ("1", "paid", 10),
+        ("2", "paid", 50),
+        ("3", "open", 70),
+        ("4", "paid", 90),
+    ] {
+        storage
+            .index_document(
+                "orders",
+                id,
+                json!({ "status": status, "amount": amount, "note": "rush" }),
+            )
+            .await
+            .unwrap();
+    }
+    test_server(storage, "7.10.2")
+}
+
+/// IDs of the hits of a filtered search, sorted
+async fn filtered_ids(server: &TestServer, query: Value) -> Vec<String> {
+    let body: Value = server
+        .post("/orders/_search")
+        .json(&json!({ "query": query, "size": 100 }))
+        .await
+        .json();
+    sorted_ids(&body)
+}
+
+async fn query_cache(server: &TestServer) -> Value {
+    let body: Value = server.get("/orders/_stats/query_cache").await.json();
+    body["indices"]["orders"]["total"]["query_cache"].clone()
+}
+
+fn paid_over_20() -> Value {
+    json!({
+        "bool": {
+            "filter": [
+                { "term": { "status": "paid" } },
+                { "range": { "amount": { "gt": 20 } } }
+            ]
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_filter_cache_follows_document_changes() {
+    let server = server().await;
+
+    assert_eq!(filtered_ids(&server, paid_over_20()).await, ["2", "4"]);
+    let stats = query_cache(&server).await;
+    assert_eq!(stats["miss_count"], 2);
+    assert_eq!(stats["cache_size"], 2);
+
+    assert_eq!(filtered_ids(&server, paid_over_20()).await, ["2", "4"]);
+    assert_eq!(query_cache(&server).await["hit_count"], 2);
+
+    // Appended, replaced and deleted documents are reflected without
+    // evaluating the clauses on every document again
+    server
+        .put("/orders/_doc/5")
+        .json(&json!({ "status": "paid", "amount": 30 }))
+        .await
+        .assert_status_success();
+    server
+        .put("/orders/_doc/2")
+        .json(&json!({ "status": "refunded", "amount": 50 }))
+        .await
+        .assert_status_success();
+    server
+        .put("/orders/_doc/3")
+        .json(&json!({ "status": "paid", "amount": 70 }))
+        .await
+        .assert_status_success();
+    server
+        .delete("/orders/_doc/4")
+        .await
+        .assert_status_success();
+    assert_eq!(filtered_ids(&server, paid_over_20()).await, ["3", "5"]);
+    let stats = query_cache(&server).await;
+    assert_eq!(stats["miss_count"], 2);
+    assert_eq!(stats["hit_count"], 4);
+}
+
+#[tokio::test]
+async fn test_filter_cache_keeps_query_semantics() {
+    let server = server().await;
+
+    // Filters alone still make should clauses optional
+    let query = json!({
+        "bool": {
+            "filter": { "term": { "status": "paid" } },
+            "should": [{ "match": { "note": "urgent" } }]
+        }
+    });
+    assert_eq!(filtered_ids(&server, query.clone()).await, ["1", "2", "4"]);
+    assert_eq!(filtered_ids(&server, query).await, ["1", "2", "4"]);
+
+    // Uncacheable clauses are evaluated with the rest of the query
+    let query = json!({
+        "bool": {
+            "must": { "match": { "note": "rush" } },
+            "must_not": { "term": { "amount": 90 } },
+            "filter": [
+                { "terms": { "status": ["paid", "open"] } },
+                { "range": { "amount": { "gte": "now-1d" } } }
+            ]
+        }
+    });
+    filtered_ids(&server, query).await;
+    assert_eq!(query_cache(&server).await["cache_size"], 2);
+}
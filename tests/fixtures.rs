@@ -0,0 +1,126 @@
+//! Tests for seeding indices from fixture directories and files
+
+use gbs::fixtures::{seed_dir, seed_file};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::path::Path;
+
+fn write(dir: &Path, name: &str, content: &str) {
+    std::fs::write(dir.join(name), content).unwrap();
+}
+
+fn fixture_dir() -> tempfile::TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "books.json",
+        &json!({
+            "settings": { "number_of_shards": 1 },
+            "mappings": { "properties": { "title": { "type": "text" } } },
+            "aliases": { "library": {} }
+        })
+        .to_string(),
+    );
+    write(
+        dir.path(),
+        "books.ndjson",
+        "{\"index\":{\"_id\":\"1\"}}\n{\"title\":\"Dune\"}\n{\"index\":{\"_id\":\"2\"}}\n{\"title\":\"Hyperion\"}\n",
+    );
+    write(
+        dir.path(),
+        "authors.yaml",
+        "aliases: [people]\ndocuments:\n  herbert: { name: Frank Herbert }\n",
+    );
+    write(
+        dir.path(),
+        "logs.ndjson",
+        "{\"create\":{}}\n{\"message\":\"started\"}\n",
+    );
+    write(dir.path(), "README.md", "Fixtures of the tests");
+    dir
+}
+
+#[tokio::test]
+async fn test_seed_dir() {
+    let dir = fixture_dir();
+    let storage = Storage::new();
+    let report = seed_dir(&storage, dir.path()).await.unwrap();
+
+    assert_eq!(report.created, vec!["authors", "books", "logs"]);
+    assert!(report.skipped.is_empty());
+    assert_eq!(report.documents, 4);
+
+    let book = storage.get_document("books", "2").await.unwrap();
+    assert_eq!(book["_source"]["title"], "Hyperion");
+    let author = storage.get_document("authors", "herbert").await.unwrap();
+    assert_eq!(author["_source"]["name"], "Frank Herbert");
+    let books = storage.get_index("books").await.unwrap();
+    assert_eq!(
+        books["books"]["mappings"]["properties"]["title"]["type"],
+        "text"
+    );
+    assert!(books["books"]["aliases"].get("library").is_some());
+    let authors = storage.get_index("authors").await.unwrap();
+    assert!(authors["authors"]["aliases"].get("people").is_some());
+    assert!(storage.index_exists("logs").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_seed_dir_skips_existing_indices() {
+    let dir = fixture_dir();
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+
+    let report = seed_dir(&storage, dir.path()).await.unwrap();
+    assert_eq!(report.created, vec!["authors", "logs"]);
+    assert_eq!(report.skipped, vec!["books"]);
+    assert_eq!(report.documents, 2);
+    assert!(storage.get_document("books", "1").await.is_err());
+
+    // Seeding again changes nothing
+    let report = seed_dir(&storage, dir.path()).await.unwrap();
+    assert!(report.created.is_empty());
+    assert_eq!(report.documents, 0);
+}
+
+#[tokio::test]
+async fn test_seed_dir_errors() {
+    let storage = Storage::new();
+    assert!(seed_dir(&storage, "/nonexistent/fixtures").await.is_err());
+
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "books.json", "{}");
+    write(dir.path(), "books.yaml", "{}");
+    let error = seed_dir(&storage, dir.path()).await.unwrap_err();
+    assert!(error.to_string().contains("both define"), "{}", error);
+
+    let dir = tempfile::tempdir().unwrap();
+    write(dir.path(), "books.ndjson", "{\"index\":{}}\nnot json\n");
+    let error = seed_dir(&storage, dir.path()).await.unwrap_err();
+    assert!(error.to_string().contains("books.ndjson"), "{}", error);
+}
+
+#[tokio::test]
+async fn test_seed_file() {
+    let dir = tempfile::tempdir().unwrap();
+    write(
+        dir.path(),
+        "fixture.json",
+        &json!({
+            "books": { "documents": { "1": { "title": "Dune" } } },
+            "authors": { "documents": { "herbert": { "name": "Frank Herbert" } } }
+        })
+        .to_string(),
+    );
+    let storage = Storage::new();
+    let report = seed_file(&storage, dir.path().join("fixture.json"))
+        .await
+        .unwrap();
+    assert_eq!(report.created, vec!["authors", "books"]);
+    assert_eq!(report.documents, 2);
+
+    // Indices of a fixture file must not exist yet
+    assert!(seed_file(&storage, dir.path().join("fixture.json"))
+        .await
+        .is_err());
+}
@@ -0,0 +1,161 @@
+//! Tests for the function_score query
+
+use gbs::error::GbsError;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn books() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "title": "Rust in Action", "likes": 9, "featured": true }),
+        ),
+        ("2", json!({ "title": "Programming Rust", "likes": 99 })),
+        ("3", json!({ "title": "Dune", "likes": 0 })),
+    ] {
+        storage.index_document("books", id, doc).await.unwrap();
+    }
+    storage
+}
+
+async fn search(storage: &Storage, query: &Value) -> Result<Vec<(String, f64)>, GbsError> {
+    let result = storage
+        .search("books", query, None, None, None, None, None)
+        .await?;
+    Ok(result["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| {
+            (
+                hit["_id"].as_str().unwrap().to_string(),
+                hit["_score"].as_f64().unwrap(),
+            )
+        })
+        .collect())
+}
+
+fn score_of(hits: &[(String, f64)], id: &str) -> f64 {
+    hits.iter().find(|(hit, _)| hit == id).unwrap().1
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-6,
+        "{} != {}",
+        actual,
+        expected
+    );
+}
+
+#[tokio::test]
+async fn test_field_value_factor_reorders_matches() {
+    let storage = books().await;
+    let base = search(&storage, &json!({ "match": { "title": "rust" } }))
+        .await
+        .unwrap();
+
+    let hits = search(
+        &storage,
+        &json!({ "function_score": {
+            "query": { "match": { "title": "rust" } },
+            "field_value_factor": { "field": "likes", "modifier": "log1p", "factor": 1 }
+        } }),
+    )
+    .await
+    .unwrap();
+    // Dune does not match the query
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].0, "2");
+    assert_close(score_of(&hits, "2"), score_of(&base, "2") * 100f64.log10());
+    assert_close(score_of(&hits, "1"), score_of(&base, "1") * 10f64.log10());
+}
+
+#[tokio::test]
+async fn test_boost_mode_and_score_mode() {
+    let storage = books().await;
+    let query = |score_mode: &str, boost_mode: &str| {
+        json!({ "function_score": {
+            "functions": [
+                { "filter": { "term": { "featured": true } }, "weight": 3 },
+                { "field_value_factor": { "field": "likes", "missing": 1 }, "weight": 2 }
+            ],
+            "score_mode": score_mode,
+            "boost_mode": boost_mode
+        } })
+    };
+
+    // Without a query every document matches with a score of 1
+    let hits = search(&storage, &query("multiply", "replace"))
+        .await
+        .unwrap();
+    assert_close(score_of(&hits, "1"), 3.0 * 18.0);
+    assert_close(score_of(&hits, "2"), 198.0);
+    let hits = search(&storage, &query("sum", "sum")).await.unwrap();
+    assert_close(score_of(&hits, "1"), 1.0 + 3.0 + 18.0);
+    let hits = search(&storage, &query("first", "multiply")).await.unwrap();
+    assert_close(score_of(&hits, "1"), 3.0);
+    let hits = search(&storage, &query("max", "avg")).await.unwrap();
+    assert_close(score_of(&hits, "2"), (1.0 + 198.0) / 2.0);
+    let hits = search(&storage, &query("min", "min")).await.unwrap();
+    assert_close(score_of(&hits, "2"), 1.0);
+    // A score of 0 from the functions still matches
+    assert_eq!(hits.len(), 3);
+}
+
+#[tokio::test]
+async fn test_max_boost_min_score_and_boost() {
+    let storage = books().await;
+    let hits = search(
+        &storage,
+        &json!({ "function_score": {
+            "field_value_factor": { "field": "likes" },
+            "max_boost": 50,
+            "min_score": 5,
+            "boost": 2
+        } }),
+    )
+    .await
+    .unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_close(score_of(&hits, "2"), 100.0);
+    assert_close(score_of(&hits, "1"), 18.0);
+}
+
+#[tokio::test]
+async fn test_random_score_is_reproducible() {
+    let storage = books().await;
+    let query = |seed: u64| {
+        json!({ "function_score": {
+            "random_score": { "seed": seed },
+            "boost_mode": "replace"
+        } })
+    };
+    let first = search(&storage, &query(7)).await.unwrap();
+    assert_eq!(first.len(), 3);
+    assert!(first.iter().all(|(_, score)| *score > 0.0 && *score < 1.0));
+    assert_eq!(search(&storage, &query(7)).await.unwrap(), first);
+    assert_ne!(search(&storage, &query(8)).await.unwrap(), first);
+}
+
+#[tokio::test]
+async fn test_malformed_function_score_queries() {
+    let storage = books().await;
+    for query in [
+        json!({ "function_score": { "field_value_factor": { "field": "title" } } }),
+        json!({ "function_score": { "weight": 2, "boost_mode": "sometimes" } }),
+        json!({ "function_score": { "functions": [{ "filter": { "match_all": {} } }] } }),
+        json!({ "function_score": { "field_value_factor": { "field": "likes", "modifier": "cube" } } }),
+    ] {
+        assert!(
+            matches!(
+                search(&storage, &query).await,
+                Err(GbsError::InvalidRequest(_))
+            ),
+            "{}",
+            query
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Tests for search highlighting fragments and tags
+
+use gbs::storage::Storage;
+use serde_json::json;
+
+const BODY: &str = "Gummy bears are small gelatin candies. \
+    They come in many colors and flavors. \
+    The first gummy candies were made in Germany in the twenties. \
+    Today gummy bears are sold all over the world.";
+
+async fn highlight(query: serde_json::Value, config: serde_json::Value) -> serde_json::Value {
+    let storage = Storage::new();
+    storage.create_index("candies", None, None).await.unwrap();
+    storage
+        .index_document(
+            "candies",
+            "1",
+            json!({ "title": "Gummy Bears", "body": BODY, "tags": ["gummy", "sweet"] }),
+        )
+        .await
+        .unwrap();
+    let result = storage
+        .search("candies", &query, None, None, None, None, Some(&config))
+        .await
+        .unwrap();
+    result["hits"]["hits"][0]["highlight"].clone()
+}
+
+fn fragments(highlight: &serde_json::Value, field: &str) -> Vec<String> {
+    highlight[field]
+        .as_array()
+        .unwrap_or_else(|| panic!("no highlight for {}: {}", field, highlight))
+        .iter()
+        .map(|fragment| fragment.as_str().unwrap().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_fragments_are_trimmed_around_matches() {
+    let highlight = highlight(
+        json!({ "match": { "body": "gummy" } }),
+        json!({ "fields": { "body": { "fragment_size": 40 } } }),
+    )
+    .await;
+
+    let fragments = fragments(&highlight, "body");
+    assert_eq!(fragments.len(), 3, "fragments: {:?}", fragments);
+    for fragment in &fragments {
+        assert!(fragment.contains("<em>"), "fragment: {}", fragment);
+        let text = fragment.replace("<em>", "").replace("</em>", "");
+        assert!(text.chars().count() <= 40, "fragment: {}", fragment);
+        assert_eq!(text.trim(), text);
+    }
+    assert!(fragments[0].starts_with("<em>Gummy</em> bears"));
+    assert!(fragments[2].contains("Today <em>gummy</em> bears"));
+}
+
+#[tokio::test]
+async fn test_number_of_fragments_limits_and_zero_returns_whole_field() {
+    let limited = highlight(
+        json!({ "match": { "body": "gummy" } }),
+        json!({ "fields": { "body": { "fragment_size": 40, "number_of_fragments": 2 } } }),
+    )
+    .await;
+    assert_eq!(fragments(&limited, "body").len(), 2);
+
+    let whole = highlight(
+        json!({ "match": { "body": "gummy" } }),
+        json!({ "number_of_fragments": 0, "fields": { "body": {} } }),
+    )
+    .await;
+    let whole = fragments(&whole, "body");
+    assert_eq!(whole.len(), 1);
+    assert_eq!(whole[0].matches("<em>").count(), 3);
+    assert_eq!(whole[0].replace("<em>", "").replace("</em>", ""), BODY);
+}
+
+#[tokio::test]
+async fn test_custom_tags_per_term_and_per_field() {
+    let highlight = highlight(
+        json!({ "match": { "title": "gummy bears" } }),
+        json!({
+            "pre_tags": ["<b>", "<i>"],
+            "post_tags": ["</b>", "</i>"],
+            "fields": {
+                "title": {},
+                "tags": { "pre_tags": "[", "post_tags": "]" }
+            }
+        }),
+    )
+    .await;
+
+    assert_eq!(
+        fragments(&highlight, "title"),
+        vec!["<b>Gummy</b> <i>Bears</i>".to_string()]
+    );
+    assert_eq!(fragments(&highlight, "tags"), vec!["[gummy]".to_string()]);
+}
+
+#[tokio::test]
+async fn test_matches_respect_word_boundaries_and_no_match_size() {
+    let highlight = highlight(
+        json!({ "match": { "body": "bear" } }),
+        json!({
+            "fields": {
+                "body": {},
+                "title": { "no_match_size": 5 }
+            }
+        }),
+    )
+    .await;
+
+    // "bears" is a different word than "bear"
+    assert!(highlight.get("body").is_none(), "highlight: {}", highlight);
+    assert_eq!(fragments(&highlight, "title"), vec!["Gummy".to_string()]);
+}
+
+#[tokio::test]
+async fn test_score_order_puts_fragments_with_most_matches_first() {
+    let highlight = highlight(
+        json!({ "match": { "body": "gummy germany" } }),
+        json!({ "fields": { "body": { "fragment_size": 60, "order": "score" } } }),
+    )
+    .await;
+
+    let fragments = fragments(&highlight, "body");
+    assert!(
+        fragments[0].contains("<em>gummy</em> candies were made in <em>Germany</em>"),
+        "fragments: {:?}",
+        fragments
+    );
+}
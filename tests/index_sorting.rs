@@ -0,0 +1,154 @@
+//! Tests for index sorting (`index.sort.*` settings)
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::{ids, open_sled};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+/// Events sorted by descending timestamp
+async fn events() -> TestServer {
+    let server = common::test_server(Storage::new(), "8.11.0");
+    server
+        .put("/events")
+        .json(&json!({
+            "settings": { "index": { "sort.field": "ts", "sort.order": "desc" } }
+        }))
+        .await
+        .assert_status_ok();
+    for (id, doc) in [
+        ("a", json!({ "ts": 3, "kind": "login" })),
+        ("b", json!({ "ts": 1, "kind": "logout" })),
+        ("c", json!({ "kind": "login" })),
+        ("d", json!({ "ts": 4, "kind": "login" })),
+        ("e", json!({ "ts": 3, "kind": "logout" })),
+    ] {
+        server
+            .put(&format!("/events/_doc/{}", id))
+            .json(&doc)
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+    server
+}
+
+async fn search(server: &TestServer, body: Value) -> Value {
+    let response = server.post("/events/_search").json(&body).await;
+    response.assert_status_ok();
+    response.json()
+}
+
+#[tokio::test]
+async fn test_match_all_returns_documents_in_index_order() {
+    let server = events().await;
+    let body = search(&server, json!({ "query": { "match_all": {} } })).await;
+    assert_eq!(body["hits"]["total"]["value"], 5);
+    // Equal values in ID order, documents without a value last
+    assert_eq!(ids(&body), vec!["d", "a", "e", "b", "c"]);
+
+    let body = search(&server, json!({ "from": 1, "size": 2 })).await;
+    assert_eq!(body["hits"]["total"]["value"], 5);
+    assert_eq!(ids(&body), vec!["a", "e"]);
+
+    // Sorted like the index, with the values the hits were sorted by
+    let body = search(&server, json!({ "sort": [{ "ts": "desc" }], "size": 3 })).await;
+    assert_eq!(ids(&body), vec!["d", "a", "e"]);
+    assert_eq!(body["hits"]["hits"][0]["sort"], json!([4.0]));
+
+    // Other sorts and queries rank every matching document
+    let body = search(&server, json!({ "sort": [{ "ts": "asc" }], "size": 2 })).await;
+    assert_eq!(ids(&body), vec!["b", "a"]);
+    let body = search(
+        &server,
+        json!({ "query": { "term": { "kind": "logout" } } }),
+    )
+    .await;
+    assert_eq!(body["hits"]["total"]["value"], 2);
+}
+
+#[tokio::test]
+async fn test_writes_keep_the_index_order() {
+    let server = events().await;
+    server
+        .put("/events/_doc/b")
+        .json(&json!({ "ts": 9, "kind": "logout" }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/events/_doc/c")
+        .json(&json!({ "ts": [2, 5], "kind": "login" }))
+        .await
+        .assert_status_ok();
+    server.delete("/events/_doc/d").await.assert_status_ok();
+
+    let body = search(&server, json!({})).await;
+    assert_eq!(body["hits"]["total"]["value"], 4);
+    // Arrays sort by their maximum in descending order
+    assert_eq!(ids(&body), vec!["b", "c", "a", "e"]);
+}
+
+#[tokio::test]
+async fn test_invalid_index_sort_settings() {
+    let server = events().await;
+    for settings in [
+        json!({ "index.sort.order": "desc" }),
+        json!({ "index.sort.field": "ts", "index.sort.order": "up" }),
+        json!({ "index.sort.field": ["ts", "kind"] }),
+        json!({ "index.sort.field": "_score" }),
+        json!({ "index.sort.field": "ts", "index.sort.mode": "avg" }),
+        json!({ "index.sort.field": "ts", "index.sort.direction": "desc" }),
+    ] {
+        server
+            .put("/sorted")
+            .json(&json!({ "settings": settings }))
+            .await
+            .assert_status_bad_request();
+    }
+    // The sort is static
+    server
+        .put("/events/_settings")
+        .json(&json!({ "index.sort.order": "asc" }))
+        .await
+        .assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_index_order_is_rebuilt_on_load() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+    {
+        let storage = open_sled(&data_path).await;
+        storage
+            .create_index(
+                "scores",
+                Some(json!({ "index.sort.field": "points", "index.sort.missing": "_first" })),
+                None,
+            )
+            .await
+            .unwrap();
+        for (id, points) in [("x", json!(20)), ("y", json!(null)), ("z", json!(10))] {
+            storage
+                .index_document("scores", id, json!({ "points": points }))
+                .await
+                .unwrap();
+        }
+    }
+
+    let storage = open_sled(&data_path).await;
+    let result = storage
+        .search(
+            "scores",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(ids(&result), vec!["y", "z", "x"]);
+}
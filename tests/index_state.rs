@@ -0,0 +1,165 @@
+//! Tests for opening, closing and freezing indices
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::open_sled;
+use gbs::storage::{IndexState, Storage};
+use serde_json::{json, Value};
+use tempfile::TempDir;
+
+async fn server() -> TestServer {
+    let storage = Storage::new();
+    for index in ["logs-1", "logs-2"] {
+        storage.create_index(index, None, None).await.unwrap();
+        common::index_disk_full(&storage, index, &["1"]).await;
+    }
+    common::test_server(storage, "6.8.23")
+}
+
+fn cat_status(rows: &Value, index: &str) -> String {
+    rows.as_array()
+        .unwrap()
+        .iter()
+        .find(|row| row["index"] == index)
+        .map(|row| row["status"].as_str().unwrap().to_string())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_close_and_open_index() {
+    let server = server().await;
+
+    let body: Value = server.post("/logs-1/_close").await.json();
+    assert_eq!(body["acknowledged"], true);
+
+    let rows: Value = server.get("/_cat/indices?format=json").await.json();
+    assert_eq!(cat_status(&rows, "logs-1"), "close");
+    assert_eq!(cat_status(&rows, "logs-2"), "open");
+
+    // Searches, reads and writes are rejected
+    let response = server
+        .post("/logs-1/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let error = &response.json::<Value>()["error"];
+    assert_eq!(error["type"], "index_closed_exception");
+    assert_eq!(error["index"], "logs-1");
+    server
+        .get("/logs-1/_doc/1")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .put("/logs-1/_doc/2")
+        .json(&json!({ "message": "late" }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .delete("/logs-1/_doc/1")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let bulk = "{\"index\":{\"_index\":\"logs-1\",\"_id\":\"3\"}}\n{\"message\":\"bulk\"}\n";
+    let body: Value = server
+        .post("/_bulk")
+        .content_type("application/x-ndjson")
+        .text(bulk)
+        .await
+        .json();
+    assert_eq!(body["errors"], true);
+    assert_eq!(
+        body["items"][0]["index"]["error"]["type"],
+        "index_closed_exception"
+    );
+
+    // Wildcards skip the closed index
+    let body: Value = server
+        .post("/logs-*/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    assert_eq!(body["hits"]["hits"][0]["_index"], "logs-2");
+
+    // Opening the index brings back its documents
+    server.post("/logs-*/_open").await.assert_status_ok();
+    let body: Value = server.get("/logs-1/_doc/1").await.json();
+    assert_eq!(body["_source"]["message"], "disk full");
+    let rows: Value = server.get("/_cat/indices?format=json").await.json();
+    assert_eq!(cat_status(&rows, "logs-1"), "open");
+
+    server
+        .post("/missing/_close")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_freeze_index() {
+    let server = server().await;
+
+    server.post("/logs-1/_freeze").await.assert_status_ok();
+
+    // Frozen indices are searchable but read-only
+    let body: Value = server
+        .post("/logs-1/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    let response = server
+        .put("/logs-1/_doc/2")
+        .json(&json!({ "message": "late" }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "cluster_block_exception"
+    );
+    let rows: Value = server.get("/_cat/indices?format=json").await.json();
+    assert_eq!(cat_status(&rows, "logs-1"), "open");
+
+    server.post("/logs-1/_unfreeze").await.assert_status_ok();
+    server
+        .put("/logs-1/_doc/2")
+        .json(&json!({ "message": "late" }))
+        .await
+        .assert_status_success();
+}
+
+#[tokio::test]
+async fn test_index_state_persisted() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    {
+        let storage = open_sled(&path).await;
+        storage.create_index("logs", None, None).await.unwrap();
+        common::index_disk_full(&storage, "logs", &["1"]).await;
+        assert!(storage
+            .set_index_state("logs", IndexState::Close)
+            .await
+            .unwrap());
+        assert!(!storage
+            .set_index_state("logs", IndexState::Close)
+            .await
+            .unwrap());
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&path).await;
+    assert!(storage.get_document("logs", "1").await.is_err());
+    storage
+        .set_index_state("logs", IndexState::Open)
+        .await
+        .unwrap();
+    let doc = storage.get_document("logs", "1").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "disk full");
+}
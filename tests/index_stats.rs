@@ -0,0 +1,94 @@
+//! Tests for the index stats API
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn server() -> TestServer {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    storage.create_index("metrics", None, None).await.unwrap();
+    common::index_disk_full(&storage, "logs", &["1", "2", "3"]).await;
+    storage.delete_document("logs", "3").await.unwrap();
+    storage
+        .index_document("metrics", "1", json!({ "cpu": 0.5 }))
+        .await
+        .unwrap();
+    common::test_server(storage, "6.8.23")
+}
+
+#[tokio::test]
+async fn test_index_stats() {
+    let server = server().await;
+
+    server
+        .post("/logs/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+    let bulk = "{\"index\":{\"_index\":\"logs\",\"_id\":\"4\"}}\n{\"message\":\"bulk\"}\n\
+                {\"delete\":{\"_index\":\"logs\",\"_id\":\"1\"}}\n";
+    server
+        .post("/_bulk")
+        .content_type("application/x-ndjson")
+        .text(bulk)
+        .await
+        .assert_status_ok();
+
+    let body: Value = server.get("/logs/_stats").await.json();
+    assert_eq!(body["_shards"]["total"], 1);
+    let stats = &body["indices"]["logs"]["primaries"];
+    assert_eq!(stats, &body["indices"]["logs"]["total"]);
+    assert_eq!(stats["docs"], json!({ "count": 2, "deleted": 0 }));
+    assert!(stats["store"]["size_in_bytes"].as_u64().unwrap() > 0);
+    assert_eq!(stats["indexing"]["index_total"], 4);
+    assert_eq!(stats["indexing"]["delete_total"], 2);
+    assert_eq!(stats["search"]["query_total"], 1);
+    assert!(body["indices"].get("metrics").is_none());
+
+    // Totals over all indices
+    let body: Value = server.get("/_stats").await.json();
+    assert_eq!(body["_shards"]["total"], 2);
+    assert_eq!(body["_all"]["primaries"]["docs"]["count"], 3);
+    assert_eq!(body["_all"]["total"]["indexing"]["index_total"], 5);
+    assert_eq!(body["indices"]["metrics"]["total"]["docs"]["count"], 1);
+
+    // Selected metrics
+    let body: Value = server.get("/_stats/docs,search").await.json();
+    let all = body["_all"]["primaries"].as_object().unwrap();
+    assert_eq!(all.keys().collect::<Vec<_>>(), ["docs", "search"]);
+    let body: Value = server.get("/log*/_stats/store").await.json();
+    let logs = body["indices"]["logs"]["total"].as_object().unwrap();
+    assert_eq!(logs.keys().collect::<Vec<_>>(), ["store"]);
+
+    server
+        .get("/_stats/segments")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/missing/_stats")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_closed_index_stats() {
+    let server = server().await;
+    server.post("/metrics/_close").await.assert_status_ok();
+
+    let body: Value = server.get("/_stats").await.json();
+    assert!(body["indices"].get("metrics").is_none());
+    assert_eq!(body["_all"]["total"]["docs"]["count"], 2);
+
+    let response = server.get("/metrics/_stats").expect_failure().await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "index_closed_exception"
+    );
+}
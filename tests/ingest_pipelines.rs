@@ -0,0 +1,448 @@
+//! Tests for ingest pipelines
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{open_sled, test_server};
+use gbs::error::GbsError;
+use gbs::storage::{IngestPipeline, Storage};
+use serde_json::{json, Value};
+
+fn run(processors: Value, doc: Value) -> gbs::error::Result<Value> {
+    IngestPipeline::parse(&json!({ "processors": processors }))
+        .unwrap()
+        .run(doc)
+}
+
+#[tokio::test]
+async fn test_processors() {
+    let doc = json!({
+        "user": { "name": "Ada", "id": " 42 " },
+        "tags": ["Rust", "Search"],
+        "active": "TRUE",
+        "score": "1.5",
+        "when": "15/03/2024 10:30:00",
+        "scratch": "x"
+    });
+    let processed = run(
+        json!([
+            { "set": { "field": "meta.source", "value": "import" } },
+            { "set": { "field": "user.name", "value": "kept", "override": false } },
+            { "set": { "field": "owner", "copy_from": "user.name" } },
+            { "remove": { "field": ["scratch", "missing"], "ignore_missing": true } },
+            { "rename": { "field": "user.id", "target_field": "user_id" } },
+            { "lowercase": { "field": "tags" } },
+            { "uppercase": { "field": "user.name", "target_field": "user.upper" } },
+            { "convert": { "field": "user_id", "type": "integer" } },
+            { "convert": { "field": "active", "type": "boolean" } },
+            { "convert": { "field": "score", "type": "auto" } },
+            { "date": { "field": "when", "formats": ["ISO8601", "dd/MM/yyyy HH:mm:ss"], "timezone": "+02:00" } },
+            { "date": { "field": "user_id", "target_field": "epoch", "formats": ["UNIX"] } }
+        ]),
+        doc,
+    )
+    .unwrap();
+    assert_eq!(
+        processed,
+        json!({
+            "user": { "name": "Ada", "upper": "ADA" },
+            "owner": "Ada",
+            "meta": { "source": "import" },
+            "user_id": 42,
+            "tags": ["rust", "search"],
+            "active": true,
+            "score": 1.5,
+            "when": "15/03/2024 10:30:00",
+            "@timestamp": "2024-03-15T10:30:00.000+02:00",
+            "epoch": "1970-01-01T00:00:42.000Z"
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_grok() {
+    let processors = json!([{
+        "grok": {
+            "field": "message",
+            "patterns": [
+                "%{IP:client.ip} %{WORD:method} %{URIPATH:path} %{NUMBER:bytes:int} %{NUMBER:took:float}",
+                "%{LOGLEVEL:level}: %{GREEDYDATA:text}"
+            ]
+        }
+    }]);
+    let processed = run(
+        processors.clone(),
+        json!({ "message": "10.0.0.7 GET /index.html 1024 0.25" }),
+    )
+    .unwrap();
+    assert_eq!(processed["client"]["ip"], "10.0.0.7");
+    assert_eq!(processed["method"], "GET");
+    assert_eq!(processed["path"], "/index.html");
+    assert_eq!(processed["bytes"], 1024);
+    assert_eq!(processed["took"], 0.25);
+
+    let processed = run(processors.clone(), json!({ "message": "WARN: disk full" })).unwrap();
+    assert_eq!(processed["level"], "WARN");
+    assert_eq!(processed["text"], "disk full");
+
+    let error = run(processors, json!({ "message": "???" })).unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "Provided Grok expressions do not match field value: [???]"
+    );
+
+    // Custom patterns can reference the built-in ones
+    let processed = run(
+        json!([{
+            "grok": {
+                "field": "line",
+                "patterns": ["%{TICKET:ticket}"],
+                "pattern_definitions": { "TICKET": "[A-Z]+-%{INT}" }
+            }
+        }]),
+        json!({ "line": "fixes GBS-123" }),
+    )
+    .unwrap();
+    assert_eq!(processed["ticket"], "GBS-123");
+}
+
+#[tokio::test]
+async fn test_processor_failures() {
+    // Missing fields fail unless ignored
+    assert!(run(
+        json!([{ "rename": { "field": "a", "target_field": "b" } }]),
+        json!({})
+    )
+    .is_err());
+    assert_eq!(
+        run(
+            json!([{ "rename": { "field": "a", "target_field": "b", "ignore_missing": true } }]),
+            json!({ "c": 1 })
+        )
+        .unwrap(),
+        json!({ "c": 1 })
+    );
+    assert_eq!(
+        run(
+            json!([{ "rename": { "field": "a", "target_field": "b" } }]),
+            json!({ "a": 1, "b": 2 })
+        )
+        .unwrap_err()
+        .to_string(),
+        "field [b] already exists"
+    );
+    assert_eq!(
+        run(
+            json!([{ "convert": { "field": "n", "type": "integer" } }]),
+            json!({ "n": "abc" })
+        )
+        .unwrap_err()
+        .to_string(),
+        "unable to convert [abc] to integer"
+    );
+    assert!(run(
+        json!([{ "lowercase": { "field": "n" } }]),
+        json!({ "n": 1 })
+    )
+    .is_err());
+
+    // A failing processor with ignore_failure leaves the document as it was
+    assert_eq!(
+        run(
+            json!([
+                { "remove": { "field": ["a", "b"], "ignore_failure": true } },
+                { "set": { "field": "c", "value": 3 } }
+            ]),
+            json!({ "a": 1 })
+        )
+        .unwrap(),
+        json!({ "a": 1, "c": 3 })
+    );
+
+    // Invalid definitions are rejected when the pipeline is stored
+    for processors in [
+        json!([{ "unknown": {} }]),
+        json!([{ "set": { "field": "a" } }]),
+        json!([{ "convert": { "field": "a", "type": "date" } }]),
+        json!([{ "date": { "field": "a", "formats": [] } }]),
+        json!([{ "grok": { "field": "a", "patterns": ["%{NOPE:x}"] } }]),
+        json!([{ "set": { "field": "a", "value": 1 }, "remove": { "field": "a" } }]),
+    ] {
+        let error = IngestPipeline::parse(&json!({ "processors": processors })).unwrap_err();
+        assert!(matches!(error, GbsError::IllegalArgument(_)), "{}", error);
+    }
+    assert!(IngestPipeline::parse(&json!({ "description": "none" })).is_err());
+}
+
+#[tokio::test]
+async fn test_pipeline_api() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    let server = test_server(storage.clone(), "6.8.23");
+
+    let pipeline = json!({
+        "description": "parse log lines",
+        "processors": [
+            { "grok": { "field": "message", "patterns": ["%{LOGLEVEL:level} %{GREEDYDATA:text}"] } },
+            { "lowercase": { "field": "level" } },
+            { "remove": { "field": "message" } }
+        ]
+    });
+    server
+        .put("/_ingest/pipeline/logs")
+        .json(&pipeline)
+        .await
+        .assert_status_ok();
+    let body: Value = server.get("/_ingest/pipeline/logs").await.json();
+    assert_eq!(body, json!({ "logs": pipeline }));
+    let body: Value = server.get("/_ingest/pipeline").await.json();
+    assert_eq!(body.as_object().unwrap().len(), 1);
+
+    // Index requests run the pipeline
+    server
+        .put("/logs/_doc/1?pipeline=logs")
+        .json(&json!({ "message": "ERROR disk full" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    let doc = storage.get_document("logs", "1").await.unwrap();
+    assert_eq!(
+        doc["_source"],
+        json!({ "level": "error", "text": "disk full" })
+    );
+
+    let body: Value = server
+        .post("/logs/_doc?pipeline=logs")
+        .json(&json!({ "message": "INFO started" }))
+        .await
+        .json();
+    let id = body["_id"].as_str().unwrap();
+    let doc = storage.get_document("logs", id).await.unwrap();
+    assert_eq!(doc["_source"]["level"], "info");
+
+    // Bulk requests run it for index and create actions; a document the
+    // pipeline fails for fails its own item
+    let bulk = [
+        json!({ "index": { "_index": "logs", "_id": "2" } }),
+        json!({ "message": "WARN low memory" }),
+        json!({ "create": { "_index": "logs", "_id": "3" } }),
+        json!({ "message": "no level here" }),
+        json!({ "index": { "_index": "logs", "_id": "4" } }),
+        json!({ "message": "DEBUG tick" }),
+    ]
+    .iter()
+    .map(|line| format!("{}\n", line))
+    .collect::<String>();
+    let body: Value = server.post("/_bulk?pipeline=logs").text(bulk).await.json();
+    assert_eq!(body["errors"], true);
+    assert_eq!(body["items"][0]["index"]["status"], 201);
+    assert_eq!(body["items"][1]["create"]["status"], 400);
+    assert_eq!(
+        body["items"][1]["create"]["error"]["type"],
+        "illegal_argument_exception"
+    );
+    assert_eq!(body["items"][2]["index"]["status"], 201);
+    let doc = storage.get_document("logs", "2").await.unwrap();
+    assert_eq!(
+        doc["_source"],
+        json!({ "level": "warn", "text": "low memory" })
+    );
+    assert!(storage.get_document("logs", "3").await.is_err());
+
+    // Simulation runs documents through a pipeline without indexing them
+    let body: Value = server
+        .post("/_ingest/pipeline/logs/_simulate")
+        .json(&json!({ "docs": [
+            { "_source": { "message": "INFO ok" } },
+            { "_source": { "message": "??" } }
+        ] }))
+        .await
+        .json();
+    assert_eq!(
+        body["docs"][0]["doc"]["_source"],
+        json!({ "level": "info", "text": "ok" })
+    );
+    assert_eq!(
+        body["docs"][1]["error"]["type"],
+        "illegal_argument_exception"
+    );
+    let body: Value = server
+        .post("/_ingest/pipeline/_simulate")
+        .json(&json!({
+            "pipeline": { "processors": [{ "uppercase": { "field": "a" } }] },
+            "docs": [{ "_source": { "a": "x" } }]
+        }))
+        .await
+        .json();
+    assert_eq!(body["docs"][0]["doc"]["_source"], json!({ "a": "X" }));
+
+    // Unknown pipelines
+    let response = server
+        .put("/logs/_doc/5?pipeline=nope")
+        .json(&json!({ "message": "INFO x" }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<Value>()["error"]["reason"],
+        "pipeline with id [nope] does not exist"
+    );
+    server
+        .put("/_ingest/pipeline/bad")
+        .json(&json!({ "processors": [{ "nope": {} }] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    server
+        .delete("/_ingest/pipeline/logs")
+        .await
+        .assert_status_ok();
+    let response = server.get("/_ingest/pipeline/logs").expect_failure().await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "resource_not_found_exception"
+    );
+    server
+        .delete("/_ingest/pipeline/logs")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_pipelines_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::with_sled(dir.path()).unwrap();
+        let pipeline = json!({ "processors": [{ "set": { "field": "stored", "value": true } }] });
+        storage
+            .put_pipeline("flag", IngestPipeline::parse(&pipeline).unwrap())
+            .await
+            .unwrap();
+        storage
+            .put_pipeline("gone", IngestPipeline::parse(&pipeline).unwrap())
+            .await
+            .unwrap();
+        storage.delete_pipeline("gone").await.unwrap();
+    }
+
+    let storage = open_sled(dir.path()).await;
+    let ids: Vec<String> = storage
+        .get_pipelines("*")
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect();
+    assert_eq!(ids, vec!["flag"]);
+    assert_eq!(
+        storage.run_pipeline("flag", json!({})).unwrap(),
+        json!({ "stored": true })
+    );
+}
+
+#[tokio::test]
+async fn test_simulate() {
+    let storage = Storage::new();
+    let server = test_server(storage.clone(), "6.8.23");
+    let request = json!({
+        "pipeline": {
+            "processors": [
+                { "set": { "field": "stage", "value": "one", "tag": "first" } },
+                { "convert": { "field": "count", "type": "integer", "ignore_failure": true } },
+                { "rename": { "field": "name", "target_field": "title" } },
+                { "uppercase": { "field": "title" } }
+            ]
+        },
+        "docs": [
+            { "_index": "books", "_id": "1", "_source": { "name": "dune", "count": "3" } },
+            { "_source": { "count": "many" } }
+        ]
+    });
+
+    let body: Value = server
+        .post("/_ingest/pipeline/_simulate")
+        .json(&request)
+        .await
+        .json();
+    let doc = &body["docs"][0]["doc"];
+    assert_eq!(doc["_index"], "books");
+    assert_eq!(doc["_id"], "1");
+    assert_eq!(
+        doc["_source"],
+        json!({ "stage": "one", "title": "DUNE", "count": 3 })
+    );
+    assert!(doc["_ingest"]["timestamp"].is_string());
+    assert_eq!(
+        body["docs"][1]["error"]["reason"],
+        "field [name] not present as part of path [name]"
+    );
+    // Nothing is indexed
+    assert!(storage.list_indices().await.is_empty());
+
+    // Verbose simulation reports every processor until the first failure
+    let body: Value = server
+        .post("/_ingest/pipeline/_simulate?verbose=true")
+        .json(&request)
+        .await
+        .json();
+    let results = body["docs"][0]["processor_results"].as_array().unwrap();
+    assert_eq!(results.len(), 4);
+    assert_eq!(results[0]["processor_type"], "set");
+    assert_eq!(results[0]["tag"], "first");
+    assert_eq!(results[0]["status"], "success");
+    assert_eq!(results[0]["doc"]["_source"]["stage"], "one");
+    assert_eq!(results[1]["doc"]["_source"]["count"], 3);
+    assert_eq!(results[2]["doc"]["_source"]["title"], "dune");
+    assert_eq!(results[3]["doc"]["_source"]["title"], "DUNE");
+
+    let results = body["docs"][1]["processor_results"].as_array().unwrap();
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[1]["status"], "error_ignored");
+    assert_eq!(
+        results[1]["ignored_error"]["error"]["reason"],
+        "unable to convert [many] to integer"
+    );
+    assert_eq!(results[1]["doc"]["_source"]["count"], "many");
+    assert_eq!(results[2]["processor_type"], "rename");
+    assert_eq!(results[2]["status"], "error");
+    assert_eq!(results[2]["error"]["type"], "illegal_argument_exception");
+    assert!(results[2].get("doc").is_none());
+
+    // Stored pipelines are simulated by id
+    storage
+        .put_pipeline(
+            "stored",
+            IngestPipeline::parse(&request["pipeline"]).unwrap(),
+        )
+        .await
+        .unwrap();
+    let body: Value = server
+        .post("/_ingest/pipeline/stored/_simulate?verbose")
+        .json(&json!({ "docs": [{ "_source": { "name": "x" } }] }))
+        .await
+        .json();
+    assert_eq!(
+        body["docs"][0]["processor_results"][3]["doc"]["_source"]["title"],
+        "X"
+    );
+    server
+        .post("/_ingest/pipeline/missing/_simulate")
+        .json(&json!({ "docs": [{ "_source": {} }] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    // Requests need documents and, without an id, a pipeline definition
+    server
+        .post("/_ingest/pipeline/_simulate")
+        .json(&json!({ "pipeline": request["pipeline"], "docs": [] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/_ingest/pipeline/_simulate")
+        .json(&json!({ "docs": [{ "_source": {} }] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,143 @@
+//! Tests for the inner hits of nested queries
+
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn posts() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("posts", None, None).await.unwrap();
+    for (id, doc) in [
+        (
+            "1",
+            json!({
+                "title": "gummy bears",
+                "topic": "sweets",
+                "comments": [
+                    { "author": "ann", "text": "great read", "stars": 4 },
+                    { "author": "bob", "text": "boring", "stars": 1 },
+                    { "author": "cid", "text": "great pictures, great bears", "stars": 5 }
+                ]
+            }),
+        ),
+        (
+            "2",
+            json!({
+                "title": "jelly beans",
+                "topic": "sweets",
+                "comments": [{ "author": "dee", "text": "great", "stars": 3 }]
+            }),
+        ),
+        (
+            "3",
+            json!({ "title": "licorice", "topic": "sweets", "comments": [] }),
+        ),
+    ] {
+        storage.index_document("posts", id, doc).await.unwrap();
+    }
+    storage
+}
+
+async fn search(storage: &Storage, body: Value) -> Value {
+    storage
+        .search("posts", &body, None, None, None, None, None)
+        .await
+        .unwrap()
+}
+
+fn hit<'a>(result: &'a Value, id: &str) -> &'a Value {
+    result["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|hit| hit["_id"] == id)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_nested_inner_hits_list_the_matching_objects() {
+    let storage = posts().await;
+    let result = search(
+        &storage,
+        json!({ "nested": {
+            "path": "comments",
+            "query": { "match": { "comments.text": "great" } },
+            "inner_hits": {}
+        } }),
+    )
+    .await;
+    assert_eq!(result["hits"]["total"]["value"], 2);
+
+    let inner = &hit(&result, "1")["inner_hits"]["comments"]["hits"];
+    assert_eq!(inner["total"]["value"], 2);
+    let inner_hits = inner["hits"].as_array().unwrap();
+    assert_eq!(inner_hits.len(), 2);
+    for inner_hit in inner_hits {
+        assert_eq!(inner_hit["_id"], "1");
+        assert_eq!(inner_hit["_index"], "posts");
+        assert_eq!(inner_hit["_nested"]["field"], "comments");
+        assert!(inner_hit["_score"].as_f64().unwrap() > 0.0);
+    }
+    // Best first
+    assert!(inner_hits[0]["_score"].as_f64() >= inner_hits[1]["_score"].as_f64());
+    assert_eq!(inner["max_score"], inner_hits[0]["_score"]);
+    let offsets: Vec<u64> = inner_hits
+        .iter()
+        .map(|hit| hit["_nested"]["offset"].as_u64().unwrap())
+        .collect();
+    let mut sorted = offsets.clone();
+    sorted.sort();
+    assert_eq!(sorted, vec![0, 2]);
+    let source = &inner_hits[offsets.iter().position(|&o| o == 0).unwrap()]["_source"];
+    assert_eq!(
+        source,
+        &json!({ "author": "ann", "text": "great read", "stars": 4 })
+    );
+}
+
+#[tokio::test]
+async fn test_nested_inner_hits_options() {
+    let storage = posts().await;
+    let result = search(
+        &storage,
+        json!({ "bool": {
+            "must": [{ "term": { "topic": "sweets" } }],
+            "should": [{ "nested": {
+                "path": "comments",
+                "query": { "range": { "comments.stars": { "gte": 1 } } },
+                "inner_hits": {
+                    "name": "by_stars",
+                    "from": 1,
+                    "size": 1,
+                    "sort": [{ "comments.stars": "desc" }]
+                }
+            } }]
+        } }),
+    )
+    .await;
+    assert_eq!(result["hits"]["total"]["value"], 3);
+
+    let inner = &hit(&result, "1")["inner_hits"]["by_stars"]["hits"];
+    assert_eq!(inner["total"]["value"], 3);
+    assert_eq!(inner["hits"].as_array().unwrap().len(), 1);
+    assert_eq!(inner["hits"][0]["_source"]["author"], "ann");
+    assert_eq!(inner["hits"][0]["sort"], json!([4.0]));
+
+    // Hits matching through other clauses have empty inner hits
+    let inner = &hit(&result, "3")["inner_hits"]["by_stars"]["hits"];
+    assert_eq!(inner["total"]["value"], 0);
+    assert_eq!(inner["hits"], json!([]));
+}
+
+#[tokio::test]
+async fn test_queries_without_inner_hits_have_none() {
+    let storage = posts().await;
+    let result = search(
+        &storage,
+        json!({ "nested": {
+            "path": "comments",
+            "query": { "match": { "comments.text": "great" } }
+        } }),
+    )
+    .await;
+    assert!(hit(&result, "1").get("inner_hits").is_none());
+}
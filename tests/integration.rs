@@ -101,7 +101,7 @@ mod tests {
 
         // Update settings
         let settings = serde_json::json!({
-            "number_of_shards": 2
+            "number_of_replicas": 2
         });
         storage
             .update_settings("test_index", settings)
@@ -3,11 +3,16 @@
 //! These tests verify the HTTP API layer using axum-test to test handlers
 //! without requiring a running server.
 
+mod common;
+
 use axum_test::http::StatusCode;
 use axum_test::TestServer;
-use gbs::server::{create_router, AppState};
-use gbs::storage::Storage;
-use serde_json::json;
+use base64::Engine;
+use gbs::auth::AuthStore;
+use gbs::config::{ApiKeyConfig, SearchConfig, SecurityConfig, UserConfig};
+use gbs::server::{create_router, AppState, RequestLimits};
+use gbs::storage::{ParallelScoring, Storage};
+use serde_json::{json, Value};
 use std::sync::Arc;
 // NOTE: Bulk operation tests are commented out because axum-test doesn't support
 // sending raw text bodies (NDJSON format). The bulk handler code is correct and works.
@@ -21,10 +26,7 @@ use std::sync::Arc;
 /// Helper function to create a test server with in-memory storage
 fn create_test_server() -> TestServer {
     let storage = Storage::new();
-    let state = AppState {
-        storage: Arc::new(storage),
-        es_version: "6.8.23".to_string(),
-    };
+    let state = AppState::new(Arc::new(storage), "6.8.23");
     let app = create_router(state);
     // For axum 0.7, use axum-test 16 which is compatible
     // The router can be used directly with axum-test 16
@@ -54,6 +56,27 @@ async fn test_cluster_health() {
     assert_eq!(body["status"], "green");
     assert_eq!(body["number_of_nodes"], 1);
     assert_eq!(body["number_of_data_nodes"], 1);
+
+    // Replicas cannot be allocated on the single node
+    server
+        .put("/replicated")
+        .json(&json!({ "settings": { "number_of_shards": 2, "number_of_replicas": 1 } }))
+        .await
+        .assert_status_ok();
+    let body: serde_json::Value = server.get("/_cluster/health").await.json();
+    assert_eq!(body["status"], "yellow");
+    assert_eq!(body["active_shards"], 2);
+    assert_eq!(body["unassigned_shards"], 2);
+    assert_eq!(body["active_shards_percent_as_number"], 50.0);
+
+    server
+        .put("/replicated/_settings")
+        .json(&json!({ "index": { "number_of_replicas": 0 } }))
+        .await
+        .assert_status_ok();
+    let body: serde_json::Value = server.get("/_cluster/health").await.json();
+    assert_eq!(body["status"], "green");
+    assert_eq!(body["unassigned_shards"], 0);
 }
 
 #[tokio::test]
@@ -214,8 +237,26 @@ async fn test_index_document() {
 
     let response = server.put("/test_index/_doc/1").json(&doc).await;
 
-    // PUT with new document returns 201 (Created) - no body, just status
+    // PUT with new document returns 201 (Created)
     response.assert_status(StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["_index"], "test_index");
+    assert_eq!(body["_id"], "1");
+    assert_eq!(body["result"], "created");
+    assert_eq!(body["_version"], 1);
+    assert_eq!(body["_seq_no"], 0);
+    assert_eq!(body["_shards"]["successful"], 1);
+
+    // Replacing it returns 200 (OK) and bumps the version
+    let response = server.put("/test_index/_doc/1").json(&doc).await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["result"], "updated");
+    assert_eq!(body["_version"], 2);
+    assert_eq!(body["_seq_no"], 1);
+
+    let body: serde_json::Value = server.get("/test_index/_doc/1").await.json();
+    assert_eq!(body["_version"], 2);
 }
 
 #[tokio::test]
@@ -258,6 +299,51 @@ async fn test_get_document_not_found() {
     response.assert_status(StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn test_get_document_source_filtering() {
+    let server = create_test_server();
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune", "author": "Herbert", "year": 1965 }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let get = |params: &'static [(&'static str, &'static str)]| {
+        let mut request = server.get("/books/_doc/1");
+        for (name, value) in params {
+            request = request.add_query_param(name, value);
+        }
+        async move { request.await.json::<serde_json::Value>() }
+    };
+
+    let body = get(&[("_source", "title,year")]).await;
+    assert_eq!(body["_source"], json!({ "title": "Dune", "year": 1965 }));
+    let body = get(&[("_source_includes", "author")]).await;
+    assert_eq!(body["_source"], json!({ "author": "Herbert" }));
+    let body = get(&[("_source_excludes", "author,year")]).await;
+    assert_eq!(body["_source"], json!({ "title": "Dune" }));
+    let body = get(&[
+        ("_source_includes", "title,author"),
+        ("_source_excludes", "author"),
+    ])
+    .await;
+    assert_eq!(body["_source"], json!({ "title": "Dune" }));
+    let body = get(&[("_source", "true")]).await;
+    assert_eq!(body["_source"]["year"], 1965);
+
+    let body = get(&[("_source", "false")]).await;
+    assert!(body.get("_source").is_none());
+    assert_eq!(body["_id"], "1");
+
+    let body: serde_json::Value = server
+        .get("/books/_source/1")
+        .add_query_param("_source_excludes", "year")
+        .await
+        .json();
+    assert_eq!(body, json!({ "title": "Dune", "author": "Herbert" }));
+}
+
 #[tokio::test]
 async fn test_create_document_auto_id() {
     let server = create_test_server();
@@ -280,6 +366,86 @@ async fn test_create_document_auto_id() {
     let body: serde_json::Value = response.json();
     assert!(body["_id"].is_string());
     assert!(!body["_id"].as_str().unwrap().is_empty());
+    assert_eq!(body["_version"], 1);
+    assert_eq!(body["_seq_no"], 0);
+    assert_eq!(body["_primary_term"], 1);
+
+    let body: serde_json::Value = server.post("/test_index/_doc").json(&doc).await.json();
+    assert_eq!(body["_seq_no"], 1);
+}
+
+#[tokio::test]
+async fn test_create_document_with_id() {
+    let server = create_test_server();
+    server.put("/test_index").await;
+
+    let response = server
+        .put("/test_index/_create/1")
+        .json(&json!({ "title": "First" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["_id"], "1");
+    assert_eq!(body["result"], "created");
+    assert_eq!(body["_version"], 1);
+    assert_eq!(body["_seq_no"], 0);
+    assert_eq!(body["_primary_term"], 1);
+
+    // Creating an existing ID fails and keeps the document
+    let response = server
+        .post("/test_index/_create/1")
+        .json(&json!({ "title": "Second" }))
+        .await;
+    response.assert_status(StatusCode::CONFLICT);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["type"], "version_conflict_engine_exception");
+    assert_eq!(body["status"], 409);
+    let body: serde_json::Value = server.get("/test_index/_doc/1").await.json();
+    assert_eq!(body["_source"]["title"], "First");
+
+    // The response of a later create matches the document it wrote
+    server.delete("/test_index/_doc/1").await.assert_status_ok();
+    let body: serde_json::Value = server
+        .put("/test_index/_doc/1?op_type=create")
+        .json(&json!({ "title": "Third" }))
+        .await
+        .json();
+    assert_eq!(body["_seq_no"], 2);
+    let fetched: serde_json::Value = server.get("/test_index/_doc/1").await.json();
+    assert_eq!(fetched["_version"], body["_version"]);
+}
+
+#[tokio::test]
+async fn test_index_document_op_type() {
+    let server = create_test_server();
+    server.put("/test_index").await;
+
+    server
+        .put("/test_index/_doc/1?op_type=create")
+        .json(&json!({ "title": "First" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .put("/test_index/_doc/1?op_type=create")
+        .json(&json!({ "title": "Second" }))
+        .await
+        .assert_status(StatusCode::CONFLICT);
+    // The default op type replaces the document
+    server
+        .put("/test_index/_doc/1?op_type=index")
+        .json(&json!({ "title": "Third" }))
+        .await
+        .assert_status_ok();
+    let body: serde_json::Value = server.get("/test_index/_doc/1").await.json();
+    assert_eq!(body["_source"]["title"], "Third");
+
+    let response = server
+        .put("/test_index/_doc/2?op_type=upsert")
+        .json(&json!({ "title": "Fourth" }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["type"], "illegal_argument_exception");
 }
 
 #[tokio::test]
@@ -301,10 +467,25 @@ async fn test_delete_document() {
     // Delete document
     let response = server.delete("/test_index/_doc/1").await;
     response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["result"], "deleted");
+    assert_eq!(body["_version"], 2);
+    assert_eq!(body["_seq_no"], 1);
 
     // Verify it's gone
     let get_response = server.get("/test_index/_doc/1").await;
     get_response.assert_status(StatusCode::NOT_FOUND);
+
+    // Deleting it again reports it missing in a write response
+    let response = server.delete("/test_index/_doc/1").await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["result"], "not_found");
+    assert!(body.get("error").is_none());
+    server
+        .delete("/missing_index/_doc/1")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
 }
 
 #[tokio::test]
@@ -492,13 +673,9 @@ async fn test_update_settings() {
 
     // Update settings
     let new_settings = json!({
-        "analysis": {
-            "analyzer": {
-                "custom": {
-                    "type": "custom",
-                    "tokenizer": "standard"
-                }
-            }
+        "index": {
+            "number_of_replicas": 2,
+            "refresh_interval": "5s"
         }
     });
 
@@ -1070,6 +1247,65 @@ async fn test_search_get_with_query_param() {
     assert!(hits.len() > 0);
 }
 
+#[tokio::test]
+async fn test_search_get_with_lucene_query_syntax() {
+    let server = create_test_server();
+    server.put("/test_index").await;
+    for (id, doc) in [
+        ("1", json!({ "title": "Rust Guide", "year": 2021 })),
+        ("2", json!({ "title": "Python Guide", "year": 2023 })),
+        ("3", json!({ "title": "Rust Cookbook", "year": 2024 })),
+    ] {
+        server
+            .put(&format!("/test_index/_doc/{}", id))
+            .json(&doc)
+            .await;
+    }
+    let search = |params: &[(&str, &str)]| {
+        let mut request = server.get("/test_index/_search");
+        for (name, value) in params {
+            request = request.add_query_param(name, value);
+        }
+        async move {
+            let response = request.await;
+            response.assert_status_ok();
+            let body: serde_json::Value = response.json();
+            let mut ids: Vec<String> = body["hits"]["hits"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["_id"].as_str().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        }
+    };
+
+    assert_eq!(
+        search(&[("q", "title:rust AND year:>2022")]).await,
+        vec!["3"]
+    );
+    assert_eq!(
+        search(&[("q", "year:[2021 TO 2023]")]).await,
+        vec!["1", "2"]
+    );
+    assert_eq!(
+        search(&[("q", "rust guide"), ("default_operator", "AND")]).await,
+        vec!["1"]
+    );
+    assert_eq!(
+        search(&[("q", "2024"), ("df", "title")]).await,
+        Vec::<String>::new()
+    );
+    assert_eq!(search(&[("q", "2024"), ("df", "year")]).await, vec!["3"]);
+
+    server
+        .get("/test_index/_search")
+        .add_query_param("q", "title:(rust")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_search_get_without_query_param() {
     let server = create_test_server();
@@ -1116,6 +1352,72 @@ async fn test_search_get_with_pagination() {
     let body: serde_json::Value = response.json();
     let hits = body["hits"]["hits"].as_array().unwrap();
     assert_eq!(hits.len(), 2);
+
+    for (name, value) in [("size", "ten"), ("from", "-1")] {
+        server
+            .get("/test_index/_search")
+            .add_query_param(name, value)
+            .await
+            .assert_status(StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn test_search_get_with_sort_and_source() {
+    let server = create_test_server();
+    server.put("/test_index").await;
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "title": "Rust Guide", "year": 2021, "pages": 300 }),
+        ),
+        (
+            "2",
+            json!({ "title": "Python Guide", "year": 2023, "pages": 200 }),
+        ),
+        (
+            "3",
+            json!({ "title": "Rust Cookbook", "year": 2021, "pages": 100 }),
+        ),
+    ] {
+        server
+            .put(&format!("/test_index/_doc/{}", id))
+            .json(&doc)
+            .await;
+    }
+
+    let body: serde_json::Value = server
+        .get("/test_index/_search")
+        .add_query_param("sort", "year:desc,pages")
+        .add_query_param("_source", "title")
+        .add_query_param("size", "2")
+        .await
+        .json();
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    let ids: Vec<&str> = hits
+        .iter()
+        .map(|hit| hit["_id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec!["2", "3"]);
+    assert_eq!(hits[0]["_source"], json!({ "title": "Python Guide" }));
+
+    let body: serde_json::Value = server
+        .get("/test_index/_search")
+        .add_query_param("q", "rust")
+        .add_query_param("sort", "pages:asc")
+        .add_query_param("_source_excludes", "title,year")
+        .await
+        .json();
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0]["_id"], "3");
+    assert_eq!(hits[0]["_source"], json!({ "pages": 100 }));
+
+    server
+        .get("/test_index/_search")
+        .add_query_param("sort", "year:sideways")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
 }
 
 #[tokio::test]
@@ -1196,6 +1498,48 @@ async fn test_search_post_range_query() {
     assert_eq!(hits.len(), 2);
 }
 
+#[tokio::test]
+async fn test_date_math_index_name_and_range_query() {
+    let server = create_test_server();
+    let today = chrono::Utc::now().format("logs-%Y.%m.%d").to_string();
+
+    // <logs-{now/d}>, URL-encoded
+    let date_math_index = "%3Clogs-%7Bnow%2Fd%7D%3E";
+    server
+        .put(&format!("/{}", date_math_index))
+        .await
+        .assert_status_ok();
+    server
+        .put(&format!("/{}/_doc/1", date_math_index))
+        .json(&json!({ "timestamp": chrono::Utc::now().to_rfc3339() }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .put(&format!("/{}/_doc/2", today))
+        .json(&json!({ "timestamp": "2000-01-01T00:00:00Z" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let query = json!({ "query": { "range": { "timestamp": { "gte": "now-1d/d" } } } });
+    let response = server
+        .post(&format!("/{}/_search", date_math_index))
+        .json(&query)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], "1");
+    assert_eq!(hits[0]["_index"], today.as_str());
+
+    let query = json!({ "query": { "range": { "timestamp": { "gte": "now-1x" } } } });
+    server
+        .post(&format!("/{}/_search", today))
+        .json(&query)
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
 #[tokio::test]
 async fn test_search_post_wildcard_query() {
     let server = create_test_server();
@@ -1553,6 +1897,56 @@ async fn test_search_multi_index() {
     assert_eq!(hits.len(), 2);
 }
 
+#[tokio::test]
+async fn test_search_multi_index_keeps_hits_of_indices_that_did_not_fail() {
+    let server = create_test_server();
+    server.put("/index1").await.assert_status(StatusCode::OK);
+    server.put("/index2").await.assert_status(StatusCode::OK);
+    server
+        .put("/index1/_doc/1")
+        .json(&json!({ "title": "Index 1 Doc" }))
+        .await;
+    server
+        .put("/index2/_doc/1")
+        .json(&json!({ "title": "Index 2 Doc" }))
+        .await;
+    server
+        .put("/index1/_search_profile/titles")
+        .json(&json!({ "fields": ["title"] }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    // index2 has no such profile, so only its search fails
+    let response = server
+        .get("/index1,index2/_search")
+        .add_query_param("q", "doc")
+        .add_query_param("search_profile", "titles")
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    assert_eq!(body["hits"]["hits"][0]["_index"], "index1");
+    assert_eq!(body["_shards"]["failed"], 1);
+    assert_eq!(body["_shards"]["failures"][0]["index"], "index2");
+}
+
+#[tokio::test]
+async fn test_search_paging_past_u32_is_rejected() {
+    let server = create_test_server();
+    server.put("/index1").await.assert_status(StatusCode::OK);
+
+    for body in [
+        json!({ "from": 5_000_000_000u64 }),
+        json!({ "size": 5_000_000_000u64 }),
+        json!({ "from": u32::MAX, "size": 10 }),
+    ] {
+        let response = server.post("/index1/_search").json(&body).await;
+        response.assert_status(StatusCode::BAD_REQUEST);
+        let body: serde_json::Value = response.json();
+        assert_eq!(body["error"]["type"], "illegal_argument_exception");
+    }
+}
+
 #[tokio::test]
 async fn test_search_multi_index_with_wildcard() {
     let server = create_test_server();
@@ -1935,11 +2329,10 @@ async fn test_update_settings_nested() {
     // Update settings with nested structure
     let settings_update = json!({
         "number_of_replicas": 1,
-        "analysis": {
-            "analyzer": {
-                "custom": {
-                    "type": "standard",
-                    "stopwords": ["the", "a", "an"]
+        "search": {
+            "slowlog": {
+                "threshold": {
+                    "query": { "warn": "10s" }
                 }
             }
         }
@@ -1957,6 +2350,14 @@ async fn test_update_settings_nested() {
     let settings = &body["test_index"]["settings"];
     assert_eq!(settings["number_of_shards"], 1); // Original preserved
     assert_eq!(settings["number_of_replicas"], 1); // New added
+    assert_eq!(
+        settings["analysis"]["analyzer"]["custom"]["type"],
+        "standard"
+    );
+    assert_eq!(
+        settings["search"]["slowlog"]["threshold"]["query"]["warn"],
+        "10s"
+    );
 }
 
 #[tokio::test]
@@ -2027,6 +2428,32 @@ async fn test_update_settings_nonexistent_index() {
     response.assert_status(StatusCode::NOT_FOUND);
 }
 
+#[tokio::test]
+async fn test_update_static_setting_is_illegal_argument() {
+    let server = create_test_server();
+    server.put("/test_index").await.assert_status_ok();
+
+    let response = server
+        .put("/test_index/_settings")
+        .json(&json!({ "settings": { "index": { "number_of_shards": 3 } } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["type"], "illegal_argument_exception");
+    assert_eq!(
+        body["error"]["reason"],
+        "Can't update non dynamic settings [[index.number_of_shards]] for open indices [[test_index]]"
+    );
+
+    let response = server
+        .put("/other_index")
+        .json(&json!({ "settings": { "number_of_shards": "three" } }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["type"], "illegal_argument_exception");
+}
+
 #[tokio::test]
 async fn test_refresh_index() {
     let server = create_test_server();
@@ -2194,8 +2621,27 @@ async fn test_root_handler() {
     // Test root endpoint
     let response = server.get("/").await;
     response.assert_status_ok();
-    let body = response.text();
-    assert!(body.contains("Gummy Bear Search"));
+    assert_eq!(response.header("X-Elastic-Product"), "Elasticsearch");
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["version"]["number"], "6.8.23");
+    assert_eq!(body["version"]["build_flavor"], "default");
+    assert_eq!(body["cluster_name"], "gbs");
+    assert_eq!(body["tagline"], "You Know, for Search");
+}
+
+#[tokio::test]
+async fn test_root_handler_follows_es_version() {
+    let state = AppState::new(Arc::new(Storage::new()), "8.11.0");
+    let server = TestServer::new(create_router(state)).unwrap();
+
+    let response = server.get("/").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["version"]["number"], "8.11.0");
+    assert_eq!(
+        body["version"]["minimum_wire_compatibility_version"],
+        "7.17.0"
+    );
 }
 
 #[tokio::test]
@@ -2213,3 +2659,2199 @@ async fn test_web_index_handler() {
             || body.contains("Gummy Bear Search")
     );
 }
+
+// ============================================================================
+// Security Tests
+// ============================================================================
+
+/// Helper function to create a test server with security enabled and an `elastic` superuser
+async fn create_secured_test_server() -> TestServer {
+    let config = SecurityConfig {
+        enabled: true,
+        users: vec![UserConfig {
+            username: "elastic".to_string(),
+            password: "changeme".to_string(),
+            roles: vec!["superuser".to_string()],
+        }],
+        ..Default::default()
+    };
+    let storage = Storage::new();
+    let auth = AuthStore::load(&config, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage), "6.8.23").with_auth(auth);
+    TestServer::new(create_router(state)).unwrap()
+}
+
+fn basic_auth(username: &str, password: &str) -> String {
+    format!(
+        "Basic {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", username, password))
+    )
+}
+
+#[tokio::test]
+async fn test_authenticate_security_disabled() {
+    let server = create_test_server();
+
+    let response = server.get("/_security/_authenticate").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["username"], "_anonymous");
+    assert_eq!(body["authentication_realm"]["type"], "anonymous");
+}
+
+#[tokio::test]
+async fn test_authenticate_valid_credentials() {
+    let server = create_secured_test_server().await;
+
+    let response = server
+        .get("/_security/_authenticate")
+        .add_header("Authorization", basic_auth("elastic", "changeme"))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["username"], "elastic");
+    assert_eq!(body["roles"][0], "superuser");
+    assert_eq!(body["enabled"], true);
+    assert_eq!(body["authentication_realm"]["type"], "native");
+}
+
+#[tokio::test]
+async fn test_authenticate_invalid_credentials() {
+    let server = create_secured_test_server().await;
+
+    let response = server.get("/_security/_authenticate").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(response.headers().contains_key("www-authenticate"));
+
+    let response = server
+        .get("/_security/_authenticate")
+        .add_header("Authorization", basic_auth("elastic", "wrong"))
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_user_management_lifecycle() {
+    let server = create_secured_test_server().await;
+    let admin = basic_auth("elastic", "changeme");
+
+    // Create user
+    let response = server
+        .put("/_security/user/jacknich")
+        .add_header("Authorization", admin.clone())
+        .json(&json!({
+            "password": "l0ng-r4nd0m-p@ssw0rd",
+            "roles": ["viewer"],
+            "full_name": "Jack Nicholson"
+        }))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["created"], true);
+
+    // New user can authenticate
+    let response = server
+        .get("/_security/_authenticate")
+        .add_header(
+            "Authorization",
+            basic_auth("jacknich", "l0ng-r4nd0m-p@ssw0rd"),
+        )
+        .await;
+    response.assert_status_ok();
+    assert_eq!(
+        response.json::<serde_json::Value>()["full_name"],
+        "Jack Nicholson"
+    );
+
+    // Update keeps the password when none is given
+    let response = server
+        .put("/_security/user/jacknich")
+        .add_header("Authorization", admin.clone())
+        .json(&json!({ "roles": ["viewer", "editor"] }))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["created"], false);
+
+    // A new password replaces the one that authenticated before
+    server
+        .put("/_security/user/jacknich")
+        .add_header("Authorization", admin.clone())
+        .json(&json!({ "password": "an0ther-p@ssw0rd" }))
+        .await
+        .assert_status_ok();
+    server
+        .get("/_security/_authenticate")
+        .add_header(
+            "Authorization",
+            basic_auth("jacknich", "l0ng-r4nd0m-p@ssw0rd"),
+        )
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    server
+        .get("/_security/_authenticate")
+        .add_header("Authorization", basic_auth("jacknich", "an0ther-p@ssw0rd"))
+        .await
+        .assert_status_ok();
+
+    // Get user
+    let response = server
+        .get("/_security/user/jacknich")
+        .add_header("Authorization", admin.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["jacknich"]["roles"], json!(["viewer", "editor"]));
+    assert!(body["jacknich"].get("password_hash").is_none());
+
+    // List users
+    let response = server
+        .get("/_security/user")
+        .add_header("Authorization", admin.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert!(body["elastic"].is_object());
+    assert!(body["jacknich"].is_object());
+
+    // Delete user
+    let response = server
+        .delete("/_security/user/jacknich")
+        .add_header("Authorization", admin.clone())
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["found"], true);
+
+    let response = server
+        .get("/_security/user/jacknich")
+        .add_header("Authorization", admin)
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_user_management_requires_superuser() {
+    let server = create_secured_test_server().await;
+
+    server
+        .put("/_security/user/reader")
+        .add_header("Authorization", basic_auth("elastic", "changeme"))
+        .json(&json!({ "password": "reader-password", "roles": ["viewer"] }))
+        .await
+        .assert_status_ok();
+
+    let response = server
+        .get("/_security/user")
+        .add_header("Authorization", basic_auth("reader", "reader-password"))
+        .await;
+    response.assert_status(StatusCode::FORBIDDEN);
+
+    let response = server.delete("/_security/user/elastic").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_create_user_without_password() {
+    let server = create_secured_test_server().await;
+
+    let response = server
+        .put("/_security/user/nopass")
+        .add_header("Authorization", basic_auth("elastic", "changeme"))
+        .json(&json!({ "roles": ["viewer"] }))
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_requests_require_authentication() {
+    let server = create_secured_test_server().await;
+
+    let response = server.get("/_cluster/health").await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert!(response.headers().contains_key("www-authenticate"));
+
+    server
+        .get("/_cluster/health")
+        .add_header("Authorization", basic_auth("elastic", "wrong"))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+    server
+        .get("/_cluster/health")
+        .add_header("Authorization", basic_auth("elastic", "changeme"))
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_api_key_lifecycle() {
+    let server = create_secured_test_server().await;
+    let admin = basic_auth("elastic", "changeme");
+
+    // Create key
+    let response = server
+        .post("/_security/api_key")
+        .add_header("Authorization", admin.clone())
+        .json(&json!({ "name": "ingest", "expiration": "1d" }))
+        .await;
+    response.assert_status_ok();
+    let created: serde_json::Value = response.json();
+    let id = created["id"].as_str().unwrap().to_string();
+    assert_eq!(created["name"], "ingest");
+    assert!(created["expiration"].as_i64().is_some());
+    let encoded = format!("ApiKey {}", created["encoded"].as_str().unwrap());
+    assert_eq!(encoded, api_key(&id, created["api_key"].as_str().unwrap()));
+
+    // Key authenticates as its owner
+    let response = server
+        .get("/_security/_authenticate")
+        .add_header("Authorization", encoded.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["username"], "elastic");
+    assert_eq!(body["authentication_type"], "api_key");
+    assert_eq!(body["api_key"]["id"], id.as_str());
+    server
+        .put("/logs")
+        .add_header("Authorization", encoded.clone())
+        .await
+        .assert_status_ok();
+    server
+        .get("/logs")
+        .add_header("Authorization", api_key(&id, "wrong"))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+
+    // List keys
+    let response = server
+        .get(&format!("/_security/api_key?id={}", id))
+        .add_header("Authorization", admin.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["api_keys"].as_array().unwrap().len(), 1);
+    assert_eq!(body["api_keys"][0]["name"], "ingest");
+    assert_eq!(body["api_keys"][0]["invalidated"], false);
+    assert!(body["api_keys"][0].get("key_hash").is_none());
+
+    // Invalidate key
+    let response = server
+        .delete("/_security/api_key")
+        .add_header("Authorization", admin.clone())
+        .json(&json!({ "ids": [id] }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["invalidated_api_keys"], json!([id]));
+
+    server
+        .get("/logs")
+        .add_header("Authorization", encoded)
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn test_configured_api_keys_and_security_file() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    std::io::Write::write_all(
+        &mut file,
+        b"users:\n  - username: reader\n    password: reader-password\n    roles: [viewer]\napi_keys:\n  - id: from-file\n    key: file-secret\n",
+    )
+    .unwrap();
+    let config = SecurityConfig {
+        enabled: true,
+        api_keys: vec![ApiKeyConfig {
+            id: "ingest".to_string(),
+            key: "ingest-secret".to_string(),
+            name: None,
+            roles: vec!["superuser".to_string()],
+        }],
+        file: Some(file.path().to_string_lossy().to_string()),
+        ..Default::default()
+    };
+    let storage = Storage::new();
+    let auth = AuthStore::load(&config, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage), "6.8.23").with_auth(auth);
+    let server = TestServer::new(create_router(state)).unwrap();
+
+    let response = server
+        .get("/_security/_authenticate")
+        .add_header("Authorization", api_key("ingest", "ingest-secret"))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<serde_json::Value>()["username"], "ingest");
+
+    server
+        .get("/_cluster/health")
+        .add_header("Authorization", api_key("from-file", "file-secret"))
+        .await
+        .assert_status_ok();
+    server
+        .get("/_cluster/health")
+        .add_header("Authorization", basic_auth("reader", "reader-password"))
+        .await
+        .assert_status_ok();
+    server
+        .get("/_cluster/health")
+        .add_header("Authorization", api_key("ingest", "wrong"))
+        .await
+        .assert_status(StatusCode::UNAUTHORIZED);
+}
+
+// ============================================================================
+// Alias Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_index_alias_routes() {
+    let server = create_test_server();
+
+    let response = server
+        .put("/logs-000001")
+        .json(&json!({ "aliases": { "logs": {} } }))
+        .await;
+    response.assert_status(StatusCode::OK);
+
+    // Documents written through the alias land in the index
+    let response = server
+        .put("/logs/_doc/1")
+        .json(&json!({ "message": "hello" }))
+        .await;
+    response.assert_status_success();
+    server
+        .get("/logs-000001/_doc/1")
+        .await
+        .assert_status(StatusCode::OK);
+
+    let response = server.get("/logs-000001").await;
+    let body: serde_json::Value = response.json();
+    assert!(body["logs-000001"]["aliases"].get("logs").is_some());
+
+    server
+        .put("/logs-000001/_alias/logs-write")
+        .await
+        .assert_status(StatusCode::OK);
+    server
+        .delete("/logs-000001/_alias/logs")
+        .await
+        .assert_status(StatusCode::OK);
+    server
+        .delete("/logs-000001/_alias/logs")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .put("/missing/_alias/logs")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Search Profile Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_search_profile_routes() {
+    let server = create_test_server();
+    server.put("/docs").await.assert_status(StatusCode::OK);
+    server
+        .put("/docs/_doc/1")
+        .json(&json!({ "title": "Rust guide", "body": "learn rust" }))
+        .await
+        .assert_status_success();
+    server
+        .put("/docs/_doc/2")
+        .json(&json!({ "title": "Cooking", "body": "rust removal guide" }))
+        .await
+        .assert_status_success();
+
+    let response = server
+        .put("/docs/_search_profile/web_search")
+        .json(&json!({ "fields": ["title^3", "body"], "default_operator": "and" }))
+        .await;
+    response.assert_status(StatusCode::CREATED);
+    server
+        .put("/docs/_search_profile/broken")
+        .json(&json!({ "fields": [] }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let response = server.get("/docs/_search_profile/web_search").await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["web_search"]["default_operator"], "and");
+
+    // GET with q and POST with a query both pick up the profile
+    let response = server
+        .get("/docs/_search")
+        .add_query_param("q", "rust guide")
+        .add_query_param("search_profile", "web_search")
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["hits"][0]["_id"], "1");
+
+    let response = server
+        .post("/docs/_search")
+        .add_query_param("search_profile", "web_search")
+        .json(&json!({ "query": { "multi_match": { "query": "removal" } } }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    assert_eq!(body["hits"]["hits"][0]["_id"], "2");
+
+    server
+        .get("/docs/_search")
+        .add_query_param("search_profile", "missing")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    server
+        .delete("/docs/_search_profile/web_search")
+        .await
+        .assert_status(StatusCode::OK);
+    let response = server.get("/docs/_search_profile").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body, json!({}));
+}
+
+// ============================================================================
+// Tiering Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_index_tier_routes() {
+    let server = create_test_server();
+    server.put("/logs").await.assert_status(StatusCode::OK);
+
+    let response = server.get("/logs/_tier").await;
+    response.assert_status(StatusCode::OK);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["tier"], "hot");
+
+    // Unknown tier names are rejected, and the in-memory test server has no
+    // disk backend to move documents to
+    server
+        .post("/logs/_tier/cold")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/logs/_tier/warm")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/missing/_tier/hot")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Index Resolution Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_search_resolved_indices_for_wildcard() {
+    let server = create_test_server();
+    server.put("/logs-a").await.assert_status(StatusCode::OK);
+    server.put("/logs-b").await.assert_status(StatusCode::OK);
+    server.put("/metrics").await.assert_status(StatusCode::OK);
+    for (index, id) in [
+        ("logs-a", "1"),
+        ("logs-a", "2"),
+        ("logs-b", "1"),
+        ("metrics", "1"),
+    ] {
+        server
+            .put(&format!("/{}/_doc/{}", index, id))
+            .json(&json!({ "message": "disk full" }))
+            .await;
+    }
+
+    let response = server
+        .post("/logs-*/_search?resolved_indices=true")
+        .json(&json!({ "query": { "match": { "message": "disk" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 3);
+    assert_eq!(
+        body["resolved_indices"],
+        json!({
+            "expression": "logs-*",
+            "indices": [
+                { "index": "logs-a", "total_hits": 2, "returned_hits": 2 },
+                { "index": "logs-b", "total_hits": 1, "returned_hits": 1 }
+            ]
+        })
+    );
+
+    // Without the parameter the response is unchanged
+    let response = server.get("/logs-*/_search").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 3);
+    assert!(body.get("resolved_indices").is_none());
+}
+
+#[tokio::test]
+async fn test_search_many_indices_concurrently() {
+    let state =
+        AppState::new(Arc::new(Storage::new()), "6.8.23").with_search_config(SearchConfig {
+            max_concurrent_index_searches: 2,
+            ..Default::default()
+        });
+    let server = TestServer::new(create_router(state)).unwrap();
+    for i in 0..12 {
+        let index = format!("logs-{:02}", i);
+        server
+            .put(&format!("/{}", index))
+            .await
+            .assert_status(StatusCode::OK);
+        for id in 0..=i {
+            server
+                .put(&format!("/{}/_doc/{}", index, id))
+                .json(&json!({ "message": "disk full" }))
+                .await;
+        }
+    }
+
+    // Results are merged in index order whatever the degree of concurrency
+    for path in [
+        "/logs-*/_search?resolved_indices=true",
+        "/logs-*/_search?resolved_indices=true&max_concurrent_shard_requests=1",
+        "/logs-*/_search?resolved_indices=true&max_concurrent_shard_requests=50",
+    ] {
+        let body: serde_json::Value = server
+            .post(path)
+            .json(&json!({ "query": { "match": { "message": "disk" } } }))
+            .await
+            .json();
+        assert_eq!(body["hits"]["total"]["value"], 78);
+        assert_eq!(body["_shards"]["failed"], 0);
+        let indices = body["resolved_indices"]["indices"].as_array().unwrap();
+        assert_eq!(indices.len(), 12);
+        for (i, entry) in indices.iter().enumerate() {
+            assert_eq!(entry["index"], format!("logs-{:02}", i));
+            assert_eq!(entry["total_hits"], i + 1);
+        }
+    }
+}
+
+/// Log output collected in memory
+#[derive(Clone, Default)]
+struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+impl std::io::Write for LogBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_search_indices_overlap() {
+    // Indices are scored on the searching thread, so that each search keeps
+    // one worker busy
+    let storage = Storage::new().with_parallel_scoring(ParallelScoring {
+        enabled: false,
+        min_docs: 0,
+    });
+    for index in ["busy-a", "busy-b"] {
+        storage.create_index(index, None, None).await.unwrap();
+        for id in 0..20000 {
+            let document = json!({ "message": format!("disk full on host {}", id % 97) });
+            storage
+                .index_document(index, &id.to_string(), document)
+                .await
+                .unwrap();
+        }
+    }
+    let state = AppState::new(Arc::new(storage), "6.8.23");
+    let server = TestServer::new(create_router(state)).unwrap();
+
+    // Order in which the searches of the indices started and completed
+    let search_events = |concurrency: usize| {
+        let buffer = LogBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        let server = &server;
+        async move {
+            let _guard = tracing::subscriber::set_default(subscriber);
+            let body: serde_json::Value = server
+                .post(&format!(
+                    "/busy-*/_search?max_concurrent_shard_requests={}",
+                    concurrency
+                ))
+                .json(&json!({ "query": { "match": { "message": "disk host" } } }))
+                .await
+                .json();
+            assert_eq!(body["hits"]["total"]["value"], 40000);
+            let logs = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            logs.lines()
+                .filter_map(|line| {
+                    if line.contains("documents in index 'busy-") {
+                        Some("started")
+                    } else if line.contains("Search completed for index 'busy-") {
+                        Some("completed")
+                    } else {
+                        None
+                    }
+                })
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // Both indices start searching before either finished
+    assert_eq!(
+        search_events(2).await,
+        ["started", "started", "completed", "completed"]
+    );
+    // One at a time, the second index waits for the first
+    assert_eq!(
+        search_events(1).await,
+        ["started", "completed", "started", "completed"]
+    );
+}
+
+#[tokio::test]
+async fn test_search_sorts_and_paginates_across_indices() {
+    let server = create_test_server();
+    // Prices interleave across the indices
+    for (index, prices) in [("shop-a", [5, 40, 20, 70]), ("shop-b", [10, 30, 60, 50])] {
+        server
+            .put(&format!("/{}", index))
+            .await
+            .assert_status(StatusCode::OK);
+        for (id, price) in prices.iter().enumerate() {
+            server
+                .put(&format!("/{}/_doc/{}", index, id))
+                .json(&json!({ "name": "item", "price": price }))
+                .await;
+        }
+    }
+
+    let response = server
+        .post("/shop-*/_search")
+        .json(&json!({
+            "query": { "match": { "name": "item" } },
+            "sort": [{ "price": "desc" }],
+            "_source": ["name"],
+            "from": 2,
+            "size": 3
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 8);
+    let sorted: Vec<&serde_json::Value> = body["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| &hit["sort"][0])
+        .collect();
+    assert_eq!(sorted, [&json!(50.0), &json!(40.0), &json!(30.0)]);
+    assert!(body["hits"]["max_score"].as_f64().is_some());
+
+    // A page past the first matches of each index still counts every match
+    let body: serde_json::Value = server
+        .post("/shop-*/_search")
+        .json(&json!({ "sort": ["price"], "from": 6, "size": 10 }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 8);
+    let prices: Vec<&serde_json::Value> = body["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| &hit["_source"]["price"])
+        .collect();
+    assert_eq!(prices, [&json!(60), &json!(70)]);
+
+    server
+        .post("/shop-*/_search")
+        .json(&json!({ "sort": [{ "price": "sideways" }] }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_search_path_list_with_exclusions() {
+    let server = create_test_server();
+    for index in ["logs-2023", "logs-2024", "logs-old", "metrics"] {
+        server
+            .put(&format!("/{}", index))
+            .await
+            .assert_status(StatusCode::OK);
+        server
+            .put(&format!("/{}/_doc/1", index))
+            .json(&json!({ "message": "disk full" }))
+            .await;
+    }
+
+    let response = server
+        .get("/logs-*,-logs-old,metrics/_search?resolved_indices=true")
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 3);
+    let indices: Vec<&str> = body["resolved_indices"]["indices"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i["index"].as_str().unwrap())
+        .collect();
+    assert_eq!(indices, ["logs-2023", "logs-2024", "metrics"]);
+
+    // Exclusions apply to the indices listed before them
+    let response = server.post("/logs-20*,-*3/_search").json(&json!({})).await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    assert_eq!(body["hits"]["hits"][0]["_index"], "logs-2024");
+
+    // GET /_search searches all indices
+    let response = server.get("/_search?q=disk").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 4);
+}
+
+#[tokio::test]
+async fn test_search_resolved_indices_for_alias_and_list() {
+    let server = create_test_server();
+    server.put("/events-1").await.assert_status(StatusCode::OK);
+    server.put("/events-2").await.assert_status(StatusCode::OK);
+    server.put("/archive").await.assert_status(StatusCode::OK);
+    server.put("/events-1/_alias/events").await;
+    server.put("/events-2/_alias/events").await;
+    for index in ["events-1", "events-2", "archive"] {
+        for id in 0..3 {
+            server
+                .put(&format!("/{}/_doc/{}", index, id))
+                .json(&json!({ "n": id }))
+                .await;
+        }
+    }
+
+    // Pages are taken from the merged hits, not from each index
+    let response = server
+        .post("/events/_search?resolved_indices=true")
+        .json(&json!({ "from": 2, "size": 3 }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 6);
+    assert_eq!(body["hits"]["hits"].as_array().unwrap().len(), 3);
+    let indices = body["resolved_indices"]["indices"].as_array().unwrap();
+    assert_eq!(indices.len(), 2);
+    assert_eq!(indices[0]["index"], "events-1");
+    assert_eq!(indices[1]["index"], "events-2");
+    let returned: u64 = indices
+        .iter()
+        .map(|i| i["returned_hits"].as_u64().unwrap())
+        .sum();
+    assert_eq!(returned, 3);
+
+    // A single concrete index is reported too
+    let response = server.get("/archive/_search?resolved_indices=true").await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(
+        body["resolved_indices"]["indices"],
+        json!([{ "index": "archive", "total_hits": 3, "returned_hits": 3 }])
+    );
+
+    // Comma-separated lists combine aliases and indices, and every concrete
+    // name must exist
+    let response = server
+        .post("/_search?resolved_indices=true")
+        .json(&json!({ "indices": ["events", "archive"] }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 9);
+    assert_eq!(body["resolved_indices"]["expression"], "events,archive");
+    assert_eq!(
+        body["resolved_indices"]["indices"]
+            .as_array()
+            .unwrap()
+            .len(),
+        3
+    );
+
+    server
+        .get("/events,missing/_search")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Aggregation Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_search_date_histogram_across_indices() {
+    let server = create_test_server();
+    server
+        .put("/logs-2024.03.15")
+        .await
+        .assert_status(StatusCode::OK);
+    server
+        .put("/logs-2024.03.17")
+        .await
+        .assert_status(StatusCode::OK);
+    for (index, timestamp) in [
+        ("logs-2024.03.15", "2024-03-15T10:00:00Z"),
+        ("logs-2024.03.15", "2024-03-15T11:00:00Z"),
+        ("logs-2024.03.17", "2024-03-17T09:00:00Z"),
+    ] {
+        server
+            .post(&format!("/{}/_doc", index))
+            .json(&json!({ "timestamp": timestamp }))
+            .await;
+    }
+    let body = json!({
+        "size": 0,
+        "aggs": { "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } } }
+    });
+
+    let response = server.post("/logs-2024.03.15/_search").json(&body).await;
+    response.assert_status_ok();
+    let result: serde_json::Value = response.json();
+    assert_eq!(
+        result["aggregations"]["per_day"]["buckets"],
+        json!([{ "key_as_string": "2024-03-15T00:00:00.000Z", "key": 1710460800000_i64, "doc_count": 2 }])
+    );
+
+    // Buckets of all indices are merged, with the gap between them filled
+    let response = server.post("/logs-*/_search").json(&body).await;
+    response.assert_status_ok();
+    let result: serde_json::Value = response.json();
+    assert_eq!(result["hits"]["total"]["value"], 3);
+    let counts: Vec<u64> = result["aggregations"]["per_day"]["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| bucket["doc_count"].as_u64().unwrap())
+        .collect();
+    assert_eq!(counts, vec![2, 0, 1]);
+
+    server
+        .post("/logs-*/_search")
+        .json(&json!({ "aggs": { "levels": { "terms": { "field": "level" } } } }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Request Limit Tests
+// ============================================================================
+
+fn create_limited_test_server() -> TestServer {
+    let state =
+        AppState::new(Arc::new(Storage::new()), "6.8.23").with_request_limits(RequestLimits {
+            max_body_bytes: 1024,
+            max_bulk_actions: 2,
+            max_document_bytes: 64,
+        });
+    TestServer::new(create_router(state)).unwrap()
+}
+
+#[tokio::test]
+async fn test_request_body_limit() {
+    let server = create_limited_test_server();
+    server.put("/test_index").await.assert_status_ok();
+
+    let response = server
+        .put("/test_index/_doc/1")
+        .json(&json!({ "text": "x".repeat(2048) }))
+        .await;
+    response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+    let body: serde_json::Value = response.json();
+    assert!(body["error"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("exceeds the limit of 1024 bytes"));
+}
+
+#[tokio::test]
+async fn test_document_size_limit() {
+    let server = create_limited_test_server();
+    server.put("/test_index").await.assert_status_ok();
+
+    server
+        .put("/test_index/_doc/1")
+        .json(&json!({ "text": "small" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .put("/test_index/_doc/2")
+        .json(&json!({ "text": "x".repeat(100) }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/test_index/_doc")
+        .json(&json!({ "text": "x".repeat(100) }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_bulk_limits() {
+    let server = create_limited_test_server();
+    server.put("/test_index").await.assert_status_ok();
+
+    // An oversized document fails its own item only
+    let body = format!(
+        "{}\n{}\n{}\n{}\n",
+        json!({ "index": { "_id": "1" } }),
+        json!({ "text": "small" }),
+        json!({ "index": { "_id": "2" } }),
+        json!({ "text": "x".repeat(100) }),
+    );
+    let response = server
+        .post("/test_index/_bulk")
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await;
+    response.assert_status_ok();
+    let result: serde_json::Value = response.json();
+    assert_eq!(result["errors"], true);
+    assert_eq!(result["items"][0]["index"]["status"], 201);
+    assert_eq!(result["items"][1]["index"]["status"], 400);
+    server.get("/test_index/_doc/1").await.assert_status_ok();
+    server
+        .get("/test_index/_doc/2")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    // Too many actions reject the whole request
+    let body = (3..6)
+        .map(|id| {
+            format!(
+                "{}\n{}\n",
+                json!({ "delete": { "_id": id.to_string() } }),
+                ""
+            )
+        })
+        .collect::<String>();
+    server
+        .post("/test_index/_bulk")
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Usage Tests
+// ============================================================================
+
+fn api_key(id: &str, key: &str) -> String {
+    format!(
+        "ApiKey {}",
+        base64::engine::general_purpose::STANDARD.encode(format!("{}:{}", id, key))
+    )
+}
+
+#[tokio::test]
+async fn test_usage_per_index_and_api_key() {
+    let server = create_test_server();
+    server.put("/logs").await.assert_status_ok();
+    server.put("/metrics").await.assert_status_ok();
+
+    server
+        .put("/logs/_doc/1")
+        .add_header("Authorization", api_key("team-a", "secret"))
+        .json(&json!({ "message": "hello" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .post("/logs/_search")
+        .add_header("Authorization", api_key("team-a", "secret"))
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+    let body = format!(
+        "{}\n{}\n",
+        json!({ "index": { "_index": "metrics", "_id": "1" } }),
+        json!({ "cpu": 0.5 }),
+    );
+    server
+        .post("/_bulk")
+        .add_header("Authorization", basic_auth("team-b", "secret"))
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await
+        .assert_status_ok();
+
+    let response = server.get("/_gbs/usage").await;
+    response.assert_status_ok();
+    let usage: serde_json::Value = response.json();
+    assert_eq!(usage["bucket_secs"], 300);
+
+    let team_a = &usage["totals"]["api_keys"]["team-a"];
+    assert_eq!(team_a["requests"], 2);
+    assert_eq!(team_a["searches"], 1);
+    assert!(team_a["bytes_indexed"].as_u64().unwrap() > 0);
+    assert!(team_a["bytes_returned"].as_u64().unwrap() > 0);
+
+    let team_b = &usage["totals"]["api_keys"]["team-b"];
+    assert_eq!(team_b["requests"], 1);
+    assert_eq!(
+        team_b["bytes_indexed"],
+        json!({ "cpu": 0.5 }).to_string().len()
+    );
+
+    let logs = &usage["totals"]["indices"]["logs"];
+    assert_eq!(logs["requests"], 3);
+    assert_eq!(logs["searches"], 1);
+    let metrics = &usage["totals"]["indices"]["metrics"];
+    assert_eq!(metrics["requests"], 1);
+    assert_eq!(metrics["bytes_indexed"], team_b["bytes_indexed"]);
+
+    let history = usage["history"].as_array().unwrap();
+    assert!(!history.is_empty());
+    let requests: u64 = history
+        .iter()
+        .map(|bucket| {
+            bucket["api_keys"]["team-a"]["requests"]
+                .as_u64()
+                .unwrap_or(0)
+        })
+        .sum();
+    assert_eq!(requests, 2);
+}
+
+// ============================================================================
+// Transaction Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_transaction_commits_all_operations() {
+    let server = create_test_server();
+    server.put("/inventory").await.assert_status_ok();
+    server
+        .put("/inventory/_doc/b")
+        .json(&json!({ "stock": 1 }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let response = server
+        .post("/inventory/_txn")
+        .json(&json!({
+            "operations": [
+                { "index": { "_id": "a", "doc": { "stock": 5 } } },
+                { "update": { "_id": "a", "doc": { "stock": 4 } } },
+                { "delete": { "_id": "b" } }
+            ]
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["committed"], true);
+    assert_eq!(body["items"][0]["index"]["status"], 201);
+    assert_eq!(body["items"][1]["update"]["result"], "updated");
+    assert_eq!(body["items"][2]["delete"]["_id"], "b");
+
+    let document: serde_json::Value = server.get("/inventory/_doc/a").await.json();
+    assert_eq!(document["_source"]["stock"], 4);
+    server
+        .get("/inventory/_doc/b")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_transaction_aborts_on_failed_operation() {
+    let server = create_test_server();
+    server.put("/inventory").await.assert_status_ok();
+
+    let response = server
+        .post("/inventory/_txn")
+        .json(&json!({
+            "operations": [
+                { "index": { "_id": "a", "doc": { "stock": 5 } } },
+                { "delete": { "_id": "missing" } }
+            ]
+        }))
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["committed"], false);
+    assert_eq!(body["failed_operation"], 1);
+    assert!(body.get("items").is_none());
+    server
+        .get("/inventory/_doc/a")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    server
+        .post("/inventory/_txn")
+        .json(&json!({ "operations": [{ "upsert": { "_id": "a" } }] }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Reindex Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_reindex_endpoint() {
+    let server = create_test_server();
+    server.put("/old").await.assert_status_ok();
+    server
+        .put("/old/_doc/1")
+        .json(&json!({ "title": "first", "tmp": 1 }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let response = server
+        .post("/_reindex")
+        .json(&json!({
+            "source": { "index": "old", "_source": { "excludes": ["tmp"] } },
+            "dest": { "index": "new" },
+            "rename": { "title": "name" }
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["total"], 1);
+    assert_eq!(body["created"], 1);
+    assert_eq!(body["failures"], json!([]));
+
+    let document: serde_json::Value = server.get("/new/_doc/1").await.json();
+    assert_eq!(document["_source"], json!({ "name": "first" }));
+
+    server
+        .post("/_reindex")
+        .json(&json!({ "source": { "index": "missing" }, "dest": { "index": "new" } }))
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Index Template Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_legacy_template_endpoints() {
+    let server = create_test_server();
+    server
+        .put("/_template/logs")
+        .json(&json!({
+            "index_patterns": ["logs-*"],
+            "order": 1,
+            "mappings": { "properties": { "level": { "type": "keyword" } } },
+            "aliases": { "logs": {} }
+        }))
+        .await
+        .assert_status_ok();
+
+    let body: serde_json::Value = server.get("/_template/logs").await.json();
+    assert_eq!(body["logs"]["index_patterns"], json!(["logs-*"]));
+    assert_eq!(body["logs"]["order"], 1);
+    let all: serde_json::Value = server.get("/_template").await.json();
+    assert!(all.get("logs").is_some());
+
+    // Writes to a missing index the template matches create it
+    server
+        .put("/logs-1/_doc/1")
+        .json(&json!({ "level": "warn" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    let index: serde_json::Value = server.get("/logs-1").await.json();
+    assert_eq!(
+        index["logs-1"]["mappings"]["properties"]["level"]["type"],
+        json!("keyword")
+    );
+    assert!(index["logs-1"]["aliases"].get("logs").is_some());
+    server.get("/logs-1/_doc/1").await.assert_status_ok();
+
+    server.delete("/_template/logs").await.assert_status_ok();
+    server
+        .get("/_template/logs")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_composable_template_endpoints() {
+    let server = create_test_server();
+    server
+        .put("/_index_template/events")
+        .json(&json!({
+            "index_patterns": ["events-*"],
+            "priority": 100,
+            "template": { "settings": { "number_of_replicas": 0 } },
+            "_meta": { "owner": "ops" }
+        }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/_index_template/bad")
+        .json(&json!({ "index_patterns": ["x-*"], "composed_of": ["component"] }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let body: serde_json::Value = server.get("/_index_template/events").await.json();
+    assert_eq!(body["index_templates"][0]["name"], "events");
+    let template = &body["index_templates"][0]["index_template"];
+    assert_eq!(template["priority"], 100);
+    assert_eq!(template["_meta"]["owner"], "ops");
+
+    server.put("/events-1").await.assert_status_ok();
+    let index: serde_json::Value = server.get("/events-1").await.json();
+    assert!(index["events-1"]["settings"]
+        .to_string()
+        .contains("number_of_replicas"));
+
+    server
+        .delete("/_index_template/events")
+        .await
+        .assert_status_ok();
+    server
+        .delete("/_index_template/events")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Warmer Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_warmer_endpoints() {
+    let server = create_test_server();
+    server.put("/events").await.assert_status_ok();
+    server
+        .put("/events/_warmer/recent")
+        .json(&json!({ "query": { "match_all": {} }, "size": 10 }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/events/_warmer/bad")
+        .json(&json!({ "highlight": {} }))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    let body: serde_json::Value = server.get("/events/_warmer").await.json();
+    assert_eq!(body["events"]["warmers"]["recent"]["size"], 10);
+    let body: serde_json::Value = server.get("/events/_warmer/recent").await.json();
+    assert_eq!(
+        body["events"]["warmers"]["recent"]["query"],
+        json!({ "match_all": {} })
+    );
+
+    server.post("/events/_refresh").await.assert_status_ok();
+    server.post("/_refresh").await.assert_status_ok();
+
+    server
+        .delete("/events/_warmer/recent")
+        .await
+        .assert_status_ok();
+    server
+        .get("/events/_warmer/recent")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Multi-Search Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_msearch_runs_each_search() {
+    let server = create_test_server();
+    server.put("/books").await.assert_status_ok();
+    server.put("/movies").await.assert_status_ok();
+    for (index, id, title) in [
+        ("books", "1", "Rust in Action"),
+        ("books", "2", "Python Tricks"),
+        ("movies", "1", "Rust and Bone"),
+    ] {
+        server
+            .put(&format!("/{}/_doc/{}", index, id))
+            .json(&json!({ "title": title }))
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    let body = [
+        r#"{"index":"books"}"#,
+        r#"{"query":{"match":{"title":"rust"}}}"#,
+        r#"{"index":["books","movies"]}"#,
+        r#"{"query":{"match":{"title":"rust"}},"size":1}"#,
+        r#"{}"#,
+        r#"{"query":{"match_all":{}}}"#,
+        r#"{"index":"missing"}"#,
+        r#"{"query":{"match_all":{}}}"#,
+    ]
+    .join("\n");
+    let response = server
+        .post("/_msearch")
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let responses = body["responses"].as_array().unwrap();
+    assert_eq!(responses.len(), 4);
+
+    assert_eq!(responses[0]["status"], 200);
+    assert_eq!(responses[0]["hits"]["total"]["value"], 1);
+    assert_eq!(responses[1]["hits"]["total"]["value"], 2);
+    assert_eq!(responses[1]["hits"]["hits"].as_array().unwrap().len(), 1);
+    assert_eq!(responses[2]["hits"]["total"]["value"], 3);
+    assert_eq!(responses[3]["status"], 404);
+    assert!(responses[3]["error"]["reason"]
+        .as_str()
+        .unwrap()
+        .contains("missing"));
+}
+
+#[tokio::test]
+async fn test_msearch_uses_path_index_and_rejects_malformed_bodies() {
+    let server = create_test_server();
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Rust in Action" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let response = server
+        .post("/books/_msearch")
+        .content_type("application/x-ndjson")
+        .text("{}\n{\"query\":{\"term\":{\"_id\":\"1\"}}}\n")
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["responses"][0]["hits"]["hits"][0]["_index"], "books");
+
+    server
+        .post("/_msearch")
+        .content_type("application/x-ndjson")
+        .text("{\"index\":\"books\"}\n")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/_msearch")
+        .content_type("application/x-ndjson")
+        .text("{\"index\":\"books\"}\nnot json\n")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Document Existence and Source Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_head_document_and_get_source() {
+    let server = create_test_server();
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Rust in Action", "year": 2021 }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    for path in ["/books/_doc/1", "/books/_source/1"] {
+        let response = server.method(axum_test::http::Method::HEAD, path).await;
+        response.assert_status_ok();
+        assert!(response.as_bytes().is_empty());
+    }
+    for path in ["/books/_doc/2", "/books/_source/2", "/missing/_doc/1"] {
+        server
+            .method(axum_test::http::Method::HEAD, path)
+            .await
+            .assert_status(StatusCode::NOT_FOUND);
+    }
+
+    let body: serde_json::Value = server.get("/books/_source/1").await.json();
+    assert_eq!(body, json!({ "title": "Rust in Action", "year": 2021 }));
+    server
+        .get("/books/_source/2")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Cat API Tests
+// ============================================================================
+
+async fn create_cat_fixture(server: &TestServer) {
+    server
+        .put("/books")
+        .json(&json!({ "settings": { "number_of_shards": 1, "number_of_replicas": 0 } }))
+        .await
+        .assert_status_ok();
+    server.put("/movies").await.assert_status_ok();
+    server.put("/books/_alias/library").await.assert_status_ok();
+    for id in ["1", "2"] {
+        server
+            .put(&format!("/books/_doc/{}", id))
+            .json(&json!({ "title": "Rust in Action" }))
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+}
+
+#[tokio::test]
+async fn test_cat_indices_columns_json_and_bytes() {
+    let server = create_test_server();
+    create_cat_fixture(&server).await;
+
+    let text = server.get("/_cat/indices?v").await.text();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("health status index"));
+    assert!(lines[1].contains("books") && lines[2].contains("movies"));
+
+    let body: serde_json::Value = server
+        .get("/_cat/indices/books?format=json&h=index,dc,rep,store.size&bytes=b")
+        .await
+        .json();
+    let rows = body.as_array().unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0]["index"], "books");
+    assert_eq!(rows[0]["docs.count"], "2");
+    assert_eq!(rows[0]["rep"], "0");
+    let size: u64 = rows[0]["store.size"].as_str().unwrap().parse().unwrap();
+    assert!(size > 0);
+    assert_eq!(rows[0].as_object().unwrap().len(), 4);
+
+    let text = server.get("/_cat/indices?h=index,docs.count").await.text();
+    assert_eq!(text, "books  2\nmovies 0\n");
+    let text = server.get("/_cat/indices?h=index,health").await.text();
+    assert_eq!(text, "books  green\nmovies yellow\n");
+
+    server
+        .get("/_cat/indices?h=nope")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/_cat/indices?bytes=zb")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/_cat/indices/missing")
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_cat_health_count_aliases_and_shards() {
+    let server = create_test_server();
+    create_cat_fixture(&server).await;
+
+    // The replica of movies has no second node to go to
+    let body: serde_json::Value = server.get("/_cat/health?format=json").await.json();
+    assert_eq!(body[0]["status"], "yellow");
+    assert_eq!(body[0]["cluster"], "gbs");
+    assert_eq!(body[0]["node.total"], "1");
+    assert_eq!(body[0]["shards"], "2");
+    assert_eq!(body[0]["unassign"], "1");
+    assert_eq!(body[0]["active_shards_percent"], "66.7%");
+
+    let body: serde_json::Value = server.get("/_cat/count?format=json").await.json();
+    assert_eq!(body[0]["count"], "2");
+    let text = server.get("/_cat/count/movies?h=count").await.text();
+    assert_eq!(text, "0\n");
+    let body: serde_json::Value = server.get("/_cat/count/library?format=json").await.json();
+    assert_eq!(body[0]["count"], "2");
+
+    let body: serde_json::Value = server.get("/_cat/aliases?format=json").await.json();
+    assert_eq!(
+        body,
+        json!([{
+            "alias": "library",
+            "index": "books",
+            "filter": "-",
+            "routing.index": "-",
+            "routing.search": "-",
+            "is_write_index": "-"
+        }])
+    );
+    let body: serde_json::Value = server.get("/_cat/aliases/lib*?format=json").await.json();
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    let body: serde_json::Value = server.get("/_cat/aliases/other?format=json").await.json();
+    assert!(body.as_array().unwrap().is_empty());
+
+    let body: serde_json::Value = server
+        .get("/_cat/shards/books?format=json&h=index,shard,prirep,state,docs")
+        .await
+        .json();
+    assert_eq!(
+        body,
+        json!([{ "index": "books", "shard": "0", "prirep": "p", "state": "STARTED", "docs": "2" }])
+    );
+    let text = server.get("/_cat/shards?v").await.text();
+    assert_eq!(text.lines().count(), 3);
+}
+
+// ============================================================================
+// Nodes API Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_nodes_info_reports_version_roles_and_http_address() {
+    let storage = Storage::new();
+    let state = AppState::new(Arc::new(storage), "7.17.0")
+        .with_http_address("0.0.0.0:9201".parse().unwrap());
+    let server = TestServer::new(create_router(state)).unwrap();
+
+    let body: serde_json::Value = server.get("/_nodes").await.json();
+    assert_eq!(body["_nodes"]["total"], 1);
+    let nodes = body["nodes"].as_object().unwrap();
+    assert_eq!(nodes.len(), 1);
+    let (id, node) = nodes.iter().next().unwrap();
+    assert_eq!(node["version"], "7.17.0");
+    assert_eq!(node["build_flavor"], "default");
+    assert_eq!(node["http"]["publish_address"], "127.0.0.1:9201");
+    assert_eq!(node["http"]["bound_address"][0], "0.0.0.0:9201");
+    assert!(node["roles"]
+        .as_array()
+        .unwrap()
+        .contains(&json!("remote_cluster_client")));
+    assert!(node["process"]["id"].as_u64().unwrap() > 0);
+
+    // Sniffers ask for the http section only, of all or specific nodes
+    for path in [
+        "/_nodes/http",
+        "/_nodes/_all/http",
+        &format!("/_nodes/{}/http", id),
+    ] {
+        let body: serde_json::Value = server.get(path).await.json();
+        let node = &body["nodes"][id];
+        assert_eq!(
+            node["http"]["publish_address"], "127.0.0.1:9201",
+            "{}",
+            path
+        );
+        assert_eq!(node["version"], "7.17.0");
+        assert!(node.get("os").is_none(), "{}", path);
+    }
+    let body: serde_json::Value = server.get("/_nodes/other-node").await.json();
+    assert_eq!(body["_nodes"]["total"], 0);
+    assert!(body["nodes"].as_object().unwrap().is_empty());
+    server
+        .get("/_nodes/_local/bogus")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_nodes_info_roles_follow_es_version() {
+    let server = create_test_server();
+    let body: serde_json::Value = server.get("/_nodes").await.json();
+    let node = body["nodes"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+    assert_eq!(node["version"], "6.8.23");
+    assert_eq!(node["roles"], json!(["master", "data", "ingest"]));
+}
+
+#[tokio::test]
+async fn test_nodes_stats() {
+    let server = create_test_server();
+    server.put("/books").await.assert_status_ok();
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Rust in Action" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let body: serde_json::Value = server.get("/_nodes/stats").await.json();
+    let node = body["nodes"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+    assert_eq!(node["indices"]["docs"]["count"], 1);
+    assert!(node["indices"]["store"]["size_in_bytes"].as_u64().unwrap() > 0);
+    assert!(node["jvm"]["uptime_in_millis"].is_u64());
+    assert!(node["process"]["mem"]["resident_in_bytes"].is_u64());
+    if cfg!(target_os = "linux") {
+        assert!(
+            node["process"]["mem"]["resident_in_bytes"]
+                .as_u64()
+                .unwrap()
+                > 0
+        );
+        assert!(node["process"]["open_file_descriptors"].as_u64().unwrap() > 0);
+    }
+    assert!(node["timestamp"].is_i64());
+
+    let body: serde_json::Value = server.get("/_nodes/_local/stats/jvm,os").await.json();
+    let node = body["nodes"]
+        .as_object()
+        .unwrap()
+        .values()
+        .next()
+        .unwrap()
+        .clone();
+    assert!(node.get("jvm").is_some() && node.get("os").is_some());
+    assert!(node.get("indices").is_none());
+
+    server
+        .get("/_nodes/stats/bogus")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+// ============================================================================
+// Task Management Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_background_reindex_task() {
+    let server = create_test_server();
+    server.put("/old").await.assert_status_ok();
+    for id in 0..3 {
+        server
+            .put(&format!("/old/_doc/{}", id))
+            .json(&json!({ "n": id }))
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    let response = server
+        .post("/_reindex?wait_for_completion=false")
+        .json(&json!({ "source": { "index": "old" }, "dest": { "index": "new" } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let task_id = body["task"].as_str().unwrap().to_string();
+    assert!(task_id.contains(':'));
+
+    let mut task = json!({});
+    for _ in 0..100 {
+        task = server.get(&format!("/_tasks/{}", task_id)).await.json();
+        if task["completed"] == true {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(task["completed"], true);
+    assert_eq!(task["task"]["action"], "indices:data/write/reindex");
+    assert_eq!(task["task"]["description"], "reindex from [old] to [new]");
+    assert_eq!(task["response"]["created"], 3);
+
+    // Completed tasks are no longer listed
+    let body: serde_json::Value = server.get("/_tasks").await.json();
+    assert_eq!(body, json!({ "nodes": {} }));
+}
+
+#[tokio::test]
+async fn test_delete_by_query_endpoint() {
+    let server = create_test_server();
+    server.put("/logs").await.assert_status_ok();
+    for (id, level) in [("1", "debug"), ("2", "error"), ("3", "debug")] {
+        server
+            .put(&format!("/logs/_doc/{}", id))
+            .json(&json!({ "level": level }))
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+
+    let response = server
+        .post("/logs/_delete_by_query")
+        .json(&json!({ "query": { "term": { "level": "debug" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["deleted"], 2);
+    assert_eq!(body["failures"], json!([]));
+    server.get("/logs/_doc/1").await.assert_status_not_found();
+    server.get("/logs/_doc/2").await.assert_status_ok();
+
+    server
+        .post("/logs/_delete_by_query")
+        .json(&json!({}))
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_task_ids_validated() {
+    let server = create_test_server();
+    server
+        .get("/_tasks/not-a-task")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/_tasks/othernode:1")
+        .await
+        .assert_status_not_found();
+    server
+        .post("/_tasks/othernode:1/_cancel")
+        .await
+        .assert_status_not_found();
+
+    let response = server.post("/_tasks/_cancel?actions=*reindex").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body, json!({ "nodes": {} }));
+}
+
+// ============================================================================
+// Metrics Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_metrics_endpoint() {
+    let server = create_test_server();
+    server.put("/products").await.assert_status_ok();
+    server
+        .put("/products/_doc/1")
+        .json(&json!({ "name": "laptop" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .post("/products/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+    server
+        .get("/missing/_doc/1")
+        .await
+        .assert_status_not_found();
+
+    let response = server.get("/_metrics").await;
+    response.assert_status_ok();
+    assert!(response
+        .header("content-type")
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    let body = response.text();
+    assert!(body.contains("# TYPE gbs_http_requests_total counter"));
+    assert!(body.contains(
+        "gbs_http_requests_total{method=\"PUT\",route=\"/:index/_doc/:id\",status=\"201\"} 1"
+    ));
+    assert!(body.contains(
+        "gbs_http_requests_total{method=\"GET\",route=\"/:index/_doc/:id\",status=\"404\"} 1"
+    ));
+    assert!(body.contains(
+        "gbs_http_request_duration_seconds_count{method=\"POST\",route=\"/:index/_search\"} 1"
+    ));
+    assert!(body.contains(
+        "gbs_http_request_duration_seconds_bucket{method=\"POST\",route=\"/:index/_search\",le=\"+Inf\"} 1"
+    ));
+    assert!(body.contains("gbs_documents_indexed_total{index=\"products\"} 1"));
+    assert!(body.contains("gbs_searches_total{index=\"products\"} 1"));
+    assert!(body.contains("gbs_index_documents{index=\"products\"} 1"));
+    assert!(body.contains("gbs_index_size_bytes{index=\"products\"}"));
+    assert!(body.contains("gbs_indices 1"));
+}
+
+// ============================================================================
+// Change Feed Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_changes_endpoint() {
+    let server = create_test_server();
+    server.put("/orders").await.assert_status_ok();
+    server
+        .put("/orders/_doc/1")
+        .json(&json!({ "status": "new" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+    server
+        .put("/orders/_doc/1")
+        .json(&json!({ "status": "paid" }))
+        .await
+        .assert_status_ok();
+    server.delete("/orders/_doc/1").await.assert_status_ok();
+
+    let response = server.get("/orders/_changes").await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["_index"], "orders");
+    assert_eq!(body["last_seq"], 3);
+    let changes = body["changes"].as_array().unwrap();
+    assert_eq!(changes.len(), 3);
+    assert_eq!(changes[0]["seq"], 1);
+    assert_eq!(changes[0]["op"], "create");
+    assert_eq!(changes[0]["_id"], "1");
+    assert_eq!(changes[1]["op"], "update");
+    assert_eq!(changes[2]["op"], "delete");
+
+    let body: serde_json::Value = server
+        .get("/orders/_changes?since=3&timeout=10ms")
+        .await
+        .json();
+    assert_eq!(body["changes"], json!([]));
+    assert_eq!(body["last_seq"], 3);
+
+    server
+        .get("/orders/_changes?since=abc")
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .get("/missing/_changes")
+        .await
+        .assert_status_not_found();
+}
+
+// ============================================================================
+// Shard Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_shards_in_responses() {
+    let server = common::test_server(common::sharded_logs().await, "6.8.23");
+
+    let body: Value = server
+        .post("/logs/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    assert_eq!(
+        body["_shards"],
+        json!({ "total": 3, "successful": 3, "skipped": 0, "failed": 0 })
+    );
+
+    let body: Value = server
+        .post("/logs,users/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    assert_eq!(body["_shards"]["total"], 4);
+    assert_eq!(body["_shards"]["successful"], 4);
+
+    let body: Value = server.get("/_stats").await.json();
+    assert_eq!(body["_shards"]["total"], 4);
+
+    let body: Value = server.get("/_cluster/health").await.json();
+    assert_eq!(body["active_primary_shards"], 4);
+
+    let body: Value = server.get("/_cluster/stats").await.json();
+    assert_eq!(body["indices"]["shards"]["total"], 4);
+    assert_eq!(body["indices"]["shards"]["index"]["shards"]["max"], 3);
+
+    let rows: Value = server
+        .get("/_cat/shards/logs?format=json&h=index,shard,docs")
+        .await
+        .json();
+    assert_eq!(
+        rows,
+        json!([
+            { "index": "logs", "shard": "0", "docs": "2" },
+            { "index": "logs", "shard": "1", "docs": "1" },
+            { "index": "logs", "shard": "2", "docs": "1" }
+        ])
+    );
+}
+
+#[tokio::test]
+async fn test_search_shards() {
+    let server = common::test_server(common::sharded_logs().await, "6.8.23");
+
+    let body: Value = server.get("/logs/_search_shards").await.json();
+    let nodes = body["nodes"].as_object().unwrap();
+    assert_eq!(nodes.len(), 1);
+    let node_id = nodes.keys().next().unwrap().clone();
+    assert_eq!(body["indices"], json!({ "logs": {} }));
+    let shards = body["shards"].as_array().unwrap();
+    assert_eq!(shards.len(), 3);
+    for (number, group) in shards.iter().enumerate() {
+        let copy = &group[0];
+        assert_eq!(copy["shard"], number);
+        assert_eq!(copy["index"], "logs");
+        assert_eq!(copy["primary"], true);
+        assert_eq!(copy["state"], "STARTED");
+        assert_eq!(copy["node"], node_id.as_str());
+    }
+
+    let body: Value = server.post("/_search_shards").await.json();
+    assert_eq!(body["shards"].as_array().unwrap().len(), 4);
+
+    server
+        .get("/missing/_search_shards")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+// ============================================================================
+// Force Merge, Flush, Cache Clearing and Segments Tests
+// ============================================================================
+
+#[tokio::test]
+async fn test_force_merge() {
+    let server = common::test_server(common::sharded_logs().await, "7.10.2");
+
+    let body: Value = server
+        .post("/logs/_forcemerge?max_num_segments=1")
+        .await
+        .json();
+    assert_eq!(
+        body["_shards"],
+        json!({ "total": 3, "successful": 3, "failed": 0 })
+    );
+    let body: Value = server.post("/_forcemerge?flush=false").await.json();
+    assert_eq!(body["_shards"]["total"], 4);
+
+    // Documents survive the merge
+    let body: Value = server
+        .post("/logs/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 4);
+
+    server
+        .post("/logs/_forcemerge?only_expunge_deletes=true&max_num_segments=1")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/logs/_forcemerge?max_num_segments=0")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/missing/_forcemerge")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+
+    server.post("/users/_close").await.assert_status_ok();
+    server
+        .post("/users/_forcemerge")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_flush() {
+    let server = common::test_server(common::sharded_logs().await, "7.10.2");
+
+    let body: Value = server.post("/logs/_flush").await.json();
+    assert_eq!(
+        body["_shards"],
+        json!({ "total": 3, "successful": 3, "failed": 0 })
+    );
+    let body: Value = server.post("/_flush").await.json();
+    assert_eq!(body["_shards"]["total"], 4);
+    server
+        .post("/missing/_flush")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_clear_cache() {
+    let storage = Arc::new(Storage::new());
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({ "level": "error", "timestamp": "2024-03-14T08:00:00Z" }),
+        )
+        .await
+        .unwrap();
+    let server = TestServer::new(create_router(AppState::new(storage.clone(), "7.10.2"))).unwrap();
+    let fill_caches = || async {
+        server
+            .post("/logs/_search")
+            .json(&json!({
+                "size": 0,
+                "query": { "bool": { "filter": [{ "term": { "level": "error" } }] } },
+                "aggs": { "per_day": { "date_histogram": {
+                    "field": "timestamp", "calendar_interval": "day"
+                } } }
+            }))
+            .await
+            .assert_status_ok();
+    };
+    let cached_filters = || async {
+        let body: Value = server.get("/logs/_stats/query_cache").await.json();
+        body["indices"]["logs"]["total"]["query_cache"]["cache_size"].clone()
+    };
+
+    fill_caches().await;
+    assert_eq!(cached_filters().await, 1);
+    assert_eq!(storage.aggregation_cache_stats().entries, 1);
+
+    // Each kind of cache can be cleared on its own
+    let body: Value = server.post("/logs/_cache/clear?request=true").await.json();
+    assert_eq!(body["_shards"]["total"], 1);
+    assert_eq!(cached_filters().await, 1);
+    assert_eq!(storage.aggregation_cache_stats().entries, 0);
+    server
+        .post("/logs/_cache/clear?query=true")
+        .await
+        .assert_status_ok();
+    assert_eq!(cached_filters().await, 0);
+
+    // Without a kind, every cache is cleared
+    fill_caches().await;
+    server.post("/_cache/clear").await.assert_status_ok();
+    assert_eq!(cached_filters().await, 0);
+    assert_eq!(storage.aggregation_cache_stats().entries, 0);
+
+    server
+        .post("/missing/_cache/clear")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_segments() {
+    let server = common::test_server(common::sharded_logs().await, "7.10.2");
+
+    let body: Value = server.get("/logs/_segments").await.json();
+    assert_eq!(body["_shards"]["total"], 3);
+    let shards = body["indices"]["logs"]["shards"].as_object().unwrap();
+    assert_eq!(shards.len(), 3);
+    let mut docs = 0;
+    for copies in shards.values() {
+        let shard = &copies[0];
+        assert_eq!(shard["routing"]["primary"], true);
+        assert_eq!(shard["num_committed_segments"], 1);
+        let segment = &shard["segments"]["_0"];
+        assert_eq!(segment["committed"], true);
+        assert_eq!(segment["deleted_docs"], 0);
+        assert_eq!(segment["version"], "8.11.1");
+        docs += segment["num_docs"].as_u64().unwrap();
+    }
+    assert_eq!(docs, 4);
+
+    // Empty shards have no segments
+    let body: Value = server.get("/_segments").await.json();
+    let users = &body["indices"]["users"]["shards"]["0"][0];
+    assert_eq!(users["num_search_segments"], 0);
+    assert_eq!(users["segments"], json!({}));
+
+    server.post("/users/_close").await.assert_status_ok();
+    let body: Value = server.get("/_segments").await.json();
+    assert!(body["indices"].get("users").is_none());
+    server
+        .get("/users/_segments")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[cfg(feature = "tls")]
+mod tls {
+    use super::*;
+    use gbs::config::TlsConfig;
+    use gbs::server::{redirect_router, serve_tls, tls_acceptor, CertificateResolver};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::rustls::{self, pki_types::ServerName, RootCertStore};
+    use tokio_rustls::TlsConnector;
+
+    /// Write a self-signed certificate for `localhost`, returning it in DER
+    fn write_certificate(config: &TlsConfig) -> Vec<u8> {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(&config.cert_path, certified.cert.pem()).unwrap();
+        std::fs::write(&config.key_path, certified.key_pair.serialize_pem()).unwrap();
+        certified.cert.der().to_vec()
+    }
+
+    /// Send `GET /` over TLS trusting only `certificate`
+    async fn get_root(
+        address: std::net::SocketAddr,
+        certificate: &[u8],
+    ) -> std::io::Result<String> {
+        let mut roots = RootCertStore::empty();
+        roots.add(certificate.to_vec().into()).unwrap();
+        let config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        let stream = tokio::net::TcpStream::connect(address).await?;
+        let mut stream = TlsConnector::from(Arc::new(config))
+            .connect(ServerName::try_from("localhost").unwrap(), stream)
+            .await?;
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_https_serves_the_reloaded_certificate() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TlsConfig {
+            cert_path: dir.path().join("cert.pem").display().to_string(),
+            key_path: dir.path().join("key.pem").display().to_string(),
+            redirect_port: None,
+            reload_interval_secs: 3600,
+        };
+        let first = write_certificate(&config);
+        let certificates = Arc::new(CertificateResolver::load(&config).unwrap());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let app = create_router(AppState::new(Arc::new(Storage::new()), "6.8.23"));
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let server = tokio::spawn(serve_tls(
+            listener,
+            tls_acceptor(certificates.clone()).unwrap(),
+            app,
+            async {
+                let _ = stopped.await;
+            },
+        ));
+
+        let response = get_root(address, &first).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"), "{}", response);
+        assert!(response.contains("\"tagline\""));
+
+        // Renewed certificates are served once reloaded
+        let second = write_certificate(&config);
+        assert!(get_root(address, &second).await.is_err());
+        certificates.reload().unwrap();
+        let response = get_root(address, &second).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200"));
+        assert!(get_root(address, &first).await.is_err());
+
+        // A broken certificate is not loaded
+        std::fs::write(&config.cert_path, "not a certificate").unwrap();
+        assert!(certificates.reload().is_err());
+        assert!(get_root(address, &second).await.is_ok());
+
+        stop.send(()).unwrap();
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_http_is_redirected_to_https() {
+        let server = TestServer::new(redirect_router(9443)).unwrap();
+        let response = server
+            .get("/logs/_search")
+            .add_query_param("q", "level:error")
+            .add_header("Host", "localhost:9200")
+            .await;
+        response.assert_status(StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.header("location"),
+            "https://localhost:9443/logs/_search?q=level%3Aerror"
+        );
+    }
+}
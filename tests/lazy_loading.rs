@@ -0,0 +1,146 @@
+//! Tests for lazy index loading and the document cache
+
+mod common;
+
+use common::open_sled;
+use gbs::storage::{IndexTier, Storage};
+use serde_json::json;
+use std::path::Path;
+use tempfile::TempDir;
+
+async fn write_books(path: &Path) {
+    let storage = open_sled(path).await;
+    storage.create_index("books", None, None).await.unwrap();
+    for (id, title) in [("1", "dune"), ("2", "emma"), ("3", "dune messiah")] {
+        storage
+            .index_document("books", id, json!({ "title": title }))
+            .await
+            .unwrap();
+    }
+    storage.flush().await.unwrap();
+}
+
+async fn open_lazily(path: &Path) -> Storage {
+    let storage = Storage::with_sled(path).unwrap().with_lazy_loading(true);
+    storage.load_from_backend().await.unwrap();
+    storage
+}
+
+#[tokio::test]
+async fn test_lazy_index_served_from_disk() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    write_books(&path).await;
+
+    let storage = open_lazily(&path).await;
+    let tier = storage.get_index_tier("books").await.unwrap();
+    assert_eq!(tier["tier"], "hot");
+    assert_eq!(tier["lazy"], true);
+    assert_eq!(tier["docs_count"], 3);
+
+    let doc = storage.get_document("books", "2").await.unwrap();
+    assert_eq!(doc["_source"]["title"], "emma");
+    let result = storage
+        .search(
+            "books",
+            &json!({ "match": { "title": "dune" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["total"]["value"], 2);
+
+    // Writes go to disk while the index is lazy
+    storage
+        .index_document("books", "4", json!({ "title": "dune again" }))
+        .await
+        .unwrap();
+    storage.delete_document("books", "2").await.unwrap();
+    let tier = storage.get_index_tier("books").await.unwrap();
+    assert_eq!(tier["docs_count"], 3);
+
+    // Moving it to the hot tier loads the documents
+    assert!(storage
+        .set_index_tier("books", IndexTier::Hot)
+        .await
+        .unwrap());
+    let tier = storage.get_index_tier("books").await.unwrap();
+    assert_eq!(tier["lazy"], false);
+    assert_eq!(tier["docs_count"], 3);
+    let doc = storage.get_document("books", "4").await.unwrap();
+    assert_eq!(doc["_source"]["title"], "dune again");
+}
+
+#[tokio::test]
+async fn test_lazy_loading_keeps_the_persisted_tier() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    write_books(&path).await;
+    {
+        let storage = open_lazily(&path).await;
+        storage
+            .index_document("books", "4", json!({ "title": "persuasion" }))
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&path).await;
+    let tier = storage.get_index_tier("books").await.unwrap();
+    assert_eq!(tier["tier"], "hot");
+    assert_eq!(tier["lazy"], false);
+    assert_eq!(tier["docs_count"], 4);
+}
+
+#[tokio::test]
+async fn test_document_cache_serves_repeated_reads() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    write_books(&path).await;
+
+    let storage = open_lazily(&path).await;
+    for _ in 0..3 {
+        storage.get_document("books", "1").await.unwrap();
+    }
+    let stats = storage.document_cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses, stats.entries), (2, 1, 1));
+
+    // A write drops the cached copy
+    storage
+        .index_document("books", "1", json!({ "title": "children of dune" }))
+        .await
+        .unwrap();
+    let doc = storage.get_document("books", "1").await.unwrap();
+    assert_eq!(doc["_source"]["title"], "children of dune");
+    assert_eq!(storage.document_cache_stats().unwrap().misses, 2);
+}
+
+#[tokio::test]
+async fn test_document_cache_stays_within_budget() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    write_books(&path).await;
+
+    // Room for about two of the documents
+    let storage = Storage::with_sled(&path)
+        .unwrap()
+        .with_lazy_loading(true)
+        .with_document_cache_budget(40);
+    storage.load_from_backend().await.unwrap();
+    for id in ["1", "2", "3"] {
+        storage.get_document("books", id).await.unwrap();
+    }
+    let stats = storage.document_cache_stats().unwrap();
+    assert!(stats.size_in_bytes <= 40);
+    assert_eq!(stats.evictions, 1);
+
+    // The least recently read document was evicted
+    storage.get_document("books", "3").await.unwrap();
+    storage.get_document("books", "1").await.unwrap();
+    let stats = storage.document_cache_stats().unwrap();
+    assert_eq!((stats.hits, stats.misses), (1, 4));
+}
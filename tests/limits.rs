@@ -0,0 +1,137 @@
+//! Tests for the index count guard and automatic rollover
+
+use gbs::storage::{next_rollover_name, Storage, StorageLimits};
+
+#[test]
+fn test_next_rollover_name() {
+    assert_eq!(next_rollover_name("logs-000001"), "logs-000002");
+    assert_eq!(next_rollover_name("logs-000999"), "logs-001000");
+    assert_eq!(next_rollover_name("logs-9"), "logs-10");
+    assert_eq!(next_rollover_name("logs"), "logs-000001");
+    assert_eq!(next_rollover_name("my-logs"), "my-logs-000001");
+}
+
+#[tokio::test]
+async fn test_max_indices_refuses_creation() {
+    let storage = Storage::new().with_limits(StorageLimits {
+        max_indices: Some(2),
+        ..Default::default()
+    });
+
+    storage.create_index("one", None, None).await.unwrap();
+    storage.create_index("two", None, None).await.unwrap();
+    let result = storage.create_index("three", None, None).await;
+    assert!(result.is_err());
+    assert!(!storage.index_exists("three").await.unwrap());
+
+    // System indices do not count towards the limit
+    storage.create_index(".system", None, None).await.unwrap();
+
+    // Deleting an index frees a slot
+    storage.delete_index("one").await.unwrap();
+    storage.create_index("three", None, None).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_write_through_alias() {
+    let storage = Storage::new();
+    storage
+        .create_index("logs-000001", None, None)
+        .await
+        .unwrap();
+    storage.put_alias("logs-000001", "logs").await.unwrap();
+
+    storage
+        .index_document("logs", "1", serde_json::json!({ "message": "hello" }))
+        .await
+        .unwrap();
+    assert!(storage.get_document("logs-000001", "1").await.is_ok());
+
+    // An index cannot be created with the name of an alias
+    assert!(storage.create_index("logs", None, None).await.is_err());
+
+    storage.delete_alias("logs-000001", "logs").await.unwrap();
+    assert!(storage.delete_alias("logs-000001", "logs").await.is_err());
+    assert!(storage
+        .index_document("logs", "2", serde_json::json!({ "message": "hello" }))
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_auto_rollover_by_doc_count() {
+    let storage = Storage::new().with_limits(StorageLimits {
+        rollover_max_docs: Some(2),
+        ..Default::default()
+    });
+    let mappings = serde_json::json!({ "properties": { "message": { "type": "text" } } });
+    storage
+        .create_index("logs-000001", None, Some(mappings.clone()))
+        .await
+        .unwrap();
+    storage.put_alias("logs-000001", "logs").await.unwrap();
+
+    for i in 0..5 {
+        storage
+            .index_document(
+                "logs",
+                &i.to_string(),
+                serde_json::json!({ "message": "line" }),
+            )
+            .await
+            .unwrap();
+    }
+
+    let mut indices = storage.list_indices().await;
+    indices.sort();
+    assert_eq!(indices, vec!["logs-000001", "logs-000002", "logs-000003"]);
+
+    // The alias points at the newest index, which inherited the mappings
+    let aliases = storage.get_aliases().await;
+    assert!(aliases["logs-000003"]["aliases"].get("logs").is_some());
+    assert!(aliases["logs-000001"]["aliases"].get("logs").is_none());
+    let index = storage.get_index("logs-000003").await.unwrap();
+    assert_eq!(index["logs-000003"]["mappings"], mappings);
+
+    let stats = storage.get_indices_stats().await;
+    let total: usize = stats.iter().map(|(_, count)| count).sum();
+    assert_eq!(total, 5);
+}
+
+#[tokio::test]
+async fn test_auto_rollover_by_size_respects_max_indices() {
+    let storage = Storage::new().with_limits(StorageLimits {
+        max_indices: Some(1),
+        rollover_max_size_bytes: Some(10),
+        ..Default::default()
+    });
+    storage.create_index("logs", None, None).await.unwrap();
+    storage.put_alias("logs", "logs-write").await.unwrap();
+
+    // Rollover is refused by the index limit, but writes keep succeeding
+    for i in 0..3 {
+        storage
+            .index_document(
+                "logs-write",
+                &i.to_string(),
+                serde_json::json!({ "message": "a line longer than ten bytes" }),
+            )
+            .await
+            .unwrap();
+    }
+    assert_eq!(storage.list_indices().await, vec!["logs"]);
+
+    // Writes directly to an index never roll over
+    let storage = Storage::new().with_limits(StorageLimits {
+        rollover_max_docs: Some(1),
+        ..Default::default()
+    });
+    storage.create_index("direct", None, None).await.unwrap();
+    for i in 0..3 {
+        storage
+            .index_document("direct", &i.to_string(), serde_json::json!({ "n": i }))
+            .await
+            .unwrap();
+    }
+    assert_eq!(storage.list_indices().await, vec!["direct"]);
+}
@@ -0,0 +1,128 @@
+//! Tests for JSON log lines and request IDs
+
+mod common;
+
+use axum_test::TestServer;
+use common::test_server;
+use gbs::logging::{JsonFields, JsonFormat};
+use gbs::storage::Storage;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Log output collected in memory
+#[derive(Clone, Default)]
+struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+impl Buffer {
+    fn lines(&self) -> Vec<serde_json::Value> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+impl Write for Buffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn json_subscriber(buffer: &Buffer) -> impl tracing::Subscriber + Send + Sync {
+    let buffer = buffer.clone();
+    tracing_subscriber::fmt()
+        .with_max_level(tracing::Level::DEBUG)
+        .with_ansi(false)
+        .event_format(JsonFormat)
+        .fmt_fields(JsonFields)
+        .with_writer(move || buffer.clone())
+        .finish()
+}
+
+fn create_test_server() -> TestServer {
+    test_server(Storage::new(), "6.8.23")
+}
+
+#[test]
+fn test_json_lines_carry_span_fields() {
+    let buffer = Buffer::default();
+    tracing::subscriber::with_default(json_subscriber(&buffer), || {
+        let span = tracing::info_span!("request", request_id = "abc", attempt = 1);
+        let _entered = span.enter();
+        span.record("attempt", 2);
+        tracing::info!(index = "books", took = 3, "search done");
+    });
+
+    let lines = buffer.lines();
+    assert_eq!(lines.len(), 1);
+    let line = &lines[0];
+    assert_eq!(line["level"], "INFO");
+    assert_eq!(line["target"], "logging");
+    assert_eq!(line["fields"]["message"], "search done");
+    assert_eq!(line["fields"]["index"], "books");
+    assert_eq!(line["fields"]["took"], 3);
+    assert_eq!(line["span"]["name"], "request");
+    assert_eq!(line["span"]["request_id"], "abc");
+    assert_eq!(line["span"]["attempt"], 2);
+    assert_eq!(line["spans"].as_array().unwrap().len(), 1);
+    assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+}
+
+#[tokio::test]
+async fn test_request_id_is_generated_and_logged() {
+    let buffer = Buffer::default();
+    let _guard = tracing::subscriber::set_default(json_subscriber(&buffer));
+    let server = create_test_server();
+
+    let response = server.get("/_cluster/health").await;
+    response.assert_status_ok();
+    let id = response.header("x-request-id");
+    let id = id.to_str().unwrap();
+    assert_eq!(id.len(), 36);
+
+    let lines = buffer.lines();
+    assert!(!lines.is_empty());
+    assert!(lines
+        .iter()
+        .all(|line| line["span"]["request_id"] == id && line["span"]["method"] == "GET"));
+
+    // Each request gets its own ID
+    let response = server.get("/_cluster/health").await;
+    assert_ne!(response.header("x-request-id").to_str().unwrap(), id);
+}
+
+#[tokio::test]
+async fn test_incoming_request_id_is_kept() {
+    let server = create_test_server();
+
+    let response = server
+        .get("/_cluster/health")
+        .add_header("X-Request-Id", "client-42")
+        .await;
+    assert_eq!(response.header("x-request-id"), "client-42");
+
+    // Errors carry the ID too
+    let response = server
+        .get("/missing/_doc/1")
+        .add_header("X-Request-Id", "client-43")
+        .await;
+    response.assert_status_not_found();
+    assert_eq!(response.header("x-request-id"), "client-43");
+
+    // IDs with spaces or of excessive length are replaced
+    for id in ["has space".to_string(), "x".repeat(201)] {
+        let response = server
+            .get("/_cluster/health")
+            .add_header("X-Request-Id", id.clone())
+            .await;
+        let echoed = response.header("x-request-id");
+        assert_ne!(echoed.to_str().unwrap(), id);
+        assert_eq!(echoed.len(), 36);
+    }
+}
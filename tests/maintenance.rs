@@ -0,0 +1,186 @@
+//! Tests for the offline maintenance commands
+
+mod common;
+
+use common::open_sled;
+use gbs::maintenance::{compact, export, import, validate, ExportOptions, ImportOptions};
+use gbs::storage::DynamicMode;
+use gbs::storage_backend::SledBackend;
+use serde_json::json;
+use std::path::Path;
+use tempfile::TempDir;
+
+async fn write_books(data_dir: &Path) {
+    let storage = open_sled(data_dir).await;
+    storage.create_index("books", None, None).await.unwrap();
+    for (id, title) in [("1", "dune"), ("2", "emma"), ("3", "persuasion")] {
+        storage
+            .index_document("books", id, json!({ "title": title }))
+            .await
+            .unwrap();
+    }
+    storage.flush().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_export_then_import_into_another_data_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    let source = temp_dir.path().join("source");
+    let target = temp_dir.path().join("target");
+    let file = temp_dir.path().join("books.ndjson");
+    write_books(&source).await;
+
+    let exported = export(&ExportOptions {
+        data_dir: source,
+        index: "books".to_string(),
+        file: file.clone(),
+    })
+    .unwrap();
+    assert_eq!(exported, 3);
+
+    let report = import(&ImportOptions {
+        data_dir: target.clone(),
+        index: "library".to_string(),
+        file,
+    })
+    .await
+    .unwrap();
+    assert!(report.created);
+    assert_eq!((report.documents, report.failed), (3, 0));
+
+    let storage = open_sled(&target).await;
+    let doc = storage.get_document("library", "2").await.unwrap();
+    assert_eq!(doc["_source"]["title"], "emma");
+}
+
+#[tokio::test]
+async fn test_import_plain_documents_and_rejects() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+    write_books(&data_dir).await;
+    {
+        let storage = open_sled(&data_dir).await;
+        storage
+            .set_dynamic_mapping("books", DynamicMode::Strict)
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+    let file = temp_dir.path().join("more.ndjson");
+    std::fs::write(
+        &file,
+        "{\"title\": \"emma\"}\n\n{\"title\": \"odd\", \"author\": \"none\"}\n",
+    )
+    .unwrap();
+
+    let report = import(&ImportOptions {
+        data_dir: data_dir.clone(),
+        index: "books".to_string(),
+        file: file.clone(),
+    })
+    .await
+    .unwrap();
+    assert!(!report.created);
+    assert_eq!((report.documents, report.failed), (1, 1));
+
+    std::fs::write(&file, "{\"title\": \"ok\"}\nnot json\n").unwrap();
+    let error = import(&ImportOptions {
+        data_dir,
+        index: "books".to_string(),
+        file,
+    })
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("line 2"));
+}
+
+#[tokio::test]
+async fn test_compact_keeps_data_and_other_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+    write_books(&data_dir).await;
+    {
+        let storage = open_sled(&data_dir).await;
+        for i in 0..200 {
+            let id = format!("tmp-{}", i);
+            storage
+                .index_document("books", &id, json!({ "title": "x".repeat(500) }))
+                .await
+                .unwrap();
+            storage.delete_document("books", &id).await.unwrap();
+        }
+        storage.flush().await.unwrap();
+    }
+    std::fs::write(data_dir.join("gbs.log"), "log line\n").unwrap();
+
+    let report = compact(&data_dir).unwrap();
+    assert!(report.size_after <= report.size_before);
+    assert!(!temp_dir.path().join("db.compact").exists());
+    assert!(!temp_dir.path().join("db.old").exists());
+    assert_eq!(
+        std::fs::read_to_string(data_dir.join("gbs.log")).unwrap(),
+        "log line\n"
+    );
+
+    let storage = open_sled(&data_dir).await;
+    let tier = storage.get_index_tier("books").await.unwrap();
+    assert_eq!(tier["docs_count"], 3);
+    let doc = storage.get_document("books", "3").await.unwrap();
+    assert_eq!(doc["_source"]["title"], "persuasion");
+}
+
+#[tokio::test]
+async fn test_validate_reports_problems() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+    write_books(&data_dir).await;
+
+    let verification = validate(&data_dir).unwrap();
+    assert_eq!((verification.indices, verification.documents), (1, 3));
+    assert!(verification.problems.is_empty());
+
+    {
+        let backend = SledBackend::new(&data_dir).unwrap();
+        backend
+            .db()
+            .insert("doc::books:4", &[0xc1, 1, 0, 0xc1][..])
+            .unwrap();
+        backend
+            .db()
+            .insert("doc::gone:1", &br#"{"title":"orphan"}"#[..])
+            .unwrap();
+        backend.flush().unwrap();
+    }
+    let verification = validate(&data_dir).unwrap();
+    assert_eq!(verification.documents, 5);
+    assert_eq!(verification.problems.len(), 2);
+    assert!(verification.problems[0].contains("Document '4' of index 'books'"));
+    assert!(verification.problems[1].contains("missing index 'gone'"));
+
+    assert!(validate(&temp_dir.path().join("missing")).is_err());
+}
+
+#[tokio::test]
+async fn test_validate_accepts_persistent_cluster_settings() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("db");
+    write_books(&data_dir).await;
+    {
+        let storage = open_sled(&data_dir).await;
+        storage
+            .update_cluster_settings(
+                &json!({ "persistent": { "search.max_result_window": 500 } }),
+                false,
+            )
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+
+    let verification = validate(&data_dir).unwrap();
+    assert!(
+        verification.problems.is_empty(),
+        "{:?}",
+        verification.problems
+    );
+}
@@ -0,0 +1,228 @@
+//! Tests for validation of document values against their mapped types
+
+mod common;
+
+use axum::http::StatusCode;
+use common::test_server;
+use gbs::bulk_ops::BulkAction;
+use gbs::error::GbsError;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn products(settings: Value) -> Storage {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "products",
+            Some(settings),
+            Some(json!({
+                "properties": {
+                    "name": { "type": "text" },
+                    "stock": { "type": "integer" },
+                    "rating": { "type": "byte" },
+                    "price": { "type": "double" },
+                    "exact": { "type": "long", "coerce": false },
+                    "active": { "type": "boolean" },
+                    "added": { "type": "date" },
+                    "vendor": { "properties": { "country": { "type": "keyword" } } }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    storage
+}
+
+/// Type and cause of the error a document is rejected with
+async fn rejection(storage: &Storage, document: Value) -> (String, Option<Value>) {
+    let error = storage
+        .index_document("products", "1", document)
+        .await
+        .unwrap_err();
+    assert!(matches!(error, GbsError::MapperParsing { .. }));
+    (error.to_string(), error.caused_by())
+}
+
+#[tokio::test]
+async fn test_values_are_checked_against_mapped_types() {
+    let storage = products(json!({ "index": { "mapping": { "validate": true } } })).await;
+
+    let (reason, caused_by) = rejection(&storage, json!({ "stock": "many" })).await;
+    assert_eq!(
+        reason,
+        "failed to parse field [stock] of type [integer] in document with id '1'"
+    );
+    assert_eq!(
+        caused_by,
+        Some(json!({
+            "type": "number_format_exception",
+            "reason": "For input string: \"many\""
+        }))
+    );
+
+    let (_, caused_by) = rejection(&storage, json!({ "rating": 300 })).await;
+    assert_eq!(
+        caused_by.unwrap()["reason"],
+        "Value [300] is out of range for a byte"
+    );
+    let (reason, _) = rejection(&storage, json!({ "active": "yes" })).await;
+    assert_eq!(
+        reason,
+        "failed to parse field [active] of type [boolean] in document with id '1'"
+    );
+    rejection(&storage, json!({ "added": "last tuesday" })).await;
+    rejection(&storage, json!({ "price": true })).await;
+    rejection(&storage, json!({ "name": { "first": "shoe" } })).await;
+    // Every element of an array is checked
+    rejection(&storage, json!({ "stock": [1, "x"] })).await;
+    let (reason, caused_by) = rejection(&storage, json!({ "vendor": "acme" })).await;
+    assert_eq!(
+        reason,
+        "object mapping for [vendor] tried to parse field [vendor] as object, but found a concrete value"
+    );
+    assert_eq!(caused_by, None);
+    let (reason, _) = rejection(
+        &storage,
+        json!({ "vendor": { "country": { "code": "DE" } } }),
+    )
+    .await;
+    assert!(reason.contains("[vendor.country] of type [keyword]"));
+    assert!(storage.get_document("products", "1").await.is_err());
+
+    // Fitting values, with numeric strings and decimals coerced
+    storage
+        .index_document(
+            "products",
+            "1",
+            json!({
+                "name": 42,
+                "stock": "7",
+                "rating": 4.5,
+                "price": "9.99",
+                "active": "false",
+                "added": "2024-03-15",
+                "vendor": [{ "country": "DE" }],
+                "unmapped": { "anything": true },
+                "exact": null
+            }),
+        )
+        .await
+        .unwrap();
+    // Stored as sent
+    let doc = storage.get_document("products", "1").await.unwrap();
+    assert_eq!(doc["_source"]["stock"], "7");
+}
+
+#[tokio::test]
+async fn test_coercion_can_be_disabled() {
+    // Per field
+    let storage = products(json!({ "index.mapping.validate": true })).await;
+    let (_, caused_by) = rejection(&storage, json!({ "exact": "5" })).await;
+    assert_eq!(caused_by.unwrap()["type"], "illegal_argument_exception");
+    let (_, caused_by) = rejection(&storage, json!({ "exact": 5.5 })).await;
+    assert_eq!(
+        caused_by.unwrap()["reason"],
+        "Value [5.5] has a decimal part"
+    );
+    storage
+        .index_document("products", "1", json!({ "exact": 5, "stock": "5" }))
+        .await
+        .unwrap();
+
+    // Per index
+    let storage = products(json!({
+        "index.mapping.validate": true,
+        "index.mapping.coerce": false
+    }))
+    .await;
+    rejection(&storage, json!({ "stock": "5" })).await;
+    rejection(&storage, json!({ "stock": 5.5 })).await;
+    rejection(&storage, json!({ "price": "9.99" })).await;
+    storage
+        .index_document("products", "1", json!({ "stock": 5, "price": 9.99 }))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_validation_is_optional() {
+    let storage = products(json!({})).await;
+    storage
+        .index_document(
+            "products",
+            "1",
+            json!({ "stock": "many", "vendor": "acme" }),
+        )
+        .await
+        .unwrap();
+
+    // Enabled later as a dynamic setting
+    storage
+        .update_settings(
+            "products",
+            json!({ "index": { "mapping": { "validate": true } } }),
+        )
+        .await
+        .unwrap();
+    rejection(&storage, json!({ "stock": "many" })).await;
+}
+
+#[tokio::test]
+async fn test_mapper_parsing_errors_over_http() {
+    let storage = products(json!({ "index.mapping.validate": true })).await;
+    let server = test_server(storage.clone(), "6.8.23");
+
+    let response = server
+        .put("/products/_doc/1")
+        .json(&json!({ "stock": "many" }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    let body: Value = response.json();
+    assert_eq!(
+        body["error"],
+        json!({
+            "root_cause": [{
+                "type": "mapper_parsing_exception",
+                "reason": "failed to parse field [stock] of type [integer] in document with id '1'"
+            }],
+            "type": "mapper_parsing_exception",
+            "reason": "failed to parse field [stock] of type [integer] in document with id '1'",
+            "caused_by": {
+                "type": "number_format_exception",
+                "reason": "For input string: \"many\""
+            }
+        })
+    );
+
+    // Bulk items fail on their own
+    let results = storage
+        .execute_bulk(vec![
+            BulkAction::Index {
+                index: "products".to_string(),
+                id: Some("1".to_string()),
+                document: json!({ "stock": 1 }),
+            },
+            BulkAction::Index {
+                index: "products".to_string(),
+                id: Some("2".to_string()),
+                document: json!({ "stock": "x" }),
+            },
+        ])
+        .await;
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(GbsError::MapperParsing { .. })));
+
+    let body = "{\"index\":{\"_index\":\"products\",\"_id\":\"3\"}}\n{\"stock\":\"x\"}\n";
+    let response: Value = server
+        .post("/_bulk")
+        .content_type("application/x-ndjson")
+        .text(body)
+        .await
+        .json();
+    assert_eq!(response["errors"], true);
+    assert_eq!(
+        response["items"][0]["index"]["error"]["type"],
+        "mapper_parsing_exception"
+    );
+}
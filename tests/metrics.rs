@@ -0,0 +1,66 @@
+//! Tests for the Prometheus metrics registry
+
+use gbs::metrics::{Metrics, UNMATCHED_ROUTE};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::time::Duration;
+
+#[test]
+fn test_latency_histogram_buckets_are_cumulative() {
+    let metrics = Metrics::default();
+    for millis in [3, 40, 40, 20_000] {
+        metrics.record_request("GET", "/_search", 200, Duration::from_millis(millis));
+    }
+    let body = metrics.render(&[]);
+
+    let labels = "method=\"GET\",route=\"/_search\"";
+    for (le, count) in [
+        ("0.005", 1),
+        ("0.025", 1),
+        ("0.05", 3),
+        ("10", 3),
+        ("+Inf", 4),
+    ] {
+        let line = format!(
+            "gbs_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+            labels, le, count
+        );
+        assert!(body.contains(&line), "missing {}", line);
+    }
+    assert!(body.contains(&format!(
+        "gbs_http_request_duration_seconds_count{{{}}} 4",
+        labels
+    )));
+    assert!(body.contains(&format!(
+        "gbs_http_requests_total{{{},status=\"200\"}} 4",
+        labels
+    )));
+}
+
+#[test]
+fn test_label_values_are_escaped() {
+    let metrics = Metrics::default();
+    metrics.record_search("logs-\"a\"\\b");
+    metrics.record_request("GET", UNMATCHED_ROUTE, 404, Duration::ZERO);
+    let body = metrics.render(&[]);
+    assert!(body.contains("gbs_searches_total{index=\"logs-\\\"a\\\"\\\\b\"} 1"));
+    assert!(body.contains("route=\"unmatched\",status=\"404\""));
+}
+
+#[tokio::test]
+async fn test_index_gauges() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document("logs", "1", json!({ "message": "started" }))
+        .await
+        .unwrap();
+
+    let metrics = Metrics::default();
+    metrics.record_indexed("logs");
+    let body = metrics.render(&storage.get_index_stats().await);
+    assert!(body.contains("gbs_documents_indexed_total{index=\"logs\"} 1"));
+    assert!(body.contains("gbs_index_documents{index=\"logs\"} 1"));
+    assert!(body.contains("# TYPE gbs_index_size_bytes gauge"));
+    assert!(body.contains("gbs_indices 1"));
+}
@@ -0,0 +1,156 @@
+//! Tests for multi-fields and `copy_to` in mappings
+
+mod common;
+
+use axum_test::TestServer;
+use common::ids;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// Books with a `title.keyword` multi-field and names copied to `author`
+async fn books() -> TestServer {
+    let server = common::test_server(Storage::new(), "8.11.0");
+    server
+        .put("/books")
+        .json(&json!({
+            "mappings": { "properties": {
+                "title": {
+                    "type": "text",
+                    "fields": {
+                        "keyword": { "type": "keyword" },
+                        "folded": { "type": "keyword", "normalizer": "lowercase" }
+                    }
+                },
+                "first_name": { "type": "text", "copy_to": "author" },
+                "last_name": { "type": "text", "copy_to": ["author", "names.all"] },
+                "author": { "type": "text" },
+                "published": { "type": "keyword", "fields": { "date": { "type": "date" } } }
+            } }
+        }))
+        .await
+        .assert_status_ok();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "title": "Dune", "first_name": "Frank", "last_name": "Herbert", "published": "1965-08-01" }),
+        ),
+        (
+            "2",
+            json!({ "title": "Emma", "first_name": "Jane", "last_name": "Austen", "published": "1815-12-23" }),
+        ),
+        (
+            "3",
+            json!({ "title": "Dune", "first_name": "Brian", "last_name": "Herbert", "published": "1999-10-01" }),
+        ),
+    ] {
+        server
+            .put(&format!("/books/_doc/{}?refresh=true", id))
+            .json(&doc)
+            .await;
+    }
+    server
+}
+
+async fn search(server: &TestServer, index: &str, body: Value) -> Value {
+    let response = server
+        .post(&format!("/{}/_search", index))
+        .json(&body)
+        .await;
+    response.assert_status_ok();
+    response.json()
+}
+
+#[tokio::test]
+async fn test_multi_fields_read_their_parent_field() {
+    let server = books().await;
+    let body = search(
+        &server,
+        "books",
+        json!({ "query": { "term": { "title.keyword": "Dune" } }, "sort": ["_id"] }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["1", "3"]);
+    // Normalizers of multi-fields apply to their values
+    let body = search(
+        &server,
+        "books",
+        json!({ "query": { "term": { "title.folded": "EMMA" } } }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["2"]);
+
+    let body = search(
+        &server,
+        "books",
+        json!({
+            "sort": [{ "title.keyword": "desc" }, { "_id": "asc" }],
+            "aggs": { "by_year": {
+                "date_histogram": { "field": "published.date", "calendar_interval": "year" }
+            } }
+        }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["2", "1", "3"]);
+    let buckets = body["aggregations"]["by_year"]["buckets"]
+        .as_array()
+        .unwrap();
+    let counts: Vec<u64> = buckets
+        .iter()
+        .map(|bucket| bucket["doc_count"].as_u64().unwrap())
+        .collect();
+    assert_eq!(buckets[0]["key_as_string"], "1815-01-01T00:00:00.000Z");
+    assert_eq!(counts.iter().sum::<u64>(), 3);
+}
+
+#[tokio::test]
+async fn test_dynamic_keyword_multi_fields() {
+    let server = books().await;
+    server.put("/notes").await.assert_status_ok();
+    server
+        .put("/notes/_doc/1?refresh=true")
+        .json(&json!({ "tag": "Urgent" }))
+        .await;
+    let body = search(
+        &server,
+        "notes",
+        json!({ "query": { "term": { "tag.keyword": "Urgent" } } }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["1"]);
+}
+
+#[tokio::test]
+async fn test_copy_to_targets_hold_the_copied_values() {
+    let server = books().await;
+    let body = search(
+        &server,
+        "books",
+        json!({ "query": { "match": { "author": "herbert" } }, "sort": ["_id"] }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["1", "3"]);
+    // The source is left as it is
+    assert!(body["hits"]["hits"][0]["_source"].get("author").is_none());
+
+    let body = search(
+        &server,
+        "books",
+        json!({ "query": { "multi_match": { "query": "jane", "fields": ["author^2", "title"] } } }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["2"]);
+    let body = search(
+        &server,
+        "books",
+        json!({ "query": { "match": { "names.all": "austen" } } }),
+    )
+    .await;
+    assert_eq!(ids(&body), vec!["2"]);
+
+    let response = server
+        .get("/books/_explain/1")
+        .json(&json!({ "query": { "match": { "author": "frank" } } }))
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.json::<Value>()["matched"], true);
+}
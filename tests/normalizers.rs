@@ -0,0 +1,172 @@
+//! Tests for keyword normalizers and case-insensitive term-level queries
+
+mod common;
+
+use axum_test::TestServer;
+use common::{sorted_ids, test_server};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// Cities with a folded name and a lowercased country code
+async fn cities() -> TestServer {
+    let server = test_server(Storage::new(), "8.11.0");
+    server
+        .put("/cities")
+        .json(&json!({
+            "settings": { "analysis": { "normalizer": {
+                "folded": { "type": "custom", "filter": ["lowercase", "asciifolding"] }
+            } } },
+            "mappings": { "properties": {
+                "name": { "type": "keyword", "normalizer": "folded" },
+                "country": { "type": "keyword", "normalizer": "lowercase" },
+                "code": { "type": "keyword" }
+            } }
+        }))
+        .await
+        .assert_status_ok();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "name": "Zürich", "country": "CH", "code": "ZRH" }),
+        ),
+        (
+            "2",
+            json!({ "name": "São Paulo", "country": "BR", "code": "GRU" }),
+        ),
+        (
+            "3",
+            json!({ "name": "Malmö", "country": "se", "code": "mmx" }),
+        ),
+    ] {
+        server
+            .put(&format!("/cities/_doc/{}?refresh=true", id))
+            .json(&doc)
+            .await;
+    }
+    server
+}
+
+async fn ids(server: &TestServer, query: Value) -> Vec<String> {
+    let response = server
+        .post("/cities/_search")
+        .json(&json!({ "query": query }))
+        .await;
+    response.assert_status_ok();
+    let body: Value = response.json();
+    sorted_ids(&body)
+}
+
+#[tokio::test]
+async fn test_normalized_fields_match_normalized_terms() {
+    let server = cities().await;
+    assert_eq!(
+        ids(&server, json!({ "term": { "name": "zurich" } })).await,
+        ["1"]
+    );
+    assert_eq!(
+        ids(
+            &server,
+            json!({ "term": { "name": { "value": "SAO PAULO" } } })
+        )
+        .await,
+        ["2"]
+    );
+    assert_eq!(
+        ids(&server, json!({ "terms": { "country": ["ch", "SE"] } })).await,
+        ["1", "3"]
+    );
+    assert_eq!(
+        ids(&server, json!({ "prefix": { "name": "MALMO" } })).await,
+        ["3"]
+    );
+    assert_eq!(
+        ids(&server, json!({ "wildcard": { "name": "s?o*" } })).await,
+        ["2"]
+    );
+    // Cached filters see the normalized terms too
+    assert_eq!(
+        ids(
+            &server,
+            json!({ "bool": { "filter": [{ "term": { "country": "Br" } }] } })
+        )
+        .await,
+        ["2"]
+    );
+}
+
+#[tokio::test]
+async fn test_case_insensitive_term_level_queries() {
+    let server = cities().await;
+    // Terms match exactly unless asked otherwise
+    assert!(ids(&server, json!({ "term": { "code": "zrh" } }))
+        .await
+        .is_empty());
+    assert_eq!(
+        ids(
+            &server,
+            json!({ "term": { "code": { "value": "zrh", "case_insensitive": true } } })
+        )
+        .await,
+        ["1"]
+    );
+    // Prefixes and wildcards ignore case unless asked otherwise
+    assert_eq!(
+        ids(&server, json!({ "prefix": { "code": "mm" } })).await,
+        ["3"]
+    );
+    assert_eq!(
+        ids(&server, json!({ "prefix": { "code": "MM" } })).await,
+        ["3"]
+    );
+    assert!(ids(
+        &server,
+        json!({ "prefix": { "code": { "value": "MM", "case_insensitive": false } } })
+    )
+    .await
+    .is_empty());
+    assert!(ids(
+        &server,
+        json!({ "wildcard": { "code": { "value": "g*", "case_insensitive": false } } })
+    )
+    .await
+    .is_empty());
+    assert_eq!(
+        ids(
+            &server,
+            json!({ "wildcard": { "code": { "value": "G*", "case_insensitive": false } } })
+        )
+        .await,
+        ["2"]
+    );
+}
+
+#[tokio::test]
+async fn test_unknown_normalizers_are_rejected() {
+    let server = cities().await;
+    for body in [
+        json!({ "mappings": { "properties": { "tag": { "type": "keyword", "normalizer": "folded" } } } }),
+        json!({ "mappings": { "properties": { "tag": { "type": "text", "normalizer": "lowercase" } } } }),
+        json!({
+            "settings": { "analysis": { "normalizer": { "n": { "filter": ["stemmer"] } } } },
+            "mappings": { "properties": { "tag": { "type": "keyword", "normalizer": "n" } } }
+        }),
+    ] {
+        server
+            .put("/tags")
+            .json(&body)
+            .await
+            .assert_status_bad_request();
+    }
+    server
+        .put("/cities/_mapping")
+        .json(
+            &json!({ "properties": { "region": { "type": "keyword", "normalizer": "missing" } } }),
+        )
+        .await
+        .assert_status_bad_request();
+    server
+        .put("/cities/_mapping")
+        .json(&json!({ "properties": { "region": { "type": "keyword", "normalizer": "folded" } } }))
+        .await
+        .assert_status_ok();
+}
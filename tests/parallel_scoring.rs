@@ -0,0 +1,141 @@
+//! Tests for scoring large indices on several threads
+
+mod common;
+
+use common::ids;
+use gbs::storage::{ParallelScoring, SearchRequest, Storage};
+use serde_json::json;
+
+/// A storage holding the same 500 events, scored in parallel or not
+async fn storage(parallel: bool) -> Storage {
+    let storage = Storage::new().with_parallel_scoring(ParallelScoring {
+        enabled: parallel,
+        min_docs: 100,
+    });
+    storage.create_index("events", None, None).await.unwrap();
+    for i in 0..500 {
+        let level = if i % 3 == 0 { "error" } else { "info" };
+        storage
+            .index_document(
+                "events",
+                &format!("event-{:03}", i),
+                json!({
+                    "level": level,
+                    "latency": (i * 37) % 500,
+                    "message": if i % 5 == 0 { "disk full disk" } else { "disk ok" },
+                    "timestamp": format!("2024-01-{:02}T00:00:00Z", i % 28 + 1)
+                }),
+            )
+            .await
+            .unwrap();
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_parallel_scoring_matches_sequential_hits() {
+    let sequential = storage(false).await;
+    let parallel = storage(true).await;
+    let query = json!({
+        "bool": {
+            "must": [{ "match": { "message": "disk" } }],
+            "filter": [{ "term": { "level": "error" } }]
+        }
+    });
+    let sort = json!([{ "latency": "desc" }]);
+
+    for (from, size) in [(0, 10), (25, 20), (160, 10)] {
+        let expected = sequential
+            .search(
+                "events",
+                &query,
+                Some(from),
+                Some(size),
+                Some(&sort),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        let actual = parallel
+            .search(
+                "events",
+                &query,
+                Some(from),
+                Some(size),
+                Some(&sort),
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+        assert_eq!(actual["hits"]["total"], expected["hits"]["total"]);
+        assert_eq!(actual["hits"]["total"]["value"], 167);
+        assert_eq!(ids(&actual), ids(&expected));
+    }
+}
+
+#[tokio::test]
+async fn test_parallel_scoring_ranks_by_score() {
+    let storage = storage(true).await;
+    let body = storage
+        .search(
+            "events",
+            &json!({ "match": { "message": "full" } }),
+            None,
+            Some(5),
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(body["hits"]["total"]["value"], 100);
+    // Ties are broken by ID
+    assert_eq!(
+        ids(&body),
+        [
+            "event-000",
+            "event-005",
+            "event-010",
+            "event-015",
+            "event-020"
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_parallel_scoring_collects_aggregations_over_all_matches() {
+    let sequential = storage(false).await;
+    let parallel = storage(true).await;
+    let query = json!({ "term": { "level": "info" } });
+    let aggs = json!({
+        "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } }
+    });
+
+    let expected = sequential
+        .search_with_request(
+            "events",
+            &SearchRequest {
+                size: Some(3),
+                aggs: Some(aggs.clone()),
+                ..SearchRequest::new(query.clone())
+            },
+        )
+        .await
+        .unwrap();
+    let actual = parallel
+        .search_with_request(
+            "events",
+            &SearchRequest {
+                size: Some(3),
+                aggs: Some(aggs.clone()),
+                ..SearchRequest::new(query.clone())
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(actual["hits"]["total"]["value"], 333);
+    assert_eq!(actual["hits"]["hits"].as_array().unwrap().len(), 3);
+    assert_eq!(actual["aggregations"], expected["aggregations"]);
+}
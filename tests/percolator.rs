@@ -0,0 +1,238 @@
+//! Tests for stored queries and the percolate query
+
+mod common;
+
+use axum::http::StatusCode;
+use common::test_server;
+use gbs::error::GbsError;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// An index of alerting rules, each a stored query
+async fn alerts() -> Storage {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "alerts",
+            None,
+            Some(json!({
+                "properties": {
+                    "query": { "type": "percolator" },
+                    "severity": { "type": "keyword" }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    let rules = [
+        (
+            "disk",
+            json!({ "query": { "match": { "message": "disk" } }, "severity": "high" }),
+        ),
+        (
+            "errors",
+            json!({
+                "query": {
+                    "bool": {
+                        "must": [{ "term": { "level": "error" } }],
+                        "filter": [{ "range": { "code": { "gte": 500 } } }]
+                    }
+                },
+                "severity": "low"
+            }),
+        ),
+        (
+            "lucene",
+            json!({ "query": { "query_string": { "query": "message:timeout" } } }),
+        ),
+        // Not a rule: no stored query
+        ("note", json!({ "severity": "low" })),
+    ];
+    for (id, rule) in rules {
+        storage.index_document("alerts", id, rule).await.unwrap();
+    }
+    storage
+}
+
+async fn percolate(storage: &Storage, query: &Value) -> Vec<(String, Value)> {
+    let response = storage
+        .search("alerts", query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let mut hits: Vec<(String, Value)> = response["hits"]["hits"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|hit| {
+            (
+                hit["_id"].as_str().unwrap().to_string(),
+                hit["fields"]["_percolator_document_slot"].clone(),
+            )
+        })
+        .collect();
+    hits.sort_by(|a, b| a.0.cmp(&b.0));
+    hits
+}
+
+#[tokio::test]
+async fn test_percolate_matches_stored_queries() {
+    let storage = alerts().await;
+
+    let hits = percolate(
+        &storage,
+        &json!({
+            "percolate": {
+                "field": "query",
+                "document": { "message": "disk full", "level": "error", "code": 507 }
+            }
+        }),
+    )
+    .await;
+    assert_eq!(
+        hits,
+        vec![
+            ("disk".to_string(), json!([0])),
+            ("errors".to_string(), json!([0]))
+        ]
+    );
+
+    // Several candidates: each hit lists the ones it matches
+    let hits = percolate(
+        &storage,
+        &json!({
+            "percolate": {
+                "field": "query",
+                "documents": [
+                    { "message": "request timeout", "level": "warn" },
+                    { "message": "disk slow" },
+                    { "level": "error", "code": 404 }
+                ]
+            }
+        }),
+    )
+    .await;
+    assert_eq!(
+        hits,
+        vec![
+            ("disk".to_string(), json!([1])),
+            ("lucene".to_string(), json!([0]))
+        ]
+    );
+
+    // Combined with a query on the rules themselves
+    let hits = percolate(
+        &storage,
+        &json!({
+            "bool": {
+                "must": {
+                    "percolate": { "field": "query", "document": { "message": "disk", "level": "error", "code": 500 } }
+                },
+                "filter": { "term": { "severity": "low" } }
+            }
+        }),
+    )
+    .await;
+    assert_eq!(hits, vec![("errors".to_string(), json!([0]))]);
+}
+
+#[tokio::test]
+async fn test_percolate_stored_document() {
+    let storage = alerts().await;
+    storage.create_index("logs", None, None).await.unwrap();
+    storage
+        .index_document("logs", "1", json!({ "message": "disk failure" }))
+        .await
+        .unwrap();
+
+    let hits = percolate(
+        &storage,
+        &json!({ "percolate": { "field": "query", "index": "logs", "id": "1" } }),
+    )
+    .await;
+    assert_eq!(hits, vec![("disk".to_string(), json!([0]))]);
+
+    let missing = storage
+        .search(
+            "alerts",
+            &json!({ "percolate": { "field": "query", "index": "logs", "id": "2" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+    assert!(matches!(missing, Err(GbsError::DocumentNotFound(_))));
+    let invalid = storage
+        .search(
+            "alerts",
+            &json!({ "percolate": { "field": "query" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+    assert!(matches!(invalid, Err(GbsError::InvalidRequest(_))));
+}
+
+#[tokio::test]
+async fn test_stored_queries_are_validated() {
+    let storage = alerts().await;
+
+    let error = storage
+        .index_document("alerts", "bad", json!({ "query": "disk" }))
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        "failed to parse field [query] of type [percolator] in document with id 'bad'"
+    );
+    let error = storage
+        .index_document(
+            "alerts",
+            "bad",
+            json!({ "query": { "query_string": { "query": "message:(disk" } } }),
+        )
+        .await
+        .unwrap_err();
+    assert!(matches!(error, GbsError::MapperParsing { .. }));
+    assert!(storage.get_document("alerts", "bad").await.is_err());
+
+    // The stored query is not mapped as an object of its own
+    let mappings = storage.get_mapping("alerts").await.unwrap();
+    assert_eq!(
+        mappings["properties"]["query"],
+        json!({ "type": "percolator" })
+    );
+}
+
+#[tokio::test]
+async fn test_percolate_api() {
+    let storage = alerts().await;
+    let server = test_server(storage, "6.8.23");
+
+    let body: Value = server
+        .post("/alerts/_search")
+        .json(&json!({
+            "query": {
+                "percolate": { "field": "query", "document": { "message": "disk" } }
+            }
+        }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    assert_eq!(body["hits"]["hits"][0]["_id"], "disk");
+    assert_eq!(
+        body["hits"]["hits"][0]["fields"]["_percolator_document_slot"],
+        json!([0])
+    );
+
+    server
+        .put("/alerts/_doc/bad")
+        .json(&json!({ "query": ["disk"] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
@@ -58,6 +58,47 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_document_versions_persist_across_restarts() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_path = temp_dir.path().join("test_db");
+
+        {
+            let storage = Storage::with_sled(&data_path).unwrap();
+            storage.load_from_backend().await.unwrap();
+            storage.create_index("books", None, None).await.unwrap();
+            for title in ["Dune", "Dune Messiah", "Children of Dune"] {
+                storage
+                    .index_document("books", "1", serde_json::json!({ "title": title }))
+                    .await
+                    .unwrap();
+            }
+            storage
+                .index_document("books", "2", serde_json::json!({ "title": "Emma" }))
+                .await
+                .unwrap();
+            let deleted = storage.delete_document("books", "2").await.unwrap();
+            assert_eq!(deleted.version, 2);
+            storage.flush().await.unwrap();
+        }
+
+        let storage = Storage::with_sled(&data_path).unwrap();
+        storage.load_from_backend().await.unwrap();
+        let doc = storage.get_document("books", "1").await.unwrap();
+        assert_eq!(doc["_version"], 3);
+        let outcome = storage
+            .index_document("books", "1", serde_json::json!({ "title": "Dune" }))
+            .await
+            .unwrap();
+        assert_eq!(outcome.version, 4);
+        // A deleted document starts over
+        let outcome = storage
+            .index_document("books", "2", serde_json::json!({ "title": "Emma" }))
+            .await
+            .unwrap();
+        assert_eq!(outcome.version, 1);
+    }
+
     #[tokio::test]
     async fn test_persistence_multiple_indices() {
         let temp_dir = TempDir::new().unwrap();
@@ -93,4 +134,46 @@ mod tests {
             assert!(indices.contains(&"index2".to_string()));
         }
     }
+
+    #[tokio::test]
+    async fn test_persistence_of_aliases_and_search_profiles() {
+        let temp_dir = TempDir::new().unwrap();
+        let data_path = temp_dir.path().join("test_db");
+        let profile = gbs::storage::SearchProfile::from_json(&serde_json::json!({
+            "fields": ["title^3", "body"],
+            "default_operator": "and"
+        }))
+        .unwrap();
+
+        {
+            let storage = Storage::with_sled(&data_path).unwrap();
+            storage.load_from_backend().await.unwrap();
+
+            storage
+                .create_index("logs-000001", None, None)
+                .await
+                .unwrap();
+            storage.put_alias("logs-000001", "logs").await.unwrap();
+            storage
+                .put_search_profile("logs-000001", "web_search", profile.clone())
+                .await
+                .unwrap();
+            storage.flush().await.unwrap();
+        }
+
+        {
+            let storage = Storage::with_sled(&data_path).unwrap();
+            storage.load_from_backend().await.unwrap();
+
+            let aliases = storage.get_aliases().await;
+            assert!(aliases["logs-000001"]["aliases"].get("logs").is_some());
+            assert_eq!(
+                storage
+                    .get_search_profile("logs-000001", "web_search")
+                    .await
+                    .unwrap(),
+                profile
+            );
+        }
+    }
 }
@@ -0,0 +1,217 @@
+//! Tests for the proxy of unsupported requests to a real Elasticsearch cluster
+
+mod common;
+
+use axum::extract::Query;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use axum_test::TestServer;
+use common::server_with;
+use gbs::config::{ProxyConfig, ProxyMode};
+use gbs::server::{AppState, Proxy, Recording};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Fake Elasticsearch with APIs gbs does not have
+async fn spawn_upstream() -> String {
+    let app = Router::new()
+        .route(
+            "/_migration/deprecations",
+            get(|| async { "deprecations: none" }),
+        )
+        .route(
+            "/_snapshot/backups",
+            post(|Json(body): Json<Value>| async move {
+                Json(json!({ "acknowledged": true, "type": body["type"] }))
+            }),
+        )
+        .route(
+            "/_cat/plugins",
+            get(|Query(params): Query<HashMap<String, String>>| async move {
+                Json(json!([{ "component": "analysis-icu", "format": params.get("format") }]))
+            }),
+        );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", address)
+}
+
+fn proxy_config(mode: ProxyMode, upstream_url: Option<String>) -> ProxyConfig {
+    ProxyConfig {
+        mode,
+        upstream_url,
+        ..ProxyConfig::default()
+    }
+}
+
+async fn server_with_proxy(proxy: Proxy) -> TestServer {
+    let storage = Arc::new(Storage::new());
+    let state = AppState::new(storage, "6.8.23").with_proxy(proxy);
+    server_with(state)
+}
+
+fn read_recordings(path: &PathBuf) -> Vec<Recording> {
+    std::fs::read_to_string(path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_proxy_off_keeps_not_found() {
+    let server = server_with_proxy(Proxy::disabled()).await;
+    server
+        .get("/_snapshot/backups")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+    // A known path with an unsupported method
+    let response = server.patch("/_cluster/health").expect_failure().await;
+    assert_eq!(response.status_code(), 405);
+}
+
+#[tokio::test]
+async fn test_proxy_record_and_replay() {
+    let upstream = spawn_upstream().await;
+    let dir = tempfile::tempdir().unwrap();
+    let recordings = dir.path().join("recordings.ndjson");
+
+    let proxy = Proxy::from_config(
+        &proxy_config(ProxyMode::Record, Some(upstream)),
+        recordings.clone(),
+    )
+    .await
+    .unwrap();
+    let server = server_with_proxy(proxy).await;
+
+    // Supported APIs are still served by gbs
+    server.put("/books").await.assert_status_ok();
+    server.get("/books").await.assert_status_ok();
+
+    server
+        .get("/_migration/deprecations")
+        .await
+        .assert_text("deprecations: none");
+    server
+        .post("/_snapshot/backups")
+        .json(&json!({ "type": "fs" }))
+        .await
+        .assert_json(&json!({ "acknowledged": true, "type": "fs" }));
+    server
+        .get("/_cat/plugins?format=json")
+        .await
+        .assert_json(&json!([{ "component": "analysis-icu", "format": "json" }]));
+    // The upstream's own 404s are passed on and recorded too
+    server
+        .get("/_ilm/policy")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    let recorded = read_recordings(&recordings);
+    assert_eq!(recorded.len(), 4);
+    assert_eq!(recorded[1].method, "POST");
+    assert_eq!(recorded[1].path, "/_snapshot/backups");
+    assert_eq!(recorded[1].status, 200);
+    assert_eq!(recorded[2].path, "/_cat/plugins?format=json");
+
+    // Replay without an upstream
+    let proxy = Proxy::from_config(&proxy_config(ProxyMode::Replay, None), recordings)
+        .await
+        .unwrap();
+    assert_eq!(proxy.recording_count().await, 4);
+    let server = server_with_proxy(proxy).await;
+    server
+        .get("/_migration/deprecations")
+        .await
+        .assert_text("deprecations: none");
+    // JSON bodies match by value, whatever their formatting
+    server
+        .post("/_snapshot/backups")
+        .text("{ \"type\" :\n \"fs\" }")
+        .content_type("application/json")
+        .await
+        .assert_json(&json!({ "acknowledged": true, "type": "fs" }));
+    server
+        .get("/_ilm/policy")
+        .expect_failure()
+        .await
+        .assert_status_not_found();
+
+    // Requests that were not recorded
+    let response = server
+        .post("/_snapshot/backups")
+        .json(&json!({ "type": "s3" }))
+        .expect_failure()
+        .await;
+    response.assert_status_not_found();
+    let body: Value = response.json();
+    assert_eq!(
+        body["error"]["reason"],
+        "No recorded response: POST /_snapshot/backups"
+    );
+}
+
+#[tokio::test]
+async fn test_proxy_upstream_unreachable() {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let dir = tempfile::tempdir().unwrap();
+    let proxy = Proxy::from_config(
+        &proxy_config(ProxyMode::Forward, Some(url)),
+        dir.path().join("recordings.ndjson"),
+    )
+    .await
+    .unwrap();
+    let server = server_with_proxy(proxy).await;
+    let response = server
+        .get("/_migration/deprecations")
+        .expect_failure()
+        .await;
+    assert_eq!(response.status_code(), 502);
+    // Forwarding does not record
+    assert!(!dir.path().join("recordings.ndjson").exists());
+}
+
+#[tokio::test]
+async fn test_proxy_config_validation() {
+    let dir = tempfile::tempdir().unwrap();
+    let recordings = dir.path().join("recordings.ndjson");
+
+    // Forwarding needs a plain http:// upstream
+    for url in [
+        None,
+        Some("https://es:9200".to_string()),
+        Some("es".to_string()),
+    ] {
+        assert!(
+            Proxy::from_config(&proxy_config(ProxyMode::Forward, url), recordings.clone())
+                .await
+                .is_err()
+        );
+    }
+    // Replay needs recordings
+    assert!(
+        Proxy::from_config(&proxy_config(ProxyMode::Replay, None), recordings.clone())
+            .await
+            .is_err()
+    );
+    std::fs::write(&recordings, "not json\n").unwrap();
+    assert!(
+        Proxy::from_config(&proxy_config(ProxyMode::Replay, None), recordings.clone())
+            .await
+            .is_err()
+    );
+
+    let proxy = Proxy::from_config(&ProxyConfig::default(), recordings)
+        .await
+        .unwrap();
+    assert_eq!(proxy.mode(), ProxyMode::Off);
+}
@@ -0,0 +1,177 @@
+//! Tests for the public query AST
+
+use gbs::error::GbsError;
+use gbs::models::QueryAst;
+use gbs::storage::Storage;
+use serde_json::json;
+
+#[test]
+fn test_parse_leaf_queries_in_short_and_long_form() {
+    let short = QueryAst::parse(&json!({ "match": { "title": "gummy bears" } })).unwrap();
+    assert_eq!(
+        short,
+        QueryAst::Match {
+            field: "title".to_string(),
+            query: "gummy bears".to_string(),
+            params: Default::default(),
+        }
+    );
+
+    let long = QueryAst::parse(&json!({
+        "term": { "status": { "value": "published", "boost": 2.0 } }
+    }))
+    .unwrap();
+    let QueryAst::Term {
+        field,
+        value,
+        params,
+    } = long
+    else {
+        panic!("expected a term query");
+    };
+    assert_eq!(field, "status");
+    assert_eq!(value, json!("published"));
+    assert_eq!(params.get("boost"), Some(&json!(2.0)));
+
+    assert_eq!(
+        QueryAst::parse(&json!({})).unwrap(),
+        QueryAst::MatchAll {
+            params: Default::default()
+        }
+    );
+}
+
+#[test]
+fn test_round_trip_preserves_queries() {
+    let queries = [
+        json!({ "match": { "title": { "query": "rust", "operator": "and", "fuzziness": "AUTO" } } }),
+        json!({ "match_phrase": { "body": "quick brown fox" } }),
+        json!({ "multi_match": { "query": "rust", "fields": ["title^2", "body"], "operator": "and" } }),
+        json!({ "fuzzy": { "name": { "value": "ki", "fuzziness": 2 } } }),
+        json!({ "terms": { "tags": ["a", "b"], "boost": 1.5 } }),
+        json!({ "ids": { "values": ["1", 2] } }),
+        json!({ "prefix": { "title": "ru" } }),
+        json!({ "wildcard": { "title": { "value": "ru*t", "boost": 3 } } }),
+        json!({ "range": { "age": { "gte": 10, "lt": 20 } } }),
+        json!({ "query_string": { "query": "title:rust AND year:>2020", "default_operator": "AND" } }),
+        json!({ "match_all": { "boost": 1.2 } }),
+        json!({
+            "function_score": {
+                "query": { "match": { "title": "rust" } },
+                "functions": [{ "filter": { "term": { "tag": "web" } }, "weight": 2 }],
+                "boost_mode": "sum"
+            }
+        }),
+        json!({
+            "bool": {
+                "must": [{ "match": { "title": "rust" } }],
+                "should": [
+                    { "term": { "tag": "web" } },
+                    { "nested": {
+                        "path": "comments",
+                        "score_mode": "max",
+                        "query": { "match": { "comments.author": "ana" } }
+                    } }
+                ],
+                "must_not": [{ "term": { "status": "draft" } }],
+                "filter": [{ "range": { "year": { "gte": 2020 } } }],
+                "minimum_should_match": 1
+            }
+        }),
+    ];
+
+    for query in queries {
+        let ast = QueryAst::parse(&query).unwrap();
+        assert_eq!(ast.to_json(), query);
+        // Serde goes through the same JSON form
+        let serialized = serde_json::to_value(&ast).unwrap();
+        assert_eq!(serialized, query);
+        assert_eq!(serde_json::from_value::<QueryAst>(serialized).unwrap(), ast);
+    }
+}
+
+#[test]
+fn test_single_bool_clauses_are_normalized_to_arrays() {
+    let ast = QueryAst::parse(&json!({
+        "bool": { "filter": { "term": { "status": "published" } } }
+    }))
+    .unwrap();
+    assert_eq!(
+        ast.to_json(),
+        json!({ "bool": { "filter": [{ "term": { "status": "published" } }] } })
+    );
+}
+
+#[test]
+fn test_malformed_and_unknown_queries_are_rejected() {
+    for query in [
+        json!("match"),
+        json!({ "match": { "title": "a" }, "term": { "tag": "b" } }),
+        json!({ "match": { "title": "a", "body": "b" } }),
+        json!({ "match": { "title": { "operator": "and" } } }),
+        json!({ "ids": { "values": "1" } }),
+        json!({ "nested": { "query": { "match_all": {} } } }),
+        json!({ "bool": { "must": [{ "match": { "title": ["a"] } }] } }),
+    ] {
+        assert!(
+            matches!(QueryAst::parse(&query), Err(GbsError::InvalidRequest(_))),
+            "query: {}",
+            query
+        );
+    }
+
+    let unknown = QueryAst::parse(&json!({ "geo_shape": { "location": {} } }));
+    assert!(
+        matches!(unknown, Err(GbsError::InvalidRequest(reason)) if reason.contains("geo_shape"))
+    );
+}
+
+#[test]
+fn test_walk_and_fields() {
+    let ast = QueryAst::parse(&json!({
+        "bool": {
+            "must": { "multi_match": { "query": "rust", "fields": ["title", "body"] } },
+            "filter": [
+                { "term": { "title": "rust" } },
+                { "nested": { "path": "comments", "query": { "match": { "comments.text": "ok" } } } }
+            ]
+        }
+    }))
+    .unwrap();
+
+    assert_eq!(ast.fields(), vec!["title", "body", "comments.text"]);
+    let mut count = 0;
+    ast.walk(&mut |_| count += 1);
+    assert_eq!(count, 5);
+}
+
+#[tokio::test]
+async fn test_rewritten_queries_run_against_storage() {
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+    for (id, title) in [("1", "Rust in Action"), ("2", "Python Tricks")] {
+        storage
+            .index_document("books", id, json!({ "title": title }))
+            .await
+            .unwrap();
+    }
+
+    // Rewrite the text of the match clauses
+    let mut ast = QueryAst::parse(&json!({
+        "bool": { "must": [{ "match": { "title": "rust" } }] }
+    }))
+    .unwrap();
+    for child in ast.children_mut() {
+        if let QueryAst::Match { query, .. } = child {
+            *query = "python".to_string();
+        }
+    }
+
+    let result = storage
+        .search("books", &ast.to_json(), None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], json!("2"));
+}
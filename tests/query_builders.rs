@@ -0,0 +1,117 @@
+//! Tests for the query DSL builders
+
+use gbs::models::query::{
+    bool_query, match_all, match_query, multi_match, nested, range, term, terms, Operator,
+};
+use gbs::models::{BoolQuery, MatchQuery, Query, QueryAst, RangeQuery, SearchRequest, SortOrder};
+use serde_json::json;
+
+#[test]
+fn test_builders_serialize_to_the_query_dsl() {
+    assert_eq!(
+        serde_json::to_value(match_query("title", "rust").operator(Operator::And)).unwrap(),
+        json!({ "match": { "title": { "query": "rust", "operator": "and" } } })
+    );
+    assert_eq!(
+        serde_json::to_value(range("year").gte(2020).lt(2024)).unwrap(),
+        json!({ "range": { "year": { "gte": 2020, "lt": 2024 } } })
+    );
+    assert_eq!(
+        serde_json::to_value(
+            bool_query()
+                .must(multi_match("rust", ["title", "body^2"]))
+                .filter(terms("status", ["published", "draft"]))
+                .minimum_should_match(1)
+        )
+        .unwrap(),
+        json!({ "bool": {
+            "must": [{ "multi_match": { "query": "rust", "fields": ["title", "body^2"] } }],
+            "filter": [{ "terms": { "status": ["published", "draft"] } }],
+            "minimum_should_match": 1
+        } })
+    );
+    // The builders and the AST agree on the JSON
+    let query = nested("comments", term("comments.author", "ann"));
+    assert_eq!(
+        QueryAst::parse(&query.to_json()).unwrap().to_json(),
+        query.to_json()
+    );
+}
+
+#[test]
+fn test_builders_round_trip_through_json() {
+    let query = bool_query()
+        .must(match_query("title", "rust").fuzziness("AUTO"))
+        .should(term("tags", "async"))
+        .must_not(range("year").lt(2015))
+        .filter(match_all())
+        .boost(2.0);
+    let json = serde_json::to_value(&query).unwrap();
+    assert_eq!(
+        serde_json::from_value::<BoolQuery>(json.clone()).unwrap(),
+        query
+    );
+    // Deserialized builders can be extended
+    let extended = serde_json::from_value::<BoolQuery>(json)
+        .unwrap()
+        .filter(term("status", "published"));
+    assert_eq!(
+        serde_json::to_value(&extended).unwrap()["bool"]["filter"][1],
+        json!({ "term": { "status": "published" } })
+    );
+
+    let matched: MatchQuery =
+        serde_json::from_value(json!({ "match": { "title": "rust" } })).unwrap();
+    assert_eq!(matched, match_query("title", "rust"));
+    let bounded: RangeQuery =
+        serde_json::from_value(json!({ "range": { "year": { "gte": 2020 } } })).unwrap();
+    assert_eq!(bounded, range("year").gte(2020));
+    let any: Query = serde_json::from_value(json!({ "term": { "status": "draft" } })).unwrap();
+    assert_eq!(any, term("status", "draft"));
+}
+
+#[test]
+fn test_deserializing_the_wrong_query_type_fails() {
+    let error =
+        serde_json::from_value::<BoolQuery>(json!({ "term": { "status": "draft" } })).unwrap_err();
+    assert!(
+        error
+            .to_string()
+            .contains("expected a [bool] query, found [term]"),
+        "{}",
+        error
+    );
+    assert!(serde_json::from_value::<MatchQuery>(json!({ "range": { "year": {} } })).is_err());
+    assert!(serde_json::from_value::<Query>(json!({ "unknown": {} })).is_err());
+}
+
+#[test]
+fn test_search_request_round_trips_through_json() {
+    let request = SearchRequest::new()
+        .query(bool_query().must(match_query("title", "rust")))
+        .from(10)
+        .size(5)
+        .sort("year", SortOrder::Desc)
+        .source_includes(["title"])
+        .highlight("title")
+        .aggregation("years", json!({ "terms": { "field": "year" } }));
+    let json = serde_json::to_value(&request).unwrap();
+    assert_eq!(json["from"], 10);
+    assert_eq!(json["highlight"], json!({ "fields": { "title": {} } }));
+    assert_eq!(
+        serde_json::from_value::<SearchRequest>(json).unwrap(),
+        request
+    );
+
+    let request: SearchRequest = serde_json::from_value(json!({
+        "query": { "match_all": {} },
+        "sort": { "year": "asc" },
+        "aggregations": { "years": { "terms": { "field": "year" } } }
+    }))
+    .unwrap();
+    assert_eq!(request.to_json()["sort"], json!([{ "year": "asc" }]));
+    assert!(request.to_json()["aggs"]["years"].is_object());
+
+    let error = serde_json::from_value::<SearchRequest>(json!({ "suggest": {} })).unwrap_err();
+    assert!(error.to_string().contains("[suggest]"), "{}", error);
+}
@@ -0,0 +1,204 @@
+//! Tests for the query_string query (Lucene query syntax)
+
+mod common;
+
+use common::search_ids;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn storage_with_products() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("products", None, None).await.unwrap();
+    for (id, doc) in [
+        (
+            "1",
+            json!({ "name": "Rust Book", "category": "books", "price": 40, "released": "2023-05-01" }),
+        ),
+        (
+            "2",
+            json!({ "name": "Rust Mug", "category": "kitchen", "price": 12, "released": "2024-01-15" }),
+        ),
+        (
+            "3",
+            json!({ "name": "Go Book", "category": "books", "price": 35, "released": "2024-03-20" }),
+        ),
+        (
+            "4",
+            json!({ "name": "Coffee Grinder", "category": "kitchen", "price": 80, "released": "2022-11-02" }),
+        ),
+    ] {
+        storage.index_document("products", id, doc).await.unwrap();
+    }
+    storage
+}
+
+async fn ids(storage: &Storage, query_string: Value) -> Vec<String> {
+    search_ids(storage, "products", json!({ "query_string": query_string })).await
+}
+
+#[tokio::test]
+async fn test_query_string_fields_and_boolean_operators() {
+    let storage = storage_with_products().await;
+
+    assert_eq!(
+        ids(&storage, json!({ "query": "rust" })).await,
+        vec!["1", "2"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "name:rust AND category:books" })).await,
+        vec!["1"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "category:books -name:go" })).await,
+        vec!["1"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "name:(mug OR grinder)" })).await,
+        vec!["2", "4"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "NOT category:kitchen" })).await,
+        vec!["1", "3"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "\"rust book\"" })).await,
+        vec!["1"]
+    );
+}
+
+#[tokio::test]
+async fn test_query_string_default_field_and_operator() {
+    let storage = storage_with_products().await;
+
+    assert_eq!(
+        ids(&storage, json!({ "query": "rust book" })).await,
+        vec!["1", "2", "3"]
+    );
+    assert_eq!(
+        ids(
+            &storage,
+            json!({ "query": "rust book", "default_operator": "AND" })
+        )
+        .await,
+        vec!["1"]
+    );
+    // With AND as default, OR still makes both sides optional
+    assert_eq!(
+        ids(
+            &storage,
+            json!({ "query": "mug OR grinder", "default_operator": "and" })
+        )
+        .await,
+        vec!["2", "4"]
+    );
+    assert_eq!(
+        ids(
+            &storage,
+            json!({ "query": "books", "default_field": "name" })
+        )
+        .await,
+        Vec::<String>::new()
+    );
+    assert_eq!(
+        ids(
+            &storage,
+            json!({ "query": "books", "fields": ["name", "category"] })
+        )
+        .await,
+        vec!["1", "3"]
+    );
+}
+
+#[tokio::test]
+async fn test_query_string_ranges_wildcards_and_fuzziness() {
+    let storage = storage_with_products().await;
+
+    assert_eq!(
+        ids(&storage, json!({ "query": "price:[35 TO 40]" })).await,
+        vec!["1", "3"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "price:{35 TO *}" })).await,
+        vec!["1", "4"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "price:<20" })).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "released:>=2024-01-01" })).await,
+        vec!["2", "3"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "name:co*" })).await,
+        vec!["4"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "name:r?st*" })).await,
+        vec!["1", "2"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "grindr~" })).await,
+        vec!["4"]
+    );
+    assert_eq!(
+        ids(&storage, json!({ "query": "*" })).await,
+        vec!["1", "2", "3", "4"]
+    );
+}
+
+#[tokio::test]
+async fn test_query_string_boost_and_nesting() {
+    let storage = storage_with_products().await;
+
+    let query = json!({
+        "bool": {
+            "must": [{ "query_string": { "query": "category:books" } }],
+            "should": [{ "query_string": { "query": "name:go^5" } }]
+        }
+    });
+    let result = storage
+        .search("products", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["total"]["value"], 2);
+    assert_eq!(result["hits"]["hits"][0]["_id"], "3");
+}
+
+#[tokio::test]
+async fn test_query_string_syntax_errors() {
+    let storage = storage_with_products().await;
+
+    for query in [
+        "name:(rust",
+        "\"rust book",
+        "price:[1 TO",
+        "AND rust",
+        "name:",
+    ] {
+        let result = storage
+            .search(
+                "products",
+                &json!({ "query_string": { "query": query } }),
+                None,
+                None,
+                None,
+                None,
+                None,
+            )
+            .await;
+        assert!(result.is_err(), "query {:?} should be rejected", query);
+    }
+    let result = storage
+        .search(
+            "products",
+            &json!({ "query_string": { "query": "rust", "default_operator": "xor" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+    assert!(result.is_err());
+}
@@ -0,0 +1,182 @@
+//! Tests for rate limiting per client
+
+mod common;
+
+use axum_test::http::StatusCode;
+use common::{api_key, basic_auth, server_with, test_server};
+use gbs::auth::AuthStore;
+use gbs::config::{ApiKeyConfig, Config, SecurityConfig, UserConfig};
+use gbs::server::{AppState, RateLimiter};
+use gbs::storage::Storage;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// Config allowing bursts of `burst` requests and one more per 100 seconds
+fn config(burst: u32) -> Config {
+    let mut config = Config::default();
+    config.rate_limit.enabled = true;
+    config.rate_limit.requests_per_second = 0.01;
+    config.rate_limit.burst = burst;
+    config
+}
+
+#[tokio::test]
+async fn test_requests_over_the_limit_are_rejected() {
+    let state = AppState::new(Arc::new(Storage::new()), "8.11.0").with_config(config(2));
+    let server = server_with(state);
+
+    let response = server.get("/").await;
+    response.assert_status_ok();
+    assert_eq!(response.header("x-ratelimit-limit"), "2");
+    assert_eq!(response.header("x-ratelimit-remaining"), "1");
+    assert_eq!(response.header("x-ratelimit-reset"), "100");
+    let response = server.get("/_cluster/health").await;
+    response.assert_status_ok();
+    assert_eq!(response.header("x-ratelimit-remaining"), "0");
+
+    let response = server.get("/").await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header("retry-after"), "100");
+    assert_eq!(response.header("x-ratelimit-remaining"), "0");
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "es_rejected_execution_exception");
+}
+
+#[tokio::test]
+async fn test_api_keys_have_their_own_limits() {
+    let security = SecurityConfig {
+        enabled: true,
+        api_keys: ["ingest", "search"]
+            .iter()
+            .map(|id| ApiKeyConfig {
+                id: id.to_string(),
+                key: format!("{}-secret", id),
+                name: None,
+                roles: vec!["superuser".to_string()],
+            })
+            .collect(),
+        ..Default::default()
+    };
+    let storage = Storage::new();
+    let auth = AuthStore::load(&security, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage), "8.11.0")
+        .with_auth(auth)
+        .with_config(config(1));
+    let server = server_with(state);
+
+    let ingest = api_key("ingest", "ingest-secret");
+    let search = api_key("search", "search-secret");
+    server
+        .get("/")
+        .add_header("Authorization", ingest.clone())
+        .await
+        .assert_status_ok();
+    server
+        .get("/")
+        .add_header("Authorization", ingest)
+        .await
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+    server
+        .get("/")
+        .add_header("Authorization", search)
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_unknown_api_keys_share_the_limit_of_their_address() {
+    let security = SecurityConfig {
+        enabled: true,
+        ..Default::default()
+    };
+    let storage = Storage::new();
+    let auth = AuthStore::load(&security, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage), "8.11.0")
+        .with_auth(auth)
+        .with_config(config(2));
+    let server = server_with(state);
+
+    for id in ["made-up-1", "made-up-2"] {
+        server
+            .get("/")
+            .add_header("Authorization", api_key(id, "guess"))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+    server
+        .get("/")
+        .add_header("Authorization", api_key("made-up-3", "guess"))
+        .await
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_wrong_passwords_are_rejected_then_limited() {
+    let security = SecurityConfig {
+        enabled: true,
+        users: vec![UserConfig {
+            username: "elastic".to_string(),
+            password: "changeme".to_string(),
+            roles: vec!["superuser".to_string()],
+        }],
+        ..Default::default()
+    };
+    let storage = Storage::new();
+    let auth = AuthStore::load(&security, &storage).await.unwrap();
+    let state = AppState::new(Arc::new(storage), "8.11.0")
+        .with_auth(auth)
+        .with_config(config(2));
+    let server = server_with(state);
+
+    let response = server
+        .get("/")
+        .add_header("Authorization", basic_auth("elastic", "wrong"))
+        .await;
+    response.assert_status(StatusCode::UNAUTHORIZED);
+    assert_eq!(response.header("x-ratelimit-remaining"), "1");
+    server
+        .get("/")
+        .add_header("Authorization", basic_auth("elastic", "changeme"))
+        .await
+        .assert_status_ok();
+    server
+        .get("/")
+        .add_header("Authorization", basic_auth("elastic", "wrong"))
+        .await
+        .assert_status(StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[tokio::test]
+async fn test_without_rate_limits_no_headers_are_added() {
+    let server = test_server(Storage::new(), "8.11.0");
+    for _ in 0..3 {
+        let response = server.get("/").await;
+        response.assert_status_ok();
+        assert!(response.maybe_header("x-ratelimit-limit").is_none());
+    }
+}
+
+#[test]
+fn test_buckets_refill_over_time() {
+    let limiter = RateLimiter::new(1000.0, 1);
+    assert!(limiter.check("client").allowed);
+    let limit = limiter.check("client");
+    assert!(!limit.allowed);
+    assert_eq!(limit.retry_after_secs, 1);
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    assert!(limiter.check("client").allowed);
+    // Other clients are unaffected
+    assert!(limiter.check("other").allowed);
+}
+
+#[test]
+fn test_tracked_clients_are_capped() {
+    let limiter = RateLimiter::new(0.01, 2);
+    for client in 0..10_001 {
+        limiter.check(&client.to_string());
+    }
+    assert_eq!(limiter.tracked_clients(), 10_000);
+    // The client with the oldest request was dropped
+    assert_eq!(limiter.check("0").remaining, 1);
+    assert_eq!(limiter.check("10000").remaining, 0);
+}
@@ -0,0 +1,251 @@
+//! Tests for refresh intervals and the `refresh` parameter of writes
+
+mod common;
+
+use axum_test::TestServer;
+use common::{server_with, test_server};
+use gbs::server::AppState;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn create_test_server() -> TestServer {
+    test_server(Storage::new(), "8.11.0")
+}
+
+async fn create_index(server: &TestServer, name: &str, refresh_interval: Option<&str>) {
+    let body = match refresh_interval {
+        Some(interval) => json!({ "settings": { "index": { "refresh_interval": interval } } }),
+        None => json!({}),
+    };
+    server
+        .put(&format!("/{}", name))
+        .json(&body)
+        .await
+        .assert_status_ok();
+}
+
+async fn hit_count(server: &TestServer, index: &str) -> u64 {
+    let body: Value = server
+        .post(&format!("/{}/_search", index))
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .json();
+    body["hits"]["total"]["value"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn test_writes_are_searchable_at_once_without_refresh_interval() {
+    let server = create_test_server();
+    create_index(&server, "books", None).await;
+
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_writes_are_held_back_until_refresh() {
+    let server = create_test_server();
+    create_index(&server, "books", Some("-1")).await;
+
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+    server
+        .post("/books/_doc")
+        .json(&json!({ "title": "Emma" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 0);
+    // Reads by ID are real-time
+    let doc: Value = server.get("/books/_doc/1").await.json();
+    assert_eq!(doc["_source"]["title"], "Dune");
+
+    server.post("/books/_refresh").await.assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 2);
+
+    // Deletes are held back too
+    server.delete("/books/_doc/1").await.assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 2);
+    server.post("/_refresh").await.assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_refresh_parameter() {
+    let server = create_test_server();
+    create_index(&server, "books", Some("-1")).await;
+
+    server
+        .put("/books/_doc/1")
+        .add_query_param("refresh", "true")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 1);
+
+    server
+        .post("/books/_bulk")
+        .add_query_param("refresh", "true")
+        .text("{\"index\":{\"_id\":\"2\"}}\n{\"title\":\"Emma\"}\n")
+        .content_type("application/x-ndjson")
+        .await
+        .assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 2);
+
+    server
+        .put("/books/_doc/3")
+        .add_query_param("refresh", "sometimes")
+        .json(&json!({ "title": "Ulysses" }))
+        .await
+        .assert_status_bad_request();
+    // A write with an invalid refresh parameter is not made
+    server.get("/books/_doc/3").await.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_refresh_interval_makes_writes_searchable() {
+    let server = create_test_server();
+    create_index(&server, "books", Some("200ms")).await;
+
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 0);
+    tokio::time::sleep(Duration::from_millis(250)).await;
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_answers_once_the_write_is_searchable() {
+    let server = create_test_server();
+    create_index(&server, "books", Some("200ms")).await;
+    server
+        .put("/books/_doc/1")
+        .add_query_param("refresh", "true")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+
+    server
+        .put("/books/_doc/2")
+        .add_query_param("refresh", "wait_for")
+        .json(&json!({ "title": "Emma" }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    assert_eq!(hit_count(&server, "books").await, 2);
+
+    server
+        .post("/books/_bulk")
+        .add_query_param("refresh", "wait_for")
+        .text("{\"delete\":{\"_id\":\"1\"}}\n")
+        .content_type("application/x-ndjson")
+        .await
+        .assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_without_refresh_interval_waits_for_an_explicit_refresh() {
+    let state = AppState::new(Arc::new(Storage::new()), "8.11.0");
+    let storage = state.storage.clone();
+    let server = server_with(state);
+    create_index(&server, "books", Some("-1")).await;
+
+    let write = server
+        .put("/books/_doc/1")
+        .add_query_param("refresh", "wait_for")
+        .json(&json!({ "title": "Dune" }));
+    let refresh = async {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        storage.refresh_index("books").await.unwrap();
+    };
+    let (response, ()) = tokio::join!(async { write.await }, refresh);
+    response.assert_status(axum::http::StatusCode::CREATED);
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_wait_for_refreshes_past_max_refresh_listeners() {
+    let server = create_test_server();
+    server
+        .put("/books")
+        .json(&json!({ "settings": {
+            "index": { "refresh_interval": "-1", "max_refresh_listeners": 0 }
+        } }))
+        .await
+        .assert_status_ok();
+
+    server
+        .put("/books/_doc/1")
+        .add_query_param("refresh", "wait_for")
+        .json(&json!({ "title": "Dune" }))
+        .await
+        .assert_status(axum::http::StatusCode::CREATED);
+    assert_eq!(hit_count(&server, "books").await, 1);
+}
+
+#[tokio::test]
+async fn test_abandoned_wait_for_does_not_count_as_listener() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "books",
+            Some(json!({ "index": { "refresh_interval": "-1", "max_refresh_listeners": 1 } })),
+            None,
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document("books", "1", json!({ "title": "Dune" }))
+        .await
+        .unwrap();
+
+    // Both writes stop waiting when their request goes away; had the first
+    // kept its place, the second would refresh instead of waiting
+    for _ in 0..2 {
+        let wait = storage.wait_for_refresh("books");
+        assert!(tokio::time::timeout(Duration::from_millis(50), wait)
+            .await
+            .is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_changing_refresh_interval_refreshes() {
+    let server = create_test_server();
+    create_index(&server, "books", Some("-1")).await;
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 0);
+
+    server
+        .put("/books/_settings")
+        .json(&json!({ "index": { "refresh_interval": null } }))
+        .await
+        .assert_status_ok();
+    assert_eq!(hit_count(&server, "books").await, 1);
+    server
+        .put("/books/_doc/2")
+        .json(&json!({ "title": "Emma" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 2);
+
+    // Writes are held back again once the interval is set back
+    server
+        .put("/books/_settings")
+        .json(&json!({ "index": { "refresh_interval": "-1" } }))
+        .await
+        .assert_status_ok();
+    server
+        .put("/books/_doc/3")
+        .json(&json!({ "title": "Ulysses" }))
+        .await;
+    assert_eq!(hit_count(&server, "books").await, 2);
+}
@@ -0,0 +1,170 @@
+//! Tests for copying documents between indices
+
+use gbs::storage::{ReindexRequest, Storage};
+use serde_json::json;
+
+async fn products(storage: &Storage) {
+    storage
+        .create_index(
+            "products-v1",
+            None,
+            Some(json!({ "properties": { "price": { "type": "keyword" } } })),
+        )
+        .await
+        .unwrap();
+    for (id, title, price) in [
+        ("1", "red shirt", "10"),
+        ("2", "blue shirt", "20"),
+        ("3", "hat", "5"),
+    ] {
+        storage
+            .index_document(
+                "products-v1",
+                id,
+                json!({ "title": title, "price": price, "legacy": true, "meta": { "sku": id } }),
+            )
+            .await
+            .unwrap();
+    }
+}
+
+async fn source_of(storage: &Storage, index: &str, id: &str) -> serde_json::Value {
+    storage.get_document(index, id).await.unwrap()["_source"].clone()
+}
+
+fn request(body: serde_json::Value) -> ReindexRequest {
+    ReindexRequest::parse(&body).unwrap()
+}
+
+#[tokio::test]
+async fn test_reindex_into_prepared_index() {
+    let storage = Storage::new();
+    products(&storage).await;
+    storage
+        .create_index(
+            "products-v2",
+            None,
+            Some(json!({ "properties": { "price": { "type": "integer" } } })),
+        )
+        .await
+        .unwrap();
+
+    let response = storage
+        .reindex(&request(json!({
+            "source": { "index": "products-v1" },
+            "dest": { "index": "products-v2" }
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.total, 3);
+    assert_eq!(response.created, 3);
+    assert_eq!(response.batches, 1);
+    assert!(response.failures.is_empty());
+    assert_eq!(
+        source_of(&storage, "products-v2", "2").await["title"],
+        "blue shirt"
+    );
+
+    // Reindexing again overwrites the copies
+    let response = storage
+        .reindex(&request(json!({
+            "source": { "index": "products-v1" },
+            "dest": { "index": "products-v2" }
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.created, 0);
+    assert_eq!(response.updated, 3);
+}
+
+#[tokio::test]
+async fn test_reindex_with_query_filter_and_rename() {
+    let storage = Storage::new();
+    products(&storage).await;
+
+    let response = storage
+        .reindex(&request(json!({
+            "source": {
+                "index": "products-v1",
+                "query": { "match": { "title": "shirt" } },
+                "_source": { "excludes": ["legacy"] }
+            },
+            "dest": { "index": "shirts" },
+            "rename": { "title": "name", "meta.sku": "sku" }
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.total, 2);
+    assert_eq!(response.created, 2);
+
+    // The destination was created
+    assert!(storage.index_exists("shirts").await.unwrap());
+    let document = source_of(&storage, "shirts", "1").await;
+    assert_eq!(
+        document,
+        json!({ "name": "red shirt", "price": "10", "meta": {}, "sku": "1" })
+    );
+    assert!(storage.get_document("shirts", "3").await.is_err());
+}
+
+#[tokio::test]
+async fn test_reindex_create_reports_conflicts() {
+    let storage = Storage::new();
+    products(&storage).await;
+    storage.create_index("copy", None, None).await.unwrap();
+    storage
+        .index_document("copy", "1", json!({ "title": "kept" }))
+        .await
+        .unwrap();
+
+    let body = json!({
+        "source": { "index": "products-v1" },
+        "dest": { "index": "copy", "op_type": "create" }
+    });
+    let response = storage.reindex(&request(body.clone())).await.unwrap();
+    assert_eq!(response.created, 2);
+    assert_eq!(response.version_conflicts, 1);
+    assert_eq!(response.failures.len(), 1);
+    assert_eq!(response.failures[0].id, "1");
+    assert_eq!(response.failures[0].status, 409);
+    assert_eq!(source_of(&storage, "copy", "1").await["title"], "kept");
+
+    let mut body = body;
+    body["conflicts"] = json!("proceed");
+    body["source"]["size"] = json!(1);
+    let response = storage.reindex(&request(body)).await.unwrap();
+    assert_eq!(response.created, 0);
+    assert_eq!(response.version_conflicts, 3);
+    assert_eq!(response.batches, 3);
+    assert!(response.failures.is_empty());
+}
+
+#[tokio::test]
+async fn test_reindex_max_docs_and_invalid_requests() {
+    let storage = Storage::new();
+    products(&storage).await;
+
+    let response = storage
+        .reindex(&request(json!({
+            "source": { "index": "products-*" },
+            "dest": { "index": "sample" },
+            "max_docs": 2
+        })))
+        .await
+        .unwrap();
+    assert_eq!(response.total, 2);
+
+    assert!(storage
+        .reindex(&request(json!({
+            "source": { "index": "products-v1" },
+            "dest": { "index": "products-v1" }
+        })))
+        .await
+        .is_err());
+    assert!(ReindexRequest::parse(&json!({ "source": { "index": "a" } })).is_err());
+    assert!(ReindexRequest::parse(&json!({
+        "source": { "index": "a" },
+        "dest": { "index": "b", "op_type": "upsert" }
+    }))
+    .is_err());
+}
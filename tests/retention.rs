@@ -0,0 +1,129 @@
+//! Tests for document and index retention
+
+mod common;
+
+use common::open_sled;
+use gbs::storage::{IndexTier, Storage};
+use serde_json::json;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_expired_documents_deleted() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            Some(json!({ "index": { "retention": { "max_document_age": "1d" } } })),
+            None,
+        )
+        .await
+        .unwrap();
+    let now = chrono::Utc::now().to_rfc3339();
+    for (id, document) in [
+        ("old", json!({ "@timestamp": "2000-01-01T00:00:00Z" })),
+        ("new", json!({ "@timestamp": now })),
+        ("untimed", json!({ "message": "no timestamp" })),
+    ] {
+        storage.index_document("logs", id, document).await.unwrap();
+    }
+
+    let report = storage.apply_retention().await.unwrap();
+    assert_eq!(report.deleted_documents.get("logs"), Some(&1));
+    assert!(!storage.document_exists("logs", "old").await.unwrap());
+    assert!(storage.document_exists("logs", "new").await.unwrap());
+    assert!(storage.document_exists("logs", "untimed").await.unwrap());
+
+    // Nothing left to do
+    assert!(storage.apply_retention().await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_expired_documents_custom_field_and_warm_index() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = open_sled(temp_dir.path().join("db")).await;
+    storage.create_index("events", None, None).await.unwrap();
+    storage
+        .index_document("events", "1", json!({ "created": 946684800000i64 }))
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "events",
+            "2",
+            json!({ "created": chrono::Utc::now().timestamp_millis() }),
+        )
+        .await
+        .unwrap();
+    storage
+        .set_index_tier("events", IndexTier::Warm)
+        .await
+        .unwrap();
+
+    // Retention is a dynamic setting
+    storage
+        .update_settings(
+            "events",
+            json!({ "index.retention.field": "created", "index.retention.max_document_age": "30d" }),
+        )
+        .await
+        .unwrap();
+
+    let report = storage.apply_retention().await.unwrap();
+    assert_eq!(report.deleted_documents.get("events"), Some(&1));
+    assert!(!storage.document_exists("events", "1").await.unwrap());
+    assert!(storage.document_exists("events", "2").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_rollover_and_delete_after() {
+    let storage = Storage::new();
+    let settings =
+        json!({ "index.retention.rollover_after": "0ms", "index.retention.delete_after": "0ms" });
+    storage
+        .create_index("logs-000001", Some(settings), None)
+        .await
+        .unwrap();
+    storage.put_alias("logs-000001", "logs").await.unwrap();
+    storage
+        .index_document("logs", "1", json!({ "message": "hello" }))
+        .await
+        .unwrap();
+
+    // The alias moves to a new index with the same settings and the old
+    // index is deleted in the same run
+    let report = storage.apply_retention().await.unwrap();
+    assert_eq!(
+        report.rolled_over,
+        vec![("logs".to_string(), "logs-000002".to_string())]
+    );
+    assert_eq!(report.deleted_indices, vec!["logs-000001".to_string()]);
+    assert!(!storage.index_exists("logs-000001").await.unwrap());
+
+    // The new index is empty and still holds the alias
+    assert!(storage.apply_retention().await.unwrap().is_empty());
+    assert!(storage.index_exists("logs-000002").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_retention_settings_validated() {
+    let storage = Storage::new();
+    let result = storage
+        .create_index(
+            "logs",
+            Some(json!({ "index.retention.max_document_age": "a week" })),
+            None,
+        )
+        .await;
+    assert!(result.is_err());
+
+    storage
+        .create_index(
+            "logs",
+            Some(json!({ "index.retention.delete_after": "-1" })),
+            None,
+        )
+        .await
+        .unwrap();
+    assert!(storage.apply_retention().await.unwrap().is_empty());
+    assert!(storage.index_exists("logs").await.unwrap());
+}
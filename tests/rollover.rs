@@ -0,0 +1,169 @@
+//! Tests for the rollover API
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::open_sled;
+use gbs::storage::{RolloverRequest, Storage};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use tempfile::TempDir;
+
+async fn server() -> TestServer {
+    let storage = Storage::new();
+    storage
+        .create_index("logs-000001", None, None)
+        .await
+        .unwrap();
+    storage.put_alias("logs-000001", "logs").await.unwrap();
+    common::index_disk_full(&storage, "logs", &["1", "2", "3"]).await;
+    common::test_server(storage, "7.10.2")
+}
+
+#[tokio::test]
+async fn test_rollover_conditions() {
+    let server = server().await;
+
+    // No condition is met yet
+    let body: Value = server
+        .post("/logs/_rollover")
+        .json(&json!({ "conditions": { "max_docs": 10, "max_age": "7d", "max_size": "1gb" } }))
+        .await
+        .json();
+    assert_eq!(body["rolled_over"], false);
+    assert_eq!(body["acknowledged"], false);
+    assert_eq!(body["old_index"], "logs-000001");
+    assert_eq!(body["new_index"], "logs-000002");
+    assert_eq!(
+        body["conditions"],
+        json!({ "[max_docs: 10]": false, "[max_age: 7d]": false, "[max_size: 1gb]": false })
+    );
+    server.get("/logs-000002").expect_failure().await;
+
+    // A dry run reports the met condition without rolling over
+    let body: Value = server
+        .post("/logs/_rollover?dry_run=true")
+        .json(&json!({ "conditions": { "max_docs": 3 } }))
+        .await
+        .json();
+    assert_eq!(body["conditions"]["[max_docs: 3]"], true);
+    assert_eq!(body["dry_run"], true);
+    assert_eq!(body["rolled_over"], false);
+
+    let body: Value = server
+        .post("/logs/_rollover")
+        .json(&json!({ "conditions": { "max_docs": 3, "max_age": "7d" } }))
+        .await
+        .json();
+    assert_eq!(body["rolled_over"], true);
+    assert_eq!(body["acknowledged"], true);
+    assert_eq!(body["new_index"], "logs-000002");
+
+    // Writes through the alias go to the new index
+    server
+        .put("/logs/_doc/4")
+        .json(&json!({ "message": "new" }))
+        .await
+        .assert_status_success();
+    let body: Value = server.get("/logs-000002/_doc/4").await.json();
+    assert_eq!(body["_source"]["message"], "new");
+    server
+        .get("/logs-000001/_doc/4")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_rollover_uses_templates() {
+    let server = server().await;
+    server
+        .put("/_index_template/logs")
+        .json(&json!({
+            "index_patterns": ["logs-*"],
+            "template": { "settings": { "number_of_replicas": 0 } }
+        }))
+        .await
+        .assert_status_ok();
+
+    // Without conditions the alias always rolls over, here to a given name
+    let body: Value = server
+        .post("/logs/_rollover/logs-2024")
+        .json(&json!({ "aliases": { "logs-read": {} } }))
+        .await
+        .json();
+    assert_eq!(body["rolled_over"], true);
+    assert_eq!(body["new_index"], "logs-2024");
+
+    let index: Value = server.get("/logs-2024").await.json();
+    assert!(index["logs-2024"]["settings"]
+        .to_string()
+        .contains("number_of_replicas"));
+    let aliases = index["logs-2024"]["aliases"].as_object().unwrap();
+    assert!(aliases.contains_key("logs"));
+    assert!(aliases.contains_key("logs-read"));
+    let old: Value = server.get("/logs-000001").await.json();
+    assert!(old["logs-000001"]["aliases"].get("logs").is_none());
+}
+
+#[tokio::test]
+async fn test_rollover_errors() {
+    let server = server().await;
+
+    server
+        .post("/logs-000001/_rollover")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/missing/_rollover")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+    server
+        .post("/logs/_rollover")
+        .json(&json!({ "conditions": { "max_age": "soon" } }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+    server
+        .post("/logs/_rollover")
+        .json(&json!({ "conditions": { "max_shards": 3 } }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+
+    // The alias of several indices has no write index
+    server.put("/logs-old").await.assert_status_ok();
+    server.put("/logs-old/_alias/logs").await.assert_status_ok();
+    let response = server.post("/logs/_rollover").expect_failure().await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "illegal_argument_exception"
+    );
+}
+
+#[tokio::test]
+async fn test_rollover_persisted() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    {
+        let storage = open_sled(&path).await;
+        storage
+            .create_index("logs-000001", None, None)
+            .await
+            .unwrap();
+        storage.put_alias("logs-000001", "logs").await.unwrap();
+        let request = RolloverRequest::parse("logs", None, &json!({}), &HashMap::new()).unwrap();
+        let response = storage.rollover(&request).await.unwrap();
+        assert!(response.rolled_over);
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&path).await;
+    common::index_disk_full(&storage, "logs", &["1"]).await;
+    let doc = storage.get_document("logs-000002", "1").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "disk full");
+}
@@ -0,0 +1,181 @@
+//! Tests for content-based routing of documents through ingest routes
+
+use gbs::bulk_ops::BulkAction;
+use gbs::config::IngestConfig;
+use gbs::storage::{IngestRoute, IngestRoutes, Storage};
+use serde_json::json;
+
+fn logs_storage() -> Storage {
+    let mut routes = IngestRoutes::default();
+    routes.add(
+        IngestRoute::new(
+            "logs",
+            "logs-{timestamp:yyyy.MM.dd}",
+            None,
+            Some(json!({ "properties": { "message": { "type": "text" } } })),
+        )
+        .unwrap(),
+    );
+    Storage::new().with_ingest_routes(routes)
+}
+
+#[test]
+fn test_route_target_index() {
+    let route = IngestRoute::new("logs", "logs-{service}-{timestamp:yyyy.MM}", None, None).unwrap();
+    let target = |doc| route.target_index(&doc);
+
+    assert_eq!(
+        target(json!({ "service": "API", "timestamp": "2024-03-15T10:00:00Z" })).unwrap(),
+        "logs-api-2024.03"
+    );
+    // Epoch milliseconds, plain dates and offsets (converted to UTC)
+    assert_eq!(
+        target(json!({ "service": "api", "timestamp": 1_709_251_200_000_i64 })).unwrap(),
+        "logs-api-2024.03"
+    );
+    assert_eq!(
+        target(json!({ "service": "api", "timestamp": "2024-12-31" })).unwrap(),
+        "logs-api-2024.12"
+    );
+    assert_eq!(
+        target(json!({ "service": "api", "timestamp": "2024-12-31T23:30:00-02:00" })).unwrap(),
+        "logs-api-2025.01"
+    );
+
+    assert!(target(json!({ "service": "api" })).is_err());
+    assert!(target(json!({ "service": "api", "timestamp": "yesterday" })).is_err());
+    assert!(target(json!({ "service": "a/b", "timestamp": "2024-03-15" })).is_err());
+
+    assert!(IngestRoute::new("logs", "logs", None, None).is_err());
+    assert!(IngestRoute::new("logs", "logs-{timestamp", None, None).is_err());
+    assert!(IngestRoute::new("logs", "logs-{timestamp:week}", None, None).is_err());
+}
+
+#[tokio::test]
+async fn test_index_document_through_route() {
+    let storage = logs_storage();
+
+    storage
+        .index_document(
+            "logs",
+            "1",
+            json!({ "timestamp": "2024-03-15T10:00:00Z", "message": "a" }),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "logs",
+            "2",
+            json!({ "timestamp": "2024-03-16T10:00:00Z", "message": "b" }),
+        )
+        .await
+        .unwrap();
+    let (id, created) = storage
+        .create_document(
+            "logs",
+            json!({ "timestamp": "2024-03-15T23:59:59Z", "message": "c" }),
+        )
+        .await
+        .unwrap();
+
+    let mut indices = storage.list_indices().await;
+    indices.sort();
+    assert_eq!(indices, vec!["logs-2024.03.15", "logs-2024.03.16"]);
+    assert!(storage.get_document("logs-2024.03.15", "1").await.is_ok());
+    assert!(storage.get_document("logs-2024.03.15", &id).await.is_ok());
+    assert_eq!(created.index, "logs-2024.03.15");
+    assert!(storage.get_document("logs-2024.03.16", "2").await.is_ok());
+
+    // Created indices use the route's mappings
+    let index = storage.get_index("logs-2024.03.16").await.unwrap();
+    assert_eq!(
+        index["logs-2024.03.16"]["mappings"]["properties"]["message"]["type"],
+        "text"
+    );
+
+    // Documents the route cannot place are rejected
+    assert!(storage
+        .index_document("logs", "3", json!({ "message": "no timestamp" }))
+        .await
+        .is_err());
+    assert_eq!(storage.list_indices().await.len(), 2);
+}
+
+#[tokio::test]
+async fn test_existing_index_takes_precedence_over_route() {
+    let storage = logs_storage();
+    storage.create_index("logs", None, None).await.unwrap();
+
+    storage
+        .index_document("logs", "1", json!({ "message": "no timestamp needed" }))
+        .await
+        .unwrap();
+    assert_eq!(storage.list_indices().await, vec!["logs"]);
+}
+
+#[tokio::test]
+async fn test_bulk_actions_through_route() {
+    let storage = logs_storage();
+
+    let (index, _, status, ..) = storage
+        .execute_bulk_action(BulkAction::Index {
+            index: "logs".to_string(),
+            id: Some("1".to_string()),
+            document: json!({ "timestamp": "2024-03-15T10:00:00Z", "level": "info" }),
+        })
+        .await
+        .unwrap();
+    assert_eq!(index, "logs-2024.03.15");
+    assert_eq!(status, 201);
+
+    let (index, ..) = storage
+        .execute_bulk_action(BulkAction::Update {
+            index: "logs".to_string(),
+            id: "1".to_string(),
+            document: json!({ "timestamp": "2024-03-15T10:00:00Z", "level": "warn" }),
+        })
+        .await
+        .unwrap();
+    assert_eq!(index, "logs-2024.03.15");
+    let doc = storage.get_document("logs-2024.03.15", "1").await.unwrap();
+    assert_eq!(doc["_source"]["level"], "warn");
+
+    // Create fails for an existing document in the derived index
+    assert!(storage
+        .execute_bulk_action(BulkAction::Create {
+            index: "logs".to_string(),
+            id: Some("1".to_string()),
+            document: json!({ "timestamp": "2024-03-15T10:00:00Z" }),
+        })
+        .await
+        .is_err());
+}
+
+#[test]
+fn test_routes_from_config() {
+    let yaml = r#"
+routes:
+  - name: metrics
+    index_pattern: "metrics-{host}"
+    settings:
+      number_of_shards: 1
+"#;
+    let config: IngestConfig = serde_yaml::from_str(yaml).unwrap();
+    let routes = IngestRoutes::from_config(&config).unwrap();
+    let route = routes.get("metrics").unwrap();
+    assert_eq!(
+        route.target_index(&json!({ "host": "web-1" })).unwrap(),
+        "metrics-web-1"
+    );
+    assert_eq!(route.settings, Some(json!({ "number_of_shards": 1 })));
+    assert!(routes.get("logs").is_none());
+
+    let yaml = r#"
+routes:
+  - name: broken
+    index_pattern: "no-placeholder"
+"#;
+    let config: IngestConfig = serde_yaml::from_str(yaml).unwrap();
+    assert!(IngestRoutes::from_config(&config).is_err());
+}
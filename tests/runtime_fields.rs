@@ -0,0 +1,145 @@
+//! Tests for runtime fields (`runtime_mappings` in search bodies)
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::ids;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+/// Orders in two indices, with timestamps in epoch milliseconds
+async fn orders() -> TestServer {
+    let server = common::test_server(Storage::new(), "8.11.0");
+    for index in ["orders", "archive"] {
+        server.put(&format!("/{}", index)).await.assert_status_ok();
+    }
+    for (index, id, doc) in [
+        (
+            "orders",
+            "1",
+            json!({ "item": "tea", "price": 4.5, "qty": 2, "status": "PAID", "ts": 1704067200000i64 }),
+        ),
+        (
+            "orders",
+            "2",
+            json!({ "item": "cake", "price": 3, "qty": 5, "status": 200, "ts": 1704070800000i64 }),
+        ),
+        (
+            "orders",
+            "3",
+            json!({ "item": "scone", "price": 2, "qty": 1, "ts": 1704153600000i64 }),
+        ),
+        (
+            "archive",
+            "4",
+            json!({ "item": "jam", "price": 6, "qty": 3, "status": "PAID", "ts": 1703980800000i64 }),
+        ),
+    ] {
+        server
+            .put(&format!("/{}/_doc/{}", index, id))
+            .json(&doc)
+            .await
+            .assert_status(StatusCode::CREATED);
+    }
+    server
+}
+
+fn runtime_mappings() -> Value {
+    json!({
+        "total": {
+            "type": "double",
+            "script": "emit(doc['price'].value * doc['qty'].value)"
+        },
+        "day": {
+            "type": "date",
+            "script": { "source": "emit(doc['ts'].value + params.offset)", "params": { "offset": 0 } }
+        },
+        "status": { "type": "keyword" }
+    })
+}
+
+#[tokio::test]
+async fn test_runtime_fields_can_be_queried_and_sorted() {
+    let server = orders().await;
+    let body: Value = server
+        .post("/orders/_search")
+        .json(&json!({
+            "runtime_mappings": runtime_mappings(),
+            "query": { "range": { "total": { "gte": 5 } } },
+            "sort": [{ "total": "desc" }]
+        }))
+        .await
+        .json();
+    assert_eq!(ids(&body), vec!["2", "1"]);
+    let hit = &body["hits"]["hits"][0];
+    assert_eq!(hit["sort"], json!([15.0]));
+    assert_eq!(hit["fields"]["total"], json!([15.0]));
+    assert_eq!(hit["fields"]["day"], json!(["2024-01-01T01:00:00.000Z"]));
+    // Runtime fields without a script shadow the source field
+    assert_eq!(hit["fields"]["status"], json!(["200"]));
+    // The source is returned as stored
+    assert_eq!(hit["_source"]["status"], 200);
+    assert!(hit["_source"].get("total").is_none());
+
+    let body: Value = server
+        .post("/orders/_search")
+        .json(&json!({
+            "runtime_mappings": runtime_mappings(),
+            "query": { "term": { "status": "200" } }
+        }))
+        .await
+        .json();
+    assert_eq!(ids(&body), vec!["2"]);
+}
+
+#[tokio::test]
+async fn test_runtime_fields_in_aggregations_and_across_indices() {
+    let server = orders().await;
+    let body: Value = server
+        .post("/orders,archive/_search")
+        .json(&json!({
+            "runtime_mappings": runtime_mappings(),
+            "query": { "match_all": {} },
+            "sort": [{ "total": "asc" }],
+            "size": 2,
+            "aggs": {
+                "per_day": { "date_histogram": { "field": "day", "calendar_interval": "day" } }
+            }
+        }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 4);
+    assert_eq!(ids(&body), vec!["3", "1"]);
+    let counts: Vec<u64> = body["aggregations"]["per_day"]["buckets"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|bucket| bucket["doc_count"].as_u64().unwrap())
+        .collect();
+    assert_eq!(counts, vec![1, 2, 1]);
+}
+
+#[tokio::test]
+async fn test_invalid_runtime_mappings() {
+    let server = orders().await;
+    for runtime_mappings in [
+        json!({ "total": { "script": "emit(1)" } }),
+        json!({ "total": { "type": "geo_shape" } }),
+        json!({ "total": { "type": "long", "script": "emit(" } }),
+        json!({ "item_count": { "type": "long", "script": "emit(doc['item'].value)" } }),
+        json!(["total"]),
+    ] {
+        server
+            .post("/orders/_search")
+            .json(&json!({ "runtime_mappings": runtime_mappings }))
+            .await
+            .assert_status_bad_request();
+    }
+    // emit is only available to runtime fields
+    server
+        .post("/orders/_search")
+        .json(&json!({ "script_fields": { "total": { "script": "emit(1)" } } }))
+        .await
+        .assert_status_bad_request();
+}
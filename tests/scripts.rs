@@ -0,0 +1,296 @@
+//! Tests for scripts: script fields and script sorting
+
+mod common;
+
+use axum::http::StatusCode;
+use common::test_server;
+use gbs::error::GbsError;
+use gbs::storage::{Script, Storage};
+use serde_json::{json, Value};
+
+fn run(source: &str, doc: Value) -> Value {
+    Script::parse(&json!(source))
+        .unwrap()
+        .execute(&doc, 1.5)
+        .unwrap()
+}
+
+async fn orders() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("orders", None, None).await.unwrap();
+    let orders = [
+        ("1", json!({ "item": "pen", "price": 2.5, "qty": 4 })),
+        ("2", json!({ "item": "book", "price": 12, "qty": 1 })),
+        ("3", json!({ "item": "lamp", "price": 30, "qty": 2 })),
+        ("4", json!({ "item": "gift card" })),
+    ];
+    for (id, order) in orders {
+        storage.index_document("orders", id, order).await.unwrap();
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_expressions() {
+    let doc = json!({
+        "price": 10,
+        "ratio": 0.5,
+        "name": "Widget",
+        "tags": ["b", "a"],
+        "nested": { "count": 3 }
+    });
+
+    // Integer arithmetic stays integral, mixed arithmetic is floating point
+    assert_eq!(run("doc['price'].value * 2 + 1", doc.clone()), json!(21));
+    assert_eq!(run("doc['price'].value / 4", doc.clone()), json!(2));
+    assert_eq!(run("doc['price'].value / 4.0", doc.clone()), json!(2.5));
+    assert_eq!(run("(doc['price'].value + 2) % 5", doc.clone()), json!(2));
+    assert_eq!(
+        run("-doc['ratio'].value * _score", doc.clone()),
+        json!(-0.75)
+    );
+    assert_eq!(run("(int) (doc['ratio'].value * 7)", doc.clone()), json!(3));
+
+    // Strings
+    assert_eq!(
+        run("doc['name'].value + ': ' + doc['price'].value", doc.clone()),
+        json!("Widget: 10")
+    );
+    assert_eq!(
+        run(
+            "doc['name.keyword'].value.toLowerCase().substring(0, 3)",
+            doc.clone()
+        ),
+        json!("wid")
+    );
+
+    // Doc values are sorted; nested fields by dotted path
+    assert_eq!(run("doc['tags'].value", doc.clone()), json!("a"));
+    assert_eq!(run("doc['tags'].size()", doc.clone()), json!(2));
+    assert_eq!(run("doc['tags'][1]", doc.clone()), json!("b"));
+    assert_eq!(run("doc['nested.count'].value", doc.clone()), json!(3));
+    assert_eq!(run("doc['missing'].empty", doc.clone()), json!(true));
+    assert_eq!(run("doc.containsKey('price')", doc.clone()), json!(true));
+
+    // Conditionals
+    assert_eq!(
+        run(
+            "doc['price'].value > 5 && !doc['tags'].empty ? 'big' : 'small'",
+            doc.clone()
+        ),
+        json!("big")
+    );
+    assert_eq!(
+        run(
+            "def total = 0; if (doc['price'].size() == 0) { return null; } \
+             else if (doc['price'].value > 100) { total = 100; } else { total = doc['price'].value; } \
+             total += 1; return total;",
+            doc.clone()
+        ),
+        json!(11)
+    );
+    assert_eq!(
+        run(
+            "Math.max(doc['price'].value, 12) + Math.round(2.5)",
+            doc.clone()
+        ),
+        json!(15)
+    );
+    assert_eq!(run("Math.sqrt(16)", doc.clone()), json!(4.0));
+    assert_eq!(run("String.valueOf(1.0) + 'x'", doc.clone()), json!("1.0x"));
+
+    // Parameters and the source
+    let script = Script::parse(&json!({
+        "lang": "painless",
+        "source": "params.base + params['factor'] * params._source.nested.count",
+        "params": { "base": 1, "factor": 2 }
+    }))
+    .unwrap();
+    assert_eq!(script.execute(&doc, 0.0).unwrap(), json!(7));
+}
+
+#[tokio::test]
+async fn test_script_errors() {
+    for source in [
+        "doc['price'].value +",
+        "(1 + 2",
+        "'unclosed",
+        "x + 1",
+        "1 # 2",
+        "",
+    ] {
+        let error = Script::parse(&json!(source)).unwrap_err();
+        assert!(
+            matches!(error, GbsError::Script(_)),
+            "{}: {}",
+            source,
+            error
+        );
+        assert!(error.to_string().starts_with("compile error"), "{}", error);
+    }
+    let nested = format!("{}1{}", "(".repeat(100), ")".repeat(100));
+    assert!(Script::parse(&json!(nested)).is_err());
+    assert!(Script::parse(&json!({ "source": "1", "lang": "groovy" })).is_err());
+
+    let runtime = |source: &str| {
+        Script::parse(&json!(source))
+            .unwrap()
+            .execute(&json!({ "price": 10, "name": "pen" }), 0.0)
+            .unwrap_err()
+            .to_string()
+    };
+    assert_eq!(
+        runtime("doc['missing'].value"),
+        "runtime error: A document doesn't have a value for a field! Use doc[<field>].size()==0 to check if a document is missing a field!"
+    );
+    assert_eq!(
+        runtime("doc['price'].value / 0"),
+        "runtime error: / by zero"
+    );
+    assert!(runtime("doc['name'].value * 2").contains("cannot apply [*] to [String] and [long]"));
+    assert!(runtime("doc['price'].value ? 1 : 2").contains("cannot cast [long] to [boolean]"));
+}
+
+#[tokio::test]
+async fn test_script_sort() {
+    let storage = orders().await;
+
+    let ids = |response: Value| -> Vec<String> {
+        response["hits"]["hits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["_id"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let sort = json!([{
+        "_script": {
+            "type": "number",
+            "script": {
+                "source": "doc['price'].value * doc['qty'].value * params.tax",
+                "params": { "tax": 1.2 }
+            },
+            "order": "desc"
+        }
+    }]);
+    let response = storage
+        .search(
+            "orders",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            Some(&sort),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    // The gift card has no price and sorts last
+    assert_eq!(ids(response), vec!["3", "2", "1", "4"]);
+
+    let sort = json!({
+        "_script": { "type": "string", "script": "doc['item'].value.substring(1)", "order": "asc" }
+    });
+    let response = storage
+        .search(
+            "orders",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            Some(&sort),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    assert_eq!(ids(response), vec!["3", "1", "4", "2"]);
+
+    let invalid = storage
+        .search(
+            "orders",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            Some(&json!({ "_script": { "type": "number", "script": "doc[" } })),
+            None,
+            None,
+        )
+        .await;
+    assert!(matches!(invalid, Err(GbsError::Script(_))));
+}
+
+#[tokio::test]
+async fn test_script_fields_api() {
+    let storage = orders().await;
+    let server = test_server(storage, "6.8.23");
+
+    let body: Value = server
+        .post("/orders/_search")
+        .json(&json!({
+            "query": { "range": { "qty": { "gte": 2 } } },
+            "sort": [{ "_script": { "type": "number", "script": "doc['qty'].value", "order": "asc" } }],
+            "script_fields": {
+                "total": {
+                    "script": {
+                        "source": "doc['price'].value * doc['qty'].value"
+                    }
+                },
+                "label": {
+                    "script": "doc['qty'].value > 3 ? 'bulk' : doc['item'].value"
+                }
+            }
+        }))
+        .await
+        .json();
+    let hits = body["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0]["_id"], "3");
+    assert_eq!(
+        hits[0]["fields"],
+        json!({ "total": [60], "label": ["lamp"] })
+    );
+    assert_eq!(
+        hits[1]["fields"],
+        json!({ "total": [10.0], "label": ["bulk"] })
+    );
+    // The source is still returned
+    assert_eq!(hits[1]["_source"]["item"], "pen");
+
+    // Multi-index searches compute script fields too
+    let body: Value = server
+        .post("/_search")
+        .json(&json!({
+            "indices": ["orders"],
+            "query": { "term": { "item": "book" } },
+            "script_fields": { "double_price": { "script": "doc['price'].value * 2" } }
+        }))
+        .await
+        .json();
+    assert_eq!(
+        body["hits"]["hits"][0]["fields"]["double_price"],
+        json!([24])
+    );
+
+    // Scripts that fail to compile or run are bad requests
+    let response = server
+        .post("/orders/_search")
+        .json(&json!({
+            "query": { "term": { "item": "nothing" } },
+            "script_fields": { "bad": { "script": "doc['qty'].value +" } }
+        }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "script_exception"
+    );
+    server
+        .post("/orders/_search")
+        .json(&json!({
+            "script_fields": { "bad": { "script": "doc['qty'].value" } }
+        }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,173 @@
+//! Tests for stored_fields and docvalue_fields in search responses
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::test_server;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn server() -> TestServer {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "events",
+            None,
+            Some(json!({
+                "properties": {
+                    "title": { "type": "text", "fields": { "keyword": { "type": "keyword" } } },
+                    "status": { "type": "keyword" },
+                    "count": { "type": "integer" },
+                    "ratio": { "type": "double" },
+                    "created": { "type": "date" },
+                    "day": { "type": "date", "format": "dd/MM/yyyy" },
+                    "user": { "properties": { "name": { "type": "keyword" } } }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "events",
+            "1",
+            json!({
+                "title": "Launch",
+                "status": "open",
+                "count": 7,
+                "ratio": 0.5,
+                "created": "2024-05-01T10:00:00Z",
+                "day": "02/05/2024",
+                "user": { "name": "ada", "roles": ["b", "a"] },
+                "tags": ["z", "y"]
+            }),
+        )
+        .await
+        .unwrap();
+    test_server(storage, "6.8.23")
+}
+
+#[tokio::test]
+async fn test_stored_fields() {
+    let server = server().await;
+
+    let body: Value = server
+        .post("/events/_search")
+        .json(&json!({ "stored_fields": ["title", "user.*", "missing"] }))
+        .await
+        .json();
+    let hit = &body["hits"]["hits"][0];
+    assert_eq!(hit["_id"], "1");
+    assert!(hit.get("_source").is_none());
+    assert_eq!(
+        hit["fields"],
+        json!({
+            "title": ["Launch"],
+            "user.name": ["ada"],
+            "user.roles": ["b", "a"]
+        })
+    );
+
+    // The source is kept when it is asked for too
+    let body: Value = server
+        .post("/events/_search")
+        .json(&json!({ "stored_fields": "status,count", "_source": ["title"] }))
+        .await
+        .json();
+    let hit = &body["hits"]["hits"][0];
+    assert_eq!(hit["_source"], json!({ "title": "Launch" }));
+    assert_eq!(hit["fields"], json!({ "status": ["open"], "count": [7] }));
+
+    // _none_ returns neither fields nor the source
+    let body: Value = server
+        .post("/events/_search")
+        .json(&json!({ "stored_fields": "_none_" }))
+        .await
+        .json();
+    let hit = &body["hits"]["hits"][0];
+    assert_eq!(hit["_id"], "1");
+    assert!(hit.get("_source").is_none());
+    assert!(hit.get("fields").is_none());
+
+    // Query parameters of GET searches
+    let body: Value = server
+        .get("/events/_search?stored_fields=status")
+        .await
+        .json();
+    assert_eq!(
+        body["hits"]["hits"][0]["fields"],
+        json!({ "status": ["open"] })
+    );
+}
+
+#[tokio::test]
+async fn test_docvalue_fields() {
+    let server = server().await;
+
+    let body: Value = server
+        .post("/events/_search")
+        .json(&json!({
+            "docvalue_fields": [
+                "status",
+                "title.keyword",
+                "count",
+                "ratio",
+                "created",
+                "day",
+                "tags.keyword",
+                "user.name",
+                { "field": "created", "format": "epoch_millis" },
+                "missing"
+            ]
+        }))
+        .await
+        .json();
+    let hit = &body["hits"]["hits"][0];
+    // The source is still returned
+    assert_eq!(hit["_source"]["status"], "open");
+    assert_eq!(
+        hit["fields"],
+        json!({
+            "status": ["open"],
+            "title.keyword": ["Launch"],
+            "count": [7],
+            "ratio": [0.5],
+            "created": ["1714557600000"],
+            "day": ["2024-05-02T00:00:00.000Z"],
+            "tags.keyword": ["y", "z"],
+            "user.name": ["ada"]
+        })
+    );
+
+    let body: Value = server
+        .post("/events/_search")
+        .json(&json!({
+            "docvalue_fields": [{ "field": "created", "format": "yyyy-MM-dd HH:mm" }, "stat*"],
+            "script_fields": { "double": { "script": "doc['count'].value * 2" } }
+        }))
+        .await
+        .json();
+    assert_eq!(
+        body["hits"]["hits"][0]["fields"],
+        json!({ "created": ["2024-05-01 10:00"], "status": ["open"], "double": [14] })
+    );
+
+    // Text fields have no doc values
+    let response = server
+        .post("/events/_search")
+        .json(&json!({ "docvalue_fields": ["title"] }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::BAD_REQUEST);
+    assert_eq!(
+        response.json::<Value>()["error"]["type"],
+        "illegal_argument_exception"
+    );
+    server
+        .post("/events/_search")
+        .json(&json!({ "docvalue_fields": [{ "format": "epoch_millis" }] }))
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,127 @@
+//! Tests for per-index search profiles
+
+use gbs::storage::{SearchProfile, Storage};
+
+fn web_search_profile() -> SearchProfile {
+    SearchProfile::from_json(&serde_json::json!({
+        "fields": ["title^3", "body"],
+        "default_operator": "and"
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_search_profile_validation() {
+    assert!(SearchProfile::from_json(&serde_json::json!({ "fields": [] })).is_err());
+    assert!(SearchProfile::from_json(&serde_json::json!({ "fields": ["title^x"] })).is_err());
+    assert!(SearchProfile::from_json(&serde_json::json!({
+        "fields": ["title"],
+        "default_operator": "xor"
+    }))
+    .is_err());
+    assert!(SearchProfile::from_json(&serde_json::json!({ "fields": ["title^2.5"] })).is_ok());
+}
+
+#[test]
+fn test_search_profile_apply() {
+    let profile = web_search_profile();
+
+    // match on _all becomes a multi_match over the profile fields
+    let query = profile.apply(&serde_json::json!({ "match": { "_all": "rust guide" } }));
+    assert_eq!(
+        query,
+        serde_json::json!({
+            "multi_match": {
+                "query": "rust guide",
+                "fields": ["title^3", "body"],
+                "operator": "and"
+            }
+        })
+    );
+
+    // Explicit fields and operators are kept; bool clauses are rewritten
+    let query = profile.apply(&serde_json::json!({
+        "bool": {
+            "must": [
+                { "multi_match": { "query": "rust", "fields": ["tags"] } },
+                { "match": { "title": { "query": "guide", "operator": "or" } } }
+            ]
+        }
+    }));
+    assert_eq!(
+        query["bool"]["must"][0]["multi_match"],
+        serde_json::json!({ "query": "rust", "fields": ["tags"], "operator": "and" })
+    );
+    assert_eq!(
+        query["bool"]["must"][1]["match"]["title"],
+        serde_json::json!({ "query": "guide", "operator": "or" })
+    );
+}
+
+#[tokio::test]
+async fn test_search_profile_boosts_ranking() {
+    let storage = Storage::new();
+    storage.create_index("docs", None, None).await.unwrap();
+    storage
+        .index_document(
+            "docs",
+            "body-match",
+            serde_json::json!({ "title": "Cooking", "body": "a rust guide" }),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "docs",
+            "title-match",
+            serde_json::json!({ "title": "A rust guide", "body": "cooking" }),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "docs",
+            "partial",
+            serde_json::json!({ "title": "Rust", "body": "iron oxide" }),
+        )
+        .await
+        .unwrap();
+
+    assert!(storage
+        .put_search_profile("docs", "web_search", web_search_profile())
+        .await
+        .unwrap());
+    assert!(!storage
+        .put_search_profile("docs", "web_search", web_search_profile())
+        .await
+        .unwrap());
+
+    let profile = storage
+        .get_search_profile("docs", "web_search")
+        .await
+        .unwrap();
+    let query = profile.apply(&serde_json::json!({ "match": { "_all": "rust guide" } }));
+    let result = storage
+        .search("docs", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+
+    // The "and" operator drops the partial match; the title boost ranks first
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0]["_id"], "title-match");
+    assert_eq!(hits[1]["_id"], "body-match");
+
+    storage
+        .delete_search_profile("docs", "web_search")
+        .await
+        .unwrap();
+    assert!(storage
+        .get_search_profile("docs", "web_search")
+        .await
+        .is_err());
+    assert!(storage
+        .delete_search_profile("docs", "web_search")
+        .await
+        .is_err());
+}
@@ -0,0 +1,251 @@
+//! Tests for stored scripts and search templates
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{open_sled, test_server};
+use gbs::error::GbsError;
+use gbs::storage::{Storage, StoredScript};
+use serde_json::{json, Value};
+
+async fn books() -> Storage {
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+    let books = [
+        (
+            "1",
+            json!({ "title": "Rust in Action", "year": 2021, "language": "rust" }),
+        ),
+        (
+            "2",
+            json!({ "title": "Programming Rust", "year": 2017, "language": "rust" }),
+        ),
+        (
+            "3",
+            json!({ "title": "Go in Action", "year": 2015, "language": "go" }),
+        ),
+    ];
+    for (id, book) in books {
+        storage.index_document("books", id, book).await.unwrap();
+    }
+    storage
+}
+
+fn render(storage: &Storage, request: Value) -> Value {
+    storage.render_search_template(&request).unwrap()
+}
+
+#[tokio::test]
+async fn test_templates_are_rendered_with_params() {
+    let storage = Storage::new();
+
+    // Strings are escaped, other values inserted as JSON
+    let rendered = render(
+        &storage,
+        json!({
+            "source": "{\"query\": {\"match\": {\"title\": \"{{title}}\"}}, \"size\": {{size}}}",
+            "params": { "title": "say \"hi\"", "size": 5 }
+        }),
+    );
+    assert_eq!(
+        rendered,
+        json!({ "query": { "match": { "title": "say \"hi\"" } }, "size": 5 })
+    );
+
+    // Defaults, sections, toJson and join
+    let source = concat!(
+        "{\"size\": {{size}}{{^size}}10{{/size}},",
+        " \"query\": {\"bool\": {\"filter\": [",
+        "{\"terms\": {\"tags\": {{#toJson}}tags{{/toJson}}}}",
+        "{{#range}}, {\"range\": {\"year\": {\"gte\": {{range.from}}}}}{{/range}}",
+        "]}},",
+        " \"_source\": \"{{#join}}fields{{/join}}\"",
+        "{{! comment }}}"
+    );
+    let rendered = render(
+        &storage,
+        json!({
+            "source": source,
+            "params": { "tags": ["rust", "go"], "fields": ["title", "year"] }
+        }),
+    );
+    assert_eq!(
+        rendered,
+        json!({
+            "size": 10,
+            "query": { "bool": { "filter": [{ "terms": { "tags": ["rust", "go"] } }] } },
+            "_source": "title,year"
+        })
+    );
+    let rendered = render(
+        &storage,
+        json!({
+            "source": source,
+            "params": { "size": 2, "tags": [], "range": { "from": 2016 }, "fields": "title" }
+        }),
+    );
+    assert_eq!(rendered["size"], 2);
+    assert_eq!(
+        rendered["query"]["bool"]["filter"][1],
+        json!({ "range": { "year": { "gte": 2016 } } })
+    );
+
+    // Sections repeat for arrays, with `.` as the element
+    let rendered = render(
+        &storage,
+        json!({
+            "source": "{\"should\": [{{#terms}}{\"term\": {\"tags\": \"{{.}}\"}}, {{/terms}}{}]}",
+            "params": { "terms": ["a", "b"] }
+        }),
+    );
+    assert_eq!(
+        rendered["should"],
+        json!([{ "term": { "tags": "a" } }, { "term": { "tags": "b" } }, {}])
+    );
+
+    // Object sources hold placeholders in strings
+    let rendered = render(
+        &storage,
+        json!({
+            "source": { "query": { "term": { "tags": "{{tag}}" } } },
+            "params": { "tag": "rust" }
+        }),
+    );
+    assert_eq!(rendered, json!({ "query": { "term": { "tags": "rust" } } }));
+}
+
+#[tokio::test]
+async fn test_invalid_templates() {
+    let storage = Storage::new();
+
+    let unclosed = storage.render_search_template(&json!({ "source": "{{#a}}{}" }));
+    assert!(matches!(unclosed, Err(GbsError::IllegalArgument(_))));
+    let not_json = storage.render_search_template(&json!({ "source": "{\"size\": {{size}}}" }));
+    assert!(matches!(not_json, Err(GbsError::InvalidRequest(_))));
+    let neither = storage.render_search_template(&json!({ "params": {} }));
+    assert!(matches!(neither, Err(GbsError::IllegalArgument(_))));
+    let missing = storage.render_search_template(&json!({ "id": "nope" }));
+    assert_eq!(
+        missing.unwrap_err().to_string(),
+        "unable to find script [nope] in cluster state"
+    );
+
+    let painless = StoredScript::parse(&json!({
+        "script": { "lang": "painless", "source": "ctx._source.x = 1" }
+    }));
+    assert!(matches!(painless, Err(GbsError::IllegalArgument(_))));
+    let no_lang = StoredScript::parse(&json!({ "script": { "source": "{}" } }));
+    assert!(no_lang.is_err());
+}
+
+#[tokio::test]
+async fn test_stored_templates_persist() {
+    let dir = tempfile::tempdir().unwrap();
+    {
+        let storage = Storage::with_sled(dir.path()).unwrap();
+        let script = StoredScript::parse(&json!({
+            "script": {
+                "lang": "mustache",
+                "source": { "query": { "match": { "title": "{{q}}" } } }
+            }
+        }))
+        .unwrap();
+        storage.put_script("by_title", script).await.unwrap();
+        storage
+            .put_script(
+                "gone",
+                StoredScript::parse(&json!({ "script": { "lang": "mustache", "source": "{}" } }))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        storage.delete_script("gone").await.unwrap();
+    }
+
+    let storage = open_sled(dir.path()).await;
+    assert_eq!(
+        render(
+            &storage,
+            json!({ "id": "by_title", "params": { "q": "rust" } })
+        ),
+        json!({ "query": { "match": { "title": "rust" } } })
+    );
+    assert!(storage.get_script("gone").is_none());
+}
+
+#[tokio::test]
+async fn test_search_template_api() {
+    let storage = books().await;
+    let server = test_server(storage, "6.8.23");
+
+    server
+        .put("/_scripts/by_language")
+        .json(&json!({
+            "script": {
+                "lang": "mustache",
+                "source": "{\"query\": {\"term\": {\"language\": \"{{language}}\"}}, \"size\": {{size}}{{^size}}10{{/size}}, \"sort\": [{\"year\": \"asc\"}]}"
+            }
+        }))
+        .await
+        .assert_status_ok();
+    let body: Value = server.get("/_scripts/by_language").await.json();
+    assert_eq!(body["found"], true);
+    assert_eq!(body["script"]["lang"], "mustache");
+
+    let body: Value = server
+        .post("/books/_search/template")
+        .json(&json!({ "id": "by_language", "params": { "language": "rust" } }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 2);
+    assert_eq!(body["hits"]["hits"][0]["_id"], "2");
+    let body: Value = server
+        .get("/_search/template")
+        .json(&json!({ "id": "by_language", "params": { "language": "rust", "size": 1 } }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["hits"].as_array().unwrap().len(), 1);
+
+    // Inline
+    let body: Value = server
+        .post("/books/_search/template")
+        .json(&json!({
+            "source": { "query": { "match": { "title": "{{q}}" } } },
+            "params": { "q": "action" }
+        }))
+        .await
+        .json();
+    assert_eq!(body["hits"]["total"]["value"], 2);
+
+    let body: Value = server
+        .post("/_render/template/by_language")
+        .json(&json!({ "params": { "language": "go", "size": 3 } }))
+        .await
+        .json();
+    assert_eq!(
+        body["template_output"],
+        json!({ "query": { "term": { "language": "go" } }, "size": 3, "sort": [{ "year": "asc" }] })
+    );
+
+    let response = server
+        .post("/books/_search/template")
+        .json(&json!({ "id": "missing" }))
+        .expect_failure()
+        .await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "resource_not_found_exception");
+
+    server
+        .delete("/_scripts/by_language")
+        .await
+        .assert_status_ok();
+    let response = server.get("/_scripts/by_language").expect_failure().await;
+    response.assert_status(StatusCode::NOT_FOUND);
+    assert_eq!(response.json::<Value>()["found"], false);
+    server
+        .delete("/_scripts/by_language")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,111 @@
+//! Tests for search timeouts and partial results
+
+mod common;
+
+use axum::http::StatusCode;
+use axum_test::TestServer;
+use common::server_with;
+use gbs::config::SearchConfig;
+use gbs::server::AppState;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+/// Documents per index, more than are scored between two timeout checks
+const DOCS: usize = 600;
+
+async fn storage() -> Storage {
+    let storage = Storage::new();
+    for index in ["logs-a", "logs-b"] {
+        storage.create_index(index, None, None).await.unwrap();
+        for id in 0..DOCS {
+            storage
+                .index_document(index, &id.to_string(), json!({ "message": "disk full" }))
+                .await
+                .unwrap();
+        }
+    }
+    storage
+}
+
+fn server(storage: Storage, search: SearchConfig) -> TestServer {
+    let state = AppState::new(Arc::new(storage), "7.10.2").with_search_config(search);
+    server_with(state)
+}
+
+#[tokio::test]
+async fn test_search_timeout_returns_partial_hits() {
+    let server = server(storage().await, SearchConfig::default());
+
+    let body: Value = server
+        .post("/logs-a/_search?timeout=0ms")
+        .json(&json!({ "query": { "match": { "message": "disk" } }, "size": 5 }))
+        .await
+        .json();
+    assert_eq!(body["timed_out"], true);
+    let total = body["hits"]["total"]["value"].as_u64().unwrap();
+    assert!(total > 0 && total < DOCS as u64);
+    assert_eq!(body["hits"]["hits"].as_array().unwrap().len(), 5);
+
+    // A generous budget, here in the body, finds every document
+    let body: Value = server
+        .post("/logs-a/_search")
+        .json(&json!({ "query": { "match": { "message": "disk" } }, "timeout": "1m" }))
+        .await
+        .json();
+    assert_eq!(body["timed_out"], false);
+    assert_eq!(body["hits"]["total"]["value"], DOCS);
+
+    // Any index timing out marks a multi-index search
+    let body: Value = server.get("/logs-*/_search?timeout=0ms").await.json();
+    assert_eq!(body["timed_out"], true);
+    assert!(body["hits"]["total"]["value"].as_u64().unwrap() < 2 * DOCS as u64);
+
+    server
+        .get("/logs-a/_search?timeout=soon")
+        .expect_failure()
+        .await
+        .assert_status(StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_search_default_timeout() {
+    let server = server(
+        storage().await,
+        SearchConfig {
+            default_timeout_ms: Some(0),
+            ..Default::default()
+        },
+    );
+
+    let body: Value = server.get("/logs-a/_search").await.json();
+    assert_eq!(body["timed_out"], true);
+
+    // A request's timeout replaces the default, and -1 disables it
+    let body: Value = server.get("/logs-a/_search?timeout=-1").await.json();
+    assert_eq!(body["timed_out"], false);
+    assert_eq!(body["hits"]["total"]["value"], DOCS);
+    let body: Value = server.get("/logs-a/_search?timeout=1m").await.json();
+    assert_eq!(body["timed_out"], false);
+}
+
+#[tokio::test]
+async fn test_timed_out_aggregations_are_not_cached() {
+    let server = server(storage().await, SearchConfig::default());
+    let aggs = json!({
+        "size": 0,
+        "aggs": { "per_day": { "date_histogram": { "field": "timestamp", "interval": "day" } } }
+    });
+
+    let body: Value = server
+        .post("/logs-a/_search?timeout=0ms")
+        .json(&aggs)
+        .await
+        .json();
+    assert_eq!(body["timed_out"], true);
+
+    // The partial count is not served from the cache afterwards
+    let body: Value = server.post("/logs-a/_search").json(&aggs).await.json();
+    assert_eq!(body["timed_out"], false);
+    assert_eq!(body["hits"]["total"]["value"], DOCS);
+}
@@ -0,0 +1,98 @@
+//! Tests for the startup self-test and feature report
+
+mod common;
+
+use common::open_sled;
+use gbs::config::Config;
+use gbs::self_test::{feature_report, run_self_test, SELF_TEST_INDEX_PREFIX};
+use gbs::server::RouteGroup;
+use gbs::storage::{IndexTemplate, Storage, TemplateKind};
+use serde_json::json;
+use tempfile::TempDir;
+
+#[tokio::test]
+async fn test_self_test_in_memory() {
+    let storage = Storage::new();
+
+    let report = run_self_test(&storage).await.unwrap();
+
+    assert_eq!(report.steps.len(), 6);
+    assert!(storage.list_indices().await.is_empty());
+}
+
+#[tokio::test]
+async fn test_self_test_with_sled_leaves_no_data() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+
+    let storage = open_sled(&data_path).await;
+    storage.create_index("existing", None, None).await.unwrap();
+
+    run_self_test(&storage).await.unwrap();
+
+    // Only the user index remains, in memory and in the backend
+    assert_eq!(storage.list_indices().await, vec!["existing".to_string()]);
+    storage.load_from_backend().await.unwrap();
+    assert_eq!(storage.list_indices().await, vec!["existing".to_string()]);
+}
+
+#[tokio::test]
+async fn test_self_test_removes_leftover_indices() {
+    let storage = Storage::new();
+    let leftover = format!("{}crashed", SELF_TEST_INDEX_PREFIX);
+    storage.create_index(&leftover, None, None).await.unwrap();
+
+    run_self_test(&storage).await.unwrap();
+
+    assert!(!storage.index_exists(&leftover).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_self_test_with_refresh_interval_from_template() {
+    let storage = Storage::new();
+    let template = IndexTemplate::parse(
+        TemplateKind::Legacy,
+        &json!({
+            "index_patterns": ["*"],
+            "settings": { "index": { "refresh_interval": "-1" } }
+        }),
+    )
+    .unwrap();
+    storage
+        .put_template(TemplateKind::Legacy, "all", template)
+        .await
+        .unwrap();
+
+    run_self_test(&storage).await.unwrap();
+}
+
+#[test]
+fn test_feature_report() {
+    let mut config = Config::default();
+    let report = feature_report(&config, &RouteGroup::ALL);
+
+    let security = report
+        .iter()
+        .find(|(family, _)| *family == "security")
+        .unwrap();
+    assert_eq!(security.1, "disabled");
+    let apis = report.iter().find(|(family, _)| *family == "apis").unwrap();
+    assert!(apis.1.starts_with("web, cluster, index"));
+
+    config.security.enabled = true;
+    config.search.parallel_scoring = false;
+    let report = feature_report(&config, &[RouteGroup::Search, RouteGroup::Bulk]);
+    let security = report
+        .iter()
+        .find(|(family, _)| *family == "security")
+        .unwrap();
+    assert!(security.1.starts_with("enabled"));
+    // Only the route groups served are listed
+    let apis = report.iter().find(|(family, _)| *family == "apis").unwrap();
+    assert_eq!(apis.1, "search, bulk");
+    let search = report
+        .iter()
+        .find(|(family, _)| *family == "search")
+        .unwrap();
+    assert!(search.1.ends_with("parallel scoring disabled"));
+}
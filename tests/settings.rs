@@ -0,0 +1,172 @@
+//! Tests for index settings validation
+
+use gbs::error::GbsError;
+use gbs::storage::Storage;
+use serde_json::json;
+
+fn illegal_argument(error: GbsError) -> String {
+    match error {
+        GbsError::IllegalArgument(reason) => reason,
+        other => panic!("expected an illegal argument error, got {:?}", other),
+    }
+}
+
+async fn settings_of(storage: &Storage, index: &str) -> serde_json::Value {
+    storage.get_index(index).await.unwrap()[index]["settings"].clone()
+}
+
+#[tokio::test]
+async fn test_create_index_accepts_known_settings() {
+    let storage = Storage::new();
+    let settings = json!({
+        "number_of_shards": 3,
+        "index": {
+            "number_of_replicas": "1",
+            "refresh_interval": "-1",
+            "translog.durability": "async",
+            "mapping": { "total_fields": { "limit": 2000 } }
+        },
+        "analysis": {
+            "analyzer": { "custom": { "type": "custom", "tokenizer": "standard" } }
+        }
+    });
+    storage
+        .create_index("logs", Some(settings), None)
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_create_index_rejects_unknown_setting() {
+    let storage = Storage::new();
+    let error = storage
+        .create_index("logs", Some(json!({ "index": { "foo": 1 } })), None)
+        .await
+        .unwrap_err();
+    assert!(illegal_argument(error).starts_with("unknown setting [index.foo]"));
+    assert!(storage.get_index("logs").await.is_err());
+}
+
+#[tokio::test]
+async fn test_create_index_rejects_invalid_values() {
+    let storage = Storage::new();
+    let cases = [
+        (
+            json!({ "number_of_shards": 0 }),
+            "Failed to parse value [0] for setting [index.number_of_shards] must be >= 1",
+        ),
+        (
+            json!({ "number_of_replicas": "many" }),
+            "Failed to parse value [many] for setting [index.number_of_replicas]",
+        ),
+        (
+            json!({ "blocks": { "write": "yes" } }),
+            "Failed to parse value [yes] as only [true] or [false] are allowed.",
+        ),
+        (
+            json!({ "refresh_interval": "soon" }),
+            "failed to parse setting [index.refresh_interval] with value [soon] as a time value: \
+             unit is missing or unrecognized",
+        ),
+        (
+            json!({ "codec": "zstd" }),
+            "Failed to parse value [zstd] for setting [index.codec]",
+        ),
+        (
+            json!({ "index.creation_date": 0 }),
+            "private index setting [index.creation_date] can not be set explicitly",
+        ),
+    ];
+    for (settings, reason) in cases {
+        let error = storage
+            .create_index("logs", Some(settings), None)
+            .await
+            .unwrap_err();
+        assert_eq!(illegal_argument(error), reason);
+    }
+}
+
+#[tokio::test]
+async fn test_update_rejects_static_settings() {
+    let storage = Storage::new();
+    storage
+        .create_index("logs", Some(json!({ "number_of_shards": 1 })), None)
+        .await
+        .unwrap();
+
+    let error = storage
+        .update_settings(
+            "logs",
+            json!({ "index": { "number_of_shards": 2, "codec": "best_compression" } }),
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(
+        illegal_argument(error),
+        "Can't update non dynamic settings [[index.codec, index.number_of_shards]] \
+         for open indices [[logs]]"
+    );
+    assert_eq!(settings_of(&storage, "logs").await["number_of_shards"], 1);
+}
+
+#[tokio::test]
+async fn test_update_merges_settings_in_any_key_form() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            Some(json!({ "number_of_shards": 1, "number_of_replicas": 0 })),
+            None,
+        )
+        .await
+        .unwrap();
+
+    storage
+        .update_settings(
+            "logs",
+            json!({ "index.number_of_replicas": 2, "index": { "refresh_interval": "5s" } }),
+        )
+        .await
+        .unwrap();
+    let settings = settings_of(&storage, "logs").await;
+    assert_eq!(
+        settings,
+        json!({
+            "number_of_shards": 1,
+            "index.number_of_replicas": 2,
+            "index": { "refresh_interval": "5s" }
+        })
+    );
+
+    // null resets a setting to its default
+    storage
+        .update_settings("logs", json!({ "refresh_interval": null }))
+        .await
+        .unwrap();
+    let settings = settings_of(&storage, "logs").await;
+    assert_eq!(
+        settings,
+        json!({ "number_of_shards": 1, "index.number_of_replicas": 2 })
+    );
+}
+
+#[tokio::test]
+async fn test_archived_settings_are_not_validated() {
+    let storage = Storage::new();
+    storage
+        .create_index(
+            "logs",
+            Some(json!({ "archived": { "index.legacy_setting": "x" } })),
+            None,
+        )
+        .await
+        .unwrap();
+    storage
+        .update_settings("logs", json!({ "archived.index.other": true }))
+        .await
+        .unwrap();
+
+    let settings = settings_of(&storage, "logs").await;
+    assert_eq!(settings["archived"]["index.legacy_setting"], "x");
+    assert_eq!(settings["archived.index.other"], true);
+}
@@ -0,0 +1,94 @@
+//! Tests for request draining at shutdown
+
+mod common;
+
+use axum_test::http::StatusCode;
+use axum_test::TestServer;
+use common::server_with;
+use gbs::server::{AppState, Shutdown};
+use gbs::storage::Storage;
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+
+async fn draining_server() -> TestServer {
+    let storage = Storage::new();
+    storage.create_index("books", None, None).await.unwrap();
+    storage
+        .index_document("books", "1", json!({ "title": "dune" }))
+        .await
+        .unwrap();
+    let state = AppState::new(Arc::new(storage), "6.8.23");
+    state.shutdown.begin();
+    server_with(state)
+}
+
+#[tokio::test]
+async fn test_writes_are_refused_while_draining() {
+    let server = draining_server().await;
+
+    let response = server
+        .put("/books/_doc/2")
+        .json(&json!({ "title": "emma" }))
+        .await;
+    response.assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"]["type"], "node_closed_exception");
+
+    server
+        .post("/books/_doc")
+        .json(&json!({ "title": "emma" }))
+        .await
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    server
+        .delete("/books")
+        .await
+        .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    // These would race the final flush
+    for path in ["/books/_refresh", "/_flush", "/books/_cache/clear"] {
+        server
+            .post(path)
+            .await
+            .assert_status(StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
+
+#[tokio::test]
+async fn test_reads_are_served_while_draining() {
+    let server = draining_server().await;
+
+    server.get("/books/_doc/1").await.assert_status_ok();
+    let response = server
+        .post("/books/_search")
+        .json(&json!({ "query": { "match": { "title": "dune" } } }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["hits"]["total"]["value"], 1);
+    server
+        .post("/_ingest/pipeline/_simulate")
+        .json(&json!({ "pipeline": { "processors": [] }, "docs": [{ "_source": {} }] }))
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_shutdown_wakes_waiters() {
+    let shutdown = Arc::new(Shutdown::default());
+    assert!(!shutdown.is_draining());
+
+    let waiter = tokio::spawn({
+        let shutdown = shutdown.clone();
+        async move { shutdown.started().await }
+    });
+    tokio::task::yield_now().await;
+    shutdown.begin();
+    tokio::time::timeout(Duration::from_secs(5), waiter)
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Waiting after draining started returns immediately
+    assert!(shutdown.is_draining());
+    shutdown.started().await;
+}
@@ -0,0 +1,62 @@
+//! Tests for the soak test mode
+
+mod common;
+
+use common::open_sled;
+use gbs::soak::{run_soak, SoakOptions, SOAK_INDEX_PREFIX};
+use gbs::storage::Storage;
+use tempfile::TempDir;
+
+fn options(temp_dir: &TempDir, cycles: u64) -> SoakOptions {
+    SoakOptions {
+        cycles,
+        ops_per_cycle: 60,
+        indices: 2,
+        docs_per_index: 10,
+        seed: 42,
+        ..SoakOptions::new(temp_dir.path().join("db"))
+    }
+}
+
+#[tokio::test]
+async fn test_soak_run_holds_invariants() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Indices that are not part of the soak test are left alone
+    {
+        let storage = Storage::with_sled(temp_dir.path().join("db")).unwrap();
+        storage.create_index("user_data", None, None).await.unwrap();
+        storage
+            .index_document("user_data", "1", serde_json::json!({ "keep": true }))
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+
+    let report = run_soak(&options(&temp_dir, 5), std::future::pending())
+        .await
+        .unwrap();
+    assert_eq!(report.seed, 42);
+    assert_eq!(report.cycles, 5);
+    assert_eq!(report.operations, 300);
+
+    let storage = open_sled(temp_dir.path().join("db")).await;
+    assert!(storage
+        .match_indices(&format!("{}*", SOAK_INDEX_PREFIX))
+        .await
+        .is_empty());
+    let doc = storage.get_document("user_data", "1").await.unwrap();
+    assert_eq!(doc["_source"]["keep"], true);
+}
+
+#[tokio::test]
+async fn test_soak_stops_when_requested() {
+    let temp_dir = TempDir::new().unwrap();
+
+    // Run "forever" but with the stop signal already fired
+    let report = run_soak(&options(&temp_dir, 0), std::future::ready(()))
+        .await
+        .unwrap();
+    assert_eq!(report.cycles, 0);
+    assert_eq!(report.operations, 0);
+}
@@ -1,6 +1,9 @@
 //! Unit tests for Storage module
 
+mod common;
+
 use gbs::storage::Storage;
+use tempfile::TempDir;
 
 #[tokio::test]
 async fn test_search_match_query() {
@@ -285,8 +288,8 @@ async fn test_update_settings() {
         .unwrap();
 
     let new_settings = serde_json::json!({
-        "number_of_shards": 2,
-        "number_of_replicas": 1
+        "number_of_replicas": 2,
+        "refresh_interval": "30s"
     });
 
     storage
@@ -302,7 +305,7 @@ async fn test_update_settings() {
     assert!(settings.is_some());
     assert_eq!(
         settings
-            .and_then(|s| s.get("number_of_shards"))
+            .and_then(|s| s.get("number_of_replicas"))
             .and_then(|v| v.as_u64())
             .unwrap(),
         2
@@ -630,3 +633,597 @@ async fn test_search_range_gt_lt() {
     assert_eq!(hits.len(), 1);
     assert_eq!(hits[0].get("_id").and_then(|id| id.as_str()).unwrap(), "2"); // price 20.0
 }
+
+#[tokio::test]
+async fn test_search_fuzzy_query() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    storage
+        .index_document("test_index", "1", serde_json::json!({"name": "kimchy"}))
+        .await
+        .unwrap();
+    storage
+        .index_document("test_index", "2", serde_json::json!({"name": "shay"}))
+        .await
+        .unwrap();
+
+    // One substitution plus one transposition away from "kimchy"
+    let query = serde_json::json!({
+        "fuzzy": {
+            "name": { "value": "kmichi", "fuzziness": 2 }
+        }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], "1");
+
+    // Without transpositions the same term needs three edits
+    let query = serde_json::json!({
+        "fuzzy": {
+            "name": { "value": "kmichi", "fuzziness": 2, "transpositions": false }
+        }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 0);
+
+    // AUTO fuzziness allows no edits for short terms
+    let query = serde_json::json!({ "fuzzy": { "name": "shy" } });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 1);
+    let query = serde_json::json!({ "fuzzy": { "name": "sh" } });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_search_match_fuzziness() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "test_index",
+            "1",
+            serde_json::json!({"title": "Rust Programming Language"}),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "test_index",
+            "2",
+            serde_json::json!({"title": "Python Tutorial"}),
+        )
+        .await
+        .unwrap();
+
+    // Typo without fuzziness finds nothing
+    let query = serde_json::json!({ "match": { "title": "progarmming" } });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 0);
+
+    // Same typo with AUTO fuzziness matches
+    let query = serde_json::json!({
+        "match": { "title": { "query": "progarmming", "fuzziness": "AUTO" } }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], "1");
+    assert!(hits[0]["_score"].as_f64().unwrap() < 0.5);
+
+    // prefix_length requires the leading characters to match exactly
+    let query = serde_json::json!({
+        "match": { "title": { "query": "tython", "fuzziness": 1, "prefix_length": 1 } }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn test_search_nested_query() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "test_index",
+            "1",
+            serde_json::json!({
+                "title": "First post",
+                "comments": [
+                    { "author": "alice", "text": "great article" },
+                    { "author": "bob", "text": "terrible formatting" }
+                ]
+            }),
+        )
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "test_index",
+            "2",
+            serde_json::json!({
+                "title": "Second post",
+                "comments": [
+                    { "author": "bob", "text": "great read" }
+                ]
+            }),
+        )
+        .await
+        .unwrap();
+
+    // Both conditions must hold for the same comment: only bob's comment on
+    // post 2 says "great"
+    let query = serde_json::json!({
+        "nested": {
+            "path": "comments",
+            "query": {
+                "bool": {
+                    "must": [
+                        { "term": { "comments.author": "bob" } },
+                        { "match": { "comments.text": "great" } }
+                    ]
+                }
+            }
+        }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], "2");
+
+    // A single condition matches any element
+    let query = serde_json::json!({
+        "nested": {
+            "path": "comments",
+            "query": { "term": { "comments.author": "bob" } },
+            "score_mode": "max"
+        }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    assert_eq!(result["hits"]["hits"].as_array().unwrap().len(), 2);
+
+    // Nested queries combine with other clauses in a bool query
+    let query = serde_json::json!({
+        "bool": {
+            "must": [
+                { "match": { "title": "first" } },
+                {
+                    "nested": {
+                        "path": "comments",
+                        "query": { "term": { "comments.author": "alice" } }
+                    }
+                }
+            ]
+        }
+    });
+    let result = storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .unwrap();
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0]["_id"], "1");
+
+    // A path is required
+    let query = serde_json::json!({ "nested": { "query": { "match_all": {} } } });
+    assert!(storage
+        .search("test_index", &query, None, None, None, None, None)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_search_ids_query() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    for id in ["1", "2", "3"] {
+        storage
+            .index_document("test_index", id, serde_json::json!({ "n": id }))
+            .await
+            .unwrap();
+    }
+
+    let search_ids = |query: serde_json::Value| {
+        let storage = storage.clone();
+        async move {
+            let result = storage
+                .search("test_index", &query, None, None, None, None, None)
+                .await
+                .unwrap();
+            let mut ids: Vec<String> = result["hits"]["hits"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["_id"].as_str().unwrap().to_string())
+                .collect();
+            ids.sort();
+            ids
+        }
+    };
+
+    // Direct lookups; unknown and duplicate IDs are ignored
+    assert_eq!(
+        search_ids(serde_json::json!({ "ids": { "values": ["1", "3", "3", "missing"] } })).await,
+        vec!["1", "3"]
+    );
+    assert_eq!(
+        search_ids(serde_json::json!({ "term": { "_id": "2" } })).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        search_ids(serde_json::json!({ "term": { "_id": { "value": 2 } } })).await,
+        vec!["2"]
+    );
+    assert_eq!(
+        search_ids(serde_json::json!({ "terms": { "_id": ["1", "2"] } })).await,
+        vec!["1", "2"]
+    );
+
+    // _id clauses combine with other clauses in a bool query
+    assert_eq!(
+        search_ids(serde_json::json!({
+            "bool": {
+                "filter": [{ "ids": { "values": ["1", "2"] } }],
+                "must_not": [{ "term": { "_id": "1" } }]
+            }
+        }))
+        .await,
+        vec!["2"]
+    );
+}
+
+#[tokio::test]
+async fn test_search_bool_minimum_should_match_and_boost() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    let docs = [
+        ("1", serde_json::json!({ "tags": "rust", "lang": "en" })),
+        ("2", serde_json::json!({ "tags": "rust", "lang": "de" })),
+        ("3", serde_json::json!({ "tags": "go", "lang": "de" })),
+        ("4", serde_json::json!({ "tags": "java", "lang": "fr" })),
+    ];
+    for (id, doc) in docs {
+        storage.index_document("test_index", id, doc).await.unwrap();
+    }
+
+    let search = |query: serde_json::Value| {
+        let storage = storage.clone();
+        async move {
+            storage
+                .search("test_index", &query, None, None, None, None, None)
+                .await
+                .unwrap()
+        }
+    };
+    let hit_ids = |result: &serde_json::Value| {
+        let mut ids: Vec<String> = result["hits"]["hits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|hit| hit["_id"].as_str().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    };
+    let should = serde_json::json!([
+        { "term": { "tags": "rust" } },
+        { "term": { "lang": "de" } }
+    ]);
+
+    // Without must or filter clauses at least one should clause has to match
+    let result = search(serde_json::json!({ "bool": { "should": should } })).await;
+    assert_eq!(hit_ids(&result), vec!["1", "2", "3"]);
+
+    // Number, negative number and percentage forms
+    let cases = [
+        (serde_json::json!(2), vec!["2"]),
+        (serde_json::json!("100%"), vec!["2"]),
+        (serde_json::json!(-1), vec!["1", "2", "3"]),
+        (serde_json::json!("-50%"), vec!["1", "2", "3"]),
+        (serde_json::json!(0), vec!["1", "2", "3", "4"]),
+    ];
+    for (minimum, expected) in cases {
+        let result = search(serde_json::json!({
+            "bool": { "should": should, "minimum_should_match": minimum }
+        }))
+        .await;
+        assert_eq!(hit_ids(&result), expected);
+    }
+
+    // With a filter, should clauses are optional and only add to the score
+    let result = search(serde_json::json!({
+        "bool": {
+            "filter": { "term": { "lang": "de" } },
+            "should": { "term": { "tags": "rust" } }
+        }
+    }))
+    .await;
+    assert_eq!(hit_ids(&result), vec!["2", "3"]);
+    assert_eq!(result["hits"]["hits"][0]["_id"], "2");
+    // Matching none of them scores 0, as in Elasticsearch
+    let hits = result["hits"]["hits"].as_array().unwrap();
+    assert!(hits[0]["_score"].as_f64().unwrap() > 0.0);
+    assert_eq!(hits[1]["_score"], 0.0);
+
+    // Boosts multiply the score of leaf and compound queries
+    let result = search(serde_json::json!({
+        "bool": {
+            "should": [
+                { "term": { "tags": { "value": "rust", "boost": 3.0 } } },
+                { "match": { "lang": { "query": "de", "boost": 0.5 } } }
+            ]
+        }
+    }))
+    .await;
+    let score = |id: &str| {
+        result["hits"]["hits"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|hit| hit["_id"] == id)
+            .unwrap()["_score"]
+            .as_f64()
+            .unwrap()
+    };
+    assert_eq!(score("1"), 3.0);
+    assert!(score("2") > score("1"));
+    assert!(score("3") < 1.0);
+
+    let result = search(serde_json::json!({
+        "bool": { "must": { "match_all": {} }, "boost": 2.0 }
+    }))
+    .await;
+    assert_eq!(result["hits"]["hits"][0]["_score"], 2.0);
+
+    let invalid = storage
+        .search(
+            "test_index",
+            &serde_json::json!({ "bool": { "should": should, "minimum_should_match": "most" } }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+    assert!(invalid.is_err());
+}
+
+#[tokio::test]
+async fn test_search_sort_multiple_fields() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    let docs = [
+        (
+            "a",
+            serde_json::json!({ "team": "red", "points": [3, 9], "name": "Ann" }),
+        ),
+        (
+            "b",
+            serde_json::json!({ "team": "blue", "points": [5, 6], "name": "Bob" }),
+        ),
+        (
+            "c",
+            serde_json::json!({ "team": "red", "points": 4, "name": "Cid" }),
+        ),
+        ("d", serde_json::json!({ "team": "blue", "name": "Dee" })),
+    ];
+    for (id, doc) in docs {
+        storage.index_document("test_index", id, doc).await.unwrap();
+    }
+
+    let sorted_ids = |sort: serde_json::Value| {
+        let storage = storage.clone();
+        async move {
+            let result = storage
+                .search(
+                    "test_index",
+                    &serde_json::json!({ "match_all": {} }),
+                    None,
+                    None,
+                    Some(&sort),
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            result["hits"]["hits"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["_id"].as_str().unwrap().to_string())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // Clauses apply in priority order; missing values go last by default
+    assert_eq!(
+        sorted_ids(serde_json::json!([{ "team": "asc" }, { "points": "desc" }])).await,
+        vec!["b", "d", "a", "c"]
+    );
+
+    // Array fields are reduced with mode (min for asc, max for desc by default)
+    assert_eq!(
+        sorted_ids(serde_json::json!({ "points": "asc" })).await,
+        vec!["a", "c", "b", "d"]
+    );
+    assert_eq!(
+        sorted_ids(
+            serde_json::json!({ "points": { "order": "asc", "mode": "avg", "missing": "_first" } })
+        )
+        .await,
+        vec!["d", "c", "b", "a"]
+    );
+    assert_eq!(
+        sorted_ids(serde_json::json!({ "points": { "order": "desc", "mode": "sum" } })).await,
+        vec!["a", "b", "c", "d"]
+    );
+
+    // _doc sorts by document ID, _score ties fall through to the next clause
+    assert_eq!(
+        sorted_ids(serde_json::json!(["_score", { "_doc": "desc" }])).await,
+        vec!["d", "c", "b", "a"]
+    );
+    assert_eq!(
+        sorted_ids(serde_json::json!(["team", "name"])).await,
+        vec!["b", "d", "a", "c"]
+    );
+
+    let invalid = storage
+        .search(
+            "test_index",
+            &serde_json::json!({ "match_all": {} }),
+            None,
+            None,
+            Some(&serde_json::json!({ "points": { "mode": "median" } })),
+            None,
+            None,
+        )
+        .await;
+    assert!(matches!(invalid, Err(gbs::GbsError::InvalidRequest(_))));
+}
+
+#[tokio::test]
+async fn test_search_pages_are_consistent() {
+    let storage = Storage::new();
+
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    for i in 0..200 {
+        storage
+            .index_document(
+                "test_index",
+                &format!("doc-{:03}", i),
+                serde_json::json!({ "rank": (i * 7) % 200, "group": i % 4 }),
+            )
+            .await
+            .unwrap();
+    }
+
+    let page = |from: u32, size: u32| {
+        let storage = storage.clone();
+        async move {
+            let result = storage
+                .search(
+                    "test_index",
+                    &serde_json::json!({ "match_all": {} }),
+                    Some(from),
+                    Some(size),
+                    Some(&serde_json::json!([{ "group": "asc" }, { "rank": "desc" }])),
+                    None,
+                    None,
+                )
+                .await
+                .unwrap();
+            assert_eq!(result["hits"]["total"]["value"], 200);
+            result["hits"]["hits"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|hit| hit["sort"].clone())
+                .collect::<Vec<_>>()
+        }
+    };
+
+    // Small pages only rank the hits up to them, and line up with one large page
+    let all = page(0, 200).await;
+    assert_eq!(all.len(), 200);
+    assert_eq!(all[0], serde_json::json!([0.0, 196.0]));
+    assert_eq!(all[199], serde_json::json!([3.0, 1.0]));
+    for from in [0, 10, 45, 190] {
+        assert_eq!(page(from, 10).await, all[from as usize..from as usize + 10]);
+    }
+    assert!(page(200, 10).await.is_empty());
+}
+
+#[tokio::test]
+async fn test_document_exists() {
+    let storage = Storage::new();
+    storage
+        .create_index("test_index", None, None)
+        .await
+        .unwrap();
+    storage
+        .index_document("test_index", "1", serde_json::json!({ "title": "a" }))
+        .await
+        .unwrap();
+
+    assert!(storage.document_exists("test_index", "1").await.unwrap());
+    assert!(!storage.document_exists("test_index", "2").await.unwrap());
+    assert!(!storage.document_exists("missing", "1").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_force_merge_persisted() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path().join("db");
+    {
+        let storage = Storage::with_sled(&path).unwrap();
+        storage.load_from_backend().await.unwrap();
+        storage.create_index("logs", None, None).await.unwrap();
+        common::index_disk_full(&storage, "logs", &["1"]).await;
+        storage
+            .force_merge(&["logs".to_string()], true)
+            .await
+            .unwrap();
+    }
+
+    let storage = Storage::with_sled(&path).unwrap();
+    storage.load_from_backend().await.unwrap();
+    let doc = storage.get_document("logs", "1").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "disk full");
+}
@@ -83,7 +83,7 @@ async fn test_create_document_auto_id() {
         .await
         .unwrap();
 
-    let id1 = storage
+    let (id1, _) = storage
         .create_document(
             "test_index",
             serde_json::json!({
@@ -93,7 +93,7 @@ async fn test_create_document_auto_id() {
         .await
         .unwrap();
 
-    let id2 = storage
+    let (id2, _) = storage
         .create_document(
             "test_index",
             serde_json::json!({
@@ -0,0 +1,107 @@
+//! Tests for the Tantivy exporter
+
+mod common;
+
+use common::open_sled;
+use gbs::tantivy_export::{export_tantivy, TantivyExportOptions};
+use serde_json::json;
+use tantivy::collector::{Count, TopDocs};
+use tantivy::query::QueryParser;
+use tantivy::schema::Value;
+use tantivy::TantivyDocument;
+use tempfile::TempDir;
+
+async fn create_data_dir(data_dir: &std::path::Path) {
+    let storage = open_sled(data_dir).await;
+    storage
+        .create_index(
+            "products",
+            None,
+            Some(json!({
+                // `tags` stays unmapped
+                "dynamic": false,
+                "properties": {
+                    "name": { "type": "text" },
+                    "sku": { "type": "keyword" },
+                    "price": { "type": "double" },
+                    "stock": { "type": "integer" },
+                    "active": { "type": "boolean" },
+                    "added": { "type": "date" },
+                    "vendor": { "properties": { "country": { "type": "keyword" } } },
+                    "location": { "type": "geo_point" }
+                }
+            })),
+        )
+        .await
+        .unwrap();
+    let documents = [
+        json!({ "name": "Red running shoes", "sku": "SH-1", "price": 59.9, "stock": 3,
+                "active": true, "added": "2024-03-15T10:00:00Z", "vendor": { "country": "DE" } }),
+        json!({ "name": "Blue running shirt", "sku": "SH-2", "price": 19.5, "stock": "many",
+                "active": false, "added": "2024-04-01", "tags": ["sale"] }),
+        json!({ "name": "Green hat", "sku": ["HT-1", "HT-2"], "price": 9, "stock": 0 }),
+    ];
+    for (id, document) in documents.into_iter().enumerate() {
+        storage
+            .index_document("products", &id.to_string(), document)
+            .await
+            .unwrap();
+    }
+    storage.flush().await.unwrap();
+}
+
+#[tokio::test]
+async fn test_export_tantivy() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_dir = temp_dir.path().join("data");
+    let out = temp_dir.path().join("tantivy");
+    create_data_dir(&data_dir).await;
+
+    let options = TantivyExportOptions {
+        data_dir: data_dir.clone(),
+        index: "products".to_string(),
+        out: out.clone(),
+    };
+    let report = export_tantivy(&options).unwrap();
+    assert_eq!(report.documents, 3);
+    assert_eq!(report.mapped_fields, 7);
+    // The "many" stock value does not fit the integer field
+    assert_eq!(report.skipped_values, 1);
+
+    let index = tantivy::Index::open_in_dir(&out).unwrap();
+    let schema = index.schema();
+    let searcher = index.reader().unwrap().searcher();
+    let count = |query: &str| {
+        let parser = QueryParser::for_index(&index, vec![schema.get_field("name").unwrap()]);
+        searcher
+            .search(&parser.parse_query(query).unwrap(), &Count)
+            .unwrap()
+    };
+
+    assert_eq!(count("running"), 2);
+    assert_eq!(count("sku:HT-2"), 1);
+    assert_eq!(count("price:[10 TO 100]"), 2);
+    assert_eq!(count("stock:3"), 1);
+    assert_eq!(count("active:true"), 1);
+    assert_eq!(count("vendor.country:DE"), 1);
+    assert_eq!(count("added:[2024-03-20T00:00:00Z TO *]"), 1);
+    // Unmapped fields are searchable through _source
+    assert_eq!(count("_source.tags:sale"), 1);
+
+    let parser = QueryParser::for_index(&index, vec![schema.get_field("name").unwrap()]);
+    let top = searcher
+        .search(&parser.parse_query("hat").unwrap(), &TopDocs::with_limit(1))
+        .unwrap();
+    let doc: TantivyDocument = searcher.doc(top[0].1).unwrap();
+    let id = doc.get_first(schema.get_field("_id").unwrap()).unwrap();
+    assert_eq!(id.as_str(), Some("2"));
+
+    // The output directory must be empty, and the index must exist
+    assert!(export_tantivy(&options).is_err());
+    let missing = TantivyExportOptions {
+        index: "missing".to_string(),
+        out: temp_dir.path().join("other"),
+        ..options
+    };
+    assert!(export_tantivy(&missing).is_err());
+}
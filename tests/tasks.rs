@@ -0,0 +1,120 @@
+//! Tests for the task registry, delete by query and cancellation
+
+use gbs::storage::{DeleteByQueryRequest, ReindexRequest, Storage};
+use gbs::tasks::{TaskHandle, TaskRegistry, BULK_ACTION, REINDEX_ACTION};
+use gbs::GbsError;
+use serde_json::json;
+use std::collections::HashMap;
+
+async fn logs(storage: &Storage) {
+    storage.create_index("logs", None, None).await.unwrap();
+    for id in 0..10 {
+        let level = if id % 2 == 0 { "debug" } else { "error" };
+        storage
+            .index_document("logs", &id.to_string(), json!({ "level": level }))
+            .await
+            .unwrap();
+    }
+}
+
+async fn count(storage: &Storage) -> u64 {
+    let result = storage
+        .search(
+            "logs",
+            &json!({ "match_all": {} }),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+    result["hits"]["total"]["value"].as_u64().unwrap()
+}
+
+#[test]
+fn test_registry_lists_and_cancels_tasks() {
+    let registry = TaskRegistry::default();
+    let (reindex_id, reindex) = registry.register(REINDEX_ACTION, "reindex", true);
+    let (bulk_id, _) = registry.register(BULK_ACTION, "bulk", false);
+
+    assert_eq!(registry.list(None).len(), 2);
+    let listed = registry.list(Some("*reindex"));
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0].id, reindex_id);
+
+    assert!(matches!(
+        registry.cancel(bulk_id),
+        Err(GbsError::IllegalArgument(_))
+    ));
+    assert!(matches!(
+        registry.cancel(99),
+        Err(GbsError::TaskNotFound(_))
+    ));
+    assert!(registry.cancel(reindex_id).unwrap().cancelled);
+    assert!(reindex.is_cancelled());
+
+    // Outcomes are only kept when asked for
+    registry.complete(bulk_id, None);
+    assert!(registry.get(bulk_id).is_none());
+    registry.complete(reindex_id, Some(Ok(json!({ "created": 0 }))));
+    let result = registry.get(reindex_id).unwrap();
+    assert_eq!(result.outcome, Some(Ok(json!({ "created": 0 }))));
+    assert!(registry.list(None).is_empty());
+}
+
+#[tokio::test]
+async fn test_delete_by_query() {
+    let storage = Storage::new();
+    logs(&storage).await;
+
+    let params = HashMap::from([("scroll_size".to_string(), "2".to_string())]);
+    let request = DeleteByQueryRequest::parse(
+        "logs",
+        &json!({ "query": { "term": { "level": "debug" } } }),
+        &params,
+    )
+    .unwrap();
+    let response = storage
+        .delete_by_query(&request, &TaskHandle::default())
+        .await
+        .unwrap();
+    assert_eq!(response.total, 5);
+    assert_eq!(response.deleted, 5);
+    assert_eq!(response.batches, 3);
+    assert!(response.failures.is_empty());
+    assert_eq!(count(&storage).await, 5);
+
+    assert!(DeleteByQueryRequest::parse("logs", &json!({}), &HashMap::new()).is_err());
+}
+
+#[tokio::test]
+async fn test_cancelled_operations_stop_before_next_batch() {
+    let storage = Storage::new();
+    logs(&storage).await;
+    let registry = TaskRegistry::default();
+
+    let (id, task) = registry.register(REINDEX_ACTION, "reindex", true);
+    registry.cancel(id).unwrap();
+    let request = ReindexRequest::parse(&json!({
+        "source": { "index": "logs", "size": 5 },
+        "dest": { "index": "logs-copy" }
+    }))
+    .unwrap();
+    let response = storage.reindex_with_task(&request, &task).await.unwrap();
+    assert_eq!(response.total, 10);
+    assert_eq!(response.created, 0);
+    assert_eq!(response.canceled.as_deref(), Some("by user request"));
+
+    let request = DeleteByQueryRequest::parse(
+        "logs",
+        &json!({ "query": { "match_all": {} } }),
+        &HashMap::new(),
+    )
+    .unwrap();
+    let response = storage.delete_by_query(&request, &task).await.unwrap();
+    assert_eq!(response.deleted, 0);
+    assert_eq!(response.canceled.as_deref(), Some("by user request"));
+    assert_eq!(count(&storage).await, 10);
+}
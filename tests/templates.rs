@@ -0,0 +1,314 @@
+//! Tests for legacy and composable index templates
+
+mod common;
+
+use common::open_sled;
+use gbs::bulk_ops::BulkAction;
+use gbs::error::GbsError;
+use gbs::storage::{IndexTemplate, Storage, TemplateKind};
+use serde_json::json;
+use tempfile::TempDir;
+
+fn template(kind: TemplateKind, body: serde_json::Value) -> IndexTemplate {
+    IndexTemplate::parse(kind, &body).unwrap()
+}
+
+async fn index_of(storage: &Storage, index: &str) -> serde_json::Value {
+    storage.get_index(index).await.unwrap()[index].clone()
+}
+
+#[tokio::test]
+async fn test_create_index_applies_matching_legacy_templates_in_order() {
+    let storage = Storage::new();
+    storage
+        .put_template(
+            TemplateKind::Legacy,
+            "logs-base",
+            template(
+                TemplateKind::Legacy,
+                json!({
+                    "index_patterns": ["logs-*"],
+                    "order": 0,
+                    "settings": { "number_of_replicas": 1, "refresh_interval": "1s" },
+                    "mappings": { "properties": { "message": { "type": "text" } } },
+                    "aliases": { "logs": {} }
+                }),
+            ),
+        )
+        .await
+        .unwrap();
+    storage
+        .put_template(
+            TemplateKind::Legacy,
+            "logs-override",
+            template(
+                TemplateKind::Legacy,
+                json!({
+                    "template": "logs-*",
+                    "order": 1,
+                    "settings": { "index": { "refresh_interval": "30s" } },
+                    "mappings": { "properties": { "level": { "type": "keyword" } } }
+                }),
+            ),
+        )
+        .await
+        .unwrap();
+
+    storage
+        .create_index("logs-2024.05.01", None, None)
+        .await
+        .unwrap();
+
+    let index = index_of(&storage, "logs-2024.05.01").await;
+    let settings = index["settings"].to_string();
+    assert!(settings.contains("30s"), "settings: {}", settings);
+    assert!(!settings.contains("\"1s\""), "settings: {}", settings);
+    assert_eq!(
+        index["mappings"]["properties"]["message"]["type"],
+        json!("text")
+    );
+    assert_eq!(
+        index["mappings"]["properties"]["level"]["type"],
+        json!("keyword")
+    );
+    assert!(index["aliases"].get("logs").is_some());
+
+    // Names the patterns do not match get no template
+    storage.create_index("metrics", None, None).await.unwrap();
+    assert!(index_of(&storage, "metrics").await["aliases"]
+        .as_object()
+        .is_none_or(|aliases| aliases.is_empty()));
+}
+
+#[tokio::test]
+async fn test_request_settings_and_mappings_override_templates() {
+    let storage = Storage::new();
+    storage
+        .put_template(
+            TemplateKind::Legacy,
+            "logs",
+            template(
+                TemplateKind::Legacy,
+                json!({
+                    "index_patterns": "logs-*",
+                    "settings": { "refresh_interval": "5s", "number_of_replicas": 2 },
+                    "mappings": { "properties": { "message": { "type": "text" } } }
+                }),
+            ),
+        )
+        .await
+        .unwrap();
+
+    storage
+        .create_index(
+            "logs-1",
+            Some(json!({ "refresh_interval": "-1" })),
+            Some(json!({ "properties": { "message": { "type": "keyword" } } })),
+        )
+        .await
+        .unwrap();
+
+    let index = index_of(&storage, "logs-1").await;
+    let settings = index["settings"].to_string();
+    assert!(settings.contains("-1"), "settings: {}", settings);
+    assert!(!settings.contains("5s"), "settings: {}", settings);
+    assert!(settings.contains('2'), "settings: {}", settings);
+    assert_eq!(
+        index["mappings"]["properties"]["message"]["type"],
+        json!("keyword")
+    );
+}
+
+#[tokio::test]
+async fn test_highest_priority_composable_template_wins_over_legacy() {
+    let storage = Storage::new();
+    storage
+        .put_template(
+            TemplateKind::Legacy,
+            "legacy",
+            template(
+                TemplateKind::Legacy,
+                json!({
+                    "index_patterns": ["*"],
+                    "mappings": { "properties": { "legacy": { "type": "keyword" } } }
+                }),
+            ),
+        )
+        .await
+        .unwrap();
+    for (name, priority, field) in [("low", 1, "low"), ("high", 10, "high")] {
+        storage
+            .put_template(
+                TemplateKind::Composable,
+                name,
+                template(
+                    TemplateKind::Composable,
+                    json!({
+                        "index_patterns": ["events-*"],
+                        "priority": priority,
+                        "template": {
+                            "mappings": { "properties": { field: { "type": "keyword" } } }
+                        }
+                    }),
+                ),
+            )
+            .await
+            .unwrap();
+    }
+
+    let resolved = storage.resolve_templates("events-1");
+    assert_eq!(resolved.templates, vec!["high".to_string()]);
+
+    storage.create_index("events-1", None, None).await.unwrap();
+    let properties = index_of(&storage, "events-1").await["mappings"]["properties"].clone();
+    assert!(properties.get("high").is_some());
+    assert!(properties.get("low").is_none());
+    assert!(properties.get("legacy").is_none());
+
+    // Without a matching composable template the legacy ones apply
+    assert_eq!(
+        storage.resolve_templates("other").templates,
+        vec!["legacy".to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_bulk_writes_create_indices_matching_a_template() {
+    let storage = Storage::new();
+    storage
+        .put_template(
+            TemplateKind::Composable,
+            "logs",
+            template(
+                TemplateKind::Composable,
+                json!({
+                    "index_patterns": ["logs-*"],
+                    "template": {
+                        "mappings": { "properties": { "level": { "type": "keyword" } } },
+                        "aliases": { "all-logs": {} }
+                    }
+                }),
+            ),
+        )
+        .await
+        .unwrap();
+
+    let results = storage
+        .execute_bulk(vec![
+            BulkAction::Index {
+                index: "logs-2024".to_string(),
+                id: Some("1".to_string()),
+                document: json!({ "level": "info" }),
+            },
+            BulkAction::Index {
+                index: "other".to_string(),
+                id: Some("1".to_string()),
+                document: json!({ "level": "info" }),
+            },
+        ])
+        .await;
+    assert!(results[0].is_ok());
+    assert!(matches!(results[1], Err(GbsError::IndexNotFound(_))));
+
+    let index = index_of(&storage, "logs-2024").await;
+    assert_eq!(
+        index["mappings"]["properties"]["level"]["type"],
+        json!("keyword")
+    );
+    assert!(index["aliases"].get("all-logs").is_some());
+    assert!(storage.get_document("logs-2024", "1").await.is_ok());
+    assert!(!storage.index_exists("other").await.unwrap());
+}
+
+#[tokio::test]
+async fn test_invalid_templates_are_rejected() {
+    let missing_patterns = IndexTemplate::parse(TemplateKind::Legacy, &json!({ "order": 1 }));
+    assert!(matches!(missing_patterns, Err(GbsError::InvalidRequest(_))));
+
+    let composed = IndexTemplate::parse(
+        TemplateKind::Composable,
+        &json!({ "index_patterns": ["a-*"], "composed_of": ["component"] }),
+    );
+    assert!(matches!(composed, Err(GbsError::InvalidRequest(_))));
+
+    let unknown_setting = IndexTemplate::parse(
+        TemplateKind::Legacy,
+        &json!({ "index_patterns": ["a-*"], "settings": { "index": { "foo": 1 } } }),
+    );
+    assert!(matches!(unknown_setting, Err(GbsError::IllegalArgument(_))));
+}
+
+#[tokio::test]
+async fn test_get_and_delete_templates() {
+    let storage = Storage::new();
+    for name in ["logs", "metrics"] {
+        storage
+            .put_template(
+                TemplateKind::Legacy,
+                name,
+                template(
+                    TemplateKind::Legacy,
+                    json!({ "index_patterns": [format!("{}-*", name)] }),
+                ),
+            )
+            .await
+            .unwrap();
+    }
+
+    assert_eq!(storage.get_templates(TemplateKind::Legacy, "*").len(), 2);
+    assert_eq!(storage.get_templates(TemplateKind::Legacy, "log*").len(), 1);
+    assert!(storage
+        .get_templates(TemplateKind::Composable, "*")
+        .is_empty());
+
+    storage
+        .delete_template(TemplateKind::Legacy, "logs")
+        .await
+        .unwrap();
+    assert!(storage
+        .get_templates(TemplateKind::Legacy, "logs")
+        .is_empty());
+    assert!(matches!(
+        storage.delete_template(TemplateKind::Legacy, "logs").await,
+        Err(GbsError::TemplateNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_templates_persist_across_restarts() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let storage = Storage::with_sled(temp_dir.path()).unwrap();
+        storage
+            .put_template(
+                TemplateKind::Composable,
+                "logs",
+                template(
+                    TemplateKind::Composable,
+                    json!({ "index_patterns": ["logs-*"], "priority": 5, "version": 3 }),
+                ),
+            )
+            .await
+            .unwrap();
+        storage
+            .put_template(
+                TemplateKind::Legacy,
+                "removed",
+                template(TemplateKind::Legacy, json!({ "index_patterns": ["x-*"] })),
+            )
+            .await
+            .unwrap();
+        storage
+            .delete_template(TemplateKind::Legacy, "removed")
+            .await
+            .unwrap();
+    }
+
+    let storage = open_sled(temp_dir.path()).await;
+    let templates = storage.get_templates(TemplateKind::Composable, "*");
+    assert_eq!(templates.len(), 1);
+    assert_eq!(templates[0].0, "logs");
+    assert_eq!(templates[0].1.priority, 5);
+    assert_eq!(templates[0].1.version, Some(3));
+    assert!(storage.get_templates(TemplateKind::Legacy, "*").is_empty());
+}
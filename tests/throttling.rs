@@ -0,0 +1,121 @@
+//! Tests for write throttling
+
+mod common;
+
+use axum_test::http::StatusCode;
+use axum_test::TestServer;
+use common::server_with;
+use gbs::config::Config;
+use gbs::server::{AppState, WriteThrottle};
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// A server serving one write at a time, with room for `queue_size` more
+fn server(queue_size: usize) -> (TestServer, AppState) {
+    let mut config = Config::default();
+    config.server.max_concurrent_writes = 1;
+    config.server.write_queue_size = queue_size;
+    let state = AppState::new(Arc::new(Storage::new()), "8.11.0").with_config(config);
+    let server = server_with(state.clone());
+    (server, state)
+}
+
+#[tokio::test]
+async fn test_writes_are_rejected_once_the_queue_is_full() {
+    let (server, state) = server(0);
+    server.put("/books").await.assert_status_ok();
+
+    let busy = state.writes.acquire().await.unwrap();
+    let response = server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await;
+    response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header("retry-after"), "1");
+    let body: Value = response.json();
+    assert_eq!(body["error"]["type"], "es_rejected_execution_exception");
+    assert_eq!(body["status"], 429);
+
+    // Reads are not throttled
+    server.get("/books/_search").await.assert_status_ok();
+    server
+        .post("/books/_search")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_ok();
+
+    drop(busy);
+    server
+        .put("/books/_doc/1")
+        .json(&json!({ "title": "Dune" }))
+        .await
+        .assert_status(StatusCode::CREATED);
+
+    let body: Value = server.get("/_nodes/stats/thread_pool").await.json();
+    let node = body["nodes"].as_object().unwrap().values().next().unwrap();
+    let write = &node["thread_pool"]["write"];
+    assert_eq!(write["threads"], 1);
+    assert_eq!(write["queue"], 0);
+    assert_eq!(write["rejected"], 1);
+    assert_eq!(write["completed"], 3);
+}
+
+#[tokio::test]
+async fn test_queued_writes_wait_for_their_turn() {
+    let throttle = Arc::new(WriteThrottle::new(1, 1));
+    let busy = throttle.acquire().await.unwrap();
+
+    let waiting = tokio::spawn({
+        let throttle = throttle.clone();
+        async move {
+            let _permit = throttle.acquire().await.unwrap();
+        }
+    });
+    while throttle.stats().queue == 0 {
+        tokio::time::sleep(Duration::from_millis(1)).await;
+    }
+    assert_eq!(throttle.stats().active, 1);
+    // The queue is full
+    assert!(throttle.acquire().await.is_err());
+
+    drop(busy);
+    waiting.await.unwrap();
+    let stats = throttle.stats();
+    assert_eq!(stats.queue, 0);
+    assert_eq!(stats.active, 0);
+    assert_eq!(stats.largest, 1);
+    assert_eq!(stats.rejected, 1);
+    assert_eq!(stats.completed, 2);
+}
+
+#[tokio::test]
+async fn test_abandoned_writes_leave_the_queue() {
+    let throttle = WriteThrottle::new(1, 1);
+    let _busy = throttle.acquire().await.unwrap();
+    let waited = tokio::time::timeout(Duration::from_millis(10), throttle.acquire()).await;
+    assert!(waited.is_err());
+    assert_eq!(throttle.stats().queue, 0);
+}
+
+#[tokio::test]
+async fn test_closing_waits_for_writes_in_flight() {
+    let throttle = Arc::new(WriteThrottle::new(1, 1));
+    let busy = throttle.acquire().await.unwrap();
+
+    let closing = tokio::spawn({
+        let throttle = throttle.clone();
+        async move { throttle.close().await }
+    });
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    assert!(!closing.is_finished());
+
+    drop(busy);
+    tokio::time::timeout(Duration::from_secs(5), closing)
+        .await
+        .unwrap()
+        .unwrap();
+    let refused = throttle.acquire().await.unwrap_err();
+    assert!(matches!(refused, gbs::error::GbsError::ShuttingDown));
+}
@@ -0,0 +1,208 @@
+//! Tests for hot/warm index tiering
+
+mod common;
+
+use common::{ids, open_sled, search_ids};
+use gbs::bulk_ops::BulkAction;
+use gbs::storage::{IndexTier, SearchRequest, Storage};
+use std::time::Duration;
+use tempfile::TempDir;
+
+async fn storage_with_docs(temp_dir: &TempDir) -> Storage {
+    let storage = open_sled(temp_dir.path().join("db")).await;
+    storage.create_index("logs", None, None).await.unwrap();
+    for (id, message) in [("1", "disk full"), ("2", "user login"), ("3", "disk slow")] {
+        storage
+            .index_document("logs", id, serde_json::json!({ "message": message }))
+            .await
+            .unwrap();
+    }
+    storage
+}
+
+#[tokio::test]
+async fn test_warm_index_served_from_disk() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = storage_with_docs(&temp_dir).await;
+
+    assert!(storage
+        .set_index_tier("logs", IndexTier::Warm)
+        .await
+        .unwrap());
+    assert!(!storage
+        .set_index_tier("logs", IndexTier::Warm)
+        .await
+        .unwrap());
+
+    let tier = storage.get_index_tier("logs").await.unwrap();
+    assert_eq!(tier["tier"], "warm");
+    assert_eq!(tier["docs_count"], 3);
+
+    // Reads and searches go to disk
+    let doc = storage.get_document("logs", "2").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "user login");
+    assert!(storage.get_document("logs", "missing").await.is_err());
+    assert!(storage.document_exists("logs", "2").await.unwrap());
+    assert!(!storage.document_exists("logs", "missing").await.unwrap());
+    assert_eq!(
+        search_ids(
+            &storage,
+            "logs",
+            serde_json::json!({ "ids": { "values": ["3", "9"] } })
+        )
+        .await,
+        vec!["3"]
+    );
+    assert_eq!(
+        search_ids(
+            &storage,
+            "logs",
+            serde_json::json!({ "match": { "message": "disk" } })
+        )
+        .await,
+        vec!["1", "3"]
+    );
+
+    // Writes keep the document count up to date
+    storage
+        .index_document("logs", "4", serde_json::json!({ "message": "disk error" }))
+        .await
+        .unwrap();
+    storage
+        .index_document(
+            "logs",
+            "1",
+            serde_json::json!({ "message": "disk replaced" }),
+        )
+        .await
+        .unwrap();
+    storage.delete_document("logs", "2").await.unwrap();
+    assert!(storage.delete_document("logs", "2").await.is_err());
+    let stats = storage.get_indices_stats().await;
+    assert_eq!(stats, vec![("logs".to_string(), 3)]);
+
+    // Bulk updates merge with the document on disk
+    storage
+        .execute_bulk_action(BulkAction::Update {
+            index: "logs".to_string(),
+            id: "4".to_string(),
+            document: serde_json::json!({ "level": "error" }),
+        })
+        .await
+        .unwrap();
+    let doc = storage.get_document("logs", "4").await.unwrap();
+    assert_eq!(doc["_source"]["message"], "disk error");
+    assert_eq!(doc["_source"]["level"], "error");
+
+    // Promoting loads the documents back into memory
+    assert!(storage
+        .set_index_tier("logs", IndexTier::Hot)
+        .await
+        .unwrap());
+    assert_eq!(
+        search_ids(
+            &storage,
+            "logs",
+            serde_json::json!({ "match": { "message": "disk" } })
+        )
+        .await,
+        vec!["1", "3", "4"]
+    );
+    let tier = storage.get_index_tier("logs").await.unwrap();
+    assert_eq!(tier["tier"], "hot");
+    assert_eq!(tier["docs_count"], 3);
+}
+
+#[tokio::test]
+async fn test_warm_index_search_pages_and_times_out() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = storage_with_docs(&temp_dir).await;
+    for id in 4..600 {
+        storage
+            .index_document(
+                "logs",
+                &id.to_string(),
+                serde_json::json!({ "message": "disk", "n": id }),
+            )
+            .await
+            .unwrap();
+    }
+    storage
+        .set_index_tier("logs", IndexTier::Warm)
+        .await
+        .unwrap();
+
+    let query = serde_json::json!({ "match": { "message": "disk" } });
+    let mut request = SearchRequest::new(query);
+    request.from = Some(2);
+    request.size = Some(3);
+    request.sort = Some(serde_json::json!([{ "n": "desc" }]));
+    let result = storage.search_with_request("logs", &request).await.unwrap();
+    assert_eq!(result["timed_out"], false);
+    assert_eq!(result["hits"]["total"]["value"], 598);
+    assert_eq!(ids(&result), ["597", "596", "595"]);
+
+    request.timeout = Some(Duration::ZERO);
+    let result = storage.search_with_request("logs", &request).await.unwrap();
+    assert_eq!(result["timed_out"], true);
+    assert!(result["hits"]["total"]["value"].as_u64().unwrap() < 598);
+}
+
+#[tokio::test]
+async fn test_warm_tier_survives_restart() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let storage = storage_with_docs(&temp_dir).await;
+        storage
+            .set_index_tier("logs", IndexTier::Warm)
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(temp_dir.path().join("db")).await;
+    let tier = storage.get_index_tier("logs").await.unwrap();
+    assert_eq!(tier["tier"], "warm");
+    assert_eq!(tier["docs_count"], 3);
+    assert_eq!(
+        search_ids(&storage, "logs", serde_json::json!({ "match_all": {} })).await,
+        vec!["1", "2", "3"]
+    );
+}
+
+#[tokio::test]
+async fn test_demote_indices_by_age() {
+    let temp_dir = TempDir::new().unwrap();
+    let storage = storage_with_docs(&temp_dir).await;
+    storage.create_index(".internal", None, None).await.unwrap();
+
+    // Nothing is a day old yet
+    let demoted = storage
+        .demote_indices_older_than(Duration::from_secs(86400))
+        .await
+        .unwrap();
+    assert!(demoted.is_empty());
+
+    // System indices are never demoted
+    let demoted = storage
+        .demote_indices_older_than(Duration::ZERO)
+        .await
+        .unwrap();
+    assert_eq!(demoted, vec!["logs"]);
+    let tier = storage.get_index_tier(".internal").await.unwrap();
+    assert_eq!(tier["tier"], "hot");
+}
+
+#[tokio::test]
+async fn test_tiering_requires_persistence() {
+    let storage = Storage::new();
+    storage.create_index("logs", None, None).await.unwrap();
+    assert!(storage
+        .set_index_tier("logs", IndexTier::Warm)
+        .await
+        .is_err());
+    assert!(storage
+        .demote_indices_older_than(Duration::ZERO)
+        .await
+        .is_err());
+}
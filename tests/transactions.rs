@@ -0,0 +1,219 @@
+//! Tests for atomic multi-document transactions
+
+mod common;
+
+use common::{index_action, open_sled};
+use gbs::bulk_ops::BulkAction;
+use gbs::error::GbsError;
+use gbs::storage::Storage;
+use serde_json::json;
+use tempfile::TempDir;
+
+fn index(id: &str, document: serde_json::Value) -> BulkAction {
+    index_action("inventory", id, document)
+}
+
+fn update(id: &str, document: serde_json::Value) -> BulkAction {
+    BulkAction::Update {
+        index: "inventory".to_string(),
+        id: id.to_string(),
+        document,
+    }
+}
+
+fn delete(id: &str) -> BulkAction {
+    BulkAction::Delete {
+        index: "inventory".to_string(),
+        id: id.to_string(),
+    }
+}
+
+async fn inventory(storage: &Storage) {
+    storage.create_index("inventory", None, None).await.unwrap();
+    storage
+        .index_document("inventory", "a", json!({ "sku": "a", "stock": 5 }))
+        .await
+        .unwrap();
+    storage
+        .index_document("inventory", "b", json!({ "sku": "b", "stock": 0 }))
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn test_transaction_applies_all_operations() {
+    let storage = Storage::new();
+    inventory(&storage).await;
+
+    let results = storage
+        .execute_transaction(
+            "inventory",
+            vec![
+                update("a", json!({ "stock": 4 })),
+                index("c", json!({ "sku": "c", "stock": 1 })),
+                delete("b"),
+            ],
+        )
+        .await
+        .unwrap();
+
+    let statuses: Vec<_> = results
+        .iter()
+        .map(|(_, id, status, ..)| (id.as_str(), *status))
+        .collect();
+    assert_eq!(statuses, vec![("a", 200), ("c", 201), ("b", 200)]);
+    assert_eq!(
+        storage.get_document("inventory", "a").await.unwrap()["_source"]["stock"],
+        4
+    );
+    assert!(storage.get_document("inventory", "c").await.is_ok());
+    assert!(storage.get_document("inventory", "b").await.is_err());
+}
+
+#[tokio::test]
+async fn test_failed_operation_aborts_transaction() {
+    let storage = Storage::new();
+    inventory(&storage).await;
+
+    let abort = storage
+        .execute_transaction(
+            "inventory",
+            vec![
+                update("a", json!({ "stock": 4 })),
+                index("c", json!({ "sku": "c" })),
+                delete("missing"),
+            ],
+        )
+        .await
+        .unwrap_err();
+
+    assert_eq!(abort.operation, Some(2));
+    assert!(matches!(abort.error, GbsError::DocumentNotFound(_)));
+    // Nothing was applied
+    assert_eq!(
+        storage.get_document("inventory", "a").await.unwrap()["_source"]["stock"],
+        5
+    );
+    assert!(storage.get_document("inventory", "c").await.is_err());
+}
+
+#[tokio::test]
+async fn test_operations_see_earlier_operations() {
+    let storage = Storage::new();
+    inventory(&storage).await;
+
+    // Deleting a document created earlier in the transaction succeeds
+    storage
+        .execute_transaction(
+            "inventory",
+            vec![index("c", json!({ "sku": "c" })), delete("c")],
+        )
+        .await
+        .unwrap();
+    assert!(storage.get_document("inventory", "c").await.is_err());
+
+    // Creating a document deleted earlier in the transaction succeeds too
+    storage
+        .execute_transaction(
+            "inventory",
+            vec![
+                delete("a"),
+                BulkAction::Create {
+                    index: "inventory".to_string(),
+                    id: Some("a".to_string()),
+                    document: json!({ "sku": "a", "stock": 9 }),
+                },
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(
+        storage.get_document("inventory", "a").await.unwrap()["_source"]["stock"],
+        9
+    );
+}
+
+#[tokio::test]
+async fn test_transaction_is_limited_to_its_index() {
+    let storage = Storage::new();
+    inventory(&storage).await;
+    storage.create_index("other", None, None).await.unwrap();
+
+    let abort = storage
+        .execute_transaction(
+            "inventory",
+            vec![
+                update("a", json!({ "stock": 1 })),
+                BulkAction::Delete {
+                    index: "other".to_string(),
+                    id: "x".to_string(),
+                },
+            ],
+        )
+        .await
+        .unwrap_err();
+    assert_eq!(abort.operation, Some(1));
+
+    let abort = storage
+        .execute_transaction("missing", vec![delete("a")])
+        .await
+        .unwrap_err();
+    assert_eq!(abort.operation, None);
+    assert!(matches!(abort.error, GbsError::IndexNotFound(_)));
+}
+
+#[tokio::test]
+async fn test_transaction_through_an_alias() {
+    let storage = Storage::new();
+    inventory(&storage).await;
+    storage.put_alias("inventory", "stock").await.unwrap();
+
+    // Actions may name the alias or the index it writes to
+    let outcomes = storage
+        .execute_transaction(
+            "stock",
+            vec![
+                update("a", json!({ "stock": 4 })),
+                BulkAction::Delete {
+                    index: "stock".to_string(),
+                    id: "b".to_string(),
+                },
+            ],
+        )
+        .await
+        .unwrap();
+    assert_eq!(outcomes.len(), 2);
+    let doc = storage.get_document("inventory", "a").await.unwrap();
+    assert_eq!(doc["_source"]["stock"], 4);
+    assert!(storage.get_document("inventory", "b").await.is_err());
+}
+
+#[tokio::test]
+async fn test_committed_transaction_is_persisted() {
+    let temp_dir = TempDir::new().unwrap();
+    let data_path = temp_dir.path().join("db");
+
+    {
+        let storage = Storage::with_sled(&data_path).unwrap();
+        inventory(&storage).await;
+        storage
+            .execute_transaction(
+                "inventory",
+                vec![update("a", json!({ "stock": 3 })), delete("b")],
+            )
+            .await
+            .unwrap();
+        storage
+            .execute_transaction("inventory", vec![delete("a"), delete("b")])
+            .await
+            .unwrap_err();
+        storage.flush().await.unwrap();
+    }
+
+    let storage = open_sled(&data_path).await;
+    assert_eq!(
+        storage.get_document("inventory", "a").await.unwrap()["_source"]["stock"],
+        3
+    );
+    assert!(storage.get_document("inventory", "b").await.is_err());
+}
@@ -0,0 +1,106 @@
+//! Tests for usage accounting
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use gbs::config::UsageConfig;
+use gbs::usage::{caller_key, Usage, UsageTracker, ANONYMOUS_CALLER};
+use std::time::Duration;
+
+fn at(secs: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(secs, 0).unwrap()
+}
+
+fn usage(caller: &str, index: Option<&str>) -> Usage {
+    Usage {
+        caller: caller.to_string(),
+        index: index.map(str::to_string),
+        requests: 1,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_caller_key() {
+    let encode = |s: &str| base64::engine::general_purpose::STANDARD.encode(s);
+
+    assert_eq!(
+        caller_key(Some(&format!("ApiKey {}", encode("team-a:secret")))),
+        "team-a"
+    );
+    assert_eq!(
+        caller_key(Some(&format!("Basic {}", encode("alice:changeme")))),
+        "alice"
+    );
+    assert_eq!(caller_key(Some("Bearer token")), ANONYMOUS_CALLER);
+    assert_eq!(caller_key(Some("ApiKey not-base64!")), ANONYMOUS_CALLER);
+    assert_eq!(caller_key(None), ANONYMOUS_CALLER);
+}
+
+#[test]
+fn test_usage_totals_per_index_and_caller() {
+    let tracker = UsageTracker::default();
+    tracker.record_at(at(0), usage("team-a", Some("logs")));
+    tracker.record_at(
+        at(1),
+        Usage {
+            searches: 1,
+            search_time: Duration::from_millis(20),
+            bytes_returned: 100,
+            ..usage("team-a", Some("logs"))
+        },
+    );
+    tracker.record_at(
+        at(2),
+        Usage {
+            bytes_indexed: 50,
+            ..usage("team-b", Some("metrics"))
+        },
+    );
+    tracker.record_at(at(3), usage("team-b", None));
+
+    let report = tracker.report();
+    let team_a = report.totals.api_keys["team-a"];
+    assert_eq!(team_a.requests, 2);
+    assert_eq!(team_a.searches, 1);
+    assert_eq!(team_a.search_time_ms, 20.0);
+    assert_eq!(team_a.bytes_returned, 100);
+    let team_b = report.totals.api_keys["team-b"];
+    assert_eq!(team_b.requests, 2);
+    assert_eq!(team_b.bytes_indexed, 50);
+
+    assert_eq!(report.totals.indices.len(), 2);
+    assert_eq!(report.totals.indices["logs"].requests, 2);
+    assert_eq!(report.totals.indices["metrics"].bytes_indexed, 50);
+}
+
+#[test]
+fn test_usage_history_buckets() {
+    let tracker = UsageTracker::from_config(&UsageConfig {
+        bucket_secs: 60,
+        history_buckets: 3,
+    });
+    tracker.record_at(at(10), usage("team-a", Some("logs")));
+    tracker.record_at(at(70), usage("team-a", Some("logs")));
+    tracker.record_at(at(80), usage("team-a", Some("logs")));
+    // Out of order usage joins its own bucket
+    tracker.record_at(at(20), usage("team-b", Some("logs")));
+
+    let report = tracker.report();
+    let starts: Vec<i64> = report.history.iter().map(|b| b.start.timestamp()).collect();
+    assert_eq!(starts, vec![0, 60]);
+    assert_eq!(report.history[0].usage.indices["logs"].requests, 2);
+    assert_eq!(report.history[1].usage.api_keys["team-a"].requests, 2);
+
+    // Buckets outside the history window are dropped, totals are kept
+    tracker.record_at(at(250), usage("team-a", Some("logs")));
+    let report = tracker.report();
+    let starts: Vec<i64> = report.history.iter().map(|b| b.start.timestamp()).collect();
+    assert_eq!(starts, vec![240]);
+    assert_eq!(report.totals.indices["logs"].requests, 5);
+
+    // Usage older than the window is only counted in the totals
+    tracker.record_at(at(100), usage("team-a", Some("logs")));
+    let report = tracker.report();
+    let starts: Vec<i64> = report.history.iter().map(|b| b.start.timestamp()).collect();
+    assert_eq!(starts, vec![240]);
+}
@@ -0,0 +1,138 @@
+//! Tests for query validation
+
+mod common;
+
+use axum_test::TestServer;
+use common::test_server;
+use gbs::models::QueryAst;
+use gbs::storage::Storage;
+use serde_json::{json, Value};
+
+async fn create_test_server() -> TestServer {
+    let server = test_server(Storage::new(), "7.17.0");
+    server
+        .put("/books")
+        .json(&json!({ "settings": { "number_of_shards": 2 } }))
+        .await
+        .assert_status_ok();
+    server.put("/articles").await.assert_status_ok();
+    server
+}
+
+#[tokio::test]
+async fn test_valid_query() {
+    let server = create_test_server().await;
+
+    let body: Value = server
+        .post("/books/_validate/query")
+        .json(&json!({
+            "query": { "bool": { "must": [{ "match": { "title": "dune" } }] } }
+        }))
+        .await
+        .json();
+    assert_eq!(body["valid"], true);
+    assert_eq!(body["_shards"]["total"], 2);
+    assert!(body.get("explanations").is_none());
+    assert!(body.get("error").is_none());
+
+    // No query validates as match_all
+    let body: Value = server.get("/_validate/query").await.json();
+    assert_eq!(body["valid"], true);
+    assert_eq!(body["_shards"]["total"], 3);
+
+    // Queries that need other documents are valid too
+    let body: Value = server
+        .post("/books/_validate/query")
+        .json(&json!({
+            "query": { "percolate": { "field": "query", "document": { "title": "dune" } } }
+        }))
+        .await
+        .json();
+    assert_eq!(body["valid"], true);
+}
+
+#[tokio::test]
+async fn test_invalid_query() {
+    let server = create_test_server().await;
+
+    for (query, reason) in [
+        (json!({ "unknown": {} }), "Unknown query type [unknown]"),
+        (
+            json!({ "match": { "title": "dune", "year": 1965 } }),
+            "[match] query malformed",
+        ),
+        (
+            json!({ "bool": { "must": { "range": { "year": 1965 } } } }),
+            "[range] query malformed",
+        ),
+        (json!({ "query_string": { "query": "title:(dune" } }), ""),
+    ] {
+        let response = server
+            .post("/books/_validate/query")
+            .json(&json!({ "query": query }))
+            .await;
+        response.assert_status_ok();
+        let body: Value = response.json();
+        assert_eq!(body["valid"], false, "{}", query);
+        let error = body["error"].as_str().unwrap();
+        assert!(error.contains(reason), "{}", error);
+        assert_eq!(body["_shards"]["failed"], 0);
+    }
+
+    // Other search request keys are not part of a query
+    let body: Value = server
+        .post("/books/_validate/query")
+        .json(&json!({ "query": { "match_all": {} }, "size": 10 }))
+        .await
+        .json();
+    assert_eq!(body["valid"], false);
+    assert!(body["error"].as_str().unwrap().contains("[size]"));
+
+    let body: Value = server
+        .get("/books/_validate/query")
+        .add_query_param("q", "title:(dune")
+        .await
+        .json();
+    assert_eq!(body["valid"], false);
+}
+
+#[tokio::test]
+async fn test_explain_gives_the_executed_query() {
+    let server = create_test_server().await;
+
+    let body: Value = server
+        .get("/books,articles/_validate/query")
+        .add_query_param("q", "title:dune")
+        .add_query_param("explain", "true")
+        .await
+        .json();
+    assert_eq!(body["valid"], true);
+    let explanations = body["explanations"].as_array().unwrap();
+    assert_eq!(explanations.len(), 2);
+    assert_eq!(explanations[0]["index"], "books");
+    assert_eq!(explanations[0]["valid"], true);
+    // Query strings are explained by their query DSL translation
+    let explanation: Value =
+        serde_json::from_str(explanations[0]["explanation"].as_str().unwrap()).unwrap();
+    assert!(explanation.get("query_string").is_none());
+    assert!(explanation.to_string().contains("dune"));
+}
+
+#[tokio::test]
+async fn test_missing_index() {
+    let server = create_test_server().await;
+    server
+        .post("/missing/_validate/query")
+        .json(&json!({ "query": { "match_all": {} } }))
+        .await
+        .assert_status_not_found();
+}
+
+#[test]
+fn test_percolate_query_ast() {
+    let json = json!({ "percolate": { "field": "query", "document": { "title": "dune" } } });
+    let query = QueryAst::parse(&json).unwrap();
+    assert_eq!(query.fields(), vec!["query"]);
+    assert_eq!(query.to_json(), json);
+    assert!(QueryAst::parse(&json!({ "percolate": { "document": {} } })).is_err());
+}
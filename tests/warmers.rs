@@ -0,0 +1,179 @@
+//! Tests for index warmers run on load and refresh
+
+use gbs::error::GbsError;
+use gbs::storage::{SearchRequest, Storage};
+use serde_json::json;
+use tempfile::TempDir;
+
+fn per_day() -> serde_json::Value {
+    json!({ "per_day": { "date_histogram": { "field": "timestamp", "calendar_interval": "day" } } })
+}
+
+async fn storage_with_events(storage: &Storage) {
+    storage.create_index("events", None, None).await.unwrap();
+    for (id, timestamp) in [("1", "2024-05-01T10:00:00Z"), ("2", "2024-05-02T10:00:00Z")] {
+        storage
+            .index_document(
+                "events",
+                id,
+                json!({ "timestamp": timestamp, "level": "error" }),
+            )
+            .await
+            .unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_put_get_and_delete_warmers() {
+    let storage = Storage::new();
+    storage_with_events(&storage).await;
+
+    storage
+        .put_warmer(
+            "events",
+            "errors",
+            json!({ "query": { "match": { "level": "error" } }, "aggs": per_day() }),
+        )
+        .await
+        .unwrap();
+    // A new body replaces the old one
+    storage
+        .put_warmer(
+            "events",
+            "errors",
+            json!({ "query": { "term": { "level": "error" } } }),
+        )
+        .await
+        .unwrap();
+
+    let warmers = storage.get_warmers("events").await.unwrap();
+    assert_eq!(
+        warmers["errors"],
+        json!({ "query": { "term": { "level": "error" } } })
+    );
+    let index = storage.get_index("events").await.unwrap();
+    assert!(index["events"]["settings"]["index"]["warmers"]["errors"].is_object());
+
+    storage.delete_warmer("events", "errors").await.unwrap();
+    assert!(storage.get_warmers("events").await.unwrap().is_empty());
+    assert!(matches!(
+        storage.delete_warmer("events", "errors").await,
+        Err(GbsError::WarmerNotFound(_))
+    ));
+    assert!(matches!(
+        storage.get_warmers("missing").await,
+        Err(GbsError::IndexNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_invalid_warmers_are_rejected() {
+    let storage = Storage::new();
+    storage.create_index("events", None, None).await.unwrap();
+
+    let unknown_key = storage
+        .put_warmer(
+            "events",
+            "bad",
+            json!({ "query": { "match_all": {} }, "from": 5 }),
+        )
+        .await;
+    assert!(matches!(unknown_key, Err(GbsError::InvalidRequest(_))));
+    let dotted_name = storage
+        .put_warmer("events", "a.b", json!({ "query": { "match_all": {} } }))
+        .await;
+    assert!(matches!(dotted_name, Err(GbsError::InvalidRequest(_))));
+
+    // Warmers given as settings are validated too
+    let result = storage
+        .create_index(
+            "other",
+            Some(json!({ "index.warmers.bad": { "size": -1 } })),
+            None,
+        )
+        .await;
+    assert!(matches!(result, Err(GbsError::InvalidRequest(_))));
+    storage
+        .update_settings(
+            "events",
+            json!({ "index": { "warmers": { "all": { "query": { "match_all": {} } } } } }),
+        )
+        .await
+        .unwrap();
+    assert!(storage
+        .get_warmers("events")
+        .await
+        .unwrap()
+        .contains_key("all"));
+}
+
+#[tokio::test]
+async fn test_refresh_runs_warmers() {
+    let storage = Storage::new();
+    storage_with_events(&storage).await;
+    storage
+        .put_warmer("events", "per_day", json!({ "aggs": per_day() }))
+        .await
+        .unwrap();
+    storage
+        .put_warmer(
+            "events",
+            "broken",
+            json!({ "sort": [{ "level": "sideways" }] }),
+        )
+        .await
+        .unwrap();
+
+    let report = storage.warm_up("events").await;
+    assert_eq!(report.warmers, vec!["per_day".to_string()]);
+    assert_eq!(report.failures.len(), 1);
+    assert_eq!(report.failures[0].0, "broken");
+
+    // A failing warmer does not fail the refresh
+    storage.refresh_index("events").await.unwrap();
+    assert_eq!(storage.aggregation_cache_stats().entries, 1);
+
+    // The dashboard query is answered from the warmed cache
+    let hits_before = storage.aggregation_cache_stats().hits;
+    storage
+        .search_with_request(
+            "events",
+            &SearchRequest {
+                size: Some(0),
+                aggs: Some(per_day()),
+                ..SearchRequest::new(json!({ "match_all": {} }))
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(storage.aggregation_cache_stats().hits, hits_before + 1);
+
+    // Refreshing a missing index has nothing to warm
+    storage.refresh_index("missing").await.unwrap();
+    assert!(storage.warm_up("missing").await.warmers.is_empty());
+}
+
+#[tokio::test]
+async fn test_warmers_run_after_restart() {
+    let temp_dir = TempDir::new().unwrap();
+    {
+        let storage = Storage::with_sled(temp_dir.path()).unwrap();
+        storage_with_events(&storage).await;
+        storage
+            .put_warmer("events", "per_day", json!({ "aggs": per_day() }))
+            .await
+            .unwrap();
+        storage.flush().await.unwrap();
+    }
+
+    let storage = Storage::with_sled(temp_dir.path()).unwrap();
+    assert_eq!(storage.aggregation_cache_stats().entries, 0);
+    storage.load_from_backend().await.unwrap();
+    assert!(storage
+        .get_warmers("events")
+        .await
+        .unwrap()
+        .contains_key("per_day"));
+    assert_eq!(storage.aggregation_cache_stats().entries, 1);
+    assert_eq!(storage.aggregation_cache_stats().misses, 1);
+}